@@ -0,0 +1,262 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use colored::*;
+use tokio::sync::Notify;
+
+// --concurrency auto 的起始並發數 (超過上限時以上限為準)
+pub const INITIAL: usize = 64;
+
+// 自動模式的並發上限 (仍受檔案描述符上限限制)
+pub const MAX: usize = 1024;
+
+// 並發數不會降到此值以下
+const MIN: usize = 4;
+
+// 每個評估視窗至少的探測數
+const MIN_WINDOW: usize = 16;
+
+// 視窗的逾時/錯誤比例比基準高出此值時視為壅塞
+const CONGESTION_MARGIN: f64 = 0.05;
+
+// 沒有壅塞時每個視窗增加的並發數
+const INCREASE: usize = 8;
+
+// 壅塞時乘上的比例
+const DECREASE: f64 = 0.5;
+
+// 探測結果對控制器的意義
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeOutcome {
+    // 有回應 (連線成功或被拒)
+    Answered,
+    // 逾時沒有回應
+    Timeout,
+    // 掃描端本身的錯誤 (例如 EMFILE)
+    Error,
+}
+
+// 一次並發數調整
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adjustment {
+    pub from: usize,
+    pub to: usize,
+    // 觸發調整的視窗中逾時與錯誤的比例
+    pub failure_rate: f64,
+}
+
+// AIMD 控制器：每個視窗 (約等於目前並發數的探測) 評估一次；
+// 逾時/錯誤的比例低於基準加上容許值時加法增加，否則乘法減少
+// 基準是目前觀察到的最低比例，目標大量端口被過濾 (本來就逾時) 時不會一直降速
+// 掃描端錯誤一律視為壅塞訊號
+#[derive(Debug, Clone)]
+pub struct AimdController {
+    limit: usize,
+    min: usize,
+    max: usize,
+    peak: usize,
+    seen: usize,
+    failures: usize,
+    errors: usize,
+    baseline: Option<f64>,
+    adjustments: usize,
+}
+
+impl AimdController {
+    pub fn new(initial: usize, max: usize) -> Self {
+        let max = max.max(1);
+        let min = MIN.min(max);
+        let limit = initial.clamp(min, max);
+        AimdController {
+            limit,
+            min,
+            max,
+            peak: limit,
+            seen: 0,
+            failures: 0,
+            errors: 0,
+            baseline: None,
+            adjustments: 0,
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn peak(&self) -> usize {
+        self.peak
+    }
+
+    pub fn adjustments(&self) -> usize {
+        self.adjustments
+    }
+
+    // 記錄一個探測結果；視窗結束且並發數改變時回傳調整
+    pub fn record(&mut self, outcome: ProbeOutcome) -> Option<Adjustment> {
+        self.seen += 1;
+        match outcome {
+            ProbeOutcome::Answered => {}
+            ProbeOutcome::Timeout => self.failures += 1,
+            ProbeOutcome::Error => {
+                self.failures += 1;
+                self.errors += 1;
+            }
+        }
+        if self.seen < self.limit.max(MIN_WINDOW) {
+            return None;
+        }
+
+        let rate = self.failures as f64 / self.seen as f64;
+        let baseline = *self.baseline.get_or_insert(rate);
+        let congested = self.errors > 0 || rate > baseline + CONGESTION_MARGIN;
+        if !congested {
+            self.baseline = Some(baseline.min(rate));
+        }
+        let from = self.limit;
+        self.limit = match congested {
+            true => ((self.limit as f64 * DECREASE) as usize).max(self.min),
+            false => (self.limit + INCREASE).min(self.max),
+        };
+        self.peak = self.peak.max(self.limit);
+        (self.seen, self.failures, self.errors) = (0, 0, 0);
+        if self.limit == from {
+            return None;
+        }
+        self.adjustments += 1;
+        Some(Adjustment {
+            from,
+            to: self.limit,
+            failure_rate: rate,
+        })
+    }
+
+    // 外部的壅塞訊號 (例如本機端口即將耗盡)：立即乘法減少，重新開始評估視窗
+    pub fn throttle(&mut self) -> Option<Adjustment> {
+        let failure_rate = match self.seen {
+            0 => 0.0,
+            seen => self.failures as f64 / seen as f64,
+        };
+        let from = self.limit;
+        self.limit = ((self.limit as f64 * DECREASE) as usize).max(self.min);
+        (self.seen, self.failures, self.errors) = (0, 0, 0);
+        if self.limit == from {
+            return None;
+        }
+        self.adjustments += 1;
+        Some(Adjustment {
+            from,
+            to: self.limit,
+            failure_rate,
+        })
+    }
+}
+
+// 掃描結束後的摘要
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveSummary {
+    pub initial: usize,
+    pub last: usize,
+    pub peak: usize,
+    pub max: usize,
+    pub adjustments: usize,
+}
+
+// 依控制器限制同時進行的探測；排程器在取得並發許可後再等待這裡
+#[derive(Debug)]
+pub struct AdaptiveLimit {
+    controller: Mutex<AimdController>,
+    initial: usize,
+    max: usize,
+    active: AtomicUsize,
+    notify: Notify,
+    // --verbose 時顯示每次調整
+    verbose: bool,
+}
+
+impl AdaptiveLimit {
+    pub fn new(max: usize, verbose: bool) -> Self {
+        let controller = AimdController::new(INITIAL, max);
+        AdaptiveLimit {
+            initial: controller.limit(),
+            max,
+            controller: Mutex::new(controller),
+            active: AtomicUsize::new(0),
+            notify: Notify::new(),
+            verbose,
+        }
+    }
+
+    fn controller(&self) -> std::sync::MutexGuard<'_, AimdController> {
+        self.controller.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // 等到進行中的探測少於目前的並發數；只有排程器會呼叫
+    pub async fn admit(&self) {
+        loop {
+            let notified = self.notify.notified();
+            if self.active.load(Ordering::SeqCst) < self.controller().limit() {
+                self.active.fetch_add(1, Ordering::SeqCst);
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    // 探測被取消：只歸還名額，不列入逾時比例
+    pub fn abandon(&self) {
+        self.active.fetch_sub(1, Ordering::SeqCst);
+        self.notify.notify_one();
+    }
+
+    // 探測結束
+    pub fn finish(&self, outcome: ProbeOutcome) {
+        self.active.fetch_sub(1, Ordering::SeqCst);
+        let adjustment = self.controller().record(outcome);
+        if let (Some(adjustment), true) = (adjustment, self.verbose) {
+            eprintln!(
+                "{}",
+                format!(
+                    "並發調整: {} → {} (逾時/錯誤 {:.1}%)",
+                    adjustment.from,
+                    adjustment.to,
+                    adjustment.failure_rate * 100.0
+                )
+                .dimmed()
+            );
+        }
+        self.notify.notify_one();
+    }
+
+    // 本機端口即將耗盡時由 ephemeral 監測呼叫
+    pub fn throttle(&self) {
+        let adjustment = self.controller().throttle();
+        if let (Some(adjustment), true) = (adjustment, self.verbose) {
+            eprintln!("{}", format!("並發調整: {} → {} (本機端口即將耗盡)", adjustment.from, adjustment.to).dimmed());
+        }
+        self.notify.notify_one();
+    }
+
+    pub fn summary(&self) -> AdaptiveSummary {
+        let controller = self.controller();
+        AdaptiveSummary {
+            initial: self.initial,
+            last: controller.limit(),
+            peak: controller.peak(),
+            max: self.max,
+            adjustments: controller.adjustments(),
+        }
+    }
+}
+
+// 摘要中的自動並發區段
+pub fn display_summary(summary: &AdaptiveSummary) {
+    println!(
+        "{} 起始 {}，最終 {}，最高 {} (上限 {})，調整 {} 次",
+        "自動並發:".bold(),
+        summary.initial,
+        summary.last,
+        summary.peak,
+        summary.max,
+        summary.adjustments
+    );
+}
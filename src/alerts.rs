@@ -0,0 +1,303 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::net::IpAddr;
+use serde::{Deserialize, Serialize};
+use crate::checks::health::{self, HealthState};
+use crate::{PortInfo, ScanResult};
+
+// 告警規則 (設定檔 [[watch.alerts]])
+#[derive(Debug, Clone, Deserialize)]
+pub struct AlertRule {
+    pub name: String,
+    #[serde(default)]
+    pub severity: Severity,
+    #[serde(flatten)]
+    pub condition: Condition,
+}
+
+// 告警的嚴重程度，決定送到哪裡 (見 notify)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    // 只記錄
+    Info,
+    // 送到 webhook
+    #[default]
+    Warning,
+    // 送到 webhook 與電子郵件，靜音時段也立即送出
+    Critical,
+}
+
+impl Severity {
+    pub fn label(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+// 規則條件
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Condition {
+    // 符合條件的端口連續 N 次出站不可用
+    Unreachable {
+        category: Option<String>,
+        #[serde(default)]
+        ports: Vec<u16>,
+        consecutive: u32,
+    },
+    // 單次掃描中狀態改變的端口數超過門檻
+    Changes { threshold: usize },
+    // 設定了健康檢查的端口連續 N 次開放但不健康
+    Unhealthy {
+        #[serde(default)]
+        ports: Vec<u16>,
+        consecutive: u32,
+    },
+}
+
+impl Condition {
+    // 端口是否在規則範圍內 (未指定類別與端口時適用全部)
+    fn matches(&self, port: &PortInfo) -> bool {
+        match self {
+            Condition::Unreachable { category, ports, .. } => {
+                let category_ok = category.as_ref().is_none_or(|c| c.eq_ignore_ascii_case(&port.category));
+                let port_ok = ports.is_empty() || ports.contains(&port.port);
+                category_ok && port_ok
+            }
+            Condition::Unhealthy { ports, .. } => ports.is_empty() || ports.contains(&port.port),
+            Condition::Changes { .. } => true,
+        }
+    }
+
+    // 單次紀錄是否屬於規則要計算的連續狀態
+    fn failing(&self, observation: &Observation) -> bool {
+        match self {
+            Condition::Unreachable { .. } => !observation.outbound,
+            Condition::Unhealthy { .. } => observation.health == Some(HealthState::Unhealthy),
+            Condition::Changes { .. } => false,
+        }
+    }
+}
+
+// 啟動時檢查規則
+pub fn validate(rules: &[AlertRule]) -> Result<(), String> {
+    let mut names = HashSet::new();
+    for rule in rules {
+        if rule.name.trim().is_empty() {
+            return Err("告警規則缺少 name".to_string());
+        }
+        if !names.insert(rule.name.as_str()) {
+            return Err(format!("告警規則名稱重複: {}", rule.name));
+        }
+        if let Condition::Unreachable { consecutive: 0, .. } | Condition::Unhealthy { consecutive: 0, .. } = rule.condition {
+            return Err(format!("告警規則 {}: consecutive 必須至少為 1", rule.name));
+        }
+    }
+    Ok(())
+}
+
+// 某次掃描中單個端口的狀態
+#[derive(Debug, Clone, Serialize)]
+pub struct Observation {
+    pub iteration: u64,
+    pub host: IpAddr,
+    pub port: u16,
+    pub service: String,
+    pub inbound: bool,
+    pub outbound: bool,
+    // 設定了健康檢查的端口的健康狀態
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health: Option<HealthState>,
+}
+
+// 與上一次掃描相比的狀態改變
+#[derive(Debug, Clone, Serialize)]
+pub struct Change {
+    pub host: IpAddr,
+    pub port: PortInfo,
+    pub before: (bool, bool),
+    pub after: (bool, bool),
+}
+
+// 觸發的告警
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub rule: String,
+    pub severity: Severity,
+    // 判斷是否為重複告警的鍵 (規則與主機、端口)
+    #[serde(skip)]
+    pub key: String,
+    pub iteration: u64,
+    pub message: String,
+    // 觸發告警的狀態紀錄
+    pub history: Vec<Observation>,
+    // 本次掃描前外部IP已變更，入站結果不能直接與先前比較
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub external_ip_changed: bool,
+    // 本次掃描期間 (或之前的等待中) 系統時間跳變或休眠，時間相關的判斷可能不準
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub clock_jump: bool,
+    // 上次送出後在去重視窗內被抑制的相同告警數
+    #[serde(skip_serializing_if = "is_zero")]
+    pub suppressed: u32,
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+// 跨掃描追蹤各端口狀態並評估規則
+pub struct AlertEngine {
+    rules: Vec<AlertRule>,
+    iteration: u64,
+    depth: usize,
+    history: HashMap<(IpAddr, u16), VecDeque<Observation>>,
+    // 已觸發且尚未恢復的 (規則, 主機, 端口)，避免每次重複告警
+    active: HashSet<(usize, IpAddr, u16)>,
+}
+
+impl AlertEngine {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        // 保留的歷史長度取決於最長的連續次數
+        let depth = rules
+            .iter()
+            .map(|rule| match rule.condition {
+                Condition::Unreachable { consecutive, .. } | Condition::Unhealthy { consecutive, .. } => consecutive as usize,
+                Condition::Changes { .. } => 1,
+            })
+            .max()
+            .unwrap_or(1)
+            .max(2);
+
+        AlertEngine {
+            rules,
+            iteration: 0,
+            depth,
+            history: HashMap::new(),
+            active: HashSet::new(),
+        }
+    }
+
+    pub fn iteration(&self) -> u64 {
+        self.iteration
+    }
+
+    // 記錄一次掃描結果，回傳狀態改變與新觸發的告警
+    pub fn observe(&mut self, results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>) -> (Vec<Change>, Vec<Alert>) {
+        self.iteration += 1;
+        let mut changes = Vec::new();
+        let mut ports: Vec<(IpAddr, &PortInfo)> = Vec::new();
+        // 上游依賴無法連線且設定 quiet_alerts 的端口照常記錄歷史，但不送出告警
+        let mut quiet = HashSet::new();
+
+        for (host, host_results) in results {
+            for (port, result) in host_results {
+                // 掃描端錯誤不代表端口狀態，不列入歷史
+                if result.error.is_some() {
+                    continue;
+                }
+                if result.upstream.as_ref().is_some_and(|upstream| upstream.quiet_alerts) {
+                    quiet.insert((*host, port.port));
+                }
+                let history = self.history.entry((*host, port.port)).or_default();
+                if let Some(last) = history.back() {
+                    if (last.inbound, last.outbound) != (result.inbound, result.outbound) {
+                        changes.push(Change {
+                            host: *host,
+                            port: port.clone(),
+                            before: (last.inbound, last.outbound),
+                            after: (result.inbound, result.outbound),
+                        });
+                    }
+                }
+
+                history.push_back(Observation {
+                    iteration: self.iteration,
+                    host: *host,
+                    port: port.port,
+                    service: port.service.clone(),
+                    inbound: result.inbound,
+                    outbound: result.outbound,
+                    health: health::state(result),
+                });
+                if history.len() > self.depth {
+                    history.pop_front();
+                }
+                ports.push((*host, port));
+            }
+        }
+        changes.sort_by_key(|c| (c.host, c.port.port));
+        ports.sort_by_key(|(host, port)| (*host, port.port));
+
+        let mut alerts = Vec::new();
+        for (index, rule) in self.rules.iter().enumerate() {
+            match &rule.condition {
+                Condition::Unreachable { consecutive, .. } | Condition::Unhealthy { consecutive, .. } => {
+                    for (host, port) in ports.iter().filter(|(host, p)| rule.condition.matches(p) && !quiet.contains(&(*host, p.port))) {
+                        let history = &self.history[&(*host, port.port)];
+                        let streak = history.iter().rev().take_while(|o| rule.condition.failing(o)).count();
+                        let key = (index, *host, port.port);
+
+                        if streak < *consecutive as usize {
+                            self.active.remove(&key);
+                        } else if self.active.insert(key) {
+                            alerts.push(Alert {
+                                rule: rule.name.clone(),
+                                severity: rule.severity,
+                                key: format!("{}|{}|{}", rule.name, host, port.port),
+                                iteration: self.iteration,
+                                message: format!(
+                                    "{} Port {} ({}) 連續 {} 次{}",
+                                    host,
+                                    port.port,
+                                    port.service,
+                                    streak,
+                                    match rule.condition {
+                                        Condition::Unhealthy { .. } => "開放但健康檢查失敗",
+                                        _ => "出站不可用",
+                                    }
+                                ),
+                                history: history.iter().cloned().collect(),
+                                external_ip_changed: false,
+                                clock_jump: false,
+                                suppressed: 0,
+                            });
+                        }
+                    }
+                }
+                Condition::Changes { threshold } => {
+                    let changes: Vec<&Change> = changes.iter().filter(|c| !quiet.contains(&(c.host, c.port.port))).collect();
+                    if changes.len() > *threshold {
+                        alerts.push(Alert {
+                            rule: rule.name.clone(),
+                            severity: rule.severity,
+                            key: rule.name.clone(),
+                            iteration: self.iteration,
+                            message: format!("本次有 {} 個端口狀態改變 (門檻 {})", changes.len(), threshold),
+                            history: changes
+                                .iter()
+                                .map(|c| Observation {
+                                    iteration: self.iteration,
+                                    host: c.host,
+                                    port: c.port.port,
+                                    service: c.port.service.clone(),
+                                    inbound: c.after.0,
+                                    outbound: c.after.1,
+                                    health: None,
+                                })
+                                .collect(),
+                            external_ip_changed: false,
+                            clock_jump: false,
+                            suppressed: 0,
+                        });
+                    }
+                }
+            }
+        }
+
+        (changes, alerts)
+    }
+}
@@ -0,0 +1,549 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use ipnet::IpNet;
+use regex::{Captures, Regex};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use crate::assertions::AssertionOutcome;
+use crate::checks::CheckOutcome;
+use crate::fingerprints;
+use crate::metadata::RunMetadata;
+use crate::scanner::ScanRecord;
+use crate::targets::{ResolveFailure, TargetSpec};
+use crate::{PortInfo, ScanResult};
+
+// 假名主機名稱的網域；.invalid 保留不會被解析
+const PLACEHOLDER_DOMAIN: &str = ".invalid";
+
+// --anonymize-map 的內容：原始值 -> 假名
+#[derive(Debug, Default, Serialize)]
+struct Mapping {
+    addresses: BTreeMap<String, String>,
+    hostnames: BTreeMap<String, String>,
+}
+
+// --anonymize：以每次執行隨機產生的鹽值把位址與主機名稱換成假名
+// 位址保留前綴關係 (同網段的位址換成同一個假網段)，同一個值在一份報告中總是得到相同的假名
+#[derive(Debug)]
+pub struct Anonymizer {
+    salt: [u8; 32],
+    addresses: Mutex<BTreeMap<IpAddr, IpAddr>>,
+    hostnames: Mutex<BTreeMap<String, String>>,
+    // 已知的主機名稱 (目標、虛擬主機、掃描端)；文字中的單段名稱只能靠此比對
+    known: Mutex<Vec<String>>,
+}
+
+// 顯示用的位址：啟用時為假名
+pub fn show_ip(anonymizer: Option<&Anonymizer>, addr: IpAddr) -> String {
+    match anonymizer {
+        Some(anonymizer) => anonymizer.ip(addr).to_string(),
+        None => addr.to_string(),
+    }
+}
+
+// 顯示用的文字：啟用時替換其中的位址與主機名稱
+pub fn show(anonymizer: Option<&Anonymizer>, text: &str) -> String {
+    match anonymizer {
+        Some(anonymizer) => anonymizer.text(text),
+        None => text.to_string(),
+    }
+}
+
+// 已經是假名的主機名稱，不再替換
+fn placeholder_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"^host-[0-9a-f]{8}\.invalid$").expect("valid regex"))
+}
+
+fn ipv4_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"\b\d{1,3}(?:\.\d{1,3}){3}(?:/\d{1,2})?\b").expect("valid regex"))
+}
+
+// 可能是 IPv6 位址的片段，替換前再以解析確認
+fn ipv6_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"(?i)[0-9a-f]*:[0-9a-f]*:[0-9a-f:.]*(?:/\d{1,3})?").expect("valid regex"))
+}
+
+// 至少兩段且最後一段為字母的網域名稱
+fn hostname_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(r"(?i)\b[a-z0-9](?:[a-z0-9-]{0,61}[a-z0-9])?(?:\.[a-z0-9](?:[a-z0-9-]{0,61}[a-z0-9])?)*\.[a-z]{2,63}\b")
+            .expect("valid regex")
+    })
+}
+
+impl Anonymizer {
+    pub fn new(salt: [u8; 32]) -> Self {
+        Anonymizer {
+            salt,
+            addresses: Mutex::new(BTreeMap::new()),
+            hostnames: Mutex::new(BTreeMap::new()),
+            known: Mutex::new(Vec::new()),
+        }
+    }
+
+    // 每次執行隨機產生鹽值，同一個值在不同次執行得到不同的假名
+    pub fn random() -> Result<Self, String> {
+        let mut salt = [0u8; 32];
+        getrandom::getrandom(&mut salt).map_err(|e| format!("無法取得亂數: {}", e))?;
+        Ok(Anonymizer::new(salt))
+    }
+
+    fn digest(&self, parts: &[&[u8]]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.salt);
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finalize().into()
+    }
+
+    // 保留前綴的置換：第 i 個位元依前 i 個原始位元決定是否翻轉
+    fn permute(&self, bits: u128, width: u32) -> u128 {
+        let mut out = 0u128;
+        for i in 0..width {
+            let prefix = if i == 0 { 0 } else { bits >> (width - i) };
+            let flip = self.digest(&[&[width as u8, i as u8], &prefix.to_be_bytes()])[0] & 1;
+            let bit = (bits >> (width - 1 - i)) & 1;
+            out = (out << 1) | (bit ^ u128::from(flip));
+        }
+        out
+    }
+
+    pub fn ip(&self, addr: IpAddr) -> IpAddr {
+        let mut addresses = self.addresses.lock().unwrap_or_else(|e| e.into_inner());
+        *addresses.entry(addr).or_insert_with(|| match addr {
+            IpAddr::V4(v4) => IpAddr::from((self.permute(u128::from(u32::from(v4)), 32) as u32).to_be_bytes()),
+            IpAddr::V6(v6) => IpAddr::from(self.permute(u128::from(v6), 128).to_be_bytes()),
+        })
+    }
+
+    // 網段的假名：網路位址的假名截到相同的前綴長度，其中主機的假名都落在此網段內
+    pub fn net(&self, net: IpNet) -> IpNet {
+        IpNet::new(self.ip(net.network()), net.prefix_len()).map(|n| n.trunc()).unwrap_or(net)
+    }
+
+    pub fn hostname(&self, name: &str) -> String {
+        // 萬用字元目標 (*.zone) 保留萬用字元，只替換區域
+        if let Some(zone) = name.strip_prefix("*.") {
+            return format!("*.{}", self.hostname(zone));
+        }
+        let key = name.trim_end_matches('.').to_ascii_lowercase();
+        if placeholder_pattern().is_match(&key) {
+            return name.to_string();
+        }
+        let mut hostnames = self.hostnames.lock().unwrap_or_else(|e| e.into_inner());
+        hostnames
+            .entry(key.clone())
+            .or_insert_with(|| {
+                let digest = self.digest(&[b"host", key.as_bytes()]);
+                let id: String = digest[..4].iter().map(|b| format!("{:02x}", b)).collect();
+                format!("host-{}{}", id, PLACEHOLDER_DOMAIN)
+            })
+            .clone()
+    }
+
+    // 記住會出現在文字中的主機名稱；IP 位址不需要
+    pub fn learn(&self, name: &str) {
+        if name.is_empty() || name.parse::<IpAddr>().is_ok() {
+            return;
+        }
+        let mut known = self.known.lock().unwrap_or_else(|e| e.into_inner());
+        if !known.iter().any(|k| k.eq_ignore_ascii_case(name)) {
+            known.push(name.to_string());
+            // 較長的名稱先替換，避免只替換到其中一段
+            known.sort_by_key(|k| std::cmp::Reverse(k.len()));
+        }
+    }
+
+    fn address_text(&self, text: &str) -> Option<String> {
+        match text.split_once('/') {
+            Some((addr, len)) => {
+                let net = IpNet::new(addr.parse().ok()?, len.parse().ok()?).ok()?;
+                Some(match net.addr() == net.network() {
+                    true => self.net(net).to_string(),
+                    false => format!("{}/{}", self.ip(net.addr()), net.prefix_len()),
+                })
+            }
+            None => text.parse().ok().map(|addr| self.ip(addr).to_string()),
+        }
+    }
+
+    // 只替換位址與已知的主機名稱；命令列中的檔案名稱 (例如 report.json) 不會被當成主機名稱
+    fn known_text(&self, text: &str) -> String {
+        let replace_addr = |caps: &Captures| self.address_text(&caps[0]).unwrap_or_else(|| caps[0].to_string());
+        let text = ipv4_pattern().replace_all(text, replace_addr);
+        let mut text = ipv6_pattern().replace_all(&text, replace_addr).into_owned();
+        let known = self.known.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        for name in known {
+            if let Ok(pattern) = Regex::new(&format!(r"(?i)\b{}\b", regex::escape(&name))) {
+                text = pattern.replace_all(&text, self.hostname(&name).as_str()).into_owned();
+            }
+        }
+        text
+    }
+
+    // 替換文字中的位址、網段與主機名稱 (含未知但形如網域名稱的字串)
+    pub fn text(&self, text: &str) -> String {
+        let text = self.known_text(text);
+        hostname_pattern().replace_all(&text, |caps: &Captures| self.hostname(&caps[0])).into_owned()
+    }
+
+    fn result(&self, result: &mut ScanResult) {
+        for vhost in &mut result.vhosts {
+            vhost.name = self.hostname(&vhost.name);
+            vhost.error = vhost.error.as_deref().map(|e| self.text(e));
+        }
+        result.note = result.note.as_deref().map(|note| self.text(note));
+        if let Some(icmp) = &mut result.icmp {
+            icmp.from = self.ip(icmp.from);
+        }
+        if let Some(upstream) = &mut result.upstream {
+            upstream.down = upstream.down.iter().map(|host| self.ip(*host)).collect();
+        }
+        // 入站測試的本機位址；0.0.0.0 不需要替換
+        for test in result.bind.iter_mut().filter(|test| !test.addr.is_unspecified()) {
+            test.addr = self.ip(test.addr);
+        }
+        if let Some(banner) = &mut result.banner {
+            banner.text = self.text(&banner.text);
+            banner.version = banner.version.as_deref().map(|v| self.text(v));
+        }
+        if let Some(caps) = &mut result.capabilities {
+            caps.notes = caps.notes.iter().map(|note| self.text(note)).collect();
+        }
+        if let Some(versions) = &mut result.http_versions {
+            versions.notes = versions.notes.iter().map(|note| self.text(note)).collect();
+        }
+        for fingerprint in &mut result.fingerprints {
+            fingerprints::hide(fingerprint.kind, &mut fingerprint.value, self);
+        }
+        // 健康檢查的回應內容可能含有主機名稱或位址
+        if let Some(health) = &mut result.health {
+            health.summary = self.text(&health.summary);
+            health.details.values_mut().for_each(|value| *value = self.text(value));
+        }
+    }
+
+    // --srv 的記錄名稱與目標是主機名稱
+    fn port(&self, mut port: PortInfo) -> PortInfo {
+        for origin in &mut port.srv {
+            origin.record = self.hostname(&origin.record);
+            origin.target = self.hostname(&origin.target);
+        }
+        port
+    }
+
+    pub fn record(&self, mut record: ScanRecord) -> ScanRecord {
+        record.host = self.ip(record.host);
+        record.port = self.port(record.port);
+        self.result(&mut record.result);
+        record
+    }
+
+    pub fn results(&self, results: BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>) -> BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>> {
+        results
+            .into_iter()
+            .map(|(host, ports)| {
+                let ports = ports
+                    .into_iter()
+                    .map(|(port, mut result)| {
+                        self.result(&mut result);
+                        (self.port(port), result)
+                    })
+                    .collect();
+                (self.ip(host), ports)
+            })
+            .collect()
+    }
+
+    pub fn checks(&self, checks: Vec<(IpAddr, Vec<CheckOutcome>)>) -> Vec<(IpAddr, Vec<CheckOutcome>)> {
+        checks
+            .into_iter()
+            .map(|(host, mut outcomes)| {
+                for outcome in &mut outcomes {
+                    outcome.summary = self.text(&outcome.summary);
+                    for (_, value) in &mut outcome.details {
+                        *value = self.text(value);
+                    }
+                }
+                (self.ip(host), outcomes)
+            })
+            .collect()
+    }
+
+    pub fn assertions(&self, outcomes: BTreeMap<IpAddr, Vec<AssertionOutcome>>) -> BTreeMap<IpAddr, Vec<AssertionOutcome>> {
+        outcomes
+            .into_iter()
+            .map(|(host, mut outcomes)| {
+                for outcome in &mut outcomes {
+                    outcome.expected = self.text(&outcome.expected);
+                    outcome.observed = outcome.observed.as_deref().map(|o| self.text(o));
+                }
+                (self.ip(host), outcomes)
+            })
+            .collect()
+    }
+
+    pub fn rekey<T>(&self, map: BTreeMap<IpAddr, T>) -> BTreeMap<IpAddr, T> {
+        map.into_iter().map(|(host, value)| (self.ip(host), value)).collect()
+    }
+
+    // 目標的假名；失敗歸因依此比對已換成假名的結果
+    pub fn targets(&self, targets: &[TargetSpec]) -> Vec<TargetSpec> {
+        targets
+            .iter()
+            .map(|target| match target {
+                TargetSpec::Host { name, addr } => TargetSpec::Host {
+                    name: match name.parse::<IpAddr>() {
+                        Ok(_) => self.ip(*addr).to_string(),
+                        Err(_) => self.hostname(name),
+                    },
+                    addr: self.ip(*addr),
+                },
+                TargetSpec::Network(net, excluded) => TargetSpec::Network(self.net(*net), excluded.clone()),
+                TargetSpec::Unresolved(name) => TargetSpec::Unresolved(self.hostname(name)),
+            })
+            .collect()
+    }
+
+    pub fn failures(&self, failures: &[ResolveFailure]) -> Vec<ResolveFailure> {
+        failures
+            .iter()
+            .map(|failure| ResolveFailure {
+                name: self.hostname(&failure.name),
+                error: self.text(&failure.error),
+                code: failure.code,
+            })
+            .collect()
+    }
+
+    // 執行者資訊與命令列也會出現在報告中
+    pub fn metadata(&self, metadata: &mut RunMetadata) {
+        if let Some(hostname) = &metadata.hostname {
+            self.learn(hostname);
+            metadata.hostname = Some(self.hostname(hostname));
+        }
+        if let Some(username) = &metadata.username {
+            let digest = self.digest(&[b"user", username.as_bytes()]);
+            metadata.username = Some(format!("user-{:02x}{:02x}", digest[0], digest[1]));
+        }
+        metadata.command_line = metadata.command_line.iter().map(|arg| self.known_text(arg)).collect();
+        for value in metadata.annotations.values_mut() {
+            *value = self.text(value);
+        }
+        for excluded in &mut metadata.excluded {
+            excluded.spec = self.text(&excluded.spec);
+        }
+        for warning in &mut metadata.target_warnings {
+            *warning = self.text(warning);
+        }
+    }
+
+    // --anonymize-map：寫出對照表，只有明確指定時才產生
+    pub fn write_map(&self, path: &Path) -> Result<(), String> {
+        let mapping = Mapping {
+            addresses: self
+                .addresses
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .iter()
+                .map(|(real, anon)| (real.to_string(), anon.to_string()))
+                .collect(),
+            hostnames: self.hostnames.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+        };
+        let json = serde_json::to_string_pretty(&mapping).map_err(|e| e.to_string())?;
+        fs::write(path, json + "\n").map_err(|e| format!("無法寫入對照表 {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checks::CheckStatus;
+    use crate::icmp::IcmpError;
+    use crate::matrix::{self, Matrix};
+    use crate::output::{self, OutputFormat, ScanSummary};
+    use crate::probes::Banner;
+    use crate::report;
+    use crate::testutil::{scan_result, TempDir};
+    use crate::vhost::VhostResult;
+
+    // 報告中不應出現的原始值
+    const RAW: &[&str] = &["10.20.30.40", "10.20.30.41", "10.20.0.0/16", "fd00::17", "db01.corp.example", "intranet", "scanner-host", "alice"];
+
+    fn anonymizer() -> Anonymizer {
+        Anonymizer::new([7; 32])
+    }
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    fn assert_clean(format: &str, text: &str) {
+        for raw in RAW {
+            assert!(!text.contains(raw), "{} 洩漏了 {}:\n{}", format, raw, text);
+        }
+    }
+
+    #[test]
+    fn addresses_keep_their_prefixes() {
+        let anon = anonymizer();
+        let (a, b, c) = (anon.ip(ip("10.20.30.40")), anon.ip(ip("10.20.30.41")), anon.ip(ip("10.20.99.1")));
+        let bits = |addr: IpAddr| match addr {
+            IpAddr::V4(v4) => u32::from(v4),
+            IpAddr::V6(_) => unreachable!(),
+        };
+        assert_ne!(a, ip("10.20.30.40"));
+        // 共同前綴的長度不變
+        assert_eq!((bits(a) ^ bits(b)).leading_zeros(), 31);
+        assert_eq!((bits(a) ^ bits(c)).leading_zeros(), (u32::from_be_bytes([10, 20, 30, 40]) ^ u32::from_be_bytes([10, 20, 99, 1])).leading_zeros());
+        // 同一份報告中總是相同
+        assert_eq!(anon.ip(ip("10.20.30.40")), a);
+        // 不同的鹽值得到不同的假名
+        assert_ne!(Anonymizer::new([8; 32]).ip(ip("10.20.30.40")), a);
+
+        let net: IpNet = "10.20.0.0/16".parse().unwrap();
+        let anon_net = anon.net(net);
+        assert_eq!(anon_net.prefix_len(), 16);
+        assert!(anon_net.contains(&a) && anon_net.contains(&c));
+
+        let v6 = anon.ip(ip("fd00::17"));
+        assert!(v6.is_ipv6() && v6 != ip("fd00::17"));
+        assert!(anon.net("fd00::/64".parse().unwrap()).contains(&v6));
+    }
+
+    #[test]
+    fn hostnames_become_stable_placeholders() {
+        let anon = anonymizer();
+        let name = anon.hostname("db01.corp.example");
+        assert!(placeholder_pattern().is_match(&name), "{}", name);
+        assert_eq!(anon.hostname("DB01.corp.example."), name);
+        assert_ne!(anon.hostname("db02.corp.example"), name);
+        // 假名不會再被替換，萬用字元保留
+        assert_eq!(anon.hostname(&name), name);
+        assert_eq!(anon.hostname("*.corp.example"), format!("*.{}", anon.hostname("corp.example")));
+    }
+
+    #[test]
+    fn text_is_scrubbed_of_addresses_and_names() {
+        let anon = anonymizer();
+        anon.learn("intranet");
+        anon.learn("192.0.2.1");
+        let text = anon.text("SSH-2.0 intranet (db01.corp.example) 10.20.30.40 via fd00::17, net 10.20.0.0/16 host 10.20.30.41/24 at 12:34:56");
+        assert_clean("文字", &text);
+        assert!(text.contains(&anon.hostname("intranet")));
+        assert!(text.contains(&anon.net("10.20.0.0/16".parse().unwrap()).to_string()));
+        assert!(text.contains(&format!("{}/24", anon.ip(ip("10.20.30.41")))));
+        // 時間不是 IPv6 位址
+        assert!(text.ends_with("at 12:34:56"), "{}", text);
+
+        // 命令列只替換位址與已知名稱，檔案名稱保留
+        let mut metadata = RunMetadata::collect(&[]);
+        metadata.command_line = vec!["r1".to_string(), "intranet,10.20.30.40".to_string(), "--json".to_string(), "report.json".to_string()];
+        anon.metadata(&mut metadata);
+        assert_eq!(metadata.command_line[3], "report.json");
+        assert_clean("命令列", &metadata.command_line.join(" "));
+    }
+
+    // 目標位址與主機名稱散布在各個欄位的結果
+    fn leaky_results() -> BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>> {
+        let mut open = scan_result(true);
+        open.banner = Some(Banner {
+            probe: "ssh".to_string(),
+            service: Some("ssh".to_string()),
+            version: Some("OpenSSH db01.corp.example".to_string()),
+            text: "SSH-2.0-OpenSSH intranet 10.20.30.40".to_string(),
+            encoding: None,
+            raw: None,
+        });
+        open.vhosts = vec![VhostResult {
+            name: "db01.corp.example".to_string(),
+            tls: true,
+            status: None,
+            cert_valid: Some(false),
+            error: Some("憑證不符 intranet (10.20.30.41)".to_string()),
+        }];
+        open.note = Some("經由 fd00::17 轉送".to_string());
+        let mut blocked = scan_result(false);
+        blocked.icmp = Some(IcmpError { kind: 3, code: 13, from: ip("10.20.30.41"), reason: "admin prohibited" });
+        BTreeMap::from([
+            (ip("10.20.30.40"), HashMap::from([(PortInfo::new(22, "SSH", "Remote"), open), (PortInfo::new(80, "HTTP", "Web"), blocked)])),
+            (ip("fd00::17"), HashMap::from([(PortInfo::new(53, "DNS", "Infra"), scan_result(true))])),
+        ])
+    }
+
+    #[test]
+    fn no_raw_value_reaches_any_output_format() {
+        let anon = anonymizer();
+        // 與執行時相同：先記住目標與虛擬主機名稱，再替換命令列
+        anon.learn("intranet");
+        anon.learn("db01.corp.example");
+        let mut metadata = RunMetadata::collect(&[("ticket".to_string(), "轉送到 db01.corp.example".to_string())]);
+        metadata.hostname = Some("scanner-host".to_string());
+        metadata.username = Some("alice".to_string());
+        metadata.command_line = vec!["r1".to_string(), "10.20.0.0/16,db01.corp.example".to_string()];
+        anon.metadata(&mut metadata);
+
+        // 結果收集完之後、任何序列化之前替換
+        let results = anon.results(leaky_results());
+        let checks = anon.checks(vec![(
+            ip("10.20.30.40"),
+            vec![CheckOutcome::new("SSH", 22, CheckStatus::Ok, "intranet 回應").detail("來源", "10.20.30.41")],
+        )]);
+
+        let json = serde_json::to_string(&report::build(&metadata, None, None, &results, &[], &checks, None)).unwrap();
+        assert_clean("JSON", &json);
+        let matrix = Matrix::pivot(&results);
+        assert_clean("HTML", &matrix::to_html(&matrix, &metadata));
+        assert_clean("矩陣 CSV", &matrix::to_csv(&matrix, &metadata));
+
+        // 串流輸出逐筆替換
+        let records: Vec<ScanRecord> = leaky_results()
+            .into_iter()
+            .flat_map(|(host, ports)| ports.into_iter().map(move |(port, result)| ScanRecord { host, port, result, identity: None }))
+            .map(|record| anon.record(record))
+            .collect();
+        let dir = TempDir::new("anonymize");
+        for (format, name) in [(OutputFormat::Ndjson, "out.ndjson"), (OutputFormat::Csv, "out.csv"), (OutputFormat::Plain, "out.txt"), (OutputFormat::Sqlite, "out.db")] {
+            let path = dir.path().join(name);
+            let mut sink = output::open_sink(&path, format, &metadata).unwrap();
+            for record in &records {
+                sink.on_result(record).unwrap();
+            }
+            sink.on_summary(&ScanSummary::default()).unwrap();
+            drop(sink);
+            let text = String::from_utf8_lossy(&fs::read(&path).unwrap()).into_owned();
+            assert!(text.contains(&anon.ip(ip("10.20.30.40")).to_string()) || format == OutputFormat::Sqlite, "{:?}", format);
+            assert_clean(name, &text);
+        }
+        let conn = output::open_database(&dir.path().join("out.db")).unwrap();
+        let column = |sql: &str| -> Vec<String> {
+            conn.prepare(sql).unwrap().query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect()
+        };
+        let hosts = column("SELECT host FROM scan_results");
+        assert_eq!(hosts.len(), 3);
+        assert_clean("SQLite", &hosts.join(" "));
+        assert_clean("SQLite 執行資訊", &column("SELECT metadata FROM scan_runs").join(" "));
+    }
+
+    #[test]
+    fn the_map_is_written_only_on_request() {
+        let anon = anonymizer();
+        let fake = anon.ip(ip("10.20.30.40"));
+        let name = anon.hostname("db01.corp.example");
+        let dir = TempDir::new("anonymize-map");
+        let path = dir.path().join("map.json");
+        assert!(!path.exists());
+        anon.write_map(&path).unwrap();
+        let map: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(map["addresses"]["10.20.30.40"], fake.to_string());
+        assert_eq!(map["hostnames"]["db01.corp.example"], name);
+    }
+}
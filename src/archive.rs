@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Component, Path};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::cli::ArchiveMember;
+use crate::layout::Layout;
+use crate::metadata::RunMetadata;
+use crate::output::ScanSummary;
+use crate::scanner::ScanRecord;
+use crate::signing;
+
+// 封存格式版本；成員或清單的意義改變時遞增，較新的封存拒絕開啟
+pub const FORMAT_VERSION: u32 = 1;
+
+// 封存中的清單檔
+const MANIFEST_NAME: &str = "manifest.json";
+
+// 單個成員的大小上限，避免解壓縮炸彈耗盡記憶體
+const MEMBER_LIMIT: u64 = 512 * 1024 * 1024;
+
+// zstd 壓縮等級
+const COMPRESSION_LEVEL: i32 = 10;
+
+impl ArchiveMember {
+    // 成員在封存中的檔名
+    pub fn file_name(self) -> &'static str {
+        match self {
+            ArchiveMember::Json => "report.json",
+            ArchiveMember::Ndjson => "results.ndjson",
+            ArchiveMember::Transcript => "transcript.txt",
+            ArchiveMember::Html => "report.html",
+        }
+    }
+}
+
+// 清單中的成員與校驗值
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemberEntry {
+    pub name: String,
+    pub size: u64,
+    // SHA-256 十六進位
+    pub sha256: String,
+}
+
+// manifest.json：格式版本、產生的版本與執行資訊、各成員的校驗值
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveManifest {
+    pub format_version: u32,
+    pub tool_version: String,
+    // 掃描的執行資訊 (同 --json 報告的 metadata)
+    pub metadata: serde_json::Value,
+    pub members: Vec<MemberEntry>,
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    signing::to_hex(&Sha256::digest(data))
+}
+
+// 寫入 .pscan：zstd 壓縮的 tar，清單放在第一個，其餘成員依序放入
+pub fn write(path: &Path, metadata: &RunMetadata, members: &[(ArchiveMember, Vec<u8>)]) -> Result<(), Box<dyn Error>> {
+    let manifest = ArchiveManifest {
+        format_version: FORMAT_VERSION,
+        tool_version: env!("CARGO_PKG_VERSION").to_string(),
+        metadata: serde_json::to_value(metadata)?,
+        members: members
+            .iter()
+            .map(|(member, data)| MemberEntry {
+                name: member.file_name().to_string(),
+                size: data.len() as u64,
+                sha256: sha256_hex(data),
+            })
+            .collect(),
+    };
+    let manifest = serde_json::to_vec_pretty(&manifest)?;
+
+    let failed = |e: io::Error| format!("無法寫入掃描封存 {}: {}", path.display(), e);
+    let file = File::create(path).map_err(failed)?;
+    let encoder = zstd::Encoder::new(BufWriter::new(file), COMPRESSION_LEVEL).map_err(failed)?;
+    let mut builder = tar::Builder::new(encoder);
+    let entries = std::iter::once((MANIFEST_NAME, manifest.as_slice()))
+        .chain(members.iter().map(|(member, data)| (member.file_name(), data.as_slice())));
+    for (name, data) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(metadata.started_at.max(0) as u64);
+        header.set_cksum();
+        builder.append_data(&mut header, name, data).map_err(failed)?;
+    }
+    let encoder = builder.into_inner().map_err(failed)?;
+    encoder.finish().map_err(failed)?.flush().map_err(failed)?;
+    Ok(())
+}
+
+// 成員名稱只能是單一的一般檔名；絕對路徑、.. 與子目錄都拒絕
+fn check_member_name(name: &Path) -> Result<String, String> {
+    let mut components = name.components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(file)), None) => file
+            .to_str()
+            .map(str::to_string)
+            .ok_or_else(|| format!("成員名稱 {} 不是有效的 UTF-8", name.display())),
+        _ => Err(format!("拒絕開啟：成員名稱 '{}' 含有路徑 (可能是路徑穿越)", name.display())),
+    }
+}
+
+// 讀取並驗證過的封存
+pub struct Archive {
+    pub manifest: ArchiveManifest,
+    members: BTreeMap<String, Vec<u8>>,
+}
+
+impl Archive {
+    // 解壓縮到記憶體；不寫入任何檔案
+    // 檢查成員名稱、格式版本、每個成員的大小與 SHA-256，清單以外的成員視為錯誤
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("無法開啟掃描封存 {}: {}", path.display(), e))?;
+        let invalid = |e: io::Error| format!("{} 不是有效的掃描封存: {}", path.display(), e);
+        let decoder = zstd::Decoder::new(file).map_err(invalid)?;
+        let mut tar = tar::Archive::new(decoder);
+
+        let mut members = BTreeMap::new();
+        for entry in tar.entries().map_err(invalid)? {
+            let entry = entry.map_err(invalid)?;
+            let name = check_member_name(&entry.path().map_err(invalid)?)?;
+            if !entry.header().entry_type().is_file() {
+                return Err(format!("拒絕開啟：成員 {} 不是一般檔案", name));
+            }
+            if members.contains_key(&name) {
+                return Err(format!("拒絕開啟：成員 {} 重複出現", name));
+            }
+            let mut data = Vec::new();
+            entry.take(MEMBER_LIMIT + 1).read_to_end(&mut data).map_err(invalid)?;
+            if data.len() as u64 > MEMBER_LIMIT {
+                return Err(format!("拒絕開啟：成員 {} 超過 {} MiB", name, MEMBER_LIMIT / 1024 / 1024));
+            }
+            members.insert(name, data);
+        }
+
+        let manifest = members
+            .remove(MANIFEST_NAME)
+            .ok_or_else(|| format!("{} 缺少 {}", path.display(), MANIFEST_NAME))?;
+        let manifest: ArchiveManifest =
+            serde_json::from_slice(&manifest).map_err(|e| format!("{} 格式錯誤: {}", MANIFEST_NAME, e))?;
+        if manifest.format_version > FORMAT_VERSION {
+            return Err(format!(
+                "封存格式版本 {} 比此版本支援的 {} 新 (由 v{} 產生)，請更新 portscanner",
+                manifest.format_version, FORMAT_VERSION, manifest.tool_version
+            ));
+        }
+        for entry in &manifest.members {
+            let data = members
+                .get(&entry.name)
+                .ok_or_else(|| format!("封存缺少清單中的成員 {}", entry.name))?;
+            if data.len() as u64 != entry.size || sha256_hex(data) != entry.sha256 {
+                return Err(format!("成員 {} 的校驗值不符，封存可能已損毀或被修改", entry.name));
+            }
+        }
+        if let Some(extra) = members.keys().find(|name| !manifest.members.iter().any(|m| &m.name == *name)) {
+            return Err(format!("拒絕開啟：成員 {} 不在清單中", extra));
+        }
+        Ok(Archive { manifest, members })
+    }
+
+    pub fn member(&self, member: ArchiveMember) -> Option<&[u8]> {
+        self.members.get(member.file_name()).map(Vec::as_slice)
+    }
+}
+
+// portscanner open：驗證封存後顯示摘要，或把指定成員原樣輸出到標準輸出
+pub fn run(path: &Path, show: Option<ArchiveMember>, screen: &Layout) -> Result<(), Box<dyn Error>> {
+    let archive = Archive::open(path)?;
+    if let Some(member) = show {
+        let data = archive
+            .member(member)
+            .ok_or_else(|| format!("封存中沒有 {}", member.file_name()))?;
+        io::stdout().write_all(data)?;
+        return Ok(());
+    }
+
+    let manifest = &archive.manifest;
+    println!("{}", format!("=== 掃描封存 {} ===", path.display()).bold());
+    println!("格式版本: {} (由 v{} 產生)", manifest.format_version, manifest.tool_version);
+    let field = |key: &str| manifest.metadata.get(key).and_then(serde_json::Value::as_str).unwrap_or("?").to_string();
+    println!("執行者: {}@{}", field("username"), field("hostname"));
+    println!("開始時間: {}", field("started"));
+    if let Some(command) = manifest.metadata.get("command_line").and_then(serde_json::Value::as_array) {
+        let words: Vec<&str> = command.iter().filter_map(serde_json::Value::as_str).collect();
+        println!("命令列: {}", words.join(" "));
+    }
+    println!("{}", "成員 (校驗值相符):".bold());
+    for entry in &manifest.members {
+        println!("  {:16} {:>10} bytes  sha256:{}", entry.name, entry.size, &entry.sha256[..16]);
+    }
+
+    if let Some(data) = archive.member(ArchiveMember::Ndjson) {
+        let mut summary = ScanSummary::new(10);
+        for (number, line) in String::from_utf8_lossy(data).lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: ScanRecord = serde_json::from_str(line)
+                .map_err(|e| format!("{} 第 {} 行格式錯誤: {}", ArchiveMember::Ndjson.file_name(), number + 1, e))?;
+            summary.add(&record);
+        }
+        crate::output::display_counts(&summary, screen);
+    }
+    println!(
+        "\n{}",
+        "以 --show json|ndjson|transcript|html 將成員輸出到標準輸出".dimmed()
+    );
+    Ok(())
+}
@@ -0,0 +1,560 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, SocketAddr};
+use colored::*;
+use regex::Regex;
+use reqwest::redirect::Policy;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::probes;
+use crate::scanner::ScanPlan;
+use crate::targets::TargetSpec;
+use crate::{vhost, PortInfo, ScanResult};
+
+// 政策檔的 [[assert]]：對可連線端口的內容斷言，同一項可以寫多個條件，例如
+//   [[assert]]
+//   port = 443
+//   cert_cn = "api.example.com"
+//   http_status = 200
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AssertionFile {
+    port: u16,
+    // 橫幅須符合的正規表示式
+    banner: Option<String>,
+    // 憑證主體 / 簽發者的 CN 須相等
+    cert_cn: Option<String>,
+    cert_issuer: Option<String>,
+    http_status: Option<u16>,
+    reason: Option<String>,
+}
+
+// 斷言的條件
+#[derive(Debug, Clone)]
+pub enum Check {
+    Banner(Regex),
+    CertCn(String),
+    CertIssuer(String),
+    HttpStatus(u16),
+}
+
+impl Check {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Check::Banner(_) => "banner",
+            Check::CertCn(_) => "cert_cn",
+            Check::CertIssuer(_) => "cert_issuer",
+            Check::HttpStatus(_) => "http_status",
+        }
+    }
+
+    // templates show 的一行，例如 "cert_cn = api.example.com"
+    pub fn describe(&self) -> String {
+        format!("{} = {}", self.name(), self.expected())
+    }
+
+    fn expected(&self) -> String {
+        match self {
+            Check::Banner(pattern) => pattern.as_str().to_string(),
+            Check::CertCn(name) | Check::CertIssuer(name) => name.clone(),
+            Check::HttpStatus(status) => status.to_string(),
+        }
+    }
+
+    fn needs_tls(&self) -> bool {
+        matches!(self, Check::CertCn(_) | Check::CertIssuer(_))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Assertion {
+    pub port: u16,
+    pub check: Check,
+    pub reason: Option<String>,
+}
+
+// 展開並驗證 [[assert]]；location 用於錯誤訊息
+pub fn compile(files: Vec<AssertionFile>, location: &str) -> Result<Vec<Assertion>, String> {
+    let mut assertions = Vec::new();
+    for (i, file) in files.into_iter().enumerate() {
+        if file.port == 0 {
+            return Err(format!("{}: [[assert]] #{} 的端口無效", location, i + 1));
+        }
+        let mut checks = Vec::new();
+        if let Some(pattern) = &file.banner {
+            let pattern = Regex::new(pattern)
+                .map_err(|e| format!("{}: [[assert]] #{} 的 banner 不是有效的正規表示式: {}", location, i + 1, e))?;
+            checks.push(Check::Banner(pattern));
+        }
+        checks.extend(file.cert_cn.map(Check::CertCn));
+        checks.extend(file.cert_issuer.map(Check::CertIssuer));
+        checks.extend(file.http_status.map(Check::HttpStatus));
+        if checks.is_empty() {
+            return Err(format!(
+                "{}: [[assert]] #{} 至少需要 banner、cert_cn、cert_issuer 或 http_status 其中之一",
+                location,
+                i + 1
+            ));
+        }
+        assertions.extend(checks.into_iter().map(|check| Assertion {
+            port: file.port,
+            check,
+            reason: file.reason.clone(),
+        }));
+    }
+    Ok(assertions)
+}
+
+// 斷言的結果；端口不可連線時無法評估，與斷言不符分開回報
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Passed,
+    Failed,
+    Unreachable,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct AssertionOutcome {
+    pub port: u16,
+    pub check: String,
+    pub expected: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub observed: Option<String>,
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+// 一個端口上觀察到的內容；只取得斷言需要的部分
+#[derive(Debug, Default)]
+struct Observation {
+    banner: Option<String>,
+    status: Option<u16>,
+    cert: Option<CertNames>,
+    // HTTP / TLS 請求失敗的原因
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct CertNames {
+    subject_cn: Option<String>,
+    issuer_cn: Option<String>,
+}
+
+// 比對一個條件與觀察到的內容；回傳 (觀察值, 是否符合)
+fn judge(check: &Check, seen: &Observation) -> (Option<String>, bool) {
+    let missing = |what: &str| seen.error.clone().map(|e| format!("{}: {}", what, e)).unwrap_or_else(|| what.to_string());
+    match check {
+        Check::Banner(pattern) => match &seen.banner {
+            Some(banner) => (Some(banner.clone()), pattern.is_match(banner)),
+            None => (Some("沒有橫幅".to_string()), false),
+        },
+        Check::CertCn(expected) => match seen.cert.as_ref().map(|c| c.subject_cn.clone()) {
+            Some(Some(cn)) => (Some(cn.clone()), cn.eq_ignore_ascii_case(expected)),
+            Some(None) => (Some("憑證沒有 CN".to_string()), false),
+            None => (Some(missing("沒有取得憑證")), false),
+        },
+        Check::CertIssuer(expected) => match seen.cert.as_ref().map(|c| c.issuer_cn.clone()) {
+            Some(Some(cn)) => (Some(cn.clone()), &cn == expected),
+            Some(None) => (Some("簽發者沒有 CN".to_string()), false),
+            None => (Some(missing("沒有取得憑證")), false),
+        },
+        Check::HttpStatus(expected) => match seen.status {
+            Some(status) => (Some(status.to_string()), status == *expected),
+            None => (Some(missing("沒有 HTTP 回應")), false),
+        },
+    }
+}
+
+// 目標位址對應的主機名稱，作為 SNI 與 Host
+fn host_names(targets: &[TargetSpec]) -> HashMap<IpAddr, String> {
+    targets
+        .iter()
+        .filter_map(|target| match target {
+            TargetSpec::Host { name, addr } if name.parse::<IpAddr>().is_err() => Some((*addr, name.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+// 以 HTTP(S) 請求取得狀態碼與對方憑證；斷言只比對欄位，不驗證憑證是否受信任
+async fn fetch(addr: SocketAddr, name: Option<&str>, tls: bool) -> Observation {
+    let host = match (name, addr.ip()) {
+        (Some(name), _) => name.to_string(),
+        (None, IpAddr::V6(ip)) => format!("[{}]", ip),
+        (None, IpAddr::V4(ip)) => ip.to_string(),
+    };
+    let mut builder = reqwest::Client::builder()
+        .timeout(vhost::WEB_TIMEOUT)
+        .redirect(Policy::none())
+        .danger_accept_invalid_certs(true)
+        .tls_info(true);
+    if let Some(name) = name {
+        builder = builder.resolve(name, addr);
+    }
+    let client = match builder.build() {
+        Ok(client) => client,
+        Err(e) => {
+            return Observation {
+                error: Some(e.to_string()),
+                ..Default::default()
+            }
+        }
+    };
+    let scheme = if tls { "https" } else { "http" };
+    match client.get(format!("{}://{}:{}/", scheme, host, addr.port())).send().await {
+        Ok(response) => Observation {
+            status: Some(response.status().as_u16()),
+            cert: response
+                .extensions()
+                .get::<reqwest::tls::TlsInfo>()
+                .and_then(|info| info.peer_certificate())
+                .map(der::names),
+            ..Default::default()
+        },
+        Err(e) => Observation {
+            error: Some(e.without_url().to_string()),
+            ..Default::default()
+        },
+    }
+}
+
+async fn observe(
+    plan: &ScanPlan,
+    host: IpAddr,
+    name: Option<&str>,
+    port: (&PortInfo, &ScanResult),
+    checks: &[&Assertion],
+) -> Observation {
+    let (port_info, result) = port;
+    let mut seen = Observation::default();
+    if checks.iter().any(|a| matches!(a.check, Check::Banner(_))) {
+        seen.banner = match &result.banner {
+            Some(banner) => Some(banner.text.clone()),
+            // 探測定義沒有涵蓋此端口時只讀取對方主動送出的內容
+            None => probes::passive(&plan.context, host, port_info.port, plan.timeouts.for_port(port_info)).await,
+        };
+    }
+    if checks.iter().any(|a| matches!(a.check, Check::HttpStatus(_)) || a.check.needs_tls()) {
+        let tls = checks.iter().any(|a| a.check.needs_tls()) || vhost::uses_tls(port_info);
+        let fetched = fetch(plan.context.socket_addr(host, port_info.port), name, tls).await;
+        seen.status = fetched.status;
+        seen.cert = fetched.cert;
+        seen.error = fetched.error;
+    }
+    seen
+}
+
+// 掃描後對每台主機評估斷言；不可連線的端口不再連線，狀態為 unreachable
+pub async fn evaluate(
+    plan: &ScanPlan,
+    assertions: &[Assertion],
+    results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>,
+) -> BTreeMap<IpAddr, Vec<AssertionOutcome>> {
+    let names = host_names(&plan.targets);
+    let mut ports: Vec<u16> = assertions.iter().map(|a| a.port).collect();
+    ports.sort_unstable();
+    ports.dedup();
+
+    let mut outcomes = BTreeMap::new();
+    for (host, host_results) in results {
+        let mut host_outcomes = Vec::new();
+        for &port in &ports {
+            let checks: Vec<&Assertion> = assertions.iter().filter(|a| a.port == port).collect();
+            let scanned = host_results.iter().find(|(info, _)| info.port == port);
+            let seen = match scanned {
+                Some(entry) if entry.1.outbound && entry.1.error.is_none() => {
+                    Some(observe(plan, *host, names.get(host).map(String::as_str), entry, &checks).await)
+                }
+                _ => None,
+            };
+            for assertion in checks {
+                let (observed, status) = match &seen {
+                    Some(seen) => match judge(&assertion.check, seen) {
+                        (observed, true) => (observed, Status::Passed),
+                        (observed, false) => (observed, Status::Failed),
+                    },
+                    None => (None, Status::Unreachable),
+                };
+                host_outcomes.push(AssertionOutcome {
+                    port,
+                    check: assertion.check.name().to_string(),
+                    expected: assertion.check.expected(),
+                    observed,
+                    status,
+                    reason: assertion.reason.clone(),
+                });
+            }
+        }
+        outcomes.insert(*host, host_outcomes);
+    }
+    outcomes
+}
+
+// 政策報告中未通過的斷言，一項一行，顯示預期值與觀察值
+pub fn describe(outcome: &AssertionOutcome) -> String {
+    let reason = outcome.reason.as_deref().map(|r| format!(" — {}", r)).unwrap_or_default();
+    match outcome.status {
+        Status::Unreachable => format!(
+            "{}{}",
+            format!("Port {:5} {} 無法評估：端口不可連線", outcome.port, outcome.check).yellow(),
+            reason.dimmed()
+        ),
+        _ => format!(
+            "{}{}",
+            format!(
+                "Port {:5} {} 不符：預期 {}，實際 {}",
+                outcome.port,
+                outcome.check,
+                outcome.expected,
+                outcome.observed.as_deref().unwrap_or("-")
+            )
+            .red(),
+            reason.dimmed()
+        ),
+    }
+}
+
+// 憑證 DER 的最小解析：只取出主體與簽發者的 CN
+mod der {
+    use super::CertNames;
+
+    // commonName 2.5.4.3
+    const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+
+    // (標籤, 內容, 剩餘)
+    fn read(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+        let (&tag, rest) = data.split_first()?;
+        let (&first, rest) = rest.split_first()?;
+        let (len, rest) = match first {
+            n if n < 0x80 => (n as usize, rest),
+            n => {
+                let count = (n & 0x7f) as usize;
+                if count == 0 || count > 4 || rest.len() < count {
+                    return None;
+                }
+                let len = rest[..count].iter().fold(0usize, |len, &b| (len << 8) | b as usize);
+                (len, &rest[count..])
+            }
+        };
+        (rest.len() >= len).then(|| (tag, &rest[..len], &rest[len..]))
+    }
+
+    // Name 中第一個 CN；字串型別 (UTF8String、PrintableString 等) 都以 UTF-8 讀取
+    fn common_name(name: &[u8]) -> Option<String> {
+        let mut sets = name;
+        while let Some((_, set, rest)) = read(sets) {
+            sets = rest;
+            let mut attributes = set;
+            while let Some((_, attribute, rest)) = read(attributes) {
+                attributes = rest;
+                let (tag, oid, value) = read(attribute)?;
+                if tag == 0x06 && oid == OID_COMMON_NAME {
+                    let (_, text, _) = read(value)?;
+                    return Some(String::from_utf8_lossy(text).into_owned());
+                }
+            }
+        }
+        None
+    }
+
+    // Certificate ::= SEQUENCE { tbsCertificate, ... }
+    // TBSCertificate ::= SEQUENCE { [0] version (可省略), serial, signature, issuer, validity, subject, ... }
+    pub fn names(cert: &[u8]) -> CertNames {
+        let parse = || -> Option<CertNames> {
+            let (_, certificate, _) = read(cert)?;
+            let (_, tbs, _) = read(certificate)?;
+            let (tag, _, mut rest) = read(tbs)?;
+            // 有版本欄位時再讀掉序號
+            if tag == 0xa0 {
+                rest = read(rest)?.2;
+            }
+            let (_, _, rest) = read(rest)?;
+            let (_, issuer, rest) = read(rest)?;
+            let (_, _, rest) = read(rest)?;
+            let (_, subject, _) = read(rest)?;
+            Some(CertNames {
+                subject_cn: common_name(subject),
+                issuer_cn: common_name(issuer),
+            })
+        };
+        parse().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::prober::fake::ScriptedProber;
+    use crate::probes::Banner;
+    use crate::testutil::{host, scan_result, scripted_plan};
+
+    #[derive(Deserialize)]
+    struct Policy {
+        #[serde(rename = "assert")]
+        assertions: Vec<AssertionFile>,
+    }
+
+    fn compiled(text: &str) -> Result<Vec<Assertion>, String> {
+        let policy: Policy = toml::from_str(text).map_err(|e| e.to_string())?;
+        compile(policy.assertions, "policy.toml")
+    }
+
+    fn seen(banner: Option<&str>, status: Option<u16>, cn: Option<(Option<&str>, Option<&str>)>, error: Option<&str>) -> Observation {
+        Observation {
+            banner: banner.map(str::to_string),
+            status,
+            cert: cn.map(|(subject, issuer)| CertNames { subject_cn: subject.map(str::to_string), issuer_cn: issuer.map(str::to_string) }),
+            error: error.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn each_condition_becomes_its_own_assertion() {
+        let assertions = compiled(
+            r#"
+            [[assert]]
+            port = 443
+            cert_cn = "api.example.com"
+            cert_issuer = "Example CA"
+            http_status = 200
+            reason = "對外 API"
+
+            [[assert]]
+            port = 22
+            banner = "^SSH-2\\.0-OpenSSH_9"
+            "#,
+        )
+        .unwrap();
+        let described: Vec<(u16, String)> = assertions.iter().map(|a| (a.port, a.check.describe())).collect();
+        assert_eq!(
+            described,
+            vec![
+                (443, "cert_cn = api.example.com".to_string()),
+                (443, "cert_issuer = Example CA".to_string()),
+                (443, "http_status = 200".to_string()),
+                (22, "banner = ^SSH-2\\.0-OpenSSH_9".to_string()),
+            ]
+        );
+        assert!(assertions[..3].iter().all(|a| a.reason.as_deref() == Some("對外 API")));
+        assert!(assertions[0].check.needs_tls() && !assertions[2].check.needs_tls());
+    }
+
+    #[test]
+    fn invalid_assertions_are_rejected() {
+        assert_eq!(compiled("[[assert]]\nport = 0\nhttp_status = 200").unwrap_err(), "policy.toml: [[assert]] #1 的端口無效");
+        assert_eq!(
+            compiled("[[assert]]\nport = 22\nreason = \"x\"").unwrap_err(),
+            "policy.toml: [[assert]] #1 至少需要 banner、cert_cn、cert_issuer 或 http_status 其中之一"
+        );
+        assert!(compiled("[[assert]]\nport = 22\nbanner = \"(\"").unwrap_err().starts_with("policy.toml: [[assert]] #1 的 banner 不是有效的正規表示式"));
+        assert!(compiled("[[assert]]\nport = 22\nhttp = 200").is_err());
+    }
+
+    #[test]
+    fn conditions_compare_observed_values() {
+        let banner = Check::Banner(Regex::new("^SSH-2\\.0-OpenSSH_9").unwrap());
+        let cn = Check::CertCn("api.example.com".to_string());
+        let issuer = Check::CertIssuer("Example CA".to_string());
+        let status = Check::HttpStatus(200);
+        let cert = Some((Some("API.example.com"), Some("example ca")));
+        let cases: Vec<(&Check, Observation, Option<&str>, bool)> = vec![
+            (&banner, seen(Some("SSH-2.0-OpenSSH_9.6p1"), None, None, None), Some("SSH-2.0-OpenSSH_9.6p1"), true),
+            (&banner, seen(Some("SSH-2.0-OpenSSH_8.9"), None, None, None), Some("SSH-2.0-OpenSSH_8.9"), false),
+            (&banner, seen(None, None, None, None), Some("沒有橫幅"), false),
+            // CN 不分大小寫，簽發者要完全相同
+            (&cn, seen(None, Some(200), cert, None), Some("API.example.com"), true),
+            (&issuer, seen(None, Some(200), cert, None), Some("example ca"), false),
+            (&cn, seen(None, Some(200), Some((None, None)), None), Some("憑證沒有 CN"), false),
+            (&issuer, seen(None, Some(200), Some((None, None)), None), Some("簽發者沒有 CN"), false),
+            (&cn, seen(None, None, None, Some("connection reset")), Some("沒有取得憑證: connection reset"), false),
+            (&status, seen(None, Some(200), None, None), Some("200"), true),
+            (&status, seen(None, Some(302), None, None), Some("302"), false),
+            (&status, seen(None, None, None, None), Some("沒有 HTTP 回應"), false),
+            (&status, seen(None, None, None, Some("timed out")), Some("沒有 HTTP 回應: timed out"), false),
+        ];
+        for (check, observation, observed, passed) in cases {
+            assert_eq!(judge(check, &observation), (observed.map(str::to_string), passed), "{} {:?}", check.describe(), observation);
+        }
+    }
+
+    // DER 的 TLV
+    fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        match content.len() {
+            n if n < 0x80 => out.push(n as u8),
+            n => out.extend([0x82, (n >> 8) as u8, n as u8]),
+        }
+        out.extend_from_slice(content);
+        out
+    }
+
+    fn name(attributes: &[(&[u8], &str)]) -> Vec<u8> {
+        let sets: Vec<u8> = attributes
+            .iter()
+            .flat_map(|(oid, value)| tlv(0x31, &tlv(0x30, &[tlv(0x06, oid), tlv(0x0c, value.as_bytes())].concat())))
+            .collect();
+        tlv(0x30, &sets)
+    }
+
+    fn certificate(version: bool, issuer: &[u8], subject: &[u8]) -> Vec<u8> {
+        let mut tbs = Vec::new();
+        if version {
+            tbs.extend(tlv(0xa0, &tlv(0x02, &[2])));
+        }
+        tbs.extend(tlv(0x02, &[0x01, 0x23]));
+        tbs.extend(tlv(0x30, &tlv(0x06, &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02])));
+        tbs.extend(issuer);
+        tbs.extend(tlv(0x30, &[tlv(0x17, b"260101000000Z"), tlv(0x17, b"270101000000Z")].concat()));
+        tbs.extend(subject);
+        // 夠長的公鑰讓長度使用多位元組編碼
+        tbs.extend(tlv(0x30, &[0u8; 300]));
+        tlv(0x30, &[tlv(0x30, &tbs), tlv(0x30, &[]), tlv(0x03, &[0])].concat())
+    }
+
+    #[test]
+    fn certificate_names_are_read_from_der() {
+        const ORGANIZATION: &[u8] = &[0x55, 0x04, 0x0a];
+        const COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+        let issuer = name(&[(ORGANIZATION, "Example"), (COMMON_NAME, "Example CA")]);
+        let subject = name(&[(COMMON_NAME, "api.example.com")]);
+        for version in [true, false] {
+            let names = der::names(&certificate(version, &issuer, &subject));
+            assert_eq!(names.subject_cn.as_deref(), Some("api.example.com"));
+            assert_eq!(names.issuer_cn.as_deref(), Some("Example CA"));
+        }
+        let names = der::names(&certificate(true, &name(&[(ORGANIZATION, "Example")]), &subject));
+        assert_eq!(names.issuer_cn, None);
+        // 截斷的憑證不會 panic
+        let cert = certificate(true, &issuer, &subject);
+        assert_eq!(der::names(&cert[..40]), CertNames::default());
+        assert_eq!(der::names(&[]), CertNames::default());
+    }
+
+    #[tokio::test]
+    async fn unreachable_ports_are_reported_apart_from_failures() {
+        let assertions = compiled("[[assert]]\nport = 22\nbanner = \"^SSH-2\\\\.0-OpenSSH_9\"\n\n[[assert]]\nport = 25\nbanner = \"ESMTP\"\nreason = \"郵件閘道\"").unwrap();
+        let plan = scripted_plan(&[host(1), host(2)], &[22, 25], 1, Arc::new(ScriptedProber::new()));
+        let with_banner = |text: &str| {
+            let mut result = scan_result(true);
+            result.banner = Some(Banner { probe: "ssh".to_string(), service: None, version: None, text: text.to_string(), encoding: None, raw: None });
+            result
+        };
+        let results = BTreeMap::from([
+            (host(1), HashMap::from([(PortInfo::new(22, "SSH", "Remote"), with_banner("SSH-2.0-OpenSSH_9.6")), (PortInfo::new(25, "SMTP", "Mail"), scan_result(false))])),
+            (host(2), HashMap::from([(PortInfo::new(22, "SSH", "Remote"), with_banner("SSH-2.0-dropbear"))])),
+        ]);
+        let outcomes = evaluate(&plan, &assertions, &results).await;
+
+        let summary = |host| outcomes[&host].iter().map(|o| (o.port, o.status, o.observed.clone())).collect::<Vec<_>>();
+        assert_eq!(summary(host(1)), vec![(22, Status::Passed, Some("SSH-2.0-OpenSSH_9.6".to_string())), (25, Status::Unreachable, None)]);
+        // 沒有掃描的端口同樣無法評估
+        assert_eq!(summary(host(2)), vec![(22, Status::Failed, Some("SSH-2.0-dropbear".to_string())), (25, Status::Unreachable, None)]);
+
+        let failed = describe(&outcomes[&host(2)][0]);
+        assert!(failed.contains("Port    22 banner 不符：預期 ^SSH-2\\.0-OpenSSH_9，實際 SSH-2.0-dropbear"), "{}", failed);
+        let unreachable = describe(&outcomes[&host(1)][1]);
+        assert!(unreachable.contains("Port    25 banner 無法評估：端口不可連線") && unreachable.contains(" — 郵件閘道"), "{}", unreachable);
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use colored::*;
+use crate::checks::{CheckOutcome, CheckStatus};
+use crate::closure::Failure;
+use crate::probes::ProbeLibrary;
+use crate::targets::{ResolveFailure, TargetSpec};
+use crate::{PortInfo, ScanResult};
+
+// 一個目標各層的統計：TCP 連線結果與應用層探測 (橫幅、虛擬主機、服務檢查)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LayerCounts {
+    pub ports: usize,
+    pub connected: usize,
+    pub reset: usize,
+    pub timeout: usize,
+    pub unreachable: usize,
+    // 掃描端錯誤 (例如檔案描述符用盡)
+    pub errors: usize,
+    // 應用層探測的次數與其中有回應的次數
+    pub app_probes: usize,
+    pub app_ok: usize,
+}
+
+impl LayerCounts {
+    fn add_port(&mut self, result: &ScanResult, probes: Option<&ProbeLibrary>, port: u16) {
+        self.ports += 1;
+        match (&result.error, result.outbound, &result.failure) {
+            (Some(_), _, _) => self.errors += 1,
+            (None, true, _) => self.connected += 1,
+            (None, false, Some(Failure::Reset { .. })) => self.reset += 1,
+            (None, false, Some(Failure::Timeout)) => self.timeout += 1,
+            (None, false, Some(Failure::Unreachable | Failure::ProxyDenied { .. })) => self.unreachable += 1,
+            // 經由代理失敗等沒有分類的情況
+            (None, false, None) => {}
+        }
+        if !result.outbound {
+            return;
+        }
+        // 沒有適用探測的端口不算嘗試
+        if probes.is_some_and(|library| library.for_port(port).next().is_some()) {
+            self.app_probes += 1;
+            self.app_ok += usize::from(result.banner.is_some());
+        }
+        for vhost in &result.vhosts {
+            self.app_probes += 1;
+            self.app_ok += usize::from(vhost.error.is_none() && vhost.status.is_some());
+        }
+    }
+
+    fn add_check(&mut self, outcome: &CheckOutcome) {
+        self.app_probes += 1;
+        self.app_ok += usize::from(matches!(outcome.status, CheckStatus::Ok | CheckStatus::Warning));
+    }
+}
+
+// 目標失敗發生在哪一層
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Attribution {
+    // 附上解析錯誤
+    Dns(String),
+    // 沒有任何掃描結果 (例如網段中的位址都被排除)
+    NoResults,
+    ScanErrors,
+    TcpTimeout,
+    TcpUnreachable,
+    TcpRefused,
+    // 被拒、逾時與不可達混合，或經由代理失敗
+    TcpFailed,
+    Application,
+    Healthy { connected: usize },
+}
+
+// 依各層統計判斷；只有整層都失敗才歸因到該層
+pub fn classify(counts: &LayerCounts) -> Attribution {
+    let failed = counts.ports - counts.connected - counts.errors;
+    match counts {
+        LayerCounts { ports: 0, .. } => Attribution::NoResults,
+        LayerCounts { connected: 0, errors, .. } if *errors == counts.ports => Attribution::ScanErrors,
+        LayerCounts { connected: 0, timeout, .. } if *timeout == failed => Attribution::TcpTimeout,
+        LayerCounts { connected: 0, unreachable, .. } if *unreachable == failed => Attribution::TcpUnreachable,
+        LayerCounts { connected: 0, reset, .. } if *reset == failed => Attribution::TcpRefused,
+        LayerCounts { connected: 0, .. } => Attribution::TcpFailed,
+        LayerCounts { app_probes, app_ok: 0, .. } if *app_probes > 0 => Attribution::Application,
+        LayerCounts { connected, .. } => Attribution::Healthy { connected: *connected },
+    }
+}
+
+impl Attribution {
+    pub fn is_failure(&self) -> bool {
+        !matches!(self, Attribution::Healthy { .. })
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Attribution::Dns(error) => format!("DNS 解析失敗 ({})", error),
+            Attribution::NoResults => "沒有掃描結果".to_string(),
+            Attribution::ScanErrors => "掃描端錯誤 — 結果不代表目標狀態 (檔案描述符或臨時端口用盡)".to_string(),
+            Attribution::TcpTimeout => "TCP 全部逾時 — 疑似主機離線或被防火牆阻擋".to_string(),
+            Attribution::TcpUnreachable => "TCP 全部不可達 — 路由或主機不可達".to_string(),
+            Attribution::TcpRefused => "TCP 全部被拒 — 主機在線但掃描的端口都已關閉".to_string(),
+            Attribution::TcpFailed => "TCP 全部失敗 — 沒有任何端口可連線".to_string(),
+            Attribution::Application => "TCP 正常但服務探測失敗".to_string(),
+            Attribution::Healthy { connected } => format!("正常 ({} 個端口可連線)", connected),
+        }
+    }
+}
+
+// 每個目標的歸因；網段合併其中所有主機的統計，解析失敗的目標排在最後
+pub fn build(
+    targets: &[TargetSpec],
+    failures: &[ResolveFailure],
+    results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>,
+    checks: &[(IpAddr, Vec<CheckOutcome>)],
+    probes: Option<&ProbeLibrary>,
+) -> Vec<(String, Attribution)> {
+    let mut attributions = Vec::new();
+    for target in targets {
+        let belongs = |host: &IpAddr| match target {
+            TargetSpec::Host { addr, .. } => addr == host,
+            TargetSpec::Network(net, _) => net.contains(host),
+            TargetSpec::Unresolved(_) => false,
+        };
+        let mut counts = LayerCounts::default();
+        for (host, ports) in results.iter().filter(|(host, _)| belongs(host)) {
+            for (port, result) in ports {
+                counts.add_port(result, probes, port.port);
+            }
+            for (_, outcomes) in checks.iter().filter(|(addr, _)| addr == host) {
+                outcomes.iter().for_each(|outcome| counts.add_check(outcome));
+            }
+        }
+        attributions.push((target.label(), classify(&counts)));
+    }
+    for failure in failures {
+        attributions.push((failure.name.clone(), Attribution::Dns(failure.error.clone())));
+    }
+    attributions
+}
+
+// 有目標整層失敗時，每個目標顯示一行歸因
+pub fn display(attributions: &[(String, Attribution)]) {
+    if !attributions.iter().any(|(_, a)| a.is_failure()) {
+        return;
+    }
+    println!("\n{}", "=== 失敗歸因 ===".bold());
+    for (label, attribution) in attributions {
+        let text = attribution.describe();
+        match attribution.is_failure() {
+            true => println!("{} {}: {}", "✗".red(), label, text.red()),
+            false => println!("{} {}: {}", "✓".green(), label, text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::probes;
+    use crate::testutil::host;
+
+    fn result(value: serde_json::Value) -> ScanResult {
+        let mut fields = json!({ "inbound": false, "outbound": false });
+        fields.as_object_mut().unwrap().extend(value.as_object().unwrap().clone());
+        serde_json::from_value(fields).unwrap()
+    }
+
+    fn open() -> ScanResult {
+        result(json!({ "outbound": true }))
+    }
+
+    fn failed(kind: &str) -> ScanResult {
+        match kind {
+            "reset" => result(json!({ "failure": { "kind": "reset", "latency_ms": 0.3 } })),
+            kind => result(json!({ "failure": { "kind": kind } })),
+        }
+    }
+
+    fn counts(ports: usize, connected: usize, reset: usize, timeout: usize, unreachable: usize, errors: usize, app: (usize, usize)) -> LayerCounts {
+        LayerCounts { ports, connected, reset, timeout, unreachable, errors, app_probes: app.0, app_ok: app.1 }
+    }
+
+    #[test]
+    fn each_layer_is_blamed_only_when_it_failed_entirely() {
+        let cases = [
+            (counts(0, 0, 0, 0, 0, 0, (0, 0)), Attribution::NoResults),
+            (counts(5, 0, 0, 0, 0, 5, (0, 0)), Attribution::ScanErrors),
+            (counts(5, 0, 0, 5, 0, 0, (0, 0)), Attribution::TcpTimeout),
+            // 掃描端錯誤不影響其餘端口的判斷
+            (counts(5, 0, 0, 3, 0, 2, (0, 0)), Attribution::TcpTimeout),
+            (counts(5, 0, 0, 0, 5, 0, (0, 0)), Attribution::TcpUnreachable),
+            (counts(5, 0, 5, 0, 0, 0, (0, 0)), Attribution::TcpRefused),
+            (counts(5, 0, 2, 3, 0, 0, (0, 0)), Attribution::TcpFailed),
+            // 經由代理失敗：沒有分類
+            (counts(5, 0, 0, 0, 0, 0, (0, 0)), Attribution::TcpFailed),
+            (counts(5, 2, 0, 3, 0, 0, (3, 0)), Attribution::Application),
+            (counts(5, 2, 0, 3, 0, 0, (3, 1)), Attribution::Healthy { connected: 2 }),
+            // 沒有應用層探測時 TCP 正常即為正常
+            (counts(5, 1, 4, 0, 0, 0, (0, 0)), Attribution::Healthy { connected: 1 }),
+        ];
+        for (counts, expected) in cases {
+            assert_eq!(classify(&counts), expected, "{:?}", counts);
+        }
+    }
+
+    #[test]
+    fn descriptions_match_the_summary_wording() {
+        assert_eq!(Attribution::Dns("NXDOMAIN".to_string()).describe(), "DNS 解析失敗 (NXDOMAIN)");
+        assert_eq!(Attribution::TcpTimeout.describe(), "TCP 全部逾時 — 疑似主機離線或被防火牆阻擋");
+        assert_eq!(Attribution::Application.describe(), "TCP 正常但服務探測失敗");
+        assert!(!Attribution::Healthy { connected: 1 }.is_failure());
+        assert!(Attribution::NoResults.is_failure());
+    }
+
+    #[test]
+    fn port_results_are_counted_per_layer() {
+        let library = probes::load(None).unwrap();
+        let mut layer = LayerCounts::default();
+        layer.add_port(&failed("reset"), Some(&library), 22);
+        layer.add_port(&failed("timeout"), Some(&library), 22);
+        layer.add_port(&failed("unreachable"), Some(&library), 23);
+        layer.add_port(&result(json!({ "error": "address_in_use" })), Some(&library), 24);
+        // 沒有適用探測的端口不算應用層嘗試
+        layer.add_port(&open(), Some(&library), 9);
+        layer.add_port(&open(), Some(&library), 22);
+        layer.add_port(&result(json!({ "outbound": true, "banner": { "probe": "ssh", "text": "SSH-2.0" } })), Some(&library), 2222);
+        layer.add_port(&open(), None, 80);
+        layer.add_port(
+            &result(json!({ "outbound": true, "vhosts": [
+                { "name": "a.example", "tls": false, "status": 200, "cert_valid": null, "error": null },
+                { "name": "b.example", "tls": true, "status": null, "cert_valid": null, "error": "憑證無效" },
+            ] })),
+            None,
+            443,
+        );
+        layer.add_check(&CheckOutcome {
+            check: "ntp",
+            port: 123,
+            status: CheckStatus::NoResponse,
+            summary: String::new(),
+            details: Vec::new(),
+        });
+        assert_eq!(layer, counts(9, 5, 1, 1, 1, 1, (5, 2)));
+    }
+
+    #[test]
+    fn targets_aggregate_their_hosts_and_dns_failures_come_last() {
+        let targets = vec![
+            TargetSpec::Host { name: "web.example".to_string(), addr: host(1) },
+            TargetSpec::Network("192.0.2.8/29".parse().unwrap(), Default::default()),
+            TargetSpec::Host { name: "192.0.2.20".to_string(), addr: host(20) },
+        ];
+        let port = |p| PortInfo::new(p, "Test", "Test");
+        let mut results: BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>> = BTreeMap::new();
+        results.insert(host(1), HashMap::from([(port(22), open()), (port(23), failed("reset"))]));
+        // 網段中一台逾時、一台被拒：合併後為混合失敗
+        results.insert(host(9), HashMap::from([(port(22), failed("timeout"))]));
+        results.insert(host(10), HashMap::from([(port(22), failed("reset"))]));
+        let failures = vec![ResolveFailure {
+            name: "gone.example".to_string(),
+            error: "NXDOMAIN".to_string(),
+            code: crate::errors::ErrorCode::DnsResolutionFailed,
+        }];
+
+        let attributions = build(&targets, &failures, &results, &[], None);
+        assert_eq!(
+            attributions,
+            vec![
+                ("web.example (192.0.2.1)".to_string(), Attribution::Healthy { connected: 1 }),
+                ("192.0.2.8/29".to_string(), Attribution::TcpFailed),
+                ("192.0.2.20".to_string(), Attribution::NoResults),
+                ("gone.example".to_string(), Attribution::Dns("NXDOMAIN".to_string())),
+            ]
+        );
+
+        // 服務檢查全部沒有回應：歸因到應用層
+        let checks = vec![(host(1), vec![CheckOutcome {
+            check: "ntp",
+            port: 123,
+            status: CheckStatus::Error,
+            summary: String::new(),
+            details: Vec::new(),
+        }])];
+        let attributions = build(&targets[..1], &[], &results, &checks, None);
+        assert_eq!(attributions[0].1, Attribution::Application);
+    }
+}
@@ -0,0 +1,516 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::errors::{self, ErrorCode};
+use crate::metadata::RunMetadata;
+use crate::targets::{self, SafetyConfig, TargetSpec};
+use crate::{config, signing};
+use crate::{PortInfo, ScanResult};
+
+// 第一筆紀錄的 prev
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+// 每行結尾的雜湊欄位；雜湊涵蓋這個欄位之前的整筆紀錄
+const HASH_FIELD: &str = ",\"hash\":\"";
+
+// 紀錄中列出的目標上限，其餘只計數
+const MAX_LABELS: usize = 20;
+
+// 預設的稽核紀錄位置 (設定目錄下的 audit.jsonl)
+pub fn default_path() -> Option<PathBuf> {
+    config::config_dir().map(|dir| dir.join("audit.jsonl"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    // --watch、--monitor 等長時間執行的模式在開始時記錄
+    Started,
+    Completed,
+    // 按 q 中止
+    Aborted,
+    // 未通過授權確認
+    Refused,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TargetSummary {
+    pub hosts: u64,
+    pub labels: Vec<String>,
+    // 超過 MAX_LABELS 未列出的目標數
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub more: usize,
+    // 非私有位址的目標
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub public: Vec<String>,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+impl TargetSummary {
+    pub fn of(targets: &[TargetSpec]) -> Self {
+        let hosts: u128 = targets.iter().map(TargetSpec::host_count).sum();
+        TargetSummary {
+            hosts: u64::try_from(hosts).unwrap_or(u64::MAX),
+            labels: targets.iter().take(MAX_LABELS).map(TargetSpec::label).collect(),
+            more: targets.len().saturating_sub(MAX_LABELS),
+            public: targets::public_labels(targets),
+        }
+    }
+}
+
+// 結果的摘要與雜湊；--output 時為輸出檔的雜湊
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResultDigest {
+    pub probes: u64,
+    pub open: u64,
+    pub sha256: String,
+}
+
+// 依 (主機, 端口) 排序後序列化，相同的結果得到相同的雜湊
+pub fn digest_results(results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>) -> ResultDigest {
+    let mut records: Vec<(IpAddr, u16, &ScanResult)> = results
+        .iter()
+        .flat_map(|(host, ports)| ports.iter().map(|(port, result)| (*host, port.port, result)))
+        .collect();
+    records.sort_by_key(|(host, port, _)| (*host, *port));
+    let encoded = serde_json::to_vec(&records).unwrap_or_default();
+    ResultDigest {
+        probes: records.len() as u64,
+        open: records.iter().filter(|(_, _, result)| result.outbound).count() as u64,
+        sha256: signing::to_hex(&Sha256::digest(&encoded)),
+    }
+}
+
+pub fn digest_file(path: &Path, probes: u64, open: u64) -> io::Result<ResultDigest> {
+    Ok(ResultDigest { probes, open, sha256: signing::to_hex(&Sha256::digest(fs::read(path)?)) })
+}
+
+// 一次掃描的稽核紀錄；prev 為前一筆的雜湊
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Entry {
+    pub seq: u64,
+    pub timestamp: String,
+    // 執行資訊的掃描 ID；舊版的紀錄沒有
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scan_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub operator: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authorized_by: Option<String>,
+    pub arguments: Vec<String>,
+    pub targets: TargetSummary,
+    pub outcome: Outcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub results: Option<ResultDigest>,
+    pub prev: String,
+}
+
+fn sha256_hex(text: &str) -> String {
+    signing::to_hex(&Sha256::digest(text.as_bytes()))
+}
+
+// 一行紀錄：紀錄本身的 JSON 加上結尾的 hash 欄位
+fn encode(entry: &Entry) -> String {
+    let body = serde_json::to_string(entry).expect("audit entry serializes");
+    let hash = sha256_hex(&body);
+    format!("{}{}{}\"}}", &body[..body.len() - 1], HASH_FIELD, hash)
+}
+
+// 拆出雜湊涵蓋的內容與記錄的雜湊
+fn split_line(line: &str) -> Option<(String, &str)> {
+    let at = line.rfind(HASH_FIELD)?;
+    let hash = line[at + HASH_FIELD.len()..].strip_suffix("\"}")?;
+    Some((format!("{}}}", &line[..at]), hash))
+}
+
+// 最後一筆的序號與雜湊；最後一行損壞時以整行的雜湊接續，verify 會指出損壞的位置
+fn head(text: &str) -> (u64, String) {
+    let Some(line) = text.lines().rev().find(|line| !line.trim().is_empty()) else {
+        return (0, GENESIS.to_string());
+    };
+    match split_line(line).and_then(|(body, hash)| Some((serde_json::from_str::<Entry>(&body).ok()?, hash))) {
+        Some((entry, hash)) => (entry.seq + 1, hash.to_string()),
+        None => (text.lines().count() as u64, sha256_hex(line)),
+    }
+}
+
+// 寫入期間鎖住檔案，同時執行的掃描不會交錯或分岔
+#[cfg(unix)]
+fn lock(file: &File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    match unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(not(unix))]
+fn lock(_file: &File) -> io::Result<()> {
+    Ok(())
+}
+
+// 附加一筆紀錄；seq 與 prev 由檔案目前的最後一筆決定
+pub fn append(path: &Path, mut entry: Entry) -> io::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+    lock(&file)?;
+    let mut text = String::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_string(&mut text)?;
+    let (seq, prev) = head(&text);
+    entry.seq = seq;
+    entry.prev = prev;
+    let line = encode(&entry);
+    // 上一次寫入中斷時補上換行，新的紀錄仍從行首開始
+    let separator = if text.is_empty() || text.ends_with('\n') { "" } else { "\n" };
+    file.write_all(format!("{}{}\n", separator, line).as_bytes())?;
+    file.sync_data()
+}
+
+// 一次掃描的稽核資訊；掃描結束 (或拒絕、開始長時間模式) 時寫入一筆
+#[derive(Debug)]
+pub struct Audit {
+    path: PathBuf,
+    template: Entry,
+}
+
+impl Audit {
+    // 掃描前確認紀錄檔可以寫入，無法記錄時不掃描
+    pub fn open(path: PathBuf, metadata: &RunMetadata, targets: &[TargetSpec]) -> Result<Self, Box<dyn Error>> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|e| format!("無法建立稽核紀錄目錄 {}: {}", dir.display(), e))?;
+        }
+        OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&path)
+            .map_err(|e| format!("無法寫入稽核紀錄 {}: {}", path.display(), e))?;
+        let operator = match (&metadata.username, &metadata.hostname) {
+            (Some(user), Some(host)) => Some(format!("{}@{}", user, host)),
+            (user, host) => user.clone().or_else(|| host.clone()),
+        };
+        let template = Entry {
+            seq: 0,
+            timestamp: metadata.started.clone(),
+            scan_id: Some(metadata.scan_id.clone()),
+            operator,
+            authorized_by: metadata.authorized_by.clone(),
+            arguments: metadata.command_line.iter().skip(1).cloned().collect(),
+            targets: TargetSummary::of(targets),
+            outcome: Outcome::Started,
+            results: None,
+            prev: String::new(),
+        };
+        Ok(Audit { path, template })
+    }
+
+    // 寫入失敗時只警告；掃描已經完成，結果仍照常輸出
+    pub fn record(&self, outcome: Outcome, results: Option<ResultDigest>) {
+        let entry = Entry { outcome, results, ..self.template.clone() };
+        if let Err(e) = append(&self.path, entry) {
+            eprintln!("{}", format!("無法寫入稽核紀錄 {}: {}", self.path.display(), e).yellow());
+        }
+    }
+}
+
+// 設定檔 [safety] audit_log，未設定時為預設位置
+pub fn configured_path(safety: &SafetyConfig) -> Option<PathBuf> {
+    safety.audit_log.clone().or_else(default_path)
+}
+
+// 掃描非私有目標前的授權：有 --authorized-by (只能在命令列指定) 時直接記錄，否則在終端上確認
+pub fn authorize(targets: &[TargetSpec], authorized_by: Option<&str>, audit: Option<&Audit>) -> Result<(), Box<dyn Error>> {
+    let summary = TargetSummary::of(targets);
+    let stdin = io::stdin();
+    let interactive = stdin.is_terminal();
+    match refusal(&summary, authorized_by, interactive, &mut stdin.lock(), &mut io::stderr())? {
+        None => Ok(()),
+        Some(reason) => {
+            if let Some(audit) = audit {
+                audit.record(Outcome::Refused, None);
+            }
+            Err(errors::coded(ErrorCode::TargetRefused, reason))
+        }
+    }
+}
+
+// 授權確認的判斷；回傳拒絕的原因，None 表示可以掃描
+fn refusal(
+    summary: &TargetSummary,
+    authorized_by: Option<&str>,
+    interactive: bool,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> io::Result<Option<&'static str>> {
+    if summary.public.is_empty() {
+        return Ok(None);
+    }
+    match authorized_by.map(str::trim) {
+        Some("") => Ok(Some("--authorized-by 不能是空白，請記錄授權人或工單")),
+        Some(_) => Ok(None),
+        None if !interactive => Ok(Some("目標包含非私有位址；非互動執行時請以 --authorized-by 記錄授權人或工單")),
+        None => match confirm(summary, input, output)? {
+            true => Ok(None),
+            false => Ok(Some("輸入的主機數不符，已取消掃描")),
+        },
+    }
+}
+
+// 公網目標的授權確認：列出目標並要求輸入主機數；輸入正確時回傳 true
+pub fn confirm(summary: &TargetSummary, input: &mut impl BufRead, output: &mut impl Write) -> io::Result<bool> {
+    writeln!(output, "{}", "=== 掃描授權確認 ===".bold())?;
+    writeln!(output, "目標包含非私有位址，請確認已取得這些目標擁有者的授權：")?;
+    for label in &summary.public {
+        writeln!(output, "  {}", label)?;
+    }
+    writeln!(output, "共 {} 台主機 (其中非私有目標 {} 個)", summary.hosts, summary.public.len())?;
+    write!(output, "輸入目標主機數 ({}) 以確認，或以 --authorized-by 記錄授權人 / 工單: ", summary.hosts)?;
+    output.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(answer.trim() == summary.hosts.to_string())
+}
+
+// 檢查結果；broken 為第一個不符的行號與原因，之後的紀錄無法信任
+#[derive(Debug)]
+pub struct Verification {
+    pub entries: u64,
+    pub head: String,
+    pub broken: Option<(usize, String)>,
+}
+
+pub fn verify(text: &str) -> Verification {
+    let mut prev = GENESIS.to_string();
+    let mut entries = 0;
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let broken = |reason: String| Verification { entries, head: prev.clone(), broken: Some((number, reason)) };
+        let Some((body, hash)) = split_line(line) else {
+            return broken("缺少 hash 欄位".to_string());
+        };
+        if sha256_hex(&body) != hash {
+            return broken("內容與雜湊不符 (紀錄被修改)".to_string());
+        }
+        let entry: Entry = match serde_json::from_str(&body) {
+            Ok(entry) => entry,
+            Err(e) => return broken(format!("格式錯誤: {}", e)),
+        };
+        if entry.seq != entries {
+            return broken(format!("序號為 {}，應為 {} (紀錄被刪除或插入)", entry.seq, entries));
+        }
+        if entry.prev != prev {
+            return broken("prev 與前一筆的雜湊不符 (紀錄被刪除、插入或重排)".to_string());
+        }
+        prev = hash.to_string();
+        entries += 1;
+    }
+    Verification { entries, head: prev, broken: None }
+}
+
+// portscanner audit verify
+pub fn run_verify(path: &Path) -> Result<(), Box<dyn Error>> {
+    let text = fs::read_to_string(path).map_err(|e| format!("無法讀取稽核紀錄 {}: {}", path.display(), e))?;
+    let result = verify(&text);
+    match result.broken {
+        None => {
+            println!("{} {}: {} 筆紀錄，雜湊鏈完整", "✓".green(), path.display(), result.entries);
+            // 結尾被截斷無法從鏈本身看出；另外保存最新的雜湊即可比對
+            println!("最新雜湊: {}", result.head);
+            Ok(())
+        }
+        Some((line, reason)) => {
+            println!("{} {}: 第 {} 行 {}", "✗".red(), path.display(), line, reason);
+            println!("前 {} 筆紀錄完整，最後可信的雜湊: {}", result.entries, result.head);
+            Err("稽核紀錄的雜湊鏈不完整".into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+
+    fn summary(hosts: u64, public: &[&str]) -> TargetSummary {
+        TargetSummary {
+            hosts,
+            labels: public.iter().map(|label| label.to_string()).collect(),
+            more: 0,
+            public: public.iter().map(|label| label.to_string()).collect(),
+        }
+    }
+
+    fn entry(arguments: &[&str]) -> Entry {
+        Entry {
+            seq: 0,
+            timestamp: "2026-01-01T00:00:00Z".to_string(),
+            scan_id: Some("scan-1".to_string()),
+            operator: Some("alice@host".to_string()),
+            authorized_by: None,
+            arguments: arguments.iter().map(|arg| arg.to_string()).collect(),
+            targets: summary(1, &["8.8.8.8"]),
+            outcome: Outcome::Completed,
+            results: None,
+            prev: String::new(),
+        }
+    }
+
+    // 回傳拒絕原因與顯示給操作者的提示
+    fn decide(summary: &TargetSummary, authorized_by: Option<&str>, interactive: bool, answer: &str) -> (Option<&'static str>, String) {
+        let mut output = Vec::new();
+        let reason = refusal(summary, authorized_by, interactive, &mut answer.as_bytes(), &mut output).unwrap();
+        (reason, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn private_targets_need_no_confirmation() {
+        let (reason, prompt) = decide(&summary(256, &[]), None, false, "");
+        assert_eq!(reason, None);
+        assert!(prompt.is_empty());
+    }
+
+    #[test]
+    fn typed_host_count_confirms() {
+        let targets = summary(256, &["203.0.113.0/24"]);
+        let (reason, prompt) = decide(&targets, None, true, " 256 \n");
+        assert_eq!(reason, None);
+        assert!(prompt.contains("203.0.113.0/24"));
+        assert!(prompt.contains("共 256 台主機"));
+
+        for answer in ["255\n", "y\n", "\n", ""] {
+            let (reason, _) = decide(&targets, None, true, answer);
+            assert_eq!(reason, Some("輸入的主機數不符，已取消掃描"), "{:?}", answer);
+        }
+    }
+
+    #[test]
+    fn authorized_by_skips_the_prompt_unless_blank() {
+        let targets = summary(1, &["8.8.8.8"]);
+        assert_eq!(decide(&targets, Some("alice/SEC-1234"), false, ""), (None, String::new()));
+        let (reason, prompt) = decide(&targets, Some("  "), true, "1\n");
+        assert!(reason.unwrap().contains("不能是空白"));
+        assert!(prompt.is_empty());
+    }
+
+    #[test]
+    fn non_interactive_runs_are_refused_without_authorizer() {
+        let (reason, prompt) = decide(&summary(1, &["8.8.8.8"]), None, false, "1\n");
+        assert!(reason.unwrap().contains("--authorized-by"));
+        assert!(prompt.is_empty());
+    }
+
+    #[test]
+    fn refusals_are_recorded() {
+        let log = TempPath::new("audit.jsonl");
+        let audit = Audit { path: log.path().to_path_buf(), template: entry(&["--target", "8.8.8.8"]) };
+        audit.record(Outcome::Refused, None);
+        let text = fs::read_to_string(log.path()).unwrap();
+        assert!(text.contains("\"outcome\":\"refused\""));
+        assert!(text.contains("\"scan_id\":\"scan-1\""));
+        assert!(verify(&text).broken.is_none());
+    }
+
+    #[test]
+    fn appended_entries_form_a_chain() {
+        let log = TempPath::new("audit.jsonl");
+        for n in 0..3 {
+            append(log.path(), entry(&["--target", &n.to_string()])).unwrap();
+        }
+        let text = fs::read_to_string(log.path()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        let mut prev = GENESIS.to_string();
+        for (seq, line) in lines.iter().enumerate() {
+            let (body, hash) = split_line(line).unwrap();
+            let entry: Entry = serde_json::from_str(&body).unwrap();
+            assert_eq!(entry.seq, seq as u64);
+            assert_eq!(entry.prev, prev);
+            prev = hash.to_string();
+        }
+        let result = verify(&text);
+        assert_eq!((result.entries, result.head, result.broken), (3, prev, None));
+    }
+
+    #[test]
+    fn append_continues_after_a_torn_line() {
+        let log = TempPath::new("audit.jsonl");
+        append(log.path(), entry(&["first"])).unwrap();
+        let mut file = OpenOptions::new().append(true).open(log.path()).unwrap();
+        file.write_all(b"{\"seq\":1,\"timest").unwrap();
+        append(log.path(), entry(&["second"])).unwrap();
+
+        let text = fs::read_to_string(log.path()).unwrap();
+        assert_eq!(text.lines().count(), 3);
+        assert!(text.lines().nth(2).unwrap().contains("\"second\""));
+        // 損壞的那一行仍會被指出，不會被新的紀錄掩蓋
+        assert_eq!(verify(&text).broken.map(|(line, _)| line), Some(2));
+    }
+
+    fn chain(count: usize) -> Vec<String> {
+        let log = TempPath::new("audit.jsonl");
+        for n in 0..count {
+            append(log.path(), entry(&[&n.to_string()])).unwrap();
+        }
+        fs::read_to_string(log.path()).unwrap().lines().map(str::to_string).collect()
+    }
+
+    fn broken_at(lines: &[String]) -> Option<(usize, String)> {
+        verify(&lines.join("\n")).broken
+    }
+
+    #[test]
+    fn verify_detects_modification() {
+        let mut lines = chain(3);
+        lines[1] = lines[1].replace("alice@host", "mallory@host");
+        let (line, reason) = broken_at(&lines).unwrap();
+        assert_eq!(line, 2);
+        assert!(reason.contains("雜湊不符"));
+    }
+
+    #[test]
+    fn verify_detects_deleted_inserted_and_reordered_entries() {
+        let lines = chain(4);
+        let deleted = vec![lines[0].clone(), lines[2].clone(), lines[3].clone()];
+        assert_eq!(broken_at(&deleted).map(|(line, _)| line), Some(2));
+
+        let duplicated = vec![lines[0].clone(), lines[1].clone(), lines[1].clone()];
+        assert_eq!(broken_at(&duplicated).map(|(line, _)| line), Some(3));
+
+        let reordered = vec![lines[0].clone(), lines[2].clone(), lines[1].clone(), lines[3].clone()];
+        assert_eq!(broken_at(&reordered).map(|(line, _)| line), Some(2));
+
+        // 每筆都重新計算雜湊也無法偽造：prev 仍指向原本的前一筆
+        let mut forged = entry(&["forged"]);
+        forged.seq = 1;
+        forged.prev = GENESIS.to_string();
+        let replaced = vec![lines[0].clone(), encode(&forged)];
+        assert!(broken_at(&replaced).unwrap().1.contains("prev"));
+    }
+
+    #[test]
+    fn verify_reports_the_trusted_prefix() {
+        let mut lines = chain(3);
+        lines[2] = lines[2].replace(HASH_FIELD, ",\"h\":\"");
+        let result = verify(&lines.join("\n"));
+        assert_eq!(result.entries, 2);
+        assert_eq!(result.head, split_line(&lines[1]).unwrap().1);
+        assert_eq!(result.broken, Some((3, "缺少 hash 欄位".to_string())));
+        assert_eq!(verify("").entries, 0);
+        assert_eq!(verify("").head, GENESIS);
+    }
+}
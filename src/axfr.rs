@@ -0,0 +1,362 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use crate::dns::DnsCache;
+
+const DNS_PORT: u16 = 53;
+
+// 每次查詢、連線與讀取的逾時
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// 區域轉送的記錄上限，避免異常的伺服器無限送出
+const MAX_RECORDS: usize = 200_000;
+
+const TYPE_A: u16 = 1;
+const TYPE_NS: u16 = 2;
+const TYPE_CNAME: u16 = 5;
+const TYPE_SOA: u16 = 6;
+const TYPE_AAAA: u16 = 28;
+pub const TYPE_SRV: u16 = 33;
+const TYPE_AXFR: u16 = 252;
+const CLASS_IN: u16 = 1;
+
+// 回應中的一筆記錄；NS、CNAME 與 SRV 另外記下指向的名稱
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub name: String,
+    pub rtype: u16,
+    pub target: Option<String>,
+    // SRV 記錄的優先順序、權重與端口
+    pub srv: Option<Srv>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Srv {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+}
+
+#[derive(Debug)]
+pub struct Message {
+    id: u16,
+    pub rcode: u8,
+    // UDP 回應超過大小被截斷 (TC)，需改以 TCP 查詢
+    truncated: bool,
+    pub answers: Vec<Record>,
+}
+
+pub fn rcode_label(rcode: u8) -> String {
+    match rcode {
+        1 => "格式錯誤 (FORMERR)".to_string(),
+        2 => "伺服器錯誤 (SERVFAIL)".to_string(),
+        3 => "名稱不存在 (NXDOMAIN)".to_string(),
+        4 => "不支援 (NOTIMP)".to_string(),
+        5 => "拒絕區域轉送 (REFUSED)".to_string(),
+        9 => "不是此區域的權威伺服器 (NOTAUTH)".to_string(),
+        n => format!("回應代碼 {}", n),
+    }
+}
+
+fn query_id() -> u16 {
+    let mut id = [0u8; 2];
+    // 取不到亂數時仍可查詢，只是較容易被偽造回應
+    let _ = getrandom::getrandom(&mut id);
+    u16::from_be_bytes(id)
+}
+
+// 單一問題的查詢；recursion 為遞迴解析器設定 RD 位元
+fn query(id: u16, name: &str, qtype: u16, recursion: bool) -> Result<Vec<u8>, String> {
+    let mut msg = Vec::with_capacity(64);
+    msg.extend(id.to_be_bytes());
+    msg.extend(match recursion {
+        true => 0x0100u16,
+        false => 0,
+    }
+    .to_be_bytes());
+    msg.extend([0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(format!("無效的網域名稱: {}", name));
+        }
+        msg.push(label.len() as u8);
+        msg.extend(label.as_bytes());
+    }
+    msg.push(0);
+    msg.extend(qtype.to_be_bytes());
+    msg.extend(CLASS_IN.to_be_bytes());
+    Ok(msg)
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let bytes = self.buf.get(self.pos..self.pos + n).ok_or("DNS 回應過短")?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    // 名稱可能以指標 (壓縮) 指回訊息中較前面的位置
+    fn name(&mut self) -> Result<String, String> {
+        let mut labels = Vec::new();
+        let mut pos = self.pos;
+        let mut end = None;
+        for _ in 0..128 {
+            let len = *self.buf.get(pos).ok_or("DNS 回應過短")? as usize;
+            match len {
+                0 => {
+                    self.pos = end.unwrap_or(pos + 1);
+                    return Ok(labels.join(".").to_ascii_lowercase());
+                }
+                l if l & 0xC0 == 0xC0 => {
+                    let low = *self.buf.get(pos + 1).ok_or("DNS 回應過短")? as usize;
+                    end.get_or_insert(pos + 2);
+                    pos = ((l & 0x3F) << 8) | low;
+                }
+                l => {
+                    let label = self.buf.get(pos + 1..pos + 1 + l).ok_or("DNS 回應過短")?;
+                    labels.push(String::from_utf8_lossy(label).into_owned());
+                    pos += 1 + l;
+                }
+            }
+        }
+        Err("DNS 回應中的名稱指標形成迴圈".to_string())
+    }
+}
+
+fn parse(buf: &[u8]) -> Result<Message, String> {
+    let mut reader = Reader { buf, pos: 0 };
+    let id = reader.u16()?;
+    let flags = reader.u16()?;
+    let questions = reader.u16()?;
+    let answers = reader.u16()?;
+    reader.take(4)?;
+    for _ in 0..questions {
+        reader.name()?;
+        reader.take(4)?;
+    }
+    let mut records = Vec::with_capacity(answers as usize);
+    for _ in 0..answers {
+        let name = reader.name()?;
+        let rtype = reader.u16()?;
+        reader.take(6)?;
+        let length = reader.u16()? as usize;
+        let start = reader.pos;
+        let (target, srv) = match rtype {
+            TYPE_NS | TYPE_CNAME => (Some(reader.name()?), None),
+            TYPE_SRV => {
+                let srv = Srv { priority: reader.u16()?, weight: reader.u16()?, port: reader.u16()? };
+                (Some(reader.name()?), Some(srv))
+            }
+            _ => (None, None),
+        };
+        reader.pos = start;
+        reader.take(length)?;
+        records.push(Record { name, rtype, target, srv });
+    }
+    Ok(Message { id, rcode: (flags & 0x000F) as u8, truncated: flags & 0x0200 != 0, answers: records })
+}
+
+// /etc/resolv.conf 的第一個 nameserver
+fn system_resolver() -> Result<IpAddr, String> {
+    let text = fs::read_to_string("/etc/resolv.conf").map_err(|e| format!("無法讀取 /etc/resolv.conf: {}", e))?;
+    text.lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .filter_map(|addr| addr.trim().split('%').next()?.parse().ok())
+        .next()
+        .ok_or_else(|| "/etc/resolv.conf 中沒有 nameserver".to_string())
+}
+
+// 以系統的遞迴解析器查詢一種記錄；UDP 回應被截斷時改以 TCP 重新查詢
+pub async fn ask(name: &str, qtype: u16) -> Result<Message, String> {
+    let resolver = SocketAddr::new(system_resolver()?, DNS_PORT);
+    let bind: SocketAddr = match resolver {
+        SocketAddr::V4(_) => "0.0.0.0:0".parse().expect("static address"),
+        SocketAddr::V6(_) => "[::]:0".parse().expect("static address"),
+    };
+    let socket = UdpSocket::bind(bind).await.map_err(|e| e.to_string())?;
+    let id = query_id();
+    let request = query(id, name, qtype, true)?;
+    socket.send_to(&request, resolver).await.map_err(|e| e.to_string())?;
+    let mut buf = [0u8; 4096];
+    let message = loop {
+        let (n, from) = tokio::time::timeout(TIMEOUT, socket.recv_from(&mut buf))
+            .await
+            .map_err(|_| format!("{} 沒有回應 {} 的查詢", resolver.ip(), name))?
+            .map_err(|e| e.to_string())?;
+        // 只接受解析器對這次查詢的回應
+        if from == resolver {
+            let message = parse(&buf[..n])?;
+            if message.id == id {
+                break message;
+            }
+        }
+    };
+    if !message.truncated {
+        return Ok(message);
+    }
+
+    let mut stream = tokio::time::timeout(TIMEOUT, TcpStream::connect(resolver))
+        .await
+        .map_err(|_| "連線逾時".to_string())?
+        .map_err(|e| e.to_string())?;
+    let mut framed = (request.len() as u16).to_be_bytes().to_vec();
+    framed.extend(request);
+    stream.write_all(&framed).await.map_err(|e| e.to_string())?;
+    let buf = read_message(&mut stream).await?.ok_or_else(|| format!("{} 關閉了 TCP 查詢連線", resolver.ip()))?;
+    let message = parse(&buf)?;
+    match message.id == id {
+        true => Ok(message),
+        false => Err("回應的查詢 ID 不符".to_string()),
+    }
+}
+
+// 以系統的遞迴解析器查詢區域的 NS 記錄
+async fn nameservers(zone: &str) -> Result<Vec<String>, String> {
+    let message = ask(zone, TYPE_NS).await?;
+    if message.rcode != 0 {
+        return Err(format!("查詢 {} 的 NS 記錄失敗: {}", zone, rcode_label(message.rcode)));
+    }
+    let servers: Vec<String> =
+        message.answers.into_iter().filter(|r| r.rtype == TYPE_NS).filter_map(|r| r.target).collect();
+    match servers.is_empty() {
+        true => Err(format!("{} 沒有 NS 記錄 (不是區域的頂點?)", zone)),
+        false => Ok(servers),
+    }
+}
+
+async fn read_message(stream: &mut TcpStream) -> Result<Option<Vec<u8>>, String> {
+    let mut len = [0u8; 2];
+    match tokio::time::timeout(TIMEOUT, stream.read_exact(&mut len)).await {
+        Err(_) => return Err("讀取 DNS 回應逾時".to_string()),
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Ok(Err(e)) => return Err(e.to_string()),
+        Ok(Ok(_)) => {}
+    }
+    let mut buf = vec![0u8; u16::from_be_bytes(len) as usize];
+    tokio::time::timeout(TIMEOUT, stream.read_exact(&mut buf))
+        .await
+        .map_err(|_| "讀取 DNS 回應逾時".to_string())?
+        .map_err(|e| e.to_string())?;
+    Ok(Some(buf))
+}
+
+// 向一台伺服器要求 AXFR；回應以 SOA 開始、以同一個 SOA 結束
+async fn transfer(server: SocketAddr, zone: &str) -> Result<Vec<Record>, String> {
+    let mut stream = tokio::time::timeout(TIMEOUT, TcpStream::connect(server))
+        .await
+        .map_err(|_| "連線逾時".to_string())?
+        .map_err(|e| e.to_string())?;
+    let id = query_id();
+    let request = query(id, zone, TYPE_AXFR, false)?;
+    let mut framed = (request.len() as u16).to_be_bytes().to_vec();
+    framed.extend(request);
+    stream.write_all(&framed).await.map_err(|e| e.to_string())?;
+
+    let mut records: Vec<Record> = Vec::new();
+    let mut soa = 0;
+    while let Some(buf) = read_message(&mut stream).await? {
+        let message = parse(&buf)?;
+        if message.id != id {
+            return Err("回應的查詢 ID 不符".to_string());
+        }
+        if message.rcode != 0 {
+            return Err(rcode_label(message.rcode));
+        }
+        for record in message.answers {
+            if record.rtype == TYPE_SOA {
+                soa += 1;
+            }
+            records.push(record);
+        }
+        if soa >= 2 {
+            return Ok(records);
+        }
+        if records.len() > MAX_RECORDS {
+            return Err(format!("區域轉送超過 {} 筆記錄", MAX_RECORDS));
+        }
+    }
+    match records.is_empty() {
+        true => Err("伺服器關閉連線 (可能不允許區域轉送)".to_string()),
+        false => Err("區域轉送不完整".to_string()),
+    }
+}
+
+// 區域轉送取得的主機名稱 (有 A、AAAA 或 CNAME 記錄的名稱)；萬用字元記錄不列入
+// server 未指定時依序嘗試區域的每台 NS，全部失敗時回傳各伺服器的原因
+pub async fn zone_names(zone: &str, server: Option<&str>, dns: &DnsCache) -> Result<Vec<String>, String> {
+    let servers = match server {
+        Some(server) => vec![server.to_string()],
+        None => nameservers(zone).await?,
+    };
+    let mut errors = Vec::new();
+    for name in servers {
+        let addr = match dns.resolve(&name).await {
+            Ok(addr) => SocketAddr::new(addr, DNS_PORT),
+            Err(e) => {
+                errors.push(format!("{}: {}", name, e));
+                continue;
+            }
+        };
+        match transfer(addr, zone).await {
+            Ok(records) => {
+                let suffix = format!(".{}", zone);
+                let names: BTreeSet<String> = records
+                    .into_iter()
+                    .filter(|r| matches!(r.rtype, TYPE_A | TYPE_AAAA | TYPE_CNAME))
+                    .map(|r| r.name)
+                    .filter(|name| (name == zone || name.ends_with(&suffix)) && !name.starts_with("*."))
+                    .collect();
+                return Ok(names.into_iter().collect());
+            }
+            Err(e) => errors.push(format!("{}: {}", name, e)),
+        }
+    }
+    Err(errors.join("；"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srv_answers_carry_priority_weight_port_and_target() {
+        let mut response = query(0x1234, "_ldap._tcp.example.com", TYPE_SRV, true).unwrap();
+        // QR、RD、RA，一個回答
+        response[2..8].copy_from_slice(&[0x81, 0x80, 0, 1, 0, 1]);
+        // 名稱指向問題 (偏移 12)，TTL 300
+        response.extend([0xC0, 12]);
+        response.extend(TYPE_SRV.to_be_bytes());
+        response.extend(CLASS_IN.to_be_bytes());
+        response.extend(300u32.to_be_bytes());
+        let mut rdata = vec![0, 10, 0, 50, 0x01, 0x85];
+        rdata.extend(b"\x03dc1");
+        // 目標的網域部分壓縮指向問題中的 example.com (偏移 12 + 6 + 5)
+        rdata.extend([0xC0, 23]);
+        response.extend((rdata.len() as u16).to_be_bytes());
+        response.extend(rdata);
+
+        let message = parse(&response).unwrap();
+        assert_eq!((message.id, message.rcode, message.truncated), (0x1234, 0, false));
+        let record = &message.answers[0];
+        assert_eq!(record.name, "_ldap._tcp.example.com");
+        assert_eq!(record.target.as_deref(), Some("dc1.example.com"));
+        assert_eq!(record.srv, Some(Srv { priority: 10, weight: 50, port: 389 }));
+
+        // 截斷的回應 (TC) 需要改以 TCP 查詢
+        response[2] |= 0x02;
+        assert!(parse(&response).unwrap().truncated);
+    }
+}
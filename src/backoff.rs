@@ -0,0 +1,335 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use colored::*;
+use rusqlite::{Connection, TransactionBehavior};
+use crate::closure::Failure;
+use crate::output;
+use crate::targets::TargetSpec;
+use crate::timefmt;
+use crate::{PortInfo, ScanResult};
+
+// 退避的設定 ([watch] backoff_after / backoff_max)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    // 連續幾次完全沒有回應後開始退避；0 表示不退避
+    pub after: u32,
+    // 退避時間的上限
+    pub max: Duration,
+}
+
+// 單一目標：連續完全失敗的次數與退避結束的時間
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TargetState {
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+// 一次掃描中目標的回應
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verdict {
+    // 任一端口可連線或回應 RST
+    Answered,
+    // 所有端口都逾時或不可達
+    Failed,
+    // 沒有結果或有掃描端錯誤，不改變計數
+    Unknown,
+}
+
+fn verdict(target: &TargetSpec, results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>) -> Verdict {
+    let mut verdict = Verdict::Unknown;
+    for result in results.iter().filter(|(host, _)| target.contains(**host)).flat_map(|(_, ports)| ports.values()) {
+        if result.error.is_some() {
+            return Verdict::Unknown;
+        }
+        if result.outbound || matches!(result.failure, Some(Failure::Reset { .. })) {
+            return Verdict::Answered;
+        }
+        verdict = Verdict::Failed;
+    }
+    verdict
+}
+
+// 這次掃描略過的目標
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Skipped {
+    pub target: String,
+    pub failures: u32,
+    pub retry_in: Duration,
+}
+
+impl Skipped {
+    // 例如 "連續 12 次失敗，退避至 1h 0m 後重試"
+    pub fn describe(&self) -> String {
+        format!("連續 {} 次失敗，退避至 {} 後重試", self.failures, timefmt::duration(self.retry_in))
+    }
+}
+
+// watch 的目標退避：連續完全沒有回應的目標略過逐次加倍的時間 (從掃描間隔開始，不超過上限)
+// 目標回應或收到 SIGHUP 時立即恢復；有 --backoff-db 時狀態跨越重新啟動保留
+pub struct Backoff {
+    policy: Policy,
+    interval: Duration,
+    states: BTreeMap<String, TargetState>,
+    db: Option<PathBuf>,
+}
+
+impl Backoff {
+    pub fn new(policy: Policy, interval: Duration) -> Self {
+        Backoff { policy, interval, states: BTreeMap::new(), db: None }
+    }
+
+    // 讀取 --backoff-db 記錄的狀態；reset 時 (--reset-backoff) 清除記錄
+    pub fn open(policy: Policy, interval: Duration, db: &Path, reset: bool) -> Result<Self, String> {
+        let mut backoff = Backoff { db: Some(db.to_path_buf()), ..Backoff::new(policy, interval) };
+        match reset {
+            true => backoff.persist()?,
+            false => backoff.load(timefmt::now(), Instant::now())?,
+        }
+        Ok(backoff)
+    }
+
+    // 第 failures 次連續失敗後的退避時間
+    fn delay(&self, failures: u32) -> Option<Duration> {
+        if self.policy.after == 0 || failures < self.policy.after {
+            return None;
+        }
+        let doublings = (failures - self.policy.after).min(31);
+        Some(self.interval.saturating_mul(1 << doublings).min(self.policy.max))
+    }
+
+    // 分成這次要掃描的目標與退避中略過的目標
+    pub fn partition(&self, targets: &[TargetSpec], now: Instant) -> (Vec<TargetSpec>, Vec<Skipped>) {
+        let (mut due, mut skipped) = (Vec::new(), Vec::new());
+        for target in targets {
+            let label = target.label();
+            match self.states.get(&label).and_then(|state| Some((state.failures, state.retry_at?))) {
+                Some((failures, retry_at)) if retry_at > now => {
+                    skipped.push(Skipped { target: label, failures, retry_in: retry_at - now })
+                }
+                _ => due.push(target.clone()),
+            }
+        }
+        (due, skipped)
+    }
+
+    // 記錄掃描過的目標的結果
+    pub fn record(&mut self, scanned: &[TargetSpec], results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>, now: Instant) {
+        for target in scanned {
+            let label = target.label();
+            match verdict(target, results) {
+                Verdict::Answered => {
+                    self.states.remove(&label);
+                }
+                Verdict::Failed => {
+                    let failures = self.states.get(&label).map_or(0, |state| state.failures) + 1;
+                    let retry_at = self.delay(failures).map(|delay| now + delay);
+                    self.states.insert(label, TargetState { failures, retry_at });
+                }
+                Verdict::Unknown => {}
+            }
+        }
+    }
+
+    // SIGHUP：所有目標立即恢復掃描
+    pub fn reset(&mut self) {
+        self.states.clear();
+    }
+
+    // 退避結束時間以牆上時間記錄；讀回時不超過上限，系統時間倒退也不會退避更久
+    fn load(&mut self, now: i64, monotonic: Instant) -> Result<(), String> {
+        let Some(db) = &self.db else {
+            return Ok(());
+        };
+        let conn = output::open_database(db).map_err(|e| format!("無法開啟退避資料庫 {}: {}", db.display(), e))?;
+        let rows: Vec<(String, u32, Option<i64>)> = conn
+            .prepare("SELECT target, failures, retry_at FROM target_backoff")
+            .and_then(|mut stmt| stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?.collect())
+            .map_err(|e| format!("無法讀取退避資料庫 {}: {}", db.display(), e))?;
+        for (target, failures, retry_at) in rows {
+            let retry_at = retry_at.map(|at| {
+                let remaining = Duration::from_secs(at.saturating_sub(now).max(0) as u64);
+                monotonic + remaining.min(self.policy.max)
+            });
+            self.states.insert(target, TargetState { failures, retry_at });
+        }
+        Ok(())
+    }
+
+    // 寫入 --backoff-db；沒有指定時不做事
+    pub fn persist(&self) -> Result<(), String> {
+        let Some(db) = &self.db else {
+            return Ok(());
+        };
+        let mut conn = output::open_database(db).map_err(|e| format!("無法開啟退避資料庫 {}: {}", db.display(), e))?;
+        self.save(&mut conn, timefmt::now(), Instant::now()).map_err(|e| format!("無法寫入退避資料庫 {}: {}", db.display(), e))
+    }
+
+    fn save(&self, conn: &mut Connection, now: i64, monotonic: Instant) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        output::with_retry(|| {
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            tx.execute("DELETE FROM target_backoff", [])?;
+            for (target, state) in &self.states {
+                let retry_at = state.retry_at.map(|at| now + at.saturating_duration_since(monotonic).as_secs() as i64);
+                tx.execute(
+                    "INSERT INTO target_backoff (target, failures, retry_at, updated_at) VALUES (?1, ?2, ?3, ?4)",
+                    rusqlite::params![target, state.failures, retry_at, now],
+                )?;
+            }
+            tx.commit()
+        })
+    }
+}
+
+// 每次 watch 掃描列出略過的目標
+pub fn display(skipped: &[Skipped]) {
+    if skipped.is_empty() {
+        return;
+    }
+    println!("{}", format!("退避中略過 {} 個目標:", skipped.len()).yellow());
+    for skip in skipped {
+        println!("  {} {}", skip.target, skip.describe().dimmed());
+    }
+}
+
+// 重新載入的信號 (Unix 的 SIGHUP)；其他平台永遠不會收到
+pub struct Reload(#[cfg(unix)] Option<tokio::signal::unix::Signal>);
+
+impl Reload {
+    #[cfg(unix)]
+    pub fn listen() -> Self {
+        Reload(tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).ok())
+    }
+
+    #[cfg(not(unix))]
+    pub fn listen() -> Self {
+        Reload()
+    }
+
+    pub async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.0 {
+            signal.recv().await;
+            return;
+        }
+        std::future::pending::<()>().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{host, scan_result, TempDir};
+
+    const INTERVAL: Duration = Duration::from_secs(300);
+    const POLICY: Policy = Policy { after: 3, max: Duration::from_secs(3600) };
+
+    fn target(n: u8) -> TargetSpec {
+        TargetSpec::Host { name: host(n).to_string(), addr: host(n) }
+    }
+
+    fn results(n: u8, result: ScanResult) -> BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>> {
+        BTreeMap::from([(host(n), HashMap::from([(PortInfo::new(22, "SSH", "Remote"), result)]))])
+    }
+
+    fn timeout() -> ScanResult {
+        ScanResult { failure: Some(Failure::Timeout), ..scan_result(false) }
+    }
+
+    // 每次掃描都失敗：門檻後依間隔加倍，達到上限後固定
+    fn fail(backoff: &mut Backoff, times: u32, now: Instant) {
+        for _ in 0..times {
+            backoff.record(&[target(1)], &results(1, timeout()), now);
+        }
+    }
+
+    #[test]
+    fn delays_double_from_the_interval_up_to_the_cap() {
+        let backoff = Backoff::new(POLICY, INTERVAL);
+        let delays: Vec<Option<u64>> = (1..=8).map(|n| backoff.delay(n).map(|d| d.as_secs())).collect();
+        assert_eq!(delays, vec![None, None, Some(300), Some(600), Some(1200), Some(2400), Some(3600), Some(3600)]);
+        assert_eq!(backoff.delay(u32::MAX), Some(POLICY.max));
+        assert_eq!(Backoff::new(Policy { after: 0, ..POLICY }, INTERVAL).delay(100), None);
+    }
+
+    #[test]
+    fn failing_targets_are_skipped_until_the_backoff_ends() {
+        let now = Instant::now();
+        let mut backoff = Backoff::new(POLICY, INTERVAL);
+        let targets = [target(1), target(2)];
+        fail(&mut backoff, 2, now);
+        assert_eq!(backoff.partition(&targets, now).0.len(), 2);
+
+        fail(&mut backoff, 10, now);
+        let (due, skipped) = backoff.partition(&targets, now);
+        assert_eq!(due.iter().map(TargetSpec::label).collect::<Vec<_>>(), vec![host(2).to_string()]);
+        assert_eq!(skipped, vec![Skipped { target: host(1).to_string(), failures: 12, retry_in: POLICY.max }]);
+        assert_eq!(skipped[0].describe(), "連續 12 次失敗，退避至 1h 0m 後重試");
+        // 退避結束後再試一次
+        assert_eq!(backoff.partition(&targets, now + POLICY.max).0.len(), 2);
+    }
+
+    #[test]
+    fn answering_resets_the_backoff() {
+        let now = Instant::now();
+        let mut backoff = Backoff::new(POLICY, INTERVAL);
+        fail(&mut backoff, 5, now);
+        // 掃描端錯誤不算回應也不算失敗
+        let error = ScanResult { error: Some(crate::limits::ScanError::TooManyOpenFiles), ..timeout() };
+        backoff.record(&[target(1)], &results(1, error), now);
+        assert_eq!(backoff.partition(&[target(1)], now).1[0].failures, 5);
+        // RST 代表主機有回應
+        let refused = ScanResult { failure: Some(Failure::Reset { latency_ms: 1.0 }), ..scan_result(false) };
+        backoff.record(&[target(1)], &results(1, refused), now);
+        assert!(backoff.states.is_empty());
+        // 重新開始計數
+        fail(&mut backoff, 2, now);
+        assert!(backoff.partition(&[target(1)], now).1.is_empty());
+    }
+
+    #[test]
+    fn reload_resets_every_target() {
+        let now = Instant::now();
+        let mut backoff = Backoff::new(POLICY, INTERVAL);
+        fail(&mut backoff, 4, now);
+        backoff.record(&[target(2)], &results(2, timeout()), now);
+        backoff.reset();
+        assert!(backoff.states.is_empty());
+        assert_eq!(backoff.partition(&[target(1), target(2)], now).0.len(), 2);
+    }
+
+    #[test]
+    fn state_survives_restarts_through_the_database() {
+        let dir = TempDir::new("backoff");
+        let db = dir.path().join("history.db");
+        let mut backoff = Backoff::open(POLICY, INTERVAL, &db, false).unwrap();
+        fail(&mut backoff, 4, Instant::now());
+        backoff.persist().unwrap();
+
+        let restarted = Backoff::open(POLICY, INTERVAL, &db, false).unwrap();
+        let skipped = &restarted.partition(&[target(1)], Instant::now()).1[0];
+        assert_eq!(skipped.failures, 4);
+        assert!(skipped.retry_in > Duration::from_secs(590) && skipped.retry_in <= Duration::from_secs(600), "{:?}", skipped);
+
+        // --reset-backoff 清除記錄
+        Backoff::open(POLICY, INTERVAL, &db, true).unwrap();
+        assert!(Backoff::open(POLICY, INTERVAL, &db, false).unwrap().states.is_empty());
+    }
+
+    // 記錄的結束時間比上限還遠 (例如系統時間曾倒退) 時以上限為準
+    #[test]
+    fn loaded_backoffs_never_exceed_the_cap() {
+        let dir = TempDir::new("backoff-cap");
+        let db = dir.path().join("history.db");
+        let mut conn = output::open_database(&db).unwrap();
+        let mut backoff = Backoff::new(POLICY, INTERVAL);
+        let monotonic = Instant::now();
+        fail(&mut backoff, 7, monotonic);
+        backoff.save(&mut conn, 1_000_000 - 30 * 86400, monotonic).unwrap();
+
+        let mut restarted = Backoff { db: Some(db), ..Backoff::new(POLICY, INTERVAL) };
+        restarted.load(1_000_000 - 60 * 86400, monotonic).unwrap();
+        assert_eq!(restarted.partition(&[target(1)], monotonic).1[0].retry_in, POLICY.max);
+    }
+}
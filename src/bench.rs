@@ -0,0 +1,227 @@
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener as StdListener, TcpStream as StdStream};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use colored::*;
+use socket2::{Domain, Socket, Type};
+use tokio::net::TcpListener;
+use crate::output::csv_field;
+use crate::selftest::localhost_plan;
+use crate::timeouts::Timeouts;
+use crate::{timefmt, PortInfo};
+
+const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+// 確認黑洞端口會丟棄 SYN 的等待時間
+const BLACKHOLE_CHECK: Duration = Duration::from_millis(200);
+
+// 端口在測試場中的角色與預期結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    // 接受連線
+    Open,
+    // 沒有監聽，回應 RST
+    Closed,
+    // accept 佇列已滿，SYN 被丟棄 (逾時)
+    Blackhole,
+}
+
+// 本機測試場：監聽中的端口、關閉的端口與黑洞端口；結構存在期間端口保持原狀
+pub struct Farm {
+    ports: Vec<(u16, Role)>,
+    // 黑洞 listener 與填滿佇列的連線
+    _held: Vec<Socket>,
+    _fillers: Vec<StdStream>,
+    // 不支援黑洞端口 (佇列滿時仍回應) 的平台
+    pub blackhole_unsupported: bool,
+}
+
+impl Farm {
+    pub async fn build(open: usize, closed: usize, blackhole: usize) -> io::Result<Self> {
+        let mut ports = Vec::new();
+        for _ in 0..open {
+            let listener = TcpListener::bind((LOCALHOST, 0)).await?;
+            ports.push((listener.local_addr()?.port(), Role::Open));
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    drop(stream);
+                }
+            });
+        }
+        // 先綁定全部再一起釋放，避免拿到同一個端口
+        let reserved: Vec<StdListener> = (0..closed).map(|_| StdListener::bind((LOCALHOST, 0))).collect::<io::Result<_>>()?;
+        for listener in reserved {
+            ports.push((listener.local_addr()?.port(), Role::Closed));
+        }
+
+        let mut farm = Farm { ports, _held: Vec::new(), _fillers: Vec::new(), blackhole_unsupported: false };
+        for _ in 0..blackhole {
+            match spawn_blackhole()? {
+                Some((port, listener, filler)) => {
+                    farm.ports.push((port, Role::Blackhole));
+                    farm._held.push(listener);
+                    farm._fillers.push(filler);
+                }
+                None => {
+                    farm.blackhole_unsupported = true;
+                    break;
+                }
+            }
+        }
+        Ok(farm)
+    }
+
+    fn count(&self, role: Role) -> usize {
+        self.ports.iter().filter(|(_, r)| *r == role).count()
+    }
+}
+
+// listen(0) 之後以一個連線填滿 accept 佇列，之後的 SYN 會被核心丟棄
+// 佇列滿時仍回應的平台回傳 None
+fn spawn_blackhole() -> io::Result<Option<(u16, Socket, StdStream)>> {
+    let listener = Socket::new(Domain::IPV4, Type::STREAM, None)?;
+    listener.bind(&SocketAddr::new(LOCALHOST, 0).into())?;
+    listener.listen(0)?;
+    let address = listener.local_addr()?.as_socket().ok_or_else(|| io::Error::other("無法取得 listener 位址"))?;
+    let filler = StdStream::connect_timeout(&address, Duration::from_secs(1))?;
+    match StdStream::connect_timeout(&address, BLACKHOLE_CHECK) {
+        Err(e) if e.kind() == io::ErrorKind::TimedOut || e.kind() == io::ErrorKind::WouldBlock => {
+            Ok(Some((address.port(), listener, filler)))
+        }
+        _ => Ok(None),
+    }
+}
+
+// 矩陣中一組設定的結果
+#[derive(Debug, Clone)]
+pub struct BenchRow {
+    pub concurrency: usize,
+    pub timeout_ms: u128,
+    pub probes: usize,
+    pub seconds: f64,
+    pub probes_per_sec: f64,
+    // 應為開放卻判斷為無法連線
+    pub false_closed: usize,
+    // 應為關閉或黑洞卻判斷為可連線
+    pub false_open: usize,
+}
+
+impl BenchRow {
+    fn accurate(&self) -> bool {
+        self.false_closed == 0 && self.false_open == 0
+    }
+}
+
+// 以一組設定掃描測試場，依各端口的角色計算誤判
+async fn run_once(farm: &Farm, concurrency: usize, timeout: Duration) -> BenchRow {
+    let ports: Vec<PortInfo> = farm.ports.iter().map(|(port, _)| PortInfo::new(*port, "Bench", "Bench")).collect();
+    let plan = localhost_plan(ports, concurrency, Timeouts::fixed(timeout), Vec::new());
+    let started = Instant::now();
+    let results = crate::perform_scan(&plan, None, true, false).await;
+    let seconds = started.elapsed().as_secs_f64();
+
+    let host_results = results.get(&LOCALHOST);
+    let mut row = BenchRow {
+        concurrency,
+        timeout_ms: timeout.as_millis(),
+        probes: farm.ports.len(),
+        seconds,
+        probes_per_sec: farm.ports.len() as f64 / seconds.max(f64::EPSILON),
+        false_closed: 0,
+        false_open: 0,
+    };
+    for (port, role) in &farm.ports {
+        let reachable = host_results
+            .and_then(|ports| ports.iter().find(|(info, _)| info.port == *port))
+            .is_some_and(|(_, result)| result.outbound);
+        match (role, reachable) {
+            (Role::Open, false) => row.false_closed += 1,
+            (Role::Closed | Role::Blackhole, true) => row.false_open += 1,
+            _ => {}
+        }
+    }
+    row
+}
+
+fn to_csv(rows: &[BenchRow]) -> String {
+    let mut out = String::from("version,concurrency,timeout_ms,probes,seconds,probes_per_sec,false_closed,false_open\n");
+    for row in rows {
+        out.push_str(&format!(
+            "{},{},{},{},{:.3},{:.1},{},{}\n",
+            csv_field(env!("CARGO_PKG_VERSION")),
+            row.concurrency,
+            row.timeout_ms,
+            row.probes,
+            row.seconds,
+            row.probes_per_sec,
+            row.false_closed,
+            row.false_open
+        ));
+    }
+    out
+}
+
+// portscanner bench：在本機建立測試場，以每組並發數與逾時掃描並比較吞吐量與準確度
+pub async fn run(
+    open: usize,
+    closed: usize,
+    blackhole: usize,
+    concurrency: &[usize],
+    timeouts: &[Duration],
+    output: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    println!("\n{}", "=== 掃描效能測試 ===".bold());
+    let farm = Farm::build(open, closed, blackhole).await.map_err(|e| format!("無法建立本機測試場: {}", e))?;
+    println!(
+        "測試場: {} 個開放、{} 個關閉、{} 個黑洞端口 (127.0.0.1)",
+        farm.count(Role::Open),
+        farm.count(Role::Closed),
+        farm.count(Role::Blackhole)
+    );
+    if farm.blackhole_unsupported {
+        println!("{}", "此平台的 accept 佇列滿時仍會回應，不測試黑洞端口".yellow());
+    }
+
+    let header = format!("{:>6} {:>8} {:>9} {:>10} {:>8} {:>8}", "並發", "逾時", "耗時", "探測/秒", "誤判關閉", "誤判開放");
+    println!("{}", header.bold());
+    let mut rows = Vec::new();
+    for &timeout in timeouts {
+        for &concurrency in concurrency {
+            let row = run_once(&farm, concurrency, timeout).await;
+            let line = format!(
+                "{:>6} {:>8} {:>9} {:>10.0} {:>8} {:>8}",
+                row.concurrency,
+                timefmt::duration(timeout),
+                timefmt::duration(Duration::from_secs_f64(row.seconds)),
+                row.probes_per_sec,
+                row.false_closed,
+                row.false_open
+            );
+            match row.accurate() {
+                true => println!("{}", line),
+                false => println!("{}", line.red()),
+            }
+            rows.push(row);
+        }
+    }
+
+    // 沒有誤判的設定中吞吐量最高者
+    if let Some(best) = rows.iter().filter(|row| row.accurate()).max_by(|a, b| a.probes_per_sec.total_cmp(&b.probes_per_sec)) {
+        println!(
+            "\n{} --concurrency {} --timeout {}ms (每秒 {:.0} 個探測，沒有誤判)",
+            "建議:".bold(),
+            best.concurrency,
+            best.timeout_ms,
+            best.probes_per_sec
+        );
+    } else {
+        println!("\n{}", "每組設定都有誤判，請降低並發數或增加逾時".yellow());
+    }
+    if let Some(path) = output {
+        fs::write(path, to_csv(&rows)).map_err(|e| format!("無法寫入 {}: {}", path.display(), e))?;
+        println!("結果已寫入 {}", path.display());
+    }
+    Ok(())
+}
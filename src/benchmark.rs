@@ -0,0 +1,308 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::scanner::ScanPlan;
+use crate::stats::median;
+use crate::{config, timefmt};
+
+// 每種設定保留的最近紀錄數
+const HISTORY_LIMIT: usize = 20;
+
+// 至少有幾次紀錄才以歷史資料估計
+const MIN_RUNS: usize = 3;
+
+// 探測數太少時固定開銷佔大部分時間，不列入紀錄
+const MIN_PROBES: u128 = 50;
+
+// 與中位數相差在此比例內視為持平
+const SAME_PACE: f64 = 0.1;
+
+// 預設位置：設定目錄下的 throughput.json
+pub fn default_path() -> Option<PathBuf> {
+    config::config_dir().map(|dir| dir.join("throughput.json"))
+}
+
+// 影響吞吐量的設定；相同設定的掃描才互相比較
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigKey {
+    pub timeout_ms: u128,
+    pub concurrency: usize,
+    pub adaptive: bool,
+    pub proxy: bool,
+}
+
+impl ConfigKey {
+    pub fn of(plan: &ScanPlan) -> Self {
+        ConfigKey {
+            timeout_ms: plan.timeouts.default.as_millis(),
+            concurrency: plan.concurrency,
+            adaptive: plan.adaptive.is_some(),
+            proxy: plan.proxy.is_some(),
+        }
+    }
+
+    // 檔案中的鍵，例如 "1000ms/c500/auto/proxy"
+    fn label(&self) -> String {
+        let mut label = format!("{}ms/c{}", self.timeout_ms, self.concurrency);
+        if self.adaptive {
+            label.push_str("/auto");
+        }
+        if self.proxy {
+            label.push_str("/proxy");
+        }
+        label
+    }
+}
+
+// 一次掃描的紀錄
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Run {
+    pub probes: u128,
+    pub seconds: f64,
+    // Unix 時間，只供檢視
+    pub at: u64,
+}
+
+impl Run {
+    fn rate(&self) -> f64 {
+        self.probes as f64 / self.seconds
+    }
+}
+
+// 依設定記錄的吞吐量；檔案不存在時為空，不影響掃描
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Store {
+    // ConfigKey::label -> 最近的紀錄 (舊的在前)
+    runs: BTreeMap<String, Vec<Run>>,
+}
+
+// 依歷史中位數估計的時間
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+pub struct Estimate {
+    pub seconds: f64,
+    // 估計所依據的掃描次數
+    pub runs: usize,
+}
+
+// 本次掃描與歷史中位數的比較
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Comparison {
+    pub rate: f64,
+    pub median: f64,
+    pub runs: usize,
+}
+
+impl Store {
+    // 讀取紀錄；檔案不存在時為空，格式錯誤時回傳錯誤讓呼叫端略過紀錄
+    pub fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Store::default());
+        }
+        let text = fs::read_to_string(path).map_err(|e| format!("無法讀取吞吐量紀錄 {}: {}", path.display(), e))?;
+        serde_json::from_str(&text).map_err(|e| format!("吞吐量紀錄 {} 格式錯誤: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("無法建立 {}: {}", dir.display(), e))?;
+        }
+        let text = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, text).map_err(|e| format!("無法寫入吞吐量紀錄 {}: {}", path.display(), e))
+    }
+
+    // 每秒探測數的中位數與紀錄數；紀錄不足 MIN_RUNS 次時為 None
+    fn median_rate(&self, key: &ConfigKey) -> Option<(f64, usize)> {
+        let runs = self.runs.get(&key.label())?;
+        if runs.len() < MIN_RUNS {
+            return None;
+        }
+        median(runs.iter().map(Run::rate).collect()).map(|rate| (rate, runs.len()))
+    }
+
+    // 探測數太少時以速率推算並不準確 (固定開銷為主)，與記錄的門檻相同
+    pub fn estimate(&self, key: &ConfigKey, probes: u128) -> Option<Estimate> {
+        if probes < MIN_PROBES {
+            return None;
+        }
+        let (rate, runs) = self.median_rate(key)?;
+        Some(Estimate {
+            seconds: probes as f64 / rate,
+            runs,
+        })
+    }
+
+    // 與加入本次之前的紀錄比較
+    pub fn compare(&self, key: &ConfigKey, probes: u128, elapsed: Duration) -> Option<Comparison> {
+        if probes < MIN_PROBES {
+            return None;
+        }
+        let (median, runs) = self.median_rate(key)?;
+        Some(Comparison {
+            rate: probes as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+            median,
+            runs,
+        })
+    }
+
+    // 加入一次紀錄；探測數太少或時間為零時回傳 false 不記錄
+    pub fn record(&mut self, key: &ConfigKey, probes: u128, elapsed: Duration, at: SystemTime) -> bool {
+        if probes < MIN_PROBES || elapsed.is_zero() {
+            return false;
+        }
+        let runs = self.runs.entry(key.label()).or_default();
+        runs.push(Run {
+            probes,
+            seconds: elapsed.as_secs_f64(),
+            at: at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default(),
+        });
+        if runs.len() > HISTORY_LIMIT {
+            runs.drain(..runs.len() - HISTORY_LIMIT);
+        }
+        true
+    }
+}
+
+// 掃描開始與 dry-run 的估計，例如 "預計 4m 30s，基於過去 7 次掃描"
+pub fn describe_estimate(estimate: &Estimate) -> String {
+    format!(
+        "預計 {}，基於過去 {} 次掃描",
+        timefmt::duration(Duration::from_secs_f64(estimate.seconds)),
+        estimate.runs
+    )
+}
+
+pub fn display_comparison(comparison: &Comparison) {
+    let change = comparison.rate / comparison.median - 1.0;
+    let pace = match change {
+        c if c.abs() <= SAME_PACE => "與過去相近".normal(),
+        c if c > 0.0 => format!("比過去快 {:.0}%", c * 100.0).green(),
+        c => format!("比過去慢 {:.0}%", -c * 100.0).yellow(),
+    };
+    println!(
+        "吞吐量: 每秒 {:.0} 個探測，{} (過去 {} 次中位數 {:.0})",
+        comparison.rate, pace, comparison.runs, comparison.median
+    );
+}
+
+// 記錄吞吐量的檔案與讀到的內容；檔案有誤時不估計也不寫入
+pub struct History {
+    path: PathBuf,
+    store: Store,
+}
+
+impl History {
+    // 沒有設定目錄時為 None；格式錯誤時顯示警告後為 None
+    pub fn open() -> Option<Self> {
+        let path = default_path()?;
+        match Store::load(&path) {
+            Ok(store) => Some(History { path, store }),
+            Err(e) => {
+                eprintln!("{}", format!("{}，不估計也不記錄吞吐量", e).yellow());
+                None
+            }
+        }
+    }
+
+    pub fn estimate(&self, plan: &ScanPlan) -> Option<Estimate> {
+        self.store.estimate(&ConfigKey::of(plan), plan.remaining_probes())
+    }
+
+    // 掃描結束：與過去比較並寫入本次紀錄；回傳比較結果供顯示，中止的掃描不列入
+    pub fn finish(mut self, plan: &ScanPlan, elapsed: Duration) -> Option<Comparison> {
+        if plan.aborted() {
+            return None;
+        }
+        let key = ConfigKey::of(plan);
+        let probes = plan.remaining_probes();
+        let comparison = self.store.compare(&key, probes, elapsed);
+        if self.store.record(&key, probes, elapsed, SystemTime::now()) {
+            if let Err(e) = self.store.save(&self.path) {
+                eprintln!("{}", e.yellow());
+            }
+        }
+        comparison
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{TempDir, TempPath};
+
+    fn key(concurrency: usize) -> ConfigKey {
+        ConfigKey { timeout_ms: 1000, concurrency, adaptive: false, proxy: false }
+    }
+
+    // 以每秒 rate 個探測記錄 runs 次
+    fn store(runs: &[f64]) -> Store {
+        let mut store = Store::default();
+        for rate in runs {
+            assert!(store.record(&key(500), 1000, Duration::from_secs_f64(1000.0 / rate), UNIX_EPOCH));
+        }
+        store
+    }
+
+    #[test]
+    fn labels_name_the_configuration() {
+        assert_eq!(key(500).label(), "1000ms/c500");
+        let key = ConfigKey { adaptive: true, proxy: true, ..key(50) };
+        assert_eq!(key.label(), "1000ms/c50/auto/proxy");
+    }
+
+    #[test]
+    fn estimates_need_enough_runs_and_probes() {
+        assert_eq!(Store::default().estimate(&key(500), 10_000), None);
+        assert_eq!(store(&[100.0, 100.0]).estimate(&key(500), 10_000), None);
+        let store = store(&[100.0, 200.0, 400.0]);
+        assert_eq!(store.estimate(&key(500), 10_000), Some(Estimate { seconds: 50.0, runs: 3 }));
+        assert_eq!(store.estimate(&key(500), MIN_PROBES - 1), None);
+        assert!(store.estimate(&key(500), MIN_PROBES).is_some());
+        // 其他設定的紀錄不列入
+        assert_eq!(store.estimate(&key(100), 10_000), None);
+    }
+
+    #[test]
+    fn comparison_uses_history_before_this_run() {
+        let store = store(&[100.0, 200.0, 300.0]);
+        let comparison = store.compare(&key(500), 1000, Duration::from_secs(4)).unwrap();
+        assert_eq!(comparison, Comparison { rate: 250.0, median: 200.0, runs: 3 });
+        assert_eq!(store.compare(&key(500), MIN_PROBES - 1, Duration::from_secs(1)), None);
+    }
+
+    #[test]
+    fn small_or_instant_runs_are_not_recorded() {
+        let mut store = Store::default();
+        assert!(!store.record(&key(500), MIN_PROBES - 1, Duration::from_secs(1), UNIX_EPOCH));
+        assert!(!store.record(&key(500), 1000, Duration::ZERO, UNIX_EPOCH));
+        assert_eq!(store, Store::default());
+    }
+
+    #[test]
+    fn history_keeps_the_latest_runs() {
+        let rates: Vec<f64> = (1..=HISTORY_LIMIT as u32 + 5).map(f64::from).collect();
+        let store = store(&rates);
+        let runs = &store.runs[&key(500).label()];
+        assert_eq!(runs.len(), HISTORY_LIMIT);
+        assert!((runs[0].rate() - 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn missing_file_is_empty_and_saves_round_trip() {
+        let dir = TempDir::new("benchmark");
+        let path = dir.path().join("nested").join("throughput.json");
+        assert_eq!(Store::load(&path).unwrap(), Store::default());
+        let store = store(&[100.0, 200.0, 300.0]);
+        store.save(&path).unwrap();
+        assert_eq!(Store::load(&path).unwrap(), store);
+    }
+
+    #[test]
+    fn corrupt_file_is_an_error() {
+        let file = TempPath::with("throughput.json", "{ not json");
+        assert!(Store::load(file.path()).unwrap_err().contains("格式錯誤"));
+    }
+}
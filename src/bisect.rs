@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::net::IpAddr;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use colored::*;
+use serde::Serialize;
+use tokio::task::JoinSet;
+use crate::closure::Failure;
+use crate::portspec;
+use crate::scanner::{self, ScanPlan};
+
+// 預設的初始取樣數
+pub const DEFAULT_SAMPLES: usize = 64;
+
+// 區段判斷所用的端口狀態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PortState {
+    Open,
+    // 收到 RST
+    Closed,
+    // 逾時或不可達
+    Filtered,
+    // 掃描端錯誤，與目標無關
+    Error,
+}
+
+impl PortState {
+    fn label(self) -> ColoredString {
+        match self {
+            PortState::Open => "open".green(),
+            PortState::Closed => "closed".red(),
+            PortState::Filtered => "filtered".yellow(),
+            PortState::Error => "error".magenta(),
+        }
+    }
+}
+
+// 相同狀態的連續端口
+#[derive(Debug, Clone, Serialize)]
+pub struct Block {
+    pub start: u16,
+    pub end: u16,
+    pub state: PortState,
+    // 區段內實際探測的端口數，其餘為推論
+    pub probed: usize,
+}
+
+// 二分探測的結果
+#[derive(Debug, Clone, Default)]
+pub struct Bisection {
+    known: BTreeMap<u16, PortState>,
+    pub rounds: usize,
+}
+
+impl Bisection {
+    pub fn probes(&self) -> usize {
+        self.known.len()
+    }
+
+    // 依序把相同狀態的樣本合併成區段；相鄰區段的邊界都已精確到相差 1
+    pub fn blocks(&self) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::new();
+        for (&port, &state) in &self.known {
+            match blocks.last_mut() {
+                Some(block) if block.state == state => {
+                    block.end = port;
+                    block.probed += 1;
+                }
+                _ => blocks.push(Block { start: port, end: port, state, probed: 1 }),
+            }
+        }
+        blocks
+    }
+
+    // 相鄰樣本狀態不同且中間還有端口時，探測中點
+    fn midpoints(&self) -> Vec<u16> {
+        self.known
+            .iter()
+            .zip(self.known.iter().skip(1))
+            .filter(|((a, sa), (b, sb))| sa != sb && **b - **a > 1)
+            .map(|((a, _), (b, _))| a + (b - a) / 2)
+            .collect()
+    }
+}
+
+// 解析 --bisect 的範圍，例如 1-65535
+pub fn parse_range(s: &str) -> Result<RangeInclusive<u16>, String> {
+    if !s.contains('-') {
+        return Err(format!("範圍格式應為 起點-終點: {}", s));
+    }
+    portspec::parse_range(s)
+}
+
+// 初始取樣：均勻分布並包含兩端
+fn initial_samples(range: &RangeInclusive<u16>, samples: usize) -> Vec<u16> {
+    let (start, end) = (*range.start() as usize, *range.end() as usize);
+    let step = (end - start + 1).div_ceil(samples.max(2) - 1).max(1);
+    let mut ports: Vec<u16> = (start..=end).step_by(step).map(|p| p as u16).collect();
+    if ports.last() != Some(&(end as u16)) {
+        ports.push(end as u16);
+    }
+    ports
+}
+
+// 先稀疏取樣，再不斷細分狀態不同的相鄰樣本，直到每個邊界都精確到相鄰端口
+// 每輪至少讓所有待分區間減半，最多約 log2(範圍) 輪；狀態相同的樣本之間假設狀態一致
+pub async fn bisect<F, Fut>(range: RangeInclusive<u16>, samples: usize, concurrency: usize, probe: F) -> Bisection
+where
+    F: Fn(u16) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = PortState> + Send + 'static,
+{
+    let mut bisection = Bisection::default();
+    let mut pending = initial_samples(&range, samples);
+    while !pending.is_empty() {
+        bisection.rounds += 1;
+        for chunk in pending.chunks(concurrency.max(1)) {
+            let mut tasks = JoinSet::new();
+            for &port in chunk {
+                let probe = probe.clone();
+                tasks.spawn(async move { (port, probe(port).await) });
+            }
+            while let Some(Ok((port, state))) = tasks.join_next().await {
+                bisection.known.insert(port, state);
+            }
+        }
+        pending = bisection.midpoints();
+    }
+    bisection
+}
+
+// 單一端口的狀態；經由代理時只能區分可連線與否
+async fn probe_port(plan: &ScanPlan, host: IpAddr, port: u16) -> PortState {
+    let limit = plan.timeouts.default;
+    if let Some(proxy) = plan.proxy {
+        return match scanner::test_outbound_via_proxy(proxy, port, host, limit).await {
+            true => PortState::Open,
+            false => PortState::Filtered,
+        };
+    }
+    let outbound = plan.prober.connect(host, port, limit, plan.icmp.as_deref()).await;
+    match (outbound.connected, outbound.error, outbound.failure) {
+        (true, _, _) => PortState::Open,
+        (false, Some(_), _) => PortState::Error,
+        (false, None, Some(Failure::Reset { .. })) => PortState::Closed,
+        (false, None, _) => PortState::Filtered,
+    }
+}
+
+// 單一主機的區段報告
+#[derive(Debug, Serialize)]
+pub struct BisectReport {
+    pub host: IpAddr,
+    pub start: u16,
+    pub end: u16,
+    pub probes: usize,
+    // 完整掃描同一範圍所需的探測數
+    pub full_sweep: usize,
+    pub rounds: usize,
+    pub blocks: Vec<Block>,
+}
+
+// 對計劃中的每個主機執行二分探測
+pub async fn run(plan: &ScanPlan, range: RangeInclusive<u16>, samples: usize) -> Vec<BisectReport> {
+    let mut reports = Vec::new();
+    let shared = Arc::new(plan.clone());
+    for target in &plan.targets {
+        for host in target.addrs() {
+            let shared = shared.clone();
+            let bisection = bisect(range.clone(), samples, plan.concurrency, move |port| {
+                let plan = shared.clone();
+                async move { probe_port(&plan, host, port).await }
+            })
+            .await;
+            reports.push(BisectReport {
+                host,
+                start: *range.start(),
+                end: *range.end(),
+                probes: bisection.probes(),
+                full_sweep: range.len(),
+                rounds: bisection.rounds,
+                blocks: bisection.blocks(),
+            });
+        }
+    }
+    reports
+}
+
+// 以區段而非個別端口顯示
+pub fn display(reports: &[BisectReport]) {
+    println!("\n{}", "=== 端口區段 (二分探測) ===".bold());
+    for report in reports {
+        println!("\n{} {}-{}", report.host.to_string().bold(), report.start, report.end);
+        for block in &report.blocks {
+            if block.start == block.end {
+                println!("  {:13} {}", block.start.to_string(), block.state.label());
+            } else {
+                println!(
+                    "  {:13} 全部 {} {}",
+                    format!("{}–{}", block.start, block.end),
+                    block.state.label(),
+                    format!("({} 個樣本)", block.probed).dimmed()
+                );
+            }
+        }
+        let saved = 100.0 - report.probes as f64 * 100.0 / report.full_sweep as f64;
+        println!(
+            "共探測 {} 個端口 ({} 輪)，完整掃描需 {} 個 (節省 {:.1}%)",
+            report.probes, report.rounds, report.full_sweep, saved
+        );
+    }
+    println!(
+        "{}",
+        "樣本之間的狀態為推論；區段內零星的不同狀態端口可能未被發現 (可提高 --bisect-samples)".dimmed()
+    );
+}
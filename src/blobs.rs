@@ -0,0 +1,190 @@
+use std::error::Error;
+use std::path::Path;
+use colored::*;
+use rusqlite::Connection;
+use sha2::{Digest, Sha256};
+use crate::output;
+use crate::resources;
+
+// 橫幅與指紋以內容的 SHA-256 為鍵存放在 blobs 資料表，掃描結果與指紋記錄只存鍵；
+// 每小時掃描時同一份橫幅或憑證不論出現幾次都只存一份
+
+pub fn key(content: &str) -> Vec<u8> {
+    Sha256::digest(content.as_bytes()).to_vec()
+}
+
+// 存入內容 (已存在時不重複) 並回傳它的鍵
+pub fn store(conn: &Connection, content: &str) -> rusqlite::Result<Vec<u8>> {
+    let key = key(content);
+    conn.prepare_cached("INSERT OR IGNORE INTO blobs (sha256, content) VALUES (?1, ?2)")?
+        .execute(rusqlite::params![key, content])?;
+    Ok(key)
+}
+
+// history vacuum 的結果 (位元組)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reclaimed {
+    pub before: u64,
+    pub after: u64,
+    // 刪除的未參照內容
+    pub orphans: usize,
+}
+
+// 資料庫的大小，包含尚未重複使用的空頁
+fn size(conn: &Connection) -> rusqlite::Result<u64> {
+    conn.query_row("SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()", [], |row| row.get::<_, i64>(0))
+        .map(|bytes| bytes as u64)
+}
+
+// 刪除沒有結果或指紋參照的內容 (例如 --accept-fingerprints 取代的舊指紋) 後重建資料庫
+pub fn vacuum(conn: &Connection) -> Result<Reclaimed, Box<dyn Error + Send + Sync>> {
+    let before = size(conn)?;
+    let orphans = output::with_retry(|| {
+        conn.execute(
+            "DELETE FROM blobs
+             WHERE sha256 NOT IN (SELECT banner_sha256 FROM scan_results WHERE banner_sha256 IS NOT NULL)
+               AND sha256 NOT IN (SELECT value_sha256 FROM service_fingerprints)",
+            [],
+        )
+    })?;
+    output::with_retry(|| conn.execute_batch("VACUUM"))?;
+    // WAL 模式下重建的頁面先寫入 WAL，checkpoint 後檔案才實際縮小
+    output::with_retry(|| conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(())))?;
+    Ok(Reclaimed { before, after: size(conn)?, orphans })
+}
+
+// history vacuum 子命令；開啟資料庫時會先把舊版直接存放的內容搬到 blobs
+pub fn run_vacuum(db: &Path) -> Result<(), Box<dyn Error>> {
+    if !db.exists() {
+        return Err(format!("找不到結果資料庫 {}", db.display()).into());
+    }
+    let conn = output::open_database(db).map_err(|e| e.to_string())?;
+    let reclaimed = vacuum(&conn).map_err(|e| format!("無法整理 {}: {}", db.display(), e))?;
+    println!(
+        "{}",
+        format!(
+            "已回收 {} ({} → {})",
+            resources::bytes(reclaimed.before.saturating_sub(reclaimed.after)),
+            resources::bytes(reclaimed.before),
+            resources::bytes(reclaimed.after)
+        )
+        .green()
+    );
+    if reclaimed.orphans > 0 {
+        println!("移除 {} 筆未參照的橫幅或指紋", reclaimed.orphans);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    // 舊版的資料表：指紋直接存在 service_fingerprints.value，掃描結果沒有橫幅
+    const LEGACY_SCHEMA: &str = "
+        CREATE TABLE scan_results (
+            id INTEGER PRIMARY KEY,
+            scanned_at INTEGER NOT NULL,
+            host TEXT NOT NULL,
+            port INTEGER NOT NULL,
+            service TEXT NOT NULL,
+            category TEXT NOT NULL,
+            inbound INTEGER,
+            outbound INTEGER,
+            tags TEXT NOT NULL DEFAULT '[]',
+            identity TEXT NOT NULL,
+            latency_ms REAL
+        );
+        CREATE TABLE scan_runs (scanned_at INTEGER PRIMARY KEY, scan_id TEXT, metadata TEXT NOT NULL);
+        CREATE TABLE service_fingerprints (
+            identity TEXT NOT NULL,
+            port INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            value TEXT NOT NULL,
+            first_seen INTEGER NOT NULL,
+            last_seen INTEGER NOT NULL,
+            PRIMARY KEY (identity, port, kind)
+        );";
+
+    const CERTIFICATE: &str = "SHA256:9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+    const HOST_KEY: &str = "ssh-ed25519 SHA256:uNiVztksCsDhcc0u9e8BujQXVUpKZIDTMczCvj3tD2s";
+
+    // 500 台主機共用同一張萬用憑證與同一把主機金鑰
+    fn legacy_database(path: &Path) {
+        let conn = Connection::open(path).unwrap();
+        conn.execute_batch(LEGACY_SCHEMA).unwrap();
+        let mut insert = conn.prepare("INSERT INTO service_fingerprints VALUES (?1, ?2, ?3, ?4, 1700000000, 1700003600)").unwrap();
+        for n in 0..500 {
+            let identity = format!("web{:03}", n);
+            insert.execute(rusqlite::params![identity, 443, "tls_certificate", CERTIFICATE]).unwrap();
+            insert.execute(rusqlite::params![identity, 22, "ssh_host_key", HOST_KEY]).unwrap();
+        }
+        insert.execute(rusqlite::params!["db01", 22, "ssh_host_key", "ssh-rsa SHA256:other"]).unwrap();
+        conn.execute_batch("VACUUM").unwrap();
+    }
+
+    fn fingerprints(conn: &Connection) -> Vec<(String, i64, String, String)> {
+        let mut rows = conn
+            .prepare(
+                "SELECT f.identity, f.port, f.kind, b.content FROM service_fingerprints f
+                 JOIN blobs b ON b.sha256 = f.value_sha256 ORDER BY f.identity, f.port",
+            )
+            .unwrap();
+        rows.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))).unwrap().map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn legacy_databases_are_migrated_in_place_and_shrink() {
+        let dir = TempDir::new("blobs");
+        let path = dir.path().join("history.db");
+        legacy_database(&path);
+        let before = size(&Connection::open(&path).unwrap()).unwrap();
+
+        let conn = output::open_database(&path).unwrap();
+        let migrated = fingerprints(&conn);
+        assert_eq!(migrated.len(), 1001);
+        assert_eq!(migrated[0], ("db01".to_string(), 22, "ssh_host_key".to_string(), "ssh-rsa SHA256:other".to_string()));
+        assert!(migrated.iter().filter(|f| f.1 == 443).all(|f| f.3 == CERTIFICATE));
+        let blobs: i64 = conn.query_row("SELECT COUNT(*) FROM blobs", [], |row| row.get(0)).unwrap();
+        assert_eq!(blobs, 3);
+        let integrity: String = conn.query_row("PRAGMA integrity_check", [], |row| row.get(0)).unwrap();
+        assert_eq!(integrity, "ok");
+        // 每個鍵都是內容的雜湊
+        let mut keys = conn.prepare("SELECT sha256, content FROM blobs").unwrap();
+        for row in keys.query_map([], |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, String>(1)?))).unwrap() {
+            let (sha256, content) = row.unwrap();
+            assert_eq!(sha256, key(&content));
+        }
+        drop(keys);
+
+        let reclaimed = vacuum(&conn).unwrap();
+        assert_eq!(reclaimed.orphans, 0);
+        assert!(reclaimed.after < before, "{} → {}", before, reclaimed.after);
+        // 再次開啟不重複搬移
+        drop(conn);
+        assert_eq!(fingerprints(&output::open_database(&path).unwrap()), migrated);
+    }
+
+    #[test]
+    fn vacuum_drops_only_unreferenced_blobs() {
+        let dir = TempDir::new("blobs");
+        let path = dir.path().join("history.db");
+        let conn = output::open_database(&path).unwrap();
+        let banner = store(&conn, "{\"probe\":\"http\",\"text\":\"HTTP/1.1 200 OK\"}").unwrap();
+        store(&conn, "SHA256:replaced").unwrap();
+        // 相同內容只存一份
+        assert_eq!(store(&conn, "{\"probe\":\"http\",\"text\":\"HTTP/1.1 200 OK\"}").unwrap(), banner);
+        conn.execute(
+            "INSERT INTO scan_results (scanned_at, host, port, service, category, identity, banner_sha256)
+             VALUES (1700000000, '10.0.0.1', 80, 'HTTP', 'Web', '10.0.0.1', ?1)",
+            [&banner],
+        )
+        .unwrap();
+
+        let reclaimed = vacuum(&conn).unwrap();
+        assert_eq!(reclaimed.orphans, 1);
+        let remaining: Vec<u8> = conn.query_row("SELECT sha256 FROM blobs", [], |row| row.get(0)).unwrap();
+        assert_eq!(remaining, banner);
+    }
+}
@@ -0,0 +1,631 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::net::IpAddr;
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::portspec;
+use crate::{PortInfo, ScanResult};
+
+// 有服務組合未通過時的結束代碼
+pub const EXIT_BUNDLE_FAILED: i32 = 5;
+
+// 設定檔的 [[bundles]]：以運算式描述一組服務的整體狀態，例如
+//   [[bundles]]
+//   name = "web stack"
+//   expr = "80 AND 443 AND (3306 OR 5432)"
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BundleDefinition {
+    pub name: String,
+    pub expr: String,
+}
+
+// 端口條件的狀態，寫在端口或類別之後，例如 22:inbound；預設為 outbound
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Outbound,
+    Inbound,
+    Both,
+}
+
+impl State {
+    const NAMES: &'static str = "outbound, inbound, both";
+
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "outbound" => Some(State::Outbound),
+            "inbound" => Some(State::Inbound),
+            "both" => Some(State::Both),
+            _ => None,
+        }
+    }
+
+    fn matches(self, result: &ScanResult) -> bool {
+        match self {
+            State::Outbound => result.outbound,
+            State::Inbound => result.inbound,
+            State::Both => result.inbound && result.outbound,
+        }
+    }
+}
+
+// 運算式的最小單位：一個端口或一個類別 (類別中任一端口符合即成立)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Subject {
+    Port(u16),
+    Category(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Term(Subject, State),
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |f: &mut fmt::Formatter<'_>, children: &[Expr], op: &str| {
+            for (i, child) in children.iter().enumerate() {
+                if i > 0 {
+                    write!(f, " {} ", op)?;
+                }
+                match child {
+                    Expr::And(_) | Expr::Or(_) => write!(f, "({})", child)?,
+                    _ => write!(f, "{}", child)?,
+                }
+            }
+            Ok(())
+        };
+        match self {
+            Expr::Term(subject, state) => {
+                match subject {
+                    Subject::Port(port) => write!(f, "{}", port)?,
+                    Subject::Category(name) => write!(f, "category:{}", name)?,
+                }
+                match state {
+                    State::Outbound => Ok(()),
+                    State::Inbound => write!(f, ":inbound"),
+                    State::Both => write!(f, ":both"),
+                }
+            }
+            Expr::Not(inner) => match inner.as_ref() {
+                Expr::And(_) | Expr::Or(_) => write!(f, "NOT ({})", inner),
+                _ => write!(f, "NOT {}", inner),
+            },
+            Expr::And(children) => join(f, children, "AND"),
+            Expr::Or(children) => join(f, children, "OR"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Word(String),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Open => "(".to_string(),
+            Token::Close => ")".to_string(),
+            Token::And => "AND".to_string(),
+            Token::Or => "OR".to_string(),
+            Token::Not => "NOT".to_string(),
+            Token::Word(word) => format!("'{}'", word),
+        }
+    }
+}
+
+// (字元位置, 記號)；位置從 1 起算，用於錯誤訊息
+fn tokenize(text: &str) -> Vec<(usize, Token)> {
+    let mut tokens = Vec::new();
+    let mut word: Option<(usize, String)> = None;
+    let flush = |word: &mut Option<(usize, String)>, tokens: &mut Vec<(usize, Token)>| {
+        if let Some((at, text)) = word.take() {
+            let token = match text.to_ascii_uppercase().as_str() {
+                "AND" => Token::And,
+                "OR" => Token::Or,
+                "NOT" => Token::Not,
+                _ => Token::Word(text),
+            };
+            tokens.push((at, token));
+        }
+    };
+    for (i, c) in text.chars().enumerate() {
+        match c {
+            '(' | ')' => {
+                flush(&mut word, &mut tokens);
+                tokens.push((i + 1, if c == '(' { Token::Open } else { Token::Close }));
+            }
+            c if c.is_whitespace() => flush(&mut word, &mut tokens),
+            c => word.get_or_insert_with(|| (i + 1, String::new())).1.push(c),
+        }
+    }
+    flush(&mut word, &mut tokens);
+    tokens
+}
+
+// 遞迴下降解析；優先順序 NOT > AND > OR
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    // 運算式結尾的位置，用於 "運算式意外結束"
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, token)| token)
+    }
+
+    fn error(&self, expected: &str) -> String {
+        match self.tokens.get(self.next) {
+            Some((at, token)) => format!("第 {} 個字元: 預期{}，卻是 {}", at, expected, token.describe()),
+            None => format!("第 {} 個字元: 預期{}，但運算式已結束", self.end, expected),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut children = vec![self.and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.next += 1;
+            children.push(self.and()?);
+        }
+        Ok(if children.len() == 1 { children.remove(0) } else { Expr::Or(children) })
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut children = vec![self.not()?];
+        while self.peek() == Some(&Token::And) {
+            self.next += 1;
+            children.push(self.not()?);
+        }
+        Ok(if children.len() == 1 { children.remove(0) } else { Expr::And(children) })
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.peek() == Some(&Token::Not) {
+            self.next += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.peek().cloned() {
+            Some(Token::Open) => {
+                self.next += 1;
+                let inner = self.or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(self.error(" )"));
+                }
+                self.next += 1;
+                Ok(inner)
+            }
+            Some(Token::Word(word)) => {
+                let at = self.tokens[self.next].0;
+                self.next += 1;
+                parse_term(&word).map_err(|e| format!("第 {} 個字元: {}", at, e))
+            }
+            _ => Err(self.error("端口、category:類別或 (")),
+        }
+    }
+}
+
+// 端口或類別，後面可接 :狀態，例如 443、22:inbound、category:Database
+fn parse_term(word: &str) -> Result<Expr, String> {
+    let (subject, state) = match word.split_once(':') {
+        Some((prefix, rest)) if prefix.eq_ignore_ascii_case("category") => match rest.rsplit_once(':') {
+            Some((name, state)) if State::parse(state).is_some() => (Subject::Category(name.to_string()), Some(state)),
+            _ => (Subject::Category(rest.to_string()), None),
+        },
+        Some((port, state)) => (parse_port(port)?, Some(state)),
+        None => (parse_port(word)?, None),
+    };
+    if matches!(&subject, Subject::Category(name) if name.is_empty()) {
+        return Err("category: 之後缺少類別名稱".to_string());
+    }
+    let state = match state {
+        None => State::Outbound,
+        Some(name) => State::parse(name).ok_or_else(|| format!("未知的狀態 '{}' (可用: {})", name, State::NAMES))?,
+    };
+    Ok(Expr::Term(subject, state))
+}
+
+fn parse_port(text: &str) -> Result<Subject, String> {
+    portspec::parse_port(text)
+        .map(Subject::Port)
+        .map_err(|_| format!("'{}' 不是有效的端口 (1-65535)，類別請寫成 category:{}", text, text))
+}
+
+pub fn parse(text: &str) -> Result<Expr, String> {
+    let mut parser = Parser {
+        tokens: tokenize(text),
+        next: 0,
+        end: text.chars().count() + 1,
+    };
+    if parser.tokens.is_empty() {
+        return Err("運算式是空的".to_string());
+    }
+    let expr = parser.or()?;
+    if parser.next < parser.tokens.len() {
+        return Err(parser.error(" AND、OR 或運算式結尾"));
+    }
+    Ok(expr)
+}
+
+impl Expr {
+    fn eval(&self, results: &HashMap<PortInfo, ScanResult>) -> bool {
+        match self {
+            Expr::Term(subject, state) => term_value(subject, *state, results).unwrap_or(false),
+            Expr::Not(inner) => !inner.eval(results),
+            Expr::And(children) => children.iter().all(|child| child.eval(results)),
+            Expr::Or(children) => children.iter().any(|child| child.eval(results)),
+        }
+    }
+
+    // 讓運算式的值不是 want 的條件：AND 列出不成立的子項，OR 列出全部子項，NOT 反轉要求
+    fn explain(&self, want: bool, results: &HashMap<PortInfo, ScanResult>, out: &mut Vec<String>) {
+        if self.eval(results) == want {
+            return;
+        }
+        match self {
+            Expr::Term(subject, state) => {
+                let label = self.to_string();
+                match term_value(subject, *state, results) {
+                    None => out.push(format!("{} ({})", label, unscanned(subject))),
+                    Some(_) if want => out.push(label),
+                    Some(_) => out.push(format!("NOT {}", label)),
+                }
+            }
+            Expr::Not(inner) => inner.explain(!want, results, out),
+            Expr::And(children) | Expr::Or(children) => {
+                children.iter().for_each(|child| child.explain(want, results, out));
+            }
+        }
+    }
+
+    // 運算式引用的端口
+    fn ports(&self, out: &mut Vec<u16>) {
+        match self {
+            Expr::Term(Subject::Port(port), _) => out.push(*port),
+            Expr::Term(Subject::Category(_), _) => {}
+            Expr::Not(inner) => inner.ports(out),
+            Expr::And(children) | Expr::Or(children) => children.iter().for_each(|child| child.ports(out)),
+        }
+    }
+}
+
+fn unscanned(subject: &Subject) -> &'static str {
+    match subject {
+        Subject::Port(_) => "未掃描",
+        Subject::Category(_) => "沒有掃描此類別的端口",
+    }
+}
+
+// 條件的值；端口未掃描或類別沒有端口時為 None (視為不成立)，掃描端錯誤的端口視為不成立
+fn term_value(subject: &Subject, state: State, results: &HashMap<PortInfo, ScanResult>) -> Option<bool> {
+    let mut matching = results
+        .iter()
+        .filter(|(port, _)| match subject {
+            Subject::Port(number) => port.port == *number,
+            Subject::Category(name) => port.category.eq_ignore_ascii_case(name),
+        })
+        .peekable();
+    matching.peek()?;
+    Some(matching.any(|(_, result)| result.error.is_none() && state.matches(result)))
+}
+
+// 驗證過的服務組合
+#[derive(Debug, Clone)]
+pub struct Bundle {
+    pub name: String,
+    pub source: String,
+    pub expr: Expr,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Bundles(Vec<Bundle>);
+
+impl Bundles {
+    // 啟動時解析全部運算式，錯誤訊息指出組合名稱與位置
+    pub fn build(definitions: &[BundleDefinition]) -> Result<Self, String> {
+        let mut bundles: Vec<Bundle> = Vec::new();
+        for (i, definition) in definitions.iter().enumerate() {
+            let name = definition.name.trim();
+            if name.is_empty() {
+                return Err(format!("服務組合 #{} 缺少名稱", i + 1));
+            }
+            if bundles.iter().any(|b| b.name == name) {
+                return Err(format!("服務組合名稱重複: {}", name));
+            }
+            let expr = parse(&definition.expr)
+                .map_err(|e| format!("服務組合 {} 的運算式 '{}' 有誤，{}", name, definition.expr, e))?;
+            bundles.push(Bundle {
+                name: name.to_string(),
+                source: definition.expr.clone(),
+                expr,
+            });
+        }
+        Ok(Bundles(bundles))
+    }
+
+    // 運算式引用了不在這次掃描中的端口時的提醒；這些條件會視為不成立
+    pub fn unscanned_warnings(&self, ports: &[PortInfo]) -> Vec<String> {
+        self.0
+            .iter()
+            .filter_map(|bundle| {
+                let mut referenced = Vec::new();
+                bundle.expr.ports(&mut referenced);
+                referenced.sort_unstable();
+                referenced.dedup();
+                let missing: Vec<String> = referenced
+                    .into_iter()
+                    .filter(|port| !ports.iter().any(|p| p.port == *port))
+                    .map(|port| port.to_string())
+                    .collect();
+                (!missing.is_empty()).then(|| {
+                    format!("服務組合 {} 引用的端口 {} 不在掃描範圍內，將視為不成立", bundle.name, missing.join(", "))
+                })
+            })
+            .collect()
+    }
+}
+
+// 一台主機上一個服務組合的結果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct BundleVerdict {
+    pub host: IpAddr,
+    pub name: String,
+    pub expr: String,
+    pub passed: bool,
+    // 使組合不通過的條件，例如 "3306"、"NOT 23"、"5432 (未掃描)"
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failing: Vec<String>,
+}
+
+pub fn evaluate(bundles: &Bundles, results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>) -> Vec<BundleVerdict> {
+    let mut verdicts = Vec::new();
+    for (host, ports) in results {
+        for bundle in &bundles.0 {
+            let passed = bundle.expr.eval(ports);
+            let mut failing = Vec::new();
+            bundle.expr.explain(true, ports, &mut failing);
+            failing.dedup();
+            verdicts.push(BundleVerdict {
+                host: *host,
+                name: bundle.name.clone(),
+                expr: bundle.source.clone(),
+                passed,
+                failing,
+            });
+        }
+    }
+    verdicts
+}
+
+pub fn failed(verdicts: &[BundleVerdict]) -> usize {
+    verdicts.iter().filter(|verdict| !verdict.passed).count()
+}
+
+// 每個組合一行；多台主機時加上主機
+pub fn display(verdicts: &[BundleVerdict], multi_host: bool) {
+    if verdicts.is_empty() {
+        return;
+    }
+    println!("\n{}", "=== 服務組合 ===".bold());
+    for verdict in verdicts {
+        let name = match multi_host {
+            true => format!("{} ({})", verdict.name, verdict.host),
+            false => verdict.name.clone(),
+        };
+        match verdict.passed {
+            true => println!("{} {}", "✓".green(), name),
+            false => println!("{} {}  {}", "✗".red(), name, format!("不成立: {}", verdict.failing.join(", ")).red()),
+        }
+        println!("  {}", verdict.expr.dimmed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{host, scan_result};
+
+    fn port(n: u16) -> Expr {
+        Expr::Term(Subject::Port(n), State::Outbound)
+    }
+
+    // 端口 -> (類別, 入站, 出站)
+    fn results(ports: &[(u16, &str, bool, bool)]) -> HashMap<PortInfo, ScanResult> {
+        ports
+            .iter()
+            .map(|&(number, category, inbound, outbound)| {
+                let mut result = scan_result(outbound);
+                result.inbound = inbound;
+                (PortInfo::new(number, "Test", category), result)
+            })
+            .collect()
+    }
+
+    fn web_host() -> HashMap<PortInfo, ScanResult> {
+        results(&[(80, "Web", false, true), (443, "Web", true, true), (3306, "Database", false, false), (23, "Remote", false, true)])
+    }
+
+    fn bundles(items: &[(&str, &str)]) -> Result<Bundles, String> {
+        let definitions: Vec<BundleDefinition> =
+            items.iter().map(|(name, expr)| BundleDefinition { name: name.to_string(), expr: expr.to_string() }).collect();
+        Bundles::build(&definitions)
+    }
+
+    #[test]
+    fn precedence_is_not_then_and_then_or() {
+        assert_eq!(parse("1 OR 2 AND NOT 3").unwrap(), Expr::Or(vec![port(1), Expr::And(vec![port(2), Expr::Not(Box::new(port(3)))])]));
+        assert_eq!(parse("(1 or 2) and 3").unwrap(), Expr::And(vec![Expr::Or(vec![port(1), port(2)]), port(3)]));
+        assert_eq!(parse("not not 22").unwrap(), Expr::Not(Box::new(Expr::Not(Box::new(port(22))))));
+        assert_eq!(
+            parse("22:inbound AND category:Database:both").unwrap(),
+            Expr::And(vec![Expr::Term(Subject::Port(22), State::Inbound), Expr::Term(Subject::Category("Database".to_string()), State::Both)])
+        );
+        // 類別名稱本身可含冒號
+        assert_eq!(parse("category:a:b").unwrap(), Expr::Term(Subject::Category("a:b".to_string()), State::Outbound));
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_problem() {
+        for (text, error) in [
+            ("", "運算式是空的"),
+            ("80 AND", "第 7 個字元: 預期端口、category:類別或 (，但運算式已結束"),
+            ("(80 OR 443", "第 11 個字元: 預期 )，但運算式已結束"),
+            ("80 443", "第 4 個字元: 預期 AND、OR 或運算式結尾，卻是 '443'"),
+            ("80 AND )", "第 8 個字元: 預期端口、category:類別或 (，卻是 )"),
+            ("Web", "第 1 個字元: 'Web' 不是有效的端口 (1-65535)，類別請寫成 category:Web"),
+            ("1 AND 22:open", "第 7 個字元: 未知的狀態 'open' (可用: outbound, inbound, both)"),
+            ("category:", "第 1 個字元: category: 之後缺少類別名稱"),
+            ("0", "第 1 個字元: '0' 不是有效的端口 (1-65535)，類別請寫成 category:0"),
+        ] {
+            assert_eq!(parse(text).unwrap_err(), error, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn verdicts_list_the_failing_terms() {
+        let bundles = bundles(&[
+            ("web", "80 AND 443 AND 3306"),
+            ("tls", "443:both"),
+            ("no telnet", "NOT 23"),
+            ("db", "3306 OR 5432"),
+            ("any web", "category:web"),
+            ("nothing legacy", "NOT (23 OR 21)"),
+        ])
+        .unwrap();
+        let scanned = BTreeMap::from([(host(1), web_host())]);
+        let verdicts = evaluate(&bundles, &scanned);
+        let summary: Vec<(&str, bool, Vec<&str>)> =
+            verdicts.iter().map(|v| (v.name.as_str(), v.passed, v.failing.iter().map(String::as_str).collect())).collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("web", false, vec!["3306"]),
+                ("tls", true, vec![]),
+                ("no telnet", false, vec!["NOT 23"]),
+                ("db", false, vec!["3306", "5432 (未掃描)"]),
+                ("any web", true, vec![]),
+                ("nothing legacy", false, vec!["NOT 23"]),
+            ]
+        );
+        assert_eq!(failed(&verdicts), 4);
+        assert_eq!(verdicts[0].expr, "80 AND 443 AND 3306");
+    }
+
+    #[test]
+    fn scanner_errors_and_missing_categories_are_false() {
+        let mut ports = web_host();
+        ports.values_mut().for_each(|result| result.error = Some(crate::limits::ScanError::AddressInUse));
+        assert!(!parse("80").unwrap().eval(&ports));
+        let mut failing = Vec::new();
+        parse("category:Mail").unwrap().explain(true, &web_host(), &mut failing);
+        assert_eq!(failing, vec!["category:Mail (沒有掃描此類別的端口)"]);
+    }
+
+    #[test]
+    fn definitions_are_validated_at_startup() {
+        assert_eq!(bundles(&[(" ", "80")]).unwrap_err(), "服務組合 #1 缺少名稱");
+        assert_eq!(bundles(&[("a", "80"), ("a", "443")]).unwrap_err(), "服務組合名稱重複: a");
+        assert_eq!(bundles(&[("web", "80 AND")]).unwrap_err(), "服務組合 web 的運算式 '80 AND' 有誤，第 7 個字元: 預期端口、category:類別或 (，但運算式已結束");
+
+        let bundles = bundles(&[("web", "80 AND (443 OR 8443) AND NOT category:Remote"), ("ssh", "22")]).unwrap();
+        let scanned: Vec<PortInfo> = [80, 443].into_iter().map(|p| PortInfo::new(p, "Test", "Web")).collect();
+        assert_eq!(
+            bundles.unscanned_warnings(&scanned),
+            vec!["服務組合 web 引用的端口 8443 不在掃描範圍內，將視為不成立", "服務組合 ssh 引用的端口 22 不在掃描範圍內，將視為不成立"]
+        );
+    }
+
+    // 固定種子的線性同餘產生器，讓產生的運算式每次相同
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (self.0 >> 33) % n
+        }
+    }
+
+    fn generate(rng: &mut Lcg, depth: u32) -> Expr {
+        let states = [State::Outbound, State::Inbound, State::Both];
+        match if depth == 0 { 0 } else { rng.below(4) } {
+            0 => {
+                let state = states[rng.below(3) as usize];
+                match rng.below(5) {
+                    0 => Expr::Term(Subject::Category(["Web", "Database", "Mail"][rng.below(3) as usize].to_string()), state),
+                    _ => Expr::Term(Subject::Port([22, 23, 80, 443, 3306, 5432][rng.below(6) as usize]), state),
+                }
+            }
+            1 => Expr::Not(Box::new(generate(rng, depth - 1))),
+            kind => {
+                let children = (0..2 + rng.below(2)).map(|_| generate(rng, depth - 1)).collect();
+                if kind == 2 { Expr::And(children) } else { Expr::Or(children) }
+            }
+        }
+    }
+
+    // 與 eval 無關的參考實作：直接依定義計算
+    fn reference(expr: &Expr, ports: &HashMap<PortInfo, ScanResult>) -> bool {
+        match expr {
+            Expr::Term(subject, state) => ports.iter().any(|(port, result)| {
+                let subject_matches = match subject {
+                    Subject::Port(number) => port.port == *number,
+                    Subject::Category(name) => port.category.eq_ignore_ascii_case(name),
+                };
+                let state_matches = match state {
+                    State::Outbound => result.outbound,
+                    State::Inbound => result.inbound,
+                    State::Both => result.inbound && result.outbound,
+                };
+                subject_matches && state_matches && result.error.is_none()
+            }),
+            Expr::Not(inner) => !reference(inner, ports),
+            Expr::And(children) => children.iter().all(|child| reference(child, ports)),
+            Expr::Or(children) => children.iter().any(|child| reference(child, ports)),
+        }
+    }
+
+    #[test]
+    fn generated_expressions_round_trip_and_evaluate_consistently() {
+        let mut rng = Lcg(0x5eed);
+        let hosts = [web_host(), results(&[(22, "Remote", true, true), (5432, "Database", true, false)]), HashMap::new()];
+        for _ in 0..500 {
+            let expr = generate(&mut rng, 4);
+            let text = expr.to_string();
+            let parsed = parse(&text).unwrap_or_else(|e| panic!("{}: {}", text, e));
+            assert_eq!(parsed, expr, "{}", text);
+            // 大小寫不影響運算子
+            assert_eq!(parse(&text.replace("AND", "and").replace("NOT", "Not")).unwrap(), expr, "{}", text);
+
+            for ports in &hosts {
+                let value = expr.eval(ports);
+                assert_eq!(value, reference(&expr, ports), "{}", text);
+                // 不成立時一定說得出原因，成立時沒有
+                let mut failing = Vec::new();
+                expr.explain(true, ports, &mut failing);
+                assert_eq!(failing.is_empty(), value, "{} -> {:?}", text, failing);
+                // De Morgan
+                let negated = Expr::Not(Box::new(expr.clone()));
+                assert_eq!(negated.eval(ports), !value);
+                if let Expr::And(children) = &expr {
+                    let or = Expr::Or(children.iter().map(|c| Expr::Not(Box::new(c.clone()))).collect());
+                    assert_eq!(or.eval(ports), !value, "{}", text);
+                }
+            }
+        }
+    }
+}
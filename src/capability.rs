@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::sync::Arc;
+use colored::*;
+use schemars::JsonSchema;
+use serde::Serialize;
+use crate::context::ScanContext;
+use crate::icmp::IcmpMonitor;
+use crate::layout;
+use crate::output;
+use crate::privhelper::{Channel, Helper, RawSocket};
+use crate::sanity;
+use crate::scanner::ScanPlan;
+use crate::syn::SynScanner;
+
+// 需要環境條件的選用功能；無法使用時掃描照常進行，只有該功能改用 degraded 說明的行為
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    RawTcp,
+    Icmp,
+    Capture,
+    Sqlite,
+    Clipboard,
+    #[serde(rename = "geoip")]
+    GeoIp,
+    PortMapping,
+}
+
+impl Capability {
+    pub fn label(self) -> &'static str {
+        match self {
+            Capability::RawTcp => "原始 TCP socket (--syn)",
+            Capability::Icmp => "ICMP 錯誤監測",
+            Capability::Capture => "封包擷取 (--pcap)",
+            Capability::Sqlite => "SQLite (--output *.db、--fingerprint-db)",
+            Capability::Clipboard => "剪貼簿 (--copy)",
+            Capability::GeoIp => "GeoIP 位置檢查",
+            Capability::PortMapping => "NAT-PMP / PCP (--nat-pmp)",
+        }
+    }
+
+    // 無法使用時的替代行為
+    pub fn degraded(self) -> &'static str {
+        match self {
+            Capability::RawTcp => "改用完整連線掃描",
+            Capability::Icmp => "不標示 ICMP 錯誤，過濾的端口只依連線結果判斷",
+            Capability::Capture => "不擷取封包",
+            Capability::Sqlite => "略過 SQLite 輸出與指紋記錄",
+            Capability::Clipboard => "只顯示一行摘要，不複製",
+            Capability::GeoIp => "不檢查外部 IP 的位置",
+            Capability::PortMapping => "不查詢閘道的端口轉發",
+        }
+    }
+}
+
+// 一項功能的偵測結果
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct CapabilityStatus {
+    pub capability: Capability,
+    pub available: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    // 無法使用時的替代行為
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub degraded: Option<String>,
+}
+
+// 啟動時偵測一次的功能狀態；各功能開始前查詢這張表，不在掃描中途才失敗
+// 沒有偵測的功能 (例如子命令與測試) 視為可用，由功能本身回報錯誤
+#[derive(Debug, Clone, Default)]
+pub struct Capabilities {
+    statuses: BTreeMap<Capability, Result<(), String>>,
+}
+
+impl Capabilities {
+    // 不需要原始 socket 的功能；原始 socket 在輔助程序啟動後由 detect_raw 偵測
+    pub fn detect(context: &ScanContext, geo_sanity: bool) -> Self {
+        let mut capabilities = Capabilities::default();
+        capabilities.record(
+            Capability::Sqlite,
+            rusqlite::Connection::open_in_memory().and_then(|conn| output::migrate(&conn)).map_err(|e| e.to_string()),
+        );
+        capabilities.record(Capability::Clipboard, arboard::Clipboard::new().map(drop).map_err(|e| e.to_string()));
+        capabilities.record(
+            Capability::GeoIp,
+            match (geo_sanity, context.external_ip_disabled()) {
+                (false, _) => Err("以 --no-geo-sanity 停用".to_string()),
+                (true, true) => Err("未查詢外部 IP".to_string()),
+                (true, false) => Ok(()),
+            },
+        );
+        capabilities.record(Capability::PortMapping, sanity::default_gateway().map(drop).ok_or_else(|| "找不到 IPv4 預設閘道".to_string()));
+        capabilities
+    }
+
+    // 有輔助程序時以它開啟的通道為準
+    pub fn detect_raw(&mut self, helper: Option<&Arc<Helper>>) {
+        self.record(Capability::RawTcp, probe_raw(Channel::Tcp, helper));
+        let icmp = probe_raw(Channel::Icmp4, helper).or_else(|_| probe_raw(Channel::Icmp6, helper));
+        self.record(Capability::Icmp, icmp);
+        self.record(Capability::Capture, probe_raw(Channel::Capture, helper));
+    }
+
+    pub fn record(&mut self, capability: Capability, status: Result<(), String>) {
+        self.statuses.insert(capability, status);
+    }
+
+    // 無法使用時回傳原因，例如 "剪貼簿 (--copy) 無法使用: ..."
+    pub fn require(&self, capability: Capability) -> Result<(), String> {
+        match self.statuses.get(&capability) {
+            Some(Err(reason)) => Err(format!("{} 無法使用: {}", capability.label(), reason)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn available(&self, capability: Capability) -> bool {
+        self.require(capability).is_ok()
+    }
+
+    // JSON 報告的 metadata.capabilities
+    pub fn statuses(&self) -> Vec<CapabilityStatus> {
+        self.statuses
+            .iter()
+            .map(|(capability, status)| CapabilityStatus {
+                capability: *capability,
+                available: status.is_ok(),
+                reason: status.as_ref().err().cloned(),
+                degraded: status.is_err().then(|| capability.degraded().to_string()),
+            })
+            .collect()
+    }
+}
+
+fn probe_raw(channel: Channel, helper: Option<&Arc<Helper>>) -> Result<(), String> {
+    RawSocket::open(channel, helper).map(drop).map_err(|e| match (e.kind(), helper) {
+        (ErrorKind::PermissionDenied, None) => "需要 root 權限或 CAP_NET_RAW".to_string(),
+        _ => e.to_string(),
+    })
+}
+
+// --syn 與 ICMP 錯誤監測：無法使用時不開啟，回傳要顯示的說明
+pub fn open_raw(plan: &mut ScanPlan, capabilities: &Capabilities, helper: Option<&Arc<Helper>>, syn: bool, icmp: bool) -> Vec<String> {
+    let mut notes = Vec::new();
+    if syn {
+        match capabilities.require(Capability::RawTcp).and_then(|()| SynScanner::open(helper)) {
+            Ok(scanner) => plan.syn = Some(Arc::new(scanner)),
+            Err(e) => notes.push(format!("{}，{}", e, Capability::RawTcp.degraded())),
+        }
+    }
+    if icmp && capabilities.available(Capability::Icmp) {
+        plan.icmp = IcmpMonitor::open(helper).map(Arc::new);
+    }
+    notes
+}
+
+// --verbose 的「功能狀態」表
+pub fn display(capabilities: &Capabilities) {
+    let statuses = capabilities.statuses();
+    if statuses.is_empty() {
+        return;
+    }
+    eprintln!("{}", "功能狀態:".bold());
+    let width = statuses.iter().map(|status| layout::display_width(status.capability.label())).max().unwrap_or(0);
+    for status in statuses {
+        let label = status.capability.label();
+        match (&status.reason, &status.degraded) {
+            (Some(reason), Some(degraded)) => {
+                eprintln!("  {} {}  {} → {}", "✗".red(), layout::fit(label, width), reason.dimmed(), degraded)
+            }
+            _ => eprintln!("  {} {}", "✓".green(), label),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prober::fake::{Script, Scripted, ScriptedProber};
+    use crate::testutil::{host, scan, scripted_plan};
+    use std::time::Duration;
+
+    const ALL: [Capability; 7] = [
+        Capability::RawTcp,
+        Capability::Icmp,
+        Capability::Capture,
+        Capability::Sqlite,
+        Capability::Clipboard,
+        Capability::GeoIp,
+        Capability::PortMapping,
+    ];
+
+    fn unavailable() -> Capabilities {
+        let mut capabilities = Capabilities::default();
+        for capability in ALL {
+            capabilities.record(capability, Err("測試環境".to_string()));
+        }
+        capabilities
+    }
+
+    #[test]
+    fn undetected_capabilities_are_assumed_available() {
+        let capabilities = Capabilities::default();
+        assert!(ALL.iter().all(|capability| capabilities.available(*capability)));
+        assert!(capabilities.statuses().is_empty());
+    }
+
+    #[test]
+    fn unavailable_capabilities_explain_the_degraded_behavior() {
+        let capabilities = unavailable();
+        for capability in ALL {
+            let error = capabilities.require(capability).unwrap_err();
+            assert!(error.starts_with(capability.label()), "{}", error);
+            assert!(error.ends_with("測試環境"), "{}", error);
+        }
+        let statuses = capabilities.statuses();
+        assert_eq!(statuses.len(), ALL.len());
+        let json = serde_json::to_value(&statuses[0]).unwrap();
+        assert_eq!(json["capability"], "raw_tcp");
+        assert_eq!(json["available"], false);
+        assert_eq!(json["degraded"], "改用完整連線掃描");
+    }
+
+    #[test]
+    fn geoip_follows_the_external_ip_lookup() {
+        let context = ScanContext::new(crate::context::ExternalIpSource::Disabled);
+        assert!(Capabilities::detect(&context, true).require(Capability::GeoIp).unwrap_err().contains("未查詢外部 IP"));
+        assert!(Capabilities::detect(&context, false).require(Capability::GeoIp).unwrap_err().contains("--no-geo-sanity"));
+        assert!(Capabilities::detect(&context, true).available(Capability::Sqlite));
+    }
+
+    // 原始 socket 都無法使用時不開啟 SYN 掃描與 ICMP 監測，掃描照常以完整連線完成
+    #[tokio::test(start_paused = true)]
+    async fn scans_complete_without_raw_sockets() {
+        let prober = Arc::new(
+            ScriptedProber::new()
+                .with(host(1), 22, Script::new(Scripted::Open, Duration::from_millis(5)))
+                .fallback(Script::new(Scripted::Refused, Duration::from_millis(5))),
+        );
+        let mut plan = scripted_plan(&[host(1)], &[22, 80], 1, prober);
+        let notes = open_raw(&mut plan, &unavailable(), None, true, true);
+        assert!(plan.syn.is_none() && plan.icmp.is_none());
+        assert_eq!(notes, ["原始 TCP socket (--syn) 無法使用: 測試環境，改用完整連線掃描"]);
+        let records = scan(&plan).await;
+        assert_eq!(records.len(), 2);
+        assert_eq!(records.iter().filter(|record| record.result.outbound).count(), 1);
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::Arc;
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+use crate::{PortInfo, ScanResult};
+use crate::scanner::ScanPlan;
+
+// 路徑可用的 TCP 功能；None 代表無法判斷 (平台不支援或本機未啟用)
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct TcpCaps {
+    // 資料是否在 SYN 中被接受 (TCP Fast Open)
+    pub tfo: Option<bool>,
+    pub ecn: Option<bool>,
+    pub window_scale: Option<bool>,
+    pub sack: Option<bool>,
+    pub timestamps: Option<bool>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+}
+
+// 精簡的旗標顯示，例如 [TFO ✓] [ECN ✗]
+pub fn flags(caps: &TcpCaps) -> String {
+    let flag = |name: &str, value: Option<bool>| match value {
+        Some(true) => format!("[{} {}]", name, "✓".green()),
+        Some(false) => format!("[{} {}]", name, "✗".red()),
+        None => format!("[{} {}]", name, "?".dimmed()),
+    };
+    let mut line = [
+        flag("TFO", caps.tfo),
+        flag("ECN", caps.ecn),
+        flag("WS", caps.window_scale),
+        flag("SACK", caps.sack),
+        flag("TS", caps.timestamps),
+    ]
+    .join(" ");
+    if !caps.notes.is_empty() {
+        line.push_str(&format!("  {}", caps.notes.join("；").dimmed()));
+    }
+    line
+}
+
+// 對所有出站可連線的端口探測 TCP 功能
+pub async fn probe_results(results: &mut BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>, plan: &ScanPlan) {
+    let limit = plan.timeouts.default;
+    let semaphore = Arc::new(Semaphore::new(plan.concurrency.max(1)));
+    let mut handles = Vec::new();
+    for (host, host_results) in results.iter() {
+        for (port, result) in host_results {
+            if !result.outbound {
+                continue;
+            }
+            let (addr, port, semaphore) = (plan.context.socket_addr(*host, port.port), port.clone(), semaphore.clone());
+            handles.push(tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let caps = tokio::task::spawn_blocking(move || platform::probe(addr, limit)).await.unwrap_or_default();
+                (addr.ip(), port, caps)
+            }));
+        }
+    }
+    for handle in handles {
+        if let Ok((host, port, caps)) = handle.await {
+            if let Some(result) = results.get_mut(&host).and_then(|r| r.get_mut(&port)) {
+                result.capabilities = Some(caps);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::fs;
+    use std::io::Write;
+    use std::mem;
+    use std::net::{SocketAddr, TcpStream};
+    use std::os::fd::AsRawFd;
+    use std::time::{Duration, Instant};
+    use socket2::{Domain, SockAddr, Socket, Type};
+    use super::TcpCaps;
+
+    // linux/tcp.h 的 tcpi_options 位元
+    const TCPI_OPT_TIMESTAMPS: u8 = 0x01;
+    const TCPI_OPT_SACK: u8 = 0x02;
+    const TCPI_OPT_WSCALE: u8 = 0x04;
+    const TCPI_OPT_ECN: u8 = 0x08;
+    const TCPI_OPT_SYN_DATA: u8 = 0x20;
+
+    const TCP_ESTABLISHED: u8 = 1;
+
+    // 隨 SYN 送出的資料；單一換行對多數以行為單位的協定無害
+    const PAYLOAD: &[u8] = b"\r\n";
+
+    fn sysctl(name: &str) -> Option<u32> {
+        fs::read_to_string(format!("/proc/sys/net/ipv4/{}", name)).ok()?.trim().parse().ok()
+    }
+
+    fn tcp_info(stream: &TcpStream) -> Option<libc::tcp_info> {
+        let mut info: libc::tcp_info = unsafe { mem::zeroed() };
+        let mut len = mem::size_of::<libc::tcp_info>() as libc::socklen_t;
+        let rc = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::IPPROTO_TCP,
+                libc::TCP_INFO,
+                &mut info as *mut _ as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        (rc == 0).then_some(info)
+    }
+
+    // 以 TCP_FASTOPEN_CONNECT 連線並送出資料，等到連線建立後讀取 TCP_INFO 的選項
+    fn connect(addr: SocketAddr, limit: Duration) -> Option<u8> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None).ok()?;
+        let enable: libc::c_int = 1;
+        unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::IPPROTO_TCP,
+                libc::TCP_FASTOPEN_CONNECT,
+                &enable as *const _ as *const libc::c_void,
+                mem::size_of::<libc::c_int>() as libc::socklen_t,
+            );
+        }
+        socket.set_write_timeout(Some(limit)).ok()?;
+        // 啟用 TCP_FASTOPEN_CONNECT 時 connect 立即返回，SYN 延到第一次寫入才送出
+        socket.connect_timeout(&SockAddr::from(addr), limit).ok()?;
+        let mut stream: TcpStream = socket.into();
+        stream.write_all(PAYLOAD).ok()?;
+
+        let deadline = Instant::now() + limit;
+        loop {
+            let info = tcp_info(&stream)?;
+            if info.tcpi_state == TCP_ESTABLISHED {
+                return Some(info.tcpi_options);
+            }
+            if Instant::now() >= deadline {
+                return None;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    pub fn probe(addr: SocketAddr, limit: Duration) -> TcpCaps {
+        let mut caps = TcpCaps::default();
+        // 第一次連線取得 TFO cookie，第二次才可能在 SYN 中帶資料
+        let Some(first) = connect(addr, limit) else {
+            caps.notes.push("無法建立連線".to_string());
+            return caps;
+        };
+        let options = connect(addr, limit).unwrap_or(first);
+
+        caps.window_scale = Some(options & TCPI_OPT_WSCALE != 0);
+        caps.sack = Some(options & TCPI_OPT_SACK != 0);
+        caps.timestamps = Some(options & TCPI_OPT_TIMESTAMPS != 0);
+
+        // tcp_fastopen 位元 1 為用戶端；tcp_ecn 為 1 時才主動要求 ECN
+        match sysctl("tcp_fastopen") {
+            Some(value) if value & 1 != 0 => caps.tfo = Some(options & TCPI_OPT_SYN_DATA != 0),
+            _ => caps.notes.push("本機未啟用 TFO 用戶端 (net.ipv4.tcp_fastopen)".to_string()),
+        }
+        match sysctl("tcp_ecn") {
+            Some(1) => caps.ecn = Some(options & TCPI_OPT_ECN != 0),
+            _ => caps.notes.push("本機未主動要求 ECN (net.ipv4.tcp_ecn)".to_string()),
+        }
+        caps
+    }
+}
+
+// 其他平台沒有 TCP_INFO，全部標示為無法判斷
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use std::net::SocketAddr;
+    use std::time::Duration;
+    use super::TcpCaps;
+
+    pub fn probe(_addr: SocketAddr, _limit: Duration) -> TcpCaps {
+        TcpCaps {
+            notes: vec!["此平台不支援 TCP 功能探測".to_string()],
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,365 @@
+use std::time::Duration;
+use colored::*;
+use reqwest::redirect::Policy;
+use reqwest::Url;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::units::parse_duration;
+use crate::vhost;
+use crate::{PortInfo, ScanResult};
+
+// 被標示的 Web 端口結果附上的說明
+pub const NOTE: &str = "疑似被透明代理/強制門戶攔截";
+
+// 預設端點：回傳空白 204 的連線檢查網址 (HTTP 與 HTTPS 各一)
+const DEFAULT_ENDPOINTS: &[&str] = &["http://connectivitycheck.gstatic.com/generate_204", "https://www.gstatic.com/generate_204"];
+
+// 設定檔 [captive] 區段
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CaptiveConfig {
+    #[serde(default = "default_endpoints")]
+    pub endpoints: Vec<EndpointSpec>,
+
+    // 每個端點的請求逾時 (例如 "3s")
+    #[serde(default = "default_timeout")]
+    pub timeout: String,
+}
+
+impl Default for CaptiveConfig {
+    fn default() -> Self {
+        CaptiveConfig {
+            endpoints: default_endpoints(),
+            timeout: default_timeout(),
+        }
+    }
+}
+
+// 回傳固定內容的網址；[[captive.endpoints]]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EndpointSpec {
+    pub url: String,
+    // 預期的狀態碼
+    #[serde(default = "default_status")]
+    pub status: u16,
+    // 預期的回應內容 (不計前後空白)；省略時只比對狀態碼
+    pub body: Option<String>,
+}
+
+fn default_endpoints() -> Vec<EndpointSpec> {
+    DEFAULT_ENDPOINTS
+        .iter()
+        .map(|url| EndpointSpec { url: url.to_string(), status: default_status(), body: None })
+        .collect()
+}
+
+fn default_timeout() -> String {
+    "3s".to_string()
+}
+
+fn default_status() -> u16 {
+    204
+}
+
+// 端點的協定決定受影響的端口：HTTP 為明文 Web 端口，HTTPS 為 TLS 的 Web 端口
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Scheme {
+    Http,
+    Https,
+}
+
+// 解析過的端點
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    url: Url,
+    scheme: Scheme,
+    status: u16,
+    body: Option<String>,
+}
+
+// 啟動時解析端點與逾時
+pub fn endpoints(config: &CaptiveConfig) -> Result<(Vec<Endpoint>, Duration), String> {
+    let limit = parse_duration(&config.timeout).map_err(|e| format!("設定檔 [captive] timeout: {}", e))?;
+    let mut endpoints = Vec::new();
+    for spec in &config.endpoints {
+        let url = Url::parse(&spec.url).map_err(|e| format!("設定檔 [captive] 端點網址無效: {}: {}", spec.url, e))?;
+        let scheme = match url.scheme() {
+            "http" => Scheme::Http,
+            "https" => Scheme::Https,
+            other => return Err(format!("設定檔 [captive] 端點必須是 http 或 https 網址: {} ({})", spec.url, other)),
+        };
+        endpoints.push(Endpoint { url, scheme, status: spec.status, body: spec.body.clone() });
+    }
+    Ok((endpoints, limit))
+}
+
+// 單一端點的判定
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    // 回應與預期相同
+    Passed,
+    // 狀態碼、內容或憑證與預期不同 (被改寫或導向登入頁)
+    Altered,
+    // 連線成功但沒有完整的回應
+    Missing,
+    // 無法連線 (離線、DNS 失敗)，無法判斷
+    Unreachable,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct EndpointCheck {
+    pub url: String,
+    pub scheme: Scheme,
+    pub verdict: Verdict,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+// JSON metadata 的 captive_portal (--no-captive-check 時不檢查)
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct CaptivePortal {
+    // 至少一個協定的端點被改寫或沒有回應
+    pub detected: bool,
+    // 結果被標示的協定
+    pub intercepted: Vec<Scheme>,
+    pub endpoints: Vec<EndpointCheck>,
+}
+
+impl CaptivePortal {
+    pub fn interception(&self) -> Interception {
+        Interception {
+            http: self.intercepted.contains(&Scheme::Http),
+            https: self.intercepted.contains(&Scheme::Https),
+        }
+    }
+
+    // CSV 註解與 HTML 的 metadata，例如 "http (狀態碼 302，導向 http://portal.example/login)"
+    pub fn describe(&self) -> String {
+        let failed: Vec<String> = self
+            .endpoints
+            .iter()
+            .filter(|check| matches!(check.verdict, Verdict::Altered | Verdict::Missing))
+            .map(|check| {
+                let scheme = match check.scheme {
+                    Scheme::Http => "http",
+                    Scheme::Https => "https",
+                };
+                format!("{} ({})", scheme, check.detail.as_deref().unwrap_or("-"))
+            })
+            .collect();
+        failed.join("; ")
+    }
+}
+
+// 同一協定中有任何端點被改寫或沒有回應就標示該協定的 Web 端口；無法連線不算
+pub fn assess(endpoints: Vec<EndpointCheck>) -> CaptivePortal {
+    let mut intercepted: Vec<Scheme> = endpoints
+        .iter()
+        .filter(|check| matches!(check.verdict, Verdict::Altered | Verdict::Missing))
+        .map(|check| check.scheme)
+        .collect();
+    intercepted.sort();
+    intercepted.dedup();
+    CaptivePortal { detected: !intercepted.is_empty(), intercepted, endpoints }
+}
+
+// 掃描時標示結果用的判定；ScanPlan 的 captive
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Interception {
+    http: bool,
+    https: bool,
+}
+
+impl Interception {
+    // 出站可連線的 Web 端口才標示
+    pub fn marks(self, port: &PortInfo, result: &ScanResult) -> bool {
+        let intercepted = match vhost::uses_tls(port) {
+            true => self.https,
+            false => self.http,
+        };
+        intercepted && result.outbound && vhost::is_web_port(port)
+    }
+
+    pub fn mark(self, port: &PortInfo, result: &mut ScanResult) {
+        if self.marks(port, result) {
+            result.intercepted = true;
+            result.note.get_or_insert_with(|| NOTE.to_string());
+        }
+    }
+}
+
+async fn probe(endpoint: &Endpoint, limit: Duration) -> EndpointCheck {
+    let check = |verdict, status, detail: Option<String>| EndpointCheck {
+        url: endpoint.url.to_string(),
+        scheme: endpoint.scheme,
+        verdict,
+        status,
+        detail,
+    };
+    // 不跟隨導向：強制門戶通常以 302 導向登入頁
+    let client = match reqwest::Client::builder().timeout(limit).redirect(Policy::none()).build() {
+        Ok(client) => client,
+        Err(e) => return check(Verdict::Unreachable, None, Some(e.to_string())),
+    };
+    let response = match client.get(endpoint.url.clone()).send().await {
+        Ok(response) => response,
+        // 透明代理以自己的憑證攔截 TLS
+        Err(e) if vhost::is_certificate_error(&e) => return check(Verdict::Altered, None, Some("TLS 憑證無效".to_string())),
+        Err(e) if e.is_connect() => return check(Verdict::Unreachable, None, Some(e.without_url().to_string())),
+        Err(e) => return check(Verdict::Missing, None, Some(e.without_url().to_string())),
+    };
+    let status = response.status().as_u16();
+    let location = response.headers().get(reqwest::header::LOCATION).and_then(|value| value.to_str().ok()).map(str::to_string);
+    let body = match response.text().await {
+        Ok(body) => body,
+        Err(e) => return check(Verdict::Missing, Some(status), Some(e.without_url().to_string())),
+    };
+    if status != endpoint.status {
+        let detail = match location {
+            Some(location) => format!("狀態碼 {}，導向 {}", status, location),
+            None => format!("狀態碼 {} (預期 {})", status, endpoint.status),
+        };
+        return check(Verdict::Altered, Some(status), Some(detail));
+    }
+    match &endpoint.body {
+        Some(expected) if body.trim() != expected.trim() => check(Verdict::Altered, Some(status), Some("回應內容與預期不同".to_string())),
+        _ => check(Verdict::Passed, Some(status), None),
+    }
+}
+
+// 同時請求所有端點
+pub async fn check(endpoints: &[Endpoint], limit: Duration) -> CaptivePortal {
+    let handles: Vec<_> = endpoints
+        .iter()
+        .cloned()
+        .map(|endpoint| tokio::spawn(async move { probe(&endpoint, limit).await }))
+        .collect();
+    let mut checks = Vec::new();
+    for (handle, endpoint) in handles.into_iter().zip(endpoints) {
+        checks.push(handle.await.unwrap_or_else(|e| EndpointCheck {
+            url: endpoint.url.to_string(),
+            scheme: endpoint.scheme,
+            verdict: Verdict::Unreachable,
+            status: None,
+            detail: Some(e.to_string()),
+        }));
+    }
+    assess(checks)
+}
+
+// 報告開頭的警告；沒有偵測到時不顯示
+pub fn display_warning(portal: &CaptivePortal) {
+    if !portal.detected {
+        return;
+    }
+    println!("\n{}", "⚠ 偵測到透明代理或強制門戶 — Web 端口的結果可能不可信".white().on_red().bold());
+    for check in portal.endpoints.iter().filter(|check| matches!(check.verdict, Verdict::Altered | Verdict::Missing)) {
+        println!("{}", format!("  {}: {}", check.url, check.detail.as_deref().unwrap_or("-")).red());
+    }
+    println!("{}", "連線檢查網址的回應被改寫或沒有回應；標示的端口「可用」可能是代理代為接受連線".red());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{BTreeMap, HashMap};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use crate::testutil::scan_result;
+
+    // 以固定回應回答一個 HTTP 請求的本機伺服器；reply 為 None 時讀完請求後直接關閉
+    async fn server(reply: Option<&'static str>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buffer = [0u8; 1024];
+                let _ = stream.read(&mut buffer).await;
+                if let Some(reply) = reply {
+                    let _ = stream.write_all(reply.as_bytes()).await;
+                }
+            }
+        });
+        format!("http://{}/generate_204", addr)
+    }
+
+    fn endpoint(url: &str, status: u16, body: Option<&str>) -> Endpoint {
+        let config = CaptiveConfig {
+            endpoints: vec![EndpointSpec { url: url.to_string(), status, body: body.map(str::to_string) }],
+            timeout: "2s".to_string(),
+        };
+        endpoints(&config).unwrap().0.remove(0)
+    }
+
+    #[tokio::test]
+    async fn altered_or_missing_responses_are_detected() {
+        let limit = Duration::from_secs(2);
+        let clean = server(Some("HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")).await;
+        assert_eq!(probe(&endpoint(&clean, 204, None), limit).await.verdict, Verdict::Passed);
+
+        let portal = server(Some("HTTP/1.1 302 Found\r\nLocation: http://portal.example/login\r\nContent-Length: 0\r\n\r\n")).await;
+        let redirected = probe(&endpoint(&portal, 204, None), limit).await;
+        assert_eq!((redirected.verdict, redirected.status), (Verdict::Altered, Some(302)));
+        assert_eq!(redirected.detail.as_deref(), Some("狀態碼 302，導向 http://portal.example/login"));
+
+        // 回傳固定內容的端點：狀態碼相同但內容被換成登入頁
+        let rewritten = server(Some("HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nlogin")).await;
+        assert_eq!(probe(&endpoint(&rewritten, 200, Some("success")), limit).await.verdict, Verdict::Altered);
+        let expected = server(Some("HTTP/1.1 200 OK\r\nContent-Length: 8\r\n\r\nsuccess\n")).await;
+        assert_eq!(probe(&endpoint(&expected, 200, Some("success")), limit).await.verdict, Verdict::Passed);
+
+        let silent = server(None).await;
+        assert_eq!(probe(&endpoint(&silent, 204, None), limit).await.verdict, Verdict::Missing);
+
+        // 沒有服務的端口：無法判斷，不算攔截
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let unreachable = probe(&endpoint(&format!("http://{}/", closed), 204, None), limit).await;
+        assert_eq!(unreachable.verdict, Verdict::Unreachable);
+    }
+
+    #[test]
+    fn only_intercepted_schemes_mark_connected_web_ports() {
+        let check = |scheme, verdict| EndpointCheck { url: String::new(), scheme, verdict, status: None, detail: None };
+        let portal = assess(vec![check(Scheme::Http, Verdict::Altered), check(Scheme::Https, Verdict::Unreachable)]);
+        assert!(portal.detected);
+        assert_eq!(portal.intercepted, [Scheme::Http]);
+        let interception = portal.interception();
+
+        let mut results: BTreeMap<_, HashMap<PortInfo, ScanResult>> = BTreeMap::new();
+        let ports = [
+            (PortInfo::new(80, "HTTP", "Web"), true),
+            (PortInfo::new(443, "HTTPS", "Web"), true),
+            (PortInfo::new(8080, "HTTP-Alt", "Web"), false),
+            (PortInfo::new(22, "SSH", "Remote"), true),
+        ];
+        results.insert(crate::testutil::host(1), ports.iter().map(|(port, open)| (port.clone(), scan_result(*open))).collect());
+        for (port, result) in results.values_mut().flatten() {
+            interception.mark(port, result);
+        }
+        let marked: Vec<u16> = {
+            let mut marked: Vec<u16> = results.values().flatten().filter(|(_, r)| r.intercepted).map(|(p, _)| p.port).collect();
+            marked.sort();
+            marked
+        };
+        assert_eq!(marked, [80]);
+        let http = &results.values().next().unwrap()[&ports[0].0];
+        assert_eq!(http.note.as_deref(), Some(NOTE));
+        assert!(!assess(vec![check(Scheme::Http, Verdict::Unreachable)]).detected);
+    }
+
+    #[test]
+    fn endpoints_must_be_web_urls() {
+        let config = |url: &str| CaptiveConfig {
+            endpoints: vec![EndpointSpec { url: url.to_string(), status: 204, body: None }],
+            timeout: "3s".to_string(),
+        };
+        assert!(endpoints(&config("ftp://example.com/")).unwrap_err().contains("http 或 https"));
+        assert!(endpoints(&config("not a url")).is_err());
+        assert_eq!(endpoints(&CaptiveConfig::default()).unwrap().0.len(), 2);
+    }
+}
@@ -0,0 +1,159 @@
+use encoding_rs::{Encoding, BIG5, GBK, SHIFT_JIS, UTF_8, WINDOWS_1252};
+
+// 非 ASCII 位元組的平均分數達到此值時視為判斷可靠，不另外保留原始位元組
+const CONFIDENT: f64 = 0.6;
+
+// 控制字元 (換行與 Tab 以外) 超過此比例時視為二進位資料
+const BINARY_RATIO: f64 = 0.1;
+
+// 依序嘗試的舊式編碼；分數相同時排在前面的優先
+const CANDIDATES: [&Encoding; 4] = [GBK, BIG5, SHIFT_JIS, WINDOWS_1252];
+
+// 解碼後的橫幅
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decoded {
+    pub text: String,
+    // 判斷出的編碼 (WHATWG 名稱)；二進位資料為 None
+    pub encoding: Option<&'static str>,
+    // 判斷不可靠時應保留原始位元組
+    pub confident: bool,
+}
+
+fn binary(bytes: &[u8]) -> bool {
+    let controls = bytes.iter().filter(|&&b| (b < 0x20 && !b"\t\n\r".contains(&b)) || b == 0x7f).count();
+    controls as f64 > bytes.len() as f64 * BINARY_RATIO
+}
+
+// 單一字元在該編碼中的分數：常用字區為 2，標點符號區為 1，罕用字為 0，
+// 把雙位元組文字切成單位元組片假名或孤立的拉丁字母時為負分
+fn char_score(encoding: &'static Encoding, c: char, latin_neighbor: bool) -> i32 {
+    if encoding == WINDOWS_1252 {
+        return match c {
+            'À'..='ÿ' if c != '×' && c != '÷' && latin_neighbor => 1,
+            '\u{80}'..='\u{9f}' => -1,
+            _ => 0,
+        };
+    }
+    let mut buffer = [0u8; 4];
+    let (bytes, _, _) = encoding.encode(c.encode_utf8(&mut buffer));
+    let (lead, trail) = match *bytes {
+        [lead, trail] => (lead, trail),
+        // Shift_JIS 的半形片假名
+        [_] => return -1,
+        _ => return 0,
+    };
+    let common = if encoding == GBK {
+        // GB2312 一級字：最常用的 3755 個簡體字
+        (0xb0..=0xd7).contains(&lead) && trail >= 0xa1
+    } else if encoding == BIG5 {
+        // Big5 常用字
+        (0xa4..=0xc6).contains(&lead)
+    } else {
+        // 平假名、片假名與第一水準漢字
+        matches!(lead, 0x82 | 0x83 | 0x88..=0x9f)
+    };
+    let punctuation = match encoding {
+        e if e == SHIFT_JIS => lead == 0x81,
+        _ => (0xa1..=0xa3).contains(&lead),
+    };
+    match (common, punctuation) {
+        (true, _) => 2,
+        (_, true) => 1,
+        _ => 0,
+    }
+}
+
+// 非 ASCII 位元組的平均分數；有無效位元組序列時為 None
+fn score(encoding: &'static Encoding, bytes: &[u8]) -> Option<(String, f64)> {
+    let text = encoding.decode_without_bom_handling_and_without_replacement(bytes)?.into_owned();
+    let chars: Vec<char> = text.chars().collect();
+    let total: i32 = chars
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| !c.is_ascii())
+        .map(|(i, &c)| {
+            let neighbor = |j: Option<usize>| j.and_then(|j| chars.get(j)).is_some_and(char::is_ascii_alphabetic);
+            char_score(encoding, c, neighbor(i.checked_sub(1)) || neighbor(Some(i + 1)))
+        })
+        .sum();
+    let high = bytes.iter().filter(|b| !b.is_ascii()).count().max(1);
+    Some((text, total as f64 / high as f64))
+}
+
+// 判斷橫幅的編碼並轉成 UTF-8：合法的 UTF-8 直接使用，否則依字元分布選擇分數最高的舊式編碼
+pub fn decode(bytes: &[u8]) -> Decoded {
+    if binary(bytes) {
+        return Decoded { text: String::from_utf8_lossy(bytes).into_owned(), encoding: None, confident: false };
+    }
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return Decoded { text: text.to_string(), encoding: Some(UTF_8.name()), confident: true };
+    }
+    let (encoding, text, score) = CANDIDATES
+        .iter()
+        .filter_map(|&encoding| score(encoding, bytes).map(|(text, score)| (encoding, text, score)))
+        .fold(None, |best: Option<(&'static Encoding, String, f64)>, candidate| match best {
+            Some(best) if best.2 >= candidate.2 => Some(best),
+            _ => Some(candidate),
+        })
+        // windows-1252 能解碼任何位元組
+        .expect("windows-1252 沒有無效的位元組");
+    Decoded { text, encoding: Some(encoding.name()), confident: score >= CONFIDENT }
+}
+
+// 終端顯示前移除控制字元、雙向文字控制與零寬字元 (可用來偽造或隱藏輸出)
+pub fn clean(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            c if c.is_control() => ' ',
+            '\u{200b}'..='\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}' | '\u{feff}' => ' ',
+            c => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded(encoding: &'static Encoding, text: &str) -> Vec<u8> {
+        let (bytes, _, unmappable) = encoding.encode(text);
+        assert!(!unmappable, "{} 無法以 {} 編碼", text, encoding.name());
+        bytes.into_owned()
+    }
+
+    #[test]
+    fn legacy_cjk_banners_are_detected() {
+        for (encoding, text) in [
+            (GBK, "220 欢迎使用文件传输服务器，请登录"),
+            (BIG5, "220 歡迎使用檔案傳輸伺服器，請登入"),
+            (SHIFT_JIS, "220 ようこそ、ファイル転送サーバーへ"),
+            (WINDOWS_1252, "220 Bienvenue sur le serveur Müller, accès réservé"),
+        ] {
+            let decoded = decode(&encoded(encoding, text));
+            assert_eq!(decoded.encoding, Some(encoding.name()), "{}", text);
+            assert_eq!(decoded.text, text);
+            assert!(decoded.confident, "{}", text);
+        }
+        let utf8 = decode("SSH-2.0-OpenSSH_9.6 歡迎".as_bytes());
+        assert_eq!((utf8.encoding, utf8.confident), (Some("UTF-8"), true));
+    }
+
+    #[test]
+    fn binary_garbage_is_not_confident() {
+        let garbage: Vec<u8> = (0u8..=255).cycle().skip(7).step_by(13).take(120).collect();
+        let decoded = decode(&garbage);
+        assert_eq!(decoded.encoding, None);
+        assert!(!decoded.confident);
+        assert!(!clean(&decoded.text).chars().any(char::is_control));
+
+        // 沒有控制字元但不像任何語言的位元組
+        let noise = [0xfd, 0xfe, 0x8f, 0xfc, 0xfd, 0x9d, 0xfe, 0xfd];
+        assert!(!decode(&noise).confident);
+    }
+
+    #[test]
+    fn terminal_text_has_no_control_characters() {
+        let cleaned = clean("a\x1b[31mb\u{202e}c\u{200b}d\u{9b}e\r\n");
+        assert_eq!(cleaned, "a [31mb c d e  ");
+    }
+}
@@ -0,0 +1,553 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::Arc;
+use colored::*;
+use regex::Regex;
+use reqwest::redirect::Policy;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
+use tokio::time::{timeout, Instant};
+use super::{CheckFuture, CheckOutcome, CheckStatus, CheckTarget, Intrusiveness, ServiceCheck, CHECK_TIMEOUT};
+use crate::grade;
+use crate::pipeline::Stage;
+use crate::probes;
+use crate::scanner::ScanPlan;
+use crate::{PortInfo, ScanResult};
+
+// HTTP 回應內容最多讀取的位元組數，之後的內容不比對
+const BODY_LIMIT: usize = 64 * 1024;
+
+// TCP 回應最多讀取的位元組數
+const RESPONSE_LIMIT: usize = 4096;
+
+// Redis 的 PING 指令 (RESP 陣列格式)
+const REDIS_PING: &[u8] = b"*1\r\n$4\r\nPING\r\n";
+
+// 設定檔 [health.<端口>]：連線成功後對該端口執行的健康檢查
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum HealthSpec {
+    // 送出 GET 請求，比對狀態碼與回應內容
+    Http {
+        #[serde(default = "default_path")]
+        path: String,
+        #[serde(default = "default_status")]
+        expect_status: u16,
+        expect_body_regex: Option<String>,
+        // 未指定時 443 與 8443 使用 HTTPS
+        tls: Option<bool>,
+    },
+    // 連線後 (先送出 send) 讀取回應並比對
+    TcpBannerRegex {
+        expect_regex: String,
+        send: Option<String>,
+    },
+    // 送出 Redis PING，預期回應 PONG
+    RedisPing,
+}
+
+fn default_path() -> String {
+    "/".to_string()
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+// 編譯後的檢查內容
+#[derive(Debug)]
+enum Probe {
+    Http { path: String, status: u16, body: Option<Regex>, tls: bool },
+    Banner { pattern: Regex, send: Vec<u8> },
+    RedisPing,
+}
+
+// 單一端口的健康檢查
+#[derive(Debug)]
+pub struct HealthCheck {
+    port: u16,
+    probe: Probe,
+}
+
+impl HealthCheck {
+    fn compile(port: u16, spec: &HealthSpec) -> Result<Self, String> {
+        let pattern = |text: &str| Regex::new(text).map_err(|e| format!("規則錯誤: {}", e));
+        let probe = match spec {
+            HealthSpec::Http { path, expect_status, expect_body_regex, tls } => {
+                if !path.starts_with('/') {
+                    return Err(format!("path 必須以 / 開頭: {}", path));
+                }
+                Probe::Http {
+                    path: path.clone(),
+                    status: *expect_status,
+                    body: expect_body_regex.as_deref().map(pattern).transpose()?,
+                    tls: tls.unwrap_or(matches!(port, 443 | 8443)),
+                }
+            }
+            HealthSpec::TcpBannerRegex { expect_regex, send } => Probe::Banner {
+                pattern: pattern(expect_regex)?,
+                send: send.as_deref().unwrap_or_default().as_bytes().to_vec(),
+            },
+            HealthSpec::RedisPing => Probe::RedisPing,
+        };
+        Ok(HealthCheck { port, probe })
+    }
+
+    async fn http(&self, target: &CheckTarget, path: &str, status: u16, body: Option<&Regex>, tls: bool) -> CheckOutcome {
+        let outcome = |status, summary: String| CheckOutcome::new(self.name(), target.port, status, summary);
+        let client = match reqwest::Client::builder()
+            .timeout(target.timeout)
+            .redirect(Policy::none())
+            .danger_accept_invalid_certs(true)
+            .build()
+        {
+            Ok(client) => client,
+            Err(e) => return outcome(CheckStatus::Error, e.to_string()),
+        };
+        let scheme = if tls { "https" } else { "http" };
+        let url = format!("{}://{}{}", scheme, target.context.socket_addr(target.addr, target.port), path);
+        let _socket = target.context.socket();
+        let mut response = match client.get(url).send().await {
+            Ok(response) => response,
+            Err(e) if e.is_connect() || e.is_timeout() => return outcome(CheckStatus::NoResponse, e.without_url().to_string()),
+            Err(e) => return outcome(CheckStatus::Error, e.without_url().to_string()),
+        };
+        let code = response.status().as_u16();
+        let mut received = Vec::new();
+        while received.len() < BODY_LIMIT {
+            match response.chunk().await {
+                Ok(Some(chunk)) => received.extend_from_slice(&chunk),
+                _ => break,
+            }
+        }
+        received.truncate(BODY_LIMIT);
+        target.context.received(received.len());
+
+        let text = String::from_utf8_lossy(&received);
+        let outcome = if code != status {
+            outcome(CheckStatus::Warning, format!("{} 回應狀態碼 {} (預期 {})", path, code, status))
+        } else if let Some(pattern) = body.filter(|pattern| !pattern.is_match(&text)) {
+            outcome(CheckStatus::Warning, format!("{} 的回應內容不符合 {}", path, pattern))
+        } else {
+            outcome(CheckStatus::Ok, format!("{} 回應 {}", path, code))
+        };
+        outcome.detail("狀態碼", code.to_string())
+    }
+
+    async fn banner(&self, target: &CheckTarget, pattern: &Regex, send: &[u8]) -> CheckOutcome {
+        let outcome = |status, summary: String| CheckOutcome::new(self.name(), target.port, status, summary);
+        let response = match converse(target, send, |text| pattern.is_match(text)).await {
+            Ok(response) if response.is_empty() => return outcome(CheckStatus::NoResponse, "沒有回應".to_string()),
+            Ok(response) => response,
+            Err(e) => return outcome(CheckStatus::NoResponse, e),
+        };
+        let text = String::from_utf8_lossy(&response);
+        let outcome = match pattern.is_match(&text) {
+            true => outcome(CheckStatus::Ok, format!("回應符合 {}", pattern)),
+            false => outcome(CheckStatus::Warning, format!("回應不符合 {}", pattern)),
+        };
+        outcome.detail("回應", probes::sanitize(&response))
+    }
+
+    async fn redis(&self, target: &CheckTarget) -> CheckOutcome {
+        let outcome = |status, summary: String| CheckOutcome::new(self.name(), target.port, status, summary);
+        let response = match converse(target, REDIS_PING, |text| text.contains("\r\n")).await {
+            Ok(response) if response.is_empty() => return outcome(CheckStatus::NoResponse, "沒有回應".to_string()),
+            Ok(response) => response,
+            Err(e) => return outcome(CheckStatus::NoResponse, e),
+        };
+        let text = String::from_utf8_lossy(&response);
+        let line = text.lines().next().unwrap_or_default();
+        match line.as_bytes().first() {
+            _ if line == "+PONG" => outcome(CheckStatus::Ok, "PONG".to_string()),
+            // 需要驗證 (NOAUTH) 或載入中 (LOADING) 等錯誤回應
+            Some(b'-') => outcome(CheckStatus::Warning, format!("Redis 回應錯誤: {}", probes::sanitize(&line.as_bytes()[1..]))),
+            _ => outcome(CheckStatus::Warning, "不是 Redis 的回應".to_string()).detail("回應", probes::sanitize(&response)),
+        }
+    }
+}
+
+// 連線後送出 payload，讀取回應直到 done 成立、對方關閉、讀滿上限或逾時
+async fn converse(target: &CheckTarget, payload: &[u8], done: impl Fn(&str) -> bool) -> Result<Vec<u8>, String> {
+    let deadline = Instant::now() + target.timeout;
+    let _socket = target.context.socket();
+    let addr = target.context.socket_addr(target.addr, target.port);
+    let mut stream = match timeout(target.timeout, TcpStream::connect(addr)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => return Err(e.to_string()),
+        Err(_) => return Err("連線逾時".to_string()),
+    };
+    if !payload.is_empty() {
+        stream.write_all(payload).await.map_err(|e| e.to_string())?;
+        target.context.sent(payload.len());
+    }
+
+    let mut response = Vec::new();
+    let mut buf = [0u8; 1024];
+    while response.len() < RESPONSE_LIMIT {
+        match timeout(deadline.saturating_duration_since(Instant::now()), stream.read(&mut buf)).await {
+            Ok(Ok(0)) | Ok(Err(_)) | Err(_) => break,
+            Ok(Ok(n)) => {
+                response.extend_from_slice(&buf[..n]);
+                if done(&String::from_utf8_lossy(&response)) {
+                    break;
+                }
+            }
+        }
+    }
+    response.truncate(RESPONSE_LIMIT);
+    target.context.received(response.len());
+    Ok(response)
+}
+
+impl ServiceCheck for HealthCheck {
+    fn name(&self) -> &'static str {
+        match self.probe {
+            Probe::Http { .. } => "health-http",
+            Probe::Banner { .. } => "health-banner",
+            Probe::RedisPing => "health-redis",
+        }
+    }
+
+    fn ports(&self) -> &[u16] {
+        std::slice::from_ref(&self.port)
+    }
+
+    // 只讀取橫幅的檢查不送出任何內容
+    fn level(&self) -> Intrusiveness {
+        match &self.probe {
+            Probe::Banner { send, .. } if send.is_empty() => Intrusiveness::Passive,
+            _ => Intrusiveness::Active,
+        }
+    }
+
+    fn run<'a>(&'a self, target: &'a CheckTarget) -> CheckFuture<'a> {
+        Box::pin(async move {
+            match &self.probe {
+                Probe::Http { path, status, body, tls } => self.http(target, path, *status, body.as_ref(), *tls).await,
+                Probe::Banner { pattern, send } => self.banner(target, pattern, send).await,
+                Probe::RedisPing => self.redis(target).await,
+            }
+        })
+    }
+}
+
+// 設定檔中所有端口的健康檢查
+#[derive(Debug, Default)]
+pub struct HealthChecks(BTreeMap<u16, HealthCheck>);
+
+impl HealthChecks {
+    // 啟動時就編譯規則，錯誤的設定不會等到掃描後才發現
+    pub fn build(specs: &BTreeMap<String, HealthSpec>) -> Result<Self, String> {
+        let mut checks = BTreeMap::new();
+        for (key, spec) in specs {
+            let port: u16 = key.parse().map_err(|_| format!("設定檔 [health.{}]: 不是有效的端口號碼", key))?;
+            let check = HealthCheck::compile(port, spec).map_err(|e| format!("設定檔 [health.{}]: {}", key, e))?;
+            checks.insert(port, check);
+        }
+        Ok(HealthChecks(checks))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, port: u16) -> Option<&HealthCheck> {
+        self.0.get(&port)
+    }
+}
+
+// 有健康檢查的端口的三種狀態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthState {
+    // 開放且檢查通過
+    Healthy,
+    // 開放但檢查失敗或沒有回應
+    Unhealthy,
+    // 端口未開放，沒有執行檢查
+    Closed,
+}
+
+impl HealthState {
+    pub fn label(self) -> &'static str {
+        match self {
+            HealthState::Healthy => "健康",
+            HealthState::Unhealthy => "不健康",
+            HealthState::Closed => "未開放",
+        }
+    }
+}
+
+// 端口的健康檢查結果
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Health {
+    pub check: String,
+    pub state: HealthState,
+    pub summary: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub details: BTreeMap<String, String>,
+}
+
+impl Health {
+    fn closed(check: &str) -> Self {
+        Health {
+            check: check.to_string(),
+            state: HealthState::Closed,
+            summary: "端口未開放，未執行檢查".to_string(),
+            details: BTreeMap::new(),
+        }
+    }
+}
+
+impl From<CheckOutcome> for Health {
+    fn from(outcome: CheckOutcome) -> Self {
+        Health {
+            check: outcome.check.to_string(),
+            state: match outcome.status {
+                CheckStatus::Ok => HealthState::Healthy,
+                _ => HealthState::Unhealthy,
+            },
+            summary: outcome.summary,
+            details: outcome.details.into_iter().collect(),
+        }
+    }
+}
+
+// 結果的健康狀態；端口沒有健康檢查或未測試出站時為 None
+pub fn state(result: &ScanResult) -> Option<HealthState> {
+    result.health.as_ref().map(|health| health.state)
+}
+
+// 對設定了健康檢查的端口執行檢查：出站可連線的端口連線檢查，不可連線的記為未開放
+// 超出 --stages 時略過；檢查後重新評分，讓等級納入健康狀態
+pub async fn probe_results(results: &mut BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>, plan: &ScanPlan) {
+    if plan.health.is_empty() || !plan.pipeline.reaches(Stage::Checks) {
+        return;
+    }
+    let limit = plan.timeouts.default.max(CHECK_TIMEOUT);
+    let semaphore = Arc::new(Semaphore::new(plan.concurrency.max(1)));
+    let mut handles = Vec::new();
+    for (host, host_results) in results.iter_mut() {
+        for (port, result) in host_results.iter_mut() {
+            let Some(check) = plan.health.get(port.port) else {
+                continue;
+            };
+            if result.error.is_some() || !result.directions.outbound() {
+                continue;
+            }
+            if !result.outbound {
+                result.health = Some(Health::closed(check.name()));
+                continue;
+            }
+            let target = CheckTarget {
+                addr: *host,
+                port: port.port,
+                timeout: limit,
+                allowed: check.level(),
+                prober: plan.prober.clone(),
+                context: plan.context.clone(),
+            };
+            let (checks, semaphore, port) = (plan.health.clone(), semaphore.clone(), port.clone());
+            handles.push(tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let outcome = checks.0[&target.port].run(&target).await;
+                (target.addr, port, outcome)
+            }));
+        }
+    }
+    for handle in handles {
+        if let Ok((host, port, outcome)) = handle.await {
+            if let Some(result) = results.get_mut(&host).and_then(|r| r.get_mut(&port)) {
+                result.health = Some(Health::from(outcome));
+                if result.grade.is_some() {
+                    result.grade = grade::grade_result(result, None, &plan.grading);
+                }
+            }
+        }
+    }
+}
+
+// 報告中的健康檢查行
+pub fn describe(health: &Health) -> String {
+    let text = format!("健康檢查 {}: {} — {}", health.check, health.state.label(), health.summary);
+    match health.state {
+        HealthState::Healthy => text.green().to_string(),
+        HealthState::Unhealthy => text.red().to_string(),
+        HealthState::Closed => text.dimmed().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+    use crate::alerts::{AlertEngine, AlertRule};
+    use crate::selftest;
+    use crate::testutil::scan_result;
+    use crate::timeouts::Timeouts;
+
+    fn specs(text: &str) -> Result<HealthChecks, String> {
+        #[derive(Deserialize)]
+        struct File {
+            health: BTreeMap<String, HealthSpec>,
+        }
+        let file: File = toml::from_str(text).map_err(|e| e.to_string())?;
+        HealthChecks::build(&file.health)
+    }
+
+    #[test]
+    fn definitions_compile_from_the_config() {
+        let checks = specs(
+            r#"
+            [health.8080]
+            type = "http"
+            path = "/healthz"
+            expect_status = 200
+            expect_body_regex = "ok"
+            [health.25]
+            type = "tcp-banner-regex"
+            expect_regex = "^220 "
+            [health.6379]
+            type = "redis-ping"
+            "#,
+        )
+        .unwrap();
+        let names: Vec<_> = checks.0.values().map(|c| (c.port, c.name(), c.level())).collect();
+        assert_eq!(
+            names,
+            vec![
+                (25, "health-banner", Intrusiveness::Passive),
+                (6379, "health-redis", Intrusiveness::Active),
+                (8080, "health-http", Intrusiveness::Active),
+            ]
+        );
+        assert!(matches!(&checks.get(8080).unwrap().probe, Probe::Http { path, status: 200, tls: false, .. } if path == "/healthz"));
+        // 443 預設使用 HTTPS
+        let checks = specs("[health.443]\ntype = \"http\"").unwrap();
+        assert!(matches!(&checks.get(443).unwrap().probe, Probe::Http { tls: true, .. }));
+
+        for bad in [
+            "[health.http]\ntype = \"redis-ping\"",
+            "[health.80]\ntype = \"http\"\npath = \"healthz\"",
+            "[health.80]\ntype = \"http\"\nexpect_body_regex = \"(\"",
+            "[health.80]\ntype = \"http\"\nexpect = 200",
+            "[health.80]\ntype = \"ping\"",
+        ] {
+            assert!(specs(bad).is_err(), "{}", bad);
+        }
+    }
+
+    // 回應固定內容的本機服務，回傳端口
+    async fn serve(reply: &'static [u8]) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf).await;
+                let _ = stream.write_all(reply).await;
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn open_ports_are_checked_and_closed_ports_recorded() {
+        let healthy = serve(b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\nConnection: close\r\n\r\nall ok").await;
+        let failing = serve(b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").await;
+        let redis = serve(b"-NOAUTH Authentication required.\r\n").await;
+        let checks = specs(&format!(
+            r#"
+            [health.{healthy}]
+            type = "http"
+            expect_body_regex = "ok$"
+            [health.{failing}]
+            type = "http"
+            [health.{redis}]
+            type = "redis-ping"
+            [health.9]
+            type = "redis-ping"
+            "#
+        ))
+        .unwrap();
+        let plan = ScanPlan {
+            health: Arc::new(checks),
+            ..selftest::localhost_plan(Vec::new(), 4, Timeouts::fixed(Duration::from_secs(2)), Vec::new())
+        };
+        let localhost: IpAddr = "127.0.0.1".parse().unwrap();
+        let mut results = BTreeMap::new();
+        let host_results: &mut HashMap<PortInfo, ScanResult> = results.entry(localhost).or_default();
+        for port in [healthy, failing, redis] {
+            let mut result = scan_result(true);
+            result.grade = grade::grade_result(&result, None, &plan.grading);
+            host_results.insert(PortInfo::new(port, "Test", "Test"), result);
+        }
+        host_results.insert(PortInfo::new(9, "Discard", "Test"), scan_result(false));
+        // 沒有健康檢查的端口不變
+        host_results.insert(PortInfo::new(22, "SSH", "Test"), scan_result(true));
+
+        probe_results(&mut results, &plan).await;
+        let outcome = |port: u16| results[&localhost].iter().find(|(p, _)| p.port == port).map(|(_, r)| r).unwrap();
+        assert_eq!(state(outcome(healthy)), Some(HealthState::Healthy));
+        assert_eq!(outcome(healthy).grade.as_ref().map(|g| g.grade), Some(grade::Grade::A));
+        let unhealthy = outcome(failing).health.as_ref().unwrap();
+        assert_eq!((unhealthy.state, unhealthy.details["狀態碼"].as_str()), (HealthState::Unhealthy, "503"));
+        assert_eq!(outcome(failing).grade.as_ref().map(|g| (g.grade, g.reason.as_str())), Some((grade::Grade::D, "可連線但健康檢查失敗")));
+        let redis = outcome(redis).health.as_ref().unwrap();
+        assert_eq!((redis.state, redis.summary.as_str()), (HealthState::Unhealthy, "Redis 回應錯誤: NOAUTH Authentication required."));
+        assert_eq!(state(outcome(9)), Some(HealthState::Closed));
+        assert_eq!(state(outcome(22)), None);
+
+        let json = serde_json::to_value(outcome(failing)).unwrap();
+        assert_eq!(json["health"]["state"], "unhealthy");
+        assert_eq!(json["health"]["check"], "health-http");
+    }
+
+    #[tokio::test]
+    async fn banners_and_pong_replies_pass() {
+        let smtp = serve(b"220 mail.example ESMTP\r\n").await;
+        let redis = serve(b"+PONG\r\n").await;
+        let checks = specs(&format!(
+            "[health.{smtp}]\ntype = \"tcp-banner-regex\"\nexpect_regex = \"^220 \"\nsend = \"EHLO scanner\\r\\n\"\n[health.{redis}]\ntype = \"redis-ping\""
+        ))
+        .unwrap();
+        let target = |port| CheckTarget {
+            addr: "127.0.0.1".parse().unwrap(),
+            port,
+            timeout: Duration::from_secs(2),
+            allowed: Intrusiveness::Active,
+            prober: Arc::new(crate::prober::fake::ScriptedProber::new()),
+            context: Arc::new(crate::context::ScanContext::offline()),
+        };
+        let smtp = checks.get(smtp).unwrap().run(&target(smtp)).await;
+        assert_eq!((smtp.status, smtp.details[0].1.as_str()), (CheckStatus::Ok, "220 mail.example ESMTP"));
+        let redis = checks.get(redis).unwrap().run(&target(redis)).await;
+        assert_eq!((redis.status, redis.summary.as_str()), (CheckStatus::Ok, "PONG"));
+    }
+
+    fn unhealthy_result(state: HealthState) -> ScanResult {
+        let mut result = scan_result(state != HealthState::Closed);
+        result.health = Some(Health { check: "health-http".to_string(), state, summary: String::new(), details: BTreeMap::new() });
+        result
+    }
+
+    #[test]
+    fn alerts_fire_after_consecutive_unhealthy_scans() {
+        let rule: AlertRule = toml::from_str("name = \"api\"\nkind = \"unhealthy\"\nconsecutive = 2").unwrap();
+        let mut engine = AlertEngine::new(vec![rule]);
+        let scan = |state| BTreeMap::from([(crate::testutil::host(1), HashMap::from([(PortInfo::new(8080, "HTTP-ALT", "Web"), unhealthy_result(state))]))]);
+        assert!(engine.observe(&scan(HealthState::Unhealthy)).1.is_empty());
+        let (_, alerts) = engine.observe(&scan(HealthState::Unhealthy));
+        assert_eq!(alerts.len(), 1);
+        assert!(alerts[0].message.ends_with("連續 2 次開放但健康檢查失敗"), "{}", alerts[0].message);
+        assert_eq!(alerts[0].history[0].health, Some(HealthState::Unhealthy));
+        // 未開放的端口由 unreachable 規則處理，不延續不健康的次數
+        assert!(engine.observe(&scan(HealthState::Closed)).1.is_empty());
+        assert!(engine.observe(&scan(HealthState::Unhealthy)).1.is_empty());
+    }
+}
@@ -0,0 +1,348 @@
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use colored::*;
+use std::collections::BTreeMap;
+use schemars::JsonSchema;
+use serde::{Serialize, Serializer};
+use tokio::net::UdpSocket;
+use tokio::time::{timeout, Instant};
+use crate::icmp::PROTO_UDP;
+use crate::prober::Prober;
+use crate::context::ScanContext;
+use crate::scanner::ScanPlan;
+
+pub mod health;
+pub mod ntp;
+pub mod tftp;
+
+// 單項檢查的預設逾時
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub type CheckFuture<'a> = Pin<Box<dyn Future<Output = CheckOutcome> + Send + 'a>>;
+
+// 服務檢查的目標
+#[derive(Debug, Clone)]
+pub struct CheckTarget {
+    pub addr: IpAddr,
+    pub port: u16,
+    pub timeout: Duration,
+    // 此檢查允許的最高侵入程度，由 registry 依使用者設定與檢查宣告決定
+    pub allowed: Intrusiveness,
+    // 單一請求/回應的 UDP 檢查經由此處送出
+    pub prober: Arc<dyn Prober>,
+    // 自行收發封包的檢查 (TFTP) 用來取得連結本地位址的區域
+    pub context: Arc<ScanContext>,
+}
+
+impl CheckTarget {
+    pub fn allows(&self, level: Intrusiveness) -> bool {
+        level <= self.allowed
+    }
+}
+
+// 檢查的侵入程度 (--intrusiveness)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Intrusiveness {
+    // 只讀取服務主動送出的橫幅
+    Passive,
+    // 送出一般的協定查詢
+    Active,
+    // 會改變或讀取目標資料的操作 (例如讀取 TFTP 檔案)
+    Intrusive,
+}
+
+impl Intrusiveness {
+    pub fn label(self) -> &'static str {
+        match self {
+            Intrusiveness::Passive => "passive",
+            Intrusiveness::Active => "active",
+            Intrusiveness::Intrusive => "intrusive",
+        }
+    }
+}
+
+// 檢查結果狀態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    // 服務有回應且未發現風險
+    Ok,
+    // 服務有回應且存在潛在風險
+    Warning,
+    // 沒有收到任何回應
+    NoResponse,
+    // 檢查本身執行失敗
+    Error,
+}
+
+// 定義檢查結果結構
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct CheckOutcome {
+    pub check: &'static str,
+    pub port: u16,
+    pub status: CheckStatus,
+    pub summary: String,
+    #[serde(serialize_with = "details_as_map")]
+    #[schemars(with = "BTreeMap<String, String>")]
+    pub details: Vec<(String, String)>,
+}
+
+// 細節以物件輸出，保留加入順序
+fn details_as_map<S: Serializer>(details: &[(String, String)], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(details.iter().map(|(k, v)| (k, v)))
+}
+
+impl CheckOutcome {
+    pub fn new(check: &'static str, port: u16, status: CheckStatus, summary: impl Into<String>) -> Self {
+        CheckOutcome {
+            check,
+            port,
+            status,
+            summary: summary.into(),
+            details: Vec::new(),
+        }
+    }
+
+    pub fn detail(mut self, key: &str, value: impl Into<String>) -> Self {
+        self.details.push((key.to_string(), value.into()));
+        self
+    }
+}
+
+// 服務檢查介面
+pub trait ServiceCheck: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn ports(&self) -> &[u16];
+    // 執行這項檢查最少需要的侵入程度
+    fn level(&self) -> Intrusiveness;
+    // 允許侵入性操作時才會做的事，執行前會顯示；沒有侵入性操作時為空
+    fn intrusive_actions(&self) -> &'static [&'static str] {
+        &[]
+    }
+    fn run<'a>(&'a self, target: &'a CheckTarget) -> CheckFuture<'a>;
+}
+
+// 所有內建檢查
+pub fn registry() -> Vec<Box<dyn ServiceCheck>> {
+    vec![Box::new(ntp::NtpCheck), Box::new(tftp::TftpCheck)]
+}
+
+// 依 --intrusiveness 篩選檢查，並決定每項檢查實際允許的程度：
+// 檢查只能以宣告的程度執行，宣告了侵入性操作且使用者要求 intrusive 時才允許，避免檢查未宣告就升級
+pub fn enabled(requested: Intrusiveness) -> Vec<(Box<dyn ServiceCheck>, Intrusiveness)> {
+    admit(registry(), requested)
+}
+
+fn admit(checks: Vec<Box<dyn ServiceCheck>>, requested: Intrusiveness) -> Vec<(Box<dyn ServiceCheck>, Intrusiveness)> {
+    checks
+        .into_iter()
+        .filter(|check| check.level() <= requested)
+        .map(|check| {
+            let allowed = if requested == Intrusiveness::Intrusive && !check.intrusive_actions().is_empty() {
+                Intrusiveness::Intrusive
+            } else {
+                check.level()
+            };
+            (check, allowed)
+        })
+        .collect()
+}
+
+// 對掃描過的端口執行相符的檢查
+// 沒有回應時，若收到 ICMP 錯誤就附上原因；檢查依序執行，不需比對來源端口
+// 侵入性操作執行前先在 stderr 說明，JSON 輸出也不受影響
+pub async fn run_checks(
+    addr: IpAddr,
+    ports: &[u16],
+    level: Intrusiveness,
+    plan: &ScanPlan,
+) -> Vec<CheckOutcome> {
+    let icmp = plan.icmp.as_deref();
+    let mut outcomes = Vec::new();
+
+    for (check, allowed) in enabled(level) {
+        for &port in check.ports().iter().filter(|p| ports.contains(p)) {
+            let target = CheckTarget {
+                addr,
+                port,
+                timeout: CHECK_TIMEOUT,
+                allowed,
+                prober: plan.prober.clone(),
+                context: plan.context.clone(),
+            };
+            if target.allows(Intrusiveness::Intrusive) {
+                for action in check.intrusive_actions() {
+                    eprintln!("{}", format!("侵入性檢查 {} ({}:{}): {}", check.name(), addr, port, action).yellow());
+                }
+            }
+            let mut outcome = check.run(&target).await;
+            if outcome.status == CheckStatus::NoResponse {
+                if let Some(error) = icmp.and_then(|m| m.take_any(PROTO_UDP, addr, port)) {
+                    outcome = outcome.detail("ICMP", error.describe());
+                }
+            }
+            outcomes.push(outcome);
+        }
+    }
+
+    outcomes
+}
+
+// 送出一個 UDP 請求並收集回應
+// 第一個回應最多等待 wait，之後每個封包間隔超過 linger 即停止
+pub(crate) async fn udp_exchange(
+    context: &ScanContext,
+    addr: IpAddr,
+    port: u16,
+    payload: &[u8],
+    wait: Duration,
+    linger: Duration,
+) -> io::Result<Vec<(SocketAddr, Vec<u8>)>> {
+    let local: SocketAddr = match addr {
+        IpAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        IpAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let _guard = context.socket();
+    let socket = UdpSocket::bind(local).await?;
+    socket.send_to(payload, context.socket_addr(addr, port)).await?;
+    context.sent(payload.len());
+
+    let mut packets = Vec::new();
+    let mut buf = vec![0u8; 65535];
+    let mut deadline = Instant::now() + wait;
+
+    while let Ok(received) = timeout(deadline.saturating_duration_since(Instant::now()), socket.recv_from(&mut buf)).await {
+        let (len, from) = received?;
+        context.received(len);
+        // 只接受來自目標主機的封包，端口可能不同 (例如 TFTP)
+        if from.ip() != addr {
+            continue;
+        }
+        packets.push((from, buf[..len].to_vec()));
+        deadline = Instant::now() + linger;
+    }
+
+    Ok(packets)
+}
+
+// 顯示服務檢查結果
+pub fn display_checks(addr: IpAddr, outcomes: &[CheckOutcome]) {
+    println!("\n{}", format!("=== 服務檢查 ({}) ===", addr).bold());
+
+    if outcomes.is_empty() {
+        println!("沒有適用的檢查");
+        return;
+    }
+
+    for outcome in outcomes {
+        print!("Port {:5} ({:15}): ", outcome.port, outcome.check);
+
+        match outcome.status {
+            CheckStatus::Ok => println!("{}", format!("✓ {}", outcome.summary).green()),
+            CheckStatus::Warning => println!("{}", format!("! {}", outcome.summary).red().bold()),
+            CheckStatus::NoResponse => println!("{}", format!("- {}", outcome.summary).dimmed()),
+            CheckStatus::Error => println!("{}", format!("✗ {}", outcome.summary).red()),
+        }
+
+        for (key, value) in &outcome.details {
+            println!("    {}: {}", key, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prober::fake::{Script, Scripted, ScriptedProber};
+    use crate::testutil::{host, scripted_plan};
+
+    // 只宣告程度與侵入性操作的檢查
+    struct Probe {
+        name: &'static str,
+        level: Intrusiveness,
+        actions: &'static [&'static str],
+    }
+
+    impl ServiceCheck for Probe {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn ports(&self) -> &[u16] {
+            &[9]
+        }
+
+        fn level(&self) -> Intrusiveness {
+            self.level
+        }
+
+        fn intrusive_actions(&self) -> &'static [&'static str] {
+            self.actions
+        }
+
+        fn run<'a>(&'a self, target: &'a CheckTarget) -> CheckFuture<'a> {
+            Box::pin(async move { CheckOutcome::new(self.name, target.port, CheckStatus::Ok, "") })
+        }
+    }
+
+    fn checks() -> Vec<Box<dyn ServiceCheck>> {
+        let probe = |name, level, actions| -> Box<dyn ServiceCheck> { Box::new(Probe { name, level, actions }) };
+        vec![
+            probe("banner", Intrusiveness::Passive, &[]),
+            probe("query", Intrusiveness::Active, &[]),
+            probe("read", Intrusiveness::Active, &["讀取檔案"]),
+            // 宣告為 intrusive 的檢查只在要求 intrusive 時執行
+            probe("write", Intrusiveness::Intrusive, &["寫入檔案"]),
+            // 沒有宣告侵入性操作，就算要求 intrusive 也不會升級
+            probe("quiet", Intrusiveness::Active, &[]),
+        ]
+    }
+
+    fn admitted(requested: Intrusiveness) -> Vec<(&'static str, Intrusiveness)> {
+        admit(checks(), requested).iter().map(|(check, allowed)| (check.name(), *allowed)).collect()
+    }
+
+    #[test]
+    fn filtering_at_each_level() {
+        use Intrusiveness::*;
+        assert_eq!(admitted(Passive), vec![("banner", Passive)]);
+        assert_eq!(admitted(Active), vec![("banner", Passive), ("query", Active), ("read", Active), ("quiet", Active)]);
+        assert_eq!(
+            admitted(Intrusive),
+            vec![("banner", Passive), ("query", Active), ("read", Intrusive), ("write", Intrusive), ("quiet", Active)]
+        );
+    }
+
+    #[test]
+    fn builtin_checks_declare_their_levels() {
+        let names = |level| enabled(level).iter().map(|(check, allowed)| (check.name(), *allowed)).collect::<Vec<_>>();
+        assert!(names(Intrusiveness::Passive).is_empty());
+        assert_eq!(names(Intrusiveness::Active), vec![("NTP", Intrusiveness::Active), ("TFTP", Intrusiveness::Active)]);
+        assert_eq!(names(Intrusiveness::Intrusive), vec![("NTP", Intrusiveness::Active), ("TFTP", Intrusiveness::Intrusive)]);
+        assert!(CheckTarget {
+            addr: host(1),
+            port: 69,
+            timeout: CHECK_TIMEOUT,
+            allowed: Intrusiveness::Active,
+            prober: Arc::new(ScriptedProber::new()),
+            context: Arc::new(ScanContext::offline()),
+        }
+        .allows(Intrusiveness::Passive));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn run_checks_only_runs_admitted_checks() {
+        let prober = Arc::new(ScriptedProber::new().with(host(1), 123, Script::new(Scripted::Open, Duration::from_millis(5))));
+        let plan = scripted_plan(&[host(1)], &[123], 1, prober);
+        assert!(run_checks(host(1), &[123], Intrusiveness::Passive, &plan).await.is_empty());
+        let outcomes = run_checks(host(1), &[123], Intrusiveness::Active, &plan).await;
+        assert_eq!(outcomes.iter().map(|o| (o.check, o.port)).collect::<Vec<_>>(), vec![("NTP", 123)]);
+        // 沒有掃描到的端口不檢查
+        assert!(run_checks(host(1), &[22], Intrusiveness::Intrusive, &plan).await.is_empty());
+    }
+}
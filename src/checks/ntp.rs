@@ -0,0 +1,395 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+use super::{CheckFuture, CheckOutcome, CheckStatus, CheckTarget, Intrusiveness, ServiceCheck};
+
+// 控制查詢可能分批回傳，最後一個封包後再等待的時間
+const LINGER: Duration = Duration::from_millis(300);
+
+// 回應大小超過請求的倍數即視為可被放大
+const AMPLIFICATION_THRESHOLD: f64 = 1.0;
+
+// NTP 伺服器回應中關心的欄位
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NtpInfo {
+    pub version: u8,
+    pub stratum: u8,
+    pub ref_id: String,
+}
+
+// 控制查詢 (mode 6 / mode 7) 的回應統計
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ControlReply {
+    pub packets: usize,
+    pub bytes: usize,
+    pub ratio: f64,
+    pub error: bool,
+    pub server_version: Option<String>,
+}
+
+// 建立 NTP 客戶端請求 (LI=0, VN=4, Mode=3)
+pub fn build_client_request() -> [u8; 48] {
+    let mut packet = [0u8; 48];
+    packet[0] = (4 << 3) | 3;
+    packet
+}
+
+// 解析伺服器回應 (Mode 4) 或廣播 (Mode 5)
+pub fn parse_client_response(buf: &[u8]) -> Option<NtpInfo> {
+    if buf.len() < 48 {
+        return None;
+    }
+
+    let mode = buf[0] & 0x07;
+    if mode != 4 && mode != 5 {
+        return None;
+    }
+
+    let stratum = buf[1];
+    let ref_id = [buf[12], buf[13], buf[14], buf[15]];
+
+    Some(NtpInfo {
+        version: (buf[0] >> 3) & 0x07,
+        stratum,
+        ref_id: format_ref_id(stratum, ref_id),
+    })
+}
+
+// 參考 ID：stratum 0/1 為 ASCII 代碼 (kiss code 或時鐘來源)，其餘為上游伺服器位址
+pub fn format_ref_id(stratum: u8, id: [u8; 4]) -> String {
+    if stratum <= 1 {
+        let code: String = id
+            .iter()
+            .take_while(|&&b| b != 0)
+            .filter(|b| b.is_ascii_graphic())
+            .map(|&b| b as char)
+            .collect();
+        if code.is_empty() {
+            "(空)".to_string()
+        } else {
+            code
+        }
+    } else {
+        Ipv4Addr::from(id).to_string()
+    }
+}
+
+// 建立 mode 6 READVAR 控制查詢 (VN=2, opcode 2)
+pub fn build_readvar_request(sequence: u16) -> [u8; 12] {
+    let mut packet = [0u8; 12];
+    packet[0] = (2 << 3) | 6;
+    packet[1] = 0x02;
+    packet[2..4].copy_from_slice(&sequence.to_be_bytes());
+    packet
+}
+
+// 判斷是否為對應序號的 mode 6 回應，回傳 (是否為錯誤回應, 資料內容)
+pub fn parse_control_response(buf: &[u8], sequence: u16) -> Option<(bool, &[u8])> {
+    if buf.len() < 12 || buf[0] & 0x07 != 6 {
+        return None;
+    }
+    // R 位元表示回應，opcode 需與請求一致
+    if buf[1] & 0x80 == 0 || buf[1] & 0x1f != 0x02 {
+        return None;
+    }
+    if u16::from_be_bytes([buf[2], buf[3]]) != sequence {
+        return None;
+    }
+
+    let error = buf[1] & 0x40 != 0;
+    let count = u16::from_be_bytes([buf[10], buf[11]]) as usize;
+    let data = &buf[12..buf.len().min(12 + count)];
+    Some((error, data))
+}
+
+// 從 READVAR 資料中取出 version="..." 欄位
+pub fn extract_version(data: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(data);
+    let start = text.find("version=\"")? + "version=\"".len();
+    let end = text[start..].find('"')? + start;
+    Some(text[start..end].to_string())
+}
+
+// 建立 mode 7 MON_GETLIST_1 (monlist) 請求
+pub fn build_monlist_request() -> [u8; 48] {
+    let mut packet = [0u8; 48];
+    packet[0] = (2 << 3) | 7;
+    packet[2] = 0x03; // IMPL_XNTPD
+    packet[3] = 0x2a; // REQ_MON_GETLIST_1
+    packet
+}
+
+// 判斷是否為 monlist 回應，回傳是否帶有錯誤碼
+pub fn parse_monlist_response(buf: &[u8]) -> Option<bool> {
+    if buf.len() < 8 || buf[0] & 0x07 != 7 || buf[0] & 0x80 == 0 || buf[3] != 0x2a {
+        return None;
+    }
+    Some(buf[4] >> 4 != 0)
+}
+
+// 計算多個回應封包的統計
+fn summarize_replies<'a>(request_len: usize, replies: impl Iterator<Item = (bool, &'a [u8], usize)>) -> Option<ControlReply> {
+    let mut reply = ControlReply::default();
+
+    for (error, data, len) in replies {
+        reply.packets += 1;
+        reply.bytes += len;
+        reply.error |= error;
+        if reply.server_version.is_none() {
+            reply.server_version = extract_version(data);
+        }
+    }
+
+    if reply.packets == 0 {
+        return None;
+    }
+    reply.ratio = reply.bytes as f64 / request_len as f64;
+    Some(reply)
+}
+
+fn describe_reply(reply: &Option<ControlReply>) -> String {
+    match reply {
+        Some(r) if r.error => format!("錯誤回應 {} 封包 / {} bytes", r.packets, r.bytes),
+        Some(r) => format!("{} 封包 / {} bytes (放大 {:.1}x)", r.packets, r.bytes, r.ratio),
+        None => "無回應".to_string(),
+    }
+}
+
+fn is_amplifier(reply: &Option<ControlReply>) -> bool {
+    matches!(reply, Some(r) if !r.error && r.ratio > AMPLIFICATION_THRESHOLD)
+}
+
+// NTP 服務與放大風險檢查
+pub struct NtpCheck;
+
+impl NtpCheck {
+    async fn probe(&self, target: &CheckTarget) -> CheckOutcome {
+        let name = self.name();
+
+        // 一般客戶端請求，確認服務是否存在
+        let request = build_client_request();
+        let info = match target.prober.udp_exchange(target.addr, target.port, &request, target.timeout, Duration::ZERO).await {
+            Ok(packets) => packets.iter().find_map(|(_, p)| parse_client_response(p)),
+            Err(e) => return CheckOutcome::new(name, target.port, CheckStatus::Error, format!("無法發送 UDP 請求: {}", e)),
+        };
+
+        // mode 6 READVAR 控制查詢
+        let sequence = 1;
+        let readvar_request = build_readvar_request(sequence);
+        let readvar = match target.prober.udp_exchange(target.addr, target.port, &readvar_request, target.timeout, LINGER).await {
+            Ok(packets) => summarize_replies(
+                readvar_request.len(),
+                packets.iter().filter_map(|(_, p)| parse_control_response(p, sequence).map(|(e, d)| (e, d, p.len()))),
+            ),
+            Err(_) => None,
+        };
+
+        // mode 7 monlist 查詢
+        let monlist_request = build_monlist_request();
+        let monlist = match target.prober.udp_exchange(target.addr, target.port, &monlist_request, target.timeout, LINGER).await {
+            Ok(packets) => summarize_replies(
+                monlist_request.len(),
+                packets.iter().filter_map(|(_, p)| parse_monlist_response(p).map(|e| (e, &p[..0], p.len()))),
+            ),
+            Err(_) => None,
+        };
+
+        if info.is_none() && readvar.is_none() && monlist.is_none() {
+            return CheckOutcome::new(name, target.port, CheckStatus::NoResponse, "無 NTP 回應");
+        }
+
+        let amplifiers: Vec<f64> = [&readvar, &monlist]
+            .into_iter()
+            .filter(|r| is_amplifier(r))
+            .filter_map(|r| r.as_ref().map(|r| r.ratio))
+            .collect();
+
+        let mut outcome = if let Some(max) = amplifiers.iter().cloned().reduce(f64::max) {
+            CheckOutcome::new(
+                name,
+                target.port,
+                CheckStatus::Warning,
+                format!("控制查詢有回應，可能被用於放大攻擊 (最高 {:.1}x)", max),
+            )
+        } else {
+            CheckOutcome::new(name, target.port, CheckStatus::Ok, "NTP 服務有回應，控制查詢未開放")
+        };
+
+        if let Some(info) = &info {
+            outcome = outcome
+                .detail("版本", format!("NTPv{}", info.version))
+                .detail("Stratum", info.stratum.to_string())
+                .detail("參考 ID", info.ref_id.clone());
+        }
+        if let Some(version) = readvar.as_ref().and_then(|r| r.server_version.clone()) {
+            outcome = outcome.detail("伺服器版本", version);
+        }
+
+        outcome
+            .detail("READVAR (mode 6)", describe_reply(&readvar))
+            .detail("monlist (mode 7)", describe_reply(&monlist))
+    }
+}
+
+impl ServiceCheck for NtpCheck {
+    fn name(&self) -> &'static str {
+        "NTP"
+    }
+
+    fn ports(&self) -> &[u16] {
+        &[123]
+    }
+
+    // 送出 NTP 查詢 (含 mode 6/7)
+    fn level(&self) -> Intrusiveness {
+        Intrusiveness::Active
+    }
+
+    fn run<'a>(&'a self, target: &'a CheckTarget) -> CheckFuture<'a> {
+        Box::pin(self.probe(target))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::context::ScanContext;
+    use crate::prober::fake::{Script, Scripted, ScriptedProber};
+    use crate::testutil::host;
+
+    fn target(prober: ScriptedProber) -> CheckTarget {
+        CheckTarget {
+            addr: host(1),
+            port: 123,
+            timeout: Duration::from_secs(1),
+            allowed: Intrusiveness::Active,
+            prober: Arc::new(prober),
+            context: Arc::new(ScanContext::offline()),
+        }
+    }
+
+    // 擷取的 stratum 1 伺服器回應 (參考來源 GPS)
+    const SERVER_REPLY: [u8; 48] = [
+        0x24, 0x01, 0x00, 0xe9, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0a, b'G', b'P', b'S', 0x00,
+        0xea, 0x8f, 0x1c, 0x10, 0x3b, 0x64, 0x5a, 0x1d, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0xea, 0x8f, 0x1c, 0x12, 0x0c, 0x49, 0xba, 0x5e, 0xea, 0x8f, 0x1c, 0x12, 0x0c, 0x4a, 0x1d, 0x33,
+    ];
+
+    // mode 6 READVAR 回應：序號 1，帶 version 欄位
+    fn readvar_reply(sequence: u16, flags: u8) -> Vec<u8> {
+        let data = b"version=\"ntpd 4.2.8p15@1.3728-o\", processor=\"x86_64\", stratum=2";
+        let mut packet = vec![0x16, flags, 0, 0, 0x06, 0x18, 0x00, 0x00, 0x00, 0x00, 0, 0];
+        packet[2..4].copy_from_slice(&sequence.to_be_bytes());
+        packet[10..12].copy_from_slice(&(data.len() as u16).to_be_bytes());
+        packet.extend_from_slice(data);
+        packet
+    }
+
+    // 擷取的 monlist 回應封包：標頭加上 6 筆 72 bytes 的紀錄
+    fn monlist_reply(error: u8) -> Vec<u8> {
+        let mut packet = vec![0x97, 0x00, 0x03, 0x2a, (error << 4), 0x06, 0x00, 0x48];
+        packet.resize(8 + 6 * 72, 0);
+        packet
+    }
+
+    #[test]
+    fn client_request_and_captured_reply() {
+        let request = build_client_request();
+        assert_eq!(request[0], 0x23);
+        assert!(request[1..].iter().all(|&b| b == 0));
+        assert_eq!(
+            parse_client_response(&SERVER_REPLY),
+            Some(NtpInfo {
+                version: 4,
+                stratum: 1,
+                ref_id: "GPS".to_string(),
+            })
+        );
+        // 客戶端請求本身 (mode 3) 與截斷的封包都不是回應
+        assert_eq!(parse_client_response(&request), None);
+        assert_eq!(parse_client_response(&SERVER_REPLY[..47]), None);
+    }
+
+    #[test]
+    fn reference_ids() {
+        assert_eq!(format_ref_id(2, [192, 0, 2, 9]), "192.0.2.9");
+        assert_eq!(format_ref_id(0, *b"RATE"), "RATE");
+        assert_eq!(format_ref_id(1, [0, 0, 0, 0]), "(空)");
+        assert_eq!(format_ref_id(1, [b'P', b'P', b'S', 0x07]), "PPS");
+    }
+
+    #[test]
+    fn readvar_replies_match_the_sequence() {
+        let request = build_readvar_request(0x1234);
+        assert_eq!(request, [0x16, 0x02, 0x12, 0x34, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        let reply = readvar_reply(7, 0x82);
+        let (error, data) = parse_control_response(&reply, 7).unwrap();
+        assert!(!error);
+        assert_eq!(extract_version(data).as_deref(), Some("ntpd 4.2.8p15@1.3728-o"));
+        assert_eq!(parse_control_response(&reply, 8), None);
+        // 沒有 R 位元 (請求本身) 或 opcode 不同
+        assert_eq!(parse_control_response(&readvar_reply(7, 0x02), 7), None);
+        assert_eq!(parse_control_response(&readvar_reply(7, 0x81), 7), None);
+        assert!(parse_control_response(&readvar_reply(7, 0xc2), 7).unwrap().0);
+        assert_eq!(extract_version(b"processor=\"x86_64\""), None);
+    }
+
+    #[test]
+    fn monlist_replies_and_amplification() {
+        let request = build_monlist_request();
+        assert_eq!(&request[..4], &[0x17, 0x00, 0x03, 0x2a]);
+        assert_eq!(parse_monlist_response(&monlist_reply(0)), Some(false));
+        assert_eq!(parse_monlist_response(&monlist_reply(4)), Some(true));
+        assert_eq!(parse_monlist_response(&request), None);
+
+        let packets = [monlist_reply(0), monlist_reply(0)];
+        let reply = summarize_replies(request.len(), packets.iter().map(|p| (false, &p[..0], p.len())));
+        let stats = reply.clone().unwrap();
+        assert_eq!((stats.packets, stats.bytes), (2, 880));
+        assert!((stats.ratio - 880.0 / 48.0).abs() < 1e-9);
+        assert!(is_amplifier(&reply));
+        assert_eq!(describe_reply(&reply), "2 封包 / 880 bytes (放大 18.3x)");
+
+        // 錯誤回應不算開放
+        let errors = [monlist_reply(4)];
+        let reply = summarize_replies(request.len(), errors.iter().map(|p| (true, &p[..0], p.len())));
+        assert!(!is_amplifier(&reply));
+        assert!(summarize_replies(48, std::iter::empty()).is_none());
+        assert!(!is_amplifier(&None));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn open_control_queries_are_flagged() {
+        let script = Script::new(Scripted::Open, Duration::from_millis(20))
+            .udp(&SERVER_REPLY)
+            .udp(&readvar_reply(1, 0x82))
+            .udp(&monlist_reply(0));
+        let outcome = NtpCheck.run(&target(ScriptedProber::new().with(host(1), 123, script))).await;
+        assert_eq!(outcome.status, CheckStatus::Warning);
+        assert!(outcome.summary.contains("放大"));
+        let detail = |key: &str| outcome.details.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+        assert_eq!(detail("Stratum"), Some("1"));
+        assert_eq!(detail("參考 ID"), Some("GPS"));
+        assert_eq!(detail("伺服器版本"), Some("ntpd 4.2.8p15@1.3728-o"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn server_reply_without_control_queries() {
+        let mut reply = [0u8; 48];
+        reply[0] = (4 << 3) | 4;
+        reply[1] = 2;
+        reply[12..16].copy_from_slice(&[192, 0, 2, 9]);
+        let script = Script::new(Scripted::Open, Duration::from_millis(20)).udp(&reply);
+        let outcome = NtpCheck.run(&target(ScriptedProber::new().with(host(1), 123, script))).await;
+        assert_eq!(outcome.status, CheckStatus::Ok);
+        assert!(outcome.details.contains(&("參考 ID".to_string(), "192.0.2.9".to_string())));
+        assert!(outcome.details.contains(&("monlist (mode 7)".to_string(), "無回應".to_string())));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn silence_is_no_response() {
+        let outcome = NtpCheck.run(&target(ScriptedProber::new())).await;
+        assert_eq!(outcome.status, CheckStatus::NoResponse);
+    }
+}
@@ -0,0 +1,212 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::net::UdpSocket;
+use tokio::time::{timeout, Instant};
+use super::{CheckFuture, CheckOutcome, CheckStatus, CheckTarget, Intrusiveness, ServiceCheck};
+
+const OPCODE_RRQ: u16 = 1;
+const OPCODE_DATA: u16 = 3;
+const OPCODE_ACK: u16 = 4;
+const OPCODE_ERROR: u16 = 5;
+const OPCODE_OACK: u16 = 6;
+
+// 標準區塊大小，小於此值代表傳輸結束
+const BLOCK_SIZE: usize = 512;
+
+// 只有 --intrusiveness intrusive 時才會嘗試讀取的檔名
+const ALLOWED_READ_FILES: &[&str] = &["startup-config"];
+
+// TFTP 回應封包
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TftpPacket {
+    Data { block: u16, len: usize },
+    Error { code: u16, message: String },
+    OptionAck,
+}
+
+// 建立讀取請求 (RRQ, octet 模式)
+pub fn build_rrq(filename: &str) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(filename.len() + 10);
+    packet.extend_from_slice(&OPCODE_RRQ.to_be_bytes());
+    packet.extend_from_slice(filename.as_bytes());
+    packet.push(0);
+    packet.extend_from_slice(b"octet");
+    packet.push(0);
+    packet
+}
+
+// 建立 ACK 封包
+pub fn build_ack(block: u16) -> [u8; 4] {
+    let mut packet = [0u8; 4];
+    packet[..2].copy_from_slice(&OPCODE_ACK.to_be_bytes());
+    packet[2..].copy_from_slice(&block.to_be_bytes());
+    packet
+}
+
+// 建立 ERROR 封包，用來中止未完成的傳輸
+pub fn build_error(code: u16, message: &str) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(message.len() + 5);
+    packet.extend_from_slice(&OPCODE_ERROR.to_be_bytes());
+    packet.extend_from_slice(&code.to_be_bytes());
+    packet.extend_from_slice(message.as_bytes());
+    packet.push(0);
+    packet
+}
+
+// 解析伺服器回應
+pub fn parse_packet(buf: &[u8]) -> Option<TftpPacket> {
+    if buf.len() < 4 {
+        return None;
+    }
+
+    match u16::from_be_bytes([buf[0], buf[1]]) {
+        OPCODE_DATA => Some(TftpPacket::Data {
+            block: u16::from_be_bytes([buf[2], buf[3]]),
+            len: buf.len() - 4,
+        }),
+        OPCODE_ERROR => {
+            let text = &buf[4..];
+            let end = text.iter().position(|&b| b == 0).unwrap_or(text.len());
+            Some(TftpPacket::Error {
+                code: u16::from_be_bytes([buf[2], buf[3]]),
+                message: String::from_utf8_lossy(&text[..end]).trim().to_string(),
+            })
+        }
+        OPCODE_OACK => Some(TftpPacket::OptionAck),
+        _ => None,
+    }
+}
+
+// 標準錯誤碼說明
+fn error_code_name(code: u16) -> &'static str {
+    match code {
+        1 => "File not found",
+        2 => "Access violation",
+        3 => "Disk full",
+        4 => "Illegal operation",
+        5 => "Unknown transfer ID",
+        6 => "File already exists",
+        7 => "No such user",
+        8 => "Option negotiation failed",
+        _ => "Not defined",
+    }
+}
+
+// 產生一個幾乎不可能存在的檔名
+fn probe_filename() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+    format!("portscanner-probe-{}-{}.bin", std::process::id(), nanos)
+}
+
+// 送出 RRQ 並等待第一個回應封包
+// 伺服器會從新的 TID (端口) 回覆，所以不能用 connect 過的 socket
+async fn request(target: &CheckTarget, filename: &str) -> std::io::Result<Option<TftpPacket>> {
+    let local: SocketAddr = match target.addr {
+        IpAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        IpAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.send_to(&build_rrq(filename), target.context.socket_addr(target.addr, target.port)).await?;
+
+    let deadline = Instant::now() + target.timeout;
+    let mut buf = [0u8; BLOCK_SIZE + 4];
+
+    while let Ok(received) = timeout(deadline.saturating_duration_since(Instant::now()), socket.recv_from(&mut buf)).await {
+        let (len, from) = received?;
+        if from.ip() != target.addr {
+            continue;
+        }
+        let Some(packet) = parse_packet(&buf[..len]) else {
+            continue;
+        };
+
+        match &packet {
+            // 只讀第一個區塊：最後一塊就正常 ACK，否則以 ERROR 中止傳輸
+            TftpPacket::Data { block, len } if *len < BLOCK_SIZE => {
+                let _ = socket.send_to(&build_ack(*block), from).await;
+            }
+            TftpPacket::Data { .. } | TftpPacket::OptionAck => {
+                let _ = socket.send_to(&build_error(0, "transfer aborted"), from).await;
+            }
+            TftpPacket::Error { .. } => {}
+        }
+        return Ok(Some(packet));
+    }
+
+    Ok(None)
+}
+
+// TFTP 服務存活檢查
+pub struct TftpCheck;
+
+impl TftpCheck {
+    async fn probe(&self, target: &CheckTarget) -> CheckOutcome {
+        let name = self.name();
+        let filename = probe_filename();
+
+        let mut outcome = match request(target, &filename).await {
+            Err(e) => return CheckOutcome::new(name, target.port, CheckStatus::Error, format!("無法發送 UDP 請求: {}", e)),
+            Ok(None) => return CheckOutcome::new(name, target.port, CheckStatus::NoResponse, "無 TFTP 回應"),
+            Ok(Some(TftpPacket::Error { code, message })) => {
+                CheckOutcome::new(name, target.port, CheckStatus::Ok, "TFTP 服務有回應")
+                    .detail("錯誤回應", format!("{} ({}) {}", code, error_code_name(code), message))
+            }
+            Ok(Some(TftpPacket::Data { block, len })) => {
+                CheckOutcome::new(name, target.port, CheckStatus::Warning, "TFTP 服務對不存在的檔名回傳資料")
+                    .detail("DATA 回應", format!("區塊 {} / {} bytes", block, len))
+            }
+            Ok(Some(TftpPacket::OptionAck)) => {
+                CheckOutcome::new(name, target.port, CheckStatus::Ok, "TFTP 服務有回應 (OACK)")
+            }
+        };
+        outcome = outcome.detail("探測檔名", filename);
+
+        if !target.allows(Intrusiveness::Intrusive) {
+            return outcome;
+        }
+
+        // 侵入性檢查：嘗試匿名讀取白名單檔案的第一個區塊
+        for file in ALLOWED_READ_FILES {
+            match request(target, file).await {
+                Ok(Some(TftpPacket::Data { len, .. })) => {
+                    outcome.status = CheckStatus::Warning;
+                    outcome.summary = format!("可匿名讀取 {}", file);
+                    let more = if len < BLOCK_SIZE { "完整" } else { "尚有後續區塊" };
+                    outcome = outcome.detail(&format!("讀取 {}", file), format!("首個區塊 {} bytes ({})", len, more));
+                }
+                Ok(Some(TftpPacket::Error { code, .. })) => {
+                    outcome = outcome.detail(&format!("讀取 {}", file), format!("拒絕 ({})", error_code_name(code)));
+                }
+                Ok(_) => outcome = outcome.detail(&format!("讀取 {}", file), "無回應"),
+                Err(e) => outcome = outcome.detail(&format!("讀取 {}", file), format!("失敗: {}", e)),
+            }
+        }
+
+        outcome
+    }
+}
+
+impl ServiceCheck for TftpCheck {
+    fn name(&self) -> &'static str {
+        "TFTP"
+    }
+
+    fn ports(&self) -> &[u16] {
+        &[69]
+    }
+
+    fn level(&self) -> Intrusiveness {
+        Intrusiveness::Active
+    }
+
+    fn intrusive_actions(&self) -> &'static [&'static str] {
+        &["嘗試匿名讀取 startup-config 的第一個區塊"]
+    }
+
+    fn run<'a>(&'a self, target: &'a CheckTarget) -> CheckFuture<'a> {
+        Box::pin(self.probe(target))
+    }
+}
@@ -0,0 +1,1106 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use crate::checks::Intrusiveness;
+use crate::cloud::Provider;
+use crate::errors::Lang;
+use crate::grade::Grade;
+use crate::init::Intensity;
+use crate::output::OutputFormat;
+use crate::pipeline::Stage;
+use crate::timefmt::TimeFormat;
+use crate::units::{DurationParser, SizeParser};
+use crate::view::{GroupBy, SortBy};
+
+// 命令列參數
+#[derive(Debug, Parser)]
+#[command(name = "portscanner", version, about = "檢測端口狀態和服務可用性")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// 遠端目標，以逗號分隔的主機名稱、IP 或 CIDR 網段 (未指定時以公共 DNS 伺服器測試本機出站)；
+    /// 也可以是萬用字元目標，例如 '*.internal.example.com'，以 --subdomain-list 或 --axfr 展開
+    #[arg(long)]
+    pub target: Option<String>,
+
+    /// 展開萬用字元目標的字詞清單，每行一個子網域標籤
+    #[arg(long, value_name = "FILE", requires = "target")]
+    pub subdomain_list: Option<PathBuf>,
+
+    /// 先嘗試向區域的 NS 要求區域轉送 (AXFR) 取得主機名稱；被拒絕時改用 --subdomain-list
+    #[arg(long, requires = "target")]
+    pub axfr: bool,
+
+    /// 要求區域轉送的伺服器 (預設為區域的 NS 記錄)
+    #[arg(long, value_name = "HOST", requires = "axfr")]
+    pub axfr_server: Option<String>,
+
+    /// 查詢 SRV 記錄 (以逗號分隔，例如 _ldap._tcp.example.com)，將記錄的目標與端口加入掃描；
+    /// 只指定 --srv 時只掃描記錄的端口
+    #[arg(long, value_name = "NAMES", value_delimiter = ',', value_parser = crate::srv::parse_name)]
+    pub srv: Vec<crate::srv::SrvName>,
+
+    /// 展開萬用字元目標與查詢 SRV 記錄時同時進行的 DNS 查詢數
+    #[arg(long, default_value_t = 32, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..=1024))]
+    pub expand_concurrency: usize,
+
+    /// 以二分取樣找出防火牆區段邊界，例如 1-65535 (輸出連續區段而非個別端口)
+    #[arg(long, value_name = "RANGE", value_parser = crate::bisect::parse_range,
+          conflicts_with_all = ["ports", "template", "policy", "watch", "output", "format_template", "dry_run"])]
+    pub bisect: Option<std::ops::RangeInclusive<u16>>,
+
+    /// --bisect 的初始取樣數；越多越能發現區段內零星的端口
+    #[arg(long, default_value_t = crate::bisect::DEFAULT_SAMPLES, requires = "bisect")]
+    pub bisect_samples: usize,
+
+    /// 分別從兩個來源 (IP 位址或網路介面，例如 192.0.2.10,wg0) 連線，並列比較每個端口的延遲與可連線狀態
+    #[arg(long, value_name = "A,B", value_parser = crate::compare::parse_sources,
+          conflicts_with_all = ["bisect", "watch", "output", "dry_run", "tor", "syn", "bundle", "format_template"])]
+    pub compare_source: Option<crate::compare::SourcePair>,
+
+    /// --compare-source 每條路徑每個端口的連線次數 (延遲取中位數)
+    #[arg(long, default_value_t = crate::compare::DEFAULT_SAMPLES, requires = "compare_source",
+          value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub compare_samples: usize,
+
+    /// 延遲差距超過此值時標示，例如 20ms
+    #[arg(long, value_parser = DurationParser::default(), default_value = "20ms", requires = "compare_source")]
+    pub compare_threshold: Duration,
+
+    /// 將路徑比較另存為 .csv 或 .json (保留兩條路徑的量測)
+    #[arg(long, value_name = "FILE", requires = "compare_source")]
+    pub compare_output: Option<PathBuf>,
+
+    /// 在指定時間內反覆探測選定的端口，結束後顯示每個端口的可用率、最長中斷與時間軸，例如 5m
+    #[arg(long, value_name = "DURATION", value_parser = DurationParser::default(),
+          conflicts_with_all = ["bisect", "watch", "output", "dry_run", "compare_source", "bundle", "format_template", "syn"])]
+    pub monitor: Option<Duration>,
+
+    /// --monitor 每輪探測的間隔
+    #[arg(long, value_parser = DurationParser::default(), default_value = "10s", requires = "monitor")]
+    pub interval: Duration,
+
+    /// 將 --monitor 的每個樣本另存為 CSV
+    #[arg(long, value_name = "FILE", requires = "monitor")]
+    pub monitor_output: Option<PathBuf>,
+
+    /// 只掃描帶有此標籤的端口，例如 owner:platform-team 或 env (見設定檔 [ports]/[tags]；可重複指定，須全部符合)
+    #[arg(long)]
+    pub tag: Vec<String>,
+
+    /// 掃描服務群組的所有成員端口，結果合併成一行 (例如 ftp-passive、rpc；見設定檔 [[groups]]，可重複指定)
+    #[arg(long, value_name = "NAME")]
+    pub group: Vec<String>,
+
+    /// 從目標中排除的 IP 或 CIDR 網段，以逗號分隔，例如 10.0.0.1,10.0.5.0/28
+    #[arg(long, requires = "target")]
+    pub exclude: Option<String>,
+
+    /// 從檔案讀取排除清單 (每行一個 IP 或網段，# 之後為註解)
+    #[arg(long, value_name = "FILE", requires = "target")]
+    pub exclude_file: Option<PathBuf>,
+
+    /// 允許掃描超過主機數上限的目標 (上限見設定檔 [safety] max_hosts，預設 256)
+    #[arg(long)]
+    pub allow_large: bool,
+
+    /// 允許掃描非私有位址 (請先確認已獲授權)
+    #[arg(long)]
+    pub allow_public: bool,
+
+    /// 仍掃描不合理的目標位址 (0.0.0.0、群播、廣播、文件範例與保留位址)；原因仍記錄為目標提醒
+    #[arg(long, requires = "target")]
+    pub force: bool,
+
+    /// 非私有目標的授權人或工單 (例如 "alice/SEC-1234")；記錄在稽核紀錄與報告中，並略過掃描前的確認
+    #[arg(long, value_name = "WHO")]
+    pub authorized_by: Option<String>,
+
+    /// 要掃描的端口，例如 22,80,8000-8100 (預設為內建常用端口表)
+    #[arg(long)]
+    pub ports: Option<String>,
+
+    /// 依合規範本掃描並檢查端口狀態，例如 pci-external (見 templates list)
+    #[arg(long, conflicts_with_all = ["policy", "output", "watch"])]
+    pub template: Option<String>,
+
+    /// 掃描端本身的錯誤 (檔案描述符用盡、DNS 解析器無法使用、探測異常中止) 視為執行失敗，以結束代碼 4 結束
+    /// 端口關閉或被過濾仍是正常的結果；錯誤另外列出，JSON 輸出附上 scanner_errors
+    #[arg(long, conflicts_with_all = ["output", "watch"])]
+    pub strict: bool,
+
+    /// 任何掃描的端口等級低於此值時以錯誤結束 (供 CI 使用；門檻見設定檔 [grading])
+    #[arg(long, value_enum, ignore_case = true, conflicts_with_all = ["output", "watch"])]
+    pub min_grade: Option<Grade>,
+
+    /// 以 Handlebars 範本輸出報告 (資料與 --json 相同，見 examples/format/)
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["json", "output", "watch", "dry_run"])]
+    pub format_template: Option<PathBuf>,
+
+    /// 以 ed25519 私鑰 (portscanner keygen 產生) 簽署 --json 報告，簽章嵌入報告的 signature 欄位
+    #[arg(long, value_name = "KEYFILE", requires = "json", conflicts_with_all = ["dry_run", "bisect"])]
+    pub sign: Option<PathBuf>,
+
+    /// 不重新探測可疑的結果 (期限邊緣的逾時、掃描端錯誤、同類別中唯一失敗的端口；見設定檔 [verify])
+    #[arg(long)]
+    pub no_verify: bool,
+
+    /// 每個可連線的端口共連線 N 次 (含掃描時的一次)，計算延遲的 p50 / p90 / p95 / p99；政策的 [[slo]] 依此評估
+    #[arg(long, value_name = "N", conflicts_with_all = ["output", "watch", "monitor", "bisect", "no_outbound"],
+          value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(2..=1000))]
+    pub samples: Option<usize>,
+
+    /// 信心分數 (0-1) 低於此值的結果在產生報告前重新探測，最多三輪；仍低於門檻的結果以 ? 標示
+    #[arg(long, value_name = "SCORE", value_parser = parse_confidence, conflicts_with_all = ["output", "watch", "bisect"])]
+    pub min_confidence: Option<f64>,
+
+    /// 不檢查開放比例異常高的目標是否為 honeypot/tarpit (會額外連線少見的高端口)
+    #[arg(long)]
+    pub no_tarpit_check: bool,
+
+    /// 對可連線的端口探測 TCP Fast Open、ECN 與 TCP 選項 (會在 SYN 中送出一個換行；僅 Linux)
+    #[arg(long, conflicts_with = "output")]
+    pub tcp_caps: bool,
+
+    /// 探測最深執行到哪個階段：connect、banner、fingerprint 或 checks；較深的階段只對前一階段成功的端口執行
+    #[arg(long, value_enum, default_value = "checks")]
+    pub stages: Stage,
+
+    /// 對可連線的 TLS 端口探測 HTTP 版本：以 ALPN 確認 h2 / http/1.1，並對同一 UDP 端口送出 QUIC Initial 確認 h3
+    #[arg(long, conflicts_with = "output")]
+    pub http_versions: bool,
+
+    /// 把 SSH 主機金鑰、TLS 憑證 SHA-256 與 HTTP Server 標頭依主機識別與端口記錄在 SQLite 資料庫 (可與 --output 的結果資料庫相同)，與記錄不同時警告
+    #[arg(long, value_name = "DB", conflicts_with_all = ["output", "tor", "jump", "proxy"])]
+    pub fingerprint_db: Option<PathBuf>,
+
+    /// 在每個端口結果後顯示最近 N 次記錄的狀態趨勢 (例如 ▇▇__▇▇▇，▇ 為開放)，讀取 --trend-db
+    #[arg(long, value_name = "N", requires = "trend_db", conflicts_with_all = ["output", "watch", "bisect", "monitor", "compare_source"],
+          value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..=crate::trend::MAX_RUNS as u64))]
+    pub trend: Option<usize>,
+
+    /// --trend 讀取的結果資料庫 (--output 寫入的 .db)
+    #[arg(long, value_name = "DB", requires = "trend")]
+    pub trend_db: Option<PathBuf>,
+
+    /// 以這次掃描的指紋取代記錄中已改變的指紋 (確認是預期的變更後使用)
+    #[arg(long, requires = "fingerprint_db")]
+    pub accept_fingerprints: bool,
+
+    /// 對列出的端口連線後做短暫的頻寬測試 (逗號分隔，例如 443)：Web 端口下載 --throughput-path 的物件，回報 MB/s
+    #[arg(long, value_name = "PORTS", value_delimiter = ',', conflicts_with_all = ["output", "tor"])]
+    pub throughput_test: Vec<u16>,
+
+    /// 頻寬測試下載的物件路徑，應為大小已知且夠大的物件
+    #[arg(long, value_name = "PATH", default_value = "/", requires = "throughput_test")]
+    pub throughput_path: String,
+
+    /// --throughput-test 的端口為回送 (echo) 端點：持續送出資料並量測收回的速率
+    #[arg(long, requires = "throughput_test")]
+    pub throughput_echo: bool,
+
+    /// 每個端口的頻寬測試最多傳輸的大小，例如 512K、32M
+    #[arg(long, value_name = "SIZE", default_value = "32M", value_parser = SizeParser, requires = "throughput_test")]
+    pub throughput_max_bytes: u64,
+
+    /// 每個端口的頻寬測試時間 (最多 10s)
+    #[arg(long, value_name = "DURATION", default_value = "2s", value_parser = DurationParser::within(Duration::from_secs(10)), requires = "throughput_test")]
+    pub throughput_duration: Duration,
+
+    /// 測試依來源端口過濾的防火牆規則：從列出的每個本機來源端口 (逗號分隔，例如 1024,53,20) 再連線每個端口一次，
+    /// 顯示 (來源端口, 目標端口) 的連線矩陣；同一來源端口的探測依序執行，會增加掃描時間
+    #[arg(long, value_name = "PORTS", value_delimiter = ',', value_parser = crate::portspec::parse_port,
+          conflicts_with_all = ["no_outbound", "tor", "proxy", "jump"])]
+    pub test_source_ports: Vec<u16>,
+
+    /// 記錄每個出站連線實際使用的本機位址，並查詢核心選擇的路由介面 (介面查詢僅 Linux)
+    #[arg(long, conflicts_with_all = ["tor", "syn"])]
+    pub route_check: bool,
+
+    /// 出站連線應經由的網路介面，例如 wg0；經由其他介面的端口會標示出來 (隱含 --route-check)
+    #[arg(long, value_name = "INTERFACE", conflicts_with_all = ["tor", "syn"])]
+    pub expect_route: Option<String>,
+
+    /// 略過掃描前的網路連線檢查 (錨點見設定檔 [sanity])
+    #[arg(long)]
+    pub no_sanity_check: bool,
+
+    /// 略過掃描前的透明代理/強制門戶檢查 (端點見設定檔 [captive])
+    #[arg(long)]
+    pub no_captive_check: bool,
+
+    /// 不監測本機端口與 TIME_WAIT (Linux)，端口即將耗盡時也不自動暫停排程
+    #[arg(long)]
+    pub no_port_pressure: bool,
+
+    /// 把終端輸出同時寫到檔案 (去除色碼，每行加上時間戳)，逐行寫入供稽核使用；啟用時不使用分頁程式
+    #[arg(long, value_name = "FILE")]
+    pub transcript: Option<PathBuf>,
+
+    /// 把完整的掃描 (JSON 報告、NDJSON 逐筆紀錄、HTML 比較表、--transcript 的逐字紀錄與清單) 封存成 zstd 壓縮的 tar，以 portscanner open 檢視
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["output", "watch", "bisect", "dry_run"])]
+    pub bundle: Option<PathBuf>,
+
+    /// 報告超過一個畫面時不使用分頁程式 (也可在設定檔 [pager] 關閉)
+    #[arg(long)]
+    pub no_pager: bool,
+
+    /// 不在進度列下方逐步顯示結果 (終端上預設先顯示暫定的連線結果，其他階段完成後更新)
+    #[arg(long)]
+    pub no_live: bool,
+
+    /// 不顯示進度列
+    #[arg(long)]
+    pub no_progress: bool,
+
+    /// 對可連線的端口讀取對方送出的內容，確認含有 listen 子命令回覆的 token (而不是中間設備代為回應)
+    #[arg(long, value_name = "TOKEN", value_parser = crate::listen::parse_token,
+          conflicts_with_all = ["output", "watch", "monitor", "bisect", "compare_source", "syn", "no_outbound"])]
+    pub expect_token: Option<String>,
+
+    /// 結束時不在標準錯誤寫出一行狀態 (portscanner: status=... exit=...)
+    #[arg(long)]
+    pub no_status_line: bool,
+
+    /// 精簡輸出：每個無法連線的端口只輸出一行，其他資訊都省略；有端口無法連線時結束代碼為 1
+    #[arg(long, conflicts_with_all = ["json", "output", "watch", "bisect", "monitor", "compare_source", "format_template", "dry_run"])]
+    pub compact: bool,
+
+    /// 將結尾的一行摘要複製到系統剪貼簿 (無法存取剪貼簿時只顯示提示)
+    #[arg(long, conflicts_with = "watch")]
+    pub copy: bool,
+
+    /// 依服務清單 (YAML，主機名稱/IP/萬用字元 -> 宣告的端口) 比對結果，有未宣告的開放端口時結束代碼為 4
+    /// 清單的 identities 區段為主機指定穩定識別；只有 identities 時可搭配 --output
+    #[arg(long, value_name = "FILE", conflicts_with = "watch")]
+    pub manifest: Option<PathBuf>,
+
+    /// 為主機指定穩定識別，格式 識別=IP或主機名稱 (可重複)；SQLite 結果依識別記錄，IP 變動後仍可用 history 查詢
+    #[arg(long, value_name = "ID=TARGET", value_parser = crate::identity::parse_host_id)]
+    pub host_id: Vec<(String, String)>,
+
+    /// 沒有其他識別的主機以反查 DNS (PTR) 的名稱作為識別
+    #[arg(long)]
+    pub identity_rdns: bool,
+
+    /// 依政策檔 (與範本相同格式) 檢查端口狀態
+    #[arg(long, conflicts_with_all = ["output", "watch"])]
+    pub policy: Option<PathBuf>,
+
+    /// 依出站政策 (TOML，允許與禁止的目的地和端口) 只探測政策中的組合與抽樣的不允許組合
+    /// 不允許的路徑可以連線時結束代碼為 10，允許的路徑無法連線時為 11
+    #[arg(long, value_name = "FILE", conflicts_with_all = [
+        "target", "ports", "group", "srv", "template", "policy", "manifest",
+        "watch", "monitor", "bisect", "compare_source", "output", "no_outbound", "resume",
+    ])]
+    pub egress_policy: Option<PathBuf>,
+
+    /// 政策檔與範本中 ${KEY} 的值，優先於同名的環境變數 (可重複指定)
+    #[arg(long = "set", value_name = "KEY=VALUE", value_parser = crate::vars::parse_assignment)]
+    pub set: Vec<(String, String)>,
+
+    /// 為服務清單未宣告或政策預期關閉的開放端口產生阻擋規則草稿 (只輸出供檢查，不會套用)
+    /// 需要 --manifest、--policy 或 --template
+    #[arg(long, value_enum, value_name = "BACKEND", conflicts_with = "watch")]
+    pub suggest_rules: Option<crate::firewall::Backend>,
+
+    /// 將 --suggest-rules 的規則寫入檔案 (預設輸出到標準輸出)
+    #[arg(long, value_name = "FILE", requires = "suggest_rules")]
+    pub rules_out: Option<PathBuf>,
+
+    /// 出站連線逾時，例如 500ms、2s (預設 1s)
+    #[arg(long, value_parser = DurationParser::default())]
+    pub timeout: Option<Duration>,
+
+    /// 依類別或端口覆蓋逾時，例如 'Database=2s,22=3s'
+    #[arg(long)]
+    pub timeout_override: Option<String>,
+
+    /// 設定檔路徑 (預設 $XDG_CONFIG_HOME/portscanner/config.toml；Windows 為 %APPDATA%，macOS 為 ~/Library/Application Support)
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// 不讀取設定檔 (包括 [defaults])，所有設定使用內建預設值
+    #[arg(long, conflicts_with = "config")]
+    pub no_config: bool,
+
+    /// 套用設定檔 [profiles.名稱] 的選項，覆蓋 [defaults] (portscanner init 會建立一個)
+    #[arg(long, value_name = "名稱", conflicts_with = "no_config")]
+    pub profile: Option<String>,
+
+    /// 不載入使用者端口資料庫 (ports.toml)，只使用內建端口表
+    #[arg(long)]
+    pub no_port_db: bool,
+
+    /// 對 Web 端口額外探測的虛擬主機名稱 (作為 SNI 與 Host)，以逗號分隔
+    #[arg(long, value_delimiter = ',')]
+    pub vhost: Vec<String>,
+
+    /// 每隔指定時間重新掃描並顯示狀態改變，例如 5m (告警規則見設定檔 [[watch.alerts]])
+    #[arg(long, value_parser = DurationParser::default(), conflicts_with_all = ["output", "json"])]
+    pub watch: Option<Duration>,
+
+    /// 把 --watch 的目標退避狀態記錄在 SQLite 資料庫 (可與 --output 的結果資料庫相同)，重新啟動後繼續退避
+    #[arg(long, value_name = "DB", requires = "watch")]
+    pub backoff_db: Option<PathBuf>,
+
+    /// 啟動時清除 --backoff-db 記錄的退避，所有目標立即恢復掃描
+    #[arg(long, requires = "backoff_db")]
+    pub reset_backoff: bool,
+
+    /// 掃描前對每個目標送出的敲門序列，例如 7000,8000,9000:udp (未指定協定時為 TCP)
+    #[arg(long)]
+    pub knock: Option<String>,
+
+    /// 掃描到可出站連線的端口時執行的指令，例如 "notify-send 'port {port} open on {host}'"
+    /// (佔位符 {host} {port} {service} {state} {old_state}，也以 PORTSCAN_HOST 等環境變數提供)
+    #[arg(long, value_name = "COMMAND")]
+    pub on_open: Option<String>,
+
+    /// watch 模式下端口狀態改變時執行的指令，佔位符同 --on-open
+    #[arg(long, value_name = "COMMAND", requires = "watch")]
+    pub on_change: Option<String>,
+
+    /// 單一事件指令的執行期限，超過時終止
+    #[arg(long, value_parser = DurationParser::default(), default_value = "10s")]
+    pub hook_timeout: Duration,
+
+    /// 同時執行的事件指令數量
+    #[arg(long, default_value_t = 4)]
+    pub hook_concurrency: usize,
+
+    /// 每個出站探測都建立新的 socket，不重複使用連線失敗的 socket (僅 Linux 會重複使用)
+    #[arg(long)]
+    pub no_socket_reuse: bool,
+
+    /// 不做入站 (綁定) 測試，只測出站連線；避免在受管制的伺服器上建立監聽
+    #[arg(long, conflicts_with = "no_outbound")]
+    pub no_inbound: bool,
+
+    /// 不做出站連線測試，只檢查本機端口能否綁定 (監聽稽核)
+    #[arg(long)]
+    pub no_outbound: bool,
+
+    /// 敲門封包之間的間隔
+    #[arg(long, value_parser = DurationParser::default(), default_value = "200ms", requires = "knock")]
+    pub knock_delay: Duration,
+
+    /// 探測失敗時重新敲門後再試一次的端口，例如 22
+    #[arg(long, requires = "knock")]
+    pub knock_protected: Option<String>,
+
+    /// 另外列出本機監聽中的 Unix domain socket (Linux 的 /proc/net/unix) 或 Windows 具名管道
+    #[arg(long, conflicts_with_all = ["output", "watch", "bisect"])]
+    pub unix_sockets: bool,
+
+    /// 對 --unix-sockets 列出的每個 stream socket 嘗試連線，確認是否有程序在接受連線
+    #[arg(long, requires = "unix_sockets")]
+    pub unix_connect: bool,
+
+    /// 經由 D-Bus 查詢 systemd 的 socket unit，標示本機目標的端口由哪個 unit 監聽及其狀態 (僅 Linux)
+    #[arg(long, conflicts_with_all = ["output", "watch", "bisect"])]
+    pub systemd: bool,
+
+    /// 以 Linux 連線追蹤表 (nf_conntrack) 中已建立的入站連線核對「只能發送」的判斷 (需要 root 或 CAP_NET_ADMIN)
+    #[arg(long, conflicts_with_all = ["output", "watch", "bisect", "no_inbound"])]
+    pub conntrack: bool,
+
+    /// 查詢 Shodan 對外部 IP 的觀察並與入站測試對照 (API 金鑰由環境變數 SHODAN_API_KEY 提供)
+    #[arg(long, conflicts_with_all = ["output", "watch", "bisect", "no_inbound", "no_external_ip"])]
+    pub shodan: bool,
+
+    /// 記錄每次探測的輸入與結果 (zstd 壓縮)，之後可用 --replay 重現同樣的報告；搭配 --anonymize 時位址換成假名
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["replay", "watch", "bisect", "monitor", "compare_source", "syn", "tor", "dry_run"])]
+    pub record_session: Option<PathBuf>,
+
+    /// 以 --record-session 的記錄取代網路重新執行掃描：目標、端口與本機環境都來自記錄檔，不做任何網路連線
+    #[arg(long, value_name = "FILE", conflicts_with_all = [
+        "target", "ports", "tag", "group", "egress_policy", "srv", "subdomain_list", "axfr", "exclude", "exclude_file",
+        "watch", "bisect", "monitor", "compare_source", "syn", "tor", "proxy", "jump", "knock", "wol", "whois",
+        "tcp_caps", "http_versions", "throughput_test", "fingerprint_db", "vuln_checks", "route_check", "expect_route",
+        "identity_rdns", "detect_cloud", "nat_pmp", "es_bulk", "heartbeat_webhook", "pcap", "privileged_helper",
+        "unix_sockets", "systemd", "conntrack", "shodan", "external_ip", "external_ip_url", "resume", "resume_file", "dry_run",
+    ])]
+    pub replay: Option<PathBuf>,
+
+    /// 將每個端口結果寫成 Elasticsearch / OpenSearch bulk API 格式 (action 與文件各一行，欄位依 ECS 命名)
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["output", "watch", "bisect"])]
+    pub es_bulk: Option<PathBuf>,
+
+    /// 直接 POST bulk 內容到 Elasticsearch / OpenSearch，例如 https://es.example.com:9200
+    /// 基本認證的帳號密碼取自 PORTSCANNER_ES_USERNAME 與 PORTSCANNER_ES_PASSWORD
+    #[arg(long, value_name = "URL", conflicts_with_all = ["output", "watch", "bisect"])]
+    pub es_url: Option<String>,
+
+    /// --es-bulk / --es-url 文件的索引名稱
+    #[arg(long, value_name = "NAME", default_value = "portscanner")]
+    pub es_index: String,
+
+    /// 目標是此雲端平台的 VM：在結果與建議中註記平台的預設防火牆規則
+    #[arg(long, value_enum, value_name = "PROVIDER", conflicts_with_all = ["output", "watch", "monitor", "bisect"])]
+    pub cloud: Option<Provider>,
+
+    /// 以中繼資料服務 (169.254.169.254) 偵測掃描端所在的雲端平台，註記平台封鎖的對外端口
+    #[arg(long, conflicts_with_all = ["output", "watch", "monitor", "bisect"])]
+    pub detect_cloud: bool,
+
+    /// 以 NAT-PMP / PCP 詢問預設閘道：支援的協定、外部位址與對應表 epoch
+    #[arg(long, conflicts_with_all = ["output", "watch", "bisect", "tor"])]
+    pub nat_pmp: bool,
+
+    /// 掃描前對沒有回應的目標送出 Wake-on-LAN 魔術封包，等待寬限時間後再掃描
+    /// MAC 取自 --wol-mac 或過去掃描時從鄰居表記下的位址 (設定目錄下的 macs.json)
+    #[arg(long, conflicts_with = "tor")]
+    pub wol: bool,
+
+    /// 目標的 MAC 位址，格式 IP=MAC (可重複)
+    #[arg(long, value_name = "IP=MAC", value_parser = crate::wol::parse_mapping, requires = "wol")]
+    pub wol_mac: Vec<(std::net::IpAddr, crate::wol::Mac)>,
+
+    /// 送出魔術封包後等待主機回應的最長時間
+    #[arg(long, value_parser = DurationParser::default(), default_value = "60s", requires = "wol")]
+    pub wol_grace: Duration,
+
+    /// 魔術封包的 UDP 目的端口
+    #[arg(long, default_value_t = crate::wol::DEFAULT_PORT, requires = "wol")]
+    pub wol_port: u16,
+
+    /// 同時進行的出站探測數量；auto 依逾時與錯誤比例自動調整 (AIMD)
+    #[arg(long, value_parser = parse_concurrency, default_value = "64")]
+    pub concurrency: Concurrency,
+
+    /// 單一主機同時進行的探測數上限；多目標掃描時各主機輪流排程，此選項再限制每台主機的負載
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub per_host_concurrency: Option<usize>,
+
+    /// 同一目的網段同時進行的探測數上限，格式 前綴長度:數量；例如 24:4 代表每個 /24 最多 4 個 (IPv6 以 /64 計)
+    #[arg(long, value_name = "PREFIX:N", value_parser = crate::netlimit::parse)]
+    pub per_net_concurrency: Option<crate::netlimit::NetLimit>,
+
+    /// 嘗試將檔案描述符的 soft limit 提高到 hard limit (Unix)
+    #[arg(long)]
+    pub raise_nofile: bool,
+
+    /// 將結果逐筆串流寫入檔案 (.ndjson / .csv / .db / .txt)，終端只顯示摘要；可重複指定以同時寫入多種格式
+    #[arg(long)]
+    pub output: Vec<PathBuf>,
+
+    /// 定期把完成的結果寫入續掃檔，中斷後可用 --resume 接續 (掃描完成後刪除)
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["resume", "output", "watch", "bisect", "dry_run"])]
+    pub resume_file: Option<PathBuf>,
+
+    /// 從續掃檔接續中斷的掃描：略過已完成的探測，並把先前的結果併入報告
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["output", "watch", "bisect", "dry_run"])]
+    pub resume: Option<PathBuf>,
+
+    /// 續掃檔寫入磁碟 (fsync) 的間隔；當機時最多遺失這段時間的結果
+    #[arg(long, value_parser = DurationParser::default(), default_value = "5s")]
+    pub checkpoint_interval: Duration,
+
+    /// 掃描期間定期在 --output 的 NDJSON 寫入 progress 事件 (完成數、速率、預估剩餘時間、進行中的探測數)
+    #[arg(long, conflicts_with_all = ["watch", "monitor", "bisect"])]
+    pub heartbeat: bool,
+
+    /// progress 事件的間隔
+    #[arg(long, value_name = "DURATION", value_parser = DurationParser::default(), default_value = "5s")]
+    pub heartbeat_interval: Duration,
+
+    /// 把 progress 事件 POST 到此網址 (不需要 --output)
+    #[arg(long, value_name = "URL", conflicts_with_all = ["watch", "monitor", "bisect"])]
+    pub heartbeat_webhook: Option<String>,
+
+    /// 掃描期間定期以原子改名改寫此檔案：狀態 (running/finished/failed/cancelled)、完成數、速率、預估剩餘時間與最近 5 筆發現
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["watch", "monitor", "bisect", "compare_source"])]
+    pub progress_file: Option<PathBuf>,
+
+    /// --progress-file 的改寫間隔
+    #[arg(long, value_name = "DURATION", value_parser = DurationParser::default(), default_value = "2s", requires = "progress_file")]
+    pub progress_file_interval: Duration,
+
+    /// 串流輸出格式 (預設依副檔名判斷；只能搭配單一 --output)
+    #[arg(long, value_enum, requires = "output")]
+    pub output_format: Option<OutputFormat>,
+
+    /// 串流模式摘要中列出的可連線端口數量
+    #[arg(long, default_value_t = 20)]
+    pub top: usize,
+
+    /// 記錄排程量測 (等待許可、連線時間、並發數、寫入端阻塞)，掃描後顯示剖析
+    #[arg(long, conflicts_with = "watch")]
+    pub profile_scan: bool,
+
+    /// 將每個探測的原始量測寫入 CSV，需搭配 --profile-scan
+    #[arg(long, requires = "profile_scan")]
+    pub profile_csv: Option<PathBuf>,
+
+    /// 摘要中加上掃描器本身的資源用量：CPU 時間、最大記憶體、檔案描述符、同時 socket 數與送出/收到的位元組
+    #[arg(long, conflicts_with_all = ["watch", "monitor", "bisect"])]
+    pub resource_report: bool,
+
+    /// 終端、CSV 與 HTML 的時間戳格式 (JSON 一律為 RFC3339 UTC)
+    #[arg(long, value_enum, default_value_t = TimeFormat::Rfc3339)]
+    pub time_format: TimeFormat,
+
+    /// 結果分組方式
+    #[arg(long, value_enum, default_value_t = GroupBy::Category)]
+    pub group_by: GroupBy,
+
+    /// 組內排序方式
+    #[arg(long, value_enum, default_value_t = SortBy::Port)]
+    pub sort: SortBy,
+
+    /// 服務群組除了摘要之外也逐一列出成員端口
+    #[arg(long)]
+    pub expand_groups: bool,
+
+    /// 終端輸出的寬度 (預設偵測終端寬度，其次為 COLUMNS)；低於 60 時每個端口分兩行顯示
+    #[arg(long, value_name = "COLUMNS", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(crate::layout::NARROWEST as u64..))]
+    pub width: Option<usize>,
+
+    /// 多目標掃描後顯示端口 x 主機的比較表與不一致端口
+    #[arg(long, requires = "target", conflicts_with_all = ["output", "json"])]
+    pub matrix: bool,
+
+    /// 依網段分組顯示多台主機的結果並彙總各網段 (設定檔 [blocks] 可為網段命名)
+    #[arg(long, conflicts_with = "output")]
+    pub blocks: bool,
+
+    /// --blocks 彙總的 IPv4 前綴長度 (IPv6 一律 /64)；設定檔 [blocks] 涵蓋的主機使用設定的網段
+    #[arg(long, default_value_t = 24, value_parser = clap::value_parser!(u8).range(1..=32), requires = "blocks")]
+    pub block_prefix: u8,
+
+    /// 將比較表另存為 .csv 或 .html，需搭配 --matrix
+    #[arg(long, requires = "matrix")]
+    pub matrix_output: Option<PathBuf>,
+
+    /// 掃描後對相關端口執行服務檢查 (例如 NTP 放大風險)
+    #[arg(long)]
+    pub vuln_checks: bool,
+
+    /// 服務檢查的侵入程度：passive 只讀橫幅、active 送出協定查詢、intrusive 允許讀取檔案等操作
+    #[arg(long, value_enum, default_value = "active", requires = "vuln_checks")]
+    pub intrusiveness: Intrusiveness,
+
+    /// 等同 --intrusiveness intrusive
+    #[arg(long, requires = "vuln_checks", conflicts_with = "intrusiveness")]
+    pub intrusive: bool,
+
+    /// 經由本機 Tor SOCKS 代理進行出站探測 (結果反映出口節點的可達性)
+    #[arg(long, conflicts_with_all = ["vuln_checks", "whois", "knock"])]
+    pub tor: bool,
+
+    /// Tor SOCKS 代理位址 (預設依序嘗試 127.0.0.1:9050、127.0.0.1:9150)
+    #[arg(long, requires = "tor")]
+    pub tor_proxy: Option<std::net::SocketAddr>,
+
+    /// 經由 SSH 跳板主機進行出站探測，例如 ops@bastion.example.com:2222 (以 direct-tcpip 通道連到目標)
+    /// 依序使用 ssh-agent 與 ~/.ssh 下的金鑰；跳板的主機金鑰必須已記錄在 known_hosts
+    #[arg(long, value_name = "USER@HOST[:PORT]",
+          conflicts_with_all = ["tor", "syn", "vuln_checks", "knock", "wol", "route_check", "expect_route", "compare_source", "monitor"])]
+    pub jump: Option<crate::jump::JumpSpec>,
+
+    /// 經由 HTTP 代理以 CONNECT 進行出站探測，例如 http://proxy:3128
+    /// Basic 驗證的帳號密碼由環境變數 PORTSCANNER_PROXY_USER、PORTSCANNER_PROXY_PASSWORD 提供
+    #[arg(long, value_name = "URL",
+          conflicts_with_all = ["tor", "jump", "syn", "vuln_checks", "knock", "wol", "route_check", "expect_route", "compare_source", "monitor"])]
+    pub proxy: Option<crate::httpproxy::ProxySpec>,
+
+    /// 只用此私鑰檔登入跳板主機 (不使用 ssh-agent)
+    #[arg(long, value_name = "FILE", requires = "jump")]
+    pub jump_key: Option<PathBuf>,
+
+    /// 每條 SSH 連線同時開啟的通道數上限 (sshd 的 MaxSessions 預設為 10)
+    #[arg(long, default_value_t = 8, requires = "jump",
+          value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..=64))]
+    pub jump_channels: usize,
+
+    /// 通道滿載時最多再開的 SSH 連線數
+    #[arg(long, default_value_t = 4, requires = "jump",
+          value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..=32))]
+    pub jump_sessions: usize,
+
+    /// 查詢外部 IP 的服務網址，回應內容應只有 IP 位址 (預設 https://api.ipify.org；遵循 HTTP(S)_PROXY 與 NO_PROXY)
+    #[arg(long, value_name = "URL")]
+    pub external_ip_url: Option<String>,
+
+    /// 直接指定外部 IP，不查詢外部服務
+    #[arg(long, value_name = "ADDR", value_parser = parse_external_ip, conflicts_with = "external_ip_url")]
+    pub external_ip: Option<std::net::IpAddr>,
+
+    /// 不查詢也不顯示外部 IP (入站測試不會綁定外部 IP，也不檢查目標是否為自己的外部 IP)
+    #[arg(long, conflicts_with_all = ["external_ip", "external_ip_url"])]
+    pub no_external_ip: bool,
+
+    /// 不取得也不顯示本機位址與網路介面 (入站測試只綁定 0.0.0.0)
+    #[arg(long)]
+    pub no_network_info: bool,
+
+    /// 略過外部 IP 的位置檢查 (以到各洲錨點的延遲比對 GeoIP 位置，不一致時提醒可能經過 VPN/代理)
+    #[arg(long)]
+    pub no_geo_sanity: bool,
+
+    /// 將掃描摘要、狀態改變與告警寫入 Windows 應用程式事件記錄
+    #[arg(long)]
+    pub eventlog: bool,
+
+    /// 以原始 SYN 封包進行半開放掃描，不完成握手 (需要 root；只支援 Linux 與 IPv4 目標)
+    #[arg(long, conflicts_with = "tor")]
+    pub syn: bool,
+
+    /// 將掃描送出與收到的探測封包寫入 pcap 檔 (需要 root；只支援 Linux)，可用 Wireshark 或 tcpdump -r 分析
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["dry_run", "watch"])]
+    pub pcap: Option<std::path::PathBuf>,
+
+    /// 經由以 sudo/pkexec 啟動的輔助程序使用原始 socket (--syn、--pcap、ICMP 錯誤)；輔助程序開啟 socket 後即降回目前使用者的權限，無法啟動時改用不需權限的方式
+    #[arg(long)]
+    pub privileged_helper: bool,
+
+    /// 以假名取代所有輸出中的 IP 位址 (保留網段關係)、主機名稱與橫幅中的位址，方便把報告提供給外部
+    #[arg(long, conflicts_with_all = ["watch", "bisect", "dry_run", "whois", "manifest", "pcap"])]
+    pub anonymize: bool,
+
+    /// 將 --anonymize 的原始值與假名對照表寫入 JSON 檔 (不指定時不保存對照表)
+    #[arg(long, value_name = "FILE", requires = "anonymize")]
+    pub anonymize_map: Option<std::path::PathBuf>,
+
+    /// 對出站可連線的端口送出探測並比對橫幅 (探測定義見 ~/.config/portscanner/probes/)
+    #[arg(long)]
+    pub banners: bool,
+
+    /// 附加在輸出中的標註，例如 ticket=OPS-1234 (可重複指定)
+    #[arg(long, value_parser = crate::metadata::parse_annotation)]
+    pub annotate: Vec<(String, String)>,
+
+    /// 這次執行的掃描 ID，例如 CI 的工作 ID (預設隨機產生 UUIDv7)；出現在標頭、所有輸出、告警與事件記錄中
+    #[arg(long, value_name = "ID", value_parser = crate::scanid::parse)]
+    pub scan_id: Option<String>,
+
+    /// 顯示詳細過程 (例如敲門的每一步與時間)
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// 只顯示掃描計劃，不進行任何探測
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// dry-run 時不解析主機名稱
+    #[arg(long, requires = "dry_run")]
+    pub no_resolve: bool,
+
+    /// 查詢遠端目標的 WHOIS 資訊 (網段名稱、組織、abuse 聯絡方式)
+    #[arg(long, requires = "target")]
+    pub whois: bool,
+
+    /// 以 JSON 輸出掃描結果 (搭配 --dry-run 時輸出掃描計劃)
+    #[arg(long)]
+    pub json: bool,
+
+    /// JSON 報告改用精簡形式：連續且狀態與延遲等級相同的未知服務端口合併成 ranges 區段
+    /// (逐端口的完整資料仍在 --output 的 NDJSON / SQLite 中)
+    #[arg(long, requires = "json")]
+    pub aggregate_ranges: bool,
+}
+
+// 子命令
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// 輸出 JSON 輸出格式的 JSON Schema
+    Schema {
+        /// 文件類型
+        #[arg(value_enum, default_value_t = SchemaKind::Report)]
+        kind: SchemaKind,
+    },
+    /// 啟動本機測試服務並掃描，驗證掃描流程是否正確
+    SelfTest,
+    /// 在本機建立測試場 (開放、關閉與黑洞端口)，以多組並發數與逾時掃描，比較吞吐量與誤判數
+    Bench {
+        /// 接受連線的端口數
+        #[arg(long, default_value_t = 200)]
+        open: usize,
+        /// 沒有監聽 (回應 RST) 的端口數
+        #[arg(long, default_value_t = 200)]
+        closed: usize,
+        /// 丟棄 SYN (逾時) 的黑洞端口數
+        #[arg(long, default_value_t = 20)]
+        blackhole: usize,
+        /// 測試的並發數，以逗號分隔
+        #[arg(long, value_delimiter = ',', default_value = "16,64,256",
+              value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        concurrency: Vec<usize>,
+        /// 測試的逾時，以逗號分隔
+        #[arg(long, value_delimiter = ',', default_value = "250ms,1s", value_parser = DurationParser::default())]
+        timeout: Vec<Duration>,
+        /// 將結果另存為 CSV，方便跨版本追蹤
+        #[arg(long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// 查看合規掃描範本
+    Templates {
+        #[command(subcommand)]
+        action: TemplatesCommand,
+    },
+    /// 管理橫幅探測定義
+    Probes {
+        #[command(subcommand)]
+        action: ProbesCommand,
+    },
+    /// 管理使用者端口資料庫 (設定目錄下的 ports.toml)，保留檔案中的註解並顯示修改的差異
+    Ports {
+        #[command(subcommand)]
+        action: PortsCommand,
+    },
+    /// 互動式建立設定檔與具名設定組合 (--profile)，寫入後可立即掃描一次；以選項提供的答案不再詢問
+    Init {
+        #[command(flatten)]
+        options: InitOptions,
+    },
+    /// 產生簽署報告用的 ed25519 金鑰 (私鑰權限 600，公鑰為同名的 .pub)
+    Keygen {
+        /// 私鑰位置 (預設為設定目錄下的 signing.key)
+        #[arg(long, value_name = "FILE")]
+        out: Option<PathBuf>,
+        /// 覆寫已存在的金鑰
+        #[arg(long)]
+        force: bool,
+    },
+    /// 驗證 --sign 簽署的 JSON 報告
+    VerifyReport {
+        /// 報告檔案
+        report: PathBuf,
+        /// 信任的公鑰 (.pub)；未指定時只用報告內嵌的公鑰確認內容未被修改
+        #[arg(long, value_name = "FILE")]
+        key: Option<PathBuf>,
+    },
+    /// 綁定端口並對每個連線回覆 token，在另一台主機以 --expect-token 掃描，確認入站連線到達的是這個程式
+    Listen {
+        /// 要綁定的端口，例如 8000-8010
+        #[arg(long, value_parser = crate::portspec::parse_list)]
+        ports: std::collections::BTreeSet<u16>,
+        /// 綁定的位址
+        #[arg(long, default_value = "0.0.0.0")]
+        bind: std::net::IpAddr,
+        /// 回覆的 token (單行，最多 64 個位元組)
+        #[arg(long, value_parser = crate::listen::parse_token, default_value = crate::listen::DEFAULT_TOKEN)]
+        reply: String,
+        /// 同時綁定 UDP，對每個封包回覆 token
+        #[arg(long)]
+        udp: bool,
+    },
+    /// 只探測一個 host:port，以結束代碼回報結果 (0 可連線、1 無法連線)，適合在腳本中使用
+    Check {
+        /// 目標，例如 example.com:443 或 [2001:db8::1]:22
+        target: crate::quickcheck::Endpoint,
+        /// 連線逾時，例如 500ms、2s
+        #[arg(long, value_parser = DurationParser::default(), default_value = "1s")]
+        timeout: Duration,
+        /// 不輸出，只以結束代碼回報
+        #[arg(long, short)]
+        quiet: bool,
+        /// 可連線時取得服務橫幅
+        #[arg(long)]
+        banner: bool,
+    },
+    /// 快速檢查本機的少數端口 (例如 pre-commit hook)：不查詢外部 IP、逾時 100ms、只輸出無法連線的端口
+    /// 未指定端口時使用設定檔的 [quick] ports；其他選項照常套用
+    Quick {
+        /// 要檢查的端口，例如 5432,6379 (指定時不讀取設定檔)
+        ports: Option<String>,
+    },
+    /// 驗證 --bundle 產生的掃描封存並顯示摘要
+    Open {
+        /// 封存檔案 (.pscan)
+        archive: PathBuf,
+        /// 將封存中的成員原樣輸出到標準輸出
+        #[arg(long, value_enum)]
+        show: Option<ArchiveMember>,
+    },
+    /// 依識別或 IP 列出 SQLite 結果中主機的每次掃描；IP 會對應到曾使用它的識別
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+    History {
+        #[command(subcommand)]
+        action: Option<HistoryCommand>,
+        /// --output 寫入的 .db 檔案
+        #[arg(required = true)]
+        db: Option<PathBuf>,
+        /// 識別 (--host-id、服務清單或反查名稱) 或 IP
+        #[arg(required = true)]
+        host: Option<String>,
+        /// 只比較最近兩次掃描的開放端口
+        #[arg(long)]
+        diff: bool,
+        /// 搭配 --diff：彙整這段期間 (例如 7d) 內所有掃描的狀態變化、新指紋、每日延遲中位數與反覆變化的端口
+        #[arg(long, value_parser = DurationParser::default(), requires = "diff")]
+        since: Option<Duration>,
+        /// 將 --since 的報告寫入檔案；副檔名為 .html 時輸出 HTML，否則為 JSON
+        #[arg(long, value_name = "FILE", requires = "since")]
+        out: Option<PathBuf>,
+    },
+    /// 合併多個地點的 --json 報告：依主機與端口對齊，計算共識並標示各地點看到的差異
+    Merge {
+        /// 兩份以上的報告；來源名稱為報告的 vantage 註記 (--annotate vantage=名稱)，沒有時為檔名
+        #[arg(required = true, num_args = 2..)]
+        reports: Vec<PathBuf>,
+        /// 將合併結果 (含各地點的原始狀態) 寫入 JSON 檔案
+        #[arg(long, value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
+    /// 檢視命令列、環境變數 (PORTSCANNER_*) 與設定檔 [defaults] 合併後的選項
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+    /// 列出常見情境的完整命令列與說明；examples run 代入目標後執行
+    Examples {
+        #[command(subcommand)]
+        action: Option<ExamplesCommand>,
+    },
+    /// 掃描稽核紀錄
+    Audit {
+        #[command(subcommand)]
+        action: AuditCommand,
+    },
+    /// 錯誤代碼表：JSON、事件與結束代碼使用的穩定代碼
+    Errors {
+        #[command(subcommand)]
+        action: ErrorsCommand,
+    },
+}
+
+// errors 子命令
+#[derive(Debug, Subcommand)]
+pub enum ErrorsCommand {
+    /// 列出所有錯誤代碼、名稱、結束代碼與說明
+    List {
+        /// 說明的語言
+        #[arg(long, value_enum, default_value = "zh-TW")]
+        lang: Lang,
+        /// 以 JSON 輸出
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+// audit 子命令
+#[derive(Debug, Subcommand)]
+pub enum AuditCommand {
+    /// 檢查稽核紀錄的雜湊鏈，找出被修改、刪除或插入的紀錄
+    Verify {
+        /// 稽核紀錄檔 (預設為設定檔 [safety] audit_log 或設定目錄下的 audit.jsonl)
+        log: Option<PathBuf>,
+    },
+}
+
+// init 的答案；寫入的位置為 --config、PORTSCANNER_CONFIG 或設定目錄下的 config.toml
+#[derive(Debug, Clone, Default, Args)]
+pub struct InitOptions {
+    /// 掃描目標 (主機名稱、IP 或 CIDR 網段，以逗號分隔)；會先解析確認
+    #[arg(long)]
+    pub target: Option<String>,
+    /// 端口：common (內建常用端口表)、all 或清單，例如 22,80,8000-8100
+    #[arg(long)]
+    pub ports: Option<String>,
+    /// 掃描強度
+    #[arg(long, value_enum)]
+    pub intensity: Option<Intensity>,
+    /// 結果逐筆寫入的檔案 (.ndjson / .csv / .db / .txt)；可重複指定
+    #[arg(long, value_name = "FILE", conflicts_with = "no_output")]
+    pub output: Vec<PathBuf>,
+    /// 不寫入結果檔案
+    #[arg(long)]
+    pub no_output: bool,
+    /// 狀態改變時 POST 告警的網址 (--watch)
+    #[arg(long, value_name = "URL", conflicts_with = "no_alerts")]
+    pub webhook: Option<String>,
+    /// 不設定告警
+    #[arg(long)]
+    pub no_alerts: bool,
+    /// 設定組合的名稱 (預設 default)
+    #[arg(long, value_name = "名稱")]
+    pub name: Option<String>,
+    /// 不詢問，沒有以選項提供的答案使用預設值
+    #[arg(short, long)]
+    pub yes: bool,
+    /// 覆寫已存在的設定檔 (原檔備份為 .bak)
+    #[arg(long)]
+    pub force: bool,
+    /// 寫入後立即以設定組合掃描一次
+    #[arg(long, conflicts_with = "no_run")]
+    pub run: bool,
+    /// 寫入後不掃描
+    #[arg(long)]
+    pub no_run: bool,
+}
+
+// history 子命令
+#[derive(Debug, Subcommand)]
+pub enum HistoryCommand {
+    /// 刪除未參照的橫幅與指紋並重建資料庫，顯示回收的空間；舊版資料庫會先升級
+    Vacuum {
+        /// --output 或 --fingerprint-db 的 .db 檔案
+        db: PathBuf,
+    },
+}
+
+// examples 子命令
+#[derive(Debug, Subcommand)]
+pub enum ExamplesCommand {
+    /// 顯示範例的完整命令列，確認後執行
+    Run {
+        /// 範例名稱
+        name: String,
+        /// 代入範例的目標
+        #[arg(long)]
+        target: Option<String>,
+        /// 不詢問，直接執行
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// 確認每個範例都能以目前的命令列選項解析
+    Check,
+}
+
+// config 子命令
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// 顯示每個選項的有效值與來源
+    Show,
+    /// 將舊版設定檔升級到目前的格式 (原檔備份為 .bak)；設定檔位置同 --config
+    Migrate {
+        /// 只顯示會改變的內容，不寫入
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+// ports 子命令
+#[derive(Debug, Subcommand)]
+pub enum PortsCommand {
+    /// 新增端口 (不能與內建端口表或資料庫中的端口重複)
+    Add {
+        #[arg(value_parser = clap::value_parser!(u16).range(1..))]
+        port: u16,
+        /// 服務名稱
+        #[arg(long)]
+        service: String,
+        /// 類別
+        #[arg(long, default_value = crate::portdb::DEFAULT_CATEGORY)]
+        category: String,
+        /// 備註
+        #[arg(long)]
+        note: Option<String>,
+        /// 只顯示修改後的資料庫，不寫入
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// 移除端口 (內建端口標記為不掃描)
+    Remove {
+        #[arg(value_parser = clap::value_parser!(u16).range(1..))]
+        port: u16,
+        /// 只顯示修改後的資料庫，不寫入
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// 把端口移到其他類別
+    Move {
+        #[arg(value_parser = clap::value_parser!(u16).range(1..))]
+        port: u16,
+        /// 新的類別
+        #[arg(long)]
+        category: String,
+        /// 只顯示修改後的資料庫，不寫入
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+// templates 子命令
+#[derive(Debug, Subcommand)]
+pub enum TemplatesCommand {
+    /// 列出內建與使用者目錄中的範本
+    List,
+    /// 顯示範本的端口與預期狀態
+    Show {
+        /// 範本名稱
+        name: String,
+    },
+}
+
+// probes 子命令
+#[derive(Debug, Subcommand)]
+pub enum ProbesCommand {
+    /// 列出已載入的探測 (內建與使用者目錄) 及其來源
+    List,
+}
+
+// 有 JSON Schema 的輸出文件
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SchemaKind {
+    /// --json 掃描報告
+    Report,
+    /// --output 的 NDJSON 逐筆紀錄
+    Record,
+    /// --heartbeat 寫入 NDJSON 與 POST 到 webhook 的 progress 事件
+    Progress,
+    /// --progress-file 的內容
+    ProgressFile,
+    /// --dry-run --json 掃描計劃
+    Plan,
+    /// merge 子命令的合併報告
+    Merge,
+}
+
+// 掃描封存中的成員
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ArchiveMember {
+    /// JSON 報告 (同 --json)
+    Json,
+    /// 逐筆紀錄 (同 --output 的 NDJSON)
+    Ndjson,
+    /// 逐字紀錄 (--transcript)
+    Transcript,
+    /// HTML 跨主機比較表
+    Html,
+}
+
+impl Cli {
+    // --intrusive 是 --intrusiveness intrusive 的簡寫
+    pub fn check_level(&self) -> Intrusiveness {
+        if self.intrusive {
+            Intrusiveness::Intrusive
+        } else {
+            self.intrusiveness
+        }
+    }
+}
+
+// --concurrency 的值
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Concurrency {
+    Fixed(usize),
+    Auto,
+}
+
+pub fn parse_concurrency(s: &str) -> Result<Concurrency, String> {
+    match s.trim() {
+        "auto" => Ok(Concurrency::Auto),
+        n => match n.parse::<usize>() {
+            Ok(0) => Err("並發數量必須大於 0".to_string()),
+            Ok(n) => Ok(Concurrency::Fixed(n)),
+            Err(_) => Err(format!("無效的並發數量 '{}' (應為正整數或 auto)", s)),
+        },
+    }
+}
+
+// --external-ip 與查詢服務的回應使用相同的檢查
+fn parse_external_ip(s: &str) -> Result<std::net::IpAddr, String> {
+    crate::context::validate_external_ip(s)
+}
+
+fn parse_confidence(s: &str) -> Result<f64, String> {
+    s.trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|score| (0.0..=1.0).contains(score))
+        .ok_or_else(|| format!("無效的信心分數 '{}' (應為 0 到 1 之間)", s))
+}
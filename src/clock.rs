@@ -0,0 +1,313 @@
+use std::time::{Duration, Instant, SystemTime};
+use schemars::JsonSchema;
+use serde::Serialize;
+use crate::notify::SystemClock;
+use crate::timefmt;
+
+// 牆上時間與單調時間的差距超過此值才視為跳變；NTP 的微調 (slew) 遠小於此
+pub const THRESHOLD: Duration = Duration::from_secs(5);
+
+// watch 等待下一次掃描時檢查時間跳變的間隔
+const POLL: Duration = Duration::from_secs(5);
+
+// 受影響的掃描結果附上的說明
+pub const NOTE: &str = "系統時間跳變/休眠，時間資料可能不準";
+
+// 時間的來源：時間長度一律以單調時間計算，牆上時間只在記錄時間戳時讀取
+// 測試可換成手動調整牆上時間與休眠的時鐘
+pub trait TimeSource {
+    fn wall(&self) -> SystemTime;
+    // 不受系統時間調整影響；Linux 上休眠期間不前進
+    fn monotonic(&self) -> Instant;
+    // 包含休眠時間的開機時間 (Linux CLOCK_BOOTTIME)；無法取得時為 None
+    fn boottime(&self) -> Option<Duration>;
+}
+
+impl TimeSource for SystemClock {
+    fn wall(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn monotonic(&self) -> Instant {
+        Instant::now()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn boottime(&self) -> Option<Duration> {
+        let mut now: libc::timespec = unsafe { std::mem::zeroed() };
+        if unsafe { libc::clock_gettime(libc::CLOCK_BOOTTIME, &mut now) } != 0 {
+            return None;
+        }
+        Some(Duration::new(now.tv_sec as u64, now.tv_nsec as u32))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn boottime(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JumpKind {
+    // 牆上時間比經過的時間多 (NTP 校正或手動調整)；無法取得開機時間的平台上休眠也是如此
+    Forward,
+    Backward,
+    Suspend,
+}
+
+// 一次檢查期間的時間跳變
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, JsonSchema)]
+pub struct TimeJump {
+    pub kind: JumpKind,
+    // 跳變或休眠的長度 (秒)
+    pub seconds: f64,
+}
+
+impl TimeJump {
+    // 例如 "系統休眠了 1h 5m，系統時間跳變/休眠，時間資料可能不準"
+    pub fn describe(&self) -> String {
+        let amount = timefmt::duration(Duration::from_secs_f64(self.seconds));
+        let what = match self.kind {
+            JumpKind::Forward => format!("系統時間向前跳了 {}", amount),
+            JumpKind::Backward => format!("系統時間倒退了 {}", amount),
+            JumpKind::Suspend => format!("系統休眠了 {}", amount),
+        };
+        format!("{}，{}", what, NOTE)
+    }
+
+    // 同一段期間內的多次跳變：同類相加，否則保留較長的一次
+    fn merge(self, other: TimeJump) -> TimeJump {
+        match (self.kind == other.kind, self.seconds >= other.seconds) {
+            (true, _) => TimeJump { kind: self.kind, seconds: self.seconds + other.seconds },
+            (false, true) => self,
+            (false, false) => other,
+        }
+    }
+}
+
+// 比較上次檢查以來牆上時間、單調時間與開機時間各自經過的時間
+pub struct JumpDetector<T: TimeSource = SystemClock> {
+    source: T,
+    wall: SystemTime,
+    monotonic: Instant,
+    boot: Option<Duration>,
+    // 上次 take 之後偵測到的跳變
+    pending: Option<TimeJump>,
+}
+
+impl<T: TimeSource> JumpDetector<T> {
+    pub fn new(source: T) -> Self {
+        let (wall, monotonic, boot) = (source.wall(), source.monotonic(), source.boottime());
+        JumpDetector { source, wall, monotonic, boot, pending: None }
+    }
+
+    // 上次檢查以來的跳變；同時累積到 pending
+    pub fn check(&mut self) -> Option<TimeJump> {
+        let (wall, monotonic, boot) = (self.source.wall(), self.source.monotonic(), self.source.boottime());
+        let elapsed = monotonic.saturating_duration_since(self.monotonic).as_secs_f64();
+        let wall_elapsed = match wall.duration_since(self.wall) {
+            Ok(forward) => forward.as_secs_f64(),
+            Err(backward) => -backward.duration().as_secs_f64(),
+        };
+        let asleep = boot.zip(self.boot).map(|(now, then)| now.saturating_sub(then).as_secs_f64() - elapsed);
+        (self.wall, self.monotonic, self.boot) = (wall, monotonic, boot);
+
+        let (skew, threshold) = (wall_elapsed - elapsed, THRESHOLD.as_secs_f64());
+        // 休眠後 NTP 的校正也算在休眠內
+        let jump = match asleep {
+            Some(asleep) if asleep >= threshold => TimeJump { kind: JumpKind::Suspend, seconds: asleep },
+            _ if skew >= threshold => TimeJump { kind: JumpKind::Forward, seconds: skew },
+            _ if skew <= -threshold => TimeJump { kind: JumpKind::Backward, seconds: -skew },
+            _ => return None,
+        };
+        self.pending = Some(self.pending.map_or(jump, |pending| pending.merge(jump)));
+        Some(jump)
+    }
+
+    // 檢查並取出上次 take 之後的跳變 (例如一次 watch 掃描與它之前的等待)
+    pub fn take(&mut self) -> Option<TimeJump> {
+        self.check();
+        self.pending.take()
+    }
+}
+
+// watch 下一次掃描前的等待：以單調時間計算，休眠的時間也算已等待
+// 休眠超過間隔時醒來立即掃描一次，不補跑錯過的次數；時間調整不影響間隔
+pub struct Wait {
+    interval: Duration,
+    started: Instant,
+    slept: Duration,
+}
+
+impl Wait {
+    pub fn new(interval: Duration, now: Instant) -> Self {
+        Wait { interval, started: now, slept: Duration::ZERO }
+    }
+
+    pub fn observe(&mut self, jump: &TimeJump) {
+        if jump.kind == JumpKind::Suspend {
+            self.slept += Duration::from_secs_f64(jump.seconds);
+        }
+    }
+
+    pub fn remaining(&self, now: Instant) -> Duration {
+        self.interval.saturating_sub(now.saturating_duration_since(self.started) + self.slept)
+    }
+}
+
+// 等到下一次掃描 (見 Wait)
+pub async fn wait<T: TimeSource>(interval: Duration, detector: &mut JumpDetector<T>) {
+    let mut wait = Wait::new(interval, detector.source.monotonic());
+    loop {
+        let left = wait.remaining(detector.source.monotonic());
+        if left.is_zero() {
+            return;
+        }
+        tokio::time::sleep(left.min(POLL)).await;
+        if let Some(jump) = detector.check() {
+            wait.observe(&jump);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    // 單調時間跟著 tokio 的 (暫停) 時間前進；牆上時間與休眠可手動調整
+    #[derive(Clone)]
+    struct ManualClock {
+        origin: Instant,
+        wall: SystemTime,
+        // (牆上時間的調整秒數, 休眠的長度)
+        offsets: Arc<Mutex<(f64, Duration)>>,
+    }
+
+    impl ManualClock {
+        fn new() -> Self {
+            ManualClock {
+                origin: tokio::time::Instant::now().into_std(),
+                wall: SystemTime::UNIX_EPOCH + Duration::from_secs(1_800_000_000),
+                offsets: Arc::default(),
+            }
+        }
+
+        fn step(&self, seconds: f64) {
+            self.offsets.lock().unwrap().0 += seconds;
+        }
+
+        fn suspend(&self, duration: Duration) {
+            self.offsets.lock().unwrap().1 += duration;
+        }
+    }
+
+    impl TimeSource for ManualClock {
+        fn wall(&self) -> SystemTime {
+            let (step, slept) = *self.offsets.lock().unwrap();
+            let base = self.wall + self.monotonic().duration_since(self.origin) + slept;
+            match step >= 0.0 {
+                true => base + Duration::from_secs_f64(step),
+                false => base - Duration::from_secs_f64(-step),
+            }
+        }
+
+        fn monotonic(&self) -> Instant {
+            tokio::time::Instant::now().into_std()
+        }
+
+        fn boottime(&self) -> Option<Duration> {
+            Some(self.monotonic().duration_since(self.origin) + self.offsets.lock().unwrap().1)
+        }
+    }
+
+    async fn advance(duration: Duration) {
+        tokio::time::advance(duration).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn steady_clocks_report_nothing() {
+        let clock = ManualClock::new();
+        let mut detector = JumpDetector::new(clock.clone());
+        advance(Duration::from_secs(600)).await;
+        // 小於門檻的校正不算
+        clock.step(2.0);
+        assert_eq!(detector.check(), None);
+        assert_eq!(detector.take(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn forward_and_backward_jumps_are_classified() {
+        let clock = ManualClock::new();
+        let mut detector = JumpDetector::new(clock.clone());
+        advance(Duration::from_secs(10)).await;
+        clock.step(3600.0);
+        let forward = detector.check().unwrap();
+        assert_eq!(forward.kind, JumpKind::Forward);
+        assert!((forward.seconds - 3600.0).abs() < 0.01, "{:?}", forward);
+
+        clock.step(-7200.0);
+        let backward = detector.check().unwrap();
+        assert_eq!(backward.kind, JumpKind::Backward);
+        assert!((backward.seconds - 7200.0).abs() < 0.01, "{:?}", backward);
+        // take 取出期間較長的一次
+        assert_eq!(detector.take().map(|jump| jump.kind), Some(JumpKind::Backward));
+        assert_eq!(detector.take(), None);
+        assert_eq!(backward.describe(), format!("系統時間倒退了 2h 0m，{}", NOTE));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn suspend_gaps_are_told_apart_from_clock_steps() {
+        let clock = ManualClock::new();
+        let mut detector = JumpDetector::new(clock.clone());
+        advance(Duration::from_secs(30)).await;
+        clock.suspend(Duration::from_secs(5400));
+        let jump = detector.take().unwrap();
+        assert_eq!(jump.kind, JumpKind::Suspend);
+        assert!((jump.seconds - 5400.0).abs() < 0.01, "{:?}", jump);
+        assert!(jump.describe().starts_with("系統休眠了 1h 30m，"));
+    }
+
+    // 休眠超過間隔：醒來後只立即掃描一次，之後回到正常間隔
+    #[tokio::test(start_paused = true)]
+    async fn waits_resume_once_after_a_long_suspend() {
+        let interval = Duration::from_secs(600);
+        let clock = ManualClock::new();
+        let mut detector = JumpDetector::new(clock.clone());
+        clock.suspend(Duration::from_secs(4 * 3600));
+        let started = tokio::time::Instant::now();
+        wait(interval, &mut detector).await;
+        assert!(started.elapsed() <= POLL, "{:?}", started.elapsed());
+        assert_eq!(detector.take().map(|jump| jump.kind), Some(JumpKind::Suspend));
+
+        let started = tokio::time::Instant::now();
+        wait(interval, &mut detector).await;
+        assert_eq!(started.elapsed(), interval);
+    }
+
+    // 時間調整不改變等待的長度
+    #[tokio::test(start_paused = true)]
+    async fn clock_steps_do_not_shorten_or_stretch_waits() {
+        let interval = Duration::from_secs(600);
+        for step in [3600.0, -3600.0] {
+            let clock = ManualClock::new();
+            let mut detector = JumpDetector::new(clock.clone());
+            clock.step(step);
+            let started = tokio::time::Instant::now();
+            wait(interval, &mut detector).await;
+            assert_eq!(started.elapsed(), interval);
+            assert!(detector.take().is_some());
+        }
+    }
+
+    #[test]
+    fn partial_suspends_shorten_the_remaining_wait() {
+        let now = Instant::now();
+        let mut wait = Wait::new(Duration::from_secs(600), now);
+        wait.observe(&TimeJump { kind: JumpKind::Suspend, seconds: 200.0 });
+        wait.observe(&TimeJump { kind: JumpKind::Forward, seconds: 1000.0 });
+        assert_eq!(wait.remaining(now + Duration::from_secs(100)), Duration::from_secs(300));
+        assert_eq!(wait.remaining(now + Duration::from_secs(500)), Duration::ZERO);
+    }
+}
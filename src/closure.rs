@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::io::{self, ErrorKind};
+use std::time::Duration;
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::{PortInfo, ScanResult};
+
+// 至少要有這麼多個未連線的端口才做推論
+const MIN_SAMPLES: usize = 5;
+
+// 比例超過此值視為主要行為
+pub const DOMINANT: f64 = 0.8;
+
+// 出站連線失敗的方式
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Failure {
+    // 收到 RST (連線被拒)，附上從送出到被拒的時間
+    Reset { latency_ms: f64 },
+    // 逾時沒有回應
+    Timeout,
+    // ICMP 不可達或其他網路錯誤
+    Unreachable,
+    // --proxy 的 HTTP 代理以 4xx 拒絕 CONNECT：路徑存在，但被代理的政策封鎖
+    ProxyDenied { status: u16 },
+}
+
+impl Failure {
+    // 依連線錯誤分類
+    pub fn from_error(error: &io::Error, elapsed: Duration) -> Self {
+        match error.kind() {
+            ErrorKind::ConnectionRefused => Failure::Reset {
+                latency_ms: elapsed.as_secs_f64() * 1000.0,
+            },
+            ErrorKind::TimedOut => Failure::Timeout,
+            _ => Failure::Unreachable,
+        }
+    }
+}
+
+// 單一主機未連線端口的回應統計
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct CloseBehavior {
+    // 有失敗紀錄的端口數
+    pub ports: usize,
+    pub reset: usize,
+    pub timeout: usize,
+    pub unreachable: usize,
+    pub proxy_denied: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub median_reset_ms: Option<f64>,
+    // 樣本足夠時的推論
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inference: Option<String>,
+}
+
+impl CloseBehavior {
+    // 彙整主機的結果；沒有失敗紀錄 (例如經由代理) 時回傳 None
+    pub fn from_results(results: &HashMap<PortInfo, ScanResult>) -> Option<Self> {
+        let failures: Vec<Failure> = results.values().filter_map(|r| r.failure).collect();
+        if failures.is_empty() {
+            return None;
+        }
+
+        let mut resets: Vec<f64> = failures
+            .iter()
+            .filter_map(|f| match f {
+                Failure::Reset { latency_ms } => Some(*latency_ms),
+                _ => None,
+            })
+            .collect();
+        resets.sort_by(f64::total_cmp);
+        let median_reset_ms = match resets.len() {
+            0 => None,
+            n if n % 2 == 1 => Some(resets[n / 2]),
+            n => Some((resets[n / 2 - 1] + resets[n / 2]) / 2.0),
+        };
+
+        let mut behavior = CloseBehavior {
+            ports: failures.len(),
+            reset: resets.len(),
+            timeout: failures.iter().filter(|f| **f == Failure::Timeout).count(),
+            unreachable: failures.iter().filter(|f| **f == Failure::Unreachable).count(),
+            proxy_denied: failures.iter().filter(|f| matches!(f, Failure::ProxyDenied { .. })).count(),
+            median_reset_ms,
+            inference: None,
+        };
+        behavior.inference = behavior.infer();
+        Some(behavior)
+    }
+
+    fn percent(&self, count: usize) -> f64 {
+        count as f64 * 100.0 / self.ports as f64
+    }
+
+    fn infer(&self) -> Option<String> {
+        if self.ports < MIN_SAMPLES {
+            return None;
+        }
+        let share = |count: usize| count as f64 / self.ports as f64;
+        let inference = if share(self.proxy_denied) >= DOMINANT {
+            format!("多數端口被 HTTP 代理依政策拒絕 ({:.0}%)，路徑存在但不允許連線", self.percent(self.proxy_denied))
+        } else if share(self.timeout) >= DOMINANT {
+            format!("目標似乎有狀態防火牆 ({:.0}% 無回應)", self.percent(self.timeout))
+        } else if share(self.reset) >= DOMINANT {
+            format!("目標直接以 RST 拒絕，未發現過濾 ({:.0}% RST)", self.percent(self.reset))
+        } else if share(self.unreachable) >= DOMINANT {
+            format!("多數探測收到不可達錯誤 ({:.0}%)，可能被路由器或防火牆拒絕", self.percent(self.unreachable))
+        } else {
+            format!(
+                "部分端口被過濾 ({:.0}% 無回應，{:.0}% RST，{:.0}% 不可達)",
+                self.percent(self.timeout),
+                self.percent(self.reset),
+                self.percent(self.unreachable)
+            )
+        };
+        Some(inference)
+    }
+}
+
+// 主機標題下的統計行
+pub fn display(behavior: &CloseBehavior) {
+    let median = behavior.median_reset_ms.map(|ms| format!("，RST 中位數 {:.1}ms", ms)).unwrap_or_default();
+    let denied = match behavior.proxy_denied {
+        0 => String::new(),
+        count => format!("，proxy 拒絕 {}", count),
+    };
+    println!(
+        "{}",
+        format!(
+            "未連線 {} 個端口：RST {}，無回應 {}，不可達 {}{}{}",
+            behavior.ports, behavior.reset, behavior.timeout, behavior.unreachable, denied, median
+        )
+        .dimmed()
+    );
+    if let Some(inference) = &behavior.inference {
+        println!("{}", inference.yellow());
+    }
+}
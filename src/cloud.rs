@@ -0,0 +1,491 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Duration;
+use clap::ValueEnum;
+use colored::*;
+use schemars::JsonSchema;
+use serde::Serialize;
+use crate::anonymize::Anonymizer;
+use crate::recommend::{Recommendation, Severity};
+use crate::{PortInfo, ScanResult};
+
+// 偵測掃描端所在平台時，每個中繼資料服務的等待時間
+pub const DETECT_TIMEOUT: Duration = Duration::from_millis(800);
+
+// 三家平台的執行個體中繼資料服務都在這個位址
+const METADATA_IP: Ipv4Addr = Ipv4Addr::new(169, 254, 169, 254);
+// Azure 平台的 WireServer (VM 代理程式與 DHCP / DNS 使用)
+const AZURE_WIRESERVER: Ipv4Addr = Ipv4Addr::new(168, 63, 129, 16);
+
+// 雲端平台 (--cloud)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    Aws,
+    Gcp,
+    Azure,
+}
+
+impl Provider {
+    pub fn label(self) -> &'static str {
+        match self {
+            Provider::Aws => "AWS",
+            Provider::Gcp => "Google Cloud",
+            Provider::Azure => "Azure",
+        }
+    }
+
+    // 從外部掃描此平台的 VM 時，預設的入站規則
+    fn default_inbound(self) -> &'static str {
+        match self {
+            Provider::Aws => "預設安全群組拒絕所有外部入站連線，可連線的端口都是安全群組與網路 ACL 明確允許的",
+            Provider::Gcp => "default 網路預設允許來自任何位址的 SSH (22)、RDP (3389) 與 ICMP，其餘入站連線預設拒絕",
+            Provider::Azure => "網路安全性群組預設拒絕來自網際網路的入站連線，但入口網站建立 VM 時常會加上 SSH / RDP 的允許規則",
+        }
+    }
+
+    // 掃描端位於此平台時，中繼資料服務的提醒
+    fn metadata_reminder(self) -> &'static str {
+        match self {
+            Provider::Aws => "此主機可存取 EC2 執行個體中繼資料服務 (169.254.169.254)，建議強制 IMDSv2",
+            Provider::Gcp => "此主機可存取 Compute Engine 中繼資料伺服器 (169.254.169.254)，服務帳戶的存取權杖可由此取得",
+            Provider::Azure => "此主機可存取 Azure Instance Metadata Service (169.254.169.254)，受控識別的權杖可由此取得",
+        }
+    }
+}
+
+// 註記適用的一方：掃描端位於雲端 (--detect-cloud)，或目標是雲端 VM (--cloud)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    Scanner,
+    Target,
+}
+
+// 註記的類別
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteKind {
+    // 平台封鎖的連線，結果不反映目標的防火牆
+    ProviderBlocked,
+    // 平台預設的防火牆規則
+    DefaultRule,
+    // 平台的中繼資料或管理服務
+    MetadataService,
+}
+
+impl NoteKind {
+    fn label(self) -> &'static str {
+        match self {
+            NoteKind::ProviderBlocked => "平台封鎖",
+            NoteKind::DefaultRule => "預設規則",
+            NoteKind::MetadataService => "中繼資料服務",
+        }
+    }
+}
+
+// 平台知識表的一項；host 為 None 時適用任何目標
+// advice 取代該端口原本的建議，可使用 {port}、{service}
+struct Note {
+    provider: Provider,
+    side: Side,
+    host: Option<Ipv4Addr>,
+    ports: &'static [u16],
+    reachable: bool,
+    kind: NoteKind,
+    message: &'static str,
+    advice: Option<(Severity, &'static str)>,
+}
+
+impl Note {
+    fn matches(&self, host: IpAddr, port: u16, result: &ScanResult) -> bool {
+        self.host.is_none_or(|h| IpAddr::V4(h) == host) && self.ports.contains(&port) && result.outbound == self.reachable
+    }
+}
+
+const NOTES: &[Note] = &[
+    Note {
+        provider: Provider::Aws,
+        side: Side::Scanner,
+        host: None,
+        ports: &[25],
+        reachable: false,
+        kind: NoteKind::ProviderBlocked,
+        message: "EC2 預設限制連往外部的 port 25，無法連線可能是 AWS 的限制而非目標的防火牆",
+        advice: Some((Severity::Info, "Port {port} ({service}) 的結果受 EC2 的對外限制影響 — 寄信請改用 587，或向 AWS 申請解除限制後再測試")),
+    },
+    Note {
+        provider: Provider::Gcp,
+        side: Side::Scanner,
+        host: None,
+        ports: &[25],
+        reachable: false,
+        kind: NoteKind::ProviderBlocked,
+        message: "Compute Engine 一律封鎖連往外部 IP 的 port 25，且無法申請解除",
+        advice: Some((Severity::Info, "Port {port} ({service}) 無法從 Compute Engine 測試 — 請改從其他網路掃描，寄信請改用 587 或 465")),
+    },
+    Note {
+        provider: Provider::Azure,
+        side: Side::Scanner,
+        host: None,
+        ports: &[25],
+        reachable: false,
+        kind: NoteKind::ProviderBlocked,
+        message: "Azure 對多數訂閱類型封鎖連往外部的 port 25",
+        advice: Some((Severity::Info, "Port {port} ({service}) 的結果受 Azure 的對外限制影響 — 寄信請改用 587 或經過驗證的 SMTP 轉送服務")),
+    },
+    Note {
+        provider: Provider::Aws,
+        side: Side::Scanner,
+        host: Some(METADATA_IP),
+        ports: &[80],
+        reachable: true,
+        kind: NoteKind::MetadataService,
+        message: "EC2 執行個體中繼資料服務 (IMDS)，可取得執行個體的 IAM 角色憑證",
+        advice: Some((Severity::Medium, "Port {port} 是 EC2 的中繼資料服務 — 建議強制 IMDSv2 (HttpTokens=required) 並把 hop limit 設為 1，避免應用程式的 SSRF 取得憑證")),
+    },
+    Note {
+        provider: Provider::Gcp,
+        side: Side::Scanner,
+        host: Some(METADATA_IP),
+        ports: &[80],
+        reachable: true,
+        kind: NoteKind::MetadataService,
+        message: "Compute Engine 中繼資料伺服器，需要 Metadata-Flavor 標頭",
+        advice: Some((Severity::Low, "Port {port} 是 Compute Engine 的中繼資料伺服器 — 建議確認 VM 的服務帳戶只有必要的存取範圍")),
+    },
+    Note {
+        provider: Provider::Azure,
+        side: Side::Scanner,
+        host: Some(METADATA_IP),
+        ports: &[80],
+        reachable: true,
+        kind: NoteKind::MetadataService,
+        message: "Azure Instance Metadata Service (IMDS)，需要 Metadata 標頭",
+        advice: Some((Severity::Low, "Port {port} 是 Azure 的中繼資料服務 — 建議確認 VM 的受控識別只有必要的權限")),
+    },
+    Note {
+        provider: Provider::Azure,
+        side: Side::Scanner,
+        host: Some(AZURE_WIRESERVER),
+        ports: &[80, 32526],
+        reachable: true,
+        kind: NoteKind::MetadataService,
+        message: "Azure 平台的 WireServer，VM 代理程式使用，不應封鎖",
+        advice: Some((Severity::Info, "Port {port} 是 Azure 平台的 WireServer — 這是平台服務，不需要處理")),
+    },
+    Note {
+        provider: Provider::Aws,
+        side: Side::Target,
+        host: None,
+        ports: &[22, 3389],
+        reachable: true,
+        kind: NoteKind::DefaultRule,
+        message: "預設安全群組不允許此端口，可連線表示有規則明確開放",
+        advice: Some((Severity::High, "Port {port} ({service}) 對外開放 — 建議移除來源為 0.0.0.0/0 的安全群組規則，改用 Session Manager 或 EC2 Instance Connect")),
+    },
+    Note {
+        provider: Provider::Gcp,
+        side: Side::Target,
+        host: None,
+        ports: &[22],
+        reachable: true,
+        kind: NoteKind::DefaultRule,
+        message: "default 網路的 default-allow-ssh 規則預設對 0.0.0.0/0 開放",
+        advice: Some((Severity::Medium, "Port {port} ({service}) 對外開放 — 建議刪除 default-allow-ssh 規則，或把來源限制為 IAP 的 35.235.240.0/20")),
+    },
+    Note {
+        provider: Provider::Gcp,
+        side: Side::Target,
+        host: None,
+        ports: &[3389],
+        reachable: true,
+        kind: NoteKind::DefaultRule,
+        message: "default 網路的 default-allow-rdp 規則預設對 0.0.0.0/0 開放",
+        advice: Some((Severity::High, "Port {port} ({service}) 對外開放 — 建議刪除 default-allow-rdp 規則，或把來源限制為 IAP 的 35.235.240.0/20")),
+    },
+    Note {
+        provider: Provider::Azure,
+        side: Side::Target,
+        host: None,
+        ports: &[22, 3389],
+        reachable: true,
+        kind: NoteKind::DefaultRule,
+        message: "入口網站建立 VM 時預設可加上此端口的入站規則",
+        advice: Some((Severity::High, "Port {port} ({service}) 對外開放 — 建議移除來源為 Internet 的網路安全性群組規則，改用 Azure Bastion 或 Just-in-Time VM 存取")),
+    },
+];
+
+// --cloud 指定的目標平台與 --detect-cloud 偵測到的掃描端平台
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct CloudContext {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<Provider>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scanner: Option<Provider>,
+}
+
+impl CloudContext {
+    pub fn is_empty(&self) -> bool {
+        self.target.is_none() && self.scanner.is_none()
+    }
+
+    fn applies(&self, note: &Note) -> bool {
+        match note.side {
+            Side::Scanner => self.scanner == Some(note.provider),
+            Side::Target => self.target == Some(note.provider),
+        }
+    }
+
+    fn notes<'a>(&'a self, host: IpAddr, port: u16, result: &'a ScanResult) -> impl Iterator<Item = &'static Note> + 'a {
+        NOTES.iter().filter(move |note| self.applies(note) && note.matches(host, port, result))
+    }
+}
+
+// 端口行之後的標示；只看端口的註記 (特定主機的註記列在雲端區段)
+pub fn mark(context: &CloudContext, port: u16, result: &ScanResult) -> Option<ColoredString> {
+    let note = NOTES.iter().find(|note| note.host.is_none() && context.applies(note) && note.ports.contains(&port) && result.outbound == note.reachable)?;
+    Some(format!("☁ {}: {}", note.provider.label(), note.kind.label()).blue())
+}
+
+// 報告中的一項平台註記
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Annotation {
+    pub provider: Provider,
+    pub side: Side,
+    pub kind: NoteKind,
+    pub host: IpAddr,
+    pub port: u16,
+    pub service: String,
+    pub message: String,
+    // 取代原本建議的平台建議
+    #[serde(skip)]
+    advice: Option<(Severity, String)>,
+}
+
+// JSON 的 cloud 欄位
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+pub struct CloudReport {
+    #[serde(flatten)]
+    pub context: CloudContext,
+    pub annotations: Vec<Annotation>,
+}
+
+impl CloudReport {
+    // 主機換成假名；需在換成假名之前評估，特定主機的註記 (中繼資料服務) 才比對得到
+    pub fn anonymize(&mut self, anonymizer: &Anonymizer) {
+        for annotation in &mut self.annotations {
+            annotation.host = anonymizer.ip(annotation.host);
+        }
+    }
+}
+
+// 依結果找出適用的平台註記，依主機、端口排序
+pub fn evaluate(context: CloudContext, results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>) -> CloudReport {
+    let mut annotations = Vec::new();
+    for (host, ports) in results {
+        for (port, result) in ports.iter().filter(|(_, r)| r.error.is_none()) {
+            for note in context.notes(*host, port.port, result) {
+                let render = |text: &str| text.replace("{port}", &port.port.to_string()).replace("{service}", &port.service);
+                annotations.push(Annotation {
+                    provider: note.provider,
+                    side: note.side,
+                    kind: note.kind,
+                    host: *host,
+                    port: port.port,
+                    service: port.service.clone(),
+                    message: note.message.to_string(),
+                    advice: note.advice.map(|(severity, text)| (severity, render(text))),
+                });
+            }
+        }
+    }
+    annotations.sort_by(|a, b| a.host.cmp(&b.host).then(a.port.cmp(&b.port)));
+    CloudReport { context, annotations }
+}
+
+// 有平台建議的端口改用平台建議，取代通用的建議
+pub fn adjust(recommendations: &mut Vec<Recommendation>, report: &CloudReport) {
+    let advised: Vec<(&Annotation, Severity, &String)> =
+        report.annotations.iter().filter_map(|a| a.advice.as_ref().map(|(severity, text)| (a, *severity, text))).collect();
+    let replaced: HashSet<(IpAddr, u16)> = advised.iter().map(|(a, ..)| (a.host, a.port)).collect();
+    recommendations.retain(|r| !replaced.contains(&(r.host, r.port)));
+    recommendations.extend(advised.into_iter().map(|(annotation, severity, message)| Recommendation {
+        severity,
+        host: annotation.host,
+        port: annotation.port,
+        service: annotation.service.clone(),
+        message: message.clone(),
+    }));
+}
+
+// 摘要中的雲端區段；多目標時標示主機
+pub fn display(report: &CloudReport, show_host: bool) {
+    if report.context.is_empty() {
+        return;
+    }
+    println!("\n{}", "=== 雲端平台 ===".bold());
+    if let Some(provider) = report.context.target {
+        println!("目標 ({}): {}", provider.label(), provider.default_inbound());
+    }
+    if let Some(provider) = report.context.scanner {
+        println!("掃描端 ({}): {}", provider.label(), provider.metadata_reminder());
+    }
+    for annotation in &report.annotations {
+        let host = if show_host { format!("{} ", annotation.host) } else { String::new() };
+        let line = format!("Port {:5} ({}) {}", annotation.port, annotation.kind.label(), annotation.message);
+        println!("  {}{}", host.dimmed(), line.blue());
+    }
+}
+
+// 以中繼資料服務偵測掃描端所在的平台；三個平台同時查詢，都沒有回應時為 None
+// 中繼資料服務只能直接連線，不經過代理
+pub async fn detect() -> Option<Provider> {
+    let client = reqwest::Client::builder().timeout(DETECT_TIMEOUT).no_proxy().build().ok()?;
+    let base = format!("http://{}", METADATA_IP);
+    let aws = client
+        .put(format!("{}/latest/api/token", base))
+        .header("X-aws-ec2-metadata-token-ttl-seconds", "60")
+        .send();
+    let gcp = client.get(format!("{}/computeMetadata/v1/", base)).header("Metadata-Flavor", "Google").send();
+    let azure = client.get(format!("{}/metadata/instance?api-version=2021-02-01", base)).header("Metadata", "true").send();
+    let (aws, gcp, azure) = tokio::join!(aws, gcp, azure);
+    // GCP 的回應帶有 Metadata-Flavor: Google 標頭，避免把其他平台的 404 當成 GCP
+    let is_gcp = gcp.is_ok_and(|r| r.status().is_success() && r.headers().get("Metadata-Flavor").is_some_and(|v| v == "Google"));
+    match (aws.is_ok_and(|r| r.status().is_success()), is_gcp, azure.is_ok_and(|r| r.status().is_success())) {
+        (_, true, _) => Some(Provider::Gcp),
+        (_, _, true) => Some(Provider::Azure),
+        (true, ..) => Some(Provider::Aws),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::ScanError;
+    use crate::testutil::{host, scan_result};
+
+    const METADATA: IpAddr = IpAddr::V4(METADATA_IP);
+
+    fn context(target: Option<Provider>, scanner: Option<Provider>) -> CloudContext {
+        CloudContext { target, scanner }
+    }
+
+    fn results(entries: &[(IpAddr, u16, &str, bool)]) -> BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>> {
+        let mut results: BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>> = BTreeMap::new();
+        for &(addr, port, service, outbound) in entries {
+            results.entry(addr).or_default().insert(PortInfo::new(port, service, "Test"), scan_result(outbound));
+        }
+        results
+    }
+
+    fn annotated(report: &CloudReport) -> Vec<(IpAddr, u16, Provider, NoteKind)> {
+        report.annotations.iter().map(|a| (a.host, a.port, a.provider, a.kind)).collect()
+    }
+
+    #[test]
+    fn knowledge_table_is_well_formed() {
+        for note in NOTES {
+            assert!(!note.ports.is_empty() && !note.message.contains('\n'), "{}", note.message);
+            // 平台封鎖只對無法連線的結果、中繼資料服務只對特定主機
+            assert_eq!(note.kind == NoteKind::ProviderBlocked, !note.reachable, "{}", note.message);
+            assert_eq!(note.kind == NoteKind::MetadataService, note.host.is_some(), "{}", note.message);
+            assert_eq!(note.side == Side::Target, note.kind == NoteKind::DefaultRule, "{}", note.message);
+            if let Some((_, advice)) = note.advice {
+                let rest = advice.replace("{port}", "").replace("{service}", "");
+                assert!(advice.contains("{port}") && !rest.contains('{') && !rest.contains('\n'), "{}", advice);
+            }
+        }
+    }
+
+    #[test]
+    fn scanner_notes_attach_to_blocked_smtp_and_the_metadata_service() {
+        let scanned = results(&[
+            (host(1), 25, "SMTP", false),
+            (host(1), 587, "Submission", false),
+            (host(2), 25, "SMTP", true),
+            (host(2), 80, "HTTP", true),
+            (METADATA, 80, "HTTP", true),
+        ]);
+        let report = evaluate(context(None, Some(Provider::Aws)), &scanned);
+        assert_eq!(annotated(&report), vec![
+            (METADATA, 80, Provider::Aws, NoteKind::MetadataService),
+            (host(1), 25, Provider::Aws, NoteKind::ProviderBlocked),
+        ]);
+
+        // GCP 與 Azure 各自的表；只有 Azure 有 WireServer
+        let wireserver = IpAddr::V4(AZURE_WIRESERVER);
+        let scanned = results(&[(METADATA, 80, "HTTP", true), (wireserver, 32526, "WireServer", true), (wireserver, 80, "HTTP", false)]);
+        assert_eq!(annotated(&evaluate(context(None, Some(Provider::Gcp)), &scanned)), vec![(METADATA, 80, Provider::Gcp, NoteKind::MetadataService)]);
+        assert_eq!(annotated(&evaluate(context(None, Some(Provider::Azure)), &scanned)), vec![
+            (wireserver, 32526, Provider::Azure, NoteKind::MetadataService),
+            (METADATA, 80, Provider::Azure, NoteKind::MetadataService),
+        ]);
+    }
+
+    #[test]
+    fn target_notes_attach_to_management_ports_that_answer() {
+        let scanned = results(&[(host(1), 22, "SSH", true), (host(1), 3389, "RDP", true), (host(2), 22, "SSH", false), (host(2), 443, "HTTPS", true)]);
+        let gcp = evaluate(context(Some(Provider::Gcp), None), &scanned);
+        assert_eq!(annotated(&gcp), vec![(host(1), 22, Provider::Gcp, NoteKind::DefaultRule), (host(1), 3389, Provider::Gcp, NoteKind::DefaultRule)]);
+        assert!(gcp.annotations[0].message.contains("default-allow-ssh") && gcp.annotations[1].message.contains("default-allow-rdp"));
+
+        // 目標平台的註記不適用掃描端，反之亦然
+        assert!(evaluate(context(None, Some(Provider::Gcp)), &scanned).annotations.is_empty());
+        let blocked = results(&[(host(1), 25, "SMTP", false)]);
+        assert!(evaluate(context(Some(Provider::Aws), None), &blocked).annotations.is_empty());
+        assert!(evaluate(CloudContext::default(), &scanned).annotations.is_empty());
+    }
+
+    #[test]
+    fn failed_probes_are_not_annotated() {
+        let mut scanned = results(&[(host(1), 25, "SMTP", false)]);
+        scanned.get_mut(&host(1)).unwrap().values_mut().for_each(|result| result.error = Some(ScanError::TooManyOpenFiles));
+        assert!(evaluate(context(None, Some(Provider::Aws)), &scanned).annotations.is_empty());
+    }
+
+    #[test]
+    fn provider_advice_replaces_generic_recommendations() {
+        let scanned = results(&[(host(1), 22, "SSH", true), (host(1), 443, "HTTPS", true)]);
+        let report = evaluate(context(Some(Provider::Azure), None), &scanned);
+        let generic = |port: u16, message: &str| Recommendation {
+            severity: Severity::Medium,
+            host: host(1),
+            port,
+            service: "Test".to_string(),
+            message: message.to_string(),
+        };
+        let mut recommendations = vec![generic(22, "通用 SSH 建議"), generic(443, "通用 HTTPS 建議")];
+        adjust(&mut recommendations, &report);
+        let summary: Vec<_> = recommendations.iter().map(|r| (r.port, r.severity)).collect();
+        assert_eq!(summary, vec![(443, Severity::Medium), (22, Severity::High)]);
+        assert!(recommendations[1].message.starts_with("Port 22 (SSH) 對外開放") && recommendations[1].message.contains("Azure Bastion"));
+        assert_eq!(recommendations[1].service, "SSH");
+    }
+
+    #[test]
+    fn port_lines_mark_only_host_independent_notes() {
+        let aws = context(Some(Provider::Aws), Some(Provider::Aws));
+        assert!(mark(&aws, 22, &scan_result(true)).unwrap().contains("☁ AWS: 預設規則"));
+        assert!(mark(&aws, 25, &scan_result(false)).unwrap().contains("☁ AWS: 平台封鎖"));
+        assert!(mark(&aws, 25, &scan_result(true)).is_none());
+        // 中繼資料服務的註記只在雲端區段列出
+        assert!(mark(&aws, 80, &scan_result(true)).is_none());
+        assert!(mark(&CloudContext::default(), 22, &scan_result(true)).is_none());
+    }
+
+    #[test]
+    fn anonymized_reports_keep_annotations_but_not_hosts() {
+        let scanned = results(&[(METADATA, 80, "HTTP", true)]);
+        let mut report = evaluate(context(None, Some(Provider::Aws)), &scanned);
+        report.anonymize(&Anonymizer::new([7; 32]));
+        assert_eq!(report.annotations.len(), 1);
+        assert_ne!(report.annotations[0].host, METADATA);
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["scanner"], "aws");
+        assert!(json.get("target").is_none());
+        assert_eq!(json["annotations"][0]["kind"], "metadata_service");
+        assert!(json["annotations"][0].get("advice").is_none());
+    }
+}
@@ -0,0 +1,340 @@
+use std::error::Error;
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use colored::*;
+use serde::Serialize;
+use tokio::net::TcpSocket;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use crate::output::csv_field;
+use crate::scanner::ScanPlan;
+use crate::PortInfo;
+
+// 每條路徑每個端口預設的連線次數
+pub const DEFAULT_SAMPLES: usize = 3;
+
+// 比較的一條路徑：綁定來源位址或網路介面 (SO_BINDTODEVICE，僅 Linux)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Addr(IpAddr),
+    Interface(String),
+}
+
+impl Source {
+    pub fn label(&self) -> String {
+        match self {
+            Source::Addr(addr) => addr.to_string(),
+            Source::Interface(name) => name.clone(),
+        }
+    }
+}
+
+// --compare-source 的兩條路徑
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourcePair(pub Source, pub Source);
+
+fn parse_source(s: &str) -> Result<Source, String> {
+    let s = s.trim();
+    if let Ok(addr) = s.parse::<IpAddr>() {
+        return Ok(Source::Addr(addr));
+    }
+    platform::validate(s)?;
+    Ok(Source::Interface(s.to_string()))
+}
+
+// 解析 "A,B"，A 與 B 為 IP 位址或網路介面名稱
+pub fn parse_sources(s: &str) -> Result<SourcePair, String> {
+    let parts: Vec<&str> = s.split(',').collect();
+    let [a, b] = parts[..] else {
+        return Err(format!("--compare-source 需要兩個來源，以逗號分隔: {}", s));
+    };
+    let pair = SourcePair(parse_source(a)?, parse_source(b)?);
+    if pair.0 == pair.1 {
+        return Err(format!("--compare-source 的兩個來源相同: {}", s));
+    }
+    Ok(pair)
+}
+
+// 一條路徑對一個端口的多次量測
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PathSample {
+    pub samples: usize,
+    pub reachable: usize,
+    // 成功連線的延遲中位數、最小與最大值
+    pub latency_ms: Option<f64>,
+    pub min_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+    // 無法從此來源連線的原因 (例如來源位址不存在、位址類型不同)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl PathSample {
+    fn from_latencies(samples: usize, mut latencies: Vec<f64>, error: Option<String>) -> Self {
+        latencies.sort_by(f64::total_cmp);
+        let middle = latencies.len() / 2;
+        let median = match latencies.len() {
+            0 => None,
+            n if n % 2 == 0 => Some((latencies[middle - 1] + latencies[middle]) / 2.0),
+            _ => Some(latencies[middle]),
+        };
+        PathSample {
+            samples,
+            reachable: latencies.len(),
+            latency_ms: median,
+            min_ms: latencies.first().copied(),
+            max_ms: latencies.last().copied(),
+            error,
+        }
+    }
+
+    // 例如 "12.3ms (3/3)"
+    fn describe(&self) -> String {
+        match (&self.error, self.latency_ms) {
+            (Some(error), _) => error.clone(),
+            (None, Some(latency)) => format!("{:.1}ms ({}/{})", latency, self.reachable, self.samples),
+            (None, None) => format!("無法連線 (0/{})", self.samples),
+        }
+    }
+}
+
+// 同一端口經由兩條路徑的結果
+#[derive(Debug, Clone, Serialize)]
+pub struct PairedResult {
+    pub host: IpAddr,
+    #[serde(flatten)]
+    pub port: PortInfo,
+    pub a: PathSample,
+    pub b: PathSample,
+    // B 的延遲中位數減去 A 的；正值代表 B 較慢
+    pub delta_ms: Option<f64>,
+    // 可連線與否不同，或延遲差距超過門檻
+    pub flagged: bool,
+}
+
+// 兩條路徑的比較
+#[derive(Debug, Clone, Serialize)]
+pub struct PathComparison {
+    pub source_a: String,
+    pub source_b: String,
+    pub samples: usize,
+    pub threshold_ms: f64,
+    pub results: Vec<PairedResult>,
+}
+
+impl PathComparison {
+    pub fn flagged(&self) -> usize {
+        self.results.iter().filter(|r| r.flagged).count()
+    }
+}
+
+fn pair(host: IpAddr, port: PortInfo, a: PathSample, b: PathSample, threshold: Duration) -> PairedResult {
+    let delta_ms = a.latency_ms.zip(b.latency_ms).map(|(a, b)| b - a);
+    let flagged = (a.reachable > 0) != (b.reachable > 0)
+        || delta_ms.is_some_and(|delta| delta.abs() > threshold.as_secs_f64() * 1000.0);
+    PairedResult { host, port, a, b, delta_ms, flagged }
+}
+
+// 從指定來源連線一次；回傳成功時的延遲，來源無法使用時回傳錯誤
+async fn connect_from(source: &Source, dest: SocketAddr, limit: Duration) -> Result<Option<Duration>, String> {
+    let socket = match dest {
+        SocketAddr::V4(_) => TcpSocket::new_v4(),
+        SocketAddr::V6(_) => TcpSocket::new_v6(),
+    }
+    .map_err(|e| e.to_string())?;
+    match source {
+        Source::Addr(addr) => {
+            if addr.is_ipv4() != dest.is_ipv4() {
+                return Err("來源與目標的位址類型不同".to_string());
+            }
+            socket
+                .bind(SocketAddr::new(*addr, 0))
+                .map_err(|e| format!("無法綁定 {}: {}", addr, e))?;
+        }
+        Source::Interface(name) => platform::bind_device(&socket, name)?,
+    }
+    let started = Instant::now();
+    Ok(match tokio::time::timeout(limit, socket.connect(dest)).await {
+        Ok(Ok(_)) => Some(started.elapsed()),
+        _ => None,
+    })
+}
+
+// 一個端口的量測：兩條路徑交替連線，減少時間先後造成的偏差
+async fn measure(sources: &SourcePair, dest: SocketAddr, limit: Duration, samples: usize) -> (PathSample, PathSample) {
+    let mut latencies = [Vec::new(), Vec::new()];
+    let mut errors: [Option<String>; 2] = [None, None];
+    for _ in 0..samples {
+        for (index, source) in [&sources.0, &sources.1].into_iter().enumerate() {
+            if errors[index].is_some() {
+                continue;
+            }
+            match connect_from(source, dest, limit).await {
+                Ok(Some(latency)) => latencies[index].push(latency.as_secs_f64() * 1000.0),
+                Ok(None) => {}
+                Err(e) => errors[index] = Some(e),
+            }
+        }
+    }
+    let [a, b] = latencies;
+    let [error_a, error_b] = errors;
+    (
+        PathSample::from_latencies(samples, a, error_a),
+        PathSample::from_latencies(samples, b, error_b),
+    )
+}
+
+// 對計劃中的每個主機與端口比較兩條路徑
+pub async fn run(plan: &ScanPlan, sources: &SourcePair, samples: usize, threshold: Duration) -> PathComparison {
+    let semaphore = Arc::new(Semaphore::new(plan.concurrency.max(1)));
+    let sources = Arc::new(sources.clone());
+    let mut tasks = JoinSet::new();
+    for target in &plan.targets {
+        for host in target.addrs() {
+            for port in &plan.ports {
+                let permit = semaphore.clone().acquire_owned().await.expect("semaphore closed");
+                let sources = sources.clone();
+                let port = port.clone();
+                let limit = plan.timeouts.for_port(&port);
+                let dest = plan.context.socket_addr(host, port.port);
+                tasks.spawn(async move {
+                    let (a, b) = measure(&sources, dest, limit, samples).await;
+                    drop(permit);
+                    pair(host, port, a, b, threshold)
+                });
+            }
+        }
+    }
+    let mut results = Vec::new();
+    while let Some(Ok(result)) = tasks.join_next().await {
+        results.push(result);
+    }
+    results.sort_by_key(|r| (r.host, r.port.port));
+    PathComparison {
+        source_a: sources.0.label(),
+        source_b: sources.1.label(),
+        samples,
+        threshold_ms: threshold.as_secs_f64() * 1000.0,
+        results,
+    }
+}
+
+// 並列顯示兩條路徑的延遲與可連線狀態，超過門檻的差距以顏色標示
+pub fn display(comparison: &PathComparison) {
+    println!("\n{}", "=== 路徑比較 ===".bold());
+    println!(
+        "A: {}  B: {}  (每條路徑各連線 {} 次，差距超過 {:.0}ms 時標示)",
+        comparison.source_a, comparison.source_b, comparison.samples, comparison.threshold_ms
+    );
+    let header = format!("{:>15} {:>5}  {:12} {:22} {:22} {}", "主機", "端口", "服務", "A", "B", "差距 (B-A)");
+    println!("{}", header.bold());
+    for result in &comparison.results {
+        let delta = match result.delta_ms {
+            Some(delta) => format!("{:+.1}ms", delta),
+            None if result.flagged => "可連線狀態不同".to_string(),
+            None => "-".to_string(),
+        };
+        let delta = match (result.flagged, result.delta_ms) {
+            (false, _) => delta.normal(),
+            (true, Some(d)) if d < 0.0 => delta.green(),
+            (true, _) => delta.red(),
+        };
+        println!(
+            "{:>15} {:>5}  {:12} {:22} {:22} {}",
+            result.host.to_string(),
+            result.port.port,
+            result.port.service,
+            result.a.describe(),
+            result.b.describe(),
+            delta
+        );
+    }
+    match comparison.flagged() {
+        0 => println!("{}", "兩條路徑沒有明顯差異".green()),
+        n => println!("{}", format!("{} 個端口的兩條路徑有明顯差異", n).yellow()),
+    }
+}
+
+fn optional(value: Option<f64>) -> String {
+    value.map(|v| format!("{:.3}", v)).unwrap_or_default()
+}
+
+pub fn to_csv(comparison: &PathComparison) -> String {
+    let mut out = String::from(
+        "host,port,service,source_a,a_reachable,a_samples,a_latency_ms,a_min_ms,a_max_ms,a_error,\
+         source_b,b_reachable,b_samples,b_latency_ms,b_min_ms,b_max_ms,b_error,delta_ms,flagged\n",
+    );
+    for r in &comparison.results {
+        let path = |source: &str, sample: &PathSample| {
+            [
+                csv_field(source),
+                sample.reachable.to_string(),
+                sample.samples.to_string(),
+                optional(sample.latency_ms),
+                optional(sample.min_ms),
+                optional(sample.max_ms),
+                csv_field(sample.error.as_deref().unwrap_or("")),
+            ]
+            .join(",")
+        };
+        out.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            r.host,
+            r.port.port,
+            csv_field(&r.port.service),
+            path(&comparison.source_a, &r.a),
+            path(&comparison.source_b, &r.b),
+            optional(r.delta_ms),
+            r.flagged
+        ));
+    }
+    out
+}
+
+// --compare-output：依副檔名寫成 .csv 或 .json
+pub fn write(comparison: &PathComparison, path: &Path) -> Result<(), Box<dyn Error>> {
+    let content = match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+        Some("csv") => to_csv(comparison),
+        Some("json") => serde_json::to_string_pretty(comparison)?,
+        _ => return Err("無法從副檔名判斷格式 (可用 .csv、.json)".into()),
+    };
+    fs::write(path, content).map_err(|e| format!("無法寫入 {}: {}", path.display(), e))?;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::ffi::CString;
+    use tokio::net::TcpSocket;
+
+    pub fn validate(name: &str) -> Result<(), String> {
+        let index = CString::new(name).map(|c| unsafe { libc::if_nametoindex(c.as_ptr()) }).unwrap_or(0);
+        match index {
+            0 => Err(format!("'{}' 不是 IP 位址，也找不到此網路介面", name)),
+            _ => Ok(()),
+        }
+    }
+
+    // SO_BINDTODEVICE；需要 CAP_NET_RAW，否則回傳權限錯誤
+    pub fn bind_device(socket: &TcpSocket, name: &str) -> Result<(), String> {
+        socket
+            .bind_device(Some(name.as_bytes()))
+            .map_err(|e| format!("無法綁定介面 {}: {}", name, e))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use tokio::net::TcpSocket;
+
+    pub fn validate(name: &str) -> Result<(), String> {
+        Err(format!("'{}' 不是 IP 位址；以網路介面指定來源只支援 Linux", name))
+    }
+
+    pub fn bind_device(_socket: &TcpSocket, name: &str) -> Result<(), String> {
+        Err(format!("無法綁定介面 {}: 只支援 Linux", name))
+    }
+}
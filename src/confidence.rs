@@ -0,0 +1,356 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use colored::*;
+use tokio::sync::Semaphore;
+use crate::closure::Failure;
+use crate::errors;
+use crate::grade;
+use crate::scanner::ScanPlan;
+use crate::syn::SynState;
+use crate::verify::{Verification, VerifyConfig};
+use crate::{PortInfo, ScanResult};
+
+// 低於此分數的結果加上 "?" 並以暗色顯示
+pub const LOW_CONFIDENCE: f64 = 0.75;
+
+// --min-confidence 最多重新探測的輪數
+const MAX_ROUNDS: u32 = 3;
+
+// 失敗或成功所花時間達逾時的此比例，視為在期限邊緣
+const NEAR_DEADLINE: f64 = 0.9;
+
+// 單一探測的基本分數
+// 完成握手或收到 SYN-ACK / RST 是明確的回應；逾時可能是防火牆丟棄，也可能只是太慢
+const OPEN: f64 = 0.95;
+const REFUSED: f64 = 0.9;
+const SLOW: f64 = 0.7;
+const ICMP: f64 = 0.85;
+const UNREACHABLE: f64 = 0.6;
+const SILENT: f64 = 0.5;
+
+// 計算分數所需的證據；只依這些欄位計算，相同的證據一定得到相同的分數
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Evidence {
+    pub outbound: bool,
+    pub latency_ms: Option<f64>,
+    pub failure: Option<Failure>,
+    pub syn: Option<SynState>,
+    // 收到 ICMP 錯誤
+    pub icmp: bool,
+    // 掃描端錯誤
+    pub error: bool,
+    // 與結果相同的額外探測次數 (覆核與 --min-confidence 的重新探測)
+    pub confirmations: u8,
+    // 該端口的逾時
+    pub timeout: Duration,
+}
+
+impl Evidence {
+    pub fn of(result: &ScanResult, timeout: Duration) -> Self {
+        Evidence {
+            outbound: result.outbound,
+            latency_ms: result.latency_ms,
+            failure: result.failure,
+            syn: result.syn,
+            icmp: result.icmp.is_some(),
+            error: result.error.is_some(),
+            confirmations: result.confirmations,
+            timeout,
+        }
+    }
+}
+
+// 信心分數 0-1：
+// 1. 掃描端錯誤為 0，不代表端口狀態
+// 2. 基本分數：SYN-ACK、SYN 收到 RST 或完整連線成功 0.95；連線被拒 (RST) 0.9；
+//    有 ICMP 錯誤的失敗 0.85；沒有 ICMP 的不可達 0.6；逾時沒有回應 (含 SYN 被過濾) 0.5
+// 3. 成功或被拒花了逾時 90% 以上的時間時改為 0.7，較短的逾時可能得到不同結果
+// 4. 每次結果相同的額外探測把剩下的不確定性減半：c = 1 - (1 - c) / 2
+// 結果四捨五入到小數點後兩位
+pub fn score(evidence: &Evidence) -> f64 {
+    if evidence.error {
+        return 0.0;
+    }
+    let limit_ms = evidence.timeout.as_secs_f64() * 1000.0 * NEAR_DEADLINE;
+    let near_deadline = |latency_ms: f64| limit_ms > 0.0 && latency_ms >= limit_ms;
+    let base = match (evidence.syn, evidence.outbound, evidence.failure) {
+        (Some(SynState::Open | SynState::Closed), _, _) => OPEN,
+        (_, true, _) if evidence.latency_ms.is_some_and(near_deadline) => SLOW,
+        (_, true, _) => OPEN,
+        (_, false, Some(Failure::Reset { latency_ms })) if near_deadline(latency_ms) => SLOW,
+        (_, false, Some(Failure::Reset { .. })) => REFUSED,
+        _ if evidence.icmp => ICMP,
+        (_, false, Some(Failure::Unreachable)) => UNREACHABLE,
+        _ => SILENT,
+    };
+    let uncertainty = (1.0 - base) / 2f64.powi(evidence.confirmations as i32);
+    ((1.0 - uncertainty) * 100.0).round() / 100.0
+}
+
+// serde：沒有額外探測時不輸出 confirmations
+pub fn unconfirmed(confirmations: &u8) -> bool {
+    *confirmations == 0
+}
+
+// 重新計算所有結果的分數
+pub fn assess(plan: &ScanPlan, results: &mut BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>) {
+    for host_results in results.values_mut() {
+        for (port, result) in host_results.iter_mut() {
+            result.confidence = Some(score(&Evidence::of(result, plan.timeouts.for_port(port))));
+        }
+    }
+}
+
+// --min-confidence 重新探測的統計
+#[derive(Debug, Default)]
+pub struct RefineSummary {
+    pub threshold: f64,
+    pub rounds: u32,
+    pub reprobed: usize,
+    pub changed: usize,
+    // 重新探測後仍低於門檻的結果
+    pub remaining: usize,
+}
+
+// 重新探測分數低於門檻的結果，直到全部達到門檻或達到輪數上限
+// 結果相同時增加確認次數，不同時改用新的結果並重新累計；經由代理時不重新探測
+pub async fn refine(
+    plan: &ScanPlan,
+    config: &VerifyConfig,
+    threshold: f64,
+    results: &mut BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>,
+) -> RefineSummary {
+    let mut summary = RefineSummary { threshold, ..Default::default() };
+    assess(plan, results);
+    let below = |results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>| -> Vec<(IpAddr, PortInfo)> {
+        results
+            .iter()
+            .flat_map(|(host, ports)| {
+                ports
+                    .iter()
+                    .filter(|(_, result)| result.confidence.is_some_and(|c| c < threshold))
+                    .map(|(port, _)| (*host, port.clone()))
+            })
+            .collect()
+    };
+    if plan.proxy.is_some() {
+        summary.remaining = below(results).len();
+        return summary;
+    }
+
+    let factor = config.timeout_factor.max(1.0);
+    let semaphore = Arc::new(Semaphore::new(plan.concurrency.max(1)));
+    while summary.rounds < MAX_ROUNDS {
+        let candidates = below(results);
+        if candidates.is_empty() {
+            break;
+        }
+        summary.rounds += 1;
+        let handles: Vec<_> = candidates
+            .into_iter()
+            .map(|(host, port)| {
+                let limit = plan.timeouts.for_port(&port).mul_f64(factor);
+                let (semaphore, prober, source) = (semaphore.clone(), plan.prober.clone(), config.source);
+                let dest = plan.context.socket_addr(host, port.port);
+                tokio::spawn(async move {
+                    let _permit = semaphore.acquire_owned().await;
+                    let outcome = prober.reprobe(dest, limit, source).await;
+                    (host, port, outcome)
+                })
+            })
+            .collect();
+        for handle in handles {
+            let Ok((host, port, (connected, latency_ms, failure, error))) = handle.await else {
+                continue;
+            };
+            let Some(result) = results.get_mut(&host).and_then(|ports| ports.get_mut(&port)) else {
+                continue;
+            };
+            summary.reprobed += 1;
+            // 重新探測本身發生掃描端錯誤時沒有新的證據
+            if error.is_some() {
+                continue;
+            }
+            if connected == result.outbound && result.error.is_none() {
+                result.confirmations = result.confirmations.saturating_add(1);
+                continue;
+            }
+            summary.changed += 1;
+            result.outbound = connected;
+            result.latency_ms = latency_ms;
+            result.failure = failure;
+            result.error = None;
+            result.icmp = None;
+            errors::reprobed(&mut result.codes, failure);
+            result.syn = None;
+            result.confirmations = 0;
+            result.verification = Some(Verification::Changed);
+            result.grade = grade::grade_result(result, None, &plan.grading);
+        }
+        assess(plan, results);
+    }
+    summary.remaining = below(results).len();
+    summary
+}
+
+// 結果前的重新探測說明
+pub fn display_summary(summary: &RefineSummary) {
+    if summary.reprobed == 0 && summary.remaining == 0 {
+        return;
+    }
+    println!(
+        "{} 信心分數低於 {:.2} 的結果重新探測 {} 次 ({} 輪)，{} 個結果改變",
+        "信心:".bold(),
+        summary.threshold,
+        summary.reprobed,
+        summary.rounds,
+        summary.changed
+    );
+    if summary.remaining > 0 {
+        println!("{}", format!("  仍有 {} 個結果低於門檻，以 ? 標示", summary.remaining).yellow());
+    }
+}
+
+// 低信心的狀態標籤加上 "?" 並以暗色顯示
+pub fn mark(label: ColoredString, confidence: Option<f64>, threshold: f64) -> ColoredString {
+    match confidence {
+        Some(c) if c < threshold => format!("{}?", &*label).dimmed(),
+        _ => label,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prober::fake::{Script, Scripted, ScriptedProber};
+    use crate::testutil::{by_host, host, scan, scripted_plan};
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn evidence(outbound: bool, latency_ms: Option<f64>, failure: Option<Failure>) -> Evidence {
+        Evidence {
+            outbound,
+            latency_ms,
+            failure,
+            syn: None,
+            icmp: false,
+            error: false,
+            confirmations: 0,
+            timeout: SECOND,
+        }
+    }
+
+    #[test]
+    fn scores_follow_the_documented_rules() {
+        let open = evidence(true, Some(20.0), None);
+        let refused = evidence(false, None, Some(Failure::Reset { latency_ms: 3.0 }));
+        let silent = evidence(false, None, Some(Failure::Timeout));
+        let unreachable = evidence(false, None, Some(Failure::Unreachable));
+        let cases = [
+            (open, 0.95),
+            (Evidence { error: true, ..open }, 0.0),
+            // 逾時邊緣的成功與被拒
+            (evidence(true, Some(899.0), None), 0.95),
+            (evidence(true, Some(900.0), None), 0.7),
+            (evidence(false, None, Some(Failure::Reset { latency_ms: 950.0 })), 0.7),
+            (refused, 0.9),
+            (unreachable, 0.6),
+            (Evidence { icmp: true, ..unreachable }, 0.85),
+            (Evidence { icmp: true, ..silent }, 0.85),
+            (silent, 0.5),
+            (evidence(false, None, None), 0.5),
+            // SYN 掃描的明確回應優先於連線結果
+            (Evidence { syn: Some(SynState::Open), ..silent }, 0.95),
+            (Evidence { syn: Some(SynState::Closed), ..silent }, 0.95),
+            (Evidence { syn: Some(SynState::Filtered), ..silent }, 0.5),
+            (Evidence { syn: Some(SynState::Filtered), ..evidence(true, Some(950.0), None) }, 0.7),
+            // 每次相同的額外探測把不確定性減半
+            (Evidence { confirmations: 1, ..silent }, 0.75),
+            (Evidence { confirmations: 2, ..silent }, 0.88),
+            (Evidence { confirmations: 3, ..unreachable }, 0.95),
+            (Evidence { confirmations: 1, ..open }, 0.98),
+            // 沒有逾時時不判斷期限邊緣
+            (Evidence { timeout: Duration::ZERO, ..evidence(true, Some(5000.0), None) }, 0.95),
+        ];
+        for (evidence, expected) in cases {
+            assert_eq!(score(&evidence), expected, "{:?}", evidence);
+            // 相同的證據得到相同的分數
+            assert_eq!(score(&evidence), score(&evidence.clone()));
+        }
+    }
+
+    #[test]
+    fn low_confidence_labels_are_marked() {
+        assert_eq!(&*mark("開放".normal(), Some(0.5), 0.75), "開放?");
+        assert_eq!(&*mark("開放".normal(), Some(0.75), 0.75), "開放");
+        assert_eq!(&*mark("開放".normal(), None, 0.75), "開放");
+        assert!(unconfirmed(&0) && !unconfirmed(&1));
+    }
+
+    fn confidence(results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>, port: u16) -> (Option<f64>, u8, bool) {
+        let result = results[&host(1)].iter().find(|(info, _)| info.port == port).map(|(_, r)| r).unwrap();
+        (result.confidence, result.confirmations, result.outbound)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn low_scores_are_reprobed_until_they_reach_the_threshold() {
+        let target = host(1);
+        let prober = Arc::new(
+            ScriptedProber::new()
+                .with(target, 22, Script::new(Scripted::Open, Duration::from_millis(10)))
+                .with(target, 25, Script::new(Scripted::Silent, Duration::ZERO))
+                .with(target, 80, Script::new(Scripted::Unreachable, Duration::from_millis(1))),
+        );
+        let plan = scripted_plan(&[target], &[22, 25, 80], 4, prober.clone());
+        let mut results = by_host(scan(&plan).await);
+        let summary = refine(&plan, &VerifyConfig::default(), 0.8, &mut results).await;
+
+        // 25: 0.5 -> 0.75 -> 0.88；80: 0.6 -> 0.8，各需要的輪數不同
+        assert_eq!(confidence(&results, 22), (Some(0.95), 0, true));
+        assert_eq!(confidence(&results, 25), (Some(0.88), 2, false));
+        assert_eq!(confidence(&results, 80), (Some(0.8), 1, false));
+        assert_eq!((summary.rounds, summary.reprobed, summary.changed, summary.remaining), (2, 3, 0, 0));
+        let mut calls: Vec<u16> = prober.calls().into_iter().map(|(_, port)| port).collect();
+        calls.sort_unstable();
+        assert_eq!(calls, vec![22, 25, 25, 25, 80, 80]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn changed_results_start_over_and_rounds_are_capped() {
+        let target = host(1);
+        let first = Arc::new(ScriptedProber::new().fallback(Script::new(Scripted::Silent, Duration::ZERO)));
+        let plan = scripted_plan(&[target], &[25, 80], 2, first);
+        let mut results = by_host(scan(&plan).await);
+
+        // 重新探測時 25 可連線 (結果改變)，80 一直沒有回應
+        let second = ScriptedProber::new()
+            .with(target, 25, Script::new(Scripted::Open, Duration::from_millis(10)))
+            .fallback(Script::new(Scripted::Silent, Duration::ZERO));
+        let plan = ScanPlan { prober: Arc::new(second), ..plan };
+        let summary = refine(&plan, &VerifyConfig::default(), 0.99, &mut results).await;
+
+        assert_eq!(summary.rounds, MAX_ROUNDS);
+        assert_eq!(summary.changed, 1);
+        assert_eq!(summary.remaining, 1);
+        let (score, confirmations, outbound) = confidence(&results, 25);
+        assert!(outbound);
+        // 改變之後重新累計確認次數
+        assert_eq!((score, confirmations), (Some(0.99), 2));
+        assert_eq!(confidence(&results, 80), (Some(0.94), 3, false));
+        let changed = results[&target].iter().find(|(info, _)| info.port == 25).unwrap().1;
+        assert_eq!(changed.verification, Some(Verification::Changed));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn proxied_scans_only_count_what_remains() {
+        let prober = Arc::new(ScriptedProber::new().fallback(Script::new(Scripted::Silent, Duration::ZERO)));
+        let plan = scripted_plan(&[host(1)], &[25], 1, prober.clone());
+        let mut results = by_host(scan(&plan).await);
+        let plan = ScanPlan { proxy: Some("127.0.0.1:9050".parse().unwrap()), ..plan };
+        let summary = refine(&plan, &VerifyConfig::default(), 0.8, &mut results).await;
+        assert_eq!((summary.rounds, summary.reprobed, summary.remaining), (0, 0, 1));
+        assert_eq!(prober.calls().len(), 1);
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use colored::*;
+use serde::{Deserialize, Deserializer};
+use crate::alerts::AlertRule;
+use crate::checks::health::HealthSpec;
+use crate::bundles::BundleDefinition;
+use crate::dns::DnsConfig;
+use crate::grade::GradingConfig;
+use crate::dependency::DependencyRule;
+use crate::groups::GroupDefinition;
+use crate::migrate;
+use crate::notify::EmailConfig;
+use crate::pager::PagerConfig;
+use crate::quick::QuickConfig;
+use crate::recommend::RecommendationRule;
+use crate::sanity::SanityConfig;
+use crate::captive::CaptiveConfig;
+use crate::tags::PortOverride;
+use crate::targets::SafetyConfig;
+use crate::threats::ThreatEntry;
+use crate::verify::VerifyConfig;
+
+// 設定檔內容
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    // default / 類別名稱 / 端口號碼 -> 逾時 (例如 "2s")
+    #[serde(default)]
+    pub timeouts: BTreeMap<String, String>,
+
+    #[serde(default)]
+    pub watch: WatchConfig,
+
+    // 健康等級的延遲門檻
+    #[serde(default)]
+    pub grading: GradingConfig,
+
+    // 掃描前的連線檢查錨點
+    #[serde(default)]
+    pub sanity: SanityConfig,
+
+    // 掃描前檢查透明代理與強制門戶的端點
+    #[serde(default)]
+    pub captive: CaptiveConfig,
+
+    // 報告超過一個畫面時的分頁
+    #[serde(default)]
+    pub pager: PagerConfig,
+
+    // 目標數量上限
+    #[serde(default)]
+    pub safety: SafetyConfig,
+
+    // 端口號碼 -> 服務名稱、類別與標籤的覆蓋
+    #[serde(default)]
+    pub ports: BTreeMap<String, PortOverride>,
+
+    // 端口號碼 -> 連線成功後的健康檢查 ([health.8080])
+    #[serde(default)]
+    pub health: BTreeMap<String, HealthSpec>,
+
+    // 可疑結果的重新探測
+    #[serde(default)]
+    pub verify: VerifyConfig,
+
+    // "22" 或 "8000-8100" -> 標籤
+    #[serde(default)]
+    pub tags: BTreeMap<String, Vec<String>>,
+
+    // --blocks 的網段名稱："10.1.2.0/24" -> "prod web"
+    #[serde(default)]
+    pub blocks: BTreeMap<String, String>,
+
+    // 目標名稱解析的快取期限
+    #[serde(default)]
+    pub dns: DnsConfig,
+
+    // 由多個端口組成的服務群組 ([[groups]])，覆蓋同名的內建群組
+    #[serde(default)]
+    pub groups: Vec<GroupDefinition>,
+
+    // 以 AND/OR/NOT 組合端口狀態的服務組合 ([[bundles]])
+    #[serde(default)]
+    pub bundles: Vec<BundleDefinition>,
+
+    // 報告建議事項的額外規則 ([[recommendations]])，先於內建規則比對
+    #[serde(default)]
+    pub recommendations: Vec<RecommendationRule>,
+
+    // 與惡意程式或後門相關的端口 ([[threats]])，覆蓋相同端口的內建項目
+    #[serde(default)]
+    pub threats: Vec<ThreatEntry>,
+
+    // portscanner quick 檢查的端口
+    #[serde(default)]
+    pub quick: QuickConfig,
+
+    // 主機對閘道或 DNS 伺服器的依賴 ([[dependencies]])
+    #[serde(default)]
+    pub dependencies: Vec<DependencyRule>,
+
+    // 命令列選項的預設值 (選項名稱 -> 值)，由 settings 模組在解析命令列時套用
+    #[serde(default, rename = "defaults")]
+    _defaults: toml::Table,
+
+    // 具名的選項組合 ([profiles.名稱])，以 --profile 選擇後覆蓋 [defaults]
+    #[serde(default, rename = "profiles")]
+    _profiles: toml::Table,
+
+    // 設定檔格式的版本；載入時已由 migrate 升級到目前版本
+    #[serde(default, rename = "version")]
+    _version: i64,
+}
+
+// [watch] 區段
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WatchConfig {
+    // 告警規則 ([[watch.alerts]])
+    #[serde(default)]
+    pub alerts: Vec<AlertRule>,
+
+    // 告警觸發時 POST JSON 的網址
+    #[serde(default, deserialize_with = "webhook")]
+    pub webhook: Option<String>,
+
+    // 每隔幾次掃描重新確認外部IP (0 表示不確認)
+    #[serde(default = "default_ip_check_every")]
+    pub ip_check_every: u64,
+
+    // 端口關閉後在此期間內重新開放視為服務重啟 (例如 "5m")
+    #[serde(default = "default_restart_window", deserialize_with = "duration")]
+    pub restart_window: Duration,
+
+    // 相同告警 (規則、主機與端口) 在此期間內只送出一次
+    #[serde(default = "default_dedup_window", deserialize_with = "duration")]
+    pub dedup_window: Duration,
+
+    // 靜音時段 (例如 "22:00-07:00")，期間的非 critical 告警在結束後彙整送出
+    pub quiet_hours: Option<String>,
+
+    // critical 告警的收件人
+    pub email: Option<EmailConfig>,
+
+    // 目標連續幾次完全沒有回應後開始退避 (略過一段逐次加倍的時間)；0 表示不退避
+    #[serde(default = "default_backoff_after")]
+    pub backoff_after: u32,
+
+    // 退避時間的上限 (例如 "1h")
+    #[serde(default = "default_backoff_max", deserialize_with = "duration")]
+    pub backoff_max: Duration,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        WatchConfig {
+            alerts: Vec::new(),
+            webhook: None,
+            ip_check_every: default_ip_check_every(),
+            restart_window: default_restart_window(),
+            dedup_window: default_dedup_window(),
+            quiet_hours: None,
+            email: None,
+            backoff_after: default_backoff_after(),
+            backoff_max: default_backoff_max(),
+        }
+    }
+}
+
+fn default_ip_check_every() -> u64 {
+    10
+}
+
+fn default_restart_window() -> Duration {
+    Duration::from_secs(300)
+}
+
+fn default_dedup_window() -> Duration {
+    Duration::from_secs(600)
+}
+
+fn default_backoff_after() -> u32 {
+    3
+}
+
+fn default_backoff_max() -> Duration {
+    Duration::from_secs(3600)
+}
+
+// 與 [timeouts] 相同的時間長度寫法，例如 "90s"、"10m"
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    crate::units::parse_duration(&text).map_err(serde::de::Error::custom)
+}
+
+// 告警 webhook 的網址；載入設定檔與 portscanner init 輸入時共用
+pub fn check_webhook(url: &str) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("webhook 網址無效: {}: {}", url, e))?;
+    match (parsed.scheme(), parsed.host()) {
+        ("http" | "https", Some(_)) => Ok(()),
+        _ => Err(format!("webhook 網址必須是 http:// 或 https:// 開頭: {}", url)),
+    }
+}
+
+fn webhook<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    let url = Option::<String>::deserialize(deserializer)?;
+    if let Some(url) = &url {
+        check_webhook(url).map_err(serde::de::Error::custom)?;
+    }
+    Ok(url)
+}
+
+// 設定目錄：有設定 $XDG_CONFIG_HOME 時一律使用，否則依平台慣例
+// Linux 等為 ~/.config/portscanner，Windows 為 %APPDATA%\portscanner，
+// macOS 為 ~/Library/Application Support/portscanner (已有 ~/.config/portscanner 時沿用)
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(base) = env::var_os("XDG_CONFIG_HOME").filter(|v| !v.is_empty()) {
+        return Some(PathBuf::from(base).join("portscanner"));
+    }
+    platform_config_dir()
+}
+
+#[cfg(windows)]
+fn platform_config_dir() -> Option<PathBuf> {
+    env::var_os("APPDATA").map(|base| PathBuf::from(base).join("portscanner"))
+}
+
+#[cfg(target_os = "macos")]
+fn platform_config_dir() -> Option<PathBuf> {
+    let home = PathBuf::from(env::var_os("HOME")?);
+    let legacy = home.join(".config").join("portscanner");
+    match legacy.is_dir() {
+        true => Some(legacy),
+        false => Some(home.join("Library").join("Application Support").join("portscanner")),
+    }
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn platform_config_dir() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| Path::new(&home).join(".config").join("portscanner"))
+}
+
+// 預設設定檔位置：設定目錄下的 config.toml
+pub fn default_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config.toml"))
+}
+
+// 讀取設定檔；明確指定的路徑必須存在，預設路徑不存在時使用空設定
+pub fn load(path: Option<&Path>) -> Result<Config, Box<dyn Error>> {
+    let (path, required) = match path {
+        Some(path) => (path.to_path_buf(), true),
+        None => match default_path() {
+            Some(path) => (path, false),
+            None => return Ok(Config::default()),
+        },
+    };
+
+    if !required && !path.exists() {
+        return Ok(Config::default());
+    }
+
+    let text = fs::read_to_string(&path).map_err(|e| format!("無法讀取設定檔 {}: {}", path.display(), e))?;
+    // 舊版格式照常載入，提醒使用者修改
+    let (text, warning) = migrate::upgrade(&text, &path)?;
+    if let Some(warning) = warning {
+        eprintln!("{}", warning.yellow());
+    }
+    Ok(parse(&text, &path)?)
+}
+
+// 解析目前版本的設定檔內容
+pub fn parse(text: &str, path: &Path) -> Result<Config, String> {
+    toml::from_str(text).map_err(|e| format!("設定檔 {} 格式錯誤: {}", path.display(), e))
+}
@@ -0,0 +1,428 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use colored::*;
+use schemars::JsonSchema;
+use serde::Serialize;
+use crate::context::ScanContext;
+use crate::{PortInfo, ScanResult};
+
+// 連線追蹤表的 procfs 介面；較新的核心預設不提供 (CONFIG_NF_CONNTRACK_PROCFS)，改用 netlink
+const PROC_PATH: &str = "/proc/net/nf_conntrack";
+
+// 連線追蹤表中的一條 TCP 連線 (原始方向的位址與端口)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Flow {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    pub sport: u16,
+    pub dport: u16,
+    pub established: bool,
+}
+
+// 連線追蹤表的讀取方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    Procfs,
+    Netlink,
+}
+
+impl Source {
+    fn label(self) -> &'static str {
+        match self {
+            Source::Procfs => PROC_PATH,
+            Source::Netlink => "netlink",
+        }
+    }
+}
+
+// 與「只能發送」判斷不符的端口
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Contradiction {
+    pub port: u16,
+    pub established: u32,
+}
+
+// --conntrack 的比對結果
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ConntrackSummary {
+    pub source: Source,
+    // 目的地為本機位址、已建立的入站 TCP 連線總數
+    pub established_inbound: usize,
+    pub contradicted: Vec<Contradiction>,
+}
+
+// /proc/net/nf_conntrack 的一行，例如
+//   ipv4 2 tcp 6 431999 ESTABLISHED src=192.0.2.7 dst=192.168.1.1 sport=51234 dport=22 src=192.168.1.1 dst=192.0.2.7 sport=22 dport=51234 [ASSURED] mark=0 use=2
+// 前面的 src/dst/sport/dport 為原始方向，後面的是回應方向；TCP 以外的連線回傳 None
+fn parse_proc_line(line: &str) -> Option<Flow> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let proto = fields.iter().position(|field| *field == "tcp")?;
+    // tcp 之後依序為協定編號、剩餘秒數與狀態
+    let state = fields.get(proto + 3)?;
+    let value = |key: &str| fields.iter().find_map(|field| field.strip_prefix(key));
+    Some(Flow {
+        src: value("src=")?.parse().ok()?,
+        dst: value("dst=")?.parse().ok()?,
+        sport: value("sport=")?.parse().ok()?,
+        dport: value("dport=")?.parse().ok()?,
+        established: *state == "ESTABLISHED",
+    })
+}
+
+fn read_proc() -> io::Result<Vec<Flow>> {
+    Ok(fs::read_to_string(PROC_PATH)?.lines().filter_map(parse_proc_line).collect())
+}
+
+// 讀取連線追蹤表：先試 procfs，沒有或無法讀取時改用 netlink；兩者都需要 root 或 CAP_NET_ADMIN
+pub fn read() -> Result<(Source, Vec<Flow>), String> {
+    let proc_error = match read_proc() {
+        Ok(flows) => return Ok((Source::Procfs, flows)),
+        Err(e) => e,
+    };
+    match platform::dump() {
+        Ok(flows) => Ok((Source::Netlink, flows)),
+        Err(e) if [proc_error.kind(), e.kind()].contains(&io::ErrorKind::PermissionDenied) => {
+            Err("--conntrack 需要 root 權限或 CAP_NET_ADMIN".to_string())
+        }
+        Err(e) => Err(format!("無法讀取連線追蹤表 ({}: {}；netlink: {})", PROC_PATH, proc_error, e)),
+    }
+}
+
+// 以連線追蹤表中已建立的入站連線核對「只能發送」的端口 (入站綁定測試判斷無法從外部連入)
+// 入站連線指目的地為本機位址 (含 DNAT 轉送前的對外位址) 且來源不是 loopback 的連線；同一個端口只計算一次
+pub fn reconcile(
+    source: Source,
+    flows: &[Flow],
+    results: &mut BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>,
+    context: &ScanContext,
+) -> ConntrackSummary {
+    let mut inbound: BTreeMap<u16, u32> = BTreeMap::new();
+    for flow in flows.iter().filter(|flow| flow.established && !flow.src.is_loopback() && context.interfaces.contains(&flow.dst)) {
+        *inbound.entry(flow.dport).or_default() += 1;
+    }
+    let mut contradicted = BTreeMap::new();
+    for (port, result) in results.values_mut().flat_map(|ports| ports.iter_mut()) {
+        let outbound_only = result.directions.inbound() && !result.inbound && result.outbound;
+        let Some(&established) = inbound.get(&port.port).filter(|_| outbound_only) else {
+            continue;
+        };
+        let note = format!("實際上有 {} 條已建立的入站連線", established);
+        result.note = Some(match result.note.take() {
+            Some(existing) => format!("{}；{}", existing, note),
+            None => note,
+        });
+        result.conntrack = Some(established);
+        contradicted.insert(port.port, established);
+    }
+    ConntrackSummary {
+        source,
+        established_inbound: inbound.values().map(|&count| count as usize).sum(),
+        contradicted: contradicted.into_iter().map(|(port, established)| Contradiction { port, established }).collect(),
+    }
+}
+
+pub fn display(summary: &ConntrackSummary) {
+    println!("\n{}", "=== 連線追蹤表比對 ===".bold());
+    println!("來源: {}，已建立的入站 TCP 連線: {}", summary.source.label(), summary.established_inbound);
+    if summary.contradicted.is_empty() {
+        println!("{}", "「只能發送」的端口都沒有已建立的入站連線".green());
+        return;
+    }
+    for contradiction in &summary.contradicted {
+        println!(
+            "{}",
+            format!("port {}: 判斷為只能發送，但實際上有 {} 條已建立的入站連線", contradiction.port, contradiction.established).yellow()
+        );
+    }
+}
+
+// nfnetlink 的 conntrack 傾印 (IPCTNL_MSG_CT_GET + NLM_F_DUMP) 與回應的屬性解析
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+mod netlink {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use super::Flow;
+
+    const NLMSG_ERROR: u16 = 2;
+    const NLMSG_DONE: u16 = 3;
+    const NLM_F_REQUEST: u16 = 0x1;
+    const NLM_F_DUMP: u16 = 0x300;
+    // (NFNL_SUBSYS_CTNETLINK << 8) | IPCTNL_MSG_CT_GET
+    const CT_GET: u16 = (1 << 8) | 1;
+    const HEADER: usize = 16;
+    // nfgenmsg：位址家族、版本與資源編號
+    const NFGENMSG: usize = 4;
+
+    // 屬性編號 (linux/netfilter/nfnetlink_conntrack.h)
+    const CTA_TUPLE_ORIG: u16 = 1;
+    const CTA_PROTOINFO: u16 = 4;
+    const CTA_TUPLE_IP: u16 = 1;
+    const CTA_TUPLE_PROTO: u16 = 2;
+    const CTA_IP_V4_SRC: u16 = 1;
+    const CTA_IP_V4_DST: u16 = 2;
+    const CTA_IP_V6_SRC: u16 = 3;
+    const CTA_IP_V6_DST: u16 = 4;
+    const CTA_PROTO_NUM: u16 = 1;
+    const CTA_PROTO_SRC_PORT: u16 = 2;
+    const CTA_PROTO_DST_PORT: u16 = 3;
+    const CTA_PROTOINFO_TCP: u16 = 1;
+    const CTA_PROTOINFO_TCP_STATE: u16 = 1;
+    const TCP_CONNTRACK_ESTABLISHED: u8 = 3;
+    const IPPROTO_TCP: u8 = 6;
+
+    // 屬性類型的 NLA_F_NESTED 與 NLA_F_NET_BYTEORDER 旗標
+    const NLA_TYPE_MASK: u16 = 0x3fff;
+
+    // 所有位址家族 (AF_UNSPEC) 的傾印請求
+    pub fn request(seq: u32) -> Vec<u8> {
+        let mut message = Vec::with_capacity(HEADER + NFGENMSG);
+        message.extend_from_slice(&((HEADER + NFGENMSG) as u32).to_ne_bytes());
+        message.extend_from_slice(&CT_GET.to_ne_bytes());
+        message.extend_from_slice(&(NLM_F_REQUEST | NLM_F_DUMP).to_ne_bytes());
+        message.extend_from_slice(&seq.to_ne_bytes());
+        message.extend_from_slice(&0u32.to_ne_bytes());
+        message.extend_from_slice(&[0, 0, 0, 0]);
+        message
+    }
+
+    fn align(len: usize) -> usize {
+        (len + 3) & !3
+    }
+
+    // (類型, 內容)；長度不合法的屬性結束解析
+    fn attributes(mut data: &[u8]) -> Vec<(u16, &[u8])> {
+        let mut found = Vec::new();
+        while data.len() >= 4 {
+            let len = u16::from_ne_bytes([data[0], data[1]]) as usize;
+            let kind = u16::from_ne_bytes([data[2], data[3]]) & NLA_TYPE_MASK;
+            if len < 4 || len > data.len() {
+                break;
+            }
+            found.push((kind, &data[4..len]));
+            data = &data[align(len).min(data.len())..];
+        }
+        found
+    }
+
+    fn find(data: &[u8], kind: u16) -> Option<&[u8]> {
+        attributes(data).into_iter().find(|(found, _)| *found == kind).map(|(_, value)| value)
+    }
+
+    fn address(value: &[u8]) -> Option<IpAddr> {
+        match value.len() {
+            4 => Some(Ipv4Addr::from(<[u8; 4]>::try_from(value).ok()?).into()),
+            16 => Some(Ipv6Addr::from(<[u8; 16]>::try_from(value).ok()?).into()),
+            _ => None,
+        }
+    }
+
+    // 端口以網路位元組順序存放
+    fn port(value: &[u8]) -> Option<u16> {
+        Some(u16::from_be_bytes(value.get(..2)?.try_into().ok()?))
+    }
+
+    // 一則 IPCTNL_MSG_CT_NEW 的內容 (nfgenmsg 之後)；TCP 以外的連線回傳 None
+    fn flow(data: &[u8]) -> Option<Flow> {
+        let tuple = find(data, CTA_TUPLE_ORIG)?;
+        let ip = find(tuple, CTA_TUPLE_IP)?;
+        let proto = find(tuple, CTA_TUPLE_PROTO)?;
+        if find(proto, CTA_PROTO_NUM)?.first() != Some(&IPPROTO_TCP) {
+            return None;
+        }
+        let (src, dst) = match find(ip, CTA_IP_V4_SRC) {
+            Some(src) => (src, find(ip, CTA_IP_V4_DST)?),
+            None => (find(ip, CTA_IP_V6_SRC)?, find(ip, CTA_IP_V6_DST)?),
+        };
+        let state = find(data, CTA_PROTOINFO)
+            .and_then(|info| find(info, CTA_PROTOINFO_TCP))
+            .and_then(|tcp| find(tcp, CTA_PROTOINFO_TCP_STATE))
+            .and_then(|state| state.first().copied());
+        Some(Flow {
+            src: address(src)?,
+            dst: address(dst)?,
+            sport: port(find(proto, CTA_PROTO_SRC_PORT)?)?,
+            dport: port(find(proto, CTA_PROTO_DST_PORT)?)?,
+            established: state == Some(TCP_CONNTRACK_ESTABLISHED),
+        })
+    }
+
+    // 一次 recv 收到的訊息；回傳是否已收到 NLMSG_DONE，核心回報錯誤時為 Err(errno)
+    pub fn parse(mut buffer: &[u8], flows: &mut Vec<Flow>) -> Result<bool, i32> {
+        while buffer.len() >= HEADER {
+            let len = u32::from_ne_bytes(buffer[..4].try_into().unwrap_or_default()) as usize;
+            let kind = u16::from_ne_bytes([buffer[4], buffer[5]]);
+            if len < HEADER || len > buffer.len() {
+                break;
+            }
+            let payload = &buffer[HEADER..len];
+            match kind {
+                NLMSG_DONE => return Ok(true),
+                NLMSG_ERROR => {
+                    let code = payload.get(..4).map_or(0, |code| i32::from_ne_bytes(code.try_into().unwrap_or_default()));
+                    if code != 0 {
+                        return Err(-code);
+                    }
+                }
+                _ => flows.extend(payload.get(NFGENMSG..).and_then(flow)),
+            }
+            buffer = &buffer[align(len).min(buffer.len())..];
+        }
+        Ok(false)
+    }
+
+    #[cfg(test)]
+    pub fn attribute(kind: u16, value: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&((4 + value.len()) as u16).to_ne_bytes());
+        data.extend_from_slice(&kind.to_ne_bytes());
+        data.extend_from_slice(value);
+        data.resize(align(data.len()), 0);
+        data
+    }
+
+    // 測試用的 IPCTNL_MSG_CT_NEW 訊息
+    #[cfg(test)]
+    pub fn message(src: [u8; 4], dst: [u8; 4], dport: u16, state: u8) -> Vec<u8> {
+        const NESTED: u16 = 0x8000;
+        let ip = [attribute(CTA_IP_V4_SRC, &src), attribute(CTA_IP_V4_DST, &dst)].concat();
+        let proto = [
+            attribute(CTA_PROTO_NUM, &[IPPROTO_TCP]),
+            attribute(CTA_PROTO_SRC_PORT, &51234u16.to_be_bytes()),
+            attribute(CTA_PROTO_DST_PORT, &dport.to_be_bytes()),
+        ]
+        .concat();
+        let tuple = [attribute(CTA_TUPLE_IP | NESTED, &ip), attribute(CTA_TUPLE_PROTO | NESTED, &proto)].concat();
+        let tcp = attribute(CTA_PROTOINFO_TCP | NESTED, &attribute(CTA_PROTOINFO_TCP_STATE, &[state]));
+        let body = [vec![2, 0, 0, 0], attribute(CTA_TUPLE_ORIG | NESTED, &tuple), attribute(CTA_PROTOINFO | NESTED, &tcp)].concat();
+        let mut message = Vec::new();
+        message.extend_from_slice(&((HEADER + body.len()) as u32).to_ne_bytes());
+        // IPCTNL_MSG_CT_NEW
+        message.extend_from_slice(&(1u16 << 8).to_ne_bytes());
+        message.extend_from_slice(&[0; 10]);
+        message.extend_from_slice(&body);
+        message
+    }
+
+    #[cfg(test)]
+    pub fn done() -> Vec<u8> {
+        let mut message = Vec::new();
+        message.extend_from_slice(&(HEADER as u32 + 4).to_ne_bytes());
+        message.extend_from_slice(&NLMSG_DONE.to_ne_bytes());
+        message.extend_from_slice(&[0; 14]);
+        message
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::io::{self, Read, Write};
+    use std::time::Duration;
+    use socket2::{Domain, Protocol, Socket, Type};
+    use super::{netlink, Flow};
+
+    // 傾印時每次讀取的上限；核心的每則回應不超過一頁
+    const RECV_BUFFER: usize = 64 * 1024;
+
+    // 核心沒有回應時不一直等待
+    const RECV_TIMEOUT: Duration = Duration::from_secs(5);
+
+    pub fn dump() -> io::Result<Vec<Flow>> {
+        let mut socket = Socket::new(Domain::from(libc::AF_NETLINK), Type::RAW, Some(Protocol::from(libc::NETLINK_NETFILTER)))?;
+        socket.set_read_timeout(Some(RECV_TIMEOUT))?;
+        socket.write_all(&netlink::request(1))?;
+        let mut flows = Vec::new();
+        let mut buffer = vec![0u8; RECV_BUFFER];
+        loop {
+            let received = socket.read(&mut buffer)?;
+            if received == 0 {
+                return Ok(flows);
+            }
+            match netlink::parse(&buffer[..received], &mut flows) {
+                Ok(true) => return Ok(flows),
+                Ok(false) => {}
+                Err(errno) => return Err(io::Error::from_raw_os_error(errno)),
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use std::io;
+    use super::Flow;
+
+    pub fn dump() -> io::Result<Vec<Flow>> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "連線追蹤表只支援 Linux"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::ExternalIpSource;
+    use crate::testutil::scan_result;
+
+    #[test]
+    fn proc_lines_are_parsed() {
+        let line = "ipv4     2 tcp      6 431999 ESTABLISHED src=192.0.2.7 dst=192.168.1.1 sport=51234 dport=22 \
+                    src=192.168.1.1 dst=192.0.2.7 sport=22 dport=51234 [ASSURED] mark=0 zone=0 use=2";
+        let flow = parse_proc_line(line).unwrap();
+        assert_eq!((flow.src, flow.dst, flow.sport, flow.dport, flow.established), ("192.0.2.7".parse().unwrap(), "192.168.1.1".parse().unwrap(), 51234, 22, true));
+        let v6 = "ipv6     10 tcp      6 117 TIME_WAIT src=2001:db8::7 dst=2001:db8::1 sport=40000 dport=443 src=2001:db8::1 dst=2001:db8::7 sport=443 dport=40000 mark=0 use=1";
+        assert_eq!(parse_proc_line(v6).map(|flow| (flow.dport, flow.established)), Some((443, false)));
+        // 舊的 ip_conntrack 格式沒有位址家族欄位
+        assert!(parse_proc_line("tcp      6 300 ESTABLISHED src=10.0.0.2 dst=10.0.0.1 sport=1 dport=80 src=10.0.0.1 dst=10.0.0.2 sport=80 dport=1").is_some());
+        assert_eq!(parse_proc_line("ipv4     2 udp      17 29 src=10.0.0.2 dst=10.0.0.1 sport=5353 dport=53"), None);
+    }
+
+    #[test]
+    fn netlink_dump_is_parsed() {
+        let buffer = [netlink::message([192, 0, 2, 7], [192, 168, 1, 1], 22, 3), netlink::message([192, 0, 2, 8], [192, 168, 1, 1], 80, 7), netlink::done()].concat();
+        let mut flows = Vec::new();
+        assert_eq!(netlink::parse(&buffer, &mut flows), Ok(true));
+        assert_eq!(flows.len(), 2);
+        assert_eq!((flows[0].dst, flows[0].dport, flows[0].established), ("192.168.1.1".parse().unwrap(), 22, true));
+        assert!(!flows[1].established);
+        assert_eq!(netlink::request(1).len(), 20);
+    }
+
+    #[test]
+    fn outbound_only_verdicts_are_contradicted() {
+        let local: IpAddr = "192.168.1.1".parse().unwrap();
+        let flow = |src: &str, dst: IpAddr, dport, established| Flow { src: src.parse().unwrap(), dst, sport: 50000, dport, established };
+        let flows = [
+            flow("192.0.2.7", local, 22, true),
+            flow("192.0.2.8", local, 22, true),
+            flow("192.0.2.9", local, 22, false),
+            flow("127.0.0.1", local, 8080, true),
+            flow("192.0.2.7", "198.51.100.1".parse().unwrap(), 443, true),
+            flow("192.0.2.7", local, 25, true),
+        ];
+        let mut outbound_only = scan_result(true);
+        outbound_only.inbound = false;
+        let mut both = scan_result(true);
+        both.inbound = true;
+        let mut results = BTreeMap::new();
+        results.insert(
+            "203.0.113.5".parse().unwrap(),
+            HashMap::from([
+                (PortInfo::new(22, "SSH", "Remote"), outbound_only.clone()),
+                (PortInfo::new(8080, "HTTP-ALT", "Web"), outbound_only.clone()),
+                (PortInfo::new(443, "HTTPS", "Web"), outbound_only),
+                (PortInfo::new(25, "SMTP", "Mail"), both),
+            ]),
+        );
+        let mut context = ScanContext::new(ExternalIpSource::Disabled);
+        context.interfaces = vec![local];
+        let summary = reconcile(Source::Procfs, &flows, &mut results, &context);
+
+        assert_eq!(summary.established_inbound, 3);
+        assert_eq!(summary.contradicted, [Contradiction { port: 22, established: 2 }]);
+        let ports = results.values().next().unwrap();
+        let ssh = ports.iter().find(|(port, _)| port.port == 22).unwrap().1;
+        assert_eq!(ssh.note.as_deref(), Some("實際上有 2 條已建立的入站連線"));
+        assert!(ports.iter().filter(|(port, _)| port.port != 22).all(|(_, result)| result.conntrack.is_none()));
+    }
+}
@@ -0,0 +1,228 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use crate::anonymize::{self, Anonymizer};
+use crate::dns::DnsCache;
+use crate::resources::{ProbeCounters, SocketGuard};
+use crate::zone::Zones;
+use crate::{cli, targets};
+
+// 預設查詢外部IP的服務
+const EXTERNAL_IP_URL: &str = "https://api.ipify.org";
+
+// 查詢外部IP的逾時；被封鎖的網路中不會一直等待
+pub const EXTERNAL_IP_TIMEOUT: Duration = Duration::from_secs(10);
+
+// 入站測試等待外部IP查詢的上限；所有探測共用同一次查詢，逾時後不再等待
+const EXTERNAL_IP_WAIT: Duration = Duration::from_secs(5);
+
+// 外部IP的查詢狀態
+#[derive(Debug, Clone)]
+pub enum ExternalIp {
+    Pending,
+    Known(String),
+    Unavailable(String),
+}
+
+// 外部IP的來源，依 --external-ip / --external-ip-url 決定
+#[derive(Debug, Clone)]
+pub enum ExternalIpSource {
+    Lookup(String),
+    // --external-ip：不查詢，watch 模式也不重新確認
+    Manual(IpAddr),
+    // --no-external-ip 與 selftest：完全不查詢，外部IP視為無法取得
+    Disabled,
+}
+
+// 不查詢外部IP時的狀態說明
+const NOT_LOOKED_UP: &str = "未查詢";
+
+// 一次掃描的網路資訊：本機位址與背景查詢的外部IP
+// 由 ScanPlan 帶著傳給掃描器與輸出，同一個程序中的不同掃描不共用
+#[derive(Debug)]
+pub struct ScanContext {
+    source: ExternalIpSource,
+    // 外部IP在背景查詢，需要的地方以 watch 通道等待結果；watch 模式中可能變更
+    external_ip: watch::Sender<ExternalIp>,
+    // 啟動時取得一次的本機位址
+    pub local_ip: Option<IpAddr>,
+    // 啟動時列出的所有本機介面位址，用來判斷外部IP是否直接設定在本機上 (沒有 NAT)
+    pub interfaces: Vec<IpAddr>,
+    // 目標清單中帶 %zone 的連結本地位址
+    pub zones: Zones,
+    // --resource-report 的探測層計數
+    pub counters: Option<Arc<ProbeCounters>>,
+    // --anonymize：這次執行的鹽值與假名對照
+    pub anonymizer: Option<Anonymizer>,
+    // 目標解析與之後查詢共用的 DNS 快取
+    pub dns: Arc<DnsCache>,
+    // 執行資訊中的掃描 ID；watch 的告警與心跳帶著它送出
+    pub scan_id: String,
+}
+
+impl ScanContext {
+    // 本機位址與網路介面由 detect_local 另外取得
+    pub fn new(source: ExternalIpSource) -> Self {
+        let state = match source {
+            ExternalIpSource::Disabled => ExternalIp::Unavailable(NOT_LOOKED_UP.to_string()),
+            _ => ExternalIp::Pending,
+        };
+        ScanContext {
+            source,
+            external_ip: watch::Sender::new(state),
+            local_ip: None,
+            interfaces: Vec::new(),
+            zones: Zones::default(),
+            counters: None,
+            anonymizer: None,
+            dns: Arc::default(),
+            scan_id: String::new(),
+        }
+    }
+
+    pub fn from_cli(cli: &cli::Cli, zones: Zones, anonymizer: Option<Anonymizer>, dns: Arc<DnsCache>) -> Self {
+        let mut context = ScanContext::new(match (cli.external_ip, &cli.external_ip_url) {
+            _ if cli.no_external_ip => ExternalIpSource::Disabled,
+            (Some(ip), _) => ExternalIpSource::Manual(ip),
+            (None, Some(url)) => ExternalIpSource::Lookup(url.clone()),
+            (None, None) => ExternalIpSource::Lookup(EXTERNAL_IP_URL.to_string()),
+        });
+        if !cli.no_network_info {
+            context.detect_local();
+        }
+        context.zones = zones;
+        context.counters = cli.resource_report.then(Arc::default);
+        context.anonymizer = anonymizer;
+        context.dns = dns;
+        context
+    }
+
+    // 不查詢外部IP (selftest)；入站測試只綁定本機位址與 0.0.0.0
+    pub fn offline() -> Self {
+        let mut context = ScanContext::new(ExternalIpSource::Disabled);
+        context.detect_local();
+        context
+    }
+
+    // 取得本機主要位址與所有網路介面的位址；--no-network-info 時略過
+    pub fn detect_local(&mut self) {
+        self.local_ip = local_ip_address::local_ip().ok();
+        self.interfaces = local_ip_address::list_afinet_netifas()
+            .map(|list| list.into_iter().map(|(_, addr)| addr).collect())
+            .unwrap_or_default();
+    }
+
+    pub fn external_ip_disabled(&self) -> bool {
+        matches!(self.source, ExternalIpSource::Disabled)
+    }
+
+    // 連線目標用的 socket 位址 (連結本地位址帶上 scope_id)
+    pub fn socket_addr(&self, host: IpAddr, port: u16) -> SocketAddr {
+        self.zones.socket_addr(host, port)
+    }
+
+    // 探測層的計數；未加 --resource-report 時不做任何事
+    pub fn sent(&self, bytes: usize) {
+        if let Some(counters) = &self.counters {
+            counters.sent(bytes);
+        }
+    }
+
+    pub fn received(&self, bytes: usize) {
+        if let Some(counters) = &self.counters {
+            counters.received(bytes);
+        }
+    }
+
+    pub fn socket(&self) -> SocketGuard {
+        self.counters.as_ref().map_or_else(SocketGuard::none, ProbeCounters::socket)
+    }
+
+    // 顯示用的位址與文字：--anonymize 時換成假名
+    pub fn show_ip(&self, addr: IpAddr) -> String {
+        anonymize::show_ip(self.anonymizer.as_ref(), addr)
+    }
+
+    pub fn show(&self, text: &str) -> String {
+        anonymize::show(self.anonymizer.as_ref(), text)
+    }
+
+    // 在背景查詢外部IP，不延後掃描開始
+    pub fn start_external_ip_lookup(self: &Arc<Self>) {
+        if self.external_ip_disabled() {
+            return;
+        }
+        let context = self.clone();
+        tokio::spawn(async move {
+            let state = match context.fetch_external_ip().await {
+                Ok(ip) => ExternalIp::Known(ip),
+                Err(e) => ExternalIp::Unavailable(e),
+            };
+            context.external_ip.send_replace(state);
+        });
+    }
+
+    // 目前已知的外部IP，不等待查詢
+    pub fn external_ip(&self) -> Option<String> {
+        match &*self.external_ip.borrow() {
+            ExternalIp::Known(ip) => Some(ip.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn set_external_ip(&self, ip: String) {
+        self.external_ip.send_replace(ExternalIp::Known(ip));
+    }
+
+    // 等待背景查詢完成，最多等待 limit；逾時時回傳 Pending
+    pub async fn wait_external_ip(&self, limit: Duration) -> ExternalIp {
+        let mut receiver = self.external_ip.subscribe();
+        let finished = tokio::time::timeout(limit, receiver.wait_for(|state| !matches!(state, ExternalIp::Pending))).await;
+        match finished {
+            Ok(Ok(state)) => state.clone(),
+            _ => ExternalIp::Pending,
+        }
+    }
+
+    // 入站測試需要外部IP時才等待，最多 EXTERNAL_IP_WAIT
+    pub async fn required_external_ip(&self) -> Option<IpAddr> {
+        match self.wait_external_ip(EXTERNAL_IP_WAIT).await {
+            ExternalIp::Known(ip) => ip.parse().ok(),
+            _ => None,
+        }
+    }
+
+    // 重新查詢外部IP；reqwest 預設套用 HTTP_PROXY、HTTPS_PROXY 與 NO_PROXY 環境變數
+    pub async fn fetch_external_ip(&self) -> Result<String, String> {
+        let url = match &self.source {
+            ExternalIpSource::Manual(ip) => return Ok(ip.to_string()),
+            ExternalIpSource::Disabled => return Err(NOT_LOOKED_UP.to_string()),
+            ExternalIpSource::Lookup(url) => url.as_str(),
+        };
+        let client = reqwest::Client::builder()
+            .timeout(EXTERNAL_IP_TIMEOUT)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let response = client
+            .get(url)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| format!("{}: {}", url, e.without_url()))?;
+        let text = response.text().await.map_err(|e| format!("{}: {}", url, e.without_url()))?;
+        let addr = validate_external_ip(&text).map_err(|e| format!("{} 的回應無效: {}", url, e))?;
+        Ok(addr.to_string())
+    }
+}
+
+// 外部IP必須是公網單播位址；手動指定與查詢服務的回應都經過此檢查
+pub fn validate_external_ip(text: &str) -> Result<IpAddr, String> {
+    let text = text.trim();
+    // 代理或入口網頁可能回傳整份 HTML，只引用開頭
+    let quoted: String = text.split_whitespace().collect::<Vec<_>>().join(" ").chars().take(40).collect();
+    let ellipsis = if quoted.chars().count() < text.chars().count() { "…" } else { "" };
+    let addr: IpAddr = text.parse().map_err(|_| format!("'{}{}' 不是有效的 IP 位址", quoted, ellipsis))?;
+    targets::check_global_unicast(addr).map_err(|reason| format!("{} 是{}，不是公網單播位址", addr, reason))?;
+    Ok(addr)
+}
@@ -0,0 +1,267 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::IpAddr;
+use ipnet::IpNet;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::pipeline::host_answered;
+use crate::{PortInfo, ScanResult};
+
+// 設定檔的 [[dependencies]]：主機依賴的閘道或 DNS 伺服器，例如
+//   [[dependencies]]
+//   hosts = ["10.1.0.0/24"]
+//   depends_on = ["10.1.0.1", "10.1.0.53"]
+//   quiet_alerts = true
+// 主機符合多條規則時只套用網段最具體的規則；depends_on = [] 代表這些主機沒有上游
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DependencyRule {
+    // 依賴上游的主機 (IP 或網段)；上游主機本身不受同一條規則影響
+    pub hosts: Vec<String>,
+    pub depends_on: Vec<IpAddr>,
+    // 上游無法連線時不送出這些主機的告警 (watch)
+    #[serde(default)]
+    pub quiet_alerts: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    hosts: Vec<IpNet>,
+    upstream: Vec<IpAddr>,
+    quiet_alerts: bool,
+}
+
+impl Rule {
+    // 涵蓋主機的網段中最長的前綴；上游主機本身不受同一條規則影響
+    fn specificity(&self, host: IpAddr) -> Option<u8> {
+        if self.upstream.contains(&host) {
+            return None;
+        }
+        self.hosts.iter().filter(|net| net.contains(&host)).map(IpNet::prefix_len).max()
+    }
+}
+
+// 結果標示的上游狀態
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Upstream {
+    // 無法連線的上游依賴，越上游越前面
+    pub down: Vec<IpAddr>,
+    // 此結果不列入告警
+    pub quiet_alerts: bool,
+}
+
+// 驗證過的依賴關係
+#[derive(Debug, Clone, Default)]
+pub struct Dependencies {
+    rules: Vec<Rule>,
+    // 所有上游主機的拓撲順序：每台主機排在依賴它的上游主機之後
+    order: Vec<IpAddr>,
+}
+
+fn parse_hosts(item: &str) -> Result<IpNet, String> {
+    let item = item.trim();
+    match item.parse::<IpNet>() {
+        Ok(net) => Ok(net.trunc()),
+        Err(_) => item.parse::<IpAddr>().map(IpNet::from).map_err(|_| format!("依賴規則的主機必須是 IP 或網段: {}", item)),
+    }
+}
+
+impl Dependencies {
+    pub fn build(configured: &[DependencyRule]) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        for (i, definition) in configured.iter().enumerate() {
+            if definition.hosts.is_empty() {
+                return Err(format!("依賴規則 #{} 需要 hosts", i + 1));
+            }
+            rules.push(Rule {
+                hosts: definition.hosts.iter().map(|item| parse_hosts(item)).collect::<Result<_, _>>()?,
+                upstream: definition.depends_on.clone(),
+                quiet_alerts: definition.quiet_alerts,
+            });
+        }
+        let mut dependencies = Dependencies { rules, order: Vec::new() };
+        dependencies.order = dependencies.topological()?;
+        Ok(dependencies)
+    }
+
+    // 上游主機依序排列，先掃描的排前面
+    pub fn order(&self) -> &[IpAddr] {
+        &self.order
+    }
+
+    // 主機的直接上游 (上游, 是否不列入告警)
+    fn upstream(&self, host: IpAddr) -> impl Iterator<Item = (IpAddr, bool)> + '_ {
+        let best = self.rules.iter().filter_map(|rule| rule.specificity(host)).max();
+        self.rules
+            .iter()
+            .filter(move |rule| best.is_some() && rule.specificity(host) == best)
+            .flat_map(|rule| rule.upstream.iter().map(move |upstream| (*upstream, rule.quiet_alerts)))
+    }
+
+    // 深度優先排序上游主機；回到路徑上的主機代表有循環
+    fn topological(&self) -> Result<Vec<IpAddr>, String> {
+        fn visit(dependencies: &Dependencies, host: IpAddr, path: &mut Vec<IpAddr>, order: &mut Vec<IpAddr>) -> Result<(), String> {
+            if let Some(start) = path.iter().position(|h| *h == host) {
+                let cycle: Vec<String> = path[start..].iter().chain([&host]).map(IpAddr::to_string).collect();
+                return Err(format!("依賴規則有循環: {}", cycle.join(" → ")));
+            }
+            if order.contains(&host) {
+                return Ok(());
+            }
+            path.push(host);
+            let upstream: Vec<IpAddr> = dependencies.upstream(host).map(|(upstream, _)| upstream).collect();
+            for next in upstream {
+                visit(dependencies, next, path, order)?;
+            }
+            path.pop();
+            order.push(host);
+            Ok(())
+        }
+        let mut order = Vec::new();
+        for host in self.rules.iter().flat_map(|rule| &rule.upstream) {
+            visit(self, *host, &mut Vec::new(), &mut order)?;
+        }
+        Ok(order)
+    }
+
+    // 經由無法連線的上游一路往上找，收集所有無法連線的上游
+    fn down_chain(&self, host: IpAddr, down: &HashSet<IpAddr>, found: &mut Vec<IpAddr>) -> bool {
+        let mut quiet = false;
+        let upstream: Vec<(IpAddr, bool)> = self.upstream(host).collect();
+        for (upstream, quiet_alerts) in upstream {
+            if down.contains(&upstream) {
+                quiet |= quiet_alerts;
+                if !found.contains(&upstream) {
+                    found.push(upstream);
+                    self.down_chain(upstream, down, found);
+                }
+            }
+        }
+        quiet
+    }
+
+    // 有掃描到且完全沒有回應的上游主機，其下游主機的失敗結果加上註記；回傳受影響的主機數
+    pub fn annotate(&self, results: &mut BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>) -> usize {
+        let down: HashSet<IpAddr> = self
+            .order
+            .iter()
+            .filter(|host| results.get(host).is_some_and(|ports| !ports.is_empty() && !host_answered(ports)))
+            .copied()
+            .collect();
+        if down.is_empty() {
+            return 0;
+        }
+        let mut affected = 0;
+        for (host, ports) in results.iter_mut() {
+            let mut found = Vec::new();
+            let quiet_alerts = self.down_chain(*host, &down, &mut found);
+            if found.is_empty() {
+                continue;
+            }
+            found.sort_by_key(|upstream| self.order.iter().position(|h| h == upstream));
+            let labels: Vec<String> = found.iter().map(IpAddr::to_string).collect();
+            let note = format!("上游依賴 ({}) 無法連線 — 結果可能受影響", labels.join("、"));
+            let mut annotated = false;
+            for result in ports.values_mut().filter(|result| !result.outbound) {
+                result.upstream = Some(Upstream { down: found.clone(), quiet_alerts });
+                result.note = Some(match result.note.take() {
+                    Some(existing) => format!("{}；{}", existing, note),
+                    None => note.clone(),
+                });
+                annotated = true;
+            }
+            affected += usize::from(annotated);
+        }
+        affected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::closure::Failure;
+    use crate::prober::fake::ScriptedProber;
+    use crate::testutil::{host, scan, scan_result, scripted_plan};
+
+    fn ip(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    fn rule(hosts: &[&str], depends_on: &[&str], quiet_alerts: bool) -> DependencyRule {
+        DependencyRule {
+            hosts: hosts.iter().map(|h| h.to_string()).collect(),
+            depends_on: depends_on.iter().map(|h| ip(h)).collect(),
+            quiet_alerts,
+        }
+    }
+
+    fn results(hosts: &[(&str, bool)]) -> BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>> {
+        hosts
+            .iter()
+            .map(|(host, open)| {
+                let mut result = scan_result(*open);
+                result.failure = (!open).then_some(Failure::Timeout);
+                (ip(host), HashMap::from([(PortInfo::new(22, "SSH", "Remote"), result)]))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        let error = Dependencies::build(&[
+            rule(&["10.1.0.0/24"], &["10.2.0.1"], false),
+            rule(&["10.2.0.0/24"], &["10.1.0.1"], false),
+        ])
+        .unwrap_err();
+        assert!(error.contains("循環"), "{}", error);
+        // 上游主機落在自己的規則範圍內不算循環
+        let dependencies = Dependencies::build(&[rule(&["10.1.0.0/24"], &["10.1.0.1", "10.1.0.53"], false)]).unwrap();
+        assert_eq!(dependencies.order(), &[ip("10.1.0.1"), ip("10.1.0.53")]);
+    }
+
+    #[test]
+    fn upstream_hosts_are_ordered_before_their_dependents() {
+        // DNS 伺服器依賴閘道，其他主機依賴 DNS 伺服器
+        let chain = [rule(&["10.1.0.0/24"], &["10.1.0.53"], false), rule(&["10.1.0.53"], &["10.1.0.1"], false)];
+        // 閘道也落在 /24 中，依賴 DNS 伺服器而形成循環
+        assert!(Dependencies::build(&chain).is_err());
+        // 更具體的規則讓閘道沒有上游
+        let dependencies = Dependencies::build(&[chain[0].clone(), chain[1].clone(), rule(&["10.1.0.1"], &[], false)]).unwrap();
+        assert_eq!(dependencies.order(), &[ip("10.1.0.1"), ip("10.1.0.53")]);
+    }
+
+    #[test]
+    fn failures_behind_a_down_upstream_are_annotated() {
+        let dependencies = Dependencies::build(&[
+            rule(&["10.1.0.0/24"], &["10.1.0.53"], true),
+            rule(&["10.1.0.53"], &["10.1.0.1"], false),
+            rule(&["10.1.0.1"], &[], false),
+        ])
+        .unwrap();
+        let mut scanned = results(&[("10.1.0.1", false), ("10.1.0.53", false), ("10.1.0.7", false), ("10.1.0.8", true), ("10.9.0.1", false)]);
+        assert_eq!(dependencies.annotate(&mut scanned), 2);
+
+        let port = PortInfo::new(22, "SSH", "Remote");
+        let dependent = &scanned[&ip("10.1.0.7")][&port];
+        assert_eq!(dependent.note.as_deref(), Some("上游依賴 (10.1.0.1、10.1.0.53) 無法連線 — 結果可能受影響"));
+        assert_eq!(dependent.upstream, Some(Upstream { down: vec![ip("10.1.0.1"), ip("10.1.0.53")], quiet_alerts: true }));
+        let dns = &scanned[&ip("10.1.0.53")][&port];
+        assert_eq!(dns.upstream.as_ref().map(|u| (u.down.clone(), u.quiet_alerts)), Some((vec![ip("10.1.0.1")], false)));
+        // 連得上的端口與不相關的主機不受影響
+        assert!(scanned[&ip("10.1.0.8")][&port].upstream.is_none());
+        assert!(scanned[&ip("10.9.0.1")][&port].upstream.is_none());
+        assert!(scanned[&ip("10.1.0.1")][&port].upstream.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn upstream_hosts_are_scanned_first() {
+        let prober = Arc::new(ScriptedProber::new());
+        let mut plan = scripted_plan(&[host(1), host(2), host(3)], &[22, 80], 1, prober.clone());
+        plan.dependencies = Arc::new(Dependencies::build(&[rule(&["192.0.2.0/24"], &["192.0.2.3"], false)]).unwrap());
+        assert_eq!(scan(&plan).await.len(), 6);
+        let order: Vec<IpAddr> = prober.calls().into_iter().map(|(host, _)| host).collect();
+        // 上游主機的端口全部排入後才輪到其他主機，且只掃描一次
+        assert_eq!(&order[..2], &[host(3), host(3)]);
+        assert_eq!(order.iter().filter(|h| **h == host(3)).count(), 2);
+    }
+}
@@ -0,0 +1,235 @@
+use colored::*;
+use schemars::JsonSchema;
+use serde::ser::{self, Impossible, SerializeStruct};
+use serde::{Deserialize, Serialize, Serializer};
+use crate::ScanResult;
+
+// 掃描測試的方向：--no-inbound 只測出站、--no-outbound 只測入站
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Directions {
+    #[default]
+    Both,
+    Outbound,
+    Inbound,
+}
+
+impl Directions {
+    pub fn of(no_inbound: bool, no_outbound: bool) -> Self {
+        match (no_inbound, no_outbound) {
+            (true, _) => Directions::Outbound,
+            (_, true) => Directions::Inbound,
+            _ => Directions::Both,
+        }
+    }
+
+    pub fn inbound(self) -> bool {
+        self != Directions::Outbound
+    }
+
+    pub fn outbound(self) -> bool {
+        self != Directions::Inbound
+    }
+
+    pub fn is_both(&self) -> bool {
+        *self == Directions::Both
+    }
+
+    // 與 JSON 相同的名稱，CSV 與純文字輸出的開頭註解使用
+    pub fn name(self) -> &'static str {
+        match self {
+            Directions::Both => "both",
+            Directions::Outbound => "outbound",
+            Directions::Inbound => "inbound",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Directions::Both => "雙向",
+            Directions::Outbound => "只測出站 (--no-inbound)",
+            Directions::Inbound => "只測入站 (--no-outbound)",
+        }
+    }
+
+    // 只測一個方向時，未測試的方向視為與測試的方向相同，圖示與排序只反映測試的方向
+    pub fn normalize(self, inbound: bool, outbound: bool) -> (bool, bool) {
+        match self {
+            Directions::Both => (inbound, outbound),
+            Directions::Outbound => (outbound, outbound),
+            Directions::Inbound => (inbound, inbound),
+        }
+    }
+
+    // 測試的方向都可用
+    pub fn available(self, inbound: bool, outbound: bool) -> bool {
+        self.normalize(inbound, outbound) == (true, true)
+    }
+
+    // 一行摘要的「開放」：雙向時以出站為準
+    pub fn open(self, inbound: bool, outbound: bool) -> bool {
+        match self {
+            Directions::Inbound => inbound,
+            _ => outbound,
+        }
+    }
+
+    // 只測一個方向時的狀態標籤；雙向時為 None，沿用原本的四種狀態
+    pub fn single_label(self, inbound: bool, outbound: bool) -> Option<ColoredString> {
+        match (self, inbound, outbound) {
+            (Directions::Both, ..) => None,
+            (Directions::Outbound, _, true) => Some("✓ 可連線".green()),
+            (Directions::Outbound, _, false) => Some("✗ 無法連線".red()),
+            (Directions::Inbound, true, _) => Some("✓ 可綁定".green()),
+            (Directions::Inbound, false, _) => Some("✗ 無法綁定".red()),
+        }
+    }
+}
+
+// JSON Schema 用：未測試的方向不輸出，兩個欄位都不是必要欄位
+pub fn maybe_omitted(_: &bool) -> bool {
+    false
+}
+
+// 記錄與報告中的端口結果：省略結果本身記錄的未測試方向 (serde 的 serialize_with，搭配 flatten)
+pub fn serialize_tested<S: Serializer>(result: &ScanResult, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut skipped = Vec::new();
+    if !result.directions.inbound() {
+        skipped.push("inbound");
+    }
+    if !result.directions.outbound() {
+        skipped.push("outbound");
+    }
+    result.serialize(SkipFields { inner: serializer, skipped })
+}
+
+// 只轉送結構的序列化，略過指定名稱的欄位；欄位順序不變
+struct SkipFields<S> {
+    inner: S,
+    skipped: Vec<&'static str>,
+}
+
+struct SkipFieldsStruct<T> {
+    inner: T,
+    skipped: Vec<&'static str>,
+}
+
+impl<T: SerializeStruct> SerializeStruct for SkipFieldsStruct<T> {
+    type Ok = T::Ok;
+    type Error = T::Error;
+
+    fn serialize_field<V: ?Sized + Serialize>(&mut self, key: &'static str, value: &V) -> Result<(), Self::Error> {
+        match self.skipped.contains(&key) {
+            true => self.inner.skip_field(key),
+            false => self.inner.serialize_field(key, value),
+        }
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.inner.end()
+    }
+}
+
+// 端口結果一定序列化為結構，其他形式不會出現
+fn unsupported<E: ser::Error>() -> E {
+    E::custom("只支援結構")
+}
+
+impl<S: Serializer> Serializer for SkipFields<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Impossible<S::Ok, S::Error>;
+    type SerializeTuple = Impossible<S::Ok, S::Error>;
+    type SerializeTupleStruct = Impossible<S::Ok, S::Error>;
+    type SerializeTupleVariant = Impossible<S::Ok, S::Error>;
+    type SerializeMap = Impossible<S::Ok, S::Error>;
+    type SerializeStruct = SkipFieldsStruct<S::SerializeStruct>;
+    type SerializeStructVariant = Impossible<S::Ok, S::Error>;
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct, Self::Error> {
+        let inner = self.inner.serialize_struct(name, len)?;
+        Ok(SkipFieldsStruct { inner, skipped: self.skipped })
+    }
+
+    fn serialize_bool(self, _: bool) -> Result<S::Ok, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_i8(self, _: i8) -> Result<S::Ok, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_i16(self, _: i16) -> Result<S::Ok, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_i32(self, _: i32) -> Result<S::Ok, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_i64(self, _: i64) -> Result<S::Ok, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_u8(self, _: u8) -> Result<S::Ok, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_u16(self, _: u16) -> Result<S::Ok, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_u32(self, _: u32) -> Result<S::Ok, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_u64(self, _: u64) -> Result<S::Ok, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_f32(self, _: f32) -> Result<S::Ok, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_f64(self, _: f64) -> Result<S::Ok, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_char(self, _: char) -> Result<S::Ok, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_str(self, _: &str) -> Result<S::Ok, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_bytes(self, _: &[u8]) -> Result<S::Ok, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_none(self) -> Result<S::Ok, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_some<V: ?Sized + Serialize>(self, _: &V) -> Result<S::Ok, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_unit(self) -> Result<S::Ok, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_unit_struct(self, _: &'static str) -> Result<S::Ok, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_unit_variant(self, _: &'static str, _: u32, _: &'static str) -> Result<S::Ok, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_newtype_struct<V: ?Sized + Serialize>(self, _: &'static str, _: &V) -> Result<S::Ok, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_newtype_variant<V: ?Sized + Serialize>(self, _: &'static str, _: u32, _: &'static str, _: &V) -> Result<S::Ok, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_tuple_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeTupleStruct, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_tuple_variant(self, _: &'static str, _: u32, _: &'static str, _: usize) -> Result<Self::SerializeTupleVariant, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, S::Error> {
+        Err(unsupported())
+    }
+    fn serialize_struct_variant(self, _: &'static str, _: u32, _: &'static str, _: usize) -> Result<Self::SerializeStructVariant, S::Error> {
+        Err(unsupported())
+    }
+}
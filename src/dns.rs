@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use colored::*;
+use serde::Deserialize;
+use crate::errors::ErrorCode;
+
+// 設定檔 [dns] 區段
+// 系統解析器不提供記錄的 TTL，快取期限以此設定為準
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DnsConfig {
+    // 成功解析的保留秒數
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+
+    // 解析失敗 (NXDOMAIN 等) 的保留秒數
+    #[serde(default = "default_negative_ttl_secs")]
+    pub negative_ttl_secs: u64,
+}
+
+impl Default for DnsConfig {
+    fn default() -> Self {
+        DnsConfig {
+            ttl_secs: default_ttl_secs(),
+            negative_ttl_secs: default_negative_ttl_secs(),
+        }
+    }
+}
+
+fn default_ttl_secs() -> u64 {
+    300
+}
+
+fn default_negative_ttl_secs() -> u64 {
+    30
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecordType {
+    A,
+    Aaaa,
+}
+
+impl RecordType {
+    fn of(addr: &IpAddr) -> Self {
+        match addr {
+            IpAddr::V4(_) => RecordType::A,
+            IpAddr::V6(_) => RecordType::Aaaa,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    // Err 為解析失敗的訊息 (負快取)
+    addrs: Result<Vec<IpAddr>, String>,
+    expires: Instant,
+}
+
+// 快取命中統計
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    // 命中中屬於負快取的次數
+    pub negative_hits: u64,
+}
+
+// 以 (名稱, 記錄類型) 為鍵的 DNS 快取；一次系統查詢同時填入 A 與 AAAA
+// 每次執行建立一份，由 ScanContext 帶著
+#[derive(Debug)]
+pub struct DnsCache {
+    ttl: Duration,
+    negative_ttl: Duration,
+    entries: Mutex<HashMap<(String, RecordType), Entry>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    negative_hits: AtomicU64,
+}
+
+impl DnsCache {
+    pub fn new(ttl: Duration, negative_ttl: Duration) -> Self {
+        DnsCache {
+            ttl,
+            negative_ttl,
+            entries: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            negative_hits: AtomicU64::new(0),
+        }
+    }
+
+    pub fn from_config(config: &DnsConfig) -> Self {
+        DnsCache::new(Duration::from_secs(config.ttl_secs), Duration::from_secs(config.negative_ttl_secs))
+    }
+
+    // 尚未過期的快取項目
+    fn cached(&self, name: &str, record: RecordType) -> Option<Result<Vec<IpAddr>, String>> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let key = (name.to_ascii_lowercase(), record);
+        match entries.get(&key) {
+            Some(entry) if entry.expires > Instant::now() => Some(entry.addrs.clone()),
+            Some(_) => {
+                entries.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn store(&self, name: &str, answer: &Result<Vec<IpAddr>, String>) {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        for record in [RecordType::A, RecordType::Aaaa] {
+            let entry = match answer {
+                Ok(addrs) => Entry {
+                    addrs: Ok(addrs.iter().copied().filter(|a| RecordType::of(a) == record).collect()),
+                    expires: now + self.ttl,
+                },
+                Err(e) => Entry {
+                    addrs: Err(e.clone()),
+                    expires: now + self.negative_ttl,
+                },
+            };
+            entries.insert((name.to_ascii_lowercase(), record), entry);
+        }
+    }
+
+    // A 與 AAAA 都在快取中時不查詢；每次呼叫只計一次命中或未命中
+    async fn answer(&self, name: &str) -> Result<Vec<IpAddr>, String> {
+        if let (Some(a), Some(aaaa)) = (self.cached(name, RecordType::A), self.cached(name, RecordType::Aaaa)) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return match (a, aaaa) {
+                (Ok(mut a), Ok(aaaa)) => {
+                    a.extend(aaaa);
+                    Ok(a)
+                }
+                (Err(e), _) | (_, Err(e)) => {
+                    self.negative_hits.fetch_add(1, Ordering::Relaxed);
+                    Err(e)
+                }
+            };
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let answer = match tokio::net::lookup_host((name, 0)).await {
+            Ok(addrs) => {
+                let mut addrs: Vec<IpAddr> = addrs.map(|a| a.ip()).collect();
+                addrs.dedup();
+                if addrs.is_empty() {
+                    Err(format!("無法解析目標: {}", name))
+                } else {
+                    Ok(addrs)
+                }
+            }
+            Err(e) => Err(format!("無法解析目標 {}: {}", name, e)),
+        };
+        self.store(name, &answer);
+        answer
+    }
+
+    // 主機名稱的第一個位址，有 A 記錄時優先使用 IPv4
+    pub async fn resolve(&self, name: &str) -> Result<IpAddr, String> {
+        if let Ok(addr) = name.parse::<IpAddr>() {
+            return Ok(addr);
+        }
+        preferred(&self.answer(name).await?).ok_or_else(|| format!("無法解析目標: {}", name))
+    }
+
+    // 主機名稱的所有 A 與 AAAA 位址
+    pub async fn addresses(&self, name: &str) -> Result<Vec<IpAddr>, String> {
+        self.answer(name).await
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            negative_hits: self.negative_hits.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// 掃描使用的位址：有 IPv4 時用第一個 IPv4，否則用第一個位址
+pub fn preferred(addrs: &[IpAddr]) -> Option<IpAddr> {
+    addrs.iter().find(|a| a.is_ipv4()).or_else(|| addrs.first()).copied()
+}
+
+// 系統解析器回報的暫時或無法復原的失敗 (getaddrinfo 的 EAI_AGAIN / EAI_FAIL、Windows 的 WSATRY_AGAIN / WSANO_RECOVERY)
+// 代表掃描端的 DNS 無法使用；名稱不存在 (NXDOMAIN、EAI_NONAME) 是目標本身的問題
+const UNAVAILABLE: [&str; 4] = [
+    "temporary failure in name resolution",
+    "non-recoverable failure in name resolution",
+    "(os error 11002)",
+    "(os error 11003)",
+];
+
+// 解析失敗的錯誤代碼：解析器無法使用時為掃描端錯誤 E2007，其他為 E1001
+pub fn failure_code(error: &str) -> ErrorCode {
+    let error = error.to_ascii_lowercase();
+    match UNAVAILABLE.iter().any(|message| error.contains(message)) {
+        true => ErrorCode::DnsUnavailable,
+        false => ErrorCode::DnsResolutionFailed,
+    }
+}
+
+// 未讀取設定檔時 (check、selftest) 使用預設期限
+impl Default for DnsCache {
+    fn default() -> Self {
+        DnsCache::from_config(&DnsConfig::default())
+    }
+}
+
+// --verbose 時顯示的快取統計，沒有查詢時不顯示
+pub fn display_stats(stats: &CacheStats) {
+    if stats.hits + stats.misses == 0 {
+        return;
+    }
+    println!(
+        "{} 查詢 {} 次，命中快取 {} 次 (其中 {} 次為解析失敗)",
+        "DNS:".bold(),
+        stats.hits + stats.misses,
+        stats.hits,
+        stats.negative_hits
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LONG: Duration = Duration::from_secs(3600);
+
+    fn addrs(items: &[&str]) -> Vec<IpAddr> {
+        items.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn answers_split_by_record_type_and_are_reused() {
+        let cache = DnsCache::new(LONG, LONG);
+        cache.store("Web.Example", &Ok(addrs(&["2001:db8::1", "192.0.2.1", "192.0.2.2"])));
+        assert_eq!(cache.cached("web.example", RecordType::A), Some(Ok(addrs(&["192.0.2.1", "192.0.2.2"]))));
+        assert_eq!(cache.cached("WEB.EXAMPLE", RecordType::Aaaa), Some(Ok(addrs(&["2001:db8::1"]))));
+
+        // 快取中的名稱不會再查詢，IPv4 優先
+        assert_eq!(cache.resolve("web.example").await, Ok("192.0.2.1".parse().unwrap()));
+        assert_eq!(cache.addresses("web.example").await.unwrap().len(), 3);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.negative_hits), (2, 0, 0));
+    }
+
+    #[tokio::test]
+    async fn misses_are_resolved_once_then_hit() {
+        let cache = DnsCache::new(LONG, LONG);
+        // IP 字面值不經過快取
+        assert_eq!(cache.resolve("192.0.2.7").await, Ok("192.0.2.7".parse().unwrap()));
+        assert_eq!(cache.stats().misses, 0);
+
+        // 系統解析器對 IP 字面值直接回答，不需要網路
+        assert_eq!(cache.addresses("127.0.0.1").await, Ok(addrs(&["127.0.0.1"])));
+        assert_eq!(cache.addresses("127.0.0.1").await, Ok(addrs(&["127.0.0.1"])));
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn failures_are_negatively_cached() {
+        let cache = DnsCache::new(LONG, LONG);
+        let nxdomain = "無法解析目標 missing.example: NXDOMAIN".to_string();
+        cache.store("missing.example", &Err(nxdomain.clone()));
+        assert_eq!(cache.resolve("missing.example").await, Err(nxdomain.clone()));
+        assert_eq!(cache.addresses("missing.example").await, Err(nxdomain));
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.negative_hits), (2, 0, 2));
+    }
+
+    #[test]
+    fn entries_expire_after_their_ttl() {
+        // 負快取的期限較短，各自計算
+        let cache = DnsCache::new(LONG, Duration::ZERO);
+        cache.store("up.example", &Ok(addrs(&["192.0.2.1"])));
+        cache.store("down.example", &Err("無法解析目標: down.example".to_string()));
+        assert!(cache.cached("up.example", RecordType::A).is_some());
+        assert_eq!(cache.cached("down.example", RecordType::A), None);
+        // 過期的項目會被移除
+        assert!(!cache.entries.lock().unwrap().contains_key(&("down.example".to_string(), RecordType::A)));
+
+        let cache = DnsCache::new(Duration::from_millis(20), LONG);
+        cache.store("up.example", &Ok(addrs(&["192.0.2.1"])));
+        assert!(cache.cached("up.example", RecordType::Aaaa).is_some());
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(cache.cached("up.example", RecordType::Aaaa), None);
+        assert_eq!(cache.cached("up.example", RecordType::A), None);
+    }
+
+    #[test]
+    fn resolver_outages_are_scanner_errors() {
+        let outage = "無法解析目標 db01.example: failed to lookup address information: Temporary failure in name resolution";
+        assert_eq!(failure_code(outage), ErrorCode::DnsUnavailable);
+        let windows = "無法解析目標 db01.example: This is usually a temporary error during hostname resolution. (os error 11002)";
+        assert_eq!(failure_code(windows), ErrorCode::DnsUnavailable);
+        let nxdomain = "無法解析目標 gone.example: failed to lookup address information: Name or service not known";
+        assert_eq!(failure_code(nxdomain), ErrorCode::DnsResolutionFailed);
+        assert_eq!(failure_code("無法解析目標: empty.example"), ErrorCode::DnsResolutionFailed);
+    }
+
+    #[test]
+    fn ipv4_is_preferred() {
+        assert_eq!(preferred(&addrs(&["2001:db8::1", "192.0.2.9"])), Some("192.0.2.9".parse().unwrap()));
+        assert_eq!(preferred(&addrs(&["2001:db8::1"])), Some("2001:db8::1".parse().unwrap()));
+        assert_eq!(preferred(&[]), None);
+        let config: DnsConfig = toml::from_str("ttl_secs = 60").unwrap();
+        assert_eq!((config.ttl_secs, config.negative_ttl_secs), (60, 30));
+    }
+}
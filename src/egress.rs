@@ -0,0 +1,569 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::errors::ErrorCode;
+use crate::portspec;
+use crate::targets::{self, TargetSpec};
+use crate::vars::Variables;
+use crate::{PortInfo, ScanResult};
+
+// 不允許的出站路徑可以連線時的結束代碼
+pub const EXIT_DISALLOWED_OPEN: i32 = 10;
+
+// 允許的出站路徑無法連線時的結束代碼
+pub const EXIT_ALLOWED_BLOCKED: i32 = 11;
+
+// 每個目的地預設抽樣的組合數
+const DEFAULT_PER_DESTINATION: usize = 3;
+
+// 預設的抽樣端口：常被用來繞過出站限制或外洩資料的服務，依序取用
+const SAMPLE_PORTS: &[u16] = &[22, 443, 80, 53, 25, 3389, 445, 8080, 1080, 3128, 6667, 4444];
+
+// 出站政策檔 (--egress-policy)，例如
+//   name = "prod-egress"
+//   [[allow]]
+//   destinations = ["db.internal", "10.0.5.10"]
+//   ports = "5432"
+//   [[deny]]
+//   destinations = ["8.8.8.8"]
+//   ports = "53"
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EgressFile {
+    name: String,
+    #[serde(default)]
+    allow: Vec<RuleFile>,
+    #[serde(default)]
+    deny: Vec<RuleFile>,
+    #[serde(default)]
+    sample: SampleFile,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    destinations: Vec<String>,
+    ports: String,
+    reason: Option<String>,
+}
+
+// [sample]：[[deny]] 以外額外探測的不允許組合
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SampleFile {
+    // 每個目的地最多抽樣的組合數；0 時只探測 [[deny]] 列出的組合
+    per_destination: usize,
+    // 允許的目的地上抽樣的端口 (預設為常見的繞過與外洩端口)
+    ports: Option<String>,
+    // 未列入 [[allow]] 的目的地，以允許的端口抽樣，確認防火牆也依目的地限制
+    destinations: Vec<String>,
+}
+
+impl Default for SampleFile {
+    fn default() -> Self {
+        SampleFile { per_destination: DEFAULT_PER_DESTINATION, ports: None, destinations: Vec::new() }
+    }
+}
+
+// 組合的來源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Origin {
+    Allow,
+    Deny,
+    // 抽樣的不允許組合
+    Sample,
+}
+
+// 計劃探測的一個 (目的地, 端口)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedPath {
+    pub destination: String,
+    pub port: u16,
+    pub origin: Origin,
+    pub reason: Option<String>,
+}
+
+impl PlannedPath {
+    pub fn allowed(&self) -> bool {
+        self.origin == Origin::Allow
+    }
+}
+
+// 驗證過的出站政策
+#[derive(Debug, Clone)]
+pub struct EgressPolicy {
+    pub name: String,
+    pub path: String,
+    pub paths: Vec<PlannedPath>,
+}
+
+// 目的地只能是單一主機 (主機名稱或 IP)，組合才能逐一判定
+fn destination(raw: &str, location: &str) -> Result<String, String> {
+    let raw = raw.trim();
+    if let Ok(addr) = raw.parse::<IpAddr>() {
+        return Ok(addr.to_string());
+    }
+    if raw.is_empty() || raw.contains(['/', '*', ',']) {
+        return Err(format!("{}: 目的地必須是主機名稱或 IP 位址 (不支援網段與萬用字元): {}", location, raw));
+    }
+    targets::to_ascii_hostname(raw).map(|name| name.to_ascii_lowercase()).map_err(|e| format!("{}: {}", location, e))
+}
+
+fn expand(rules: &[RuleFile], origin: Origin, location: &str, paths: &mut Vec<PlannedPath>) -> Result<(), String> {
+    for rule in rules {
+        if rule.destinations.is_empty() {
+            return Err(format!("{}: destinations 不能是空的", location));
+        }
+        let ports = portspec::parse_list(&rule.ports).map_err(|e| format!("{}: {}", location, e))?;
+        for raw in &rule.destinations {
+            let destination = destination(raw, location)?;
+            for &port in &ports {
+                paths.push(PlannedPath { destination: destination.clone(), port, origin, reason: rule.reason.clone() });
+            }
+        }
+    }
+    Ok(())
+}
+
+// 依出現順序去除重複的目的地
+fn unique<'a>(destinations: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut seen = Vec::new();
+    for destination in destinations {
+        if !seen.contains(&destination) {
+            seen.push(destination);
+        }
+    }
+    seen
+}
+
+impl EgressPolicy {
+    pub fn load(path: &Path, vars: &Variables) -> Result<Self, String> {
+        let location = path.display().to_string();
+        let text = fs::read_to_string(path).map_err(|e| format!("無法讀取出站政策 {}: {}", location, e))?;
+        EgressPolicy::parse(&text, &location, vars)
+    }
+
+    fn parse(text: &str, location: &str, vars: &Variables) -> Result<Self, String> {
+        let mut table: toml::Table = toml::from_str(text).map_err(|e| format!("{}: 格式錯誤: {}", location, e))?;
+        vars.expand_toml(&mut table).map_err(|e| format!("{}: {}", location, e))?;
+        let file: EgressFile = toml::Value::Table(table).try_into().map_err(|e| format!("{}: 格式錯誤: {}", location, e))?;
+        if file.name.trim().is_empty() {
+            return Err(format!("{}: 缺少 name", location));
+        }
+        if file.allow.is_empty() {
+            return Err(format!("{}: 至少需要一個 [[allow]]", location));
+        }
+
+        let mut listed = Vec::new();
+        expand(&file.allow, Origin::Allow, location, &mut listed)?;
+        expand(&file.deny, Origin::Deny, location, &mut listed)?;
+        let mut paths: Vec<PlannedPath> = Vec::new();
+        for path in listed {
+            match paths.iter().find(|p| p.destination == path.destination && p.port == path.port) {
+                Some(existing) if existing.origin != path.origin => {
+                    return Err(format!("{}: {}:{} 同時列在 [[allow]] 與 [[deny]]", location, path.destination, path.port));
+                }
+                Some(_) => {}
+                None => paths.push(path),
+            }
+        }
+
+        // 抽樣：允許的目的地取不允許的端口，未列入的目的地取允許的端口
+        let candidates: Vec<u16> = match &file.sample.ports {
+            Some(spec) => portspec::parse_list(spec).map_err(|e| format!("{}: {}", location, e))?.into_iter().collect(),
+            None => SAMPLE_PORTS.to_vec(),
+        };
+        let allowed_ports: BTreeSet<u16> = paths.iter().filter(|p| p.allowed()).map(|p| p.port).collect();
+        let allowed: Vec<String> =
+            unique(paths.iter().filter(|p| p.allowed()).map(|p| p.destination.as_str())).into_iter().map(str::to_string).collect();
+        let mut sampled = Vec::new();
+        for destination in &allowed {
+            sampled.push((destination.clone(), candidates.clone()));
+        }
+        for raw in &file.sample.destinations {
+            let destination = destination(raw, location)?;
+            if allowed.contains(&destination) {
+                return Err(format!("{}: 抽樣的目的地 {} 已列在 [[allow]]", location, destination));
+            }
+            sampled.push((destination, allowed_ports.iter().copied().collect()));
+        }
+        for (destination, candidates) in sampled {
+            let picked: Vec<u16> = candidates
+                .into_iter()
+                .filter(|port| !paths.iter().any(|p| p.destination == destination && p.port == *port))
+                .take(file.sample.per_destination)
+                .collect();
+            for port in picked {
+                paths.push(PlannedPath { destination: destination.clone(), port, origin: Origin::Sample, reason: None });
+            }
+        }
+
+        Ok(EgressPolicy { name: file.name, path: location.to_string(), paths })
+    }
+
+    // 所有目的地，作為掃描目標
+    pub fn target_spec(&self) -> String {
+        unique(self.paths.iter().map(|p| p.destination.as_str())).join(",")
+    }
+
+    // 所有組合的端口
+    pub fn port_spec(&self) -> String {
+        let ports: BTreeSet<u16> = self.paths.iter().map(|p| p.port).collect();
+        ports.iter().map(u16::to_string).collect::<Vec<_>>().join(",")
+    }
+
+    // 目標與端口的組合中不在政策內的，併入 ScanPlan.completed，掃描時略過
+    pub fn skipped(&self, targets: &[TargetSpec], ports: &[PortInfo]) -> HashSet<(IpAddr, u16)> {
+        let planned: HashSet<(IpAddr, u16)> = self
+            .paths
+            .iter()
+            .flat_map(|path| hosts(&path.destination, targets).into_iter().map(move |host| (host, path.port)))
+            .collect();
+        targets
+            .iter()
+            .flat_map(TargetSpec::addrs)
+            .flat_map(|host| ports.iter().map(move |port| (host, port.port)))
+            .filter(|pair| !planned.contains(pair))
+            .collect()
+    }
+}
+
+// 目的地對應的掃描位址；主機名稱可能解析出多個位址
+fn hosts(destination: &str, targets: &[TargetSpec]) -> Vec<IpAddr> {
+    let mut hosts: Vec<IpAddr> = targets
+        .iter()
+        .filter_map(|target| match target {
+            TargetSpec::Host { name, addr } if name.eq_ignore_ascii_case(destination) || addr.to_string() == destination => {
+                Some(*addr)
+            }
+            _ => None,
+        })
+        .collect();
+    hosts.dedup();
+    hosts
+}
+
+// 一個組合的出站探測結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PathState {
+    Open,
+    Blocked,
+    // 掃描端錯誤，無法判斷
+    Error,
+    // 連線被透明代理攔截，無法確認實際的路徑
+    Intercepted,
+    // 目的地無法解析或被排除，沒有掃描
+    NotScanned,
+}
+
+impl PathState {
+    fn label(self) -> ColoredString {
+        match self {
+            PathState::Open => "可連線".green(),
+            PathState::Blocked => "無法連線".red(),
+            PathState::Error => "掃描端錯誤".yellow(),
+            PathState::Intercepted => "被攔截".yellow(),
+            PathState::NotScanned => "未掃描".yellow(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct EgressPath {
+    pub destination: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<IpAddr>,
+    pub port: u16,
+    pub origin: Origin,
+    pub state: PathState,
+    // 連線失敗的方式 (可連線時省略)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub violation: bool,
+}
+
+impl EgressPath {
+    // 無法判斷 (不是違規也不是符合)
+    fn unverified(&self) -> bool {
+        !self.violation && !matches!(self.state, PathState::Open | PathState::Blocked)
+    }
+}
+
+// 報告的一節：允許或不允許的路徑
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct EgressSection {
+    pub total: usize,
+    pub violations: usize,
+    pub unverified: usize,
+    pub paths: Vec<EgressPath>,
+}
+
+impl EgressSection {
+    fn of(paths: Vec<EgressPath>) -> Self {
+        EgressSection {
+            total: paths.len(),
+            violations: paths.iter().filter(|p| p.violation).count(),
+            unverified: paths.iter().filter(|p| p.unverified()).count(),
+            paths,
+        }
+    }
+}
+
+// 出站政策的合規報告：允許的路徑都能連線，不允許的路徑都無法連線
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct EgressReport {
+    pub policy: String,
+    pub file: String,
+    pub passed: bool,
+    pub allowed: EgressSection,
+    pub disallowed: EgressSection,
+}
+
+impl EgressReport {
+    // 違規時的錯誤代碼與訊息；不允許的路徑可以連線優先
+    pub fn failure(&self) -> Option<(ErrorCode, String)> {
+        let blocked = format!("{} 個允許的出站路徑無法連線", self.allowed.violations);
+        let open = format!("{} 個不允許的出站路徑可以連線", self.disallowed.violations);
+        match (self.allowed.violations, self.disallowed.violations) {
+            (0, 0) => None,
+            (0, _) => Some((ErrorCode::EgressDisallowedOpen, open)),
+            (_, 0) => Some((ErrorCode::EgressAllowedBlocked, blocked)),
+            _ => Some((ErrorCode::EgressDisallowedOpen, format!("{}，{}", open, blocked))),
+        }
+    }
+}
+
+fn state(result: Option<&ScanResult>) -> (PathState, Option<ErrorCode>) {
+    match result {
+        None => (PathState::NotScanned, None),
+        Some(result) if result.error.is_some() => (PathState::Error, result.codes.first().copied()),
+        Some(result) if result.intercepted => (PathState::Intercepted, None),
+        Some(result) if result.outbound => (PathState::Open, None),
+        Some(result) => (PathState::Blocked, result.codes.first().copied()),
+    }
+}
+
+// 依出站探測結果判定每個組合
+pub fn evaluate(
+    policy: &EgressPolicy,
+    targets: &[TargetSpec],
+    results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>,
+) -> EgressReport {
+    let (mut allowed, mut disallowed) = (Vec::new(), Vec::new());
+    for path in &policy.paths {
+        let hosts = hosts(&path.destination, targets);
+        let found: Vec<(Option<IpAddr>, Option<&ScanResult>)> = match hosts.is_empty() {
+            true => vec![(None, None)],
+            false => hosts
+                .iter()
+                .map(|host| {
+                    let result = results.get(host).and_then(|r| r.iter().find(|(port, _)| port.port == path.port));
+                    (Some(*host), result.map(|(_, result)| result))
+                })
+                .collect(),
+        };
+        for (host, result) in found {
+            let (state, code) = state(result);
+            let violation = match path.allowed() {
+                true => matches!(state, PathState::Blocked | PathState::NotScanned),
+                false => state == PathState::Open,
+            };
+            let checked = EgressPath {
+                destination: path.destination.clone(),
+                host,
+                port: path.port,
+                origin: path.origin,
+                state,
+                code,
+                reason: path.reason.clone(),
+                violation,
+            };
+            match path.allowed() {
+                true => allowed.push(checked),
+                false => disallowed.push(checked),
+            }
+        }
+    }
+    let (allowed, disallowed) = (EgressSection::of(allowed), EgressSection::of(disallowed));
+    EgressReport {
+        policy: policy.name.clone(),
+        file: policy.path.clone(),
+        passed: allowed.violations == 0 && disallowed.violations == 0,
+        allowed,
+        disallowed,
+    }
+}
+
+fn display_section(title: &str, section: &EgressSection, expected: PathState) {
+    let matched = section.paths.iter().filter(|p| p.state == expected).count();
+    let summary = format!("{}: {}/{} {}", title, matched, section.total, expected.label());
+    match section.violations {
+        0 => println!("{}", summary.bold()),
+        _ => println!("{}", summary.red().bold()),
+    }
+    // 符合的組合只計數，違規與無法判斷的逐一列出
+    for path in section.paths.iter().filter(|p| p.violation || p.unverified()) {
+        let target = match path.host {
+            Some(host) if host.to_string() != path.destination => format!("{} ({}):{}", path.destination, host, path.port),
+            _ => format!("{}:{}", path.destination, path.port),
+        };
+        let mark = if path.violation { "✗".red() } else { "?".yellow() };
+        let code = path.code.map(|code| format!(" [{}]", code.code())).unwrap_or_default();
+        let origin = if path.origin == Origin::Sample { " (抽樣)".dimmed().to_string() } else { String::new() };
+        let reason = path.reason.as_deref().map(|r| format!(" — {}", r).dimmed().to_string()).unwrap_or_default();
+        println!("  {} {} {}{}{}{}", mark, target, path.state.label(), code, origin, reason);
+    }
+}
+
+pub fn display_report(report: &EgressReport) {
+    println!("\n{}", format!("=== 出站政策 {} ({}) ===", report.policy, report.file).bold());
+    display_section("允許的路徑", &report.allowed, PathState::Open);
+    display_section("不允許的路徑", &report.disallowed, PathState::Blocked);
+    match report.failure() {
+        None => println!("{}", "符合出站政策".green().bold()),
+        Some((_, message)) => println!("{}", message.red().bold()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::ScanError;
+    use crate::testutil::{host, scan_result};
+
+    const POLICY: &str = r#"
+name = "prod-egress"
+
+[[allow]]
+destinations = ["DB.internal", "192.0.2.10"]
+ports = "5432"
+reason = "資料庫"
+
+[[allow]]
+destinations = ["192.0.2.10"]
+ports = "443"
+
+[[deny]]
+destinations = ["192.0.2.53"]
+ports = "53"
+reason = "不得直接使用外部 DNS"
+
+[sample]
+per_destination = 2
+destinations = ["192.0.2.99"]
+"#;
+
+    fn parse(text: &str) -> Result<EgressPolicy, String> {
+        EgressPolicy::parse(text, "egress.toml", &Variables::default())
+    }
+
+    fn pairs(policy: &EgressPolicy) -> Vec<(&str, u16, Origin)> {
+        policy.paths.iter().map(|p| (p.destination.as_str(), p.port, p.origin)).collect()
+    }
+
+    fn named(name: &str, n: u8) -> TargetSpec {
+        TargetSpec::Host { name: name.to_string(), addr: host(n) }
+    }
+
+    #[test]
+    fn sample_adds_disallowed_pairs_per_destination() {
+        let policy = parse(POLICY).unwrap();
+        assert_eq!(
+            pairs(&policy),
+            [
+                ("db.internal", 5432, Origin::Allow),
+                ("192.0.2.10", 5432, Origin::Allow),
+                ("192.0.2.10", 443, Origin::Allow),
+                ("192.0.2.53", 53, Origin::Deny),
+                // 允許的目的地依序取預設端口中不允許的 (192.0.2.10 已允許 443)
+                ("db.internal", 22, Origin::Sample),
+                ("db.internal", 443, Origin::Sample),
+                ("192.0.2.10", 22, Origin::Sample),
+                ("192.0.2.10", 80, Origin::Sample),
+                // 未列入的目的地取允許的端口
+                ("192.0.2.99", 443, Origin::Sample),
+                ("192.0.2.99", 5432, Origin::Sample),
+            ]
+        );
+        assert_eq!(policy.target_spec(), "db.internal,192.0.2.10,192.0.2.53,192.0.2.99");
+        assert_eq!(policy.port_spec(), "22,53,80,443,5432");
+
+        let only_listed = parse(&POLICY.replace("per_destination = 2", "per_destination = 0")).unwrap();
+        assert_eq!(only_listed.paths.len(), 4);
+    }
+
+    #[test]
+    fn invalid_policies_are_rejected() {
+        let err = |text: &str| parse(text).unwrap_err();
+        assert!(err("name = \"x\"\n").contains("[[allow]]"));
+        assert!(err("name = \"x\"\n[[allow]]\ndestinations = [\"10.0.0.0/24\"]\nports = \"443\"").contains("不支援網段"));
+        let conflict = "name = \"x\"\n[[allow]]\ndestinations = [\"a.example\"]\nports = \"443\"\n[[deny]]\ndestinations = [\"A.example\"]\nports = \"443\"";
+        assert!(err(conflict).contains("a.example:443 同時列在"), "{}", err(conflict));
+        let sample = "name = \"x\"\n[[allow]]\ndestinations = [\"a.example\"]\nports = \"443\"\n[sample]\ndestinations = [\"a.example\"]";
+        assert!(err(sample).contains("已列在 [[allow]]"));
+    }
+
+    #[test]
+    fn only_planned_pairs_are_scanned() {
+        let policy = parse(POLICY).unwrap();
+        let targets = vec![named("db.internal", 1), named("192.0.2.10", 10), named("192.0.2.53", 53), named("192.0.2.99", 99)];
+        let ports: Vec<PortInfo> = [22, 53, 80, 443, 5432].iter().map(|&p| PortInfo::new(p, "Test", "Test")).collect();
+        let skipped = policy.skipped(&targets, &ports);
+        // 4 台主機 x 5 個端口，計劃中的 10 個組合之外都略過
+        assert_eq!(skipped.len(), 10);
+        assert!(skipped.contains(&(host(53), 443)));
+        assert!(!skipped.contains(&(host(53), 53)));
+        assert!(!skipped.contains(&(host(1), 22)));
+    }
+
+    #[test]
+    fn violations_are_reported_in_both_directions() {
+        let policy = parse(POLICY).unwrap();
+        // db.internal 無法解析，沒有掃描
+        let targets = vec![named("192.0.2.10", 10), named("192.0.2.53", 53), named("192.0.2.99", 99)];
+        let result = |open: bool| scan_result(open);
+        let mut failed = scan_result(false);
+        failed.error = Some(ScanError::TooManyOpenFiles);
+        let port = |p: u16| PortInfo::new(p, "Test", "Test");
+        let results: BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>> = [
+            (host(10), HashMap::from([(port(5432), result(true)), (port(443), result(false)), (port(22), result(false)), (port(80), failed)])),
+            (host(53), HashMap::from([(port(53), result(true))])),
+            (host(99), HashMap::from([(port(443), result(false)), (port(5432), result(false))])),
+        ]
+        .into_iter()
+        .collect();
+
+        let report = evaluate(&policy, &targets, &results);
+        assert!(!report.passed);
+        let states = |section: &EgressSection| {
+            section.paths.iter().map(|p| (p.destination.clone(), p.port, p.state, p.violation)).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            states(&report.allowed),
+            [
+                ("db.internal".to_string(), 5432, PathState::NotScanned, true),
+                ("192.0.2.10".to_string(), 5432, PathState::Open, false),
+                ("192.0.2.10".to_string(), 443, PathState::Blocked, true),
+            ]
+        );
+        assert_eq!((report.allowed.violations, report.allowed.unverified), (2, 0));
+        // 外部 DNS 可以連線是違規；掃描端錯誤與未解析的抽樣無法判斷
+        assert_eq!((report.disallowed.total, report.disallowed.violations, report.disallowed.unverified), (7, 1, 3));
+        assert_eq!(report.disallowed.paths[0].state, PathState::Open);
+        assert_eq!(report.disallowed.paths[0].reason.as_deref(), Some("不得直接使用外部 DNS"));
+
+        let (code, message) = report.failure().unwrap();
+        assert_eq!(code, ErrorCode::EgressDisallowedOpen);
+        assert_eq!(message, "1 個不允許的出站路徑可以連線，2 個允許的出站路徑無法連線");
+        assert_eq!(code.exit_code(), EXIT_DISALLOWED_OPEN);
+        assert_eq!(ErrorCode::EgressAllowedBlocked.exit_code(), EXIT_ALLOWED_BLOCKED);
+    }
+}
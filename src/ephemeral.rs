@@ -0,0 +1,388 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use colored::*;
+use schemars::JsonSchema;
+use serde::Serialize;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior};
+use crate::adaptive::AdaptiveLimit;
+
+// 取樣系統連線計數的間隔
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+// 使用比例達到此值時警告 (只警告一次)；暫停後降回此值以下才恢復排程
+const WARN_USAGE: f64 = 0.7;
+
+// 使用比例達到此值時視為即將耗盡：暫停發出新的連線並降低自動並發數
+const THROTTLE_USAGE: f64 = 0.85;
+
+// 一次讀取的系統計數 (整個系統，不只本程式)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Sample {
+    // 本機暫時端口範圍 (ip_local_port_range) 的端口數
+    pub range: u64,
+    // 使用中與 TIME_WAIT 的 TCP socket
+    pub in_use: u64,
+    pub time_wait: u64,
+    // 連線追蹤表 (nf_conntrack) 的使用量與上限；未載入時為 None
+    pub conntrack: Option<(u64, u64)>,
+}
+
+impl Sample {
+    // 端口範圍與連線追蹤表中較接近上限的比例
+    pub fn usage(&self) -> f64 {
+        let ports = (self.in_use + self.time_wait) as f64 / self.range.max(1) as f64;
+        let table = self.conntrack.map_or(0.0, |(count, max)| count as f64 / max.max(1) as f64);
+        ports.max(table)
+    }
+}
+
+// ip_local_port_range 的內容 ("32768\t60999") 換算成端口數
+pub fn parse_port_range(text: &str) -> Option<u64> {
+    let mut bounds = text.split_whitespace().map(|n| n.parse::<u64>().ok());
+    let (low, high) = (bounds.next()??, bounds.next()??);
+    (high >= low).then(|| high - low + 1)
+}
+
+// /proc/net/sockstat 的 "TCP: inuse 18 orphan 0 tw 3 alloc 19 mem 115" 一行
+pub fn parse_sockstat(text: &str) -> Option<(u64, u64)> {
+    let line = text.lines().find_map(|line| line.strip_prefix("TCP:"))?;
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let field = |name: &str| fields.chunks(2).find(|pair| pair[0] == name).and_then(|pair| pair.get(1)?.parse().ok());
+    Some((field("inuse")?, field("tw")?))
+}
+
+// 讀取不到 (容器限制、權限) 時為 None，不監測也不顯示
+#[cfg(target_os = "linux")]
+pub fn read() -> Option<Sample> {
+    let text = |path: &str| std::fs::read_to_string(path).ok();
+    let range = parse_port_range(&text("/proc/sys/net/ipv4/ip_local_port_range")?)?;
+    let (in_use, time_wait) = parse_sockstat(&text("/proc/net/sockstat")?)?;
+    let number = |path: &str| text(path)?.trim().parse::<u64>().ok();
+    let conntrack =
+        number("/proc/sys/net/netfilter/nf_conntrack_count").zip(number("/proc/sys/net/netfilter/nf_conntrack_max"));
+    Some(Sample { range, in_use, time_wait, conntrack })
+}
+
+// 其他平台沒有對應的計數，不監測
+#[cfg(not(target_os = "linux"))]
+pub fn read() -> Option<Sample> {
+    None
+}
+
+// 監測工作每次取樣呼叫；測試以劇本代替 /proc
+pub type Reader = Box<dyn FnMut() -> Option<Sample> + Send>;
+
+#[derive(Debug, Default)]
+struct Record {
+    range: u64,
+    last_opened: u64,
+    last_exhausted: u64,
+    peak_rate: f64,
+    peak_time_wait: u64,
+    peak_usage: f64,
+    warned: bool,
+    pauses: usize,
+    paused_since: Option<Instant>,
+    paused_for: Duration,
+}
+
+// 本機端口壓力：出站連線數、TIME_WAIT 與端口耗盡的錯誤；即將耗盡時暫停排程
+// 只在出站掃描且能讀取系統計數時建立，放在 ScanPlan 中
+#[derive(Debug)]
+pub struct PortPressure {
+    opened: AtomicU64,
+    exhausted: AtomicU64,
+    paused: AtomicBool,
+    resume: Notify,
+    // --concurrency auto 時同時降低並發數，恢復後由控制器逐步放寬
+    adaptive: Option<Arc<AdaptiveLimit>>,
+    quiet: bool,
+    record: Mutex<Record>,
+}
+
+impl PortPressure {
+    pub fn new(adaptive: Option<Arc<AdaptiveLimit>>, quiet: bool) -> Self {
+        PortPressure {
+            opened: AtomicU64::new(0),
+            exhausted: AtomicU64::new(0),
+            paused: AtomicBool::new(false),
+            resume: Notify::new(),
+            adaptive,
+            quiet,
+            record: Mutex::default(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Record> {
+        self.record.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // 排程器發出一個出站連線
+    pub fn opened(&self) {
+        self.opened.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // 探測遇到 EADDRINUSE / EADDRNOTAVAIL：本機端口已經用盡
+    pub fn exhausted(&self) {
+        self.exhausted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // 暫停期間等到恢復；只有排程器會呼叫
+    pub async fn admit(&self) {
+        loop {
+            let notified = self.resume.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if !self.paused.load(Ordering::SeqCst) {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    // 每次取樣後評估；elapsed 為與上次取樣的間隔
+    fn observe(&self, sample: Option<Sample>, elapsed: Duration) {
+        let mut record = self.lock();
+        let opened = self.opened.load(Ordering::Relaxed);
+        let rate = (opened - record.last_opened) as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+        record.last_opened = opened;
+        record.peak_rate = record.peak_rate.max(rate);
+        let exhausted = self.exhausted.load(Ordering::Relaxed);
+        let errors = exhausted > record.last_exhausted;
+        record.last_exhausted = exhausted;
+
+        let usage = sample.map_or(0.0, |sample| sample.usage());
+        if let Some(sample) = sample {
+            record.range = sample.range;
+            record.peak_time_wait = record.peak_time_wait.max(sample.time_wait);
+        }
+        record.peak_usage = record.peak_usage.max(usage);
+        if usage >= WARN_USAGE && !record.warned {
+            record.warned = true;
+            if !self.quiet {
+                let sample = sample.unwrap_or_default();
+                eprintln!(
+                    "{}",
+                    format!(
+                        "警告: 本機端口接近上限 ({:.0}%，TIME_WAIT {}，暫時端口 {} 個)；達到 {:.0}% 時自動暫停發出新的連線",
+                        usage * 100.0,
+                        sample.time_wait,
+                        sample.range,
+                        THROTTLE_USAGE * 100.0
+                    )
+                    .yellow()
+                );
+            }
+        }
+
+        if errors || usage >= THROTTLE_USAGE {
+            if record.paused_since.is_none() {
+                record.paused_since = Some(Instant::now());
+                record.pauses += 1;
+                self.paused.store(true, Ordering::SeqCst);
+                if let Some(adaptive) = &self.adaptive {
+                    adaptive.throttle();
+                }
+            }
+        } else if usage < WARN_USAGE {
+            self.resume_locked(&mut record);
+        }
+    }
+
+    fn resume_locked(&self, record: &mut Record) {
+        if let Some(since) = record.paused_since.take() {
+            record.paused_for += since.elapsed();
+            self.paused.store(false, Ordering::SeqCst);
+            self.resume.notify_waiters();
+        }
+    }
+
+    pub fn summary(&self) -> PressureSummary {
+        let record = self.lock();
+        let paused_for = record.paused_for + record.paused_since.map_or(Duration::ZERO, |since| since.elapsed());
+        PressureSummary {
+            port_range: record.range,
+            peak_connections_per_sec: record.peak_rate,
+            peak_time_wait: record.peak_time_wait,
+            peak_usage: record.peak_usage,
+            pauses: record.pauses,
+            paused_ms: paused_for.as_millis() as u64,
+            bind_errors: self.exhausted.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// 掃描期間每秒取樣一次
+pub struct Monitor {
+    pressure: Arc<PortPressure>,
+    sampler: JoinHandle<()>,
+}
+
+impl Monitor {
+    pub fn start(pressure: Arc<PortPressure>, mut read: Reader) -> Self {
+        let shared = pressure.clone();
+        let sampler = tokio::spawn(async move {
+            let mut interval = tokio::time::interval_at(Instant::now() + SAMPLE_INTERVAL, SAMPLE_INTERVAL);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            let mut last = Instant::now();
+            loop {
+                interval.tick().await;
+                shared.observe(read(), last.elapsed());
+                last = Instant::now();
+            }
+        });
+        Monitor { pressure, sampler }
+    }
+
+    // 停止取樣；仍在暫停中的排程一併恢復
+    pub fn finish(self) -> PressureSummary {
+        self.sampler.abort();
+        self.pressure.resume_locked(&mut self.pressure.lock());
+        self.pressure.summary()
+    }
+}
+
+impl std::fmt::Debug for Monitor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Monitor").field("pressure", &self.pressure).finish()
+    }
+}
+
+// 摘要與 JSON 報告的端口壓力區段
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct PressureSummary {
+    // 本機暫時端口範圍的端口數
+    pub port_range: u64,
+    pub peak_connections_per_sec: f64,
+    pub peak_time_wait: u64,
+    // 端口範圍或連線追蹤表的最高使用比例 (0 到 1)
+    pub peak_usage: f64,
+    // 因端口即將耗盡而暫停排程的次數與總時間
+    pub pauses: usize,
+    pub paused_ms: u64,
+    // 因 EADDRINUSE / EADDRNOTAVAIL 失敗的探測數
+    pub bind_errors: u64,
+}
+
+impl PressureSummary {
+    // 沒有接近上限也沒有介入時不顯示
+    pub fn notable(&self) -> bool {
+        self.peak_usage >= WARN_USAGE || self.pauses > 0
+    }
+}
+
+pub fn display_summary(summary: &PressureSummary) {
+    println!(
+        "{} 每秒最多 {:.0} 個連線，TIME_WAIT 最高 {}，使用比例最高 {:.0}% (暫時端口 {} 個)",
+        "端口壓力:".bold(),
+        summary.peak_connections_per_sec,
+        summary.peak_time_wait,
+        summary.peak_usage * 100.0,
+        summary.port_range
+    );
+    if summary.pauses > 0 {
+        println!(
+            "{}",
+            format!(
+                "  自動暫停排程 {} 次，共 {:.1}s (端口耗盡錯誤 {} 個)",
+                summary.pauses,
+                summary.paused_ms as f64 / 1000.0,
+                summary.bind_errors
+            )
+            .yellow()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use crate::adaptive;
+    use crate::limits::ScanError;
+    use crate::prober::fake::{Script, Scripted, ScriptedProber};
+    use crate::testutil::{host, scan, scripted_plan};
+
+    fn scripted(time_wait: &[u64]) -> Reader {
+        let mut samples: VecDeque<u64> = time_wait.iter().copied().collect();
+        Box::new(move || {
+            let time_wait = samples.pop_front()?;
+            Some(Sample { range: 1000, in_use: 0, time_wait, conntrack: None })
+        })
+    }
+
+    #[test]
+    fn proc_files_are_parsed() {
+        assert_eq!(parse_port_range("32768\t60999\n"), Some(28232));
+        assert_eq!(parse_port_range("60999 32768"), None);
+        assert_eq!(parse_port_range(""), None);
+        let sockstat = "sockets: used 33\nTCP: inuse 18 orphan 0 tw 7 alloc 19 mem 115\nUDP: inuse 0 mem 0\n";
+        assert_eq!(parse_sockstat(sockstat), Some((18, 7)));
+        assert_eq!(parse_sockstat("UDP: inuse 0 mem 0\n"), None);
+
+        // 連線追蹤表較滿時以它為準
+        let sample = Sample { range: 1000, in_use: 100, time_wait: 100, conntrack: Some((90, 100)) };
+        assert!((sample.usage() - 0.9).abs() < 1e-9);
+        assert!((Sample { conntrack: None, ..sample }.usage() - 0.2).abs() < 1e-9);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn scheduler_pauses_until_time_wait_drains() {
+        let limit = Arc::new(AdaptiveLimit::new(adaptive::MAX, false));
+        let pressure = Arc::new(PortPressure::new(Some(limit.clone()), true));
+        let monitor = Monitor::start(pressure.clone(), scripted(&[100, 900, 800, 500, 100]));
+        for _ in 0..50 {
+            pressure.opened();
+        }
+        tokio::time::sleep(Duration::from_millis(1050)).await;
+        assert!(!pressure.paused.load(Ordering::SeqCst));
+
+        // 90%：暫停並把自動並發數減半
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(pressure.paused.load(Ordering::SeqCst));
+        assert_eq!(limit.summary().last, adaptive::INITIAL / 2);
+        let waiting = pressure.clone();
+        let admitted = tokio::spawn(async move {
+            waiting.admit().await;
+            Instant::now()
+        });
+
+        // 80% 仍在警告門檻以上，繼續暫停；50% 時恢復
+        let paused_at = Instant::now();
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        let resumed = admitted.await.unwrap();
+        assert_eq!(resumed - paused_at, Duration::from_millis(1950));
+        assert!(!pressure.paused.load(Ordering::SeqCst));
+
+        let summary = monitor.finish();
+        assert_eq!((summary.pauses, summary.paused_ms, summary.peak_time_wait), (1, 2000, 900));
+        assert_eq!((summary.port_range, summary.peak_connections_per_sec), (1000, 50.0));
+        assert!((summary.peak_usage - 0.9).abs() < 1e-9);
+        assert!(summary.notable());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bind_errors_pause_even_without_counts() {
+        let error = Script::new(Scripted::Error(ScanError::AddressInUse), Duration::from_millis(150));
+        let prober = Arc::new(ScriptedProber::new().fallback(error));
+        let ports: Vec<u16> = (1..=40).collect();
+        let mut plan = scripted_plan(&[host(1)], &ports, 4, prober);
+        let pressure = Arc::new(PortPressure::new(None, true));
+        plan.pressure = Some(pressure.clone());
+        // 讀不到系統計數時仍依探測錯誤暫停
+        let monitor = Monitor::start(pressure.clone(), Box::new(|| None));
+
+        let started = Instant::now();
+        let results = scan(&plan).await;
+        assert_eq!(results.len(), 40);
+        // 4 個並發、每個 150ms，不暫停只要 1.5s；1s 時暫停，2s 時仍有新的錯誤，3s 才恢復
+        assert!(started.elapsed() >= Duration::from_millis(3450), "{:?}", started.elapsed());
+        let summary = monitor.finish();
+        assert_eq!(summary.bind_errors, 40);
+        assert_eq!(summary.pauses, 1);
+        assert!(summary.notable());
+    }
+}
@@ -0,0 +1,424 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use clap::ValueEnum;
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::closure::Failure;
+use crate::icmp::IcmpError;
+use crate::limits::ScanError;
+
+// 錯誤代碼一經發佈就不再改變意義或移除；新增代碼放在所屬類別的最後
+// 第一位數為類別：1 目標、2 本機、3 探測、4 設定與選項、5 輸出、6 掃描結果的判定、9 其他
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema)]
+pub enum ErrorCode {
+    #[serde(rename = "E1001")]
+    DnsResolutionFailed,
+    #[serde(rename = "E1002")]
+    InvalidTarget,
+    #[serde(rename = "E1003")]
+    NoTargets,
+    #[serde(rename = "E1004")]
+    InvalidZone,
+    #[serde(rename = "E1005")]
+    TargetRefused,
+    #[serde(rename = "E2001")]
+    FdExhausted,
+    #[serde(rename = "E2002")]
+    LocalPortExhausted,
+    #[serde(rename = "E2003")]
+    BindPermissionDenied,
+    #[serde(rename = "E2004")]
+    BindAddressInUse,
+    #[serde(rename = "E2005")]
+    BindAddressNotAvailable,
+    #[serde(rename = "E2006")]
+    BindFailed,
+    #[serde(rename = "E2007")]
+    DnsUnavailable,
+    #[serde(rename = "E2008")]
+    ProbePanicked,
+    #[serde(rename = "E3001")]
+    ConnectionRefused,
+    #[serde(rename = "E3002")]
+    ProbeTimeout,
+    #[serde(rename = "E3003")]
+    NetworkUnreachable,
+    #[serde(rename = "E3004")]
+    IcmpUnreachable,
+    #[serde(rename = "E3005")]
+    AdminProhibited,
+    #[serde(rename = "E3006")]
+    ProxyDenied,
+    #[serde(rename = "E4001")]
+    ConfigInvalid,
+    #[serde(rename = "E4002")]
+    InvalidOptions,
+    #[serde(rename = "E5001")]
+    OutputFailed,
+    #[serde(rename = "E6001")]
+    NetworkSuspect,
+    #[serde(rename = "E6002")]
+    UnexpectedOpen,
+    #[serde(rename = "E6003")]
+    BundleFailed,
+    #[serde(rename = "E6004")]
+    PolicyFailed,
+    #[serde(rename = "E6005")]
+    PortsUnavailable,
+    #[serde(rename = "E6006")]
+    ScannerErrors,
+    #[serde(rename = "E6007")]
+    EgressDisallowedOpen,
+    #[serde(rename = "E6008")]
+    EgressAllowedBlocked,
+    #[serde(rename = "E9001")]
+    Unclassified,
+}
+
+// errors list 的說明語言
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Lang {
+    /// 繁體中文
+    #[default]
+    #[value(name = "zh-TW", alias = "zh")]
+    ZhTw,
+    /// English
+    #[value(name = "en")]
+    En,
+}
+
+impl ErrorCode {
+    pub const ALL: [ErrorCode; 31] = [
+        ErrorCode::DnsResolutionFailed,
+        ErrorCode::InvalidTarget,
+        ErrorCode::NoTargets,
+        ErrorCode::InvalidZone,
+        ErrorCode::TargetRefused,
+        ErrorCode::FdExhausted,
+        ErrorCode::LocalPortExhausted,
+        ErrorCode::BindPermissionDenied,
+        ErrorCode::BindAddressInUse,
+        ErrorCode::BindAddressNotAvailable,
+        ErrorCode::BindFailed,
+        ErrorCode::DnsUnavailable,
+        ErrorCode::ProbePanicked,
+        ErrorCode::ConnectionRefused,
+        ErrorCode::ProbeTimeout,
+        ErrorCode::NetworkUnreachable,
+        ErrorCode::IcmpUnreachable,
+        ErrorCode::AdminProhibited,
+        ErrorCode::ProxyDenied,
+        ErrorCode::ConfigInvalid,
+        ErrorCode::InvalidOptions,
+        ErrorCode::OutputFailed,
+        ErrorCode::NetworkSuspect,
+        ErrorCode::UnexpectedOpen,
+        ErrorCode::BundleFailed,
+        ErrorCode::PolicyFailed,
+        ErrorCode::PortsUnavailable,
+        ErrorCode::ScannerErrors,
+        ErrorCode::EgressDisallowedOpen,
+        ErrorCode::EgressAllowedBlocked,
+        ErrorCode::Unclassified,
+    ];
+
+    // (代碼, 名稱, 中文說明, 英文說明)
+    fn entry(self) -> (&'static str, &'static str, &'static str, &'static str) {
+        match self {
+            ErrorCode::DnsResolutionFailed => ("E1001", "DNS_RESOLUTION_FAILED", "目標主機名稱無法解析", "A target hostname could not be resolved"),
+            ErrorCode::InvalidTarget => ("E1002", "INVALID_TARGET", "目標不是有效的位址、網段或主機名稱", "A target is not a valid address, network or hostname"),
+            ErrorCode::NoTargets => ("E1003", "NO_TARGETS", "沒有可掃描的目標 (未指定或全部被排除)", "No targets left to scan (none given or all excluded)"),
+            ErrorCode::InvalidZone => ("E1004", "INVALID_ZONE", "連結本地位址的區域不是存在的網路介面", "The zone of a link-local address is not an existing interface"),
+            ErrorCode::TargetRefused => ("E1005", "TARGET_REFUSED", "目標超過安全上限或未經授權", "Targets exceed the safety limits or were not authorized"),
+            ErrorCode::FdExhausted => ("E2001", "FD_EXHAUSTED", "掃描端的檔案描述符用盡", "The scanner ran out of file descriptors"),
+            ErrorCode::LocalPortExhausted => ("E2002", "LOCAL_PORT_EXHAUSTED", "掃描端的本機臨時端口衝突或用盡", "Local ephemeral ports conflicted or ran out"),
+            ErrorCode::BindPermissionDenied => ("E2003", "BIND_PERMISSION_DENIED", "入站測試沒有權限綁定端口", "The inbound test had no permission to bind the port"),
+            ErrorCode::BindAddressInUse => ("E2004", "BIND_ADDRESS_IN_USE", "入站測試的端口已被其他程式使用", "The port for the inbound test is already in use"),
+            ErrorCode::BindAddressNotAvailable => ("E2005", "BIND_ADDRESS_NOT_AVAILABLE", "入站測試的位址不屬於本機", "The inbound test address is not local"),
+            ErrorCode::BindFailed => ("E2006", "BIND_FAILED", "入站測試因其他原因無法綁定", "The inbound test could not bind for another reason"),
+            ErrorCode::DnsUnavailable => ("E2007", "DNS_UNAVAILABLE", "掃描端的 DNS 解析器無法使用 (逾時或暫時失敗)", "The scanner's DNS resolver is unavailable (timeout or temporary failure)"),
+            ErrorCode::ProbePanicked => ("E2008", "PROBE_PANICKED", "探測工作異常中止，端口沒有結果", "A probe task panicked and the port has no result"),
+            ErrorCode::ConnectionRefused => ("E3001", "CONNECTION_REFUSED", "目標以 RST 拒絕連線", "The target refused the connection with RST"),
+            ErrorCode::ProbeTimeout => ("E3002", "PROBE_TIMEOUT", "探測逾時沒有回應", "The probe timed out without a response"),
+            ErrorCode::NetworkUnreachable => ("E3003", "NETWORK_UNREACHABLE", "連線因網路錯誤失敗", "The connection failed with a network error"),
+            ErrorCode::IcmpUnreachable => ("E3004", "ICMP_UNREACHABLE", "路由器或目標回報 ICMP 不可達", "A router or the target reported ICMP unreachable"),
+            ErrorCode::AdminProhibited => ("E3005", "ADMIN_PROHIBITED", "ICMP 回報被管理上禁止 (防火牆)", "ICMP reported administratively prohibited (firewall)"),
+            ErrorCode::ProxyDenied => ("E3006", "PROXY_DENIED", "HTTP 代理依政策拒絕 CONNECT (4xx)", "The HTTP proxy refused the CONNECT by policy (4xx)"),
+            ErrorCode::ConfigInvalid => ("E4001", "CONFIG_INVALID", "設定檔無法讀取或內容有誤", "The config file could not be read or is invalid"),
+            ErrorCode::InvalidOptions => ("E4002", "INVALID_OPTIONS", "命令列選項的組合或值無效", "Invalid combination or value of command-line options"),
+            ErrorCode::OutputFailed => ("E5001", "OUTPUT_FAILED", "無法寫入輸出檔案或資料庫", "The output file or database could not be written"),
+            ErrorCode::NetworkSuspect => ("E6001", "NETWORK_SUSPECT", "掃描前的連線檢查全部失敗，網路疑似中斷", "All pre-scan sanity checks failed; the network looks down"),
+            ErrorCode::UnexpectedOpen => ("E6002", "UNEXPECTED_OPEN", "發現服務清單未宣告的開放端口", "Open ports not declared in the manifest were found"),
+            ErrorCode::BundleFailed => ("E6003", "BUNDLE_FAILED", "有服務組合不成立", "At least one service bundle does not hold"),
+            ErrorCode::PolicyFailed => ("E6004", "POLICY_FAILED", "結果未通過政策、範本或 --min-grade 檢查", "Results failed a policy, template or --min-grade check"),
+            ErrorCode::PortsUnavailable => ("E6005", "PORTS_UNAVAILABLE", "--compact 檢查的端口有無法連線的", "At least one port checked with --compact is unavailable"),
+            ErrorCode::ScannerErrors => ("E6006", "SCANNER_ERRORS", "--strict 時掃描端本身發生錯誤，結果不完整", "With --strict, the scanner itself failed and the results are incomplete"),
+            ErrorCode::EgressDisallowedOpen => ("E6007", "EGRESS_DISALLOWED_OPEN", "出站政策不允許的路徑可以連線", "A path the egress policy does not allow is open"),
+            ErrorCode::EgressAllowedBlocked => ("E6008", "EGRESS_ALLOWED_BLOCKED", "出站政策允許的路徑無法連線", "A path the egress policy allows is not working"),
+            ErrorCode::Unclassified => ("E9001", "UNCLASSIFIED", "尚未分類的錯誤", "An error that has no specific code yet"),
+        }
+    }
+
+    pub fn code(self) -> &'static str {
+        self.entry().0
+    }
+
+    pub fn name(self) -> &'static str {
+        self.entry().1
+    }
+
+    pub fn describe(self, lang: Lang) -> &'static str {
+        match lang {
+            Lang::ZhTw => self.entry().2,
+            Lang::En => self.entry().3,
+        }
+    }
+
+    // 程式因此錯誤結束時的結束代碼；3、4、5 沿用既有的判定結果代碼
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorCode::NetworkSuspect => crate::sanity::EXIT_NETWORK_SUSPECT,
+            ErrorCode::UnexpectedOpen => crate::manifest::EXIT_UNEXPECTED_OPEN,
+            ErrorCode::BundleFailed => crate::bundles::EXIT_BUNDLE_FAILED,
+            ErrorCode::ScannerErrors => crate::strict::EXIT_SCANNER_ERRORS,
+            ErrorCode::EgressDisallowedOpen => crate::egress::EXIT_DISALLOWED_OPEN,
+            ErrorCode::EgressAllowedBlocked => crate::egress::EXIT_ALLOWED_BLOCKED,
+            _ => match self.code().as_bytes()[1] {
+                b'1' => 6,
+                b'2' | b'3' => 7,
+                b'4' => 8,
+                b'5' => 9,
+                _ => 1,
+            },
+        }
+    }
+
+    // 入站測試的代碼
+    pub fn is_bind(self) -> bool {
+        matches!(
+            self,
+            ErrorCode::BindPermissionDenied | ErrorCode::BindAddressInUse | ErrorCode::BindAddressNotAvailable | ErrorCode::BindFailed
+        )
+    }
+
+    // 掃描端本身的錯誤 (--strict)；端口關閉、被過濾或無法綁定是探測的判定，不屬於此類
+    pub fn is_internal(self) -> bool {
+        matches!(
+            self,
+            ErrorCode::FdExhausted | ErrorCode::LocalPortExhausted | ErrorCode::DnsUnavailable | ErrorCode::ProbePanicked
+        )
+    }
+
+    pub fn of_bind(error: &io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => ErrorCode::BindPermissionDenied,
+            io::ErrorKind::AddrInUse => ErrorCode::BindAddressInUse,
+            io::ErrorKind::AddrNotAvailable => ErrorCode::BindAddressNotAvailable,
+            _ => ErrorCode::BindFailed,
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.code(), self.name())
+    }
+}
+
+// ICMPv4 類型 3 代碼 9、10、13 與 ICMPv6 類型 1 代碼 1 為管理上禁止
+fn prohibited(icmp: &IcmpError) -> bool {
+    match icmp.from {
+        IpAddr::V4(_) => icmp.kind == 3 && matches!(icmp.code, 9 | 10 | 13),
+        IpAddr::V6(_) => icmp.kind == 1 && icmp.code == 1,
+    }
+}
+
+// 出站探測結果對應的代碼；掃描端錯誤優先於連線失敗的方式
+pub fn outbound_codes(error: Option<ScanError>, failure: Option<Failure>, icmp: Option<&IcmpError>) -> Vec<ErrorCode> {
+    let code = match (error, icmp, failure) {
+        (Some(error), ..) => error.code(),
+        (None, Some(icmp), _) if prohibited(icmp) => ErrorCode::AdminProhibited,
+        (None, Some(_), _) => ErrorCode::IcmpUnreachable,
+        (None, None, Some(Failure::Reset { .. })) => ErrorCode::ConnectionRefused,
+        (None, None, Some(Failure::Timeout)) => ErrorCode::ProbeTimeout,
+        (None, None, Some(Failure::Unreachable)) => ErrorCode::NetworkUnreachable,
+        (None, None, Some(Failure::ProxyDenied { .. })) => ErrorCode::ProxyDenied,
+        (None, None, None) => return Vec::new(),
+    };
+    vec![code]
+}
+
+// 文字輸出附在出站結果之後的代碼，例如 " [E3004]"；入站綁定的代碼不顯示
+pub fn tag(codes: &[ErrorCode]) -> String {
+    codes.iter().filter(|code| !code.is_bind()).map(|code| format!(" [{}]", code.code())).collect()
+}
+
+// 重新探測改變結果時，出站的代碼換成新的失敗方式，入站綁定的代碼保留
+pub fn reprobed(codes: &mut Vec<ErrorCode>, failure: Option<Failure>) {
+    codes.retain(|code| code.is_bind());
+    codes.extend(outbound_codes(None, failure, None));
+}
+
+// 帶有代碼的錯誤；其他函式回傳的字串錯誤在最外層歸為 E9001
+#[derive(Debug)]
+pub struct CodedError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl fmt::Display for CodedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for CodedError {}
+
+pub fn coded(code: ErrorCode, message: impl Into<String>) -> Box<dyn Error> {
+    Box::new(CodedError { code, message: message.into() })
+}
+
+// 為 Result 的錯誤加上代碼，例如 config::load(..).code(ErrorCode::ConfigInvalid)?
+pub trait WithCode<T> {
+    fn code(self, code: ErrorCode) -> Result<T, Box<dyn Error>>;
+}
+
+impl<T, E: fmt::Display> WithCode<T> for Result<T, E> {
+    fn code(self, code: ErrorCode) -> Result<T, Box<dyn Error>> {
+        self.map_err(|e| coded(code, e.to_string()))
+    }
+}
+
+pub fn classify(error: &(dyn Error + 'static)) -> ErrorCode {
+    match error.downcast_ref::<CodedError>() {
+        Some(coded) => coded.code,
+        None => ErrorCode::Unclassified,
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorReport<'a> {
+    schema_version: u32,
+    error: ErrorDetail<'a>,
+}
+
+#[derive(Serialize)]
+struct ErrorDetail<'a> {
+    code: ErrorCode,
+    name: &'static str,
+    message: &'a str,
+    exit_code: i32,
+}
+
+// 顯示錯誤的代碼與訊息，回傳代碼供呼叫端決定結束代碼
+// --json 時錯誤以一行 JSON 寫到標準錯誤，標準輸出仍只有報告，腳本不必解析文字
+pub fn report(error: &(dyn Error + 'static), json: bool) -> ErrorCode {
+    let code = classify(error);
+    let message = error.to_string();
+    let report = ErrorReport {
+        schema_version: crate::report::SCHEMA_VERSION,
+        error: ErrorDetail { code, name: code.name(), message: &message, exit_code: code.exit_code() },
+    };
+    match serde_json::to_string(&report) {
+        Ok(line) if json => eprintln!("{}", line),
+        _ => eprintln!("Error [{}]: {}", code.code(), message),
+    }
+    code
+}
+
+#[derive(Serialize)]
+struct CatalogEntry {
+    code: ErrorCode,
+    name: &'static str,
+    exit_code: i32,
+    description: &'static str,
+}
+
+// portscanner errors list
+pub fn list(lang: Lang, json: bool) -> Result<(), Box<dyn Error>> {
+    if json {
+        let entries: Vec<CatalogEntry> = ErrorCode::ALL
+            .iter()
+            .map(|&code| CatalogEntry { code, name: code.name(), exit_code: code.exit_code(), description: code.describe(lang) })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+    let header = match lang {
+        Lang::ZhTw => "代碼   名稱                          結束代碼  說明",
+        Lang::En => "Code   Name                          Exit      Description",
+    };
+    println!("{}", header.bold());
+    for line in catalog_lines(lang) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+// 目錄的每一行 (不含標題)
+fn catalog_lines(lang: Lang) -> Vec<String> {
+    ErrorCode::ALL
+        .iter()
+        .map(|code| format!("{}  {:28}  {:<8}  {}", code.code(), code.name(), code.exit_code(), code.describe(lang)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use super::*;
+
+    // 代碼是對外的穩定介面：變更目錄時必須一併更新快照
+    #[test]
+    fn catalog_matches_snapshot() {
+        for (lang, snapshot) in [
+            (Lang::ZhTw, include_str!("snapshots/errors-list.zh-TW.txt")),
+            (Lang::En, include_str!("snapshots/errors-list.en.txt")),
+        ] {
+            assert_eq!(catalog_lines(lang).join("\n"), snapshot.trim_end(), "{:?}", lang);
+        }
+    }
+
+    #[test]
+    fn codes_and_names_are_unique() {
+        let codes: HashSet<&str> = ErrorCode::ALL.iter().map(|code| code.code()).collect();
+        let names: HashSet<&str> = ErrorCode::ALL.iter().map(|code| code.name()).collect();
+        assert_eq!(codes.len(), ErrorCode::ALL.len());
+        assert_eq!(names.len(), ErrorCode::ALL.len());
+    }
+
+    // 序列化的代碼與目錄一致，JSON 輸出不會和 errors list 不同
+    #[test]
+    fn serialized_code_matches_catalog() {
+        for code in ErrorCode::ALL {
+            assert_eq!(serde_json::to_value(code).unwrap(), serde_json::Value::from(code.code()));
+        }
+    }
+
+    #[test]
+    fn exit_codes_follow_category() {
+        for code in ErrorCode::ALL {
+            let expected = match &code.code()[..2] {
+                "E1" => 6,
+                "E2" | "E3" => 7,
+                "E4" => 8,
+                "E5" => 9,
+                _ => continue,
+            };
+            assert_eq!(code.exit_code(), expected, "{}", code.code());
+        }
+    }
+
+    #[test]
+    fn uncoded_errors_are_unclassified() {
+        let plain: Box<dyn Error> = "plain".into();
+        assert_eq!(classify(plain.as_ref()), ErrorCode::Unclassified);
+        let coded = coded(ErrorCode::InvalidOptions, "無效的端口: 0");
+        assert_eq!(classify(coded.as_ref()), ErrorCode::InvalidOptions);
+        let converted = Err::<(), _>("無效的端口: x").code(ErrorCode::InvalidOptions).unwrap_err();
+        assert_eq!(classify(converted.as_ref()), ErrorCode::InvalidOptions);
+    }
+}
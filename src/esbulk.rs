@@ -0,0 +1,234 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::Duration;
+use colored::*;
+use serde::Serialize;
+use serde_json::Value;
+use crate::checks::{CheckOutcome, CheckStatus};
+use crate::identity::Identity;
+use crate::metadata::RunMetadata;
+use crate::{PortInfo, ScanResult};
+
+// --es-url 的基本認證帳號密碼
+pub const USERNAME_ENV: &str = "PORTSCANNER_ES_USERNAME";
+pub const PASSWORD_ENV: &str = "PORTSCANNER_ES_PASSWORD";
+
+// 送出 bulk 請求的逾時
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+// 回應中逐筆列出的失敗上限，其餘只計數
+const SHOWN_FAILURES: usize = 10;
+
+// 文件欄位依 ECS 命名：目的端為 destination.*，服務為 service.name，連線時間為 event.duration (奈秒)
+// ECS 沒有對應的掃描結果放在 portscanner.*
+#[derive(Debug, Serialize)]
+struct Document<'a> {
+    #[serde(rename = "@timestamp")]
+    timestamp: &'a str,
+    event: Event,
+    destination: Destination<'a>,
+    network: Network,
+    service: Service<'a>,
+    observer: Observer<'a>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    labels: &'a BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    tags: &'a [String],
+    portscanner: Details<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct Event {
+    kind: &'static str,
+    category: [&'static str; 1],
+    dataset: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct Destination<'a> {
+    ip: IpAddr,
+    port: u16,
+    // 主機識別 (掃描目標的主機名稱、--host-id 等)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    domain: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize)]
+struct Network {
+    transport: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct Service<'a> {
+    name: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct Observer<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hostname: Option<&'a str>,
+    product: &'static str,
+    version: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct Details<'a> {
+    // 執行資訊的掃描 ID，同一次執行的文件相同
+    scan_id: &'a str,
+    category: &'a str,
+    // open / closed / filtered / error，與 inbound、outbound 一起方便彙總
+    state: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inbound: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outbound: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    grade: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confidence: Option<f64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    checks: Vec<Check<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct Check<'a> {
+    name: &'a str,
+    status: CheckStatus,
+    summary: &'a str,
+}
+
+// --no-outbound 時以能否綁定為準
+pub fn state(result: &ScanResult) -> &'static str {
+    match (&result.error, result.directions.open(result.inbound, result.outbound), &result.failure) {
+        (Some(_), _, _) => "error",
+        (None, true, _) => "open",
+        (None, false, Some(crate::closure::Failure::Reset { .. })) => "closed",
+        _ => "filtered",
+    }
+}
+
+// 每個端口結果一組 action + 文件；依主機與端口排序
+pub fn build(
+    index: &str,
+    metadata: &RunMetadata,
+    results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>,
+    checks: &[(IpAddr, Vec<CheckOutcome>)],
+    identities: &BTreeMap<IpAddr, Identity>,
+) -> Result<String, serde_json::Error> {
+    let action = serde_json::to_string(&serde_json::json!({ "index": { "_index": index } }))?;
+    let mut out = String::new();
+    for (host, ports) in results {
+        let outcomes: Vec<&CheckOutcome> = checks.iter().filter(|(h, _)| h == host).flat_map(|(_, o)| o).collect();
+        let mut ports: Vec<_> = ports.iter().collect();
+        ports.sort_by_key(|(port, _)| port.port);
+        for (port, result) in ports {
+            let document = Document {
+                timestamp: &metadata.started,
+                event: Event {
+                    kind: "state",
+                    category: ["network"],
+                    dataset: "portscanner.port",
+                    duration: result.latency_ms.map(|ms| (ms * 1_000_000.0) as u64),
+                },
+                destination: Destination {
+                    ip: *host,
+                    port: port.port,
+                    domain: identities.get(host).map(|identity| identity.name.as_str()),
+                },
+                network: Network { transport: "tcp" },
+                service: Service { name: &port.service },
+                observer: Observer {
+                    hostname: metadata.hostname.as_deref(),
+                    product: "portscanner",
+                    version: &metadata.version,
+                },
+                labels: &metadata.annotations,
+                tags: &port.tags,
+                portscanner: Details {
+                    scan_id: &metadata.scan_id,
+                    category: &port.category,
+                    state: state(result),
+                    inbound: result.directions.inbound().then_some(result.inbound),
+                    outbound: result.directions.outbound().then_some(result.outbound),
+                    latency_ms: result.latency_ms,
+                    grade: result.grade.as_ref().map(|g| g.grade.to_string()),
+                    confidence: result.confidence,
+                    checks: outcomes
+                        .iter()
+                        .filter(|o| o.port == port.port)
+                        .map(|o| Check { name: o.check, status: o.status, summary: &o.summary })
+                        .collect(),
+                },
+            };
+            out.push_str(&action);
+            out.push('\n');
+            out.push_str(&serde_json::to_string(&document)?);
+            out.push('\n');
+        }
+    }
+    Ok(out)
+}
+
+pub fn write(path: &Path, payload: &str) -> Result<(), Box<dyn Error>> {
+    fs::write(path, payload).map_err(|e| format!("無法寫入 {}: {}", path.display(), e))?;
+    Ok(())
+}
+
+// POST 到 URL/_bulk (URL 已以 _bulk 結尾時直接使用)；帳號密碼取自環境變數
+// 請求本身失敗時回傳錯誤，個別文件失敗只列出
+pub async fn send(url: &str, payload: &str, quiet: bool) -> Result<(), Box<dyn Error>> {
+    let trimmed = url.trim_end_matches('/');
+    let endpoint = match trimmed.ends_with("/_bulk") {
+        true => trimmed.to_string(),
+        false => format!("{}/_bulk", trimmed),
+    };
+    let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+    let mut request = client.post(&endpoint).header("Content-Type", "application/x-ndjson").body(payload.to_string());
+    if let Ok(username) = env::var(USERNAME_ENV) {
+        request = request.basic_auth(username, env::var(PASSWORD_ENV).ok());
+    }
+    let response = request.send().await.map_err(|e| format!("無法送出到 {}: {}", endpoint, e))?;
+    let status = response.status();
+    let body: Value = response.json().await.map_err(|e| format!("{} 的回應不是 JSON ({}): {}", endpoint, status, e))?;
+    if !status.is_success() {
+        let reason = body.pointer("/error/reason").and_then(Value::as_str).unwrap_or("");
+        return Err(format!("{} 回應 {} {}", endpoint, status, reason).into());
+    }
+
+    let items = body.get("items").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+    // 每個 item 只有一個鍵 (index)，其值含 status 與失敗時的 error
+    let failures: Vec<(usize, &Value)> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(i, item)| item.as_object()?.values().next().map(|result| (i, result)))
+        .filter(|(_, result)| result.get("error").is_some())
+        .collect();
+    if !quiet {
+        println!("已送出 {} 筆文件到 {}，{} 筆失敗", items.len(), endpoint, failures.len());
+    }
+    // 第 i 筆 item 對應 payload 的第 i 個文件行
+    let documents: Vec<&str> = payload.lines().skip(1).step_by(2).collect();
+    for (i, result) in failures.iter().take(SHOWN_FAILURES) {
+        let document = documents.get(*i).and_then(|line| serde_json::from_str::<Value>(line).ok());
+        let endpoint = document
+            .as_ref()
+            .and_then(|doc| Some(format!("{}:{}", doc.pointer("/destination/ip")?.as_str()?, doc.pointer("/destination/port")?)))
+            .unwrap_or_else(|| format!("第 {} 筆", i + 1));
+        let kind = result.pointer("/error/type").and_then(Value::as_str).unwrap_or("?");
+        let reason = result.pointer("/error/reason").and_then(Value::as_str).unwrap_or("");
+        let code = result.get("status").and_then(Value::as_u64).unwrap_or_default();
+        eprintln!("{}", format!("  {} ({}): {} {}", endpoint, code, kind, reason).yellow());
+    }
+    if failures.len() > SHOWN_FAILURES {
+        eprintln!("{}", format!("  另有 {} 筆失敗", failures.len() - SHOWN_FAILURES).yellow());
+    }
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use serde::Serialize;
+use crate::scanid;
+
+// 事件 ID 固定不變，供排程工作與監控規則比對
+pub const EVENT_SCAN_COMPLETED: u32 = 1000;
+pub const EVENT_STATE_CHANGED: u32 = 2000;
+pub const EVENT_EXTERNAL_IP_CHANGED: u32 = 2001;
+pub const EVENT_SERVICE_RESTART: u32 = 2002;
+pub const EVENT_ALERT: u32 = 3000;
+pub const EVENT_UNEXPECTED_OPEN: u32 = 3001;
+pub const EVENT_FINGERPRINT_CHANGED: u32 = 3002;
+pub const EVENT_EGRESS_VIOLATION: u32 = 3003;
+
+// 事件來源名稱 (應用程式記錄檔)
+#[cfg(windows)]
+const SOURCE: &str = "PortScanner";
+
+// 事件等級
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventLevel {
+    Information,
+    Warning,
+    Error,
+}
+
+// Windows 事件記錄；其他平台無法開啟
+#[cfg_attr(not(windows), allow(dead_code))]
+pub struct EventLog {
+    #[cfg(windows)]
+    handle: windows::Win32::Foundation::HANDLE,
+    scan_id: String,
+}
+
+impl EventLog {
+    #[cfg(windows)]
+    pub fn open(scan_id: &str) -> Result<Self, String> {
+        use windows::core::{HSTRING, PCWSTR};
+        use windows::Win32::System::EventLog::RegisterEventSourceW;
+
+        let handle = unsafe { RegisterEventSourceW(PCWSTR::null(), &HSTRING::from(SOURCE)) }
+            .map_err(|e| format!("無法註冊事件來源 {}: {}", SOURCE, e))?;
+        Ok(EventLog { handle, scan_id: scan_id.to_string() })
+    }
+
+    #[cfg(not(windows))]
+    pub fn open(_scan_id: &str) -> Result<Self, String> {
+        Err("--eventlog 只支援 Windows".to_string())
+    }
+
+    // 寫入一筆事件：第一個字串為說明，第二個為加上掃描 ID 的 JSON 內容 (payload 必須是物件)
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn report<T: Serialize>(&self, level: EventLevel, id: u32, message: &str, payload: &T) {
+        let json = serde_json::to_string(&scanid::tag(&self.scan_id, payload)).unwrap_or_default();
+        if let Err(e) = self.write(level, id, message, &json) {
+            eprintln!("無法寫入事件記錄: {}", e);
+        }
+    }
+
+    #[cfg(windows)]
+    fn write(&self, level: EventLevel, id: u32, message: &str, json: &str) -> Result<(), String> {
+        use windows::core::{HSTRING, PCWSTR};
+        use windows::Win32::System::EventLog::{
+            ReportEventW, EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE,
+        };
+
+        let kind = match level {
+            EventLevel::Information => EVENTLOG_INFORMATION_TYPE,
+            EventLevel::Warning => EVENTLOG_WARNING_TYPE,
+            EventLevel::Error => EVENTLOG_ERROR_TYPE,
+        };
+        let message = HSTRING::from(message);
+        let json = HSTRING::from(json);
+        let strings = [PCWSTR(message.as_ptr()), PCWSTR(json.as_ptr())];
+
+        unsafe { ReportEventW(self.handle, kind, 0, id, None, 0, Some(&strings), None) }.map_err(|e| e.to_string())
+    }
+
+    #[cfg(not(windows))]
+    fn write(&self, _level: EventLevel, _id: u32, _message: &str, _json: &str) -> Result<(), String> {
+        Err("--eventlog 只支援 Windows".to_string())
+    }
+}
+
+#[cfg(windows)]
+impl Drop for EventLog {
+    fn drop(&mut self) {
+        use windows::Win32::System::EventLog::DeregisterEventSource;
+        let _ = unsafe { DeregisterEventSource(self.handle) };
+    }
+}
@@ -0,0 +1,249 @@
+use std::error::Error;
+use std::io::{self, Write};
+use std::process::Command;
+use clap::Parser;
+use colored::*;
+use crate::cli::Cli;
+
+// 範例命令列中代表使用者指定目標的位置
+const TARGET: &str = "{target}";
+
+// examples check 代入的目標 (RFC 5737 文件用位址)
+const CHECK_TARGET: &str = "192.0.2.10";
+
+// 範例情境：執行的參數 (不含程式名稱) 與說明
+pub struct Scenario {
+    pub name: &'static str,
+    pub title: &'static str,
+    pub args: &'static [&'static str],
+    pub explanation: &'static str,
+}
+
+pub const SCENARIOS: &[Scenario] = &[
+    Scenario {
+        name: "quick-local",
+        title: "快速檢查本機",
+        args: &["--ports", "22,80,443,3306,8080"],
+        explanation: "不指定目標時，以公共測試位址確認本機出站連線，並確認本機這些端口能否被監聽。適合在換網路或調整防火牆後快速看一眼常用端口。",
+    },
+    Scenario {
+        name: "web-audit",
+        title: "稽核遠端網頁伺服器",
+        args: &["--target", TARGET, "--ports", "80,443,8080,8443", "--banners", "--http-versions", "--tcp-caps"],
+        explanation: "掃描常見的網頁端口，取得服務橫幅、以 ALPN 與 QUIC 確認支援的 HTTP 版本，並探測 TCP Fast Open 與 ECN。結果的建議事項會指出未加密的 HTTP 等問題。",
+    },
+    Scenario {
+        name: "watch-db",
+        title: "監看資料庫端口並告警",
+        args: &["--target", TARGET, "--ports", "1433,1521,3306,5432,6379,27017", "--watch", "5m"],
+        explanation: "每五分鐘重新掃描常見的資料庫端口，只顯示狀態改變。設定檔的 [[watch.alerts]] 規則與 webhook 決定何時送出告警，例如資料庫端口從外部變成可連線。",
+    },
+    Scenario {
+        name: "pci-external",
+        title: "PCI 外部掃描檢查",
+        args: &["--target", TARGET, "--template", "pci-external"],
+        explanation: "依內建的 pci-external 範本掃描，確認只開放 HTTPS、管理與資料庫端口都已關閉；不符合範本時以非零結束代碼結束，可以放進排程或 CI。",
+    },
+    Scenario {
+        name: "subnet-sweep",
+        title: "掃描整個網段並依網段比較",
+        args: &["--target", TARGET, "--ports", "22,80,443,3389", "--concurrency", "256", "--blocks", "--matrix"],
+        explanation: "目標可以是 CIDR 網段。結果依 /24 網段分組並彙總，比較表列出同一網段中狀態不一致的端口，方便找出設定和其他主機不同的機器。",
+    },
+    Scenario {
+        name: "diff-runs",
+        title: "輸出可比較的純文字結果",
+        args: &["--target", TARGET, "--output", "scan.txt"],
+        explanation: "寫出欄位固定、依端口排序的純文字結果。在變更前後各執行一次並改用不同的檔名，再以 diff -u 比較兩個檔案。",
+    },
+    Scenario {
+        name: "udp-checks",
+        title: "檢查常見 UDP 服務",
+        args: &["--target", TARGET, "--vuln-checks", "--intrusiveness", "active"],
+        explanation: "掃描後對 NTP、TFTP 等 UDP 服務送出一般的協定查詢，確認服務是否回應以及是否有 NTP 放大等可被濫用的設定。active 不會讀取檔案；需要時改用 --intrusive。",
+    },
+    Scenario {
+        name: "uptime-monitor",
+        title: "量測端口可用率",
+        args: &["--target", TARGET, "--ports", "443", "--monitor", "10m", "--interval", "5s"],
+        explanation: "十分鐘內每五秒探測一次，結束後顯示可用率、最長中斷與時間軸。適合確認負載平衡器切換或網路維護期間服務是否中斷。",
+    },
+    Scenario {
+        name: "vendor-report",
+        title: "產生可交給廠商的匿名報告",
+        args: &["--target", TARGET, "--json", "--anonymize"],
+        explanation: "輸出 JSON 報告，所有位址與主機名稱都換成假名，網段結構保留。需要對照時另外加上 --anonymize-map 保存假名對照表，不要隨報告一起提供。",
+    },
+    Scenario {
+        name: "plan-large",
+        title: "掃描大型網段前先估計",
+        args: &["--target", TARGET, "--dry-run"],
+        explanation: "只列出掃描計劃：目標與端口數、總探測數與預估時間，不送出任何封包。目標很大時先確認範圍與排除清單是否正確。",
+    },
+];
+
+// 命令列參數中若有空白或引號，以單引號包住
+fn shell_quote(arg: &str) -> String {
+    match arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || "'\"$`\\;&|<>*?".contains(c)) {
+        true => format!("'{}'", arg.replace('\'', r"'\''")),
+        false => arg.to_string(),
+    }
+}
+
+impl Scenario {
+    pub fn needs_target(&self) -> bool {
+        self.args.contains(&TARGET)
+    }
+
+    // 代入目標後的參數
+    pub fn arguments(&self, target: &str) -> Vec<String> {
+        self.args.iter().map(|arg| arg.replace(TARGET, target)).collect()
+    }
+
+    fn command_line(&self, target: &str) -> String {
+        let args: Vec<String> = self.arguments(target).iter().map(|arg| shell_quote(arg)).collect();
+        format!("portscanner {}", args.join(" "))
+    }
+}
+
+fn find(name: &str) -> Result<&'static Scenario, String> {
+    SCENARIOS.iter().find(|s| s.name == name).ok_or_else(|| {
+        let names: Vec<&str> = SCENARIOS.iter().map(|s| s.name).collect();
+        format!("找不到範例 '{}' (可用: {})", name, names.join(", "))
+    })
+}
+
+// portscanner examples：列出所有範例
+pub fn list() {
+    println!("\n{}", "=== 使用範例 ===".bold());
+    for scenario in SCENARIOS {
+        println!("\n{}  {}", scenario.name.bold().cyan(), scenario.title);
+        println!("  {}", scenario.command_line("<目標>").green());
+        println!("  {}", scenario.explanation);
+    }
+    println!("\n{}", "執行範例: portscanner examples run <名稱> --target <目標>".dimmed());
+}
+
+// 每個範例代入文件用位址後，都要能以目前的命令列定義解析
+pub fn check() -> Result<(), Box<dyn Error>> {
+    let mut failures = 0;
+    for scenario in SCENARIOS {
+        let args = scenario.arguments(CHECK_TARGET);
+        match Cli::try_parse_from(std::iter::once("portscanner".to_string()).chain(args)) {
+            Ok(_) => println!("{} {}", "✓".green(), scenario.name),
+            Err(e) => {
+                failures += 1;
+                println!("{} {}: {}", "✗".red(), scenario.name, e.to_string().lines().next().unwrap_or_default());
+            }
+        }
+    }
+    match failures {
+        0 => Ok(()),
+        n => Err(format!("{} 個範例無法解析", n).into()),
+    }
+}
+
+// portscanner examples run：顯示完整命令列並確認後，以同一個執行檔執行
+pub fn run(name: &str, target: Option<&str>, yes: bool) -> Result<(), Box<dyn Error>> {
+    let scenario = find(name)?;
+    let target = match (scenario.needs_target(), target) {
+        (true, Some(target)) => target,
+        (true, None) => return Err(format!("範例 {} 需要指定 --target", scenario.name).into()),
+        (false, Some(_)) => return Err(format!("範例 {} 掃描本機，不使用 --target", scenario.name).into()),
+        (false, None) => "",
+    };
+    println!("{}  {}", scenario.title.bold(), scenario.explanation.dimmed());
+    println!("{}", scenario.command_line(target).green());
+    if !yes {
+        print!("執行這個命令? [y/N] ");
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            println!("已取消");
+            return Ok(());
+        }
+    }
+    let status = Command::new(std::env::current_exe()?).args(scenario.arguments(target)).status()?;
+    // 結束代碼與直接執行命令時相同
+    if !status.success() {
+        std::process::exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use crate::cli::{Command as CliCommand, ExamplesCommand};
+
+    fn parse(args: &[String]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(std::iter::once("portscanner".to_string()).chain(args.iter().cloned()))
+    }
+
+    #[test]
+    fn every_scenario_parses_against_the_real_cli() {
+        for scenario in SCENARIOS {
+            let cli = parse(&scenario.arguments(CHECK_TARGET)).unwrap_or_else(|e| panic!("{}: {}", scenario.name, e));
+            // 代入的目標確實成為 --target
+            let expected = scenario.needs_target().then(|| CHECK_TARGET.to_string());
+            assert_eq!(cli.target, expected, "{}", scenario.name);
+            assert!(cli.command.is_none(), "{}", scenario.name);
+        }
+        check().unwrap();
+        // 解析確實會拒絕未知的旗標
+        assert!(parse(&["--no-such-flag".to_string()]).is_err());
+    }
+
+    #[test]
+    fn scenarios_are_well_formed() {
+        assert!(SCENARIOS.len() >= 10);
+        let names: HashSet<&str> = SCENARIOS.iter().map(|s| s.name).collect();
+        assert_eq!(names.len(), SCENARIOS.len(), "範例名稱重複");
+        for scenario in SCENARIOS {
+            assert!(scenario.name.chars().all(|c| c.is_ascii_lowercase() || c == '-'), "{}", scenario.name);
+            assert!(!scenario.title.is_empty() && scenario.explanation.ends_with('。'), "{}", scenario.name);
+            // 目標只能出現在 --target 之後
+            for (i, arg) in scenario.args.iter().enumerate() {
+                if arg.contains(TARGET) {
+                    assert_eq!(scenario.args.get(i.wrapping_sub(1)), Some(&"--target"), "{}", scenario.name);
+                }
+            }
+        }
+        assert!(!find("quick-local").unwrap().needs_target());
+        assert!(find("web-audit").unwrap().needs_target());
+    }
+
+    #[test]
+    fn command_lines_substitute_and_quote_the_target() {
+        let scenario = find("plan-large").unwrap();
+        assert_eq!(scenario.arguments("10.0.0.0/24"), vec!["--target", "10.0.0.0/24", "--dry-run"]);
+        assert_eq!(scenario.command_line("10.0.0.0/24"), "portscanner --target 10.0.0.0/24 --dry-run");
+        assert_eq!(scenario.command_line("a.example, b.example"), "portscanner --target 'a.example, b.example' --dry-run");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("$HOME"), "'$HOME'");
+        assert_eq!(shell_quote("22,80"), "22,80");
+    }
+
+    #[test]
+    fn unknown_names_list_the_choices() {
+        let error = find("nope").err().unwrap();
+        assert!(error.starts_with("找不到範例 'nope' (可用: quick-local, web-audit,"), "{}", error);
+        assert_eq!(run("web-audit", None, true).unwrap_err().to_string(), "範例 web-audit 需要指定 --target");
+        assert_eq!(run("quick-local", Some("192.0.2.1"), true).unwrap_err().to_string(), "範例 quick-local 掃描本機，不使用 --target");
+    }
+
+    #[test]
+    fn the_examples_subcommand_parses() {
+        let cli = parse(&["examples", "run", "web-audit", "--target", "192.0.2.1", "-y"].map(String::from)).unwrap();
+        match cli.command {
+            Some(CliCommand::Examples { action: Some(ExamplesCommand::Run { name, target, yes }) }) => {
+                assert_eq!((name.as_str(), target.as_deref(), yes), ("web-audit", Some("192.0.2.1"), true));
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(parse(&["examples".to_string()]).unwrap().command, Some(CliCommand::Examples { action: None })));
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+use colored::*;
+use schemars::JsonSchema;
+use serde::Serialize;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use crate::targets::{self, ResolveFailure, TargetSpec};
+use crate::anonymize::Anonymizer;
+use crate::context::ScanContext;
+use crate::dns::DnsCache;
+use crate::zone::Zones;
+use crate::{anonymize, axfr, dns};
+
+// 偵測萬用字元 DNS 時查詢的隨機名稱數
+const WILDCARD_PROBES: usize = 2;
+
+// 萬用字元目標 (*.zone) 的展開選項
+#[derive(Debug, Clone, Copy)]
+pub struct ExpandOptions<'a> {
+    pub wordlist: Option<&'a Path>,
+    pub axfr: bool,
+    pub axfr_server: Option<&'a str>,
+    pub concurrency: usize,
+    pub quiet: bool,
+    pub anonymizer: Option<&'a Anonymizer>,
+    pub dns: &'a Arc<DnsCache>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NameSource {
+    Axfr,
+    Wordlist,
+}
+
+impl NameSource {
+    fn label(self) -> &'static str {
+        match self {
+            NameSource::Axfr => "區域轉送",
+            NameSource::Wordlist => "字詞清單",
+        }
+    }
+}
+
+// 解析到同一個位址的名稱只掃描一次
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ExpandedHost {
+    pub addr: IpAddr,
+    pub names: Vec<String>,
+}
+
+// 一個萬用字元目標的展開結果
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Expansion {
+    pub zone: String,
+    pub source: NameSource,
+    // 查詢的候選名稱數
+    pub candidates: usize,
+    pub hosts: Vec<ExpandedHost>,
+    // 不存在的名稱也會解析到的位址 (萬用字元 DNS)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub wildcard: Vec<IpAddr>,
+    // 只解析到萬用字元位址、合併成 *.zone 的名稱數
+    pub collapsed: usize,
+    // 無法解析 (多半是 NXDOMAIN) 的名稱數
+    pub unresolved: usize,
+}
+
+impl Expansion {
+    pub fn anonymize(&mut self, anonymizer: &anonymize::Anonymizer) {
+        self.zone = anonymizer.hostname(&self.zone);
+        for host in &mut self.hosts {
+            host.addr = anonymizer.ip(host.addr);
+            host.names = host.names.iter().map(|name| anonymizer.hostname(name)).collect();
+        }
+        self.wildcard = self.wildcard.iter().map(|addr| anonymizer.ip(*addr)).collect();
+    }
+}
+
+// 結果標題顯示的名稱，依位址查詢；主機不是由萬用字元目標展開時沒有項目
+pub fn names(expansions: &[Expansion]) -> BTreeMap<IpAddr, Vec<String>> {
+    let mut names: BTreeMap<IpAddr, Vec<String>> = BTreeMap::new();
+    for host in expansions.iter().flat_map(|e| &e.hosts) {
+        let entry = names.entry(host.addr).or_default();
+        entry.extend(host.names.iter().filter(|name| !entry.contains(name)).cloned().collect::<Vec<_>>());
+    }
+    names
+}
+
+// *.internal.example.com 的區域部分
+fn wildcard_zone(item: &str) -> Option<&str> {
+    item.strip_prefix("*.").filter(|zone| !zone.is_empty())
+}
+
+// 字詞清單：每行一個標籤 (可含點，例如 vpn.eu)，# 開頭為註解
+fn read_wordlist(path: &Path, zone: &str) -> Result<Vec<String>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("無法讀取 {}: {}", path.display(), e))?;
+    let mut names = BTreeSet::new();
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let label = line.trim_end_matches('.').to_ascii_lowercase();
+        let name = targets::to_ascii_hostname(&format!("{}.{}", label, zone))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        names.insert(name.to_ascii_lowercase());
+    }
+    Ok(names.into_iter().collect())
+}
+
+// 候選名稱：有 --axfr 時先嘗試區域轉送，失敗時改用字詞清單
+async fn candidates(zone: &str, options: &ExpandOptions<'_>) -> Result<(NameSource, Vec<String>), String> {
+    if options.axfr {
+        match axfr::zone_names(zone, options.axfr_server, options.dns).await {
+            Ok(names) => return Ok((NameSource::Axfr, names)),
+            Err(e) if options.wordlist.is_some() => {
+                if !options.quiet {
+                    eprintln!("{}", anonymize::show(options.anonymizer, &format!("{} 區域轉送失敗 ({})，改用字詞清單", zone, e)).yellow());
+                }
+            }
+            Err(e) => return Err(format!("{} 區域轉送失敗: {}", zone, e)),
+        }
+    }
+    match options.wordlist {
+        Some(path) => Ok((NameSource::Wordlist, read_wordlist(path, zone)?)),
+        None => Err(format!("萬用字元目標 *.{} 需要 --subdomain-list 或 --axfr", zone)),
+    }
+}
+
+type Answers = Vec<(String, Result<Vec<IpAddr>, String>)>;
+
+// 以 --expand-concurrency 同時查詢；結果依名稱排序
+async fn resolve_names(names: Vec<String>, concurrency: usize, dns: &Arc<DnsCache>) -> Answers {
+    let permits = Arc::new(Semaphore::new(concurrency));
+    let mut lookups = JoinSet::new();
+    for name in names {
+        let (permits, dns) = (permits.clone(), dns.clone());
+        lookups.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            let answer = dns.addresses(&name).await;
+            (name, answer)
+        });
+    }
+    let mut answers = Vec::new();
+    while let Some(answer) = lookups.join_next().await {
+        if let Ok(answer) = answer {
+            answers.push(answer);
+        }
+    }
+    answers.sort_by(|a, b| a.0.cmp(&b.0));
+    answers
+}
+
+// 隨機且幾乎不可能存在的名稱也能解析時，區域有萬用字元記錄
+async fn wildcard_addrs(zone: &str, dns: &DnsCache) -> Vec<IpAddr> {
+    let mut addrs = BTreeSet::new();
+    for _ in 0..WILDCARD_PROBES {
+        let mut random = [0u8; 8];
+        if getrandom::getrandom(&mut random).is_err() {
+            break;
+        }
+        let label: String = random.iter().map(|b| format!("{:02x}", b)).collect();
+        if let Ok(found) = dns.addresses(&format!("ps-{}.{}", label, zone)).await {
+            addrs.extend(found);
+        }
+    }
+    addrs.into_iter().collect()
+}
+
+async fn expand_zone(zone: &str, options: &ExpandOptions<'_>) -> Result<Expansion, String> {
+    let (source, names) = candidates(zone, options).await?;
+    let wildcard = wildcard_addrs(zone, options.dns).await;
+    let candidates = names.len();
+    let mut hosts: BTreeMap<IpAddr, Vec<String>> = BTreeMap::new();
+    let (mut collapsed, mut unresolved) = (0, 0);
+    for (name, answer) in resolve_names(names, options.concurrency, options.dns).await {
+        match answer {
+            // 只解析到萬用字元位址的名稱與不存在的名稱無法區分，合併成一個 *.zone
+            Ok(addrs) if !wildcard.is_empty() && addrs.iter().all(|addr| wildcard.contains(addr)) => collapsed += 1,
+            Ok(addrs) => match dns::preferred(&addrs) {
+                Some(addr) => hosts.entry(addr).or_default().push(name),
+                None => unresolved += 1,
+            },
+            Err(_) => unresolved += 1,
+        }
+    }
+    if collapsed > 0 {
+        if let Some(addr) = dns::preferred(&wildcard) {
+            hosts.entry(addr).or_default().push(format!("*.{}", zone));
+        }
+    }
+    Ok(Expansion {
+        zone: zone.to_string(),
+        source,
+        candidates,
+        hosts: hosts.into_iter().map(|(addr, names)| ExpandedHost { addr, names }).collect(),
+        wildcard,
+        collapsed,
+        unresolved,
+    })
+}
+
+type Parsed = (Vec<TargetSpec>, Vec<ResolveFailure>, Vec<Expansion>);
+
+// 目標清單中的 *.zone 依字詞清單或區域轉送展開，其餘交給 targets::parse_targets
+// 展開的主機以解析到的位址去重，已是其他目標的位址不再重複掃描
+pub async fn parse_targets(spec: &str, resolve: bool, options: &ExpandOptions<'_>, zones: &mut Zones) -> Result<Parsed, Box<dyn Error>> {
+    let items: Vec<&str> = spec.split(',').map(str::trim).filter(|s| !s.is_empty()).collect();
+    let (wildcards, rest): (Vec<&str>, Vec<&str>) = items.into_iter().partition(|item| wildcard_zone(item).is_some());
+    if wildcards.is_empty() {
+        if options.wordlist.is_some() || options.axfr {
+            return Err("--subdomain-list 與 --axfr 需要萬用字元目標，例如 --target '*.example.com'".into());
+        }
+        let (targets, failures) = targets::parse_targets(spec, resolve, zones, options.dns).await?;
+        return Ok((targets, failures, Vec::new()));
+    }
+
+    let (mut targets, failures) = match rest.is_empty() {
+        true => (Vec::new(), Vec::new()),
+        false => targets::parse_targets(&rest.join(","), resolve, zones, options.dns).await?,
+    };
+    let mut expansions = Vec::new();
+    for item in wildcards {
+        let zone = targets::to_ascii_hostname(wildcard_zone(item).expect("partitioned"))?.to_ascii_lowercase();
+        // --no-resolve 的 dry-run 無法展開，只列出萬用字元目標
+        if !resolve {
+            targets.push(TargetSpec::Unresolved(format!("*.{}", zone)));
+            continue;
+        }
+        expansions.push(expand_zone(&zone, options).await?);
+    }
+
+    let mut scanned: BTreeSet<IpAddr> = targets
+        .iter()
+        .filter_map(|target| match target {
+            TargetSpec::Host { addr, .. } => Some(*addr),
+            _ => None,
+        })
+        .collect();
+    for host in expansions.iter().flat_map(|e| &e.hosts) {
+        if scanned.insert(host.addr) {
+            targets.push(TargetSpec::Host { name: host.names[0].clone(), addr: host.addr });
+        }
+    }
+    if targets.is_empty() {
+        return Err("萬用字元目標沒有解析出任何主機".into());
+    }
+    Ok((targets, failures, expansions))
+}
+
+// 掃描前顯示每個萬用字元目標的展開摘要
+pub fn display(expansions: &[Expansion], context: &ScanContext) {
+    for expansion in expansions {
+        let mut line = format!(
+            "*.{}: {} {} 個名稱 → {} 台主機，{} 個無法解析",
+            expansion.zone,
+            expansion.source.label(),
+            expansion.candidates,
+            expansion.hosts.len(),
+            expansion.unresolved
+        );
+        if !expansion.wildcard.is_empty() {
+            let addrs: Vec<String> = expansion.wildcard.iter().map(IpAddr::to_string).collect();
+            line.push_str(&format!("；萬用字元 DNS 指向 {}，合併 {} 個名稱", addrs.join(", "), expansion.collapsed));
+        }
+        println!("{}", context.show(&line));
+    }
+}
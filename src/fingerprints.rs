@@ -0,0 +1,467 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use colored::*;
+use rusqlite::{Connection, OptionalExtension, TransactionBehavior};
+use russh::client;
+use russh::keys::{HashAlg, PublicKeyOrCertificate};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
+use crate::alerts::{Alert, Severity};
+use crate::anonymize::Anonymizer;
+use crate::blobs;
+use crate::context::ScanContext;
+use crate::identity::Identities;
+use crate::output;
+use crate::pipeline::Evidence;
+use crate::probes::Banner;
+use crate::scanner::ScanPlan;
+use crate::signing;
+use crate::timefmt;
+use crate::{PortInfo, ScanResult};
+
+// SSH 金鑰交換與 TLS 握手比單純連線需要更多時間
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 匿名化時取代金鑰與憑證指紋 (可在憑證透明度記錄中反查主機)
+const HIDDEN: &str = "(已隱藏)";
+
+// 指紋的種類；資料庫以 name 記錄
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    SshHostKey,
+    TlsCertificate,
+    HttpServer,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::SshHostKey => "ssh_host_key",
+            Kind::TlsCertificate => "tls_certificate",
+            Kind::HttpServer => "http_server",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Kind::SshHostKey => "SSH 主機金鑰",
+            Kind::TlsCertificate => "TLS 憑證",
+            Kind::HttpServer => "HTTP Server 標頭",
+        }
+    }
+}
+
+// 端口上辨識到的服務指紋，例如 SSH 的 "ssh-ed25519 SHA256:..."、TLS 憑證的 "SHA256:<十六進位>"
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Fingerprint {
+    pub kind: Kind,
+    pub value: String,
+}
+
+// 與資料庫記錄不同的指紋
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct Change {
+    pub host: IpAddr,
+    pub identity: String,
+    pub port: u16,
+    pub service: String,
+    pub kind: Kind,
+    pub before: String,
+    pub after: String,
+    // 記錄中的指紋第一次與最後一次出現的時間 (Unix 秒)
+    pub first_seen: i64,
+    pub last_seen: i64,
+    // --accept-fingerprints 已以新的指紋取代記錄
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub accepted: bool,
+}
+
+// 一次掃描的比對結果 (JSON 的 fingerprints)
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+pub struct Reconciliation {
+    // 第一次記錄的指紋數
+    pub recorded: usize,
+    pub unchanged: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<Change>,
+}
+
+impl Reconciliation {
+    // 尚未接受的變更
+    pub fn unaccepted(&self) -> impl Iterator<Item = &Change> {
+        self.changes.iter().filter(|change| !change.accepted)
+    }
+
+    pub fn anonymize(&mut self, anonymizer: &Anonymizer) {
+        for change in &mut self.changes {
+            change.host = anonymizer.ip(change.host);
+            change.identity = anonymizer.text(&change.identity);
+            hide(change.kind, &mut change.before, anonymizer);
+            hide(change.kind, &mut change.after, anonymizer);
+        }
+    }
+}
+
+// 匿名化的指紋：HTTP Server 標頭可能含主機名稱，金鑰與憑證指紋不輸出
+pub fn hide(kind: Kind, value: &mut String, anonymizer: &Anonymizer) {
+    *value = match kind {
+        Kind::HttpServer => anonymizer.text(value),
+        Kind::SshHostKey | Kind::TlsCertificate => HIDDEN.to_string(),
+    };
+}
+
+// 只需要主機金鑰：記下指紋後拒絕，不繼續驗證
+struct KeyCapture(Arc<Mutex<Option<String>>>);
+
+impl client::Handler for KeyCapture {
+    type Error = russh::Error;
+
+    async fn check_server_key(&mut self, server: &PublicKeyOrCertificate) -> Result<bool, russh::Error> {
+        // 主機憑證每次簽發都不同，以其中的公鑰為準
+        let (algorithm, fingerprint) = match server {
+            PublicKeyOrCertificate::PublicKey { key, .. } => (key.algorithm(), key.fingerprint(HashAlg::Sha256)),
+            PublicKeyOrCertificate::Certificate(certificate) => {
+                (certificate.algorithm(), certificate.public_key().fingerprint(HashAlg::Sha256))
+            }
+        };
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = Some(format!("{} {}", algorithm.as_str(), fingerprint));
+        Ok(false)
+    }
+}
+
+async fn ssh_host_key(addr: SocketAddr, limit: Duration) -> Option<String> {
+    let captured = Arc::new(Mutex::new(None));
+    let handler = KeyCapture(captured.clone());
+    let attempt = async {
+        let stream = TcpStream::connect(addr).await.ok()?;
+        // 拒絕金鑰後連線以錯誤結束
+        let _ = client::connect_stream(Arc::new(client::Config::default()), stream, handler).await;
+        Some(())
+    };
+    timeout(limit, attempt).await.ok()??;
+    let key = captured.lock().unwrap_or_else(|e| e.into_inner()).take();
+    key
+}
+
+// 伺服器憑證 (DER) 的 SHA-256；不驗證憑證，自簽憑證也記錄
+async fn tls_certificate(addr: SocketAddr, limit: Duration) -> Option<String> {
+    let connector = native_tls::TlsConnector::builder()
+        .danger_accept_invalid_certs(true)
+        .danger_accept_invalid_hostnames(true)
+        .build()
+        .ok()?;
+    let connector = tokio_native_tls::TlsConnector::from(connector);
+    let attempt = async {
+        let stream = TcpStream::connect(addr).await.ok()?;
+        let stream = connector.connect(&addr.ip().to_string(), stream).await.ok()?;
+        let der = stream.get_ref().peer_certificate().ok()??.to_der().ok()?;
+        Some(format!("SHA256:{}", signing::to_hex(&Sha256::digest(der))))
+    };
+    timeout(limit, attempt).await.ok()?
+}
+
+// 橫幅中的 Server 標頭；文字被截斷時使用內建 http 探測比對出的版本
+fn server_header(banner: &Banner) -> Option<String> {
+    let header = banner.text.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim().eq_ignore_ascii_case("server").then(|| value.trim().to_string())
+    });
+    header
+        .or_else(|| banner.version.clone().filter(|_| banner.probe == "http"))
+        .filter(|value| !value.is_empty())
+}
+
+fn speaks_ssh(evidence: &Evidence) -> bool {
+    evidence.port.service.eq_ignore_ascii_case("ssh")
+        || evidence.banner.is_some_and(|b| b.text.starts_with("SSH-") || b.service.as_deref() == Some("ssh"))
+}
+
+// 對出站可連線的端口取得指紋：SSH 主機金鑰、TLS 憑證，以及 --banners 橫幅中的 HTTP Server 標頭
+pub async fn probe_results(results: &mut BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>, plan: &ScanPlan) {
+    let limit = plan.timeouts.default.max(HANDSHAKE_TIMEOUT);
+    let semaphore = Arc::new(Semaphore::new(plan.concurrency.max(1)));
+    let mut handles = Vec::new();
+    for (host, host_results) in results.iter_mut() {
+        for (port, result) in host_results.iter_mut() {
+            let evidence = Evidence::of(port, result, false);
+            if !evidence.connected {
+                continue;
+            }
+            let (ssh, tls) = (speaks_ssh(&evidence), evidence.speaks_tls());
+            result.fingerprints = result
+                .banner
+                .as_ref()
+                .and_then(server_header)
+                .map(|value| Fingerprint { kind: Kind::HttpServer, value })
+                .into_iter()
+                .collect();
+            if !ssh && !tls {
+                continue;
+            }
+            let (addr, port, semaphore) = (plan.context.socket_addr(*host, port.port), port.clone(), semaphore.clone());
+            handles.push(tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let mut found = Vec::new();
+                if let Some(value) = if ssh { ssh_host_key(addr, limit).await } else { None } {
+                    found.push(Fingerprint { kind: Kind::SshHostKey, value });
+                }
+                if let Some(value) = if tls { tls_certificate(addr, limit).await } else { None } {
+                    found.push(Fingerprint { kind: Kind::TlsCertificate, value });
+                }
+                (addr.ip(), port, found)
+            }));
+        }
+    }
+    for handle in handles {
+        if let Ok((host, port, found)) = handle.await {
+            if let Some(result) = results.get_mut(&host).and_then(|r| r.get_mut(&port)) {
+                result.fingerprints.extend(found);
+                result.fingerprints.sort_by_key(|fingerprint| fingerprint.kind);
+            }
+        }
+    }
+}
+
+// 一個端口上的一個指紋與主機識別
+struct Observed<'a> {
+    host: IpAddr,
+    identity: String,
+    port: &'a PortInfo,
+    fingerprint: &'a Fingerprint,
+}
+
+// 依主機識別與端口記錄指紋 (--fingerprint-db)；像 known_hosts 一樣，改變的指紋不會自動取代記錄
+pub struct Tracker {
+    conn: Connection,
+    db: PathBuf,
+    identities: Arc<Identities>,
+    // --accept-fingerprints
+    accept: bool,
+}
+
+impl Tracker {
+    pub fn open(db: &Path, identities: Arc<Identities>, accept: bool) -> Result<Self, String> {
+        let conn = output::open_database(db).map_err(|e| format!("無法開啟指紋資料庫 {}: {}", db.display(), e))?;
+        Ok(Tracker { conn, db: db.to_path_buf(), identities, accept })
+    }
+
+    // 取得這次掃描的指紋並與記錄比對
+    pub async fn observe(
+        &mut self,
+        results: &mut BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>,
+        plan: &ScanPlan,
+    ) -> Result<Reconciliation, String> {
+        probe_results(results, plan).await;
+        self.identities.prefetch(results.keys().copied().collect()).await;
+        let mut observed = Vec::new();
+        for (host, host_results) in results.iter() {
+            let identity = self.identities.of(*host).name;
+            for (port, result) in host_results {
+                for fingerprint in &result.fingerprints {
+                    observed.push(Observed { host: *host, identity: identity.clone(), port, fingerprint });
+                }
+            }
+        }
+        observed.sort_by_key(|o| (o.host, o.port.port, o.fingerprint.kind));
+        self.reconcile(&observed, timefmt::now())
+    }
+
+    fn reconcile(&mut self, observed: &[Observed], now: i64) -> Result<Reconciliation, String> {
+        let accept = self.accept;
+        let conn = &mut self.conn;
+        output::with_retry(|| {
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let mut reconciliation = Reconciliation::default();
+            for seen in observed {
+                let (identity, port, kind) = (&seen.identity, seen.port.port, seen.fingerprint.kind.name());
+                let recorded: Option<(String, i64, i64)> = tx
+                    .query_row(
+                        "SELECT b.content, f.first_seen, f.last_seen FROM service_fingerprints f JOIN blobs b ON b.sha256 = f.value_sha256
+                         WHERE f.identity = ?1 AND f.port = ?2 AND f.kind = ?3",
+                        rusqlite::params![identity, port, kind],
+                        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                    )
+                    .optional()?;
+                let value = &seen.fingerprint.value;
+                match recorded {
+                    None => {
+                        tx.execute(
+                            "INSERT INTO service_fingerprints (identity, port, kind, value_sha256, first_seen, last_seen) VALUES (?1, ?2, ?3, ?4, ?5, ?5)",
+                            rusqlite::params![identity, port, kind, blobs::store(&tx, value)?, now],
+                        )?;
+                        reconciliation.recorded += 1;
+                    }
+                    Some((before, _, _)) if &before == value => {
+                        tx.execute(
+                            "UPDATE service_fingerprints SET last_seen = ?4 WHERE identity = ?1 AND port = ?2 AND kind = ?3",
+                            rusqlite::params![identity, port, kind, now],
+                        )?;
+                        reconciliation.unchanged += 1;
+                    }
+                    Some((before, first_seen, last_seen)) => {
+                        if accept {
+                            tx.execute(
+                                "UPDATE service_fingerprints SET value_sha256 = ?4, first_seen = ?5, last_seen = ?5
+                                 WHERE identity = ?1 AND port = ?2 AND kind = ?3",
+                                rusqlite::params![identity, port, kind, blobs::store(&tx, value)?, now],
+                            )?;
+                        }
+                        reconciliation.changes.push(Change {
+                            host: seen.host,
+                            identity: identity.clone(),
+                            port,
+                            service: seen.port.service.clone(),
+                            kind: seen.fingerprint.kind,
+                            before,
+                            after: value.clone(),
+                            first_seen,
+                            last_seen,
+                            accepted: accept,
+                        });
+                    }
+                }
+            }
+            tx.commit()?;
+            Ok(reconciliation)
+        })
+        .map_err(|e| format!("無法更新指紋資料庫 {}: {}", self.db.display(), e))
+    }
+}
+
+// 終端顯示的一項變更
+fn describe(change: &Change, context: &ScanContext) -> Vec<String> {
+    let host = match change.identity == change.host.to_string() {
+        true => context.show_ip(change.host),
+        false => format!("{} ({})", context.show(&change.identity), context.show_ip(change.host)),
+    };
+    vec![
+        format!("{} port {} ({})：{}已改變", host, change.port, change.service, change.kind.label()),
+        format!(
+            "  先前: {}  (記錄於 {} 至 {})",
+            change.before,
+            timefmt::timestamp(change.first_seen),
+            timefmt::timestamp(change.last_seen)
+        ),
+        format!("  現在: {}", change.after),
+    ]
+}
+
+// 「指紋變更」區段；沒有變更時只顯示一行統計
+pub fn display(reconciliation: &Reconciliation, context: &ScanContext) {
+    if reconciliation.changes.is_empty() {
+        println!(
+            "\n{}",
+            format!("服務指紋: 新記錄 {}、未改變 {}", reconciliation.recorded, reconciliation.unchanged).dimmed()
+        );
+        return;
+    }
+    println!("\n{}", "=== 指紋變更 ===".red().bold());
+    for change in &reconciliation.changes {
+        let mut lines = describe(change, context).into_iter();
+        if let Some(headline) = lines.next() {
+            match change.accepted {
+                true => println!("{} {}", headline.yellow(), "(已接受，記錄已更新)".dimmed()),
+                false => println!("{}", format!("⚠ {}", headline).red().bold()),
+            }
+        }
+        for line in lines {
+            println!("{}", line);
+        }
+    }
+    if reconciliation.unaccepted().next().is_some() {
+        println!(
+            "{}",
+            "未改變的端口出現新的主機金鑰或憑證可能代表遭到中間人攻擊；確認是預期的變更 (重新安裝、更新憑證) 後加上 --accept-fingerprints 更新記錄"
+                .yellow()
+        );
+    }
+}
+
+// watch 模式的告警；變更接受前每次掃描都會產生，重複的由告警路由在去重視窗內抑制
+pub fn alerts(reconciliation: &Reconciliation, iteration: u64, context: &ScanContext) -> Vec<Alert> {
+    reconciliation
+        .unaccepted()
+        .map(|change| Alert {
+            rule: "fingerprint_changed".to_string(),
+            severity: Severity::Critical,
+            key: format!("fingerprint_changed:{}:{}:{}", change.identity, change.port, change.kind.name()),
+            iteration,
+            message: describe(change, context).join("\n"),
+            history: Vec::new(),
+            external_ip_changed: false,
+            clock_jump: false,
+            suppressed: 0,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{host, TempDir};
+
+    fn banner(probe: &str, text: &str, version: Option<&str>) -> Banner {
+        Banner { probe: probe.to_string(), service: Some("http".to_string()), version: version.map(str::to_string), text: text.to_string(), encoding: None, raw: None }
+    }
+
+    #[test]
+    fn server_headers_come_from_the_banner() {
+        let text = "HTTP/1.1 200 OK\r\nDate: Tue, 13 Oct 2026 08:00:00 GMT\r\nserver:  nginx/1.25.3\r\n";
+        assert_eq!(server_header(&banner("http", text, None)).as_deref(), Some("nginx/1.25.3"));
+        // 截斷的橫幅使用探測比對出的版本
+        assert_eq!(server_header(&banner("http", "HTTP/1.1 200 OK\r\nDate: ...", Some("Apache"))).as_deref(), Some("Apache"));
+        assert_eq!(server_header(&banner("custom", "HTTP/1.1 200 OK", Some("1.0"))), None);
+    }
+
+    fn tracker(dir: &TempDir, accept: bool) -> Tracker {
+        Tracker::open(&dir.path().join("history.db"), Arc::new(Identities::default()), accept).unwrap()
+    }
+
+    fn observe(tracker: &mut Tracker, value: &str, now: i64) -> Reconciliation {
+        let port = PortInfo::new(22, "SSH", "Remote");
+        let fingerprint = Fingerprint { kind: Kind::SshHostKey, value: value.to_string() };
+        let observed = [Observed { host: host(1), identity: "bastion".to_string(), port: &port, fingerprint: &fingerprint }];
+        tracker.reconcile(&observed, now).unwrap()
+    }
+
+    #[test]
+    fn changed_fingerprints_warn_until_accepted() {
+        let dir = TempDir::new("fingerprints");
+        let mut first = tracker(&dir, false);
+        assert_eq!(observe(&mut first, "ssh-ed25519 SHA256:old", 100).recorded, 1);
+        assert_eq!(observe(&mut first, "ssh-ed25519 SHA256:old", 200).unchanged, 1);
+
+        let changed = observe(&mut first, "ssh-ed25519 SHA256:new", 300);
+        let change = &changed.changes[0];
+        assert_eq!((change.before.as_str(), change.after.as_str()), ("ssh-ed25519 SHA256:old", "ssh-ed25519 SHA256:new"));
+        assert_eq!((change.first_seen, change.last_seen, change.accepted), (100, 200, false));
+        // 不會自動取代記錄，下一次掃描仍然警告
+        assert_eq!(observe(&mut first, "ssh-ed25519 SHA256:new", 400).unaccepted().count(), 1);
+
+        let mut accepting = tracker(&dir, true);
+        assert!(observe(&mut accepting, "ssh-ed25519 SHA256:new", 500).changes[0].accepted);
+        assert_eq!(observe(&mut first, "ssh-ed25519 SHA256:new", 600).unchanged, 1);
+    }
+
+    #[test]
+    fn changes_render_as_critical_alerts() {
+        let dir = TempDir::new("fingerprint-alerts");
+        let mut tracker = tracker(&dir, false);
+        observe(&mut tracker, "SHA256:aa", 1_700_000_000);
+        let changed = observe(&mut tracker, "SHA256:bb", 1_700_000_600);
+        let alerts = alerts(&changed, 3, &ScanContext::offline());
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].severity, Severity::Critical);
+        assert_eq!(alerts[0].key, "fingerprint_changed:bastion:22:ssh_host_key");
+        assert!(alerts[0].message.starts_with("bastion (192.0.2.1) port 22 (SSH)：SSH 主機金鑰已改變"), "{}", alerts[0].message);
+        assert!(alerts[0].message.contains("  現在: SHA256:bb"));
+    }
+}
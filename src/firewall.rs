@@ -0,0 +1,257 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use clap::ValueEnum;
+use crate::direction::Directions;
+use crate::manifest::{self, ManifestReport};
+use crate::policy::{Expected, PolicyReport};
+use crate::{PortInfo, ScanResult};
+
+// --suggest-rules 的規則格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    /// iptables / ip6tables 指令
+    Iptables,
+    /// nft 指令 (inet filter 表)
+    Nftables,
+    /// Windows PowerShell 的 New-NetFirewallRule
+    Windows,
+}
+
+impl Backend {
+    fn name(self) -> &'static str {
+        match self {
+            Backend::Iptables => "iptables",
+            Backend::Nftables => "nftables",
+            Backend::Windows => "Windows 防火牆",
+        }
+    }
+}
+
+// 規則阻擋的方向，以掃描端為準：出站為本機連到目標，入站為外部連到本機
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Direction {
+    Outbound,
+    Inbound,
+}
+
+// 一條阻擋建議；入站的規則套用在本機，不限定位址
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub direction: Direction,
+    pub host: Option<IpAddr>,
+    pub port: u16,
+    pub service: String,
+    // 判定為不應開放的來源，例如 "服務清單 hosts.yaml 未宣告"
+    pub reasons: Vec<String>,
+}
+
+// 服務清單未宣告的開放端口與政策預期關閉卻開放的端口；只測入站時建議入站規則
+pub fn suggest(
+    results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>,
+    policy: Option<&PolicyReport>,
+    manifest: Option<&ManifestReport>,
+) -> Vec<Suggestion> {
+    let mut findings: Vec<(IpAddr, u16, String, String)> = Vec::new();
+    if let Some(report) = manifest {
+        for host in &report.hosts {
+            for finding in host.findings.iter().filter(|f| f.class == manifest::Class::Unexpected) {
+                findings.push((host.host, finding.port, finding.service.clone(), format!("服務清單 {} 未宣告", report.manifest)));
+            }
+        }
+    }
+    if let Some(report) = policy {
+        for host in &report.hosts {
+            for finding in host.findings.iter().filter(|f| f.expected == Expected::Closed) {
+                let reason = match &finding.reason {
+                    Some(reason) => format!("{} 預期關閉: {}", report.title, reason),
+                    None => format!("{} 預期關閉", report.title),
+                };
+                findings.push((host.host, finding.port, finding.service.clone(), reason));
+            }
+        }
+    }
+
+    let mut suggestions: BTreeMap<(Direction, Option<IpAddr>, u16), Suggestion> = BTreeMap::new();
+    for (host, port, service, reason) in findings {
+        let directions = results
+            .get(&host)
+            .and_then(|ports| ports.iter().find(|(info, _)| info.port == port))
+            .map(|(_, result)| result.directions)
+            .unwrap_or_default();
+        let (direction, host) = match directions {
+            Directions::Inbound => (Direction::Inbound, None),
+            _ => (Direction::Outbound, Some(host)),
+        };
+        let suggestion = suggestions.entry((direction, host, port)).or_insert_with(|| Suggestion {
+            direction,
+            host,
+            port,
+            service,
+            reasons: Vec::new(),
+        });
+        if !suggestion.reasons.contains(&reason) {
+            suggestion.reasons.push(reason);
+        }
+    }
+    suggestions.into_values().collect()
+}
+
+// 規則草稿；每條規則前附上端口與判定來源的註解
+pub fn render(backend: Backend, suggestions: &[Suggestion]) -> String {
+    let mut lines = vec![
+        format!("# portscanner 建議的防火牆規則 ({})", backend.name()),
+        "# 只是草稿：請逐條檢查後再套用，portscanner 不會修改防火牆".to_string(),
+    ];
+    if backend == Backend::Nftables {
+        lines.push("# 假設已有 inet filter 表與 input、output 鏈".to_string());
+    }
+    if suggestions.is_empty() {
+        lines.push("# 沒有需要阻擋的端口".to_string());
+    }
+    for suggestion in suggestions {
+        lines.push(String::new());
+        let target = match suggestion.host {
+            Some(host) => format!("{} port {}", host, suggestion.port),
+            None => format!("本機 port {}", suggestion.port),
+        };
+        lines.push(format!("# {} ({})：{}", target, suggestion.service, suggestion.reasons.join("；")));
+        lines.push(match suggestion.direction {
+            Direction::Outbound => "# 阻擋本機連到目標的出站連線 (在掃描端或出口防火牆套用)".to_string(),
+            Direction::Inbound => "# 阻擋外部連到本機的入站連線".to_string(),
+        });
+        lines.extend(rules(backend, suggestion));
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+fn rules(backend: Backend, suggestion: &Suggestion) -> Vec<String> {
+    let port = suggestion.port;
+    match (backend, suggestion.host) {
+        (Backend::Iptables, Some(host)) => {
+            let command = if host.is_ipv6() { "ip6tables" } else { "iptables" };
+            vec![format!("{} -A OUTPUT -p tcp -d {} --dport {} -j REJECT --reject-with tcp-reset", command, host, port)]
+        }
+        // 入站規則不限定位址，IPv4 與 IPv6 各一條
+        (Backend::Iptables, None) => ["iptables", "ip6tables"]
+            .iter()
+            .map(|command| format!("{} -A INPUT -p tcp --dport {} -j DROP", command, port))
+            .collect(),
+        (Backend::Nftables, Some(host)) => {
+            let family = if host.is_ipv6() { "ip6" } else { "ip" };
+            vec![format!("nft add rule inet filter output {} daddr {} tcp dport {} reject with tcp reset", family, host, port)]
+        }
+        (Backend::Nftables, None) => vec![format!("nft add rule inet filter input tcp dport {} drop", port)],
+        (Backend::Windows, Some(host)) => vec![format!(
+            "New-NetFirewallRule -DisplayName \"portscanner: 阻擋 {}\" -Direction Outbound -Action Block -Protocol TCP -RemoteAddress {} -RemotePort {}",
+            SocketAddr::new(host, port),
+            host,
+            port
+        )],
+        (Backend::Windows, None) => vec![format!(
+            "New-NetFirewallRule -DisplayName \"portscanner: 阻擋入站 {}\" -Direction Inbound -Action Block -Protocol TCP -LocalPort {}",
+            port, port
+        )],
+    }
+}
+
+// --rules-out 寫入檔案，否則輸出到標準輸出
+pub fn write(text: &str, path: Option<&Path>, quiet: bool) -> Result<(), String> {
+    match path {
+        Some(path) => {
+            fs::write(path, text).map_err(|e| format!("無法寫入防火牆規則 {}: {}", path.display(), e))?;
+            if !quiet {
+                println!("防火牆規則建議已寫入 {}", path.display());
+            }
+        }
+        None => print!("\n{}", text),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::{Class, Finding, HostReconciliation};
+    use crate::policy::{self, HostVerdict};
+    use crate::testutil::scan_result;
+
+    const IPTABLES: &str = include_str!("fixtures/rules/iptables.txt");
+    const NFTABLES: &str = include_str!("fixtures/rules/nftables.txt");
+    const WINDOWS: &str = include_str!("fixtures/rules/windows.txt");
+
+    fn v4() -> IpAddr {
+        "192.0.2.10".parse().unwrap()
+    }
+
+    fn v6() -> IpAddr {
+        "2001:db8::5".parse().unwrap()
+    }
+
+    fn results() -> BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>> {
+        let open = |port, service| (PortInfo::new(port, service, "Other"), scan_result(true));
+        let inbound_only = ScanResult { inbound: true, outbound: false, directions: Directions::Inbound, ..scan_result(false) };
+        BTreeMap::from([
+            (v4(), HashMap::from([open(3306, "MySQL"), open(443, "HTTPS")])),
+            (v6(), HashMap::from([open(22, "SSH"), (PortInfo::new(8080, "HTTP-Alt", "Web"), inbound_only)])),
+        ])
+    }
+
+    fn manifest() -> ManifestReport {
+        let finding = |port, service: &str, class| Finding { port, service: service.to_string(), class };
+        let host = |host, findings| HostReconciliation { host, name: None, entry: None, findings, not_scanned: Vec::new() };
+        ManifestReport {
+            manifest: "hosts.yaml".to_string(),
+            expected: 1,
+            missing: 0,
+            unexpected: 3,
+            hosts: vec![
+                host(v4(), vec![finding(443, "HTTPS", Class::Expected), finding(3306, "MySQL", Class::Unexpected)]),
+                host(v6(), vec![finding(22, "SSH", Class::Unexpected), finding(8080, "HTTP-Alt", Class::Unexpected)]),
+            ],
+        }
+    }
+
+    fn policy() -> PolicyReport {
+        let finding = |port, service: &str, expected, reason: Option<&str>| policy::Finding {
+            port,
+            service: service.to_string(),
+            expected,
+            reason: reason.map(str::to_string),
+            group: None,
+        };
+        let verdict = HostVerdict {
+            host: v4(),
+            checked: 2,
+            findings: vec![finding(3306, "MySQL", Expected::Closed, Some("資料庫不應對外")), finding(443, "HTTPS", Expected::Open, None)],
+            assertions: Vec::new(),
+            objectives: Vec::new(),
+        };
+        PolicyReport { name: "db".to_string(), title: "資料庫隔離".to_string(), passed: false, message: String::new(), hosts: vec![verdict] }
+    }
+
+    #[test]
+    fn unexpected_open_ports_become_suggestions() {
+        let suggestions = suggest(&results(), Some(&policy()), Some(&manifest()));
+        // 預期開放卻關閉的端口不產生規則，同一端口的多個來源合併
+        assert_eq!(suggestions.len(), 3);
+        assert_eq!(suggestions[0].reasons, vec!["服務清單 hosts.yaml 未宣告", "資料庫隔離 預期關閉: 資料庫不應對外"]);
+        assert_eq!((suggestions[2].direction, suggestions[2].host), (Direction::Inbound, None));
+        assert!(suggest(&results(), None, None).is_empty());
+    }
+
+    #[test]
+    fn rules_match_golden_files() {
+        let suggestions = suggest(&results(), Some(&policy()), Some(&manifest()));
+        assert_eq!(render(Backend::Iptables, &suggestions), IPTABLES);
+        assert_eq!(render(Backend::Nftables, &suggestions), NFTABLES);
+        assert_eq!(render(Backend::Windows, &suggestions), WINDOWS);
+    }
+
+    #[test]
+    fn nothing_to_block() {
+        assert!(render(Backend::Iptables, &[]).contains("# 沒有需要阻擋的端口"));
+    }
+}
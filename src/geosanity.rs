@@ -0,0 +1,239 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::net::{self, TcpStream};
+use tokio::task::JoinSet;
+use tokio::time::{timeout, Instant};
+use crate::context::{self, ExternalIp, ScanContext};
+
+// 外部 IP 的 GeoIP 查詢 ({} 為 IP)；回應含 country_name 與 continent_code
+const GEOIP_URL: &str = "https://ipapi.co/{}/json/";
+
+const GEOIP_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 每個錨點以 TCP 連線量測的次數，取最小值
+const ATTEMPTS: usize = 3;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+// GeoIP 所在洲的延遲至少是最近的洲的兩倍，且多出這麼多時才視為不一致；相鄰的洲延遲本來就接近
+const MIN_RATIO: f64 = 2.0;
+const MIN_GAP_MS: f64 = 60.0;
+
+// 分布在各洲的錨點 (TCP 80)
+const ANCHORS: &[(Continent, &str)] = &[
+    (Continent::Europe, "fra-de-ping.vultr.com"),
+    (Continent::Europe, "lon-gb-ping.vultr.com"),
+    (Continent::NorthAmerica, "nj-us-ping.vultr.com"),
+    (Continent::NorthAmerica, "lax-ca-us-ping.vultr.com"),
+    (Continent::SouthAmerica, "sao-br-ping.vultr.com"),
+    (Continent::Asia, "hnd-jp-ping.vultr.com"),
+    (Continent::Asia, "sgp-ping.vultr.com"),
+    (Continent::Oceania, "syd-au-ping.vultr.com"),
+    (Continent::Africa, "jnb-za-ping.vultr.com"),
+];
+
+const ANCHOR_PORT: u16 = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Continent {
+    Europe,
+    NorthAmerica,
+    SouthAmerica,
+    Asia,
+    Oceania,
+    Africa,
+}
+
+impl Continent {
+    // GeoIP 的洲代碼 (EU、NA、SA、AS、OC、AF)
+    fn from_code(code: &str) -> Option<Self> {
+        match code.to_ascii_uppercase().as_str() {
+            "EU" => Some(Continent::Europe),
+            "NA" => Some(Continent::NorthAmerica),
+            "SA" => Some(Continent::SouthAmerica),
+            "AS" => Some(Continent::Asia),
+            "OC" => Some(Continent::Oceania),
+            "AF" => Some(Continent::Africa),
+            _ => None,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Continent::Europe => "歐洲",
+            Continent::NorthAmerica => "北美洲",
+            Continent::SouthAmerica => "南美洲",
+            Continent::Asia => "亞洲",
+            Continent::Oceania => "大洋洲",
+            Continent::Africa => "非洲",
+        }
+    }
+}
+
+// 外部 IP 的位置與延遲特徵 (JSON metadata 的 geo_sanity)
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct GeoSanity {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub continent: Option<Continent>,
+    // 每個洲延遲最低的錨點 RTT (ms)；連不上的洲不列入
+    pub rtt_ms: BTreeMap<Continent, f64>,
+    // 延遲最低的洲
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nearest: Option<Continent>,
+    // 位置與延遲特徵明顯不一致時的提醒
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+    // 無法判斷的原因 (查不到位置、錨點都連不上等)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inconclusive: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeoIpResponse {
+    country_name: Option<String>,
+    continent_code: Option<String>,
+}
+
+// 延遲最低的洲
+fn nearest(rtt_ms: &BTreeMap<Continent, f64>) -> Option<Continent> {
+    rtt_ms.iter().min_by(|a, b| a.1.total_cmp(b.1)).map(|(continent, _)| *continent)
+}
+
+// 位置所在的洲明顯比最近的洲慢時回傳最近的洲；沒有該洲的量測時無法判斷
+fn mismatch(located: Continent, rtt_ms: &BTreeMap<Continent, f64>) -> Option<Continent> {
+    let expected = *rtt_ms.get(&located)?;
+    let closest = nearest(rtt_ms)?;
+    let fastest = rtt_ms[&closest];
+    (closest != located && expected >= fastest * MIN_RATIO && expected - fastest >= MIN_GAP_MS).then_some(closest)
+}
+
+// 依 GeoIP 位置與錨點延遲評估
+pub fn assess(country: Option<String>, continent: Option<Continent>, rtt_ms: BTreeMap<Continent, f64>) -> GeoSanity {
+    let inconclusive = match (continent, rtt_ms.is_empty()) {
+        (None, _) => Some("查不到外部 IP 的位置".to_string()),
+        (_, true) => Some("錨點都無法連線".to_string()),
+        (Some(continent), false) if !rtt_ms.contains_key(&continent) => Some(format!("{}的錨點無法連線", continent.label())),
+        _ => None,
+    };
+    let warning = continent.and_then(|located| mismatch(located, &rtt_ms)).map(|closest| {
+        let place = match &country {
+            Some(country) => format!("{} ({})", country, continent.map(Continent::label).unwrap_or_default()),
+            None => continent.map(Continent::label).unwrap_or_default().to_string(),
+        };
+        format!("外部 IP 顯示在 {}，但延遲特徵更像{} — 可能經過 VPN/代理", place, closest.label())
+    });
+    GeoSanity { country, continent, nearest: nearest(&rtt_ms), rtt_ms, warning, inconclusive }
+}
+
+// 以 TCP 連線時間量測到錨點的 RTT，取幾次中的最小值
+async fn probe_anchor(host: &'static str) -> Option<f64> {
+    let addr: SocketAddr = timeout(CONNECT_TIMEOUT, net::lookup_host((host, ANCHOR_PORT))).await.ok()?.ok()?.next()?;
+    let mut best: Option<f64> = None;
+    for _ in 0..ATTEMPTS {
+        let started = Instant::now();
+        if let Ok(Ok(_)) = timeout(CONNECT_TIMEOUT, TcpStream::connect(addr)).await {
+            let ms = started.elapsed().as_secs_f64() * 1000.0;
+            best = Some(best.map_or(ms, |b| b.min(ms)));
+        }
+    }
+    best
+}
+
+async fn locate(ip: &str) -> Result<GeoIpResponse, String> {
+    let client = reqwest::Client::builder().timeout(GEOIP_TIMEOUT).build().map_err(|e| e.to_string())?;
+    let response = client
+        .get(GEOIP_URL.replace("{}", ip))
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|e| e.without_url().to_string())?;
+    response.json().await.map_err(|e| e.without_url().to_string())
+}
+
+// 等待外部 IP 後查詢位置，同時量測各洲錨點的延遲；掃描期間在背景執行
+pub async fn check(context: Arc<ScanContext>) -> GeoSanity {
+    let mut probes = JoinSet::new();
+    for (continent, host) in ANCHORS {
+        probes.spawn(async move { (*continent, probe_anchor(host).await) });
+    }
+    let location = match context.wait_external_ip(context::EXTERNAL_IP_TIMEOUT).await {
+        ExternalIp::Known(ip) => locate(&ip).await.ok(),
+        _ => None,
+    };
+    let mut rtt_ms: BTreeMap<Continent, f64> = BTreeMap::new();
+    while let Some(Ok((continent, rtt))) = probes.join_next().await {
+        if let Some(rtt) = rtt {
+            let best = rtt_ms.entry(continent).or_insert(rtt);
+            *best = best.min(rtt);
+        }
+    }
+    let (country, continent) = match location {
+        Some(response) => (response.country_name, response.continent_code.as_deref().and_then(Continent::from_code)),
+        None => (None, None),
+    };
+    assess(country, continent, rtt_ms)
+}
+
+// 顯示在外部 IP 之後；一致時不顯示
+pub fn display(geo: &GeoSanity) {
+    if let Some(warning) = &geo.warning {
+        println!("{}", warning.yellow());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rtts(values: &[(Continent, f64)]) -> BTreeMap<Continent, f64> {
+        values.iter().copied().collect()
+    }
+
+    #[test]
+    fn continents_parse_from_geoip_codes() {
+        assert_eq!(Continent::from_code("eu"), Some(Continent::Europe));
+        assert_eq!(Continent::from_code("OC"), Some(Continent::Oceania));
+        assert_eq!(Continent::from_code("AN"), None);
+        let response: GeoIpResponse = serde_json::from_str(r#"{"ip": "203.0.113.9", "country_name": "Germany", "continent_code": "EU"}"#).unwrap();
+        assert_eq!((response.country_name.as_deref(), response.continent_code.as_deref()), (Some("Germany"), Some("EU")));
+    }
+
+    #[test]
+    fn a_location_far_from_the_latency_profile_warns() {
+        let profile = rtts(&[(Continent::Europe, 240.0), (Continent::Asia, 35.0), (Continent::NorthAmerica, 160.0)]);
+        let geo = assess(Some("Germany".to_string()), Some(Continent::Europe), profile);
+        assert_eq!(geo.nearest, Some(Continent::Asia));
+        assert_eq!(geo.warning.as_deref(), Some("外部 IP 顯示在 Germany (歐洲)，但延遲特徵更像亞洲 — 可能經過 VPN/代理"));
+        assert!(geo.inconclusive.is_none());
+    }
+
+    #[test]
+    fn neighbouring_or_consistent_profiles_do_not_warn() {
+        // 位置所在的洲最快
+        let profile = rtts(&[(Continent::Europe, 12.0), (Continent::Asia, 210.0)]);
+        assert!(assess(None, Some(Continent::Europe), profile).warning.is_none());
+        // 非洲北部到歐洲比到南非快，差距不大時不提醒
+        let profile = rtts(&[(Continent::Europe, 40.0), (Continent::Africa, 75.0)]);
+        assert!(assess(None, Some(Continent::Africa), profile).warning.is_none());
+    }
+
+    #[test]
+    fn missing_measurements_are_inconclusive() {
+        let geo = assess(None, None, rtts(&[(Continent::Asia, 30.0)]));
+        assert!(geo.warning.is_none());
+        assert_eq!(geo.inconclusive.as_deref(), Some("查不到外部 IP 的位置"));
+        let geo = assess(None, Some(Continent::Europe), BTreeMap::new());
+        assert_eq!(geo.inconclusive.as_deref(), Some("錨點都無法連線"));
+        let geo = assess(None, Some(Continent::Europe), rtts(&[(Continent::Asia, 30.0)]));
+        assert!(geo.warning.is_none());
+        assert_eq!(geo.inconclusive.as_deref(), Some("歐洲的錨點無法連線"));
+    }
+}
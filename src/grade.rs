@@ -0,0 +1,298 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::net::IpAddr;
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::checks::health::{self, HealthState};
+use crate::checks::{CheckOutcome, CheckStatus};
+use crate::{PortInfo, ScanResult};
+
+// 端口健康等級，A 最好、F 為無法連線
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema, clap::ValueEnum)]
+#[value(rename_all = "UPPER")]
+pub enum Grade {
+    A,
+    B,
+    C,
+    D,
+    F,
+}
+
+const GRADES: [Grade; 5] = [Grade::A, Grade::B, Grade::C, Grade::D, Grade::F];
+
+impl fmt::Display for Grade {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let letter = match self {
+            Grade::A => "A",
+            Grade::B => "B",
+            Grade::C => "C",
+            Grade::D => "D",
+            Grade::F => "F",
+        };
+        f.write_str(letter)
+    }
+}
+
+// 設定檔 [grading] 區段：延遲門檻 (毫秒)
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct GradingConfig {
+    // 超過此值降為 B
+    pub good_ms: f64,
+    // 超過此值降為 C
+    pub slow_ms: f64,
+    // 超過此值降為 D
+    pub very_slow_ms: f64,
+}
+
+impl Default for GradingConfig {
+    fn default() -> Self {
+        GradingConfig {
+            good_ms: 100.0,
+            slow_ms: 500.0,
+            very_slow_ms: 1000.0,
+        }
+    }
+}
+
+// 評分結果
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PortGrade {
+    pub grade: Grade,
+    pub reason: String,
+}
+
+// 評分依據
+#[derive(Debug, Clone, Copy)]
+pub struct GradeInput {
+    pub reachable: bool,
+    pub latency_ms: Option<f64>,
+    // 多次探測的成功次數 / 總次數
+    pub successes: u32,
+    pub attempts: u32,
+    // 此端口服務檢查中最差的結果
+    pub check: Option<CheckStatus>,
+    // 設定檔的健康檢查失敗 (開放但不健康)
+    pub unhealthy: bool,
+}
+
+// 綜合可達性、延遲、穩定度與服務檢查給出等級
+pub fn grade(input: &GradeInput, config: &GradingConfig) -> PortGrade {
+    if !input.reachable || (input.attempts > 0 && input.successes == 0) {
+        return PortGrade {
+            grade: Grade::F,
+            reason: "無法連線".to_string(),
+        };
+    }
+
+    let mut level = 0;
+    let mut reasons = Vec::new();
+
+    if let Some(ms) = input.latency_ms {
+        let penalty = if ms > config.very_slow_ms {
+            3
+        } else if ms > config.slow_ms {
+            2
+        } else if ms > config.good_ms {
+            1
+        } else {
+            0
+        };
+        if penalty > 0 {
+            level += penalty;
+            reasons.push(format!("延遲 {:.0}ms", ms));
+        }
+    }
+
+    let failures = input.attempts.saturating_sub(input.successes);
+    if failures > 0 {
+        let ratio = f64::from(input.successes) / f64::from(input.attempts);
+        level += if ratio >= 0.8 { 1 } else { 2 };
+        reasons.push(format!("{}/{} 次失敗", failures, input.attempts));
+    }
+
+    match input.check {
+        Some(CheckStatus::Warning) => {
+            level = level.max(2);
+            reasons.push("服務檢查有警告".to_string());
+        }
+        Some(CheckStatus::Error) => {
+            level = level.max(1);
+            reasons.push("服務檢查失敗".to_string());
+        }
+        _ => {}
+    }
+    // 開放但不健康的服務實際上無法使用，至少降為 D
+    if input.unhealthy {
+        level = level.max(3);
+        reasons.push("健康檢查失敗".to_string());
+    }
+
+    let reason = if reasons.is_empty() {
+        "可連線".to_string()
+    } else {
+        format!("可連線但{}", reasons.join(" 且 "))
+    };
+    PortGrade {
+        grade: GRADES[level.min(GRADES.len() - 1)],
+        reason,
+    }
+}
+
+// 依單次掃描結果評分；掃描端錯誤時不評分
+pub fn grade_result(result: &ScanResult, check: Option<CheckStatus>, config: &GradingConfig) -> Option<PortGrade> {
+    if result.error.is_some() {
+        return None;
+    }
+    let input = GradeInput {
+        reachable: result.outbound,
+        latency_ms: result.latency_ms,
+        successes: u32::from(result.outbound),
+        attempts: 1,
+        check,
+        unhealthy: health::state(result) == Some(HealthState::Unhealthy),
+    };
+    Some(grade(&input, config))
+}
+
+// 服務檢查結果的嚴重程度，用來挑出最差的一個
+fn severity(status: CheckStatus) -> u8 {
+    match status {
+        CheckStatus::Ok | CheckStatus::NoResponse => 0,
+        CheckStatus::Error => 1,
+        CheckStatus::Warning => 2,
+    }
+}
+
+// 服務檢查完成後，將結果納入對應端口的等級
+pub fn apply_checks(
+    results: &mut BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>,
+    checks: &[(IpAddr, Vec<CheckOutcome>)],
+    config: &GradingConfig,
+) {
+    for (host, outcomes) in checks {
+        let Some(host_results) = results.get_mut(host) else {
+            continue;
+        };
+        for (port, result) in host_results.iter_mut() {
+            let worst = outcomes
+                .iter()
+                .filter(|o| o.port == port.port)
+                .map(|o| o.status)
+                .max_by_key(|s| severity(*s));
+            if worst.is_some() {
+                result.grade = grade_result(result, worst, config);
+            }
+        }
+    }
+}
+
+// 彩色等級標籤
+pub fn badge(grade: Grade) -> ColoredString {
+    let text = format!(" {} ", grade);
+    match grade {
+        Grade::A | Grade::B => text.black().on_green(),
+        Grade::C => text.black().on_yellow(),
+        Grade::D | Grade::F => text.white().on_red(),
+    }
+}
+
+// 低於 --min-grade 的端口數
+pub fn below(results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>, min: Grade) -> usize {
+    results
+        .values()
+        .flat_map(|ports| ports.values())
+        .filter(|r| r.grade.as_ref().is_some_and(|g| g.grade > min))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::ScanError;
+    use crate::testutil::scan_result;
+
+    fn input(latency_ms: Option<f64>, successes: u32, attempts: u32, check: Option<CheckStatus>) -> GradeInput {
+        GradeInput { reachable: true, latency_ms, successes, attempts, check, unhealthy: false }
+    }
+
+    #[test]
+    fn grading_table() {
+        let unreachable = GradeInput { reachable: false, ..input(None, 0, 0, None) };
+        let cases = [
+            (unreachable, Grade::F, "無法連線"),
+            (input(Some(20.0), 0, 3, None), Grade::F, "無法連線"),
+            (input(Some(20.0), 1, 1, None), Grade::A, "可連線"),
+            (input(None, 0, 0, None), Grade::A, "可連線"),
+            (input(Some(100.0), 1, 1, None), Grade::A, "可連線"),
+            (input(Some(150.0), 1, 1, None), Grade::B, "可連線但延遲 150ms"),
+            (input(Some(800.0), 1, 1, None), Grade::C, "可連線但延遲 800ms"),
+            (input(Some(1500.0), 1, 1, None), Grade::D, "可連線但延遲 1500ms"),
+            (input(None, 4, 5, None), Grade::B, "可連線但1/5 次失敗"),
+            (input(None, 3, 5, None), Grade::C, "可連線但2/5 次失敗"),
+            (input(Some(150.0), 4, 5, None), Grade::C, "可連線但延遲 150ms 且 1/5 次失敗"),
+            (input(Some(800.0), 4, 5, None), Grade::D, "可連線但延遲 800ms 且 1/5 次失敗"),
+            (input(Some(800.0), 3, 5, None), Grade::F, "可連線但延遲 800ms 且 2/5 次失敗"),
+            (input(Some(1500.0), 1, 2, None), Grade::F, "可連線但延遲 1500ms 且 1/2 次失敗"),
+            (input(Some(20.0), 1, 1, Some(CheckStatus::Ok)), Grade::A, "可連線"),
+            (input(Some(20.0), 1, 1, Some(CheckStatus::NoResponse)), Grade::A, "可連線"),
+            (input(Some(20.0), 1, 1, Some(CheckStatus::Error)), Grade::B, "可連線但服務檢查失敗"),
+            (input(Some(20.0), 1, 1, Some(CheckStatus::Warning)), Grade::C, "可連線但服務檢查有警告"),
+            (input(Some(150.0), 1, 1, Some(CheckStatus::Error)), Grade::B, "可連線但延遲 150ms 且 服務檢查失敗"),
+            (input(Some(1500.0), 1, 1, Some(CheckStatus::Warning)), Grade::D, "可連線但延遲 1500ms 且 服務檢查有警告"),
+            (GradeInput { unhealthy: true, ..input(Some(20.0), 1, 1, None) }, Grade::D, "可連線但健康檢查失敗"),
+            (GradeInput { unhealthy: true, ..input(Some(800.0), 4, 5, None) }, Grade::D, "可連線但延遲 800ms 且 1/5 次失敗 且 健康檢查失敗"),
+        ];
+        for (input, expected, reason) in cases {
+            let graded = grade(&input, &GradingConfig::default());
+            assert_eq!((graded.grade, graded.reason.as_str()), (expected, reason), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn thresholds_come_from_the_config() {
+        let strict = GradingConfig { good_ms: 10.0, slow_ms: 20.0, very_slow_ms: 30.0 };
+        let grades: Vec<Grade> =
+            [5.0, 15.0, 25.0, 35.0].into_iter().map(|ms| grade(&input(Some(ms), 1, 1, None), &strict).grade).collect();
+        assert_eq!(grades, vec![Grade::A, Grade::B, Grade::C, Grade::D]);
+        let config: GradingConfig = toml::from_str("good_ms = 50").unwrap();
+        assert_eq!((config.good_ms, config.slow_ms), (50.0, 500.0));
+        assert!(toml::from_str::<GradingConfig>("fast_ms = 1").is_err());
+    }
+
+    #[test]
+    fn results_are_graded_unless_the_scanner_failed() {
+        let config = GradingConfig::default();
+        let mut result = scan_result(true);
+        result.latency_ms = Some(250.0);
+        assert_eq!(grade_result(&result, None, &config).map(|g| g.grade), Some(Grade::B));
+        assert_eq!(grade_result(&scan_result(false), None, &config).map(|g| g.grade), Some(Grade::F));
+        result.error = Some(ScanError::TooManyOpenFiles);
+        assert_eq!(grade_result(&result, None, &config), None);
+    }
+
+    #[test]
+    fn checks_use_the_worst_outcome_and_min_grade_counts() {
+        let host: IpAddr = "192.0.2.1".parse().unwrap();
+        let config = GradingConfig::default();
+        let ssh = PortInfo::new(22, "SSH", "Remote");
+        let web = PortInfo::new(80, "HTTP", "Web");
+        let mut open = scan_result(true);
+        open.latency_ms = Some(5.0);
+        open.grade = grade_result(&open, None, &config);
+        let mut results = BTreeMap::from([(host, HashMap::from([(ssh.clone(), open.clone()), (web.clone(), open)]))]);
+        let outcomes = vec![
+            CheckOutcome::new("SSH", 22, CheckStatus::Error, "失敗"),
+            CheckOutcome::new("SSH", 22, CheckStatus::Warning, "警告"),
+            CheckOutcome::new("SSH", 22, CheckStatus::Ok, "正常"),
+        ];
+        apply_checks(&mut results, &[(host, outcomes)], &config);
+        assert_eq!(results[&host][&ssh].grade.as_ref().map(|g| g.grade), Some(Grade::C));
+        assert_eq!(results[&host][&web].grade.as_ref().map(|g| g.grade), Some(Grade::A));
+
+        assert_eq!(below(&results, Grade::A), 1);
+        assert_eq!(below(&results, Grade::B), 1);
+        assert_eq!(below(&results, Grade::C), 0);
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::ranges;
+use crate::{PortInfo, ScanResult};
+
+// 設定檔的 [[groups]]：由多個端口組成的服務，例如
+//   [[groups]]
+//   name = "ftp-passive"
+//   ports = "21,50000-50010"
+//   category = "File"
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GroupDefinition {
+    pub name: String,
+    // 與 --ports 相同的格式
+    pub ports: String,
+    pub category: Option<String>,
+}
+
+// 內建群組 (名稱, 端口, 類別)；被動模式與動態端口範圍依伺服器設定而不同，請以設定檔覆蓋
+const BUILTIN_GROUPS: &[(&str, &str, &str)] = &[
+    // FTP 控制端口與被動模式資料端口
+    ("ftp-passive", "21,50000-50010", "File"),
+    // RPC 端點對應器與 Windows 動態 RPC 端口的起點
+    ("rpc", "135,49664-49669", "Windows"),
+    // SIP 信令與 SIP over TLS
+    ("sip", "5060,5061", "VoIP"),
+];
+
+// 驗證過的服務群組
+#[derive(Debug, Clone)]
+pub struct ServiceGroup {
+    pub name: String,
+    pub ports: BTreeSet<u16>,
+    pub category: Option<String>,
+}
+
+// 設定檔群組加上內建群組；同名的設定檔群組覆蓋內建群組
+#[derive(Debug, Clone, Default)]
+pub struct Groups(Vec<ServiceGroup>);
+
+impl Groups {
+    pub fn build(configured: &[GroupDefinition]) -> Result<Self, String> {
+        let mut groups = Vec::new();
+        for (i, definition) in configured.iter().enumerate() {
+            let name = definition.name.trim();
+            if name.is_empty() || name.contains(',') {
+                return Err(format!("服務群組 #{} 的名稱無效: {:?}", i + 1, definition.name));
+            }
+            if groups.iter().any(|g: &ServiceGroup| g.name == name) {
+                return Err(format!("服務群組名稱重複: {}", name));
+            }
+            let ports = crate::portspec::parse_list(&definition.ports).map_err(|e| format!("服務群組 {}: {}", name, e))?;
+            groups.push(ServiceGroup {
+                name: name.to_string(),
+                ports,
+                category: definition.category.clone(),
+            });
+        }
+        for (name, ports, category) in BUILTIN_GROUPS {
+            if groups.iter().any(|g| g.name == *name) {
+                continue;
+            }
+            groups.push(ServiceGroup {
+                name: name.to_string(),
+                ports: crate::portspec::parse_list(ports)?,
+                category: Some(category.to_string()),
+            });
+        }
+        Ok(Groups(groups))
+    }
+
+    pub fn find(&self, name: &str) -> Result<&ServiceGroup, String> {
+        self.0.iter().find(|g| g.name == name).ok_or_else(|| {
+            let names: Vec<&str> = self.0.iter().map(|g| g.name.as_str()).collect();
+            format!("找不到服務群組 {} (可用: {})", name, names.join(", "))
+        })
+    }
+
+    // --group 指定群組的所有端口，作為 --ports 的補充；未指定群組時為 None
+    pub fn port_spec(&self, names: &[String]) -> Result<Option<String>, String> {
+        let mut ports = BTreeSet::new();
+        for name in names {
+            ports.extend(self.find(name)?.ports.iter().copied());
+        }
+        Ok((!ports.is_empty()).then(|| ports.iter().map(u16::to_string).collect::<Vec<_>>().join(",")))
+    }
+
+    // 標記選用群組的成員端口；同一端口屬於多個群組時採用先列出的群組
+    // 資料庫中沒有的成員端口以群組名稱為服務名稱、群組類別為類別
+    pub fn assign(&self, ports: &mut [PortInfo], selected: &[String]) {
+        let groups: Vec<&ServiceGroup> = self.0.iter().filter(|g| selected.contains(&g.name)).collect();
+        for port in ports {
+            let Some(group) = groups.iter().find(|g| g.ports.contains(&port.port)) else {
+                continue;
+            };
+            port.group = Some(group.name.clone());
+            if port.service == ranges::UNKNOWN_SERVICE {
+                port.service = group.name.clone();
+                if let Some(category) = &group.category {
+                    port.category = category.clone();
+                }
+            }
+        }
+    }
+}
+
+// 一個主機上服務群組的摘要
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct GroupSummary {
+    pub name: String,
+    pub ports: usize,
+    pub open: usize,
+    // 掃描端錯誤的成員端口，不代表端口狀態
+    pub errors: usize,
+    pub open_ports: Vec<u16>,
+}
+
+// 依端口的群組標記彙整；沒有群組成員時為空
+pub fn summarize(results: &HashMap<PortInfo, ScanResult>) -> Vec<GroupSummary> {
+    let mut groups: BTreeMap<&str, GroupSummary> = BTreeMap::new();
+    for (port, result) in results {
+        let Some(name) = port.group.as_deref() else {
+            continue;
+        };
+        let summary = groups.entry(name).or_insert_with(|| GroupSummary {
+            name: name.to_string(),
+            ports: 0,
+            open: 0,
+            errors: 0,
+            open_ports: Vec::new(),
+        });
+        summary.ports += 1;
+        match (&result.error, result.outbound) {
+            (Some(_), _) => summary.errors += 1,
+            (None, true) => {
+                summary.open += 1;
+                summary.open_ports.push(port.port);
+            }
+            (None, false) => {}
+        }
+    }
+    let mut summaries: Vec<GroupSummary> = groups.into_values().collect();
+    summaries.iter_mut().for_each(|s| s.open_ports.sort_unstable());
+    summaries
+}
+
+// 群組的一行摘要：全部可連線為綠色，部分可連線為黃色
+pub fn describe(summary: &GroupSummary) -> String {
+    let counts = format!("{}/{} 個端口可連線", summary.open, summary.ports);
+    let counts = match summary.open {
+        0 => counts.red(),
+        n if n == summary.ports => counts.green(),
+        _ => counts.yellow(),
+    };
+    let open = match summary.open_ports.is_empty() {
+        true => String::new(),
+        false => format!("  ({})", crate::plan::compress_ports(&summary.open_ports)),
+    };
+    let errors = match summary.errors {
+        0 => String::new(),
+        n => format!("  {}", format!("{} 個掃描端錯誤", n).yellow()),
+    };
+    format!("{}{}{}", counts, open.dimmed(), errors)
+}
@@ -0,0 +1,290 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use schemars::JsonSchema;
+use serde::Serialize;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior};
+use crate::output::Entry;
+use crate::scanid;
+
+// 心跳 webhook 每次 POST 的等待時間
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+// --progress-file 保留的最近發現數
+pub const RECENT_FINDINGS: usize = 5;
+
+// 排程器更新的進度計數；計數只用原子操作，只有發現開放端口時才取得鎖
+#[derive(Debug, Default)]
+pub struct Counters {
+    completed: AtomicU64,
+    in_flight: AtomicUsize,
+    // 最近的發現 (由舊到新)，最多 RECENT_FINDINGS 筆
+    findings: Mutex<VecDeque<Finding>>,
+}
+
+// 值得注意的發現：出站可連線的端口
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct Finding {
+    pub host: String,
+    pub port: u16,
+    pub service: String,
+}
+
+impl Counters {
+    // 探測取得許可、開始進行
+    pub fn start(&self) {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // 探測完成或被放棄
+    pub fn finish(&self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+        self.completed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // 被取消的主機未排程的端口直接計入完成數
+    pub fn skip(&self, count: u64) {
+        self.completed.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn found(&self, finding: Finding) {
+        let mut findings = self.findings.lock().unwrap_or_else(|e| e.into_inner());
+        if findings.len() == RECENT_FINDINGS {
+            findings.pop_front();
+        }
+        findings.push_back(finding);
+    }
+
+    // 由新到舊
+    pub fn recent(&self) -> Vec<Finding> {
+        self.findings.lock().unwrap_or_else(|e| e.into_inner()).iter().rev().cloned().collect()
+    }
+}
+
+// NDJSON 中以 "event": "progress" 與結果紀錄區分
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Progress,
+}
+
+// --heartbeat 的 progress 事件
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct ProgressEvent {
+    pub event: EventKind,
+    pub completed: u64,
+    pub total: u64,
+    // 最近一個間隔的每秒完成數
+    pub rate: f64,
+    // 依目前速率估計的剩餘秒數；速率為 0 時省略
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eta_secs: Option<u64>,
+    pub in_flight: usize,
+    pub elapsed_ms: u64,
+    // 掃描結束後的最後一筆
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub done: bool,
+}
+
+// 依計數產生事件；速率以上一次取樣起算
+pub struct Sampler {
+    counters: Arc<Counters>,
+    total: u64,
+    started: Instant,
+    last: (Instant, u64),
+}
+
+impl Sampler {
+    pub fn new(counters: Arc<Counters>, total: u64) -> Self {
+        let now = Instant::now();
+        Sampler { counters, total, started: now, last: (now, 0) }
+    }
+
+    pub fn sample(&mut self, done: bool) -> ProgressEvent {
+        let now = Instant::now();
+        let completed = self.counters.completed.load(Ordering::Relaxed).min(self.total);
+        let in_flight = self.counters.in_flight.load(Ordering::Relaxed);
+        let (since, before) = self.last;
+        let secs = now.duration_since(since).as_secs_f64();
+        let rate = match secs > 0.0 {
+            true => (completed - before) as f64 / secs,
+            false => 0.0,
+        };
+        self.last = (now, completed);
+        ProgressEvent {
+            event: EventKind::Progress,
+            completed,
+            total: self.total,
+            rate: (rate * 10.0).round() / 10.0,
+            eta_secs: (rate > 0.0 && !done).then(|| ((self.total - completed) as f64 / rate).ceil() as u64),
+            in_flight,
+            elapsed_ms: now.duration_since(self.started).as_millis() as u64,
+            done,
+        }
+    }
+}
+
+// 事件的去處：--output 的寫入通道與 --heartbeat-webhook
+pub struct Listeners {
+    pub stream: Option<mpsc::Sender<Entry>>,
+    pub webhook: Option<String>,
+    // webhook 的事件加上掃描 ID；NDJSON 由寫入端加上
+    pub scan_id: String,
+}
+
+impl Listeners {
+    fn is_empty(&self) -> bool {
+        self.stream.is_none() && self.webhook.is_none()
+    }
+}
+
+// 執行中的心跳；stop 送出最後一筆事件
+pub struct Heartbeat {
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<Option<String>>,
+}
+
+pub fn start(counters: Arc<Counters>, total: u128, interval: Duration, listeners: Listeners) -> Option<Heartbeat> {
+    if listeners.is_empty() {
+        return None;
+    }
+    let (stop, mut stopped) = oneshot::channel();
+    let mut sampler = Sampler::new(counters, total.min(u64::MAX as u128) as u64);
+    let client = reqwest::Client::builder().timeout(WEBHOOK_TIMEOUT).build().ok();
+    let task = tokio::spawn(async move {
+        let mut failures = Failures::default();
+        let mut ticker = tokio::time::interval_at(Instant::now() + interval, interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            let done = tokio::select! {
+                _ = &mut stopped => true,
+                _ = ticker.tick() => false,
+            };
+            let event = sampler.sample(done);
+            if let Some(stream) = &listeners.stream {
+                // 寫入端落後時略過這一筆：結果仍在流入，不需要心跳
+                match done {
+                    true => {
+                        let _ = stream.send(Entry::Progress(event.clone())).await;
+                    }
+                    false => {
+                        let _ = stream.try_send(Entry::Progress(event.clone()));
+                    }
+                }
+            }
+            if let (Some(url), Some(client)) = (&listeners.webhook, &client) {
+                failures.record(client.post(url).json(&scanid::tag(&listeners.scan_id, &event)).send().await.and_then(|r| r.error_for_status()).err());
+            }
+            if done {
+                return failures.warning();
+            }
+        }
+    });
+    Some(Heartbeat { stop, task })
+}
+
+impl Heartbeat {
+    // 送出 done 事件後結束；webhook 曾經失敗時傳回提醒
+    pub async fn stop(self) -> Option<String> {
+        let _ = self.stop.send(());
+        self.task.await.ok().flatten()
+    }
+}
+
+// webhook 失敗不影響掃描，結束時彙整一次
+#[derive(Default)]
+struct Failures {
+    count: usize,
+    last: Option<String>,
+}
+
+impl Failures {
+    fn record(&mut self, error: Option<reqwest::Error>) {
+        if let Some(error) = error {
+            self.count += 1;
+            self.last = Some(error.without_url().to_string());
+        }
+    }
+
+    fn warning(&self) -> Option<String> {
+        let last = self.last.as_ref()?;
+        Some(format!("心跳 webhook 失敗 {} 次，最後一次: {}", self.count, last))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn samples_report_rate_and_eta_for_the_last_interval() {
+        let counters = Arc::new(Counters::default());
+        let mut sampler = Sampler::new(counters.clone(), 100);
+        for _ in 0..23 {
+            counters.start();
+        }
+        let first = sampler.sample(false);
+        assert_eq!((first.completed, first.in_flight, first.rate, first.eta_secs), (0, 23, 0.0, None));
+
+        tokio::time::advance(Duration::from_secs(2)).await;
+        (0..20).for_each(|_| counters.finish());
+        counters.skip(10);
+        let second = sampler.sample(false);
+        assert_eq!((second.completed, second.in_flight, second.rate, second.eta_secs), (30, 3, 15.0, Some(5)));
+        assert_eq!(second.elapsed_ms, 2000);
+
+        // 速率只看最近一個間隔
+        tokio::time::advance(Duration::from_secs(5)).await;
+        counters.skip(10);
+        let third = sampler.sample(false);
+        assert_eq!((third.rate, third.eta_secs), (2.0, Some(30)));
+        let last = sampler.sample(true);
+        assert!(last.done && last.eta_secs.is_none());
+    }
+
+    #[test]
+    fn only_the_latest_findings_are_kept() {
+        let counters = Counters::default();
+        for port in 1..=7 {
+            counters.found(Finding { host: "192.0.2.1".to_string(), port, service: "Test".to_string() });
+        }
+        let ports: Vec<u16> = counters.recent().iter().map(|finding| finding.port).collect();
+        assert_eq!(ports, [7, 6, 5, 4, 3]);
+    }
+
+    #[test]
+    fn events_are_tagged_for_ndjson_consumers() {
+        let mut sampler = Sampler::new(Arc::new(Counters::default()), 4);
+        let value = serde_json::to_value(sampler.sample(false)).unwrap();
+        assert_eq!(value["event"], "progress");
+        assert_eq!(value["total"], 4);
+        assert!(value.get("eta_secs").is_none() && value.get("done").is_none());
+        assert_eq!(serde_json::to_value(sampler.sample(true)).unwrap()["done"], true);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn heartbeat_streams_ticks_and_a_final_event() {
+        let counters = Arc::new(Counters::default());
+        let (tx, mut rx) = mpsc::channel(8);
+        let heartbeat = start(counters.clone(), 10, Duration::from_secs(5), Listeners { stream: Some(tx), webhook: None, scan_id: String::new() }).unwrap();
+        counters.start();
+        counters.finish();
+        tokio::time::sleep(Duration::from_millis(5500)).await;
+        let Some(Entry::Progress(tick)) = rx.recv().await else { panic!("沒有心跳") };
+        assert_eq!((tick.completed, tick.done), (1, false));
+        counters.skip(9);
+        assert_eq!(heartbeat.stop().await, None);
+        let Some(Entry::Progress(last)) = rx.recv().await else { panic!("沒有最後一筆") };
+        assert_eq!((last.completed, last.done), (10, true));
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[test]
+    fn no_listeners_means_no_heartbeat() {
+        let listeners = Listeners { stream: None, webhook: None, scan_id: String::new() };
+        assert!(start(Arc::new(Counters::default()), 1, Duration::from_secs(1), listeners).is_none());
+    }
+}
@@ -0,0 +1,448 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use colored::*;
+use rusqlite::Connection;
+use schemars::JsonSchema;
+use serde::Serialize;
+use crate::matrix::html_escape;
+use crate::{identity, stats, timefmt};
+
+// 期間內狀態改變至少這麼多次的端口視為反覆變化
+const FLAP_THRESHOLD: usize = 2;
+
+// 端口的狀態改變 (開放 ↔ 不開放)
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct StateChange {
+    pub port: u16,
+    pub service: String,
+    // 發現改變的掃描時間 (Unix 秒)
+    pub at: i64,
+    pub open: bool,
+}
+
+// 期間內第一次記錄的服務指紋
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct NewFingerprint {
+    pub port: u16,
+    pub kind: String,
+    pub value: String,
+    pub first_seen: i64,
+}
+
+// 一天 (UTC) 內開放端口的延遲中位數
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct DailyLatency {
+    pub day: String,
+    pub median_ms: f64,
+    pub samples: usize,
+}
+
+// 期間內反覆開關的端口
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct Flapping {
+    pub port: u16,
+    pub service: String,
+    pub changes: usize,
+}
+
+// history --diff --since 的一個識別的報告
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct WindowReport {
+    pub identity: String,
+    pub since: i64,
+    pub until: i64,
+    // 期間內的掃描次數
+    pub runs: usize,
+    // 期間開始前最後一次掃描；作為第一次變化的比較基準
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<i64>,
+    pub changes: Vec<StateChange>,
+    pub fingerprints: Vec<NewFingerprint>,
+    pub latency: Vec<DailyLatency>,
+    pub flapping: Vec<Flapping>,
+}
+
+// 一次掃描中一個端口的狀態：(掃描時間, 端口, 服務, 是否開放)
+type PortState = (i64, u16, String, bool);
+
+// 依掃描時間排序的每個端口狀態；同一次掃描中未測試的端口不出現
+fn load_states(conn: &Connection, identity: &str, since: i64) -> rusqlite::Result<(Option<i64>, Vec<PortState>)> {
+    let baseline: Option<i64> = conn.query_row(
+        "SELECT MAX(scanned_at) FROM scan_results WHERE identity = ?1 AND scanned_at < ?2",
+        rusqlite::params![identity, since],
+        |row| row.get(0),
+    )?;
+    let mut rows = conn.prepare(
+        "SELECT scanned_at, port, service, outbound FROM scan_results
+         WHERE identity = ?1 AND scanned_at >= ?2 AND outbound IS NOT NULL
+         ORDER BY scanned_at, port",
+    )?;
+    let states = rows
+        .query_map(rusqlite::params![identity, baseline.unwrap_or(since)], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok((baseline, states))
+}
+
+// 依序比較每個端口相鄰兩次測試的狀態；基準掃描只提供起始狀態
+fn extract_changes(states: &[PortState], since: i64) -> Vec<StateChange> {
+    let mut last: BTreeMap<u16, bool> = BTreeMap::new();
+    let mut changes = Vec::new();
+    for (at, port, service, open) in states {
+        let previous = last.insert(*port, *open);
+        if *at >= since && previous.is_some_and(|previous| previous != *open) {
+            changes.push(StateChange { port: *port, service: service.clone(), at: *at, open: *open });
+        }
+    }
+    changes
+}
+
+fn flapping(changes: &[StateChange]) -> Vec<Flapping> {
+    let mut counts: BTreeMap<u16, (String, usize)> = BTreeMap::new();
+    for change in changes {
+        counts.entry(change.port).or_insert_with(|| (change.service.clone(), 0)).1 += 1;
+    }
+    counts
+        .into_iter()
+        .filter(|(_, (_, changes))| *changes >= FLAP_THRESHOLD)
+        .map(|(port, (service, changes))| Flapping { port, service, changes })
+        .collect()
+}
+
+// 以 UTC 日期分組開放端口的延遲，每組取中位數
+fn daily_latency(conn: &Connection, identity: &str, since: i64) -> rusqlite::Result<Vec<DailyLatency>> {
+    let mut rows = conn.prepare(
+        "SELECT date(scanned_at, 'unixepoch') AS day, latency_ms FROM scan_results
+         WHERE identity = ?1 AND scanned_at >= ?2 AND outbound = 1 AND latency_ms IS NOT NULL
+         ORDER BY day",
+    )?;
+    let mut days: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    for row in rows.query_map(rusqlite::params![identity, since], |row| Ok((row.get::<_, String>(0)?, row.get::<_, f64>(1)?)))? {
+        let (day, latency) = row?;
+        days.entry(day).or_default().push(latency);
+    }
+    Ok(days
+        .into_iter()
+        .filter_map(|(day, values)| {
+            let samples = values.len();
+            stats::median(values).map(|median_ms| DailyLatency { day, median_ms, samples })
+        })
+        .collect())
+}
+
+fn new_fingerprints(conn: &Connection, identity: &str, since: i64) -> rusqlite::Result<Vec<NewFingerprint>> {
+    let mut rows = conn.prepare(
+        "SELECT f.port, f.kind, b.content, f.first_seen FROM service_fingerprints f JOIN blobs b ON b.sha256 = f.value_sha256
+         WHERE f.identity = ?1 AND f.first_seen >= ?2 ORDER BY f.first_seen, f.port, f.kind",
+    )?;
+    let fingerprints = rows
+        .query_map(rusqlite::params![identity, since], |row| {
+            Ok(NewFingerprint { port: row.get(0)?, kind: row.get(1)?, value: row.get(2)?, first_seen: row.get(3)? })
+        })?
+        .collect();
+    fingerprints
+}
+
+// 一個識別在 since 之後的變化報告
+pub fn build(conn: &Connection, identity: &str, since: i64, until: i64) -> rusqlite::Result<WindowReport> {
+    let (baseline, states) = load_states(conn, identity, since)?;
+    let runs: i64 = conn.query_row(
+        "SELECT COUNT(DISTINCT scanned_at) FROM scan_results WHERE identity = ?1 AND scanned_at >= ?2",
+        rusqlite::params![identity, since],
+        |row| row.get(0),
+    )?;
+    let changes = extract_changes(&states, since);
+    Ok(WindowReport {
+        identity: identity.to_string(),
+        since,
+        until,
+        runs: runs as usize,
+        baseline,
+        flapping: flapping(&changes),
+        changes,
+        fingerprints: new_fingerprints(conn, identity, since)?,
+        latency: daily_latency(conn, identity, since)?,
+    })
+}
+
+// 與前一天相比的延遲變化，例如 "+18%"
+fn trend(previous: Option<&DailyLatency>, day: &DailyLatency) -> String {
+    match previous {
+        Some(previous) if previous.median_ms > 0.0 => {
+            format!("{:+.0}%", (day.median_ms - previous.median_ms) / previous.median_ms * 100.0)
+        }
+        _ => String::new(),
+    }
+}
+
+fn describe_change(change: &StateChange) -> String {
+    match change.open {
+        true => format!("{} ({}) 開放", change.port, change.service),
+        false => format!("{} ({}) 不再開放", change.port, change.service),
+    }
+}
+
+// 期間內沒有可比較的掃描時的說明
+fn gap(report: &WindowReport) -> Option<&'static str> {
+    match (report.runs, report.baseline) {
+        (0, _) => Some("這段期間沒有掃描"),
+        (1, None) => Some("期間內只有一次掃描且之前沒有紀錄，無法比較"),
+        _ => None,
+    }
+}
+
+pub fn display(report: &WindowReport) {
+    println!("\n{}", format!("=== {} ===", report.identity).bold());
+    println!(
+        "{} → {}，{} 次掃描",
+        timefmt::timestamp(report.since),
+        timefmt::timestamp(report.until),
+        report.runs
+    );
+    match gap(report) {
+        Some(gap) => println!("{}", gap.dimmed()),
+        None => {
+            println!("{}", "狀態變化:".bold());
+            if report.changes.is_empty() {
+                println!("  沒有變化");
+            }
+            for change in &report.changes {
+                let line = format!("  {}  {}", timefmt::timestamp(change.at), describe_change(change));
+                println!("{}", if change.open { line.green() } else { line.red() });
+            }
+        }
+    }
+    if !report.flapping.is_empty() {
+        println!("{}", "反覆變化:".bold());
+        for flap in &report.flapping {
+            println!("  {}", format!("{} ({}) 改變 {} 次", flap.port, flap.service, flap.changes).yellow());
+        }
+    }
+    if !report.fingerprints.is_empty() {
+        println!("{}", "新指紋:".bold());
+        for fingerprint in &report.fingerprints {
+            println!(
+                "  {}  {} {}: {}",
+                timefmt::timestamp(fingerprint.first_seen),
+                fingerprint.port,
+                fingerprint.kind,
+                fingerprint.value
+            );
+        }
+    }
+    if !report.latency.is_empty() {
+        println!("{}", "每日延遲中位數 (UTC):".bold());
+        let mut previous = None;
+        for day in &report.latency {
+            println!("  {}  {:>8.1} ms  {:>5}  (n={})", day.day, day.median_ms, trend(previous, day).dimmed(), day.samples);
+            previous = Some(day);
+        }
+    }
+}
+
+pub fn to_html(reports: &[WindowReport]) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>期間變化報告</title>\n<style>\
+         body{font-family:sans-serif}table{border-collapse:collapse;font-family:monospace;margin-bottom:1em}\
+         td,th{border:1px solid #ccc;padding:2px 6px}.open{color:#080}.closed{color:#c00}.flap{color:#b80}\
+         </style></head><body>\n",
+    );
+    for report in reports {
+        out.push_str(&format!(
+            "<h2>{}</h2>\n<p>{} → {}，{} 次掃描</p>\n",
+            html_escape(&report.identity),
+            html_escape(&timefmt::timestamp(report.since)),
+            html_escape(&timefmt::timestamp(report.until)),
+            report.runs
+        ));
+        match gap(report) {
+            Some(gap) => out.push_str(&format!("<p>{}</p>\n", gap)),
+            None => {
+                out.push_str("<h3>狀態變化</h3>\n<table>\n<tr><th>時間</th><th>端口</th><th>服務</th><th>狀態</th></tr>\n");
+                for change in &report.changes {
+                    let (class, state) = if change.open { ("open", "開放") } else { ("closed", "不再開放") };
+                    out.push_str(&format!(
+                        "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                        class,
+                        html_escape(&timefmt::timestamp(change.at)),
+                        change.port,
+                        html_escape(&change.service),
+                        state
+                    ));
+                }
+                out.push_str("</table>\n");
+            }
+        }
+        if !report.flapping.is_empty() {
+            out.push_str("<h3>反覆變化</h3>\n<table>\n<tr><th>端口</th><th>服務</th><th>改變次數</th></tr>\n");
+            for flap in &report.flapping {
+                out.push_str(&format!(
+                    "<tr class=\"flap\"><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                    flap.port,
+                    html_escape(&flap.service),
+                    flap.changes
+                ));
+            }
+            out.push_str("</table>\n");
+        }
+        if !report.fingerprints.is_empty() {
+            out.push_str("<h3>新指紋</h3>\n<table>\n<tr><th>首次記錄</th><th>端口</th><th>種類</th><th>指紋</th></tr>\n");
+            for fingerprint in &report.fingerprints {
+                out.push_str(&format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                    html_escape(&timefmt::timestamp(fingerprint.first_seen)),
+                    fingerprint.port,
+                    html_escape(&fingerprint.kind),
+                    html_escape(&fingerprint.value)
+                ));
+            }
+            out.push_str("</table>\n");
+        }
+        if !report.latency.is_empty() {
+            out.push_str("<h3>每日延遲中位數 (UTC)</h3>\n<table>\n<tr><th>日期</th><th>中位數 (ms)</th><th>變化</th><th>樣本</th></tr>\n");
+            let mut previous = None;
+            for day in &report.latency {
+                out.push_str(&format!(
+                    "<tr><td>{}</td><td>{:.1}</td><td>{}</td><td>{}</td></tr>\n",
+                    day.day,
+                    day.median_ms,
+                    trend(previous, day),
+                    day.samples
+                ));
+                previous = Some(day);
+            }
+            out.push_str("</table>\n");
+        }
+    }
+    out.push_str("</body></html>\n");
+    out
+}
+
+// portscanner history DB HOST --diff --since 7d [--out FILE]
+pub fn run(db: &Path, query: &str, window: Duration, out: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    if !db.exists() {
+        return Err(format!("找不到結果資料庫 {}", db.display()).into());
+    }
+    let conn = crate::output::open_database(db).map_err(|e| e.to_string())?;
+    let identities = identity::matching_identities(&conn, query)?;
+    if identities.is_empty() {
+        return Err(format!("{} 中沒有 {} 的紀錄", db.display(), query).into());
+    }
+    let until = timefmt::now();
+    let since = until - window.as_secs() as i64;
+    let reports = identities
+        .iter()
+        .map(|identity| build(&conn, identity, since, until))
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for report in &reports {
+        display(report);
+    }
+    if let Some(path) = out {
+        let html = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("html") || ext.eq_ignore_ascii_case("htm"));
+        let content = match html {
+            true => to_html(&reports),
+            false => serde_json::to_string_pretty(&reports)?,
+        };
+        fs::write(path, content).map_err(|e| format!("無法寫入 {}: {}", path.display(), e))?;
+        println!("\n報告已寫入 {}", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 86400;
+    // 2026-10-01 00:00:00 UTC
+    const START: i64 = 1_790_812_800;
+
+    fn database() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        crate::output::migrate(&conn).unwrap();
+        conn
+    }
+
+    fn insert(conn: &Connection, at: i64, port: u16, open: bool, latency_ms: Option<f64>) {
+        conn.execute(
+            "INSERT INTO scan_results (scanned_at, host, port, service, category, inbound, outbound, tags, identity, latency_ms)
+             VALUES (?1, '192.0.2.1', ?2, 'svc', 'Other', ?3, ?3, '[]', 'db01', ?4)",
+            rusqlite::params![at, port, open, latency_ms],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn changes_are_extracted_across_sequential_runs_and_flapping_is_counted() {
+        let conn = database();
+        // 基準：5432 開放、22 開放
+        insert(&conn, START - DAY, 5432, true, None);
+        insert(&conn, START - DAY, 22, true, None);
+        // 期間內：5432 關 → 開 → 關，22 一直開放，8080 新開放
+        insert(&conn, START + 3600, 5432, false, None);
+        insert(&conn, START + 3600, 22, true, None);
+        insert(&conn, START + DAY, 5432, true, None);
+        insert(&conn, START + DAY, 8080, false, None);
+        insert(&conn, START + 2 * DAY, 5432, false, None);
+        insert(&conn, START + 2 * DAY, 8080, true, None);
+
+        let report = build(&conn, "db01", START, START + 3 * DAY).unwrap();
+        assert_eq!((report.runs, report.baseline), (3, Some(START - DAY)));
+        let changes: Vec<(i64, u16, bool)> = report.changes.iter().map(|c| (c.at - START, c.port, c.open)).collect();
+        assert_eq!(changes, [(3600, 5432, false), (DAY, 5432, true), (2 * DAY, 5432, false), (2 * DAY, 8080, true)]);
+        assert_eq!(report.flapping, [Flapping { port: 5432, service: "svc".to_string(), changes: 3 }]);
+    }
+
+    #[test]
+    fn latency_is_bucketed_by_day_with_medians() {
+        let conn = database();
+        for (at, port, latency) in [(START, 1, 10.0), (START + 60, 2, 30.0), (START + 120, 3, 20.0), (START + DAY, 1, 40.0)] {
+            insert(&conn, at, port, true, Some(latency));
+        }
+        // 不開放的端口與期間前的掃描不列入
+        insert(&conn, START + DAY, 2, false, Some(900.0));
+        insert(&conn, START - DAY, 1, true, Some(900.0));
+
+        let report = build(&conn, "db01", START, START + 2 * DAY).unwrap();
+        let days: Vec<(&str, f64, usize)> = report.latency.iter().map(|d| (d.day.as_str(), d.median_ms, d.samples)).collect();
+        assert_eq!(days, [("2026-10-01", 20.0, 3), ("2026-10-02", 40.0, 1)]);
+        assert_eq!(trend(Some(&report.latency[0]), &report.latency[1]), "+100%");
+    }
+
+    #[test]
+    fn fingerprints_first_seen_in_the_window_are_listed() {
+        let conn = database();
+        for (port, kind, value, first_seen) in [(22, "ssh_host_key", "ssh-ed25519 SHA256:new", START + 10), (443, "tls_certificate", "SHA256:old", START - DAY)] {
+            conn.execute(
+                "INSERT INTO service_fingerprints VALUES ('db01', ?1, ?2, ?3, ?4, ?5)",
+                rusqlite::params![port, kind, crate::blobs::store(&conn, value).unwrap(), first_seen, START + 10],
+            )
+            .unwrap();
+        }
+        insert(&conn, START + 10, 22, true, None);
+        let report = build(&conn, "db01", START, START + DAY).unwrap();
+        let values: Vec<&str> = report.fingerprints.iter().map(|f| f.value.as_str()).collect();
+        assert_eq!(values, ["ssh-ed25519 SHA256:new"]);
+    }
+
+    #[test]
+    fn empty_windows_and_single_runs_are_explained() {
+        let conn = database();
+        insert(&conn, START - 10 * DAY, 22, true, None);
+        let empty = build(&conn, "db01", START, START + DAY).unwrap();
+        assert_eq!(empty.runs, 0);
+        assert_eq!(gap(&empty), Some("這段期間沒有掃描"));
+        assert!(to_html(&[empty]).contains("這段期間沒有掃描"));
+
+        let conn = database();
+        insert(&conn, START + 10, 22, true, None);
+        let single = build(&conn, "db01", START, START + DAY).unwrap();
+        assert_eq!((single.runs, single.baseline), (1, None));
+        assert!(single.changes.is_empty());
+        assert_eq!(gap(&single), Some("期間內只有一次掃描且之前沒有紀錄，無法比較"));
+    }
+}
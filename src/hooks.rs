@@ -0,0 +1,209 @@
+use std::net::IpAddr;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use colored::*;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+use crate::timefmt;
+use crate::PortInfo;
+
+// 摘要中最多列出的失敗數
+const MAX_LISTED_FAILURES: usize = 10;
+
+// 觸發指令的事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    // --on-open：掃描到可出站連線的端口
+    Open,
+    // --on-change：watch 模式下端口狀態改變
+    Change,
+}
+
+impl HookEvent {
+    fn flag(self) -> &'static str {
+        match self {
+            HookEvent::Open => "--on-open",
+            HookEvent::Change => "--on-change",
+        }
+    }
+}
+
+// 佔位符使用的狀態名稱，與串流摘要的欄位相同
+pub fn state_name(inbound: bool, outbound: bool) -> &'static str {
+    match (inbound, outbound) {
+        (true, true) => "both",
+        (true, false) => "inbound_only",
+        (false, true) => "outbound_only",
+        (false, false) => "unavailable",
+    }
+}
+
+// 執行失敗的指令
+#[derive(Debug, Clone)]
+struct HookFailure {
+    event: HookEvent,
+    command: String,
+    reason: String,
+}
+
+#[derive(Debug, Default)]
+struct HookStats {
+    runs: usize,
+    failures: Vec<HookFailure>,
+}
+
+// 事件指令 (--on-open / --on-change)：經由 shell 在背景執行，不會阻塞掃描
+// 指令中的 {host} {port} {service} {state} {old_state} 會被取代，
+// 同樣的值也以 PORTSCAN_HOST 等環境變數提供 (值可能含有 shell 特殊字元時較安全)
+#[derive(Debug)]
+pub struct HookRunner {
+    on_open: Option<String>,
+    on_change: Option<String>,
+    limit: Duration,
+    semaphore: Arc<Semaphore>,
+    // --verbose 時顯示每個指令的輸出
+    verbose: bool,
+    tasks: Mutex<JoinSet<()>>,
+    stats: Arc<Mutex<HookStats>>,
+}
+
+fn expand(template: &str, values: &[(&str, String)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |command, (name, value)| command.replace(&format!("{{{}}}", name), value))
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+// 執行指令並收集輸出；逾時時終止指令
+async fn execute(command: &str, env: &[(&str, String)], limit: Duration) -> Result<String, String> {
+    let mut child = shell(command);
+    child
+        .envs(env.iter().map(|(name, value)| (format!("PORTSCAN_{}", name.to_ascii_uppercase()), value)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    let child = child.spawn().map_err(|e| format!("無法執行: {}", e))?;
+    let output = match timeout(limit, child.wait_with_output()).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => return Err(format!("執行失敗: {}", e)),
+        Err(_) => return Err(format!("逾時 ({})，已終止", timefmt::duration(limit))),
+    };
+    let text = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    if output.status.success() {
+        return Ok(text);
+    }
+    let status = match output.status.code() {
+        Some(code) => format!("結束代碼 {}", code),
+        None => "被訊號終止".to_string(),
+    };
+    match text.lines().rev().find(|line| !line.trim().is_empty()) {
+        Some(line) => Err(format!("{}: {}", status, line.trim())),
+        None => Err(status),
+    }
+}
+
+impl HookRunner {
+    // 沒有設定任何指令時回傳 None
+    pub fn new(
+        on_open: Option<String>,
+        on_change: Option<String>,
+        limit: Duration,
+        concurrency: usize,
+        verbose: bool,
+    ) -> Option<Self> {
+        if on_open.is_none() && on_change.is_none() {
+            return None;
+        }
+        Some(HookRunner {
+            on_open,
+            on_change,
+            limit,
+            semaphore: Arc::new(Semaphore::new(concurrency.max(1))),
+            verbose,
+            tasks: Mutex::new(JoinSet::new()),
+            stats: Arc::default(),
+        })
+    }
+
+    // 排入背景執行後立即返回；同時執行的數量受 --hook-concurrency 限制
+    pub fn fire(&self, event: HookEvent, host: IpAddr, port: &PortInfo, state: &str, old_state: Option<&str>) {
+        let template = match event {
+            HookEvent::Open => &self.on_open,
+            HookEvent::Change => &self.on_change,
+        };
+        let Some(template) = template else {
+            return;
+        };
+        let values = vec![
+            ("host", host.to_string()),
+            ("port", port.port.to_string()),
+            ("service", port.service.clone()),
+            ("state", state.to_string()),
+            ("old_state", old_state.unwrap_or("-").to_string()),
+        ];
+        let command = expand(template, &values);
+        let (semaphore, stats, limit, verbose) = (self.semaphore.clone(), self.stats.clone(), self.limit, self.verbose);
+        let mut tasks = self.tasks.lock().unwrap_or_else(|e| e.into_inner());
+        // watch 模式會長時間執行，先清掉已完成的工作
+        while tasks.try_join_next().is_some() {}
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            let outcome = execute(&command, &values, limit).await;
+            if verbose {
+                match &outcome {
+                    Ok(output) => eprintln!("{}", format!("[{}] {}\n{}", event.flag(), command, output.trim_end()).dimmed()),
+                    Err(reason) => eprintln!("{}", format!("[{}] {}: {}", event.flag(), command, reason).yellow()),
+                }
+            }
+            let mut stats = stats.lock().unwrap_or_else(|e| e.into_inner());
+            stats.runs += 1;
+            if let Err(reason) = outcome {
+                stats.failures.push(HookFailure { event, command, reason });
+            }
+        });
+    }
+
+    // 等待已排入的指令完成 (每個最多 --hook-timeout)
+    pub async fn wait(&self) {
+        let mut tasks = std::mem::take(&mut *self.tasks.lock().unwrap_or_else(|e| e.into_inner()));
+        while tasks.join_next().await.is_some() {}
+    }
+
+    // 摘要中的事件指令區段；沒有執行過指令時不顯示
+    pub fn display_summary(&self) {
+        let stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        if stats.runs == 0 {
+            return;
+        }
+        println!("\n{}", "=== 事件指令 ===".bold());
+        let summary = format!("執行 {} 次，失敗 {} 次", stats.runs, stats.failures.len());
+        if stats.failures.is_empty() {
+            println!("{}", summary.green());
+            return;
+        }
+        println!("{}", summary.yellow());
+        for failure in stats.failures.iter().take(MAX_LISTED_FAILURES) {
+            println!("  {} {}: {}", failure.event.flag(), failure.command, failure.reason.red());
+        }
+        if stats.failures.len() > MAX_LISTED_FAILURES {
+            println!("{}", format!("  ...另有 {} 個失敗", stats.failures.len() - MAX_LISTED_FAILURES).dimmed());
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
+use crate::pipeline::Evidence;
+use crate::{PortInfo, ScanResult};
+use crate::scanner::ScanPlan;
+
+// 強制版本協商的 QUIC 版本 (RFC 9000 保留的 0x?a?a?a?a 格式)
+const GREASE_VERSION: u32 = 0x1a2a_3a4a;
+
+// 用戶端 Initial 封包所在的 UDP datagram 至少需 1200 位元組，否則伺服器可以不回應
+const INITIAL_SIZE: usize = 1200;
+
+// HTTP 協定支援；None 代表無法判斷 (例如 TLS 握手失敗)
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct HttpVersions {
+    pub h1: Option<bool>,
+    pub h2: Option<bool>,
+    pub h3: Option<bool>,
+    // 同時提供 h2 與 http/1.1 時伺服器選擇的 ALPN 協定
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alpn: Option<String>,
+    // 版本協商封包列出的 QUIC 版本 (十六進位)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub quic_versions: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+}
+
+// 精簡的旗標顯示，例如 [h1 ✓][h2 ✓][h3 ✗]
+pub fn flags(versions: &HttpVersions) -> String {
+    let flag = |name: &str, value: Option<bool>| match value {
+        Some(true) => format!("[{} {}]", name, "✓".green()),
+        Some(false) => format!("[{} {}]", name, "✗".red()),
+        None => format!("[{} {}]", name, "?".dimmed()),
+    };
+    let mut line = [flag("h1", versions.h1), flag("h2", versions.h2), flag("h3", versions.h3)].concat();
+    let mut details = Vec::new();
+    if let Some(alpn) = &versions.alpn {
+        details.push(format!("ALPN {}", alpn));
+    }
+    if !versions.quic_versions.is_empty() {
+        details.push(format!("QUIC {}", versions.quic_versions.join(", ")));
+    }
+    details.extend(versions.notes.iter().cloned());
+    if !details.is_empty() {
+        line.push_str(&format!("  {}", details.join("；").dimmed()));
+    }
+    line
+}
+
+// 以指定的 ALPN 清單完成 TLS 握手；伺服器選擇的協定為 None 代表沒有 ALPN
+// 只確認協定，不驗證憑證
+async fn handshake(addr: SocketAddr, alpns: &[&str], limit: Duration) -> Result<Option<String>, String> {
+    let connector = native_tls::TlsConnector::builder()
+        .request_alpns(alpns)
+        .danger_accept_invalid_certs(true)
+        .danger_accept_invalid_hostnames(true)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let connector = tokio_native_tls::TlsConnector::from(connector);
+    let attempt = async {
+        let stream = TcpStream::connect(addr).await.map_err(|e| e.to_string())?;
+        let stream = connector.connect(&addr.ip().to_string(), stream).await.map_err(|e| e.to_string())?;
+        let selected = stream.get_ref().negotiated_alpn().map_err(|e| e.to_string())?;
+        Ok(selected.map(|protocol| String::from_utf8_lossy(&protocol).into_owned()))
+    };
+    tokio::time::timeout(limit, attempt).await.map_err(|_| "TLS 握手逾時".to_string())?
+}
+
+// 同時提供 h2 與 http/1.1；伺服器選擇 h2 時再只提供 http/1.1，確認是否仍接受 HTTP/1.1
+async fn probe_alpn(addr: SocketAddr, limit: Duration, versions: &mut HttpVersions) {
+    match handshake(addr, &["h2", "http/1.1"], limit).await {
+        Ok(Some(protocol)) if protocol == "h2" => {
+            versions.h2 = Some(true);
+            versions.h1 = Some(handshake(addr, &["http/1.1"], limit).await.is_ok());
+            versions.alpn = Some(protocol);
+        }
+        Ok(Some(protocol)) => {
+            versions.h1 = Some(protocol == "http/1.1");
+            versions.h2 = Some(false);
+            versions.alpn = Some(protocol);
+        }
+        // 不支援 ALPN 的伺服器只能使用 HTTP/1.1
+        Ok(None) => {
+            versions.h1 = Some(true);
+            versions.h2 = Some(false);
+            versions.notes.push("伺服器不支援 ALPN".to_string());
+        }
+        Err(e) => versions.notes.push(format!("TLS 握手失敗: {}", e)),
+    }
+}
+
+// 長標頭的 Initial 封包，版本為保留值，依 RFC 9000 6.1 伺服器應回應版本協商
+// 不需要受保護的酬載：伺服器在解析版本之後就決定回應
+fn initial_packet(dcid: &[u8; 8], scid: &[u8; 8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(INITIAL_SIZE);
+    // 長標頭、固定位元、Initial 類型、4 位元組封包編號
+    packet.push(0xc3);
+    packet.extend_from_slice(&GREASE_VERSION.to_be_bytes());
+    packet.push(dcid.len() as u8);
+    packet.extend_from_slice(dcid);
+    packet.push(scid.len() as u8);
+    packet.extend_from_slice(scid);
+    // 沒有 token；長度欄位以 2 位元組 varint 表示剩下的位元組
+    packet.push(0);
+    let remaining = INITIAL_SIZE - packet.len() - 2;
+    packet.extend_from_slice(&(0x4000 | remaining as u16).to_be_bytes());
+    packet.resize(INITIAL_SIZE, 0);
+    packet
+}
+
+// 回應必須是長標頭，且目的連線 ID 為我們送出的來源連線 ID
+// 版本協商 (版本 0) 回傳列出的版本；其他長標頭回傳空清單
+fn parse_response(packet: &[u8], scid: &[u8; 8]) -> Option<Vec<u32>> {
+    if packet.len() < 7 || packet[0] & 0x80 == 0 {
+        return None;
+    }
+    let version = u32::from_be_bytes(packet[1..5].try_into().ok()?);
+    let dcid_len = packet[5] as usize;
+    let dcid = packet.get(6..6 + dcid_len)?;
+    if dcid != scid {
+        return None;
+    }
+    if version != 0 {
+        return Some(Vec::new());
+    }
+    let scid_len = *packet.get(6 + dcid_len)? as usize;
+    let list = packet.get(7 + dcid_len + scid_len..)?;
+    Some(list.chunks_exact(4).map(|chunk| u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])).collect())
+}
+
+// 送出一個 QUIC Initial 到同一端口的 UDP，收到合法回應即視為支援 HTTP/3
+async fn probe_quic(addr: SocketAddr, limit: Duration, versions: &mut HttpVersions) {
+    let mut ids = [0u8; 16];
+    if let Err(e) = getrandom::getrandom(&mut ids) {
+        versions.notes.push(format!("無法取得亂數: {}", e));
+        return;
+    }
+    let dcid: [u8; 8] = ids[..8].try_into().unwrap_or_default();
+    let scid: [u8; 8] = ids[8..].try_into().unwrap_or_default();
+    let local: SocketAddr = match addr {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let attempt = tokio::task::spawn_blocking(move || -> std::io::Result<Vec<u32>> {
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(addr)?;
+        socket.set_read_timeout(Some(limit))?;
+        socket.send(&initial_packet(&dcid, &scid))?;
+        let started = Instant::now();
+        let mut buffer = [0u8; 1500];
+        // 忽略不合法的封包，直到期限
+        while started.elapsed() < limit {
+            let received = socket.recv(&mut buffer)?;
+            if let Some(found) = parse_response(&buffer[..received], &scid) {
+                return Ok(found);
+            }
+        }
+        Err(std::io::ErrorKind::TimedOut.into())
+    });
+    match attempt.await {
+        Ok(Ok(found)) => {
+            versions.h3 = Some(true);
+            versions.quic_versions = found.into_iter().filter(|v| *v != GREASE_VERSION).map(|v| format!("0x{:08x}", v)).collect();
+        }
+        // ICMP 端口不可達會在 recv 時變成連線被拒
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::ConnectionRefused => versions.h3 = Some(false),
+        Ok(Err(e)) if matches!(e.kind(), std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock) => {
+            versions.h3 = Some(false);
+            versions.notes.push("UDP 沒有 QUIC 回應".to_string());
+        }
+        Ok(Err(e)) => versions.notes.push(format!("QUIC 探測失敗: {}", e)),
+        Err(_) => {}
+    }
+}
+
+pub async fn probe(addr: SocketAddr, limit: Duration) -> HttpVersions {
+    let mut versions = HttpVersions::default();
+    probe_alpn(addr, limit, &mut versions).await;
+    probe_quic(addr, limit, &mut versions).await;
+    versions
+}
+
+// 對所有出站可連線、且端口或橫幅顯示是 TLS 的端口探測 HTTP 協定版本
+pub async fn probe_results(results: &mut BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>, plan: &ScanPlan) {
+    let limit = plan.timeouts.default;
+    let semaphore = Arc::new(Semaphore::new(plan.concurrency.max(1)));
+    let mut handles = Vec::new();
+    for (host, host_results) in results.iter() {
+        for (port, result) in host_results {
+            let evidence = Evidence::of(port, result, false);
+            if !evidence.connected || !evidence.speaks_tls() {
+                continue;
+            }
+            let (addr, port, semaphore) = (plan.context.socket_addr(*host, port.port), port.clone(), semaphore.clone());
+            handles.push(tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                (addr.ip(), port, probe(addr, limit).await)
+            }));
+        }
+    }
+    for handle in handles {
+        if let Ok((host, port, versions)) = handle.await {
+            if let Some(result) = results.get_mut(&host).and_then(|r| r.get_mut(&port)) {
+                result.http_versions = Some(versions);
+            }
+        }
+    }
+}
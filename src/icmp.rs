@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+use crate::privhelper::{Channel, Helper, Origin, RawSocket};
+
+// 引用封包中的傳輸層協定號碼
+pub const PROTO_TCP: u8 = 6;
+pub const PROTO_UDP: u8 = 17;
+
+// 連線立即失敗時，等待監聽執行緒處理 ICMP 的時間
+pub const ERROR_GRACE: Duration = Duration::from_millis(50);
+
+// ICMP 錯誤所引用的原始探測
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProbeKey {
+    pub protocol: u8,
+    pub dest: IpAddr,
+    pub dest_port: u16,
+    pub source_port: u16,
+}
+
+// 路由器或目標回報的 ICMP 錯誤
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct IcmpError {
+    #[serde(rename = "type")]
+    pub kind: u8,
+    pub code: u8,
+    // 送出 ICMP 的路由器或主機
+    pub from: IpAddr,
+    pub reason: &'static str,
+}
+
+// 從續掃檔讀回時依類型/代碼重新取得說明
+#[derive(Deserialize)]
+struct StoredIcmpError {
+    #[serde(rename = "type")]
+    kind: u8,
+    code: u8,
+    from: IpAddr,
+}
+
+impl<'de> Deserialize<'de> for IcmpError {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let stored = StoredIcmpError::deserialize(deserializer)?;
+        let reason = match stored.from {
+            IpAddr::V4(_) => reason_v4(stored.kind, stored.code),
+            IpAddr::V6(_) => reason_v6(stored.kind, stored.code),
+        };
+        Ok(IcmpError {
+            kind: stored.kind,
+            code: stored.code,
+            from: stored.from,
+            reason: reason.unwrap_or("未知的 ICMP 錯誤"),
+        })
+    }
+}
+
+impl IcmpError {
+    pub fn describe(&self) -> String {
+        format!("{} 來自 {}", self.reason, self.from)
+    }
+}
+
+// ICMPv4 類型/代碼的說明；不是探測相關的錯誤時回傳 None
+fn reason_v4(kind: u8, code: u8) -> Option<&'static str> {
+    Some(match (kind, code) {
+        (3, 0) => "網路無法到達 (net unreachable)",
+        (3, 1) => "主機無法到達 (host unreachable)",
+        (3, 2) => "協定無法到達 (protocol unreachable)",
+        (3, 3) => "端口無法到達 (port unreachable)",
+        (3, 9) | (3, 10) | (3, 13) => "被路由器拒絕 (admin prohibited)",
+        (3, _) => "目的地無法到達 (destination unreachable)",
+        (11, _) => "存活時間逾時 (time exceeded)",
+        _ => return None,
+    })
+}
+
+// ICMPv6 類型/代碼的說明
+fn reason_v6(kind: u8, code: u8) -> Option<&'static str> {
+    Some(match (kind, code) {
+        (1, 0) | (1, 6) => "沒有路由 (no route)",
+        (1, 1) | (1, 5) => "被路由器拒絕 (admin prohibited)",
+        (1, 3) => "主機無法到達 (address unreachable)",
+        (1, 4) => "端口無法到達 (port unreachable)",
+        (1, _) => "目的地無法到達 (destination unreachable)",
+        (3, _) => "存活時間逾時 (time exceeded)",
+        _ => return None,
+    })
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*bytes.get(at)?, *bytes.get(at + 1)?]))
+}
+
+// 引用封包的傳輸層標頭前 4 位元組為來源與目的端口
+fn quoted_ports(transport: &[u8]) -> Option<(u16, u16)> {
+    Some((read_u16(transport, 0)?, read_u16(transport, 2)?))
+}
+
+// 解析 IPv4 原始 socket 收到的封包 (含外層 IP 標頭)
+pub fn parse_v4(packet: &[u8]) -> Option<(ProbeKey, IcmpError)> {
+    if packet.first()? >> 4 != 4 {
+        return None;
+    }
+    let outer_len = usize::from(packet[0] & 0x0f) * 4;
+    let from = Ipv4Addr::from(<[u8; 4]>::try_from(packet.get(12..16)?).ok()?);
+
+    let icmp = packet.get(outer_len..)?;
+    let (kind, code) = (*icmp.first()?, *icmp.get(1)?);
+    let reason = reason_v4(kind, code)?;
+
+    // ICMP 標頭 8 位元組之後是原始 IP 標頭與傳輸層前 8 位元組
+    let quoted = icmp.get(8..)?;
+    if quoted.first()? >> 4 != 4 {
+        return None;
+    }
+    let inner_len = usize::from(quoted[0] & 0x0f) * 4;
+    let protocol = *quoted.get(9)?;
+    let dest = Ipv4Addr::from(<[u8; 4]>::try_from(quoted.get(16..20)?).ok()?);
+    let (source_port, dest_port) = quoted_ports(quoted.get(inner_len..)?)?;
+
+    Some((
+        ProbeKey {
+            protocol,
+            dest: IpAddr::V4(dest),
+            dest_port,
+            source_port,
+        },
+        IcmpError {
+            kind,
+            code,
+            from: IpAddr::V4(from),
+            reason,
+        },
+    ))
+}
+
+// 解析 ICMPv6 原始 socket 收到的訊息 (不含外層 IP 標頭)
+pub fn parse_v6(message: &[u8], from: Ipv6Addr) -> Option<(ProbeKey, IcmpError)> {
+    let (kind, code) = (*message.first()?, *message.get(1)?);
+    let reason = reason_v6(kind, code)?;
+
+    // 原始 IPv6 標頭固定 40 位元組；不處理擴充標頭
+    let quoted = message.get(8..)?;
+    if quoted.first()? >> 4 != 6 {
+        return None;
+    }
+    let protocol = *quoted.get(6)?;
+    let dest = Ipv6Addr::from(<[u8; 16]>::try_from(quoted.get(24..40)?).ok()?);
+    let (source_port, dest_port) = quoted_ports(quoted.get(40..)?)?;
+
+    Some((
+        ProbeKey {
+            protocol,
+            dest: IpAddr::V6(dest),
+            dest_port,
+            source_port,
+        },
+        IcmpError {
+            kind,
+            code,
+            from: IpAddr::V6(from),
+            reason,
+        },
+    ))
+}
+
+type ErrorTable = Mutex<HashMap<ProbeKey, IcmpError>>;
+
+// 以原始 socket 收集 ICMP 錯誤，供探測失敗時查詢原因
+#[derive(Debug)]
+pub struct IcmpMonitor {
+    errors: Arc<ErrorTable>,
+}
+
+impl IcmpMonitor {
+    // 需要系統權限或 --privileged-helper 的輔助程序；無法開啟時回傳 None，掃描沿用原本的判斷
+    pub fn open(helper: Option<&Arc<Helper>>) -> Option<Self> {
+        let errors = Arc::new(Mutex::new(HashMap::new()));
+        let mut listening = false;
+
+        for channel in [Channel::Icmp4, Channel::Icmp6] {
+            let Ok(socket) = RawSocket::open(channel, helper) else {
+                continue;
+            };
+            let errors = Arc::downgrade(&errors);
+            if std::thread::Builder::new()
+                .name("icmp-monitor".to_string())
+                .spawn(move || listen(socket, errors))
+                .is_ok()
+            {
+                listening = true;
+            }
+        }
+
+        listening.then_some(IcmpMonitor { errors })
+    }
+
+    // 取出某次探測的 ICMP 錯誤
+    pub fn take(&self, key: &ProbeKey) -> Option<IcmpError> {
+        self.errors.lock().ok()?.remove(key)
+    }
+
+    // 取出發往某個目的端口的 ICMP 錯誤，不比對來源端口
+    pub fn take_any(&self, protocol: u8, dest: IpAddr, dest_port: u16) -> Option<IcmpError> {
+        let mut errors = self.errors.lock().ok()?;
+        let key = *errors
+            .keys()
+            .find(|k| k.protocol == protocol && k.dest == dest && k.dest_port == dest_port)?;
+        errors.remove(&key)
+    }
+
+    // 查詢 ICMP 錯誤，最多等待 grace 讓剛到達的封包被處理
+    pub async fn wait_for(&self, key: &ProbeKey, grace: Duration) -> Option<IcmpError> {
+        let deadline = Instant::now() + grace;
+        loop {
+            if let Some(error) = self.take(key) {
+                return Some(error);
+            }
+            if Instant::now() >= deadline {
+                return None;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+}
+
+// 監聽執行緒：監視器釋放後結束
+fn listen(socket: RawSocket, errors: Weak<ErrorTable>) {
+    let mut buf = [0u8; 1500];
+
+    loop {
+        let received = socket.recv(&mut buf);
+        let Some(errors) = errors.upgrade() else {
+            return;
+        };
+        let (len, from) = match received {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) => continue,
+            Err(_) => return,
+        };
+        let packet = &buf[..len];
+
+        let parsed = match from {
+            Origin::Addr(IpAddr::V6(from)) => parse_v6(packet, from),
+            _ => parse_v4(packet),
+        };
+        if let Some((key, error)) = parsed {
+            if let Ok(mut errors) = errors.lock() {
+                errors.insert(key, error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 擷取的 IPv4 封包：路由器 198.51.100.1 回報 admin prohibited (3/13)，
+    // 引用 192.0.2.10:49152 → 203.0.113.5:443 的 TCP SYN
+    const ADMIN_PROHIBITED_V4: [u8; 56] = [
+        // 外層 IP 標頭
+        0x45, 0xc0, 0x00, 0x38, 0x8f, 0x1a, 0x00, 0x00, 0x3e, 0x01, 0x6b, 0x2c, 198, 51, 100, 1, 192, 0, 2, 10,
+        // ICMP 標頭
+        0x03, 0x0d, 0x4a, 0x21, 0x00, 0x00, 0x00, 0x00,
+        // 引用的 IP 標頭
+        0x45, 0x00, 0x00, 0x3c, 0x1c, 0x46, 0x40, 0x00, 0x3f, 0x06, 0x00, 0x00, 192, 0, 2, 10, 203, 0, 113, 5,
+        // 引用的 TCP 標頭前 8 位元組
+        0xc0, 0x00, 0x01, 0xbb, 0x5e, 0x1f, 0x93, 0x02,
+    ];
+
+    // 擷取的 IPv6 訊息 (不含外層標頭)：port unreachable，引用 [2001:db8::10]:53000 → [2001:db8::5]:161 的 UDP
+    fn port_unreachable_v6() -> Vec<u8> {
+        let mut message = vec![0x01, 0x04, 0x8b, 0x6e, 0x00, 0x00, 0x00, 0x00];
+        message.extend_from_slice(&[0x60, 0x00, 0x00, 0x00, 0x00, 0x26, PROTO_UDP, 0x40]);
+        message.extend_from_slice(&"2001:db8::10".parse::<Ipv6Addr>().unwrap().octets());
+        message.extend_from_slice(&"2001:db8::5".parse::<Ipv6Addr>().unwrap().octets());
+        message.extend_from_slice(&[0xcf, 0x08, 0x00, 0xa1, 0x00, 0x26, 0x3b, 0x91]);
+        message
+    }
+
+    fn key(protocol: u8, dest: &str, dest_port: u16, source_port: u16) -> ProbeKey {
+        ProbeKey { protocol, dest: dest.parse().unwrap(), dest_port, source_port }
+    }
+
+    fn monitor() -> IcmpMonitor {
+        IcmpMonitor { errors: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    #[test]
+    fn parses_ipv4_admin_prohibited() {
+        let (probe, error) = parse_v4(&ADMIN_PROHIBITED_V4).unwrap();
+        assert_eq!(probe, key(PROTO_TCP, "203.0.113.5", 443, 49152));
+        assert_eq!((error.kind, error.code), (3, 13));
+        assert_eq!(error.from, "198.51.100.1".parse::<IpAddr>().unwrap());
+        assert_eq!(error.describe(), "被路由器拒絕 (admin prohibited) 來自 198.51.100.1");
+    }
+
+    #[test]
+    fn ipv4_header_options_are_skipped() {
+        // 外層標頭帶 4 位元組選項 (IHL 6)，引用的 UDP 探測得到 port unreachable
+        let mut packet = ADMIN_PROHIBITED_V4.to_vec();
+        packet[0] = 0x46;
+        packet.splice(20..20, [0x01, 0x01, 0x01, 0x00]);
+        packet[25] = 3;
+        packet[41] = PROTO_UDP;
+        let (probe, error) = parse_v4(&packet).unwrap();
+        assert_eq!(probe, key(PROTO_UDP, "203.0.113.5", 443, 49152));
+        assert_eq!(error.reason, "端口無法到達 (port unreachable)");
+    }
+
+    #[test]
+    fn ipv4_non_errors_and_truncation_are_ignored() {
+        // echo reply 與 redirect 不是探測錯誤
+        for kind in [0, 5, 8] {
+            let mut packet = ADMIN_PROHIBITED_V4;
+            packet[20] = kind;
+            assert!(parse_v4(&packet).is_none(), "type {}", kind);
+        }
+        for len in [0, 19, 21, 28, 47, 51] {
+            assert!(parse_v4(&ADMIN_PROHIBITED_V4[..len]).is_none(), "len {}", len);
+        }
+        // 引用的不是 IPv4 標頭
+        let mut packet = ADMIN_PROHIBITED_V4;
+        packet[28] = 0x65;
+        assert!(parse_v4(&packet).is_none());
+        // 端口只引用到前 4 位元組即可解析
+        assert!(parse_v4(&ADMIN_PROHIBITED_V4[..52]).is_some());
+    }
+
+    #[test]
+    fn ipv4_reasons() {
+        assert_eq!(reason_v4(3, 0), Some("網路無法到達 (net unreachable)"));
+        assert_eq!(reason_v4(3, 1), Some("主機無法到達 (host unreachable)"));
+        assert_eq!(reason_v4(3, 9), reason_v4(3, 10));
+        assert_eq!(reason_v4(3, 4), Some("目的地無法到達 (destination unreachable)"));
+        assert_eq!(reason_v4(11, 0), Some("存活時間逾時 (time exceeded)"));
+        assert_eq!(reason_v4(0, 0), None);
+    }
+
+    #[test]
+    fn parses_ipv6_port_unreachable() {
+        let router: Ipv6Addr = "2001:db8:ffff::1".parse().unwrap();
+        let (probe, error) = parse_v6(&port_unreachable_v6(), router).unwrap();
+        assert_eq!(probe, key(PROTO_UDP, "2001:db8::5", 161, 53000));
+        assert_eq!(error.reason, "端口無法到達 (port unreachable)");
+        assert_eq!(error.from, IpAddr::V6(router));
+
+        let mut prohibited = port_unreachable_v6();
+        prohibited[1] = 1;
+        assert_eq!(parse_v6(&prohibited, router).unwrap().1.reason, "被路由器拒絕 (admin prohibited)");
+        // echo request、引用的不是 IPv6 標頭、截斷
+        let mut echo = port_unreachable_v6();
+        echo[0] = 128;
+        assert!(parse_v6(&echo, router).is_none());
+        let mut wrong = port_unreachable_v6();
+        wrong[8] = 0x45;
+        assert!(parse_v6(&wrong, router).is_none());
+        assert!(parse_v6(&port_unreachable_v6()[..51], router).is_none());
+    }
+
+    #[test]
+    fn stored_errors_regain_their_reason() {
+        let (_, error) = parse_v4(&ADMIN_PROHIBITED_V4).unwrap();
+        let restored: IcmpError = serde_json::from_str(&serde_json::to_string(&error).unwrap()).unwrap();
+        assert_eq!(restored, error);
+        let unknown: IcmpError = serde_json::from_str(r#"{"type":42,"code":0,"from":"2001:db8::1"}"#).unwrap();
+        assert_eq!(unknown.reason, "未知的 ICMP 錯誤");
+    }
+
+    #[test]
+    fn errors_are_matched_to_the_quoting_probe() {
+        let monitor = monitor();
+        let (probe, error) = parse_v4(&ADMIN_PROHIBITED_V4).unwrap();
+        monitor.errors.lock().unwrap().insert(probe, error.clone());
+
+        // 同一目的但來源端口不同的探測不會拿到別人的錯誤
+        assert_eq!(monitor.take(&key(PROTO_TCP, "203.0.113.5", 443, 49153)), None);
+        assert_eq!(monitor.take(&key(PROTO_UDP, "203.0.113.5", 443, 49152)), None);
+        assert_eq!(monitor.take(&probe), Some(error.clone()));
+        // 取出後移除
+        assert_eq!(monitor.take(&probe), None);
+
+        monitor.errors.lock().unwrap().insert(probe, error.clone());
+        assert_eq!(monitor.take_any(PROTO_TCP, probe.dest, 80), None);
+        assert_eq!(monitor.take_any(PROTO_TCP, probe.dest, 443), Some(error));
+        assert!(monitor.errors.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn late_errors_arrive_within_the_grace_period() {
+        let monitor = monitor();
+        let (probe, error) = parse_v4(&ADMIN_PROHIBITED_V4).unwrap();
+        assert_eq!(monitor.wait_for(&probe, Duration::from_millis(10)).await, None);
+
+        let errors = monitor.errors.clone();
+        let late = error.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            errors.lock().unwrap().insert(probe, late);
+        });
+        assert_eq!(monitor.wait_for(&probe, Duration::from_secs(5)).await, Some(error));
+    }
+}
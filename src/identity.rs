@@ -0,0 +1,324 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use colored::*;
+use rusqlite::Connection;
+use schemars::JsonSchema;
+use serde::Serialize;
+use crate::anonymize::Anonymizer;
+use crate::context::ScanContext;
+use crate::manifest::Manifest;
+use crate::targets::TargetSpec;
+
+// 識別名稱的來源，依優先順序排列
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum IdentitySource {
+    // --host-id
+    HostId,
+    // 服務清單的 identities
+    Manifest,
+    // 掃描目標的主機名稱
+    TargetName,
+    // --identity-rdns 反查的 PTR 名稱
+    ReverseDns,
+    // 沒有其他識別時使用 IP
+    Address,
+}
+
+impl IdentitySource {
+    pub fn label(self) -> &'static str {
+        match self {
+            IdentitySource::HostId => "--host-id",
+            IdentitySource::Manifest => "服務清單",
+            IdentitySource::TargetName => "目標名稱",
+            IdentitySource::ReverseDns => "反查 DNS",
+            IdentitySource::Address => "IP",
+        }
+    }
+}
+
+// 主機的穩定識別
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Identity {
+    pub name: String,
+    pub source: IdentitySource,
+}
+
+// 同一次掃描中兩個以上的識別指向同一個 IP
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub host: IpAddr,
+    // (識別, 來源)；第一個為實際採用的
+    pub claims: Vec<(String, IdentitySource)>,
+}
+
+// --host-id ID=TARGET，TARGET 為 IP 或 --target 中的主機名稱
+pub fn parse_host_id(s: &str) -> Result<(String, String), String> {
+    let (id, target) = s
+        .split_once('=')
+        .map(|(id, target)| (id.trim(), target.trim()))
+        .filter(|(id, target)| !id.is_empty() && !target.is_empty())
+        .ok_or_else(|| format!("--host-id 格式應為 識別=IP或主機名稱: {}", s))?;
+    Ok((id.to_string(), target.to_string()))
+}
+
+// 名稱 (IP 或目標主機名稱) 對應到的位址
+fn addrs_for(name: &str, targets: &[TargetSpec]) -> Vec<IpAddr> {
+    if let Ok(addr) = name.parse::<IpAddr>() {
+        return vec![addr];
+    }
+    targets
+        .iter()
+        .filter_map(|target| match target {
+            TargetSpec::Host { name: host, addr } if host.eq_ignore_ascii_case(name) => Some(*addr),
+            _ => None,
+        })
+        .collect()
+}
+
+// 本次掃描各主機的識別；--identity-rdns 的反查在需要時才進行並快取
+#[derive(Debug, Default)]
+pub struct Identities {
+    claims: HashMap<IpAddr, Identity>,
+    // 目標主機名稱
+    names: HashMap<IpAddr, String>,
+    reverse_dns: bool,
+    cache: Mutex<HashMap<IpAddr, Option<String>>>,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl Identities {
+    // --host-id 優先於服務清單；同一 IP 有不同的識別時記錄衝突，採用優先順序較高 (先出現) 的
+    pub fn build(
+        host_ids: &[(String, String)],
+        manifest: Option<&Manifest>,
+        targets: &[TargetSpec],
+        reverse_dns: bool,
+    ) -> Result<Self, String> {
+        let mut claimed: BTreeMap<IpAddr, Vec<(String, IdentitySource)>> = BTreeMap::new();
+        for (id, target) in host_ids {
+            let addrs = addrs_for(target, targets);
+            if addrs.is_empty() {
+                return Err(format!("--host-id {}={}: {} 不是 IP，也不是掃描目標的主機名稱", id, target, target));
+            }
+            for addr in addrs {
+                claimed.entry(addr).or_default().push((id.clone(), IdentitySource::HostId));
+            }
+        }
+        // 服務清單可能涵蓋這次沒有掃描的主機，對不上的項目略過
+        for (id, members) in manifest.map(Manifest::identities).into_iter().flatten() {
+            for member in members {
+                for addr in addrs_for(member, targets) {
+                    claimed.entry(addr).or_default().push((id.clone(), IdentitySource::Manifest));
+                }
+            }
+        }
+
+        let mut identities = Identities {
+            reverse_dns,
+            ..Default::default()
+        };
+        for (host, mut claims) in claimed {
+            let mut seen = BTreeSet::new();
+            claims.retain(|(id, _)| seen.insert(id.clone()));
+            if claims.len() > 1 {
+                identities.conflicts.push(Conflict { host, claims: claims.clone() });
+            }
+            let (name, source) = claims.swap_remove(0);
+            identities.claims.insert(host, Identity { name, source });
+        }
+        for target in targets {
+            if let TargetSpec::Host { name, addr } = target {
+                if name.parse::<IpAddr>().is_err() {
+                    identities.names.entry(*addr).or_insert_with(|| name.clone());
+                }
+            }
+        }
+        Ok(identities)
+    }
+
+    // 主機的識別；需要反查時會阻塞，async 環境中先呼叫 prefetch
+    pub fn of(&self, host: IpAddr) -> Identity {
+        if let Some(identity) = self.claims.get(&host) {
+            return identity.clone();
+        }
+        if let Some(name) = self.names.get(&host) {
+            return Identity { name: name.clone(), source: IdentitySource::TargetName };
+        }
+        if self.reverse_dns {
+            let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+            let name = cache.entry(host).or_insert_with(|| reverse_lookup(host)).clone();
+            if let Some(name) = name {
+                return Identity { name, source: IdentitySource::ReverseDns };
+            }
+        }
+        Identity { name: host.to_string(), source: IdentitySource::Address }
+    }
+
+    // 在背景執行緒先反查這些主機
+    pub async fn prefetch(self: &Arc<Self>, hosts: Vec<IpAddr>) {
+        if !self.reverse_dns {
+            return;
+        }
+        let identities = self.clone();
+        let _ = tokio::task::spawn_blocking(move || {
+            for host in hosts {
+                identities.of(host);
+            }
+        })
+        .await;
+    }
+}
+
+// 顯示與輸出用的識別名稱：--anonymize 時換成假名
+pub fn show(identity: &Identity, anonymizer: Option<&Anonymizer>) -> String {
+    match (anonymizer, identity.name.parse::<IpAddr>()) {
+        (None, _) => identity.name.clone(),
+        (Some(anonymizer), Ok(addr)) => anonymizer.ip(addr).to_string(),
+        (Some(anonymizer), Err(_)) => anonymizer.hostname(&identity.name),
+    }
+}
+
+// 在標準錯誤顯示識別衝突
+pub fn display_conflicts(conflicts: &[Conflict], context: &ScanContext) {
+    for conflict in conflicts {
+        let claims: Vec<String> = conflict
+            .claims
+            .iter()
+            .map(|(id, source)| format!("{} ({})", id, source.label()))
+            .collect();
+        let message = format!("⚠ {} 同時被多個識別宣告: {}；採用 {}", conflict.host, claims.join("、"), conflict.claims[0].0);
+        eprintln!("{}", context.show(&message).yellow());
+    }
+}
+
+// PTR 反查；沒有名稱時為 None
+#[cfg(unix)]
+fn reverse_lookup(host: IpAddr) -> Option<String> {
+    use std::ffi::CStr;
+    use std::net::SocketAddr;
+    let addr = socket2::SockAddr::from(SocketAddr::new(host, 0));
+    let mut name = [0 as libc::c_char; libc::NI_MAXHOST as usize];
+    let found = unsafe {
+        libc::getnameinfo(
+            addr.as_ptr().cast(),
+            addr.len(),
+            name.as_mut_ptr(),
+            name.len() as libc::socklen_t,
+            std::ptr::null_mut(),
+            0,
+            libc::NI_NAMEREQD,
+        )
+    };
+    if found != 0 {
+        return None;
+    }
+    let name = unsafe { CStr::from_ptr(name.as_ptr()) }.to_str().ok()?;
+    Some(name.trim_end_matches('.').to_ascii_lowercase())
+}
+
+#[cfg(not(unix))]
+fn reverse_lookup(_host: IpAddr) -> Option<String> {
+    None
+}
+
+// history 子命令中的一次掃描
+struct Run {
+    scanned_at: i64,
+    hosts: BTreeSet<String>,
+    open: BTreeMap<u16, String>,
+}
+
+// 符合 query 的識別：識別本身，或曾使用這個 IP 的識別
+pub fn matching_identities(conn: &Connection, query: &str) -> rusqlite::Result<Vec<String>> {
+    let mut identities = conn.prepare("SELECT DISTINCT identity FROM scan_results WHERE identity = ?1 OR host = ?1 ORDER BY identity")?;
+    let identities = identities.query_map([query], |row| row.get(0))?.collect();
+    identities
+}
+
+// 依識別讀出每次掃描的 IP 與開放端口；query 可以是識別或 IP
+fn load_history(conn: &Connection, query: &str) -> Result<BTreeMap<String, Vec<Run>>, Box<dyn Error>> {
+    let identities = matching_identities(conn, query)?;
+
+    let mut history = BTreeMap::new();
+    let mut rows = conn.prepare(
+        "SELECT scanned_at, host, port, service, outbound FROM scan_results WHERE identity = ?1 ORDER BY scanned_at, port",
+    )?;
+    for identity in identities {
+        let mut runs: Vec<Run> = Vec::new();
+        let mut query = rows.query([&identity])?;
+        while let Some(row) = query.next()? {
+            let scanned_at: i64 = row.get(0)?;
+            if runs.last().is_none_or(|run| run.scanned_at != scanned_at) {
+                runs.push(Run { scanned_at, hosts: BTreeSet::new(), open: BTreeMap::new() });
+            }
+            let run = runs.last_mut().expect("pushed above");
+            run.hosts.insert(row.get(1)?);
+            if row.get::<_, Option<bool>>(4)?.unwrap_or(false) {
+                run.open.insert(row.get(2)?, row.get(3)?);
+            }
+        }
+        history.insert(identity, runs);
+    }
+    Ok(history)
+}
+
+fn describe_ports(ports: &BTreeMap<u16, String>) -> String {
+    match ports.is_empty() {
+        true => "無".to_string(),
+        false => ports.iter().map(|(port, service)| format!("{} ({})", port, service)).collect::<Vec<_>>().join(", "),
+    }
+}
+
+// portscanner history：列出識別 (或 IP 曾對應的識別) 在 SQLite 結果中的每次掃描
+// diff 時只比較最近兩次掃描的開放端口
+pub fn run_history(db: &Path, query: &str, diff: bool) -> Result<(), Box<dyn Error>> {
+    if !db.exists() {
+        return Err(format!("找不到結果資料庫 {}", db.display()).into());
+    }
+    let conn = crate::output::open_database(db).map_err(|e| e.to_string())?;
+    let history = load_history(&conn, query)?;
+    if history.is_empty() {
+        return Err(format!("{} 中沒有 {} 的紀錄", db.display(), query).into());
+    }
+    for (identity, runs) in &history {
+        println!("\n{}", format!("=== {} ===", identity).bold());
+        if diff {
+            let [.., before, after] = runs.as_slice() else {
+                println!("只有一次掃描，無法比較");
+                continue;
+            };
+            println!(
+                "{} ({}) → {} ({})",
+                crate::timefmt::timestamp(before.scanned_at),
+                before.hosts.iter().cloned().collect::<Vec<_>>().join(", "),
+                crate::timefmt::timestamp(after.scanned_at),
+                after.hosts.iter().cloned().collect::<Vec<_>>().join(", ")
+            );
+            let opened: Vec<_> = after.open.iter().filter(|(port, _)| !before.open.contains_key(port)).collect();
+            let closed: Vec<_> = before.open.iter().filter(|(port, _)| !after.open.contains_key(port)).collect();
+            if opened.is_empty() && closed.is_empty() {
+                println!("開放端口沒有變化");
+            }
+            for (port, service) in opened {
+                println!("{}", format!("+ {} ({}) 新開放", port, service).green());
+            }
+            for (port, service) in closed {
+                println!("{}", format!("- {} ({}) 不再開放", port, service).red());
+            }
+            continue;
+        }
+        for run in runs {
+            println!(
+                "{}  {:20} {}",
+                crate::timefmt::timestamp(run.scanned_at),
+                run.hosts.iter().cloned().collect::<Vec<_>>().join(", "),
+                describe_ports(&run.open)
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,497 @@
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use clap::ValueEnum;
+use colored::*;
+use crate::cli::InitOptions;
+use crate::config;
+use crate::dns::{DnsCache, DnsConfig};
+use crate::migrate;
+use crate::output::OutputFormat;
+use crate::portspec;
+use crate::settings;
+use crate::targets;
+use crate::timefmt;
+use crate::zone::Zones;
+
+// 沒有指定名稱時的設定組合
+const DEFAULT_NAME: &str = "default";
+
+// 掃描強度：同時進行的探測數與出站逾時
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Intensity {
+    Gentle,
+    Normal,
+    Aggressive,
+}
+
+impl Intensity {
+    const ALL: [Intensity; 3] = [Intensity::Gentle, Intensity::Normal, Intensity::Aggressive];
+
+    fn name(self) -> &'static str {
+        match self {
+            Intensity::Gentle => "gentle",
+            Intensity::Normal => "normal",
+            Intensity::Aggressive => "aggressive",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Intensity::Gentle => "溫和：同時 16 個探測、逾時 3s，適合慢速或會限流的網路",
+            Intensity::Normal => "一般：同時 64 個探測、逾時 1s (內建預設值)",
+            Intensity::Aggressive => "積極：同時 512 個探測、逾時 500ms，只適合自己管理的區網",
+        }
+    }
+
+    // (concurrency, timeout)
+    fn options(self) -> (u32, &'static str) {
+        match self {
+            Intensity::Gentle => (16, "3s"),
+            Intensity::Normal => (64, "1s"),
+            Intensity::Aggressive => (512, "500ms"),
+        }
+    }
+}
+
+// 精靈的回答，寫成設定檔的 [profiles.名稱] 與 [watch]
+#[derive(Debug, Clone, PartialEq)]
+struct Answers {
+    name: String,
+    // None 時檢查本機出站
+    target: Option<String>,
+    // None 時為內建常用端口表
+    ports: Option<String>,
+    intensity: Intensity,
+    outputs: Vec<PathBuf>,
+    webhook: Option<String>,
+}
+
+// 以下的檢查同時用於選項與互動輸入；錯誤訊息直接顯示給使用者
+
+// 解析目標，回傳解析出的位址說明
+async fn check_target(spec: &str) -> Result<String, String> {
+    let dns = DnsCache::from_config(&DnsConfig::default());
+    let (resolved, failures) =
+        targets::parse_targets(spec, true, &mut Zones::default(), &dns).await.map_err(|e| e.to_string())?;
+    if let Some(failure) = failures.first() {
+        return Err(format!("無法解析 {}: {}", failure.name, failure.error));
+    }
+    let labels: Vec<String> = resolved.iter().map(|target| target.label()).collect();
+    let mut summary = format!("→ {}", labels.join(", "));
+    if !targets::public_labels(&resolved).is_empty() {
+        summary.push_str("；包含非私有位址，掃描前會要求確認授權");
+    }
+    Ok(summary)
+}
+
+// common 或空白為內建端口表 (None)，all 為全部端口
+fn check_ports(spec: &str) -> Result<Option<String>, String> {
+    match spec.trim() {
+        "" | "common" => Ok(None),
+        "all" => Ok(Some("1-65535".to_string())),
+        spec => portspec::parse_list(spec).map(|_| Some(spec.to_string())),
+    }
+}
+
+fn check_intensity(answer: &str) -> Result<Intensity, String> {
+    let answer = answer.trim().to_lowercase();
+    Intensity::ALL
+        .iter()
+        .enumerate()
+        .find(|(index, intensity)| answer == (index + 1).to_string() || answer == intensity.name())
+        .map(|(_, intensity)| *intensity)
+        .ok_or_else(|| format!("請輸入 1-3 或 gentle / normal / aggressive: {}", answer))
+}
+
+fn check_output(path: &Path) -> Result<(), String> {
+    match OutputFormat::from_path(path) {
+        Some(_) => Ok(()),
+        None => Err(format!("無法由副檔名判斷 {} 的格式 (可用 .ndjson / .csv / .db / .txt)", path.display())),
+    }
+}
+
+// 設定組合名稱同時是 TOML 的鍵與 --profile 的值
+fn check_name(name: &str) -> Result<(), String> {
+    match !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        true => Ok(()),
+        false => Err(format!("設定組合名稱只能使用英數字、- 與 _: {}", name)),
+    }
+}
+
+// 讀取回答；輸入結束 (EOF) 時為錯誤
+struct Prompter<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Prompter<R, W> {
+    fn line(&mut self, question: &str, default: &str) -> Result<String, Box<dyn Error>> {
+        match default.is_empty() {
+            true => write!(self.output, "{} ", question)?,
+            false => write!(self.output, "{} [{}] ", question, default)?,
+        }
+        self.output.flush()?;
+        let mut answer = String::new();
+        if self.input.read_line(&mut answer)? == 0 {
+            return Err("輸入已結束；不需要互動時以 --yes 與選項提供答案".into());
+        }
+        let answer = answer.trim();
+        Ok(if answer.is_empty() { default } else { answer }.to_string())
+    }
+
+    // 驗證失敗時顯示原因並重新詢問
+    fn ask<T>(&mut self, question: &str, default: &str, mut check: impl FnMut(&str) -> Result<T, String>) -> Result<T, Box<dyn Error>> {
+        loop {
+            let answer = self.line(question, default)?;
+            match check(&answer) {
+                Ok(value) => return Ok(value),
+                Err(e) => writeln!(self.output, "  {}", e.yellow())?,
+            }
+        }
+    }
+
+    fn confirm(&mut self, question: &str, default: bool) -> Result<bool, Box<dyn Error>> {
+        let hint = if default { "Y/n" } else { "y/N" };
+        self.ask(question, hint, |answer| match answer.to_lowercase().as_str() {
+            "y/n" => Ok(default),
+            "y" | "yes" => Ok(true),
+            "n" | "no" => Ok(false),
+            _ => Err("請輸入 y 或 n".to_string()),
+        })
+    }
+
+    fn say(&mut self, text: &str) -> io::Result<()> {
+        writeln!(self.output, "{}", text)
+    }
+}
+
+// 以選項提供的答案先檢查，其餘在互動時詢問；--yes 時使用預設值
+async fn collect<R: BufRead, W: Write>(options: &InitOptions, prompter: &mut Prompter<R, W>) -> Result<Answers, Box<dyn Error>> {
+    let interactive = !options.yes;
+
+    let target = match (&options.target, interactive) {
+        (Some(spec), _) => {
+            prompter.say(&check_target(spec).await?)?;
+            Some(spec.clone())
+        }
+        (None, false) => None,
+        (None, true) => loop {
+            let spec = prompter.line("要掃描什麼? 主機名稱、IP 或 CIDR 網段，以逗號分隔 (留空則檢查本機出站)", "")?;
+            if spec.is_empty() {
+                break None;
+            }
+            match check_target(&spec).await {
+                Ok(summary) => {
+                    prompter.say(&format!("  {}", summary))?;
+                    break Some(spec);
+                }
+                Err(e) => prompter.say(&format!("  {}", e.yellow()))?,
+            }
+        },
+    };
+
+    let ports = match (&options.ports, interactive) {
+        (Some(spec), _) => check_ports(spec)?,
+        (None, false) => None,
+        (None, true) => prompter.ask("端口: common (內建常用端口表)、all 或清單，例如 22,80,8000-8100", "common", check_ports)?,
+    };
+
+    let intensity = match (options.intensity, interactive) {
+        (Some(intensity), _) => intensity,
+        (None, false) => Intensity::Normal,
+        (None, true) => {
+            prompter.say("掃描強度:")?;
+            for (index, intensity) in Intensity::ALL.iter().enumerate() {
+                prompter.say(&format!("  {}. {}", index + 1, intensity.label()))?;
+            }
+            prompter.ask("請選擇", "2", check_intensity)?
+        }
+    };
+
+    let outputs = match (options.output.is_empty() && !options.no_output, interactive) {
+        (false, _) => {
+            options.output.iter().try_for_each(|path| check_output(path))?;
+            options.output.clone()
+        }
+        (true, false) => Vec::new(),
+        (true, true) => prompter.ask("結果寫入的檔案 (.ndjson / .csv / .db / .txt，以逗號分隔；留空不寫檔)", "", |answer| {
+            let paths: Vec<PathBuf> = answer.split(',').map(str::trim).filter(|s| !s.is_empty()).map(PathBuf::from).collect();
+            paths.iter().try_for_each(|path| check_output(path)).map(|()| paths)
+        })?,
+    };
+
+    let webhook = match (&options.webhook, options.no_alerts || !interactive) {
+        (Some(url), _) => {
+            config::check_webhook(url)?;
+            Some(url.clone())
+        }
+        (None, true) => None,
+        (None, false) => match prompter.confirm("--watch 掃描到狀態改變時送出告警?", false)? {
+            true => Some(prompter.ask("webhook 網址 (http:// 或 https://)", "", |url| config::check_webhook(url).map(|()| url.to_string()))?),
+            false => None,
+        },
+    };
+
+    let name = match (&options.name, interactive) {
+        (Some(name), _) => {
+            check_name(name)?;
+            name.clone()
+        }
+        (None, false) => DEFAULT_NAME.to_string(),
+        (None, true) => prompter.ask("設定組合名稱", DEFAULT_NAME, |name| check_name(name).map(|()| name.to_string()))?,
+    };
+
+    Ok(Answers { name, target, ports, intensity, outputs, webhook })
+}
+
+// TOML 字串
+fn quote(text: &str) -> String {
+    toml::Value::String(text.to_string()).to_string()
+}
+
+// 附註解的設定檔；其他區段維持預設值
+fn render(answers: &Answers, created_at: i64) -> String {
+    let mut text = format!(
+        "# portscanner 設定檔，由 portscanner init 於 {} 建立\n\
+         # 以 portscanner --profile {} 套用下面的設定組合；命令列指定的選項優先\n\
+         version = {}\n\n\
+         # 設定組合 {}：命令列沒有指定的選項使用這裡的值，寫法與 [defaults] 相同 (選項名稱 = 值)\n\
+         [profiles.{}]\n",
+        timefmt::timestamp(created_at),
+        answers.name,
+        migrate::CURRENT_VERSION,
+        answers.name,
+        answers.name
+    );
+    match &answers.target {
+        Some(target) => text.push_str(&format!("# 掃描目標\ntarget = {}\n", quote(target))),
+        None => text.push_str("# 沒有 target：以公共 DNS 伺服器檢查本機出站\n"),
+    }
+    match &answers.ports {
+        Some(ports) => text.push_str(&format!("# 掃描的端口\nports = {}\n", quote(ports))),
+        None => text.push_str("# 沒有 ports：掃描內建常用端口表\n"),
+    }
+    let (concurrency, timeout) = answers.intensity.options();
+    text.push_str(&format!(
+        "# 掃描強度 {}：同時進行的探測數與出站逾時\nconcurrency = {}\ntimeout = {}\n",
+        answers.intensity.name(),
+        concurrency,
+        quote(timeout)
+    ));
+    if !answers.outputs.is_empty() {
+        let outputs: Vec<String> = answers.outputs.iter().map(|path| quote(&path.to_string_lossy())).collect();
+        text.push_str(&format!("# 結果逐筆寫入的檔案 (不能搭配 --watch)\noutput = [{}]\n", outputs.join(", ")));
+    }
+    if let Some(webhook) = &answers.webhook {
+        text.push_str(&format!(
+            "\n# --watch 的告警：任何端口狀態改變時 POST JSON 到 webhook；其他規則見 README 的「告警去重與靜音時段」\n\
+             [watch]\n\
+             webhook = {}\n\n\
+             [[watch.alerts]]\n\
+             name = \"port-changes\"\n\
+             kind = \"changes\"\n\
+             threshold = 0\n",
+            quote(webhook)
+        ));
+    }
+    text
+}
+
+// 寫入前以掃描時相同的方式載入；確認後才取代原檔 (原檔備份為 .bak)
+fn write(path: &Path, text: &str, name: &str) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|e| format!("無法建立設定目錄 {}: {}", dir.display(), e))?;
+    }
+    let mut staged = path.as_os_str().to_os_string();
+    staged.push(".new");
+    let staged = PathBuf::from(staged);
+    fs::write(&staged, text).map_err(|e| format!("無法寫入 {}: {}", staged.display(), e))?;
+    if let Err(e) = settings::validate(&staged, name) {
+        let _ = fs::remove_file(&staged);
+        return Err(format!("產生的設定檔無法載入: {}", e).into());
+    }
+    if path.exists() {
+        let backup = migrate::backup_path(path);
+        fs::copy(path, &backup).map_err(|e| format!("無法備份到 {}: {}", backup.display(), e))?;
+    }
+    fs::rename(&staged, path).map_err(|e| format!("無法寫入設定檔 {}: {}", path.display(), e))?;
+    Ok(())
+}
+
+// 建立設定檔；取消時回傳 None
+async fn setup<R: BufRead, W: Write>(
+    options: &InitOptions,
+    path: &Path,
+    prompter: &mut Prompter<R, W>,
+) -> Result<Option<Answers>, Box<dyn Error>> {
+    if path.exists() && !options.force {
+        if options.yes {
+            return Err(format!("設定檔 {} 已存在，以 --force 覆寫", path.display()).into());
+        }
+        let question = format!("設定檔 {} 已存在，覆寫? (原檔備份為 .bak)", path.display());
+        if !prompter.confirm(&question, false)? {
+            prompter.say("已取消，設定檔未修改")?;
+            return Ok(None);
+        }
+    }
+    let answers = collect(options, prompter).await?;
+    write(path, &render(&answers, timefmt::now()), &answers.name)?;
+    Ok(Some(answers))
+}
+
+// portscanner init
+pub async fn run(options: InitOptions, config: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let path = settings::config_path(config).ok_or("找不到設定目錄，請以 --config 指定設定檔")?;
+    let mut prompter = Prompter { input: BufReader::new(io::stdin()), output: io::stdout() };
+    let Some(answers) = setup(&options, &path, &mut prompter).await? else {
+        return Ok(());
+    };
+
+    // 不是預設位置時掃描也要指定同一個設定檔
+    let mut args = Vec::new();
+    if config.is_some() {
+        args.extend(["--config".into(), path.clone().into_os_string()]);
+    }
+    args.extend(["--profile".into(), answers.name.clone().into()]);
+    let command_line: Vec<String> = args.iter().map(|arg| arg.to_string_lossy().into_owned()).collect();
+    println!("{}", format!("已寫入 {}", path.display()).green());
+    println!("以 {} 套用這個設定組合", format!("portscanner {}", command_line.join(" ")).bold());
+
+    let scan = match (options.run, options.no_run || options.yes) {
+        (true, _) => true,
+        (false, true) => false,
+        (false, false) => prompter.confirm("現在掃描一次?", true)?,
+    };
+    if !scan {
+        return Ok(());
+    }
+    let status = Command::new(std::env::current_exe()?).args(&args).status()?;
+    // 結束代碼與直接執行命令時相同
+    if !status.success() {
+        std::process::exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    fn prompter(input: &str) -> Prompter<&[u8], Vec<u8>> {
+        Prompter { input: input.as_bytes(), output: Vec::new() }
+    }
+
+    fn flags() -> InitOptions {
+        InitOptions {
+            target: Some("10.0.0.0/24".to_string()),
+            ports: Some("22,80,443".to_string()),
+            intensity: Some(Intensity::Gentle),
+            output: vec![PathBuf::from("scans.db")],
+            webhook: Some("https://hooks.example.com/portscanner".to_string()),
+            name: Some("office".to_string()),
+            yes: true,
+            ..InitOptions::default()
+        }
+    }
+
+    // 以選項提供所有答案時不讀取輸入，產生的設定檔可以載入並套用設定組合
+    #[tokio::test]
+    async fn flags_skip_every_question() {
+        let dir = TempDir::new("init");
+        let path = dir.path().join("portscanner").join("config.toml");
+        let mut prompter = prompter("");
+        let answers = setup(&flags(), &path, &mut prompter).await.unwrap().unwrap();
+        assert_eq!(answers.name, "office");
+        assert_eq!(answers.intensity, Intensity::Gentle);
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with("# portscanner 設定檔，由 portscanner init 於 "), "{}", text);
+        assert!(text.contains("[profiles.office]\n# 掃描目標\ntarget = \"10.0.0.0/24\"\n"), "{}", text);
+        assert!(text.contains("concurrency = 16\ntimeout = \"3s\"\n"), "{}", text);
+        settings::validate(&path, "office").unwrap();
+        let config = config::load(Some(&path)).unwrap();
+        assert_eq!(config.watch.webhook.as_deref(), Some("https://hooks.example.com/portscanner"));
+        assert_eq!(config.watch.alerts.len(), 1);
+        assert!(!dir.path().join("portscanner").join("config.toml.new").exists());
+    }
+
+    #[tokio::test]
+    async fn yes_uses_defaults_for_missing_answers() {
+        let dir = TempDir::new("init");
+        let path = dir.path().join("config.toml");
+        let options = InitOptions { yes: true, ..InitOptions::default() };
+        let answers = setup(&options, &path, &mut prompter("")).await.unwrap().unwrap();
+        assert_eq!(
+            answers,
+            Answers { name: "default".to_string(), target: None, ports: None, intensity: Intensity::Normal, outputs: Vec::new(), webhook: None }
+        );
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(!text.contains("[watch]"));
+        settings::validate(&path, "default").unwrap();
+    }
+
+    // 無效的回答顯示原因後重新詢問
+    #[tokio::test]
+    async fn invalid_answers_are_asked_again() {
+        let dir = TempDir::new("init");
+        let path = dir.path().join("config.toml");
+        let input = "10.0.0.0/33\n192.168.1.10\n22,http\n22,8000-8100\n9\n3\nscan.xyz\nscan.db, scan.csv\ny\nftp://hooks.example.com\nhttps://hooks.example.com/a\nmy lab\nlab\n";
+        let mut prompter = prompter(input);
+        let answers = setup(&InitOptions::default(), &path, &mut prompter).await.unwrap().unwrap();
+        assert_eq!(
+            answers,
+            Answers {
+                name: "lab".to_string(),
+                target: Some("192.168.1.10".to_string()),
+                ports: Some("22,8000-8100".to_string()),
+                intensity: Intensity::Aggressive,
+                outputs: vec![PathBuf::from("scan.db"), PathBuf::from("scan.csv")],
+                webhook: Some("https://hooks.example.com/a".to_string()),
+            }
+        );
+        let transcript = String::from_utf8(prompter.output).unwrap();
+        assert!(transcript.contains("無效的網段: 10.0.0.0/33"), "{}", transcript);
+        assert!(transcript.contains("→ 192.168.1.10"), "{}", transcript);
+        assert!(transcript.contains("無法由副檔名判斷 scan.xyz 的格式"), "{}", transcript);
+        assert!(transcript.contains("webhook 網址必須是 http:// 或 https:// 開頭"), "{}", transcript);
+        assert!(transcript.contains("設定組合名稱只能使用英數字"), "{}", transcript);
+        settings::validate(&path, "lab").unwrap();
+    }
+
+    #[tokio::test]
+    async fn existing_configs_are_kept_without_confirmation() {
+        let dir = TempDir::new("init");
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "[timeouts]\ndefault = \"2s\"\n").unwrap();
+
+        let error = setup(&flags(), &path, &mut prompter("")).await.unwrap_err();
+        assert!(error.to_string().contains("以 --force 覆寫"), "{}", error);
+        let interactive = InitOptions { yes: false, ..flags() };
+        assert_eq!(setup(&interactive, &path, &mut prompter("\n")).await.unwrap(), None);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[timeouts]\ndefault = \"2s\"\n");
+
+        let forced = InitOptions { force: true, ..flags() };
+        setup(&forced, &path, &mut prompter("")).await.unwrap().unwrap();
+        assert!(fs::read_to_string(&path).unwrap().contains("[profiles.office]"));
+        assert_eq!(fs::read_to_string(dir.path().join("config.toml.bak")).unwrap(), "[timeouts]\ndefault = \"2s\"\n");
+    }
+
+    #[tokio::test]
+    async fn invalid_flags_are_errors_not_questions() {
+        let dir = TempDir::new("init");
+        let path = dir.path().join("config.toml");
+        for (options, message) in [
+            (InitOptions { ports: Some("22-".to_string()), ..flags() }, "無效的端口"),
+            (InitOptions { webhook: Some("hooks.example.com".to_string()), ..flags() }, "webhook 網址無效"),
+            (InitOptions { name: Some("a.b".to_string()), ..flags() }, "設定組合名稱"),
+        ] {
+            let error = setup(&options, &path, &mut prompter("")).await.unwrap_err();
+            assert!(error.to_string().contains(message), "{}", error);
+        }
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,423 @@
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use russh::client::{self, Handle, Msg};
+use russh::keys::{self, PrivateKeyWithHashAlg, PublicKeyOrCertificate};
+use russh::{ChannelOpenFailure, ChannelStream};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
+use tokio::time::{timeout, Instant};
+use crate::closure::Failure;
+use crate::context::ScanContext;
+use crate::errors::ErrorCode;
+use crate::icmp::IcmpMonitor;
+use crate::prober::{ProbeFuture, Prober, UdpReplies};
+use crate::probes::{self, Banner, ProbeLibrary};
+use crate::scanner::{self, Outbound};
+use crate::verify::Reprobe;
+use crate::vhost::{self, VhostResult};
+use crate::PortInfo;
+
+// 建立 SSH 連線 (含金鑰交換與驗證) 的等待時間
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+// 沒有 --jump-key 時依序嘗試的金鑰檔 (~/.ssh 之下)
+const DEFAULT_KEYS: &[&str] = &["id_ed25519", "id_ecdsa", "id_rsa"];
+
+// --jump user@host[:port]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JumpSpec {
+    pub user: String,
+    pub host: String,
+    pub port: u16,
+}
+
+impl FromStr for JumpSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let invalid = || format!("跳板主機格式應為 user@host[:port]: {}", s);
+        let (user, rest) = s.trim().split_once('@').ok_or_else(invalid)?;
+        // IPv6 位址需加上方括號，例如 ops@[2001:db8::1]:2222
+        let (host, port) = match rest.strip_prefix('[') {
+            Some(bracketed) => {
+                let (host, after) = bracketed.split_once(']').ok_or_else(invalid)?;
+                match after {
+                    "" => (host, None),
+                    _ => (host, Some(after.strip_prefix(':').ok_or_else(invalid)?)),
+                }
+            }
+            None => match rest.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (rest, None),
+            },
+        };
+        if user.is_empty() || host.is_empty() || host.contains(['@', ':', '/']) && host.parse::<IpAddr>().is_err() {
+            return Err(invalid());
+        }
+        let port = match port {
+            Some(port) => crate::portspec::parse_port(port)?,
+            None => 22,
+        };
+        Ok(JumpSpec { user: user.to_string(), host: host.to_string(), port })
+    }
+}
+
+impl fmt::Display for JumpSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.host.contains(':') {
+            true => write!(f, "{}@[{}]:{}", self.user, self.host, self.port),
+            false => write!(f, "{}@{}:{}", self.user, self.host, self.port),
+        }
+    }
+}
+
+// SSH 層的錯誤，訊息直接顯示給使用者
+#[derive(Debug)]
+pub struct JumpError(String);
+
+impl fmt::Display for JumpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for JumpError {}
+
+impl From<russh::Error> for JumpError {
+    fn from(error: russh::Error) -> Self {
+        JumpError(error.to_string())
+    }
+}
+
+// 只接受 known_hosts 中記錄的主機金鑰；未知或改變時拒絕連線
+struct KnownHosts {
+    host: String,
+    port: u16,
+}
+
+impl client::Handler for KnownHosts {
+    type Error = JumpError;
+
+    async fn check_server_key(&mut self, server: &PublicKeyOrCertificate) -> Result<bool, JumpError> {
+        let PublicKeyOrCertificate::PublicKey { key, .. } = server else {
+            return Err(JumpError(format!("跳板主機 {} 使用主機憑證，目前只支援 known_hosts 中的主機金鑰", self.host)));
+        };
+        match keys::check_known_hosts(&self.host, self.port, key) {
+            Ok(true) => Ok(true),
+            Ok(false) => Err(JumpError(format!(
+                "跳板主機 {} 的金鑰不在 known_hosts 中，請先用 ssh 連線一次確認指紋",
+                self.host
+            ))),
+            Err(keys::Error::KeyChanged { line }) => Err(JumpError(format!(
+                "跳板主機 {} 的金鑰與 known_hosts 第 {} 行記錄的不同，可能遭到中間人攻擊",
+                self.host, line
+            ))),
+            Err(e) => Err(JumpError(format!("無法讀取 known_hosts: {}", e))),
+        }
+    }
+}
+
+// 一條 SSH 連線與其通道上限
+struct Session {
+    handle: Handle<KnownHosts>,
+    channels: Arc<Semaphore>,
+}
+
+// 跳板主機的 SSH 連線；探測共用連線，每條連線同時開啟的通道數有上限，滿載時才開新連線
+pub struct JumpHost {
+    spec: JumpSpec,
+    key: Option<PathBuf>,
+    config: Arc<client::Config>,
+    sessions: Mutex<Vec<Arc<Session>>>,
+    max_sessions: usize,
+    channels: usize,
+}
+
+impl fmt::Debug for JumpHost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JumpHost").field("spec", &self.spec).field("channels", &self.channels).finish()
+    }
+}
+
+impl JumpHost {
+    // 先建立第一條連線，驗證失敗或主機金鑰不符時在掃描開始前回報
+    pub async fn connect(spec: JumpSpec, key: Option<PathBuf>, max_sessions: usize, channels: usize) -> Result<Self, JumpError> {
+        let jump = JumpHost {
+            spec,
+            key,
+            config: Arc::new(client::Config::default()),
+            sessions: Mutex::new(Vec::new()),
+            max_sessions: max_sessions.max(1),
+            channels: channels.max(1),
+        };
+        let session = jump.open_session().await?;
+        jump.sessions.lock().await.push(Arc::new(session));
+        Ok(jump)
+    }
+
+    pub fn spec(&self) -> &JumpSpec {
+        &self.spec
+    }
+
+    async fn open_session(&self) -> Result<Session, JumpError> {
+        let handler = KnownHosts { host: self.spec.host.clone(), port: self.spec.port };
+        let attempt = async {
+            let mut handle = client::connect(self.config.clone(), (self.spec.host.as_str(), self.spec.port), handler).await?;
+            authenticate(&mut handle, &self.spec.user, self.key.as_deref()).await?;
+            Ok::<_, JumpError>(handle)
+        };
+        let handle = timeout(CONNECT_TIMEOUT, attempt)
+            .await
+            .map_err(|_| JumpError(format!("連線到跳板主機 {} 逾時", self.spec)))??;
+        Ok(Session { handle, channels: Arc::new(Semaphore::new(self.channels)) })
+    }
+
+    // 取得一個通道的額度：先用現有連線的空位，都滿載時開新連線，到達連線數上限時等待
+    async fn slot(&self) -> Result<(Arc<Session>, OwnedSemaphorePermit), JumpError> {
+        let mut sessions = self.sessions.lock().await;
+        sessions.retain(|session| !session.handle.is_closed());
+        for session in sessions.iter() {
+            if let Ok(permit) = session.channels.clone().try_acquire_owned() {
+                return Ok((session.clone(), permit));
+            }
+        }
+        if sessions.len() < self.max_sessions {
+            let session = Arc::new(self.open_session().await?);
+            let permit = session.channels.clone().try_acquire_owned().expect("new session has free channels");
+            sessions.push(session.clone());
+            return Ok((session, permit));
+        }
+        // 排在最久的連線之後；等待期間不佔住清單，其他探測仍可使用釋出的空位
+        let session = sessions[0].clone();
+        drop(sessions);
+        let permit = session.channels.clone().acquire_owned().await.expect("semaphore closed");
+        Ok((session, permit))
+    }
+
+    // 經由跳板以 direct-tcpip 開啟到目的端的通道；等待通道額度不計入逾時
+    pub async fn open(&self, dest: SocketAddr, limit: Duration) -> Result<Tunnel, TunnelError> {
+        let (session, permit) = self.slot().await.map_err(TunnelError::Session)?;
+        let started = Instant::now();
+        let opened = timeout(limit, session.handle.channel_open_direct_tcpip(dest.ip().to_string(), dest.port() as u32, "127.0.0.1", 0)).await;
+        match opened {
+            Err(_) => Err(TunnelError::Timeout),
+            Ok(Ok(channel)) => Ok(Tunnel { stream: channel.into_stream(), _permit: permit, elapsed: started.elapsed() }),
+            Ok(Err(russh::Error::ChannelOpenFailure(reason))) => Err(TunnelError::Rejected(reason, started.elapsed())),
+            Ok(Err(e)) => Err(TunnelError::Session(e.into())),
+        }
+    }
+}
+
+// 依序嘗試 ssh-agent 的金鑰與金鑰檔；指定 --jump-key 時只用該檔
+async fn authenticate(handle: &mut Handle<KnownHosts>, user: &str, key: Option<&Path>) -> Result<(), JumpError> {
+    let hash_alg = handle.best_supported_rsa_hash().await?.flatten();
+    if key.is_none() && agent_auth(handle, user, hash_alg).await? {
+        return Ok(());
+    }
+    let candidates: Vec<PathBuf> = match key {
+        Some(path) => vec![path.to_path_buf()],
+        None => match std::env::var_os("HOME") {
+            Some(home) => DEFAULT_KEYS.iter().map(|name| Path::new(&home).join(".ssh").join(name)).filter(|path| path.exists()).collect(),
+            None => Vec::new(),
+        },
+    };
+    for path in &candidates {
+        // 有密碼的金鑰無法在掃描中輸入，請改用 ssh-agent
+        let secret = keys::load_secret_key(path, None).map_err(|e| JumpError(format!("無法讀取金鑰 {}: {} (有密碼的金鑰請先加入 ssh-agent)", path.display(), e)))?;
+        let secret = PrivateKeyWithHashAlg::new(Arc::new(secret), hash_alg);
+        if handle.authenticate_publickey(user, secret).await?.success() {
+            return Ok(());
+        }
+    }
+    Err(JumpError(format!("跳板主機拒絕了 {} 的所有金鑰 (ssh-agent 與 {} 個金鑰檔)", user, candidates.len())))
+}
+
+// ssh-agent 中的每把金鑰；沒有 agent 時視為沒有金鑰
+#[cfg(unix)]
+async fn agent_auth(handle: &mut Handle<KnownHosts>, user: &str, hash_alg: Option<keys::HashAlg>) -> Result<bool, JumpError> {
+    use russh::keys::agent::AgentIdentity;
+    let Ok(mut agent) = keys::agent::client::AgentClient::connect_env().await else {
+        return Ok(false);
+    };
+    let identities = agent.request_identities().await.unwrap_or_default();
+    for identity in identities {
+        let AgentIdentity::PublicKey { key, .. } = identity else {
+            continue;
+        };
+        if let Ok(result) = handle.authenticate_publickey_with(user, key, hash_alg, &mut agent).await {
+            if result.success() {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(not(unix))]
+async fn agent_auth(_handle: &mut Handle<KnownHosts>, _user: &str, _hash_alg: Option<keys::HashAlg>) -> Result<bool, JumpError> {
+    Ok(false)
+}
+
+// 開啟通道失敗的原因
+#[derive(Debug)]
+pub enum TunnelError {
+    // 跳板回報無法連線 (附上等待時間) 或不允許轉送
+    Rejected(ChannelOpenFailure, Duration),
+    Timeout,
+    // SSH 連線本身的問題，不代表目的端口的狀態
+    Session(JumpError),
+}
+
+impl TunnelError {
+    // 對應一般連線失敗的分類：跳板很快回報無法連線視為被拒 (關閉)，不允許轉送視為被過濾
+    pub fn failure(&self) -> Failure {
+        match self {
+            TunnelError::Rejected(ChannelOpenFailure::ConnectFailed, elapsed) => Failure::Reset { latency_ms: elapsed.as_secs_f64() * 1000.0 },
+            TunnelError::Timeout => Failure::Timeout,
+            TunnelError::Rejected(..) | TunnelError::Session(_) => Failure::Unreachable,
+        }
+    }
+}
+
+impl fmt::Display for TunnelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TunnelError::Rejected(reason, _) => write!(f, "跳板無法開啟通道: {:?}", reason),
+            TunnelError::Timeout => f.write_str("逾時"),
+            TunnelError::Session(e) => e.fmt(f),
+        }
+    }
+}
+
+// 一個 direct-tcpip 通道；存在期間佔用所屬連線的一個通道額度
+pub struct Tunnel {
+    stream: ChannelStream<Msg>,
+    _permit: OwnedSemaphorePermit,
+    elapsed: Duration,
+}
+
+impl AsyncRead for Tunnel {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Tunnel {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+// --jump：出站、橫幅、覆核與虛擬主機探測都經由跳板；入站測試仍在本機綁定
+#[derive(Debug)]
+pub struct JumpProber {
+    jump: Arc<JumpHost>,
+    context: Arc<ScanContext>,
+}
+
+impl JumpProber {
+    pub fn new(jump: Arc<JumpHost>, context: Arc<ScanContext>) -> Self {
+        JumpProber { jump, context }
+    }
+
+    async fn connect(&self, dest: SocketAddr, limit: Duration) -> Outbound {
+        match self.jump.open(dest, limit).await {
+            Ok(tunnel) => Outbound { connected: true, setup: tunnel.elapsed, ..Default::default() },
+            Err(e) => Outbound { failure: Some(e.failure()), ..Default::default() },
+        }
+    }
+}
+
+impl Prober for JumpProber {
+    // 跳板之後的網路不會把 ICMP 送回本機
+    fn connect<'a>(&'a self, dest: IpAddr, port: u16, limit: Duration, _icmp: Option<&'a IcmpMonitor>) -> ProbeFuture<'a, Outbound> {
+        Box::pin(JumpProber::connect(self, SocketAddr::new(dest, port), limit))
+    }
+
+    fn bind(&self, port: u16, addr: IpAddr) -> ProbeFuture<'_, Result<(), ErrorCode>> {
+        Box::pin(scanner::test_inbound_port(port, addr))
+    }
+
+    fn banner<'a>(&'a self, library: &'a ProbeLibrary, dest: IpAddr, port: u16, limit: Duration) -> ProbeFuture<'a, Option<Banner>> {
+        Box::pin(probes::grab_with(library, port, move |probe| async move {
+            let deadline = Instant::now() + limit;
+            let tunnel = self.jump.open(SocketAddr::new(dest, port), limit).await.ok()?;
+            probes::exchange_on(&self.context, tunnel, probe, deadline).await
+        }))
+    }
+
+    // SSH 只能轉送 TCP，UDP 服務檢查沒有回應
+    fn udp_exchange<'a>(&'a self, _dest: IpAddr, _port: u16, _payload: &'a [u8], _wait: Duration, _linger: Duration) -> ProbeFuture<'a, UdpReplies> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    // 來源位址由跳板決定，忽略 source
+    fn reprobe(&self, dest: SocketAddr, limit: Duration, _source: Option<IpAddr>) -> ProbeFuture<'_, Reprobe> {
+        Box::pin(async move {
+            let outbound = JumpProber::connect(self, dest, limit).await;
+            let latency_ms = outbound.connected.then_some(outbound.setup.as_secs_f64() * 1000.0);
+            (outbound.connected, latency_ms, outbound.failure, outbound.error)
+        })
+    }
+
+    fn vhosts<'a>(&'a self, dest: IpAddr, port: &'a PortInfo, names: &'a [String]) -> ProbeFuture<'a, Vec<VhostResult>> {
+        Box::pin(async move {
+            let tls = vhost::uses_tls(port);
+            let addr = SocketAddr::new(dest, port.port);
+            let mut results = Vec::with_capacity(names.len());
+            for name in names {
+                let result = vhost::probe_one(name, tls, |insecure| async move {
+                    let tunnel = self.jump.open(addr, vhost::WEB_TIMEOUT).await.map_err(|e| e.to_string())?;
+                    vhost::request_on(tunnel, name, tls, insecure).await
+                });
+                results.push(result.await);
+            }
+            results
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jump_specs() {
+        let spec: JumpSpec = "ops@bastion.example.com".parse().unwrap();
+        assert_eq!(spec, JumpSpec { user: "ops".to_string(), host: "bastion.example.com".to_string(), port: 22 });
+        let spec: JumpSpec = " ops@10.0.0.1:2222 ".parse().unwrap();
+        assert_eq!((spec.host.as_str(), spec.port), ("10.0.0.1", 2222));
+        let spec: JumpSpec = "ops@[2001:db8::1]:2222".parse().unwrap();
+        assert_eq!((spec.host.as_str(), spec.port), ("2001:db8::1", 2222));
+        assert_eq!(spec.to_string(), "ops@[2001:db8::1]:2222");
+        assert_eq!("ops@[2001:db8::1]".parse::<JumpSpec>().unwrap().port, 22);
+
+        for bad in ["bastion", "@bastion", "ops@", "ops@bastion:0", "ops@bastion:ssh", "ops@[2001:db8::1", "ops@[::1]x", "a@b@c"] {
+            assert!(bad.parse::<JumpSpec>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn channel_failures_map_to_port_states() {
+        let fast = Duration::from_millis(12);
+        assert!(matches!(TunnelError::Rejected(ChannelOpenFailure::ConnectFailed, fast).failure(), Failure::Reset { latency_ms } if latency_ms == 12.0));
+        assert_eq!(TunnelError::Rejected(ChannelOpenFailure::AdministrativelyProhibited, fast).failure(), Failure::Unreachable);
+        assert_eq!(TunnelError::Timeout.failure(), Failure::Timeout);
+        assert_eq!(TunnelError::Session(JumpError("closed".to_string())).failure(), Failure::Unreachable);
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::BTreeMap;
+use std::io::IsTerminal;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use colored::*;
+use indicatif::ProgressBar;
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+use crate::targets::TargetSpec;
+use crate::timefmt;
+
+// 按鍵說明，掃描開始時顯示在進度列上方
+const HINT: &str = "按 p 暫停、r 繼續、s 顯示目前進度、x 取消單一目標、q 中止並保留已完成的結果";
+
+// 互動掃描的暫停與中止狀態；排程器在取得許可前檢查
+#[derive(Debug)]
+pub struct ScanControl {
+    paused: watch::Sender<bool>,
+    aborted: AtomicBool,
+    // 已完成探測中出站可連線的數量，供 s 的中途摘要
+    open: AtomicU64,
+    // 整個掃描的取消權杖；每個目標的權杖由它衍生
+    token: CancellationToken,
+    // 已排程過的目標與其權杖；按 x 取消的目標權杖已取消
+    targets: Mutex<BTreeMap<IpAddr, CancellationToken>>,
+    // 掃描的目標清單，用來確認要取消的主機在掃描範圍內
+    scope: Mutex<Vec<TargetSpec>>,
+}
+
+impl Default for ScanControl {
+    fn default() -> Self {
+        ScanControl {
+            paused: watch::Sender::new(false),
+            aborted: AtomicBool::new(false),
+            open: AtomicU64::new(0),
+            token: CancellationToken::new(),
+            targets: Mutex::new(BTreeMap::new()),
+            scope: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl ScanControl {
+    pub fn pause(&self) {
+        self.paused.send_replace(true);
+    }
+
+    pub fn resume(&self) {
+        self.paused.send_replace(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    // 中止也會解除暫停，讓等待中的排程器結束
+    pub fn abort(&self) {
+        self.aborted.store(true, Ordering::SeqCst);
+        self.paused.send_replace(false);
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::SeqCst)
+    }
+
+    // 暫停時等待繼續；回傳 false 表示已中止，不再排入新的探測
+    pub async fn wait_running(&self) -> bool {
+        let mut receiver = self.paused.subscribe();
+        let _ = receiver.wait_for(|paused| !paused).await;
+        !self.is_aborted()
+    }
+
+    // 目標的取消權杖；取消整個掃描的權杖時一併取消
+    pub fn target_token(&self, host: IpAddr) -> CancellationToken {
+        let mut targets = self.targets.lock().unwrap_or_else(|e| e.into_inner());
+        targets.entry(host).or_insert_with(|| self.token.child_token()).clone()
+    }
+
+    pub fn set_scope(&self, targets: &[TargetSpec]) {
+        *self.scope.lock().unwrap_or_else(|e| e.into_inner()) = targets.to_vec();
+    }
+
+    // 輸入的位址或目標主機名稱；不在掃描範圍內時為 None
+    fn find_target(&self, input: &str) -> Option<IpAddr> {
+        let scope = self.scope.lock().unwrap_or_else(|e| e.into_inner());
+        match input.parse::<IpAddr>() {
+            Ok(host) => scope.iter().any(|target| target.contains(host)).then_some(host),
+            Err(_) => scope.iter().find_map(|target| match target {
+                TargetSpec::Host { name, addr } if name.eq_ignore_ascii_case(input) => Some(*addr),
+                _ => None,
+            }),
+        }
+    }
+
+    // 只取消一個目標：尚未排程的探測不再送出，進行中的探測立即放棄，其他目標照常掃描
+    pub fn cancel_target(&self, host: IpAddr) {
+        self.target_token(host).cancel();
+    }
+
+    // 被取消的目標，依位址排序
+    pub fn cancelled(&self) -> Vec<IpAddr> {
+        let targets = self.targets.lock().unwrap_or_else(|e| e.into_inner());
+        targets.iter().filter(|(_, token)| token.is_cancelled()).map(|(host, _)| *host).collect()
+    }
+
+    pub fn record(&self, connected: bool) {
+        if connected {
+            self.open.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // s 的中途摘要
+    fn summary(&self, pb: &ProgressBar) -> String {
+        let state = if self.is_paused() { "，已暫停" } else { "" };
+        format!(
+            "目前進度: {}/{} 個探測完成，{} 個可連線，已經過 {}{}",
+            pb.position(),
+            pb.length().unwrap_or_default(),
+            self.open.load(Ordering::Relaxed),
+            timefmt::duration(pb.elapsed()),
+            state
+        )
+    }
+}
+
+// 標準輸入與輸出都是終端時才啟用按鍵控制
+pub fn available() -> bool {
+    cfg!(unix) && std::io::stdin().is_terminal() && std::io::stdout().is_terminal()
+}
+
+// 掃描期間讀取按鍵的執行緒；結束 (drop) 時停止讀取並還原終端設定
+pub struct Keyboard {
+    stop: Arc<AtomicBool>,
+    reader: Option<JoinHandle<()>>,
+    #[cfg(unix)]
+    saved: libc::termios,
+}
+
+// 讀取執行緒檢查停止旗標的間隔
+#[cfg(unix)]
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[cfg(unix)]
+impl Keyboard {
+    // 終端切換為逐字輸入且不回顯；Ctrl+C 改由讀取執行緒處理，先還原終端再送出 SIGINT
+    pub fn start(control: Arc<ScanControl>, pb: ProgressBar) -> Option<Self> {
+        if !available() {
+            return None;
+        }
+        let fd = libc::STDIN_FILENO;
+        // 安全性：termios 為純資料結構，由 tcgetattr 填入
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut saved) } != 0 {
+            return None;
+        }
+        let mut raw = saved;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+            return None;
+        }
+
+        pb.println(HINT.dimmed().to_string());
+        let stop = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let stop = stop.clone();
+            std::thread::spawn(move || read_keys(&control, &pb, &stop, saved))
+        };
+        Some(Keyboard {
+            stop,
+            reader: Some(reader),
+            saved,
+        })
+    }
+}
+
+#[cfg(not(unix))]
+impl Keyboard {
+    pub fn start(_control: Arc<ScanControl>, _pb: ProgressBar) -> Option<Self> {
+        None
+    }
+}
+
+#[cfg(unix)]
+fn restore(saved: &libc::termios) {
+    unsafe {
+        libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved);
+    }
+}
+
+// 以 poll 等待輸入，停止旗標設定後不再讀取，之後的輸入留給程序結束前的提示
+#[cfg(unix)]
+fn read_keys(control: &ScanControl, pb: &ProgressBar, stop: &AtomicBool, saved: libc::termios) {
+    let mut poll = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let mut input: Option<String> = None;
+    while !stop.load(Ordering::SeqCst) {
+        let ready = unsafe { libc::poll(&mut poll, 1, POLL_INTERVAL.as_millis() as libc::c_int) };
+        if ready <= 0 || poll.revents & libc::POLLIN == 0 {
+            // 輸入已關閉時不再讀取
+            if ready > 0 {
+                return;
+            }
+            continue;
+        }
+        let mut byte = 0u8;
+        if unsafe { libc::read(libc::STDIN_FILENO, (&mut byte as *mut u8).cast(), 1) } != 1 {
+            return;
+        }
+        // 按 x 之後輸入要取消的目標位址，Enter 確認、Esc 放棄；Ctrl+C 照常處理
+        if let Some(mut line) = input.take().filter(|_| byte != 0x03) {
+            match byte {
+                b'\r' | b'\n' => {
+                    pb.set_message("");
+                    let line = line.trim();
+                    match control.find_target(line) {
+                        Some(host) => {
+                            control.cancel_target(host);
+                            pb.println(format!("已取消 {}，其他目標繼續掃描", host).yellow().to_string());
+                        }
+                        None if line.is_empty() => {}
+                        None => pb.println(format!("{} 不在掃描目標中", line).red().to_string()),
+                    }
+                }
+                0x1b => pb.set_message(""),
+                0x7f | 0x08 => {
+                    line.pop();
+                    pb.set_message(format!("取消目標: {}", line));
+                    input = Some(line);
+                }
+                b if b.is_ascii_graphic() => {
+                    line.push(b as char);
+                    pb.set_message(format!("取消目標: {}", line));
+                    input = Some(line);
+                }
+                _ => input = Some(line),
+            }
+            continue;
+        }
+        match byte.to_ascii_lowercase() {
+            b'p' if !control.is_paused() => {
+                control.pause();
+                pb.set_message("已暫停".yellow().to_string());
+            }
+            b'r' if control.is_paused() => {
+                control.resume();
+                pb.set_message("");
+            }
+            b's' => pb.println(control.summary(pb)),
+            b'x' => {
+                input = Some(String::new());
+                pb.set_message("取消目標: ");
+            }
+            b'q' => {
+                control.abort();
+                pb.set_message("中止中，等待進行中的探測完成…".yellow().to_string());
+            }
+            // Ctrl+C
+            0x03 => {
+                restore(&saved);
+                unsafe {
+                    libc::kill(libc::getpid(), libc::SIGINT);
+                }
+                return;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Drop for Keyboard {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+        #[cfg(unix)]
+        restore(&self.saved);
+    }
+}
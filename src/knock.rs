@@ -0,0 +1,145 @@
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::net::{TcpSocket, UdpSocket};
+use tokio::sync::Mutex;
+use tokio::time::{sleep, timeout};
+use crate::context::ScanContext;
+use crate::portspec;
+
+// 敲門封包的協定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KnockProto {
+    Tcp,
+    Udp,
+}
+
+// 敲門序列中的一步
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Knock {
+    pub port: u16,
+    pub proto: KnockProto,
+}
+
+impl fmt::Display for Knock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.proto {
+            KnockProto::Tcp => write!(f, "{}/tcp", self.port),
+            KnockProto::Udp => write!(f, "{}/udp", self.port),
+        }
+    }
+}
+
+// 敲門設定
+#[derive(Debug)]
+pub struct KnockPlan {
+    pub sequence: Vec<Knock>,
+    pub delay: Duration,
+    // 探測失敗時重新敲門後再試一次的端口
+    pub protected: Vec<u16>,
+    // 同時進行的探測共用重新敲門，避免序列互相穿插
+    lock: Mutex<()>,
+}
+
+impl KnockPlan {
+    pub fn new(sequence: Vec<Knock>, delay: Duration, protected: Vec<u16>) -> Self {
+        KnockPlan {
+            sequence,
+            delay,
+            protected,
+            lock: Mutex::new(()),
+        }
+    }
+
+    // 重新敲門 (供受保護端口重試使用)
+    pub async fn reknock(&self, context: &ScanContext, addr: IpAddr) -> Result<(), String> {
+        let _guard = self.lock.lock().await;
+        knock(context, addr, &self.sequence, self.delay, |_, _| {}).await.map(|_| ())
+    }
+}
+
+// 解析敲門序列，例如 "7000,8000,9000:udp"；未指定協定時為 TCP
+pub fn parse_sequence(spec: &str) -> Result<Vec<Knock>, String> {
+    let mut sequence = Vec::new();
+    for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let (port, proto) = match item.split_once(':') {
+            Some((port, "tcp")) => (port, KnockProto::Tcp),
+            Some((port, "udp")) => (port, KnockProto::Udp),
+            Some(_) => return Err(format!("無效的敲門協定: {} (可用 tcp、udp)", item)),
+            None => (item, KnockProto::Tcp),
+        };
+        let port = portspec::parse_port(port).map_err(|_| format!("無效的敲門端口: {}", item))?;
+        sequence.push(Knock { port, proto });
+    }
+
+    if sequence.is_empty() {
+        return Err("敲門序列是空的".to_string());
+    }
+    Ok(sequence)
+}
+
+// 遠端拒絕或丟棄都是預期的；只有本機無法送出時才算失敗
+fn is_send_failure(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::NetworkUnreachable
+            | io::ErrorKind::AddrNotAvailable
+            | io::ErrorKind::PermissionDenied
+            | io::ErrorKind::Unsupported
+            | io::ErrorKind::InvalidInput
+    )
+}
+
+// 送出單個敲門封包；TCP 只需送出 SYN，不等待連線完成
+async fn send_knock(context: &ScanContext, addr: IpAddr, knock: Knock, wait: Duration) -> io::Result<()> {
+    let target = context.socket_addr(addr, knock.port);
+    match knock.proto {
+        KnockProto::Tcp => {
+            let socket = match addr {
+                IpAddr::V4(_) => TcpSocket::new_v4()?,
+                IpAddr::V6(_) => TcpSocket::new_v6()?,
+            };
+            match timeout(wait, socket.connect(target)).await {
+                Ok(Err(e)) if is_send_failure(&e) => Err(e),
+                _ => Ok(()),
+            }
+        }
+        KnockProto::Udp => {
+            let bind: SocketAddr = match addr {
+                IpAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+                IpAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+            };
+            let socket = UdpSocket::bind(bind).await?;
+            socket.send_to(&[0u8], target).await.map(|_| ())
+        }
+    }
+}
+
+// 依序送出敲門序列，每步之間間隔 delay；on_knock 收到每步的相對時間
+pub async fn knock(
+    context: &ScanContext,
+    addr: IpAddr,
+    sequence: &[Knock],
+    delay: Duration,
+    mut on_knock: impl FnMut(Knock, Duration),
+) -> Result<Duration, String> {
+    let start = Instant::now();
+    for (index, step) in sequence.iter().enumerate() {
+        let sent_at = Instant::now();
+        on_knock(*step, start.elapsed());
+
+        // TCP 連線嘗試最多等到下一步之前
+        let wait = delay.max(Duration::from_millis(10));
+        send_knock(context, addr, *step, wait)
+            .await
+            .map_err(|e| format!("無法送出敲門封包到 {} {}: {}", addr, step, e))?;
+
+        if index + 1 < sequence.len() {
+            if let Some(rest) = delay.checked_sub(sent_at.elapsed()) {
+                sleep(rest).await;
+            }
+        }
+    }
+    Ok(start.elapsed())
+}
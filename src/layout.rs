@@ -0,0 +1,215 @@
+use std::env;
+use std::io::{self, IsTerminal};
+
+// 無法取得終端寬度 (例如輸出到檔案) 且沒有 COLUMNS 時的寬度
+pub const DEFAULT_WIDTH: usize = 100;
+
+// 低於此寬度時端口結果改為兩行一組
+pub const MIN_WIDTH: usize = 60;
+
+// --width 可指定的最小值；更窄的終端仍以此寬度排版
+pub const NARROWEST: usize = 20;
+
+// 終端顯示寬度：去除 ANSI 色碼，全形字元佔兩格
+pub fn display_width(text: &str) -> usize {
+    console::measure_text_width(text)
+}
+
+// 截斷到指定顯示寬度，過長時結尾改為 "…"；保留色碼
+pub fn truncate(text: &str, width: usize) -> String {
+    if display_width(text) <= width {
+        return text.to_string();
+    }
+    console::truncate_str(text, width, "…").into_owned()
+}
+
+// 補空白到指定顯示寬度，過長時截斷
+pub fn fit(text: &str, width: usize) -> String {
+    let text = truncate(text, width);
+    let pad = width.saturating_sub(display_width(&text));
+    text + &" ".repeat(pad)
+}
+
+// 靠右對齊到指定顯示寬度，過長時截斷
+pub fn fit_right(text: &str, width: usize) -> String {
+    let text = truncate(text, width);
+    " ".repeat(width.saturating_sub(display_width(&text))) + &text
+}
+
+// 終端輸出的排版寬度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub width: usize,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout { width: DEFAULT_WIDTH }
+    }
+}
+
+impl Layout {
+    pub fn new(width: usize) -> Self {
+        Layout { width: width.max(NARROWEST) }
+    }
+
+    // --width 優先，其次為標準輸出的終端寬度與 COLUMNS
+    pub fn detect(width: Option<usize>) -> Self {
+        let detected = width
+            .or_else(|| io::stdout().is_terminal().then(|| console::Term::stdout().size_checked()).flatten().map(|(_, columns)| columns as usize))
+            .or_else(|| env::var("COLUMNS").ok().and_then(|v| v.parse().ok()))
+            .unwrap_or(DEFAULT_WIDTH);
+        Layout::new(detected)
+    }
+
+    // 端口結果改為兩行一組
+    pub fn stacked(&self) -> bool {
+        self.width < MIN_WIDTH
+    }
+
+    // 截斷超出終端寬度的一行
+    pub fn clip(&self, line: &str) -> String {
+        truncate(line, self.width)
+    }
+}
+
+// 欄位的對齊方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+// 欄寬依內容決定的表格；放不下時先縮減最寬的靠左欄位，窄終端改為每列兩行
+pub struct Table {
+    aligns: Vec<Align>,
+    rows: Vec<Vec<String>>,
+}
+
+// 縮減欄位時每欄至少保留的寬度
+const MIN_COLUMN: usize = 4;
+
+impl Table {
+    pub fn new(aligns: &[Align]) -> Self {
+        Table { aligns: aligns.to_vec(), rows: Vec::new() }
+    }
+
+    pub fn row(&mut self, cells: Vec<String>) {
+        self.rows.push(cells);
+    }
+
+    // 每欄的顯示寬度；欄間以一個空白分隔
+    fn widths(&self, width: usize) -> Vec<usize> {
+        let mut widths = vec![0; self.aligns.len()];
+        for row in &self.rows {
+            for (column, cell) in row.iter().enumerate().take(widths.len()) {
+                widths[column] = widths[column].max(display_width(cell));
+            }
+        }
+        let total = |widths: &[usize]| widths.iter().sum::<usize>() + widths.len().saturating_sub(1);
+        while total(&widths) > width {
+            let widest = (0..widths.len())
+                .filter(|column| self.aligns[*column] == Align::Left && widths[*column] > MIN_COLUMN)
+                .max_by_key(|column| widths[*column]);
+            let Some(column) = widest else {
+                break;
+            };
+            widths[column] -= 1;
+        }
+        widths
+    }
+
+    pub fn render(&self, layout: &Layout) -> Vec<String> {
+        if layout.stacked() {
+            return self.stacked(layout);
+        }
+        let widths = self.widths(layout.width);
+        self.rows
+            .iter()
+            .map(|row| {
+                let cells: Vec<String> = row
+                    .iter()
+                    .zip(&widths)
+                    .zip(&self.aligns)
+                    .map(|((cell, width), align)| match align {
+                        Align::Left => fit(cell, *width),
+                        Align::Right => fit_right(cell, *width),
+                    })
+                    .collect();
+                layout.clip(cells.join(" ").trim_end())
+            })
+            .collect()
+    }
+
+    // 第一欄自成一行，其餘欄位縮排在下一行
+    fn stacked(&self, layout: &Layout) -> Vec<String> {
+        let mut lines = Vec::new();
+        for row in &self.rows {
+            let Some((first, rest)) = row.split_first() else {
+                continue;
+            };
+            lines.push(layout.clip(first));
+            if !rest.is_empty() {
+                lines.push(layout.clip(&format!("  {}", rest.join(" "))));
+            }
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn widths_ignore_color_codes_and_count_wide_characters() {
+        assert_eq!(display_width("Port 22"), 7);
+        assert_eq!(display_width("雙向可用"), 8);
+        assert_eq!(display_width("\x1b[32m✓ 雙向可用\x1b[0m"), 10);
+    }
+
+    #[test]
+    fn truncation_keeps_the_display_width() {
+        assert_eq!(truncate("Microsoft SQL Server", 10), "Microsoft…");
+        assert_eq!(truncate("SSH", 10), "SSH");
+        assert_eq!(fit("SSH", 6), "SSH   ");
+        assert_eq!(fit("遠端桌面服務", 7), "遠端桌…");
+        assert_eq!(fit("遠端桌面服務", 8), "遠端桌… ");
+        assert_eq!(fit_right("22", 5), "   22");
+        assert_eq!(display_width(&fit("遠端桌面服務", 7)), 7);
+    }
+
+    #[test]
+    fn layout_width_has_a_floor() {
+        assert_eq!(Layout::new(5).width, NARROWEST);
+        assert!(Layout::new(59).stacked());
+        assert!(!Layout::new(60).stacked());
+        assert_eq!(Layout::detect(Some(72)).width, 72);
+    }
+
+    fn table() -> Table {
+        let mut table = Table::new(&[Align::Left, Align::Right, Align::Left]);
+        table.row(vec!["Web".to_string(), "3".to_string(), "HTTP, HTTPS, HTTP-Alt, HTTP-Proxy, WebSocket, gRPC-Web, WebDAV".to_string()]);
+        table.row(vec!["Database".to_string(), "12".to_string(), "MySQL".to_string()]);
+        table
+    }
+
+    #[test]
+    fn snapshots_at_several_widths() {
+        assert_eq!(
+            table().render(&Layout::new(100)),
+            vec!["Web       3 HTTP, HTTPS, HTTP-Alt, HTTP-Proxy, WebSocket, gRPC-Web, WebDAV", "Database 12 MySQL"]
+        );
+        // 只縮減最寬的靠左欄位
+        assert_eq!(
+            table().render(&Layout::new(60)),
+            vec!["Web       3 HTTP, HTTPS, HTTP-Alt, HTTP-Proxy, WebSocket, g…", "Database 12 MySQL"]
+        );
+        // 窄終端每列兩行
+        assert_eq!(
+            table().render(&Layout::new(40)),
+            vec!["Web", "  3 HTTP, HTTPS, HTTP-Alt, HTTP-Proxy, …", "Database", "  12 MySQL"]
+        );
+        assert!(table().render(&Layout::new(NARROWEST)).iter().all(|line| display_width(line) <= NARROWEST));
+    }
+}
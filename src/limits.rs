@@ -0,0 +1,123 @@
+use std::io;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::errors::ErrorCode;
+
+// 保留給標準輸出入、入站測試、輸出檔案與執行緒等的檔案描述符
+const RESERVED_FDS: u64 = 64;
+
+// 每個探測最多同時使用的檔案描述符 (出站連線 + 虛擬主機或橫幅探測)
+const FDS_PER_PROBE: u64 = 2;
+
+// 掃描端本身的錯誤，不代表端口狀態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanError {
+    // EMFILE / ENFILE：檔案描述符用盡
+    TooManyOpenFiles,
+    // EADDRINUSE / EADDRNOTAVAIL：本機臨時端口衝突或用盡
+    AddressInUse,
+    // 探測工作 panic；這個端口沒有探測結果
+    Panicked,
+}
+
+impl ScanError {
+    pub fn describe(self) -> &'static str {
+        match self {
+            ScanError::TooManyOpenFiles => "檔案描述符不足 (掃描端錯誤)",
+            ScanError::AddressInUse => "本機端口衝突 (掃描端錯誤)",
+            ScanError::Panicked => "探測工作異常中止 (掃描端錯誤)",
+        }
+    }
+
+    pub fn code(self) -> ErrorCode {
+        match self {
+            ScanError::TooManyOpenFiles => ErrorCode::FdExhausted,
+            ScanError::AddressInUse => ErrorCode::LocalPortExhausted,
+            ScanError::Panicked => ErrorCode::ProbePanicked,
+        }
+    }
+
+    // 依 I/O 錯誤判斷是否為掃描端錯誤
+    pub fn classify(error: &io::Error) -> Option<Self> {
+        #[cfg(unix)]
+        let (exhausted, in_use) = (
+            matches!(error.raw_os_error(), Some(libc::EMFILE) | Some(libc::ENFILE)),
+            matches!(error.raw_os_error(), Some(libc::EADDRINUSE) | Some(libc::EADDRNOTAVAIL)),
+        );
+        // WSAEMFILE、WSAEADDRINUSE / WSAEADDRNOTAVAIL
+        #[cfg(windows)]
+        let (exhausted, in_use) = (
+            error.raw_os_error() == Some(10024),
+            matches!(error.raw_os_error(), Some(10048) | Some(10049)),
+        );
+        #[cfg(not(any(unix, windows)))]
+        let (exhausted, in_use) = (false, false);
+
+        match (exhausted, in_use) {
+            (true, _) => Some(ScanError::TooManyOpenFiles),
+            (_, true) => Some(ScanError::AddressInUse),
+            _ => None,
+        }
+    }
+}
+
+// 目前的 RLIMIT_NOFILE (soft, hard)；rlim_t 的寬度依平台而異
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)]
+pub fn nofile() -> Option<(u64, u64)> {
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return None;
+    }
+    Some((limit.rlim_cur as u64, limit.rlim_max as u64))
+}
+
+#[cfg(not(unix))]
+pub fn nofile() -> Option<(u64, u64)> {
+    None
+}
+
+// --raise-nofile：將 soft limit 提高到 hard limit，回傳新的 soft limit
+#[cfg(unix)]
+pub fn raise_nofile() -> Result<u64, String> {
+    let (soft, hard) = nofile().ok_or("無法讀取 RLIMIT_NOFILE")?;
+    // macOS 的 hard limit 可能是無限，但實際上限為 OPEN_MAX
+    #[cfg(target_os = "macos")]
+    let target = hard.min(10240);
+    #[cfg(not(target_os = "macos"))]
+    let target = hard;
+    if soft >= target {
+        return Ok(soft);
+    }
+
+    let limit = libc::rlimit {
+        rlim_cur: target as libc::rlim_t,
+        rlim_max: hard as libc::rlim_t,
+    };
+    if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) } != 0 {
+        return Err(format!("無法提高 RLIMIT_NOFILE: {}", io::Error::last_os_error()));
+    }
+    Ok(target)
+}
+
+#[cfg(not(unix))]
+pub fn raise_nofile() -> Result<u64, String> {
+    Err("--raise-nofile 只支援 Unix 系統".to_string())
+}
+
+// 依檔案描述符上限調整並發數量；被調降時回傳警告
+pub fn cap_concurrency(requested: usize) -> (usize, Option<String>) {
+    let Some((soft, _)) = nofile() else {
+        return (requested, None);
+    };
+    let allowed = (soft.saturating_sub(RESERVED_FDS) / FDS_PER_PROBE).max(1);
+    if requested as u64 <= allowed {
+        return (requested, None);
+    }
+    let warning = format!(
+        "並發數量 {} 超過檔案描述符上限 {} 可支援的範圍，調降為 {} (可用 --raise-nofile 提高上限)",
+        requested, soft, allowed
+    );
+    (allowed as usize, Some(warning))
+}
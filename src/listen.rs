@@ -0,0 +1,301 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use colored::*;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::sync::Semaphore;
+use crate::probes::{Probe, ProbeLibrary, ProbeSource};
+use crate::scanner::ScanPlan;
+use crate::{timefmt, PortInfo, ScanResult};
+
+// listen 子命令：在另一台主機上綁定端口並回覆 token，讓掃描端以 --expect-token 確認連到的是這個程式而不是中間設備
+
+// 預設回覆的 token
+pub const DEFAULT_TOKEN: &str = "portscanner-listen";
+// 一次最多綁定的端口數
+const MAX_PORTS: usize = 1024;
+// 同時處理的 TCP 連線數；已滿時暫停接受新連線，不在記憶體中排隊
+const MAX_CONNECTIONS: usize = 256;
+// 寫出 token 的時間上限，避免不讀取的對方占住連線
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+// --expect-token 讀取回應的時間下限
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(2);
+
+// token 必須是可列印的單行文字，才能在橫幅中比對
+pub fn parse_token(s: &str) -> Result<String, String> {
+    match s {
+        "" => Err("token 不能是空的".to_string()),
+        s if s.len() > 64 => Err("token 最多 64 個位元組".to_string()),
+        s if s.chars().any(char::is_control) => Err("token 不能含有控制字元或換行".to_string()),
+        s => Ok(s.to_string()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl Protocol {
+    fn name(self) -> &'static str {
+        match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        }
+    }
+}
+
+// 每個綁定的 socket 收到的連線 (UDP 為封包) 數
+type Counters = BTreeMap<(u16, Protocol), Arc<AtomicU64>>;
+
+fn log_connection(port: u16, protocol: Protocol, peer: SocketAddr, count: u64) {
+    println!("{} {}/{} ← {} (#{})", timefmt::timestamp(timefmt::now()).dimmed(), port, protocol.name(), peer, count);
+}
+
+// 接受連線並回覆 token 後關閉
+async fn serve_tcp(listener: TcpListener, reply: Arc<Vec<u8>>, counter: Arc<AtomicU64>, permits: Arc<Semaphore>) {
+    let port = listener.local_addr().map(|addr| addr.port()).unwrap_or_default();
+    loop {
+        let Ok(permit) = permits.clone().acquire_owned().await else {
+            return;
+        };
+        let (mut stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                eprintln!("{}", format!("{}/tcp 接受連線失敗: {}", port, e).yellow());
+                continue;
+            }
+        };
+        let count = counter.fetch_add(1, Ordering::Relaxed) + 1;
+        log_connection(port, Protocol::Tcp, peer, count);
+        let reply = reply.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+            let _ = tokio::time::timeout(REPLY_TIMEOUT, async {
+                stream.write_all(&reply).await?;
+                stream.shutdown().await
+            })
+            .await;
+        });
+    }
+}
+
+// 每個封包都回覆 token 給來源
+async fn serve_udp(socket: UdpSocket, reply: Arc<Vec<u8>>, counter: Arc<AtomicU64>) {
+    let port = socket.local_addr().map(|addr| addr.port()).unwrap_or_default();
+    let mut buffer = [0u8; 1500];
+    loop {
+        let peer = match socket.recv_from(&mut buffer).await {
+            Ok((_, peer)) => peer,
+            // ICMP 端口不可達等錯誤只影響上一個回覆，繼續接收
+            Err(_) => continue,
+        };
+        let count = counter.fetch_add(1, Ordering::Relaxed) + 1;
+        log_connection(port, Protocol::Udp, peer, count);
+        let _ = socket.send_to(&reply, peer).await;
+    }
+}
+
+// listen：綁定所有端口 (個別失敗只回報)，直到 Ctrl+C 後顯示每個端口的連線數
+pub async fn run(ports: &BTreeSet<u16>, bind: IpAddr, reply: &str, udp: bool) -> Result<(), Box<dyn Error>> {
+    if ports.len() > MAX_PORTS {
+        return Err(format!("listen 一次最多綁定 {} 個端口", MAX_PORTS).into());
+    }
+    let reply = Arc::new(format!("{}\n", reply).into_bytes());
+    let permits = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+    let mut counters = Counters::new();
+    let mut tasks = Vec::new();
+    let mut failed = 0;
+    for &port in ports {
+        match TcpListener::bind((bind, port)).await {
+            Ok(listener) => {
+                let counter = counters.entry((port, Protocol::Tcp)).or_default().clone();
+                tasks.push(tokio::spawn(serve_tcp(listener, reply.clone(), counter, permits.clone())));
+            }
+            Err(e) => {
+                failed += 1;
+                eprintln!("{} {}/tcp 無法綁定: {}", "✗".red(), port, e);
+            }
+        }
+        if !udp {
+            continue;
+        }
+        match UdpSocket::bind((bind, port)).await {
+            Ok(socket) => {
+                let counter = counters.entry((port, Protocol::Udp)).or_default().clone();
+                tasks.push(tokio::spawn(serve_udp(socket, reply.clone(), counter)));
+            }
+            Err(e) => {
+                failed += 1;
+                eprintln!("{} {}/udp 無法綁定: {}", "✗".red(), port, e);
+            }
+        }
+    }
+    if counters.is_empty() {
+        return Err("沒有任何端口綁定成功".into());
+    }
+    let mut listening = format!("在 {} 上監聽 {} 個 socket", bind, counters.len());
+    if failed > 0 {
+        listening.push_str(&format!("，{} 個無法綁定", failed));
+    }
+    println!("{} (回覆 {:?}，按 Ctrl+C 結束)", listening, String::from_utf8_lossy(&reply[..reply.len() - 1]));
+
+    tokio::signal::ctrl_c().await?;
+    tasks.iter().for_each(|task| task.abort());
+    display_summary(&counters);
+    Ok(())
+}
+
+fn display_summary(counters: &Counters) {
+    println!("\n{}", "連線數".bold());
+    let mut total = 0;
+    for ((port, protocol), counter) in counters {
+        let count = counter.load(Ordering::Relaxed);
+        total += count;
+        let line = format!("  {:>5}/{}  {}", port, protocol.name(), count);
+        println!("{}", if count > 0 { line.normal() } else { line.dimmed() });
+    }
+    println!("共 {} 個連線", total);
+}
+
+// --expect-token 的結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenCheck {
+    // 回應含有預期的 token
+    Matched,
+    // 連得上但回應沒有 token：可能是中間設備或其他程式
+    Missing,
+}
+
+// 對可連線的端口再連線一次，讀取對方主動送出的內容並比對 token；回傳沒有 token 的端口數
+pub async fn verify(results: &mut BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>, plan: &ScanPlan, token: &str) -> usize {
+    let ports: BTreeSet<u16> = results.values().flat_map(|ports| ports.keys().map(|port| port.port)).collect();
+    let library = Arc::new(ProbeLibrary {
+        probes: vec![Probe {
+            name: "token".to_string(),
+            ports: ports.into_iter().collect(),
+            payload: Vec::new(),
+            read_size: token.len() + 1,
+            matchers: Vec::new(),
+            source: ProbeSource::Builtin,
+        }],
+        conflicts: Vec::new(),
+    });
+    let limit = plan.timeouts.default.max(TOKEN_TIMEOUT);
+    let semaphore = Arc::new(Semaphore::new(plan.concurrency.max(1)));
+    let mut handles = Vec::new();
+    for (host, host_results) in results.iter() {
+        for (port, result) in host_results {
+            if result.error.is_some() || !result.outbound {
+                continue;
+            }
+            let (host, port, prober, library, semaphore) = (*host, port.clone(), plan.prober.clone(), library.clone(), semaphore.clone());
+            handles.push(tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let banner = prober.banner(&library, host, port.port, limit).await;
+                (host, port, banner.map(|banner| banner.text))
+            }));
+        }
+    }
+    let token = token.to_string();
+    let mut missing = 0;
+    for handle in handles {
+        let Ok((host, port, text)) = handle.await else {
+            continue;
+        };
+        let Some(result) = results.get_mut(&host).and_then(|ports| ports.get_mut(&port)) else {
+            continue;
+        };
+        let matched = text.is_some_and(|text| text.contains(&token));
+        result.token = Some(if matched { TokenCheck::Matched } else { TokenCheck::Missing });
+        if !matched {
+            missing += 1;
+            let note = "回應中沒有預期的 token，可能連到中間設備而不是 listen".to_string();
+            result.note = Some(match result.note.take() {
+                Some(existing) => format!("{}；{}", existing, note),
+                None => note,
+            });
+        }
+    }
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpStream;
+    use crate::probes::Banner;
+    use crate::prober::fake::{Script, Scripted, ScriptedProber};
+    use crate::testutil::{by_host, host, scan, scripted_plan};
+
+    #[test]
+    fn tokens_are_single_printable_lines() {
+        assert_eq!(parse_token("abc-123"), Ok("abc-123".to_string()));
+        assert!(parse_token("").is_err());
+        assert!(parse_token("a\nb").is_err());
+        assert!(parse_token(&"x".repeat(65)).is_err());
+    }
+
+    #[tokio::test]
+    async fn tcp_and_udp_sockets_reply_with_the_token() {
+        let reply = Arc::new(b"token-1\n".to_vec());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let tcp = Arc::new(AtomicU64::new(0));
+        let server = tokio::spawn(serve_tcp(listener, reply.clone(), tcp.clone(), Arc::new(Semaphore::new(1))));
+        // 同時只處理一個連線時，連續的連線依序完成
+        for _ in 0..2 {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            let mut received = String::new();
+            stream.read_to_string(&mut received).await.unwrap();
+            assert_eq!(received, "token-1\n");
+        }
+        assert_eq!(tcp.load(Ordering::Relaxed), 2);
+        server.abort();
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let udp = Arc::new(AtomicU64::new(0));
+        let server = tokio::spawn(serve_udp(socket, reply, udp.clone()));
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.send_to(b"ping", addr).await.unwrap();
+        let mut buffer = [0u8; 64];
+        let (n, _) = tokio::time::timeout(Duration::from_secs(5), client.recv_from(&mut buffer)).await.unwrap().unwrap();
+        assert_eq!(&buffer[..n], b"token-1\n");
+        assert_eq!(udp.load(Ordering::Relaxed), 1);
+        server.abort();
+    }
+
+    fn banner(text: &str) -> Banner {
+        Banner { probe: "token".to_string(), service: None, version: None, text: text.to_string(), encoding: None, raw: None }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ports_without_the_token_are_flagged() {
+        let open = || Script::new(Scripted::Open, Duration::from_millis(5));
+        let prober = Arc::new(
+            ScriptedProber::new()
+                .with(host(1), 8000, open().banner(banner("token-1")))
+                .with(host(1), 8001, open().banner(banner("HTTP/1.1 400 Bad Request")))
+                .with(host(1), 8002, open()),
+        );
+        let plan = scripted_plan(&[host(1)], &[8000, 8001, 8002, 8003], 4, prober);
+        let mut results = by_host(scan(&plan).await);
+        assert_eq!(verify(&mut results, &plan, "token-1").await, 2);
+        let check = |port: u16| results[&host(1)].iter().find(|(info, _)| info.port == port).map(|(_, result)| result.token).unwrap();
+        assert_eq!(check(8000), Some(TokenCheck::Matched));
+        assert_eq!(check(8001), Some(TokenCheck::Missing));
+        assert_eq!(check(8002), Some(TokenCheck::Missing));
+        // 連不上的端口不檢查
+        assert_eq!(check(8003), None);
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::IsTerminal;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use colored::*;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use tokio::task::JoinHandle;
+use crate::{layout, probes, view, PortInfo, ScanResult};
+
+// 重新繪製區域的間隔；期間收到的結果合併成一次繪製
+const REDRAW_INTERVAL: Duration = Duration::from_millis(200);
+
+// 區域以外保留給進度列與提示的行數
+const RESERVED_ROWS: usize = 4;
+
+// 標準輸出與錯誤輸出 (進度列) 都是終端時才逐步顯示；否則維持掃描結束後一次顯示
+pub fn available() -> bool {
+    std::io::stdout().is_terminal() && std::io::stderr().is_terminal()
+}
+
+// 端口的逐步結果；provisional 代表只有連線結果，橫幅與服務辨識還在進行中
+struct Entry {
+    result: ScanResult,
+    provisional: bool,
+}
+
+// 已附加到進度列時的繪製狀態
+struct Region {
+    bar: ProgressBar,
+    ticker: JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct State {
+    entries: BTreeMap<IpAddr, HashMap<PortInfo, Entry>>,
+    // 上次繪製後有新結果，或終端大小改變
+    dirty: bool,
+    size: (usize, usize),
+    region: Option<Region>,
+}
+
+// 進度列下方逐步填入的結果區域：連線結果先以暫定狀態顯示，之後的階段完成後就地更新
+// 掃描結束時清除，接著顯示完整的結果
+pub struct LiveReport {
+    view: view::ResultView,
+    state: Mutex<State>,
+}
+
+impl std::fmt::Debug for LiveReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.lock();
+        f.debug_struct("LiveReport").field("attached", &state.region.is_some()).field("hosts", &state.entries.len()).finish()
+    }
+}
+
+impl LiveReport {
+    pub fn new(view: view::ResultView) -> Self {
+        LiveReport { view, state: Mutex::new(State::default()) }
+    }
+
+    // 掃描開始時把進度列與結果區域放進同一個繪製目標，並定期重新繪製
+    pub fn attach(self: &Arc<Self>, pb: &ProgressBar) {
+        let multi = MultiProgress::new();
+        multi.add(pb.clone());
+        let bar = multi.add(ProgressBar::new(0));
+        bar.set_style(ProgressStyle::with_template("{msg}").expect("static template"));
+        let live = self.clone();
+        let ticker = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(REDRAW_INTERVAL);
+            loop {
+                interval.tick().await;
+                live.redraw();
+            }
+        });
+        let mut state = self.lock();
+        state.entries.clear();
+        state.dirty = true;
+        state.region = Some(Region { bar, ticker });
+    }
+
+    // 清除區域；之後收到的結果不再顯示
+    pub fn detach(&self) {
+        let mut state = self.lock();
+        if let Some(region) = state.region.take() {
+            region.ticker.abort();
+            region.bar.finish_and_clear();
+        }
+        state.entries.clear();
+    }
+
+    // 連線階段的結果；之後的階段完成前先標示為暫定
+    pub fn provisional(&self, host: IpAddr, port: &PortInfo, result: &ScanResult) {
+        self.update(host, port, result, true);
+    }
+
+    // 端口的最終結果 (結果通道中的紀錄)
+    pub fn finalize(&self, host: IpAddr, port: &PortInfo, result: &ScanResult) {
+        self.update(host, port, result, false);
+    }
+
+    fn update(&self, host: IpAddr, port: &PortInfo, result: &ScanResult, provisional: bool) {
+        let mut state = self.lock();
+        if state.region.is_none() {
+            return;
+        }
+        state.entries.entry(host).or_default().insert(port.clone(), Entry { result: result.clone(), provisional });
+        state.dirty = true;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // 有變動或終端大小改變時重新組出區域內容
+    fn redraw(&self) {
+        let mut state = self.lock();
+        let size = terminal_size();
+        if !state.dirty && state.size == size {
+            return;
+        }
+        let text = self.render(&state.entries, size);
+        state.dirty = false;
+        state.size = size;
+        if let Some(region) = &state.region {
+            region.bar.set_message(text);
+        }
+    }
+
+    // 依 --group-by / --sort 排列；每行截到終端寬度，超過可用行數時只顯示前面的部分
+    fn render(&self, entries: &BTreeMap<IpAddr, HashMap<PortInfo, Entry>>, (rows, columns): (usize, usize)) -> String {
+        let mut lines = Vec::new();
+        let hosts = entries.len();
+        for (host, ports) in entries {
+            if hosts > 1 {
+                lines.push(format!("=== {} ===", host).bold().to_string());
+            }
+            let results: HashMap<&PortInfo, &ScanResult> = ports.iter().map(|(port, entry)| (port, &entry.result)).collect();
+            for (title, items) in view::arrange(results.into_iter(), &self.view) {
+                if let Some(title) = title {
+                    lines.push(format!("--- {} ---", title).bold().to_string());
+                }
+                for (port, result) in items {
+                    lines.push(line(port, result, ports[port].provisional, columns));
+                }
+            }
+        }
+        let limit = rows.saturating_sub(RESERVED_ROWS).max(1);
+        if lines.len() > limit {
+            let hidden = lines.len() - limit + 1;
+            lines.truncate(limit - 1);
+            lines.push(format!("… 還有 {} 行，掃描結束後顯示完整結果", hidden).dimmed().to_string());
+        }
+        lines.join("\n")
+    }
+}
+
+// 與結果列表相同的狀態欄位，加上橫幅、虛擬主機數與暫定標示
+fn line(port: &PortInfo, result: &ScanResult, provisional: bool, columns: usize) -> String {
+    let mut details = Vec::new();
+    if let Some(ms) = result.latency_ms {
+        details.push(format!("{:.1}ms", ms));
+    }
+    if let Some(banner) = &result.banner {
+        details.push(probes::describe(banner));
+    }
+    if !result.vhosts.is_empty() {
+        details.push(format!("{} 個虛擬主機", result.vhosts.len()));
+    }
+    let head = format!("Port {:5} ({:15}): ", port.port, port.service);
+    let label = crate::portline::status_label(result.directions, result.inbound, result.outbound);
+    let details = match details.is_empty() {
+        true => String::new(),
+        false => format!("  {}", details.join("  ")),
+    };
+    let tag = if provisional { "  (暫定)" } else { "" };
+    // 放得下時保留狀態顏色；過長時截斷成純文字，避免切斷顏色控制碼
+    let width = columns.saturating_sub(layout::display_width(tag) + 1);
+    let plain = format!("{}{}{}", head, &*label, details);
+    let text = match layout::display_width(&plain) <= width {
+        true => format!("{}{}{}", head, label, details.dimmed()),
+        false => layout::fit(&plain, width),
+    };
+    format!("{}{}", text, tag.dimmed())
+}
+
+fn terminal_size() -> (usize, usize) {
+    let (rows, columns) = console::Term::stderr().size();
+    (rows as usize, columns as usize)
+}
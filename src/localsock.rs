@@ -0,0 +1,291 @@
+use std::io;
+use colored::*;
+use schemars::JsonSchema;
+use serde::Serialize;
+use crate::anonymize::Anonymizer;
+
+// 本機 socket 的位址；TCP/UDP 端口以外的端點
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Endpoint {
+    // Unix domain socket；abstract 為 Linux 的抽象命名空間 (沒有檔案)
+    Unix { path: String, #[serde(rename = "abstract")] abstract_name: bool },
+    // Windows 具名管道 (\\.\pipe\名稱)
+    #[cfg_attr(not(windows), allow(dead_code))]
+    Pipe { name: String },
+}
+
+impl Endpoint {
+    pub fn label(&self) -> String {
+        match self {
+            Endpoint::Unix { path, abstract_name: true } => format!("@{}", path),
+            Endpoint::Unix { path, .. } => path.clone(),
+            Endpoint::Pipe { name } => format!(r"\\.\pipe\{}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SocketType {
+    Stream,
+    Datagram,
+    SeqPacket,
+    #[cfg_attr(not(windows), allow(dead_code))]
+    Pipe,
+}
+
+impl SocketType {
+    fn label(self) -> &'static str {
+        match self {
+            SocketType::Stream => "stream",
+            SocketType::Datagram => "dgram",
+            SocketType::SeqPacket => "seqpacket",
+            SocketType::Pipe => "pipe",
+        }
+    }
+}
+
+// 狀態：列在監聽中；--unix-connect 時另外確認是否接受連線
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum SocketState {
+    Listening,
+    Accepting,
+    // 連線失敗，例如沒有程序在 accept 或權限不足
+    NotAccepting { reason: String },
+}
+
+// 擁有者：檔案的使用者與持有 socket 的程序 (需要權限讀取其他程序的 /proc/PID/fd)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Owner {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub process: Option<String>,
+}
+
+impl Owner {
+    fn describe(&self) -> String {
+        let process = match (&self.process, self.pid) {
+            (Some(name), Some(pid)) => Some(format!("{} ({})", name, pid)),
+            (None, Some(pid)) => Some(format!("PID {}", pid)),
+            _ => None,
+        };
+        [self.user.clone(), process].into_iter().flatten().collect::<Vec<_>>().join(" / ")
+    }
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct LocalSocket {
+    pub endpoint: Endpoint,
+    #[serde(rename = "type")]
+    pub socket_type: SocketType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Owner>,
+    #[serde(flatten)]
+    pub state: SocketState,
+}
+
+// 列出監聽中的本機 socket；connect 時對每個 stream socket 嘗試連線
+pub fn enumerate(connect: bool) -> io::Result<Vec<LocalSocket>> {
+    let mut sockets = platform::listening()?;
+    if connect {
+        for socket in &mut sockets {
+            if let Some(result) = platform::connect(socket) {
+                socket.state = match result {
+                    Ok(()) => SocketState::Accepting,
+                    Err(e) => SocketState::NotAccepting { reason: e.to_string() },
+                };
+            }
+        }
+    }
+    sockets.sort_by(|a, b| a.endpoint.cmp(&b.endpoint));
+    Ok(sockets)
+}
+
+// --anonymize：路徑、使用者與程序名稱中學過的名稱換成假名
+pub fn anonymize(sockets: &mut [LocalSocket], anonymizer: &Anonymizer) {
+    for socket in sockets {
+        match &mut socket.endpoint {
+            Endpoint::Unix { path, .. } => *path = anonymizer.text(path),
+            Endpoint::Pipe { name } => *name = anonymizer.text(name),
+        }
+        if let Some(owner) = &mut socket.owner {
+            owner.user = owner.user.as_deref().map(|user| anonymizer.text(user));
+            owner.process = owner.process.as_deref().map(|process| anonymizer.text(process));
+        }
+    }
+}
+
+pub fn display(sockets: &[LocalSocket]) {
+    println!("\n{}", "=== 本機 Socket ===".bold());
+    if sockets.is_empty() {
+        println!("沒有監聽中的 Unix domain socket 或具名管道");
+        return;
+    }
+    for socket in sockets {
+        let state = match &socket.state {
+            SocketState::Listening => "監聽中".normal(),
+            SocketState::Accepting => "✓ 接受連線".green(),
+            SocketState::NotAccepting { reason } => format!("✗ {}", reason).red(),
+        };
+        let owner = socket.owner.as_ref().map(Owner::describe).filter(|o| !o.is_empty()).unwrap_or_else(|| "?".to_string());
+        println!("{:9} {:50} {:24} {}", socket.socket_type.label(), socket.endpoint.label(), owner, state);
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::collections::HashMap;
+    use std::ffi::CStr;
+    use std::fs;
+    use std::io;
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::fs::MetadataExt;
+    use std::os::unix::net::{SocketAddr, UnixStream};
+    use super::{Endpoint, LocalSocket, Owner, SocketState, SocketType};
+
+    // __SO_ACCEPTCON：socket 處於 listen 狀態
+    const ACCEPT_CONNECTIONS: u32 = 0x10000;
+
+    // /proc/net/unix：Num RefCount Protocol Flags Type St Inode Path
+    pub fn listening() -> io::Result<Vec<LocalSocket>> {
+        let text = fs::read_to_string("/proc/net/unix")?;
+        let owners = socket_owners();
+        let mut sockets = Vec::new();
+        for line in text.lines().skip(1) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [_, _, _, flags, kind, _, inode, path, ..] = fields.as_slice() else {
+                continue;
+            };
+            let flags = u32::from_str_radix(flags, 16).unwrap_or_default();
+            let socket_type = match *kind {
+                "0001" => SocketType::Stream,
+                "0002" => SocketType::Datagram,
+                "0005" => SocketType::SeqPacket,
+                _ => continue,
+            };
+            // 資料包 socket 沒有 listen，綁定了路徑就算在等待資料
+            if socket_type != SocketType::Datagram && flags & ACCEPT_CONNECTIONS == 0 {
+                continue;
+            }
+            let endpoint = match path.strip_prefix('@') {
+                Some(name) => Endpoint::Unix { path: name.to_string(), abstract_name: true },
+                None => Endpoint::Unix { path: path.to_string(), abstract_name: false },
+            };
+            let mut owner = inode.parse::<u64>().ok().and_then(|inode| owners.get(&inode).cloned()).unwrap_or_default();
+            if !path.starts_with('@') {
+                owner.user = fs::metadata(path).ok().and_then(|meta| user_name(meta.uid()));
+            }
+            sockets.push(LocalSocket {
+                endpoint,
+                socket_type,
+                owner: (owner != Owner::default()).then_some(owner),
+                state: SocketState::Listening,
+            });
+        }
+        Ok(sockets)
+    }
+
+    // 掃描 /proc/PID/fd 中的 socket:[inode]；沒有權限的程序略過
+    fn socket_owners() -> HashMap<u64, Owner> {
+        let mut owners = HashMap::new();
+        let Ok(processes) = fs::read_dir("/proc") else {
+            return owners;
+        };
+        for process in processes.flatten() {
+            let Some(pid) = process.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) else {
+                continue;
+            };
+            let Ok(fds) = fs::read_dir(process.path().join("fd")) else {
+                continue;
+            };
+            let name = fs::read_to_string(process.path().join("comm")).ok().map(|comm| comm.trim().to_string());
+            for fd in fds.flatten() {
+                let Ok(target) = fs::read_link(fd.path()) else {
+                    continue;
+                };
+                let inode = target
+                    .to_str()
+                    .and_then(|t| t.strip_prefix("socket:["))
+                    .and_then(|t| t.strip_suffix(']'))
+                    .and_then(|t| t.parse::<u64>().ok());
+                if let Some(inode) = inode {
+                    owners.entry(inode).or_insert_with(|| Owner { user: None, pid: Some(pid), process: name.clone() });
+                }
+            }
+        }
+        owners
+    }
+
+    fn user_name(uid: u32) -> Option<String> {
+        let mut buffer = vec![0 as libc::c_char; 4096];
+        let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut found: *mut libc::passwd = std::ptr::null_mut();
+        let status = unsafe { libc::getpwuid_r(uid, &mut entry, buffer.as_mut_ptr(), buffer.len(), &mut found) };
+        if status != 0 || found.is_null() {
+            return Some(uid.to_string());
+        }
+        Some(unsafe { CStr::from_ptr(entry.pw_name) }.to_string_lossy().into_owned())
+    }
+
+    // 只對 stream socket 嘗試連線；連上後立即關閉
+    pub fn connect(socket: &LocalSocket) -> Option<io::Result<()>> {
+        if socket.socket_type != SocketType::Stream {
+            return None;
+        }
+        let Endpoint::Unix { path, abstract_name } = &socket.endpoint else {
+            return None;
+        };
+        let address = match abstract_name {
+            true => SocketAddr::from_abstract_name(path.as_bytes()),
+            false => SocketAddr::from_pathname(path),
+        };
+        Some(address.and_then(|address| UnixStream::connect_addr(&address)).map(drop))
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::fs;
+    use std::io;
+    use super::{Endpoint, LocalSocket, SocketState, SocketType};
+
+    // \\.\pipe\ 目錄列出所有具名管道；無法判斷擁有者
+    pub fn listening() -> io::Result<Vec<LocalSocket>> {
+        Ok(fs::read_dir(r"\\.\pipe\")?
+            .flatten()
+            .map(|entry| LocalSocket {
+                endpoint: Endpoint::Pipe { name: entry.file_name().to_string_lossy().into_owned() },
+                socket_type: SocketType::Pipe,
+                owner: None,
+                state: SocketState::Listening,
+            })
+            .collect())
+    }
+
+    // 以一般檔案開啟管道即是連線
+    pub fn connect(socket: &LocalSocket) -> Option<io::Result<()>> {
+        let Endpoint::Pipe { name } = &socket.endpoint else {
+            return None;
+        };
+        Some(fs::OpenOptions::new().read(true).write(true).open(format!(r"\\.\pipe\{}", name)).map(drop))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+mod platform {
+    use std::io;
+    use super::LocalSocket;
+
+    pub fn listening() -> io::Result<Vec<LocalSocket>> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "此平台不支援列出本機 socket"))
+    }
+
+    pub fn connect(_socket: &LocalSocket) -> Option<io::Result<()>> {
+        None
+    }
+}
@@ -1,20 +1,186 @@
-use std::collections::{HashMap, HashSet};
-use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener};
-use std::time::Duration;
-use tokio::time::timeout;
-use tokio::net::TcpSocket;
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr};
 use std::error::Error;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use colored::*;
-use indicatif::{ProgressBar, ProgressStyle};
-use tokio::sync::OnceCell;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+mod adaptive;
+mod ephemeral;
+mod alerts;
+mod archive;
+mod assertions;
+mod anonymize;
+mod attribution;
+mod audit;
+mod axfr;
+mod backoff;
+mod bench;
+mod benchmark;
+mod bisect;
+mod blobs;
+mod bundles;
+mod capability;
+mod caps;
+mod captive;
+mod charset;
+mod clock;
+mod checks;
+mod cli;
+mod closure;
+mod cloud;
+mod compare;
+mod confidence;
+mod config;
+mod conntrack;
+mod dependency;
+mod context;
+mod direction;
+mod dns;
+mod esbulk;
+mod examples;
+mod expand;
+mod errors;
+mod egress;
+mod eventlog;
+mod fingerprints;
+mod firewall;
+mod geosanity;
+mod historydiff;
+mod privhelper;
+mod grade;
+mod groups;
+mod heartbeat;
+mod hooks;
+mod httpproxy;
+mod httpver;
+mod keyboard;
+mod icmp;
+mod jump;
+mod identity;
+mod init;
+mod knock;
+mod layout;
+mod limits;
+mod listen;
+mod live;
+mod localsock;
+mod manifest;
+mod matrix;
+mod menu;
+mod merge;
+mod migrate;
+mod metadata;
+mod monitor;
+mod natpmp;
+mod netblocks;
+mod netlimit;
+mod notify;
+mod osguess;
+mod output;
+mod pager;
+mod pcap;
+mod pipeline;
+mod plan;
+mod pool;
+mod policy;
+mod portdb;
+mod portline;
+mod portspec;
+mod probes;
+mod prober;
+mod progressfile;
+mod profile;
+mod quick;
+mod quickcheck;
+mod ranges;
+mod recommend;
+mod render;
+mod report;
+mod resources;
+mod restarts;
+mod route;
+mod resume;
+mod scanid;
+mod scanner;
+mod samples;
+mod sanity;
+mod selftest;
+mod session;
+mod settings;
+mod share;
+mod shodan;
+mod signing;
+mod socks;
+mod srcport;
+mod status;
+mod srv;
+mod startup;
+mod strict;
+mod stats;
+mod syn;
+mod systemd;
+mod tags;
+mod tarpit;
+mod targets;
+#[cfg(test)]
+mod testutil;
+mod threats;
+mod throughput;
+mod timefmt;
+mod timeouts;
+mod tor;
+mod transcript;
+mod trend;
+mod units;
+mod vars;
+mod verify;
+mod vhost;
+mod view;
+mod watch;
+mod whois;
+mod wol;
+mod zone;
+
+use cli::{ArchiveMember, AuditCommand, Cli, Command, Concurrency, ConfigCommand, ErrorsCommand, ExamplesCommand, HistoryCommand, ProbesCommand, TemplatesCommand};
+use errors::{ErrorCode, WithCode};
+use context::{ExternalIp, ScanContext};
+use output::OutputFormat;
+use pipeline::Stage;
+use privhelper::Channel;
+use scanner::ScanPlan;
+use share::ShareLine;
+use targets::TargetSpec;
+use timeouts::Timeouts;
+
+// 未指定目標時，出站測試連線的公共 DNS 伺服器 (OpenDNS)
+const OUTBOUND_PROBE_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::new(208, 67, 222, 222));
+
+// 掃描結果通道容量，寫入端落後時掃描端會在此等待
+const RESULT_CHANNEL_CAPACITY: usize = 1024;
 
 
 // 定義port
-#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
 struct PortInfo {
     port: u16,
     service: String,
     category: String,
+    // 設定檔 [ports] 與 [tags] 的標籤，例如 env:prod；較早的紀錄沒有此欄位
+    #[serde(default)]
+    tags: Vec<String>,
+    // --group 選用的服務群組
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+    // --srv 查詢到此端口的 SRV 記錄與目標
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    srv: Vec<srv::SrvOrigin>,
 }
 
 impl PortInfo {
@@ -23,15 +189,116 @@ impl PortInfo {
             port,
             service: service.to_string(),
             category: category.to_string(),
+            tags: Vec::new(),
+            group: None,
+            srv: Vec::new(),
         }
     }
 }
 
 // 定義掃描結果結構
-#[derive(Debug)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 struct ScanResult {
+    // --no-inbound / --no-outbound 時未測試的方向不輸出 (見 direction::serialize_tested)
+    #[serde(default)]
+    #[schemars(skip_serializing_if = "direction::maybe_omitted")]
     inbound: bool,
+    #[serde(default)]
+    #[schemars(skip_serializing_if = "direction::maybe_omitted")]
     outbound: bool,
+    // 這個結果測試的方向；讀回記錄時依此判斷未測試的欄位
+    #[serde(default, skip_serializing_if = "direction::Directions::is_both")]
+    directions: direction::Directions,
+    // 入站測試綁定的各位址 (本機介面位址、0.0.0.0) 與各自的結果
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    bind: Vec<scanner::BindTest>,
+    // Web 端口的各虛擬主機探測結果
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    vhosts: Vec<vhost::VhostResult>,
+    // 出站連線成功時的連線時間
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_ms: Option<f64>,
+    // 對結果的補充說明 (例如 Tor 出口節點可能封鎖)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+    // 出站失敗時路由器或目標回報的 ICMP 錯誤
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icmp: Option<icmp::IcmpError>,
+    // --banners 的橫幅探測結果
+    #[serde(skip_serializing_if = "Option::is_none")]
+    banner: Option<probes::Banner>,
+    // --syn 半開放掃描的判斷 (開放 / 關閉 / 被過濾)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    syn: Option<syn::SynState>,
+    // 掃描端本身的錯誤；此時 outbound 不代表端口狀態
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<limits::ScanError>,
+    // 出站連線失敗的方式，用於推論目標是否有防火牆
+    #[serde(skip_serializing_if = "Option::is_none")]
+    failure: Option<closure::Failure>,
+    // 綜合可達性、延遲與服務檢查的健康等級
+    #[serde(skip_serializing_if = "Option::is_none")]
+    grade: Option<grade::PortGrade>,
+    // 可疑結果重新探測後的結論
+    #[serde(skip_serializing_if = "Option::is_none")]
+    verification: Option<verify::Verification>,
+    // 結果的信心分數 (0-1)，計算方式見 confidence::score
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confidence: Option<f64>,
+    // 與結果相同的額外探測次數
+    #[serde(default, skip_serializing_if = "confidence::unconfirmed")]
+    confirmations: u8,
+    // --tcp-caps 的 TFO / ECN / TCP 選項探測
+    #[serde(skip_serializing_if = "Option::is_none")]
+    capabilities: Option<caps::TcpCaps>,
+    // --http-versions 的 ALPN 與 QUIC 探測
+    #[serde(skip_serializing_if = "Option::is_none")]
+    http_versions: Option<httpver::HttpVersions>,
+    // --fingerprint-db 記錄的 SSH 主機金鑰、TLS 憑證與 HTTP Server 標頭
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    fingerprints: Vec<fingerprints::Fingerprint>,
+    // --route-check 的本機位址與路由介面
+    #[serde(skip_serializing_if = "Option::is_none")]
+    route: Option<route::RouteInfo>,
+    // 來自 --resume 續掃檔的先前結果
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    resumed: bool,
+    // --throughput-test 的頻寬測試 (額外的資料傳輸)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    throughput: Option<throughput::Throughput>,
+    // --samples 的延遲百分位數
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    samples: Option<samples::LatencySamples>,
+    // 掃描中途按 x 取消的主機；該主機只有部分端口的結果
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    cancelled: bool,
+    // 結果相關的錯誤代碼 (入站綁定失敗、出站失敗的原因)，代碼表見 errors list
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    codes: Vec<errors::ErrorCode>,
+    // 設定檔 [health.<端口>] 的健康檢查結果 (健康 / 不健康 / 未開放)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    health: Option<checks::health::Health>,
+    // --trend：結果資料庫中最近幾次的出站狀態 (由舊到新)；沒有紀錄時省略
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    trend: Vec<bool>,
+    // 出站可連線的 Web 端口疑似被透明代理或強制門戶代為接受 (note 附上說明)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    intercepted: bool,
+    // --test-source-ports：依來源端口順序的連線結果
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    source_ports: Vec<srcport::SourcePortResult>,
+    // --systemd：本機目標的端口對應的 socket unit 與啟用狀態
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    systemd: Option<systemd::SocketUnit>,
+    // --conntrack：判斷為只能發送，但連線追蹤表中這個端口已建立的入站 TCP 連線數
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    conntrack: Option<u32>,
+    // 設定檔 [[dependencies]]：無法連線的上游依賴；連線失敗可能只是上游造成的
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    upstream: Option<dependency::Upstream>,
+    // --expect-token：回應是否含有 listen 回覆的 token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    token: Option<listen::TokenCheck>,
 }
 
 // 定義常用port和服務
@@ -96,156 +363,2034 @@ fn get_common_ports() -> Vec<PortInfo> {
 }
     
 
-// 主函數
+// 原始 socket 輔助程序以提升的權限啟動：不解析設定檔也不啟動 tokio，開啟 socket 後即降權
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some(privhelper::HELPER_ARG) {
+        privhelper::serve(&args[2..]);
+    }
+    start();
+}
+
+// 主函數；錯誤以代碼顯示，並以代碼對應的結束代碼結束
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    print_header();
-    show_network_info().await?;
-    let scan_results = perform_scan().await;
-    display_results(&scan_results);
-    
-    println!("\n按 'q' 後Enter 離開程序...");
-    
-    let mut buffer = String::new();
-    while let Ok(_) = std::io::stdin().read_line(&mut buffer) {
-        if buffer.trim().to_lowercase() == "q" {
-            break;
+async fn start() {
+    // 參數解析失敗時還不知道是否要求 --json，以文字顯示；是否關閉狀態行從原始參數判斷
+    let status = status::StatusLine::new(!std::env::args().any(|arg| arg == "--no-status-line"));
+    let (cli, layers) = match settings::parse() {
+        Ok(parsed) => parsed,
+        Err(e) => status.exit(errors::report(e.as_ref(), false)),
+    };
+    let json = cli.json;
+    if cli.no_status_line {
+        status.disable();
+    }
+    match run(cli, layers, &status).await {
+        Ok(()) => status.finish(None, 0),
+        Err(e) => status.exit(errors::report(e.as_ref(), json)),
+    }
+}
+
+async fn run(mut cli: Cli, layers: settings::Layers, status: &status::StatusLine) -> Result<(), Box<dyn Error>> {
+    timefmt::set_format(cli.time_format);
+    let mut quick_mode = false;
+    // 子命令不是掃描，沒有狀態行；quick 照一般掃描執行
+    if !matches!(cli.command, None | Some(Command::Quick { .. })) {
+        status.disable();
+    }
+    match cli.command.take() {
+        Some(Command::Errors { action: ErrorsCommand::List { lang, json } }) => return errors::list(lang, json),
+        Some(Command::Schema { kind }) => {
+            println!("{}", serde_json::to_string_pretty(&report::schema(kind))?);
+            return Ok(());
+        }
+        Some(Command::SelfTest) => return selftest::run().await,
+        Some(Command::Bench { open, closed, blackhole, concurrency, timeout, output }) => {
+            return bench::run(open, closed, blackhole, &concurrency, &timeout, output.as_deref()).await
+        }
+        Some(Command::Listen { ports, bind, reply, udp }) => return listen::run(&ports, bind, &reply, udp).await,
+        Some(Command::Check { target, timeout, quiet, banner }) => return quickcheck::run(&target, timeout, quiet, banner).await,
+        Some(Command::Ports { action }) => return portdb::run(&action, get_common_ports()),
+        Some(Command::Open { archive, show }) => return archive::run(&archive, show, &layout::Layout::detect(cli.width)),
+        Some(Command::History { action: Some(HistoryCommand::Vacuum { db }), .. }) => return blobs::run_vacuum(&db),
+        Some(Command::History { action: None, db, host, diff, since, out }) => {
+            // 沒有子命令時 clap 已要求兩者
+            let (db, host) = db.zip(host).ok_or("需要結果資料庫與主機")?;
+            return match since {
+                Some(since) => historydiff::run(&db, &host, since, out.as_deref()),
+                None => identity::run_history(&db, &host, diff),
+            }
+        }
+        Some(Command::Merge { reports, out }) => return merge::run(&reports, out.as_deref()),
+        Some(Command::Init { options }) => return init::run(options, cli.config.as_deref()).await,
+        Some(Command::Keygen { out, force }) => return signing::keygen(out.as_deref(), force),
+        Some(Command::VerifyReport { report, key }) => return signing::verify_report(&report, key.as_deref()),
+        Some(Command::Config { action: ConfigCommand::Show }) => {
+            settings::display(&layers);
+            return Ok(());
+        }
+        Some(Command::Config { action: ConfigCommand::Migrate { dry_run } }) => return migrate::run(cli.config.as_deref(), dry_run),
+        Some(Command::Examples { action }) => match action {
+            None => {
+                examples::list();
+                return Ok(());
+            }
+            Some(ExamplesCommand::Check) => return examples::check(),
+            Some(ExamplesCommand::Run { name, target, yes }) => return examples::run(&name, target.as_deref(), yes),
+        },
+        Some(Command::Audit { action: AuditCommand::Verify { log } }) => {
+            let path = match log {
+                Some(path) => path,
+                None => audit::configured_path(&config::load(cli.config.as_deref())?.safety).ok_or("找不到設定目錄，請指定稽核紀錄檔")?,
+            };
+            return audit::run_verify(&path);
+        }
+        Some(Command::Probes { action: ProbesCommand::List }) => {
+            let dir = probes::default_dir();
+            probes::display_list(&probes::load(dir.as_deref())?, dir.as_deref());
+            return Ok(());
+        }
+        Some(Command::Templates { action }) => {
+            let dir = policy::templates_dir();
+            let vars = vars::Variables::new(&cli.set);
+            match action {
+                TemplatesCommand::List => policy::display_list(&policy::templates(dir.as_deref(), &vars)?, dir.as_deref()),
+                TemplatesCommand::Show { name } => policy::display_template(&policy::find_template(&name, &vars)?),
+            }
+            return Ok(());
+        }
+        // quick 只是在選項上套用預設，之後照一般掃描執行
+        Some(Command::Quick { ports }) => {
+            quick::preset(&mut cli, ports);
+            quick_mode = true;
         }
-        buffer.clear();
+        None => {}
     }
-    
+
+    if let Some(path) = &cli.transcript {
+        transcript::start(path)?;
+    }
+    // 掃描開始前可能拖慢啟動的步驟都可以略過，耗時記錄在 startup 中
+    let mut startup = startup::Startup::default();
+    let config = startup
+        .time("設定檔", !cli.no_config, || config::load(cli.config.as_deref()))
+        .transpose()
+        .code(ErrorCode::ConfigInvalid)?
+        .unwrap_or_default();
+    if quick_mode && cli.ports.is_none() {
+        let ports = config.quick.ports.clone();
+        cli.ports = Some(ports.ok_or_else(|| {
+            errors::coded(ErrorCode::InvalidOptions, "quick 需要端口：portscanner quick 5432,6379 或在設定檔 [quick] 指定 ports")
+        })?);
+    }
+    // 掃描前先讀取私鑰，金鑰有誤時不必等掃描結束才失敗
+    let signing_key = cli.sign.as_deref().map(signing::load_signing_key).transpose()?;
+    let dns = Arc::new(dns::DnsCache::from_config(&config.dns));
+    alerts::validate(&config.watch.alerts)?;
+    let recommendation_rules = recommend::Rules::build(&config.recommendations)?;
+    let service_groups = groups::Groups::build(&config.groups)?;
+    let service_bundles = bundles::Bundles::build(&config.bundles)?;
+    let mut result_view = view::ResultView {
+        group_by: cli.group_by,
+        sort: cli.sort,
+        expand_groups: cli.expand_groups,
+        low_confidence: cli.min_confidence.unwrap_or(confidence::LOW_CONFIDENCE),
+        hosts: Arc::default(),
+        threats: Arc::new(threats::ThreatTable::build(&config.threats)?),
+        cloud: cloud::CloudContext { target: cli.cloud, scanner: None },
+        layout: layout::Layout::detect(cli.width),
+        glyphs: trend::Glyphs::detect(),
+    };
+    // 出站政策只檢查出站連線
+    let directions = direction::Directions::of(cli.no_inbound || cli.egress_policy.is_some(), cli.no_outbound);
+    let mut run_metadata = metadata::RunMetadata::collect(&cli.annotate);
+    if let Some(scan_id) = &cli.scan_id {
+        run_metadata.scan_id = scan_id.clone();
+    }
+    status.scan_id(&run_metadata.scan_id);
+    run_metadata.directions = directions;
+    run_metadata.authorized_by = cli.authorized_by.clone();
+
+    // --template / --policy：未指定 --ports 時只掃描政策涵蓋的端口
+    let vars = vars::Variables::new(&cli.set);
+    let mut policy = match (&cli.template, &cli.policy) {
+        (Some(name), _) => Some(policy::find_template(name, &vars)?),
+        (None, Some(path)) => Some(policy::Policy::load(path, &vars)?),
+        (None, None) => None,
+    };
+    // 政策以群組名稱指定的預期，展開成群組的成員端口
+    let mut selected_groups = cli.group.clone();
+    if let Some(policy) = &mut policy {
+        policy.resolve_groups(&service_groups)?;
+        selected_groups.extend(policy.group_names());
+    }
+    let service_manifest = cli.manifest.as_deref().map(manifest::Manifest::load).transpose()?;
+    // --shodan：API 金鑰只從環境變數讀取
+    let shodan_key = cli
+        .shodan
+        .then(|| std::env::var(shodan::API_KEY_ENV).ok().filter(|key| !key.trim().is_empty()))
+        .map(|key| key.ok_or_else(|| errors::coded(ErrorCode::InvalidOptions, format!("--shodan 需要以環境變數 {} 提供 API 金鑰", shodan::API_KEY_ENV))))
+        .transpose()?;
+    // --replay：目標、端口與本機環境都來自記錄檔，掃描前後會連線的檢查都略過
+    let replay = cli.replay.as_deref().map(session::Session::load).transpose().code(ErrorCode::InvalidOptions)?;
+    if let Some(session) = &replay {
+        cli.target = session.header.target.clone();
+        cli.external_ip = session.environment.external_ip;
+        cli.no_external_ip = session.environment.external_ip.is_none();
+        cli.no_network_info = true;
+        cli.no_sanity_check = true;
+        cli.no_captive_check = true;
+        cli.no_tarpit_check = true;
+        cli.no_geo_sanity = true;
+        cli.no_port_pressure = true;
+    }
+    // --egress-policy：目標與端口都來自政策，掃描時只探測政策中的組合
+    let egress_policy = cli.egress_policy.as_deref().map(|path| egress::EgressPolicy::load(path, &vars)).transpose()?;
+    if let Some(policy) = &egress_policy {
+        cli.target = Some(policy.target_spec());
+    }
+    let port_spec = cli
+        .ports
+        .clone()
+        .or_else(|| egress_policy.as_ref().map(egress::EgressPolicy::port_spec))
+        .or_else(|| policy.as_ref().map(policy::Policy::port_spec))
+        .or_else(|| service_manifest.as_ref().filter(|m| m.declares_hosts()).map(manifest::Manifest::port_spec));
+    // --group 的成員端口加到 --ports 之後；只指定 --group 時只掃描群組端口
+    let port_spec = match (port_spec, service_groups.port_spec(&cli.group)?) {
+        (Some(ports), Some(members)) => Some(format!("{},{}", ports, members)),
+        (ports, members) => ports.or(members),
+    };
+    // --srv：SRV 記錄的目標與端口併入掃描；明確指定的端口保留原本的服務名稱
+    let srv_discovery = match cli.srv.is_empty() {
+        true => None,
+        false => Some(srv::discover(&cli.srv, Arc::new(srv::SystemResolver), cli.expand_concurrency).await),
+    };
+    let (port_spec, explicit_ports) = match &srv_discovery {
+        Some(discovery) => {
+            let explicit = port_spec.as_deref().map(portspec::parse_list).transpose().code(ErrorCode::InvalidOptions)?;
+            cli.target = discovery.target_spec(cli.target.as_deref());
+            if cli.target.is_none() {
+                srv::display(discovery, None, true);
+                return Err(errors::coded(ErrorCode::NoTargets, "SRV 記錄沒有可掃描的目標"));
+            }
+            (discovery.port_spec(port_spec.as_deref()), explicit.unwrap_or_default())
+        }
+        None => (port_spec, Default::default()),
+    };
+    let tag_rules = tags::TagRules::build(config.ports, &config.tags)?;
+    let block_names = netblocks::BlockNames::parse(&config.blocks)?;
+    let port_database = startup
+        .time("端口資料庫", !cli.no_port_db, || portdb::PortDatabase::load(portdb::default_path().as_deref()))
+        .transpose()?
+        .unwrap_or_default()
+        .merge(get_common_ports());
+
+    // 啟動時就載入並驗證探測定義，錯誤的檔案不會等到掃描中才發現
+    // 政策有橫幅斷言時自動探測橫幅
+    let probe_library = if cli.banners || policy.as_ref().is_some_and(policy::Policy::needs_banners) {
+        let library = probes::load(probes::default_dir().as_deref())?;
+        if !cli.json {
+            for conflict in &library.conflicts {
+                println!("{}", format!("探測: {}", conflict).yellow());
+            }
+        }
+        Some(Arc::new(library))
+    } else {
+        None
+    };
+    let sanity_anchors = if cli.no_sanity_check { None } else { Some(sanity::anchors(&config.sanity)?) };
+    let captive_endpoints = if cli.no_captive_check { None } else { Some(captive::endpoints(&config.captive)?) };
+    // --format-template 也在啟動時驗證
+    let text_template = cli.format_template.as_deref().map(render::TextTemplate::load).transpose()?;
+    let eventlog = if cli.eventlog { Some(eventlog::EventLog::open(&run_metadata.scan_id)?) } else { None };
+
+    // 並發數量不能超過檔案描述符上限，否則探測會因 EMFILE 失敗
+    if cli.raise_nofile {
+        match limits::raise_nofile() {
+            Ok(limit) if !cli.json => println!("檔案描述符上限: {}", limit),
+            Ok(_) => {}
+            Err(e) => eprintln!("{}", e.yellow()),
+        }
+    }
+    // 自動模式以上限為探測許可數，實際並發由控制器決定；上限被調降是預期的，不提示
+    let requested = match cli.concurrency {
+        Concurrency::Fixed(n) => n,
+        Concurrency::Auto => adaptive::MAX,
+    };
+    let (concurrency, warning) = limits::cap_concurrency(requested);
+    if let (Some(warning), Concurrency::Fixed(_)) = (warning, cli.concurrency) {
+        eprintln!("{}", warning.yellow());
+    }
+
+    // --anonymize：之後顯示與寫出的位址、主機名稱都換成假名
+    let anonymizer = cli.anonymize.then(anonymize::Anonymizer::random).transpose()?;
+    let expand_options = expand::ExpandOptions {
+        wordlist: cli.subdomain_list.as_deref(),
+        axfr: cli.axfr,
+        axfr_server: cli.axfr_server.as_deref(),
+        concurrency: cli.expand_concurrency,
+        quiet: cli.json || cli.compact || text_template.is_some(),
+        anonymizer: anonymizer.as_ref(),
+        dns: &dns,
+    };
+    if let Some(discovery) = &srv_discovery {
+        srv::display(discovery, anonymizer.as_ref(), expand_options.quiet);
+    }
+    let mut zones = zone::Zones::default();
+    let (targets, resolve_failures, mut expansions) = match (&replay, &cli.target) {
+        (Some(session), _) => (session.header.targets(), Vec::new(), Vec::new()),
+        (None, Some(spec)) => expand::parse_targets(spec, !cli.no_resolve, &expand_options, &mut zones).await?,
+        (None, None) => (
+            vec![TargetSpec::Host {
+                name: OUTBOUND_PROBE_ADDR.to_string(),
+                addr: OUTBOUND_PROBE_ADDR,
+            }],
+            Vec::new(),
+            Vec::new(),
+        ),
+    };
+    for failure in &resolve_failures {
+        if let Some(anonymizer) = &anonymizer {
+            anonymizer.learn(&failure.name);
+        }
+        eprintln!("{}", format!("{}，略過此目標", anonymize::show(anonymizer.as_ref(), &failure.error)).yellow());
+    }
+    let exclusions = targets::load_exclusions(cli.exclude.as_deref(), cli.exclude_file.as_deref())?;
+    let (targets, excluded) = targets::apply_exclusions(targets, &exclusions);
+    if targets.is_empty() {
+        return Err(errors::coded(ErrorCode::NoTargets, "所有目標都被排除"));
+    }
+    run_metadata.excluded = excluded;
+    // 不可能有服務的目標位址直接拒絕；--force 時改為提醒
+    let (rejected, warnings) = targets::validate_scopes(&targets, &zones);
+    if !rejected.is_empty() && !cli.force {
+        return Err(errors::coded(
+            ErrorCode::InvalidTarget,
+            format!("不合理的目標 (確定要掃描請加上 --force)：{}", rejected.join("；")),
+        ));
+    }
+    run_metadata.target_warnings = rejected.into_iter().chain(warnings).collect();
+    result_view.hosts = Arc::new(view::HostLabels {
+        zones: zones.clone(),
+        names: expand::names(&expansions),
+    });
+    // 未指定 --target 時使用內建的出站測試位址，不受限制
+    let guardrail = match cli.target {
+        Some(_) => targets::guardrail_violations(
+            &targets,
+            config.safety.max_hosts,
+            cli.allow_large,
+            cli.allow_public || cli.authorized_by.is_some(),
+        ),
+        None => Vec::new(),
+    };
+    let mut ports = match &replay {
+        Some(session) => session.header.ports.clone(),
+        None => select_ports(port_database, port_spec.as_deref(), &tag_rules, &cli.tag).code(ErrorCode::InvalidOptions)?,
+    };
+    if let Some(discovery) = &srv_discovery {
+        discovery.annotate(&mut ports, &explicit_ports);
+    }
+    service_groups.assign(&mut ports, &selected_groups);
+    for warning in service_bundles.unscanned_warnings(&ports) {
+        eprintln!("{}", warning.yellow());
+    }
+    let context_at = Instant::now();
+    let mut context = ScanContext::from_cli(&cli, zones.clone(), anonymizer, dns.clone());
+    context.scan_id = run_metadata.scan_id.clone();
+    if let Some(session) = &replay {
+        session.environment.apply(&mut context);
+    }
+    let context = Arc::new(context);
+    startup.record("網路資訊", match cli.no_network_info {
+        true => startup::Step::Skipped,
+        false => startup::Step::Took(context_at.elapsed()),
+    });
+    let mut plan = ScanPlan {
+        targets,
+        ports,
+        concurrency,
+        per_host_concurrency: cli.per_host_concurrency,
+        per_net: cli.per_net_concurrency.map(|limit| Arc::new(netlimit::NetLimiter::new(limit))),
+        timeouts: Timeouts::build(
+            &config.timeouts,
+            cli.timeout.or(cli.tor.then_some(tor::TOR_TIMEOUT)),
+            cli.timeout_override.as_deref(),
+        )?,
+        vhosts: cli.vhost.clone(),
+        knock: match &cli.knock {
+            Some(spec) => {
+                let protected = match &cli.knock_protected {
+                    Some(ports) => portspec::parse_list(ports).code(ErrorCode::InvalidOptions)?.into_iter().collect(),
+                    None => Vec::new(),
+                };
+                Some(Arc::new(knock::KnockPlan::new(knock::parse_sequence(spec).code(ErrorCode::InvalidOptions)?, cli.knock_delay, protected)))
+            }
+            None => None,
+        },
+        proxy: None,
+        profiler: None,
+        icmp: None,
+        probes: probe_library,
+        syn: None,
+        grading: config.grading,
+        completed: Default::default(),
+        hooks: hooks::HookRunner::new(
+            cli.on_open.clone(),
+            cli.on_change.clone(),
+            cli.hook_timeout,
+            cli.hook_concurrency,
+            cli.verbose,
+        )
+        .map(Arc::new),
+        adaptive: (cli.concurrency == Concurrency::Auto)
+            .then(|| Arc::new(adaptive::AdaptiveLimit::new(concurrency, cli.verbose))),
+        context: context.clone(),
+        prober: match &replay {
+            Some(session) => session.prober.clone(),
+            None => Arc::new(prober::NetProber::new(context.clone(), match cli.no_socket_reuse {
+                true => None,
+                false => pool::SocketPool::open(concurrency),
+            })),
+        },
+        // 終端互動的單次掃描才接受按鍵；watch 與 bisect 會重複掃描
+        control: (!cli.json && !cli.compact && text_template.is_none() && cli.watch.is_none() && cli.bisect.is_none() && keyboard::available())
+            .then(|| Arc::new(keyboard::ScanControl::default())),
+        route: match cli.route_check || cli.expect_route.is_some() {
+            true => Some(Arc::new(route::RouteCheck::new(cli.expect_route.clone())?)),
+            false => None,
+        },
+        pipeline: pipeline::Pipeline::new(cli.stages),
+        // 掃描結束後一次顯示的單次掃描才逐步顯示；watch 與 bisect 有自己的輸出
+        live: (!cli.no_live && !cli.json && !cli.compact && text_template.is_none() && cli.output.is_empty())
+            .then_some(())
+            .filter(|_| cli.watch.is_none() && cli.bisect.is_none() && live::available())
+            .map(|_| Arc::new(live::LiveReport::new(result_view.clone()))),
+        progress: (cli.heartbeat || cli.heartbeat_webhook.is_some() || cli.progress_file.is_some()).then(Arc::default),
+        directions,
+        health: Arc::new(checks::health::HealthChecks::build(&config.health)?),
+        dependencies: Arc::new(dependency::Dependencies::build(&config.dependencies)?),
+        // 重複的來源端口只測一次，保留第一次出現的順序
+        source_ports: cli.test_source_ports.iter().fold(Vec::new(), |mut ports, port| {
+            if !ports.contains(port) {
+                ports.push(*port);
+            }
+            ports
+        }),
+        // 掃描前的強制門戶檢查之後填入
+        captive: Default::default(),
+        pressure: None,
+    };
+    if plan.progress.is_some() && cli.heartbeat_interval.is_zero() {
+        return Err(errors::coded(ErrorCode::InvalidOptions, "--heartbeat-interval 必須大於 0"));
+    }
+    if let Some(policy) = &egress_policy {
+        plan.completed = Arc::new(policy.skipped(&plan.targets, &plan.ports));
+    }
+    if cli.progress_file.is_some() && cli.progress_file_interval.is_zero() {
+        return Err(errors::coded(ErrorCode::InvalidOptions, "--progress-file-interval 必須大於 0"));
+    }
+    // 趨勢只讀取既有的紀錄，不建立新的資料庫
+    if let Some(db) = cli.trend_db.as_deref().filter(|db| !db.exists()) {
+        return Err(errors::coded(ErrorCode::InvalidOptions, format!("找不到結果資料庫 {}", db.display())));
+    }
+    if cli.suggest_rules.is_some() && cli.manifest.is_none() && cli.policy.is_none() && cli.template.is_none() {
+        return Err(errors::coded(ErrorCode::InvalidOptions, "--suggest-rules 需要 --manifest、--policy 或 --template 判斷哪些端口不應開放"));
+    }
+
+    if let Some(anonymizer) = &context.anonymizer {
+        for target in &plan.targets {
+            if let TargetSpec::Host { name, .. } = target {
+                anonymizer.learn(name);
+                if let Some(unicode) = targets::unicode_hostname(name) {
+                    anonymizer.learn(&unicode);
+                }
+            }
+        }
+        plan.vhosts.iter().for_each(|name| anonymizer.learn(name));
+        for expansion in &expansions {
+            anonymizer.learn(&expansion.zone);
+            expansion.hosts.iter().flat_map(|host| &host.names).for_each(|name| anonymizer.learn(name));
+        }
+        anonymizer.metadata(&mut run_metadata);
+    }
+    if !cli.json && !cli.compact && text_template.is_none() {
+        expand::display(&expansions, &context);
+    }
+    for warning in plan.targets.iter().filter_map(TargetSpec::confusable_warning) {
+        eprintln!("{}", context.show(&warning).yellow());
+    }
+    let identities = Arc::new(identity::Identities::build(
+        &cli.host_id,
+        service_manifest.as_ref(),
+        &plan.targets,
+        cli.identity_rdns,
+    )?);
+    identity::display_conflicts(&identities.conflicts, &context);
+
+    // 相同設定過去掃描的吞吐量；watch 與 bisect 的掃描模式不同，不估計也不記錄
+    // --compact 不顯示估計與比較，也不讀取紀錄
+    let throughput = startup
+        .time("吞吐量紀錄", cli.watch.is_none() && cli.bisect.is_none() && !cli.compact, benchmark::History::open)
+        .flatten();
+
+    // dry-run：只輸出計劃，不觸及網路
+    if cli.dry_run {
+        let mut report = plan::build_report(&plan, cli.vuln_checks, cli.check_level(), cli.output.first().map(std::path::PathBuf::as_path));
+        report.historical_estimate = throughput.as_ref().and_then(|history| history.estimate(&plan));
+        report.excluded = run_metadata.excluded.clone();
+        report.guardrail = guardrail;
+        if cli.json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            print_header(&run_metadata);
+            plan::display_plan(&report);
+        }
+        return Ok(());
+    }
+
+    if cli.json && !cli.output.is_empty() {
+        return Err(errors::coded(
+            ErrorCode::InvalidOptions,
+            format!("--json 不能與 --output 同時使用{}", layers.origins(&["json", "output"])),
+        ));
+    }
+    if !guardrail.is_empty() {
+        return Err(errors::coded(ErrorCode::TargetRefused, format!("拒絕掃描: {}", guardrail.join("；"))));
+    }
+    // 每次掃描 (包含未通過授權確認的) 都在稽核紀錄附加一筆
+    let audit = audit::configured_path(&config.safety)
+        .map(|path| audit::Audit::open(path, &run_metadata, &plan.targets))
+        .transpose()?;
+    if cli.target.is_some() {
+        audit::authorize(&plan.targets, cli.authorized_by.as_deref(), audit.as_ref())?;
+    }
+    let record_start = || {
+        if let Some(audit) = &audit {
+            audit.record(audit::Outcome::Started, None);
+        }
+    };
+    let resource_monitor = plan.context.counters.clone().map(resources::ResourceMonitor::start);
+    // 選用功能在開始前偵測一次，無法使用的功能改用替代行為
+    let mut capabilities = capability::Capabilities::detect(&plan.context, !cli.no_geo_sanity);
+    let fingerprint_db = match (cli.fingerprint_db.as_deref(), capabilities.require(capability::Capability::Sqlite)) {
+        (Some(_), Err(e)) => {
+            eprintln!("{}", format!("{}，{}", e, capability::Capability::Sqlite.degraded()).yellow());
+            None
+        }
+        (db, _) => db,
+    };
+    let mut fingerprint_tracker = fingerprint_db
+        .map(|db| fingerprints::Tracker::open(db, identities.clone(), cli.accept_fingerprints))
+        .transpose()
+        .code(ErrorCode::OutputFailed)?;
+
+    // 續掃檔在掃描前驗證，設定不同或損壞時不開始掃描
+    let resume_state = cli.resume.as_deref().map(|path| resume::load(path, &plan)).transpose()?;
+    let checkpoint = match (&resume_state, &cli.resume_file) {
+        (Some(state), _) => Some(resume::Checkpoint::resume(state, cli.checkpoint_interval)?),
+        (None, Some(path)) => Some(resume::Checkpoint::create(path, &plan, cli.checkpoint_interval)?),
+        (None, None) => None,
+    };
+    if let Some(state) = &resume_state {
+        plan.completed = Arc::new(state.completed());
+    }
+
+    // JSON 或自訂範本模式下終端只輸出報告本身；--compact 只輸出無法連線的端口
+    let quiet = cli.json || cli.compact || text_template.is_some();
+    if !quiet {
+        print_header(&run_metadata);
+    }
+    // 出站掃描時監測本機端口；讀不到系統計數 (非 Linux 或受限的容器) 時不監測
+    if plan.directions.outbound() && !cli.no_port_pressure && ephemeral::read().is_some() {
+        plan.pressure = Some(Arc::new(ephemeral::PortPressure::new(plan.adaptive.clone(), quiet)));
+    }
+    let pressure_monitor = plan.pressure.clone().map(|pressure| ephemeral::Monitor::start(pressure, Box::new(ephemeral::read)));
+    plan.context.start_external_ip_lookup();
+    startup.record("外部IP", match cli.no_external_ip {
+        true => startup::Step::Skipped,
+        false => startup::Step::Background,
+    });
+    show_network_info(&plan.context, quiet || cli.no_network_info);
+    if cli.verbose {
+        eprintln!("{}", startup.describe().dimmed());
+    }
+    // 外部 IP 的位置檢查與掃描同時在背景進行
+    let geo_check = capabilities.available(capability::Capability::GeoIp).then(|| tokio::spawn(geosanity::check(plan.context.clone())));
+
+    // Tor 模式：確認代理可用，所有出站探測都經由代理
+    let mut tor_exit_ip = None;
+    if cli.tor {
+        let proxy = tor::find_proxy(cli.tor_proxy).await?;
+        plan.proxy = Some(proxy);
+        tor_exit_ip = tor::exit_ip(proxy).await.ok().map(|ip| context.show(&ip));
+        if !quiet {
+            let exit = tor_exit_ip.as_deref().unwrap_or("無法取得");
+            println!("{} 經由 {} (出口 IP {})", "Tor 模式:".bold(), proxy, exit.green());
+            println!("{}", "結果反映的是 Tor 出口節點的可達性，而非本機網路".italic());
+        }
+    }
+
+    // 跳板模式：先登入跳板確認金鑰與主機金鑰，出站探測改經由 SSH 通道
+    if let Some(spec) = cli.jump.clone() {
+        let jump = jump::JumpHost::connect(spec, cli.jump_key.clone(), cli.jump_sessions, cli.jump_channels).await?;
+        if !quiet {
+            println!("{} 經由 {} (每條連線最多 {} 個通道)", "跳板模式:".bold(), jump.spec(), cli.jump_channels);
+            println!("{}", "結果反映的是跳板主機的可達性，而非本機網路".italic());
+        }
+        plan.prober = Arc::new(jump::JumpProber::new(Arc::new(jump), context.clone()));
+    }
+    // HTTP 代理模式：先確認代理可連線，出站探測改以 CONNECT 經由代理
+    if let Some(spec) = cli.proxy.clone() {
+        let proxy = httpproxy::HttpProxy::connect(spec).await?;
+        if !quiet {
+            println!("{} 經由 {}", "HTTP 代理模式:".bold(), proxy.spec());
+            println!("{}", "結果反映的是代理的可達性與政策，「proxy 拒絕」表示路徑存在但被代理封鎖".italic());
+        }
+        plan.prober = Arc::new(httpproxy::ProxyProber::new(Arc::new(proxy), context.clone()));
+    }
+    // 經由 Tor、跳板或 HTTP 代理時，本機直接連線的結果不代表掃描路徑
+    let direct = plan.proxy.is_none() && cli.jump.is_none() && cli.proxy.is_none();
+    // 健康檢查直接連線，經由代理時結果不代表掃描路徑
+    if !direct && !plan.health.is_empty() {
+        if !quiet {
+            eprintln!("{}", "經由代理掃描時略過設定檔的健康檢查".yellow());
+        }
+        plan.health = Arc::default();
+    }
+    // 健康檢查與政策斷言直接連線，不經過記錄的探測
+    if replay.is_some() {
+        let skipped = !plan.health.is_empty() || policy.as_ref().is_some_and(|policy| !policy.assertions.is_empty());
+        if skipped && !quiet {
+            eprintln!("{}", "重播時略過設定檔的健康檢查與政策斷言".yellow());
+        }
+        plan.health = Arc::default();
+        if let Some(policy) = &mut policy {
+            policy.assertions.clear();
+        }
+    }
+    // --record-session：之後所有經過 Prober 的探測都寫入記錄檔
+    let recorder = match &cli.record_session {
+        Some(path) => {
+            let recorder = Arc::new(session::Recorder::create(path, &plan, cli.target.clone()).code(ErrorCode::OutputFailed)?);
+            plan.prober = recorder.clone();
+            Some(recorder)
+        }
+        None => None,
+    };
+    if let (Some(path), false) = (&cli.replay, quiet) {
+        println!("{} {} (不進行任何網路連線)", "重播模式:".bold(), path.display());
+    }
+
+    if cli.target.is_some() && !quiet {
+        let labels: Vec<String> = plan.targets.iter().map(|target| context.show(&target.label())).collect();
+        println!("{} {}", "掃描目標:".bold(), labels.join(", "));
+        if cli.verbose {
+            dns::display_stats(&context.dns.stats());
+        }
+    }
+    if let (Some(state), false) = (&resume_state, quiet) {
+        resume::display(state, plan.total_probes());
+    }
+    if let (Some(estimate), false) = (throughput.as_ref().and_then(|history| history.estimate(&plan)), quiet) {
+        println!("{}", benchmark::describe_estimate(&estimate).dimmed());
+    }
+
+    let whois = if cli.whois {
+        whois::lookup_targets(&plan.targets).await
+    } else {
+        Vec::new()
+    };
+    if !quiet {
+        for (target, result) in &whois {
+            println!("  {} WHOIS: {}", target.label(), whois::describe(result));
+        }
+    }
+
+    if let Some(knock) = &plan.knock {
+        knock_targets(&plan, knock, cli.verbose && !quiet).await?;
+    }
+
+    // 喚醒休眠中的區網主機後再開始掃描
+    let mut wake_report = match cli.wol {
+        true => {
+            let macs = wol::resolve(&plan, &cli.wol_mac)?;
+            let report = wol::wake(&plan, &macs, cli.wol_grace, cli.wol_port, quiet).await?;
+            if !quiet {
+                wol::display(&report, &context);
+            }
+            Some(report)
+        }
+        false => None,
+    };
+
+    // --privileged-helper：只為實際會用到的原始 socket 啟動輔助程序
+    let helper = match cli.privileged_helper {
+        true => {
+            let wanted = [(cli.syn, Channel::Tcp), (direct, Channel::Icmp4), (direct, Channel::Icmp6), (cli.pcap.is_some(), Channel::Capture)];
+            let channels: Vec<Channel> = wanted.into_iter().filter(|(used, _)| *used).map(|(_, channel)| channel).collect();
+            privhelper::start(&channels, quiet)
+        }
+        false => None,
+    };
+
+    capabilities.detect_raw(helper.as_ref());
+    if cli.verbose {
+        capability::display(&capabilities);
+    }
+    run_metadata.capabilities = capabilities.statuses();
+
+    // 沒有權限時說明原因，改用完整連線掃描；經由代理時收到的 ICMP 與探測無關
+    for note in capability::open_raw(&mut plan, &capabilities, helper.as_ref(), cli.syn, direct && replay.is_none()) {
+        if !quiet {
+            println!("{}", note.yellow());
+        }
+    }
+
+    // 沒有權限時說明原因，照常掃描
+    let capture = match &cli.pcap {
+        Some(path) => match capabilities
+            .require(capability::Capability::Capture)
+            .and_then(|()| pcap::Capture::start(path, pcap::CaptureFilter::from_plan(&plan), helper.as_ref()))
+        {
+            Ok(capture) => {
+                if !quiet {
+                    println!("{} {} ({})", "封包擷取:".bold(), capture.path().display(), capture.filter().expression().dimmed());
+                }
+                Some(Arc::new(capture))
+            }
+            Err(e) => {
+                eprintln!("{}", format!("{}，{}", e, capability::Capability::Capture.degraded()).yellow());
+                None
+            }
+        },
+        None => None,
+    };
+    // 之後以任何方式結束都會寫入最後的狀態 (丟棄時為 failed)
+    let progress_file = match (&cli.progress_file, &plan.progress) {
+        (Some(path), Some(counters)) => Some(
+            progressfile::start(path, counters.clone(), plan.remaining_probes(), &run_metadata.scan_id, run_metadata.started_at, cli.progress_file_interval)
+                .map_err(|e| format!("無法寫入進度檔 {}: {}", path.display(), e))
+                .code(ErrorCode::OutputFailed)?,
+        ),
+        _ => None,
+    };
+    // Ctrl+C 時寫完擷取檔與進度檔再結束
+    let interrupt = progress_file.as_ref().map(progressfile::ProgressFile::interrupt);
+    if capture.is_some() || interrupt.is_some() {
+        let capture = capture.clone();
+        let status = status.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                if let Some(interrupt) = &interrupt {
+                    interrupt.cancel();
+                }
+                report_capture(capture.as_deref(), false);
+                status.interrupted();
+            }
+        });
+    }
+
+    if cli.profile_scan {
+        plan.profiler = Some(Arc::new(profile::Profiler::default()));
+    }
+
+    if let Some(interval) = cli.watch {
+        record_start();
+        let fingerprints = fingerprint_tracker.as_mut().filter(|_| direct && plan.pipeline.reaches(Stage::Fingerprint));
+        let policy = backoff::Policy { after: config.watch.backoff_after, max: config.watch.backoff_max };
+        let backoff = match &cli.backoff_db {
+            Some(db) => backoff::Backoff::open(policy, interval, db, cli.reset_backoff)?,
+            None => backoff::Backoff::new(policy, interval),
+        };
+        let options = watch::Options { interval, show_host: cli.target.is_some(), backoff };
+        let outcome = watch::run(&plan, &config.watch, options, result_view, eventlog.as_ref(), fingerprints).await?;
+        status.record(outcome.tally);
+        if outcome.changed {
+            status.changed();
+        }
+        return Ok(());
+    }
+
+    // 掃描前確認網路可達、Web 連線沒有被攔截；經由代理時直接連線的結果不代表掃描路徑
+    let (network_suspect, captive_portal) = tokio::join!(
+        async {
+            match (&sanity_anchors, direct) {
+                (Some((anchors, limit)), true) => sanity::check(anchors, *limit).await == sanity::Sanity::Suspect,
+                _ => false,
+            }
+        },
+        async {
+            match (&captive_endpoints, direct && plan.directions.outbound()) {
+                (Some((endpoints, limit)), true) => Some(captive::check(endpoints, *limit).await),
+                _ => None,
+            }
+        },
+    );
+    // 結果在掃描時就標示，串流輸出的紀錄與開頭的 metadata 都帶有判定
+    if let Some(portal) = &captive_portal {
+        plan.captive = portal.interception();
+    }
+    run_metadata.captive_portal = captive_portal;
+
+    if let Some(sources) = &cli.compare_source {
+        record_start();
+        let comparison = compare::run(&plan, sources, cli.compare_samples, cli.compare_threshold).await;
+        report_capture(capture.as_deref(), quiet);
+        if let Some(path) = &cli.compare_output {
+            compare::write(&comparison, path)?;
+        }
+        if cli.json {
+            println!("{}", serde_json::to_string_pretty(&comparison)?);
+        } else {
+            if network_suspect {
+                sanity::display_warning();
+            }
+            compare::display(&comparison);
+            if let Some(path) = &cli.compare_output {
+                println!("路徑比較已寫入 {}", path.display());
+            }
+        }
+        if network_suspect {
+            status.exit(ErrorCode::NetworkSuspect);
+        }
+        return Ok(());
+    }
+
+    if let Some(duration) = cli.monitor {
+        if cli.interval.is_zero() {
+            return Err(errors::coded(ErrorCode::InvalidOptions, "--interval 必須大於 0"));
+        }
+        record_start();
+        let mut report = monitor::run(&plan, duration, cli.interval, quiet).await;
+        report_capture(capture.as_deref(), quiet);
+        if let Some(path) = &cli.monitor_output {
+            monitor::write_csv(&report, &context, path)?;
+        }
+        if cli.json {
+            if let Some(anonymizer) = &context.anonymizer {
+                report.ports.iter_mut().for_each(|availability| availability.host = anonymizer.ip(availability.host));
+            }
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            if network_suspect {
+                sanity::display_warning();
+            }
+            monitor::display(&report, &context, &result_view.layout);
+            if let Some(path) = &cli.monitor_output {
+                println!("樣本已寫入 {}", path.display());
+            }
+        }
+        if network_suspect {
+            status.exit(ErrorCode::NetworkSuspect);
+        }
+        return Ok(());
+    }
+
+    if let Some(range) = cli.bisect.clone() {
+        record_start();
+        let reports = bisect::run(&plan, range, cli.bisect_samples).await;
+        report_capture(capture.as_deref(), quiet);
+        if cli.json {
+            println!("{}", serde_json::to_string_pretty(&reports)?);
+        } else {
+            if network_suspect {
+                sanity::display_warning();
+            }
+            bisect::display(&reports);
+        }
+        if network_suspect {
+            status.exit(ErrorCode::NetworkSuspect);
+        }
+        return Ok(());
+    }
+
+    if !cli.output.is_empty() {
+        // 串流模式：結果直接寫入各個檔案，只保留統計
+        if cli.output_format.is_some() && cli.output.len() > 1 {
+            return Err(errors::coded(ErrorCode::InvalidOptions, "--output-format 只能搭配單一 --output，多個輸出依副檔名判斷格式"));
+        }
+        let formats = cli
+            .output
+            .iter()
+            .map(|path| {
+                cli.output_format.or_else(|| OutputFormat::from_path(path)).ok_or_else(|| {
+                    errors::coded(ErrorCode::InvalidOptions, format!("無法從 {} 的副檔名判斷輸出格式，請指定 --output-format", path.display()))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if service_manifest.as_ref().is_some_and(manifest::Manifest::declares_hosts) {
+            return Err(errors::coded(
+                ErrorCode::InvalidOptions,
+                "服務清單的 hosts 比對不能與 --output 同時使用 (只有 identities 的清單可以)",
+            ));
+        }
+        let sqlite = capabilities.require(capability::Capability::Sqlite);
+        let outputs = cli
+            .output
+            .iter()
+            .zip(formats)
+            .filter(|(path, format)| match (format, &sqlite) {
+                (OutputFormat::Sqlite, Err(e)) => {
+                    eprintln!("{}", format!("{}，{}: {}", e, capability::Capability::Sqlite.degraded(), path.display()).yellow());
+                    false
+                }
+                _ => true,
+            })
+            .map(|(path, format)| output::Output::open(path, format, &run_metadata))
+            .collect::<Result<Vec<_>, _>>()
+            .code(ErrorCode::OutputFailed)?;
+        // 其他格式沒有放事件的位置，只送 webhook
+        let ndjson = outputs.iter().any(|output| output.format == OutputFormat::Ndjson);
+
+        let (tx, rx) = mpsc::channel(RESULT_CHANNEL_CAPACITY);
+        let writer = output::spawn_writer(outputs, rx, cli.top, identities.clone(), context.clone());
+        let pb = create_progress_bar(plan.total_probes());
+        if cli.no_progress {
+            pb.set_draw_target(ProgressDrawTarget::hidden());
+        }
+        let started = Instant::now();
+        let stream = (cli.heartbeat && ndjson).then(|| tx.clone());
+        let heartbeat = start_heartbeat(&plan, &cli, stream);
+        let keyboard = plan.control.clone().and_then(|control| keyboard::Keyboard::start(control, pb.clone()));
+        scanner::run_scan(&plan, tx, &pb).await;
+        drop(keyboard);
+        finish_recording(recorder.as_deref(), replay.as_ref(), cli.record_session.as_deref(), quiet);
+        finish_progress(&plan, &pb, false);
+        stop_heartbeat(heartbeat).await;
+        finish_progress_file(progress_file.as_ref(), &plan);
+        let scan_elapsed = started.elapsed();
+
+        let (mut summary, statuses) = writer.await?;
+        if let Some(audit) = &audit {
+            let open = summary.both + summary.outbound_only;
+            audit.record(audit_outcome(&plan), audit::digest_file(&cli.output[0], summary.total, open).ok());
+        }
+        report_capture(capture.as_deref(), false);
+        if network_suspect {
+            sanity::display_warning();
+        }
+        if let Some(portal) = &run_metadata.captive_portal {
+            captive::display_warning(portal);
+        }
+        show_external_ip(&plan.context, finish_geo_check(geo_check).await.as_ref()).await;
+        metadata::display_target_warnings(&external_target_warnings(&plan, cli.target.is_some()).await);
+        output::display_summary(&summary, &statuses, &result_view.layout);
+        if let Some(comparison) = throughput.and_then(|history| history.finish(&plan, scan_elapsed)) {
+            benchmark::display_comparison(&comparison);
+        }
+        if let Some(log) = &eventlog {
+            report_scan_event(log, &summary);
+        }
+        report_profile(&plan, cli.profile_csv.as_deref(), false)?;
+        report_hooks(&plan, false).await;
+        report_adaptive(&plan, false);
+        if let Some(summary) = pressure_monitor.map(ephemeral::Monitor::finish).filter(ephemeral::PressureSummary::notable) {
+            ephemeral::display_summary(&summary);
+        }
+        if let Some(monitor) = resource_monitor {
+            resources::display(&monitor.finish());
+        }
+
+        let target = cli.target.as_deref().map(|target| context.show(target));
+        summary.share.set_run(target.as_deref(), run_metadata.started_at);
+        summary.share.finish(started.elapsed());
+        status.record(summary.share.tally());
+        share_summary(&summary.share, cli.copy, &capabilities, false);
+        write_anonymize_map(cli.anonymize_map.as_deref(), context.anonymizer.as_ref(), false)?;
+        if network_suspect {
+            status.exit(ErrorCode::NetworkSuspect);
+        }
+    } else {
+        let started = Instant::now();
+        // 進度列清除後才開始暫存報告，超過一個畫面時交給分頁程式
+        let paging = !quiet && pager::wanted(&config.pager, cli.no_pager);
+        let heartbeat = start_heartbeat(&plan, &cli, None);
+        let mut clock_jumps = clock::JumpDetector::new(notify::SystemClock);
+        let mut scan_results = perform_scan(&plan, checkpoint, quiet || cli.no_progress, paging).await;
+        stop_heartbeat(heartbeat).await;
+        run_metadata.clock_jump = clock_jumps.take();
+        if let Some(jump) = &run_metadata.clock_jump {
+            eprintln!("{}", format!("⚠ {}", jump.describe()).yellow());
+        }
+        finish_progress_file(progress_file.as_ref(), &plan);
+        let nat_warnings = external_target_warnings(&plan, cli.target.is_some()).await;
+        run_metadata.target_warnings.extend(nat_warnings.iter().cloned());
+        run_metadata.geo_sanity = finish_geo_check(geo_check).await;
+        if let Some(audit) = &audit {
+            audit.record(audit_outcome(&plan), Some(audit::digest_results(&scan_results)));
+        }
+        // 記下區網主機的 MAC，之後的 --wol 不必指定；重播的主機不是目前網路上的主機
+        if replay.is_none() {
+            wol::learn(scan_results.keys().copied());
+        }
+        let mut local_sockets = match cli.unix_sockets {
+            true => match localsock::enumerate(cli.unix_connect) {
+                Ok(sockets) => Some(sockets),
+                Err(e) => {
+                    eprintln!("{}", format!("無法列出本機 socket: {}", e).yellow());
+                    None
+                }
+            },
+            false => None,
+        };
+        let mut port_mapping = match cli.nat_pmp {
+            true => Some(match capabilities.require(capability::Capability::PortMapping) {
+                Ok(()) => natpmp::query(natpmp::QUERY_TIMEOUT).await,
+                Err(e) => natpmp::PortMapping {
+                    notes: vec![format!("{}，{}", e, capability::Capability::PortMapping.degraded())],
+                    ..Default::default()
+                },
+            }),
+            false => None,
+        };
+        if cli.detect_cloud {
+            result_view.cloud.scanner = cloud::detect().await;
+            if result_view.cloud.scanner.is_none() && !quiet {
+                println!("{}", "未偵測到雲端平台的中繼資料服務，掃描端不在 AWS / Google Cloud / Azure 的 VM 上".dimmed());
+            }
+        }
+        let scan_elapsed = started.elapsed();
+        if let Some(state) = resume_state {
+            for record in state.records {
+                let mut result = record.result;
+                result.resumed = true;
+                scan_results.entry(record.host).or_default().insert(record.port, result);
+            }
+        }
+        if let Some(path) = cli.resume.as_deref().or(cli.resume_file.as_deref()) {
+            resume::finish(path);
+        }
+        // 覆核與信心分數都針對出站連線，--no-outbound 時略過
+        let verified = match cli.no_verify || !plan.directions.outbound() {
+            true => verify::VerifySummary::default(),
+            false => verify::verify(&plan, &config.verify, &mut scan_results).await,
+        };
+        let refined = match cli.min_confidence.filter(|_| plan.directions.outbound()) {
+            Some(threshold) => Some(confidence::refine(&plan, &config.verify, threshold, &mut scan_results).await),
+            None if !plan.directions.outbound() => None,
+            None => {
+                confidence::assess(&plan, &mut scan_results);
+                None
+            }
+        };
+        // --samples 或政策的延遲目標：重複連線取得延遲的百分位數
+        let sample_count = cli
+            .samples
+            .or_else(|| policy.as_ref().filter(|p| p.needs_samples()).map(|_| samples::POLICY_SAMPLES));
+        let sampled = match sample_count.filter(|_| plan.directions.outbound()) {
+            Some(count) => samples::sample(&plan, count, &mut scan_results).await,
+            None => samples::SampleSummary::default(),
+        };
+        // 經由代理時直接連線的結果不代表掃描路徑
+        let mut tarpits = match cli.no_tarpit_check || !direct {
+            true => BTreeMap::new(),
+            false => tarpit::detect(&scan_results, &plan.context, plan.timeouts.default).await,
+        };
+        // --syn 時依收到的 SYN-ACK 特徵猜測作業系統；完整連線無法取得 TTL，不猜測
+        let mut os_guesses = plan.syn.as_ref().map(|syn| osguess::guess_all(syn.samples())).unwrap_or_default();
+        // 識別依真實 IP 決定；只記錄有 IP 以外識別的主機
+        identities.prefetch(scan_results.keys().copied().collect()).await;
+        if let (Some(runs), Some(db)) = (cli.trend, &cli.trend_db) {
+            trend::apply(db, runs, &mut scan_results, &identities).code(ErrorCode::OutputFailed)?;
+        }
+        if !plan.source_ports.is_empty() {
+            let phase_at = Instant::now();
+            srcport::probe_results(&mut scan_results, &plan).await;
+            record_phase(&plan, Stage::Connect, "source-ports", phase_at);
+        }
+        // 覆核之後才判斷上游是否完全無法連線
+        let upstream_affected = plan.dependencies.annotate(&mut scan_results);
+        if upstream_affected > 0 && !quiet {
+            println!("{}", format!("{} 台主機的上游依賴無法連線，失敗的端口已加上註記", upstream_affected).yellow());
+        }
+        if cli.systemd {
+            match systemd::query().await {
+                Ok(units) if units.is_empty() && !quiet => println!("{}", "systemd 沒有監聽 TCP 端口的 socket unit".dimmed()),
+                Ok(units) => {
+                    units.apply(&mut scan_results, &context);
+                }
+                Err(e) => eprintln!("{}", format!("無法取得 systemd 的 socket unit: {}", e).yellow()),
+            }
+        }
+        let conntrack_summary = match cli.conntrack {
+            true => match conntrack::read() {
+                Ok((source, flows)) => Some(conntrack::reconcile(source, &flows, &mut scan_results, &context)),
+                Err(e) => {
+                    eprintln!("{}", e.yellow());
+                    None
+                }
+            },
+            false => None,
+        };
+        let mut host_identities: BTreeMap<IpAddr, identity::Identity> = scan_results
+            .keys()
+            .map(|host| (*host, identities.of(*host)))
+            .filter(|(_, found)| found.source != identity::IdentitySource::Address)
+            .map(|(host, found)| (host, identity::Identity { name: identity::show(&found, context.anonymizer.as_ref()), source: found.source }))
+            .collect();
+        // 服務辨識階段：超出 --stages 時略過，各探測只對符合前置條件的端口執行
+        let fingerprint = plan.pipeline.reaches(Stage::Fingerprint) && direct;
+        if cli.tcp_caps && fingerprint {
+            let phase_at = Instant::now();
+            caps::probe_results(&mut scan_results, &plan).await;
+            record_phase(&plan, Stage::Fingerprint, "tcp-caps", phase_at);
+        }
+        if cli.http_versions && fingerprint {
+            let phase_at = Instant::now();
+            httpver::probe_results(&mut scan_results, &plan).await;
+            record_phase(&plan, Stage::Fingerprint, "http-versions", phase_at);
+        }
+        if !cli.throughput_test.is_empty() && fingerprint {
+            let phase_at = Instant::now();
+            let config = throughput::ThroughputConfig {
+                ports: cli.throughput_test.clone(),
+                path: cli.throughput_path.clone(),
+                echo: cli.throughput_echo,
+                max_bytes: cli.throughput_max_bytes,
+                duration: cli.throughput_duration,
+                timeout: plan.timeouts.default.max(throughput::MIN_TIMEOUT),
+                context: plan.context.clone(),
+            };
+            throughput::measure_results(&mut scan_results, &config).await;
+            record_phase(&plan, Stage::Fingerprint, "throughput", phase_at);
+        }
+        let mut fingerprint_report = None;
+        if let (Some(tracker), true) = (&mut fingerprint_tracker, fingerprint) {
+            let phase_at = Instant::now();
+            let report = tracker.observe(&mut scan_results, &plan).await.code(ErrorCode::OutputFailed)?;
+            if report.unaccepted().next().is_some() {
+                status.changed();
+            }
+            fingerprint_report = Some(report);
+            record_phase(&plan, Stage::Fingerprint, "fingerprints", phase_at);
+        }
+        // 健康檢查在政策評估與評分之前執行，讓三種狀態一致地納入
+        if !plan.health.is_empty() {
+            let phase_at = Instant::now();
+            checks::health::probe_results(&mut scan_results, &plan).await;
+            record_phase(&plan, Stage::Checks, "health", phase_at);
+        }
+        if let Some(token) = &cli.expect_token {
+            let missing = listen::verify(&mut scan_results, &plan, token).await;
+            if missing > 0 && !quiet {
+                println!("{}", format!("{} 個可連線的端口回應中沒有預期的 token", missing).yellow());
+            }
+        }
+        // 政策斷言需要另外連線，在換成假名之前執行
+        let mut assertion_outcomes = match &policy {
+            Some(policy) if !policy.assertions.is_empty() => {
+                assertions::evaluate(&plan, &policy.assertions, &scan_results).await
+            }
+            _ => BTreeMap::new(),
+        };
+        let mut pager = if paging { pager::Pager::start(&config.pager) } else { None };
+        let mut share_line = ShareLine::default();
+        let target = cli.target.as_deref().map(|target| context.show(target));
+        share_line.set_run(target.as_deref(), run_metadata.started_at);
+        for (host, results) in &scan_results {
+            for (port, result) in results {
+                let record = scanner::ScanRecord { host: *host, port: port.clone(), result: result.clone(), identity: None };
+                share_line.add(&match &context.anonymizer {
+                    Some(anonymizer) => anonymizer.record(record),
+                    None => record,
+                });
+            }
+        }
+        share_line.finish(started.elapsed());
+        status.record(share_line.tally());
+
+        // 服務檢查先於顯示執行，讓等級能納入檢查結果
+        let mut check_results = Vec::new();
+        if cli.vuln_checks && plan.pipeline.reaches(Stage::Checks) {
+            let phase_at = Instant::now();
+            // 未指定目標時檢查本機服務；否則只檢查連線階段有回應的主機
+            let hosts: Vec<IpAddr> = match cli.target {
+                Some(_) => scan_results.iter().filter(|(_, r)| pipeline::host_answered(r)).map(|(h, _)| *h).collect(),
+                None => vec![IpAddr::V4(Ipv4Addr::LOCALHOST)],
+            };
+            let ports: Vec<u16> = plan.ports.iter().map(|p| p.port).collect();
+            for host in hosts {
+                let outcomes = checks::run_checks(host, &ports, cli.check_level(), &plan).await;
+                check_results.push((host, outcomes));
+            }
+            record_phase(&plan, Stage::Checks, "vuln-checks", phase_at);
+        }
+        finish_recording(recorder.as_deref(), replay.as_ref(), cli.record_session.as_deref(), quiet);
+        // 平台註記比對中繼資料服務的位址，需在換成假名之前評估
+        let mut cloud_report = cloud::evaluate(result_view.cloud, &scan_results);
+        // 所有網路探測結束後才換成假名，之後的顯示與輸出都只看到假名
+        let mut attribution_targets = (plan.targets.clone(), resolve_failures.clone());
+        if let Some(anonymizer) = &context.anonymizer {
+            scan_results = anonymizer.results(scan_results);
+            check_results = anonymizer.checks(check_results);
+            tarpits = anonymizer.rekey(tarpits);
+            os_guesses = anonymizer.rekey(os_guesses);
+            host_identities = anonymizer.rekey(host_identities);
+            if let Some(wake) = &mut wake_report {
+                wake.anonymize(anonymizer);
+            }
+            if let Some(sockets) = &mut local_sockets {
+                localsock::anonymize(sockets, anonymizer);
+            }
+            if let Some(mapping) = &mut port_mapping {
+                mapping.anonymize(anonymizer);
+            }
+            cloud_report.anonymize(anonymizer);
+            if let Some(report) = &mut fingerprint_report {
+                report.anonymize(anonymizer);
+            }
+            assertion_outcomes = anonymizer.assertions(assertion_outcomes);
+            attribution_targets = (anonymizer.targets(&plan.targets), anonymizer.failures(&resolve_failures));
+            expansions.iter_mut().for_each(|expansion| expansion.anonymize(anonymizer));
+            result_view.hosts = Arc::new(result_view.hosts.anonymize(anonymizer));
+        }
+        // --strict：掃描端本身的錯誤與端口的判定分開列出，有任何一個就以錯誤結束
+        let scanner_errors = match cli.strict {
+            true => strict::collect(&scan_results, &attribution_targets.1),
+            false => Vec::new(),
+        };
+        grade::apply_checks(&mut scan_results, &check_results, &plan.grading);
+        report_capture(capture.as_deref(), quiet);
+        // --blocks 在換成假名之後彙總；假名保留網段結構，設定的網段也換成假名
+        let blocks = cli.blocks.then(|| {
+            let names = match &context.anonymizer {
+                Some(anonymizer) => block_names.anonymize(anonymizer),
+                None => block_names.clone(),
+            };
+            netblocks::summarize(&scan_results, &check_results, cli.block_prefix, &names)
+        });
+        let suspicious = threats::evaluate(&result_view.threats, &scan_results);
+        let mut recommendations = recommend::evaluate(&recommendation_rules, &scan_results);
+        cloud::adjust(&mut recommendations, &cloud_report);
+        recommendations.extend(threats::recommendations(&suspicious));
+        recommend::sort(&mut recommendations);
+        let bundle_verdicts = bundles::evaluate(&service_bundles, &scan_results);
+
+        if let Some(log) = &eventlog {
+            let mut summary = output::ScanSummary::new(0);
+            for (host, results) in &scan_results {
+                for (port, result) in results {
+                    summary.add(&scanner::ScanRecord { host: *host, port: port.clone(), result: result.clone(), identity: None });
+                }
+            }
+            report_scan_event(log, &summary);
+        }
+        if !quiet {
+            if network_suspect {
+                sanity::display_warning();
+            }
+            if let Some(portal) = &run_metadata.captive_portal {
+                captive::display_warning(portal);
+            }
+            tarpit::display_warnings(&tarpits);
+            verify::display_summary(&verified);
+            if let Some(refined) = &refined {
+                confidence::display_summary(refined);
+            }
+            show_external_ip(&plan.context, run_metadata.geo_sanity.as_ref()).await;
+            metadata::display_target_warnings(&nat_warnings);
+            match &blocks {
+                Some(blocks) => {
+                    for block in blocks {
+                        netblocks::display_header(block);
+                        for host in &block.hosts {
+                            display_results(Some(*host), &scan_results[host], os_guesses.get(host), &result_view);
+                        }
+                    }
+                    netblocks::display_summary(blocks);
+                }
+                None => {
+                    for (host, results) in &scan_results {
+                        display_results(cli.target.as_ref().map(|_| *host), results, os_guesses.get(host), &result_view);
+                    }
+                }
+            }
+            samples::display_histogram(&sampled);
+            if let Some(sockets) = &local_sockets {
+                localsock::display(sockets);
+            }
+            if let Some(mapping) = &port_mapping {
+                natpmp::display(mapping);
+            }
+            if plan.directions.outbound() {
+                attribution::display(&attribution::build(
+                    &attribution_targets.0,
+                    &attribution_targets.1,
+                    &scan_results,
+                    &check_results,
+                    plan.probes.as_deref(),
+                ));
+            }
+            srcport::display(&scan_results, &plan.source_ports);
+            strict::display(&scanner_errors);
+            cloud::display(&cloud_report, scan_results.len() > 1);
+            threats::display(&suspicious, scan_results.len() > 1);
+            recommend::display(&recommendations, scan_results.len() > 1);
+            print_legend(plan.directions);
+        }
+        if cli.matrix {
+            let table = match &blocks {
+                Some(blocks) => matrix::Matrix::pivot_blocks(&scan_results, blocks),
+                None => matrix::Matrix::pivot(&scan_results),
+            };
+            matrix::display_matrix(&table, &result_view.layout);
+            if let Some(path) = &cli.matrix_output {
+                matrix::write(&table, path, &run_metadata).map_err(|e| format!("無法寫入 {}: {}", path.display(), e))?;
+                println!("比較表已寫入 {}", path.display());
+            }
+        }
+        report_profile(&plan, cli.profile_csv.as_deref(), quiet)?;
+        report_hooks(&plan, quiet).await;
+        report_adaptive(&plan, quiet);
+        let pressure = pressure_monitor.map(ephemeral::Monitor::finish).filter(ephemeral::PressureSummary::notable);
+        if let (Some(summary), false) = (&pressure, quiet) {
+            ephemeral::display_summary(summary);
+        }
+        let resource_usage = resource_monitor.map(resources::ResourceMonitor::finish);
+        if let (Some(usage), false) = (&resource_usage, quiet) {
+            resources::display(usage);
+        }
+        let comparison = throughput.and_then(|history| history.finish(&plan, scan_elapsed));
+        if let (Some(comparison), false) = (&comparison, quiet) {
+            benchmark::display_comparison(comparison);
+        }
+
+        if !quiet {
+            for (host, outcomes) in &check_results {
+                checks::display_checks(*host, outcomes);
+            }
+        }
+
+        let policy_report = policy.as_ref().map(|policy| policy::evaluate(policy, &scan_results, &assertion_outcomes));
+        if let (Some(report), false) = (&policy_report, quiet) {
+            policy::display_report(report);
+        }
+        let manifest_report = service_manifest
+            .as_ref()
+            .filter(|manifest| manifest.declares_hosts())
+            .map(|manifest| manifest::reconcile(manifest, &plan.targets, &scan_results));
+        if let (Some(report), false) = (&manifest_report, quiet) {
+            manifest::display_report(report);
+        }
+        let egress_report = egress_policy.as_ref().map(|policy| egress::evaluate(policy, &plan.targets, &scan_results));
+        if let (Some(report), false) = (&egress_report, quiet) {
+            egress::display_report(report);
+        }
+        if let (Some(summary), false) = (&conntrack_summary, quiet) {
+            conntrack::display(summary);
+        }
+        let shodan_exposure = match &shodan_key {
+            Some(key) => match plan.context.wait_external_ip(context::EXTERNAL_IP_TIMEOUT).await {
+                ExternalIp::Known(ip) => match shodan::lookup(&ip, key, shodan::cache_path().as_deref()).await {
+                    Ok((host, origin, fetched_at, error)) => {
+                        Some(shodan::cross_reference(&ip, host, origin, fetched_at, error, &scan_results, &context))
+                    }
+                    Err(e) => {
+                        eprintln!("{}", format!("無法查詢 Shodan: {}", e).yellow());
+                        None
+                    }
+                },
+                _ => {
+                    eprintln!("{}", "無法取得外部 IP，略過 Shodan 比對".yellow());
+                    None
+                }
+            },
+            None => None,
+        };
+        if let (Some(exposure), false) = (&shodan_exposure, quiet) {
+            shodan::display(exposure);
+        }
+        if !quiet {
+            bundles::display(&bundle_verdicts, scan_results.len() > 1);
+        }
+        if let (Some(report), false) = (&fingerprint_report, quiet) {
+            fingerprints::display(report, &context);
+        }
+        if let Some(backend) = cli.suggest_rules {
+            let suggestions = firewall::suggest(&scan_results, policy_report.as_ref(), manifest_report.as_ref());
+            firewall::write(&firewall::render(backend, &suggestions), cli.rules_out.as_deref(), quiet)?;
+        }
+        if let (Some(report), Some(log)) = (&manifest_report, &eventlog) {
+            if report.unexpected > 0 {
+                let message = format!("發現 {} 個未宣告的開放端口 ({})", report.unexpected, report.manifest);
+                log.report(eventlog::EventLevel::Warning, eventlog::EVENT_UNEXPECTED_OPEN, &message, report);
+            }
+        }
+        if let (Some(report), Some(log)) = (&egress_report, &eventlog) {
+            if let Some((code, message)) = report.failure() {
+                let level = match code {
+                    ErrorCode::EgressDisallowedOpen => eventlog::EventLevel::Error,
+                    _ => eventlog::EventLevel::Warning,
+                };
+                log.report(level, eventlog::EVENT_EGRESS_VIOLATION, &format!("{} ({})", message, report.policy), report);
+            }
+        }
+        if let (Some(report), Some(log)) = (&fingerprint_report, &eventlog) {
+            for change in report.unaccepted() {
+                let message = format!("{} port {} 的{}已改變", change.identity, change.port, change.kind.label());
+                log.report(eventlog::EventLevel::Error, eventlog::EVENT_FINGERPRINT_CHANGED, &message, change);
+            }
+        }
+        share_summary(&share_line, cli.copy, &capabilities, quiet);
+        // 不符合政策時以錯誤結束，方便在排程或 CI 中判斷
+        let policy_failure = policy_report
+            .as_ref()
+            .filter(|report| !report.passed)
+            .map(policy::PolicyReport::failure_summary);
+
+        if cli.json || text_template.is_some() || cli.bundle.is_some() {
+            let external_ip = match plan.context.wait_external_ip(context::EXTERNAL_IP_TIMEOUT).await {
+                ExternalIp::Known(ip) => Some(context.show(&ip)),
+                _ => None,
+            };
+            let mut report = report::build(
+                &run_metadata,
+                external_ip.as_deref(),
+                tor_exit_ip.as_deref(),
+                &scan_results,
+                &whois,
+                &check_results,
+                policy_report.as_ref(),
+            );
+            report.network_suspect = network_suspect;
+            report.manifest = manifest_report.as_ref();
+            report.egress = egress_report.as_ref();
+            report.recommendations = &recommendations;
+            report.suspicious = &suspicious;
+            report.cloud = (!cloud_report.context.is_empty()).then_some(&cloud_report);
+            report.bundles = &bundle_verdicts;
+            report.wake = wake_report.as_ref();
+            report.local_sockets = local_sockets.as_deref();
+            report.port_mapping = port_mapping.as_ref();
+            report.blocks = blocks.as_deref();
+            report.expansions = (!expansions.is_empty()).then_some(expansions.as_slice());
+            report.resources = resource_usage.as_ref();
+            report.port_pressure = pressure.as_ref();
+            report.conntrack = conntrack_summary.as_ref();
+            report.shodan = shodan_exposure.as_ref();
+            report.fingerprints = fingerprint_report.as_ref();
+            report.scanner_errors = cli.strict.then_some(scanner_errors.as_slice());
+            for host in &mut report.hosts {
+                host.tarpit = tarpits.get(&host.host);
+                host.os_guess = os_guesses.get(&host.host);
+                host.identity = host_identities.get(&host.host);
+            }
+            if cli.aggregate_ranges {
+                report::aggregate_ranges(&mut report);
+            }
+            if let Some(key) = &signing_key {
+                report.signature = Some(signing::sign(key, &serde_json::to_value(&report)?));
+            }
+            if let Some(path) = &cli.bundle {
+                write_archive(path, &report, &scan_results, cli.transcript.as_deref())?;
+                if !quiet {
+                    println!("掃描封存已寫入 {}", path.display());
+                }
+            }
+            match &text_template {
+                Some(template) => print!("{}", template.render(&report, &share_line.summary())?),
+                None if cli.json => println!("{}", serde_json::to_string_pretty(&report)?),
+                None => {}
+            }
+        }
+        if cli.es_bulk.is_some() || cli.es_url.is_some() {
+            let payload = esbulk::build(&cli.es_index, &run_metadata, &scan_results, &check_results, &host_identities)?;
+            if let Some(path) = &cli.es_bulk {
+                esbulk::write(path, &payload)?;
+                if !quiet {
+                    println!("bulk 內容已寫入 {}", path.display());
+                }
+            }
+            if let Some(url) = &cli.es_url {
+                esbulk::send(url, &payload, quiet).await?;
+            }
+        }
+        let compact_lines = match cli.compact {
+            true => quick::failures(&scan_results),
+            false => Vec::new(),
+        };
+        compact_lines.iter().for_each(|line| println!("{}", line));
+        write_anonymize_map(cli.anonymize_map.as_deref(), context.anonymizer.as_ref(), quiet)?;
+        // 網路疑似離線時其他判斷都不可信，以獨立的結束代碼優先回報
+        if network_suspect {
+            if !quiet {
+                sanity::display_warning();
+            }
+            if let Some(pager) = &mut pager {
+                pager.finish();
+            }
+            std::io::stdout().flush()?;
+            status.exit(ErrorCode::NetworkSuspect);
+        }
+        // 掃描端錯誤代表結果不完整，之後依結果的判斷都不可靠
+        if !scanner_errors.is_empty() {
+            if let Some(pager) = &mut pager {
+                pager.finish();
+            }
+            eprintln!("{}", format!("[{}] 掃描端發生 {} 個錯誤", ErrorCode::ScannerErrors.code(), scanner_errors.len()).red());
+            std::io::stdout().flush()?;
+            status.exit(ErrorCode::ScannerErrors);
+        }
+        // 未宣告的開放端口是安全相關的訊號，以獨立的結束代碼回報
+        if let Some(report) = manifest_report.as_ref().filter(|r| r.unexpected > 0) {
+            if let Some(pager) = &mut pager {
+                pager.finish();
+            }
+            eprintln!("{}", format!("[{}] 發現 {} 個未宣告的開放端口", ErrorCode::UnexpectedOpen.code(), report.unexpected).red());
+            std::io::stdout().flush()?;
+            status.exit(ErrorCode::UnexpectedOpen);
+        }
+        // 出站政策的違規以獨立的結束代碼回報；不允許的路徑可以連線優先
+        if let Some((code, message)) = egress_report.as_ref().and_then(egress::EgressReport::failure) {
+            if let Some(pager) = &mut pager {
+                pager.finish();
+            }
+            eprintln!("{}", format!("[{}] {}", code.code(), message).red());
+            std::io::stdout().flush()?;
+            status.exit(code);
+        }
+        // 服務組合不成立代表應用程式不完整，同樣以獨立的結束代碼回報
+        let failed_bundles = bundles::failed(&bundle_verdicts);
+        if failed_bundles > 0 {
+            if let Some(pager) = &mut pager {
+                pager.finish();
+            }
+            eprintln!("{}", format!("[{}] {} 個服務組合不成立", ErrorCode::BundleFailed.code(), failed_bundles).red());
+            std::io::stdout().flush()?;
+            status.exit(ErrorCode::BundleFailed);
+        }
+        if let Some(failure) = policy_failure {
+            return Err(errors::coded(ErrorCode::PolicyFailed, failure));
+        }
+        // --min-grade：有端口低於門檻時以錯誤結束
+        if let Some(min) = cli.min_grade {
+            let count = grade::below(&scan_results, min);
+            if count > 0 {
+                return Err(errors::coded(ErrorCode::PolicyFailed, format!("{} 個端口的等級低於 {}", count, min)));
+            }
+        }
+        if !compact_lines.is_empty() {
+            return Err(errors::coded(ErrorCode::PortsUnavailable, format!("{} 個端口無法連線", compact_lines.len())));
+        }
+        if quiet {
+            return Ok(());
+        }
+        if let Some(pager) = &mut pager {
+            pager.finish();
+        }
+        // 互動執行時提供掃描後的操作選單；非終端直接結束
+        if menu::available() {
+            let session = menu::Session {
+                plan: &plan,
+                metadata: &run_metadata,
+                whois: &whois,
+                checks: &check_results,
+                os_guesses: &os_guesses,
+                show_hosts: cli.target.is_some(),
+                result_view,
+            };
+            menu::run(&session, &mut scan_results).await?;
+        }
+    }
+
     Ok(())
 }
 
+// 依 --ports 選出要掃描的端口，已知端口沿用內建表的服務名稱
+// 再套用設定檔的覆蓋與標籤，並依 --tag 篩選
+// common 為內建端口表與使用者端口資料庫合併的結果
+fn select_ports(
+    common: Vec<PortInfo>,
+    spec: Option<&str>,
+    tag_rules: &tags::TagRules,
+    tag_filters: &[String],
+) -> Result<Vec<PortInfo>, String> {
+    let mut ports = match spec {
+        None => common,
+        Some(spec) => portspec::parse_list(spec)?
+            .into_iter()
+            .map(|port| {
+                common
+                    .iter()
+                    .find(|p| p.port == port)
+                    .cloned()
+                    .unwrap_or_else(|| PortInfo::new(port, ranges::UNKNOWN_SERVICE, "Custom"))
+            })
+            .collect(),
+    };
+
+    tag_rules.apply(&mut ports);
+    ports.retain(|port| tags::matches_all(port, tag_filters));
+    if ports.is_empty() {
+        return Err(format!("沒有端口符合標籤: {}", tag_filters.join(", ")));
+    }
+    Ok(ports)
+}
+
 // 顯示程序標題
-fn print_header() {
+fn print_header(metadata: &metadata::RunMetadata) {
     println!("\n{}", "=== 端口掃描工具 ===".bold());
     println!("{}", "檢測端口狀態和服務可用性\n".italic());
+    metadata::display(metadata);
 }
 
-// 顯示網絡
-async fn show_network_info() -> Result<(), Box<dyn Error>> {
-    // 本地IP
-    if let Ok(local_ip) = local_ip_address::local_ip() {
-        println!("{} {}", "本地 IP:".bold(), local_ip);
+// 顯示本地IP；外部IP在背景查詢，於結果中顯示
+fn show_network_info(context: &ScanContext, quiet: bool) {
+    if quiet {
+        return;
+    }
+    if let Some(local_ip) = context.local_ip {
+        println!("{} {}", "本地 IP:".bold(), context.show_ip(local_ip));
     } else {
         println!("{}", "無法取得本地 IP".red());
     }
+}
 
-    // 獲取外部IP
+// 顯示外部IP；查詢尚未完成時最多等待至查詢逾時
+// 連線失敗也只顯示無法取得，讓掃描照常進行並由連線檢查判斷網路狀態
+// --no-external-ip 時不顯示
+async fn show_external_ip(context: &ScanContext, geo: Option<&geosanity::GeoSanity>) {
+    if context.external_ip_disabled() {
+        return;
+    }
     print!("{}", "外部 IP: ".bold());
-    match reqwest::get("https://api.ipify.org").await?.text().await {
-        Ok(ip) => {
-            println!("{}", ip.green());
-            // 使用OnceCell存儲外部IP
-            EXTERNAL_IP.set(ip).unwrap_or_else(|_| println!("警告：外部ip已經設置"));
+    match context.wait_external_ip(context::EXTERNAL_IP_TIMEOUT).await {
+        ExternalIp::Known(ip) => println!("{}", context.show(&ip).green()),
+        ExternalIp::Unavailable(e) => println!("{} {}", "無法取得".red(), format!("({})", e).dimmed()),
+        ExternalIp::Pending => println!("{} {}", "無法取得".red(), "(查詢逾時)".dimmed()),
+    }
+    if let Some(geo) = geo {
+        geosanity::display(geo);
+    }
+}
+
+// 等待背景的位置檢查；--no-geo-sanity 時沒有結果
+async fn finish_geo_check(task: Option<tokio::task::JoinHandle<geosanity::GeoSanity>>) -> Option<geosanity::GeoSanity> {
+    task?.await.ok()
+}
+
+// 目標包含自己的外部 IP 時的提醒；未指定 --target 時掃描的是內建的出站測試位址
+// 結束 --record-session 的記錄；--replay 時提醒記錄中沒有的探測
+fn finish_recording(recorder: Option<&session::Recorder>, replay: Option<&session::Session>, path: Option<&Path>, quiet: bool) {
+    if let (Some(recorder), Some(path)) = (recorder, path) {
+        match recorder.finish() {
+            Ok(probes) if !quiet => println!("已記錄 {} 個探測到 {}", probes, path.display()),
+            Ok(_) => {}
+            Err(e) => eprintln!("{}", e.yellow()),
+        }
+    }
+    let missing = replay.map_or(0, |session| session.prober.missing());
+    if missing > 0 {
+        eprintln!("{}", format!("記錄中沒有 {} 個探測 (視為逾時或失敗)，重播時請使用與錄製相同的選項", missing).yellow());
+    }
+}
+
+async fn external_target_warnings(plan: &ScanPlan, targeted: bool) -> Vec<String> {
+    if !targeted {
+        return Vec::new();
+    }
+    match plan.context.wait_external_ip(context::EXTERNAL_IP_TIMEOUT).await {
+        ExternalIp::Known(ip) => match ip.parse() {
+            Ok(ip) => targets::external_ip_warnings(&plan.targets, ip).iter().map(|w| plan.context.show(w)).collect(),
+            Err(_) => Vec::new(),
         },
-        Err(_) => println!("{}", "無法取得".red()),
+        _ => Vec::new(),
+    }
+}
+
+// 執行掃描並依目標收集結果
+// clear_progress：結束後清除進度列 (接著要交給分頁程式時)
+// checkpoint 為 --resume-file / --resume 的續掃檔，沒有新結果時也定期寫到磁碟
+async fn perform_scan(
+    plan: &ScanPlan,
+    mut checkpoint: Option<resume::Checkpoint>,
+    quiet: bool,
+    clear_progress: bool,
+) -> BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>> {
+    let pb = create_progress_bar(plan.remaining_probes());
+    if quiet {
+        pb.set_draw_target(ProgressDrawTarget::hidden());
+    }
+    let (tx, mut rx) = mpsc::channel::<scanner::ScanRecord>(RESULT_CHANNEL_CAPACITY);
+    let total = plan.remaining_probes();
+    let live = plan.live.clone().filter(|_| !quiet);
+    if let Some(live) = &live {
+        live.attach(&pb);
+    }
+    let collected = live.clone();
+
+    let collector = tokio::spawn(async move {
+        let mut results: BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>> = BTreeMap::new();
+        let (mut received, mut milestone) = (0u128, 0u128);
+        let mut ticker = tokio::time::interval(checkpoint.as_ref().map_or(Duration::from_secs(60), |c| c.interval()));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            let record = tokio::select! {
+                record = rx.recv() => record,
+                _ = ticker.tick() => {
+                    checkpoint.iter_mut().for_each(resume::Checkpoint::sync);
+                    continue;
+                }
+            };
+            let Some(record) = record else {
+                break;
+            };
+            if let Some(checkpoint) = &mut checkpoint {
+                checkpoint.write(&record);
+            }
+            // 進度列不會進入逐字紀錄檔，每完成四分之一記一次
+            received += 1;
+            if total > 0 && received * 4 / total > milestone {
+                milestone = received * 4 / total;
+                transcript::note(&format!("掃描進度: {}/{} ({}%)", received, total, received * 100 / total));
+            }
+            if let Some(live) = &collected {
+                live.finalize(record.host, &record.port, &record.result);
+            }
+            results.entry(record.host).or_default().insert(record.port, record.result);
+        }
+        checkpoint.iter_mut().for_each(resume::Checkpoint::sync);
+        results
+    });
+
+    let keyboard = plan.control.clone().filter(|_| !quiet).and_then(|control| keyboard::Keyboard::start(control, pb.clone()));
+    scanner::run_scan(plan, tx, &pb).await;
+    drop(keyboard);
+    if let Some(live) = &live {
+        live.detach();
+    }
+    finish_progress(plan, &pb, clear_progress);
+    let mut results = collector.await.unwrap_or_default();
+    // 被取消的主機已完成的結果都標記為不完整
+    for host in plan.control.iter().flat_map(|control| control.cancelled()) {
+        for result in results.get_mut(&host).into_iter().flat_map(HashMap::values_mut) {
+            result.cancelled = true;
+        }
+    }
+    results
+}
+
+// --heartbeat / --heartbeat-webhook：stream 為 --output 的 NDJSON 寫入通道
+fn start_heartbeat(plan: &ScanPlan, cli: &Cli, stream: Option<mpsc::Sender<output::Entry>>) -> Option<heartbeat::Heartbeat> {
+    let listeners = heartbeat::Listeners { stream, webhook: cli.heartbeat_webhook.clone(), scan_id: plan.context.scan_id.clone() };
+    heartbeat::start(plan.progress.clone()?, plan.remaining_probes(), cli.heartbeat_interval, listeners)
+}
+
+// webhook 失敗只在結束時提醒一次
+async fn stop_heartbeat(heartbeat: Option<heartbeat::Heartbeat>) {
+    if let Some(warning) = match heartbeat {
+        Some(heartbeat) => heartbeat.stop().await,
+        None => None,
+    } {
+        eprintln!("{}", warning.yellow());
+    }
+}
+
+// 掃描結束時寫入最後的狀態；寫入失敗不影響結果，只提醒
+fn finish_progress_file(progress_file: Option<&progressfile::ProgressFile>, plan: &ScanPlan) {
+    let state = match plan.aborted() {
+        true => progressfile::State::Cancelled,
+        false => progressfile::State::Finished,
+    };
+    if let Some(Err(e)) = progress_file.map(|file| file.finish(state)) {
+        eprintln!("{}", format!("無法寫入進度檔: {}", e).yellow());
+    }
+}
+
+fn audit_outcome(plan: &ScanPlan) -> audit::Outcome {
+    match plan.aborted() {
+        true => audit::Outcome::Aborted,
+        false => audit::Outcome::Completed,
+    }
+}
+
+// 結束進度列；按 q 中止時保留停下的位置並提醒結果不完整
+fn finish_progress(plan: &ScanPlan, pb: &ProgressBar, clear: bool) {
+    let done = pb.position();
+    match (plan.aborted(), clear) {
+        (_, true) => pb.finish_and_clear(),
+        (false, false) => pb.finish_with_message("掃描完成"),
+        (true, false) => pb.abandon_with_message("已中止"),
+    }
+    if plan.aborted() {
+        let message = format!("掃描已中止，結果只包含已完成的 {}/{} 個探測", done, plan.remaining_probes());
+        eprintln!("{}", message.yellow());
+    }
+    let cancelled = plan.control.as_ref().map(|control| control.cancelled()).unwrap_or_default();
+    if !cancelled.is_empty() {
+        let hosts: Vec<String> = cancelled.iter().map(|host| plan.context.show_ip(*host)).collect();
+        eprintln!("{}", format!("已取消 {} 個目標 ({})，這些主機的結果不完整", hosts.len(), hosts.join(", ")).yellow());
+    }
+}
+
+// 對所有目標送出敲門序列，無法送出時中止
+async fn knock_targets(plan: &ScanPlan, knock: &knock::KnockPlan, verbose: bool) -> Result<(), Box<dyn Error>> {
+    for target in &plan.targets {
+        for host in target.addrs() {
+            let elapsed = knock::knock(&plan.context, host, &knock.sequence, knock.delay, |step, at| {
+                if verbose {
+                    println!("  敲門 {} {} (+{}ms)", plan.context.show_ip(host), step, at.as_millis());
+                }
+            })
+            .await?;
+            if verbose {
+                println!("{} {} 敲門完成，耗時 {}", "敲門:".bold(), plan.context.show_ip(host), timefmt::duration(elapsed));
+            }
+        }
+    }
+    Ok(())
+}
+
+// 掃描完成的事件記錄
+fn report_scan_event(log: &eventlog::EventLog, summary: &output::ScanSummary) {
+    let message = match summary.directions {
+        direction::Directions::Both => format!(
+            "掃描完成: {} 個結果，雙向 {}、只能接收 {}、只能發送 {}、不可用 {}",
+            summary.total, summary.both, summary.inbound_only, summary.outbound_only, summary.unavailable
+        ),
+        direction::Directions::Outbound => {
+            format!("掃描完成: {} 個結果 (只測出站)，可連線 {}、不可用 {}", summary.total, summary.outbound_only, summary.unavailable)
+        }
+        direction::Directions::Inbound => {
+            format!("掃描完成: {} 個結果 (只測入站)，可綁定 {}、不可用 {}", summary.total, summary.inbound_only, summary.unavailable)
+        }
+    };
+    log.report(eventlog::EventLevel::Information, eventlog::EVENT_SCAN_COMPLETED, &message, summary);
+}
+
+// 等待背景的事件指令結束後顯示執行結果
+async fn report_hooks(plan: &ScanPlan, quiet: bool) {
+    if let Some(hooks) = &plan.hooks {
+        hooks.wait().await;
+        if !quiet {
+            hooks.display_summary();
+        }
     }
+}
 
+// --concurrency auto 的最終與最高並發數
+// --anonymize-map：所有輸出完成後寫出對照表
+fn write_anonymize_map(path: Option<&std::path::Path>, anonymizer: Option<&anonymize::Anonymizer>, quiet: bool) -> Result<(), Box<dyn Error>> {
+    let (Some(path), Some(anonymizer)) = (path, anonymizer) else {
+        return Ok(());
+    };
+    anonymizer.write_map(path)?;
+    if !quiet {
+        println!("假名對照表已寫入 {}", path.display());
+    }
     Ok(())
 }
 
+// 結束封包擷取並顯示寫入的封包數；已結束時不重複顯示
+fn report_capture(capture: Option<&pcap::Capture>, quiet: bool) {
+    let Some(capture) = capture else {
+        return;
+    };
+    match capture.finish() {
+        Some(Ok(stats)) if !quiet => {
+            println!("已擷取 {} 個封包 ({} 位元組) 至 {}", stats.packets, stats.bytes, capture.path().display());
+        }
+        Some(Err(e)) => eprintln!("{}", e.red()),
+        _ => {}
+    }
+}
 
-static EXTERNAL_IP: OnceCell<String> = OnceCell::const_new();
+fn report_adaptive(plan: &ScanPlan, quiet: bool) {
+    if let (Some(limit), false) = (&plan.adaptive, quiet) {
+        adaptive::display_summary(&limit.summary());
+    }
+}
 
-// 執行掃描
-async fn perform_scan() -> HashMap<PortInfo, ScanResult> {
-    let ports = get_common_ports();
-    let pb = create_progress_bar(ports.len());
-    let mut results = HashMap::new();
+// --bundle：報告、逐筆紀錄、比較表與逐字紀錄封存成一個檔案
+fn write_archive(
+    path: &std::path::Path,
+    report: &report::ScanReport,
+    scan_results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>,
+    transcript: Option<&std::path::Path>,
+) -> Result<(), Box<dyn Error>> {
+    let mut records = Vec::new();
+    for (host, results) in scan_results {
+        for (port, result) in results {
+            let record = scanner::ScanRecord { host: *host, port: port.clone(), result: result.clone(), identity: None };
+            serde_json::to_writer(&mut records, &record)?;
+            records.push(b'\n');
+        }
+    }
+    let mut members = vec![
+        (ArchiveMember::Json, serde_json::to_vec_pretty(report)?),
+        (ArchiveMember::Ndjson, records),
+        (ArchiveMember::Html, matrix::to_html(&matrix::Matrix::pivot(scan_results), report.metadata).into_bytes()),
+    ];
+    // 逐字紀錄只包含封存前已輸出的內容
+    if let Some(transcript) = transcript {
+        std::io::stdout().flush()?;
+        let text = std::fs::read(transcript).map_err(|e| format!("無法讀取逐字紀錄檔 {}: {}", transcript.display(), e))?;
+        members.push((ArchiveMember::Transcript, text));
+    }
+    archive::write(path, report.metadata, &members)
+}
 
-    for port_info in ports {
-        let scan_result = scan_port(&port_info.port).await;
-        results.insert(port_info, scan_result);
-        pb.inc(1);
+// 顯示掃描剖析並視需要寫出原始量測
+// --profile-scan 時記錄掃描後整批執行的階段耗時
+fn record_phase(plan: &ScanPlan, stage: Stage, name: &'static str, started: Instant) {
+    if let Some(profiler) = &plan.profiler {
+        profiler.phase(stage, name, started.elapsed());
     }
+}
 
-    pb.finish_with_message("掃描完成");
-    results
+fn report_profile(plan: &ScanPlan, csv: Option<&std::path::Path>, quiet: bool) -> Result<(), Box<dyn Error>> {
+    let Some(profiler) = &plan.profiler else {
+        return Ok(());
+    };
+
+    if !quiet {
+        let sockets = plan.prober.socket_stats();
+        profile::display_profile(&profile::summarize(profiler, 5), sockets, plan.concurrency);
+        if let Some(limiter) = &plan.per_net {
+            netlimit::display_throttled(limiter);
+        }
+    }
+    if let Some(path) = csv {
+        profile::write_csv(profiler, path).map_err(|e| format!("無法寫入 {}: {}", path.display(), e))?;
+        if !quiet {
+            println!("原始量測已寫入 {}", path.display());
+        }
+    }
+    Ok(())
 }
 
 // 進度條
-fn create_progress_bar(len: usize) -> ProgressBar {
-    let pb = ProgressBar::new(len as u64);
+fn create_progress_bar(len: u128) -> ProgressBar {
+    let pb = ProgressBar::new(len.min(u64::MAX as u128) as u64);
     pb.set_style(
         ProgressStyle::default_bar()
-            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta}) {msg}")
             .unwrap()
             .progress_chars("#>-")
     );
     pb
 }
 
-// 掃描單個端口
-async fn scan_port(port: &u16) -> ScanResult {
-    let inbound = test_inbound_port(*port).await;
-    let outbound = test_outbound_port(*port).await;
-    
-    ScanResult {
-        inbound,
-        outbound,
-    }
-}
-
-// 測試入站連接
-async fn test_inbound_port(port: u16) -> bool {
-    if let Some(ip) = EXTERNAL_IP.get() {
-        if let Ok(addr) = ip.parse::<IpAddr>() {
-            return TcpListener::bind((addr, port)).is_ok();
+// 顯示掃描結果
+fn display_results(
+    host: Option<IpAddr>,
+    results: &HashMap<PortInfo, ScanResult>,
+    os_guess: Option<&osguess::OsGuess>,
+    result_view: &view::ResultView,
+) {
+    match host {
+        Some(host) => {
+            // 由萬用字元目標展開的主機同時列出解析到此位址的名稱
+            println!("\n{}", format!("=== 掃描結果 ({}) ===", result_view.hosts.title(host)).bold());
+            if results.values().any(|result| result.cancelled) {
+                println!("{}", "掃描中途已取消，只有部分端口的結果".yellow());
+            }
+            if let Some(guess) = os_guess {
+                osguess::display(guess);
+            }
+            if let Some(behavior) = closure::CloseBehavior::from_results(results) {
+                closure::display(&behavior);
+            }
         }
+        None => println!("\n{}", "=== 掃描結果 ===".bold()),
     }
-    
-    // 如果外部IP不可用,回退到使用"0.0.0.0"
-    TcpListener::bind(("0.0.0.0", port)).is_ok()
-}
 
-// 測試出站連接
-async fn test_outbound_port(port: u16) -> bool {
-    if let Ok(socket) = TcpSocket::new_v4() {
-        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(208,67,222,222)), port);
-        match timeout(Duration::from_secs(1), socket.connect(addr)).await {
-            Ok(Ok(_)) => return true,
-            _ => return false,
+    // 依 --group-by 分組、--sort 排序顯示結果；服務群組的成員另外合併成一行
+    let columns = portline::Columns::measure(results.keys(), &result_view.layout);
+    let single = results.iter().filter(|(port, _)| port.group.is_none());
+    for (title, entries) in view::arrange(single, result_view) {
+        match title {
+            Some(title) => println!("\n{}", format!("--- {} ---", title).bold()),
+            None => println!(),
+        }
+        // 依端口排序時連續且狀態相同的未知服務端口合併成一行區段
+        let items = match result_view.sort {
+            view::SortBy::Port => ranges::aggregate(entries),
+            _ => entries.into_iter().map(ranges::Item::Port).collect(),
+        };
+        for item in items {
+            match item {
+                ranges::Item::Port((port_info, result)) => display_port(port_info, result, "", result_view, &columns),
+                ranges::Item::Range(range) => println!("{}", ranges::line(&range, &result_view.layout)),
+            }
         }
     }
-    false
-}
 
-// 顯示掃描結果
-fn display_results(results: &HashMap<PortInfo, ScanResult>) {
-    println!("\n{}", "=== 掃描結果 ===".bold());
+    if let Some((count, expected)) = route::mismatches(results.values().filter_map(|r| r.route.as_ref())) {
+        println!("\n{}", format!("⚠ {} 個端口的出站連線未經由 {}", count, expected).red().bold());
+    }
 
-    // 按類別分組顯示結果
-    let categories: HashSet<_> = results.keys().map(|p: &PortInfo| &p.category).collect();
-    
-    for category in categories {
-        println!("\n{}", format!("--- {} ---", category).bold());
-        
-        for (port_info, result) in results.iter().filter(|(p, _)| &p.category == category) {
-            print!("Port {:5} ({:15}): ", port_info.port, port_info.service);
-            
-            match (result.inbound, result.outbound) {
-                (true, true) => println!("{}", "✓ 雙向可用".green()),
-                (true, false) => println!("{}", "↓ 只能接收".yellow()),
-                (false, true) => println!("{}", "↑ 只能發送".yellow()),
-                (false, false) => println!("{}", "✗ 不可用".red()),
+    let groups = groups::summarize(results);
+    if groups.is_empty() {
+        return;
+    }
+    println!("\n{}", "--- 服務群組 ---".bold());
+    let name_width = groups.iter().map(|summary| layout::display_width(&summary.name)).max().unwrap_or(0);
+    for summary in &groups {
+        println!("{}", result_view.layout.clip(&format!("群組 {}: {}", layout::fit(&summary.name, name_width), groups::describe(summary))));
+        if !result_view.expand_groups {
+            continue;
+        }
+        let members = results.iter().filter(|(port, _)| port.group.as_deref() == Some(summary.name.as_str()));
+        for (_, entries) in view::arrange(members, &view::ResultView { group_by: view::GroupBy::None, ..result_view.clone() }) {
+            for (port_info, result) in entries {
+                display_port(port_info, result, "  ", result_view, &columns);
             }
         }
     }
+}
 
+// 單個端口的結果；indent 加在每一行之前 (服務群組的成員)
+fn display_port(port_info: &PortInfo, result: &ScanResult, indent: &str, result_view: &view::ResultView, columns: &portline::Columns) {
+    for line in portline::port_lines(port_info, result, indent, result_view, columns) {
+        println!("{}", line);
+    }
+}
 
-    // 顯示圖例
-    print_legend();
+// 報告結尾的一行摘要；--copy 時同時複製到剪貼簿，失敗只顯示提示
+fn share_summary(line: &ShareLine, copy: bool, capabilities: &capability::Capabilities, quiet: bool) {
+    let text = line.to_string();
+    if !quiet {
+        println!("\n{}", text);
+    }
+    if !copy {
+        return;
+    }
+    let copied = capabilities
+        .require(capability::Capability::Clipboard)
+        .map_err(|e| format!("{}，{}", e, capability::Capability::Clipboard.degraded()))
+        .and_then(|()| share::copy(&text));
+    match copied {
+        Ok(()) if !quiet => println!("{}", "已複製到剪貼簿".dimmed()),
+        Ok(()) => {}
+        Err(e) if quiet => eprintln!("注意: {}", e),
+        Err(e) => println!("{}", format!("注意: {}", e).yellow()),
+    }
 }
 
 // 顯示圖例說明
-fn print_legend() {
+fn print_legend(directions: direction::Directions) {
     println!("\n{}", "圖例說明：".bold());
-    println!("✓ {}: 端口可以接收和發送連接", "雙向可用".green());
-    println!("↓ {}: 端口只接受入站連接", "只能接收".yellow());
-    println!("↑ {}: 端口只允許出站連接", "只能發送".yellow());
-    println!("✗ {}: 端口完全不可用", "不可用".red());
+    match directions {
+        direction::Directions::Both => {
+            println!("✓ {}: 端口可以接收和發送連接", "雙向可用".green());
+            println!("↓ {}: 端口只接受入站連接", "只能接收".yellow());
+            println!("↑ {}: 端口只允許出站連接", "只能發送".yellow());
+            println!("✗ {}: 端口完全不可用", "不可用".red());
+        }
+        direction::Directions::Outbound => {
+            println!("✓ {}: 可以連線到目標端口", "可連線".green());
+            println!("✗ {}: 無法連線到目標端口", "無法連線".red());
+            println!("  {}", "未做入站 (綁定) 測試 (--no-inbound)".dimmed());
+        }
+        direction::Directions::Inbound => {
+            println!("✓ {}: 本機可以綁定此端口", "可綁定".green());
+            println!("✗ {}: 本機無法綁定此端口 (已被佔用或權限不足)", "無法綁定".red());
+            println!("  {}", "未做出站連線測試 (--no-outbound)".dimmed());
+        }
+    }
+    println!("? {}: 結果的信心分數偏低 (例如逾時沒有回應)，可用 --min-confidence 重新探測", "暗色".dimmed());
     
     println!("\n{}", "注意事項：".bold());
     println!("1. 某些端口可能需要管理員權限");
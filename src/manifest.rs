@@ -0,0 +1,461 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::portspec;
+use crate::targets::TargetSpec;
+use crate::{PortInfo, ScanResult};
+
+// 有未宣告的開放端口時的結束代碼 (一般錯誤為 1，網路疑似離線為 3)
+pub const EXIT_UNEXPECTED_OPEN: i32 = 4;
+
+// 服務清單 (--manifest)，例如
+//   hosts:
+//     web-01.example.com: [443, {port: 22, service: ssh}]
+//     "*.db.internal": ["5432", "9100-9102"]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestFile {
+    #[serde(default)]
+    hosts: BTreeMap<String, Vec<Declared>>,
+    // 主機的穩定識別：識別 -> IP 或主機名稱，例如 alice-laptop: [10.0.0.23, alice.lan]
+    #[serde(default)]
+    identities: BTreeMap<String, Vec<String>>,
+}
+
+// 宣告的服務：端口號碼、"起點-終點" 或附服務名稱
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum Declared {
+    Port(u16),
+    Detailed { port: u16, service: Option<String> },
+    Spec(String),
+}
+
+// 一筆主機宣告；pattern 可含 * 萬用字元
+#[derive(Debug, Clone)]
+struct HostEntry {
+    pattern: String,
+    // 端口 -> 宣告的服務名稱
+    ports: BTreeMap<u16, Option<String>>,
+}
+
+#[derive(Debug)]
+pub struct Manifest {
+    pub path: String,
+    entries: Vec<HostEntry>,
+    identities: BTreeMap<String, Vec<String>>,
+}
+
+fn parse_declared(pattern: &str, declared: &[Declared]) -> Result<BTreeMap<u16, Option<String>>, String> {
+    let mut ports = BTreeMap::new();
+    let invalid = |item: &str| format!("服務清單 {}: 無效的端口 {}", pattern, item);
+    for item in declared {
+        match item {
+            Declared::Port(0) | Declared::Detailed { port: 0, .. } => return Err(invalid("0")),
+            Declared::Port(port) => {
+                ports.entry(*port).or_insert(None);
+            }
+            Declared::Detailed { port, service } => {
+                ports.insert(*port, service.clone());
+            }
+            Declared::Spec(spec) => {
+                for port in portspec::parse_range(spec).map_err(|_| invalid(spec))? {
+                    ports.entry(port).or_insert(None);
+                }
+            }
+        }
+    }
+    Ok(ports)
+}
+
+// 簡單的萬用字元比對：* 代表任意長度的字元 (不分大小寫)
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let (pattern, name) = (pattern.to_ascii_lowercase(), name.to_ascii_lowercase());
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let (first, rest) = parts.split_first().expect("split 至少回傳一段");
+    let Some(mut remaining) = name.strip_prefix(first) else {
+        return false;
+    };
+    let Some((last, middle)) = rest.split_last() else {
+        // 沒有萬用字元
+        return remaining.is_empty();
+    };
+    for part in middle {
+        match remaining.find(part) {
+            Some(index) => remaining = &remaining[index + part.len()..],
+            None => return false,
+        }
+    }
+    remaining.ends_with(last)
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("無法讀取服務清單 {}: {}", path.display(), e))?;
+        let file: ManifestFile =
+            serde_yaml::from_str(&text).map_err(|e| format!("服務清單 {} 格式錯誤: {}", path.display(), e))?;
+        let entries = file
+            .hosts
+            .iter()
+            .map(|(pattern, declared)| {
+                Ok(HostEntry {
+                    pattern: pattern.clone(),
+                    ports: parse_declared(pattern, declared)?,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Manifest {
+            path: path.display().to_string(),
+            entries,
+            identities: file.identities,
+        })
+    }
+
+    // 只有 identities 的清單不做端口比對
+    pub fn declares_hosts(&self) -> bool {
+        !self.entries.is_empty()
+    }
+
+    pub fn identities(&self) -> impl Iterator<Item = (&String, &Vec<String>)> {
+        self.identities.iter()
+    }
+
+    // 未指定 --ports 時掃描所有宣告的端口
+    pub fn port_spec(&self) -> String {
+        let ports: BTreeSet<u16> = self.entries.iter().flat_map(|e| e.ports.keys().copied()).collect();
+        ports.iter().map(u16::to_string).collect::<Vec<_>>().join(",")
+    }
+
+    // 完全相符 (主機名稱或 IP) 優先，其次是最具體 (非萬用字元部分最長) 的萬用字元
+    fn find(&self, names: &[String]) -> Option<&HostEntry> {
+        let exact = self
+            .entries
+            .iter()
+            .find(|e| !e.pattern.contains('*') && names.iter().any(|n| n.eq_ignore_ascii_case(&e.pattern)));
+        exact.or_else(|| {
+            self.entries
+                .iter()
+                .filter(|e| e.pattern.contains('*') && names.iter().any(|n| glob_matches(&e.pattern, n)))
+                .max_by_key(|e| e.pattern.replace('*', "").len())
+        })
+    }
+}
+
+// 比對分類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Class {
+    // 已宣告且開放
+    Expected,
+    // 已宣告但未開放
+    Missing,
+    // 開放但未宣告
+    Unexpected,
+}
+
+impl Class {
+    // 以全形空白補齊寬度
+    fn label(self) -> ColoredString {
+        match self {
+            Class::Expected => "符合　".green(),
+            Class::Missing => "缺少　".yellow(),
+            Class::Unexpected => "未宣告".red().bold(),
+        }
+    }
+}
+
+// 單一端口的分類；service 優先使用清單宣告的名稱
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Finding {
+    pub port: u16,
+    pub service: String,
+    pub class: Class,
+}
+
+// 單一主機的比對結果
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct HostReconciliation {
+    pub host: IpAddr,
+    // 掃描目標的主機名稱
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    // 相符的清單項目；未列入清單的主機所有開放端口都視為未宣告
+    pub entry: Option<String>,
+    pub findings: Vec<Finding>,
+    // 已宣告但這次沒有掃描的端口
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub not_scanned: Vec<u16>,
+}
+
+// 服務清單比對報告
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ManifestReport {
+    pub manifest: String,
+    pub expected: usize,
+    pub missing: usize,
+    pub unexpected: usize,
+    pub hosts: Vec<HostReconciliation>,
+}
+
+// 各主機可比對的名稱：掃描目標的主機名稱與 IP
+fn host_names(targets: &[TargetSpec], host: IpAddr) -> (Option<String>, Vec<String>) {
+    let name = targets.iter().find_map(|target| match target {
+        TargetSpec::Host { name, addr } if *addr == host && name != &host.to_string() => Some(name.clone()),
+        _ => None,
+    });
+    let mut names = vec![host.to_string()];
+    names.extend(name.clone());
+    (name, names)
+}
+
+// 依宣告分類每個掃描到的端口；沒有掃描到或掃描端錯誤的端口不列入判斷
+pub fn reconcile(
+    manifest: &Manifest,
+    targets: &[TargetSpec],
+    results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>,
+) -> ManifestReport {
+    let hosts: Vec<HostReconciliation> = results
+        .iter()
+        .map(|(host, host_results)| {
+            let (name, names) = host_names(targets, *host);
+            let entry = manifest.find(&names);
+            let declared = entry.map(|e| &e.ports);
+
+            let mut ports: Vec<(&PortInfo, &ScanResult)> =
+                host_results.iter().filter(|(_, r)| r.error.is_none()).collect();
+            ports.sort_by_key(|(p, _)| p.port);
+            let findings = ports
+                .iter()
+                .filter_map(|(port, result)| {
+                    let declared_service = declared.and_then(|d| d.get(&port.port));
+                    let open = result.directions.open(result.inbound, result.outbound);
+                    let class = match (declared_service.is_some(), open) {
+                        (true, true) => Class::Expected,
+                        (true, false) => Class::Missing,
+                        (false, true) => Class::Unexpected,
+                        (false, false) => return None,
+                    };
+                    let service = declared_service.cloned().flatten().unwrap_or_else(|| port.service.clone());
+                    Some(Finding { port: port.port, service, class })
+                })
+                .collect();
+            let scanned: BTreeSet<u16> = host_results.keys().map(|p| p.port).collect();
+            let not_scanned = declared
+                .map(|d| d.keys().copied().filter(|p| !scanned.contains(p)).collect())
+                .unwrap_or_default();
+
+            HostReconciliation {
+                host: *host,
+                name,
+                entry: entry.map(|e| e.pattern.clone()),
+                findings,
+                not_scanned,
+            }
+        })
+        .collect();
+
+    let count = |class: Class| hosts.iter().flat_map(|h| &h.findings).filter(|f| f.class == class).count();
+    ManifestReport {
+        manifest: manifest.path.clone(),
+        expected: count(Class::Expected),
+        missing: count(Class::Missing),
+        unexpected: count(Class::Unexpected),
+        hosts,
+    }
+}
+
+// 顯示比對報告；符合的端口只計數，缺少與未宣告逐一列出
+pub fn display_report(report: &ManifestReport) {
+    println!("\n{}", format!("=== 服務清單比對 ({}) ===", report.manifest).bold());
+    for host in &report.hosts {
+        let count = |class: Class| host.findings.iter().filter(|f| f.class == class).count();
+        let label = match &host.name {
+            Some(name) => format!("{} ({})", host.host, name),
+            None => host.host.to_string(),
+        };
+        let entry = match &host.entry {
+            Some(pattern) => format!("清單項目 {}", pattern).dimmed(),
+            None => "未列入清單".yellow(),
+        };
+        println!(
+            "{}  {}  符合 {}、缺少 {}、未宣告 {}",
+            label.bold(),
+            entry,
+            count(Class::Expected),
+            count(Class::Missing),
+            count(Class::Unexpected)
+        );
+        for finding in host.findings.iter().filter(|f| f.class != Class::Expected) {
+            println!("    {} Port {:5} ({})", finding.class.label(), finding.port, finding.service);
+        }
+        if !host.not_scanned.is_empty() {
+            let ports: Vec<String> = host.not_scanned.iter().map(u16::to_string).collect();
+            println!("{}", format!("    已宣告但未掃描: {}", ports.join(", ")).dimmed());
+        }
+    }
+    let summary = format!(
+        "總計: 符合 {}、缺少 {}、未宣告的開放端口 {}",
+        report.expected, report.missing, report.unexpected
+    );
+    if report.unexpected > 0 {
+        println!("{}", summary.red().bold());
+    } else {
+        println!("{}", summary.green().bold());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::ScanError;
+    use crate::testutil::{host, scan_result, TempPath};
+
+    const MANIFEST: &str = "\
+hosts:
+  web-01.example.com: [443, {port: 22, service: ssh-admin}]
+  \"*.example.com\": [\"80\", \"9100-9101\"]
+  \"*.db.example.com\": [5432]
+  192.0.2.9: [53]
+identities:
+  alice-laptop: [192.0.2.23, alice.lan]
+";
+
+    fn manifest(text: &str) -> Result<Manifest, String> {
+        let file = TempPath::with("services.yaml", text);
+        Manifest::load(file.path())
+    }
+
+    fn named(name: &str, n: u8) -> TargetSpec {
+        TargetSpec::Host { name: name.to_string(), addr: host(n) }
+    }
+
+    fn port(port: u16) -> PortInfo {
+        PortInfo::new(port, "Test", "Test")
+    }
+
+    // 主機 -> (端口, 是否開放)
+    fn results(hosts: &[(u8, &[(u16, bool)])]) -> BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>> {
+        hosts
+            .iter()
+            .map(|(n, ports)| (host(*n), ports.iter().map(|&(p, open)| (port(p), scan_result(open))).collect()))
+            .collect()
+    }
+
+    fn classes(report: &HostReconciliation) -> Vec<(u16, Class)> {
+        report.findings.iter().map(|f| (f.port, f.class)).collect()
+    }
+
+    #[test]
+    fn globs_match_case_insensitively() {
+        for (pattern, name, expected) in [
+            ("*.example.com", "web-01.example.com", true),
+            ("*.example.com", "WEB.Example.COM", true),
+            ("*.example.com", "example.com", false),
+            ("web-*.example.com", "web-07.example.com", true),
+            ("web-*.example.com", "db-07.example.com", false),
+            ("*-*.lan", "a-b.lan", true),
+            ("*-*.lan", "ab.lan", false),
+            ("*", "anything", true),
+            ("host", "host", true),
+            ("host", "host2", false),
+        ] {
+            assert_eq!(glob_matches(pattern, name), expected, "{} ~ {}", pattern, name);
+        }
+    }
+
+    #[test]
+    fn manifests_parse_ports_ranges_and_services() {
+        let manifest = manifest(MANIFEST).unwrap();
+        assert!(manifest.declares_hosts());
+        assert_eq!(manifest.port_spec(), "22,53,80,443,5432,9100,9101");
+        let web = manifest.find(&["web-01.example.com".to_string()]).unwrap();
+        assert_eq!(web.ports.get(&22), Some(&Some("ssh-admin".to_string())));
+        assert_eq!(web.ports.get(&443), Some(&None));
+        assert_eq!(manifest.identities().map(|(k, v)| (k.as_str(), v.len())).collect::<Vec<_>>(), vec![("alice-laptop", 2)]);
+
+        let only_identities = self::manifest("identities:\n  printer: [192.0.2.50]\n").unwrap();
+        assert!(!only_identities.declares_hosts());
+
+        assert_eq!(self::manifest("hosts:\n  a: [0]\n").unwrap_err(), "服務清單 a: 無效的端口 0");
+        assert_eq!(self::manifest("hosts:\n  a: [\"90-80\"]\n").unwrap_err(), "服務清單 a: 無效的端口 90-80");
+        assert!(self::manifest("servers: {}\n").unwrap_err().contains("格式錯誤"));
+    }
+
+    #[test]
+    fn exact_entries_beat_the_most_specific_wildcard() {
+        let manifest = manifest(MANIFEST).unwrap();
+        let find = |names: &[&str]| {
+            let names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+            manifest.find(&names).map(|e| e.pattern.clone())
+        };
+        assert_eq!(find(&["192.0.2.1", "web-01.example.com"]).as_deref(), Some("web-01.example.com"));
+        assert_eq!(find(&["192.0.2.1", "WEB-01.example.com"]).as_deref(), Some("web-01.example.com"));
+        assert_eq!(find(&["192.0.2.2", "pg.db.example.com"]).as_deref(), Some("*.db.example.com"));
+        assert_eq!(find(&["192.0.2.3", "mail.example.com"]).as_deref(), Some("*.example.com"));
+        assert_eq!(find(&["192.0.2.9"]).as_deref(), Some("192.0.2.9"));
+        assert_eq!(find(&["192.0.2.4", "other.test"]), None);
+    }
+
+    #[test]
+    fn findings_are_classified_three_ways() {
+        let manifest = manifest(MANIFEST).unwrap();
+        let targets = vec![named("web-01.example.com", 1), named("pg.db.example.com", 2), TargetSpec::Host { name: "192.0.2.4".to_string(), addr: host(4) }];
+        let mut scanned = results(&[
+            (1, &[(22, true), (443, false), (80, false), (8080, true), (25, false)]),
+            (2, &[(5432, true), (6379, true)]),
+            (4, &[(22, true), (80, false)]),
+        ]);
+        // 掃描端錯誤不能當成缺少
+        let mut failed = scan_result(false);
+        failed.error = Some(ScanError::TooManyOpenFiles);
+        scanned.get_mut(&host(2)).unwrap().insert(port(5433), failed.clone());
+
+        let report = reconcile(&manifest, &targets, &scanned);
+        assert_eq!((report.expected, report.missing, report.unexpected), (2, 1, 3));
+
+        let web = &report.hosts[0];
+        assert_eq!(web.name.as_deref(), Some("web-01.example.com"));
+        assert_eq!(web.entry.as_deref(), Some("web-01.example.com"));
+        // 完全相符的項目不會再套用 *.example.com 的 80 端口
+        assert_eq!(classes(web), vec![(22, Class::Expected), (443, Class::Missing), (8080, Class::Unexpected)]);
+        assert_eq!(web.findings[0].service, "ssh-admin");
+        assert_eq!(web.findings[2].service, "Test");
+
+        let db = &report.hosts[1];
+        assert_eq!(db.entry.as_deref(), Some("*.db.example.com"));
+        assert_eq!(classes(db), vec![(5432, Class::Expected), (6379, Class::Unexpected)]);
+
+        // 未列入清單的主機，所有開放端口都算未宣告
+        let unlisted = &report.hosts[2];
+        assert_eq!((unlisted.name.clone(), unlisted.entry.clone()), (None, None));
+        assert_eq!(classes(unlisted), vec![(22, Class::Unexpected)]);
+    }
+
+    #[test]
+    fn declared_ports_outside_the_scan_are_reported_separately() {
+        let manifest = manifest(MANIFEST).unwrap();
+        let targets = vec![named("mail.example.com", 3)];
+        let report = reconcile(&manifest, &targets, &results(&[(3, &[(80, true)])]));
+        let mail = &report.hosts[0];
+        assert_eq!(classes(mail), vec![(80, Class::Expected)]);
+        assert_eq!(mail.not_scanned, vec![9100, 9101]);
+        assert_eq!((report.expected, report.missing, report.unexpected), (1, 0, 0));
+
+        // IP 也能直接對應清單項目
+        let report = reconcile(&manifest, &[], &results(&[(9, &[(53, false)])]));
+        assert_eq!(classes(&report.hosts[0]), vec![(53, Class::Missing)]);
+    }
+
+    #[test]
+    fn inbound_only_scans_judge_the_inbound_result() {
+        let manifest = manifest(MANIFEST).unwrap();
+        let listening = ScanResult { inbound: true, outbound: false, directions: crate::direction::Directions::Inbound, ..scan_result(false) };
+        let results = BTreeMap::from([(host(9), HashMap::from([(port(8080), listening)]))]);
+        let report = reconcile(&manifest, &[], &results);
+        assert_eq!(classes(&report.hosts[0]), vec![(8080, Class::Unexpected)]);
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+use colored::*;
+use crate::layout::{self, fit, Layout};
+use crate::metadata::RunMetadata;
+use crate::netblocks::BlockSummary;
+use crate::output::csv_field;
+use crate::{PortInfo, ScanResult};
+
+// 主機欄位最多顯示的字元數，過長的位址會被截斷
+const MAX_COLUMN: usize = 15;
+
+// 端口欄位 ("22 (SSH)") 最多佔用的寬度
+const MAX_ROW_LABEL: usize = 24;
+
+// 單格狀態：(入站, 出站)，None 代表該主機沒有掃描這個端口
+type Cell = Option<(bool, bool)>;
+
+// 端口 x 主機的狀態表
+pub struct Matrix {
+    pub hosts: Vec<IpAddr>,
+    pub rows: Vec<(PortInfo, Vec<Cell>)>,
+    // --blocks 時每一欄主機所屬的網段標題；主機依網段排列
+    pub blocks: Vec<String>,
+}
+
+impl Matrix {
+    // 將 (主機, 端口) 結果轉成以端口為列、主機為欄的表
+    pub fn pivot(results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>) -> Self {
+        let hosts: Vec<IpAddr> = results.keys().copied().collect();
+        let ports: BTreeMap<(u16, &str, &str), &PortInfo> = results
+            .values()
+            .flat_map(|r| r.keys())
+            .map(|p| ((p.port, p.service.as_str(), p.category.as_str()), p))
+            .collect();
+
+        let rows = ports
+            .into_values()
+            .map(|info| {
+                let cells = hosts
+                    .iter()
+                    .map(|host| results[host].get(info).map(|r| r.directions.normalize(r.inbound, r.outbound)))
+                    .collect();
+                (info.clone(), cells)
+            })
+            .collect();
+
+        Matrix { hosts, rows, blocks: Vec::new() }
+    }
+
+    // 依網段排列主機欄位，並記錄每一欄所屬的網段
+    pub fn pivot_blocks(results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>, blocks: &[BlockSummary]) -> Self {
+        let ordered = blocks.iter().flat_map(|block| block.hosts.iter().map(move |host| (block, host)));
+        let mut matrix = Matrix::pivot(results);
+        let columns: HashMap<IpAddr, usize> = matrix.hosts.iter().enumerate().map(|(i, host)| (*host, i)).collect();
+        let order: Vec<(usize, String)> =
+            ordered.filter_map(|(block, host)| Some((*columns.get(host)?, block.title()))).collect();
+        matrix.hosts = order.iter().map(|(i, _)| matrix.hosts[*i]).collect();
+        for (_, cells) in &mut matrix.rows {
+            *cells = order.iter().map(|(i, _)| cells[*i]).collect();
+        }
+        matrix.blocks = order.into_iter().map(|(_, title)| title).collect();
+        matrix
+    }
+
+    // 連續的欄位區段：(網段標題, 起, 迄)；沒有網段時為整個表
+    fn segments(&self) -> Vec<(Option<&str>, usize, usize)> {
+        if self.blocks.is_empty() {
+            return vec![(None, 0, self.hosts.len())];
+        }
+        let mut segments: Vec<(Option<&str>, usize, usize)> = Vec::new();
+        for (i, title) in self.blocks.iter().enumerate() {
+            match segments.last_mut() {
+                Some((Some(last), _, end)) if *last == title.as_str() => *end = i + 1,
+                _ => segments.push((Some(title), i, i + 1)),
+            }
+        }
+        segments
+    }
+
+    // 各主機狀態不同的端口
+    pub fn inconsistent(&self) -> Vec<&(PortInfo, Vec<Cell>)> {
+        self.rows.iter().filter(|(_, cells)| !consistent(cells)).collect()
+    }
+}
+
+// 一列中所有主機的狀態是否相同
+fn consistent(cells: &[Cell]) -> bool {
+    cells.windows(2).all(|pair| pair[0] == pair[1])
+}
+
+// 狀態符號，與結果列表的圖例一致
+fn symbol(cell: Cell) -> &'static str {
+    match cell {
+        Some((true, true)) => "✓",
+        Some((true, false)) => "↓",
+        Some((false, true)) => "↑",
+        Some((false, false)) => "✗",
+        None => "-",
+    }
+}
+
+fn colored_symbol(cell: Cell) -> ColoredString {
+    match cell {
+        Some((true, true)) => symbol(cell).green(),
+        Some((false, false)) => symbol(cell).red(),
+        Some(_) => symbol(cell).yellow(),
+        None => symbol(cell).dimmed(),
+    }
+}
+
+// 在終端顯示狀態表；主機太多時分頁，每頁放得下多少欄就顯示多少
+pub fn display_matrix(matrix: &Matrix, screen: &Layout) {
+    println!("\n{}", "=== 跨主機比較 ===".bold());
+    if matrix.hosts.is_empty() {
+        println!("沒有結果");
+        return;
+    }
+
+    let labels: Vec<String> = matrix.hosts.iter().map(|h| h.to_string()).collect();
+    let column = labels.iter().map(|l| l.len()).max().unwrap_or(1).clamp(3, MAX_COLUMN);
+    let names: Vec<String> = matrix.rows.iter().map(|(port, _)| format!("{} ({})", port.port, port.service)).collect();
+    let row_label = names.iter().map(|name| layout::display_width(name)).max().unwrap_or(0).clamp(4, MAX_ROW_LABEL.min(screen.width / 2));
+    let per_page = ((screen.width.saturating_sub(row_label + 1)) / (column + 1)).max(1);
+
+    // 有網段時每個網段各自分頁
+    for (title, first, last) in matrix.segments() {
+        if let Some(title) = title {
+            println!("\n{}", format!("--- {} ---", title).cyan());
+        }
+        let pages = (last - first).div_ceil(per_page);
+        for page in 0..pages {
+            let start = first + page * per_page;
+            let end = (start + per_page).min(last);
+            if pages > 1 {
+                println!("\n{}", format!("主機 {}-{} / {}", start - first + 1, end - first, last - first).italic());
+            }
+
+            let header: Vec<String> = labels[start..end].iter().map(|l| fit(l, column)).collect();
+            println!("{} {}", fit("端口", row_label), header.join(" "));
+
+            for ((_, cells), name) in matrix.rows.iter().zip(&names) {
+                let cells: Vec<String> = cells[start..end]
+                    .iter()
+                    .map(|cell| format!("{}{}", colored_symbol(*cell), " ".repeat(column - 1)))
+                    .collect();
+                println!("{} {}", fit(name, row_label), cells.join(" "));
+            }
+        }
+    }
+
+    let inconsistent = matrix.inconsistent();
+    println!("\n{}", format!("--- 不一致端口 ({}) ---", inconsistent.len()).bold());
+    if inconsistent.is_empty() {
+        println!("所有主機的端口狀態一致");
+    }
+    for (port, cells) in inconsistent {
+        // 依狀態列出主機，方便看出少數派
+        let mut by_state: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+        for (host, cell) in matrix.hosts.iter().zip(cells) {
+            by_state.entry(symbol(*cell)).or_default().push(host.to_string());
+        }
+        let parts: Vec<String> = by_state
+            .iter()
+            .map(|(state, hosts)| format!("{} {}", state, hosts.join(", ")))
+            .collect();
+        println!("{}", screen.clip(&format!("Port {:5} ({}): {}", port.port, port.service, parts.join("  |  "))));
+    }
+}
+
+// 以 CSV 輸出：每列一個端口，每欄一個主機；開頭以 # 註解列出執行資訊
+pub fn to_csv(matrix: &Matrix, metadata: &RunMetadata) -> String {
+    let mut out = String::new();
+    for (key, value) in metadata.entries() {
+        out.push_str(&format!("# {}: {}\n", key, value.replace(['\r', '\n'], " ")));
+    }
+    out.push_str("port,service,category,tags");
+    for host in &matrix.hosts {
+        out.push(',');
+        out.push_str(&csv_field(&host.to_string()));
+    }
+    out.push_str(",consistent\n");
+    // --blocks 時第二列為各主機所屬的網段
+    if !matrix.blocks.is_empty() {
+        out.push_str("block,,,");
+        for title in &matrix.blocks {
+            out.push(',');
+            out.push_str(&csv_field(title));
+        }
+        out.push_str(",\n");
+    }
+
+    for (port, cells) in &matrix.rows {
+        out.push_str(&format!(
+            "{},{},{},{}",
+            port.port,
+            csv_field(&port.service),
+            csv_field(&port.category),
+            csv_field(&port.tags.join(";"))
+        ));
+        for cell in cells {
+            out.push(',');
+            out.push_str(symbol(*cell));
+        }
+        out.push_str(if consistent(cells) { ",true\n" } else { ",false\n" });
+    }
+    out
+}
+
+pub fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// 以 HTML 表格輸出，不一致的列會標示出來
+pub fn to_html(matrix: &Matrix, metadata: &RunMetadata) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>跨主機比較</title>\n<style>\
+         table{border-collapse:collapse;font-family:monospace}td,th{border:1px solid #ccc;padding:2px 6px;text-align:center}\
+         .both{color:#080}.none{color:#c00}.partial{color:#b80}tr.diff{background:#fff3cd}\
+         </style></head><body>\n<dl>\n",
+    );
+    for (key, value) in metadata.entries() {
+        out.push_str(&format!("<dt>{}</dt><dd>{}</dd>\n", html_escape(&key), html_escape(&value)));
+    }
+    out.push_str("</dl>\n<table>\n");
+    if !matrix.blocks.is_empty() {
+        out.push_str("<tr><th colspan=\"2\"></th>");
+        for (title, first, last) in matrix.segments() {
+            out.push_str(&format!("<th colspan=\"{}\">{}</th>", last - first, html_escape(title.unwrap_or_default())));
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("<tr><th>端口</th><th>服務</th>");
+    for host in &matrix.hosts {
+        out.push_str(&format!("<th>{}</th>", html_escape(&host.to_string())));
+    }
+    out.push_str("</tr>\n");
+
+    for (port, cells) in &matrix.rows {
+        let class = if consistent(cells) { "" } else { " class=\"diff\"" };
+        out.push_str(&format!("<tr{}><td>{}</td><td>{}</td>", class, port.port, html_escape(&port.service)));
+        for cell in cells {
+            let state = match cell {
+                Some((true, true)) => "both",
+                Some((false, false)) => "none",
+                Some(_) => "partial",
+                None => "",
+            };
+            out.push_str(&format!("<td class=\"{}\">{}</td>", state, symbol(*cell)));
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("</table>\n</body></html>\n");
+    out
+}
+
+// 依副檔名寫出 CSV 或 HTML
+pub fn write(matrix: &Matrix, path: &Path, metadata: &RunMetadata) -> io::Result<()> {
+    let content = match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+        Some("html") | Some("htm") => to_html(matrix, metadata),
+        Some("csv") => to_csv(matrix, metadata),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "無法從副檔名判斷格式 (可用 .csv、.html)",
+            ))
+        }
+    };
+    fs::write(path, content)
+}
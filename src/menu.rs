@@ -0,0 +1,213 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::io::{self, IsTerminal, Write};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use colored::*;
+use crate::checks::CheckOutcome;
+use crate::keyboard::ScanControl;
+use crate::metadata::RunMetadata;
+use crate::portspec;
+use crate::osguess::OsGuess;
+use crate::output::{self, OutputFormat, ScanSummary};
+use crate::report::{self, PortReport};
+use crate::scanner::{ScanPlan, ScanRecord};
+use crate::targets::TargetSpec;
+use crate::whois::WhoisInfo;
+use crate::{view, PortInfo, ScanResult};
+
+// 標準輸入與輸出都是終端時才顯示選單
+pub fn available() -> bool {
+    io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+// 選單操作需要的掃描資料；結果在重新掃描後就地更新
+pub struct Session<'a> {
+    pub plan: &'a ScanPlan,
+    pub metadata: &'a RunMetadata,
+    pub whois: &'a [(TargetSpec, Result<WhoisInfo, String>)],
+    pub checks: &'a [(IpAddr, Vec<CheckOutcome>)],
+    pub os_guesses: &'a BTreeMap<IpAddr, OsGuess>,
+    // 顯示結果時是否加上主機標題 (有指定 --target)
+    pub show_hosts: bool,
+    pub result_view: view::ResultView,
+}
+
+// 顯示提示並讀取一行；輸入結束 (EOF) 時回傳 None
+fn prompt(text: &str) -> Option<String> {
+    print!("{}", text);
+    let _ = io::stdout().flush();
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim().to_string()),
+    }
+}
+
+// 不是雙向可用的端口
+fn failed(results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>) -> BTreeMap<IpAddr, Vec<PortInfo>> {
+    results
+        .iter()
+        .map(|(host, ports)| {
+            let mut failed: Vec<PortInfo> =
+                ports.iter().filter(|(_, r)| !r.directions.available(r.inbound, r.outbound)).map(|(port, _)| port.clone()).collect();
+            failed.sort_by_key(|port| port.port);
+            (*host, failed)
+        })
+        .filter(|(_, ports)| !ports.is_empty())
+        .collect()
+}
+
+// 掃描後的操作選單；輸入 q、選擇離開或輸入結束時返回
+pub async fn run(session: &Session<'_>, results: &mut BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>) -> Result<(), Box<dyn Error>> {
+    loop {
+        let pending: usize = failed(results).values().map(Vec::len).sum();
+        println!("\n{}", "=== 下一步 ===".bold());
+        println!("1. 重新掃描未雙向可用的端口 ({} 個)", pending);
+        println!("2. 將結果存檔");
+        println!("3. 顯示端口詳細資訊");
+        println!("4. 離開");
+        let Some(choice) = prompt("請選擇 [1-4]: ") else {
+            return Ok(());
+        };
+        match choice.to_lowercase().as_str() {
+            "1" => rescan(session, results).await,
+            "2" => {
+                if let Err(e) = save(session, results) {
+                    println!("{}", format!("存檔失敗: {}", e).red());
+                }
+            }
+            "3" => details(session, results),
+            "4" | "q" => return Ok(()),
+            "" => {}
+            other => println!("{}", format!("無效的選項: {}", other).yellow()),
+        }
+    }
+}
+
+// 只對未雙向可用的端口重新掃描，新的結果取代原本的結果後重新顯示
+async fn rescan(session: &Session<'_>, results: &mut BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>) {
+    // 匿名化後的結果只有假名，無法再連線
+    if session.plan.context.anonymizer.is_some() {
+        println!("{}", "--anonymize 時無法重新掃描".yellow());
+        return;
+    }
+    let failed = failed(results);
+    if failed.is_empty() {
+        println!("所有端口都是雙向可用");
+        return;
+    }
+    let (mut probed, mut changed) = (0, 0);
+    for (host, ports) in failed {
+        // 保留原本目標的主機名稱，虛擬主機探測才會相同
+        let target = session
+            .plan
+            .targets
+            .iter()
+            .find(|target| matches!(target, TargetSpec::Host { addr, .. } if *addr == host))
+            .cloned()
+            .unwrap_or_else(|| TargetSpec::Host { name: host.to_string(), addr: host });
+        let plan = ScanPlan {
+            targets: vec![target],
+            ports,
+            completed: Arc::new(HashSet::new()),
+            // 上一次掃描可能已按 q 中止
+            control: session.plan.control.as_ref().map(|_| Arc::new(ScanControl::default())),
+            profiler: None,
+            live: None,
+            progress: None,
+            ..session.plan.clone()
+        };
+        let fresh = crate::perform_scan(&plan, None, false, true).await;
+        let Some(host_results) = results.get_mut(&host) else {
+            continue;
+        };
+        for (port, result) in fresh.into_values().flatten() {
+            probed += 1;
+            if host_results.get(&port).is_some_and(|old| (old.inbound, old.outbound) != (result.inbound, result.outbound)) {
+                changed += 1;
+            }
+            host_results.insert(port, result);
+        }
+    }
+    for (host, host_results) in results.iter() {
+        crate::display_results(session.show_hosts.then_some(*host), host_results, session.os_guesses.get(host), &session.result_view);
+    }
+    println!("\n重新掃描 {} 個端口，{} 個結果改變", probed, changed);
+}
+
+// 詢問格式與路徑後寫入：JSON 報告 (同 --json) 或 --output 的逐筆格式
+fn save(session: &Session<'_>, results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>) -> Result<(), Box<dyn Error>> {
+    let Some(format) = prompt("格式 [1 JSON 報告 / 2 NDJSON / 3 CSV / 4 SQLite / 5 純文字]: ") else {
+        return Ok(());
+    };
+    let (format, extension) = match format.to_lowercase().as_str() {
+        "1" | "json" => (None, "json"),
+        "2" | "ndjson" => (Some(OutputFormat::Ndjson), "ndjson"),
+        "3" | "csv" => (Some(OutputFormat::Csv), "csv"),
+        "4" | "sqlite" => (Some(OutputFormat::Sqlite), "db"),
+        "5" | "plain" => (Some(OutputFormat::Plain), "txt"),
+        other => return Err(format!("無效的格式: {}", other).into()),
+    };
+    let default = format!("portscanner-{}.{}", session.metadata.started_at, extension);
+    let Some(path) = prompt(&format!("檔案 [{}]: ", default)) else {
+        return Ok(());
+    };
+    let path = PathBuf::from(if path.is_empty() { default } else { path });
+
+    match format {
+        None => {
+            let report = report::build(session.metadata, None, None, results, session.whois, session.checks, None);
+            std::fs::write(&path, serde_json::to_string_pretty(&report)?)?;
+        }
+        Some(format) => {
+            let mut sink = output::open_sink(&path, format, session.metadata)?;
+            let mut summary = ScanSummary::default();
+            for (host, ports) in results {
+                let mut ports: Vec<_> = ports.iter().collect();
+                ports.sort_by_key(|(port, _)| port.port);
+                for (port, result) in ports {
+                    let record = ScanRecord { host: *host, port: port.clone(), result: result.clone(), identity: None };
+                    summary.add(&record);
+                    sink.on_result(&record).map_err(|e| e.to_string())?;
+                }
+            }
+            sink.on_summary(&summary).map_err(|e| e.to_string())?;
+        }
+    }
+    println!("結果已寫入 {}", path.display());
+    Ok(())
+}
+
+// 端口的完整結果：一般顯示加上所有 JSON 欄位
+fn details(session: &Session<'_>, results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>) {
+    let Some(input) = prompt("端口 (例如 443 或 10.0.0.1:443): ") else {
+        return;
+    };
+    let (host, port) = match input.rsplit_once(':') {
+        Some((host, port)) => (Some(host.trim_matches(['[', ']'])), port),
+        None => (None, input.as_str()),
+    };
+    let Ok(port) = portspec::parse_port(port) else {
+        println!("{}", format!("無效的端口: {}", input).yellow());
+        return;
+    };
+    let mut found = false;
+    for (address, ports) in results {
+        if host.is_some_and(|host| host != address.to_string()) {
+            continue;
+        }
+        for (info, result) in ports.iter().filter(|(info, _)| info.port == port) {
+            found = true;
+            println!("\n{}", format!("=== {} ===", std::net::SocketAddr::new(*address, port)).bold());
+            crate::display_port(info, result, "", &session.result_view, &crate::portline::Columns::measure([info], &session.result_view.layout));
+            if let Ok(json) = serde_json::to_string_pretty(&PortReport { port: info, result }) {
+                println!("{}", json.dimmed());
+            }
+        }
+    }
+    if !found {
+        println!("{}", format!("結果中沒有端口 {}", input).yellow());
+    }
+}
@@ -0,0 +1,436 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::confidence::LOW_CONFIDENCE;
+use crate::layout;
+use crate::ranges::PortRange;
+use crate::report::SCHEMA_VERSION;
+
+// 合併報告的格式版本
+pub const MERGE_SCHEMA_VERSION: u32 = 1;
+
+// 來源名稱取自此註記 (--annotate vantage=office-A)，沒有時使用檔名
+const VANTAGE_ANNOTATION: &str = "vantage";
+
+// 單一地點看到的端口狀態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PortState {
+    Open,
+    // 收到 RST
+    Closed,
+    // 逾時或不可達
+    Filtered,
+    // 掃描端錯誤，不代表端口狀態
+    Error,
+}
+
+impl PortState {
+    fn reachable(self) -> Option<bool> {
+        match self {
+            PortState::Open => Some(true),
+            PortState::Closed | PortState::Filtered => Some(false),
+            PortState::Error => None,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            PortState::Open => "✓",
+            PortState::Closed => "✗",
+            PortState::Filtered => "·",
+            PortState::Error => "!",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Consensus {
+    Open,
+    Closed,
+    Filtered,
+    // 有地點可連線，有地點無法連線
+    Split,
+    // 沒有來源有可用的結果
+    Unknown,
+}
+
+impl Consensus {
+    fn label(self) -> ColoredString {
+        match self {
+            Consensus::Open => "開放".green(),
+            Consensus::Closed => "關閉".normal(),
+            Consensus::Filtered => "被過濾".dimmed(),
+            Consensus::Split => "分歧".yellow().bold(),
+            Consensus::Unknown => "未知".dimmed(),
+        }
+    }
+}
+
+// 一份輸入報告
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Source {
+    pub name: String,
+    pub file: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    // 沒有此欄位的舊版報告為 None
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+}
+
+// 單一來源對一個端口的觀察
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Observation {
+    pub state: PortState,
+    // 該地點掃描的 IP；以主機名稱對齊時各地點可能不同
+    pub address: IpAddr,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct MergedPort {
+    pub port: u16,
+    pub service: String,
+    pub consensus: Consensus,
+    // 採用的來源的信心分數合併後的結果
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+    // 各地點對可否連線的看法不同 (包括低信心的來源)
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub disagreement: bool,
+    // 依來源名稱；沒有掃描此端口的來源不列出
+    pub sources: BTreeMap<String, Observation>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct MergedHost {
+    // 識別名稱 (例如掃描目標的主機名稱) 或 IP
+    pub host: String,
+    pub ports: Vec<MergedPort>,
+}
+
+// portscanner merge 的結果
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct MergedReport {
+    pub schema_version: u32,
+    pub sources: Vec<Source>,
+    pub hosts: Vec<MergedHost>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub disagreements: Vec<String>,
+    // 輸入格式的相容性提醒
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+// 報告中的一台主機
+struct LoadedHost {
+    address: IpAddr,
+    identity: Option<String>,
+    ports: Vec<(u16, String, Observation)>,
+}
+
+// 對齊中的端口：服務名稱與各來源的觀察
+type AlignedPorts = BTreeMap<u16, (String, BTreeMap<String, Observation>)>;
+
+struct Loaded {
+    source: Source,
+    vantage: Option<String>,
+    hosts: Vec<LoadedHost>,
+}
+
+// 以 JSON 值讀取，只取合併需要的欄位：不同版本新增的欄位不影響讀取，缺少的欄位使用保守的預設值
+fn load(path: &Path, warnings: &mut Vec<String>) -> Result<Loaded, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("無法讀取 {}: {}", path.display(), e))?;
+    let report: Value = serde_json::from_str(&text).map_err(|e| format!("{} 不是有效的 JSON: {}", path.display(), e))?;
+    let hosts = report
+        .get("hosts")
+        .and_then(Value::as_array)
+        .ok_or_else(|| format!("{} 不是 --json 掃描報告 (沒有 hosts)", path.display()))?;
+
+    let schema_version = report.get("schema_version").and_then(Value::as_u64);
+    match schema_version {
+        None => warnings.push(format!("{} 沒有 schema_version，視為舊版報告讀取", path.display())),
+        Some(v) if v > SCHEMA_VERSION as u64 => {
+            warnings.push(format!("{} 的格式版本 {} 比此版本支援的 {} 新，未知的欄位會被忽略", path.display(), v, SCHEMA_VERSION))
+        }
+        _ => {}
+    }
+    let metadata = report.get("metadata");
+    let text_at = |pointer: &str| metadata.and_then(|m| m.pointer(pointer)).and_then(Value::as_str).map(str::to_string);
+    let source = Source {
+        name: String::new(),
+        file: path.display().to_string(),
+        version: text_at("/version"),
+        schema_version,
+        started: text_at("/started"),
+        hostname: text_at("/hostname"),
+    };
+
+    let mut loaded = Vec::new();
+    for host in hosts {
+        let Some(address) = host.get("host").and_then(Value::as_str).and_then(|h| h.parse::<IpAddr>().ok()) else {
+            continue;
+        };
+        let identity = host.pointer("/identity/name").and_then(Value::as_str).map(str::to_string);
+        let mut ports: Vec<(u16, String, Observation)> = host
+            .get("ports")
+            .and_then(Value::as_array)
+            .map(|ports| {
+                ports
+                    .iter()
+                    .filter_map(|port| {
+                        let number = port.get("port").and_then(Value::as_u64).and_then(|p| u16::try_from(p).ok())?;
+                        let service = port.get("service").and_then(Value::as_str).unwrap_or("").to_string();
+                        Some((number, service, observe(port, address)))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        // --aggregate-ranges 的區段展開成逐一端口；區段只記錄延遲等級，不提供延遲
+        let ranges = host.get("ranges").and_then(Value::as_array).into_iter().flatten();
+        for range in ranges.filter_map(|range| PortRange::deserialize(range).ok()) {
+            for (info, result) in range.expand() {
+                let value = serde_json::to_value(&result).unwrap_or_default();
+                ports.push((info.port, info.service, Observation { latency_ms: None, ..observe(&value, address) }));
+            }
+        }
+        loaded.push(LoadedHost { address, identity, ports });
+    }
+    Ok(Loaded { source, vantage: text_at(&format!("/annotations/{}", VANTAGE_ANNOTATION)), hosts: loaded })
+}
+
+// 依 error、syn、outbound 與 failure 判斷狀態；沒有 failure 的舊版報告把無法連線視為被過濾
+fn observe(port: &Value, address: IpAddr) -> Observation {
+    let present = |field: &str| port.get(field).is_some_and(|value| !value.is_null());
+    let state = if present("error") {
+        PortState::Error
+    } else if let Some(syn) = port.get("syn").and_then(Value::as_str) {
+        match syn {
+            "open" => PortState::Open,
+            "closed" => PortState::Closed,
+            _ => PortState::Filtered,
+        }
+    } else if port.get("outbound").and_then(Value::as_bool).unwrap_or(false) {
+        PortState::Open
+    } else if port.pointer("/failure/kind").and_then(Value::as_str) == Some("reset") {
+        PortState::Closed
+    } else {
+        PortState::Filtered
+    };
+    Observation {
+        state,
+        address,
+        confidence: port.get("confidence").and_then(Value::as_f64),
+        latency_ms: port.get("latency_ms").and_then(Value::as_f64),
+    }
+}
+
+// 共識規則：
+// 1. 掃描端錯誤的來源不參與
+// 2. 有信心分數達 LOW_CONFIDENCE 的來源 (沒有分數的舊版報告視為可信) 時只採用這些來源，
+//    低信心的逾時不會推翻其他地點確認的結果；全部都是低信心時採用全部
+// 3. 採用的來源都可連線為 open；都無法連線時有任一 RST 為 closed，否則為 filtered；兩者並存為 split
+// 4. 信心：採用的來源視為獨立的證據合併為 1 - Π(1 - c)；split 沒有信心分數
+fn consensus(observations: &BTreeMap<String, Observation>) -> (Consensus, Option<f64>) {
+    let valid: Vec<&Observation> = observations.values().filter(|o| o.state != PortState::Error).collect();
+    let confident: Vec<&Observation> =
+        valid.iter().copied().filter(|o| o.confidence.is_none_or(|c| c >= LOW_CONFIDENCE)).collect();
+    let used = if confident.is_empty() { valid } else { confident };
+    if used.is_empty() {
+        return (Consensus::Unknown, None);
+    }
+
+    let open = used.iter().filter(|o| o.state == PortState::Open).count();
+    let state = match open {
+        n if n == used.len() => Consensus::Open,
+        0 if used.iter().any(|o| o.state == PortState::Closed) => Consensus::Closed,
+        0 => Consensus::Filtered,
+        _ => return (Consensus::Split, None),
+    };
+    let scores: Vec<f64> = used.iter().filter_map(|o| o.confidence).collect();
+    let confidence = (!scores.is_empty()).then(|| {
+        let doubt: f64 = scores.iter().map(|c| 1.0 - c).product();
+        ((1.0 - doubt) * 100.0).round() / 100.0
+    });
+    (state, confidence)
+}
+
+// 例如 "office-A 看得到 web:443，office-B、office-C 看不到"
+fn describe_disagreement(host: &str, port: u16, observations: &BTreeMap<String, Observation>) -> Option<String> {
+    let names = |reachable: bool| -> Vec<&str> {
+        observations
+            .iter()
+            .filter(|(_, o)| o.state.reachable() == Some(reachable))
+            .map(|(name, _)| name.as_str())
+            .collect()
+    };
+    let (seeing, blind) = (names(true), names(false));
+    if seeing.is_empty() || blind.is_empty() {
+        return None;
+    }
+    Some(format!("{} 看得到 {}:{}，{} 看不到", seeing.join("、"), host, port, blind.join("、")))
+}
+
+// 來源名稱：vantage 註記或檔名；重複時加上序號
+fn source_names(loaded: &[Loaded]) -> Vec<String> {
+    let mut used = BTreeSet::new();
+    loaded
+        .iter()
+        .map(|report| {
+            let base = report.vantage.clone().unwrap_or_else(|| {
+                Path::new(&report.source.file).file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default()
+            });
+            let mut name = base.clone();
+            let mut index = 2;
+            while !used.insert(name.clone()) {
+                name = format!("{}-{}", base, index);
+                index += 1;
+            }
+            name
+        })
+        .collect()
+}
+
+// 依 (主機, 端口) 對齊所有報告
+// 主機的對齊鍵為識別名稱，沒有識別時為 IP；其他報告中同一 IP 有識別時改用該識別，
+// 所以以主機名稱掃描的報告可以和以 IP 掃描的報告對齊，也能對齊各地點解析到的不同 IP
+pub fn merge(paths: &[PathBuf]) -> Result<MergedReport, String> {
+    let mut warnings = Vec::new();
+    let loaded: Vec<Loaded> = paths.iter().map(|path| load(path, &mut warnings)).collect::<Result<_, _>>()?;
+    let names = source_names(&loaded);
+
+    let mut named: BTreeMap<IpAddr, String> = BTreeMap::new();
+    for host in loaded.iter().flat_map(|report| &report.hosts) {
+        if let Some(identity) = &host.identity {
+            if let Some(previous) = named.insert(host.address, identity.clone()) {
+                if previous != *identity {
+                    warnings.push(format!("{} 在不同報告中的識別不同 ({} / {})，使用 {}", host.address, previous, identity, identity));
+                }
+            }
+        }
+    }
+
+    let mut aligned: BTreeMap<String, AlignedPorts> = BTreeMap::new();
+    for (report, name) in loaded.iter().zip(&names) {
+        for host in &report.hosts {
+            let key = host.identity.clone().or_else(|| named.get(&host.address).cloned()).unwrap_or_else(|| host.address.to_string());
+            let ports = aligned.entry(key).or_default();
+            for (port, service, observation) in &host.ports {
+                let (known, observations) = ports.entry(*port).or_insert_with(|| (service.clone(), BTreeMap::new()));
+                if known.is_empty() {
+                    *known = service.clone();
+                }
+                // 同一地點的識別解析到多個 IP 時，取最能連線的結果
+                match observations.get(name) {
+                    Some(existing) if existing.state <= observation.state => {}
+                    _ => {
+                        observations.insert(name.clone(), observation.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    let mut disagreements = Vec::new();
+    let hosts = aligned
+        .into_iter()
+        .map(|(host, ports)| {
+            let ports = ports
+                .into_iter()
+                .map(|(port, (service, sources))| {
+                    let (consensus, confidence) = consensus(&sources);
+                    let disagreement = describe_disagreement(&host, port, &sources);
+                    let flagged = disagreement.is_some();
+                    disagreements.extend(disagreement);
+                    MergedPort { port, service, consensus, confidence, disagreement: flagged, sources }
+                })
+                .collect();
+            MergedHost { host, ports }
+        })
+        .collect();
+
+    let sources = loaded
+        .into_iter()
+        .zip(names)
+        .map(|(report, name)| Source { name, ..report.source })
+        .collect();
+    Ok(MergedReport { schema_version: MERGE_SCHEMA_VERSION, sources, hosts, disagreements, warnings })
+}
+
+pub fn display(report: &MergedReport) {
+    println!("\n{}", "=== 多地點合併 ===".bold());
+    for source in &report.sources {
+        let details: Vec<&str> = [source.started.as_deref(), source.hostname.as_deref(), source.version.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect();
+        println!("{} {} ({})", format!("{}:", source.name).bold(), source.file, details.join("，"));
+    }
+    for warning in &report.warnings {
+        println!("{}", warning.yellow());
+    }
+
+    let widths: Vec<usize> = report.sources.iter().map(|s| s.name.chars().count().max(3)).collect();
+    let mut header = format!("\n{} {}", layout::fit("主機:端口", 30), layout::fit("服務", 16));
+    for (source, width) in report.sources.iter().zip(&widths) {
+        header.push_str(&format!(" {:^width$}", source.name, width = width));
+    }
+    println!("{}  共識", header.bold());
+    for host in &report.hosts {
+        for port in &host.ports {
+            let mut line = format!("{} {}", layout::fit(&format!("{}:{}", host.host, port.port), 30), layout::fit(&port.service, 16));
+            for (source, width) in report.sources.iter().zip(&widths) {
+                let cell = match port.sources.get(&source.name) {
+                    Some(observation) => {
+                        let cell = format!(" {:^width$}", observation.state.symbol(), width = width);
+                        match observation.state {
+                            PortState::Open => cell.green(),
+                            PortState::Error => cell.red(),
+                            _ if observation.confidence.is_some_and(|c| c < LOW_CONFIDENCE) => cell.dimmed(),
+                            _ => cell.normal(),
+                        }
+                    }
+                    None => format!(" {:^width$}", "-", width = width).dimmed(),
+                };
+                line.push_str(&cell.to_string());
+            }
+            let confidence = port.confidence.map(|c| format!(" ({:.2})", c)).unwrap_or_default();
+            println!("{}  {}{}", line, port.consensus.label(), confidence.dimmed());
+        }
+    }
+    println!("{}", "✓ 可連線  ✗ 連線被拒  · 逾時或不可達  ! 掃描端錯誤  - 未掃描；暗色為低信心".dimmed());
+
+    if !report.disagreements.is_empty() {
+        println!("\n{}", format!("各地點結果不同 ({}):", report.disagreements.len()).yellow().bold());
+        for disagreement in &report.disagreements {
+            println!("  {}", disagreement.yellow());
+        }
+    }
+}
+
+// portscanner merge
+pub fn run(paths: &[PathBuf], out: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let report = merge(paths)?;
+    display(&report);
+    if let Some(path) = out {
+        let json = serde_json::to_string_pretty(&report)?;
+        fs::write(path, json).map_err(|e| format!("無法寫入 {}: {}", path.display(), e))?;
+        println!("\n合併結果已寫入 {}", path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use colored::*;
+use schemars::JsonSchema;
+use serde::Serialize;
+use crate::capability::CapabilityStatus;
+use crate::captive::CaptivePortal;
+use crate::clock::TimeJump;
+use crate::direction::Directions;
+use crate::geosanity::GeoSanity;
+use crate::scanid;
+use crate::targets::Excluded;
+use crate::timefmt;
+
+// 每次執行的稽核資訊：誰、何時、從哪裡、為什麼
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct RunMetadata {
+    // 掃描 ID (--scan-id 或隨機的 UUIDv7)，用來對照同一次執行的各種輸出
+    pub scan_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    pub version: String,
+    pub command_line: Vec<String>,
+    // 開始時間 (Unix 秒)
+    pub started_at: i64,
+    // 開始時間 (RFC3339 UTC)
+    pub started: String,
+    // --annotate key=value
+    pub annotations: BTreeMap<String, String>,
+    // --exclude / --exclude-file 實際移除的目標
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub excluded: Vec<Excluded>,
+    // --authorized-by 記錄的授權人或工單
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authorized_by: Option<String>,
+    // --no-inbound / --no-outbound 時只測試的方向
+    #[serde(skip_serializing_if = "Directions::is_both")]
+    pub directions: Directions,
+    // 目標位址的解讀提醒 (CGNAT、自己的外部 IP 等) 與 --force 略過的拒絕原因
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub target_warnings: Vec<String>,
+    // 外部 IP 的 GeoIP 位置與各洲錨點的延遲 (--no-geo-sanity 時不檢查)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geo_sanity: Option<GeoSanity>,
+    // 掃描前的透明代理/強制門戶檢查 (--no-captive-check 時不檢查)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub captive_portal: Option<CaptivePortal>,
+    // 啟動時偵測的選用功能狀態
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<CapabilityStatus>,
+    // 掃描期間系統時間跳變或休眠；延遲、逾時與耗時可能不準
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clock_jump: Option<TimeJump>,
+}
+
+// 主機名稱：環境變數或 /etc/hostname
+fn hostname() -> Option<String> {
+    env::var("COMPUTERNAME")
+        .or_else(|_| env::var("HOSTNAME"))
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+fn username() -> Option<String> {
+    env::var("USER").or_else(|_| env::var("USERNAME")).ok().filter(|name| !name.is_empty())
+}
+
+impl RunMetadata {
+    pub fn collect(annotations: &[(String, String)]) -> Self {
+        let started_at = timefmt::now();
+        RunMetadata {
+            scan_id: scanid::generate(),
+            hostname: hostname(),
+            username: username(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            command_line: env::args().collect(),
+            started_at,
+            started: timefmt::rfc3339_utc(started_at),
+            annotations: annotations.iter().cloned().collect(),
+            excluded: Vec::new(),
+            authorized_by: None,
+            directions: Directions::default(),
+            target_warnings: Vec::new(),
+            geo_sanity: None,
+            captive_portal: None,
+            capabilities: Vec::new(),
+            clock_jump: None,
+        }
+    }
+
+    // 以 (名稱, 值) 列出，供 CSV 註解與 HTML 使用
+    pub fn entries(&self) -> Vec<(String, String)> {
+        let mut entries: Vec<(String, String)> = [("hostname", &self.hostname), ("username", &self.username)]
+            .into_iter()
+            .filter_map(|(key, value)| value.clone().map(|value| (key.to_string(), value)))
+            .collect();
+        entries.extend([
+            ("version".to_string(), self.version.clone()),
+            ("command_line".to_string(), self.command_line.join(" ")),
+            ("started_at".to_string(), timefmt::timestamp(self.started_at)),
+            ("scan_id".to_string(), self.scan_id.clone()),
+        ]);
+        if !self.excluded.is_empty() {
+            entries.push(("excluded".to_string(), describe_excluded(&self.excluded)));
+        }
+        if !self.directions.is_both() {
+            entries.push(("directions".to_string(), self.directions.name().to_string()));
+        }
+        if !self.target_warnings.is_empty() {
+            entries.push(("target_warnings".to_string(), self.target_warnings.join("; ")));
+        }
+        if let Some(jump) = &self.clock_jump {
+            entries.push(("clock_jump".to_string(), jump.describe()));
+        }
+        if let Some(portal) = self.captive_portal.as_ref().filter(|portal| portal.detected) {
+            entries.push(("captive_portal".to_string(), portal.describe()));
+        }
+        entries.extend(self.annotations.iter().map(|(k, v)| (k.clone(), v.clone())));
+        entries
+    }
+}
+
+// 排除項目與移除的主機數，例如 "10.0.5.0/28 (16 台)"
+pub fn describe_excluded(excluded: &[Excluded]) -> String {
+    excluded
+        .iter()
+        .map(|e| format!("{} ({} 台)", e.spec, e.hosts))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// 解析 --annotate key=value
+pub fn parse_annotation(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s.split_once('=').ok_or_else(|| format!("標註格式應為 key=value: {}", s))?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(format!("標註缺少名稱: {}", s));
+    }
+    Ok((key.to_string(), value.trim().to_string()))
+}
+
+// 終端標頭中的執行資訊
+pub fn display(metadata: &RunMetadata) {
+    let user = metadata.username.as_deref().unwrap_or("?");
+    let host = metadata.hostname.as_deref().unwrap_or("?");
+    println!("{} {}@{} (v{})", "執行者:".bold(), user, host, metadata.version);
+    println!("{} {}", "開始時間:".bold(), timefmt::timestamp(metadata.started_at));
+    println!("{} {}", "掃描 ID:".bold(), metadata.scan_id);
+    for (key, value) in &metadata.annotations {
+        println!("{} {}={}", "標註:".bold(), key, value);
+    }
+    if !metadata.excluded.is_empty() {
+        println!("{} {}", "排除:".bold(), describe_excluded(&metadata.excluded));
+    }
+    if !metadata.directions.is_both() {
+        println!("{} {}", "測試方向:".bold(), metadata.directions.label());
+    }
+    display_target_warnings(&metadata.target_warnings);
+}
+
+// 目標提醒；外部 IP 在掃描後才確定，相關的提醒在結果之前另外顯示
+pub fn display_target_warnings(warnings: &[String]) {
+    for warning in warnings {
+        println!("{} {}", "目標提醒:".bold(), warning.yellow());
+    }
+}
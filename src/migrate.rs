@@ -0,0 +1,251 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use colored::*;
+use toml_edit::{value, DocumentMut, Item};
+use crate::config;
+use crate::portdb;
+
+// 設定檔格式的版本；沒有 version 的檔案視為版本 1
+pub const CURRENT_VERSION: i64 = 2;
+
+// 從 from 升級到 from + 1 的一步；回傳對使用者說明的每項改動
+struct Step {
+    from: i64,
+    apply: fn(&mut DocumentMut) -> Vec<String>,
+}
+
+// 依版本順序排列，升級時從檔案的版本一路套用到目前版本
+const STEPS: &[Step] = &[Step { from: 1, apply: watch_section }];
+
+// 一次升級的結果
+#[derive(Debug, PartialEq)]
+pub struct Migration {
+    pub from: i64,
+    pub changes: Vec<String>,
+}
+
+impl Migration {
+    pub fn is_current(&self) -> bool {
+        self.from == CURRENT_VERSION
+    }
+}
+
+// 檔案宣告的版本
+fn version(document: &DocumentMut) -> Result<i64, String> {
+    match document.get("version") {
+        None => Ok(1),
+        Some(item) => match item.as_integer() {
+            Some(version) if (1..=CURRENT_VERSION).contains(&version) => Ok(version),
+            Some(version) if version > CURRENT_VERSION => {
+                Err(format!("設定檔版本 {} 比這個版本的 portscanner 支援的 {} 新，請更新 portscanner", version, CURRENT_VERSION))
+            }
+            _ => Err(format!("無效的設定檔版本: {}", item.to_string().trim())),
+        },
+    }
+}
+
+// 依序套用升級步驟並寫入目前的版本；已是目前版本時不修改
+pub fn migrate(document: &mut DocumentMut) -> Result<Migration, String> {
+    let from = version(document)?;
+    let mut changes = Vec::new();
+    for step in STEPS.iter().filter(|step| step.from >= from) {
+        changes.extend((step.apply)(document));
+    }
+    if from != CURRENT_VERSION {
+        // 新的值寫在檔案開頭，與後面的區段以空行分開
+        document.insert("version", value(CURRENT_VERSION));
+        let first = document.iter_mut().filter_map(|(_, item)| item.as_table_mut()).min_by_key(|table| table.position());
+        if let Some(table) = first {
+            let prefix = table.decor().prefix().and_then(|prefix| prefix.as_str()).unwrap_or("").to_string();
+            table.decor_mut().set_prefix(format!("\n{}", prefix));
+        }
+        changes.push(format!("加上 version = {}", CURRENT_VERSION));
+    }
+    Ok(Migration { from, changes })
+}
+
+// 版本 1 -> 2：告警規則移到 [watch] 之下，時間窗改用與 [timeouts] 相同的時間長度寫法
+fn watch_section(document: &mut DocumentMut) -> Vec<String> {
+    let mut changes = Vec::new();
+    let alerts = document.remove("alerts");
+    let renames = [("restart_window_secs", "restart_window"), ("dedup_window_secs", "dedup_window")];
+    let needs_watch = alerts.is_some() || renames.iter().any(|(old, _)| document.get("watch").and_then(|w| w.get(old)).is_some());
+    if !needs_watch {
+        return changes;
+    }
+    if !document.contains_key("watch") {
+        document.insert("watch", toml_edit::table());
+    }
+    let Some(watch) = document["watch"].as_table_like_mut() else {
+        return changes;
+    };
+    if let Some(alerts) = alerts {
+        watch.insert("alerts", alerts);
+        changes.push("[[alerts]] 改為 [[watch.alerts]]".to_string());
+    }
+    for (old, new) in renames {
+        let Some(item) = watch.remove(old) else {
+            continue;
+        };
+        match item.as_integer() {
+            Some(secs) => {
+                // 保留同一行的註解
+                let mut renamed = toml_edit::Value::from(format!("{}s", secs));
+                if let Some(old) = item.as_value() {
+                    *renamed.decor_mut() = old.decor().clone();
+                }
+                watch.insert(new, Item::Value(renamed));
+                changes.push(format!("[watch] {} = {} 改為 {} = \"{}s\"", old, secs, new, secs));
+            }
+            // 不是整數時無法換算，保留原值讓載入時指出錯誤
+            None => {
+                watch.insert(old, item);
+            }
+        }
+    }
+    changes
+}
+
+// 載入時的升級：回傳升級後的內容與提醒；已是目前版本時沒有提醒
+pub fn upgrade(text: &str, path: &Path) -> Result<(String, Option<String>), String> {
+    let mut document: DocumentMut = text.parse().map_err(|e| format!("設定檔 {} 格式錯誤: {}", path.display(), e))?;
+    let migration = migrate(&mut document).map_err(|e| format!("設定檔 {}: {}", path.display(), e))?;
+    if migration.is_current() {
+        return Ok((text.to_string(), None));
+    }
+    let mut warning = format!("注意: 設定檔 {} 使用舊版格式 (版本 {})，請修改:", path.display(), migration.from);
+    for change in &migration.changes {
+        warning.push_str(&format!("\n  - {}", change));
+    }
+    warning.push_str("\n或執行 portscanner config migrate 自動更新 (會保留 .bak 備份)");
+    Ok((document.to_string(), Some(warning)))
+}
+
+// 備份檔：原檔名加上 .bak
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+// config migrate：升級設定檔並保留原檔為 .bak
+pub fn run(path: Option<&Path>, dry_run: bool) -> Result<(), Box<dyn Error>> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => config::default_path().ok_or("找不到設定目錄，請以 --config 指定設定檔")?,
+    };
+    let before = fs::read_to_string(&path).map_err(|e| format!("無法讀取設定檔 {}: {}", path.display(), e))?;
+    let mut document: DocumentMut = before.parse().map_err(|e| format!("設定檔 {} 格式錯誤: {}", path.display(), e))?;
+    let migration = migrate(&mut document)?;
+    if migration.is_current() {
+        println!("設定檔 {} 已是目前的版本 ({})", path.display(), CURRENT_VERSION);
+        return Ok(());
+    }
+    let after = document.to_string();
+    // 寫入前確認升級後的設定檔可以載入
+    config::parse(&after, &path)?;
+
+    println!("{}", format!("設定檔版本 {} -> {}", migration.from, CURRENT_VERSION).bold());
+    for change in &migration.changes {
+        println!("  - {}", change);
+    }
+    println!();
+    portdb::display_diff(&path, &before, &after);
+    if dry_run {
+        println!("\n{}", "--dry-run，未寫入".dimmed());
+        return Ok(());
+    }
+    let backup = backup_path(&path);
+    fs::copy(&path, &backup).map_err(|e| format!("無法備份到 {}: {}", backup.display(), e))?;
+    fs::write(&path, after).map_err(|e| format!("無法寫入設定檔 {}: {}", path.display(), e))?;
+    println!("{}", format!("已更新 {} (原檔備份為 {})", path.display(), backup.display()).green());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    const V1: &str = include_str!("fixtures/config/v1.toml");
+    const V2: &str = include_str!("fixtures/config/v2.toml");
+
+    fn migrated(text: &str) -> (String, Migration) {
+        let mut document: DocumentMut = text.parse().unwrap();
+        let migration = migrate(&mut document).unwrap();
+        (document.to_string(), migration)
+    }
+
+    #[test]
+    fn version_one_fixture_upgrades_to_the_current_fixture() {
+        let (text, migration) = migrated(V1);
+        assert_eq!(migration.from, 1);
+        assert_eq!(
+            migration.changes,
+            vec![
+                "[[alerts]] 改為 [[watch.alerts]]",
+                "[watch] restart_window_secs = 120 改為 restart_window = \"120s\"",
+                "[watch] dedup_window_secs = 600 改為 dedup_window = \"600s\"",
+                "加上 version = 2",
+            ]
+        );
+        assert_eq!(text, V2);
+        // 升級後的內容可以載入，註解保留
+        let config = config::parse(&text, Path::new("v1.toml")).unwrap();
+        assert_eq!(config.watch.alerts.len(), 2);
+        assert_eq!(config.watch.restart_window, std::time::Duration::from_secs(120));
+        assert!(text.contains("# 資料庫不應從外部連線"));
+    }
+
+    #[test]
+    fn current_files_are_left_alone() {
+        let (text, migration) = migrated(V2);
+        assert!(migration.is_current() && migration.changes.is_empty());
+        assert_eq!(text, V2);
+        assert_eq!(upgrade(V2, Path::new("v2.toml")).unwrap(), (V2.to_string(), None));
+    }
+
+    #[test]
+    fn unversioned_files_without_old_keys_only_gain_a_version() {
+        let (text, migration) = migrated("[timeouts]\ndefault = \"2s\"\n");
+        assert_eq!(migration.changes, vec!["加上 version = 2"]);
+        assert_eq!(text, "version = 2\n\n[timeouts]\ndefault = \"2s\"\n");
+    }
+
+    #[test]
+    fn loading_an_old_file_warns_with_each_change() {
+        let (text, warning) = upgrade(V1, Path::new("old.toml")).unwrap();
+        assert_eq!(text, V2);
+        let warning = warning.unwrap();
+        assert!(warning.starts_with("注意: 設定檔 old.toml 使用舊版格式 (版本 1)"), "{}", warning);
+        assert!(warning.contains("  - [watch] dedup_window_secs = 600 改為 dedup_window = \"600s\""));
+        assert!(warning.contains("portscanner config migrate"));
+    }
+
+    #[test]
+    fn unknown_versions_are_errors() {
+        for (text, message) in [("version = 3\n", "比這個版本的 portscanner 支援的 2 新"), ("version = \"2\"\n", "無效的設定檔版本"), ("version = 0\n", "無效的設定檔版本")] {
+            let mut document: DocumentMut = text.parse().unwrap();
+            let error = migrate(&mut document).unwrap_err();
+            assert!(error.contains(message), "{}", error);
+        }
+    }
+
+    #[test]
+    fn migrate_writes_a_backup() {
+        let dir = TempDir::new("migrate");
+        let path = dir.path().join("config.toml");
+        fs::write(&path, V1).unwrap();
+        run(Some(&path), true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), V1);
+
+        run(Some(&path), false).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), V2);
+        assert_eq!(fs::read_to_string(dir.path().join("config.toml.bak")).unwrap(), V1);
+        // 再執行一次不會覆蓋備份
+        fs::remove_file(dir.path().join("config.toml.bak")).unwrap();
+        run(Some(&path), false).unwrap();
+        assert!(!dir.path().join("config.toml.bak").exists());
+    }
+}
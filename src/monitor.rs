@@ -0,0 +1,250 @@
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use colored::*;
+use schemars::JsonSchema;
+use serde::Serialize;
+use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use crate::context::ScanContext;
+use crate::output::csv_field;
+use crate::scanner::ScanPlan;
+use crate::stats::median;
+use crate::layout::Layout;
+use crate::{timefmt, PortInfo};
+
+// 時間軸的字元，依該時段的可連線比例由低到高
+const LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+// 時間軸前的標籤寬度 (主機:端口與可用率)
+const LABEL_WIDTH: usize = 34;
+
+// 單次探測
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Sample {
+    // 距離監測開始的時間
+    pub at_ms: u64,
+    pub up: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<f64>,
+}
+
+// 單一主機端口的可用性
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Availability {
+    pub host: IpAddr,
+    #[serde(flatten)]
+    pub port: PortInfo,
+    // 0-100
+    pub percent: f64,
+    // 連續失敗最長的時間：第一次失敗到下一次成功 (或監測結束)
+    pub longest_outage_ms: u64,
+    // 成功探測的延遲中位數
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub median_latency_ms: Option<f64>,
+    pub samples: Vec<Sample>,
+}
+
+// --monitor 的結果
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct MonitorReport {
+    pub duration_ms: u64,
+    pub interval_ms: u64,
+    pub rounds: usize,
+    pub ports: Vec<Availability>,
+}
+
+async fn probe(dest: SocketAddr, limit: Duration) -> (bool, Option<f64>) {
+    let started = Instant::now();
+    match tokio::time::timeout(limit, TcpStream::connect(dest)).await {
+        Ok(Ok(_)) => (true, Some(started.elapsed().as_secs_f64() * 1000.0)),
+        _ => (false, None),
+    }
+}
+
+// 從樣本計算可用率、最長中斷與延遲
+fn aggregate(host: IpAddr, port: PortInfo, samples: Vec<Sample>, end_ms: u64) -> Availability {
+    let up = samples.iter().filter(|s| s.up).count();
+    let percent = match samples.len() {
+        0 => 0.0,
+        n => up as f64 * 100.0 / n as f64,
+    };
+    let mut longest = 0;
+    let mut down_since = None;
+    for sample in &samples {
+        match (sample.up, down_since) {
+            (false, None) => down_since = Some(sample.at_ms),
+            (true, Some(since)) => {
+                longest = longest.max(sample.at_ms - since);
+                down_since = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(since) = down_since {
+        longest = longest.max(end_ms.saturating_sub(since));
+    }
+    Availability {
+        host,
+        port,
+        percent,
+        longest_outage_ms: longest,
+        median_latency_ms: median(samples.iter().filter_map(|s| s.latency_ms).collect()),
+        samples,
+    }
+}
+
+// 在 duration 內每隔 interval 探測一輪所有主機端口；上一輪超過間隔時下一輪立即開始
+// 非 quiet 時在標準錯誤顯示進度
+pub async fn run(plan: &ScanPlan, duration: Duration, interval: Duration, quiet: bool) -> MonitorReport {
+    let endpoints: Vec<(IpAddr, PortInfo)> = plan
+        .targets
+        .iter()
+        .flat_map(|target| target.addrs())
+        .flat_map(|host| plan.ports.iter().map(move |port| (host, port.clone())))
+        .collect();
+    let mut samples: Vec<Vec<Sample>> = vec![Vec::new(); endpoints.len()];
+    let semaphore = Arc::new(Semaphore::new(plan.concurrency.max(1)));
+    let started = Instant::now();
+    let mut rounds = 0;
+
+    while started.elapsed() < duration {
+        let round_start = Instant::now();
+        let at_ms = started.elapsed().as_millis() as u64;
+        let mut tasks = JoinSet::new();
+        for (index, (host, port)) in endpoints.iter().enumerate() {
+            let semaphore = semaphore.clone();
+            let dest = plan.context.socket_addr(*host, port.port);
+            let limit = plan.timeouts.for_port(port);
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                (index, probe(dest, limit).await)
+            });
+        }
+        let mut up = 0;
+        while let Some(Ok((index, (reachable, latency_ms)))) = tasks.join_next().await {
+            up += usize::from(reachable);
+            samples[index].push(Sample { at_ms, up: reachable, latency_ms });
+        }
+        rounds += 1;
+        if !quiet {
+            eprint!(
+                "\r{}",
+                format!(
+                    "監測中 {} / {}，第 {} 輪：{}/{} 可連線   ",
+                    timefmt::clock(started.elapsed()),
+                    timefmt::clock(duration),
+                    rounds,
+                    up,
+                    endpoints.len()
+                )
+                .dimmed()
+            );
+            let _ = io::stderr().flush();
+        }
+        let next = round_start + interval;
+        let deadline = started + duration;
+        if next >= deadline {
+            break;
+        }
+        tokio::time::sleep_until(next.into()).await;
+    }
+    if !quiet {
+        eprintln!();
+    }
+
+    let end_ms = started.elapsed().as_millis() as u64;
+    let ports = endpoints
+        .into_iter()
+        .zip(samples)
+        .map(|((host, port), samples)| aggregate(host, port, samples, end_ms))
+        .collect();
+    MonitorReport {
+        duration_ms: duration.as_millis() as u64,
+        interval_ms: interval.as_millis() as u64,
+        rounds,
+        ports,
+    }
+}
+
+// 把樣本平均分到 width 個時段，每個時段以可連線比例選擇字元
+// 樣本比寬度少時一個樣本一格
+pub fn timeline(samples: &[Sample], width: usize) -> String {
+    let width = width.max(1);
+    let buckets = samples.len().min(width);
+    (0..buckets)
+        .map(|bucket| {
+            let start = bucket * samples.len() / buckets;
+            let end = ((bucket + 1) * samples.len() / buckets).max(start + 1);
+            let slice = &samples[start..end];
+            let ratio = slice.iter().filter(|s| s.up).count() as f64 / slice.len() as f64;
+            let level = LEVELS[((ratio * (LEVELS.len() - 1) as f64).round() as usize).min(LEVELS.len() - 1)];
+            let cell = level.to_string();
+            match ratio {
+                r if r >= 1.0 => cell.green().to_string(),
+                r if r <= 0.0 => cell.red().to_string(),
+                _ => cell.yellow().to_string(),
+            }
+        })
+        .collect()
+}
+
+pub fn display(report: &MonitorReport, context: &ScanContext, screen: &Layout) {
+    println!("\n{}", "=== 可用性監測 ===".bold());
+    println!(
+        "監測 {}，每 {} 一輪，共 {} 輪",
+        timefmt::duration(Duration::from_millis(report.duration_ms)),
+        timefmt::duration(Duration::from_millis(report.interval_ms)),
+        report.rounds
+    );
+    let width = screen.width.saturating_sub(LABEL_WIDTH).max(10);
+    for availability in &report.ports {
+        let host = context.anonymizer.as_ref().map_or(availability.host, |anonymizer| anonymizer.ip(availability.host));
+        let label = SocketAddr::new(host, availability.port.port).to_string();
+        let percent = format!("{:6.2}%", availability.percent);
+        let percent = match availability.percent {
+            p if p >= 100.0 => percent.green(),
+            p if p >= 99.0 => percent.yellow(),
+            _ => percent.red(),
+        };
+        println!("{:24} {} {}", label, percent, timeline(&availability.samples, width));
+        let latency = availability
+            .median_latency_ms
+            .map(|ms| format!("，延遲中位數 {:.1}ms", ms))
+            .unwrap_or_default();
+        let outage = match availability.longest_outage_ms {
+            0 => "沒有中斷".to_string(),
+            ms => format!("最長中斷 {}", timefmt::duration(Duration::from_millis(ms))),
+        };
+        println!("{}", format!("{:24} {} ({}){}", "", outage, availability.port.service, latency).dimmed());
+    }
+}
+
+// 每個樣本一行的 CSV
+pub fn to_csv(report: &MonitorReport, context: &ScanContext) -> String {
+    let mut out = String::from("host,port,service,at_ms,up,latency_ms\n");
+    for availability in &report.ports {
+        for sample in &availability.samples {
+            out.push_str(&format!(
+                "{},{},{},{},{},{}\n",
+                context.show_ip(availability.host),
+                availability.port.port,
+                csv_field(&availability.port.service),
+                sample.at_ms,
+                sample.up,
+                sample.latency_ms.map(|ms| format!("{:.3}", ms)).unwrap_or_default()
+            ));
+        }
+    }
+    out
+}
+
+pub fn write_csv(report: &MonitorReport, context: &ScanContext, path: &Path) -> Result<(), Box<dyn Error>> {
+    fs::write(path, to_csv(report, context)).map_err(|e| format!("無法寫入 {}: {}", path.display(), e))?;
+    Ok(())
+}
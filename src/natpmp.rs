@@ -0,0 +1,185 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use colored::*;
+use schemars::JsonSchema;
+use serde::Serialize;
+use tokio::net::UdpSocket;
+use crate::{anonymize, sanity};
+
+// NAT-PMP (RFC 6886) 與 PCP (RFC 6887) 共用的閘道端口
+const SERVER_PORT: u16 = 5351;
+
+// 詢問閘道的總時間 (RFC 6886 的重送間隔 250ms 起每次加倍)
+pub const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+// 第一次重送前的等待時間，之後每次加倍 (RFC 6886 3.1)
+const INITIAL_RETRY: Duration = Duration::from_millis(250);
+
+const NATPMP_VERSION: u8 = 0;
+const PCP_VERSION: u8 = 2;
+// 回應的 opcode 為請求的 opcode 加上 128 (PCP 的 R 位元)
+const RESPONSE: u8 = 0x80;
+const OPCODE_EXTERNAL_ADDRESS: u8 = 0;
+const OPCODE_ANNOUNCE: u8 = 0;
+// PCP 伺服器收到不支援的版本時回應的結果代碼；只支援 NAT-PMP 的閘道也以此代碼回應 PCP 請求
+const UNSUPPORTED_VERSION: u16 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MappingProtocol {
+    NatPmp,
+    Pcp,
+}
+
+impl MappingProtocol {
+    fn label(self) -> &'static str {
+        match self {
+            MappingProtocol::NatPmp => "NAT-PMP",
+            MappingProtocol::Pcp => "PCP",
+        }
+    }
+}
+
+// --nat-pmp 的結果：閘道支援的協定與外部位址
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+pub struct PortMapping {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gateway: Option<IpAddr>,
+    pub protocols: Vec<MappingProtocol>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_address: Option<IpAddr>,
+    // 閘道的 epoch (開機或重設對應表後經過的秒數)；數值變小代表對應表已清除
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub epoch_seconds: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+}
+
+impl PortMapping {
+    pub fn anonymize(&mut self, anonymizer: &anonymize::Anonymizer) {
+        self.gateway = self.gateway.map(|ip| anonymizer.ip(ip));
+        self.external_address = self.external_address.map(|ip| anonymizer.ip(ip));
+    }
+}
+
+// 送出請求，依 RFC 6886 的間隔重送，直到收到 accept 接受的回應或超過 limit
+async fn exchange(socket: &UdpSocket, request: &[u8], limit: Duration, accept: impl Fn(&[u8]) -> bool) -> Option<Vec<u8>> {
+    let deadline = tokio::time::Instant::now() + limit;
+    let mut wait = INITIAL_RETRY;
+    let mut buffer = [0u8; 1100];
+    while tokio::time::Instant::now() < deadline {
+        socket.send(request).await.ok()?;
+        let attempt_end = (tokio::time::Instant::now() + wait).min(deadline);
+        while let Ok(received) = tokio::time::timeout_at(attempt_end, socket.recv(&mut buffer)).await {
+            match received {
+                Ok(n) if accept(&buffer[..n]) => return Some(buffer[..n].to_vec()),
+                Ok(_) => {}
+                Err(_) => return None,
+            }
+        }
+        wait *= 2;
+    }
+    None
+}
+
+// NAT-PMP 外部位址請求：版本 0、opcode 0
+// 回應：版本、opcode 128、結果 (2)、epoch (4)、外部 IPv4 (4)
+async fn query_natpmp(socket: &UdpSocket, limit: Duration, mapping: &mut PortMapping) {
+    let accept = |packet: &[u8]| packet.len() >= 12 && packet[0] == NATPMP_VERSION && packet[1] == RESPONSE | OPCODE_EXTERNAL_ADDRESS;
+    let Some(response) = exchange(socket, &[NATPMP_VERSION, OPCODE_EXTERNAL_ADDRESS], limit, accept).await else {
+        return;
+    };
+    mapping.protocols.push(MappingProtocol::NatPmp);
+    match u16::from_be_bytes([response[2], response[3]]) {
+        0 => {
+            mapping.epoch_seconds = Some(u32::from_be_bytes([response[4], response[5], response[6], response[7]]));
+            let external = Ipv4Addr::new(response[8], response[9], response[10], response[11]);
+            // 閘道本身還沒有外部位址時回應 0.0.0.0
+            match external.is_unspecified() {
+                true => mapping.notes.push("NAT-PMP 閘道尚未取得外部位址".to_string()),
+                false => mapping.external_address = Some(IpAddr::V4(external)),
+            }
+        }
+        code => mapping.notes.push(format!("NAT-PMP 外部位址請求失敗 (結果代碼 {})", code)),
+    }
+}
+
+// PCP ANNOUNCE：版本 2、opcode 0、保留、lifetime 0、用戶端 IP (IPv4 對應的 IPv6)
+// 只確認 PCP 伺服器存在；PCP 要建立對應才會回報外部位址與端口
+async fn query_pcp(socket: &UdpSocket, limit: Duration, mapping: &mut PortMapping) {
+    let Ok(SocketAddr::V4(local)) = socket.local_addr() else {
+        return;
+    };
+    let mut request = vec![PCP_VERSION, OPCODE_ANNOUNCE, 0, 0, 0, 0, 0, 0];
+    request.extend_from_slice(&local.ip().to_ipv6_mapped().octets());
+    // 只支援 NAT-PMP 的閘道以版本 0 的格式回應不支援的版本
+    let accept = |packet: &[u8]| match packet.first() {
+        Some(&PCP_VERSION) => packet.len() >= 24 && packet[1] == RESPONSE | OPCODE_ANNOUNCE,
+        Some(&NATPMP_VERSION) => packet.len() >= 4,
+        _ => false,
+    };
+    let Some(response) = exchange(socket, &request, limit, accept).await else {
+        return;
+    };
+    if response[0] == NATPMP_VERSION {
+        if u16::from_be_bytes([response[2], response[3]]) == UNSUPPORTED_VERSION {
+            mapping.notes.push("閘道不支援 PCP，只支援 NAT-PMP".to_string());
+        }
+        return;
+    }
+    mapping.protocols.push(MappingProtocol::Pcp);
+    match response[3] {
+        0 => {
+            mapping.epoch_seconds.get_or_insert(u32::from_be_bytes([response[8], response[9], response[10], response[11]]));
+        }
+        code => mapping.notes.push(format!("PCP ANNOUNCE 失敗 (結果代碼 {})", code)),
+    }
+}
+
+// 從路由表找到預設閘道，依序詢問 NAT-PMP 與 PCP
+pub async fn query(limit: Duration) -> PortMapping {
+    let mut mapping = PortMapping::default();
+    let Some(gateway) = sanity::default_gateway() else {
+        mapping.notes.push("找不到 IPv4 預設閘道".to_string());
+        return mapping;
+    };
+    mapping.gateway = Some(IpAddr::V4(gateway));
+    let socket = match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            mapping.notes.push(format!("無法建立 UDP socket: {}", e));
+            return mapping;
+        }
+    };
+    if let Err(e) = socket.connect((gateway, SERVER_PORT)).await {
+        mapping.notes.push(format!("無法連線到閘道: {}", e));
+        return mapping;
+    }
+    query_natpmp(&socket, limit, &mut mapping).await;
+    query_pcp(&socket, limit, &mut mapping).await;
+    if mapping.protocols.is_empty() {
+        mapping.notes.push("閘道沒有回應 NAT-PMP 或 PCP".to_string());
+    } else {
+        mapping.notes.push("NAT-PMP 與 PCP 無法列出其他主機建立的轉發規則，只回報閘道狀態".to_string());
+    }
+    mapping
+}
+
+pub fn display(mapping: &PortMapping) {
+    println!("\n{}", "=== 路由器轉發狀態 ===".bold());
+    let gateway = mapping.gateway.map(|ip| ip.to_string()).unwrap_or_else(|| "?".to_string());
+    let protocols = match mapping.protocols.is_empty() {
+        true => "無".dimmed().to_string(),
+        false => mapping.protocols.iter().map(|p| p.label()).collect::<Vec<_>>().join(" / ").green().to_string(),
+    };
+    println!("閘道 {}：{}", gateway, protocols);
+    if let Some(external) = mapping.external_address {
+        println!("外部位址: {}", external);
+    }
+    if let Some(epoch) = mapping.epoch_seconds {
+        println!("對應表 epoch: {} 秒", epoch);
+    }
+    for note in &mapping.notes {
+        println!("{}", note.dimmed());
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use colored::*;
+use ipnet::IpNet;
+use schemars::JsonSchema;
+use serde::Serialize;
+use crate::checks::{CheckOutcome, CheckStatus};
+use crate::{anonymize, PortInfo, ScanResult};
+
+// IPv6 一律以 /64 彙總，與 --per-net-concurrency 相同
+const V6_PREFIX: u8 = 64;
+
+// 每個網段列出的常見開放端口數
+const COMMON_PORTS: usize = 5;
+
+// 每個網段列出的值得注意項目上限
+const FINDINGS: usize = 10;
+
+// 設定檔 [blocks] 的網段名稱，例如 "10.1.2.0/24" = "prod web"
+#[derive(Debug, Clone, Default)]
+pub struct BlockNames {
+    blocks: Vec<(IpNet, String)>,
+}
+
+impl BlockNames {
+    pub fn parse(config: &BTreeMap<String, String>) -> Result<Self, String> {
+        let mut blocks = Vec::new();
+        for (network, name) in config {
+            let net: IpNet = network.trim().parse().map_err(|_| format!("[blocks] 中無效的網段 '{}'", network))?;
+            blocks.push((net.trunc(), name.clone()));
+        }
+        Ok(BlockNames { blocks })
+    }
+
+    // --anonymize 時網段換成假名；主機的假名仍落在網段的假名內
+    pub fn anonymize(&self, anonymizer: &anonymize::Anonymizer) -> Self {
+        BlockNames { blocks: self.blocks.iter().map(|(net, name)| (anonymizer.net(*net), anonymizer.text(name))).collect() }
+    }
+
+    // 包含位址的設定網段中前綴最長的一個
+    fn lookup(&self, addr: IpAddr) -> Option<&(IpNet, String)> {
+        self.blocks.iter().filter(|(net, _)| net.contains(&addr)).max_by_key(|(net, _)| net.prefix_len())
+    }
+}
+
+// 主機所屬的網段：設定中有涵蓋的網段時使用最長的那個，否則彙總到 /prefix (IPv6 /64)
+pub fn block_of(addr: IpAddr, prefix_v4: u8, names: &BlockNames) -> (IpNet, Option<String>) {
+    if let Some((net, name)) = names.lookup(addr) {
+        return (*net, Some(name.clone()));
+    }
+    let prefix = match addr {
+        IpAddr::V4(_) => prefix_v4,
+        IpAddr::V6(_) => V6_PREFIX,
+    };
+    (IpNet::new(addr, prefix).expect("prefix checked by clap").trunc(), None)
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct PortCount {
+    pub port: u16,
+    pub service: String,
+    pub hosts: usize,
+}
+
+// 一個網段的彙總
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct BlockSummary {
+    pub network: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub hosts: Vec<IpAddr>,
+    // 至少有一個端口可連線的主機數
+    pub hosts_up: usize,
+    pub open_ports: usize,
+    // 網段內開放主機最多的端口
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub common_ports: Vec<PortCount>,
+    // 服務檢查的警告，以及網段內只有一台主機開放的端口
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub findings: Vec<String>,
+}
+
+impl BlockSummary {
+    // 例如 "10.1.2.0/24 prod web"
+    pub fn title(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{} {}", self.network, name),
+            None => self.network.clone(),
+        }
+    }
+}
+
+// 依網段彙總每台主機的結果；網段依網路位址排序
+pub fn summarize(
+    results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>,
+    checks: &[(IpAddr, Vec<CheckOutcome>)],
+    prefix_v4: u8,
+    names: &BlockNames,
+) -> Vec<BlockSummary> {
+    let mut blocks: BTreeMap<IpNet, (Option<String>, Vec<IpAddr>)> = BTreeMap::new();
+    for host in results.keys() {
+        let (net, name) = block_of(*host, prefix_v4, names);
+        blocks.entry(net).or_insert_with(|| (name, Vec::new())).1.push(*host);
+    }
+
+    blocks
+        .into_iter()
+        .map(|(net, (name, hosts))| {
+            let open = |host: &IpAddr| -> Vec<&PortInfo> {
+                results[host].iter().filter(|(_, r)| r.outbound).map(|(port, _)| port).collect()
+            };
+            let mut ports: BTreeMap<(u16, &str), Vec<IpAddr>> = BTreeMap::new();
+            for host in &hosts {
+                for port in open(host) {
+                    ports.entry((port.port, port.service.as_str())).or_default().push(*host);
+                }
+            }
+            let hosts_up = hosts.iter().filter(|host| !open(host).is_empty()).count();
+            let open_ports = ports.values().map(Vec::len).sum();
+
+            let mut common_ports: Vec<PortCount> = ports
+                .iter()
+                .map(|((port, service), found)| PortCount { port: *port, service: service.to_string(), hosts: found.len() })
+                .collect();
+            common_ports.sort_by_key(|p| (std::cmp::Reverse(p.hosts), p.port));
+            common_ports.truncate(COMMON_PORTS);
+
+            let mut findings: Vec<String> = checks
+                .iter()
+                .filter(|(host, _)| hosts.contains(host))
+                .flat_map(|(host, outcomes)| outcomes.iter().map(move |o| (host, o)))
+                .filter(|(_, o)| o.status == CheckStatus::Warning)
+                .map(|(host, o)| format!("{}:{} {}: {}", host, o.port, o.check, o.summary))
+                .collect();
+            // 多台主機的網段中只有一台開放的端口，常是設定不一致
+            if hosts.len() > 1 {
+                findings.extend(
+                    ports
+                        .iter()
+                        .filter(|(_, found)| found.len() == 1)
+                        .map(|((port, service), found)| format!("只有 {} 開放 Port {} ({})", found[0], port, service)),
+                );
+            }
+            findings.truncate(FINDINGS);
+
+            BlockSummary { network: net.to_string(), name, hosts, hosts_up, open_ports, common_ports, findings }
+        })
+        .collect()
+}
+
+// 網段標題與彙總，接著顯示該網段的主機結果
+pub fn display_header(block: &BlockSummary) {
+    println!("\n{}", format!("##### {} #####", block.title()).bold().cyan());
+    println!(
+        "主機 {} 台，{} 台有開放端口，共 {} 個開放端口",
+        block.hosts.len(),
+        block.hosts_up,
+        block.open_ports
+    );
+    if !block.common_ports.is_empty() {
+        let common: Vec<String> =
+            block.common_ports.iter().map(|p| format!("{} ({}) x{}", p.port, p.service, p.hosts)).collect();
+        println!("常見端口: {}", common.join("、"));
+    }
+    for finding in &block.findings {
+        println!("{}", format!("  ! {}", finding).yellow());
+    }
+}
+
+// 所有網段的一覽表
+pub fn display_summary(blocks: &[BlockSummary]) {
+    println!("\n{}", "=== 網段彙總 ===".bold());
+    for block in blocks {
+        println!(
+            "{}  主機 {:>3}  有開放 {:>3}  開放端口 {:>4}  注意事項 {}",
+            crate::layout::fit(&block.title(), 36),
+            block.hosts.len(),
+            block.hosts_up,
+            block.open_ports,
+            block.findings.len()
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use colored::*;
+use ipnet::IpNet;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use crate::targets::TargetSpec;
+
+// IPv6 一律以 /64 為單位；一個 /64 通常是同一個網段
+const V6_PREFIX: u8 = 64;
+
+// 剖析結果列出延後次數最多的網段數
+const TOP_NETWORKS: usize = 5;
+
+// --per-net-concurrency PREFIX:N，例如 24:4 代表每個 IPv4 /24 (IPv6 /64) 同時最多 4 個探測
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetLimit {
+    pub prefix_v4: u8,
+    pub cap: usize,
+}
+
+impl NetLimit {
+    // 位址所屬的網段
+    pub fn network(&self, addr: IpAddr) -> IpNet {
+        let prefix = match addr {
+            IpAddr::V4(_) => self.prefix_v4,
+            IpAddr::V6(_) => V6_PREFIX,
+        };
+        IpNet::new(addr, prefix).expect("prefix checked by parse").trunc()
+    }
+
+    // 目標涵蓋的網段數 (估計用的上限，單一主機各算一個)
+    pub fn network_count(&self, target: &TargetSpec) -> u128 {
+        match target {
+            TargetSpec::Network(net, _) => {
+                let prefix = match net {
+                    IpNet::V4(_) => self.prefix_v4,
+                    IpNet::V6(_) => V6_PREFIX,
+                };
+                1u128.checked_shl(prefix.saturating_sub(net.prefix_len()) as u32).unwrap_or(u128::MAX)
+            }
+            _ => 1,
+        }
+    }
+
+    // 例如 "每個 /24 (IPv6 /64) 最多 4 個"
+    pub fn describe(&self) -> String {
+        format!("每個 /{} (IPv6 /{}) 最多 {} 個", self.prefix_v4, V6_PREFIX, self.cap)
+    }
+}
+
+pub fn parse(s: &str) -> Result<NetLimit, String> {
+    let (prefix, cap) = s
+        .trim()
+        .split_once(':')
+        .ok_or_else(|| format!("無效的網段並發上限 '{}' (應為 前綴長度:數量，例如 24:4)", s))?;
+    let prefix_v4: u8 = prefix
+        .trim()
+        .trim_start_matches('/')
+        .parse()
+        .ok()
+        .filter(|p| (1..=32).contains(p))
+        .ok_or_else(|| format!("無效的 IPv4 前綴長度 '{}' (應為 1-32)", prefix))?;
+    let cap: usize = cap
+        .trim()
+        .parse()
+        .ok()
+        .filter(|&n| n > 0)
+        .ok_or_else(|| format!("無效的網段並發數量 '{}' (應為正整數)", cap))?;
+    Ok(NetLimit { prefix_v4, cap })
+}
+
+// 依目的網段分配的並發許可
+// 排程器以 try_acquire 取得許可，網段已滿時先輪到其他主機；每個被延後的探測記錄一次
+#[derive(Debug)]
+pub struct NetLimiter {
+    pub limit: NetLimit,
+    networks: Mutex<HashMap<IpNet, Arc<Semaphore>>>,
+    // 各網段因上限而延後的探測數
+    throttled: Mutex<HashMap<IpNet, u64>>,
+}
+
+impl NetLimiter {
+    pub fn new(limit: NetLimit) -> Self {
+        NetLimiter {
+            limit,
+            networks: Mutex::new(HashMap::new()),
+            throttled: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // 位址所屬網段的許可；同一網段的主機共用
+    pub fn semaphore(&self, addr: IpAddr) -> Arc<Semaphore> {
+        let network = self.limit.network(addr);
+        let mut networks = self.networks.lock().expect("netlimit lock");
+        networks
+            .entry(network)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.limit.cap)))
+            .clone()
+    }
+
+    // 網段未滿時立即取得許可，已滿時回傳 None
+    pub fn try_acquire(&self, addr: IpAddr) -> Option<OwnedSemaphorePermit> {
+        self.semaphore(addr).try_acquire_owned().ok()
+    }
+
+    // 記錄一次因網段上限而延後的探測
+    pub fn record_throttled(&self, addr: IpAddr) {
+        *self.throttled.lock().expect("netlimit lock").entry(self.limit.network(addr)).or_default() += 1;
+    }
+
+    // 延後次數，多的在前
+    pub fn throttled(&self) -> Vec<(IpNet, u64)> {
+        let mut counts: Vec<(IpNet, u64)> = self
+            .throttled
+            .lock()
+            .expect("netlimit lock")
+            .iter()
+            .map(|(net, count)| (*net, *count))
+            .collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        counts
+    }
+}
+
+// --profile-scan 時顯示網段上限延後的探測數
+pub fn display_throttled(limiter: &NetLimiter) {
+    let counts = limiter.throttled();
+    let total: u64 = counts.iter().map(|(_, count)| count).sum();
+    println!("網段並發上限: {}，延後 {} 個探測", limiter.limit.describe(), total);
+    for (network, count) in counts.iter().take(TOP_NETWORKS) {
+        println!("  {:>18}  {} 個", network.to_string(), count);
+    }
+    if counts.len() > TOP_NETWORKS {
+        println!("{}", format!("  ...另有 {} 個網段", counts.len() - TOP_NETWORKS).dimmed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::prober::fake::{Script, Scripted, ScriptedProber};
+    use crate::testutil::{host, scan, scripted_plan};
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    fn net(text: &str) -> IpNet {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn limits_parse_prefix_and_count() {
+        assert_eq!(parse("24:4"), Ok(NetLimit { prefix_v4: 24, cap: 4 }));
+        assert_eq!(parse(" /16 : 10 "), Ok(NetLimit { prefix_v4: 16, cap: 10 }));
+        assert_eq!(parse("24"), Err("無效的網段並發上限 '24' (應為 前綴長度:數量，例如 24:4)".to_string()));
+        assert_eq!(parse("33:4"), Err("無效的 IPv4 前綴長度 '33' (應為 1-32)".to_string()));
+        assert_eq!(parse("0:4").unwrap_err(), "無效的 IPv4 前綴長度 '0' (應為 1-32)");
+        assert_eq!(parse("24:0"), Err("無效的網段並發數量 '0' (應為正整數)".to_string()));
+        assert!(parse("24:x").is_err());
+        assert_eq!(parse("24:4").unwrap().describe(), "每個 /24 (IPv6 /64) 最多 4 個");
+    }
+
+    #[test]
+    fn addresses_are_keyed_by_v4_prefix_and_v6_slash_64() {
+        let limit = parse("24:4").unwrap();
+        assert_eq!(limit.network(ip("192.0.2.77")), net("192.0.2.0/24"));
+        assert_eq!(limit.network(ip("2001:db8::1:2")), net("2001:db8::/64"));
+        assert_ne!(limit.network(ip("2001:db8:0:1::1")), limit.network(ip("2001:db8::1")));
+        assert_eq!(parse("20:1").unwrap().network(ip("10.1.31.5")), net("10.1.16.0/20"));
+
+        let count = |target: &str| match target.parse::<IpNet>() {
+            Ok(net) => limit.network_count(&TargetSpec::Network(net, Default::default())),
+            Err(_) => limit.network_count(&TargetSpec::Host { name: target.to_string(), addr: ip(target) }),
+        };
+        assert_eq!(count("10.0.0.0/22"), 4);
+        assert_eq!(count("10.0.0.0/28"), 1);
+        assert_eq!(count("192.0.2.1"), 1);
+        assert_eq!(count("2001:db8::/60"), 16);
+        assert_eq!(count("::/0"), 1 << 64);
+    }
+
+    #[test]
+    fn hosts_in_one_network_share_permits() {
+        let limiter = NetLimiter::new(parse("24:2").unwrap());
+        let first = limiter.try_acquire(ip("192.0.2.1")).unwrap();
+        let _second = limiter.try_acquire(ip("192.0.2.200")).unwrap();
+        assert!(limiter.try_acquire(ip("192.0.2.3")).is_none());
+        // 其他網段不受影響
+        assert!(limiter.try_acquire(ip("192.0.3.1")).is_some());
+        drop(first);
+        assert!(limiter.try_acquire(ip("192.0.2.3")).is_some());
+
+        for addr in ["192.0.2.9", "192.0.2.10", "198.51.100.1", "192.0.2.9"] {
+            limiter.record_throttled(ip(addr));
+        }
+        assert_eq!(limiter.throttled(), vec![(net("192.0.2.0/24"), 3), (net("198.51.100.0/24"), 1)]);
+    }
+
+    fn in_net(text: &str) -> impl Fn(IpAddr) -> bool {
+        let net = net(text);
+        move |addr| net.contains(&addr)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_per_network_cap_is_never_exceeded() {
+        let prober = Arc::new(ScriptedProber::new().fallback(Script::new(Scripted::Open, Duration::from_millis(100))));
+        let mut hosts: Vec<IpAddr> = (1..=6).map(host).collect();
+        hosts.extend([ip("198.51.100.1"), ip("198.51.100.2"), ip("198.51.100.3")]);
+        let ports: Vec<u16> = (1..=5).collect();
+        let mut plan = scripted_plan(&hosts, &ports, 32, prober.clone());
+        let limiter = Arc::new(NetLimiter::new(parse("24:2").unwrap()));
+        plan.per_net = Some(limiter.clone());
+        let records = scan(&plan).await;
+
+        assert_eq!(records.len(), 45);
+        assert_eq!(prober.peak_among(in_net("192.0.2.0/24")), 2);
+        assert_eq!(prober.peak_among(in_net("198.51.100.0/24")), 2);
+        assert_eq!(prober.peak(), 4);
+        let throttled = limiter.throttled();
+        assert_eq!(throttled.len(), 2);
+        assert!(throttled.iter().all(|(_, count)| *count > 0), "{:?}", throttled);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ipv6_hosts_are_limited_per_slash_64() {
+        let prober = Arc::new(ScriptedProber::new().fallback(Script::new(Scripted::Open, Duration::from_millis(50))));
+        let hosts = [ip("2001:db8::1"), ip("2001:db8::ffff:1"), ip("2001:db8:0:1::1")];
+        let mut plan = scripted_plan(&hosts, &[22, 80, 443], 16, prober.clone());
+        plan.per_net = Some(Arc::new(NetLimiter::new(parse("24:1").unwrap())));
+        scan(&plan).await;
+        assert_eq!(prober.peak_among(in_net("2001:db8::/64")), 1);
+        assert_eq!(prober.peak_among(in_net("2001:db8:0:1::/64")), 1);
+        assert_eq!(prober.peak(), 2);
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::time::Duration;
+use chrono::{DateTime, Local, NaiveTime};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use crate::alerts::{Alert, Severity};
+
+// 寄信指令的逾時
+const SENDMAIL_TIMEOUT: Duration = Duration::from_secs(30);
+
+// [watch.email]：critical 告警經由本機的 sendmail 寄出
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmailConfig {
+    pub to: Vec<String>,
+    pub from: Option<String>,
+    #[serde(default = "default_sendmail")]
+    pub sendmail: String,
+}
+
+fn default_sendmail() -> String {
+    "/usr/sbin/sendmail".to_string()
+}
+
+// 每天的靜音時段，例如 22:00-07:00 (本地時間，可跨越午夜)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuietHours {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl QuietHours {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let invalid = || format!("quiet_hours 格式應為 HH:MM-HH:MM: {}", spec);
+        let (start, end) = spec.split_once('-').ok_or_else(invalid)?;
+        let time = |s: &str| NaiveTime::parse_from_str(s.trim(), "%H:%M").map_err(|_| invalid());
+        let (start, end) = (time(start)?, time(end)?);
+        if start == end {
+            return Err(format!("quiet_hours 的開始與結束相同: {}", spec));
+        }
+        Ok(QuietHours { start, end })
+    }
+
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        match self.start < self.end {
+            true => self.start <= time && time < self.end,
+            false => time >= self.start || time < self.end,
+        }
+    }
+}
+
+// 目前時間的來源；路由只透過它取得時間，測試可換成固定或手動推進的時鐘
+pub trait Clock {
+    fn now(&self) -> DateTime<Local>;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+}
+
+// 告警送往的地方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Route {
+    pub webhook: bool,
+    pub email: bool,
+}
+
+impl Route {
+    // info 只記錄、warning 送 webhook、critical 送 webhook 與電子郵件
+    pub fn of(severity: Severity) -> Self {
+        match severity {
+            Severity::Info => Route { webhook: false, email: false },
+            Severity::Warning => Route { webhook: true, email: false },
+            Severity::Critical => Route { webhook: true, email: true },
+        }
+    }
+}
+
+// 規則引擎產生的告警經過去重與靜音時段後的處置
+#[derive(Debug)]
+pub enum Verdict {
+    // 立即送出 (suppressed 已填入先前被抑制的次數)
+    Send(Alert, Route),
+    // 去重視窗內的相同告警，只計數
+    Suppressed { rule: String, count: u32 },
+    // 靜音時段中的非 critical 告警，時段結束後彙整送出
+    Queued(Alert),
+}
+
+// 靜音時段結束後一次送出的彙整
+#[derive(Debug, Serialize)]
+pub struct Digest {
+    pub event: &'static str,
+    pub alerts: Vec<Alert>,
+}
+
+#[derive(Debug)]
+struct Sent {
+    at: DateTime<Local>,
+    suppressed: u32,
+}
+
+// 介於規則引擎與 webhook / 電子郵件之間：相同告警在 window 內只送一次，靜音時段中暫存
+#[derive(Debug)]
+pub struct Router<C: Clock = SystemClock> {
+    clock: C,
+    window: Duration,
+    quiet: Option<QuietHours>,
+    sent: HashMap<String, Sent>,
+    queued: Vec<Alert>,
+}
+
+impl<C: Clock> Router<C> {
+    pub fn new(clock: C, window: Duration, quiet: Option<QuietHours>) -> Self {
+        Router { clock, window, quiet, sent: HashMap::new(), queued: Vec::new() }
+    }
+
+    fn quiet_now(&self, now: DateTime<Local>) -> bool {
+        self.quiet.is_some_and(|quiet| quiet.contains(now.time()))
+    }
+
+    pub fn submit(&mut self, mut alert: Alert) -> Verdict {
+        let now = self.clock.now();
+        let window = chrono::Duration::from_std(self.window).unwrap_or(chrono::Duration::MAX);
+        match self.sent.get_mut(&alert.key) {
+            Some(sent) if now.signed_duration_since(sent.at) < window => {
+                sent.suppressed += 1;
+                return Verdict::Suppressed { rule: alert.rule, count: sent.suppressed };
+            }
+            Some(sent) => alert.suppressed = sent.suppressed,
+            None => {}
+        }
+        self.sent.insert(alert.key.clone(), Sent { at: now, suppressed: 0 });
+
+        let route = Route::of(alert.severity);
+        if route.webhook && alert.severity < Severity::Critical && self.quiet_now(now) {
+            self.queued.push(alert.clone());
+            return Verdict::Queued(alert);
+        }
+        Verdict::Send(alert, route)
+    }
+
+    // 靜音時段結束後第一次呼叫時取出暫存的告警
+    pub fn digest(&mut self) -> Option<Digest> {
+        if self.queued.is_empty() || self.quiet_now(self.clock.now()) {
+            return None;
+        }
+        Some(Digest { event: "alert_digest", alerts: std::mem::take(&mut self.queued) })
+    }
+
+    pub fn pending(&self) -> usize {
+        self.queued.len()
+    }
+}
+
+// 以 sendmail -t 寄出；失敗回傳原因
+pub async fn email(config: &EmailConfig, subject: &str, body: &str) -> Result<(), String> {
+    let mut message = String::new();
+    if let Some(from) = &config.from {
+        message.push_str(&format!("From: {}\r\n", from));
+    }
+    message.push_str(&format!("To: {}\r\n", config.to.join(", ")));
+    message.push_str(&format!("Subject: {}\r\n", subject));
+    message.push_str("MIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n");
+    message.push_str(body);
+
+    let mut child = Command::new(&config.sendmail)
+        .arg("-t")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("無法執行 {}: {}", config.sendmail, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(message.as_bytes()).await.map_err(|e| e.to_string())?;
+    }
+    let output = tokio::time::timeout(SENDMAIL_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| format!("{} 逾時", config.sendmail))?
+        .map_err(|e| e.to_string())?;
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(format!("{} 結束代碼 {}: {}", config.sendmail, output.status, String::from_utf8_lossy(&output.stderr).trim())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use chrono::TimeZone;
+
+    // 手動推進的時鐘
+    #[derive(Debug, Clone)]
+    struct ManualClock(Rc<Cell<DateTime<Local>>>);
+
+    impl ManualClock {
+        fn at(hour: u32, minute: u32) -> Self {
+            ManualClock(Rc::new(Cell::new(Local.with_ymd_and_hms(2026, 1, 5, hour, minute, 0).unwrap())))
+        }
+
+        fn advance(&self, minutes: i64) {
+            self.0.set(self.0.get() + chrono::Duration::minutes(minutes));
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> DateTime<Local> {
+            self.0.get()
+        }
+    }
+
+    fn alert(key: &str, severity: Severity) -> Alert {
+        Alert {
+            rule: format!("rule-{}", key),
+            severity,
+            key: key.to_string(),
+            iteration: 1,
+            message: format!("{} 狀態改變", key),
+            history: Vec::new(),
+            external_ip_changed: false,
+            clock_jump: false,
+            suppressed: 0,
+        }
+    }
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    const WINDOW: Duration = Duration::from_secs(600);
+
+    #[test]
+    fn quiet_hours_may_cross_midnight() {
+        let night = QuietHours::parse("22:00-07:00").unwrap();
+        for (hour, minute, quiet) in [(21, 59, false), (22, 0, true), (0, 0, true), (6, 59, true), (7, 0, false), (12, 0, false)] {
+            assert_eq!(night.contains(time(hour, minute)), quiet, "{:02}:{:02}", hour, minute);
+        }
+        let lunch = QuietHours::parse(" 12:00 - 13:30 ").unwrap();
+        assert!(lunch.contains(time(12, 0)) && lunch.contains(time(13, 29)) && !lunch.contains(time(13, 30)));
+
+        assert_eq!(QuietHours::parse("22:00").unwrap_err(), "quiet_hours 格式應為 HH:MM-HH:MM: 22:00");
+        assert_eq!(QuietHours::parse("25:00-07:00").unwrap_err(), "quiet_hours 格式應為 HH:MM-HH:MM: 25:00-07:00");
+        assert_eq!(QuietHours::parse("07:00-07:00").unwrap_err(), "quiet_hours 的開始與結束相同: 07:00-07:00");
+    }
+
+    #[test]
+    fn severities_route_to_their_senders() {
+        assert_eq!(Route::of(Severity::Info), Route { webhook: false, email: false });
+        assert_eq!(Route::of(Severity::Warning), Route { webhook: true, email: false });
+        assert_eq!(Route::of(Severity::Critical), Route { webhook: true, email: true });
+    }
+
+    #[test]
+    fn identical_alerts_are_suppressed_within_the_window() {
+        let clock = ManualClock::at(12, 0);
+        let mut router = Router::new(clock.clone(), WINDOW, None);
+        assert!(matches!(router.submit(alert("a", Severity::Warning)), Verdict::Send(_, Route { webhook: true, email: false })));
+        clock.advance(1);
+        assert!(matches!(router.submit(alert("a", Severity::Warning)), Verdict::Suppressed { count: 1, .. }));
+        // 不同的鍵不受影響
+        assert!(matches!(router.submit(alert("b", Severity::Warning)), Verdict::Send(..)));
+        clock.advance(8);
+        match router.submit(alert("a", Severity::Warning)) {
+            Verdict::Suppressed { rule, count } => assert_eq!((rule.as_str(), count), ("rule-a", 2)),
+            other => panic!("{:?}", other),
+        }
+
+        // 視窗從上次送出起算；過了之後送出並附上被抑制的次數
+        clock.advance(1);
+        match router.submit(alert("a", Severity::Warning)) {
+            Verdict::Send(sent, _) => assert_eq!(sent.suppressed, 2),
+            other => panic!("{:?}", other),
+        }
+        // 計數重新開始
+        clock.advance(11);
+        match router.submit(alert("a", Severity::Warning)) {
+            Verdict::Send(sent, _) => assert_eq!(sent.suppressed, 0),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn non_critical_alerts_wait_for_the_end_of_quiet_hours() {
+        let clock = ManualClock::at(2, 50);
+        let mut router = Router::new(clock.clone(), WINDOW, Some(QuietHours::parse("22:00-03:00").unwrap()));
+        assert!(matches!(router.submit(alert("a", Severity::Warning)), Verdict::Queued(_)));
+        // critical 在靜音時段也立即送出；info 本來就只記錄
+        assert!(matches!(router.submit(alert("b", Severity::Critical)), Verdict::Send(_, Route { email: true, .. })));
+        assert!(matches!(router.submit(alert("c", Severity::Info)), Verdict::Send(_, Route { webhook: false, .. })));
+        // 暫存的告警同樣參與去重
+        assert!(matches!(router.submit(alert("a", Severity::Warning)), Verdict::Suppressed { count: 1, .. }));
+        assert_eq!(router.pending(), 1);
+        assert!(router.digest().is_none());
+
+        clock.advance(10);
+        let digest = router.digest().unwrap();
+        assert_eq!(digest.event, "alert_digest");
+        assert_eq!(digest.alerts.iter().map(|a| a.key.as_str()).collect::<Vec<_>>(), vec!["a"]);
+        assert_eq!(router.pending(), 0);
+        assert!(router.digest().is_none());
+        assert!(matches!(router.submit(alert("d", Severity::Warning)), Verdict::Send(..)));
+    }
+
+    #[tokio::test]
+    async fn email_reports_sendmail_failures() {
+        let config = |sendmail: &str| EmailConfig { to: vec!["ops@example.com".to_string()], from: None, sendmail: sendmail.to_string() };
+        // cat -t 讀完郵件內容後正常結束
+        assert_eq!(email(&config("cat"), "主旨", "內容").await, Ok(()));
+        // false 可能在寫入前就結束，錯誤可能是管線中斷或結束代碼
+        assert!(email(&config("false"), "主旨", "內容").await.is_err());
+        let missing = email(&config("/nonexistent/sendmail"), "主旨", "內容").await.unwrap_err();
+        assert!(missing.starts_with("無法執行 /nonexistent/sendmail"), "{}", missing);
+        let parsed: EmailConfig = toml::from_str("to = [\"ops@example.com\"]").unwrap();
+        assert_eq!(parsed.sendmail, "/usr/sbin/sendmail");
+    }
+}
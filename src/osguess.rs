@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::IpAddr;
+use colored::*;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+// 每台主機最多保留的 SYN-ACK 樣本數；幾個開放端口就足以判斷
+pub const SAMPLES_PER_HOST: usize = 3;
+
+// 分數低於此值時不回報猜測
+const MIN_CONFIDENCE: f64 = 0.5;
+
+// TCP 選項種類 (RFC 9293 等)
+pub const OPT_EOL: u8 = 0;
+pub const OPT_NOP: u8 = 1;
+pub const OPT_MSS: u8 = 2;
+pub const OPT_WSCALE: u8 = 3;
+pub const OPT_SACK_PERMITTED: u8 = 4;
+pub const OPT_TIMESTAMP: u8 = 8;
+
+// 一個 SYN-ACK 的特徵
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct TcpSample {
+    pub port: u16,
+    // 收到時的 IP TTL
+    pub ttl: u8,
+    pub window: u16,
+    // 選項種類依出現順序
+    pub options: Vec<u8>,
+}
+
+impl TcpSample {
+    // 選項順序的簡寫，例如 "M,S,T,N,W"
+    pub fn layout(&self) -> String {
+        self.options
+            .iter()
+            .map(|kind| match *kind {
+                OPT_EOL => "E".to_string(),
+                OPT_NOP => "N".to_string(),
+                OPT_MSS => "M".to_string(),
+                OPT_WSCALE => "W".to_string(),
+                OPT_SACK_PERMITTED => "S".to_string(),
+                OPT_TIMESTAMP => "T".to_string(),
+                other => other.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+// 作業系統類別
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OsClass {
+    Linux,
+    Windows,
+    Bsd,
+    // 網路設備與嵌入式系統 (路由器、印表機、IoT)
+    Embedded,
+}
+
+impl fmt::Display for OsClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OsClass::Linux => "Linux",
+            OsClass::Windows => "Windows",
+            OsClass::Bsd => "BSD/macOS",
+            OsClass::Embedded => "嵌入式/網路設備",
+        })
+    }
+}
+
+// 簽章：初始 TTL、選項順序與常見的視窗大小
+struct Signature {
+    class: OsClass,
+    initial_ttl: u8,
+    layout: &'static str,
+    windows: &'static [u16],
+}
+
+// 常見預設值；同一類別可有多個簽章，分數取最高者
+const SIGNATURES: &[Signature] = &[
+    Signature { class: OsClass::Linux, initial_ttl: 64, layout: "M,S,T,N,W", windows: &[65160, 64240, 43440, 29200, 28960, 14600, 5792, 5840] },
+    Signature { class: OsClass::Linux, initial_ttl: 64, layout: "M,N,N,S,N,W", windows: &[65160, 64240, 29200, 14600] },
+    Signature { class: OsClass::Windows, initial_ttl: 128, layout: "M,N,W,N,N,S", windows: &[65535, 64240, 8192, 16384] },
+    Signature { class: OsClass::Windows, initial_ttl: 128, layout: "M,N,W,S,T", windows: &[65535, 64240, 8192] },
+    Signature { class: OsClass::Bsd, initial_ttl: 64, layout: "M,N,W,N,N,T,S,E", windows: &[65535] },
+    Signature { class: OsClass::Bsd, initial_ttl: 64, layout: "M,N,W,S,T", windows: &[65535] },
+    Signature { class: OsClass::Embedded, initial_ttl: 255, layout: "M", windows: &[4128, 8192, 16384, 65535] },
+    Signature { class: OsClass::Embedded, initial_ttl: 64, layout: "M", windows: &[536, 1024, 2048, 4096, 5840, 8192] },
+    Signature { class: OsClass::Embedded, initial_ttl: 255, layout: "M,N,W,N,N,T,S", windows: &[32768, 49232] },
+];
+
+// 推論的初始 TTL：收到的 TTL 往上取最接近的常見預設值
+pub fn initial_ttl(observed: u8) -> u8 {
+    [32, 64, 128, 255].into_iter().find(|&ttl| observed <= ttl).unwrap_or(255)
+}
+
+// 猜測結果
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct OsGuess {
+    pub class: OsClass,
+    // 0-1：TTL、選項順序與視窗大小符合的比例
+    pub confidence: f64,
+    pub initial_ttl: u8,
+    // 依初始 TTL 估算的路由跳數
+    pub hops: u8,
+    pub samples: Vec<TcpSample>,
+}
+
+impl OsGuess {
+    pub fn describe(&self) -> String {
+        let level = match self.confidence {
+            c if c >= 0.9 => "高",
+            c if c >= 0.7 => "中",
+            _ => "低",
+        };
+        format!(
+            "{} (可信度{} {:.0}%，初始 TTL {}，約 {} 跳)",
+            self.class,
+            level,
+            self.confidence * 100.0,
+            self.initial_ttl,
+            self.hops
+        )
+    }
+}
+
+// 單一樣本對簽章的分數：TTL 0.4、選項順序 0.4、視窗大小 0.2
+fn score(signature: &Signature, sample: &TcpSample) -> f64 {
+    let mut score = 0.0;
+    if initial_ttl(sample.ttl) == signature.initial_ttl {
+        score += 0.4;
+    }
+    if sample.layout() == signature.layout {
+        score += 0.4;
+    }
+    if signature.windows.contains(&sample.window) {
+        score += 0.2;
+    }
+    score
+}
+
+// 以樣本比對簽章表：每個類別取最高分簽章的平均分數，分數最高且夠高的類別為猜測
+// 只依輸入的樣本計算，不做任何 I/O
+pub fn guess(samples: &[TcpSample]) -> Option<OsGuess> {
+    if samples.is_empty() {
+        return None;
+    }
+    let mut best: BTreeMap<OsClass, f64> = BTreeMap::new();
+    for signature in SIGNATURES {
+        let average = samples.iter().map(|s| score(signature, s)).sum::<f64>() / samples.len() as f64;
+        let entry = best.entry(signature.class).or_insert(0.0);
+        *entry = entry.max(average);
+    }
+    let (class, confidence) = best.into_iter().max_by(|a, b| a.1.total_cmp(&b.1))?;
+    if confidence < MIN_CONFIDENCE {
+        return None;
+    }
+    let ttl = samples.iter().map(|s| s.ttl).max().unwrap_or_default();
+    let initial = initial_ttl(ttl);
+    Some(OsGuess {
+        class,
+        confidence,
+        initial_ttl: initial,
+        hops: initial - ttl,
+        samples: samples.to_vec(),
+    })
+}
+
+// 主機標頭中的猜測
+pub fn display(guess: &OsGuess) {
+    println!("{} {}", "作業系統猜測:".bold(), guess.describe());
+}
+
+// 每台主機的猜測
+pub fn guess_all(samples: BTreeMap<IpAddr, Vec<TcpSample>>) -> BTreeMap<IpAddr, OsGuess> {
+    samples
+        .into_iter()
+        .filter_map(|(host, samples)| guess(&samples).map(|guess| (host, guess)))
+        .collect()
+}
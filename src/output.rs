@@ -0,0 +1,1270 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc as std_mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+use clap::ValueEnum;
+use colored::*;
+use rusqlite::{Connection, OptionalExtension, TransactionBehavior};
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use crate::blobs;
+use crate::context::ScanContext;
+use crate::direction::Directions;
+use crate::heartbeat::ProgressEvent;
+use crate::identity::{self, Identities, IdentitySource};
+use crate::layout::{self, Align, Layout, Table};
+use crate::metadata::RunMetadata;
+use crate::scanid;
+use crate::scanner::ScanRecord;
+use crate::share::ShareLine;
+
+pub type SinkResult = Result<(), Box<dyn Error + Send + Sync>>;
+
+// 串流輸出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Ndjson,
+    Csv,
+    Sqlite,
+    // 可用 diff 比較的固定格式純文字
+    Plain,
+}
+
+impl OutputFormat {
+    // 依副檔名推斷格式
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "ndjson" | "jsonl" => Some(OutputFormat::Ndjson),
+            "csv" => Some(OutputFormat::Csv),
+            "db" | "sqlite" | "sqlite3" => Some(OutputFormat::Sqlite),
+            "txt" | "plain" => Some(OutputFormat::Plain),
+            _ => None,
+        }
+    }
+}
+
+// 輸出目的地：所有格式依序收到同一份執行資訊、逐筆結果與最後的摘要
+// 每個目的地在自己的執行緒中寫入 (見 spawn_writer)，失敗時只停用該目的地
+pub trait OutputSink: Send {
+    // 開啟後、掃描開始前呼叫一次
+    fn on_metadata(&mut self, _metadata: &RunMetadata) -> SinkResult {
+        Ok(())
+    }
+
+    fn on_result(&mut self, record: &ScanRecord) -> SinkResult;
+
+    // --heartbeat 的進度事件；只有 NDJSON 寫入，其他格式略過
+    fn on_progress(&mut self, _event: &ProgressEvent) -> SinkResult {
+        Ok(())
+    }
+
+    // 所有結果之後呼叫一次；在此寫完並關閉輸出
+    fn on_summary(&mut self, summary: &ScanSummary) -> SinkResult;
+}
+
+// 寫入通道的一項：掃描結果或心跳事件
+#[derive(Debug)]
+pub enum Entry {
+    Record(Box<ScanRecord>),
+    Progress(ProgressEvent),
+}
+
+impl From<ScanRecord> for Entry {
+    fn from(record: ScanRecord) -> Self {
+        Entry::Record(Box::new(record))
+    }
+}
+
+// 每行一個 JSON 物件，開頭加上執行資訊的掃描 ID
+struct NdjsonSink {
+    out: BufWriter<File>,
+    scan_id: String,
+}
+
+impl OutputSink for NdjsonSink {
+    fn on_metadata(&mut self, metadata: &RunMetadata) -> SinkResult {
+        self.scan_id = metadata.scan_id.clone();
+        Ok(())
+    }
+
+    fn on_result(&mut self, record: &ScanRecord) -> SinkResult {
+        serde_json::to_writer(&mut self.out, &scanid::tag(&self.scan_id, record))?;
+        self.out.write_all(b"\n")?;
+        Ok(())
+    }
+
+    // 心跳要讓讀取端立即看到，寫入後清空緩衝
+    fn on_progress(&mut self, event: &ProgressEvent) -> SinkResult {
+        serde_json::to_writer(&mut self.out, &scanid::tag(&self.scan_id, event))?;
+        self.out.write_all(b"\n")?;
+        self.out.flush()?;
+        Ok(())
+    }
+
+    fn on_summary(&mut self, _summary: &ScanSummary) -> SinkResult {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+struct CsvSink {
+    out: BufWriter<File>,
+}
+
+// 未測試的方向 (--no-inbound / --no-outbound) 為空欄位
+fn tested(tested: bool, value: bool) -> String {
+    match tested {
+        true => value.to_string(),
+        false => String::new(),
+    }
+}
+
+// CSV 欄位跳脫
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// 開頭以註解記錄執行資訊
+fn metadata_comments(out: &mut impl Write, metadata: &RunMetadata) -> io::Result<()> {
+    for (key, value) in metadata.entries() {
+        writeln!(out, "# {}: {}", key, value.replace(['\r', '\n'], " "))?;
+    }
+    Ok(())
+}
+
+impl OutputSink for CsvSink {
+    fn on_metadata(&mut self, metadata: &RunMetadata) -> SinkResult {
+        metadata_comments(&mut self.out, metadata)?;
+        writeln!(self.out, "host,port,service,category,inbound,outbound,grade,tags")?;
+        Ok(())
+    }
+
+    fn on_result(&mut self, record: &ScanRecord) -> SinkResult {
+        writeln!(
+            self.out,
+            "{},{},{},{},{},{},{},{}",
+            record.host,
+            record.port.port,
+            csv_field(&record.port.service),
+            csv_field(&record.port.category),
+            tested(record.result.directions.inbound(), record.result.inbound),
+            tested(record.result.directions.outbound(), record.result.outbound),
+            record.result.grade.as_ref().map(|g| g.grade.to_string()).unwrap_or_default(),
+            csv_field(&record.port.tags.join(";"))
+        )?;
+        Ok(())
+    }
+
+    fn on_summary(&mut self, _summary: &ScanSummary) -> SinkResult {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+// 純文字格式的版本；欄位名稱、順序與寬度改變時才遞增，腳本可以依此判斷
+pub const PLAIN_FORMAT_VERSION: u32 = 2;
+
+// 欄位的最小寬度 (不足時以空白補齊，較長的值不截斷)；HOST 依位址家族為 IPv4 或 IPv6 的最長寫法
+const PLAIN_HOST_V4: usize = 15;
+const PLAIN_HOST_V6: usize = 39;
+const PLAIN_SERVICE: usize = 16;
+const PLAIN_CATEGORY: usize = 12;
+
+// 記憶體中排序的行數上限；超過時排序後寫到輸出檔旁的暫存檔，結束時合併
+const PLAIN_RUN_LINES: usize = 100_000;
+
+// 每個端口一行，欄位固定且沒有顏色，例如
+//   HOST=10.0.0.5        PORT=00443 SERVICE=HTTPS            CAT=Web          IN=open   OUT=open   LAT=23ms
+// 結果到齊後依主機、端口與整行內容排序輸出，兩次掃描可以直接 diff -u
+// 大型掃描以外部排序處理：記憶體只保留一批，其餘是已排序的暫存檔
+struct PlainSink {
+    out: BufWriter<File>,
+    path: PathBuf,
+    lines: Vec<PlainLine>,
+    run_lines: usize,
+    runs: Vec<PathBuf>,
+}
+
+// (主機, 端口, 整行)；以整行作為最後的排序鍵，排序結果不受寫入順序影響
+type PlainLine = (IpAddr, u16, String);
+
+// 欄位值不含空白與 "="，讓每一行都能以空白切開
+fn plain_value(value: &str) -> String {
+    match value.trim() {
+        "" => "-".to_string(),
+        value => value.chars().map(|c| if c.is_whitespace() || c == '=' { '_' } else { c }).collect(),
+    }
+}
+
+// 未測試的方向為 "-"
+fn plain_state(tested: bool, open: bool) -> &'static str {
+    match (tested, open) {
+        (false, _) => "-",
+        (true, true) => "open",
+        (true, false) => "closed",
+    }
+}
+
+// 以終端顯示寬度補齊 (全形字元佔兩格)，服務名稱為中文時欄位仍對齊
+fn plain_padded(value: &str, width: usize) -> String {
+    let value = plain_value(value);
+    let pad = width.saturating_sub(layout::display_width(&value));
+    value + &" ".repeat(pad)
+}
+
+fn plain_line(record: &ScanRecord) -> String {
+    let latency = match (record.result.error, record.result.latency_ms) {
+        (Some(_), _) => "error".to_string(),
+        (None, Some(ms)) => format!("{}ms", ms.round() as u64),
+        (None, None) => "-".to_string(),
+    };
+    let host_width = match record.host {
+        IpAddr::V4(_) => PLAIN_HOST_V4,
+        IpAddr::V6(_) => PLAIN_HOST_V6,
+    };
+    format!(
+        "HOST={:host_width$} PORT={:05} SERVICE={} CAT={} IN={:6} OUT={:6} LAT={}",
+        record.host.to_string(),
+        record.port.port,
+        plain_padded(&record.port.service, PLAIN_SERVICE),
+        plain_padded(&record.port.category, PLAIN_CATEGORY),
+        plain_state(record.result.directions.inbound(), record.result.inbound),
+        plain_state(record.result.directions.outbound(), record.result.outbound),
+        latency
+    )
+}
+
+// 從暫存檔讀回的行取出排序鍵
+fn plain_key(line: String) -> io::Result<PlainLine> {
+    let mut fields = line.split_whitespace();
+    let host = fields.next().and_then(|field| field.strip_prefix("HOST=")?.parse().ok());
+    let port = fields.next().and_then(|field| field.strip_prefix("PORT=")?.parse().ok());
+    match (host, port) {
+        (Some(host), Some(port)) => Ok((host, port, line)),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, format!("排序暫存檔內容損壞: {}", line))),
+    }
+}
+
+impl PlainSink {
+    fn new(out: BufWriter<File>, path: &Path, run_lines: usize) -> Self {
+        PlainSink { out, path: path.to_path_buf(), lines: Vec::new(), run_lines, runs: Vec::new() }
+    }
+
+    // 目前這一批排序後寫到暫存檔
+    fn spill(&mut self) -> SinkResult {
+        self.lines.sort_unstable();
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".sort{}.tmp", self.runs.len()));
+        let path = PathBuf::from(name);
+        self.runs.push(path.clone());
+        let mut run = BufWriter::new(File::create(&path)?);
+        for (_, _, line) in self.lines.drain(..) {
+            writeln!(run, "{}", line)?;
+        }
+        run.flush()?;
+        Ok(())
+    }
+
+    // 合併各暫存檔：每個檔案只讀目前最小的一行
+    fn merge(&mut self) -> SinkResult {
+        let mut readers = Vec::new();
+        for path in &self.runs {
+            readers.push(BufReader::new(File::open(path)?).lines());
+        }
+        let mut heap = BinaryHeap::new();
+        for (index, reader) in readers.iter_mut().enumerate() {
+            if let Some(line) = reader.next() {
+                heap.push(Reverse((plain_key(line?)?, index)));
+            }
+        }
+        while let Some(Reverse(((_, _, line), index))) = heap.pop() {
+            writeln!(self.out, "{}", line)?;
+            if let Some(next) = readers[index].next() {
+                heap.push(Reverse((plain_key(next?)?, index)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OutputSink for PlainSink {
+    fn on_metadata(&mut self, metadata: &RunMetadata) -> SinkResult {
+        writeln!(self.out, "# format: portscanner-plain/{}", PLAIN_FORMAT_VERSION)?;
+        metadata_comments(&mut self.out, metadata)?;
+        Ok(())
+    }
+
+    fn on_result(&mut self, record: &ScanRecord) -> SinkResult {
+        self.lines.push((record.host, record.port.port, plain_line(record)));
+        if self.lines.len() >= self.run_lines {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn on_summary(&mut self, _summary: &ScanSummary) -> SinkResult {
+        match self.runs.is_empty() {
+            true => {
+                self.lines.sort_unstable();
+                for (_, _, line) in self.lines.drain(..) {
+                    writeln!(self.out, "{}", line)?;
+                }
+            }
+            false => {
+                if !self.lines.is_empty() {
+                    self.spill()?;
+                }
+                self.merge()?;
+            }
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+// 結束或中途失敗時都刪除暫存檔
+impl Drop for PlainSink {
+    fn drop(&mut self) {
+        for run in &self.runs {
+            let _ = std::fs::remove_file(run);
+        }
+    }
+}
+
+// SQLite 每批次提交的筆數
+const SQLITE_BATCH: usize = 1000;
+
+// 其他執行個體持有寫入鎖時，SQLite 內部等待的時間
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 等待逾時後整個操作重試的次數與起始間隔 (每次加倍)
+const BUSY_RETRIES: u32 = 5;
+const BUSY_BACKOFF: Duration = Duration::from_millis(200);
+
+// 超過此時間的執行鎖視為遺留 (執行個體當掉或被中止)
+const RUN_LOCK_EXPIRY_SECS: i64 = 24 * 60 * 60;
+
+fn is_busy(error: &rusqlite::Error) -> bool {
+    matches!(
+        error.sqlite_error_code(),
+        Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked)
+    )
+}
+
+// 資料庫忙碌時以遞增的間隔重試；重試用盡仍忙碌時回傳說明錯誤
+pub fn with_retry<T>(mut operation: impl FnMut() -> rusqlite::Result<T>) -> Result<T, Box<dyn Error + Send + Sync>> {
+    let mut wait = BUSY_BACKOFF;
+    for _ in 0..BUSY_RETRIES {
+        match operation() {
+            Err(e) if is_busy(&e) => {
+                std::thread::sleep(wait);
+                wait *= 2;
+            }
+            other => return Ok(other?),
+        }
+    }
+    operation().map_err(|e| match is_busy(&e) {
+        true => "結果資料庫被其他執行個體長時間鎖定，請稍後再試".into(),
+        false => e.into(),
+    })
+}
+
+// 開啟結果資料庫：WAL 模式讓讀取不阻擋寫入，並等待其他執行個體的寫入鎖
+// history 子命令與 SQLite 輸出共用
+pub fn open_database(path: &Path) -> Result<Connection, Box<dyn Error + Send + Sync>> {
+    let conn = Connection::open(path)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    with_retry(|| conn.query_row("PRAGMA journal_mode = WAL", [], |row| row.get::<_, String>(0)))?;
+    // 多個執行個體同時升級舊版資料庫時，只有一個會真正加上欄位
+    with_retry(|| {
+        conn.execute_batch("BEGIN IMMEDIATE")?;
+        match migrate(&conn) {
+            Ok(()) => conn.execute_batch("COMMIT"),
+            Err(e) => {
+                let _ = conn.execute_batch("ROLLBACK");
+                Err(e)
+            }
+        }
+    })?;
+    Ok(conn)
+}
+
+// 執行鎖的持有者：主機名稱與程序編號
+fn lock_owner(metadata: &RunMetadata) -> String {
+    format!("{}:{}", metadata.hostname.as_deref().unwrap_or("?"), std::process::id())
+}
+
+// 持有者是否仍在執行；只能確認同一台主機上的程序，其他主機的鎖依期限判斷
+fn owner_alive(owner: &str, hostname: &str) -> bool {
+    let Some((host, pid)) = owner.rsplit_once(':') else {
+        return false;
+    };
+    if host != hostname {
+        return true;
+    }
+    let Ok(pid) = pid.parse::<u32>() else {
+        return false;
+    };
+    process_alive(pid)
+}
+
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    // 訊號 0 只檢查程序是否存在；EPERM 代表存在但屬於其他使用者
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM) }
+}
+
+#[cfg(not(unix))]
+fn process_alive(_pid: u32) -> bool {
+    true
+}
+
+// 取得掃描編號的執行鎖；同一秒開始的其他執行個體正在寫入、或該編號已有紀錄時改用下一個編號
+// 回傳實際使用的掃描編號
+fn acquire_run_lock(conn: &mut Connection, scanned_at: i64, owner: &str, hostname: &str) -> Result<i64, Box<dyn Error + Send + Sync>> {
+    let now = crate::timefmt::now();
+    with_retry(|| {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS scan_locks (
+                scanned_at INTEGER PRIMARY KEY,
+                owner TEXT NOT NULL,
+                acquired_at INTEGER NOT NULL
+            )",
+            [],
+        )?;
+        let mut candidate = scanned_at;
+        loop {
+            // 已完成的掃描 (或只寫入結果的舊版紀錄) 佔用的編號不能再用
+            let recorded: bool = tx.query_row(
+                "SELECT EXISTS (SELECT 1 FROM scan_runs WHERE scanned_at = ?1)
+                     OR EXISTS (SELECT 1 FROM scan_results WHERE scanned_at = ?1)",
+                [candidate],
+                |row| row.get(0),
+            )?;
+            if recorded {
+                candidate += 1;
+                continue;
+            }
+            let holder: Option<(String, i64)> = tx
+                .query_row("SELECT owner, acquired_at FROM scan_locks WHERE scanned_at = ?1", [candidate], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })
+                .optional()?;
+            match holder {
+                Some((holder, acquired_at)) if now - acquired_at < RUN_LOCK_EXPIRY_SECS && owner_alive(&holder, hostname) => {
+                    candidate += 1;
+                }
+                _ => break,
+            }
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO scan_locks (scanned_at, owner, acquired_at) VALUES (?1, ?2, ?3)",
+            rusqlite::params![candidate, owner, now],
+        )?;
+        tx.commit()?;
+        Ok(candidate)
+    })
+}
+
+// 掃描中的結果先寫入連線私有的暫存資料表，不佔用資料庫的寫入鎖
+// 結束時在單一交易中搬入 scan_results 並記錄執行資訊，其他執行個體不會看到寫到一半的掃描
+// 掃描編號、執行鎖的擁有者與執行資訊在 on_metadata 取得
+struct SqliteSink {
+    conn: Connection,
+    scanned_at: i64,
+    scan_id: String,
+    owner: String,
+    metadata: String,
+    pending: usize,
+}
+
+impl OutputSink for SqliteSink {
+    fn on_metadata(&mut self, metadata: &RunMetadata) -> SinkResult {
+        let owner = lock_owner(metadata);
+        let hostname = metadata.hostname.as_deref().unwrap_or("?");
+        let scanned_at = acquire_run_lock(&mut self.conn, metadata.started_at, &owner, hostname)?;
+        if scanned_at != metadata.started_at {
+            eprintln!(
+                "{}",
+                format!("掃描編號 {} 已被其他執行個體使用，這次掃描記錄為 {}", metadata.started_at, scanned_at).yellow()
+            );
+        }
+        self.conn.execute_batch(
+            "CREATE TEMP TABLE IF NOT EXISTS pending_results (
+                host TEXT NOT NULL,
+                port INTEGER NOT NULL,
+                service TEXT NOT NULL,
+                category TEXT NOT NULL,
+                inbound INTEGER,
+                outbound INTEGER,
+                tags TEXT NOT NULL,
+                identity TEXT NOT NULL,
+                latency_ms REAL,
+                banner TEXT,
+                banner_sha256 BLOB
+            )",
+        )?;
+        self.scanned_at = scanned_at;
+        self.scan_id = metadata.scan_id.clone();
+        self.owner = owner;
+        self.metadata = serde_json::to_string(metadata)?;
+        Ok(())
+    }
+
+    fn on_result(&mut self, record: &ScanRecord) -> SinkResult {
+        // 橫幅在掃描結束時才寫入 blobs，掃描期間不鎖定主資料庫
+        let banner = record.result.banner.as_ref().map(serde_json::to_string).transpose()?;
+        let banner_sha256 = banner.as_deref().map(blobs::key);
+        if self.pending == 0 {
+            self.conn.execute_batch("BEGIN")?;
+        }
+        self.conn
+            .prepare_cached(
+                "INSERT INTO temp.pending_results (host, port, service, category, inbound, outbound, tags, identity, latency_ms, banner, banner_sha256)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            )?
+            .execute(rusqlite::params![
+                record.host.to_string(),
+                record.port.port,
+                record.port.service,
+                record.port.category,
+                record.result.directions.inbound().then_some(record.result.inbound),
+                record.result.directions.outbound().then_some(record.result.outbound),
+                serde_json::to_string(&record.port.tags)?,
+                record.identity.clone().unwrap_or_else(|| record.host.to_string()),
+                record.result.latency_ms,
+                banner,
+                banner_sha256,
+            ])?;
+
+        self.pending += 1;
+        if self.pending >= SQLITE_BATCH {
+            self.conn.execute_batch("COMMIT")?;
+            self.pending = 0;
+        }
+        Ok(())
+    }
+
+    fn on_summary(&mut self, _summary: &ScanSummary) -> SinkResult {
+        if self.pending > 0 {
+            self.conn.execute_batch("COMMIT")?;
+            self.pending = 0;
+        }
+        let (scanned_at, scan_id, owner, metadata) = (self.scanned_at, &self.scan_id, &self.owner, &self.metadata);
+        let conn = &mut self.conn;
+        with_retry(|| {
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            tx.execute(
+                "INSERT OR IGNORE INTO main.blobs (sha256, content)
+                 SELECT banner_sha256, banner FROM temp.pending_results WHERE banner_sha256 IS NOT NULL",
+                [],
+            )?;
+            tx.execute(
+                "INSERT INTO main.scan_results (scanned_at, host, port, service, category, inbound, outbound, tags, identity, latency_ms, banner_sha256)
+                 SELECT ?1, host, port, service, category, inbound, outbound, tags, identity, latency_ms, banner_sha256
+                 FROM temp.pending_results ORDER BY rowid",
+                [scanned_at],
+            )?;
+            tx.execute(
+                "INSERT INTO scan_runs (scanned_at, scan_id, metadata) VALUES (?1, ?2, ?3)",
+                rusqlite::params![scanned_at, scan_id, metadata],
+            )?;
+            tx.execute("DELETE FROM scan_locks WHERE scanned_at = ?1 AND owner = ?2", rusqlite::params![scanned_at, owner])?;
+            tx.commit()
+        })?;
+        self.conn.execute_batch("DELETE FROM temp.pending_results")?;
+        Ok(())
+    }
+}
+
+// 建立 SQLite 資料表並補上舊版資料庫缺少的欄位
+pub fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS scan_results (
+            id INTEGER PRIMARY KEY,
+            scanned_at INTEGER NOT NULL,
+            host TEXT NOT NULL,
+            port INTEGER NOT NULL,
+            service TEXT NOT NULL,
+            category TEXT NOT NULL,
+            inbound INTEGER,
+            outbound INTEGER,
+            tags TEXT NOT NULL DEFAULT '[]',
+            identity TEXT NOT NULL,
+            latency_ms REAL,
+            banner_sha256 BLOB REFERENCES blobs (sha256)
+        );
+        CREATE TABLE IF NOT EXISTS blobs (
+            sha256 BLOB PRIMARY KEY,
+            content TEXT NOT NULL
+        ) WITHOUT ROWID;
+        CREATE TABLE IF NOT EXISTS scan_runs (
+            scanned_at INTEGER PRIMARY KEY,
+            scan_id TEXT,
+            metadata TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS service_fingerprints (
+            identity TEXT NOT NULL,
+            port INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            value_sha256 BLOB NOT NULL REFERENCES blobs (sha256),
+            first_seen INTEGER NOT NULL,
+            last_seen INTEGER NOT NULL,
+            PRIMARY KEY (identity, port, kind)
+        );
+        CREATE TABLE IF NOT EXISTS target_backoff (
+            target TEXT PRIMARY KEY,
+            failures INTEGER NOT NULL,
+            retry_at INTEGER,
+            updated_at INTEGER NOT NULL
+        )",
+    )?;
+    let has_column = |table: &str, name: &str| -> rusqlite::Result<bool> {
+        conn.query_row("SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2", [table, name], |row| row.get(0))
+    };
+    // 舊版建立的資料庫沒有 tags 欄位
+    if !has_column("scan_results", "tags")? {
+        conn.execute_batch("ALTER TABLE scan_results ADD COLUMN tags TEXT NOT NULL DEFAULT '[]'")?;
+    }
+    // 沒有 identity 欄位的舊紀錄以 IP 作為識別
+    if !has_column("scan_results", "identity")? {
+        conn.execute_batch(
+            "ALTER TABLE scan_results ADD COLUMN identity TEXT NOT NULL DEFAULT '';
+             UPDATE scan_results SET identity = host WHERE identity = '';",
+        )?;
+    }
+    // 舊版的 inbound / outbound 不允許 NULL；--no-inbound / --no-outbound 的未測試方向寫入 NULL，需重建資料表
+    let not_null: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info('scan_results') WHERE name = 'inbound' AND \"notnull\" = 1",
+        [],
+        |row| row.get(0),
+    )?;
+    if not_null {
+        conn.execute_batch(
+            "ALTER TABLE scan_results RENAME TO scan_results_old;
+             DROP INDEX IF EXISTS scan_results_identity;
+             CREATE TABLE scan_results (
+                id INTEGER PRIMARY KEY,
+                scanned_at INTEGER NOT NULL,
+                host TEXT NOT NULL,
+                port INTEGER NOT NULL,
+                service TEXT NOT NULL,
+                category TEXT NOT NULL,
+                inbound INTEGER,
+                outbound INTEGER,
+                tags TEXT NOT NULL DEFAULT '[]',
+                identity TEXT NOT NULL
+             );
+             INSERT INTO scan_results (id, scanned_at, host, port, service, category, inbound, outbound, tags, identity)
+             SELECT id, scanned_at, host, port, service, category, inbound, outbound, tags, identity FROM scan_results_old;
+             DROP TABLE scan_results_old;",
+        )?;
+    }
+    // 舊版沒有記錄延遲；history --since 的每日延遲只涵蓋之後的掃描
+    if !has_column("scan_results", "latency_ms")? {
+        conn.execute_batch("ALTER TABLE scan_results ADD COLUMN latency_ms REAL")?;
+    }
+    // 舊版的執行紀錄沒有掃描 ID，保留為 NULL
+    if !has_column("scan_runs", "scan_id")? {
+        conn.execute_batch("ALTER TABLE scan_runs ADD COLUMN scan_id TEXT")?;
+    }
+    // 舊版沒有記錄橫幅
+    if !has_column("scan_results", "banner_sha256")? {
+        conn.execute_batch("ALTER TABLE scan_results ADD COLUMN banner_sha256 BLOB REFERENCES blobs (sha256)")?;
+    }
+    // 舊版的指紋直接存在 service_fingerprints.value；搬到 blobs 後重建資料表
+    if has_column("service_fingerprints", "value")? {
+        conn.execute_batch(
+            "ALTER TABLE service_fingerprints RENAME TO service_fingerprints_old;
+             CREATE TABLE service_fingerprints (
+                identity TEXT NOT NULL,
+                port INTEGER NOT NULL,
+                kind TEXT NOT NULL,
+                value_sha256 BLOB NOT NULL REFERENCES blobs (sha256),
+                first_seen INTEGER NOT NULL,
+                last_seen INTEGER NOT NULL,
+                PRIMARY KEY (identity, port, kind)
+             );",
+        )?;
+        // 讀取舊資料表的陳述式結束後才能刪除它
+        {
+            let mut select = conn.prepare("SELECT identity, port, kind, value, first_seen, last_seen FROM service_fingerprints_old")?;
+            let mut insert = conn.prepare(
+                "INSERT INTO service_fingerprints (identity, port, kind, value_sha256, first_seen, last_seen) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            let mut rows = select.query([])?;
+            while let Some(row) = rows.next()? {
+                let (identity, port, kind): (String, i64, String) = (row.get(0)?, row.get(1)?, row.get(2)?);
+                let key = blobs::store(conn, &row.get::<_, String>(3)?)?;
+                insert.execute(rusqlite::params![identity, port, kind, key, row.get::<_, i64>(4)?, row.get::<_, i64>(5)?])?;
+            }
+        }
+        conn.execute_batch("DROP TABLE service_fingerprints_old")?;
+    }
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS scan_results_identity ON scan_results (identity, scanned_at);
+         CREATE INDEX IF NOT EXISTS scan_results_scanned_at ON scan_results (scanned_at);",
+    )
+}
+
+// 開啟輸出目的地並寫入執行資訊；CSV 與純文字以開頭註解、SQLite 以 scan_runs 資料表記錄
+pub fn open_sink(path: &Path, format: OutputFormat, metadata: &RunMetadata) -> Result<Box<dyn OutputSink>, Box<dyn Error>> {
+    let mut sink: Box<dyn OutputSink> = match format {
+        OutputFormat::Ndjson => Box::new(NdjsonSink { out: BufWriter::new(File::create(path)?), scan_id: String::new() }),
+        OutputFormat::Csv => Box::new(CsvSink { out: BufWriter::new(File::create(path)?) }),
+        OutputFormat::Plain => Box::new(PlainSink::new(BufWriter::new(File::create(path)?), path, PLAIN_RUN_LINES)),
+        OutputFormat::Sqlite => {
+            let conn = open_database(path).map_err(|e| e.to_string())?;
+            Box::new(SqliteSink { conn, scanned_at: 0, scan_id: String::new(), owner: String::new(), metadata: String::new(), pending: 0 })
+        }
+    };
+    sink.on_metadata(metadata).map_err(|e| e.to_string())?;
+    Ok(sink)
+}
+
+// 一個 --output 目的地
+pub struct Output {
+    pub path: PathBuf,
+    pub format: OutputFormat,
+    sink: Box<dyn OutputSink>,
+}
+
+impl Output {
+    pub fn open(path: &Path, format: OutputFormat, metadata: &RunMetadata) -> Result<Self, Box<dyn Error>> {
+        let sink = open_sink(path, format, metadata)?;
+        Ok(Output { path: path.to_path_buf(), format, sink })
+    }
+
+    #[cfg(test)]
+    fn from_sink(path: &Path, format: OutputFormat, sink: Box<dyn OutputSink>) -> Self {
+        Output { path: path.to_path_buf(), format, sink }
+    }
+}
+
+// 掃描結束時每個目的地的狀態；寫入失敗的目的地從那一筆起停用
+#[derive(Debug)]
+pub struct SinkStatus {
+    pub path: PathBuf,
+    pub error: Option<String>,
+}
+
+// 串流模式下只保留的統計資料
+#[derive(Debug, Default, Serialize)]
+pub struct ScanSummary {
+    pub total: u64,
+    pub both: u64,
+    pub inbound_only: u64,
+    pub outbound_only: u64,
+    pub unavailable: u64,
+    // 掃描端錯誤 (例如檔案描述符用盡)，不計入上面的端口狀態
+    pub scan_errors: u64,
+    // 類別 -> [雙向, 只能接收, 只能發送, 不可用]
+    pub by_category: BTreeMap<String, [u64; 4]>,
+    // 最先發現的前 N 筆可連線結果
+    pub highlights: Vec<ScanRecord>,
+    #[serde(skip)]
+    pub highlight_limit: usize,
+    // 結尾的一行摘要
+    #[serde(skip)]
+    pub share: ShareLine,
+    // 紀錄測試的方向 (同一次掃描的紀錄都相同)；只測一個方向時只顯示兩種狀態
+    #[serde(skip_serializing_if = "Directions::is_both")]
+    pub directions: Directions,
+}
+
+impl ScanSummary {
+    pub fn new(highlight_limit: usize) -> Self {
+        ScanSummary {
+            highlight_limit,
+            ..Default::default()
+        }
+    }
+
+    pub fn add(&mut self, record: &ScanRecord) {
+        self.share.add(record);
+        self.directions = record.result.directions;
+        if record.result.error.is_some() {
+            self.total += 1;
+            self.scan_errors += 1;
+            return;
+        }
+        let index = match (record.result.inbound, record.result.outbound) {
+            (true, true) => 0,
+            (true, false) => 1,
+            (false, true) => 2,
+            (false, false) => 3,
+        };
+        self.total += 1;
+        match index {
+            0 => self.both += 1,
+            1 => self.inbound_only += 1,
+            2 => self.outbound_only += 1,
+            _ => self.unavailable += 1,
+        }
+        self.by_category.entry(record.port.category.clone()).or_default()[index] += 1;
+
+        if record.result.directions.open(record.result.inbound, record.result.outbound) && self.highlights.len() < self.highlight_limit {
+            self.highlights.push(record.clone());
+        }
+    }
+}
+
+// 每個目的地的事件佇列長度；最慢的目的地讓分送端等待，不無限制地暫存結果
+const SINK_QUEUE: usize = 256;
+
+// 分送給各目的地的事件；結果只放一份，各執行緒共用
+#[derive(Clone)]
+enum SinkEvent {
+    Result(Arc<ScanRecord>),
+    Progress(Arc<ProgressEvent>),
+    Summary(Arc<ScanSummary>),
+}
+
+// 目的地的寫入迴圈；第一個錯誤後結束，收不到事件的分送端就不再送給它
+fn drive(sink: &mut dyn OutputSink, events: std_mpsc::Receiver<SinkEvent>) -> Option<String> {
+    for event in events {
+        let written = match event {
+            SinkEvent::Result(record) => sink.on_result(&record),
+            SinkEvent::Progress(event) => sink.on_progress(&event),
+            SinkEvent::Summary(summary) => sink.on_summary(&summary),
+        };
+        if let Err(e) = written {
+            return Some(e.to_string());
+        }
+    }
+    None
+}
+
+// 在獨立執行緒中接收結果、統計摘要並分送到各目的地，每個目的地各自一個執行緒
+// 某個目的地寫入失敗時只停用它，其他目的地照常寫入；分送端繼續消化通道，避免掃描端永久阻塞
+pub fn spawn_writer(
+    outputs: Vec<Output>,
+    mut rx: mpsc::Receiver<Entry>,
+    highlight_limit: usize,
+    identities: Arc<Identities>,
+    context: Arc<ScanContext>,
+) -> JoinHandle<(ScanSummary, Vec<SinkStatus>)> {
+    tokio::task::spawn_blocking(move || {
+        let mut summary = ScanSummary::new(highlight_limit);
+        let statuses = thread::scope(|scope| {
+            let mut lanes: Vec<_> = outputs
+                .into_iter()
+                .map(|Output { path, mut sink, .. }| {
+                    let (tx, events) = std_mpsc::sync_channel(SINK_QUEUE);
+                    let writer = scope.spawn(move || SinkStatus { error: drive(sink.as_mut(), events), path });
+                    (Some(tx), writer)
+                })
+                .collect();
+            let mut broadcast = |event: SinkEvent| {
+                for (lane, _) in lanes.iter_mut() {
+                    if lane.as_ref().is_some_and(|tx| tx.send(event.clone()).is_err()) {
+                        *lane = None;
+                    }
+                }
+            };
+
+            while let Some(entry) = rx.blocking_recv() {
+                let mut record = match entry {
+                    Entry::Record(record) => *record,
+                    Entry::Progress(event) => {
+                        broadcast(SinkEvent::Progress(Arc::new(event)));
+                        continue;
+                    }
+                };
+                // 識別依真實 IP 決定，換成假名之前取得
+                let found = identities.of(record.host);
+                record.identity = (found.source != IdentitySource::Address).then(|| identity::show(&found, context.anonymizer.as_ref()));
+                // --anonymize：寫入任何格式前換成假名
+                let record = match &context.anonymizer {
+                    Some(anonymizer) => anonymizer.record(record),
+                    None => record,
+                };
+                summary.add(&record);
+                broadcast(SinkEvent::Result(Arc::new(record)));
+            }
+
+            let finished = Arc::new(std::mem::take(&mut summary));
+            broadcast(SinkEvent::Summary(finished.clone()));
+            let statuses: Vec<SinkStatus> = lanes
+                .into_iter()
+                .map(|(lane, writer)| {
+                    drop(lane);
+                    writer.join().expect("輸出執行緒 panic")
+                })
+                .collect();
+            summary = Arc::into_inner(finished).expect("輸出執行緒都已結束");
+            statuses
+        });
+        (summary, statuses)
+    })
+}
+
+// 顯示串流模式的掃描摘要與各目的地的寫入結果
+pub fn display_summary(summary: &ScanSummary, statuses: &[SinkStatus], screen: &Layout) {
+    display_counts(summary, screen);
+    println!();
+    for status in statuses {
+        match &status.error {
+            Some(e) => println!("{}", format!("寫入 {} 失敗，已停用此輸出: {}", status.path.display(), e).red()),
+            None => println!("結果已寫入 {}", status.path.display()),
+        }
+    }
+}
+
+// 各狀態與類別的數量及可連線端口；掃描封存 (portscanner open) 也使用
+pub fn display_counts(summary: &ScanSummary, screen: &Layout) {
+    println!("\n{}", "=== 掃描摘要 ===".bold());
+    println!("總探測數: {}", summary.total);
+    // 只測一個方向時只有兩種狀態
+    match summary.directions {
+        Directions::Both => {
+            println!("✓ {}: {}", "雙向可用".green(), summary.both);
+            println!("↓ {}: {}", "只能接收".yellow(), summary.inbound_only);
+            println!("↑ {}: {}", "只能發送".yellow(), summary.outbound_only);
+        }
+        Directions::Outbound => println!("✓ {}: {}", "可連線".green(), summary.outbound_only),
+        Directions::Inbound => println!("✓ {}: {}", "可綁定".green(), summary.inbound_only),
+    }
+    println!("✗ {}: {}", "不可用".red(), summary.unavailable);
+    if summary.scan_errors > 0 {
+        println!("! {}: {}", "掃描端錯誤".yellow(), summary.scan_errors);
+    }
+
+    println!("\n{}", "--- 各類別 ---".bold());
+    for line in category_table(summary).render(screen) {
+        println!("{}", line);
+    }
+
+    if !summary.highlights.is_empty() {
+        println!("\n{}", format!("--- 可連線端口 (前 {} 筆) ---", summary.highlight_limit).bold());
+        let mut table = Table::new(&[Align::Left, Align::Left, Align::Right, Align::Left]);
+        for record in &summary.highlights {
+            table.row(vec![record.host.to_string(), "Port".to_string(), record.port.port.to_string(), format!("({})", record.port.service)]);
+        }
+        for line in table.render(screen) {
+            println!("{}", line);
+        }
+    }
+}
+
+// 各類別的狀態數量；欄寬依類別名稱與數字決定
+fn category_table(summary: &ScanSummary) -> Table {
+    // 只測一個方向時只有兩種狀態
+    let columns: &[(&str, usize)] = match summary.directions {
+        Directions::Both => &[("✓", 0), ("↓", 1), ("↑", 2), ("✗", 3)],
+        Directions::Outbound => &[("✓", 2), ("✗", 3)],
+        Directions::Inbound => &[("✓", 1), ("✗", 3)],
+    };
+    let mut aligns = vec![Align::Left];
+    aligns.extend(columns.iter().flat_map(|_| [Align::Left, Align::Right]));
+    let mut table = Table::new(&aligns);
+    for (category, counts) in &summary.by_category {
+        let mut row = vec![category.clone()];
+        row.extend(columns.iter().flat_map(|(symbol, index)| [symbol.to_string(), counts[*index].to_string()]));
+        table.row(row);
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+    use std::thread;
+    use super::*;
+    use crate::testutil::{self, TempDir};
+    use crate::{PortInfo, ScanResult};
+
+    fn metadata(started_at: i64) -> RunMetadata {
+        RunMetadata { started_at, ..RunMetadata::collect(&[]) }
+    }
+
+    // 寫入一次完整的掃描，回傳實際使用的掃描編號
+    fn write_run(path: &Path, started_at: i64, host: IpAddr, ports: u16) -> SinkResult {
+        let mut sink = open_sink(path, OutputFormat::Sqlite, &metadata(started_at)).map_err(|e| e.to_string())?;
+        for port in 1..=ports {
+            let record = ScanRecord { host, port: PortInfo::new(port, "svc", "Test"), result: testutil::scan_result(port % 2 == 0), identity: None };
+            sink.on_result(&record)?;
+        }
+        sink.on_summary(&ScanSummary::default())
+    }
+
+    fn run_ids(path: &Path) -> Vec<(i64, i64)> {
+        let conn = open_database(path).unwrap();
+        let mut statement = conn
+            .prepare("SELECT r.scanned_at, (SELECT COUNT(*) FROM scan_results s WHERE s.scanned_at = r.scanned_at) FROM scan_runs r ORDER BY r.scanned_at")
+            .unwrap();
+        statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap().map(Result::unwrap).collect()
+    }
+
+    fn record(host: &str, port: u16, service: &str, category: &str, result: ScanResult) -> ScanRecord {
+        ScanRecord { host: host.parse().unwrap(), port: PortInfo::new(port, service, category), result, identity: None }
+    }
+
+    fn plain_records() -> Vec<ScanRecord> {
+        let mut latency = testutil::scan_result(true);
+        latency.latency_ms = Some(23.4);
+        let mut failed = testutil::scan_result(false);
+        failed.error = Some(crate::limits::ScanError::TooManyOpenFiles);
+        let mut inbound = testutil::scan_result(false);
+        inbound.inbound = true;
+        vec![
+            record("10.0.0.5", 443, "HTTPS", "Web", latency),
+            record("10.0.0.5", 22, "SSH", "Remote", inbound),
+            record("2001:db8::1", 8080, "HTTP ALT=proxy", "", testutil::scan_result(false)),
+            record("10.0.0.10", 5432, "PostgreSQL-Replication", "Database", failed),
+            record("10.0.0.5", 22, "SSH", "Remote", testutil::scan_result(false)),
+            record("10.0.0.5", 9000, "未知", "Custom", testutil::scan_result(false)),
+        ]
+    }
+
+    // 格式凍結在 portscanner-plain/2：任何輸出的改變都必須連同版本與快照一起更新
+    #[test]
+    fn plain_output_matches_snapshot() {
+        let dir = TempDir::new("plain");
+        let path = dir.path().join("scan.txt");
+        let metadata = RunMetadata {
+            hostname: Some("scanner01".to_string()),
+            username: None,
+            version: "1.0.0".to_string(),
+            command_line: vec!["portscanner".to_string(), "--target".to_string(), "10.0.0.5".to_string()],
+            started_at: 1_792_000_000,
+            scan_id: "0199e3f1-6a80-7000-8000-000000000001".to_string(),
+            ..RunMetadata::collect(&[])
+        };
+        let mut sink = open_sink(&path, OutputFormat::Plain, &metadata).unwrap();
+        for record in plain_records() {
+            sink.on_result(&record).unwrap();
+        }
+        sink.on_summary(&ScanSummary::default()).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), include_str!("snapshots/plain-v2.txt"));
+    }
+
+    // 分批寫到暫存檔再合併的結果與全部在記憶體中排序相同，結束後不留下暫存檔
+    #[test]
+    fn plain_external_sort_matches_in_memory_sort() {
+        let dir = TempDir::new("plain");
+        let records: Vec<ScanRecord> = (0..50u16)
+            .map(|n| record(&format!("10.0.{}.{}", n % 3, 50 - n), ((u32::from(n) * 7919) % 1000 + 1) as u16, "svc", "Test", testutil::scan_result(n % 2 == 0)))
+            .collect();
+        let mut outputs = Vec::new();
+        for run_lines in [usize::MAX, 1, 3, 7] {
+            let path = dir.path().join(format!("sorted-{}.txt", run_lines));
+            let mut sink = PlainSink::new(BufWriter::new(File::create(&path).unwrap()), &path, run_lines);
+            for record in &records {
+                sink.on_result(record).unwrap();
+            }
+            sink.on_summary(&ScanSummary::default()).unwrap();
+            drop(sink);
+            outputs.push(std::fs::read_to_string(&path).unwrap());
+        }
+        assert_eq!(outputs[0].lines().count(), records.len());
+        assert!(outputs.iter().all(|output| *output == outputs[0]));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 4);
+    }
+
+    // 數個執行個體在同一秒開始並同時寫入：每次掃描各自一個編號，沒有遺失或混在一起的結果，也沒有忙碌錯誤
+    #[test]
+    fn concurrent_writers_keep_every_row() {
+        const WRITERS: u8 = 8;
+        const PORTS: u16 = 300;
+        let dir = TempDir::new("sqlite");
+        let path = dir.path().join("history.db");
+        let writers: Vec<_> = (0..WRITERS)
+            .map(|n| {
+                let path = path.clone();
+                thread::spawn(move || write_run(&path, 1_700_000_000, IpAddr::from([10, 0, 0, n]), PORTS).map_err(|e| e.to_string()))
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap().unwrap();
+        }
+
+        let runs = run_ids(&path);
+        assert_eq!(runs.len(), WRITERS as usize);
+        assert!(runs.iter().all(|(_, rows)| *rows == PORTS as i64), "{:?}", runs);
+        let conn = open_database(&path).unwrap();
+        // 每個掃描編號只屬於一台主機，執行個體之間沒有交錯
+        let mixed: i64 = conn
+            .query_row("SELECT COUNT(*) FROM (SELECT scanned_at FROM scan_results GROUP BY scanned_at HAVING COUNT(DISTINCT host) > 1)", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(mixed, 0);
+        let locks: i64 = conn.query_row("SELECT COUNT(*) FROM scan_locks", [], |row| row.get(0)).unwrap();
+        assert_eq!(locks, 0);
+    }
+
+    // 前一次掃描已完成、執行鎖已釋放時，同一秒開始的掃描不能覆蓋它的執行資訊
+    #[test]
+    fn finished_runs_keep_their_id() {
+        let dir = TempDir::new("sqlite");
+        let path = dir.path().join("history.db");
+        for n in 0..3 {
+            write_run(&path, 1_700_000_000, IpAddr::from([10, 0, 0, n]), 5).unwrap();
+        }
+        assert_eq!(run_ids(&path), vec![(1_700_000_000, 5), (1_700_000_001, 5), (1_700_000_002, 5)]);
+    }
+
+    // 舊版只寫入結果、沒有執行資訊的編號也會被略過
+    #[test]
+    fn legacy_result_rows_reserve_their_id() {
+        let dir = TempDir::new("sqlite");
+        let path = dir.path().join("history.db");
+        open_database(&path)
+            .unwrap()
+            .execute_batch("INSERT INTO scan_results (scanned_at, host, port, service, category, inbound, outbound, identity) VALUES (1700000000, '10.0.0.9', 22, 'SSH', 'Remote', 1, 1, '10.0.0.9')")
+            .unwrap();
+        write_run(&path, 1_700_000_000, IpAddr::from([10, 0, 0, 1]), 2).unwrap();
+        assert_eq!(run_ids(&path), vec![(1_700_000_001, 2)]);
+    }
+
+    // 每次掃描相同的橫幅只存一份，結果列以鍵參照
+    #[test]
+    fn identical_banners_are_stored_once() {
+        let dir = TempDir::new("sqlite");
+        let path = dir.path().join("history.db");
+        for started_at in [1_700_000_000, 1_700_003_600] {
+            let mut sink = open_sink(&path, OutputFormat::Sqlite, &metadata(started_at)).unwrap();
+            for host in ["10.0.0.1", "10.0.0.2"] {
+                let mut result = testutil::scan_result(true);
+                result.banner = Some(crate::probes::Banner {
+                    probe: "ssh".to_string(),
+                    service: Some("ssh".to_string()),
+                    version: None,
+                    text: "SSH-2.0-OpenSSH_9.6".to_string(),
+                    encoding: None,
+                    raw: None,
+                });
+                sink.on_result(&record(host, 22, "SSH", "Remote", result)).unwrap();
+            }
+            sink.on_result(&record("10.0.0.1", 80, "HTTP", "Web", testutil::scan_result(false))).unwrap();
+            sink.on_summary(&ScanSummary::default()).unwrap();
+        }
+        let conn = open_database(&path).unwrap();
+        let count = |sql: &str| -> i64 { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+        assert_eq!(count("SELECT COUNT(*) FROM blobs"), 1);
+        assert_eq!(count("SELECT COUNT(*) FROM scan_results WHERE banner_sha256 IS NOT NULL"), 4);
+        let text: String = conn
+            .query_row("SELECT b.content ->> '$.text' FROM scan_results s JOIN blobs b ON b.sha256 = s.banner_sha256 LIMIT 1", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(text, "SSH-2.0-OpenSSH_9.6");
+    }
+
+    // 測試用的目的地：以 JSON 記下收到的每個事件；fail_at 筆結果時寫入失敗
+    struct Recorder {
+        events: Arc<std::sync::Mutex<Vec<String>>>,
+        fail_at: Option<usize>,
+        results: usize,
+    }
+
+    impl OutputSink for Recorder {
+        fn on_metadata(&mut self, metadata: &RunMetadata) -> SinkResult {
+            self.events.lock().unwrap().push(serde_json::to_string(metadata)?);
+            Ok(())
+        }
+
+        fn on_result(&mut self, record: &ScanRecord) -> SinkResult {
+            self.results += 1;
+            if self.fail_at == Some(self.results) {
+                return Err("磁碟已滿".into());
+            }
+            self.events.lock().unwrap().push(serde_json::to_string(record)?);
+            Ok(())
+        }
+
+        fn on_summary(&mut self, summary: &ScanSummary) -> SinkResult {
+            self.events.lock().unwrap().push(serde_json::to_string(summary)?);
+            Ok(())
+        }
+    }
+
+    // 同一次合成掃描：每個目的地收到相同的執行資訊、結果與摘要，失敗的目的地不影響其他目的地
+    #[tokio::test]
+    async fn every_sink_receives_the_same_scan() {
+        let dir = TempDir::new("sinks");
+        let metadata = metadata(1_700_000_000);
+        let recorder = |fail_at| {
+            let events = Arc::default();
+            let mut sink = Recorder { events: Arc::clone(&events), fail_at, results: 0 };
+            sink.on_metadata(&metadata).unwrap();
+            (Box::new(sink) as Box<dyn OutputSink>, events)
+        };
+        let (first, first_events) = recorder(None);
+        let (second, second_events) = recorder(None);
+        let (failing, _) = recorder(Some(3));
+        let mut outputs = vec![
+            Output::from_sink(&dir.path().join("first"), OutputFormat::Ndjson, first),
+            Output::from_sink(&dir.path().join("failing"), OutputFormat::Ndjson, failing),
+            Output::from_sink(&dir.path().join("second"), OutputFormat::Ndjson, second),
+        ];
+        let formats = [("scan.ndjson", OutputFormat::Ndjson), ("scan.csv", OutputFormat::Csv), ("scan.txt", OutputFormat::Plain), ("scan.db", OutputFormat::Sqlite)];
+        for (name, format) in formats {
+            outputs.push(Output::open(&dir.path().join(name), format, &metadata).unwrap());
+        }
+
+        let records = plain_records();
+        let (tx, rx) = mpsc::channel(4);
+        let writer = spawn_writer(outputs, rx, 2, Arc::default(), Arc::new(ScanContext::offline()));
+        for record in records.clone() {
+            tx.send(record.into()).await.unwrap();
+        }
+        drop(tx);
+        let (summary, statuses) = writer.await.unwrap();
+
+        assert_eq!(summary.total, records.len() as u64);
+        let failed: Vec<_> = statuses.iter().filter(|status| status.error.is_some()).map(|status| status.path.file_name().unwrap()).collect();
+        assert_eq!(failed, ["failing"]);
+        let first_events = first_events.lock().unwrap().clone();
+        assert_eq!(first_events.len(), records.len() + 2);
+        assert_eq!(first_events, *second_events.lock().unwrap());
+
+        // 實際的格式都寫入了每一筆結果
+        let mut expected: Vec<(IpAddr, u16)> = records.iter().map(|record| (record.host, record.port.port)).collect();
+        expected.sort();
+        let sorted = |mut rows: Vec<(IpAddr, u16)>| {
+            rows.sort();
+            rows
+        };
+        let text = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        let ndjson = text("scan.ndjson").lines().map(|line| serde_json::from_str::<ScanRecord>(line).unwrap()).map(|r| (r.host, r.port.port)).collect();
+        let csv = text("scan.csv")
+            .lines()
+            .filter(|line| !line.starts_with('#') && !line.starts_with("host,"))
+            .map(|line| {
+                let mut fields = line.split(',');
+                (fields.next().unwrap().parse().unwrap(), fields.next().unwrap().parse().unwrap())
+            })
+            .collect();
+        let plain = text("scan.txt").lines().filter(|line| !line.starts_with('#')).map(|line| plain_key(line.to_string()).unwrap()).map(|(host, port, _)| (host, port)).collect();
+        let conn = open_database(&dir.path().join("scan.db")).unwrap();
+        let sqlite = conn
+            .prepare("SELECT host, port FROM scan_results")
+            .unwrap()
+            .query_map([], |row| Ok((row.get::<_, String>(0)?.parse().unwrap(), row.get(1)?)))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        for (name, rows) in [("ndjson", ndjson), ("csv", csv), ("plain", plain), ("sqlite", sqlite)] {
+            assert_eq!(sorted(rows), expected, "{}", name);
+        }
+
+        // 每一種格式都帶著同一個掃描 ID
+        let scan_id = metadata.scan_id.as_str();
+        assert!(text("scan.ndjson").lines().all(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["scan_id"] == scan_id));
+        for name in ["scan.csv", "scan.txt"] {
+            assert!(text(name).lines().any(|line| line == format!("# scan_id: {}", scan_id)), "{}", name);
+        }
+        let stored: String = conn.query_row("SELECT scan_id FROM scan_runs", [], |row| row.get(0)).unwrap();
+        assert_eq!(stored, scan_id);
+    }
+}
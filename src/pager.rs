@@ -0,0 +1,296 @@
+use std::io::{self, IsTerminal};
+use serde::Deserialize;
+
+// 設定檔 [pager] 區段
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PagerConfig {
+    // false 時等同 --no-pager
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    // 分頁程式，未設定時使用 $PAGER，再退回 less 與內建分頁
+    pub command: Option<String>,
+}
+
+impl Default for PagerConfig {
+    fn default() -> Self {
+        PagerConfig {
+            enabled: default_enabled(),
+            command: None,
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+// 只有輸出到終端時才分頁
+pub fn wanted(config: &PagerConfig, disabled: bool) -> bool {
+    config.enabled && !disabled && io::stdout().is_terminal() && io::stdin().is_terminal()
+}
+
+// 換行後在終端佔用的列數
+fn rows(text: &str, columns: usize) -> usize {
+    text.lines().map(|line| crate::layout::display_width(line).div_ceil(columns.max(1)).max(1)).sum()
+}
+
+#[cfg(unix)]
+pub use unix::Pager;
+
+#[cfg(unix)]
+mod unix {
+    use std::env;
+    use std::io::{self, Read, Write};
+    use std::process::{Command, Stdio};
+    use std::thread::JoinHandle;
+    use super::PagerConfig;
+
+    // 把標準輸出導向管線暫存，結束時依長度決定直接輸出或交給分頁程式
+    pub struct Pager {
+        saved_stdout: libc::c_int,
+        reader: Option<JoinHandle<Vec<u8>>>,
+        command: Option<String>,
+    }
+
+    impl Pager {
+        pub fn start(config: &PagerConfig) -> Option<Self> {
+            // 導向後 colored 會以為不是終端，先固定使用色彩
+            colored::control::set_override(true);
+            io::stdout().flush().ok()?;
+
+            let mut fds = [0; 2];
+            if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+                return None;
+            }
+            let [read_fd, write_fd] = fds;
+            let saved_stdout = unsafe { libc::dup(libc::STDOUT_FILENO) };
+            if saved_stdout < 0 || unsafe { libc::dup2(write_fd, libc::STDOUT_FILENO) } < 0 {
+                unsafe {
+                    libc::close(read_fd);
+                    libc::close(write_fd);
+                }
+                return None;
+            }
+            unsafe { libc::close(write_fd) };
+
+            let reader = std::thread::spawn(move || {
+                use std::os::fd::FromRawFd;
+                let mut pipe = unsafe { std::fs::File::from_raw_fd(read_fd) };
+                let mut buffer = Vec::new();
+                let _ = pipe.read_to_end(&mut buffer);
+                buffer
+            });
+
+            let command = config
+                .command
+                .clone()
+                .or_else(|| env::var("PAGER").ok())
+                .filter(|c| !c.trim().is_empty());
+            Some(Pager {
+                saved_stdout,
+                reader: Some(reader),
+                command,
+            })
+        }
+
+        // 還原標準輸出並顯示暫存的報告；可重複呼叫
+        pub fn finish(&mut self) {
+            let Some(reader) = self.reader.take() else {
+                return;
+            };
+            let _ = io::stdout().flush();
+            unsafe {
+                libc::dup2(self.saved_stdout, libc::STDOUT_FILENO);
+                libc::close(self.saved_stdout);
+            }
+            let buffer = reader.join().unwrap_or_default();
+            let text = String::from_utf8_lossy(&buffer);
+
+            let (columns, height) = terminal_size();
+            if super::rows(&text, columns) < height {
+                print!("{}", text);
+                return;
+            }
+            let external = match &self.command {
+                Some(command) => run_external(command, &buffer),
+                None => run_external("less", &buffer),
+            };
+            if external.is_err() {
+                super::internal::page(&text, height);
+            }
+        }
+    }
+
+    impl Drop for Pager {
+        fn drop(&mut self) {
+            self.finish();
+        }
+    }
+
+    // 經由 sh 執行分頁程式；less 預設保留色彩、不清除畫面
+    fn run_external(command: &str, buffer: &[u8]) -> io::Result<()> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("LESS", env::var("LESS").unwrap_or_else(|_| "FRX".to_string()))
+            .stdin(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // 使用者提早離開分頁程式時寫入會失敗，不算錯誤
+            let _ = stdin.write_all(buffer);
+        }
+        let status = child.wait()?;
+        // sh 找不到指令時回傳 127
+        if status.code() == Some(127) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "找不到分頁程式"));
+        }
+        Ok(())
+    }
+
+    // 終端的 (寬, 高)
+    pub(super) fn terminal_size() -> (usize, usize) {
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0 && size.ws_row > 0 {
+            return (usize::from(size.ws_col.max(1)), usize::from(size.ws_row));
+        }
+        (80, 24)
+    }
+}
+
+#[cfg(unix)]
+mod internal {
+    use std::io::{self, Read, Write};
+
+    // 畫面恢復原本的終端模式
+    struct RawMode(libc::termios);
+
+    impl RawMode {
+        fn enable() -> Option<Self> {
+            let mut original: libc::termios = unsafe { std::mem::zeroed() };
+            if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+                return None;
+            }
+            let mut raw = original;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+                return None;
+            }
+            Some(RawMode(original))
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.0) };
+        }
+    }
+
+    enum Key {
+        Down,
+        Up,
+        PageDown,
+        PageUp,
+        Top,
+        Bottom,
+        Quit,
+        Other,
+    }
+
+    fn read_key(input: &mut impl Read) -> Key {
+        let mut byte = [0u8; 1];
+        if input.read(&mut byte).unwrap_or(0) == 0 {
+            return Key::Quit;
+        }
+        match byte[0] {
+            b'q' | b'Q' => Key::Quit,
+            b' ' | b'f' => Key::PageDown,
+            b'b' => Key::PageUp,
+            b'j' | b'\n' => Key::Down,
+            b'k' => Key::Up,
+            b'g' => Key::Top,
+            b'G' => Key::Bottom,
+            0x1b => {
+                // ESC [ A / B / 5~ / 6~
+                let mut seq = [0u8; 2];
+                if input.read(&mut seq).unwrap_or(0) < 2 || seq[0] != b'[' {
+                    return Key::Quit;
+                }
+                match seq[1] {
+                    b'A' => Key::Up,
+                    b'B' => Key::Down,
+                    b'5' | b'6' => {
+                        let _ = input.read(&mut byte);
+                        if seq[1] == b'5' {
+                            Key::PageUp
+                        } else {
+                            Key::PageDown
+                        }
+                    }
+                    _ => Key::Other,
+                }
+            }
+            _ => Key::Other,
+        }
+    }
+
+    // 簡易分頁：空白/PgDn 下一頁、b/PgUp 上一頁、方向鍵或 j/k 捲動、g/G 開頭/結尾、q 離開
+    pub fn page(text: &str, height: usize) {
+        let lines: Vec<&str> = text.lines().collect();
+        let page = height.saturating_sub(1).max(1);
+        let Some(_raw) = RawMode::enable() else {
+            print!("{}", text);
+            return;
+        };
+        let mut stdin = io::stdin();
+        let mut stdout = io::stdout();
+        let mut top = 0usize;
+        let last_top = lines.len().saturating_sub(page);
+
+        loop {
+            let _ = write!(stdout, "\x1b[2J\x1b[H");
+            for line in lines.iter().skip(top).take(page) {
+                let _ = write!(stdout, "{}\x1b[0m\r\n", line);
+            }
+            let end = (top + page).min(lines.len());
+            let _ = write!(
+                stdout,
+                "\x1b[7m 第 {}-{} 行 / 共 {} 行 (空白 下一頁, b 上一頁, ↑↓ 捲動, q 離開) \x1b[0m",
+                top + 1,
+                end,
+                lines.len()
+            );
+            let _ = stdout.flush();
+
+            top = match read_key(&mut stdin) {
+                Key::Quit => break,
+                Key::Down => (top + 1).min(last_top),
+                Key::Up => top.saturating_sub(1),
+                Key::PageDown => (top + page).min(last_top),
+                Key::PageUp => top.saturating_sub(page),
+                Key::Top => 0,
+                Key::Bottom => last_top,
+                Key::Other => top,
+            };
+        }
+        // 離開時保留最後一頁，游標移到新行
+        let _ = write!(stdout, "\r\x1b[2K");
+        let _ = stdout.flush();
+    }
+}
+
+// 其他平台不分頁
+#[cfg(not(unix))]
+pub struct Pager;
+
+#[cfg(not(unix))]
+impl Pager {
+    pub fn start(_config: &PagerConfig) -> Option<Self> {
+        None
+    }
+
+    pub fn finish(&mut self) {}
+}
@@ -0,0 +1,296 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{SystemTime, UNIX_EPOCH};
+use ipnet::IpNet;
+use crate::icmp::{PROTO_TCP, PROTO_UDP};
+use crate::privhelper::Helper;
+use crate::scanner::ScanPlan;
+use crate::targets::TargetSpec;
+
+// 每個封包最多保存的位元組數
+const SNAPLEN: u32 = 65535;
+
+// pcap 的連結層類型：LINKTYPE_RAW，封包直接從 IP 標頭開始
+const LINKTYPE_RAW: u32 = 101;
+
+const PROTO_ICMP: u8 = 1;
+const PROTO_ICMPV6: u8 = 58;
+
+// 從掃描計畫建立的擷取條件：目標位址 (或代理) 上掃描端口的 TCP/UDP，以及與目標相關的 ICMP
+#[derive(Debug, Clone)]
+pub struct CaptureFilter {
+    hosts: Vec<IpNet>,
+    ports: BTreeSet<u16>,
+    // --tor 時探測送往本機的 SOCKS 代理
+    proxy: Option<SocketAddr>,
+}
+
+impl CaptureFilter {
+    pub fn from_plan(plan: &ScanPlan) -> Self {
+        let hosts = plan
+            .targets
+            .iter()
+            .filter_map(|target| match target {
+                TargetSpec::Host { addr, .. } => Some(IpNet::from(*addr)),
+                TargetSpec::Network(net, _) => Some(*net),
+                TargetSpec::Unresolved(_) => None,
+            })
+            .collect();
+        CaptureFilter {
+            hosts,
+            ports: plan.ports.iter().map(|p| p.port).collect(),
+            proxy: plan.proxy,
+        }
+    }
+
+    fn is_target(&self, addr: IpAddr) -> bool {
+        self.hosts.iter().any(|net| net.contains(&addr))
+    }
+
+    fn is_probe(&self, addr: IpAddr, port: u16) -> bool {
+        (self.is_target(addr) && self.ports.contains(&port)) || self.proxy == Some(SocketAddr::new(addr, port))
+    }
+
+    // 對應的 tcpdump 過濾語法，附在訊息中方便對照；路由器回報的 ICMP 錯誤另外比對其中引用的封包
+    pub fn expression(&self) -> String {
+        let hosts: Vec<String> = self
+            .hosts
+            .iter()
+            .map(|net| match net.prefix_len() == net.max_prefix_len() {
+                true => format!("host {}", net.addr()),
+                false => format!("net {}", net),
+            })
+            .collect();
+        // 連續的端口合併為 portrange
+        let mut ranges: Vec<(u16, u16)> = Vec::new();
+        for &port in &self.ports {
+            match ranges.last_mut() {
+                Some((_, end)) if u32::from(*end) + 1 == u32::from(port) => *end = port,
+                _ => ranges.push((port, port)),
+            }
+        }
+        let ports: Vec<String> = ranges
+            .iter()
+            .map(|&(start, end)| match start == end {
+                true => format!("port {}", start),
+                false => format!("portrange {}-{}", start, end),
+            })
+            .collect();
+        let hosts = hosts.join(" or ");
+        let mut expression = format!("(({}) and ({})) or (({}) and (icmp or icmp6))", hosts, ports.join(" or "), hosts);
+        if let Some(proxy) = self.proxy {
+            expression.push_str(&format!(" or (host {} and port {})", proxy.ip(), proxy.port()));
+        }
+        expression
+    }
+
+    // 依 IP 封包內容判斷是否為探測流量
+    pub fn matches(&self, packet: &[u8]) -> bool {
+        let Some((protocol, src, dst, payload)) = split_ip(packet) else {
+            return false;
+        };
+        match protocol {
+            PROTO_TCP | PROTO_UDP if payload.len() >= 4 => {
+                let sport = u16::from_be_bytes([payload[0], payload[1]]);
+                let dport = u16::from_be_bytes([payload[2], payload[3]]);
+                self.is_probe(dst, dport) || self.is_probe(src, sport)
+            }
+            // 路由器回報的錯誤比對其中引用的原始封包
+            PROTO_ICMP | PROTO_ICMPV6 => {
+                self.is_target(src)
+                    || self.is_target(dst)
+                    || payload.get(8..).and_then(split_ip).is_some_and(|(_, _, quoted, _)| self.is_target(quoted))
+            }
+            _ => false,
+        }
+    }
+}
+
+// 取出 (協定, 來源, 目的, 傳輸層內容)；IPv6 只處理沒有延伸標頭的封包
+fn split_ip(packet: &[u8]) -> Option<(u8, IpAddr, IpAddr, &[u8])> {
+    match packet.first()? >> 4 {
+        4 => {
+            let header_len = usize::from(packet[0] & 0x0f) * 4;
+            let src = <[u8; 4]>::try_from(packet.get(12..16)?).ok()?;
+            let dst = <[u8; 4]>::try_from(packet.get(16..20)?).ok()?;
+            Some((*packet.get(9)?, IpAddr::from(src), IpAddr::from(dst), packet.get(header_len..)?))
+        }
+        6 => {
+            let src = <[u8; 16]>::try_from(packet.get(8..24)?).ok()?;
+            let dst = <[u8; 16]>::try_from(packet.get(24..40)?).ok()?;
+            Some((*packet.get(6)?, IpAddr::from(src), IpAddr::from(dst), packet.get(40..)?))
+        }
+        _ => None,
+    }
+}
+
+// 以 pcap 格式寫入封包；Wireshark 與 tcpdump -r 可直接讀取
+struct PcapWriter {
+    out: BufWriter<File>,
+}
+
+impl PcapWriter {
+    fn create(path: &Path) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(&0xa1b2_c3d4u32.to_le_bytes())?;
+        out.write_all(&2u16.to_le_bytes())?;
+        out.write_all(&4u16.to_le_bytes())?;
+        // 時區修正與時間精確度，固定為 0
+        out.write_all(&[0; 8])?;
+        out.write_all(&SNAPLEN.to_le_bytes())?;
+        out.write_all(&LINKTYPE_RAW.to_le_bytes())?;
+        Ok(PcapWriter { out })
+    }
+
+    fn write(&mut self, packet: &[u8], original_len: usize) -> io::Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let kept = &packet[..packet.len().min(SNAPLEN as usize)];
+        self.out.write_all(&(now.as_secs() as u32).to_le_bytes())?;
+        self.out.write_all(&now.subsec_micros().to_le_bytes())?;
+        self.out.write_all(&(kept.len() as u32).to_le_bytes())?;
+        self.out.write_all(&(original_len as u32).to_le_bytes())?;
+        self.out.write_all(kept)
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.out.flush()?;
+        self.out.get_ref().sync_all()
+    }
+}
+
+// 擷取結束時的統計
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CaptureStats {
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+// 背景執行緒擷取探測流量，直到 finish 或被釋放；Ctrl+C 時由呼叫端呼叫 finish 寫完檔案
+#[derive(Debug)]
+pub struct Capture {
+    path: PathBuf,
+    filter: CaptureFilter,
+    stop: Arc<AtomicBool>,
+    worker: Mutex<Option<JoinHandle<io::Result<CaptureStats>>>>,
+}
+
+impl Capture {
+    // 需要 root 或 CAP_NET_RAW (或 --privileged-helper 的輔助程序)；沒有權限或平台不支援時回傳原因
+    pub fn start(path: &Path, filter: CaptureFilter, helper: Option<&Arc<Helper>>) -> Result<Self, String> {
+        let socket = platform::open(helper)?;
+        let writer = PcapWriter::create(path).map_err(|e| format!("無法建立 {}: {}", path.display(), e))?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let (flag, rules) = (stop.clone(), filter.clone());
+        let worker = std::thread::Builder::new()
+            .name("pcap-capture".to_string())
+            .spawn(move || platform::capture(&socket, writer, &rules, &flag))
+            .map_err(|e| e.to_string())?;
+        Ok(Capture {
+            path: path.to_path_buf(),
+            filter,
+            stop,
+            worker: Mutex::new(Some(worker)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn filter(&self) -> &CaptureFilter {
+        &self.filter
+    }
+
+    // 停止擷取並寫完檔案；已結束時回傳 None
+    pub fn finish(&self) -> Option<Result<CaptureStats, String>> {
+        self.stop.store(true, Ordering::Relaxed);
+        let worker = self.worker.lock().unwrap_or_else(|e| e.into_inner()).take()?;
+        Some(match worker.join() {
+            Ok(result) => result.map_err(|e| format!("寫入 {} 失敗: {}", self.path.display(), e)),
+            Err(_) => Err("封包擷取執行緒異常結束".to_string()),
+        })
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::io::{self, ErrorKind};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use crate::privhelper::{Channel, Helper, Origin, RawSocket};
+    use super::{CaptureFilter, CaptureStats, PcapWriter};
+
+    // 回送介面的硬體類型；同一個封包會以送出與收到各出現一次
+    const ARPHRD_LOOPBACK: u16 = 772;
+
+    pub fn open(helper: Option<&Arc<Helper>>) -> Result<RawSocket, String> {
+        RawSocket::open(Channel::Capture, helper).map_err(|e| match (e.kind(), helper) {
+            (_, Some(_)) => e.to_string(),
+            (ErrorKind::PermissionDenied, None) => "--pcap 需要 root 權限或 CAP_NET_RAW".to_string(),
+            _ => format!("無法開啟擷取 socket: {}", e),
+        })
+    }
+
+    pub fn capture(socket: &RawSocket, mut writer: PcapWriter, filter: &CaptureFilter, stop: &AtomicBool) -> io::Result<CaptureStats> {
+        let mut buf = vec![0u8; 65536];
+        let mut stats = CaptureStats::default();
+        while !stop.load(Ordering::Relaxed) {
+            let (len, link) = match socket.recv(&mut buf) {
+                Ok((len, Origin::Link(link))) => (len, link),
+                Ok(_) => continue,
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) => continue,
+                Err(e) => return Err(e),
+            };
+            if !matches!(i32::from(link.ethertype), libc::ETH_P_IP | libc::ETH_P_IPV6) {
+                continue;
+            }
+            if link.hatype == ARPHRD_LOOPBACK && link.outgoing {
+                continue;
+            }
+            let packet = &buf[..len];
+            if filter.matches(packet) {
+                writer.write(packet, len)?;
+                stats.packets += 1;
+                stats.bytes += len as u64;
+            }
+        }
+        writer.finish()?;
+        Ok(stats)
+    }
+}
+
+// 其他平台沒有 AF_PACKET；Windows 需要 Npcap，目前尚未支援
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use std::io;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use crate::privhelper::Helper;
+    use super::{CaptureFilter, CaptureStats, PcapWriter};
+
+    pub struct Socket;
+
+    pub fn open(_helper: Option<&Arc<Helper>>) -> Result<Socket, String> {
+        match cfg!(windows) {
+            true => Err("--pcap 在 Windows 需要 Npcap，目前版本尚未支援".to_string()),
+            false => Err("--pcap 目前只支援 Linux".to_string()),
+        }
+    }
+
+    pub fn capture(_socket: &Socket, writer: PcapWriter, _filter: &CaptureFilter, _stop: &AtomicBool) -> io::Result<CaptureStats> {
+        writer.finish()?;
+        Ok(CaptureStats::default())
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use clap::ValueEnum;
+use crate::closure::Failure;
+use crate::probes::Banner;
+use crate::{vhost, PortInfo, ScanResult};
+
+// 每個端口依序經過的探測階段，後面的階段成本較高
+// 只有前面的階段成功且符合前置條件時才執行；--stages 決定最深執行到哪個階段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Stage {
+    /// 只做出站連線 (完整連線、SYN 或經由代理)
+    Connect,
+    /// 可連線的端口加上橫幅探測 (--banners)
+    Banner,
+    /// 再加上服務辨識：虛擬主機、TCP 功能 (--tcp-caps) 與 HTTP 版本 (--http-versions)
+    Fingerprint,
+    /// 再加上服務檢查 (--vuln-checks)
+    Checks,
+}
+
+impl Stage {
+    pub fn label(self) -> &'static str {
+        match self {
+            Stage::Connect => "連線",
+            Stage::Banner => "橫幅",
+            Stage::Fingerprint => "服務辨識",
+            Stage::Checks => "服務檢查",
+        }
+    }
+}
+
+// 下一個階段之前已知的結果
+#[derive(Debug, Clone, Copy)]
+pub struct Evidence<'a> {
+    pub port: &'a PortInfo,
+    pub connected: bool,
+    // 經由代理時只有代理回報的連線結果，之後的階段都需要直接連線
+    pub proxied: bool,
+    pub banner: Option<&'a Banner>,
+}
+
+impl<'a> Evidence<'a> {
+    // 掃描結束後的階段 (TCP 功能、HTTP 版本) 從端口結果取得證據
+    pub fn of(port: &'a PortInfo, result: &'a ScanResult, proxied: bool) -> Self {
+        Evidence { port, connected: result.outbound, proxied, banner: result.banner.as_ref() }
+    }
+
+    fn banner_service(&self) -> Option<String> {
+        self.banner.and_then(|b| b.service.as_deref()).map(str::to_lowercase)
+    }
+
+    // 虛擬主機探測：Web 端口，或橫幅比對為 HTTP
+    pub fn speaks_http(&self) -> bool {
+        vhost::is_web_port(self.port) || self.banner_service().is_some_and(|s| s.starts_with("http"))
+    }
+
+    // TLS 檢查：TLS 端口，或橫幅顯示是 TLS 服務
+    // 對 TLS 端口送出明文 HTTP 時，常見的伺服器會回應 "sent to HTTPS port"
+    pub fn speaks_tls(&self) -> bool {
+        vhost::uses_tls(self.port)
+            || self.banner_service().is_some_and(|s| s == "https" || s.contains("tls") || s.contains("ssl"))
+            || self.banner.is_some_and(|b| b.text.contains("sent to HTTPS port"))
+    }
+}
+
+// 各階段的前置條件：連線之後的階段都需要直接連線成功
+fn ready(stage: Stage, evidence: &Evidence) -> bool {
+    match stage {
+        Stage::Connect => true,
+        Stage::Banner | Stage::Fingerprint | Stage::Checks => evidence.connected && !evidence.proxied,
+    }
+}
+
+// --stages 的上限
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pipeline {
+    pub depth: Stage,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Pipeline { depth: Stage::Checks }
+    }
+}
+
+impl Pipeline {
+    pub fn new(depth: Stage) -> Self {
+        Pipeline { depth }
+    }
+
+    // 階段是否在 --stages 的範圍內
+    pub fn reaches(self, stage: Stage) -> bool {
+        stage <= self.depth
+    }
+
+    // 階段在範圍內且符合前置條件
+    pub fn admits(self, stage: Stage, evidence: &Evidence) -> bool {
+        self.reaches(stage) && ready(stage, evidence)
+    }
+}
+
+// 服務檢查是 UDP 查詢，同一端口的 TCP 結果無法代表服務是否存在
+// 只要求主機在連線階段有任何回應 (連線成功或被重設)，沒有回應的主機不再檢查
+pub fn host_answered(results: &HashMap<PortInfo, ScanResult>) -> bool {
+    results.values().any(|r| r.outbound || matches!(r.failure, Some(Failure::Reset { .. })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use crate::prober::fake::{Script, Scripted, ScriptedProber};
+    use crate::probes::ProbeLibrary;
+    use crate::testutil::{by_host, host, scan, scan_result, scripted_plan};
+
+    const STAGES: [Stage; 4] = [Stage::Connect, Stage::Banner, Stage::Fingerprint, Stage::Checks];
+
+    fn banner(service: Option<&str>, text: &str) -> Banner {
+        Banner { probe: "test".to_string(), service: service.map(str::to_string), version: None, text: text.to_string(), encoding: None, raw: None }
+    }
+
+    #[test]
+    fn later_stages_need_a_direct_connection() {
+        let port = PortInfo::new(2222, "Test", "Remote");
+        // (連線成功, 經由代理) -> 各階段是否執行
+        for (connected, proxied, expected) in [
+            (true, false, [true, true, true, true]),
+            (false, false, [true, false, false, false]),
+            (true, true, [true, false, false, false]),
+            (false, true, [true, false, false, false]),
+        ] {
+            let evidence = Evidence { port: &port, connected, proxied, banner: None };
+            let admitted = STAGES.map(|stage| Pipeline::default().admits(stage, &evidence));
+            assert_eq!(admitted, expected, "connected={} proxied={}", connected, proxied);
+        }
+    }
+
+    #[test]
+    fn stages_stop_at_the_requested_depth() {
+        let port = PortInfo::new(80, "HTTP", "Web");
+        let evidence = Evidence { port: &port, connected: true, proxied: false, banner: None };
+        for (depth, deepest) in STAGES.iter().enumerate() {
+            let pipeline = Pipeline::new(*deepest);
+            let admitted = STAGES.map(|stage| pipeline.admits(stage, &evidence));
+            let expected: Vec<bool> = (0..STAGES.len()).map(|i| i <= depth).collect();
+            assert_eq!(admitted.to_vec(), expected, "--stages {:?}", deepest);
+        }
+        assert_eq!(Stage::from_str("fingerprint", true), Ok(Stage::Fingerprint));
+        assert_eq!(Stage::Checks.label(), "服務檢查");
+    }
+
+    #[test]
+    fn protocol_hints_come_from_the_port_or_the_banner() {
+        let web = PortInfo::new(80, "HTTP", "Web");
+        let tls = PortInfo::new(443, "HTTPS", "Web");
+        let other = PortInfo::new(9999, "Test", "Other");
+        let evidence = |port, banner| Evidence { port, connected: true, proxied: false, banner };
+        let http_banner = banner(Some("HTTP"), "HTTP/1.1 200 OK");
+        let tls_banner = banner(Some("ssl/http"), "");
+        let plain_to_tls = banner(None, "400 The plain HTTP request was sent to HTTPS port");
+        let ssh = banner(Some("ssh"), "SSH-2.0");
+
+        assert!(evidence(&web, None).speaks_http() && !evidence(&web, None).speaks_tls());
+        assert!(evidence(&tls, None).speaks_tls());
+        assert!(!evidence(&other, None).speaks_http() && !evidence(&other, None).speaks_tls());
+        assert!(evidence(&other, Some(&http_banner)).speaks_http());
+        assert!(evidence(&other, Some(&tls_banner)).speaks_tls());
+        assert!(evidence(&other, Some(&plain_to_tls)).speaks_tls());
+        assert!(!evidence(&other, Some(&ssh)).speaks_http() && !evidence(&other, Some(&ssh)).speaks_tls());
+
+        let mut result = scan_result(true);
+        result.banner = Some(http_banner.clone());
+        let from_result = Evidence::of(&other, &result, true);
+        assert!(from_result.connected && from_result.proxied && from_result.speaks_http());
+    }
+
+    #[test]
+    fn checks_need_any_answer_from_the_host() {
+        let port = |n| PortInfo::new(n, "Test", "Test");
+        let mut reset = scan_result(false);
+        reset.failure = Some(Failure::Reset { latency_ms: 1.0 });
+        let mut silent = scan_result(false);
+        silent.failure = Some(Failure::Timeout);
+        assert!(host_answered(&HashMap::from([(port(1), scan_result(true))])));
+        assert!(host_answered(&HashMap::from([(port(1), silent.clone()), (port(2), reset)])));
+        assert!(!host_answered(&HashMap::from([(port(1), silent)])));
+        assert!(!host_answered(&HashMap::new()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_scanner_skips_banners_beyond_the_depth_or_on_closed_ports() {
+        let target = host(1);
+        let prober = || {
+            Arc::new(
+                ScriptedProber::new()
+                    .with(target, 22, Script::new(Scripted::Open, Duration::from_millis(5)).banner(banner(Some("ssh"), "SSH-2.0-Test")))
+                    .with(target, 23, Script::new(Scripted::Refused, Duration::from_millis(5)).banner(banner(None, "不應出現"))),
+            )
+        };
+        let banners = |depth: Stage| async move {
+            let mut plan = scripted_plan(&[target], &[22, 23], 2, prober());
+            plan.probes = Some(Arc::new(ProbeLibrary::default()));
+            plan.pipeline = Pipeline::new(depth);
+            let results = by_host(scan(&plan).await).remove(&target).unwrap();
+            let mut found: Vec<(u16, Option<String>)> =
+                results.into_iter().map(|(port, result)| (port.port, result.banner.map(|b| b.text))).collect();
+            found.sort();
+            found
+        };
+        assert_eq!(banners(Stage::Connect).await, vec![(22, None), (23, None)]);
+        assert_eq!(banners(Stage::Banner).await, vec![(22, Some("SSH-2.0-Test".to_string())), (23, None)]);
+        assert_eq!(banners(Stage::Checks).await, vec![(22, Some("SSH-2.0-Test".to_string())), (23, None)]);
+    }
+}
@@ -0,0 +1,335 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+use colored::*;
+use schemars::JsonSchema;
+use serde::Serialize;
+use crate::benchmark::{self, Estimate};
+use crate::checks::{self, Intrusiveness, CHECK_TIMEOUT};
+use crate::report::SCHEMA_VERSION;
+use crate::scanner::ScanPlan;
+use crate::srcport;
+use crate::metadata::describe_excluded;
+use crate::targets::{Excluded, TargetSpec};
+use crate::timefmt;
+
+// dry-run 計劃中的單個目標
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PlannedTarget {
+    pub spec: String,
+    pub addresses: Vec<String>,
+    pub hosts: u128,
+    pub resolved: bool,
+}
+
+// dry-run 計劃中的服務檢查
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PlannedCheck {
+    pub name: &'static str,
+    pub ports: Vec<u16>,
+    // 實際允許的侵入程度
+    pub level: Intrusiveness,
+    // 允許時會執行的侵入性操作
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub intrusive_actions: Vec<&'static str>,
+}
+
+// 使用相同逾時的端口
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct TimeoutGroup {
+    pub timeout_ms: u128,
+    pub ports: String,
+}
+
+// --test-source-ports 的額外探測
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SourcePortPlan {
+    pub ports: Vec<u16>,
+    // 來源端口數 x 主機數 x 端口數
+    pub probes: u128,
+    // 估計時間中因此增加的部分 (同一來源端口的探測依序執行)
+    pub estimated_seconds: f64,
+}
+
+// 完整的掃描計劃報告
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PlanReport {
+    pub schema_version: u32,
+    pub targets: Vec<PlannedTarget>,
+    // 排除清單移除的目標
+    pub excluded: Vec<Excluded>,
+    // 實際掃描時會被安全檢查拒絕的原因
+    pub guardrail: Vec<String>,
+    pub port_count: usize,
+    pub ports: String,
+    pub ports_by_category: BTreeMap<String, usize>,
+    pub total_probes: u128,
+    pub concurrency: usize,
+    // --concurrency auto：concurrency 為上限，實際並發自動調整
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub adaptive_concurrency: bool,
+    // --per-host-concurrency
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub per_host_concurrency: Option<usize>,
+    // --per-net-concurrency，例如 "每個 /24 (IPv6 /64) 最多 4 個"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub per_net_concurrency: Option<String>,
+    pub rate_limit: Option<u32>,
+    pub outbound_timeout_ms: u128,
+    pub timeout_groups: Vec<TimeoutGroup>,
+    pub check_timeout_ms: u128,
+    pub checks: Vec<PlannedCheck>,
+    pub intrusive: bool,
+    pub intrusiveness: Intrusiveness,
+    pub vhosts: Vec<String>,
+    pub knock: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_ports: Option<SourcePortPlan>,
+    pub output: Option<String>,
+    pub estimated_seconds: f64,
+    // 依過去相同設定掃描的吞吐量估計；紀錄不足時省略
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub historical_estimate: Option<Estimate>,
+}
+
+// 將端口清單壓縮成範圍表示，例如 "22, 80, 8000-8100"
+pub fn compress_ports(ports: &[u16]) -> String {
+    let mut sorted = ports.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+
+    let mut parts = Vec::new();
+    let mut iter = sorted.into_iter().peekable();
+    while let Some(start) = iter.next() {
+        let mut end = start;
+        while iter.peek() == Some(&(end.wrapping_add(1))) && end < u16::MAX {
+            end = iter.next().unwrap_or(end);
+        }
+        if start == end {
+            parts.push(start.to_string());
+        } else {
+            parts.push(format!("{}-{}", start, end));
+        }
+    }
+    parts.join(", ")
+}
+
+// 最壞情況估計：每輪並發探測都等到逾時，檢查的每個查詢也等到逾時
+pub fn estimate_duration(plan: &ScanPlan, checks: &[PlannedCheck], check_hosts: u128) -> Duration {
+    let hosts: u128 = plan.targets.iter().map(TargetSpec::host_count).sum();
+    let per_host: f64 = plan.ports.iter().map(|p| plan.timeouts.for_port(p).as_secs_f64()).sum();
+    // 並發足夠時，總時間至少是最長的單一逾時
+    let longest = plan.ports.iter().map(|p| plan.timeouts.for_port(p).as_secs_f64()).fold(0.0, f64::max);
+    // 每台主機的上限使總並發數無法超過 上限 x 主機數
+    let mut concurrency = match plan.per_host_concurrency {
+        Some(cap) => plan.concurrency.min(cap.saturating_mul(hosts.min(usize::MAX as u128) as usize)),
+        None => plan.concurrency,
+    };
+    // 每個網段的上限同理：不超過 上限 x 網段數
+    if let Some(limiter) = &plan.per_net {
+        let networks = plan.targets.iter().map(|t| limiter.limit.network_count(t)).fold(0u128, u128::saturating_add);
+        concurrency = concurrency.min(limiter.limit.cap.saturating_mul(networks.min(usize::MAX as u128) as usize));
+    }
+    let scan = (per_host * hosts as f64 / concurrency.max(1) as f64).max(longest);
+
+    // NTP 送出三個查詢，其餘檢查以兩個估計
+    let queries: usize = checks
+        .iter()
+        .map(|c| c.ports.len() * if c.name == "NTP" { 3 } else { 2 })
+        .sum();
+    let check = CHECK_TIMEOUT.as_secs_f64() * queries as f64 * check_hosts as f64;
+
+    Duration::from_secs_f64(scan + check) + srcport::estimate(plan, hosts)
+}
+
+// 依掃描計劃與選項建立報告
+pub fn build_report(plan: &ScanPlan, vuln_checks: bool, intrusiveness: Intrusiveness, output: Option<&Path>) -> PlanReport {
+    let targets: Vec<PlannedTarget> = plan
+        .targets
+        .iter()
+        .map(|target| PlannedTarget {
+            spec: target.label(),
+            addresses: match target {
+                TargetSpec::Host { addr, .. } => vec![addr.to_string()],
+                _ => Vec::new(),
+            },
+            hosts: target.host_count(),
+            resolved: !matches!(target, TargetSpec::Unresolved(_)),
+        })
+        .collect();
+
+    let mut ports_by_category = BTreeMap::new();
+    for port_info in &plan.ports {
+        *ports_by_category.entry(port_info.category.clone()).or_insert(0) += 1;
+    }
+    let port_numbers: Vec<u16> = plan.ports.iter().map(|p| p.port).collect();
+
+    let checks: Vec<PlannedCheck> = if vuln_checks {
+        checks::enabled(intrusiveness)
+            .iter()
+            .map(|(check, level)| PlannedCheck {
+                name: check.name(),
+                ports: check.ports().iter().copied().filter(|p| port_numbers.contains(p)).collect(),
+                level: *level,
+                intrusive_actions: match level {
+                    Intrusiveness::Intrusive => check.intrusive_actions().to_vec(),
+                    _ => Vec::new(),
+                },
+            })
+            .filter(|check| !check.ports.is_empty())
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let hosts: u128 = targets.iter().map(|t| t.hosts).sum();
+    let estimated = estimate_duration(plan, &checks, hosts.max(1));
+
+    PlanReport {
+        schema_version: SCHEMA_VERSION,
+        targets,
+        excluded: Vec::new(),
+        guardrail: Vec::new(),
+        port_count: plan.ports.len(),
+        ports: compress_ports(&port_numbers),
+        ports_by_category,
+        total_probes: plan.total_probes(),
+        concurrency: plan.concurrency,
+        adaptive_concurrency: plan.adaptive.is_some(),
+        per_host_concurrency: plan.per_host_concurrency,
+        per_net_concurrency: plan.per_net.as_ref().map(|limiter| limiter.limit.describe()),
+        rate_limit: None,
+        outbound_timeout_ms: plan.timeouts.default.as_millis(),
+        timeout_groups: plan
+            .timeouts
+            .groups(plan.ports.iter())
+            .into_iter()
+            .map(|(timeout, ports)| TimeoutGroup {
+                timeout_ms: timeout.as_millis(),
+                ports: compress_ports(&ports),
+            })
+            .collect(),
+        check_timeout_ms: CHECK_TIMEOUT.as_millis(),
+        checks,
+        intrusive: intrusiveness == Intrusiveness::Intrusive,
+        intrusiveness,
+        vhosts: plan.vhosts.clone(),
+        knock: plan
+            .knock
+            .iter()
+            .flat_map(|k| k.sequence.iter().map(ToString::to_string))
+            .collect(),
+        source_ports: (!plan.source_ports.is_empty()).then(|| SourcePortPlan {
+            ports: plan.source_ports.clone(),
+            probes: plan.total_probes() * plan.source_ports.len() as u128,
+            estimated_seconds: srcport::estimate(plan, hosts.max(1)).as_secs_f64(),
+        }),
+        output: output.map(|p| p.display().to_string()),
+        estimated_seconds: estimated.as_secs_f64(),
+        historical_estimate: None,
+    }
+}
+
+// 顯示掃描計劃
+pub fn display_plan(report: &PlanReport) {
+    println!("\n{}", "=== 掃描計劃 (dry-run) ===".bold());
+
+    println!("\n{}", "--- 目標 ---".bold());
+    for target in &report.targets {
+        if !target.resolved {
+            println!("{} (未解析)", target.spec);
+        } else if target.addresses.is_empty() {
+            println!("{} ({} 台主機)", target.spec, target.hosts);
+        } else {
+            println!("{} → {}", target.spec, target.addresses.join(", "));
+        }
+    }
+    if !report.excluded.is_empty() {
+        println!("排除: {}", describe_excluded(&report.excluded));
+    }
+    for violation in &report.guardrail {
+        println!("{}", format!("⚠ 實際掃描時會被拒絕: {}", violation).yellow());
+    }
+
+    println!("\n{}", format!("--- 端口 ({} 個) ---", report.port_count).bold());
+    println!("{}", report.ports);
+    for (category, count) in &report.ports_by_category {
+        println!("  {:10} {}", category, count);
+    }
+
+    println!("\n{}", "--- 參數 ---".bold());
+    println!("探測總數: {}", report.total_probes);
+    match report.adaptive_concurrency {
+        true => println!("並發數量: auto (上限 {})", report.concurrency),
+        false => println!("並發數量: {}", report.concurrency),
+    }
+    if let Some(cap) = report.per_host_concurrency {
+        println!("每台主機並發上限: {}", cap);
+    }
+    if let Some(limit) = &report.per_net_concurrency {
+        println!("網段並發上限: {}", limit);
+    }
+    match report.rate_limit {
+        Some(rate) => println!("速率限制: {}/s", rate),
+        None => println!("速率限制: 無"),
+    }
+    if report.timeout_groups.len() <= 1 {
+        println!("出站逾時: {}ms", report.outbound_timeout_ms);
+    } else {
+        println!("出站逾時:");
+        for group in &report.timeout_groups {
+            println!("  {:>6}ms  {}", group.timeout_ms, group.ports);
+        }
+    }
+
+    if report.checks.is_empty() {
+        println!("服務檢查: 未啟用");
+    } else {
+        println!(
+            "服務檢查: 侵入程度 {} (逾時 {}ms)",
+            report.intrusiveness.label(),
+            report.check_timeout_ms
+        );
+        for check in &report.checks {
+            println!("  {:6} {:10} {}", check.name, check.level.label(), compress_ports(&check.ports));
+            for action in &check.intrusive_actions {
+                println!("{}", format!("         侵入性操作: {}", action).yellow());
+            }
+        }
+    }
+
+    if !report.vhosts.is_empty() {
+        println!("虛擬主機: {}", report.vhosts.join(", "));
+    }
+
+    if !report.knock.is_empty() {
+        println!("敲門序列: {}", report.knock.join(" → "));
+    }
+
+    if let Some(source_ports) = &report.source_ports {
+        let ports: Vec<String> = source_ports.ports.iter().map(ToString::to_string).collect();
+        println!("來源端口測試: {} (另外 {} 次探測)", ports.join(", "), source_ports.probes);
+        println!(
+            "{}",
+            format!(
+                "⚠ 同一來源端口的探測依序執行，預估時間因此增加最長約 {}",
+                timefmt::duration(Duration::from_secs_f64(source_ports.estimated_seconds))
+            )
+            .yellow()
+        );
+    }
+
+    match &report.output {
+        Some(path) => println!("結果輸出: 串流寫入 {}", path),
+        None => println!("結果輸出: 終端"),
+    }
+
+    println!(
+        "預估時間: 最長約 {}",
+        timefmt::duration(Duration::from_secs_f64(report.estimated_seconds)).bold()
+    );
+    if let Some(estimate) = &report.historical_estimate {
+        println!("          {}", benchmark::describe_estimate(estimate));
+    }
+    println!("\n{}", "(dry-run：未進行任何端口探測)".italic());
+}
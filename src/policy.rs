@@ -0,0 +1,788 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::assertions::{self, Assertion, AssertionFile, AssertionOutcome};
+use crate::checks::health::{self, HealthState};
+use crate::groups::Groups;
+use crate::stats::Quantile;
+use crate::vars::Variables;
+use crate::{PortInfo, ScanResult};
+
+// 內建範本，與使用者範本格式相同
+const BUILTIN_TEMPLATES: &[&str] = &[
+    r#"
+name = "pci-external"
+title = "PCI 外部掃描檢查"
+description = "從外部網路檢查：只開放 HTTPS，管理與資料庫端口必須關閉"
+pass_message = "符合 PCI 外部掃描要求"
+fail_message = "不符合 PCI 外部掃描要求，請修正後重新掃描"
+
+[[expect]]
+ports = "443"
+state = "open"
+reason = "對外提供的 HTTPS 服務"
+
+[[expect]]
+ports = "21,22,23,25,53,110,111,135,137-139,143,445,1433,1521,2049,3306,3389,5432,5900,6379,8080,9200,11211,27017"
+state = "closed"
+reason = "管理、檔案分享與資料庫服務不應對外開放"
+"#,
+    r#"
+name = "ssh-hardening"
+title = "SSH 強化檢查"
+description = "SSH 只在標準端口提供，舊式遠端登入服務必須關閉"
+pass_message = "SSH 設定符合強化建議"
+fail_message = "發現不安全的遠端登入服務"
+
+[[expect]]
+ports = "22"
+state = "open"
+reason = "SSH 管理端口"
+
+[[expect]]
+ports = "23,512-514,2222,3389,5900"
+state = "closed"
+reason = "Telnet、r 系列指令、替代 SSH 端口與圖形遠端桌面"
+"#,
+    r#"
+name = "mail-server"
+title = "郵件伺服器檢查"
+description = "郵件伺服器應提供加密的收發端口，明文收信端口應關閉"
+pass_message = "郵件服務端口符合預期"
+fail_message = "郵件服務端口與預期不符"
+
+[[expect]]
+ports = "25,465,587,993,995"
+state = "open"
+reason = "SMTP、提交與加密的 IMAP/POP3"
+
+[[expect]]
+ports = "110,143"
+state = "closed"
+reason = "明文 POP3/IMAP"
+"#,
+];
+
+// 預期的端口狀態 (出站探測結果)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Expected {
+    Open,
+    Closed,
+    // 開放且設定檔的健康檢查通過
+    Healthy,
+}
+
+impl Expected {
+    fn label(self) -> &'static str {
+        match self {
+            Expected::Open => "開放",
+            Expected::Closed => "關閉",
+            Expected::Healthy => "開放且健康",
+        }
+    }
+
+    // 端口是否應為開放；開放與健康不互相衝突
+    fn open(self) -> bool {
+        self != Expected::Closed
+    }
+
+    // 結果是否符合預期；端口沒有設定健康檢查時無法確認健康，視為不符合
+    fn satisfied_by(self, result: &ScanResult) -> bool {
+        let open = result.directions.open(result.inbound, result.outbound);
+        match self {
+            Expected::Open => open,
+            Expected::Closed => !open,
+            Expected::Healthy => open && health::state(result) == Some(HealthState::Healthy),
+        }
+    }
+}
+
+// 政策檔 / 範本檔 (--policy 或 ~/.config/portscanner/templates/*.toml)
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    name: String,
+    title: Option<String>,
+    description: Option<String>,
+    pass_message: Option<String>,
+    fail_message: Option<String>,
+    #[serde(default, rename = "expect")]
+    expectations: Vec<ExpectationFile>,
+    #[serde(default, rename = "assert")]
+    assertions: Vec<AssertionFile>,
+    #[serde(default, rename = "slo")]
+    objectives: Vec<ObjectiveFile>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExpectationFile {
+    // 端口或服務群組 (見設定檔 [[groups]]) 擇一
+    ports: Option<String>,
+    group: Option<String>,
+    state: Expected,
+    reason: Option<String>,
+}
+
+// [[slo]]：延遲目標，例如 objective = "p95 < 100ms"
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ObjectiveFile {
+    ports: Option<String>,
+    group: Option<String>,
+    objective: String,
+    reason: Option<String>,
+}
+
+// 一組端口的延遲目標：該百分位的延遲必須低於 below_ms
+#[derive(Debug, Clone)]
+pub struct Objective {
+    pub ports: BTreeSet<u16>,
+    pub group: Option<String>,
+    pub quantile: Quantile,
+    pub below_ms: f64,
+    pub reason: Option<String>,
+}
+
+impl Objective {
+    fn describe(&self) -> String {
+        format!("{} < {}ms", self.quantile.label(), self.below_ms)
+    }
+}
+
+// 解析 "p95 < 100ms"；時間單位與 --timeout 相同
+fn parse_objective(text: &str) -> Result<(Quantile, f64), String> {
+    let invalid = || format!("延遲目標的格式應為 \"p95 < 100ms\" (可用 p50、p90、p95、p99): {}", text);
+    let (quantile, limit) = text.split_once('<').ok_or_else(invalid)?;
+    let quantile = Quantile::parse(quantile).ok_or_else(invalid)?;
+    let limit = crate::units::parse_duration(limit).map_err(|e| format!("{}: {}", invalid(), e))?;
+    if limit.is_zero() {
+        return Err(format!("延遲目標必須大於 0: {}", text));
+    }
+    Ok((quantile, limit.as_secs_f64() * 1000.0))
+}
+
+// 一組端口的預期狀態
+#[derive(Debug, Clone)]
+pub struct Expectation {
+    pub ports: BTreeSet<u16>,
+    // 以群組指定時，ports 在 resolve_groups 之後才填入
+    pub group: Option<String>,
+    pub state: Expected,
+    pub reason: Option<String>,
+}
+
+// 範本或政策的來源
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicySource {
+    Builtin,
+    File(PathBuf),
+}
+
+impl PolicySource {
+    pub fn label(&self) -> String {
+        match self {
+            PolicySource::Builtin => "內建".to_string(),
+            PolicySource::File(path) => path.display().to_string(),
+        }
+    }
+}
+
+// 驗證過的政策：端口的預期狀態與報告用語
+#[derive(Debug, Clone)]
+pub struct Policy {
+    pub name: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub pass_message: String,
+    pub fail_message: String,
+    pub expectations: Vec<Expectation>,
+    // 可連線端口的橫幅、憑證與 HTTP 狀態斷言
+    pub assertions: Vec<Assertion>,
+    // [[slo]] 的延遲目標，以 --samples 的樣本評估
+    pub objectives: Vec<Objective>,
+    pub source: PolicySource,
+}
+
+impl Policy {
+    // 字串值中的 ${VAR} 在解析成欄位之前替換 (見 vars::Variables)
+    fn parse(text: &str, source: PolicySource, vars: &Variables) -> Result<Self, String> {
+        let location = source.label();
+        let mut table: toml::Table = toml::from_str(text).map_err(|e| format!("{}: 格式錯誤: {}", location, e))?;
+        vars.expand_toml(&mut table).map_err(|e| format!("{}: {}", location, e))?;
+        let file: PolicyFile = toml::Value::Table(table).try_into().map_err(|e| format!("{}: 格式錯誤: {}", location, e))?;
+        if file.name.trim().is_empty() {
+            return Err(format!("{}: 缺少 name", location));
+        }
+        if file.expectations.is_empty() && file.assertions.is_empty() && file.objectives.is_empty() {
+            return Err(format!("{}: 至少需要一個 [[expect]]、[[assert]] 或 [[slo]]", location));
+        }
+        let assertions = assertions::compile(file.assertions, &location)?;
+
+        let mut expectations = Vec::new();
+        for expect in file.expectations {
+            let ports = match (&expect.ports, &expect.group) {
+                (Some(ports), None) => crate::portspec::parse_list(ports).map_err(|e| format!("{}: {}", location, e))?,
+                (None, Some(_)) => BTreeSet::new(),
+                _ => return Err(format!("{}: 每個 [[expect]] 必須指定 ports 或 group 其中之一", location)),
+            };
+            expectations.push(Expectation {
+                ports,
+                group: expect.group,
+                state: expect.state,
+                reason: expect.reason,
+            });
+        }
+        check_conflicts(&expectations).map_err(|e| format!("{}: {}", location, e))?;
+
+        let mut objectives = Vec::new();
+        for slo in file.objectives {
+            let ports = match (&slo.ports, &slo.group) {
+                (Some(ports), None) => crate::portspec::parse_list(ports).map_err(|e| format!("{}: {}", location, e))?,
+                (None, Some(_)) => BTreeSet::new(),
+                _ => return Err(format!("{}: 每個 [[slo]] 必須指定 ports 或 group 其中之一", location)),
+            };
+            let (quantile, below_ms) = parse_objective(&slo.objective).map_err(|e| format!("{}: {}", location, e))?;
+            objectives.push(Objective { ports, group: slo.group, quantile, below_ms, reason: slo.reason });
+        }
+
+        Ok(Policy {
+            title: file.title.unwrap_or_else(|| file.name.clone()),
+            name: file.name,
+            description: file.description,
+            pass_message: file.pass_message.unwrap_or_else(|| "所有端口符合政策".to_string()),
+            fail_message: file.fail_message.unwrap_or_else(|| "部分端口不符合政策".to_string()),
+            expectations,
+            assertions,
+            objectives,
+            source,
+        })
+    }
+
+    // --policy 指定的檔案
+    pub fn load(path: &Path, vars: &Variables) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("無法讀取政策檔 {}: {}", path.display(), e))?;
+        Policy::parse(&text, PolicySource::File(path.to_path_buf()), vars)
+    }
+
+    // 以群組指定的預期展開成群組的成員端口
+    pub fn resolve_groups(&mut self, groups: &Groups) -> Result<(), String> {
+        for expect in &mut self.expectations {
+            if let Some(name) = &expect.group {
+                expect.ports = groups.find(name)?.ports.clone();
+            }
+        }
+        for objective in &mut self.objectives {
+            if let Some(name) = &objective.group {
+                objective.ports = groups.find(name)?.ports.clone();
+            }
+        }
+        check_conflicts(&self.expectations).map_err(|e| format!("{}: {}", self.source.label(), e))
+    }
+
+    // 政策引用的群組，結果中以群組顯示
+    pub fn group_names(&self) -> Vec<String> {
+        let expected = self.expectations.iter().filter_map(|e| e.group.clone());
+        expected.chain(self.objectives.iter().filter_map(|o| o.group.clone())).collect()
+    }
+
+    // 有橫幅斷言時即使沒有 --banners 也要探測橫幅
+    pub fn needs_banners(&self) -> bool {
+        self.assertions.iter().any(|a| matches!(a.check, assertions::Check::Banner(_)))
+    }
+
+    // 有延遲目標時即使沒有 --samples 也要取樣
+    pub fn needs_samples(&self) -> bool {
+        !self.objectives.is_empty()
+    }
+
+    // 政策涵蓋的所有端口 (含斷言的端口)，作為 --ports 的預設值
+    pub fn port_spec(&self) -> String {
+        let ports: BTreeSet<u16> = self
+            .expectations
+            .iter()
+            .flat_map(|e| e.ports.iter().copied())
+            .chain(self.assertions.iter().map(|a| a.port))
+            .chain(self.objectives.iter().flat_map(|o| o.ports.iter().copied()))
+            .collect();
+        ports.iter().map(u16::to_string).collect::<Vec<_>>().join(",")
+    }
+}
+
+// 同一端口不能同時被要求開放與關閉
+fn check_conflicts(expectations: &[Expectation]) -> Result<(), String> {
+    let mut seen: BTreeMap<u16, Expected> = BTreeMap::new();
+    for expect in expectations {
+        for &port in &expect.ports {
+            if seen.insert(port, expect.state).is_some_and(|prev| prev.open() != expect.state.open()) {
+                return Err(format!("Port {} 同時被要求開放與關閉", port));
+            }
+        }
+    }
+    Ok(())
+}
+
+// 使用者範本目錄：設定檔目錄下的 templates/
+pub fn templates_dir() -> Option<PathBuf> {
+    crate::config::config_dir().map(|dir| dir.join("templates"))
+}
+
+// 載入內建範本與使用者範本；同名的使用者範本覆蓋內建範本
+pub fn templates(dir: Option<&Path>, vars: &Variables) -> Result<Vec<Policy>, String> {
+    let mut templates = BUILTIN_TEMPLATES
+        .iter()
+        .map(|text| Policy::parse(text, PolicySource::Builtin, vars))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let Some(dir) = dir.filter(|dir| dir.is_dir()) else {
+        return Ok(templates);
+    };
+    let entries = fs::read_dir(dir).map_err(|e| format!("無法讀取範本目錄 {}: {}", dir.display(), e))?;
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let template = Policy::load(&path, vars)?;
+        match templates.iter().position(|t| t.name == template.name) {
+            Some(index) if templates[index].source == PolicySource::Builtin => templates[index] = template,
+            Some(index) => {
+                return Err(format!(
+                    "範本名稱重複: {} ({} 與 {})",
+                    template.name,
+                    templates[index].source.label(),
+                    path.display()
+                ))
+            }
+            None => templates.push(template),
+        }
+    }
+    Ok(templates)
+}
+
+// 依名稱找範本
+pub fn find_template(name: &str, vars: &Variables) -> Result<Policy, String> {
+    let templates = templates(templates_dir().as_deref(), vars)?;
+    let names: Vec<String> = templates.iter().map(|t| t.name.clone()).collect();
+    templates
+        .into_iter()
+        .find(|t| t.name == name)
+        .ok_or_else(|| format!("找不到範本 {} (可用: {})", name, names.join(", ")))
+}
+
+// 不符合政策的端口
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Finding {
+    pub port: u16,
+    pub service: String,
+    pub expected: Expected,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    // 以服務群組指定的預期
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+// 一項延遲目標的評估；measured_ms 為樣本的百分位數
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ObjectiveOutcome {
+    pub port: u16,
+    pub service: String,
+    pub quantile: Quantile,
+    pub threshold_ms: f64,
+    pub measured_ms: f64,
+    pub samples: usize,
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+// 單一主機的政策檢查結果
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct HostVerdict {
+    pub host: IpAddr,
+    pub checked: usize,
+    pub findings: Vec<Finding>,
+    // [[assert]] 的結果，與可達性的 findings 分開
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub assertions: Vec<AssertionOutcome>,
+    // [[slo]] 的結果；沒有延遲樣本 (無法連線) 的端口不列入
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub objectives: Vec<ObjectiveOutcome>,
+}
+
+impl HostVerdict {
+    fn failed_assertions(&self) -> impl Iterator<Item = &AssertionOutcome> {
+        self.assertions.iter().filter(|a| a.status != assertions::Status::Passed)
+    }
+
+    fn breaches(&self) -> impl Iterator<Item = &ObjectiveOutcome> {
+        self.objectives.iter().filter(|o| !o.passed)
+    }
+
+    fn passed(&self) -> bool {
+        self.findings.is_empty() && self.failed_assertions().next().is_none() && self.breaches().next().is_none()
+    }
+}
+
+// 政策檢查結果
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct PolicyReport {
+    pub name: String,
+    pub title: String,
+    pub passed: bool,
+    pub message: String,
+    pub hosts: Vec<HostVerdict>,
+}
+
+impl PolicyReport {
+    pub fn violations(&self) -> usize {
+        self.hosts.iter().map(|h| h.findings.len()).sum()
+    }
+
+    // 未通過的斷言 (含無法評估)
+    pub fn assertion_failures(&self) -> usize {
+        self.hosts.iter().map(|h| h.failed_assertions().count()).sum()
+    }
+
+    // 未達成的延遲目標
+    pub fn breaches(&self) -> usize {
+        self.hosts.iter().map(|h| h.breaches().count()).sum()
+    }
+
+    // 未通過時的錯誤訊息，例如 "2 個端口不符合 PCI 外部掃描檢查，1 項斷言不符"
+    pub fn failure_summary(&self) -> String {
+        let summary = match (self.violations(), self.assertion_failures(), self.breaches()) {
+            (0, 0, breaches) => return format!("{} 項延遲目標不符合 {}", breaches, self.title),
+            (ports, 0, _) => format!("{} 個端口不符合 {}", ports, self.title),
+            (0, asserts, _) => format!("{} 項斷言不符合 {}", asserts, self.title),
+            (ports, asserts, _) => format!("{} 個端口不符合 {}，{} 項斷言不符", ports, self.title, asserts),
+        };
+        match self.breaches() {
+            0 => summary,
+            breaches => format!("{}，{} 項延遲目標未達成", summary, breaches),
+        }
+    }
+}
+
+// 比對掃描結果與政策；沒有掃描到、掃描端錯誤或疑似被強制門戶攔截的端口不列入判斷
+// outcomes 為掃描後另外評估的斷言結果 (assertions::evaluate)
+pub fn evaluate(
+    policy: &Policy,
+    results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>,
+    outcomes: &BTreeMap<IpAddr, Vec<AssertionOutcome>>,
+) -> PolicyReport {
+    let hosts: Vec<HostVerdict> = results
+        .iter()
+        .map(|(host, host_results)| {
+            let mut checked = 0;
+            let mut findings = Vec::new();
+            let mut objectives = Vec::new();
+            let mut ports: Vec<(&PortInfo, &ScanResult)> =
+                host_results.iter().filter(|(_, r)| r.error.is_none() && !r.intercepted).collect();
+            ports.sort_by_key(|(p, _)| p.port);
+
+            for (port, result) in ports {
+                if let Some(samples) = &result.samples {
+                    for objective in policy.objectives.iter().filter(|o| o.ports.contains(&port.port)) {
+                        let measured_ms = samples.percentiles.get(objective.quantile);
+                        objectives.push(ObjectiveOutcome {
+                            port: port.port,
+                            service: port.service.clone(),
+                            quantile: objective.quantile,
+                            threshold_ms: objective.below_ms,
+                            measured_ms,
+                            samples: samples.percentiles.count,
+                            passed: measured_ms < objective.below_ms,
+                            reason: objective.reason.clone(),
+                        });
+                    }
+                }
+                let Some(expect) = policy.expectations.iter().find(|e| e.ports.contains(&port.port)) else {
+                    continue;
+                };
+                checked += 1;
+                if !expect.state.satisfied_by(result) {
+                    findings.push(Finding {
+                        port: port.port,
+                        service: port.service.clone(),
+                        expected: expect.state,
+                        reason: expect.reason.clone(),
+                        group: expect.group.clone(),
+                    });
+                }
+            }
+            HostVerdict {
+                host: *host,
+                checked,
+                findings,
+                assertions: outcomes.get(host).cloned().unwrap_or_default(),
+                objectives,
+            }
+        })
+        .collect();
+
+    let passed = hosts.iter().all(HostVerdict::passed);
+    PolicyReport {
+        name: policy.name.clone(),
+        title: policy.title.clone(),
+        passed,
+        message: if passed { policy.pass_message.clone() } else { policy.fail_message.clone() },
+        hosts,
+    }
+}
+
+// 顯示政策檢查結果
+pub fn display_report(report: &PolicyReport) {
+    println!("\n{}", format!("=== {} ===", report.title).bold());
+    for verdict in &report.hosts {
+        let mut asserted = match verdict.assertions.len() {
+            0 => String::new(),
+            n => format!("，{} 項斷言", n),
+        };
+        if !verdict.objectives.is_empty() {
+            asserted.push_str(&format!("，{} 項延遲目標", verdict.objectives.len()));
+        }
+        if verdict.passed() {
+            println!("{} {} ({} 個端口{})", "✓".green(), verdict.host, verdict.checked, asserted);
+            continue;
+        }
+        let failed = verdict.failed_assertions().count();
+        let mut failed = match failed {
+            0 => String::new(),
+            n => format!("，{} / {} 項斷言不符", n, verdict.assertions.len()),
+        };
+        if let n @ 1.. = verdict.breaches().count() {
+            failed.push_str(&format!("，{} / {} 項延遲目標未達成", n, verdict.objectives.len()));
+        }
+        println!(
+            "{} {} ({} / {} 個端口不符合{})",
+            "✗".red(),
+            verdict.host,
+            verdict.findings.len(),
+            verdict.checked,
+            failed
+        );
+        for finding in &verdict.findings {
+            let group = finding.group.as_deref().map(|g| format!(" [群組 {}]", g)).unwrap_or_default();
+            let reason = finding.reason.as_deref().map(|r| format!(" — {}", r)).unwrap_or_default();
+            println!(
+                "    Port {:5} ({:15}): 應為{}{}{}",
+                finding.port,
+                finding.service,
+                finding.expected.label(),
+                group,
+                reason.dimmed()
+            );
+        }
+        for outcome in verdict.failed_assertions() {
+            println!("    {}", assertions::describe(outcome));
+        }
+        for breach in verdict.breaches() {
+            let reason = breach.reason.as_deref().map(|r| format!(" — {}", r)).unwrap_or_default();
+            println!(
+                "    Port {:5} ({:15}): {} {:.1}ms，目標 < {}ms ({} 次){}",
+                breach.port,
+                breach.service,
+                breach.quantile.label(),
+                breach.measured_ms,
+                breach.threshold_ms,
+                breach.samples,
+                reason.dimmed()
+            );
+        }
+    }
+
+    if report.passed {
+        println!("{}", report.message.green().bold());
+    } else {
+        println!("{}", report.message.red().bold());
+    }
+}
+
+// templates list
+pub fn display_list(templates: &[Policy], dir: Option<&Path>) {
+    println!("{}", "=== 掃描範本 ===".bold());
+    if let Some(dir) = dir {
+        println!("使用者目錄: {}", dir.display());
+    }
+    for template in templates {
+        println!(
+            "\n{} ({})  {}",
+            template.name.bold(),
+            template.source.label().dimmed(),
+            template.title
+        );
+        if let Some(description) = &template.description {
+            println!("  {}", description);
+        }
+    }
+}
+
+// templates show
+pub fn display_template(template: &Policy) {
+    println!("{} ({})", template.title.bold(), template.name);
+    println!("來源: {}", template.source.label());
+    if let Some(description) = &template.description {
+        println!("{}", description);
+    }
+    for expect in &template.expectations {
+        let reason = expect.reason.as_deref().map(|r| format!(" — {}", r)).unwrap_or_default();
+        println!("\n應為{}{}", expect.state.label().bold(), reason);
+        match (&expect.group, expect.ports.is_empty()) {
+            (Some(group), true) => println!("  服務群組 {}", group),
+            _ => {
+                let ports: Vec<String> = expect.ports.iter().map(u16::to_string).collect();
+                println!("  {}", ports.join(", "));
+            }
+        }
+    }
+    for assertion in &template.assertions {
+        let reason = assertion.reason.as_deref().map(|r| format!(" — {}", r)).unwrap_or_default();
+        println!("\n斷言 Port {} {}{}", assertion.port, assertion.check.describe(), reason);
+    }
+    for objective in &template.objectives {
+        let reason = objective.reason.as_deref().map(|r| format!(" — {}", r)).unwrap_or_default();
+        let ports = match (&objective.group, objective.ports.is_empty()) {
+            (Some(group), true) => format!("服務群組 {}", group),
+            _ => objective.ports.iter().map(u16::to_string).collect::<Vec<_>>().join(", "),
+        };
+        println!("\n延遲目標 {} {}{}", ports, objective.describe(), reason);
+    }
+    println!("\n通過: {}", template.pass_message);
+    println!("未通過: {}", template.fail_message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::samples::LatencySamples;
+    use crate::stats::Percentiles;
+    use crate::testutil;
+
+    #[test]
+    fn objectives_parse_with_duration_units() {
+        assert_eq!(parse_objective("p95 < 100ms"), Ok((Quantile::P95, 100.0)));
+        assert_eq!(parse_objective(" median<1.5s "), Ok((Quantile::P50, 1500.0)));
+        for bad in ["p95 100ms", "p75 < 100ms", "p95 < fast", "p95 < 0ms", "< 10ms"] {
+            assert!(parse_objective(bad).is_err(), "{}", bad);
+        }
+    }
+
+    fn slo_policy() -> Policy {
+        let text = r#"
+            name = "latency"
+            [[slo]]
+            ports = "443"
+            objective = "p95 < 100ms"
+            [[slo]]
+            ports = "22,443"
+            objective = "p50 < 20ms"
+            reason = "互動操作"
+        "#;
+        Policy::parse(text, PolicySource::Builtin, &Variables::default()).unwrap()
+    }
+
+    fn sampled(values: &[f64]) -> ScanResult {
+        let mut result = testutil::scan_result(true);
+        result.samples = Some(LatencySamples { percentiles: Percentiles::of(values).unwrap(), lost: 0 });
+        result
+    }
+
+    #[test]
+    fn breaches_report_measured_percentiles() {
+        let policy = slo_policy();
+        assert!(policy.needs_samples());
+        // 443: p95 = 120ms (超過)、p50 = 15ms；22: p50 = 30ms (超過)
+        let web: Vec<f64> = (1..=20).map(|n| if n > 18 { 120.0 } else { 15.0 }).collect();
+        let results = BTreeMap::from([(
+            "10.0.0.1".parse().unwrap(),
+            HashMap::from([
+                (PortInfo::new(443, "HTTPS", "Web"), sampled(&web)),
+                (PortInfo::new(22, "SSH", "Remote"), sampled(&[30.0, 30.0, 30.0])),
+                // 沒有延遲樣本的端口不列入
+                (PortInfo::new(80, "HTTP", "Web"), testutil::scan_result(false)),
+            ]),
+        )]);
+        let report = evaluate(&policy, &results, &BTreeMap::new());
+        assert!(!report.passed);
+        assert_eq!(report.breaches(), 2);
+        let outcomes: Vec<(u16, Quantile, bool)> =
+            report.hosts[0].objectives.iter().map(|o| (o.port, o.quantile, o.passed)).collect();
+        assert_eq!(outcomes, vec![(22, Quantile::P50, false), (443, Quantile::P95, false), (443, Quantile::P50, true)]);
+        let p95 = &report.hosts[0].objectives[1];
+        assert_eq!(p95.measured_ms, 120.0);
+        assert_eq!(p95.samples, 20);
+        assert!(report.failure_summary().contains("2 項延遲目標"));
+    }
+
+    #[test]
+    fn intercepted_ports_are_not_judged() {
+        let text = "name = \"web\"\n[[expect]]\nports = \"80,443\"\nstate = \"closed\"";
+        let policy = Policy::parse(text, PolicySource::Builtin, &Variables::default()).unwrap();
+        let mut portal = testutil::scan_result(true);
+        portal.intercepted = true;
+        let results = BTreeMap::from([(
+            testutil::host(1),
+            HashMap::from([(PortInfo::new(80, "HTTP", "Web"), portal), (PortInfo::new(443, "HTTPS", "Web"), testutil::scan_result(true))]),
+        )]);
+        let report = evaluate(&policy, &results, &BTreeMap::new());
+        // 強制門戶代為接受的 80 不算違反，也不算檢查過
+        assert_eq!(report.hosts[0].checked, 1);
+        let failed: Vec<u16> = report.hosts[0].findings.iter().map(|f| f.port).collect();
+        assert_eq!(failed, [443]);
+    }
+
+    #[test]
+    fn healthy_expectations_need_a_passing_health_check() {
+        use crate::checks::health::Health;
+        let text = r#"
+            name = "api"
+            [[expect]]
+            ports = "8080,8081,8082"
+            state = "healthy"
+            [[expect]]
+            ports = "8080"
+            state = "open"
+        "#;
+        let policy = Policy::parse(text, PolicySource::Builtin, &Variables::default()).unwrap();
+        let checked = |state| {
+            let mut result = testutil::scan_result(state != HealthState::Closed);
+            result.health = Some(Health { check: "health-http".to_string(), state, summary: String::new(), details: BTreeMap::new() });
+            result
+        };
+        let results = BTreeMap::from([(
+            testutil::host(1),
+            HashMap::from([
+                (PortInfo::new(8080, "HTTP-ALT", "Web"), checked(HealthState::Healthy)),
+                (PortInfo::new(8081, "API", "Web"), checked(HealthState::Unhealthy)),
+                (PortInfo::new(8082, "API", "Web"), checked(HealthState::Closed)),
+            ]),
+        )]);
+        let report = evaluate(&policy, &results, &BTreeMap::new());
+        let failed: Vec<(u16, Expected)> = report.hosts[0].findings.iter().map(|f| (f.port, f.expected)).collect();
+        assert_eq!(failed, vec![(8081, Expected::Healthy), (8082, Expected::Healthy)]);
+        // 健康隱含開放，只有與關閉同時要求時才衝突
+        let conflict = "name = \"x\"\n[[expect]]\nports = \"80\"\nstate = \"healthy\"\n[[expect]]\nports = \"80\"\nstate = \"closed\"";
+        assert!(Policy::parse(conflict, PolicySource::Builtin, &Variables::default()).unwrap_err().contains("同時被要求開放與關閉"));
+    }
+
+    #[test]
+    fn placeholders_are_filled_before_fields_are_parsed() {
+        let text = "name = \"${PORTSCANNER_TEST_NAME:-ci}\"\n[[expect]]\nports = \"${PORTSCANNER_TEST_PORTS}\"\nstate = \"open\"";
+        let source = PolicySource::File(PathBuf::from("ci.toml"));
+        let vars = Variables::new(&[("PORTSCANNER_TEST_PORTS".to_string(), "22,5432".to_string())]);
+        let policy = Policy::parse(text, source.clone(), &vars).unwrap();
+        assert_eq!(policy.name, "ci");
+        assert_eq!(policy.port_spec(), "22,5432");
+        let err = Policy::parse(text, source, &Variables::new(&[])).unwrap_err();
+        assert!(err.starts_with("ci.toml: 未設定的變數: PORTSCANNER_TEST_PORTS "), "{}", err);
+    }
+}
@@ -0,0 +1,217 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use socket2::Socket;
+
+// 出站探測的 socket 池：連線失敗 (RST / 逾時) 的 socket 解除關聯後留給下一個探測，
+// 省下每次探測的 socket()/close()；來源端口仍由核心在每次 connect 時配置
+// 只在 Linux 啟用 (以 connect(AF_UNSPEC) 把 socket 還原為未連線)；其他平台每次建立新的 socket
+// 連線成功的 socket 已建立 TCP 連線，直接關閉不回收
+#[derive(Debug)]
+pub struct SocketPool {
+    // 保留的閒置 socket 上限 (與並發數相同，每個許可最多一個)
+    capacity: usize,
+    idle_v4: Mutex<Vec<Socket>>,
+    idle_v6: Mutex<Vec<Socket>>,
+    created: AtomicU64,
+    reused: AtomicU64,
+}
+
+// 單次連線嘗試
+pub struct Attempt {
+    // None 為逾時
+    pub outcome: Option<io::Result<()>>,
+    // 用於比對 ICMP 錯誤引用的探測
+    pub source_port: Option<u16>,
+    // 連線成功時使用的本機位址 (--route-check)
+    pub local: Option<SocketAddr>,
+    // 取得 socket 並送出 SYN 所花的時間
+    pub setup: Duration,
+}
+
+// 建立與重複使用的次數
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PoolStats {
+    pub created: u64,
+    pub reused: u64,
+}
+
+impl SocketPool {
+    // 平台不支援重複使用時回傳 None，呼叫端改用一般的連線方式
+    pub fn open(capacity: usize) -> Option<Self> {
+        platform::SUPPORTED.then(|| SocketPool {
+            capacity: capacity.max(1),
+            idle_v4: Mutex::new(Vec::new()),
+            idle_v6: Mutex::new(Vec::new()),
+            created: AtomicU64::new(0),
+            reused: AtomicU64::new(0),
+        })
+    }
+
+    fn idle(&self, dest: &SocketAddr) -> &Mutex<Vec<Socket>> {
+        match dest {
+            SocketAddr::V4(_) => &self.idle_v4,
+            SocketAddr::V6(_) => &self.idle_v6,
+        }
+    }
+
+    fn take(&self, dest: &SocketAddr) -> io::Result<Socket> {
+        let reused = self.idle(dest).lock().unwrap_or_else(|e| e.into_inner()).pop();
+        match reused {
+            Some(socket) => {
+                self.reused.fetch_add(1, Ordering::Relaxed);
+                Ok(socket)
+            }
+            None => {
+                let socket = platform::create(dest)?;
+                self.created.fetch_add(1, Ordering::Relaxed);
+                Ok(socket)
+            }
+        }
+    }
+
+    // 無法解除關聯或池已滿時關閉
+    fn give_back(&self, socket: Socket, dest: &SocketAddr) {
+        if !platform::disconnect(&socket) {
+            return;
+        }
+        let mut idle = self.idle(dest).lock().unwrap_or_else(|e| e.into_inner());
+        if idle.len() < self.capacity {
+            idle.push(socket);
+        }
+    }
+
+    // 以池中的 socket 連線；每個 socket 同時只屬於一個探測，由並發許可保證上限
+    pub async fn connect(&self, dest: SocketAddr, limit: Duration) -> Attempt {
+        let started = Instant::now();
+        let socket = match self.take(&dest) {
+            Ok(socket) => socket,
+            Err(e) => {
+                return Attempt {
+                    outcome: Some(Err(e)),
+                    source_port: None,
+                    local: None,
+                    setup: started.elapsed(),
+                }
+            }
+        };
+        let (socket, outcome, local, setup) = platform::connect(socket, dest, limit, started).await;
+        let connected = matches!(outcome, Some(Ok(())));
+        if !connected {
+            if let Some(socket) = socket {
+                self.give_back(socket, &dest);
+            }
+        }
+        Attempt {
+            outcome,
+            source_port: local.map(|addr| addr.port()),
+            local: local.filter(|_| connected),
+            setup,
+        }
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            created: self.created.load(Ordering::Relaxed),
+            reused: self.reused.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::io;
+    use std::mem;
+    use std::net::SocketAddr;
+    use std::os::fd::AsRawFd;
+    use std::time::{Duration, Instant};
+    use socket2::{Domain, Protocol, Socket, Type};
+    use tokio::io::unix::AsyncFd;
+    use tokio::io::Interest;
+    use tokio::time::timeout;
+
+    pub const SUPPORTED: bool = true;
+
+    pub fn create(dest: &SocketAddr) -> io::Result<Socket> {
+        let socket = Socket::new(Domain::for_address(*dest), Type::STREAM.nonblocking(), Some(Protocol::TCP))?;
+        Ok(socket)
+    }
+
+    // connect(AF_UNSPEC) 讓 TCP socket 回到未連線狀態，之後可以再次 connect
+    pub fn disconnect(socket: &Socket) -> bool {
+        let mut addr: libc::sockaddr = unsafe { mem::zeroed() };
+        addr.sa_family = libc::AF_UNSPEC as libc::sa_family_t;
+        let rc = unsafe {
+            libc::connect(socket.as_raw_fd(), &addr, mem::size_of::<libc::sockaddr>() as libc::socklen_t)
+        };
+        rc == 0
+    }
+
+    fn local_addr(socket: &Socket) -> Option<SocketAddr> {
+        socket.local_addr().ok()?.as_socket()
+    }
+
+    // 非阻塞 connect 後等待可寫入，再以 SO_ERROR 取得結果
+    // 回傳的 socket 為 None 時已無法回收；本機位址在 connect 時由核心選定
+    pub async fn connect(
+        socket: Socket,
+        dest: SocketAddr,
+        limit: Duration,
+        started: Instant,
+    ) -> (Option<Socket>, Option<io::Result<()>>, Option<SocketAddr>, Duration) {
+        let pending = match socket.connect(&dest.into()) {
+            Ok(()) => false,
+            Err(e) if e.raw_os_error() == Some(libc::EINPROGRESS) => true,
+            Err(e) => return (Some(socket), Some(Err(e)), None, started.elapsed()),
+        };
+        // 來源端口在 connect 時才配置
+        let port = local_addr(&socket);
+        let setup = started.elapsed();
+        if !pending {
+            return (None, Some(Ok(())), port, setup);
+        }
+        let fd = match AsyncFd::with_interest(socket, Interest::WRITABLE) {
+            Ok(fd) => fd,
+            Err(e) => return (None, Some(Err(e)), port, setup),
+        };
+        let outcome = match timeout(limit, fd.writable()).await {
+            Ok(Ok(_)) => Some(match fd.get_ref().take_error() {
+                Ok(None) => Ok(()),
+                Ok(Some(e)) | Err(e) => Err(e),
+            }),
+            Ok(Err(e)) => Some(Err(e)),
+            Err(_) => None,
+        };
+        (Some(fd.into_inner()), outcome, port, setup)
+    }
+}
+
+// 其他平台不回收 socket
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use std::io;
+    use std::net::SocketAddr;
+    use std::time::{Duration, Instant};
+    use socket2::Socket;
+
+    pub const SUPPORTED: bool = false;
+
+    pub fn create(_dest: &SocketAddr) -> io::Result<Socket> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    pub fn disconnect(_socket: &Socket) -> bool {
+        false
+    }
+
+    pub async fn connect(
+        _socket: Socket,
+        _dest: SocketAddr,
+        _limit: Duration,
+        started: Instant,
+    ) -> (Option<Socket>, Option<io::Result<()>>, Option<SocketAddr>, Duration) {
+        (None, Some(Err(io::ErrorKind::Unsupported.into())), None, started.elapsed())
+    }
+}
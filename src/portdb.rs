@@ -0,0 +1,322 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use colored::*;
+use serde::Deserialize;
+use toml_edit::{value, DocumentMut, Item};
+use crate::cli::PortsCommand;
+use crate::config;
+use crate::portspec;
+use crate::PortInfo;
+
+// 新建立的資料庫檔案開頭的說明
+const HEADER: &str = "# 使用者端口資料庫：[端口] 區段新增端口，或覆蓋內建端口的服務名稱與類別\n\
+# removed = true 表示預設掃描不包含此內建端口\n\
+# 可直接編輯，或使用 portscanner ports add / remove / move\n";
+
+// 未指定類別時新增端口的類別，與 --ports 中未知端口相同
+pub const DEFAULT_CATEGORY: &str = "Custom";
+
+// ports.toml 中的一個端口，例如
+//   [9200]
+//   service = "Elasticsearch"
+//   category = "Database"
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Entry {
+    pub service: Option<String>,
+    pub category: Option<String>,
+    pub note: Option<String>,
+    // 預設掃描不包含此內建端口
+    #[serde(default)]
+    pub removed: bool,
+}
+
+// 使用者端口資料庫；與內建端口表合併成掃描使用的端口清單
+#[derive(Debug, Default)]
+pub struct PortDatabase {
+    pub entries: BTreeMap<u16, Entry>,
+}
+
+// 預設位置：設定目錄下的 ports.toml
+pub fn default_path() -> Option<PathBuf> {
+    config::config_dir().map(|dir| dir.join("ports.toml"))
+}
+
+impl PortDatabase {
+    pub fn parse(text: &str, path: &Path) -> Result<Self, String> {
+        let raw: BTreeMap<String, Entry> =
+            toml::from_str(text).map_err(|e| format!("端口資料庫 {} 格式錯誤: {}", path.display(), e))?;
+        let mut entries = BTreeMap::new();
+        for (key, entry) in raw {
+            let blank = |field: &Option<String>| field.as_deref().is_some_and(|s| s.trim().is_empty());
+            if blank(&entry.service) || blank(&entry.category) {
+                return Err(format!("端口資料庫 {} 的 [{}] 服務名稱與類別不能是空字串", path.display(), key));
+            }
+            let port = portspec::parse_port(&key).map_err(|e| format!("端口資料庫 {} {}", path.display(), e))?;
+            if entries.insert(port, entry).is_some() {
+                return Err(format!("端口資料庫 {} 重複的端口: {}", path.display(), key));
+            }
+        }
+        Ok(PortDatabase { entries })
+    }
+
+    // 讀取資料庫；檔案不存在時為空
+    pub fn load(path: Option<&Path>) -> Result<Self, String> {
+        let Some(path) = path.filter(|p| p.exists()) else {
+            return Ok(PortDatabase::default());
+        };
+        let text = fs::read_to_string(path).map_err(|e| format!("無法讀取端口資料庫 {}: {}", path.display(), e))?;
+        Self::parse(&text, path)
+    }
+
+    // 內建端口依覆蓋修改 (移除的略過)，再依端口順序加上新增的端口
+    pub fn merge(&self, builtin: Vec<PortInfo>) -> Vec<PortInfo> {
+        let known: BTreeSet<u16> = builtin.iter().map(|p| p.port).collect();
+        let mut ports: Vec<PortInfo> = builtin
+            .into_iter()
+            .filter_map(|mut port| match self.entries.get(&port.port) {
+                Some(entry) if entry.removed => None,
+                Some(entry) => {
+                    if let Some(service) = &entry.service {
+                        port.service = service.clone();
+                    }
+                    if let Some(category) = &entry.category {
+                        port.category = category.clone();
+                    }
+                    Some(port)
+                }
+                None => Some(port),
+            })
+            .collect();
+        for (port, entry) in self.entries.iter().filter(|(port, e)| !known.contains(port) && !e.removed) {
+            ports.push(PortInfo::new(
+                *port,
+                entry.service.as_deref().unwrap_or("未知"),
+                entry.category.as_deref().unwrap_or(DEFAULT_CATEGORY),
+            ));
+        }
+        ports
+    }
+}
+
+// ports add / remove / move 對檔案內容的修改；保留原有的註解與排版
+struct Edit<'a> {
+    document: DocumentMut,
+    builtin: &'a [PortInfo],
+    database: PortDatabase,
+    path: PathBuf,
+}
+
+impl Edit<'_> {
+    fn builtin(&self, port: u16) -> Option<&PortInfo> {
+        self.builtin.iter().find(|p| p.port == port)
+    }
+
+    // 目前合併後的端口 (不含已移除的)
+    fn current(&self, port: u16) -> Option<PortInfo> {
+        self.database.merge(self.builtin.to_vec()).into_iter().find(|p| p.port == port)
+    }
+
+    fn entry(&mut self, port: u16) -> &mut Item {
+        let key = port.to_string();
+        if !self.document.contains_key(&key) {
+            self.document[&key] = toml_edit::table();
+        }
+        &mut self.document[&key]
+    }
+
+    fn add(&mut self, port: u16, service: &str, category: &str, note: Option<&str>) -> Result<(), String> {
+        if let Some(current) = self.current(port) {
+            let origin = match self.builtin(port) {
+                Some(_) => "內建端口表",
+                None => "端口資料庫",
+            };
+            return Err(format!(
+                "{}已有端口 {} ({}，類別 {})；修改類別請使用 ports move",
+                origin, port, current.service, current.category
+            ));
+        }
+        // 新增先前移除的內建端口時恢復並覆蓋其名稱與類別
+        let builtin = self.builtin(port).cloned();
+        let entry = self.entry(port);
+        if let Some(table) = entry.as_table_like_mut() {
+            table.remove("removed");
+        }
+        match builtin {
+            Some(builtin) if builtin.service == service => {
+                if let Some(table) = entry.as_table_like_mut() {
+                    table.remove("service");
+                }
+            }
+            _ => entry["service"] = value(service),
+        }
+        entry["category"] = value(category);
+        if let Some(note) = note {
+            entry["note"] = value(note);
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, port: u16) -> Result<(), String> {
+        if self.current(port).is_none() {
+            return Err(format!("端口 {} 不在端口資料庫中", port));
+        }
+        match self.builtin(port) {
+            // 內建端口無法刪除，只標記為不掃描
+            Some(_) => self.entry(port)["removed"] = value(true),
+            None => {
+                self.document.remove(&port.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    fn move_to(&mut self, port: u16, category: &str) -> Result<(), String> {
+        let current = self.current(port).ok_or_else(|| format!("端口 {} 不在端口資料庫中", port))?;
+        if current.category == category {
+            return Err(format!("端口 {} ({}) 已在類別 {}", port, current.service, category));
+        }
+        let builtin = self.builtin(port).cloned();
+        let entry = self.entry(port);
+        match builtin {
+            // 移回內建的類別時去掉覆蓋
+            Some(builtin) if builtin.category == category => {
+                if let Some(table) = entry.as_table_like_mut() {
+                    table.remove("category");
+                }
+            }
+            _ => entry["category"] = value(category),
+        }
+        if entry.as_table_like().is_some_and(|t| t.is_empty()) {
+            self.document.remove(&port.to_string());
+        }
+        Ok(())
+    }
+}
+
+// 行層級的差異 (最長共同子序列)，資料庫檔案不大
+fn diff(before: &str, after: &str) -> Vec<(char, String)> {
+    let a: Vec<&str> = before.lines().collect();
+    let b: Vec<&str> = after.lines().collect();
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = match a[i] == b[j] {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            lines.push((' ', a[i].to_string()));
+            (i, j) = (i + 1, j + 1);
+        } else if j < b.len() && (i == a.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            lines.push(('+', b[j].to_string()));
+            j += 1;
+        } else {
+            lines.push(('-', a[i].to_string()));
+            i += 1;
+        }
+    }
+    lines
+}
+
+// 顯示改變的行，前後各保留一行內容
+pub fn display_diff(path: &Path, before: &str, after: &str) {
+    println!("{}", format!("--- {}", path.display()).bold());
+    println!("{}", format!("+++ {}", path.display()).bold());
+    let lines = diff(before, after);
+    let changed = |i: usize| lines.get(i).is_some_and(|(kind, _)| *kind != ' ');
+    for (i, (kind, line)) in lines.iter().enumerate() {
+        match kind {
+            '+' => println!("{}", format!("+{}", line).green()),
+            '-' => println!("{}", format!("-{}", line).red()),
+            _ if changed(i + 1) || (i > 0 && changed(i - 1)) => println!(" {}", line),
+            _ => {}
+        }
+    }
+}
+
+// 合併後的端口清單，依類別分組
+pub fn display_database(ports: &[PortInfo], database: &PortDatabase) {
+    let mut categories: BTreeMap<&str, Vec<&PortInfo>> = BTreeMap::new();
+    for port in ports {
+        categories.entry(port.category.as_str()).or_default().push(port);
+    }
+    for (category, ports) in categories {
+        println!("\n{}", category.bold());
+        for port in ports {
+            let note = database.entries.get(&port.port).and_then(|e| e.note.as_deref());
+            let note = note.map(|n| format!(" — {}", n)).unwrap_or_default();
+            println!("  Port {:5} {}{}", port.port, port.service, note.dimmed());
+        }
+    }
+}
+
+// ports add / remove / move；dry_run 時只顯示結果不寫入
+pub fn run(action: &PortsCommand, builtin: Vec<PortInfo>) -> Result<(), Box<dyn Error>> {
+    let path = default_path().ok_or("找不到設定目錄，無法決定端口資料庫位置")?;
+    let before = match path.exists() {
+        true => fs::read_to_string(&path).map_err(|e| format!("無法讀取端口資料庫 {}: {}", path.display(), e))?,
+        false => String::new(),
+    };
+    let database = PortDatabase::parse(&before, &path)?;
+    let document: DocumentMut = before.parse().map_err(|e| format!("端口資料庫 {} 格式錯誤: {}", path.display(), e))?;
+    let categories: BTreeSet<String> = database.merge(builtin.clone()).into_iter().map(|p| p.category).collect();
+    let mut edit = Edit {
+        document,
+        builtin: &builtin,
+        database,
+        path,
+    };
+
+    let (dry_run, category) = match action {
+        PortsCommand::Add { port, service, category, note, dry_run } => {
+            if service.trim().is_empty() || category.trim().is_empty() {
+                return Err("服務名稱與類別不能是空字串".into());
+            }
+            edit.add(*port, service.trim(), category.trim(), note.as_deref())?;
+            (*dry_run, Some(category.trim()))
+        }
+        PortsCommand::Remove { port, dry_run } => {
+            edit.remove(*port)?;
+            (*dry_run, None)
+        }
+        PortsCommand::Move { port, category, dry_run } => {
+            if category.trim().is_empty() {
+                return Err("類別不能是空字串".into());
+            }
+            edit.move_to(*port, category.trim())?;
+            (*dry_run, Some(category.trim()))
+        }
+    };
+    // 類別打錯時容易沒注意到，新類別另外提示
+    if let Some(category) = category.filter(|c| !categories.contains(*c)) {
+        println!("{}", format!("注意: {} 是新的類別", category).yellow());
+    }
+
+    // 新檔案加上說明；只有註解的文件 toml_edit 會把註解放到最後
+    let after = match before.is_empty() {
+        true => format!("{}\n{}", HEADER, edit.document),
+        false => edit.document.to_string(),
+    };
+    // 寫入前確認結果仍是有效的資料庫
+    let updated = PortDatabase::parse(&after, &edit.path)?;
+    display_diff(&edit.path, &before, &after);
+    if dry_run {
+        println!("\n{}", "=== 合併後的端口資料庫 (--dry-run，未寫入) ===".bold());
+        display_database(&updated.merge(builtin), &updated);
+        return Ok(());
+    }
+    if let Some(parent) = edit.path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("無法建立目錄 {}: {}", parent.display(), e))?;
+    }
+    fs::write(&edit.path, after).map_err(|e| format!("無法寫入端口資料庫 {}: {}", edit.path.display(), e))?;
+    println!("{}", format!("已更新 {}", edit.path.display()).green());
+    Ok(())
+}
@@ -0,0 +1,244 @@
+use colored::*;
+use crate::direction::Directions;
+use crate::layout::{self, Layout};
+use crate::view::ResultView;
+use crate::checks::health::{self, HealthState};
+use crate::closure::Failure;
+use crate::{caps, captive, cloud, confidence, errors, grade, httpproxy, httpver, probes, route, samples, scanner, srv, syn, tags, threats, throughput, trend, verify, vhost};
+use crate::{PortInfo, ScanResult};
+
+// 狀態、延遲與標籤至少保留的寬度；服務欄位只用剩下的空間
+const STATUS_WIDTH: usize = 24;
+
+// 服務欄位至少保留的寬度
+const MIN_SERVICE: usize = 6;
+
+// 虛擬主機名稱欄位的上限
+const MAX_VHOST: usize = 28;
+
+// 同一份結果中各端口共用的欄寬，依實際內容計算
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Columns {
+    port: usize,
+    service: usize,
+}
+
+impl Columns {
+    pub fn measure<'a>(ports: impl IntoIterator<Item = &'a PortInfo>, layout: &Layout) -> Self {
+        let (mut port, mut service) = (1, 0);
+        for info in ports {
+            port = port.max(info.port.to_string().len());
+            service = service.max(layout::display_width(&info.service));
+        }
+        // "  Port NNNNN (服務): " 之後留給狀態
+        let room = layout.width.saturating_sub(2 + "Port  (): ".len() + port + STATUS_WIDTH);
+        Columns { port, service: service.min(room).max(MIN_SERVICE) }
+    }
+}
+
+// 單個端口的結果行；indent 加在每一行之前 (服務群組的成員)
+// 窄終端改為兩行一組：第一行端口與服務，第二行狀態
+pub fn port_lines(port_info: &PortInfo, result: &ScanResult, indent: &str, result_view: &ResultView, columns: &Columns) -> Vec<String> {
+    let layout = &result_view.layout;
+    let mut lines = Vec::new();
+    let status = status_text(port_info, result, result_view);
+    match layout.stacked() {
+        false => {
+            let service = layout::fit(&port_info.service, columns.service);
+            lines.push(format!("{}Port {:>width$} ({}): {}", indent, port_info.port, service, status, width = columns.port));
+        }
+        true => {
+            lines.push(format!("{}Port {} ({})", indent, port_info.port, port_info.service));
+            lines.push(format!("{}  {}", indent, status));
+        }
+    }
+
+    let detail = format!("{}    ", indent);
+    if let Some(grade) = &result.grade {
+        match grade.grade {
+            grade::Grade::A | grade::Grade::F => lines.push(format!("{}{}", detail, grade::badge(grade.grade))),
+            _ => lines.push(format!("{}{} {}", detail, grade::badge(grade.grade), grade.reason.dimmed())),
+        }
+    }
+    // 未開放的端口已由狀態說明，只顯示有執行的檢查
+    if let Some(health) = result.health.as_ref().filter(|h| h.state != HealthState::Closed) {
+        lines.push(format!("{}{}", detail, health::describe(health)));
+    }
+    if let Some(samples) = &result.samples {
+        lines.push(format!("{}{}", detail, samples::describe(samples)));
+    }
+    if let Some(capabilities) = &result.capabilities {
+        lines.push(format!("{}{}", detail, caps::flags(capabilities)));
+    }
+    if let Some(versions) = &result.http_versions {
+        lines.push(format!("{}{}", detail, httpver::flags(versions)));
+    }
+    if let Some(throughput) = &result.throughput {
+        lines.push(format!("{}{}", detail, throughput::describe(throughput)));
+    }
+    if let Some(route) = &result.route {
+        lines.push(format!("{}{}", detail, route::describe(route)));
+    }
+    let name_width = result.vhosts.iter().map(|v| layout::display_width(&v.name)).max().unwrap_or(0).min(MAX_VHOST);
+    for vhost in &result.vhosts {
+        match layout.stacked() {
+            false => lines.push(format!("{}{} {}", detail, layout::fit(&vhost.name, name_width), vhost::describe(vhost))),
+            true => lines.push(format!("{}{}: {}", detail, vhost.name, vhost::describe(vhost))),
+        }
+    }
+    if let Some(banner) = &result.banner {
+        lines.push(format!("{}{}", detail, probes::describe(banner).cyan()));
+    }
+    lines.iter().map(|line| layout.clip(line)).collect()
+}
+
+// 狀態、延遲或錯誤，以及覆核、續掃、標籤等註記
+fn status_text(port_info: &PortInfo, result: &ScanResult, result_view: &ResultView) -> String {
+    // 覆核後改變的結果與標籤附在狀態之後
+    let mut suffix = String::new();
+    if !result.trend.is_empty() {
+        suffix.push_str(&format!("  {}", trend::render(&result.trend, result_view.glyphs).cyan()));
+    }
+    if result.verification == Some(verify::Verification::Changed) {
+        suffix.push_str(&format!("  {}", "已覆核".magenta()));
+    }
+    if result.resumed {
+        suffix.push_str(&format!("  {}", "來自續掃".blue()));
+    }
+    if !port_info.tags.is_empty() {
+        suffix.push_str(&format!("  {}", tags::describe(&port_info.tags).cyan()));
+    }
+    if !result.bind.is_empty() {
+        let verdicts: Vec<String> = result.bind.iter().map(scanner::BindTest::describe).collect();
+        suffix.push_str(&format!("  {}", verdicts.join("、").dimmed()));
+    }
+    if !port_info.srv.is_empty() {
+        suffix.push_str(&format!("  {}", srv::describe(&port_info.srv).dimmed()));
+    }
+    if result.intercepted {
+        suffix.push_str(&format!("  {}", format!("⚠ {}", captive::NOTE).yellow()));
+    }
+    if let Some(threat) = threats::flagged(&result_view.threats, port_info.port, result) {
+        suffix.push_str(&format!("  {}", threats::mark(threat)));
+    }
+    if let Some(mark) = cloud::mark(&result_view.cloud, port_info.port, result) {
+        suffix.push_str(&format!("  {}", mark));
+    }
+
+    let latency = result.latency_ms.map(|ms| format!("  {:.1}ms", ms)).unwrap_or_default();
+    let label = confidence::mark(status_label(result.directions, result.inbound, result.outbound), result.confidence, result_view.low_confidence);
+    // 掃描端錯誤與 ICMP 錯誤附上錯誤代碼
+    if let Some(error) = result.error {
+        return format!("{}{}{}", format!("! {}", error.describe()).yellow(), errors::tag(&result.codes).dimmed(), suffix);
+    }
+    // 路徑存在但被代理的政策封鎖，與一般的不可用分開顯示
+    if let (false, Some(Failure::ProxyDenied { status })) = (result.outbound, result.failure) {
+        return format!("{}  {}{}{}", label, httpproxy::describe_denied(status).magenta(), errors::tag(&result.codes).dimmed(), suffix);
+    }
+    match (&result.note, &result.icmp) {
+        (Some(note), _) if !result.outbound => format!("{}{}", format!("? {}", note).yellow(), suffix),
+        (_, Some(icmp)) if !result.outbound => {
+            format!("{}  {}{}{}", label, icmp.describe().red(), errors::tag(&result.codes).dimmed(), suffix)
+        }
+        (_, None) if result.syn.is_some() && !result.outbound => {
+            let state = result.syn.map(syn::SynState::describe).unwrap_or_default();
+            format!("{}  {}{}", label, state.dimmed(), suffix)
+        }
+        _ => format!("{}{}{}", label, latency.dimmed(), suffix),
+    }
+}
+
+// 狀態標籤
+pub fn status_label(directions: Directions, inbound: bool, outbound: bool) -> ColoredString {
+    if let Some(label) = directions.single_label(inbound, outbound) {
+        return label;
+    }
+    match (inbound, outbound) {
+        (true, true) => "✓ 雙向可用".green(),
+        (true, false) => "↓ 只能接收".yellow(),
+        (false, true) => "↑ 只能發送".yellow(),
+        (false, false) => "✗ 不可用".red(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::probes::Banner;
+    use crate::testutil::scan_result;
+
+    fn ports() -> Vec<(PortInfo, ScanResult)> {
+        let ssh = PortInfo::new(22, "SSH", "Remote");
+        let mut sql = scan_result(false);
+        sql.latency_ms = None;
+        let long = PortInfo::new(1433, "Microsoft SQL Server Browser", "Database");
+        let mut web = ScanResult { inbound: true, ..scan_result(true) };
+        web.latency_ms = Some(12.34);
+        web.banner = Some(Banner {
+            probe: "GetRequest".to_string(),
+            service: Some("nginx".to_string()),
+            version: Some("1.25.3 (Ubuntu) built with the stream, mail and geoip modules".to_string()),
+            text: String::new(),
+            encoding: None,
+            raw: None,
+        });
+        vec![(ssh, web), (long, sql)]
+    }
+
+    fn render(width: usize) -> Vec<String> {
+        let view = ResultView { layout: Layout::new(width), ..Default::default() };
+        let ports = ports();
+        let columns = Columns::measure(ports.iter().map(|(info, _)| info), &view.layout);
+        ports.iter().flat_map(|(info, result)| port_lines(info, result, "", &view, &columns)).collect()
+    }
+
+    #[test]
+    fn wide_terminals_size_columns_from_the_data() {
+        assert_eq!(
+            render(120),
+            vec![
+                "Port   22 (SSH                         ): ✓ 雙向可用  12.3ms",
+                "    nginx 1.25.3 (Ubuntu) built with the stream, mail and geoip modules",
+                "Port 1433 (Microsoft SQL Server Browser): ✗ 不可用",
+            ]
+        );
+    }
+
+    #[test]
+    fn medium_terminals_truncate_services_and_banners() {
+        assert_eq!(
+            render(64),
+            vec![
+                "Port   22 (SSH                     ): ✓ 雙向可用  12.3ms",
+                "    nginx 1.25.3 (Ubuntu) built with the stream, mail and geoip…",
+                "Port 1433 (Microsoft SQL Server Br…): ✗ 不可用",
+            ]
+        );
+    }
+
+    #[test]
+    fn narrow_terminals_stack_each_port() {
+        assert_eq!(
+            render(32),
+            vec![
+                "Port 22 (SSH)",
+                "  ✓ 雙向可用  12.3ms",
+                "    nginx 1.25.3 (Ubuntu) built…",
+                "Port 1433 (Microsoft SQL Server…",
+                "  ✗ 不可用",
+            ]
+        );
+        assert!(render(layout::NARROWEST).iter().all(|line| layout::display_width(line) <= layout::NARROWEST));
+    }
+
+    #[test]
+    fn proxy_denials_are_shown_apart_from_unreachable_ports() {
+        let view = ResultView::default();
+        let info = PortInfo::new(5432, "PostgreSQL", "Database");
+        let mut denied = scan_result(false);
+        denied.failure = Some(Failure::ProxyDenied { status: 403 });
+        denied.codes = vec![errors::ErrorCode::ProxyDenied];
+        let columns = Columns::measure([&info], &view.layout);
+        assert_eq!(port_lines(&info, &denied, "", &view, &columns), vec!["Port 5432 (PostgreSQL): ✗ 不可用  proxy 拒絕 (403) [E3006]"]);
+    }
+}
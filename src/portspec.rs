@@ -0,0 +1,76 @@
+use std::collections::BTreeSet;
+use std::ops::RangeInclusive;
+
+// 端口寫法的共用解析：命令列、設定檔、端口資料庫與清單檔都用同一套規則
+// 呼叫端再自行加上來源 (例如設定檔區段) 的說明
+
+// 單一端口，1-65535
+pub fn parse_port(s: &str) -> Result<u16, String> {
+    match s.trim().parse::<u16>() {
+        Ok(0) | Err(_) => Err(format!("無效的端口: {}", s.trim())),
+        Ok(port) => Ok(port),
+    }
+}
+
+// "22" 或 "8000-8100"；起點不可大於終點
+pub fn parse_range(s: &str) -> Result<RangeInclusive<u16>, String> {
+    match s.split_once('-') {
+        Some((start, end)) => {
+            let (start, end) = (parse_port(start)?, parse_port(end)?);
+            if start > end {
+                return Err(format!("無效的端口範圍: {}", s.trim()));
+            }
+            Ok(start..=end)
+        }
+        None => {
+            let port = parse_port(s)?;
+            Ok(port..=port)
+        }
+    }
+}
+
+// 以逗號分隔的端口與範圍，例如 "22,80,8000-8100"
+pub fn parse_list(spec: &str) -> Result<BTreeSet<u16>, String> {
+    let mut ports = BTreeSet::new();
+    for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        ports.extend(parse_range(item)?);
+    }
+
+    if ports.is_empty() {
+        return Err("沒有指定任何端口".to_string());
+    }
+    Ok(ports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_ports() {
+        assert_eq!(parse_port(" 22 "), Ok(22));
+        assert_eq!(parse_port("65535"), Ok(65535));
+        for bad in ["0", "65536", "-1", "", "ssh", "22/tcp"] {
+            assert!(parse_port(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_range("80"), Ok(80..=80));
+        assert_eq!(parse_range("8000 - 8100"), Ok(8000..=8100));
+        assert_eq!(parse_range("1-65535"), Ok(1..=65535));
+        assert_eq!(parse_range("100-10"), Err("無效的端口範圍: 100-10".to_string()));
+        assert_eq!(parse_range("0-10"), Err("無效的端口: 0".to_string()));
+        assert!(parse_range("10-").is_err());
+        assert!(parse_range("1-2-3").is_err());
+    }
+
+    #[test]
+    fn lists_merge_and_deduplicate() {
+        let ports = parse_list("443, 80,79-81,,443").unwrap();
+        assert_eq!(ports.into_iter().collect::<Vec<_>>(), vec![79, 80, 81, 443]);
+        assert_eq!(parse_list(" , "), Err("沒有指定任何端口".to_string()));
+        assert!(parse_list("22,x").is_err());
+    }
+}
@@ -0,0 +1,797 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, ErrorKind, Read, Write};
+use std::mem::MaybeUninit;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddrV4};
+use std::sync::Arc;
+use std::time::Duration;
+use colored::*;
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+
+// 主程序以這個隱藏的第一個參數啟動輔助程序；不經過 clap 與設定檔
+pub const HELPER_ARG: &str = "__raw-helper";
+
+// 每個訊息的標頭：種類、通道、內容長度 (u32 big-endian)
+const HEADER_LEN: usize = 6;
+
+// 擷取封包最大 64 KiB，加上來源資訊
+const MAX_PAYLOAD: usize = 65536 + 64;
+
+// 輔助程序 → 主程序：通道開啟結果，內容為空表示成功，否則為錯誤訊息
+const READY: u8 = 1;
+// 主程序 → 輔助程序：IPv4 目的位址 + TCP 區段
+const SEND: u8 = 2;
+// 輔助程序 → 主程序：來源資訊 + 收到的封包
+const PACKET: u8 = 3;
+
+// 來源資訊的種類
+const ORIGIN_UNKNOWN: u8 = 0;
+const ORIGIN_V4: u8 = 4;
+const ORIGIN_V6: u8 = 6;
+const ORIGIN_LINK: u8 = 7;
+
+// TCP 旗標：輔助程序只轉送 SYN 與 RST
+const SYN: u8 = 0x02;
+const RST: u8 = 0x04;
+
+// 每個通道暫存的封包數；主程序來不及處理時丟棄新封包，不無限制地累積
+const INBOX_CAPACITY: usize = 4096;
+
+// 等待使用者輸入密碼並啟動輔助程序的時間
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(120);
+
+// 輔助程序連線後回報各通道開啟結果的時間
+const READY_TIMEOUT: Duration = Duration::from_secs(10);
+
+// 需要原始 socket 的功能各自使用的通道
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Channel {
+    // --syn 的原始 TCP (IPv4)
+    Tcp,
+    // ICMP 錯誤監視
+    Icmp4,
+    Icmp6,
+    // --pcap 的 AF_PACKET 擷取 (只支援 Linux)
+    Capture,
+}
+
+impl Channel {
+    const ALL: [Channel; 4] = [Channel::Tcp, Channel::Icmp4, Channel::Icmp6, Channel::Capture];
+
+    fn id(self) -> u8 {
+        match self {
+            Channel::Tcp => 1,
+            Channel::Icmp4 => 2,
+            Channel::Icmp6 => 3,
+            Channel::Capture => 4,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        Channel::ALL.into_iter().find(|channel| channel.id() == id)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Channel::Tcp => "tcp",
+            Channel::Icmp4 => "icmp4",
+            Channel::Icmp6 => "icmp6",
+            Channel::Capture => "capture",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Channel::ALL.into_iter().find(|channel| channel.name() == name)
+    }
+
+    // 接收執行緒檢查使用者是否仍在的間隔，與各模組原本的設定相同
+    fn poll_interval(self) -> Duration {
+        match self {
+            Channel::Capture => Duration::from_millis(200),
+            _ => Duration::from_millis(500),
+        }
+    }
+
+    // 直接開啟 (需要 root 或 CAP_NET_RAW)
+    fn open_socket(self) -> io::Result<Socket> {
+        let socket = match self {
+            Channel::Tcp => Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::TCP))?,
+            Channel::Icmp4 => Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4))?,
+            Channel::Icmp6 => Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::ICMPV6))?,
+            Channel::Capture => open_capture()?,
+        };
+        socket.set_read_timeout(Some(self.poll_interval()))?;
+        Ok(socket)
+    }
+}
+
+// AF_PACKET 的 SOCK_DGRAM 由核心移除連結層標頭，收到的內容即為 IP 封包
+#[cfg(target_os = "linux")]
+fn open_capture() -> io::Result<Socket> {
+    let protocol = Protocol::from(i32::from((libc::ETH_P_ALL as u16).to_be()));
+    Socket::new(Domain::PACKET, Type::DGRAM, Some(protocol))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_capture() -> io::Result<Socket> {
+    Err(io::Error::new(ErrorKind::Unsupported, "封包擷取只支援 Linux"))
+}
+
+// AF_PACKET 收到的連結層資訊
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Link {
+    pub ethertype: u16,
+    pub hatype: u16,
+    // 本機送出的封包 (PACKET_OUTGOING)
+    pub outgoing: bool,
+}
+
+// 收到的封包來自哪裡
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    Unknown,
+    Addr(IpAddr),
+    Link(Link),
+}
+
+#[cfg(target_os = "linux")]
+fn link_of(from: &SockAddr) -> Origin {
+    // recv_from 在 AF_PACKET 上填入的是 sockaddr_ll
+    let link = unsafe { &*from.as_ptr().cast::<libc::sockaddr_ll>() };
+    Origin::Link(Link {
+        ethertype: u16::from_be(link.sll_protocol),
+        hatype: link.sll_hatype,
+        outgoing: link.sll_pkttype == libc::PACKET_OUTGOING,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn link_of(_from: &SockAddr) -> Origin {
+    Origin::Unknown
+}
+
+// 從本機開啟的 socket 接收
+fn recv_local(socket: &Socket, channel: Channel, buf: &mut [u8]) -> io::Result<(usize, Origin)> {
+    // recv_from 只寫入緩衝區，不讀取原本的內容
+    let uninit = unsafe { &mut *(buf as *mut [u8] as *mut [MaybeUninit<u8>]) };
+    let (len, from) = socket.recv_from(uninit)?;
+    let origin = match channel {
+        Channel::Capture => link_of(&from),
+        _ => from.as_socket().map_or(Origin::Unknown, |addr| Origin::Addr(addr.ip())),
+    };
+    Ok((len.min(buf.len()), origin))
+}
+
+// 原始 socket：本機直接開啟，或經由輔助程序轉送
+#[derive(Debug)]
+pub enum RawSocket {
+    Local { socket: Socket, channel: Channel },
+    Relayed { helper: Arc<Helper>, channel: Channel },
+}
+
+impl RawSocket {
+    // 有輔助程序時使用它開啟的通道，否則本機開啟；失敗時回傳 io 錯誤，由呼叫端說明需要的權限
+    pub fn open(channel: Channel, helper: Option<&Arc<Helper>>) -> io::Result<Self> {
+        match helper {
+            Some(helper) => {
+                helper.status(channel)?;
+                Ok(RawSocket::Relayed { helper: helper.clone(), channel })
+            }
+            None => Ok(RawSocket::Local { socket: channel.open_socket()?, channel }),
+        }
+    }
+
+    // 送出 TCP 區段 (只用於 Tcp 通道)
+    pub fn send_to(&self, segment: &[u8], dest: Ipv4Addr) -> io::Result<()> {
+        match self {
+            RawSocket::Local { socket, .. } => socket.send_to(segment, &SockAddr::from(SocketAddrV4::new(dest, 0))).map(|_| ()),
+            RawSocket::Relayed { helper, channel } => helper.send(*channel, dest, segment),
+        }
+    }
+
+    // 接收一個封包；逾時回傳 WouldBlock 或 TimedOut，輔助程序結束時回傳其他錯誤
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<(usize, Origin)> {
+        match self {
+            RawSocket::Local { socket, channel } => recv_local(socket, *channel, buf),
+            RawSocket::Relayed { helper, channel } => helper.recv(*channel, buf),
+        }
+    }
+}
+
+// 協定的一個訊息
+#[derive(Debug, PartialEq, Eq)]
+struct Frame {
+    kind: u8,
+    channel: Channel,
+    payload: Vec<u8>,
+}
+
+fn write_frame(out: &mut impl Write, frame: &Frame) -> io::Result<()> {
+    let mut message = Vec::with_capacity(HEADER_LEN + frame.payload.len());
+    message.push(frame.kind);
+    message.push(frame.channel.id());
+    message.extend_from_slice(&(frame.payload.len() as u32).to_be_bytes());
+    message.extend_from_slice(&frame.payload);
+    out.write_all(&message)
+}
+
+// 對方正常關閉時回傳 None；長度超過上限或通道未知時視為協定錯誤
+fn read_frame(input: &mut impl Read) -> io::Result<Option<Frame>> {
+    let mut header = [0u8; HEADER_LEN];
+    match input.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let invalid = |message: &str| io::Error::new(ErrorKind::InvalidData, message.to_string());
+    let channel = Channel::from_id(header[1]).ok_or_else(|| invalid("未知的通道"))?;
+    let len = u32::from_be_bytes([header[2], header[3], header[4], header[5]]) as usize;
+    if len > MAX_PAYLOAD {
+        return Err(invalid("訊息過長"));
+    }
+    let mut payload = vec![0u8; len];
+    input.read_exact(&mut payload)?;
+    Ok(Some(Frame { kind: header[0], channel, payload }))
+}
+
+fn encode_packet(origin: Origin, packet: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(packet.len() + 17);
+    match origin {
+        Origin::Unknown => payload.push(ORIGIN_UNKNOWN),
+        Origin::Addr(IpAddr::V4(addr)) => {
+            payload.push(ORIGIN_V4);
+            payload.extend_from_slice(&addr.octets());
+        }
+        Origin::Addr(IpAddr::V6(addr)) => {
+            payload.push(ORIGIN_V6);
+            payload.extend_from_slice(&addr.octets());
+        }
+        Origin::Link(link) => {
+            payload.push(ORIGIN_LINK);
+            payload.extend_from_slice(&link.ethertype.to_be_bytes());
+            payload.extend_from_slice(&link.hatype.to_be_bytes());
+            payload.push(u8::from(link.outgoing));
+        }
+    }
+    payload.extend_from_slice(packet);
+    payload
+}
+
+fn decode_packet(payload: &[u8]) -> Option<(Origin, &[u8])> {
+    let (&tag, rest) = payload.split_first()?;
+    Some(match tag {
+        ORIGIN_UNKNOWN => (Origin::Unknown, rest),
+        ORIGIN_V4 => (Origin::Addr(IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(rest.get(..4)?).ok()?))), &rest[4..]),
+        ORIGIN_V6 => (Origin::Addr(IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(rest.get(..16)?).ok()?))), &rest[16..]),
+        ORIGIN_LINK => {
+            let link = rest.get(..5)?;
+            let origin = Origin::Link(Link {
+                ethertype: u16::from_be_bytes([link[0], link[1]]),
+                hatype: u16::from_be_bytes([link[2], link[3]]),
+                outgoing: link[4] != 0,
+            });
+            (origin, &rest[5..])
+        }
+        _ => return None,
+    })
+}
+
+fn encode_send(dest: Ipv4Addr, segment: &[u8]) -> Vec<u8> {
+    let mut payload = dest.octets().to_vec();
+    payload.extend_from_slice(segment);
+    payload
+}
+
+fn decode_send(payload: &[u8]) -> Option<(Ipv4Addr, &[u8])> {
+    let dest = Ipv4Addr::from(<[u8; 4]>::try_from(payload.get(..4)?).ok()?);
+    Some((dest, &payload[4..]))
+}
+
+// 輔助程序只送出不含資料的 SYN 或 RST 標頭，不成為任意封包的送出管道
+fn allowed_segment(segment: &[u8]) -> bool {
+    if !(20..=60).contains(&segment.len()) {
+        return false;
+    }
+    let header_len = usize::from(segment[12] >> 4) * 4;
+    header_len == segment.len() && segment[13] & (SYN | RST) != 0
+}
+
+// 輔助程序的連線；釋放時關閉連線，輔助程序隨之結束
+#[derive(Debug)]
+pub struct Helper {
+    #[cfg(unix)]
+    writer: std::sync::Mutex<std::os::unix::net::UnixStream>,
+    #[cfg(unix)]
+    inboxes: HashMap<Channel, std::sync::Mutex<std::sync::mpsc::Receiver<Vec<u8>>>>,
+    #[cfg(unix)]
+    child: Option<std::process::Child>,
+    opened: BTreeMap<Channel, Result<(), String>>,
+}
+
+impl Helper {
+    // 通道在輔助程序中是否開啟成功
+    fn status(&self, channel: Channel) -> io::Result<()> {
+        match self.opened.get(&channel) {
+            Some(Ok(())) => Ok(()),
+            Some(Err(e)) => Err(io::Error::new(ErrorKind::PermissionDenied, format!("輔助程序無法開啟 {}: {}", channel.name(), e))),
+            None => Err(io::Error::new(ErrorKind::NotConnected, format!("輔助程序沒有開啟 {}", channel.name()))),
+        }
+    }
+
+    // 成功開啟的通道
+    pub fn channels(&self) -> Vec<Channel> {
+        self.opened.iter().filter(|(_, status)| status.is_ok()).map(|(channel, _)| *channel).collect()
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::collections::{BTreeMap, HashMap};
+    use std::env;
+    use std::fs::{self, DirBuilder};
+    use std::io::{self, ErrorKind, IsTerminal, Write};
+    use std::net::{Ipv4Addr, SocketAddrV4};
+    use std::os::unix::fs::DirBuilderExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+    use std::process::{Child, Command, Stdio};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::{self, RecvTimeoutError, SyncSender};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+    use socket2::{SockAddr, Socket};
+    use super::*;
+
+    // 只有目前使用者能進入的暫存目錄，放置輔助程序連回的 socket；釋放時刪除
+    struct SocketDir(PathBuf);
+
+    impl SocketDir {
+        fn create() -> io::Result<Self> {
+            let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or_default();
+            let path = env::temp_dir().join(format!("r1-helper-{}-{}", std::process::id(), nanos));
+            DirBuilder::new().mode(0o700).create(&path)?;
+            Ok(SocketDir(path))
+        }
+    }
+
+    impl Drop for SocketDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn on_path(program: &str) -> bool {
+        env::var_os("PATH").is_some_and(|paths| env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+    }
+
+    // 終端機上先詢問再以 sudo 啟動；沒有終端機時由 pkexec 的 polkit 代理程式確認
+    fn launcher() -> Result<Command, String> {
+        if io::stdin().is_terminal() && on_path("sudo") {
+            eprint!("--privileged-helper 會以 sudo 啟動只處理原始 socket 的輔助程序 (開啟 socket 後即降回目前使用者的權限)。繼續? [y/N] ");
+            io::stderr().flush().map_err(|e| e.to_string())?;
+            let mut answer = String::new();
+            io::stdin().read_line(&mut answer).map_err(|e| e.to_string())?;
+            if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+                return Err("已取消".to_string());
+            }
+            let mut command = Command::new("sudo");
+            command.arg("--");
+            return Ok(command);
+        }
+        match on_path("pkexec") {
+            true => Ok(Command::new("pkexec")),
+            false => Err("找不到 sudo 或 pkexec".to_string()),
+        }
+    }
+
+    // 等待輔助程序連回；它在驗證失敗或被取消時會先結束
+    fn accept(listener: &UnixListener, child: &mut Child) -> Result<UnixStream, String> {
+        let deadline = Instant::now() + STARTUP_TIMEOUT;
+        loop {
+            match listener.accept() {
+                Ok((stream, _)) => return Ok(stream),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.to_string()),
+            }
+            if let Ok(Some(status)) = child.try_wait() {
+                return Err(format!("輔助程序未啟動 ({})", status));
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                return Err("等待輔助程序逾時".to_string());
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    impl Helper {
+        // 以 sudo 或 pkexec 啟動輔助程序並等待它回報各通道的開啟結果
+        pub fn spawn(channels: &[Channel]) -> Result<Self, String> {
+            let dir = SocketDir::create().map_err(|e| format!("無法建立暫存目錄: {}", e))?;
+            let path = dir.0.join("raw.sock");
+            let listener = UnixListener::bind(&path).map_err(|e| e.to_string())?;
+            listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+
+            let exe = env::current_exe().map_err(|e| e.to_string())?;
+            let names: Vec<&str> = channels.iter().map(|channel| channel.name()).collect();
+            // 輔助程序不讀取終端機輸入，避免 sudo 轉送按鍵而影響互動按鍵
+            let mut child = launcher()?
+                .arg(exe)
+                .arg(HELPER_ARG)
+                .arg(&path)
+                .arg(unsafe { libc::getuid() }.to_string())
+                .arg(unsafe { libc::getgid() }.to_string())
+                .arg(names.join(","))
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .spawn()
+                .map_err(|e| e.to_string())?;
+            let stream = accept(&listener, &mut child)?;
+            drop(dir);
+            Helper::attach(stream, channels, Some(child))
+        }
+
+        // 讀取各通道的開啟結果後，由背景執行緒把收到的封包分到各通道
+        pub(super) fn attach(stream: UnixStream, channels: &[Channel], child: Option<Child>) -> Result<Self, String> {
+            stream.set_nonblocking(false).map_err(|e| e.to_string())?;
+            stream.set_read_timeout(Some(READY_TIMEOUT)).map_err(|e| e.to_string())?;
+            let mut reader = stream.try_clone().map_err(|e| e.to_string())?;
+            let mut opened = BTreeMap::new();
+            while opened.len() < channels.len() {
+                let frame = read_frame(&mut reader)
+                    .map_err(|e| format!("輔助程序回應錯誤: {}", e))?
+                    .ok_or("輔助程序提前結束")?;
+                if frame.kind != READY || !channels.contains(&frame.channel) {
+                    return Err("輔助程序回應錯誤".to_string());
+                }
+                let status = match frame.payload.is_empty() {
+                    true => Ok(()),
+                    false => Err(String::from_utf8_lossy(&frame.payload).into_owned()),
+                };
+                opened.insert(frame.channel, status);
+            }
+            reader.set_read_timeout(None).map_err(|e| e.to_string())?;
+
+            let mut inboxes = HashMap::new();
+            let mut senders = HashMap::new();
+            for channel in opened.iter().filter(|(_, status)| status.is_ok()).map(|(channel, _)| *channel) {
+                let (tx, rx) = mpsc::sync_channel(INBOX_CAPACITY);
+                senders.insert(channel, tx);
+                inboxes.insert(channel, Mutex::new(rx));
+            }
+            std::thread::Builder::new()
+                .name("raw-helper".to_string())
+                .spawn(move || demux(reader, senders))
+                .map_err(|e| e.to_string())?;
+            Ok(Helper { writer: Mutex::new(stream), inboxes, child, opened })
+        }
+
+        pub(super) fn send(&self, channel: Channel, dest: Ipv4Addr, segment: &[u8]) -> io::Result<()> {
+            if channel != Channel::Tcp {
+                return Err(io::Error::new(ErrorKind::Unsupported, "只有 tcp 通道可以送出"));
+            }
+            let frame = Frame { kind: SEND, channel, payload: encode_send(dest, segment) };
+            let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+            write_frame(&mut *writer, &frame)
+        }
+
+        pub(super) fn recv(&self, channel: Channel, buf: &mut [u8]) -> io::Result<(usize, Origin)> {
+            let inbox = self.inboxes.get(&channel).ok_or(ErrorKind::NotConnected)?;
+            let inbox = inbox.lock().unwrap_or_else(|e| e.into_inner());
+            let deadline = Instant::now() + channel.poll_interval();
+            loop {
+                let wait = deadline.saturating_duration_since(Instant::now());
+                let payload = match inbox.recv_timeout(wait) {
+                    Ok(payload) => payload,
+                    Err(RecvTimeoutError::Timeout) => return Err(ErrorKind::TimedOut.into()),
+                    Err(RecvTimeoutError::Disconnected) => return Err(ErrorKind::BrokenPipe.into()),
+                };
+                // 格式錯誤的封包略過
+                if let Some((origin, packet)) = decode_packet(&payload) {
+                    let len = packet.len().min(buf.len());
+                    buf[..len].copy_from_slice(&packet[..len]);
+                    return Ok((len, origin));
+                }
+            }
+        }
+    }
+
+    impl Drop for Helper {
+        fn drop(&mut self) {
+            if let Ok(writer) = self.writer.get_mut() {
+                let _ = writer.shutdown(std::net::Shutdown::Both);
+            }
+            // 輔助程序讀到連線關閉即結束，不強制終止已降權的程序
+            if let Some(child) = &mut self.child {
+                let deadline = Instant::now() + Duration::from_secs(2);
+                while Instant::now() < deadline && matches!(child.try_wait(), Ok(None)) {
+                    std::thread::sleep(Duration::from_millis(20));
+                }
+            }
+        }
+    }
+
+    // 主程序的分派執行緒：通道暫存已滿時丟棄封包；連線中斷後結束，各通道的接收端隨之收到錯誤
+    fn demux(mut reader: UnixStream, senders: HashMap<Channel, SyncSender<Vec<u8>>>) {
+        while let Ok(Some(frame)) = read_frame(&mut reader) {
+            if frame.kind != PACKET {
+                continue;
+            }
+            if let Some(tx) = senders.get(&frame.channel) {
+                let _ = tx.try_send(frame.payload);
+            }
+        }
+    }
+
+    // 降回呼叫者的使用者與群組；root 的能力 (capabilities) 在 setuid 後全部清除
+    fn drop_privileges(uid: u32, gid: u32) -> io::Result<()> {
+        // 只呼叫權限相關的系統呼叫，沒有傳入需要保持有效的指標
+        unsafe {
+            if libc::setgroups(0, std::ptr::null()) != 0 || libc::setgid(gid) != 0 || libc::setuid(uid) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if libc::setuid(0) == 0 || libc::geteuid() != uid || libc::getegid() != gid {
+                return Err(io::Error::other("無法放棄 root 權限"));
+            }
+            #[cfg(target_os = "linux")]
+            if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    // 輔助程序端：回報各通道開啟結果，轉送收到的封包並送出主程序要求的 SYN/RST
+    pub(super) fn relay(stream: UnixStream, sockets: Vec<(Channel, Result<Socket, String>)>) -> io::Result<()> {
+        let writer = Arc::new(Mutex::new(stream.try_clone()?));
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut tcp = None;
+        for (channel, socket) in sockets {
+            let payload = match &socket {
+                Ok(_) => Vec::new(),
+                Err(e) => e.clone().into_bytes(),
+            };
+            write_frame(&mut *writer.lock().unwrap_or_else(|e| e.into_inner()), &Frame { kind: READY, channel, payload })?;
+            let Ok(socket) = socket else {
+                continue;
+            };
+            let socket = Arc::new(socket);
+            if channel == Channel::Tcp {
+                tcp = Some(socket.clone());
+            }
+            let (writer, stop) = (writer.clone(), stop.clone());
+            std::thread::Builder::new()
+                .name(format!("raw-helper-{}", channel.name()))
+                .spawn(move || forward(&socket, channel, &writer, &stop))?;
+        }
+
+        let mut reader = stream;
+        let result = loop {
+            let frame = match read_frame(&mut reader) {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break Ok(()),
+                // 主程序結束時可能還有未讀的封包，連線以 reset 關閉
+                Err(e) if e.kind() == ErrorKind::ConnectionReset => break Ok(()),
+                Err(e) => break Err(e),
+            };
+            if frame.kind != SEND || frame.channel != Channel::Tcp {
+                continue;
+            }
+            let (Some(socket), Some((dest, segment))) = (&tcp, decode_send(&frame.payload)) else {
+                continue;
+            };
+            if allowed_segment(segment) {
+                let _ = socket.send_to(segment, &SockAddr::from(SocketAddrV4::new(dest, 0)));
+            }
+        };
+        stop.store(true, Ordering::Relaxed);
+        result
+    }
+
+    fn forward(socket: &Socket, channel: Channel, writer: &Mutex<UnixStream>, stop: &AtomicBool) {
+        let mut buf = vec![0u8; 65536];
+        while !stop.load(Ordering::Relaxed) {
+            let (len, origin) = match recv_local(socket, channel, &mut buf) {
+                Ok(received) => received,
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) => continue,
+                Err(_) => return,
+            };
+            let frame = Frame { kind: PACKET, channel, payload: encode_packet(origin, &buf[..len]) };
+            if write_frame(&mut *writer.lock().unwrap_or_else(|e| e.into_inner()), &frame).is_err() {
+                return;
+            }
+        }
+    }
+
+    fn parse_args(args: &[String]) -> Result<(PathBuf, u32, u32, Vec<Channel>), String> {
+        let [path, uid, gid, channels] = args else {
+            return Err("參數錯誤".to_string());
+        };
+        let uid: u32 = uid.parse().map_err(|_| "uid 錯誤")?;
+        let gid: u32 = gid.parse().map_err(|_| "gid 錯誤")?;
+        // 不以 root 身分繼續執行
+        if uid == 0 {
+            return Err("呼叫者已是 root，不需要輔助程序".to_string());
+        }
+        let channels = channels
+            .split(',')
+            .map(|name| Channel::from_name(name).ok_or_else(|| format!("未知的通道: {}", name)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((PathBuf::from(path), uid, gid, channels))
+    }
+
+    // 開啟 socket、降權後才連回主程序；之後只轉送封包
+    fn serve_with(args: &[String]) -> Result<(), String> {
+        let (path, uid, gid, channels) = parse_args(args)?;
+        let sockets: Vec<_> = channels
+            .into_iter()
+            .map(|channel| (channel, channel.open_socket().map_err(|e| e.to_string())))
+            .collect();
+        drop_privileges(uid, gid).map_err(|e| format!("無法降低權限: {}", e))?;
+        let stream = UnixStream::connect(Path::new(&path)).map_err(|e| format!("無法連線到 {}: {}", path.display(), e))?;
+        relay(stream, sockets).map_err(|e| e.to_string())
+    }
+
+    pub(super) fn serve(args: &[String]) -> ! {
+        match serve_with(args) {
+            Ok(()) => std::process::exit(0),
+            Err(e) => {
+                eprintln!("原始 socket 輔助程序: {}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+impl Helper {
+    pub fn spawn(_channels: &[Channel]) -> Result<Self, String> {
+        Err("--privileged-helper 目前只支援 Linux 與其他 Unix 系統".to_string())
+    }
+
+    fn send(&self, _channel: Channel, _dest: Ipv4Addr, _segment: &[u8]) -> io::Result<()> {
+        Err(ErrorKind::Unsupported.into())
+    }
+
+    fn recv(&self, _channel: Channel, _buf: &mut [u8]) -> io::Result<(usize, Origin)> {
+        Err(ErrorKind::Unsupported.into())
+    }
+}
+
+// 輔助程序的進入點 (main 在解析參數前呼叫)
+pub fn serve(args: &[String]) -> ! {
+    #[cfg(unix)]
+    unix::serve(args);
+    #[cfg(not(unix))]
+    {
+        let _ = args;
+        eprintln!("原始 socket 輔助程序只支援 Unix 系統");
+        std::process::exit(2);
+    }
+}
+
+// 已是 root 時直接開啟原始 socket；無法啟動時說明原因，各功能沿用不需權限的方式
+pub fn start(channels: &[Channel], quiet: bool) -> Option<Arc<Helper>> {
+    #[cfg(unix)]
+    if unsafe { libc::geteuid() } == 0 {
+        if !quiet {
+            println!("{}", "已具備原始 socket 權限，不需要輔助程序".dimmed());
+        }
+        return None;
+    }
+    if channels.is_empty() {
+        return None;
+    }
+    match Helper::spawn(channels) {
+        Ok(helper) => {
+            if !quiet {
+                let names: Vec<&str> = helper.channels().into_iter().map(Channel::name).collect();
+                println!("{} {}", "原始 socket 輔助程序:".bold(), names.join(", "));
+            }
+            Some(Arc::new(helper))
+        }
+        Err(e) => {
+            eprintln!("{}", format!("無法啟動原始 socket 輔助程序: {}，改用不需權限的方式", e).yellow());
+            None
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::net::UdpSocket;
+    use std::os::unix::net::UnixStream;
+    use super::*;
+
+    #[test]
+    fn frames_round_trip_and_reject_oversized_lengths() {
+        let frame = Frame { kind: PACKET, channel: Channel::Icmp6, payload: vec![1, 2, 3] };
+        let mut bytes = Vec::new();
+        write_frame(&mut bytes, &frame).unwrap();
+        assert_eq!(bytes[..HEADER_LEN], [PACKET, 3, 0, 0, 0, 3]);
+        let mut input = bytes.as_slice();
+        assert_eq!(read_frame(&mut input).unwrap(), Some(frame));
+        assert_eq!(read_frame(&mut input).unwrap(), None);
+
+        let oversized = [PACKET, 1, 0xff, 0xff, 0xff, 0xff];
+        assert_eq!(read_frame(&mut oversized.as_slice()).unwrap_err().kind(), ErrorKind::InvalidData);
+        let unknown_channel = [PACKET, 9, 0, 0, 0, 0];
+        assert_eq!(read_frame(&mut unknown_channel.as_slice()).unwrap_err().kind(), ErrorKind::InvalidData);
+        // 內容不完整
+        assert!(read_frame(&mut [PACKET, 1, 0, 0, 0, 4, 0].as_slice()).is_err());
+    }
+
+    #[test]
+    fn packet_origins_round_trip() {
+        let link = Link { ethertype: 0x86dd, hatype: 772, outgoing: true };
+        for origin in [
+            Origin::Unknown,
+            Origin::Addr("198.51.100.1".parse().unwrap()),
+            Origin::Addr("2001:db8::1".parse().unwrap()),
+            Origin::Link(link),
+        ] {
+            let payload = encode_packet(origin, &[0x45, 0x00]);
+            assert_eq!(decode_packet(&payload), Some((origin, &[0x45, 0x00][..])));
+        }
+        assert_eq!(decode_packet(&[ORIGIN_V6, 0x20, 0x01]), None);
+        assert_eq!(decode_packet(&[99]), None);
+        let send = encode_send(Ipv4Addr::new(192, 0, 2, 7), &[1, 2]);
+        assert_eq!(decode_send(&send), Some((Ipv4Addr::new(192, 0, 2, 7), &[1, 2][..])));
+    }
+
+    #[test]
+    fn only_bare_syn_or_rst_headers_are_relayed() {
+        let mut syn = vec![0u8; 24];
+        syn[12] = 6 << 4;
+        syn[13] = SYN;
+        assert!(allowed_segment(&syn));
+        let mut rst = vec![0u8; 20];
+        rst[12] = 5 << 4;
+        rst[13] = RST;
+        assert!(allowed_segment(&rst));
+        // 帶資料、只有 ACK 或長度不符
+        let mut data = syn.clone();
+        data.extend_from_slice(b"GET /");
+        assert!(!allowed_segment(&data));
+        let mut ack = rst.clone();
+        ack[13] = 0x10;
+        assert!(!allowed_segment(&ack));
+        assert!(!allowed_segment(&syn[..19]));
+    }
+
+    #[test]
+    fn relayed_packets_reach_the_channel_and_failures_are_reported() {
+        // 以 UDP socket 代替原始 socket，不需要權限
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+        let local = receiver.local_addr().unwrap();
+        let (parent, child) = UnixStream::pair().unwrap();
+        let sockets = vec![(Channel::Icmp4, Ok(Socket::from(receiver))), (Channel::Tcp, Err("權限不足".to_string()))];
+        let helper = std::thread::spawn(move || unix::relay(child, sockets));
+
+        let helper_conn = Arc::new(Helper::attach(parent, &[Channel::Icmp4, Channel::Tcp], None).unwrap());
+        assert_eq!(helper_conn.channels(), vec![Channel::Icmp4]);
+        let error = RawSocket::open(Channel::Tcp, Some(&helper_conn)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+        assert!(error.to_string().contains("權限不足"));
+
+        let socket = RawSocket::open(Channel::Icmp4, Some(&helper_conn)).unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender.send_to(b"probe", local).unwrap();
+        let mut buf = [0u8; 64];
+        let (len, origin) = loop {
+            match socket.recv(&mut buf) {
+                Err(e) if e.kind() == ErrorKind::TimedOut => continue,
+                received => break received.unwrap(),
+            }
+        };
+        assert_eq!(&buf[..len], b"probe");
+        assert_eq!(origin, Origin::Addr(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+        // 只有 tcp 通道可以送出
+        assert_eq!(socket.send_to(&[0; 20], Ipv4Addr::LOCALHOST).unwrap_err().kind(), ErrorKind::Unsupported);
+
+        // 主程序釋放連線後輔助程序結束
+        drop(socket);
+        drop(helper_conn);
+        assert!(helper.join().unwrap().is_ok());
+    }
+}
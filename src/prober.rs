@@ -0,0 +1,426 @@
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::checks;
+use crate::context::ScanContext;
+use crate::errors::ErrorCode;
+use crate::icmp::IcmpMonitor;
+use crate::pool::{PoolStats, SocketPool};
+use crate::probes::{self, Banner, ProbeLibrary};
+use crate::scanner::{self, Outbound};
+use crate::srcport::{self, SourceAttempt};
+use crate::verify::{self, Reprobe};
+use crate::vhost::{self, VhostResult};
+use crate::PortInfo;
+
+pub type ProbeFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+// UDP 請求收到的回應 (來源, 內容)
+pub type UdpReplies = io::Result<Vec<(SocketAddr, Vec<u8>)>>;
+
+// 掃描對網路的操作；實際掃描使用 NetProber，測試可換成 fake::ScriptedProber
+// 代理 (SOCKS/Tor) 與 --syn 是不同的傳輸方式，不經過這裡
+pub trait Prober: Send + Sync + fmt::Debug {
+    // 以 TCP 連線測試出站；有 ICMP 監聽時比對不可達錯誤
+    fn connect<'a>(&'a self, dest: IpAddr, port: u16, limit: Duration, icmp: Option<&'a IcmpMonitor>) -> ProbeFuture<'a, Outbound>;
+
+    // 能否在本機的這個位址綁定此端口 (入站測試)，失敗時回傳原因的錯誤代碼
+    fn bind(&self, port: u16, addr: IpAddr) -> ProbeFuture<'_, Result<(), ErrorCode>>;
+
+    // 對可連線的端口送出探測並比對橫幅
+    fn banner<'a>(&'a self, library: &'a ProbeLibrary, dest: IpAddr, port: u16, limit: Duration) -> ProbeFuture<'a, Option<Banner>>;
+
+    // 送出一個 UDP 請求並收集回應 (服務檢查)
+    fn udp_exchange<'a>(
+        &'a self,
+        dest: IpAddr,
+        port: u16,
+        payload: &'a [u8],
+        wait: Duration,
+        linger: Duration,
+    ) -> ProbeFuture<'a, UdpReplies>;
+
+    // 覆核的重新連線：較長的逾時，可指定本機來源位址
+    fn reprobe(&self, dest: SocketAddr, limit: Duration, source: Option<IpAddr>) -> ProbeFuture<'_, Reprobe>;
+
+    // 以各虛擬主機名稱對 Web 端口送出 HTTP(S) 請求
+    fn vhosts<'a>(&'a self, dest: IpAddr, port: &'a PortInfo, names: &'a [String]) -> ProbeFuture<'a, Vec<VhostResult>>;
+
+    // --test-source-ports：從指定的本機來源端口連線；經由代理或跳板時無法指定來源端口
+    fn connect_from(&self, _dest: SocketAddr, _source_port: u16, _limit: Duration) -> ProbeFuture<'_, SourceAttempt> {
+        Box::pin(async { Err(ErrorCode::BindFailed) })
+    }
+
+    // socket 池的統計 (--profile-scan)；沒有 socket 池時為 None
+    fn socket_stats(&self) -> Option<PoolStats> {
+        None
+    }
+}
+
+// 實際的網路操作
+#[derive(Debug)]
+pub struct NetProber {
+    // 重複使用出站探測的 socket (Linux；--no-socket-reuse 時為 None)
+    sockets: Option<Arc<SocketPool>>,
+    // 與 ScanPlan 共用：連結本地位址的區域與 --resource-report 的計數
+    context: Arc<ScanContext>,
+}
+
+impl NetProber {
+    pub fn new(context: Arc<ScanContext>, sockets: Option<SocketPool>) -> Self {
+        NetProber {
+            sockets: sockets.map(Arc::new),
+            context,
+        }
+    }
+}
+
+impl Prober for NetProber {
+    fn connect<'a>(&'a self, dest: IpAddr, port: u16, limit: Duration, icmp: Option<&'a IcmpMonitor>) -> ProbeFuture<'a, Outbound> {
+        Box::pin(scanner::test_outbound_port(&self.context, port, dest, limit, icmp, self.sockets.as_deref()))
+    }
+
+    fn bind(&self, port: u16, addr: IpAddr) -> ProbeFuture<'_, Result<(), ErrorCode>> {
+        Box::pin(scanner::test_inbound_port(port, addr))
+    }
+
+    fn banner<'a>(&'a self, library: &'a ProbeLibrary, dest: IpAddr, port: u16, limit: Duration) -> ProbeFuture<'a, Option<Banner>> {
+        Box::pin(probes::grab(library, &self.context, dest, port, limit))
+    }
+
+    fn udp_exchange<'a>(
+        &'a self,
+        dest: IpAddr,
+        port: u16,
+        payload: &'a [u8],
+        wait: Duration,
+        linger: Duration,
+    ) -> ProbeFuture<'a, UdpReplies> {
+        Box::pin(checks::udp_exchange(&self.context, dest, port, payload, wait, linger))
+    }
+
+    fn reprobe(&self, dest: SocketAddr, limit: Duration, source: Option<IpAddr>) -> ProbeFuture<'_, Reprobe> {
+        Box::pin(verify::reprobe(dest, limit, source))
+    }
+
+    fn vhosts<'a>(&'a self, dest: IpAddr, port: &'a PortInfo, names: &'a [String]) -> ProbeFuture<'a, Vec<VhostResult>> {
+        Box::pin(vhost::probe_vhosts(&self.context, dest, port, names))
+    }
+
+    fn connect_from(&self, dest: SocketAddr, source_port: u16, limit: Duration) -> ProbeFuture<'_, SourceAttempt> {
+        Box::pin(async move {
+            let _socket = self.context.socket();
+            srcport::connect_from(dest, source_port, limit).await
+        })
+    }
+
+    fn socket_stats(&self) -> Option<PoolStats> {
+        self.sockets.as_ref().map(|pool| pool.stats())
+    }
+}
+
+// 依 (位址, 端口) 回傳預先設定結果的假網路，供測試與效能量測使用 (--features fake-net)
+// 結果在設定的延遲後回傳，搭配 tokio 暫停時間可以得到確定的排程結果
+#[cfg(any(test, feature = "fake-net"))]
+#[cfg_attr(not(test), allow(dead_code))]
+pub mod fake {
+    use std::collections::{HashMap, HashSet};
+    use std::net::{IpAddr, SocketAddr};
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use super::{ProbeFuture, Prober, UdpReplies};
+    use crate::srcport::SourceAttempt;
+    use crate::verify::Reprobe;
+    use crate::errors::ErrorCode;
+    use crate::closure::Failure;
+    use crate::icmp::IcmpMonitor;
+    use crate::limits::ScanError;
+    use crate::probes::{Banner, ProbeLibrary};
+    use crate::scanner::Outbound;
+    use crate::vhost::VhostResult;
+    use crate::PortInfo;
+
+    // 出站連線的結果
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Scripted {
+        Open,
+        // 收到 RST
+        Refused,
+        // 沒有回應，等到逾時
+        Silent,
+        // ICMP 不可達或其他網路錯誤
+        Unreachable,
+        // 掃描端錯誤 (例如 EMFILE)
+        Error(ScanError),
+        // 探測工作 panic
+        Panic,
+    }
+
+    // 單一 (位址, 端口) 的劇本
+    #[derive(Debug, Clone)]
+    pub struct Script {
+        pub outcome: Scripted,
+        // 回應前的延遲；超過探測逾時時視為逾時
+        pub latency: Duration,
+        pub banner: Option<Banner>,
+        // UDP 請求的回應
+        pub udp: Vec<Vec<u8>>,
+    }
+
+    impl Script {
+        pub fn new(outcome: Scripted, latency: Duration) -> Self {
+            Script {
+                outcome,
+                latency,
+                banner: None,
+                udp: Vec::new(),
+            }
+        }
+
+        pub fn banner(mut self, banner: Banner) -> Self {
+            self.banner = Some(banner);
+            self
+        }
+
+        pub fn udp(mut self, reply: &[u8]) -> Self {
+            self.udp.push(reply.to_vec());
+            self
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct ScriptedProber {
+        scripts: HashMap<(IpAddr, u16), Script>,
+        // 沒有設定的端口使用的結果
+        fallback: Script,
+        // 可以在本機綁定的端口 (入站測試)
+        bindable: HashSet<u16>,
+        // --test-source-ports：從特定來源端口連到某端口時改用的劇本 (模擬依來源端口過濾的防火牆)
+        sources: HashMap<(u16, u16), Script>,
+        // 無法綁定的來源端口
+        unbindable_sources: HashSet<u16>,
+        // 依呼叫順序記錄的出站探測
+        calls: Mutex<Vec<(IpAddr, u16)>>,
+        // 進行中的出站探測數，用來檢查並發上限
+        flight: Mutex<Flight>,
+    }
+
+    #[derive(Debug, Default)]
+    struct Flight {
+        total: usize,
+        peak: usize,
+        hosts: HashMap<IpAddr, (usize, usize)>,
+        // 每個探測開始時各主機進行中的探測數；只在開始時增加，因此足以算出任意主機集合的峰值
+        starts: Vec<Vec<(IpAddr, usize)>>,
+    }
+
+    // 探測結束 (包含被取消而中途丟棄) 時離開
+    struct InFlight<'a> {
+        prober: &'a ScriptedProber,
+        dest: IpAddr,
+    }
+
+    impl Drop for InFlight<'_> {
+        fn drop(&mut self) {
+            let mut flight = self.prober.flight();
+            flight.total -= 1;
+            if let Some((current, _)) = flight.hosts.get_mut(&self.dest) {
+                *current -= 1;
+            }
+        }
+    }
+
+    impl Default for ScriptedProber {
+        fn default() -> Self {
+            ScriptedProber {
+                scripts: HashMap::new(),
+                fallback: Script::new(Scripted::Refused, Duration::from_millis(1)),
+                bindable: HashSet::new(),
+                sources: HashMap::new(),
+                unbindable_sources: HashSet::new(),
+                calls: Mutex::new(Vec::new()),
+                flight: Mutex::default(),
+            }
+        }
+    }
+
+    impl ScriptedProber {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn with(mut self, dest: IpAddr, port: u16, script: Script) -> Self {
+            self.scripts.insert((dest, port), script);
+            self
+        }
+
+        pub fn fallback(mut self, script: Script) -> Self {
+            self.fallback = script;
+            self
+        }
+
+        pub fn bindable(mut self, port: u16) -> Self {
+            self.bindable.insert(port);
+            self
+        }
+
+        pub fn source_script(mut self, source_port: u16, port: u16, script: Script) -> Self {
+            self.sources.insert((source_port, port), script);
+            self
+        }
+
+        pub fn unbindable_source(mut self, source_port: u16) -> Self {
+            self.unbindable_sources.insert(source_port);
+            self
+        }
+
+        pub fn calls(&self) -> Vec<(IpAddr, u16)> {
+            self.calls.lock().unwrap_or_else(|e| e.into_inner()).clone()
+        }
+
+        // 同時進行的出站探測最多幾個
+        pub fn peak(&self) -> usize {
+            self.flight().peak
+        }
+
+        // 單一主機同時進行的出站探測最多幾個
+        pub fn host_peak(&self, dest: IpAddr) -> usize {
+            self.flight().hosts.get(&dest).map_or(0, |(_, peak)| *peak)
+        }
+
+        // 符合條件的主機 (例如同一網段) 合計同時進行的出站探測最多幾個
+        pub fn peak_among(&self, member: impl Fn(IpAddr) -> bool) -> usize {
+            let flight = self.flight();
+            let count = |start: &Vec<(IpAddr, usize)>| start.iter().filter(|(host, _)| member(*host)).map(|(_, n)| n).sum();
+            flight.starts.iter().map(count).max().unwrap_or(0)
+        }
+
+        fn flight(&self) -> std::sync::MutexGuard<'_, Flight> {
+            self.flight.lock().unwrap_or_else(|e| e.into_inner())
+        }
+
+        fn enter(&self, dest: IpAddr) -> InFlight<'_> {
+            let mut flight = self.flight();
+            flight.total += 1;
+            flight.peak = flight.peak.max(flight.total);
+            let (current, peak) = flight.hosts.entry(dest).or_default();
+            *current += 1;
+            *peak = (*peak).max(*current);
+            let start = flight.hosts.iter().filter(|(_, (n, _))| *n > 0).map(|(host, (n, _))| (*host, *n)).collect();
+            flight.starts.push(start);
+            InFlight { prober: self, dest }
+        }
+
+        // 依劇本等待並產生連線結果
+        async fn outcome(&self, dest: IpAddr, port: u16, limit: Duration) -> Outbound {
+            let _in_flight = self.enter(dest);
+            let script = self.script(dest, port).clone();
+            Self::play(&script, dest, port, limit).await
+        }
+
+        async fn play(script: &Script, dest: IpAddr, port: u16, limit: Duration) -> Outbound {
+            let timed_out = script.outcome == Scripted::Silent || script.latency >= limit;
+            tokio::time::sleep(if timed_out { limit } else { script.latency }).await;
+            if timed_out {
+                return Outbound {
+                    failure: Some(Failure::Timeout),
+                    ..Default::default()
+                };
+            }
+            match script.outcome {
+                Scripted::Open => Outbound {
+                    connected: true,
+                    ..Default::default()
+                },
+                Scripted::Refused => Outbound {
+                    failure: Some(Failure::Reset {
+                        latency_ms: script.latency.as_secs_f64() * 1000.0,
+                    }),
+                    ..Default::default()
+                },
+                Scripted::Unreachable | Scripted::Silent => Outbound {
+                    failure: Some(Failure::Unreachable),
+                    ..Default::default()
+                },
+                Scripted::Error(error) => Outbound {
+                    error: Some(error),
+                    ..Default::default()
+                },
+                Scripted::Panic => panic!("劇本中的探測 panic: {}:{}", dest, port),
+            }
+        }
+
+        fn script(&self, dest: IpAddr, port: u16) -> &Script {
+            self.scripts.get(&(dest, port)).unwrap_or(&self.fallback)
+        }
+    }
+
+    impl Prober for ScriptedProber {
+        fn connect<'a>(&'a self, dest: IpAddr, port: u16, limit: Duration, _icmp: Option<&'a IcmpMonitor>) -> ProbeFuture<'a, Outbound> {
+            self.calls.lock().unwrap_or_else(|e| e.into_inner()).push((dest, port));
+            Box::pin(self.outcome(dest, port, limit))
+        }
+
+        fn bind(&self, port: u16, _addr: IpAddr) -> ProbeFuture<'_, Result<(), ErrorCode>> {
+            let bound = self.bindable.contains(&port).then_some(()).ok_or(ErrorCode::BindAddressInUse);
+            Box::pin(async move { bound })
+        }
+
+        fn banner<'a>(&'a self, _library: &'a ProbeLibrary, dest: IpAddr, port: u16, _limit: Duration) -> ProbeFuture<'a, Option<Banner>> {
+            let banner = self.script(dest, port).banner.clone();
+            Box::pin(async move { banner })
+        }
+
+        fn udp_exchange<'a>(
+            &'a self,
+            dest: IpAddr,
+            port: u16,
+            _payload: &'a [u8],
+            wait: Duration,
+            _linger: Duration,
+        ) -> ProbeFuture<'a, UdpReplies> {
+            let script = self.script(dest, port).clone();
+            Box::pin(async move {
+                let from = SocketAddr::new(dest, port);
+                if script.udp.is_empty() {
+                    tokio::time::sleep(wait).await;
+                    return Ok(Vec::new());
+                }
+                tokio::time::sleep(script.latency.min(wait)).await;
+                Ok(script.udp.into_iter().map(|reply| (from, reply)).collect())
+            })
+        }
+
+        // 覆核與一般探測使用同一份劇本；另外記錄在呼叫順序中
+        fn reprobe(&self, dest: SocketAddr, limit: Duration, _source: Option<IpAddr>) -> ProbeFuture<'_, Reprobe> {
+            Box::pin(async move {
+                let outbound = self.connect(dest.ip(), dest.port(), limit, None).await;
+                // 與實際覆核相同，只有連線成功時才有延遲
+                let latency_ms = outbound.connected.then(|| self.script(dest.ip(), dest.port()).latency.as_secs_f64() * 1000.0);
+                (outbound.connected, latency_ms, outbound.failure, outbound.error)
+            })
+        }
+
+        // 有設定來源端口的劇本時使用，否則與一般探測相同
+        fn connect_from(&self, dest: SocketAddr, source_port: u16, limit: Duration) -> ProbeFuture<'_, SourceAttempt> {
+            Box::pin(async move {
+                if self.unbindable_sources.contains(&source_port) {
+                    return Err(ErrorCode::BindAddressInUse);
+                }
+                let script = self.sources.get(&(source_port, dest.port())).unwrap_or_else(|| self.script(dest.ip(), dest.port()));
+                let outbound = Self::play(script, dest.ip(), dest.port(), limit).await;
+                let latency_ms = outbound.connected.then_some(script.latency.as_secs_f64() * 1000.0);
+                Ok((outbound.connected, latency_ms, outbound.failure, outbound.error))
+            })
+        }
+
+        // 假網路沒有 HTTP 服務
+        fn vhosts<'a>(&'a self, _dest: IpAddr, _port: &'a PortInfo, _names: &'a [String]) -> ProbeFuture<'a, Vec<VhostResult>> {
+            Box::pin(async { Vec::new() })
+        }
+    }
+}
@@ -0,0 +1,463 @@
+use std::fs;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use colored::*;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Instant};
+use crate::charset;
+use crate::context::ScanContext;
+
+// 預設讀取的回應長度
+const DEFAULT_READ_SIZE: usize = 1024;
+
+// 顯示與輸出的橫幅長度上限
+const BANNER_LIMIT: usize = 200;
+
+// 內建探測，與使用者檔案格式相同
+const BUILTIN: &[&str] = &[
+    r#"
+name = "ssh"
+ports = [22, 2222]
+
+[[match]]
+pattern = '^SSH-([\d.]+)-(\S+)'
+service = "ssh"
+version = "$2"
+"#,
+    r#"
+name = "ftp"
+ports = [21]
+
+[[match]]
+pattern = '^220[ -](.*)'
+service = "ftp"
+version = "$1"
+"#,
+    r#"
+name = "smtp"
+ports = [25, 465, 587]
+
+[[match]]
+pattern = '^220[ -](\S+) .*E?SMTP'
+service = "smtp"
+version = "$1"
+"#,
+    r#"
+name = "http"
+ports = [80, 8000, 8008, 8080, 8888]
+payload = "HEAD / HTTP/1.0\r\n\r\n"
+
+[[match]]
+pattern = '(?mi)^Server: *([^\r\n]+)'
+service = "http"
+version = "$1"
+
+[[match]]
+pattern = '^HTTP/1\.[01] '
+service = "http"
+"#,
+];
+
+// 探測定義檔 (~/.config/portscanner/probes/*.toml)
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProbeFile {
+    name: String,
+    ports: Vec<u16>,
+    // 文字內容 (可用 TOML 跳脫字元) 或十六進位內容，兩者擇一；都沒有時只讀取橫幅
+    payload: Option<String>,
+    payload_hex: Option<String>,
+    read_size: Option<usize>,
+    #[serde(default, rename = "match")]
+    matchers: Vec<MatcherFile>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MatcherFile {
+    pattern: toml::Spanned<String>,
+    service: String,
+    // 版本樣板，例如 "$1"
+    version: Option<String>,
+}
+
+// 探測來源
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProbeSource {
+    Builtin,
+    File(PathBuf),
+}
+
+impl ProbeSource {
+    pub fn label(&self) -> String {
+        match self {
+            ProbeSource::Builtin => "內建".to_string(),
+            ProbeSource::File(path) => path.display().to_string(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Matcher {
+    pub pattern: Regex,
+    pub service: String,
+    pub version: Option<String>,
+}
+
+// 編譯後的探測
+#[derive(Debug)]
+pub struct Probe {
+    pub name: String,
+    pub ports: Vec<u16>,
+    pub payload: Vec<u8>,
+    pub read_size: usize,
+    pub matchers: Vec<Matcher>,
+    pub source: ProbeSource,
+}
+
+// 橫幅探測結果
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Banner {
+    pub probe: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    pub text: String,
+    // 不是 UTF-8 時判斷出的編碼 (例如 GBK、Big5、Shift_JIS)；無法判斷的二進位資料省略
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    // 編碼判斷不可靠時保留的原始位元組 (base64)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<String>,
+}
+
+// 已載入的探測 (內建 + 使用者目錄)
+#[derive(Debug, Default)]
+pub struct ProbeLibrary {
+    pub probes: Vec<Probe>,
+    // 載入時發現的衝突 (例如使用者探測覆蓋內建探測)
+    pub conflicts: Vec<String>,
+}
+
+// 使用者探測目錄：設定檔目錄下的 probes/
+pub fn default_dir() -> Option<PathBuf> {
+    crate::config::config_dir().map(|dir| dir.join("probes"))
+}
+
+// 位元組位置換算成行號
+fn line_of(text: &str, offset: usize) -> usize {
+    text[..offset.min(text.len())].matches('\n').count() + 1
+}
+
+fn parse_hex(hex: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<char> = hex.chars().filter(|c| !c.is_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err("payload_hex 長度必須為偶數".to_string());
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let byte: String = pair.iter().collect();
+            u8::from_str_radix(&byte, 16).map_err(|_| format!("payload_hex 含有無效字元: {}", byte))
+        })
+        .collect()
+}
+
+// 解析並驗證一個探測定義
+fn compile(text: &str, source: ProbeSource) -> Result<Probe, String> {
+    let location = source.label();
+    let file: ProbeFile = toml::from_str(text).map_err(|e| format!("{}: 格式錯誤: {}", location, e))?;
+
+    if file.name.trim().is_empty() {
+        return Err(format!("{}: 缺少 name", location));
+    }
+    if file.ports.is_empty() {
+        return Err(format!("{}: ports 不能是空的", location));
+    }
+    let payload = match (&file.payload, &file.payload_hex) {
+        (Some(_), Some(_)) => return Err(format!("{}: payload 與 payload_hex 只能擇一", location)),
+        (Some(text), None) => text.as_bytes().to_vec(),
+        (None, Some(hex)) => parse_hex(hex).map_err(|e| format!("{}: {}", location, e))?,
+        (None, None) => Vec::new(),
+    };
+    let read_size = file.read_size.unwrap_or(DEFAULT_READ_SIZE);
+    if read_size == 0 {
+        return Err(format!("{}: read_size 必須大於 0", location));
+    }
+
+    let mut matchers = Vec::new();
+    for matcher in file.matchers {
+        let line = line_of(text, matcher.pattern.span().start);
+        let pattern = Regex::new(matcher.pattern.get_ref())
+            .map_err(|e| format!("{}:{}: 無效的正規表示式: {}", location, line, e))?;
+        matchers.push(Matcher {
+            pattern,
+            service: matcher.service,
+            version: matcher.version,
+        });
+    }
+
+    Ok(Probe {
+        name: file.name,
+        ports: file.ports,
+        payload,
+        read_size,
+        matchers,
+        source,
+    })
+}
+
+// 載入內建探測與使用者目錄中的 *.toml；同名的使用者探測覆蓋內建探測
+pub fn load(dir: Option<&Path>) -> Result<ProbeLibrary, String> {
+    let mut library = ProbeLibrary::default();
+    for text in BUILTIN {
+        library.probes.push(compile(text, ProbeSource::Builtin)?);
+    }
+
+    let Some(dir) = dir.filter(|dir| dir.is_dir()) else {
+        return Ok(library);
+    };
+    let entries = fs::read_dir(dir).map_err(|e| format!("無法讀取探測目錄 {}: {}", dir.display(), e))?;
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let text = fs::read_to_string(&path).map_err(|e| format!("無法讀取 {}: {}", path.display(), e))?;
+        let probe = compile(&text, ProbeSource::File(path.clone()))?;
+
+        match library.probes.iter().position(|p| p.name == probe.name) {
+            Some(index) if library.probes[index].source == ProbeSource::Builtin => {
+                library
+                    .conflicts
+                    .push(format!("{}: {} 覆蓋內建探測", probe.name, path.display()));
+                library.probes[index] = probe;
+            }
+            Some(index) => {
+                return Err(format!(
+                    "探測名稱重複: {} ({} 與 {})",
+                    probe.name,
+                    library.probes[index].source.label(),
+                    path.display()
+                ));
+            }
+            None => library.probes.push(probe),
+        }
+    }
+
+    // 多個探測適用同一端口時依載入順序嘗試，列出來讓使用者確認
+    let mut ports: Vec<u16> = library.probes.iter().flat_map(|p| p.ports.iter().copied()).collect();
+    ports.sort_unstable();
+    ports.dedup();
+    for port in ports {
+        let names: Vec<&str> = library.for_port(port).map(|p| p.name.as_str()).collect();
+        if names.len() > 1 {
+            library
+                .conflicts
+                .push(format!("Port {} 有多個探測，依序嘗試: {}", port, names.join(", ")));
+        }
+    }
+
+    Ok(library)
+}
+
+impl ProbeLibrary {
+    pub fn for_port(&self, port: u16) -> impl Iterator<Item = &Probe> {
+        self.probes.iter().filter(move |probe| probe.ports.contains(&port))
+    }
+}
+
+// 轉成 UTF-8、移除控制字元並截斷，方便顯示
+pub(crate) fn sanitize(response: &[u8]) -> String {
+    shorten(&charset::decode(response).text)
+}
+
+fn shorten(text: &str) -> String {
+    let text = charset::clean(text).split_whitespace().collect::<Vec<_>>().join(" ");
+    text.chars().take(BANNER_LIMIT).collect()
+}
+
+// 回應的顯示文字與判斷出的編碼；不可靠時一併保留原始位元組
+fn banner(probe: &Probe, decoded: &charset::Decoded, response: &[u8]) -> Banner {
+    Banner {
+        probe: probe.name.clone(),
+        service: None,
+        version: None,
+        text: shorten(&decoded.text),
+        encoding: decoded.encoding.filter(|&name| name != "UTF-8").map(str::to_string),
+        raw: (!decoded.confident).then(|| BASE64_STANDARD.encode(response)),
+    }
+}
+
+// 送出探測內容並讀取回應，直到讀滿、對方關閉或逾時
+async fn exchange(context: &ScanContext, addr: SocketAddr, probe: &Probe, limit: Duration) -> Option<Vec<u8>> {
+    let deadline = Instant::now() + limit;
+    let _socket = context.socket();
+    let stream = timeout(limit, TcpStream::connect(addr)).await.ok()?.ok()?;
+    exchange_on(context, stream, probe, deadline).await
+}
+
+// 在已連線的串流 (TCP 或 SSH 通道) 上完成一次探測，截止時間包含連線所花的時間
+pub async fn exchange_on<S: AsyncRead + AsyncWrite + Unpin>(context: &ScanContext, mut stream: S, probe: &Probe, deadline: Instant) -> Option<Vec<u8>> {
+    if !probe.payload.is_empty() {
+        stream.write_all(&probe.payload).await.ok()?;
+        context.sent(probe.payload.len());
+    }
+
+    let mut response = vec![0u8; probe.read_size];
+    let mut filled = 0;
+    while filled < response.len() {
+        match timeout(deadline.saturating_duration_since(Instant::now()), stream.read(&mut response[filled..])).await {
+            Ok(Ok(0)) | Ok(Err(_)) | Err(_) => break,
+            Ok(Ok(n)) => filled += n,
+        }
+    }
+    context.received(filled);
+    response.truncate(filled);
+    (!response.is_empty()).then_some(response)
+}
+
+// 依序嘗試適用於此端口的探測；第一個比對成功的結果優先，否則回傳第一個有回應的原始橫幅
+pub async fn grab(library: &ProbeLibrary, context: &ScanContext, host: IpAddr, port: u16, limit: Duration) -> Option<Banner> {
+    let addr = context.socket_addr(host, port);
+    grab_with(library, port, |probe| exchange(context, addr, probe, limit)).await
+}
+
+// 與 grab 相同，每個探測的連線與交換由呼叫端提供 (例如經由 SSH 跳板)
+pub async fn grab_with<'a, F, Fut>(library: &'a ProbeLibrary, port: u16, mut exchange: F) -> Option<Banner>
+where
+    F: FnMut(&'a Probe) -> Fut,
+    Fut: Future<Output = Option<Vec<u8>>>,
+{
+    let mut unmatched = None;
+
+    for probe in library.for_port(port) {
+        let Some(response) = exchange(probe).await else {
+            continue;
+        };
+        // 以解碼後的文字比對，舊式編碼的橫幅也能取出版本
+        let decoded = charset::decode(&response);
+
+        for matcher in &probe.matchers {
+            if let Some(captures) = matcher.pattern.captures(&decoded.text) {
+                let version = matcher.version.as_ref().map(|template| {
+                    let mut version = String::new();
+                    captures.expand(template, &mut version);
+                    shorten(&version)
+                });
+                return Some(Banner {
+                    service: Some(matcher.service.clone()),
+                    version: version.filter(|v| !v.is_empty()),
+                    ..banner(probe, &decoded, &response)
+                });
+            }
+        }
+
+        unmatched.get_or_insert(banner(probe, &decoded, &response));
+    }
+
+    unmatched
+}
+
+// 不送出內容，只讀取對方主動送出的橫幅 (政策斷言用於探測定義沒有涵蓋的端口)
+pub async fn passive(context: &ScanContext, host: IpAddr, port: u16, limit: Duration) -> Option<String> {
+    let probe = Probe {
+        name: "passive".to_string(),
+        ports: vec![port],
+        payload: Vec::new(),
+        read_size: DEFAULT_READ_SIZE,
+        matchers: Vec::new(),
+        source: ProbeSource::Builtin,
+    };
+    exchange(context, context.socket_addr(host, port), &probe, limit).await.map(|response| sanitize(&response))
+}
+
+// 橫幅的顯示文字
+pub fn describe(banner: &Banner) -> String {
+    match (&banner.service, &banner.version) {
+        (Some(service), Some(version)) => format!("{} {}", service, version),
+        (Some(service), None) => service.clone(),
+        _ => banner.text.clone(),
+    }
+}
+
+// probes list：顯示已載入的探測與來源
+pub fn display_list(library: &ProbeLibrary, dir: Option<&Path>) {
+    println!("{}", "=== 已載入的探測 ===".bold());
+    if let Some(dir) = dir {
+        println!("使用者目錄: {}", dir.display());
+    }
+
+    for probe in &library.probes {
+        let ports: Vec<String> = probe.ports.iter().map(u16::to_string).collect();
+        println!(
+            "\n{} ({})",
+            probe.name.bold(),
+            match &probe.source {
+                ProbeSource::Builtin => probe.source.label().dimmed(),
+                ProbeSource::File(_) => probe.source.label().cyan(),
+            }
+        );
+        println!("  端口: {}", ports.join(", "));
+        println!("  送出: {} 位元組，讀取上限 {} 位元組", probe.payload.len(), probe.read_size);
+        for matcher in &probe.matchers {
+            let version = matcher.version.as_deref().map(|v| format!(" 版本 {}", v)).unwrap_or_default();
+            println!("  比對 {} → {}{}", matcher.pattern.as_str(), matcher.service, version);
+        }
+    }
+
+    if !library.conflicts.is_empty() {
+        println!("\n{}", "--- 衝突 ---".bold());
+        for conflict in &library.conflicts {
+            println!("{}", conflict.yellow());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library() -> ProbeLibrary {
+        let probe = Probe {
+            name: "ftp".to_string(),
+            ports: vec![21],
+            payload: Vec::new(),
+            read_size: DEFAULT_READ_SIZE,
+            matchers: vec![Matcher {
+                pattern: Regex::new(r"^220 (\S+) 伺服器").unwrap(),
+                service: "ftp".to_string(),
+                version: Some("$1".to_string()),
+            }],
+            source: ProbeSource::Builtin,
+        };
+        ProbeLibrary { probes: vec![probe], conflicts: Vec::new() }
+    }
+
+    #[tokio::test]
+    async fn legacy_banners_are_decoded_before_matching() {
+        let library = library();
+        let (big5, _, _) = encoding_rs::BIG5.encode("220 FileZilla 伺服器\r\n歡迎光臨");
+        let banner = grab_with(&library, 21, |_| std::future::ready(Some(big5.to_vec()))).await.unwrap();
+        assert_eq!(banner.version.as_deref(), Some("FileZilla"));
+        assert_eq!(banner.text, "220 FileZilla 伺服器 歡迎光臨");
+        assert_eq!((banner.encoding.as_deref(), banner.raw.as_deref()), (Some("Big5"), None));
+
+        // 無法判斷的二進位回應保留原始位元組，顯示文字沒有控制字元
+        let garbage = vec![0x00, 0x1b, 0x5b, 0x32, 0x4a, 0x07, 0xff, 0x01];
+        let banner = grab_with(&library, 21, |_| std::future::ready(Some(garbage.clone()))).await.unwrap();
+        assert_eq!(banner.encoding, None);
+        assert_eq!(banner.raw.as_deref(), Some("ABtbMkoH/wE="));
+        assert!(!banner.text.chars().any(char::is_control), "{:?}", banner.text);
+    }
+}
@@ -0,0 +1,262 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use colored::*;
+use crate::pipeline::Stage;
+use crate::pool::PoolStats;
+use crate::{stats, timefmt};
+
+// 單個探測的排程紀錄
+#[derive(Debug, Clone)]
+pub struct ProbeSample {
+    pub host: IpAddr,
+    pub port: u16,
+    // 從掃描開始到探測開始的時間
+    pub started: Duration,
+    // 等待並發許可的時間
+    pub queued: Duration,
+    // 取得 socket 並送出 SYN 的時間
+    pub setup: Duration,
+    // 實際連線花費的時間
+    pub connect: Duration,
+    // 橫幅與服務辨識階段的時間；未通過前置條件或超出 --stages 時為 None
+    pub banner: Option<Duration>,
+    pub fingerprint: Option<Duration>,
+    // 結果通道已滿而等待寫入端的時間
+    pub stalled: Duration,
+    // 探測開始時同時進行的探測數 (含自己)
+    pub active: usize,
+}
+
+// 排程器的量測掛勾，由 --profile-scan 啟用
+#[derive(Debug)]
+pub struct Profiler {
+    start: Instant,
+    active: AtomicUsize,
+    peak: AtomicUsize,
+    samples: Mutex<Vec<ProbeSample>>,
+    // 掃描結束後整批執行的階段 (TCP 功能、HTTP 版本、服務檢查)
+    phases: Mutex<Vec<PhaseSample>>,
+}
+
+// 掃描後整批執行的階段耗時
+#[derive(Debug, Clone)]
+pub struct PhaseSample {
+    pub stage: Stage,
+    pub name: &'static str,
+    pub elapsed: Duration,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler {
+            start: Instant::now(),
+            active: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            samples: Mutex::new(Vec::new()),
+            phases: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl Profiler {
+    // 探測開始，回傳開始時間點與當下的並發數
+    pub fn begin(&self) -> (Duration, usize) {
+        let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(active, Ordering::SeqCst);
+        (self.start.elapsed(), active)
+    }
+
+    // 探測被取消，不留下樣本
+    pub fn abandon(&self) {
+        self.active.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    // 探測結束
+    pub fn finish(&self, sample: ProbeSample) {
+        self.active.fetch_sub(1, Ordering::SeqCst);
+        self.samples.lock().expect("profiler lock").push(sample);
+    }
+
+    pub fn samples(&self) -> Vec<ProbeSample> {
+        self.samples.lock().expect("profiler lock").clone()
+    }
+
+    pub fn phase(&self, stage: Stage, name: &'static str, elapsed: Duration) {
+        self.phases.lock().expect("profiler lock").push(PhaseSample { stage, name, elapsed });
+    }
+
+    pub fn phases(&self) -> Vec<PhaseSample> {
+        self.phases.lock().expect("profiler lock").clone()
+    }
+
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::SeqCst)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+// 彙整後的掃描剖析
+#[derive(Debug, Default)]
+pub struct ProfileSummary {
+    pub probes: usize,
+    pub elapsed: Duration,
+    pub queue_p50: Duration,
+    pub queue_p95: Duration,
+    pub setup_p50: Duration,
+    pub setup_p95: Duration,
+    pub connect_p50: Duration,
+    pub connect_p95: Duration,
+    // 有執行的端口數與 p50 / p95
+    pub banner: StageTiming,
+    pub fingerprint: StageTiming,
+    pub phases: Vec<PhaseSample>,
+    pub slowest: Vec<ProbeSample>,
+    pub peak_concurrency: usize,
+    pub stalled: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct StageTiming {
+    pub ports: usize,
+    pub p50: Duration,
+    pub p95: Duration,
+}
+
+impl StageTiming {
+    fn of(mut times: Vec<Duration>) -> Self {
+        times.sort_unstable();
+        StageTiming { ports: times.len(), p50: percentile(&times, 50.0), p95: percentile(&times, 95.0) }
+    }
+}
+
+// 與延遲樣本相同的 stats::percentile (線性內插)，輸入需已排序；沒有紀錄時為 0
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let secs: Vec<f64> = sorted.iter().map(Duration::as_secs_f64).collect();
+    stats::percentile(&secs, p).map(Duration::from_secs_f64).unwrap_or_default()
+}
+
+// 彙整紀錄
+pub fn summarize(profiler: &Profiler, slowest: usize) -> ProfileSummary {
+    let mut samples = profiler.samples();
+    let mut queued: Vec<Duration> = samples.iter().map(|s| s.queued).collect();
+    let mut connect: Vec<Duration> = samples.iter().map(|s| s.connect).collect();
+    let mut setup: Vec<Duration> = samples.iter().map(|s| s.setup).collect();
+    let stalled = samples.iter().map(|s| s.stalled).sum();
+    let banner = StageTiming::of(samples.iter().filter_map(|s| s.banner).collect());
+    let fingerprint = StageTiming::of(samples.iter().filter_map(|s| s.fingerprint).collect());
+    queued.sort_unstable();
+    setup.sort_unstable();
+    connect.sort_unstable();
+
+    samples.sort_by_key(|s| std::cmp::Reverse(s.connect));
+    samples.truncate(slowest);
+
+    ProfileSummary {
+        probes: queued.len(),
+        elapsed: profiler.elapsed(),
+        queue_p50: percentile(&queued, 50.0),
+        queue_p95: percentile(&queued, 95.0),
+        setup_p50: percentile(&setup, 50.0),
+        setup_p95: percentile(&setup, 95.0),
+        connect_p50: percentile(&connect, 50.0),
+        connect_p95: percentile(&connect, 95.0),
+        banner,
+        fingerprint,
+        phases: profiler.phases(),
+        peak_concurrency: profiler.peak(),
+        stalled,
+        slowest: samples,
+    }
+}
+
+// 顯示剖析結果；sockets 為 socket 池的統計 (未啟用時為 None)
+pub fn display_profile(summary: &ProfileSummary, sockets: Option<PoolStats>, concurrency: usize) {
+    println!("\n{}", "=== 掃描剖析 ===".bold());
+    println!("探測數量: {} (總耗時 {})", summary.probes, timefmt::duration(summary.elapsed));
+    println!(
+        "等待許可: p50 {}  p95 {}",
+        timefmt::millis(summary.queue_p50),
+        timefmt::millis(summary.queue_p95)
+    );
+    println!(
+        "建立 socket: p50 {}  p95 {}",
+        timefmt::millis(summary.setup_p50),
+        timefmt::millis(summary.setup_p95)
+    );
+    match sockets {
+        Some(stats) if stats.created + stats.reused > 0 => println!(
+            "socket 重複使用: {} / {} ({:.0}%)，新建 {} 個",
+            stats.reused,
+            stats.created + stats.reused,
+            stats.reused as f64 * 100.0 / (stats.created + stats.reused) as f64,
+            stats.created
+        ),
+        Some(_) => {}
+        None => println!("{}", "socket 重複使用: 未啟用".dimmed()),
+    }
+    println!(
+        "連線時間: p50 {}  p95 {}",
+        timefmt::millis(summary.connect_p50),
+        timefmt::millis(summary.connect_p95)
+    );
+    for (stage, timing) in [(Stage::Banner, &summary.banner), (Stage::Fingerprint, &summary.fingerprint)] {
+        match timing.ports {
+            0 => println!("{}", format!("{}: 未執行", stage.label()).dimmed()),
+            ports => println!(
+                "{}: p50 {}  p95 {} ({} 個端口)",
+                stage.label(),
+                timefmt::millis(timing.p50),
+                timefmt::millis(timing.p95),
+                ports
+            ),
+        }
+    }
+    for phase in &summary.phases {
+        println!("{} ({}): {}", phase.stage.label(), phase.name, timefmt::millis(phase.elapsed));
+    }
+    println!("最高並發: {} / {}", summary.peak_concurrency, concurrency);
+    println!("寫入端阻塞: {}", timefmt::millis(summary.stalled));
+
+    if !summary.slowest.is_empty() {
+        println!("最慢的探測:");
+        for sample in &summary.slowest {
+            println!("  {:>15} Port {:5}  {}", sample.host, sample.port, timefmt::millis(sample.connect));
+        }
+    }
+}
+
+// 將原始紀錄寫成 CSV
+pub fn write_csv(profiler: &Profiler, path: &Path) -> io::Result<()> {
+    let mut samples = profiler.samples();
+    samples.sort_by_key(|s| s.started);
+
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "host,port,started_ms,queued_ms,setup_ms,connect_ms,banner_ms,fingerprint_ms,stalled_ms,active")?;
+    // 未執行的階段留空
+    let millis = |d: Option<Duration>| d.map(|d| format!("{:.3}", d.as_secs_f64() * 1000.0)).unwrap_or_default();
+    for s in &samples {
+        writeln!(
+            out,
+            "{},{},{:.3},{:.3},{:.3},{:.3},{},{},{:.3},{}",
+            s.host,
+            s.port,
+            s.started.as_secs_f64() * 1000.0,
+            s.queued.as_secs_f64() * 1000.0,
+            s.setup.as_secs_f64() * 1000.0,
+            s.connect.as_secs_f64() * 1000.0,
+            millis(s.banner),
+            millis(s.fingerprint),
+            s.stalled.as_secs_f64() * 1000.0,
+            s.active
+        )?;
+    }
+    out.flush()
+}
@@ -0,0 +1,238 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use schemars::JsonSchema;
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior};
+use crate::heartbeat::{Counters, Finding, Sampler};
+use crate::timefmt;
+
+// 掃描的狀態；running 以外都是最後一次寫入
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum State {
+    Running,
+    Finished,
+    // 發生錯誤或 panic，掃描沒有正常結束
+    Failed,
+    // 按 q 中止或 Ctrl+C
+    Cancelled,
+}
+
+// --progress-file 的內容
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct ProgressDocument {
+    pub scan_id: String,
+    pub state: State,
+    pub completed: u64,
+    pub total: u64,
+    // 最近一個間隔的每秒完成數
+    pub rate: f64,
+    // 依目前速率估計的剩餘秒數；速率為 0 或已結束時省略
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eta_secs: Option<u64>,
+    pub started_at: String,
+    pub updated_at: String,
+    // 最近的發現 (由新到舊)
+    pub findings: Vec<Finding>,
+}
+
+struct Writer {
+    path: PathBuf,
+    scan_id: String,
+    started_at: String,
+    counters: Arc<Counters>,
+    sampler: Sampler,
+    // 已寫入最後的狀態，之後不再更新
+    finished: bool,
+}
+
+impl Writer {
+    fn write(&mut self, state: State) -> io::Result<()> {
+        let event = self.sampler.sample(state != State::Running);
+        let document = ProgressDocument {
+            scan_id: self.scan_id.clone(),
+            state,
+            completed: event.completed,
+            total: event.total,
+            rate: event.rate,
+            eta_secs: event.eta_secs,
+            started_at: self.started_at.clone(),
+            updated_at: timefmt::rfc3339_utc(timefmt::now()),
+            findings: self.counters.recent(),
+        };
+        replace(&self.path, &serde_json::to_vec_pretty(&document)?)
+    }
+}
+
+// 先寫入同目錄的暫存檔再改名，讀取端不會看到寫到一半的內容
+fn replace(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    fs::write(&temp, contents)?;
+    fs::rename(&temp, path)
+}
+
+fn lock(writer: &Mutex<Writer>) -> MutexGuard<'_, Writer> {
+    writer.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// 只有第一次呼叫會寫入，之後的定期更新也停止
+fn finish(writer: &Mutex<Writer>, state: State) -> io::Result<()> {
+    let mut writer = lock(writer);
+    if writer.finished {
+        return Ok(());
+    }
+    writer.finished = true;
+    writer.write(state)
+}
+
+// 定期改寫的進度檔；沒有呼叫 finish 就被丟棄時 (錯誤返回或 panic) 寫入 failed
+pub struct ProgressFile {
+    writer: Arc<Mutex<Writer>>,
+    ticker: JoinHandle<()>,
+}
+
+// 開始時先寫入一次，無法寫入的路徑在掃描前就回報
+pub fn start(path: &Path, counters: Arc<Counters>, total: u128, scan_id: &str, started_at: i64, interval: Duration) -> io::Result<ProgressFile> {
+    let mut writer = Writer {
+        path: path.to_path_buf(),
+        scan_id: scan_id.to_string(),
+        started_at: timefmt::rfc3339_utc(started_at),
+        sampler: Sampler::new(counters.clone(), total.min(u64::MAX as u128) as u64),
+        counters,
+        finished: false,
+    };
+    writer.write(State::Running)?;
+    let writer = Arc::new(Mutex::new(writer));
+    let shared = writer.clone();
+    let ticker = tokio::spawn(async move {
+        let mut ticker = tokio::time::interval_at(Instant::now() + interval, interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let mut writer = lock(&shared);
+            if writer.finished {
+                return;
+            }
+            // 暫時無法寫入時下一次再試；最後一次寫入的錯誤才回報
+            let _ = writer.write(State::Running);
+        }
+    });
+    Ok(ProgressFile { writer, ticker })
+}
+
+impl ProgressFile {
+    pub fn finish(&self, state: State) -> io::Result<()> {
+        self.ticker.abort();
+        finish(&self.writer, state)
+    }
+
+    // Ctrl+C 的處理工作用來寫入 cancelled
+    pub fn interrupt(&self) -> Interrupt {
+        Interrupt(self.writer.clone())
+    }
+}
+
+impl Drop for ProgressFile {
+    fn drop(&mut self) {
+        self.ticker.abort();
+        let _ = finish(&self.writer, State::Failed);
+    }
+}
+
+pub struct Interrupt(Arc<Mutex<Writer>>);
+
+impl Interrupt {
+    pub fn cancel(&self) {
+        let _ = finish(&self.0, State::Cancelled);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prober::fake::{Script, Scripted, ScriptedProber};
+    use crate::testutil::{host, scan, scripted_plan, TempDir};
+
+    fn read(path: &Path) -> serde_json::Value {
+        serde_json::from_slice(&fs::read(path).unwrap()).unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn file_is_rewritten_while_a_slow_scan_runs() {
+        let dir = TempDir::new("progressfile");
+        let path = dir.path().join("scan.progress");
+        let prober = Arc::new(
+            ScriptedProber::new()
+                .with(host(1), 443, Script::new(Scripted::Open, Duration::from_millis(500)))
+                .fallback(Script::new(Scripted::Refused, Duration::from_millis(500))),
+        );
+        let mut plan = scripted_plan(&[host(1)], &[22, 80, 443, 8080], 1, prober);
+        let counters = Arc::new(Counters::default());
+        plan.progress = Some(counters.clone());
+        let progress = start(&path, counters, plan.remaining_probes(), "scan-1", 1_700_000_000, Duration::from_millis(400)).unwrap();
+        assert_eq!(read(&path)["state"], "running");
+        assert_eq!(read(&path)["started_at"], "2023-11-14T22:13:20Z");
+        assert_eq!(read(&path)["scan_id"], "scan-1");
+
+        let scanning = tokio::spawn(async move { scan(&plan).await });
+        // 一次一個探測，每個 500ms；每 400ms 改寫一次，每 410ms 讀取
+        let mut seen = Vec::new();
+        for _ in 0..4 {
+            tokio::time::sleep(Duration::from_millis(410)).await;
+            let document = read(&path);
+            assert_eq!((document["state"].as_str(), document["total"].as_u64()), (Some("running"), Some(4)));
+            seen.push(document["completed"].as_u64().unwrap());
+        }
+        assert_eq!(seen, [0, 1, 2, 3]);
+        let document = read(&path);
+        assert_eq!(document["findings"][0]["port"], 443);
+        assert!(document["eta_secs"].as_u64().is_some());
+
+        scanning.await.unwrap();
+        progress.finish(State::Finished).unwrap();
+        let document = read(&path);
+        assert_eq!((document["state"].as_str(), document["completed"].as_u64()), (Some("finished"), Some(4)));
+        assert!(document.get("eta_secs").is_none());
+        // 暫存檔已改名，不會留下
+        assert!(!dir.path().join("scan.progress.tmp").exists());
+
+        // 結束後不再更新
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(read(&path)["state"], "finished");
+    }
+
+    #[tokio::test]
+    async fn dropping_without_finishing_records_a_failure() {
+        let dir = TempDir::new("progressfile");
+        let path = dir.path().join("scan.progress");
+        let progress = start(&path, Arc::default(), 10, "scan-1", 0, Duration::from_secs(60)).unwrap();
+        drop(progress);
+        assert_eq!(read(&path)["state"], "failed");
+
+        // panic 時 (例如在探測以外的地方) 丟棄的 guard 同樣寫入
+        let failing = dir.path().join("panicked.progress");
+        let panicked = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+            runtime.block_on(async move {
+                let _progress = start(&failing, Arc::default(), 10, "scan-1", 0, Duration::from_secs(60)).unwrap();
+                panic!("測試用的 panic");
+            })
+        })
+        .join();
+        assert!(panicked.is_err());
+        assert_eq!(read(&dir.path().join("panicked.progress"))["state"], "failed");
+
+        // Ctrl+C 寫入 cancelled 後，之後的 finish 與丟棄都不再改寫
+        let progress = start(&path, Arc::default(), 10, "scan-1", 0, Duration::from_secs(60)).unwrap();
+        progress.interrupt().cancel();
+        progress.finish(State::Finished).unwrap();
+        drop(progress);
+        assert_eq!(read(&path)["state"], "cancelled");
+    }
+}
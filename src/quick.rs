@@ -0,0 +1,145 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use serde::Deserialize;
+use crate::cli::{Cli, Concurrency};
+use crate::{errors, portline};
+use crate::{PortInfo, ScanResult};
+
+// quick 檢查的目標
+pub const TARGET: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+// 本機的連線不是立即成功就是立即被拒，100ms 足以判斷
+pub const TIMEOUT: Duration = Duration::from_millis(100);
+
+// 少數端口全部同時探測
+const CONCURRENCY: usize = 256;
+
+// 設定檔 [quick] 區段
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuickConfig {
+    // portscanner quick 未指定端口時檢查的端口，例如 "5432,6379"
+    pub ports: Option<String>,
+}
+
+// quick 子命令：在一般掃描的選項上套用預設，之後的掃描流程與一般掃描相同
+// 命令列明確指定的目標、逾時與輸出格式優先；開關選項只會被打開
+pub fn preset(cli: &mut Cli, ports: Option<String>) {
+    if ports.is_some() {
+        cli.ports = ports;
+        cli.no_config = true;
+    }
+    cli.target.get_or_insert_with(|| TARGET.to_string());
+    cli.timeout.get_or_insert(TIMEOUT);
+    cli.concurrency = Concurrency::Fixed(CONCURRENCY);
+    // 本機的服務只需要確認能否連線；綁定測試會與監聽中的服務衝突
+    cli.no_inbound = true;
+    cli.no_external_ip = true;
+    cli.no_network_info = true;
+    cli.no_port_db = true;
+    cli.no_progress = true;
+    cli.no_live = true;
+    cli.no_pager = true;
+    cli.no_sanity_check = true;
+    cli.no_geo_sanity = true;
+    cli.no_verify = true;
+    cli.no_tarpit_check = true;
+    cli.compact = !cli.json && cli.output.is_empty() && cli.format_template.is_none();
+}
+
+// 端口是否正常：測試的方向可用且掃描端沒有錯誤
+pub fn ok(result: &ScanResult) -> bool {
+    result.error.is_none() && result.directions.open(result.inbound, result.outbound)
+}
+
+// --compact 的一行，例如 "✗ 無法連線 127.0.0.1:5432 PostgreSQL [E3001]"
+pub fn line(host: IpAddr, port: &PortInfo, result: &ScanResult) -> String {
+    let state = match result.error {
+        Some(error) => format!("! {}", error.describe()),
+        None => portline::status_label(result.directions, result.inbound, result.outbound).to_string(),
+    };
+    format!("{} {} {}{}", state, SocketAddr::new(host, port.port), port.service, errors::tag(&result.codes))
+}
+
+// 依主機與端口順序列出不正常的端口
+pub fn failures(results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>) -> Vec<String> {
+    let mut lines = Vec::new();
+    for (host, ports) in results {
+        let mut failed: Vec<(&PortInfo, &ScanResult)> = ports.iter().filter(|(_, result)| !ok(result)).collect();
+        failed.sort_by_key(|(port, _)| port.port);
+        lines.extend(failed.into_iter().map(|(port, result)| line(*host, port, result)));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use clap::Parser;
+    use tokio::time::Instant;
+    use crate::cli::Command;
+    use crate::context::ScanContext;
+    use crate::direction::Directions;
+    use crate::prober::NetProber;
+    use crate::scanner::ScanPlan;
+    use crate::selftest::localhost_plan;
+    use crate::tags::TagRules;
+    use crate::testutil::{by_host, scan};
+    use crate::timeouts::Timeouts;
+
+    // pre-commit hook 的目標：啟動、掃描與輸出合計遠低於一秒
+    const BUDGET: Duration = Duration::from_millis(500);
+
+    fn quick_cli(args: &[&str]) -> Cli {
+        let mut cli = Cli::try_parse_from(args).unwrap();
+        let Some(Command::Quick { ports }) = cli.command.take() else {
+            panic!("not a quick command");
+        };
+        preset(&mut cli, ports);
+        cli
+    }
+
+    #[test]
+    fn preset_keeps_explicit_options() {
+        let cli = quick_cli(&["r1", "--timeout", "2s", "--json", "quick", "22"]);
+        assert_eq!(cli.ports.as_deref(), Some("22"));
+        assert!(cli.no_config && cli.no_external_ip && cli.no_progress);
+        assert_eq!(cli.timeout, Some(Duration::from_secs(2)));
+        assert!(!cli.compact);
+        let cli = quick_cli(&["r1", "quick"]);
+        assert_eq!(cli.ports, None);
+        assert!(!cli.no_config && cli.compact);
+        assert_eq!(cli.target.as_deref(), Some("127.0.0.1"));
+    }
+
+    #[tokio::test]
+    async fn loopback_check_finishes_within_budget() {
+        let listener = TcpListener::bind((TARGET, 0)).unwrap();
+        let open = listener.local_addr().unwrap().port();
+        let closed = TcpListener::bind((TARGET, 0)).unwrap().local_addr().unwrap().port();
+
+        let started = Instant::now();
+        let cli = quick_cli(&["r1", "quick", &format!("{},{}", open, closed)]);
+        // 與 run 相同的啟動步驟：內建端口表、不查詢外部IP、不取得網路資訊
+        let ports = crate::select_ports(crate::get_common_ports(), cli.ports.as_deref(), &TagRules::default(), &[]).unwrap();
+        let context = Arc::new(ScanContext::from_cli(&cli, Default::default(), None, Default::default()));
+        context.start_external_ip_lookup();
+        let plan = ScanPlan {
+            prober: Arc::new(NetProber::new(context.clone(), None)),
+            context,
+            directions: Directions::of(cli.no_inbound, cli.no_outbound),
+            ..localhost_plan(ports, CONCURRENCY, Timeouts::fixed(cli.timeout.unwrap()), Vec::new())
+        };
+        let lines = failures(&by_host(scan(&plan).await));
+        let elapsed = started.elapsed();
+
+        assert!(elapsed < BUDGET, "{:?}", elapsed);
+        assert_eq!(lines.len(), 1, "{:?}", lines);
+        assert!(lines[0].contains(&format!("127.0.0.1:{}", closed)), "{}", lines[0]);
+        assert!(lines[0].contains("[E3001]"), "{}", lines[0]);
+        drop(listener);
+    }
+}
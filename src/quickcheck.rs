@@ -0,0 +1,108 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use colored::*;
+use crate::closure::Failure;
+use crate::context::ScanContext;
+use crate::dns::DnsCache;
+use crate::portspec;
+use crate::{probes, scanner, targets};
+
+// check 子命令的目標：host:port，IPv6 位址以方括號包住
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    pub host: String,
+    pub port: u16,
+}
+
+impl FromStr for Endpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (host, port) = s.rsplit_once(':').ok_or("格式應為 host:port，例如 example.com:443")?;
+        let host = match host.strip_prefix('[') {
+            Some(inner) => inner.strip_suffix(']').ok_or("IPv6 位址缺少結尾的 ]")?,
+            // 沒有方括號的 IPv6 位址無法分辨端口
+            None if host.contains(':') => return Err("IPv6 位址請以方括號包住，例如 [2001:db8::1]:22".to_string()),
+            None => host,
+        };
+        if host.is_empty() {
+            return Err("缺少主機名稱".to_string());
+        }
+        let host = match host.parse::<std::net::IpAddr>() {
+            Ok(_) => host.to_string(),
+            Err(_) => targets::to_ascii_hostname(host)?,
+        };
+        let port = portspec::parse_port(port).map_err(|_| format!("'{}' 不是有效的端口 (1-65535)", port))?;
+        Ok(Endpoint { host, port })
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.host.contains(':') {
+            true => write!(f, "[{}]:{}", self.host, self.port),
+            false => write!(f, "{}:{}", targets::display_hostname(&self.host), self.port),
+        }
+    }
+}
+
+fn failure_reason(failure: Option<Failure>) -> &'static str {
+    match failure {
+        Some(Failure::Reset { .. }) => "連線被拒",
+        Some(Failure::Timeout) => "逾時",
+        Some(Failure::ProxyDenied { .. }) => "proxy 拒絕",
+        Some(Failure::Unreachable) | None => "無法到達",
+    }
+}
+
+// check：只做一次出站探測，可連線時結束代碼為 0，否則為 1；不顯示標頭、網路資訊與進度列
+pub async fn run(endpoint: &Endpoint, limit: Duration, quiet: bool, banner: bool) -> Result<(), Box<dyn Error>> {
+    // 橫幅探測定義有誤時在連線前就回報
+    let library = match banner {
+        true => Some(probes::load(probes::default_dir().as_deref())?),
+        false => None,
+    };
+    let addr = match DnsCache::default().resolve(&endpoint.host).await {
+        Ok(addr) => addr,
+        Err(e) => {
+            if !quiet {
+                println!("{} {} {}", "✗".red(), endpoint, e);
+            }
+            std::process::exit(1);
+        }
+    };
+
+    // 單次探測不需要外部IP、區域與計數
+    let context = ScanContext::offline();
+    let started = Instant::now();
+    let outbound = scanner::test_outbound_port(&context, endpoint.port, addr, limit, None, None).await;
+    let latency = started.elapsed();
+    if !outbound.connected {
+        if !quiet {
+            let reason = match outbound.error {
+                Some(error) => error.describe(),
+                None => failure_reason(outbound.failure),
+            };
+            println!("{} {} {}", "✗".red(), endpoint, reason.red());
+        }
+        std::process::exit(1);
+    }
+    if quiet {
+        return Ok(());
+    }
+
+    let resolved = match endpoint.host.parse::<std::net::IpAddr>() {
+        Ok(_) => String::new(),
+        Err(_) => format!(" ({})", addr),
+    };
+    println!("{} {}{} 可連線 {:.1}ms", "✓".green(), endpoint, resolved.dimmed(), latency.as_secs_f64() * 1000.0);
+    if let Some(library) = &library {
+        match probes::grab(library, &context, addr, endpoint.port, limit).await {
+            Some(found) => println!("  橫幅: {}", probes::describe(&found).cyan()),
+            None => println!("  {}", "橫幅: 無回應".dimmed()),
+        }
+    }
+    Ok(())
+}
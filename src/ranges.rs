@@ -0,0 +1,393 @@
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::closure::Failure;
+use crate::direction::Directions;
+use crate::esbulk;
+use crate::layout::Layout;
+use crate::portline;
+use crate::view::Entry;
+use crate::{PortInfo, ScanResult};
+
+// 沒有服務名稱的端口 (select_ports 以此建立)
+pub const UNKNOWN_SERVICE: &str = "未知";
+
+// 至少這麼多個連續端口才合併成區段；較短的仍逐一列出
+pub const MIN_RUN: usize = 8;
+
+// 延遲等級：出站連線成功或收到 RST 的時間；同一區段的端口等級相同
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LatencyClass {
+    // 10ms 以下
+    Fast,
+    // 10-100ms
+    Medium,
+    // 100ms 以上
+    Slow,
+}
+
+impl LatencyClass {
+    pub fn of(ms: f64) -> Self {
+        match ms {
+            ms if ms < 10.0 => LatencyClass::Fast,
+            ms if ms < 100.0 => LatencyClass::Medium,
+            _ => LatencyClass::Slow,
+        }
+    }
+
+    // 展開區段時使用的延遲 (等級的下限)
+    fn floor_ms(self) -> f64 {
+        match self {
+            LatencyClass::Fast => 0.0,
+            LatencyClass::Medium => 10.0,
+            LatencyClass::Slow => 100.0,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LatencyClass::Fast => "<10ms",
+            LatencyClass::Medium => "10-100ms",
+            LatencyClass::Slow => "≥100ms",
+        }
+    }
+}
+
+// 狀態與延遲等級相同的連續端口，例如 "1024-49151: filtered"
+// 只包含沒有服務名稱、標籤與額外探測結果的端口，逐端口的完整資料見完整 JSON 與 SQLite 輸出
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PortRange {
+    pub start: u16,
+    pub end: u16,
+    pub category: String,
+    // 與 --es-bulk 相同的分類：open / closed / filtered
+    pub state: String,
+    #[serde(default)]
+    pub inbound: bool,
+    #[serde(default)]
+    pub outbound: bool,
+    #[serde(default, skip_serializing_if = "Directions::is_both")]
+    pub directions: Directions,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency: Option<LatencyClass>,
+}
+
+impl PortRange {
+    pub fn len(&self) -> usize {
+        usize::from(self.end - self.start) + 1
+    }
+
+    // 區段內每個端口的代表結果：狀態與延遲等級相同，其他欄位為空
+    pub fn expand(&self) -> Vec<(PortInfo, ScanResult)> {
+        let latency_ms = self.latency.map(LatencyClass::floor_ms);
+        let failure = match self.state.as_str() {
+            "open" => None,
+            "closed" => Some(Failure::Reset { latency_ms: latency_ms.unwrap_or_default() }),
+            _ => Some(Failure::Timeout),
+        };
+        let result = ScanResult {
+            inbound: self.inbound,
+            outbound: self.outbound,
+            directions: self.directions,
+            latency_ms: latency_ms.filter(|_| failure.is_none()),
+            failure,
+            ..Default::default()
+        };
+        (self.start..=self.end).map(|port| (PortInfo::new(port, UNKNOWN_SERVICE, &self.category), result.clone())).collect()
+    }
+}
+
+// 依端口順序排列的結果：單一端口或合併的區段
+#[derive(Debug)]
+pub enum Item<'a> {
+    Port(Entry<'a>),
+    Range(PortRange),
+}
+
+// 區段的分組依據；相鄰端口的 key 相同才能合併
+#[derive(PartialEq)]
+struct Key<'a> {
+    category: &'a str,
+    state: &'static str,
+    directions: Directions,
+    normalized: (bool, bool),
+    latency: Option<LatencyClass>,
+}
+
+fn key<'a>((port, result): Entry<'a>) -> Option<Key<'a>> {
+    let plain = port.service == UNKNOWN_SERVICE && port.tags.is_empty() && port.group.is_none() && port.srv.is_empty();
+    (plain && !notable(result)).then(|| Key {
+        category: &port.category,
+        state: esbulk::state(result),
+        directions: result.directions,
+        normalized: result.directions.normalize(result.inbound, result.outbound),
+        latency: latency_class(result),
+    })
+}
+
+// 除了狀態之外另有發現的結果 (說明、橫幅、ICMP 錯誤、服務檢查等) 一律逐一列出
+fn notable(result: &ScanResult) -> bool {
+    result.error.is_some()
+        || result.note.is_some()
+        || result.icmp.is_some()
+        || result.banner.is_some()
+        || result.verification.is_some()
+        || result.health.is_some()
+        || result.token.is_some()
+        || result.throughput.is_some()
+        || result.samples.is_some()
+        || result.capabilities.is_some()
+        || result.http_versions.is_some()
+        || result.systemd.is_some()
+        || result.conntrack.is_some()
+        || result.upstream.is_some()
+        || result.intercepted
+        || result.cancelled
+        || !result.vhosts.is_empty()
+        || !result.fingerprints.is_empty()
+        || !result.source_ports.is_empty()
+        || matches!(result.failure, Some(Failure::ProxyDenied { .. }))
+}
+
+fn latency_class(result: &ScanResult) -> Option<LatencyClass> {
+    let ms = match result.failure {
+        Some(Failure::Reset { latency_ms }) => Some(latency_ms),
+        _ => result.latency_ms,
+    };
+    ms.map(LatencyClass::of)
+}
+
+// 合併依端口排序的結果中的連續區段；有服務名稱或額外發現的端口會中斷區段並保留原樣
+pub fn aggregate(entries: Vec<Entry<'_>>) -> Vec<Item<'_>> {
+    let mut items = Vec::new();
+    let mut run: Vec<Entry> = Vec::new();
+    let mut run_key = None;
+    for entry in entries {
+        let entry_key = key(entry);
+        let continues = match (run.last(), &entry_key) {
+            (Some((last, _)), Some(k)) => u32::from(last.port) + 1 == u32::from(entry.0.port) && run_key.as_ref() == Some(k),
+            _ => false,
+        };
+        if !continues {
+            flush(&mut items, &mut run);
+        }
+        match entry_key {
+            Some(k) => {
+                run.push(entry);
+                run_key = Some(k);
+            }
+            None => items.push(Item::Port(entry)),
+        }
+    }
+    flush(&mut items, &mut run);
+    items
+}
+
+fn flush<'a>(items: &mut Vec<Item<'a>>, run: &mut Vec<Entry<'a>>) {
+    if run.len() < MIN_RUN {
+        items.extend(run.drain(..).map(Item::Port));
+        return;
+    }
+    let ((first, result), (last, _)) = (run[0], run[run.len() - 1]);
+    let (inbound, outbound) = result.directions.normalize(result.inbound, result.outbound);
+    items.push(Item::Range(PortRange {
+        start: first.port,
+        end: last.port,
+        category: first.category.clone(),
+        state: esbulk::state(result).to_string(),
+        inbound,
+        outbound,
+        directions: result.directions,
+        latency: latency_class(result),
+    }));
+    run.clear();
+}
+
+// 終端的區段行，例如 "Port 1024-49151 (48128 個端口): ✗ 不可用  被過濾"
+pub fn line(range: &PortRange, layout: &Layout) -> String {
+    let state = match range.state.as_str() {
+        "open" => "",
+        "closed" => "  關閉 (RST)",
+        _ => "  被過濾",
+    };
+    let latency = range.latency.map(|class| format!("  {}", class.label())).unwrap_or_default();
+    layout.clip(&format!(
+        "Port {}-{} ({} 個端口): {}{}",
+        range.start,
+        range.end,
+        range.len(),
+        portline::status_label(range.directions, range.inbound, range.outbound),
+        format!("{}{}", state, latency).dimmed()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::probes::Banner;
+    use crate::testutil::scan_result;
+
+    fn closed(ms: f64) -> ScanResult {
+        ScanResult { failure: Some(Failure::Reset { latency_ms: ms }), ..scan_result(false) }
+    }
+
+    fn filtered() -> ScanResult {
+        ScanResult { failure: Some(Failure::Timeout), ..scan_result(false) }
+    }
+
+    fn unknown(port: u16) -> PortInfo {
+        PortInfo::new(port, UNKNOWN_SERVICE, "Custom")
+    }
+
+    // 1-99 關閉 (22 是 SSH)、100-199 被過濾 (150 有橫幅)、200-204 開放 (太短)、1000-1099 關閉但較慢
+    fn sweep() -> Vec<(PortInfo, ScanResult)> {
+        let mut results = Vec::new();
+        for port in 1..100 {
+            let info = match port {
+                22 => PortInfo::new(22, "SSH", "Remote"),
+                _ => unknown(port),
+            };
+            results.push((info, closed(0.3)));
+        }
+        for port in 100..200 {
+            let mut result = filtered();
+            if port == 150 {
+                result.banner = Some(Banner {
+                    probe: "NULL".to_string(),
+                    service: None,
+                    version: None,
+                    text: "hello".to_string(),
+                    encoding: None,
+                    raw: None,
+                });
+            }
+            results.push((unknown(port), result));
+        }
+        for port in 200..205 {
+            results.push((unknown(port), ScanResult { latency_ms: Some(1.0), ..scan_result(true) }));
+        }
+        for port in 1000..1100 {
+            results.push((unknown(port), closed(if port < 1050 { 20.0 } else { 25.0 })));
+        }
+        results
+    }
+
+    fn entries(results: &[(PortInfo, ScanResult)]) -> Vec<Entry<'_>> {
+        results.iter().map(|(p, r)| (p, r)).collect()
+    }
+
+    fn ranges(items: &[Item]) -> Vec<(u16, u16, String)> {
+        items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Range(range) => Some((range.start, range.end, range.state.clone())),
+                Item::Port(_) => None,
+            })
+            .collect()
+    }
+
+    fn singles(items: &[Item]) -> Vec<u16> {
+        items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Port((port, _)) => Some(port.port),
+                Item::Range(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn known_services_and_findings_split_ranges() {
+        let results = sweep();
+        let items = aggregate(entries(&results));
+        let closed = "closed".to_string();
+        let filtered = "filtered".to_string();
+        assert_eq!(
+            ranges(&items),
+            vec![
+                (1, 21, closed.clone()),
+                (23, 99, closed.clone()),
+                (100, 149, filtered.clone()),
+                (151, 199, filtered),
+                (1000, 1099, closed),
+            ]
+        );
+        assert_eq!(singles(&items), vec![22, 150, 200, 201, 202, 203, 204]);
+        // 區段與單一端口依端口順序排列
+        let order: Vec<u16> = items
+            .iter()
+            .map(|item| match item {
+                Item::Port((port, _)) => port.port,
+                Item::Range(range) => range.start,
+            })
+            .collect();
+        assert!(order.windows(2).all(|w| w[0] < w[1]), "{:?}", order);
+    }
+
+    #[test]
+    fn ranges_need_consecutive_ports_in_one_category_and_latency_class() {
+        let mut results: Vec<_> = (1..=20).filter(|port| *port != 10).map(|port| (unknown(port), filtered())).collect();
+        for (info, _) in results.iter_mut().filter(|(info, _)| info.port > 15) {
+            info.category = "Lab".to_string();
+        }
+        // 1-9、11-15 (太短)、16-20 (另一類別，太短)
+        let items = aggregate(entries(&results));
+        assert_eq!(ranges(&items), vec![(1, 9, "filtered".to_string())]);
+        assert_eq!(singles(&items).len(), 10);
+
+        let results: Vec<_> = (1..=20).map(|port| (unknown(port), closed(if port <= 10 { 1.0 } else { 150.0 }))).collect();
+        let items = aggregate(entries(&results));
+        assert_eq!(ranges(&items), vec![(1, 10, "closed".to_string()), (11, 20, "closed".to_string())]);
+    }
+
+    // 區段展開後每個端口的狀態與原本相同，再次合併得到同樣的區段
+    #[test]
+    fn compact_and_full_forms_round_trip() {
+        let results = sweep();
+        let items = aggregate(entries(&results));
+        let mut expanded: Vec<(PortInfo, ScanResult)> = Vec::new();
+        for item in &items {
+            match item {
+                Item::Port((port, result)) => expanded.push(((*port).clone(), (*result).clone())),
+                Item::Range(range) => expanded.extend(range.expand()),
+            }
+        }
+        assert_eq!(expanded.len(), results.len());
+        for ((original, before), (port, after)) in results.iter().zip(&expanded) {
+            assert_eq!(original, port);
+            assert_eq!(esbulk::state(before), esbulk::state(after), "port {}", port.port);
+            assert_eq!(latency_class(before), latency_class(after), "port {}", port.port);
+            assert_eq!(before.directions.normalize(before.inbound, before.outbound), after.directions.normalize(after.inbound, after.outbound));
+        }
+
+        let again = aggregate(entries(&expanded));
+        assert_eq!(ranges(&again), ranges(&items));
+        assert_eq!(singles(&again), singles(&items));
+
+        // JSON 形式讀回後相同
+        let compact: Vec<&PortRange> = items.iter().filter_map(|item| match item {
+            Item::Range(range) => Some(range),
+            Item::Port(_) => None,
+        }).collect();
+        let json = serde_json::to_string(&compact).unwrap();
+        let parsed: Vec<PortRange> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.iter().collect::<Vec<_>>(), compact);
+        assert!(json.contains(r#""start":1,"end":21,"category":"Custom","state":"closed","inbound":false,"outbound":false,"latency":"fast""#), "{}", json);
+    }
+
+    #[test]
+    fn terminal_lines_show_the_span_and_state() {
+        let range = PortRange {
+            start: 1024,
+            end: 49151,
+            category: "Custom".to_string(),
+            state: "filtered".to_string(),
+            inbound: false,
+            outbound: false,
+            directions: Directions::Both,
+            latency: None,
+        };
+        assert_eq!(line(&range, &Layout::default()), "Port 1024-49151 (48128 個端口): ✗ 不可用  被過濾");
+    }
+}
@@ -0,0 +1,342 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::{PortInfo, ScanResult};
+
+// 文字報告最多列出的建議數，其餘以 "其餘 N 項" 帶過
+pub const DISPLAY_LIMIT: usize = 10;
+
+// 建議的嚴重程度，依此排序
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+}
+
+impl Severity {
+    pub fn label(self) -> ColoredString {
+        match self {
+            Severity::High => "高".red().bold(),
+            Severity::Medium => "中".yellow(),
+            Severity::Low => "低".cyan(),
+            Severity::Info => "資訊".dimmed(),
+        }
+    }
+}
+
+// 規則比對的端口狀態
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PortState {
+    // 出站可連線 (服務對外開放)
+    #[default]
+    Reachable,
+    Unreachable,
+    Any,
+}
+
+impl PortState {
+    fn matches(self, result: &ScanResult) -> bool {
+        match self {
+            PortState::Reachable => result.outbound,
+            PortState::Unreachable => !result.outbound,
+            PortState::Any => true,
+        }
+    }
+}
+
+// 設定檔的 [[recommendations]]：端口或類別 + 狀態 + 嚴重程度 -> 建議
+// message 可使用 {port}、{service}、{host}
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RecommendationRule {
+    #[serde(default)]
+    pub ports: Vec<u16>,
+    pub category: Option<String>,
+    #[serde(default)]
+    pub state: PortState,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl RecommendationRule {
+    fn builtin(ports: &[u16], severity: Severity, message: &str) -> Self {
+        RecommendationRule {
+            ports: ports.to_vec(),
+            category: None,
+            state: PortState::Reachable,
+            severity,
+            message: message.to_string(),
+        }
+    }
+
+    fn matches(&self, port: &PortInfo, result: &ScanResult) -> bool {
+        let port_matches = self.ports.is_empty() || self.ports.contains(&port.port);
+        let category_matches = self.category.as_ref().is_none_or(|c| c.eq_ignore_ascii_case(&port.category));
+        port_matches && category_matches && self.state.matches(result)
+    }
+
+    fn render(&self, host: IpAddr, port: &PortInfo) -> String {
+        self.message
+            .replace("{port}", &port.port.to_string())
+            .replace("{service}", &port.service)
+            .replace("{host}", &host.to_string())
+    }
+}
+
+// 內建規則；設定檔的規則先比對，可覆蓋相同端口的內建建議
+fn builtin_rules() -> Vec<RecommendationRule> {
+    vec![
+        RecommendationRule::builtin(&[23], Severity::High, "Port {port} (Telnet) 對外開放 — 建議停用並改用 SSH"),
+        RecommendationRule::builtin(&[21], Severity::Medium, "Port {port} ({service}) 以明文傳送帳號密碼 — 建議改用 SFTP 或 FTPS"),
+        RecommendationRule::builtin(
+            &[1433, 1521, 3306, 5432, 6379, 9200, 11211, 27017],
+            Severity::High,
+            "Port {port} ({service}) 可從外部連線 — 建議限制來源 IP",
+        ),
+        RecommendationRule::builtin(&[3389, 5900], Severity::High, "Port {port} ({service}) 對外開放 — 建議只允許經由 VPN 或跳板主機連線"),
+        RecommendationRule::builtin(&[135, 139, 445], Severity::High, "Port {port} ({service}) 對外開放 — 建議在邊界防火牆封鎖"),
+        RecommendationRule::builtin(&[2375], Severity::High, "Port {port} ({service}) 未加密的 Docker API 可取得主機控制權 — 建議立即關閉或改用 TLS"),
+        RecommendationRule::builtin(&[80, 8080], Severity::Low, "Port {port} ({service}) 使用未加密的 HTTP — 建議導向 HTTPS"),
+    ]
+}
+
+pub fn validate(rules: &[RecommendationRule]) -> Result<(), String> {
+    for (i, rule) in rules.iter().enumerate() {
+        if rule.message.trim().is_empty() {
+            return Err(format!("建議規則 #{} 缺少 message", i + 1));
+        }
+        if rule.ports.is_empty() && rule.category.is_none() {
+            return Err(format!("建議規則 #{} 必須指定 ports 或 category", i + 1));
+        }
+    }
+    Ok(())
+}
+
+// 設定檔規則加上內建規則
+#[derive(Debug, Clone)]
+pub struct Rules(Vec<RecommendationRule>);
+
+impl Rules {
+    pub fn build(configured: &[RecommendationRule]) -> Result<Self, String> {
+        validate(configured)?;
+        Ok(Rules(configured.iter().cloned().chain(builtin_rules()).collect()))
+    }
+
+    // 每個 (主機, 端口) 只採用第一條符合的規則
+    fn first_match(&self, port: &PortInfo, result: &ScanResult) -> Option<&RecommendationRule> {
+        self.0.iter().find(|rule| rule.matches(port, result))
+    }
+}
+
+// 報告中的一項建議
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Recommendation {
+    pub severity: Severity,
+    pub host: IpAddr,
+    pub port: u16,
+    pub service: String,
+    pub message: String,
+}
+
+// 疑似被攔截的端口不套用規則 (可連線不代表服務開放)，改為提醒重新掃描
+const INTERCEPTED: &str = "Port {port} ({service}) 的連線疑似被透明代理或強制門戶攔截 — 結果不可信，建議登入門戶或改用其他網路後重新掃描";
+
+// 依最終結果產生建議，依嚴重程度、主機、端口排序
+pub fn evaluate(rules: &Rules, results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>) -> Vec<Recommendation> {
+    let intercepted = RecommendationRule::builtin(&[], Severity::Medium, INTERCEPTED);
+    let mut found = Vec::new();
+    for (host, ports) in results {
+        for (port, result) in ports {
+            let rule = match result.intercepted {
+                true => Some(&intercepted),
+                false => rules.first_match(port, result),
+            };
+            if let Some(rule) = rule {
+                found.push(Recommendation {
+                    severity: rule.severity,
+                    host: *host,
+                    port: port.port,
+                    service: port.service.clone(),
+                    message: rule.render(*host, port),
+                });
+            }
+        }
+    }
+    sort(&mut found);
+    found
+}
+
+// 依嚴重程度 (高到低)、主機、端口排序
+pub fn sort(recommendations: &mut [Recommendation]) {
+    recommendations.sort_by(|a, b| b.severity.cmp(&a.severity).then(a.host.cmp(&b.host)).then(a.port.cmp(&b.port)));
+}
+
+// 編號列出建議；多目標時標示主機
+pub fn display(recommendations: &[Recommendation], show_host: bool) {
+    if recommendations.is_empty() {
+        return;
+    }
+    println!("\n{}", "=== 建議事項 ===".bold());
+    for (i, item) in recommendations.iter().take(DISPLAY_LIMIT).enumerate() {
+        let host = if show_host { format!("{} ", item.host) } else { String::new() };
+        println!("{:>2}. [{}] {}{}", i + 1, item.severity.label(), host.dimmed(), item.message);
+    }
+    if recommendations.len() > DISPLAY_LIMIT {
+        println!("    {}", format!("其餘 {} 項 (完整清單見 --json 的 recommendations)", recommendations.len() - DISPLAY_LIMIT).dimmed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{host, scan_result};
+
+    #[derive(Deserialize)]
+    struct Config {
+        recommendations: Vec<RecommendationRule>,
+    }
+
+    fn rules(toml_text: &str) -> Rules {
+        let config: Config = toml::from_str(toml_text).unwrap();
+        Rules::build(&config.recommendations).unwrap()
+    }
+
+    // 端口, 服務, 類別, 是否開放
+    type Port<'a> = (u16, &'a str, &'a str, bool);
+
+    fn results(hosts: &[(u8, &[Port])]) -> BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>> {
+        hosts
+            .iter()
+            .map(|(n, ports)| {
+                let ports = ports
+                    .iter()
+                    .map(|&(port, service, category, open)| (PortInfo::new(port, service, category), scan_result(open)))
+                    .collect();
+                (host(*n), ports)
+            })
+            .collect()
+    }
+
+    fn summary(found: &[Recommendation]) -> Vec<(Severity, u8, u16)> {
+        found
+            .iter()
+            .map(|r| match r.host {
+                IpAddr::V4(v4) => (r.severity, v4.octets()[3], r.port),
+                IpAddr::V6(_) => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn builtin_rules_flag_reachable_risky_ports() {
+        let rules = Rules::build(&[]).unwrap();
+        let found = evaluate(
+            &rules,
+            &results(&[
+                (1, &[(23, "Telnet", "Remote", true), (80, "HTTP", "Web", true), (443, "HTTPS", "Web", true)]),
+                (2, &[(3306, "MySQL", "Database", true), (23, "Telnet", "Remote", false)]),
+            ]),
+        );
+        // 依嚴重程度、主機、端口排序；關閉的 Telnet 與沒有規則的 443 不列入
+        assert_eq!(summary(&found), vec![(Severity::High, 1, 23), (Severity::High, 2, 3306), (Severity::Low, 1, 80)]);
+        assert_eq!(found[0].message, "Port 23 (Telnet) 對外開放 — 建議停用並改用 SSH");
+        assert_eq!(found[1].message, "Port 3306 (MySQL) 可從外部連線 — 建議限制來源 IP");
+        assert_eq!(found[1].service, "MySQL");
+    }
+
+    #[test]
+    fn intercepted_ports_ask_for_a_rescan_instead_of_matching_rules() {
+        let rules = Rules::build(&[]).unwrap();
+        let mut scanned = results(&[(1, &[(80, "HTTP", "Web", true), (23, "Telnet", "Remote", true)])]);
+        for (port, result) in scanned.values_mut().flatten() {
+            result.intercepted = port.port == 80;
+        }
+        let found = evaluate(&rules, &scanned);
+        assert_eq!(summary(&found), vec![(Severity::High, 1, 23), (Severity::Medium, 1, 80)]);
+        assert!(found[1].message.starts_with("Port 80 (HTTP) 的連線疑似被透明代理或強制門戶攔截"), "{}", found[1].message);
+    }
+
+    #[test]
+    fn configured_rules_come_before_builtins() {
+        let rules = rules(
+            r#"
+            [[recommendations]]
+            ports = [23]
+            severity = "medium"
+            message = "{host}:{port} 的 {service} 僅限機房內部使用"
+
+            [[recommendations]]
+            category = "database"
+            severity = "info"
+            message = "資料庫 {service}"
+
+            [[recommendations]]
+            ports = [443]
+            state = "unreachable"
+            severity = "low"
+            message = "HTTPS 無法連線"
+            "#,
+        );
+        let found = evaluate(
+            &rules,
+            &results(&[(
+                1,
+                &[
+                    (23, "Telnet", "Remote", true),
+                    (3306, "MySQL", "Database", true),
+                    (5433, "Postgres", "Database", false),
+                    (443, "HTTPS", "Web", false),
+                ],
+            )]),
+        );
+        assert_eq!(summary(&found), vec![(Severity::Medium, 1, 23), (Severity::Low, 1, 443), (Severity::Info, 1, 3306)]);
+        assert_eq!(found[0].message, "192.0.2.1:23 的 Telnet 僅限機房內部使用");
+        // 類別不分大小寫，且覆蓋了 3306 的內建建議
+        assert_eq!(found[2].message, "資料庫 MySQL");
+    }
+
+    #[test]
+    fn any_state_matches_closed_ports_too() {
+        let rules = rules(
+            r#"
+            [[recommendations]]
+            ports = [9]
+            state = "any"
+            severity = "info"
+            message = "discard"
+            "#,
+        );
+        let found = evaluate(&rules, &results(&[(1, &[(9, "Discard", "Other", false)]), (2, &[(9, "Discard", "Other", true)])]));
+        assert_eq!(summary(&found), vec![(Severity::Info, 1, 9), (Severity::Info, 2, 9)]);
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        let rule = |ports: Vec<u16>, category: Option<&str>, message: &str| RecommendationRule {
+            ports,
+            category: category.map(str::to_string),
+            state: PortState::Reachable,
+            severity: Severity::Low,
+            message: message.to_string(),
+        };
+        assert!(validate(&[rule(vec![22], None, "ok"), rule(Vec::new(), Some("Web"), "ok")]).is_ok());
+        assert_eq!(validate(&[rule(vec![22], None, "ok"), rule(vec![23], None, "  ")]).unwrap_err(), "建議規則 #2 缺少 message");
+        assert_eq!(Rules::build(&[rule(Vec::new(), None, "x")]).unwrap_err(), "建議規則 #1 必須指定 ports 或 category");
+        assert!(toml::from_str::<Config>("[[recommendations]]\nports = [1]\nseverity = \"urgent\"\nmessage = \"x\"").is_err());
+        assert!(toml::from_str::<Config>("[[recommendations]]\nport = 1\nseverity = \"low\"\nmessage = \"x\"").is_err());
+    }
+
+    #[test]
+    fn sorting_is_by_severity_then_host_then_port() {
+        let item = |severity, n, port| Recommendation { severity, host: host(n), port, service: String::new(), message: String::new() };
+        let mut items = vec![item(Severity::Low, 1, 80), item(Severity::High, 2, 23), item(Severity::High, 1, 445), item(Severity::High, 1, 23)];
+        sort(&mut items);
+        assert_eq!(summary(&items), vec![(Severity::High, 1, 23), (Severity::High, 1, 445), (Severity::High, 2, 23), (Severity::Low, 1, 80)]);
+    }
+}
@@ -0,0 +1,62 @@
+use std::fs;
+use std::path::Path;
+use handlebars::{handlebars_helper, Handlebars, TemplateError};
+use serde::Serialize;
+use crate::report::ScanReport;
+use crate::share::Summary;
+use crate::timefmt;
+
+const TEMPLATE_NAME: &str = "report";
+
+// --format-template 的自訂文字輸出 (Handlebars 語法)
+//
+// 範本的資料與 --json 報告相同，另外加上 summary：
+//   {{report.metadata.hostname}}、{{#each report.hosts}}...{{/each}}、{{summary.line}}
+// 雙大括號輸出 HTML 跳脫後的字串，三大括號 {{{ }}} 輸出原始字串 (例如橫幅內容)
+// 另提供 {{fixed 數值 位數}} 與 {{time Unix秒}} (依 --time-format) 輔助函式
+pub struct TextTemplate {
+    registry: Handlebars<'static>,
+}
+
+// {{fixed latency_ms 1}}：固定小數位數
+handlebars_helper!(fixed: |value: f64, digits: u64| format!("{:.*}", digits as usize, value));
+
+// {{time report.metadata.started_at}}：依 --time-format 顯示時間戳
+handlebars_helper!(time: |secs: i64| timefmt::timestamp(secs));
+
+// 範本的資料
+#[derive(Serialize)]
+struct Context<'a> {
+    report: &'a ScanReport<'a>,
+    summary: &'a Summary,
+}
+
+impl TextTemplate {
+    // 啟動時載入並編譯，語法錯誤附上行號與欄位
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("無法讀取輸出範本 {}: {}", path.display(), e))?;
+        let mut registry = Handlebars::new();
+        registry.register_helper("fixed", Box::new(fixed));
+        registry.register_helper("time", Box::new(time));
+        registry
+            .register_template_string(TEMPLATE_NAME, text)
+            .map_err(|e| describe_error(path, &e))?;
+        Ok(TextTemplate { registry })
+    }
+
+    pub fn render(&self, report: &ScanReport, summary: &Summary) -> Result<String, String> {
+        self.registry
+            .render(TEMPLATE_NAME, &Context { report, summary })
+            .map_err(|e| match (e.line_no, e.column_no) {
+                (Some(line), Some(column)) => format!("輸出範本第 {} 行第 {} 欄: {}", line, column, e.reason()),
+                _ => format!("輸出範本: {}", e.reason()),
+            })
+    }
+}
+
+fn describe_error(path: &Path, error: &TemplateError) -> String {
+    match error.pos() {
+        Some((line, column)) => format!("{}:{}:{}: 範本語法錯誤: {}", path.display(), line, column, error.reason()),
+        _ => format!("{}: 範本語法錯誤: {}", path.display(), error.reason()),
+    }
+}
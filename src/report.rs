@@ -0,0 +1,543 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use schemars::generate::SchemaSettings;
+use schemars::JsonSchema;
+use serde::{Serialize, Serializer};
+use crate::bundles::BundleVerdict;
+use crate::checks::CheckOutcome;
+use crate::cli::SchemaKind;
+use crate::closure::CloseBehavior;
+use crate::cloud::CloudReport;
+use crate::direction;
+use crate::groups::{self, GroupSummary};
+use crate::heartbeat::ProgressEvent;
+use crate::progressfile::ProgressDocument;
+use crate::identity::Identity;
+use crate::metadata::RunMetadata;
+use crate::osguess::OsGuess;
+use crate::plan::PlanReport;
+use crate::localsock::LocalSocket;
+use crate::manifest::ManifestReport;
+use crate::merge::MergedReport;
+use crate::natpmp::PortMapping;
+use crate::expand::Expansion;
+use crate::fingerprints::Reconciliation;
+use crate::netblocks::BlockSummary;
+use crate::resources::ResourceUsage;
+use crate::conntrack::ConntrackSummary;
+use crate::egress::EgressReport;
+use crate::ephemeral::PressureSummary;
+use crate::policy::PolicyReport;
+use crate::ranges::{self, Item, PortRange};
+use crate::recommend::Recommendation;
+use crate::threats::Suspicious;
+use crate::tarpit::TarpitAssessment;
+use crate::scanner::ScanRecord;
+use crate::shodan::Exposure;
+use crate::signing::ReportSignature;
+use crate::strict::ScannerError;
+use crate::targets::TargetSpec;
+use crate::whois::{self, WhoisInfo};
+use crate::wol::WakeReport;
+use crate::{PortInfo, ScanResult};
+
+// JSON 輸出格式版本；只做向下相容的新增欄位時不變，移除或改變欄位意義時遞增
+pub const SCHEMA_VERSION: u32 = 1;
+
+// 省略結果未測試的方向
+fn tested<S: Serializer>(result: &&ScanResult, serializer: S) -> Result<S::Ok, S::Error> {
+    direction::serialize_tested(result, serializer)
+}
+
+// JSON 報告中的單個端口
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PortReport<'a> {
+    #[serde(flatten)]
+    pub port: &'a PortInfo,
+    #[serde(flatten, serialize_with = "tested")]
+    pub result: &'a ScanResult,
+}
+
+// JSON 報告中的單個主機
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct HostReport<'a> {
+    pub host: IpAddr,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub whois: Option<&'a WhoisInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub whois_error: Option<&'a str>,
+    // 未連線端口的 RST / 逾時統計
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub close_behavior: Option<CloseBehavior>,
+    // 開放比例異常高時的 honeypot / tarpit 判斷
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tarpit: Option<&'a TarpitAssessment>,
+    // --syn 時依 SYN-ACK 特徵猜測的作業系統
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os_guess: Option<&'a OsGuess>,
+    // --host-id、服務清單、目標名稱或反查 DNS 指定的穩定識別
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identity: Option<&'a Identity>,
+    // --group 服務群組的成員統計；各成員端口也列在 ports 中
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<GroupSummary>,
+    pub ports: Vec<PortReport<'a>>,
+    // --aggregate-ranges：狀態與延遲等級相同的連續端口，這些端口不再列在 ports 中
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ranges: Vec<PortRange>,
+}
+
+// JSON 報告中的服務檢查結果
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CheckReport<'a> {
+    pub host: IpAddr,
+    #[serde(flatten)]
+    pub outcome: &'a CheckOutcome,
+}
+
+// 完整的 JSON 掃描報告
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ScanReport<'a> {
+    pub schema_version: u32,
+    pub metadata: &'a RunMetadata,
+    pub external_ip: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tor_exit_ip: Option<&'a str>,
+    pub hosts: Vec<HostReport<'a>>,
+    pub checks: Vec<CheckReport<'a>>,
+    // 掃描前的連線檢查全部失敗，結果可能反映網路中斷而非防火牆
+    pub network_suspect: bool,
+    // --template / --policy 的檢查結果
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub policy: Option<&'a PolicyReport>,
+    // --manifest 的服務清單比對
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manifest: Option<&'a ManifestReport>,
+    // --egress-policy 的合規報告：允許與不允許的出站路徑
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub egress: Option<&'a EgressReport>,
+    // 依結果產生的建議事項，依嚴重程度排序
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub recommendations: &'a [Recommendation],
+    // 任一方向可用、與惡意程式或後門相關的端口
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub suspicious: &'a [Suspicious],
+    // --cloud / --detect-cloud 的雲端平台與平台註記
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cloud: Option<&'a CloudReport>,
+    // 設定檔 [[bundles]] 的服務組合結果 (每台主機每個組合一項)
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub bundles: &'a [BundleVerdict],
+    // --unix-sockets 的本機 Unix domain socket / 具名管道
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_sockets: Option<&'a [LocalSocket]>,
+    // --nat-pmp 的閘道 NAT-PMP / PCP 狀態
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port_mapping: Option<&'a PortMapping>,
+    // --wol 的喚醒結果
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wake: Option<&'a WakeReport>,
+    // --blocks 的網段彙總
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blocks: Option<&'a [BlockSummary]>,
+    // 萬用字元目標的展開結果 (名稱與位址的對應)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expansions: Option<&'a [Expansion]>,
+    // --resource-report 的掃描器資源用量
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<&'a ResourceUsage>,
+    // 接近上限或自動暫停排程時的本機端口壓力
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port_pressure: Option<&'a PressureSummary>,
+    // --conntrack 與連線追蹤表的比對
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conntrack: Option<&'a ConntrackSummary>,
+    // --shodan：外部掃描器對外部 IP 的觀察與入站測試的對照
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shodan: Option<&'a Exposure>,
+    // --fingerprint-db 的服務指紋比對
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprints: Option<&'a Reconciliation>,
+    // --strict 的掃描端錯誤 (檔案描述符用盡、DNS 解析器無法使用、探測異常中止)；沒有錯誤時為空陣列
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scanner_errors: Option<&'a [ScannerError]>,
+    // --sign 的簽章，涵蓋此欄位以外的整份報告
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<ReportSignature>,
+}
+
+// 依掃描、WHOIS 與服務檢查結果建立報告
+pub fn build<'a>(
+    metadata: &'a RunMetadata,
+    external_ip: Option<&'a str>,
+    tor_exit_ip: Option<&'a str>,
+    results: &'a BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>,
+    whois: &'a [(TargetSpec, Result<WhoisInfo, String>)],
+    checks: &'a [(IpAddr, Vec<CheckOutcome>)],
+    policy: Option<&'a PolicyReport>,
+) -> ScanReport<'a> {
+    let hosts = results
+        .iter()
+        .map(|(host, ports)| {
+            let close_behavior = CloseBehavior::from_results(ports);
+            let groups = groups::summarize(ports);
+            let mut ports: Vec<PortReport> = ports.iter().map(|(port, result)| PortReport { port, result }).collect();
+            ports.sort_by_key(|p| p.port.port);
+
+            let lookup = whois::for_host(whois, *host);
+            HostReport {
+                host: *host,
+                whois: lookup.and_then(|r| r.as_ref().ok()),
+                whois_error: lookup.and_then(|r| r.as_ref().err()).map(String::as_str),
+                close_behavior,
+                tarpit: None,
+                os_guess: None,
+                identity: None,
+                groups,
+                ports,
+                ranges: Vec::new(),
+            }
+        })
+        .collect();
+
+    let checks = checks
+        .iter()
+        .flat_map(|(host, outcomes)| outcomes.iter().map(|outcome| CheckReport { host: *host, outcome }))
+        .collect();
+
+    ScanReport {
+        schema_version: SCHEMA_VERSION,
+        metadata,
+        external_ip,
+        tor_exit_ip,
+        hosts,
+        checks,
+        network_suspect: false,
+        policy,
+        manifest: None,
+        egress: None,
+        recommendations: &[],
+        suspicious: &[],
+        cloud: None,
+        bundles: &[],
+        local_sockets: None,
+        port_mapping: None,
+        wake: None,
+        blocks: None,
+        expansions: None,
+        resources: None,
+        port_pressure: None,
+        conntrack: None,
+        shodan: None,
+        fingerprints: None,
+        scanner_errors: None,
+        signature: None,
+    }
+}
+
+// --aggregate-ranges 的精簡報告：各主機的連續端口改列為區段，有服務名稱或額外發現的端口照常列出
+pub fn aggregate_ranges(report: &mut ScanReport) {
+    for host in &mut report.hosts {
+        let entries = host.ports.drain(..).map(|p| (p.port, p.result)).collect();
+        for item in ranges::aggregate(entries) {
+            match item {
+                Item::Port((port, result)) => host.ports.push(PortReport { port, result }),
+                Item::Range(range) => host.ranges.push(range),
+            }
+        }
+    }
+}
+
+// 輸出指定文件類型的 JSON Schema (依序列化結果描述，省略的欄位不列為必要)
+pub fn schema(kind: SchemaKind) -> schemars::Schema {
+    let generator = SchemaSettings::default().for_serialize().into_generator();
+    match kind {
+        SchemaKind::Report => generator.into_root_schema_for::<ScanReport<'static>>(),
+        SchemaKind::Record => generator.into_root_schema_for::<ScanRecord>(),
+        SchemaKind::Progress => generator.into_root_schema_for::<ProgressEvent>(),
+        SchemaKind::ProgressFile => generator.into_root_schema_for::<ProgressDocument>(),
+        SchemaKind::Plan => generator.into_root_schema_for::<PlanReport>(),
+        SchemaKind::Merge => generator.into_root_schema_for::<MergedReport>(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use serde_json::{json, Value};
+    use crate::checks::CheckStatus;
+    use crate::closure::Failure;
+    use crate::errors::ErrorCode;
+    use crate::grade::{self, GradingConfig};
+    use crate::limits::ScanError;
+    use crate::merge;
+    use crate::probes::Banner;
+    use crate::progressfile::State;
+    use crate::syn::SynState;
+    use crate::testutil::scan_result;
+    use crate::verify::Verification;
+
+    // 測試用的 JSON Schema 驗證：涵蓋 schemars 產生的關鍵字 ($ref、型別、列舉、組合、物件與陣列)
+    fn validate(root: &Value, schema: &Value, value: &Value, at: &str) -> Result<(), String> {
+        let schema = match schema {
+            Value::Bool(true) => return Ok(()),
+            Value::Bool(false) => return Err(format!("{}: 不允許任何值", at)),
+            Value::Object(schema) => schema,
+            _ => return Err(format!("{}: 無效的 schema", at)),
+        };
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            let target = root.pointer(reference.trim_start_matches('#')).ok_or(format!("{}: 找不到 {}", at, reference))?;
+            validate(root, target, value, at)?;
+        }
+        if let Some(types) = schema.get("type") {
+            let types: Vec<&str> = match types {
+                Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+                other => other.as_str().into_iter().collect(),
+            };
+            let matches = |kind: &str| match kind {
+                "null" => value.is_null(),
+                "boolean" => value.is_boolean(),
+                "object" => value.is_object(),
+                "array" => value.is_array(),
+                "string" => value.is_string(),
+                "number" => value.is_number(),
+                "integer" => value.is_i64() || value.is_u64(),
+                _ => false,
+            };
+            if !types.iter().any(|kind| matches(kind)) {
+                return Err(format!("{}: {} 不是 {:?}", at, value, types));
+            }
+        }
+        if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+            if !allowed.contains(value) {
+                return Err(format!("{}: {} 不在列舉中", at, value));
+            }
+        }
+        if let Some(expected) = schema.get("const") {
+            if expected != value {
+                return Err(format!("{}: {} 不是 {}", at, value, expected));
+            }
+        }
+        if let (Some(minimum), Some(n)) = (schema.get("minimum").and_then(Value::as_f64), value.as_f64()) {
+            if n < minimum {
+                return Err(format!("{}: {} 小於 {}", at, n, minimum));
+            }
+        }
+        if let (Some(maximum), Some(n)) = (schema.get("maximum").and_then(Value::as_f64), value.as_f64()) {
+            if n > maximum {
+                return Err(format!("{}: {} 大於 {}", at, n, maximum));
+            }
+        }
+        if let Some(all) = schema.get("allOf").and_then(Value::as_array) {
+            for branch in all {
+                validate(root, branch, value, at)?;
+            }
+        }
+        if let Some(any) = schema.get("anyOf").and_then(Value::as_array) {
+            if !any.iter().any(|branch| validate(root, branch, value, at).is_ok()) {
+                return Err(format!("{}: {} 不符合 anyOf 的任何一項", at, value));
+            }
+        }
+        if let Some(one) = schema.get("oneOf").and_then(Value::as_array) {
+            let matched = one.iter().filter(|branch| validate(root, branch, value, at).is_ok()).count();
+            if matched != 1 {
+                return Err(format!("{}: {} 符合 oneOf 的 {} 項", at, value, matched));
+            }
+        }
+        if let Value::Object(object) = value {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for key in schema.get("required").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str) {
+                if !object.contains_key(key) {
+                    return Err(format!("{}: 缺少 {}", at, key));
+                }
+            }
+            for (key, field) in object {
+                let at = format!("{}/{}", at, key);
+                match (properties.and_then(|p| p.get(key)), schema.get("additionalProperties")) {
+                    (Some(property), _) => validate(root, property, field, &at)?,
+                    (None, Some(extra)) => validate(root, extra, field, &at)?,
+                    (None, None) => {}
+                }
+            }
+        }
+        if let (Value::Array(items), Some(item)) = (value, schema.get("items")) {
+            for (index, element) in items.iter().enumerate() {
+                validate(root, item, element, &format!("{}/{}", at, index))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check(kind: SchemaKind, value: &Value) -> Result<(), String> {
+        let schema = serde_json::to_value(schema(kind)).unwrap();
+        validate(&schema, &schema, value, "")
+    }
+
+    // 涵蓋常見欄位的結果：開放、被拒、掃描端錯誤、未測試入站
+    fn representative() -> BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>> {
+        let grading = GradingConfig::default();
+        let mut open = scan_result(true);
+        open.inbound = true;
+        open.latency_ms = Some(12.5);
+        open.banner = Some(Banner {
+            probe: "NULL".to_string(),
+            service: Some("ssh".to_string()),
+            version: Some("OpenSSH_9.6".to_string()),
+            text: "SSH-2.0-OpenSSH_9.6".to_string(),
+            encoding: None,
+            raw: None,
+        });
+        open.syn = Some(SynState::Open);
+        open.confidence = Some(0.97);
+        open.confirmations = 2;
+        open.verification = Some(Verification::Confirmed);
+        open.grade = grade::grade_result(&open, None, &grading);
+
+        let mut refused = scan_result(false);
+        refused.failure = Some(Failure::Reset { latency_ms: 3.0 });
+        refused.codes = vec![ErrorCode::BindAddressInUse];
+        refused.note = Some("可能被出口節點封鎖".to_string());
+        refused.grade = grade::grade_result(&refused, None, &grading);
+
+        let mut failed = scan_result(false);
+        failed.error = Some(ScanError::TooManyOpenFiles);
+        failed.failure = Some(Failure::Timeout);
+
+        let mut results = BTreeMap::new();
+        results.insert(
+            "192.0.2.1".parse().unwrap(),
+            HashMap::from([
+                (PortInfo::new(22, "SSH", "Remote"), open),
+                (PortInfo::new(80, "HTTP", "Web"), refused),
+                (PortInfo::new(443, "HTTPS", "Web"), failed),
+            ]),
+        );
+        results.insert("2001:db8::1".parse().unwrap(), HashMap::from([(PortInfo::new(53, "DNS", "Infra"), scan_result(false))]));
+        results
+    }
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/fixtures/compat").join(name)
+    }
+
+    #[test]
+    fn report_matches_its_schema() {
+        let metadata = RunMetadata { started_at: 1_700_000_000, ..RunMetadata::collect(&[("ticket".to_string(), "OPS-1".to_string())]) };
+        let results = representative();
+        let whois = [(
+            TargetSpec::Host { name: "192.0.2.1".to_string(), addr: "192.0.2.1".parse().unwrap() },
+            Ok(WhoisInfo { netname: Some("TEST-NET-1".to_string()), org: None, abuse: None, server: "whois.example".to_string() }),
+        )];
+        let outcome = CheckOutcome::new("SSH", 22, CheckStatus::Ok, "可連線").detail("版本", "OpenSSH_9.6");
+        let checks = [("192.0.2.1".parse().unwrap(), vec![outcome])];
+        let report = build(&metadata, Some("198.51.100.7"), None, &results, &whois, &checks, None);
+        let value = serde_json::to_value(&report).unwrap();
+        assert_eq!(value["schema_version"], json!(SCHEMA_VERSION));
+        check(SchemaKind::Report, &value).unwrap();
+
+        // 驗證本身會拒絕不符合的文件
+        let mut broken = value.clone();
+        broken.as_object_mut().unwrap().remove("schema_version");
+        assert!(check(SchemaKind::Report, &broken).unwrap_err().contains("schema_version"));
+        let mut broken = value;
+        broken["hosts"][0]["ports"][0]["port"] = json!("22");
+        assert!(check(SchemaKind::Report, &broken).is_err());
+    }
+
+    #[test]
+    fn records_match_their_schema() {
+        for (host, ports) in representative() {
+            for (port, result) in ports {
+                let record = ScanRecord { host, port, result, identity: Some("web-1".to_string()) };
+                check(SchemaKind::Record, &serde_json::to_value(&record).unwrap()).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn progress_events_match_their_schema() {
+        let event = ProgressEvent { event: crate::heartbeat::EventKind::Progress, completed: 3, total: 10, rate: 1.5, eta_secs: Some(5), in_flight: 2, elapsed_ms: 2000, done: false };
+        check(SchemaKind::Progress, &serde_json::to_value(&event).unwrap()).unwrap();
+        let done = ProgressEvent { eta_secs: None, done: true, ..event };
+        check(SchemaKind::Progress, &serde_json::to_value(&done).unwrap()).unwrap();
+        let mut untagged = serde_json::to_value(&done).unwrap();
+        untagged["event"] = "record".into();
+        assert!(check(SchemaKind::Progress, &untagged).is_err());
+    }
+
+    #[test]
+    fn progress_files_match_their_schema() {
+        let finding = crate::heartbeat::Finding { host: "192.0.2.1".to_string(), port: 443, service: "HTTPS".to_string() };
+        let document = ProgressDocument {
+            scan_id: "scan-1".to_string(),
+            state: State::Running,
+            completed: 3,
+            total: 10,
+            rate: 1.5,
+            eta_secs: Some(5),
+            started_at: "2024-01-01T00:00:00Z".to_string(),
+            updated_at: "2024-01-01T00:00:02Z".to_string(),
+            findings: vec![finding],
+        };
+        check(SchemaKind::ProgressFile, &serde_json::to_value(&document).unwrap()).unwrap();
+        let done = ProgressDocument { state: State::Cancelled, eta_secs: None, findings: Vec::new(), ..document };
+        check(SchemaKind::ProgressFile, &serde_json::to_value(&done).unwrap()).unwrap();
+        let mut unknown = serde_json::to_value(&done).unwrap();
+        unknown["state"] = "paused".into();
+        assert!(check(SchemaKind::ProgressFile, &unknown).is_err());
+    }
+
+    #[test]
+    fn old_records_still_deserialize() {
+        let text = std::fs::read_to_string(fixture("records-v0.jsonl")).unwrap();
+        let records: Vec<ScanRecord> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(records.len(), 3);
+        assert!(records[0].result.outbound && records[0].result.inbound);
+        assert_eq!(records[1].result.failure, Some(Failure::Timeout));
+        assert_eq!(records[2].port.service, "HTTPS");
+        // 舊紀錄寫出後仍符合目前的 schema
+        for record in &records {
+            check(SchemaKind::Record, &serde_json::to_value(record).unwrap()).unwrap();
+        }
+    }
+
+    #[test]
+    fn old_reports_still_merge() {
+        let merged = merge::merge(&[fixture("report-v0.json"), fixture("report-v1.json")]).unwrap();
+        assert!(merged.warnings.iter().any(|w| w.contains("沒有 schema_version")));
+        assert_eq!(merged.hosts.len(), 1);
+        assert_eq!(merged.hosts[0].ports.len(), 2);
+    }
+
+    // --aggregate-ranges 的精簡報告符合 schema，合併時與完整報告得到相同的端口狀態
+    #[test]
+    fn compact_reports_merge_like_full_reports() {
+        let mut ports = representative().remove(&"192.0.2.1".parse().unwrap()).unwrap();
+        for port in 1000..1100 {
+            let failure = if port < 1050 { Failure::Reset { latency_ms: 0.4 } } else { Failure::Timeout };
+            ports.insert(PortInfo::new(port, ranges::UNKNOWN_SERVICE, "Custom"), ScanResult { failure: Some(failure), ..scan_result(false) });
+        }
+        let results = BTreeMap::from([("192.0.2.1".parse().unwrap(), ports)]);
+        let metadata = RunMetadata::collect(&[]);
+        let full = build(&metadata, None, None, &results, &[], &[], None);
+        let mut compact = build(&metadata, None, None, &results, &[], &[], None);
+        aggregate_ranges(&mut compact);
+        assert_eq!(compact.hosts[0].ports.len(), 3);
+        assert_eq!(compact.hosts[0].ranges.iter().map(|r| (r.start, r.end)).collect::<Vec<_>>(), vec![(1000, 1049), (1050, 1099)]);
+        let value = serde_json::to_value(&compact).unwrap();
+        check(SchemaKind::Report, &value).unwrap();
+        assert_eq!(value["hosts"][0]["ranges"][1], json!({ "start": 1050, "end": 1099, "category": "Custom", "state": "filtered", "inbound": false, "outbound": false }));
+
+        let dir = crate::testutil::TempDir::new("compact-merge");
+        let (full_path, compact_path) = (dir.path().join("full.json"), dir.path().join("compact.json"));
+        std::fs::write(&full_path, serde_json::to_string(&full).unwrap()).unwrap();
+        std::fs::write(&compact_path, value.to_string()).unwrap();
+        let states = |path: &PathBuf| -> Vec<(u16, merge::Consensus)> {
+            let merged = merge::merge(std::slice::from_ref(path)).unwrap();
+            merged.hosts[0].ports.iter().map(|p| (p.port, p.consensus)).collect()
+        };
+        assert_eq!(states(&compact_path).len(), 103);
+        assert_eq!(states(&compact_path), states(&full_path));
+    }
+}
@@ -0,0 +1,227 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use colored::*;
+use schemars::JsonSchema;
+use serde::Serialize;
+use tokio::task::JoinHandle;
+
+// 取樣檔案描述符數的間隔
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+// 探測層的計數；只有 --resource-report 時才建立，放在 ScanContext 中
+#[derive(Debug, Default)]
+pub struct ProbeCounters {
+    sockets: AtomicU64,
+    peak_sockets: AtomicU64,
+    sent: AtomicU64,
+    received: AtomicU64,
+}
+
+impl ProbeCounters {
+    // 探測送出的應用層位元組 (不含 TCP/IP 標頭)
+    pub fn sent(&self, bytes: usize) {
+        self.sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    // 探測收到的應用層位元組
+    pub fn received(&self, bytes: usize) {
+        self.received.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    // 探測使用中的 socket；離開作用域時減回
+    pub fn socket(self: &Arc<Self>) -> SocketGuard {
+        let now = self.sockets.fetch_add(1, Ordering::Relaxed) + 1;
+        self.peak_sockets.fetch_max(now, Ordering::Relaxed);
+        SocketGuard(Some(self.clone()))
+    }
+}
+
+// 使用中的探測 socket；未計數時為空
+pub struct SocketGuard(Option<Arc<ProbeCounters>>);
+
+impl SocketGuard {
+    pub fn none() -> Self {
+        SocketGuard(None)
+    }
+}
+
+impl Drop for SocketGuard {
+    fn drop(&mut self) {
+        if let Some(counters) = &self.0 {
+            counters.sockets.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// --resource-report 的摘要；平台不支援的項目為 None
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ResourceUsage {
+    pub wall_ms: u64,
+    // 使用者與核心 CPU 時間合計
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peak_rss_bytes: Option<u64>,
+    // 取樣得到的最大檔案描述符數 (Windows 為 handle 數)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peak_fds: Option<u64>,
+    // 同時進行中的探測 socket (連線、橫幅與 UDP 檢查)
+    pub peak_sockets: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+// 從啟動到 finish 之間的資源用量
+pub struct ResourceMonitor {
+    counters: Arc<ProbeCounters>,
+    started: Instant,
+    cpu_start: Option<Duration>,
+    peak_fds: Arc<AtomicU64>,
+    sampler: JoinHandle<()>,
+}
+
+impl ResourceMonitor {
+    pub fn start(counters: Arc<ProbeCounters>) -> Self {
+        let peak_fds = Arc::new(AtomicU64::new(0));
+        let peak = peak_fds.clone();
+        let sampler = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                if let Some(fds) = platform::open_fds() {
+                    peak.fetch_max(fds, Ordering::Relaxed);
+                }
+            }
+        });
+        ResourceMonitor { counters, started: Instant::now(), cpu_start: platform::usage().map(|u| u.cpu), peak_fds, sampler }
+    }
+
+    pub fn finish(self) -> ResourceUsage {
+        self.sampler.abort();
+        if let Some(fds) = platform::open_fds() {
+            self.peak_fds.fetch_max(fds, Ordering::Relaxed);
+        }
+        let usage = platform::usage();
+        let peak_fds = self.peak_fds.load(Ordering::Relaxed);
+        ResourceUsage {
+            wall_ms: self.started.elapsed().as_millis() as u64,
+            cpu_ms: usage.as_ref().map(|u| u.cpu.saturating_sub(self.cpu_start.unwrap_or_default()).as_millis() as u64),
+            peak_rss_bytes: usage.map(|u| u.peak_rss),
+            peak_fds: (peak_fds > 0).then_some(peak_fds),
+            peak_sockets: self.counters.peak_sockets.load(Ordering::Relaxed),
+            bytes_sent: self.counters.sent.load(Ordering::Relaxed),
+            bytes_received: self.counters.received.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl std::fmt::Debug for ResourceMonitor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResourceMonitor").field("started", &self.started).finish()
+    }
+}
+
+pub fn bytes(n: u64) -> String {
+    match n {
+        n if n >= 1 << 30 => format!("{:.1} GiB", n as f64 / (1u64 << 30) as f64),
+        n if n >= 1 << 20 => format!("{:.1} MiB", n as f64 / (1u64 << 20) as f64),
+        n if n >= 1 << 10 => format!("{:.1} KiB", n as f64 / (1u64 << 10) as f64),
+        n => format!("{} B", n),
+    }
+}
+
+pub fn display(usage: &ResourceUsage) {
+    println!("\n{}", "=== 資源用量 ===".bold());
+    let unknown = || "-".dimmed().to_string();
+    let cpu = usage.cpu_ms.map(|ms| {
+        let share = ms as f64 / usage.wall_ms.max(1) as f64 * 100.0;
+        format!("{:.2}s ({:.0}% 單核)", ms as f64 / 1000.0, share)
+    });
+    println!("經過時間: {:.2}s", usage.wall_ms as f64 / 1000.0);
+    println!("CPU 時間: {}", cpu.unwrap_or_else(unknown));
+    println!("最大常駐記憶體: {}", usage.peak_rss_bytes.map(bytes).unwrap_or_else(unknown));
+    println!("最多檔案描述符: {}", usage.peak_fds.map(|n| n.to_string()).unwrap_or_else(unknown));
+    println!("最多同時 socket: {}", usage.peak_sockets);
+    println!("送出 / 收到: {} / {}", bytes(usage.bytes_sent), bytes(usage.bytes_received));
+}
+
+struct Usage {
+    cpu: Duration,
+    peak_rss: u64,
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::time::Duration;
+    use super::Usage;
+
+    // ru_maxrss 在 Linux 為 KiB、macOS 為位元組
+    pub fn usage() -> Option<Usage> {
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+            return None;
+        }
+        let time = |t: libc::timeval| Duration::new(t.tv_sec as u64, t.tv_usec as u32 * 1000);
+        #[cfg(target_os = "macos")]
+        let peak_rss = usage.ru_maxrss as u64;
+        #[cfg(not(target_os = "macos"))]
+        let peak_rss = usage.ru_maxrss as u64 * 1024;
+        Some(Usage { cpu: time(usage.ru_utime) + time(usage.ru_stime), peak_rss })
+    }
+
+    // /proc/self/fd (macOS 為 /dev/fd)；讀取目錄本身佔用的描述符不計
+    pub fn open_fds() -> Option<u64> {
+        #[cfg(target_os = "macos")]
+        let dir = "/dev/fd";
+        #[cfg(not(target_os = "macos"))]
+        let dir = "/proc/self/fd";
+        let count = std::fs::read_dir(dir).ok()?.count() as u64;
+        Some(count.saturating_sub(1))
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::time::Duration;
+    use windows::Win32::Foundation::FILETIME;
+    use windows::Win32::System::ProcessStatus::{GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS};
+    use windows::Win32::System::Threading::{GetCurrentProcess, GetProcessHandleCount, GetProcessTimes};
+    use super::Usage;
+
+    // FILETIME 以 100 奈秒為單位
+    fn duration(time: FILETIME) -> Duration {
+        let ticks = (u64::from(time.dwHighDateTime) << 32) | u64::from(time.dwLowDateTime);
+        Duration::from_nanos(ticks * 100)
+    }
+
+    pub fn usage() -> Option<Usage> {
+        let process = unsafe { GetCurrentProcess() };
+        let [mut created, mut exited, mut kernel, mut user] = [FILETIME::default(); 4];
+        unsafe { GetProcessTimes(process, &mut created, &mut exited, &mut kernel, &mut user) }.ok()?;
+        let mut counters = PROCESS_MEMORY_COUNTERS::default();
+        let size = std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32;
+        unsafe { GetProcessMemoryInfo(process, &mut counters, size) }.ok()?;
+        Some(Usage { cpu: duration(kernel) + duration(user), peak_rss: counters.PeakWorkingSetSize as u64 })
+    }
+
+    pub fn open_fds() -> Option<u64> {
+        let mut count = 0u32;
+        unsafe { GetProcessHandleCount(GetCurrentProcess(), &mut count) }.ok()?;
+        Some(u64::from(count))
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    use super::Usage;
+
+    pub fn usage() -> Option<Usage> {
+        None
+    }
+
+    pub fn open_fds() -> Option<u64> {
+        None
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::time::Duration;
+use colored::*;
+use serde::Serialize;
+use crate::timefmt;
+use crate::{PortInfo, ScanResult};
+
+// 單一端口在監控期間的狀態
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PortState {
+    // 監控開始後尚未開放過，不計入離線
+    NeverUp,
+    // 開放
+    Up,
+    // 關閉，自此時間點起
+    Down { since: Duration },
+}
+
+// 端口的狀態機與累計離線時間
+#[derive(Debug, Clone)]
+struct PortTracker {
+    service: String,
+    state: PortState,
+    downtime: Duration,
+    restarts: u32,
+    outages: u32,
+}
+
+// 關閉後在時間窗內重新開放，判定為服務重啟
+#[derive(Debug, Clone, Serialize)]
+pub struct Restart {
+    pub event: &'static str,
+    pub iteration: u64,
+    pub host: IpAddr,
+    pub port: u16,
+    pub service: String,
+    // 估計的離線秒數 (首次觀察到關閉到再次開放)
+    pub downtime_secs: u64,
+    // 本次監控中此端口的累計離線秒數
+    pub total_downtime_secs: u64,
+}
+
+impl Restart {
+    pub fn message(&self) -> String {
+        format!(
+            "{} Port {} ({}) 服務疑似重啟 (離線 {})",
+            self.host,
+            self.port,
+            self.service,
+            timefmt::clock(Duration::from_secs(self.downtime_secs))
+        )
+    }
+}
+
+// 跨掃描追蹤端口的關閉 → 開放序列
+pub struct RestartDetector {
+    window: Duration,
+    ports: HashMap<(IpAddr, u16), PortTracker>,
+}
+
+impl RestartDetector {
+    pub fn new(window: Duration) -> Self {
+        RestartDetector {
+            window,
+            ports: HashMap::new(),
+        }
+    }
+
+    // 記錄一次掃描；elapsed 為自監控開始的時間
+    pub fn observe(
+        &mut self,
+        iteration: u64,
+        elapsed: Duration,
+        results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>,
+    ) -> Vec<Restart> {
+        let mut restarts = Vec::new();
+        for (host, host_results) in results {
+            for (port, result) in host_results {
+                // 掃描端錯誤不代表端口狀態
+                if result.error.is_some() {
+                    continue;
+                }
+                if let Some(restart) = self.transition(*host, port, result.outbound, elapsed) {
+                    restarts.push(Restart { iteration, ..restart });
+                }
+            }
+        }
+        restarts.sort_by_key(|r| (r.host, r.port));
+        restarts
+    }
+
+    fn transition(&mut self, host: IpAddr, port: &PortInfo, open: bool, now: Duration) -> Option<Restart> {
+        let initial = if open { PortState::Up } else { PortState::NeverUp };
+        let tracker = self.ports.entry((host, port.port)).or_insert_with(|| PortTracker {
+            service: port.service.clone(),
+            state: initial,
+            downtime: Duration::ZERO,
+            restarts: 0,
+            outages: 0,
+        });
+
+        match (tracker.state, open) {
+            (PortState::NeverUp, true) => {
+                tracker.state = PortState::Up;
+                None
+            }
+            (PortState::Up, false) => {
+                tracker.state = PortState::Down { since: now };
+                None
+            }
+            (PortState::Down { since }, true) => {
+                let downtime = now.saturating_sub(since);
+                tracker.downtime += downtime;
+                tracker.state = PortState::Up;
+                if downtime > self.window {
+                    // 長時間中斷後恢復，只算一般狀態改變
+                    tracker.outages += 1;
+                    return None;
+                }
+                tracker.restarts += 1;
+                Some(Restart {
+                    event: "service_restart",
+                    iteration: 0,
+                    host,
+                    port: port.port,
+                    service: tracker.service.clone(),
+                    downtime_secs: downtime.as_secs(),
+                    total_downtime_secs: tracker.downtime.as_secs(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    // 曾經離線的端口與累計離線時間；仍關閉的端口計算到 elapsed
+    fn downtime(&self, elapsed: Duration) -> Vec<(&(IpAddr, u16), &PortTracker, Duration)> {
+        let mut rows: Vec<(&(IpAddr, u16), &PortTracker, Duration)> = self
+            .ports
+            .iter()
+            .map(|(key, tracker)| {
+                let ongoing = match tracker.state {
+                    PortState::Down { since } => elapsed.saturating_sub(since),
+                    PortState::NeverUp | PortState::Up => Duration::ZERO,
+                };
+                (key, tracker, tracker.downtime + ongoing)
+            })
+            .filter(|(_, tracker, total)| !total.is_zero() || tracker.restarts > 0)
+            .collect();
+        rows.sort_by_key(|((host, port), _, _)| (*host, *port));
+        rows
+    }
+
+    // 監控結束時的摘要：只列出曾經離線的端口
+    pub fn display_summary(&self, elapsed: Duration) {
+        let rows = self.downtime(elapsed);
+        println!("\n{}", "=== 監控摘要 ===".bold());
+        println!("監控時間: {}", timefmt::clock(elapsed));
+        if rows.is_empty() {
+            println!("沒有端口離線");
+            return;
+        }
+        for ((host, port), tracker, total) in rows {
+            let still_down = matches!(tracker.state, PortState::Down { .. });
+            println!(
+                "{} Port {:5} ({:15}): 累計離線 {}，疑似重啟 {} 次，長時間中斷 {} 次{}",
+                host,
+                port,
+                tracker.service,
+                timefmt::clock(total),
+                tracker.restarts,
+                tracker.outages,
+                if still_down { "，目前仍關閉".red().to_string() } else { String::new() }
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::ScanError;
+    use crate::testutil::{host, scan_result};
+
+    const WINDOW: Duration = Duration::from_secs(120);
+
+    fn secs(n: u64) -> Duration {
+        Duration::from_secs(n)
+    }
+
+    fn ssh() -> PortInfo {
+        PortInfo::new(22, "SSH", "Remote")
+    }
+
+    // 依序觀察同一個端口的狀態，回傳每次觀察的重啟事件
+    fn run(detector: &mut RestartDetector, states: &[(u64, bool)]) -> Vec<Option<Restart>> {
+        states
+            .iter()
+            .enumerate()
+            .map(|(i, &(at, open))| {
+                let results = BTreeMap::from([(host(1), HashMap::from([(ssh(), scan_result(open))]))]);
+                detector.observe(i as u64 + 1, secs(at), &results).pop()
+            })
+            .collect()
+    }
+
+    fn tracker(detector: &RestartDetector) -> &PortTracker {
+        &detector.ports[&(host(1), 22)]
+    }
+
+    #[test]
+    fn quick_close_open_is_a_restart() {
+        let mut detector = RestartDetector::new(WINDOW);
+        let events = run(&mut detector, &[(0, true), (60, false), (102, true)]);
+        assert!(events[0].is_none() && events[1].is_none());
+        let restart = events[2].as_ref().unwrap();
+        assert_eq!((restart.event, restart.iteration, restart.downtime_secs, restart.total_downtime_secs), ("service_restart", 3, 42, 42));
+        assert_eq!(restart.message(), "192.0.2.1 Port 22 (SSH) 服務疑似重啟 (離線 00:42)");
+    }
+
+    #[test]
+    fn flapping_counts_every_restart_and_sums_downtime() {
+        let mut detector = RestartDetector::new(WINDOW);
+        let events = run(&mut detector, &[(0, true), (10, false), (20, true), (30, false), (40, false), (60, true), (70, true)]);
+        let restarts: Vec<(u64, u64)> = events.iter().flatten().map(|r| (r.downtime_secs, r.total_downtime_secs)).collect();
+        assert_eq!(restarts, vec![(10, 10), (30, 40)]);
+        let tracker = tracker(&detector);
+        assert_eq!((tracker.restarts, tracker.outages, tracker.downtime), (2, 0, secs(40)));
+    }
+
+    #[test]
+    fn sustained_outage_is_not_a_restart() {
+        let mut detector = RestartDetector::new(WINDOW);
+        let events = run(&mut detector, &[(0, true), (60, false), (120, false), (300, true)]);
+        assert!(events.iter().all(Option::is_none));
+        let tracker = tracker(&detector);
+        assert_eq!((tracker.restarts, tracker.outages, tracker.downtime), (0, 1, secs(240)));
+        // 剛好等於時間窗仍算重啟
+        let mut detector = RestartDetector::new(WINDOW);
+        assert!(run(&mut detector, &[(0, true), (10, false), (130, true)])[2].is_some());
+    }
+
+    #[test]
+    fn ports_that_were_never_up_are_not_down() {
+        let mut detector = RestartDetector::new(WINDOW);
+        let events = run(&mut detector, &[(0, false), (10, false), (20, true), (30, true)]);
+        assert!(events.iter().all(Option::is_none));
+        assert!(detector.downtime(secs(40)).is_empty());
+    }
+
+    #[test]
+    fn scanner_errors_do_not_change_state() {
+        let mut detector = RestartDetector::new(WINDOW);
+        run(&mut detector, &[(0, true)]);
+        let mut failed = scan_result(false);
+        failed.error = Some(ScanError::TooManyOpenFiles);
+        let results = BTreeMap::from([(host(1), HashMap::from([(ssh(), failed)]))]);
+        assert!(detector.observe(2, secs(10), &results).is_empty());
+        assert_eq!(tracker(&detector).state, PortState::Up);
+    }
+
+    #[test]
+    fn summary_includes_ongoing_outages() {
+        let mut detector = RestartDetector::new(WINDOW);
+        run(&mut detector, &[(0, true), (10, false), (30, true), (50, false)]);
+        let rows = detector.downtime(secs(100));
+        assert_eq!(rows.len(), 1);
+        // 已恢復的 20 秒加上仍在進行的 50 秒
+        assert_eq!(rows[0].2, secs(70));
+        assert!(matches!(rows[0].1.state, PortState::Down { since } if since == secs(50)));
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use crate::scanner::{ScanPlan, ScanRecord};
+use crate::targets::TargetSpec;
+
+// 續掃檔格式版本
+const VERSION: u32 = 1;
+
+// 續掃檔 (--resume-file / --resume)：每行為 "<檢查碼>\t<JSON>"
+// 第一行是掃描設定，之後每行一筆完成的結果；檢查碼為 JSON 的 FNV-1a 64 位元雜湊
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Header {
+    version: u32,
+    targets: Vec<String>,
+    ports: Vec<u16>,
+}
+
+impl Header {
+    fn for_plan(plan: &ScanPlan) -> Self {
+        Header {
+            version: VERSION,
+            targets: plan.targets.iter().map(TargetSpec::label).collect(),
+            ports: plan.ports.iter().map(|p| p.port).collect(),
+        }
+    }
+}
+
+fn checksum(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+fn encode<T: Serialize>(value: &T) -> String {
+    let json = serde_json::to_string(value).expect("續掃紀錄可序列化");
+    format!("{:016x}\t{}\n", checksum(&json), json)
+}
+
+// 檢查碼相符時回傳 JSON 部分
+fn decode(line: &str) -> Option<&str> {
+    let (sum, json) = line.split_once('\t')?;
+    (u64::from_str_radix(sum, 16).ok()? == checksum(json)).then_some(json)
+}
+
+// 從續掃檔讀回的先前結果
+#[derive(Debug)]
+pub struct ResumeState {
+    pub path: PathBuf,
+    pub records: Vec<ScanRecord>,
+    // 最後一行在寫入時中斷，已捨棄
+    pub truncated: bool,
+    // 完整紀錄結束的位置，接續寫入前截斷到這裡
+    valid_len: u64,
+}
+
+impl ResumeState {
+    // 已完成的 (主機, 端口)，掃描時略過
+    pub fn completed(&self) -> HashSet<(IpAddr, u16)> {
+        self.records.iter().map(|r| (r.host, r.port.port)).collect()
+    }
+}
+
+// 讀取並驗證續掃檔；掃描設定不同或內容損壞時回報錯誤，不會略過
+// 只有沒有換行結尾的最後一行視為寫入中斷 (例如當機)
+pub fn load(path: &Path, plan: &ScanPlan) -> Result<ResumeState, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("無法讀取續掃檔 {}: {}", path.display(), e))?;
+    let corrupted = |line: usize, reason: &str| format!("續掃檔 {}:{} 已損壞: {}", path.display(), line, reason);
+
+    let mut lines = text.split_inclusive('\n').enumerate();
+    let (_, first) = lines.next().ok_or_else(|| corrupted(1, "檔案是空的"))?;
+    let header: Header = decode(first.trim_end_matches('\n'))
+        .and_then(|json| serde_json::from_str(json).ok())
+        .ok_or_else(|| corrupted(1, "無法讀取掃描設定"))?;
+    if header.version != VERSION {
+        return Err(format!("續掃檔 {} 的版本 {} 不支援", path.display(), header.version));
+    }
+    if header != Header::for_plan(plan) {
+        return Err(format!(
+            "續掃檔 {} 的目標或端口與這次的掃描不同 (目標 {}，{} 個端口)",
+            path.display(),
+            header.targets.join(", "),
+            header.ports.len()
+        ));
+    }
+
+    let mut state = ResumeState {
+        path: path.to_path_buf(),
+        records: Vec::new(),
+        truncated: false,
+        valid_len: first.len() as u64,
+    };
+    for (index, line) in lines {
+        // 只有最後一行可能沒有換行
+        let Some(line) = line.strip_suffix('\n') else {
+            state.truncated = true;
+            break;
+        };
+        let record = decode(line)
+            .and_then(|json| serde_json::from_str::<ScanRecord>(json).ok())
+            .ok_or_else(|| corrupted(index + 1, "檢查碼不符或格式錯誤"))?;
+        state.records.push(record);
+        state.valid_len += line.len() as u64 + 1;
+    }
+    Ok(state)
+}
+
+// 定期寫入完成的結果；每隔 interval 才 fsync，避免每筆結果都等待磁碟
+pub struct Checkpoint {
+    path: PathBuf,
+    out: BufWriter<File>,
+    interval: Duration,
+    synced_at: Instant,
+    // 寫入失敗後不再寫入，只提示一次
+    failed: bool,
+}
+
+impl Checkpoint {
+    // 新的掃描：檔案已存在時拒絕覆蓋，避免誤刪先前的進度
+    pub fn create(path: &Path, plan: &ScanPlan, interval: Duration) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::AlreadyExists => {
+                    format!("續掃檔 {} 已存在；要接續先前的掃描請使用 --resume", path.display())
+                }
+                _ => format!("無法建立續掃檔 {}: {}", path.display(), e),
+            })?;
+        let mut checkpoint = Checkpoint::new(path, file, interval);
+        checkpoint
+            .out
+            .write_all(encode(&Header::for_plan(plan)).as_bytes())
+            .and_then(|_| checkpoint.sync_now())
+            .map_err(|e| format!("無法寫入續掃檔 {}: {}", path.display(), e))?;
+        Ok(checkpoint)
+    }
+
+    // 接續寫入：先截斷寫入中斷的最後一行
+    pub fn resume(state: &ResumeState, interval: Duration) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .append(true)
+            .open(&state.path)
+            .and_then(|file| file.set_len(state.valid_len).map(|_| file))
+            .map_err(|e| format!("無法寫入續掃檔 {}: {}", state.path.display(), e))?;
+        Ok(Checkpoint::new(&state.path, file, interval))
+    }
+
+    fn new(path: &Path, file: File, interval: Duration) -> Self {
+        Checkpoint {
+            path: path.to_path_buf(),
+            out: BufWriter::new(file),
+            interval: interval.max(Duration::from_millis(100)),
+            synced_at: Instant::now(),
+            failed: false,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn sync_now(&mut self) -> std::io::Result<()> {
+        self.out.flush()?;
+        self.out.get_ref().sync_data()?;
+        self.synced_at = Instant::now();
+        Ok(())
+    }
+
+    fn report(&mut self, result: std::io::Result<()>) {
+        if let (Err(e), false) = (result, self.failed) {
+            self.failed = true;
+            eprintln!("{}", format!("無法寫入續掃檔 {}: {}，之後的結果不會保存", self.path.display(), e).yellow());
+        }
+    }
+
+    pub fn write(&mut self, record: &ScanRecord) {
+        if self.failed {
+            return;
+        }
+        let result = self.out.write_all(encode(record).as_bytes());
+        self.report(result);
+        if self.synced_at.elapsed() >= self.interval {
+            self.sync();
+        }
+    }
+
+    // 把緩衝中的結果寫到磁碟
+    pub fn sync(&mut self) {
+        if !self.failed {
+            let result = self.sync_now();
+            self.report(result);
+        }
+    }
+}
+
+// 顯示載入的續掃進度
+pub fn display(state: &ResumeState, total: u128) {
+    println!(
+        "{} 從 {} 載入 {} 個先前的結果 (共 {} 個探測)，只掃描其餘端口",
+        "續掃:".bold(),
+        state.path.display(),
+        state.records.len(),
+        total
+    );
+    if state.truncated {
+        println!("{}", "續掃檔最後一筆紀錄不完整 (寫入時中斷)，已捨棄並重新掃描".yellow());
+    }
+}
+
+// 掃描完成後移除續掃檔
+pub fn finish(path: &Path) {
+    if let Err(e) = std::fs::remove_file(path) {
+        eprintln!("{}", format!("無法刪除續掃檔 {}: {}", path.display(), e).yellow());
+    }
+}
@@ -0,0 +1,334 @@
+use std::net::{IpAddr, SocketAddr};
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// --route-check：出站連線實際使用的本機位址與核心選擇的介面
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct RouteInfo {
+    // getsockname 取得的本機位址
+    pub local: SocketAddr,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interface: Option<String>,
+    // 下一跳；直接相連的網段沒有閘道
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gateway: Option<IpAddr>,
+    // --expect-route 指定的介面
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub mismatch: bool,
+    // 路由查詢失敗的原因；此時只有本機位址
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// 掃描中的路由檢查設定
+#[derive(Debug, Clone, Default)]
+pub struct RouteCheck {
+    expected: Option<String>,
+}
+
+impl RouteCheck {
+    // 預期的介面不存在時回報錯誤，避免每個端口都標示不符
+    pub fn new(expected: Option<String>) -> Result<Self, String> {
+        if let Some(name) = &expected {
+            platform::validate(name)?;
+        }
+        Ok(RouteCheck { expected })
+    }
+
+    // 查詢核心對這條連線 (目的、本機位址與端口) 選擇的路由；查詢是同步的 netlink 呼叫
+    pub async fn inspect(&self, dest: SocketAddr, local: SocketAddr) -> RouteInfo {
+        let lookup = tokio::task::spawn_blocking(move || platform::lookup(dest, local))
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()));
+        let (interface, gateway, error) = match lookup {
+            Ok(route) => (route.interface, route.gateway, None),
+            Err(e) => (None, None, Some(e)),
+        };
+        let mismatch = match (&self.expected, &interface) {
+            (Some(expected), Some(interface)) => expected != interface,
+            _ => false,
+        };
+        RouteInfo {
+            local,
+            interface,
+            gateway,
+            expected: self.expected.clone(),
+            mismatch,
+            error,
+        }
+    }
+}
+
+// 查詢結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub interface: Option<String>,
+    pub gateway: Option<IpAddr>,
+}
+
+// 端口結果下的一行，例如 "路由: wg0 經 10.8.0.1，來源 10.8.0.2:51234"；不符時為紅色
+pub fn describe(route: &RouteInfo) -> String {
+    let source = format!("來源 {}", route.local);
+    let Some(interface) = &route.interface else {
+        let reason = route.error.as_deref().unwrap_or("無法判斷介面");
+        return format!("路由: {}，{}", reason.yellow(), source.dimmed());
+    };
+    let via = route.gateway.map(|gateway| format!(" 經 {}", gateway)).unwrap_or_default();
+    match (&route.expected, route.mismatch) {
+        (Some(expected), true) => {
+            format!("{}，{}", format!("路由: {}{} (預期 {})", interface, via, expected).red(), source.dimmed())
+        }
+        _ => format!("路由: {}{}，{}", interface, via, source.dimmed()),
+    }
+}
+
+// 結果中未經由預期介面的端口數與預期的介面
+pub fn mismatches<'a>(routes: impl Iterator<Item = &'a RouteInfo>) -> Option<(usize, String)> {
+    let mismatched: Vec<&RouteInfo> = routes.filter(|route| route.mismatch).collect();
+    let expected = mismatched.first()?.expected.clone()?;
+    Some((mismatched.len(), expected))
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::ffi::{CStr, CString};
+    use std::io;
+    use std::mem;
+    use std::net::SocketAddr;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use super::{netlink, Route};
+
+    // 回覆的等待上限；核心通常立即回覆
+    const RECV_TIMEOUT_SECS: libc::time_t = 1;
+
+    static SEQUENCE: AtomicU32 = AtomicU32::new(1);
+
+    pub fn validate(name: &str) -> Result<(), String> {
+        let index = CString::new(name).map(|c| unsafe { libc::if_nametoindex(c.as_ptr()) }).unwrap_or(0);
+        match index {
+            0 => Err(format!("找不到網路介面 {}", name)),
+            _ => Ok(()),
+        }
+    }
+
+    fn interface_name(index: u32) -> Option<String> {
+        let mut name = [0 as libc::c_char; libc::IF_NAMESIZE];
+        let found = unsafe { libc::if_indextoname(index, name.as_mut_ptr()) };
+        if found.is_null() {
+            return None;
+        }
+        Some(unsafe { CStr::from_ptr(name.as_ptr()) }.to_string_lossy().into_owned())
+    }
+
+    // 以 rtnetlink RTM_GETROUTE 查詢，與 `ip route get <dest> from <local> ipproto tcp sport .. dport ..` 相同
+    pub fn lookup(dest: SocketAddr, local: SocketAddr) -> Result<Route, String> {
+        let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, libc::NETLINK_ROUTE) };
+        if fd < 0 {
+            return Err(format!("路由查詢失敗: {}", io::Error::last_os_error()));
+        }
+        // 安全性：fd 剛由 socket 建立，之後只由 OwnedFd 關閉
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let wait = libc::timeval {
+            tv_sec: RECV_TIMEOUT_SECS,
+            tv_usec: 0,
+        };
+        unsafe {
+            libc::setsockopt(
+                fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                (&wait as *const libc::timeval).cast(),
+                mem::size_of::<libc::timeval>() as libc::socklen_t,
+            );
+        }
+
+        let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+        let request = netlink::request(sequence, dest, local);
+        let mut kernel: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        kernel.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        let sent = unsafe {
+            libc::sendto(
+                fd.as_raw_fd(),
+                request.as_ptr().cast(),
+                request.len(),
+                0,
+                (&kernel as *const libc::sockaddr_nl).cast(),
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if sent < 0 {
+            return Err(format!("路由查詢失敗: {}", io::Error::last_os_error()));
+        }
+        let mut reply = vec![0u8; 8192];
+        let received = unsafe { libc::recv(fd.as_raw_fd(), reply.as_mut_ptr().cast(), reply.len(), 0) };
+        if received < 0 {
+            return Err(format!("路由查詢失敗: {}", io::Error::last_os_error()));
+        }
+        let route = netlink::parse_reply(&reply[..received as usize], sequence)?;
+        Ok(Route {
+            interface: route.oif.and_then(interface_name),
+            gateway: route.gateway,
+        })
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use std::net::SocketAddr;
+    use super::Route;
+
+    // 其他平台無法查詢，不檢查介面名稱
+    pub fn validate(_name: &str) -> Result<(), String> {
+        Err("--expect-route 只支援 Linux".to_string())
+    }
+
+    pub fn lookup(_dest: SocketAddr, _local: SocketAddr) -> Result<Route, String> {
+        Err("路由查詢只支援 Linux".to_string())
+    }
+}
+
+// rtnetlink 訊息的組成與解析，與 socket 分開以便用錄下的回覆驗證
+// 欄位為主機位元組順序，端口為網路位元組順序
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+mod netlink {
+    use std::io;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    const NLMSG_ERROR: u16 = 2;
+    const RTM_NEWROUTE: u16 = 24;
+    const RTM_GETROUTE: u16 = 26;
+    const NLM_F_REQUEST: u16 = 1;
+    const AF_INET: u8 = 2;
+    const AF_INET6: u8 = 10;
+    const IPPROTO_TCP: u8 = 6;
+
+    const RTA_DST: u16 = 1;
+    const RTA_SRC: u16 = 2;
+    const RTA_OIF: u16 = 4;
+    const RTA_GATEWAY: u16 = 5;
+    const RTA_IP_PROTO: u16 = 27;
+    const RTA_SPORT: u16 = 28;
+    const RTA_DPORT: u16 = 29;
+
+    // nlmsghdr 與 rtmsg 的長度
+    const HEADER_LEN: usize = 16;
+    const RTMSG_LEN: usize = 12;
+
+    // 回覆中用到的欄位
+    #[derive(Debug, Clone, PartialEq, Eq, Default)]
+    pub struct Reply {
+        pub oif: Option<u32>,
+        pub gateway: Option<IpAddr>,
+    }
+
+    fn align(len: usize) -> usize {
+        (len + 3) & !3
+    }
+
+    fn push_attr(buf: &mut Vec<u8>, kind: u16, data: &[u8]) {
+        buf.extend_from_slice(&((4 + data.len()) as u16).to_ne_bytes());
+        buf.extend_from_slice(&kind.to_ne_bytes());
+        buf.extend_from_slice(data);
+        buf.resize(align(buf.len()), 0);
+    }
+
+    fn octets(ip: IpAddr) -> Vec<u8> {
+        match ip {
+            IpAddr::V4(ip) => ip.octets().to_vec(),
+            IpAddr::V6(ip) => ip.octets().to_vec(),
+        }
+    }
+
+    // 依目的、來源與 TCP 端口查詢，讓以端口或來源位址分流的策略路由也能反映
+    pub fn request(sequence: u32, dest: SocketAddr, local: SocketAddr) -> Vec<u8> {
+        let (family, prefix) = match dest {
+            SocketAddr::V4(_) => (AF_INET, 32),
+            SocketAddr::V6(_) => (AF_INET6, 128),
+        };
+        let mut buf = vec![0u8; HEADER_LEN];
+        // rtmsg：family, dst_len, src_len, tos, table, protocol, scope, type, flags
+        buf.extend_from_slice(&[family, prefix, prefix, 0, 0, 0, 0, 0]);
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        push_attr(&mut buf, RTA_DST, &octets(dest.ip()));
+        push_attr(&mut buf, RTA_SRC, &octets(local.ip()));
+        push_attr(&mut buf, RTA_IP_PROTO, &[IPPROTO_TCP]);
+        push_attr(&mut buf, RTA_SPORT, &local.port().to_be_bytes());
+        push_attr(&mut buf, RTA_DPORT, &dest.port().to_be_bytes());
+
+        let len = buf.len() as u32;
+        buf[0..4].copy_from_slice(&len.to_ne_bytes());
+        buf[4..6].copy_from_slice(&RTM_GETROUTE.to_ne_bytes());
+        buf[6..8].copy_from_slice(&NLM_F_REQUEST.to_ne_bytes());
+        buf[8..12].copy_from_slice(&sequence.to_ne_bytes());
+        buf
+    }
+
+    fn u16_at(buf: &[u8], at: usize) -> Option<u16> {
+        Some(u16::from_ne_bytes(buf.get(at..at + 2)?.try_into().ok()?))
+    }
+
+    fn u32_at(buf: &[u8], at: usize) -> Option<u32> {
+        Some(u32::from_ne_bytes(buf.get(at..at + 4)?.try_into().ok()?))
+    }
+
+    fn parse_ip(data: &[u8]) -> Option<IpAddr> {
+        match data.len() {
+            4 => Some(IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(data).ok()?))),
+            16 => Some(IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(data).ok()?))),
+            _ => None,
+        }
+    }
+
+    fn parse_route(message: &[u8]) -> Result<Reply, String> {
+        let mut reply = Reply::default();
+        let mut at = HEADER_LEN + RTMSG_LEN;
+        while at + 4 <= message.len() {
+            let (Some(len), Some(kind)) = (u16_at(message, at), u16_at(message, at + 2)) else {
+                break;
+            };
+            let len = len as usize;
+            if len < 4 || at + len > message.len() {
+                return Err("路由查詢的回覆格式錯誤".to_string());
+            }
+            let data = &message[at + 4..at + len];
+            match kind {
+                RTA_OIF => reply.oif = u32_at(data, 0),
+                RTA_GATEWAY => reply.gateway = parse_ip(data),
+                _ => {}
+            }
+            at += align(len);
+        }
+        Ok(reply)
+    }
+
+    // 解析核心的回覆；略過序號不符的訊息，NLMSG_ERROR 轉為錯誤說明
+    pub fn parse_reply(buf: &[u8], sequence: u32) -> Result<Reply, String> {
+        let mut at = 0;
+        while at + HEADER_LEN <= buf.len() {
+            let (Some(len), Some(kind), Some(seq)) = (u32_at(buf, at), u16_at(buf, at + 4), u32_at(buf, at + 8)) else {
+                break;
+            };
+            let len = len as usize;
+            if len < HEADER_LEN || at + len > buf.len() {
+                return Err("路由查詢的回覆格式錯誤".to_string());
+            }
+            let message = &buf[at..at + len];
+            if seq == sequence {
+                match kind {
+                    RTM_NEWROUTE => return parse_route(message),
+                    NLMSG_ERROR => {
+                        let code = u32_at(message, HEADER_LEN).map(|code| code as i32).unwrap_or_default();
+                        return Err(format!("路由查詢失敗: {}", io::Error::from_raw_os_error(-code)));
+                    }
+                    _ => {}
+                }
+            }
+            at += align(len);
+        }
+        Err("路由查詢沒有回覆".to_string())
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+use crate::scanner::ScanPlan;
+use crate::stats::{self, Percentiles, HISTOGRAM_EDGES};
+use crate::{PortInfo, ScanResult};
+
+// 政策有延遲目標但未指定 --samples 時的樣本數
+pub const POLICY_SAMPLES: usize = 10;
+
+// 連續兩次量測之間的間隔，避免把同一個端口的樣本擠在同一瞬間
+const INTERVAL: Duration = Duration::from_millis(20);
+
+// 一個端口的延遲樣本 (--samples)；第一個樣本是掃描時的連線
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct LatencySamples {
+    #[serde(flatten)]
+    pub percentiles: Percentiles,
+    // 連線失敗、不列入百分位數的樣本數
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub lost: usize,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+// 量測的統計，供延遲分佈使用
+#[derive(Debug, Default)]
+pub struct SampleSummary {
+    pub ports: usize,
+    pub values: Vec<f64>,
+}
+
+// 對每個可連線的端口再連線 count - 1 次，以全部樣本計算百分位數
+// 經由代理時直接連線的延遲不代表掃描路徑，不取樣
+pub async fn sample(plan: &ScanPlan, count: usize, results: &mut BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>) -> SampleSummary {
+    let mut summary = SampleSummary::default();
+    if plan.proxy.is_some() || count < 2 {
+        return summary;
+    }
+    let semaphore = Arc::new(Semaphore::new(plan.concurrency.max(1)));
+    let mut handles = Vec::new();
+    for (host, ports) in results.iter() {
+        for (port, result) in ports.iter().filter(|(_, r)| r.outbound && r.error.is_none()) {
+            let (host, port, first) = (*host, port.clone(), result.latency_ms);
+            let (prober, limit, semaphore) = (plan.prober.clone(), plan.timeouts.for_port(&port), semaphore.clone());
+            handles.push(tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let mut values: Vec<f64> = first.into_iter().collect();
+                let mut lost = 0;
+                for _ in 1..count {
+                    tokio::time::sleep(INTERVAL).await;
+                    let started = Instant::now();
+                    match prober.connect(host, port.port, limit, None).await.connected {
+                        true => values.push(started.elapsed().as_secs_f64() * 1000.0),
+                        false => lost += 1,
+                    }
+                }
+                (host, port, values, lost)
+            }));
+        }
+    }
+    for handle in handles {
+        let Ok((host, port, values, lost)) = handle.await else {
+            continue;
+        };
+        let Some(result) = results.get_mut(&host).and_then(|ports| ports.get_mut(&port)) else {
+            continue;
+        };
+        summary.ports += 1;
+        result.samples = Percentiles::of(&values).map(|percentiles| LatencySamples { percentiles, lost });
+        summary.values.extend(values);
+    }
+    summary
+}
+
+// 端口結果之下的一行，例如 [延遲] p50 1.2ms  p90 3.4ms  p95 3.9ms  p99 4.1ms (10 次)
+pub fn describe(samples: &LatencySamples) -> String {
+    let lost = match samples.lost {
+        0 => String::new(),
+        n => format!("，{} 次失敗", n).yellow().to_string(),
+    };
+    format!("{} {}{}", "[延遲]".cyan(), samples.percentiles.describe(), lost)
+}
+
+// 摘要中的延遲分佈；所有取樣端口的樣本合併計算
+pub fn display_histogram(summary: &SampleSummary) {
+    let Some(overall) = Percentiles::of(&summary.values) else {
+        return;
+    };
+    println!("\n{}", format!("=== 延遲分佈 ({} 個端口) ===", summary.ports).bold());
+    let counts = stats::histogram(&summary.values, &HISTOGRAM_EDGES);
+    let peak = counts.iter().copied().max().unwrap_or(0).max(1);
+    for (index, count) in counts.iter().enumerate() {
+        if *count == 0 {
+            continue;
+        }
+        let bar = "█".repeat((count * 40).div_ceil(peak));
+        println!("{:>10} {} {}", stats::bucket_label(&HISTOGRAM_EDGES, index), bar.cyan(), count);
+    }
+    println!("{}", overall.describe().dimmed());
+}
@@ -0,0 +1,137 @@
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use colored::*;
+use serde::Deserialize;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use crate::units::parse_duration;
+
+// 網路疑似離線時的結束代碼 (一般錯誤為 1)
+pub const EXIT_NETWORK_SUSPECT: i32 = 3;
+
+// 預設錨點：兩個大型 CDN 的 HTTPS 與預設閘道
+const DEFAULT_ANCHORS: &[&str] = &["1.1.1.1:443", "8.8.8.8:443", "gateway:53"];
+
+// 錨點探測的預設端口
+const DEFAULT_ANCHOR_PORT: u16 = 443;
+
+// 設定檔 [sanity] 區段
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SanityConfig {
+    // IP:port (IPv6 以 [位址]:port)；gateway 代表預設閘道
+    #[serde(default = "default_anchors")]
+    pub anchors: Vec<String>,
+
+    // 每個錨點的連線逾時 (例如 "2s")
+    #[serde(default = "default_timeout")]
+    pub timeout: String,
+}
+
+impl Default for SanityConfig {
+    fn default() -> Self {
+        SanityConfig {
+            anchors: default_anchors(),
+            timeout: default_timeout(),
+        }
+    }
+}
+
+fn default_anchors() -> Vec<String> {
+    DEFAULT_ANCHORS.iter().map(|a| a.to_string()).collect()
+}
+
+fn default_timeout() -> String {
+    "2s".to_string()
+}
+
+// 掃描前的連線檢查結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sanity {
+    // 至少一個錨點有回應
+    Online,
+    // 所有錨點都沒有回應
+    Suspect,
+    // 沒有可用的錨點 (例如找不到閘道)
+    Unknown,
+}
+
+// 啟動時解析錨點與逾時
+pub fn anchors(config: &SanityConfig) -> Result<(Vec<SocketAddr>, Duration), String> {
+    let limit = parse_duration(&config.timeout).map_err(|e| format!("設定檔 [sanity] timeout: {}", e))?;
+    let mut anchors = Vec::new();
+    for anchor in &config.anchors {
+        let (host, port) = match anchor.rsplit_once(':') {
+            Some((host, port)) => {
+                let port = port.parse().map_err(|_| format!("設定檔 [sanity] 錨點端口無效: {}", anchor))?;
+                (host, port)
+            }
+            None => (anchor.as_str(), DEFAULT_ANCHOR_PORT),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let addr = if host.eq_ignore_ascii_case("gateway") {
+            // 沒有預設閘道時略過
+            match default_gateway() {
+                Some(gateway) => IpAddr::V4(gateway),
+                None => continue,
+            }
+        } else {
+            host.parse().map_err(|_| format!("設定檔 [sanity] 錨點必須是 IP 位址或 gateway: {}", anchor))?
+        };
+        anchors.push(SocketAddr::new(addr, port));
+    }
+    Ok((anchors, limit))
+}
+
+// 同時連線所有錨點；連線被拒也代表網路可達
+pub async fn check(anchors: &[SocketAddr], limit: Duration) -> Sanity {
+    if anchors.is_empty() {
+        return Sanity::Unknown;
+    }
+    let handles: Vec<_> = anchors
+        .iter()
+        .map(|&addr| {
+            tokio::spawn(async move {
+                match timeout(limit, TcpStream::connect(addr)).await {
+                    Ok(Ok(_)) => true,
+                    Ok(Err(e)) => e.kind() == ErrorKind::ConnectionRefused,
+                    Err(_) => false,
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        if handle.await.unwrap_or(false) {
+            return Sanity::Online;
+        }
+    }
+    Sanity::Suspect
+}
+
+// 報告開頭的醒目警告
+pub fn display_warning() {
+    println!("\n{}", "⚠ 網路可能離線 — 結果可能不可信".white().on_red().bold());
+    println!("{}", "所有連線檢查錨點都沒有回應，端口「不可用」可能是上游網路中斷而非防火牆".red());
+}
+
+// 從 /proc/net/route 讀取 IPv4 預設閘道
+#[cfg(target_os = "linux")]
+pub fn default_gateway() -> Option<Ipv4Addr> {
+    let routes = std::fs::read_to_string("/proc/net/route").ok()?;
+    routes.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.get(1) != Some(&"00000000") {
+            return None;
+        }
+        // 十六進位，以主機位元組順序印出
+        let gateway = u32::from_str_radix(fields.get(2)?, 16).ok()?;
+        let gateway = Ipv4Addr::from(gateway.to_ne_bytes());
+        (!gateway.is_unspecified()).then_some(gateway)
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn default_gateway() -> Option<Ipv4Addr> {
+    None
+}
@@ -0,0 +1,85 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::Serialize;
+
+// --scan-id 的長度上限；CI 的工作 ID 通常遠短於此
+const MAX_LEN: usize = 64;
+
+// 每次執行的掃描 ID (UUIDv7)：前 48 位元為毫秒時間，依時間排序即依開始時間排序
+// 只用來對照同一次執行的各種輸出，取不到系統亂數時以時間與程序 ID 代替
+pub fn generate() -> String {
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or_default();
+    let mut random = [0u8; 10];
+    if getrandom::getrandom(&mut random).is_err() {
+        let mut hasher = DefaultHasher::new();
+        (SystemTime::now(), std::process::id()).hash(&mut hasher);
+        let hash = hasher.finish().to_be_bytes();
+        random[..8].copy_from_slice(&hash);
+    }
+    let mut bytes = [0u8; 16];
+    bytes[..6].copy_from_slice(&millis.to_be_bytes()[2..]);
+    bytes[6..].copy_from_slice(&random);
+    bytes[6] = 0x70 | (bytes[6] & 0x0f);
+    bytes[8] = 0x80 | (bytes[8] & 0x3f);
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+// --scan-id：外部提供的 ID (例如 CI 的工作 ID)；會放進以空白分隔的狀態行與 CSV 註解，只允許英數字與 . _ -
+pub fn parse(s: &str) -> Result<String, String> {
+    if s.is_empty() || s.len() > MAX_LEN {
+        return Err(format!("掃描 ID 的長度必須是 1 到 {} 個字元", MAX_LEN));
+    }
+    match s.chars().find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))) {
+        Some(c) => Err(format!("掃描 ID 只能包含英數字與 . _ -，不能有 '{}'", c)),
+        None => Ok(s.to_string()),
+    }
+}
+
+// 加上掃描 ID 的 JSON 物件：NDJSON 每一行、webhook 與事件記錄的內容
+#[derive(Debug, Serialize)]
+pub struct Tagged<'a, T: Serialize> {
+    pub scan_id: &'a str,
+    #[serde(flatten)]
+    pub item: &'a T,
+}
+
+pub fn tag<'a, T: Serialize>(scan_id: &'a str, item: &'a T) -> Tagged<'a, T> {
+    Tagged { scan_id, item }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_ids_are_time_ordered_uuid_v7() {
+        let first = generate();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let second = generate();
+        assert_eq!(first.len(), 36);
+        assert_eq!(first.as_bytes()[14], b'7');
+        assert!(matches!(first.as_bytes()[19], b'8' | b'9' | b'a' | b'b'), "{}", first);
+        assert!(first < second, "{} {}", first, second);
+        assert_eq!(parse(&first), Ok(first));
+    }
+
+    #[test]
+    fn supplied_ids_are_validated() {
+        assert_eq!(parse("ci-1234.5_a"), Ok("ci-1234.5_a".to_string()));
+        assert!(parse("").is_err());
+        assert!(parse("job 1").unwrap_err().contains("' '"));
+        assert!(parse(&"a".repeat(65)).is_err());
+    }
+
+    #[test]
+    fn tagged_objects_carry_the_id_first() {
+        #[derive(Serialize)]
+        struct Event {
+            event: &'static str,
+        }
+        let json = serde_json::to_string(&tag("abc", &Event { event: "alert" })).unwrap();
+        assert_eq!(json, r#"{"scan_id":"abc","event":"alert"}"#);
+    }
+}
@@ -0,0 +1,997 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener};
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use indicatif::ProgressBar;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpSocket;
+use tokio::sync::{mpsc, Semaphore};
+use tokio::time::{timeout, Instant};
+use tokio_util::sync::CancellationToken;
+use crate::confidence;
+use crate::errors::{self, ErrorCode};
+use crate::captive::Interception;
+use crate::closure::Failure;
+use crate::context::ScanContext;
+use crate::direction::{self, Directions};
+use crate::checks::health::HealthChecks;
+use crate::dependency::Dependencies;
+use crate::grade::{self, GradingConfig};
+use crate::adaptive::{AdaptiveLimit, ProbeOutcome};
+use crate::ephemeral::PortPressure;
+use crate::heartbeat::{Counters, Finding};
+use crate::hooks::{self, HookEvent, HookRunner};
+use crate::icmp::{self, IcmpError, IcmpMonitor, ProbeKey};
+use crate::keyboard::ScanControl;
+use crate::knock::KnockPlan;
+use crate::netlimit::NetLimiter;
+use crate::pipeline::{Evidence, Pipeline, Stage};
+use crate::pool::{self, SocketPool};
+use crate::prober::Prober;
+use crate::limits::ScanError;
+use crate::live::LiveReport;
+use crate::probes::ProbeLibrary;
+use crate::profile::{ProbeSample, Profiler};
+use crate::route::RouteCheck;
+use crate::syn::{SynScanner, SynState};
+use crate::targets::TargetSpec;
+use crate::timeouts::Timeouts;
+use crate::{socks, tor};
+use crate::{PortInfo, ScanResult};
+
+// 預設出站連線逾時
+pub const OUTBOUND_TIMEOUT: Duration = Duration::from_secs(1);
+
+// 單筆掃描紀錄 (目標 + 端口 + 結果)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ScanRecord {
+    pub host: IpAddr,
+    #[serde(flatten)]
+    pub port: PortInfo,
+    #[serde(flatten, serialize_with = "direction::serialize_tested")]
+    pub result: ScanResult,
+    // 主機的穩定識別 (--host-id、服務清單、目標名稱或反查 DNS)；串流寫入時填入
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+}
+
+// 掃描計劃
+#[derive(Debug, Clone)]
+pub struct ScanPlan {
+    pub targets: Vec<TargetSpec>,
+    pub ports: Vec<PortInfo>,
+    pub concurrency: usize,
+    // 單一主機同時進行的探測數上限 (--per-host-concurrency)
+    pub per_host_concurrency: Option<usize>,
+    // 同一目的網段同時進行的探測數上限 (--per-net-concurrency)
+    pub per_net: Option<Arc<NetLimiter>>,
+    pub timeouts: Timeouts,
+    pub vhosts: Vec<String>,
+    // --knock 敲門設定
+    pub knock: Option<Arc<KnockPlan>>,
+    // --tor 時所有出站探測經由此 SOCKS5 代理
+    pub proxy: Option<SocketAddr>,
+    // --profile-scan 時記錄排程量測
+    pub profiler: Option<Arc<Profiler>>,
+    // 有權限開啟原始 socket 時，用來取得探測失敗的 ICMP 原因
+    pub icmp: Option<Arc<IcmpMonitor>>,
+    // --banners 時對出站可連線的端口送出的探測
+    pub probes: Option<Arc<ProbeLibrary>>,
+    // --syn 且有權限時以半開放掃描取代完整連線
+    pub syn: Option<Arc<SynScanner>>,
+    // 健康等級的延遲門檻
+    pub grading: GradingConfig,
+    // --resume 時續掃檔中已完成的 (主機, 端口)，不再探測
+    pub completed: Arc<HashSet<(IpAddr, u16)>>,
+    // --on-open / --on-change 事件指令
+    pub hooks: Option<Arc<HookRunner>>,
+    // --concurrency auto 的並發控制器；concurrency 此時為上限
+    pub adaptive: Option<Arc<AdaptiveLimit>>,
+    // 本機位址與外部IP
+    pub context: Arc<ScanContext>,
+    // 出站、入站與橫幅探測的網路操作
+    pub prober: Arc<dyn Prober>,
+    // 互動掃描的按鍵控制 (暫停、繼續、中止)
+    pub control: Option<Arc<ScanControl>>,
+    // --route-check / --expect-route：記錄出站連線的本機位址與路由介面
+    pub route: Option<Arc<RouteCheck>>,
+    // --stages：每個端口的探測階段上限
+    pub pipeline: Pipeline,
+    // 終端上逐步顯示的結果區域
+    pub live: Option<Arc<LiveReport>>,
+    // --heartbeat / --heartbeat-webhook 讀取的進度計數
+    pub progress: Option<Arc<Counters>>,
+    // --no-inbound / --no-outbound：略過的測試方向
+    pub directions: Directions,
+    // 設定檔 [health.<端口>] 的健康檢查
+    pub health: Arc<HealthChecks>,
+    // --test-source-ports：依序從這些本機來源端口重新連線
+    pub source_ports: Vec<u16>,
+    // 掃描前偵測到透明代理或強制門戶時標示的 Web 端口
+    pub captive: Interception,
+    // 本機端口壓力的監測；即將耗盡時暫停發出新的連線
+    pub pressure: Option<Arc<PortPressure>>,
+    // 設定檔 [[dependencies]]：上游主機先掃描，結束後標示受影響的下游主機
+    pub dependencies: Arc<Dependencies>,
+}
+
+impl ScanPlan {
+    // 某個目標要探測的虛擬主機：目標本身的主機名稱加上 --vhost 清單
+    pub fn vhost_names(&self, target: &TargetSpec) -> Vec<String> {
+        let mut names = Vec::new();
+        if let TargetSpec::Host { name, .. } = target {
+            if name.parse::<IpAddr>().is_err() {
+                names.push(name.clone());
+            }
+        }
+        for name in &self.vhosts {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        names
+    }
+
+    // 總探測數 (主機數 x 端口數)
+    pub fn total_probes(&self) -> u128 {
+        let hosts: u128 = self.targets.iter().map(TargetSpec::host_count).sum();
+        hosts * self.ports.len() as u128
+    }
+
+    // 按 q 中止，結果只包含已完成的探測
+    pub fn aborted(&self) -> bool {
+        self.control.as_ref().is_some_and(|control| control.is_aborted())
+    }
+
+    // 扣除續掃已完成的探測後，這次要探測的數量
+    pub fn remaining_probes(&self) -> u128 {
+        self.total_probes().saturating_sub(self.completed.len() as u128)
+    }
+}
+
+// 同時輪替排程的主機數
+const HOST_WINDOW: usize = 1024;
+
+// 輪替窗口中的一台主機與下一個要排程的端口
+struct HostQueue {
+    host: IpAddr,
+    vhost_names: Arc<Vec<String>>,
+    next: usize,
+    // --per-host-concurrency
+    limit: Option<Arc<Semaphore>>,
+    // 下一個端口已因 --per-net-concurrency 延後過，不重複計入
+    throttled: bool,
+    // 互動掃描中這台主機的取消權杖 (按 x 取消)
+    token: Option<CancellationToken>,
+}
+
+impl HostQueue {
+    fn cancelled(&self) -> bool {
+        self.token.as_ref().is_some_and(CancellationToken::is_cancelled)
+    }
+}
+
+// 依計劃執行掃描，結果送入 tx
+// 通道滿時探測工作會卡在 send 並持有許可，排程器因此自動降速
+pub async fn run_scan<T: From<ScanRecord> + Send + 'static>(plan: &ScanPlan, tx: mpsc::Sender<T>, pb: &ProgressBar) {
+    // 入站測試只與本機端口有關，每個端口測一次，避免多目標同時綁定同一端口
+    // 分別綁定本機介面位址與 0.0.0.0 (見 bind_addresses)，各自記錄結果
+    // --no-inbound 時完全不綁定，所有端口的入站結果為 false (輸出時省略)
+    let mut inbound = HashMap::new();
+    let addrs = match plan.ports.is_empty() || !plan.directions.inbound() {
+        true => Vec::new(),
+        false => bind_addresses(plan.context.local_ip, plan.context.required_external_ip().await, &plan.context.interfaces),
+    };
+    for port_info in &plan.ports {
+        if let Entry::Vacant(entry) = inbound.entry(port_info.port) {
+            let mut tests = Vec::with_capacity(addrs.len());
+            for &addr in &addrs {
+                tests.push(BindTest { addr, code: plan.prober.bind(port_info.port, addr).await.err() });
+            }
+            entry.insert(tests);
+        }
+    }
+
+    let concurrency = plan.concurrency.max(1);
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+    if let Some(control) = &plan.control {
+        control.set_scope(&plan.targets);
+    }
+
+    // 主機依序進入輪替窗口，窗口內的主機每次各排一個端口，每台主機的探測分散在整個掃描期間
+    // 大型網段仍逐一展開，不會一次放進記憶體
+    // 在目標中的上游依賴主機最先進入窗口，它們的探測全部排入後才輪到其他主機
+    let upstream: Vec<(IpAddr, Arc<Vec<String>>)> = plan
+        .dependencies
+        .order()
+        .iter()
+        .filter_map(|host| {
+            let target = plan.targets.iter().find(|target| target.contains(*host))?;
+            Some((*host, Arc::new(plan.vhost_names(target))))
+        })
+        .collect();
+    let first: HashSet<IpAddr> = upstream.iter().map(|(host, _)| *host).collect();
+    let mut upstream = upstream.into_iter();
+    let mut upstream_done = first.is_empty();
+    let mut pending = plan
+        .targets
+        .iter()
+        .flat_map(|target| {
+            let vhost_names = Arc::new(plan.vhost_names(target));
+            target.addrs().map(move |host| (host, vhost_names.clone()))
+        })
+        .filter(|(host, _)| !first.contains(host));
+    let mut active: VecDeque<HostQueue> = VecDeque::new();
+    // 連續因 --per-host-concurrency 或 --per-net-concurrency 略過的主機數
+    let mut blocked = 0;
+
+    loop {
+        while active.len() < HOST_WINDOW {
+            let next = match upstream.next() {
+                Some(next) => Some(next),
+                None if upstream_done => pending.next(),
+                None => {
+                    upstream_done = !active.iter().any(|queue| first.contains(&queue.host));
+                    upstream_done.then(|| pending.next()).flatten()
+                }
+            };
+            let Some((host, vhost_names)) = next else {
+                break;
+            };
+            active.push_back(HostQueue {
+                host,
+                vhost_names,
+                next: 0,
+                limit: plan.per_host_concurrency.map(|cap| Arc::new(Semaphore::new(cap.max(1)))),
+                throttled: false,
+                token: plan.control.as_ref().map(|control| control.target_token(host)),
+            });
+        }
+        let Some(mut queue) = active.pop_front() else {
+            break;
+        };
+        let host = queue.host;
+        // 被取消的主機不再排程，其餘端口直接計入進度
+        if queue.cancelled() {
+            let skipped = plan.ports[queue.next..].iter().filter(|p| !plan.completed.contains(&(host, p.port))).count();
+            pb.inc(skipped as u64);
+            if let Some(progress) = &plan.progress {
+                progress.skip(skipped as u64);
+            }
+            continue;
+        }
+        while queue.next < plan.ports.len() && plan.completed.contains(&(host, plan.ports[queue.next].port)) {
+            queue.next += 1;
+        }
+        let Some(port_info) = plan.ports.get(queue.next) else {
+            continue;
+        };
+        // 主機已達並發上限時先輪到其他主機；窗口內全部達到上限時才等待
+        let host_permit = match queue.limit.clone() {
+            None => None,
+            Some(limit) => match limit.clone().try_acquire_owned() {
+                Ok(permit) => Some(permit),
+                Err(_) if blocked < active.len() => {
+                    blocked += 1;
+                    active.push_back(queue);
+                    continue;
+                }
+                Err(_) => Some(limit.acquire_owned().await.expect("semaphore closed")),
+            },
+        };
+        // 目的網段已達 --per-net-concurrency 上限時同樣先輪到其他主機
+        let net_permit = match &plan.per_net {
+            None => None,
+            Some(limiter) => match limiter.try_acquire(host) {
+                Some(permit) => Some(permit),
+                None => {
+                    if !queue.throttled {
+                        limiter.record_throttled(host);
+                        queue.throttled = true;
+                    }
+                    if blocked < active.len() {
+                        blocked += 1;
+                        active.push_back(queue);
+                        continue;
+                    }
+                    Some(limiter.semaphore(host).acquire_owned().await.expect("semaphore closed"))
+                }
+            },
+        };
+        queue.throttled = false;
+        blocked = 0;
+        // 暫停時不再取得新的許可，進行中的探測照常完成；中止時不再排入探測
+        if let Some(control) = &plan.control {
+            if !control.wait_running().await {
+                break;
+            }
+        }
+        queue.next += 1;
+        let queued_at = Instant::now();
+        let permit = semaphore.clone().acquire_owned().await.expect("semaphore closed");
+        // 等待許可期間被取消時歸還許可，下一輪略過這台主機
+        if queue.cancelled() {
+            queue.next -= 1;
+            active.push_front(queue);
+            continue;
+        }
+        if let Some(pressure) = &plan.pressure {
+            pressure.admit().await;
+        }
+        if let Some(adaptive) = &plan.adaptive {
+            adaptive.admit().await;
+        }
+        let queued = queued_at.elapsed();
+        let tx = tx.clone();
+        let pb = pb.clone();
+        let port_info = port_info.clone();
+        let panicked_port = port_info.clone();
+        // 所有位址都能綁定才算入站可用；錯誤代碼取第一個失敗的位址
+        let bind = inbound[&port_info.port].clone();
+        let bind_code = bind.iter().find_map(|test| test.code);
+        let inbound = !bind.is_empty() && bind.iter().all(BindTest::bound);
+        let directions = plan.directions;
+        let outbound_test = directions.outbound();
+        let probe_timeout = plan.timeouts.for_port(&port_info);
+        let vhost_names = queue.vhost_names.clone();
+        let profiler = plan.profiler.clone();
+        let proxy = plan.proxy;
+        let icmp = plan.icmp.clone();
+        let probe_library = plan.probes.clone();
+        let syn = plan.syn.clone().filter(|_| proxy.is_none());
+        let grading = plan.grading;
+        let knock = plan.knock.clone().filter(|k| k.protected.contains(&port_info.port));
+        let hooks = plan.hooks.clone();
+        let prober = plan.prober.clone();
+        let adaptive = plan.adaptive.clone();
+        let control = plan.control.clone();
+        let route_check = plan.route.clone();
+        let pipeline = plan.pipeline;
+        let live = plan.live.clone();
+        let context = plan.context.clone();
+        let token = queue.token.clone();
+        let progress = plan.progress.clone();
+        let captive = plan.captive;
+        let pressure = plan.pressure.clone();
+        if let Some(progress) = &progress {
+            progress.start();
+        }
+        if let (Some(pressure), true) = (&pressure, outbound_test) {
+            pressure.opened();
+        }
+
+        tokio::spawn(async move {
+            let begin = profiler.as_ref().map(|p| p.begin());
+            let work = async {
+                let connect_at = Instant::now();
+                let mut syn_state = None;
+                let mut probe = match (proxy, syn.as_deref().zip(SynScanner::supports(host))) {
+                    // --no-outbound：不連線，之後的階段也因未連線而略過
+                    _ if !outbound_test => Outbound::default(),
+                    (Some(proxy), _) => Outbound {
+                        connected: test_outbound_via_proxy(proxy, port_info.port, host, probe_timeout).await,
+                        ..Default::default()
+                    },
+                    (None, Some((syn, dest))) => {
+                        let state = syn.probe(dest, port_info.port, probe_timeout).await;
+                        let elapsed = connect_at.elapsed();
+                        syn_state = Some(state);
+                        let icmp_error = match (state, &icmp) {
+                            (SynState::Filtered, Some(monitor)) => monitor.take(&ProbeKey {
+                                protocol: icmp::PROTO_TCP,
+                                dest: host,
+                                dest_port: port_info.port,
+                                source_port: syn.source_port(),
+                            }),
+                            _ => None,
+                        };
+                        let failure = match (state, icmp_error.is_some()) {
+                            (SynState::Open, _) => None,
+                            (SynState::Closed, _) => Some(Failure::Reset {
+                                latency_ms: elapsed.as_secs_f64() * 1000.0,
+                            }),
+                            (SynState::Filtered, true) => Some(Failure::Unreachable),
+                            (SynState::Filtered, false) => Some(Failure::Timeout),
+                        };
+                        Outbound {
+                            connected: state == SynState::Open,
+                            icmp: icmp_error,
+                            error: None,
+                            failure,
+                            ..Default::default()
+                        }
+                    }
+                    (None, None) => {
+                        prober.connect(host, port_info.port, probe_timeout, icmp.as_deref()).await
+                    }
+                };
+                // 受敲門保護的端口失敗時，重新敲門後再試一次
+                if let (false, true, Some(knock)) = (probe.connected, outbound_test, &knock) {
+                    if knock.reknock(&context, host).await.is_ok() {
+                        probe = prober.connect(host, port_info.port, probe_timeout, icmp.as_deref()).await;
+                    }
+                }
+                let Outbound { connected: outbound, icmp: icmp_error, error, failure, setup, local, elapsed } = probe;
+                let connect = elapsed.unwrap_or_else(|| connect_at.elapsed());
+                let port = port_info.port;
+                let route = match (&route_check, local) {
+                    (Some(check), Some(local)) => Some(check.inspect(context.socket_addr(host, port), local).await),
+                    _ => None,
+                };
+                let note = (proxy.is_some() && !outbound && tor::commonly_blocked(port))
+                    .then(|| "可能被出口節點封鎖".to_string());
+                if let (Some(pressure), Some(ScanError::AddressInUse)) = (&pressure, &error) {
+                    pressure.exhausted();
+                }
+                let outcome = match (&error, &failure) {
+                    (Some(_), _) => ProbeOutcome::Error,
+                    (None, Some(Failure::Timeout)) => ProbeOutcome::Timeout,
+                    _ => ProbeOutcome::Answered,
+                };
+                let codes = bind_code.into_iter().chain(errors::outbound_codes(error, failure, icmp_error.as_ref())).collect();
+                let mut result = ScanResult {
+                        inbound,
+                        outbound,
+                        directions,
+                        bind,
+                        vhosts: Vec::new(),
+                        latency_ms: outbound.then_some(connect.as_secs_f64() * 1000.0),
+                        note,
+                        icmp: icmp_error,
+                        banner: None,
+                        syn: syn_state,
+                        error,
+                        failure,
+                        grade: None,
+                        verification: None,
+                        confidence: None,
+                        confirmations: 0,
+                        capabilities: None,
+                        http_versions: None,
+                        fingerprints: Vec::new(),
+                        route,
+                        resumed: false,
+                        throughput: None,
+                        samples: None,
+                        cancelled: false,
+                        codes,
+                        health: None,
+                        trend: Vec::new(),
+                        intercepted: false,
+                        source_ports: Vec::new(),
+                        systemd: None,
+                        conntrack: None,
+                        upstream: None,
+                        token: None,
+                };
+                // 連線之後的階段都直接連線，經由代理時略過
+                let evidence = Evidence { port: &port_info, connected: outbound, proxied: proxy.is_some(), banner: None };
+                let grab = probe_library.as_ref().filter(|_| pipeline.admits(Stage::Banner, &evidence));
+                // 之後還有階段要執行時，先在逐步顯示的區域放上暫定的連線結果
+                if let (Some(live), true) = (&live, grab.is_some() || (outbound && !vhost_names.is_empty())) {
+                    live.provisional(host, &port_info, &result);
+                }
+                let banner_time = match grab {
+                    Some(library) => {
+                        let banner_at = Instant::now();
+                        result.banner = prober.banner(library, host, port, probe_timeout).await;
+                        Some(banner_at.elapsed())
+                    }
+                    None => None,
+                };
+                // 虛擬主機探測需要 Web 端口或看起來是 HTTP 的橫幅
+                let evidence = Evidence { banner: result.banner.as_ref(), ..evidence };
+                let fingerprint =
+                    !vhost_names.is_empty() && pipeline.admits(Stage::Fingerprint, &evidence) && evidence.speaks_http();
+                let fingerprint_time = match fingerprint {
+                    true => {
+                        let fingerprint_at = Instant::now();
+                        result.vhosts = prober.vhosts(host, &port_info, &vhost_names).await;
+                        Some(fingerprint_at.elapsed())
+                    }
+                    false => None,
+                };
+                // 等級與信心分數都依出站連線計算，--no-outbound 時不適用
+                if outbound_test {
+                    result.grade = grade::grade_result(&result, None, &grading);
+                    result.confidence = Some(confidence::score(&confidence::Evidence::of(&result, probe_timeout)));
+                }
+                captive.mark(&port_info, &mut result);
+                if let (Some(hooks), true) = (&hooks, outbound) {
+                    hooks.fire(HookEvent::Open, host, &port_info, hooks::state_name(inbound, outbound), None);
+                }
+                if let Some(control) = &control {
+                    control.record(outbound);
+                }
+                if let (Some(progress), true) = (&progress, outbound) {
+                    progress.found(Finding { host: context.show_ip(host), port, service: port_info.service.clone() });
+                }
+                let record = ScanRecord {
+                    host,
+                    port: port_info,
+                    result,
+                    identity: None,
+                };
+                let send_at = Instant::now();
+                let _ = tx.send(record.into()).await;
+
+                if let (Some(profiler), Some((started, active))) = (&profiler, begin) {
+                    profiler.finish(ProbeSample {
+                        host,
+                        port,
+                        started,
+                        queued,
+                        setup,
+                        connect,
+                        banner: banner_time,
+                        fingerprint: fingerprint_time,
+                        stalled: send_at.elapsed(),
+                        active,
+                    });
+                }
+                pb.inc(1);
+                if let Some(adaptive) = &adaptive {
+                    adaptive.finish(outcome);
+                }
+            };
+            // 目標被取消時放棄進行中的探測，許可立即歸還給排程器
+            let work = CatchUnwind(Box::pin(work));
+            let finished = match &token {
+                Some(token) => tokio::select! {
+                    biased;
+                    _ = token.cancelled() => None,
+                    finished = work => Some(finished),
+                },
+                None => Some(work.await),
+            };
+            if finished != Some(Ok(())) {
+                pb.inc(1);
+                if let Some(profiler) = &profiler {
+                    profiler.abandon();
+                }
+                if let Some(adaptive) = &adaptive {
+                    adaptive.abandon();
+                }
+            }
+            // 探測 panic 時以掃描端錯誤記錄這個端口，結果不會默默消失
+            if finished == Some(Err(Panicked)) {
+                let result = ScanResult {
+                    directions,
+                    error: Some(ScanError::Panicked),
+                    codes: vec![ErrorCode::ProbePanicked],
+                    ..Default::default()
+                };
+                let _ = tx.send(ScanRecord { host, port: panicked_port, result, identity: None }.into()).await;
+            }
+            if let Some(progress) = &progress {
+                progress.finish();
+            }
+            drop(permit);
+            drop(host_permit);
+            drop(net_permit);
+        });
+        active.push_back(queue);
+    }
+
+    // 取回全部許可即代表所有探測都已完成
+    let _ = semaphore.acquire_many(concurrency as u32).await;
+}
+
+// 探測工作 panic (已由 panic hook 印出訊息)
+#[derive(Debug, PartialEq, Eq)]
+struct Panicked;
+
+// 把探測工作中的 panic 轉為 Err，其他端口的探測照常完成
+struct CatchUnwind<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = Result<F::Output, Panicked>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let work = self.0.as_mut();
+        match panic::catch_unwind(AssertUnwindSafe(|| work.poll(cx))) {
+            Ok(poll) => poll.map(Ok),
+            Err(_) => Poll::Ready(Err(Panicked)),
+        }
+    }
+}
+
+// 入站測試在一個位址上的綁定結果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct BindTest {
+    pub addr: IpAddr,
+    // 綁定失敗的原因；可綁定時省略
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl BindTest {
+    pub fn bound(&self) -> bool {
+        self.code.is_none()
+    }
+
+    pub fn describe(&self) -> String {
+        match self.code {
+            None => format!("可綁定於 {}", self.addr),
+            Some(code) => format!("無法綁定於 {} ({})", self.addr, code.code()),
+        }
+    }
+}
+
+// 入站測試要綁定的位址：本機主要介面位址與 0.0.0.0
+// 外部IP只有確實設定在本機介面上時才另外測試；NAT 後的外部IP不屬於本機，綁定必定失敗
+pub fn bind_addresses(local: Option<IpAddr>, external: Option<IpAddr>, interfaces: &[IpAddr]) -> Vec<IpAddr> {
+    let assigned = external.filter(|addr| interfaces.contains(addr));
+    let mut addrs = Vec::new();
+    for addr in [local, assigned, Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED))].into_iter().flatten() {
+        if !addrs.contains(&addr) {
+            addrs.push(addr);
+        }
+    }
+    addrs
+}
+
+// 測試入站連接：在指定的本機位址綁定端口後立即釋放
+pub async fn test_inbound_port(port: u16, addr: IpAddr) -> Result<(), ErrorCode> {
+    TcpListener::bind((addr, port)).map(drop).map_err(|e| ErrorCode::of_bind(&e))
+}
+
+// 出站探測的結果
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Outbound {
+    pub connected: bool,
+    // 失敗時收到的 ICMP 錯誤
+    pub icmp: Option<IcmpError>,
+    // 掃描端本身的錯誤 (例如檔案描述符用盡)，不代表端口狀態
+    pub error: Option<ScanError>,
+    // 連線失敗的方式 (RST / 逾時 / 不可達)
+    pub failure: Option<Failure>,
+    // 取得 socket 並送出 SYN 的時間
+    pub setup: Duration,
+    // 連線成功時使用的本機位址
+    pub local: Option<SocketAddr>,
+    // 探測層量得的連線時間 (--record-session / --replay)；沒有時由掃描器自行計時
+    #[serde(skip)]
+    pub elapsed: Option<Duration>,
+}
+
+// 一般的連線方式：每次建立新的 socket；監聽 ICMP 時先綁定臨時端口
+async fn connect_fresh(dest: SocketAddr, limit: Duration, bind: bool) -> pool::Attempt {
+    let started = Instant::now();
+    let socket = match dest {
+        SocketAddr::V4(_) => TcpSocket::new_v4(),
+        SocketAddr::V6(_) => TcpSocket::new_v6(),
+    };
+    let socket = match socket {
+        Ok(socket) => socket,
+        Err(e) => {
+            return pool::Attempt {
+                outcome: Some(Err(e)),
+                source_port: None,
+                local: None,
+                setup: started.elapsed(),
+            }
+        }
+    };
+    // 先綁定臨時端口，才能以來源端口比對 ICMP 錯誤引用的探測
+    let source_port = match bind {
+        true => {
+            let local = match dest {
+                SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+                SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+            };
+            socket.bind(local).ok().and_then(|_| socket.local_addr().ok()).map(|addr| addr.port())
+        }
+        false => None,
+    };
+    let setup = started.elapsed();
+    let (outcome, local) = match timeout(limit, socket.connect(dest)).await {
+        Ok(Ok(stream)) => (Some(Ok(())), stream.local_addr().ok()),
+        Ok(Err(e)) => (Some(Err(e)), None),
+        Err(_) => (None, None),
+    };
+    pool::Attempt { outcome, source_port, local, setup }
+}
+
+// 測試出站連接；有 socket 池時重複使用連線失敗的 socket
+pub async fn test_outbound_port(
+    context: &ScanContext,
+    port: u16,
+    dest: IpAddr,
+    limit: Duration,
+    icmp: Option<&IcmpMonitor>,
+    sockets: Option<&SocketPool>,
+) -> Outbound {
+    let started = Instant::now();
+    let target = context.socket_addr(dest, port);
+    let _socket = context.socket();
+    let attempt = match sockets {
+        Some(pool) => pool.connect(target, limit).await,
+        None => connect_fresh(target, limit, icmp.is_some()).await,
+    };
+    let pool::Attempt { outcome, source_port, local, setup } = attempt;
+    let (grace, failure) = match outcome {
+        Some(Ok(())) => {
+            return Outbound {
+                connected: true,
+                setup,
+                local,
+                ..Default::default()
+            }
+        }
+        Some(Err(e)) => {
+            if let Some(error) = ScanError::classify(&e) {
+                return Outbound {
+                    error: Some(error),
+                    setup,
+                    ..Default::default()
+                };
+            }
+            // 連線立即失敗時 ICMP 可能還沒被監聽執行緒處理
+            (icmp::ERROR_GRACE, Failure::from_error(&e, started.elapsed()))
+        }
+        None => (Duration::ZERO, Failure::Timeout),
+    };
+
+    let icmp_error = match (icmp, source_port) {
+        (Some(monitor), Some(source_port)) => {
+            let key = ProbeKey {
+                protocol: icmp::PROTO_TCP,
+                dest,
+                dest_port: port,
+                source_port,
+            };
+            monitor.wait_for(&key, grace).await
+        }
+        _ => None,
+    };
+    Outbound {
+        connected: false,
+        // 收到 ICMP 錯誤時以不可達計
+        failure: Some(if icmp_error.is_some() { Failure::Unreachable } else { failure }),
+        icmp: icmp_error,
+        error: None,
+        setup,
+        local: None,
+        elapsed: None,
+    }
+}
+
+// 經由 SOCKS5 代理測試出站連接
+pub async fn test_outbound_via_proxy(proxy: SocketAddr, port: u16, dest: IpAddr, limit: Duration) -> bool {
+    socks::connect(proxy, SocketAddr::new(dest, port), limit).await.is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adaptive;
+    use crate::alerts::AlertEngine;
+    use crate::grade::Grade;
+    use crate::prober::fake::{Script, Scripted, ScriptedProber};
+    use crate::probes::Banner;
+    use crate::testutil::{by_host, host, scan, scripted_plan};
+
+    fn open(ms: u64) -> Script {
+        Script::new(Scripted::Open, Duration::from_millis(ms))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn concurrency_cap_is_never_exceeded() {
+        let prober = Arc::new(ScriptedProber::new().fallback(open(100)));
+        let ports: Vec<u16> = (1..=10).collect();
+        let plan = scripted_plan(&[host(1), host(2), host(3)], &ports, 4, prober.clone());
+        let started = Instant::now();
+        let records = scan(&plan).await;
+        assert_eq!(records.len(), 30);
+        assert_eq!(prober.peak(), 4);
+        // 30 個探測、每次 4 個，共 8 輪
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(800) && elapsed < Duration::from_millis(900), "{:?}", elapsed);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hosts_take_turns() {
+        let prober = Arc::new(ScriptedProber::new().fallback(open(10)));
+        let (a, b, c) = (host(1), host(2), host(3));
+        let plan = scripted_plan(&[a, b, c], &[22, 80, 443], 1, prober.clone());
+        scan(&plan).await;
+        let expected: Vec<(IpAddr, u16)> =
+            [22, 80, 443].into_iter().flat_map(|port| [(a, port), (b, port), (c, port)]).collect();
+        assert_eq!(prober.calls(), expected);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn per_host_limit_lets_other_hosts_through() {
+        // 慢的主機佔住自己的上限，快的主機仍然持續被排程
+        let (slow, fast) = (host(1), host(2));
+        let mut prober = ScriptedProber::new().fallback(open(10));
+        for port in 1..=8 {
+            prober = prober.with(slow, port, Script::new(Scripted::Silent, Duration::ZERO));
+        }
+        let prober = Arc::new(prober);
+        let ports: Vec<u16> = (1..=8).collect();
+        let mut plan = scripted_plan(&[slow, fast], &ports, 8, prober.clone());
+        plan.per_host_concurrency = Some(2);
+        let records = scan(&plan).await;
+        assert_eq!(prober.host_peak(slow), 2);
+        assert_eq!(prober.host_peak(fast), 2);
+        assert!(prober.peak() <= 4);
+        // 快的主機在慢的主機第一批逾時前就全部完成
+        let first_slow = records.iter().position(|r| r.host == slow).unwrap();
+        assert!(records[..first_slow].iter().filter(|r| r.host == fast).count() == 8);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn streamed_results_show_every_host_early() {
+        // 輸出串流中每一輪都包含所有主機，而不是一台掃完才換下一台
+        let prober = Arc::new(ScriptedProber::new().fallback(open(10)));
+        let hosts: Vec<IpAddr> = (1..=5).map(host).collect();
+        let ports: Vec<u16> = (1..=20).collect();
+        let plan = scripted_plan(&hosts, &ports, 5, prober);
+        let records = scan(&plan).await;
+        assert_eq!(records.len(), 100);
+        for (round, chunk) in records.chunks(hosts.len()).enumerate() {
+            let mut seen: Vec<IpAddr> = chunk.iter().map(|r| r.host).collect();
+            seen.sort();
+            assert_eq!(seen, hosts, "第 {} 輪", round);
+            assert!(chunk.iter().all(|r| r.port.port == ports[round]), "第 {} 輪", round);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn network_hosts_are_interleaved_with_single_hosts() {
+        let prober = Arc::new(ScriptedProber::new().fallback(open(10)));
+        let single = host(100);
+        let mut plan = scripted_plan(&[single], &[22, 80], 1, prober.clone());
+        plan.targets.insert(0, TargetSpec::Network("192.0.2.0/30".parse().unwrap(), Default::default()));
+        scan(&plan).await;
+        let order = [host(1), host(2), single];
+        let expected: Vec<(IpAddr, u16)> = [22, 80].into_iter().flat_map(|port| order.map(|h| (h, port))).collect();
+        assert_eq!(prober.calls(), expected);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn outcomes_are_graded() {
+        let target = host(1);
+        let prober = Arc::new(
+            ScriptedProber::new()
+                .with(target, 1, open(50).banner(Banner {
+                    probe: "NULL".to_string(),
+                    service: Some("ssh".to_string()),
+                    version: None,
+                    text: "SSH-2.0-Test".to_string(),
+                    encoding: None,
+                    raw: None,
+                }))
+                .with(target, 2, open(300))
+                .with(target, 3, Script::new(Scripted::Refused, Duration::from_millis(5)))
+                .with(target, 4, Script::new(Scripted::Silent, Duration::ZERO))
+                .with(target, 5, Script::new(Scripted::Error(ScanError::TooManyOpenFiles), Duration::ZERO))
+                .with(target, 6, Script::new(Scripted::Unreachable, Duration::from_millis(5)))
+                .bindable(1),
+        );
+        let mut plan = scripted_plan(&[target], &[1, 2, 3, 4, 5, 6], 8, prober);
+        plan.probes = Some(Arc::new(ProbeLibrary::default()));
+        let results = by_host(scan(&plan).await).remove(&target).unwrap();
+        let result = |port: u16| results.iter().find(|(info, _)| info.port == port).map(|(_, r)| r).unwrap();
+        let grade = |port: u16| result(port).grade.as_ref().map(|g| g.grade);
+
+        assert!(result(1).inbound && result(1).outbound);
+        assert_eq!(grade(1), Some(Grade::A));
+        assert_eq!(result(1).banner.as_ref().map(|b| b.text.as_str()), Some("SSH-2.0-Test"));
+        assert!(result(2).banner.is_none());
+        let latency = result(2).latency_ms.unwrap();
+        assert!((300.0..310.0).contains(&latency), "{}", latency);
+        assert_eq!(grade(2), Some(Grade::B));
+        assert!(matches!(result(3).failure, Some(Failure::Reset { .. })));
+        assert_eq!(grade(3), Some(Grade::F));
+        assert_eq!(result(4).failure, Some(Failure::Timeout));
+        assert_eq!(result(5).error, Some(ScanError::TooManyOpenFiles));
+        assert_eq!(grade(5), None);
+        assert_eq!(result(6).failure, Some(Failure::Unreachable));
+        assert!(!result(2).inbound);
+        assert_eq!(result(2).codes.first(), Some(&ErrorCode::BindAddressInUse));
+        // 每個綁定位址各有結果，最後一個是 0.0.0.0
+        let wildcard = result(1).bind.last().unwrap();
+        assert!(wildcard.addr.is_unspecified() && wildcard.bound());
+        assert!(result(2).bind.iter().all(|test| !test.bound()));
+    }
+
+    #[test]
+    fn nat_external_ip_is_not_bound() {
+        let local: IpAddr = "192.168.1.5".parse().unwrap();
+        let external: IpAddr = "203.0.113.7".parse().unwrap();
+        let wildcard = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+        let interfaces = [IpAddr::V4(Ipv4Addr::LOCALHOST), local];
+        assert_eq!(bind_addresses(Some(local), Some(external), &interfaces), vec![local, wildcard]);
+        // 查不到外部IP或本機位址時仍測試 0.0.0.0
+        assert_eq!(bind_addresses(Some(local), None, &interfaces), vec![local, wildcard]);
+        assert_eq!(bind_addresses(None, None, &[]), vec![wildcard]);
+    }
+
+    #[test]
+    fn public_external_ip_is_bound_once() {
+        let public: IpAddr = "203.0.113.7".parse().unwrap();
+        let wildcard = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+        assert_eq!(bind_addresses(Some(public), Some(public), &[public]), vec![public, wildcard]);
+        // 多張網卡：外部IP在另一張介面上時也一併測試
+        let private: IpAddr = "10.0.0.2".parse().unwrap();
+        assert_eq!(bind_addresses(Some(private), Some(public), &[private, public]), vec![private, public, wildcard]);
+    }
+
+    #[test]
+    fn bind_verdict_names_the_address() {
+        let ok = BindTest { addr: "192.168.1.5".parse().unwrap(), code: None };
+        let busy = BindTest { addr: IpAddr::V4(Ipv4Addr::UNSPECIFIED), code: Some(ErrorCode::BindAddressInUse) };
+        assert_eq!(ok.describe(), "可綁定於 192.168.1.5");
+        assert_eq!(busy.describe(), "無法綁定於 0.0.0.0 (E2004)");
+    }
+
+    #[tokio::test]
+    async fn panicked_probe_is_recorded_as_scanner_error() {
+        let target = host(1);
+        let prober = Arc::new(
+            ScriptedProber::new()
+                .with(target, 1, Script::new(Scripted::Open, Duration::ZERO))
+                .with(target, 2, Script::new(Scripted::Panic, Duration::ZERO))
+                .with(target, 3, Script::new(Scripted::Refused, Duration::ZERO)),
+        );
+        let results = by_host(scan(&scripted_plan(&[target], &[1, 2, 3], 2, prober)).await).remove(&target).unwrap();
+        let result = |port: u16| results.iter().find(|(info, _)| info.port == port).map(|(_, r)| r).unwrap();
+
+        // 其他端口照常完成，panic 的端口有結果且標記為掃描端錯誤
+        assert_eq!(results.len(), 3);
+        assert!(result(1).outbound);
+        assert_eq!(result(2).error, Some(ScanError::Panicked));
+        assert_eq!(result(2).codes, [ErrorCode::ProbePanicked]);
+        assert_eq!(result(3).error, None);
+        assert!(result(3).codes.contains(&ErrorCode::ConnectionRefused));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn adaptive_limit_backs_off_on_scanner_errors() {
+        let error = Script::new(Scripted::Error(ScanError::TooManyOpenFiles), Duration::from_millis(10));
+        let prober = Arc::new(ScriptedProber::new().fallback(error));
+        let ports: Vec<u16> = (1..=512).collect();
+        let limit = Arc::new(AdaptiveLimit::new(adaptive::MAX, false));
+        let mut plan = scripted_plan(&[host(1)], &ports, adaptive::MAX, prober.clone());
+        plan.adaptive = Some(limit.clone());
+        scan(&plan).await;
+        let summary = limit.summary();
+        assert!(prober.peak() <= adaptive::INITIAL);
+        assert!(summary.last < adaptive::INITIAL, "{:?}", summary);
+        assert!(summary.adjustments > 0);
+
+        // 全部有回應時逐步放寬
+        let prober = Arc::new(ScriptedProber::new().fallback(open(10)));
+        let limit = Arc::new(AdaptiveLimit::new(adaptive::MAX, false));
+        let mut plan = scripted_plan(&[host(1)], &ports, adaptive::MAX, prober.clone());
+        plan.adaptive = Some(limit.clone());
+        scan(&plan).await;
+        let summary = limit.summary();
+        assert!(summary.last > adaptive::INITIAL, "{:?}", summary);
+        assert!(prober.peak() > adaptive::INITIAL);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rescans_report_changes() {
+        let target = host(1);
+        let mut engine = AlertEngine::new(Vec::new());
+        let first = Arc::new(ScriptedProber::new().with(target, 80, open(10)).with(target, 443, open(10)));
+        let (changes, _) = engine.observe(&by_host(scan(&scripted_plan(&[target], &[80, 443], 4, first)).await));
+        assert!(changes.is_empty());
+
+        let second = Arc::new(ScriptedProber::new().with(target, 443, open(10)));
+        let (changes, _) = engine.observe(&by_host(scan(&scripted_plan(&[target], &[80, 443], 4, second)).await));
+        assert_eq!(changes.len(), 1);
+        assert_eq!((changes[0].host, changes[0].port.port), (target, 80));
+        assert_eq!((changes[0].before, changes[0].after), ((false, true), (false, false)));
+    }
+}
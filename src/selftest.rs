@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::net::{IpAddr, Ipv4Addr, TcpListener as StdListener};
+use std::sync::Arc;
+use colored::*;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use crate::context::ScanContext;
+use crate::prober::NetProber;
+use crate::probes;
+use crate::scanner::ScanPlan;
+use crate::targets::TargetSpec;
+use crate::timeouts::Timeouts;
+use crate::{PortInfo, ScanResult};
+
+const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+// 單個測試案例的預期結果
+struct Case {
+    name: &'static str,
+    port: PortInfo,
+    inbound: bool,
+    outbound: bool,
+    // Web 案例預期的虛擬主機狀態碼
+    http_status: Option<u16>,
+    // 預期的橫幅版本 (內建 ssh 探測比對的結果)
+    banner: Option<&'static str>,
+}
+
+// 案例的結果 (名稱, 端口, 失敗原因)
+type Outcome = (&'static str, u16, Vec<String>);
+
+// 連線後主動送出 SSH 橫幅
+async fn spawn_banner() -> std::io::Result<u16> {
+    let listener = TcpListener::bind((LOCALHOST, 0)).await?;
+    let port = listener.local_addr()?.port();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let _ = stream.write_all(b"SSH-2.0-SelfTest_1.0\r\n").await;
+            });
+        }
+    });
+    Ok(port)
+}
+
+// TCP echo：把收到的資料原樣送回
+async fn spawn_echo() -> std::io::Result<u16> {
+    let listener = TcpListener::bind((LOCALHOST, 0)).await?;
+    let port = listener.local_addr()?.port();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buf = [0u8; 1024];
+                while let Ok(n) = stream.read(&mut buf).await {
+                    if n == 0 || stream.write_all(&buf[..n]).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    Ok(port)
+}
+
+// 假的 HTTP 伺服器：對任何請求回 200
+async fn spawn_http() -> std::io::Result<u16> {
+    let listener = TcpListener::bind((LOCALHOST, 0)).await?;
+    let port = listener.local_addr()?.port();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buf = [0u8; 2048];
+                if matches!(stream.read(&mut buf).await, Ok(n) if n > 0) {
+                    let _ = stream
+                        .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok")
+                        .await;
+                }
+            });
+        }
+    });
+    Ok(port)
+}
+
+// 只接受連線、從不回應
+async fn spawn_silent() -> std::io::Result<u16> {
+    let listener = TcpListener::bind((LOCALHOST, 0)).await?;
+    let port = listener.local_addr()?.port();
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((stream, _)) = listener.accept().await {
+            held.push(stream);
+        }
+    });
+    Ok(port)
+}
+
+// 取得一個目前沒有人監聽的端口
+fn closed_port() -> std::io::Result<u16> {
+    let listener = StdListener::bind((LOCALHOST, 0))?;
+    listener.local_addr().map(|a| a.port())
+}
+
+// 檢查單個案例，回傳失敗原因
+fn verify(case: &Case, result: Option<&ScanResult>) -> Vec<String> {
+    let Some(result) = result else {
+        return vec!["沒有結果".to_string()];
+    };
+
+    let mut failures = Vec::new();
+    if result.inbound != case.inbound {
+        failures.push(format!("入站 預期 {} 實際 {}", case.inbound, result.inbound));
+    }
+    if result.outbound != case.outbound {
+        failures.push(format!("出站 預期 {} 實際 {}", case.outbound, result.outbound));
+    }
+    // 連線成功才有延遲
+    match (result.outbound, result.latency_ms) {
+        (true, None) => failures.push("缺少延遲".to_string()),
+        (true, Some(ms)) if !(ms >= 0.0 && ms.is_finite()) => failures.push(format!("無效的延遲 {}", ms)),
+        (false, Some(ms)) => failures.push(format!("未連線卻有延遲 {}", ms)),
+        _ => {}
+    }
+    let version = result.banner.as_ref().and_then(|b| b.version.as_deref());
+    if version != case.banner {
+        failures.push(format!("橫幅 預期 {:?} 實際 {:?}", case.banner, version));
+    }
+    if let Some(expected) = case.http_status {
+        match result.vhosts.first() {
+            Some(vhost) if vhost.status == Some(expected) => {}
+            Some(vhost) => failures.push(format!("HTTP 預期 {} 實際 {}", expected, crate::vhost::describe(vhost))),
+            None => failures.push("沒有 HTTP 探測結果".to_string()),
+        }
+    }
+    failures
+}
+
+// 只掃描本機的計劃，不使用代理、ICMP 或其他選用功能；bench 子命令也使用
+pub fn localhost_plan(ports: Vec<PortInfo>, concurrency: usize, timeouts: Timeouts, vhosts: Vec<String>) -> ScanPlan {
+    let context = Arc::new(ScanContext::offline());
+    ScanPlan {
+        targets: vec![TargetSpec::Host {
+            name: LOCALHOST.to_string(),
+            addr: LOCALHOST,
+        }],
+        ports,
+        concurrency,
+        per_host_concurrency: None,
+        per_net: None,
+        timeouts,
+        vhosts,
+        knock: None,
+        proxy: None,
+        profiler: None,
+        icmp: None,
+        probes: None,
+        syn: None,
+        grading: Default::default(),
+        completed: Default::default(),
+        hooks: None,
+        adaptive: None,
+        context: context.clone(),
+        prober: Arc::new(NetProber::new(context, None)),
+        control: None,
+        route: None,
+        pipeline: Default::default(),
+        live: None,
+        progress: None,
+        directions: Default::default(),
+        health: Default::default(),
+        dependencies: Default::default(),
+        source_ports: Vec::new(),
+        captive: Default::default(),
+        pressure: None,
+    }
+}
+
+// 啟動本機測試服務並掃描，逐項驗證結果
+async fn run_cases() -> Result<Vec<Outcome>, Box<dyn Error>> {
+    // 監聽中的端口無法再被綁定，所以入站為 false；關閉的端口則可以綁定
+    let cases = [
+        Case {
+            name: "TCP echo",
+            port: PortInfo::new(spawn_echo().await?, "Echo", "SelfTest"),
+            inbound: false,
+            outbound: true,
+            http_status: None,
+            banner: None,
+        },
+        Case {
+            name: "HTTP 回應",
+            port: PortInfo::new(spawn_http().await?, "HTTP", "Web"),
+            inbound: false,
+            outbound: true,
+            http_status: Some(200),
+            banner: None,
+        },
+        Case {
+            name: "SSH 橫幅",
+            port: PortInfo::new(spawn_banner().await?, "SSH", "SelfTest"),
+            inbound: false,
+            outbound: true,
+            http_status: None,
+            banner: Some("SelfTest_1.0"),
+        },
+        Case {
+            name: "只接受連線",
+            port: PortInfo::new(spawn_silent().await?, "Silent", "SelfTest"),
+            inbound: false,
+            outbound: true,
+            http_status: None,
+            banner: None,
+        },
+        Case {
+            name: "關閉的端口",
+            port: PortInfo::new(closed_port()?, "Closed", "SelfTest"),
+            inbound: true,
+            outbound: false,
+            http_status: None,
+            banner: None,
+        },
+    ];
+
+    // 只對橫幅案例的臨時端口套用內建的 ssh 探測
+    let mut library = probes::load(None)?;
+    library.probes.retain(|probe| probe.name == "ssh");
+    let banner_ports: Vec<u16> = cases.iter().filter(|c| c.banner.is_some()).map(|c| c.port.port).collect();
+    for probe in &mut library.probes {
+        probe.ports = banner_ports.clone();
+    }
+
+    let plan = ScanPlan {
+        probes: Some(Arc::new(library)),
+        ..localhost_plan(
+            cases.iter().map(|c| c.port.clone()).collect(),
+            cases.len(),
+            Timeouts::default(),
+            vec!["localhost".to_string()],
+        )
+    };
+
+    let results = crate::perform_scan(&plan, None, true, false).await;
+    let empty = HashMap::new();
+    let host_results = results.get(&LOCALHOST).unwrap_or(&empty);
+    Ok(cases.iter().map(|case| (case.name, case.port.port, verify(case, host_results.get(&case.port)))).collect())
+}
+
+// self-test 子命令：印出每個案例的 PASS/FAIL
+pub async fn run() -> Result<(), Box<dyn Error>> {
+    println!("\n{}", "=== 自我測試 ===".bold());
+
+    let outcomes = run_cases().await?;
+    let mut failed = 0;
+    for (name, port, failures) in &outcomes {
+        if failures.is_empty() {
+            println!("{} {} (Port {})", "PASS".green().bold(), name, port);
+        } else {
+            failed += 1;
+            println!("{} {} (Port {}): {}", "FAIL".red().bold(), name, port, failures.join("; "));
+        }
+    }
+
+    println!("\n{} / {} 通過", outcomes.len() - failed, outcomes.len());
+    if failed > 0 {
+        return Err(format!("{} 個案例失敗", failed).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 與 self-test 子命令相同的案例，在 cargo test 中跑完整的探測流程
+    #[tokio::test]
+    async fn local_listeners_scan_as_expected() {
+        for (name, port, failures) in run_cases().await.unwrap() {
+            assert!(failures.is_empty(), "{} (Port {}): {}", name, port, failures.join("; "));
+        }
+    }
+
+    #[test]
+    fn verify_reports_each_mismatch() {
+        let case = Case {
+            name: "HTTP 回應",
+            port: PortInfo::new(8080, "HTTP", "Web"),
+            inbound: false,
+            outbound: true,
+            http_status: Some(200),
+            banner: Some("SelfTest_1.0"),
+        };
+        assert_eq!(verify(&case, None), vec!["沒有結果".to_string()]);
+        let mut result = crate::testutil::scan_result(false);
+        result.inbound = true;
+        result.latency_ms = Some(3.0);
+        assert_eq!(
+            verify(&case, Some(&result)),
+            vec![
+                "入站 預期 false 實際 true".to_string(),
+                "出站 預期 true 實際 false".to_string(),
+                "未連線卻有延遲 3".to_string(),
+                "橫幅 預期 Some(\"SelfTest_1.0\") 實際 None".to_string(),
+                "沒有 HTTP 探測結果".to_string(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,660 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use crate::closure::Failure;
+use crate::context::ScanContext;
+use crate::errors::ErrorCode;
+use crate::icmp::IcmpMonitor;
+use crate::limits::ScanError;
+use crate::pool::PoolStats;
+use crate::prober::{ProbeFuture, Prober, UdpReplies};
+use crate::probes::{Banner, ProbeLibrary};
+use crate::scanner::{Outbound, ScanPlan};
+use crate::srcport::SourceAttempt;
+use crate::targets::{Exclusions, TargetSpec};
+use crate::verify::Reprobe;
+use crate::vhost::VhostResult;
+use crate::PortInfo;
+
+// 記錄檔格式；不相容的變更時遞增
+const FORMAT: &str = "portscanner-session/1";
+
+// 重播時整份記錄都載入記憶體，解壓縮後超過此大小就拒絕
+const SIZE_LIMIT: u64 = 256 * 1024 * 1024;
+
+const COMPRESSION_LEVEL: i32 = 3;
+
+// 記錄的目標：網段連同落在其中的排除範圍
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RecordedTarget {
+    Host { name: String, addr: IpAddr },
+    Network { net: IpNet, excluded: Vec<IpNet> },
+}
+
+// 記錄檔的第一行：重播時的目標與端口都取自這裡
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Header {
+    pub format: String,
+    pub tool_version: String,
+    pub recorded_at: i64,
+    // 錄製時的 --target (未指定時為 None，即內建的出站測試位址)
+    pub target: Option<String>,
+    pub targets: Vec<RecordedTarget>,
+    pub ports: Vec<PortInfo>,
+    // 位址與主機名稱已換成 --anonymize 的假名
+    pub anonymized: bool,
+}
+
+impl Header {
+    // 以假名記錄時，網段的排除範圍也換成假名網段
+    fn new(target: Option<String>, plan: &ScanPlan) -> Self {
+        let anonymizer = plan.context.anonymizer.as_ref();
+        let shown = anonymizer.map_or_else(|| plan.targets.clone(), |anonymizer| anonymizer.targets(&plan.targets));
+        let targets = shown
+            .into_iter()
+            .zip(&plan.targets)
+            .filter_map(|(shown, original)| match (shown, original) {
+                (TargetSpec::Host { name, addr }, _) => Some(RecordedTarget::Host { name, addr }),
+                (TargetSpec::Network(net, _), TargetSpec::Network(_, excluded)) => Some(RecordedTarget::Network {
+                    net,
+                    excluded: excluded.nets().into_iter().map(|net| anonymizer.map_or(net, |a| a.net(net))).collect(),
+                }),
+                _ => None,
+            })
+            .collect();
+        let ports = plan
+            .ports
+            .iter()
+            .cloned()
+            .map(|mut port| {
+                // SRV 記錄帶有主機名稱，假名記錄時不保留
+                if anonymizer.is_some() {
+                    port.srv.clear();
+                }
+                port
+            })
+            .collect();
+        Header {
+            format: FORMAT.to_string(),
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            recorded_at: chrono::Utc::now().timestamp(),
+            target: target.map(|target| plan.context.show(&target)),
+            targets,
+            ports,
+            anonymized: anonymizer.is_some(),
+        }
+    }
+
+    pub fn targets(&self) -> Vec<TargetSpec> {
+        self.targets
+            .iter()
+            .map(|target| match target {
+                RecordedTarget::Host { name, addr } => TargetSpec::Host { name: name.clone(), addr: *addr },
+                RecordedTarget::Network { net, excluded } => TargetSpec::Network(*net, Arc::new(Exclusions::new(excluded))),
+            })
+            .collect()
+    }
+}
+
+// 記錄檔的最後一行：錄製時的本機網路環境，掃描結束時寫入
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Environment {
+    pub external_ip: Option<IpAddr>,
+    pub local_ip: Option<IpAddr>,
+    pub interfaces: Vec<IpAddr>,
+}
+
+impl Environment {
+    // 重播時不偵測本機網路，改用錄製時的位址
+    pub fn apply(&self, context: &mut ScanContext) {
+        context.local_ip = self.local_ip;
+        context.interfaces = self.interfaces.clone();
+    }
+}
+
+// 經過 Prober 的一次呼叫；重播時以此查詢記錄的結果
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Call {
+    Connect { dest: IpAddr, port: u16 },
+    Bind { port: u16, addr: IpAddr },
+    Banner { dest: IpAddr, port: u16 },
+    Udp { dest: IpAddr, port: u16 },
+    Reprobe { dest: SocketAddr, source: Option<IpAddr> },
+    Vhosts { dest: IpAddr, port: u16 },
+    ConnectFrom { dest: SocketAddr, source_port: u16 },
+}
+
+// 呼叫的結果，與 Call 的種類一一對應
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Outcome {
+    Connect(Outbound),
+    Bind(Result<(), ErrorCode>),
+    Banner(Option<Banner>),
+    // I/O 錯誤只保留訊息
+    Udp(Result<Vec<(SocketAddr, Vec<u8>)>, String>),
+    Reprobe(bool, Option<f64>, Option<Failure>, Option<ScanError>),
+    Vhosts(Vec<VhostResult>),
+    ConnectFrom(SourceAttempt),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Recorded {
+    call: Call,
+    outcome: Outcome,
+    // 從呼叫到取得結果的時間；重播時等待同樣久
+    elapsed: Duration,
+}
+
+impl Recorded {
+    fn consistent(&self) -> bool {
+        matches!(
+            (&self.call, &self.outcome),
+            (Call::Connect { .. }, Outcome::Connect(_))
+                | (Call::Bind { .. }, Outcome::Bind(_))
+                | (Call::Banner { .. }, Outcome::Banner(_))
+                | (Call::Udp { .. }, Outcome::Udp(_))
+                | (Call::Reprobe { .. }, Outcome::Reprobe(..))
+                | (Call::Vhosts { .. }, Outcome::Vhosts(_))
+                | (Call::ConnectFrom { .. }, Outcome::ConnectFrom(_))
+        )
+    }
+}
+
+// 記錄檔的一行：zstd 壓縮的 JSON Lines
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Line {
+    Header(Header),
+    Probe(Recorded),
+    Environment(Environment),
+}
+
+fn write_line(writer: &mut impl Write, line: &Line) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, line)?;
+    writer.write_all(b"\n")
+}
+
+type Writer = zstd::Encoder<'static, BufWriter<File>>;
+
+struct Sink {
+    // 寫入失敗或結束後為 None
+    writer: Option<Writer>,
+    error: Option<String>,
+    probes: usize,
+}
+
+// --record-session：包裝實際的 Prober，每次呼叫的輸入與結果寫入記錄檔
+// --anonymize 時寫入的位址、主機名稱與橫幅都換成假名
+pub struct Recorder {
+    inner: Arc<dyn Prober>,
+    context: Arc<ScanContext>,
+    path: PathBuf,
+    sink: Mutex<Sink>,
+}
+
+impl fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recorder").field("inner", &self.inner).field("path", &self.path).finish_non_exhaustive()
+    }
+}
+
+impl Recorder {
+    // 記錄 plan 目前的 Prober；之後應以回傳的 Recorder 取代 plan.prober
+    pub fn create(path: &Path, plan: &ScanPlan, target: Option<String>) -> Result<Self, String> {
+        let failed = |e: io::Error| format!("無法寫入探測記錄檔 {}: {}", path.display(), e);
+        let file = File::create(path).map_err(failed)?;
+        let mut writer = zstd::Encoder::new(BufWriter::new(file), COMPRESSION_LEVEL).map_err(failed)?;
+        write_line(&mut writer, &Line::Header(Header::new(target, plan))).map_err(failed)?;
+        Ok(Recorder {
+            inner: plan.prober.clone(),
+            context: plan.context.clone(),
+            path: path.to_path_buf(),
+            sink: Mutex::new(Sink { writer: Some(writer), error: None, probes: 0 }),
+        })
+    }
+
+    fn sink(&self) -> MutexGuard<'_, Sink> {
+        self.sink.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // 寫入失敗後不再記錄，錯誤在 finish 時回報
+    fn write(&self, call: Call, outcome: Outcome, elapsed: Duration) {
+        let line = Line::Probe(Recorded { call, outcome, elapsed });
+        let mut sink = self.sink();
+        let written = match &mut sink.writer {
+            Some(writer) => write_line(writer, &line),
+            None => return,
+        };
+        match written {
+            Ok(()) => sink.probes += 1,
+            Err(e) => {
+                sink.error = Some(format!("無法寫入探測記錄檔 {}: {}", self.path.display(), e));
+                sink.writer = None;
+            }
+        }
+    }
+
+    // 寫入錄製時的網路環境並結束壓縮串流，回傳記錄的探測數
+    pub fn finish(&self) -> Result<usize, String> {
+        let environment = Environment {
+            external_ip: self.context.external_ip().and_then(|ip| ip.parse().ok()).map(|ip| self.ip(ip)),
+            local_ip: self.context.local_ip.map(|ip| self.ip(ip)),
+            interfaces: self.context.interfaces.iter().map(|ip| self.ip(*ip)).collect(),
+        };
+        let mut sink = self.sink();
+        if let Some(error) = sink.error.take() {
+            return Err(error);
+        }
+        let Some(mut writer) = sink.writer.take() else {
+            return Err(format!("探測記錄檔 {} 已經結束", self.path.display()));
+        };
+        write_line(&mut writer, &Line::Environment(environment))
+            .and_then(|_| writer.finish())
+            .and_then(|mut file| file.flush())
+            .map_err(|e| format!("無法寫入探測記錄檔 {}: {}", self.path.display(), e))?;
+        Ok(sink.probes)
+    }
+
+    // 入站測試固定綁定 0.0.0.0，不換成假名重播時才對得上
+    fn ip(&self, addr: IpAddr) -> IpAddr {
+        match &self.context.anonymizer {
+            Some(anonymizer) if !addr.is_unspecified() => anonymizer.ip(addr),
+            _ => addr,
+        }
+    }
+
+    fn socket_addr(&self, addr: SocketAddr) -> SocketAddr {
+        SocketAddr::new(self.ip(addr.ip()), addr.port())
+    }
+
+    fn text(&self, text: &str) -> String {
+        self.context.show(text)
+    }
+
+    fn outbound(&self, outbound: &Outbound) -> Outbound {
+        let mut recorded = outbound.clone();
+        recorded.local = recorded.local.map(|local| self.socket_addr(local));
+        if let Some(icmp) = &mut recorded.icmp {
+            icmp.from = self.ip(icmp.from);
+        }
+        recorded
+    }
+
+    // 原始位元組無法換成假名，假名記錄時只保留解碼後的文字
+    fn banner(&self, banner: &Option<Banner>) -> Option<Banner> {
+        let anonymized = self.context.anonymizer.is_some();
+        banner.clone().map(|banner| Banner {
+            text: self.text(&banner.text),
+            raw: banner.raw.filter(|_| !anonymized),
+            ..banner
+        })
+    }
+
+    fn vhosts(&self, results: &[VhostResult]) -> Vec<VhostResult> {
+        results
+            .iter()
+            .map(|result| VhostResult {
+                name: self.text(&result.name),
+                error: result.error.as_deref().map(|error| self.text(error)),
+                ..result.clone()
+            })
+            .collect()
+    }
+
+    fn reprobe_outcome(&self, (connected, latency_ms, failure, error): Reprobe) -> Outcome {
+        Outcome::Reprobe(connected, latency_ms, failure, error)
+    }
+}
+
+impl Prober for Recorder {
+    fn connect<'a>(&'a self, dest: IpAddr, port: u16, limit: Duration, icmp: Option<&'a IcmpMonitor>) -> ProbeFuture<'a, Outbound> {
+        Box::pin(async move {
+            let started = Instant::now();
+            let mut outbound = self.inner.connect(dest, port, limit, icmp).await;
+            // 錄製時也使用探測層的計時，重播的延遲才會與原本的報告相同
+            let elapsed = *outbound.elapsed.get_or_insert(started.elapsed());
+            self.write(Call::Connect { dest: self.ip(dest), port }, Outcome::Connect(self.outbound(&outbound)), elapsed);
+            outbound
+        })
+    }
+
+    fn bind(&self, port: u16, addr: IpAddr) -> ProbeFuture<'_, Result<(), ErrorCode>> {
+        Box::pin(async move {
+            let started = Instant::now();
+            let bound = self.inner.bind(port, addr).await;
+            self.write(Call::Bind { port, addr: self.ip(addr) }, Outcome::Bind(bound), started.elapsed());
+            bound
+        })
+    }
+
+    fn banner<'a>(&'a self, library: &'a ProbeLibrary, dest: IpAddr, port: u16, limit: Duration) -> ProbeFuture<'a, Option<Banner>> {
+        Box::pin(async move {
+            let started = Instant::now();
+            let banner = self.inner.banner(library, dest, port, limit).await;
+            self.write(Call::Banner { dest: self.ip(dest), port }, Outcome::Banner(self.banner(&banner)), started.elapsed());
+            banner
+        })
+    }
+
+    fn udp_exchange<'a>(
+        &'a self,
+        dest: IpAddr,
+        port: u16,
+        payload: &'a [u8],
+        wait: Duration,
+        linger: Duration,
+    ) -> ProbeFuture<'a, UdpReplies> {
+        Box::pin(async move {
+            let started = Instant::now();
+            let replies = self.inner.udp_exchange(dest, port, payload, wait, linger).await;
+            let recorded = match &replies {
+                Ok(replies) => Ok(replies.iter().map(|(from, reply)| (self.socket_addr(*from), reply.clone())).collect()),
+                Err(e) => Err(self.text(&e.to_string())),
+            };
+            self.write(Call::Udp { dest: self.ip(dest), port }, Outcome::Udp(recorded), started.elapsed());
+            replies
+        })
+    }
+
+    fn reprobe(&self, dest: SocketAddr, limit: Duration, source: Option<IpAddr>) -> ProbeFuture<'_, Reprobe> {
+        Box::pin(async move {
+            let started = Instant::now();
+            let outcome = self.inner.reprobe(dest, limit, source).await;
+            let call = Call::Reprobe { dest: self.socket_addr(dest), source: source.map(|source| self.ip(source)) };
+            self.write(call, self.reprobe_outcome(outcome), started.elapsed());
+            outcome
+        })
+    }
+
+    fn vhosts<'a>(&'a self, dest: IpAddr, port: &'a PortInfo, names: &'a [String]) -> ProbeFuture<'a, Vec<VhostResult>> {
+        Box::pin(async move {
+            let started = Instant::now();
+            let results = self.inner.vhosts(dest, port, names).await;
+            self.write(Call::Vhosts { dest: self.ip(dest), port: port.port }, Outcome::Vhosts(self.vhosts(&results)), started.elapsed());
+            results
+        })
+    }
+
+    fn connect_from(&self, dest: SocketAddr, source_port: u16, limit: Duration) -> ProbeFuture<'_, SourceAttempt> {
+        Box::pin(async move {
+            let started = Instant::now();
+            let attempt = self.inner.connect_from(dest, source_port, limit).await;
+            self.write(Call::ConnectFrom { dest: self.socket_addr(dest), source_port }, Outcome::ConnectFrom(attempt), started.elapsed());
+            attempt
+        })
+    }
+
+    fn socket_stats(&self) -> Option<PoolStats> {
+        self.inner.socket_stats()
+    }
+}
+
+// --replay：依記錄回傳每次呼叫的結果，不做任何網路操作
+// 同一呼叫出現多次時依記錄順序回傳，用完後重複最後一筆
+#[derive(Debug)]
+pub struct ReplayProber {
+    recorded: Mutex<HashMap<Call, VecDeque<(Outcome, Duration)>>>,
+    // 記錄中沒有的呼叫數 (例如重播時多加了選項)
+    missing: AtomicUsize,
+}
+
+impl ReplayProber {
+    pub fn missing(&self) -> usize {
+        self.missing.load(Ordering::Relaxed)
+    }
+
+    // 等待記錄的時間後回傳結果，保留原本的完成順序
+    async fn replay(&self, call: Call) -> Option<(Outcome, Duration)> {
+        let next = {
+            let mut recorded = self.recorded.lock().unwrap_or_else(|e| e.into_inner());
+            recorded
+                .get_mut(&call)
+                .and_then(|queue| if queue.len() > 1 { queue.pop_front() } else { queue.front().cloned() })
+        };
+        match next {
+            Some((outcome, elapsed)) => {
+                tokio::time::sleep(elapsed).await;
+                Some((outcome, elapsed))
+            }
+            None => {
+                self.missing.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+}
+
+// 記錄中沒有的連線視為逾時
+fn missing_reprobe() -> Reprobe {
+    (false, None, Some(Failure::Timeout), None)
+}
+
+impl Prober for ReplayProber {
+    fn connect<'a>(&'a self, dest: IpAddr, port: u16, _limit: Duration, _icmp: Option<&'a IcmpMonitor>) -> ProbeFuture<'a, Outbound> {
+        Box::pin(async move {
+            match self.replay(Call::Connect { dest, port }).await {
+                Some((Outcome::Connect(outbound), elapsed)) => Outbound { elapsed: Some(elapsed), ..outbound },
+                _ => Outbound { failure: Some(Failure::Timeout), ..Default::default() },
+            }
+        })
+    }
+
+    fn bind(&self, port: u16, addr: IpAddr) -> ProbeFuture<'_, Result<(), ErrorCode>> {
+        Box::pin(async move {
+            match self.replay(Call::Bind { port, addr }).await {
+                Some((Outcome::Bind(bound), _)) => bound,
+                _ => Err(ErrorCode::BindFailed),
+            }
+        })
+    }
+
+    fn banner<'a>(&'a self, _library: &'a ProbeLibrary, dest: IpAddr, port: u16, _limit: Duration) -> ProbeFuture<'a, Option<Banner>> {
+        Box::pin(async move {
+            match self.replay(Call::Banner { dest, port }).await {
+                Some((Outcome::Banner(banner), _)) => banner,
+                _ => None,
+            }
+        })
+    }
+
+    fn udp_exchange<'a>(
+        &'a self,
+        dest: IpAddr,
+        port: u16,
+        _payload: &'a [u8],
+        _wait: Duration,
+        _linger: Duration,
+    ) -> ProbeFuture<'a, UdpReplies> {
+        Box::pin(async move {
+            match self.replay(Call::Udp { dest, port }).await {
+                Some((Outcome::Udp(replies), _)) => replies.map_err(io::Error::other),
+                _ => Ok(Vec::new()),
+            }
+        })
+    }
+
+    fn reprobe(&self, dest: SocketAddr, _limit: Duration, source: Option<IpAddr>) -> ProbeFuture<'_, Reprobe> {
+        Box::pin(async move {
+            match self.replay(Call::Reprobe { dest, source }).await {
+                Some((Outcome::Reprobe(connected, latency_ms, failure, error), _)) => (connected, latency_ms, failure, error),
+                _ => missing_reprobe(),
+            }
+        })
+    }
+
+    fn vhosts<'a>(&'a self, dest: IpAddr, port: &'a PortInfo, _names: &'a [String]) -> ProbeFuture<'a, Vec<VhostResult>> {
+        Box::pin(async move {
+            match self.replay(Call::Vhosts { dest, port: port.port }).await {
+                Some((Outcome::Vhosts(results), _)) => results,
+                _ => Vec::new(),
+            }
+        })
+    }
+
+    fn connect_from(&self, dest: SocketAddr, source_port: u16, _limit: Duration) -> ProbeFuture<'_, SourceAttempt> {
+        Box::pin(async move {
+            match self.replay(Call::ConnectFrom { dest, source_port }).await {
+                Some((Outcome::ConnectFrom(attempt), _)) => attempt,
+                _ => Err(ErrorCode::BindFailed),
+            }
+        })
+    }
+}
+
+// 讀取的記錄檔
+#[derive(Debug)]
+pub struct Session {
+    pub header: Header,
+    pub environment: Environment,
+    pub prober: Arc<ReplayProber>,
+}
+
+impl Session {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("無法開啟探測記錄檔 {}: {}", path.display(), e))?;
+        let invalid = |e: &dyn fmt::Display| format!("{} 不是有效的探測記錄檔: {}", path.display(), e);
+        let decoder = zstd::Decoder::new(file).map_err(|e| invalid(&e))?;
+        let mut reader = BufReader::new(decoder).take(SIZE_LIMIT + 1);
+
+        let mut header = None;
+        let mut environment = None;
+        let mut recorded: HashMap<Call, VecDeque<(Outcome, Duration)>> = HashMap::new();
+        for (number, line) in (&mut reader).lines().enumerate() {
+            let line = line.map_err(|e| invalid(&e))?;
+            let line: Line = serde_json::from_str(&line).map_err(|e| invalid(&format!("第 {} 行: {}", number + 1, e)))?;
+            match (line, &header) {
+                (Line::Header(found), None) if number == 0 => {
+                    if found.format != FORMAT {
+                        return Err(format!("{} 的格式 {} 不受支援 (需要 {})", path.display(), found.format, FORMAT));
+                    }
+                    header = Some(found);
+                }
+                (Line::Probe(probe), Some(_)) if probe.consistent() => {
+                    recorded.entry(probe.call).or_default().push_back((probe.outcome, probe.elapsed));
+                }
+                (Line::Environment(found), Some(_)) => environment = Some(found),
+                _ => return Err(invalid(&format!("第 {} 行的位置或內容不正確", number + 1))),
+            }
+        }
+        if reader.limit() == 0 {
+            return Err(format!("{} 解壓縮後超過 {} MiB", path.display(), SIZE_LIMIT / 1024 / 1024));
+        }
+        let header = header.ok_or_else(|| invalid(&"沒有檔頭"))?;
+        let environment = environment.ok_or_else(|| invalid(&"記錄不完整 (錄製沒有正常結束)"))?;
+        Ok(Session {
+            header,
+            environment,
+            prober: Arc::new(ReplayProber { recorded: Mutex::new(recorded), missing: AtomicUsize::new(0) }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anonymize::Anonymizer;
+    use crate::context::ExternalIpSource;
+    use crate::prober::fake::{Script, Scripted, ScriptedProber};
+    use crate::testutil::{host, scan, scripted_plan, TempPath};
+
+    fn network() -> ScriptedProber {
+        ScriptedProber::new()
+            .with(host(1), 22, Script::new(Scripted::Open, Duration::from_millis(7)))
+            .with(host(1), 443, Script::new(Scripted::Unreachable, Duration::from_millis(30)))
+            .with(host(2), 80, Script::new(Scripted::Silent, Duration::ZERO))
+            .fallback(Script::new(Scripted::Refused, Duration::from_millis(3)))
+            .bindable(22)
+    }
+
+    // 依 (主機, 端口) 排序的 JSON 結果，用來比較兩次掃描
+    async fn results(plan: &ScanPlan) -> Vec<serde_json::Value> {
+        let mut records = scan(plan).await;
+        records.sort_by_key(|record| (record.host, record.port.port));
+        records.iter().map(|record| serde_json::to_value(record).unwrap()).collect()
+    }
+
+    // 與 main 的 --replay 相同：目標、端口與本機位址都來自記錄檔
+    fn replay_plan(session: &Session, concurrency: usize) -> ScanPlan {
+        let mut context = ScanContext::new(ExternalIpSource::Disabled);
+        session.environment.apply(&mut context);
+        ScanPlan {
+            targets: session.header.targets(),
+            ports: session.header.ports.clone(),
+            prober: session.prober.clone(),
+            context: Arc::new(context),
+            ..scripted_plan(&[], &[], concurrency, Arc::new(ScriptedProber::new()))
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn replay_reproduces_the_recorded_scan() {
+        let path = TempPath::new("session.bin");
+        let mut plan = scripted_plan(&[host(1), host(2)], &[22, 80, 443], 4, Arc::new(network()));
+        let recorder = Arc::new(Recorder::create(path.path(), &plan, Some("192.0.2.1,192.0.2.2".to_string())).unwrap());
+        plan.prober = recorder.clone();
+        let recorded = results(&plan).await;
+        assert!(recorder.finish().unwrap() >= 6);
+
+        let session = Session::load(path.path()).unwrap();
+        assert_eq!(session.header.target.as_deref(), Some("192.0.2.1,192.0.2.2"));
+        assert_eq!(session.header.ports.len(), 3);
+        let replay = replay_plan(&session, 4);
+        assert_eq!(results(&replay).await, recorded);
+        assert_eq!(session.prober.missing(), 0);
+        // 第二次重播得到同樣的結果
+        assert_eq!(results(&replay).await, recorded);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn anonymized_sessions_replay_without_real_addresses() {
+        let path = TempPath::new("session.bin");
+        let mut context = ScanContext::new(ExternalIpSource::Disabled);
+        context.anonymizer = Some(Anonymizer::new([7; 32]));
+        let mut plan = scripted_plan(&[host(1), host(2)], &[22, 80], 2, Arc::new(network()));
+        plan.context = Arc::new(context);
+        let recorder = Arc::new(Recorder::create(path.path(), &plan, None).unwrap());
+        plan.prober = recorder.clone();
+        let recorded = results(&plan).await;
+        recorder.finish().unwrap();
+
+        let mut text = String::new();
+        zstd::Decoder::new(File::open(path.path()).unwrap()).unwrap().read_to_string(&mut text).unwrap();
+        assert!(!text.contains("192.0.2."), "{}", text);
+
+        let session = Session::load(path.path()).unwrap();
+        assert!(session.header.anonymized);
+        let replay = replay_plan(&session, 2);
+        let replayed = results(&replay).await;
+        assert_eq!(session.prober.missing(), 0);
+        // 連線結果相同，只有位址換成假名
+        let states = |results: &[serde_json::Value]| -> Vec<(serde_json::Value, serde_json::Value)> {
+            results.iter().map(|r| (r["result"]["outbound"].clone(), r["result"]["latency_ms"].clone())).collect()
+        };
+        let mut expected = states(&recorded);
+        let mut actual = states(&replayed);
+        expected.sort_by_key(|state| state.1.to_string());
+        actual.sort_by_key(|state| state.1.to_string());
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn truncated_recordings_are_rejected() {
+        let path = TempPath::new("session.bin");
+        let plan = scripted_plan(&[host(1)], &[22], 1, Arc::new(network()));
+        let recorder = Recorder::create(path.path(), &plan, None).unwrap();
+        // 沒有呼叫 finish：壓縮串流與環境都不完整
+        drop(recorder);
+        assert!(Session::load(path.path()).is_err());
+    }
+}
@@ -0,0 +1,498 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use clap::error::{ContextKind, ContextValue};
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches};
+use colored::*;
+use crate::cli::Cli;
+use crate::config;
+use crate::errors::{self, ErrorCode};
+
+// 環境變數的前綴：--timeout 對應 PORTSCANNER_TIMEOUT，--no-pager 對應 PORTSCANNER_NO_PAGER
+const ENV_PREFIX: &str = "PORTSCANNER_";
+
+// 放寬安全限制的選項只能在命令列明確指定，環境變數或設定檔提供時視為錯誤
+const COMMAND_LINE_ONLY: &[&str] = &["allow_public", "allow_large", "force", "authorized_by", "intrusive", "intrusiveness"];
+
+// 不能由環境變數或設定檔提供的選項
+const SKIPPED: &[&str] = &["help", "version", "allow_public", "allow_large", "force", "authorized_by", "intrusive", "intrusiveness"];
+
+// 選項值的來源，依優先順序排列
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    CommandLine,
+    Env(String),
+    // 設定檔的 [defaults] 區段
+    Config(PathBuf),
+    // 設定檔的 [profiles.名稱] 區段 (--profile)
+    Profile(PathBuf, String),
+    Default,
+}
+
+impl Source {
+    fn describe(&self) -> String {
+        match self {
+            Source::CommandLine => "命令列".to_string(),
+            Source::Env(name) => format!("環境變數 {}", name),
+            Source::Config(path) => format!("設定檔 {} [defaults]", path.display()),
+            Source::Profile(path, name) => format!("設定檔 {} [profiles.{}]", path.display(), name),
+            Source::Default => "預設值".to_string(),
+        }
+    }
+
+    // 設定檔的問題歸為 E4001，其餘 (環境變數) 為 E4002
+    fn error(&self, message: String) -> Box<dyn Error> {
+        let code = match self {
+            Source::Config(_) | Source::Profile(..) => ErrorCode::ConfigInvalid,
+            _ => ErrorCode::InvalidOptions,
+        };
+        errors::coded(code, message)
+    }
+}
+
+// 由環境變數或設定檔補上的選項
+#[derive(Debug)]
+struct Injection {
+    id: String,
+    long: String,
+    args: Vec<OsString>,
+    source: Source,
+}
+
+// 解析後各選項的來源，供 config show 顯示
+#[derive(Debug)]
+pub struct Layers {
+    matches: ArgMatches,
+    sources: BTreeMap<String, Source>,
+    // 設定檔位置與來源；None 表示沒有可用的預設位置
+    config: Option<(PathBuf, Source)>,
+}
+
+impl Layers {
+    // 錯誤訊息的補充：列出非命令列提供的選項來源，例如 " (--output 來自環境變數 PORTSCANNER_OUTPUT)"
+    pub fn origins(&self, ids: &[&str]) -> String {
+        let notes: Vec<String> = ids
+            .iter()
+            .filter_map(|id| match self.sources.get(*id) {
+                Some(source @ (Source::Env(_) | Source::Config(_) | Source::Profile(..))) => Some(format!("--{} 來自{}", id.replace('_', "-"), source.describe())),
+                _ => None,
+            })
+            .collect();
+        match notes.is_empty() {
+            true => String::new(),
+            false => format!(" ({})", notes.join("，")),
+        }
+    }
+}
+
+fn env_name(long: &str) -> String {
+    format!("{}{}", ENV_PREFIX, long.to_ascii_uppercase().replace('-', "_"))
+}
+
+// 開關選項的文字值
+fn parse_flag(value: &str, source: &Source) -> Result<bool, Box<dyn Error>> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "" | "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err(source.error(format!("{} 的值 '{}' 無效 (應為 true 或 false)", source.describe(), value))),
+    }
+}
+
+// 設定檔 [defaults] 的值轉為命令列文字；陣列對應可重複指定的選項
+fn config_values(key: &str, value: &toml::Value, source: &Source) -> Result<Vec<String>, Box<dyn Error>> {
+    let scalar = |value: &toml::Value| match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(n) => Ok(n.to_string()),
+        toml::Value::Float(n) => Ok(n.to_string()),
+        toml::Value::Boolean(b) => Ok(b.to_string()),
+        _ => Err(source.error(format!("{} 的 {} 必須是字串、數字、布林值或陣列", source.describe(), key))),
+    };
+    match value {
+        toml::Value::Array(items) => items.iter().map(scalar).collect(),
+        value => scalar(value).map(|v| vec![v]),
+    }
+}
+
+// 設定檔位置：--config > PORTSCANNER_CONFIG > 設定目錄下的 config.toml
+fn explicit_location(command_line: Option<&Path>) -> Option<(PathBuf, Source)> {
+    if let Some(path) = command_line {
+        return Some((path.to_path_buf(), Source::CommandLine));
+    }
+    let name = env_name("config");
+    match env::var_os(&name).filter(|v| !v.is_empty()) {
+        Some(path) => Some((PathBuf::from(path), Source::Env(name))),
+        None => config::default_path().map(|path| (path, Source::Default)),
+    }
+}
+
+// 掃描時讀取的設定檔位置 (portscanner init 寫入的位置)；找不到設定目錄時為 None
+pub fn config_path(command_line: Option<&Path>) -> Option<PathBuf> {
+    explicit_location(command_line).map(|(path, _)| path)
+}
+
+// --no-config、指定了端口的 quick 與 init 不讀取設定檔
+fn config_location(first: &ArgMatches) -> Option<(PathBuf, Source)> {
+    if skips_config(first) {
+        return None;
+    }
+    let command_line = match first.value_source("config") {
+        Some(ValueSource::CommandLine) => first.get_one::<PathBuf>("config"),
+        _ => None,
+    };
+    explicit_location(command_line.map(PathBuf::as_path))
+}
+
+// init 可能正要取代無法載入的設定檔
+fn skips_config(first: &ArgMatches) -> bool {
+    let quick_ports = first.subcommand_matches("quick").is_some_and(|quick| quick.get_one::<String>("ports").is_some());
+    first.get_flag("no_config") || quick_ports || first.subcommand_matches("init").is_some()
+}
+
+// --profile 或 PORTSCANNER_PROFILE 選擇的設定組合；init 是建立設定組合，不套用
+fn profile_name(first: &ArgMatches) -> Option<String> {
+    if first.subcommand_matches("init").is_some() {
+        return None;
+    }
+    match first.value_source("profile") {
+        Some(ValueSource::CommandLine) => first.get_one::<String>("profile").cloned(),
+        _ => env::var(env_name("profile")).ok().filter(|name| !name.is_empty()),
+    }
+}
+
+// 設定檔提供的選項：選項名稱 (no-pager 的寫法) -> (檔案中的鍵, 值, 來源)
+type ConfigEntries = BTreeMap<String, (String, toml::Value, Source)>;
+
+// 設定檔的 [defaults] 區段，再以 [profiles.名稱] 覆蓋；預設位置不存在時視為空
+fn config_entries(path: &Path, source: &Source, profile: Option<&str>) -> Result<ConfigEntries, Box<dyn Error>> {
+    let invalid = |message: String| errors::coded(ErrorCode::ConfigInvalid, message);
+    let mut table = match *source == Source::Default && !path.exists() {
+        true => toml::Table::new(),
+        false => {
+            let text = fs::read_to_string(path).map_err(|e| invalid(format!("無法讀取設定檔 {}: {}", path.display(), e)))?;
+            toml::from_str(&text).map_err(|e| invalid(format!("設定檔 {} 格式錯誤: {}", path.display(), e)))?
+        }
+    };
+    let mut entries = ConfigEntries::new();
+    let mut add = |section: toml::Table, source: Source| {
+        for (key, value) in section {
+            entries.insert(key.replace('_', "-"), (key, value, source.clone()));
+        }
+    };
+    match table.remove("defaults") {
+        Some(toml::Value::Table(defaults)) => add(defaults, Source::Config(path.to_path_buf())),
+        Some(_) => return Err(invalid(format!("設定檔 {} 的 defaults 必須是區段", path.display()))),
+        None => {}
+    }
+    if let Some(name) = profile {
+        let section = match table.remove("profiles") {
+            Some(toml::Value::Table(mut profiles)) => profiles.remove(name),
+            Some(_) => return Err(invalid(format!("設定檔 {} 的 profiles 必須是區段", path.display()))),
+            None => None,
+        };
+        match section {
+            Some(toml::Value::Table(section)) => add(section, Source::Profile(path.to_path_buf(), name.to_string())),
+            Some(_) => return Err(invalid(format!("設定檔 {} 的 profiles.{} 必須是區段", path.display(), name))),
+            None => return Err(invalid(format!("設定檔 {} 沒有設定組合 [profiles.{}]", path.display(), name))),
+        }
+    }
+    Ok(entries)
+}
+
+// 命令列沒有指定的選項依序從環境變數與設定檔 ([profiles.名稱] > [defaults]) 補上
+fn injections(first: &ArgMatches, config: Option<&(PathBuf, Source)>) -> Result<Vec<Injection>, Box<dyn Error>> {
+    let profile = profile_name(first);
+    let mut entries = match (config, &profile) {
+        (Some((path, source)), _) => config_entries(path, source, profile.as_deref())?,
+        (None, Some(name)) => return Err(errors::coded(ErrorCode::InvalidOptions, format!("設定組合 {} 需要設定檔，不能搭配 --no-config", name))),
+        (None, None) => ConfigEntries::new(),
+    };
+    let mut found = Vec::new();
+    for arg in Cli::command().get_arguments() {
+        let (id, Some(long)) = (arg.get_id().as_str(), arg.get_long()) else {
+            continue;
+        };
+        // 設定檔可用 no-pager 或 no_pager，config_entries 已統一為前者
+        let from_config = entries.remove(long);
+        // --config 與 --profile 決定讀取的位置，設定檔本身不能再指定
+        if let (Some((_, _, source)), "config" | "profile") = (&from_config, id) {
+            return Err(source.error(format!("{} 不能指定 {}", source.describe(), id)));
+        }
+        let name = env_name(long);
+        if COMMAND_LINE_ONLY.contains(&id) {
+            let source = match (env::var_os(&name), &from_config) {
+                (Some(_), _) => Some(Source::Env(name)),
+                (None, Some((_, _, source))) => Some(source.clone()),
+                _ => None,
+            };
+            if let Some(source) = source {
+                return Err(source.error(format!("{} 不能指定 --{}，放寬安全限制的選項只能在命令列指定", source.describe(), long)));
+            }
+            continue;
+        }
+        if SKIPPED.contains(&id) || first.value_source(id) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+
+        let (values, source) = match (env::var(&name), from_config) {
+            (Ok(value), _) => (vec![value], Source::Env(name)),
+            (Err(env::VarError::NotUnicode(_)), _) => {
+                return Err(Source::Env(name.clone()).error(format!("環境變數 {} 不是有效的 UTF-8", name)));
+            }
+            (Err(_), Some((key, value, source))) => (config_values(&key, &value, &source)?, source),
+            _ => continue,
+        };
+        let args = match arg.get_action() {
+            ArgAction::SetTrue => {
+                let mut enabled = false;
+                for value in &values {
+                    enabled = parse_flag(value, &source)?;
+                }
+                match enabled {
+                    true => vec![OsString::from(format!("--{}", long))],
+                    false => continue,
+                }
+            }
+            _ => values.iter().map(|value| OsString::from(format!("--{}={}", long, value))).collect(),
+        };
+        found.push(Injection {
+            id: id.to_string(),
+            long: long.to_string(),
+            args,
+            source,
+        });
+    }
+    if let Some((key, _, source)) = entries.into_values().next() {
+        return Err(source.error(format!("{} 中的 {} 不是有效的選項", source.describe(), key)));
+    }
+    Ok(found)
+}
+
+// 補上的值造成的錯誤改以來源描述，命令列本身的錯誤照 clap 的方式顯示
+fn blame(error: &clap::Error, injected: &[Injection]) -> Option<Box<dyn Error>> {
+    let mentioned = |kind: ContextKind| match error.get(kind) {
+        Some(ContextValue::String(arg)) => Some(arg.clone()),
+        Some(ContextValue::Strings(args)) => args.first().cloned(),
+        _ => None,
+    };
+    let culprit = [ContextKind::InvalidArg, ContextKind::PriorArg]
+        .into_iter()
+        .filter_map(mentioned)
+        .find_map(|arg| {
+            injected.iter().find(|i| {
+                let flag = format!("--{}", i.long);
+                arg == flag || arg.starts_with(&format!("{} ", flag)) || arg.starts_with(&format!("{}=", flag))
+            })
+        })?;
+    let rendered = error.render().to_string();
+    let message = rendered.lines().next().unwrap_or_default().trim_start_matches("error: ");
+    Some(culprit.source.error(format!("{} 提供的選項有誤: {}", culprit.source.describe(), message)))
+}
+
+// 解析結果、補上的選項與設定檔位置
+type Resolved = (ArgMatches, Vec<Injection>, Option<(PathBuf, Source)>);
+
+// 補上環境變數與設定檔的值後解析；命令列本身的錯誤以 clap::Error 回傳
+fn resolve(args: &[OsString]) -> Result<Resolved, Box<dyn Error>> {
+    let first = Cli::command().try_get_matches_from(args)?;
+    let config = config_location(&first);
+    // PORTSCANNER_CONFIG 與其他環境變數一樣補成 --config，主程式照常讀取
+    let injected = injections(&first, config.as_ref())?;
+
+    let matches = match injected.is_empty() {
+        true => first,
+        false => {
+            let mut merged = args[..1].to_vec();
+            merged.extend(injected.iter().flat_map(|i| i.args.iter().cloned()));
+            merged.extend(args.iter().skip(1).cloned());
+            match Cli::command().try_get_matches_from(&merged) {
+                Ok(matches) => matches,
+                Err(e) => return Err(blame(&e, &injected).unwrap_or_else(|| Box::new(e))),
+            }
+        }
+    };
+    Ok((matches, injected, config))
+}
+
+// 解析命令列；優先順序為命令列 > 環境變數 > 設定檔 [profiles.名稱] > [defaults] > 預設值
+// 補上的值放在命令列參數之前一起解析，衝突與格式檢查和命令列相同
+pub fn parse() -> Result<(Cli, Layers), Box<dyn Error>> {
+    let args: Vec<OsString> = env::args_os().collect();
+    let (matches, injected, config) = match resolve(&args) {
+        Ok(resolved) => resolved,
+        Err(e) => match e.downcast::<clap::Error>() {
+            Ok(e) => e.exit(),
+            Err(e) => return Err(e),
+        },
+    };
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    let mut sources = BTreeMap::new();
+    for arg in Cli::command().get_arguments() {
+        let id = arg.get_id().as_str();
+        let source = match (injected.iter().find(|i| i.id == id), matches.value_source(id)) {
+            (Some(injection), _) => injection.source.clone(),
+            (None, Some(ValueSource::CommandLine)) => Source::CommandLine,
+            _ => Source::Default,
+        };
+        sources.insert(id.to_string(), source);
+    }
+    Ok((cli, Layers { matches, sources, config }))
+}
+
+// 以掃描時相同的方式載入設定檔並套用設定組合；portscanner init 確認產生的檔案可以使用
+pub fn validate(path: &Path, profile: &str) -> Result<(), Box<dyn Error>> {
+    config::load(Some(path))?;
+    let args = ["portscanner".into(), "--config".into(), path.as_os_str().to_os_string(), "--profile".into(), profile.into()];
+    let (matches, _, _) = resolve(&args).map_err(|e| match e.downcast::<clap::Error>() {
+        Ok(e) => format!("設定檔 {} 的選項有誤: {}", path.display(), e.render().to_string().lines().next().unwrap_or_default()).into(),
+        Err(e) => e,
+    })?;
+    Cli::from_arg_matches(&matches)?;
+    Ok(())
+}
+
+// config show：顯示每個選項的有效值與來源
+pub fn display(layers: &Layers) {
+    println!("\n{}", "=== 有效設定 ===".bold());
+    match &layers.config {
+        Some((path, source)) => {
+            let origin = match source {
+                Source::Default => "預設位置".to_string(),
+                source => source.describe(),
+            };
+            let state = if path.exists() { "" } else { "，不存在" };
+            println!("設定檔: {} ({}{})", path.display(), origin, state);
+        }
+        None => println!("設定檔: 無 (找不到設定目錄)"),
+    }
+    println!("{}", "優先順序: 命令列 > 環境變數 (PORTSCANNER_*) > 設定檔 [defaults] > 預設值".dimmed());
+    println!();
+
+    for arg in Cli::command().get_arguments() {
+        let (id, Some(long)) = (arg.get_id().as_str(), arg.get_long()) else {
+            continue;
+        };
+        if SKIPPED.contains(&id) {
+            continue;
+        }
+        let value = layers
+            .matches
+            .get_raw(id)
+            .map(|values| values.map(|v| v.to_string_lossy().into_owned()).collect::<Vec<_>>().join(","))
+            .filter(|value| !value.is_empty());
+        let source = layers.sources.get(id).cloned().unwrap_or(Source::Default);
+        // 先補齊寬度再上色，色碼不佔顯示寬度
+        let value = match value {
+            Some(value) => format!("{:24}", value).normal(),
+            None => format!("{:24}", "(未設定)").dimmed(),
+        };
+        let source = match source {
+            Source::Default => source.describe().dimmed(),
+            _ => source.describe().cyan(),
+        };
+        println!("  --{:24} {:24} {}", long, value, source);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+
+    fn config_file(text: &str) -> (TempPath, (PathBuf, Source)) {
+        let file = TempPath::with("settings.toml", text);
+        let location = (file.path().to_path_buf(), Source::CommandLine);
+        (file, location)
+    }
+
+    fn first() -> ArgMatches {
+        Cli::command().try_get_matches_from(["r1"]).unwrap()
+    }
+
+    #[test]
+    fn command_line_only_options_exist_and_are_skipped() {
+        let ids: Vec<String> = Cli::command().get_arguments().map(|arg| arg.get_id().to_string()).collect();
+        for id in COMMAND_LINE_ONLY {
+            assert!(ids.iter().any(|known| known == id), "{}", id);
+            assert!(SKIPPED.contains(id), "{}", id);
+        }
+    }
+
+    #[test]
+    fn config_cannot_relax_safety_limits() {
+        for (key, value) in [("allow-public", "true"), ("allow_large", "true"), ("force", "true"), ("authorized-by", "\"x\""), ("intrusive", "true"), ("intrusiveness", "\"intrusive\"")] {
+            let (_file, location) = config_file(&format!("[defaults]\n{} = {}\n", key, value));
+            let error = injections(&first(), Some(&location)).unwrap_err();
+            assert_eq!(errors::classify(error.as_ref()), ErrorCode::ConfigInvalid, "{}", key);
+            assert!(error.to_string().contains("只能在命令列指定"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn config_values_are_injected_with_their_source() {
+        let (_file, location) = config_file("[defaults]\nno_pager = true\nvhost = [\"a.example\", \"b.example\"]\n");
+        let injected = injections(&first(), Some(&location)).unwrap();
+        let vhost = injected.iter().find(|i| i.id == "vhost").unwrap();
+        assert_eq!(vhost.args, vec![OsString::from("--vhost=a.example"), OsString::from("--vhost=b.example")]);
+        assert_eq!(vhost.source, Source::Config(location.0.clone()));
+        assert!(injected.iter().any(|i| i.id == "no_pager" && i.args == vec![OsString::from("--no-pager")]));
+    }
+
+    #[test]
+    fn profiles_override_defaults() {
+        let (_file, location) = config_file(
+            "[defaults]\nno_pager = true\nports = \"22\"\n\n[profiles.office]\nports = \"80,443\"\n\n[profiles.lab]\nconcurrency = 8\n",
+        );
+        let profile = |name: &str| Cli::command().try_get_matches_from(["r1", "--profile", name]).unwrap();
+        let injected = injections(&profile("office"), Some(&location)).unwrap();
+        let ports = injected.iter().find(|i| i.id == "ports").unwrap();
+        assert_eq!(ports.args, vec![OsString::from("--ports=80,443")]);
+        assert_eq!(ports.source, Source::Profile(location.0.clone(), "office".to_string()));
+        assert!(ports.source.describe().ends_with("[profiles.office]"));
+        // 設定組合沒有的選項沿用 [defaults]，其他設定組合不套用
+        assert!(injected.iter().any(|i| i.id == "no_pager" && i.source == Source::Config(location.0.clone())));
+        assert!(!injected.iter().any(|i| i.id == "concurrency"));
+
+        let error = injections(&profile("home"), Some(&location)).unwrap_err();
+        assert_eq!(errors::classify(error.as_ref()), ErrorCode::ConfigInvalid);
+        assert!(error.to_string().contains("沒有設定組合 [profiles.home]"), "{}", error);
+    }
+
+    #[test]
+    fn profiles_cannot_relax_safety_limits_or_pick_files() {
+        for (key, message) in [("force = true", "只能在命令列指定"), ("profile = \"other\"", "不能指定 profile"), ("config = \"x.toml\"", "不能指定 config")] {
+            let (_file, location) = config_file(&format!("[profiles.office]\n{}\n", key));
+            let first = Cli::command().try_get_matches_from(["r1", "--profile", "office"]).unwrap();
+            let error = injections(&first, Some(&location)).unwrap_err();
+            assert!(error.to_string().contains(message), "{}: {}", key, error);
+            assert!(error.to_string().contains("[profiles.office]"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn quick_with_ports_skips_config_discovery() {
+        let matches = |args: &[&str]| Cli::command().try_get_matches_from(args).unwrap();
+        assert!(config_location(&matches(&["r1", "--no-config"])).is_none());
+        assert!(config_location(&matches(&["r1", "quick", "5432"])).is_none());
+        // 沒有端口時需要設定檔的 [quick] ports
+        assert!(!skips_config(&matches(&["r1", "quick"])));
+    }
+
+    #[test]
+    fn config_errors_are_coded_by_source() {
+        let (_file, location) = config_file("[defaults]\nno-such-option = 1\n");
+        let error = injections(&first(), Some(&location)).unwrap_err();
+        assert_eq!(errors::classify(error.as_ref()), ErrorCode::ConfigInvalid);
+        let (_file, location) = config_file("defaults = 1\n");
+        let error = injections(&first(), Some(&location)).unwrap_err();
+        assert_eq!(errors::classify(error.as_ref()), ErrorCode::ConfigInvalid);
+
+        let error = parse_flag("maybe", &Source::Env(env_name("no-pager"))).unwrap_err();
+        assert_eq!(errors::classify(error.as_ref()), ErrorCode::InvalidOptions);
+        assert!(error.to_string().contains("PORTSCANNER_NO_PAGER"));
+    }
+}
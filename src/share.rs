@@ -0,0 +1,130 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::time::Duration;
+use serde::Serialize;
+use crate::scanner::ScanRecord;
+use crate::closure::Failure;
+use crate::ScanResult;
+
+// 可分享的一行摘要，格式固定供其他程式解析 (不受 --time-format 影響)：
+//
+//   scan host=<目標> at=<開始時間 Unix 秒> open=<端口,...|-> closed=<數量> filtered=<數量> errors=<數量> dur=<秒數>s
+//
+// - 欄位順序固定，以單一空白分隔，值不含空白
+// - host 為 --target 原樣 (去除空白)，未指定目標時為 local
+// - open 為可出站連線的端口，由小到大、不重複；沒有時為 -
+// - filtered 為逾時無回應或收到不可達錯誤的端口，其餘未連線的端口 (RST 或經由代理失敗) 計入 closed
+// - errors 為掃描端錯誤 (例如檔案描述符用盡)，不計入其他欄位
+// - dur 為掃描耗時，保留一位小數
+// 之後新增的欄位只會加在行尾
+#[derive(Debug, Default, Clone)]
+pub struct ShareLine {
+    host: String,
+    started_at: i64,
+    open: BTreeSet<u16>,
+    // 可出站連線的結果數 (open 只記端口，跨主機不重複)
+    open_results: u64,
+    closed: u64,
+    filtered: u64,
+    errors: u64,
+    duration: Duration,
+}
+
+impl ShareLine {
+    // 目標與開始時間，結果可先行累計
+    pub fn set_run(&mut self, target: Option<&str>, started_at: i64) {
+        self.host = target
+            .map(|t| t.chars().filter(|c| !c.is_whitespace()).collect::<String>())
+            .filter(|t| !t.is_empty())
+            .unwrap_or_else(|| "local".to_string());
+        self.started_at = started_at;
+    }
+
+    pub fn add(&mut self, record: &ScanRecord) {
+        self.add_result(record.port.port, &record.result);
+    }
+
+    pub fn add_result(&mut self, port: u16, result: &ScanResult) {
+        if result.error.is_some() {
+            self.errors += 1;
+        } else if result.directions.open(result.inbound, result.outbound) {
+            self.open.insert(port);
+            self.open_results += 1;
+        } else if matches!(result.failure, Some(Failure::Timeout | Failure::Unreachable)) {
+            self.filtered += 1;
+        } else {
+            self.closed += 1;
+        }
+    }
+
+    pub fn finish(&mut self, duration: Duration) {
+        self.duration = duration;
+    }
+
+    // 結束狀態行使用的計數
+    pub fn tally(&self) -> Tally {
+        Tally { open: self.open_results, closed: self.closed, filtered: self.filtered, duration: self.duration }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Tally {
+    pub open: u64,
+    pub closed: u64,
+    pub filtered: u64,
+    pub duration: Duration,
+}
+
+// 輸出範本使用的摘要
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub host: String,
+    pub open: Vec<u16>,
+    pub closed: u64,
+    pub filtered: u64,
+    pub errors: u64,
+    pub duration_secs: f64,
+    // 與報告結尾相同的一行摘要
+    pub line: String,
+}
+
+impl ShareLine {
+    pub fn summary(&self) -> Summary {
+        Summary {
+            host: self.host.clone(),
+            open: self.open.iter().copied().collect(),
+            closed: self.closed,
+            filtered: self.filtered,
+            errors: self.errors,
+            duration_secs: self.duration.as_secs_f64(),
+            line: self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ShareLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let open = if self.open.is_empty() {
+            "-".to_string()
+        } else {
+            self.open.iter().map(u16::to_string).collect::<Vec<_>>().join(",")
+        };
+        write!(
+            f,
+            "scan host={} at={} open={} closed={} filtered={} errors={} dur={:.1}s",
+            self.host,
+            self.started_at,
+            open,
+            self.closed,
+            self.filtered,
+            self.errors,
+            self.duration.as_secs_f64()
+        )
+    }
+}
+
+// --copy：複製到系統剪貼簿；無圖形環境等失敗時只回傳提示訊息
+pub fn copy(text: &str) -> Result<(), String> {
+    let mut clipboard = arboard::Clipboard::new().map_err(|e| format!("無法存取剪貼簿: {}", e))?;
+    clipboard.set_text(text).map_err(|e| format!("無法複製到剪貼簿: {}", e))
+}
@@ -0,0 +1,339 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use colored::*;
+use reqwest::header::RETRY_AFTER;
+use reqwest::StatusCode;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::config;
+use crate::context::ScanContext;
+use crate::{PortInfo, ScanResult};
+
+// API 金鑰只從環境變數讀取，不出現在命令列或設定檔中
+pub const API_KEY_ENV: &str = "SHODAN_API_KEY";
+
+const HOST_URL: &str = "https://api.shodan.io/shodan/host/";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+// 被限制速率 (HTTP 429) 時的重試次數；Shodan 的 API 限制為每秒一次
+const RATE_LIMIT_RETRIES: u32 = 3;
+const RATE_LIMIT_WAIT: Duration = Duration::from_secs(1);
+// Retry-After 超過此值時不等待，改用快取
+const RATE_LIMIT_MAX_WAIT: Duration = Duration::from_secs(10);
+
+// 顯示與報告中的橫幅長度上限
+const BANNER_LIMIT: usize = 80;
+
+// 預設位置：設定目錄下的 shodan.json，保存最近一次的回應
+pub fn cache_path() -> Option<PathBuf> {
+    config::config_dir().map(|dir| dir.join("shodan.json"))
+}
+
+// Shodan host API 的回應 (只取用到的欄位)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HostInfo {
+    #[serde(default)]
+    pub ports: Vec<u16>,
+    #[serde(default)]
+    pub data: Vec<Service>,
+    #[serde(default)]
+    pub last_update: Option<String>,
+}
+
+// Shodan 觀察到的一項服務
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Service {
+    pub port: u16,
+    #[serde(default = "tcp")]
+    pub transport: String,
+    #[serde(default)]
+    pub product: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+    // 橫幅原文
+    #[serde(default)]
+    pub data: String,
+    #[serde(default)]
+    pub timestamp: Option<String>,
+}
+
+fn tcp() -> String {
+    "tcp".to_string()
+}
+
+// 快取檔的內容：查詢的 IP、時間與回應
+#[derive(Debug, Serialize, Deserialize)]
+struct Cached {
+    ip: String,
+    fetched_at: i64,
+    host: HostInfo,
+}
+
+// 資料來源：這次查詢或 API 無法使用時的快取
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Origin {
+    Api,
+    Cache,
+}
+
+// 與本機掃描的對照
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    // 入站測試也確認可以連入
+    Confirmed,
+    // 外部掃描器可見，但入站測試判斷無法連入
+    Unconfirmed,
+    // 這次沒有測試這個端口的入站方向
+    NotScanned,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ExposedPort {
+    pub port: u16,
+    pub transport: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub banner: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<String>,
+    pub verdict: Verdict,
+}
+
+// --shodan 的報告區段
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Exposure {
+    pub ip: String,
+    pub origin: Origin,
+    // 資料取得的時間 (Unix 秒)；來自快取時為上次查詢的時間
+    pub fetched_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_update: Option<String>,
+    pub ports: Vec<ExposedPort>,
+    // API 查詢失敗而改用快取時的原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Exposure {
+    pub fn unconfirmed(&self) -> usize {
+        self.ports.iter().filter(|port| port.verdict == Verdict::Unconfirmed).count()
+    }
+}
+
+// 429 時等待的時間：Retry-After 的秒數，沒有時為預設值
+fn retry_delay(retry_after: Option<&str>) -> Duration {
+    retry_after.and_then(|value| value.trim().parse::<u64>().ok()).map_or(RATE_LIMIT_WAIT, Duration::from_secs)
+}
+
+// 查詢 host API；沒有任何資料 (404) 時回傳空的結果
+async fn fetch(ip: &str, key: &str) -> Result<HostInfo, String> {
+    let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build().map_err(|e| e.to_string())?;
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        // 錯誤訊息不含網址，避免印出 API 金鑰
+        let response = client
+            .get(format!("{}{}", HOST_URL, ip))
+            .query(&[("key", key), ("minify", "false")])
+            .send()
+            .await
+            .map_err(|e| e.without_url().to_string())?;
+        match response.status() {
+            StatusCode::NOT_FOUND => return Ok(HostInfo::default()),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => return Err(format!("API 金鑰無效 ({})", API_KEY_ENV)),
+            StatusCode::TOO_MANY_REQUESTS => {
+                let wait = retry_delay(response.headers().get(RETRY_AFTER).and_then(|value| value.to_str().ok()));
+                if attempt > RATE_LIMIT_RETRIES || wait > RATE_LIMIT_MAX_WAIT {
+                    return Err("已達 Shodan API 的速率限制".to_string());
+                }
+                tokio::time::sleep(wait).await;
+            }
+            _ => {
+                let response = response.error_for_status().map_err(|e| e.without_url().to_string())?;
+                return response.json().await.map_err(|e| e.without_url().to_string());
+            }
+        }
+    }
+}
+
+fn read_cache(path: &Path, ip: &str) -> Option<Cached> {
+    let cached: Cached = serde_json::from_str(&fs::read_to_string(path).ok()?).ok()?;
+    (cached.ip == ip).then_some(cached)
+}
+
+fn write_cache(path: &Path, cached: &Cached) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_vec_pretty(cached)?)
+}
+
+// 查詢外部 IP 的 Shodan 資料；API 無法使用時改用同一個 IP 上次的回應
+// 回傳 (資料, 來源, 取得時間, 查詢失敗的原因)
+pub async fn lookup(ip: &str, key: &str, cache: Option<&Path>) -> Result<(HostInfo, Origin, i64, Option<String>), String> {
+    match fetch(ip, key).await {
+        Ok(host) => {
+            let cached = Cached { ip: ip.to_string(), fetched_at: chrono::Utc::now().timestamp(), host };
+            if let Some(path) = cache {
+                if let Err(e) = write_cache(path, &cached) {
+                    eprintln!("{}", format!("無法寫入 Shodan 快取 {}: {}", path.display(), e).yellow());
+                }
+            }
+            Ok((cached.host, Origin::Api, cached.fetched_at, None))
+        }
+        Err(e) => match cache.and_then(|path| read_cache(path, ip)) {
+            Some(cached) => Ok((cached.host, Origin::Cache, cached.fetched_at, Some(e))),
+            None => Err(e),
+        },
+    }
+}
+
+fn banner(service: &Service) -> Option<String> {
+    let text = service.data.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then(|| text.chars().take(BANNER_LIMIT).collect())
+}
+
+// 對照 Shodan 觀察到的 TCP 端口與這次的入站測試；同一個端口有多筆時取最新的一筆
+pub fn cross_reference(
+    ip: &str,
+    host: HostInfo,
+    origin: Origin,
+    fetched_at: i64,
+    error: Option<String>,
+    results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>,
+    context: &ScanContext,
+) -> Exposure {
+    // 入站結果對每個目標都相同，任一個有測試入站的結果即可
+    let inbound: BTreeMap<u16, bool> = results
+        .values()
+        .flat_map(|ports| ports.iter())
+        .filter(|(_, result)| result.directions.inbound() && result.error.is_none())
+        .map(|(port, result)| (port.port, result.inbound))
+        .collect();
+    let mut services: BTreeMap<u16, Service> = BTreeMap::new();
+    for service in host.data.into_iter().filter(|service| service.transport == "tcp") {
+        let newer = services.get(&service.port).is_none_or(|known| service.timestamp > known.timestamp);
+        if newer {
+            services.insert(service.port, service);
+        }
+    }
+    // ports 欄位有、data 沒有詳細資料的端口
+    for port in host.ports {
+        services.entry(port).or_insert_with(|| Service { port, transport: tcp(), product: None, version: None, data: String::new(), timestamp: None });
+    }
+    let ports = services
+        .into_values()
+        .map(|service| ExposedPort {
+            port: service.port,
+            verdict: match inbound.get(&service.port) {
+                Some(true) => Verdict::Confirmed,
+                Some(false) => Verdict::Unconfirmed,
+                None => Verdict::NotScanned,
+            },
+            product: service.product.as_ref().map(|product| match &service.version {
+                Some(version) => format!("{} {}", product, version),
+                None => product.clone(),
+            }),
+            banner: banner(&service).map(|text| context.show(&text)),
+            last_seen: service.timestamp,
+            transport: service.transport,
+        })
+        .collect();
+    Exposure { ip: context.show(ip), origin, fetched_at, last_update: host.last_update, ports, error }
+}
+
+pub fn display(exposure: &Exposure) {
+    println!("\n{}", format!("=== 外部掃描器 (Shodan) 對 {} 的觀察 ===", exposure.ip).bold());
+    if let Some(error) = &exposure.error {
+        let fetched = chrono::DateTime::from_timestamp(exposure.fetched_at, 0).map(|at| at.format("%Y-%m-%d %H:%M UTC").to_string());
+        println!("{}", format!("API 查詢失敗 ({})，以下為 {} 的快取資料", error, fetched.unwrap_or_default()).yellow());
+    }
+    if exposure.ports.is_empty() {
+        println!("{}", "Shodan 沒有這個 IP 的開放端口紀錄".green());
+        return;
+    }
+    for port in &exposure.ports {
+        let label = format!("port {:>5}", port.port);
+        let detail = [port.product.as_deref(), port.banner.as_deref()].into_iter().flatten().collect::<Vec<_>>().join(" | ");
+        match port.verdict {
+            Verdict::Confirmed => println!("{} {} {}", label, "入站測試也可連入".green(), detail.dimmed()),
+            Verdict::Unconfirmed => println!("{} {} {}", label, "外部掃描器可見但本機測試未確認".red().bold(), detail),
+            Verdict::NotScanned => println!("{} {} {}", label, "未測試".dimmed(), detail.dimmed()),
+        }
+    }
+    let unconfirmed = exposure.unconfirmed();
+    if unconfirmed > 0 {
+        println!(
+            "{}",
+            format!("{} 個端口外部可見但入站測試判斷無法連入：可能是其他裝置 (路由器、NAT 轉送) 提供的服務，或 Shodan 的資料已過時", unconfirmed).yellow()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::ExternalIpSource;
+    use crate::testutil::{scan_result, TempDir};
+
+    const RESPONSE: &str = r#"{
+        "ip_str": "203.0.113.5",
+        "ports": [22, 443, 8443, 3389],
+        "last_update": "2026-10-01T08:00:00.000000",
+        "data": [
+            {"port": 22, "transport": "tcp", "product": "OpenSSH", "version": "9.6", "data": "SSH-2.0-OpenSSH_9.6\r\n", "timestamp": "2026-09-30T01:00:00"},
+            {"port": 443, "transport": "tcp", "product": "nginx", "data": "HTTP/1.1 200 OK\r\nServer: nginx", "timestamp": "2026-09-01T01:00:00"},
+            {"port": 443, "transport": "tcp", "product": "nginx", "version": "1.27", "data": "HTTP/1.1 301", "timestamp": "2026-09-29T01:00:00"},
+            {"port": 3389, "transport": "tcp", "data": ""},
+            {"port": 500, "transport": "udp", "data": "IKE"}
+        ]
+    }"#;
+
+    #[test]
+    fn shodan_ports_are_cross_referenced() {
+        let host: HostInfo = serde_json::from_str(RESPONSE).unwrap();
+        let mut results = BTreeMap::new();
+        let mut open = scan_result(true);
+        open.inbound = true;
+        let closed = scan_result(true);
+        results.insert(
+            "198.51.100.1".parse().unwrap(),
+            HashMap::from([(PortInfo::new(22, "SSH", "Remote"), open), (PortInfo::new(443, "HTTPS", "Web"), closed.clone()), (PortInfo::new(3389, "RDP", "Remote"), closed)]),
+        );
+        let context = ScanContext::new(ExternalIpSource::Disabled);
+        let exposure = cross_reference("203.0.113.5", host, Origin::Api, 0, None, &results, &context);
+
+        let verdicts: Vec<(u16, Verdict)> = exposure.ports.iter().map(|port| (port.port, port.verdict)).collect();
+        assert_eq!(verdicts, [(22, Verdict::Confirmed), (443, Verdict::Unconfirmed), (3389, Verdict::Unconfirmed), (8443, Verdict::NotScanned)]);
+        assert_eq!(exposure.unconfirmed(), 2);
+        // 同一個端口取最新的一筆
+        assert_eq!(exposure.ports[1].product.as_deref(), Some("nginx 1.27"));
+        assert_eq!(exposure.ports[0].banner.as_deref(), Some("SSH-2.0-OpenSSH_9.6"));
+        assert_eq!(exposure.ports[2].banner, None);
+    }
+
+    #[test]
+    fn rate_limit_waits_follow_retry_after() {
+        assert_eq!(retry_delay(Some("3")), Duration::from_secs(3));
+        assert_eq!(retry_delay(Some("Wed, 21 Oct 2026 07:28:00 GMT")), RATE_LIMIT_WAIT);
+        assert_eq!(retry_delay(None), RATE_LIMIT_WAIT);
+    }
+
+    #[test]
+    fn cache_is_only_used_for_the_same_ip() {
+        let dir = TempDir::new("shodan");
+        let path = dir.path().join("nested").join("shodan.json");
+        let host: HostInfo = serde_json::from_str(RESPONSE).unwrap();
+        write_cache(&path, &Cached { ip: "203.0.113.5".to_string(), fetched_at: 1_790_000_000, host }).unwrap();
+        let cached = read_cache(&path, "203.0.113.5").unwrap();
+        assert_eq!((cached.fetched_at, cached.host.ports.len(), cached.host.data.len()), (1_790_000_000, 4, 5));
+        assert!(read_cache(&path, "203.0.113.6").is_none());
+    }
+}
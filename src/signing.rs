@@ -0,0 +1,345 @@
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use colored::*;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use crate::config;
+
+// 報告中簽章欄位的名稱；計算與驗證簽章時先移除此欄位
+const FIELD: &str = "signature";
+
+// 簽章演算法，寫在報告中供驗證時確認
+const ALGORITHM: &str = "ed25519";
+
+// 金鑰檔的第一行，用來辨識檔案種類
+const SECRET_HEADER: &str = "portscanner-ed25519-secret";
+const PUBLIC_HEADER: &str = "portscanner-ed25519-public";
+
+// 嵌入 JSON 報告的簽章 (--sign)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ReportSignature {
+    pub algorithm: String,
+    // 公鑰的 SHA-256 指紋，例如 SHA256:3f2a…
+    pub key_fingerprint: String,
+    // 十六進位的公鑰；驗證時若未指定 --key 則使用此公鑰 (只能證明內容未被修改)
+    pub public_key: String,
+    // 對移除 signature 欄位後的標準化 JSON 所做的十六進位簽章
+    pub value: String,
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex<const N: usize>(text: &str) -> Option<[u8; N]> {
+    let text = text.trim();
+    if text.len() != N * 2 || !text.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0u8; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+pub fn fingerprint(key: &VerifyingKey) -> String {
+    let digest = Sha256::digest(key.as_bytes());
+    format!("SHA256:{}", to_hex(&digest[..16]))
+}
+
+// 標準化 JSON：物件鍵依字典順序排列，不含空白；數字與字串沿用 serde_json 的輸出
+// 美化輸出、縮排或重新排列鍵都不影響簽章
+pub fn canonicalize(value: &Value) -> String {
+    let mut out = String::new();
+    write_canonical(value, &mut out);
+    out
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(&map[key], out);
+            }
+            out.push('}');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+// 預設金鑰位置：設定目錄下的 signing.key
+pub fn default_key_path() -> Option<PathBuf> {
+    config::config_dir().map(|dir| dir.join("signing.key"))
+}
+
+pub fn public_path(secret: &Path) -> PathBuf {
+    let mut name = secret.as_os_str().to_owned();
+    name.push(".pub");
+    PathBuf::from(name)
+}
+
+// 讀取金鑰檔的內容：標頭行加上一行十六進位
+fn read_key<const N: usize>(path: &Path, header: &str) -> Result<[u8; N], String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("無法讀取金鑰 {}: {}", path.display(), e))?;
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+    match (lines.next(), lines.next()) {
+        (Some(first), Some(key)) if first == header => {
+            from_hex(key).ok_or_else(|| format!("金鑰 {} 的內容無效", path.display()))
+        }
+        _ => Err(format!("{} 不是 {} 金鑰檔", path.display(), header)),
+    }
+}
+
+pub fn load_signing_key(path: &Path) -> Result<SigningKey, String> {
+    let seed = read_key::<32>(path, SECRET_HEADER)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Ok(meta) = fs::metadata(path) {
+            if meta.permissions().mode() & 0o077 != 0 {
+                eprintln!("{}", format!("警告: 私鑰 {} 可被其他使用者讀取，建議執行 chmod 600", path.display()).yellow());
+            }
+        }
+    }
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+pub fn load_verifying_key(path: &Path) -> Result<VerifyingKey, String> {
+    let bytes = read_key::<32>(path, PUBLIC_HEADER)?;
+    VerifyingKey::from_bytes(&bytes).map_err(|_| format!("公鑰 {} 無效", path.display()))
+}
+
+// 建立只有擁有者可讀寫的新檔案；已存在時除非 force 否則拒絕覆寫
+fn write_private(path: &Path, contents: &str, force: bool) -> Result<(), String> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    match force {
+        true => options.create(true).truncate(true),
+        false => options.create_new(true),
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::AlreadyExists => format!("{} 已存在 (使用 --force 覆寫)", path.display()),
+        _ => format!("無法建立 {}: {}", path.display(), e),
+    })?;
+    // 覆寫既有檔案時 mode 不會套用，另外設定權限
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("無法設定 {} 的權限: {}", path.display(), e))?;
+    }
+    file.write_all(contents.as_bytes()).map_err(|e| format!("無法寫入 {}: {}", path.display(), e))
+}
+
+// keygen：產生私鑰 (權限 600) 與同名的 .pub 公鑰
+pub fn keygen(path: Option<&Path>, force: bool) -> Result<(), Box<dyn Error>> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => default_key_path().ok_or("找不到設定目錄，請以 --out 指定金鑰位置")?,
+    };
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(|e| format!("無法建立目錄 {}: {}", parent.display(), e))?;
+    }
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed).map_err(|e| format!("無法取得亂數: {}", e))?;
+    let key = SigningKey::from_bytes(&seed);
+    let public = key.verifying_key();
+
+    write_private(&path, &format!("{}\n{}\n", SECRET_HEADER, to_hex(&seed)), force)?;
+    let public_file = public_path(&path);
+    let public_text = format!("{}\n{}\n", PUBLIC_HEADER, to_hex(public.as_bytes()));
+    fs::write(&public_file, public_text).map_err(|e| format!("無法寫入 {}: {}", public_file.display(), e))?;
+
+    println!("私鑰: {}", path.display());
+    println!("公鑰: {}", public_file.display());
+    println!("指紋: {}", fingerprint(&public).cyan());
+    Ok(())
+}
+
+// 對報告簽章；報告中不能已有 signature 欄位
+pub fn sign(key: &SigningKey, report: &Value) -> ReportSignature {
+    let public = key.verifying_key();
+    let signature = key.sign(canonicalize(report).as_bytes());
+    ReportSignature {
+        algorithm: ALGORITHM.to_string(),
+        key_fingerprint: fingerprint(&public),
+        public_key: to_hex(public.as_bytes()),
+        value: to_hex(&signature.to_bytes()),
+    }
+}
+
+// 驗證成功時的資訊
+#[derive(Debug)]
+pub struct Verified {
+    pub fingerprint: String,
+    // 以 --key 指定的公鑰驗證 (否則只用報告內嵌的公鑰)
+    pub trusted: bool,
+}
+
+// 驗證報告的簽章；trusted 為使用者指定的公鑰，內嵌的公鑰必須與其相同
+pub fn verify(document: &str, trusted: Option<&VerifyingKey>) -> Result<Verified, String> {
+    let mut report: Value = serde_json::from_str(document).map_err(|e| format!("報告不是有效的 JSON: {}", e))?;
+    let field = report
+        .as_object_mut()
+        .ok_or("報告的最上層不是 JSON 物件")?
+        .remove(FIELD)
+        .ok_or("報告沒有簽章 (以 --sign 產生)")?;
+    let signature: ReportSignature = serde_json::from_value(field).map_err(|e| format!("簽章欄位格式錯誤: {}", e))?;
+    if signature.algorithm != ALGORITHM {
+        return Err(format!("不支援的簽章演算法: {}", signature.algorithm));
+    }
+    let embedded = from_hex::<32>(&signature.public_key)
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+        .ok_or("簽章中的公鑰無效")?;
+    if fingerprint(&embedded) != signature.key_fingerprint {
+        return Err("簽章中的公鑰與指紋不符".to_string());
+    }
+    let key = match trusted {
+        Some(trusted) if trusted != &embedded => {
+            return Err(format!(
+                "報告由其他金鑰簽署 ({})，與指定的公鑰 ({}) 不同",
+                signature.key_fingerprint,
+                fingerprint(trusted)
+            ));
+        }
+        Some(trusted) => trusted,
+        None => &embedded,
+    };
+    let value = from_hex::<64>(&signature.value).ok_or("簽章值格式錯誤")?;
+    key.verify(canonicalize(&report).as_bytes(), &Signature::from_bytes(&value))
+        .map_err(|_| "簽章不符，報告內容在簽署後被修改".to_string())?;
+    Ok(Verified {
+        fingerprint: signature.key_fingerprint,
+        trusted: trusted.is_some(),
+    })
+}
+
+// verify-report：顯示驗證結果，失敗時以錯誤結束
+pub fn verify_report(path: &Path, key: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let trusted = key.map(load_verifying_key).transpose()?;
+    let document = fs::read_to_string(path).map_err(|e| format!("無法讀取報告 {}: {}", path.display(), e))?;
+    match verify(&document, trusted.as_ref()) {
+        Ok(verified) if verified.trusted => {
+            println!("{} {} (金鑰 {})", "✓ 簽章有效:".green().bold(), path.display(), verified.fingerprint);
+            Ok(())
+        }
+        // 內嵌的公鑰任何人都能替換，沒有 --key 時不能當作驗證通過
+        Ok(verified) => {
+            println!("{} {} (金鑰 {})", "? 內容與內嵌公鑰相符，但簽署者未確認:".yellow().bold(), path.display(), verified.fingerprint);
+            Err("未指定 --key，無法確認簽署者；請以 --key 指定信任的公鑰".into())
+        }
+        Err(e) => Err(format!("{} 驗證失敗: {}", path.display(), e).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+    use crate::testutil::{TempDir, TempPath};
+
+    fn key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    fn signed(key: &SigningKey, report: &Value) -> String {
+        let mut document = report.clone();
+        document[FIELD] = serde_json::to_value(sign(key, report)).unwrap();
+        serde_json::to_string_pretty(&document).unwrap()
+    }
+
+    fn report() -> Value {
+        json!({ "hosts": [{ "ip": "10.0.0.1", "ports": [22, 443] }], "summary": { "open": 2, "ratio": 0.5 } })
+    }
+
+    #[test]
+    fn canonical_form_sorts_keys_without_whitespace() {
+        let value: Value = serde_json::from_str("{ \"b\": [1, {\"z\": null, \"a\": \"é\"}], \"a\": true }").unwrap();
+        assert_eq!(canonicalize(&value), "{\"a\":true,\"b\":[1,{\"a\":\"é\",\"z\":null}]}");
+    }
+
+    #[test]
+    fn signature_survives_reformatting() {
+        let document = signed(&key(1), &report());
+        let compact = serde_json::to_string(&serde_json::from_str::<Value>(&document).unwrap()).unwrap();
+        let public = key(1).verifying_key();
+        for document in [document, compact] {
+            let verified = verify(&document, Some(&public)).unwrap();
+            assert!(verified.trusted);
+            assert_eq!(verified.fingerprint, fingerprint(&public));
+        }
+    }
+
+    #[test]
+    fn modified_reports_fail() {
+        let document = signed(&key(1), &report()).replace("443", "444");
+        let error = verify(&document, Some(&key(1).verifying_key())).unwrap_err();
+        assert!(error.contains("被修改"), "{}", error);
+    }
+
+    #[test]
+    fn other_keys_fail() {
+        let document = signed(&key(1), &report());
+        let error = verify(&document, Some(&key(2).verifying_key())).unwrap_err();
+        assert!(error.contains("其他金鑰"), "{}", error);
+
+        // 換掉內嵌公鑰並重新簽署：沒有 --key 時內容仍一致，但不算可信
+        let forged = signed(&key(2), &report());
+        assert!(!verify(&forged, None).unwrap().trusted);
+        assert!(verify(&forged, Some(&key(1).verifying_key())).is_err());
+    }
+
+    #[test]
+    fn malformed_signatures_fail() {
+        assert!(verify(&serde_json::to_string(&report()).unwrap(), None).unwrap_err().contains("沒有簽章"));
+        let mut document: Value = serde_json::from_str(&signed(&key(1), &report())).unwrap();
+        document[FIELD]["key_fingerprint"] = json!("SHA256:00");
+        assert!(verify(&document.to_string(), None).unwrap_err().contains("指紋不符"));
+        document[FIELD]["algorithm"] = json!("rsa");
+        assert!(verify(&document.to_string(), None).unwrap_err().contains("不支援"));
+        assert!(verify("[]", None).is_err());
+    }
+
+    #[test]
+    fn verify_report_requires_a_trusted_key() {
+        let dir = TempDir::new("signing");
+        let secret = dir.path().join("signing.key");
+        keygen(Some(&secret), false).unwrap();
+        let signing = load_signing_key(&secret).unwrap();
+        let report_file = TempPath::with("report.json", &signed(&signing, &report()));
+
+        assert!(verify_report(report_file.path(), Some(&public_path(&secret))).is_ok());
+        assert!(verify_report(report_file.path(), None).is_err());
+        // 已存在的金鑰不會被覆寫
+        assert!(keygen(Some(&secret), false).is_err());
+    }
+}
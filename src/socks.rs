@@ -0,0 +1,128 @@
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+// SOCKS5 協定常數 (RFC 1928)
+const VERSION: u8 = 0x05;
+const NO_AUTH: u8 = 0x00;
+const CMD_CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+// SOCKS5 連線失敗的原因
+#[derive(Debug)]
+pub enum SocksError {
+    // 無法連到代理本身或通訊中斷
+    Io(io::Error),
+    // 代理回應不是 SOCKS5 或不接受無驗證
+    Protocol(String),
+    // 代理回報目標連線失敗 (REP 欄位)
+    Reply(u8),
+    Timeout,
+}
+
+impl fmt::Display for SocksError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SocksError::Io(e) => write!(f, "{}", e),
+            SocksError::Protocol(msg) => write!(f, "{}", msg),
+            SocksError::Reply(code) => write!(f, "{}", reply_text(*code)),
+            SocksError::Timeout => write!(f, "逾時"),
+        }
+    }
+}
+
+impl From<io::Error> for SocksError {
+    fn from(e: io::Error) -> Self {
+        SocksError::Io(e)
+    }
+}
+
+// REP 欄位說明
+fn reply_text(code: u8) -> &'static str {
+    match code {
+        0x01 => "代理一般性錯誤",
+        0x02 => "代理規則不允許",
+        0x03 => "網路無法到達",
+        0x04 => "主機無法到達",
+        0x05 => "連線被拒",
+        0x06 => "TTL 過期",
+        0x07 => "不支援的指令",
+        0x08 => "不支援的位址類型",
+        _ => "未知的代理錯誤",
+    }
+}
+
+// 連到代理並完成無驗證的協商
+async fn greet(proxy: SocketAddr) -> Result<TcpStream, SocksError> {
+    let mut stream = TcpStream::connect(proxy).await?;
+    stream.write_all(&[VERSION, 1, NO_AUTH]).await?;
+
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != VERSION {
+        return Err(SocksError::Protocol("代理回應不是 SOCKS5".to_string()));
+    }
+    if reply[1] != NO_AUTH {
+        return Err(SocksError::Protocol("代理要求驗證".to_string()));
+    }
+    Ok(stream)
+}
+
+// 讀取 CONNECT 回應並略過綁定位址
+async fn read_reply(stream: &mut TcpStream) -> Result<(), SocksError> {
+    let mut head = [0u8; 4];
+    stream.read_exact(&mut head).await?;
+    if head[0] != VERSION {
+        return Err(SocksError::Protocol("代理回應不是 SOCKS5".to_string()));
+    }
+    if head[1] != 0x00 {
+        return Err(SocksError::Reply(head[1]));
+    }
+
+    let remaining = match head[3] {
+        ATYP_IPV4 => 4 + 2,
+        ATYP_IPV6 => 16 + 2,
+        ATYP_DOMAIN => stream.read_u8().await? as usize + 2,
+        other => return Err(SocksError::Protocol(format!("未知的位址類型 {}", other))),
+    };
+    let mut rest = vec![0u8; remaining];
+    stream.read_exact(&mut rest).await?;
+    Ok(())
+}
+
+// 透過 SOCKS5 代理連到目標
+pub async fn connect(proxy: SocketAddr, dest: SocketAddr, limit: Duration) -> Result<TcpStream, SocksError> {
+    let attempt = async {
+        let mut stream = greet(proxy).await?;
+
+        let mut request = vec![VERSION, CMD_CONNECT, 0x00];
+        match dest.ip() {
+            IpAddr::V4(v4) => {
+                request.push(ATYP_IPV4);
+                request.extend_from_slice(&v4.octets());
+            }
+            IpAddr::V6(v6) => {
+                request.push(ATYP_IPV6);
+                request.extend_from_slice(&v6.octets());
+            }
+        }
+        request.extend_from_slice(&dest.port().to_be_bytes());
+        stream.write_all(&request).await?;
+
+        read_reply(&mut stream).await?;
+        Ok(stream)
+    };
+
+    timeout(limit, attempt).await.unwrap_or(Err(SocksError::Timeout))
+}
+
+// 確認代理可用：能連線且完成 SOCKS5 協商
+pub async fn verify(proxy: SocketAddr, limit: Duration) -> Result<(), SocksError> {
+    timeout(limit, greet(proxy)).await.unwrap_or(Err(SocksError::Timeout)).map(|_| ())
+}
@@ -0,0 +1,200 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use socket2::SockRef;
+use tokio::net::TcpSocket;
+use crate::errors::{self, ErrorCode};
+use crate::limits::ScanError;
+use crate::scanner::ScanPlan;
+use crate::verify::{self, Reprobe};
+use crate::{PortInfo, ScanResult};
+
+// 從指定來源端口連線的結果；Err 為來源端口無法綁定的原因
+pub type SourceAttempt = Result<Reprobe, ErrorCode>;
+
+// --test-source-ports 的一個 (來源端口, 目標端口) 組合
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SourcePortResult {
+    pub source_port: u16,
+    pub connected: bool,
+    // 連線失敗的方式或來源端口無法綁定的原因；連線成功時省略
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+}
+
+impl SourcePortResult {
+    fn of(source_port: u16, attempt: SourceAttempt) -> Self {
+        let code = match attempt {
+            Ok((_, _, failure, error)) => errors::outbound_codes(error, failure, None).first().copied(),
+            Err(code) => Some(code),
+        };
+        SourcePortResult { source_port, connected: code.is_none(), code }
+    }
+}
+
+// 綁定來源端口後連線 (實際網路)
+// SO_REUSEADDR 讓同一來源端口能連續對不同目標使用；以 RST 關閉連線，來源端口不會停在 TIME_WAIT
+pub async fn connect_from(dest: SocketAddr, source_port: u16, limit: Duration) -> SourceAttempt {
+    let (socket, local) = match dest {
+        SocketAddr::V4(_) => (TcpSocket::new_v4(), SocketAddr::from((Ipv4Addr::UNSPECIFIED, source_port))),
+        SocketAddr::V6(_) => (TcpSocket::new_v6(), SocketAddr::from((Ipv6Addr::UNSPECIFIED, source_port))),
+    };
+    let socket = match socket {
+        Ok(socket) => socket,
+        Err(e) => return Ok((false, None, None, ScanError::classify(&e))),
+    };
+    let _ = socket.set_reuseaddr(true);
+    let _ = SockRef::from(&socket).set_linger(Some(Duration::ZERO));
+    socket.bind(local).map_err(|e| ErrorCode::of_bind(&e))?;
+    Ok(verify::connect(socket, dest, limit).await)
+}
+
+// 每個來源端口對所有掃描過的 (主機, 端口) 各連線一次
+// 同一來源端口的探測依序執行 (同時使用會互相衝突)，不同來源端口之間並行
+pub async fn probe_results(results: &mut BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>, plan: &ScanPlan) {
+    let mut targets: Vec<(IpAddr, PortInfo)> =
+        results.iter().flat_map(|(host, ports)| ports.keys().map(|port| (*host, port.clone()))).collect();
+    targets.sort_by_key(|(host, port)| (*host, port.port));
+
+    let mut handles = Vec::new();
+    for &source_port in &plan.source_ports {
+        let (prober, context, timeouts, targets) = (plan.prober.clone(), plan.context.clone(), plan.timeouts.clone(), targets.clone());
+        handles.push(tokio::spawn(async move {
+            let mut tested = Vec::with_capacity(targets.len());
+            for (host, port) in targets {
+                let dest = context.socket_addr(host, port.port);
+                let attempt = prober.connect_from(dest, source_port, timeouts.for_port(&port)).await;
+                tested.push((host, port, SourcePortResult::of(source_port, attempt)));
+            }
+            tested
+        }));
+    }
+    // 依 --test-source-ports 的順序填入
+    for handle in handles {
+        let Ok(tested) = handle.await else {
+            continue;
+        };
+        for (host, port, tested) in tested {
+            if let Some(result) = results.get_mut(&host).and_then(|r| r.get_mut(&port)) {
+                result.source_ports.push(tested);
+            }
+        }
+    }
+}
+
+// 最壞情況的額外時間：每個來源端口依序等到每個探測逾時，不同來源端口並行
+pub fn estimate(plan: &ScanPlan, hosts: u128) -> Duration {
+    if plan.source_ports.is_empty() {
+        return Duration::ZERO;
+    }
+    let per_host: f64 = plan.ports.iter().map(|p| plan.timeouts.for_port(p).as_secs_f64()).sum();
+    Duration::from_secs_f64(per_host * hosts as f64)
+}
+
+fn cell(result: Option<&SourcePortResult>) -> ColoredString {
+    match result {
+        Some(SourcePortResult { connected: true, .. }) => "✓".green(),
+        Some(SourcePortResult { code: Some(code), .. }) if code.is_bind() || code.is_internal() => "!".yellow(),
+        Some(_) => "✗".red(),
+        None => "-".dimmed(),
+    }
+}
+
+// 每台主機一個矩陣：列為目標端口，欄為來源端口
+pub fn display(results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>, source_ports: &[u16]) {
+    if source_ports.is_empty() {
+        return;
+    }
+    println!("\n{}", "=== 來源端口測試 ===".bold());
+    let width = source_ports.iter().map(|port| port.to_string().len()).max().unwrap_or(0).max(5);
+    let header: String = source_ports.iter().map(|port| format!(" {:>width$}", port)).collect();
+    for (host, ports) in results {
+        let mut ports: Vec<(&PortInfo, &ScanResult)> = ports.iter().collect();
+        ports.sort_by_key(|(port, _)| port.port);
+        println!("\n{}", host.to_string().bold());
+        println!("{:<20}{}", "目標端口 \\ 來源", header.dimmed());
+        for (port, result) in ports {
+            let cells: String = source_ports
+                .iter()
+                .map(|source| {
+                    let found = result.source_ports.iter().find(|tested| tested.source_port == *source);
+                    format!(" {:>width$}", cell(found))
+                })
+                .collect();
+            println!("{:<20}{}", format!("{} {}", port.port, port.service), cells);
+        }
+        // 無法綁定的來源端口 (低於 1024 的端口通常需要管理員權限)
+        for source in source_ports {
+            let failed = ports_bind_failure(results, *host, *source);
+            if let Some(code) = failed {
+                println!("{}", format!("  來源端口 {} 無法綁定 [{}]", source, code.code()).yellow());
+            }
+        }
+    }
+    println!("{}", "✓ 可連線  ✗ 無法連線  ! 來源端口無法綁定或掃描端錯誤".dimmed());
+}
+
+fn ports_bind_failure(results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>, host: IpAddr, source: u16) -> Option<ErrorCode> {
+    results[&host]
+        .values()
+        .flat_map(|result| &result.source_ports)
+        .filter(|tested| tested.source_port == source)
+        .find_map(|tested| tested.code.filter(|code| code.is_bind()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::time::Instant;
+    use crate::prober::fake::{Script, Scripted, ScriptedProber};
+    use crate::testutil::{by_host, host, scan, scripted_plan};
+
+    #[tokio::test(start_paused = true)]
+    async fn combinations_are_tested_and_serialized_per_source_port() {
+        let target = host(1);
+        let latency = Duration::from_millis(100);
+        let prober = Arc::new(
+            ScriptedProber::new()
+                .with(target, 5432, Script::new(Scripted::Open, latency))
+                .with(target, 22, Script::new(Scripted::Open, latency))
+                .with(target, 80, Script::new(Scripted::Silent, Duration::ZERO))
+                // 防火牆只允許來源端口 53 連到 5432
+                .source_script(1024, 5432, Script::new(Scripted::Silent, Duration::ZERO))
+                .unbindable_source(20),
+        );
+        let mut plan = scripted_plan(&[target], &[22, 80, 5432], 4, prober);
+        plan.source_ports = vec![1024, 53, 20];
+        let mut results = by_host(scan(&plan).await);
+
+        let started = Instant::now();
+        probe_results(&mut results, &plan).await;
+        // 同一來源端口依序探測：1024 為 100ms + 逾時 1s + 逾時 1s，53 為 100ms + 1s + 100ms；不同來源端口並行
+        assert_eq!(started.elapsed(), Duration::from_millis(2100));
+        let tested = |port: u16| {
+            let (_, result) = results[&target].iter().find(|(info, _)| info.port == port).unwrap();
+            result.source_ports.iter().map(|t| (t.source_port, t.connected, t.code)).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            tested(5432),
+            [(1024, false, Some(ErrorCode::ProbeTimeout)), (53, true, None), (20, false, Some(ErrorCode::BindAddressInUse))]
+        );
+        assert_eq!(tested(22)[..2], [(1024, true, None), (53, true, None)]);
+        assert!(tested(80).iter().all(|(_, connected, _)| !connected));
+        assert_eq!(ports_bind_failure(&results, target, 20), Some(ErrorCode::BindAddressInUse));
+        assert_eq!(ports_bind_failure(&results, target, 53), None);
+    }
+
+    #[test]
+    fn estimate_adds_one_sequential_pass() {
+        let ports = [22, 80, 443];
+        let mut plan = scripted_plan(&[host(1), host(2)], &ports, 64, Arc::new(ScriptedProber::new()));
+        assert_eq!(estimate(&plan, 2), Duration::ZERO);
+        plan.source_ports = vec![53, 1024];
+        // 2 台主機 x 3 個端口 x 逾時 1s，不同來源端口並行
+        assert_eq!(estimate(&plan, 2), Duration::from_secs(6));
+    }
+}
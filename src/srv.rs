@@ -0,0 +1,332 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use crate::anonymize::{self, Anonymizer};
+use crate::axfr;
+use crate::prober::ProbeFuture;
+use crate::PortInfo;
+
+// --srv 的記錄名稱：_服務._協定.網域
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrvName {
+    pub name: String,
+    // 去掉底線的服務與協定標籤，例如 ldap / tcp
+    pub service: String,
+    pub protocol: String,
+}
+
+// 解析 --srv 的一個名稱
+pub fn parse_name(text: &str) -> Result<SrvName, String> {
+    let name = text.trim().trim_end_matches('.').to_ascii_lowercase();
+    let invalid = || format!("無效的 SRV 名稱 {}：應為 _服務._協定.網域，例如 _ldap._tcp.example.com", text.trim());
+    let mut labels = name.splitn(3, '.');
+    let (Some(service), Some(protocol), Some(domain)) = (labels.next(), labels.next(), labels.next()) else {
+        return Err(invalid());
+    };
+    let service = service.strip_prefix('_').filter(|s| !s.is_empty()).ok_or_else(invalid)?;
+    let protocol = protocol.strip_prefix('_').filter(|p| !p.is_empty()).ok_or_else(invalid)?;
+    if domain.split('.').any(str::is_empty) {
+        return Err(invalid());
+    }
+    Ok(SrvName { service: service.to_string(), protocol: protocol.to_string(), name })
+}
+
+// 一筆 SRV 回答
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrvRecord {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+// 查詢 SRV 記錄；實際查詢經由系統的遞迴解析器，測試可換成固定的回答
+pub trait SrvResolver: Send + Sync {
+    fn lookup<'a>(&'a self, name: &'a str) -> ProbeFuture<'a, Result<Vec<SrvRecord>, String>>;
+}
+
+pub struct SystemResolver;
+
+impl SrvResolver for SystemResolver {
+    fn lookup<'a>(&'a self, name: &'a str) -> ProbeFuture<'a, Result<Vec<SrvRecord>, String>> {
+        Box::pin(async move {
+            let message = axfr::ask(name, axfr::TYPE_SRV).await?;
+            if message.rcode != 0 {
+                return Err(axfr::rcode_label(message.rcode));
+            }
+            Ok(message
+                .answers
+                .into_iter()
+                .filter_map(|record| {
+                    let srv = record.srv?;
+                    Some(SrvRecord { priority: srv.priority, weight: srv.weight, port: srv.port, target: record.target? })
+                })
+                .collect())
+        })
+    }
+}
+
+// 端口來自哪一筆 SRV 記錄 (PortInfo 的 srv 欄位)
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SrvOrigin {
+    pub record: String,
+    pub target: String,
+    pub priority: u16,
+    pub weight: u16,
+    // 記錄中優先順序最小、其中權重最大的目標；相同時都標為主要
+    pub primary: bool,
+}
+
+// 一個名稱的查詢結果
+#[derive(Debug, Clone)]
+pub struct Lookup {
+    pub name: SrvName,
+    pub answer: Result<Vec<SrvOrigin>, String>,
+    // 回答的端口，與 answer 的目標一一對應
+    ports: Vec<u16>,
+}
+
+// 所有 --srv 名稱的查詢結果，依指定順序
+#[derive(Debug, Clone, Default)]
+pub struct Discovery {
+    pub lookups: Vec<Lookup>,
+}
+
+// 依 RFC 2782 排序並標示主要目標；目標為 "." 表示明確不提供此服務
+fn rank(name: &SrvName, mut records: Vec<SrvRecord>) -> (Vec<SrvOrigin>, Vec<u16>) {
+    records.retain(|record| record.port != 0 && !record.target.is_empty() && record.target != ".");
+    records.sort_by(|a, b| (a.priority, std::cmp::Reverse(a.weight), &a.target).cmp(&(b.priority, std::cmp::Reverse(b.weight), &b.target)));
+    records.dedup();
+    let best = records.first().map(|r| (r.priority, r.weight));
+    records
+        .into_iter()
+        .map(|record| {
+            let origin = SrvOrigin {
+                record: name.name.clone(),
+                target: record.target.trim_end_matches('.').to_ascii_lowercase(),
+                priority: record.priority,
+                weight: record.weight,
+                primary: Some((record.priority, record.weight)) == best,
+            };
+            (origin, record.port)
+        })
+        .unzip()
+}
+
+// 同時查詢所有名稱；單一名稱失敗只記錄原因，不影響其他名稱
+pub async fn discover(names: &[SrvName], resolver: Arc<dyn SrvResolver>, concurrency: usize) -> Discovery {
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    let mut tasks = JoinSet::new();
+    for (index, name) in names.iter().cloned().enumerate() {
+        let (resolver, semaphore) = (resolver.clone(), semaphore.clone());
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            let answer = resolver.lookup(&name.name).await;
+            (index, name, answer)
+        });
+    }
+    let mut lookups = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        if let Ok((index, name, answer)) = joined {
+            let (answer, ports) = match answer {
+                Ok(records) if records.is_empty() => (Err("沒有 SRV 記錄".to_string()), Vec::new()),
+                Ok(records) => {
+                    let (origins, ports) = rank(&name, records);
+                    match origins.is_empty() {
+                        true => (Err("記錄表示不提供此服務 (目標為 \".\")".to_string()), Vec::new()),
+                        false => (Ok(origins), ports),
+                    }
+                }
+                Err(e) => (Err(e), Vec::new()),
+            };
+            lookups.push((index, Lookup { name, answer, ports }));
+        }
+    }
+    lookups.sort_by_key(|(index, _)| *index);
+    Discovery { lookups: lookups.into_iter().map(|(_, lookup)| lookup).collect() }
+}
+
+impl Discovery {
+    fn answers(&self) -> impl Iterator<Item = (&Lookup, &SrvOrigin, u16)> {
+        self.lookups.iter().flat_map(|lookup| {
+            let origins = lookup.answer.as_deref().unwrap_or_default();
+            origins.iter().zip(&lookup.ports).map(move |(origin, port)| (lookup, origin, *port))
+        })
+    }
+
+    // 加入掃描的目標名稱，依記錄順序且不重複
+    pub fn targets(&self) -> Vec<String> {
+        let mut targets: Vec<String> = Vec::new();
+        for (_, origin, _) in self.answers() {
+            if !targets.contains(&origin.target) {
+                targets.push(origin.target.clone());
+            }
+        }
+        targets
+    }
+
+    // 與明確指定的 --target 合併後的目標
+    pub fn target_spec(&self, explicit: Option<&str>) -> Option<String> {
+        let targets = self.targets();
+        match (explicit, targets.is_empty()) {
+            (explicit, true) => explicit.map(str::to_string),
+            (Some(explicit), false) => Some(format!("{},{}", explicit, targets.join(","))),
+            (None, false) => Some(targets.join(",")),
+        }
+    }
+
+    // 與明確指定的端口合併後的端口；只指定 --srv 時只掃描記錄的端口
+    pub fn port_spec(&self, explicit: Option<&str>) -> Option<String> {
+        let ports: BTreeSet<u16> = self.answers().map(|(_, _, port)| port).collect();
+        let ports: Vec<String> = ports.iter().map(u16::to_string).collect();
+        match (explicit, ports.is_empty()) {
+            (explicit, true) => explicit.map(str::to_string),
+            (Some(explicit), false) => Some(format!("{},{}", explicit, ports.join(","))),
+            (None, false) => Some(ports.join(",")),
+        }
+    }
+
+    // 為記錄的端口加上 SRV 來源；不是明確指定的端口改用記錄的服務名稱 (例如 _ldap -> LDAP)
+    pub fn annotate(&self, ports: &mut [PortInfo], explicit: &BTreeSet<u16>) {
+        let mut origins: BTreeMap<u16, (&SrvName, Vec<SrvOrigin>)> = BTreeMap::new();
+        for (lookup, origin, port) in self.answers() {
+            origins.entry(port).or_insert_with(|| (&lookup.name, Vec::new())).1.push(origin.clone());
+        }
+        for port in ports {
+            let Some((name, found)) = origins.get(&port.port) else {
+                continue;
+            };
+            if !explicit.contains(&port.port) {
+                port.service = name.service.to_ascii_uppercase();
+            }
+            port.srv = found.clone();
+        }
+    }
+}
+
+// 端口行的 SRV 註記：記錄名稱
+pub fn describe(origins: &[SrvOrigin]) -> String {
+    let records: BTreeSet<&str> = origins.iter().map(|origin| origin.record.as_str()).collect();
+    format!("SRV {}", records.into_iter().collect::<Vec<_>>().join(", "))
+}
+
+// 掃描前顯示查詢結果；失敗的名稱以警告顯示，quiet 時只顯示警告
+pub fn display(discovery: &Discovery, anonymizer: Option<&Anonymizer>, quiet: bool) {
+    if !quiet {
+        println!("{}", "=== SRV 服務探索 ===".bold());
+    }
+    for lookup in &discovery.lookups {
+        let name = anonymize::show(anonymizer, &lookup.name.name);
+        let origins = match &lookup.answer {
+            Ok(origins) => origins,
+            Err(e) => {
+                eprintln!("{}", format!("{}: 查詢失敗，略過此記錄 ({})", name, e).yellow());
+                continue;
+            }
+        };
+        if quiet {
+            continue;
+        }
+        println!("{}", name.bold());
+        if lookup.name.protocol != "tcp" {
+            println!("  {}", format!("{} 記錄的端口以 TCP 連線測試", lookup.name.protocol.to_ascii_uppercase()).dimmed());
+        }
+        for (origin, port) in origins.iter().zip(&lookup.ports) {
+            let primary = if origin.primary { format!("  {}", "主要".green()) } else { String::new() };
+            println!(
+                "  {}:{}  優先 {} 權重 {}{}",
+                anonymize::show(anonymizer, &origin.target),
+                port,
+                origin.priority,
+                origin.weight,
+                primary
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    // 固定回答的解析器；沒有列出的名稱回答 NXDOMAIN
+    struct Answers(HashMap<&'static str, Vec<SrvRecord>>);
+
+    impl SrvResolver for Answers {
+        fn lookup<'a>(&'a self, name: &'a str) -> ProbeFuture<'a, Result<Vec<SrvRecord>, String>> {
+            Box::pin(async move { self.0.get(name).cloned().ok_or_else(|| axfr::rcode_label(3)) })
+        }
+    }
+
+    fn record(priority: u16, weight: u16, port: u16, target: &str) -> SrvRecord {
+        SrvRecord { priority, weight, port, target: target.to_string() }
+    }
+
+    fn names(text: &str) -> Vec<SrvName> {
+        text.split(',').map(|name| parse_name(name).unwrap()).collect()
+    }
+
+    async fn discovered() -> Discovery {
+        let answers = Answers(HashMap::from([
+            (
+                "_ldap._tcp.example.com",
+                vec![record(10, 50, 389, "dc2.example.com."), record(0, 100, 389, "DC1.example.com."), record(0, 20, 389, "dc3.example.com.")],
+            ),
+            ("_gc._tcp.example.com", vec![record(0, 100, 3268, "dc1.example.com.")]),
+            ("_sip._udp.example.com", vec![record(0, 0, 0, ".")]),
+        ]));
+        let names = names("_ldap._tcp.example.com,_GC._tcp.example.com.,_sip._udp.example.com,_xmpp._tcp.example.com");
+        discover(&names, Arc::new(answers), 2).await
+    }
+
+    #[test]
+    fn names_need_service_protocol_and_domain() {
+        assert_eq!(
+            parse_name(" _LDAP._tcp.Example.com. "),
+            Ok(SrvName { name: "_ldap._tcp.example.com".to_string(), service: "ldap".to_string(), protocol: "tcp".to_string() })
+        );
+        for bad in ["ldap._tcp.example.com", "_ldap.tcp.example.com", "_ldap._tcp", "_._tcp.example.com", "_ldap._tcp.example..com"] {
+            assert!(parse_name(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[tokio::test]
+    async fn lookups_rank_targets_and_report_failures() {
+        let discovery = discovered().await;
+        let names: Vec<&str> = discovery.lookups.iter().map(|l| l.name.name.as_str()).collect();
+        assert_eq!(names, vec!["_ldap._tcp.example.com", "_gc._tcp.example.com", "_sip._udp.example.com", "_xmpp._tcp.example.com"]);
+
+        let ldap = discovery.lookups[0].answer.as_ref().unwrap();
+        let ranked: Vec<(&str, bool)> = ldap.iter().map(|o| (o.target.as_str(), o.primary)).collect();
+        assert_eq!(ranked, vec![("dc1.example.com", true), ("dc3.example.com", false), ("dc2.example.com", false)]);
+        assert!(discovery.lookups[2].answer.as_ref().unwrap_err().contains("不提供此服務"));
+        assert_eq!(discovery.lookups[3].answer.as_ref().unwrap_err(), "名稱不存在 (NXDOMAIN)");
+
+        assert_eq!(discovery.targets(), vec!["dc1.example.com", "dc3.example.com", "dc2.example.com"]);
+        assert_eq!(discovery.target_spec(Some("192.0.2.1")).as_deref(), Some("192.0.2.1,dc1.example.com,dc3.example.com,dc2.example.com"));
+        assert_eq!(discovery.port_spec(None).as_deref(), Some("389,3268"));
+        assert_eq!(discovery.port_spec(Some("22,389")).as_deref(), Some("22,389,389,3268"));
+        assert_eq!(Discovery::default().port_spec(None), None);
+    }
+
+    #[tokio::test]
+    async fn explicit_ports_keep_their_names_and_gain_the_origin() {
+        let discovery = discovered().await;
+        let mut ports = vec![PortInfo::new(389, "LDAP-Custom", "Directory"), PortInfo::new(3268, "未知", "Custom")];
+        discovery.annotate(&mut ports, &BTreeSet::from([389]));
+        assert_eq!((ports[0].service.as_str(), ports[0].srv.len()), ("LDAP-Custom", 3));
+        assert_eq!((ports[1].service.as_str(), ports[1].category.as_str()), ("GC", "Custom"));
+        assert_eq!(describe(&ports[1].srv), "SRV _gc._tcp.example.com");
+
+        let json = serde_json::to_value(&ports[1]).unwrap();
+        assert_eq!(json["srv"][0]["target"], "dc1.example.com");
+        assert_eq!(json["srv"][0]["primary"], true);
+        // 沒有 SRV 來源的端口不輸出欄位
+        assert!(serde_json::to_value(PortInfo::new(22, "SSH", "Remote")).unwrap().get("srv").is_none());
+    }
+}
@@ -0,0 +1,78 @@
+use std::time::{Duration, Instant};
+use crate::timefmt;
+
+// 啟動步驟的結果
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    Took(Duration),
+    // 被選項略過
+    Skipped,
+    // 在背景進行，不延後掃描開始
+    Background,
+}
+
+// 掃描開始前各個啟動步驟的耗時；--verbose 時顯示，用來找出拖慢啟動的步驟
+#[derive(Debug, Default)]
+pub struct Startup {
+    steps: Vec<(&'static str, Step)>,
+}
+
+impl Startup {
+    // 執行並記錄一個步驟；run 為 false 時略過，回傳 None
+    pub fn time<T>(&mut self, name: &'static str, run: bool, step: impl FnOnce() -> T) -> Option<T> {
+        if !run {
+            self.steps.push((name, Step::Skipped));
+            return None;
+        }
+        let started = Instant::now();
+        let value = step();
+        self.steps.push((name, Step::Took(started.elapsed())));
+        Some(value)
+    }
+
+    pub fn record(&mut self, name: &'static str, step: Step) {
+        self.steps.push((name, step));
+    }
+
+    pub fn total(&self) -> Duration {
+        self.steps
+            .iter()
+            .map(|(_, step)| match step {
+                Step::Took(elapsed) => *elapsed,
+                _ => Duration::ZERO,
+            })
+            .sum()
+    }
+
+    // 例如 "啟動 3.1ms：設定檔 1.2ms、端口資料庫 略過、外部IP 背景"
+    pub fn describe(&self) -> String {
+        let steps: Vec<String> = self
+            .steps
+            .iter()
+            .map(|(name, step)| match step {
+                Step::Took(elapsed) => format!("{} {}", name, timefmt::millis(*elapsed)),
+                Step::Skipped => format!("{} 略過", name),
+                Step::Background => format!("{} 背景", name),
+            })
+            .collect();
+        format!("啟動 {}：{}", timefmt::millis(self.total()), steps.join("、"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skipped_steps_do_not_run() {
+        let mut startup = Startup::default();
+        let mut ran = false;
+        assert_eq!(startup.time("設定檔", false, || ran = true), None);
+        assert!(!ran);
+        assert_eq!(startup.time("端口資料庫", true, || 7), Some(7));
+        startup.record("外部IP", Step::Background);
+        assert!(matches!(startup.steps[..], [("設定檔", Step::Skipped), ("端口資料庫", Step::Took(_)), ("外部IP", Step::Background)]));
+        let text = startup.describe();
+        assert!(text.contains("設定檔 略過") && text.contains("外部IP 背景"), "{}", text);
+    }
+}
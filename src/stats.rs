@@ -0,0 +1,193 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// 百分位數 (線性內插，與 numpy / Excel PERCENTILE.INC 相同)，輸入需已排序
+// 第 p 百分位落在 (n-1)·p/100 的位置，介於兩個樣本之間時依距離內插；只有一個樣本時就是該樣本
+pub fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    let last = sorted.len().checked_sub(1)?;
+    let rank = last as f64 * (p.clamp(0.0, 100.0) / 100.0);
+    let lower = rank.floor() as usize;
+    let upper = (lower + 1).min(last);
+    Some(sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64))
+}
+
+// 中位數；偶數個樣本時為中間兩個的平均
+pub fn median(mut values: Vec<f64>) -> Option<f64> {
+    values.sort_by(f64::total_cmp);
+    percentile(&values, 50.0)
+}
+
+// 延遲目標使用的百分位
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Quantile {
+    P50,
+    P90,
+    P95,
+    P99,
+}
+
+impl Quantile {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "p50" | "median" => Some(Quantile::P50),
+            "p90" => Some(Quantile::P90),
+            "p95" => Some(Quantile::P95),
+            "p99" => Some(Quantile::P99),
+            _ => None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Quantile::P50 => "p50",
+            Quantile::P90 => "p90",
+            Quantile::P95 => "p95",
+            Quantile::P99 => "p99",
+        }
+    }
+}
+
+// 一組延遲樣本的摘要 (毫秒)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Percentiles {
+    pub count: usize,
+    pub min: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl Percentiles {
+    pub fn of(values: &[f64]) -> Option<Self> {
+        let mut sorted = values.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let at = |p| percentile(&sorted, p);
+        Some(Percentiles {
+            count: sorted.len(),
+            min: *sorted.first()?,
+            p50: at(50.0)?,
+            p90: at(90.0)?,
+            p95: at(95.0)?,
+            p99: at(99.0)?,
+            max: *sorted.last()?,
+        })
+    }
+
+    pub fn get(&self, quantile: Quantile) -> f64 {
+        match quantile {
+            Quantile::P50 => self.p50,
+            Quantile::P90 => self.p90,
+            Quantile::P95 => self.p95,
+            Quantile::P99 => self.p99,
+        }
+    }
+
+    // 例如 "p50 1.2ms  p90 3.4ms  p95 3.9ms  p99 4.1ms (10 次)"
+    pub fn describe(&self) -> String {
+        format!(
+            "p50 {:.1}ms  p90 {:.1}ms  p95 {:.1}ms  p99 {:.1}ms ({} 次)",
+            self.p50, self.p90, self.p95, self.p99, self.count
+        )
+    }
+}
+
+// 延遲分佈的區間上限 (毫秒)；最後一個區間沒有上限
+pub const HISTOGRAM_EDGES: [f64; 7] = [1.0, 5.0, 10.0, 50.0, 100.0, 500.0, 1000.0];
+
+// 各區間的樣本數；edges 為遞增的上限 (不含)，結果比 edges 多一個「以上」的區間
+pub fn histogram(values: &[f64], edges: &[f64]) -> Vec<usize> {
+    let mut counts = vec![0; edges.len() + 1];
+    for &value in values {
+        counts[edges.partition_point(|&edge| edge <= value)] += 1;
+    }
+    counts
+}
+
+// 區間的標籤，例如 "<1ms"、"5-10ms"、"≥1000ms"
+pub fn bucket_label(edges: &[f64], index: usize) -> String {
+    match (index.checked_sub(1).map(|i| edges[i]), edges.get(index)) {
+        (None, Some(upper)) => format!("<{}ms", upper),
+        (Some(lower), Some(upper)) => format!("{}-{}ms", lower, upper),
+        (Some(lower), None) => format!("≥{}ms", lower),
+        (None, None) => "全部".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(actual: Option<f64>, expected: f64) -> bool {
+        actual.is_some_and(|actual| (actual - expected).abs() < 1e-9)
+    }
+
+    // 預期值與 numpy.percentile (linear) 相同
+    #[test]
+    fn percentile_matches_known_datasets() {
+        let data = [15.0, 20.0, 35.0, 40.0, 50.0];
+        for (p, expected) in [(0.0, 15.0), (5.0, 16.0), (30.0, 23.0), (40.0, 29.0), (50.0, 35.0), (95.0, 48.0), (100.0, 50.0)] {
+            assert!(close(percentile(&data, p), expected), "p{} = {:?}", p, percentile(&data, p));
+        }
+        let data = [1.0, 2.0, 3.0, 4.0];
+        for (p, expected) in [(25.0, 1.75), (50.0, 2.5), (90.0, 3.7), (99.0, 3.97)] {
+            assert!(close(percentile(&data, p), expected), "p{}", p);
+        }
+        let data: Vec<f64> = (1..=100).map(f64::from).collect();
+        for (p, expected) in [(50.0, 50.5), (90.0, 90.1), (95.0, 95.05), (99.0, 99.01)] {
+            assert!(close(percentile(&data, p), expected), "p{}", p);
+        }
+    }
+
+    #[test]
+    fn percentile_small_samples() {
+        assert_eq!(percentile(&[], 50.0), None);
+        for p in [0.0, 50.0, 99.0, 100.0] {
+            assert_eq!(percentile(&[7.0], p), Some(7.0));
+        }
+        assert!(close(percentile(&[10.0, 20.0], 95.0), 19.5));
+        assert!(close(percentile(&[10.0, 20.0], 50.0), 15.0));
+        // 範圍外的百分位取兩端
+        assert_eq!(percentile(&[1.0, 2.0], -5.0), Some(1.0));
+        assert_eq!(percentile(&[1.0, 2.0], 150.0), Some(2.0));
+    }
+
+    #[test]
+    fn median_sorts_its_input() {
+        assert_eq!(median(vec![3.0, 1.0, 2.0]), Some(2.0));
+        assert_eq!(median(vec![4.0, 1.0, 3.0, 2.0]), Some(2.5));
+        assert_eq!(median(Vec::new()), None);
+    }
+
+    #[test]
+    fn percentiles_summary() {
+        let summary = Percentiles::of(&[5.0, 1.0, 4.0, 2.0, 3.0]).unwrap();
+        assert_eq!((summary.count, summary.min, summary.p50, summary.max), (5, 1.0, 3.0, 5.0));
+        assert!((summary.p90 - 4.6).abs() < 1e-9);
+        assert!(summary.p50 <= summary.p90 && summary.p90 <= summary.p95 && summary.p95 <= summary.p99);
+        assert_eq!(summary.get(Quantile::P50), 3.0);
+        assert_eq!(Percentiles::of(&[]), None);
+    }
+
+    #[test]
+    fn quantile_names() {
+        for quantile in [Quantile::P50, Quantile::P90, Quantile::P95, Quantile::P99] {
+            assert_eq!(Quantile::parse(quantile.label()), Some(quantile));
+        }
+        assert_eq!(Quantile::parse(" Median "), Some(Quantile::P50));
+        assert_eq!(Quantile::parse("p75"), None);
+    }
+
+    #[test]
+    fn histogram_uses_exclusive_upper_edges() {
+        let edges = [1.0, 5.0, 10.0];
+        assert_eq!(histogram(&[0.5, 1.0, 4.9, 5.0, 9.99, 10.0, 250.0], &edges), vec![1, 2, 2, 2]);
+        assert_eq!(histogram(&[], &edges), vec![0, 0, 0, 0]);
+        assert_eq!(histogram(&[3.0], &[]), vec![1]);
+        let labels: Vec<String> = (0..=edges.len()).map(|i| bucket_label(&edges, i)).collect();
+        assert_eq!(labels, ["<1ms", "1-5ms", "5-10ms", "≥10ms"]);
+        assert_eq!(bucket_label(&[], 0), "全部");
+    }
+}
@@ -0,0 +1,198 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use crate::errors::ErrorCode;
+use crate::share::Tally;
+
+// 掃描結束時寫到標準錯誤的最後一行，供擷取標準輸出的包裝腳本判斷結果 (第 1 版)：
+//
+//   portscanner: status=<ok|changed|violations|error> open=<數量> closed=<數量> filtered=<數量> duration_ms=<毫秒> exit=<結束代碼> scan_id=<ID> v=2
+//
+// - 欄位順序固定，以單一空白分隔；之後新增的欄位加在 exit 之後並提高版本，v 一律是最後一個欄位
+// - 計數與一行摘要 (share.rs) 相同，open 為可出站連線的結果數
+// - changed：沒有違規，但指紋與記錄不同或 watch 期間有端口狀態改變
+// - violations：服務清單、服務組合、政策、--compact 或出站政策的判定不成立
+// - error：其他錯誤，包括網路疑似中斷與 --strict 的掃描端錯誤
+// - scan_id：執行資訊的掃描 ID (第 2 版加入)；取得執行資訊前就結束時為 -
+pub const VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Changed,
+    Violations,
+    Error,
+}
+
+impl Status {
+    fn name(self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Changed => "changed",
+            Status::Violations => "violations",
+            Status::Error => "error",
+        }
+    }
+
+    // 依結束的錯誤代碼判定；沒有錯誤時看是否有變化
+    fn of(code: Option<ErrorCode>, changed: bool) -> Self {
+        match code {
+            None if changed => Status::Changed,
+            None => Status::Ok,
+            Some(
+                ErrorCode::UnexpectedOpen
+                | ErrorCode::BundleFailed
+                | ErrorCode::PolicyFailed
+                | ErrorCode::PortsUnavailable
+                | ErrorCode::EgressDisallowedOpen
+                | ErrorCode::EgressAllowedBlocked,
+            ) => Status::Violations,
+            Some(_) => Status::Error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub status: Status,
+    pub tally: Tally,
+    pub exit: i32,
+    pub scan_id: String,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "portscanner: status={} open={} closed={} filtered={} duration_ms={} exit={} scan_id={} v={}",
+            self.status.name(),
+            self.tally.open,
+            self.tally.closed,
+            self.tally.filtered,
+            self.tally.duration.as_millis(),
+            self.exit,
+            self.scan_id,
+            VERSION
+        )
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    enabled: bool,
+    started: Instant,
+    tally: Option<Tally>,
+    changed: bool,
+    written: bool,
+    scan_id: Option<String>,
+}
+
+// 執行期間累計狀態行的內容；Ctrl+C 的處理工作也需要，所以可以複製共用
+#[derive(Debug, Clone)]
+pub struct StatusLine {
+    state: Arc<Mutex<State>>,
+}
+
+impl StatusLine {
+    pub fn new(enabled: bool) -> Self {
+        let state = State { enabled, started: Instant::now(), tally: None, changed: false, written: false, scan_id: None };
+        StatusLine { state: Arc::new(Mutex::new(state)) }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // 子命令不是掃描，沒有狀態行
+    pub fn disable(&self) {
+        self.state().enabled = false;
+    }
+
+    // 一行摘要的計數
+    pub fn record(&self, tally: Tally) {
+        self.state().tally = Some(tally);
+    }
+
+    pub fn changed(&self) {
+        self.state().changed = true;
+    }
+
+    pub fn scan_id(&self, scan_id: &str) {
+        self.state().scan_id = Some(scan_id.to_string());
+    }
+
+    fn line(&self, code: Option<ErrorCode>, exit: i32) -> Line {
+        let state = self.state();
+        // 掃描前就結束時沒有摘要，耗時從程式開始計算
+        let tally = state.tally.unwrap_or(Tally { duration: state.started.elapsed(), ..Tally::default() });
+        let status = match exit {
+            0 => Status::of(None, state.changed),
+            _ => code.map_or(Status::Error, |code| Status::of(Some(code), state.changed)),
+        };
+        let scan_id = state.scan_id.clone().unwrap_or_else(|| "-".to_string());
+        Line { status, tally, exit, scan_id }
+    }
+
+    // 寫出狀態行，每次執行只寫一次
+    pub fn finish(&self, code: Option<ErrorCode>, exit: i32) {
+        let line = self.line(code, exit);
+        let mut state = self.state();
+        if state.enabled && !state.written {
+            state.written = true;
+            eprintln!("{}", line);
+        }
+    }
+
+    // 以錯誤代碼結束
+    pub fn exit(&self, code: ErrorCode) -> ! {
+        self.finish(Some(code), code.exit_code());
+        std::process::exit(code.exit_code());
+    }
+
+    // Ctrl+C 中斷
+    pub fn interrupted(&self) -> ! {
+        self.finish(None, 130);
+        std::process::exit(130);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::scanner::ScanRecord;
+    use crate::share::ShareLine;
+    use crate::testutil::{host, scan_result};
+    use crate::PortInfo;
+
+    #[test]
+    fn status_follows_the_exit_code() {
+        assert_eq!(Status::of(None, false), Status::Ok);
+        assert_eq!(Status::of(None, true), Status::Changed);
+        assert_eq!(Status::of(Some(ErrorCode::PolicyFailed), true), Status::Violations);
+        assert_eq!(Status::of(Some(ErrorCode::EgressAllowedBlocked), false), Status::Violations);
+        assert_eq!(Status::of(Some(ErrorCode::NetworkSuspect), false), Status::Error);
+        assert_eq!(Status::of(Some(ErrorCode::ConfigInvalid), false), Status::Error);
+
+        let status = StatusLine::new(true);
+        assert_eq!(status.line(None, 130).status, Status::Error);
+        status.changed();
+        assert_eq!(status.line(None, 0).status, Status::Changed);
+    }
+
+    #[test]
+    fn fields_come_from_the_share_summary() {
+        let mut share = ShareLine::default();
+        for (n, open) in [(1, true), (2, true), (3, false)] {
+            share.add(&ScanRecord { host: host(n), port: PortInfo::new(22, "SSH", "Remote"), result: scan_result(open), identity: None });
+        }
+        share.finish(Duration::from_millis(4210));
+        let status = StatusLine::new(true);
+        status.record(share.tally());
+        assert!(status.line(None, 0).to_string().contains(" exit=0 scan_id=- v=2"));
+        status.scan_id("ci-42");
+        let line = status.line(None, 0).to_string();
+        assert!(line.starts_with("portscanner: status=ok open=2 closed=1 filtered=0 duration_ms=4210 exit=0 scan_id=ci-42"), "{}", line);
+        assert!(line.ends_with(" v=2"), "{}", line);
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, SocketAddr};
+use colored::*;
+use schemars::JsonSchema;
+use serde::Serialize;
+use crate::errors::ErrorCode;
+use crate::targets::ResolveFailure;
+use crate::{PortInfo, ScanResult};
+
+// --strict 時掃描端錯誤的結束代碼
+pub const EXIT_SCANNER_ERRORS: i32 = 4;
+
+// 掃描端本身的一個錯誤；與端口關閉或被過濾的判定不同，代表這部分結果不存在或不可信
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct ScannerError {
+    pub code: ErrorCode,
+    // 主機名稱解析失敗時為名稱，端口的錯誤為 位址:端口
+    pub target: String,
+    pub message: String,
+}
+
+// 依目標與端口順序列出所有掃描端錯誤
+pub fn collect(results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>, failures: &[ResolveFailure]) -> Vec<ScannerError> {
+    let mut errors: Vec<ScannerError> = failures
+        .iter()
+        .filter(|failure| failure.code.is_internal())
+        .map(|failure| ScannerError { code: failure.code, target: failure.name.clone(), message: failure.error.clone() })
+        .collect();
+    for (host, ports) in results {
+        let mut failed: Vec<(u16, _)> = ports.iter().filter_map(|(port, result)| Some((port.port, result.error?))).collect();
+        failed.sort_by_key(|(port, _)| *port);
+        errors.extend(failed.into_iter().map(|(port, error)| ScannerError {
+            code: error.code(),
+            target: SocketAddr::new(*host, port).to_string(),
+            message: error.describe().to_string(),
+        }));
+    }
+    errors
+}
+
+pub fn display(errors: &[ScannerError]) {
+    if errors.is_empty() {
+        return;
+    }
+    println!("\n{}", "=== 掃描端錯誤 ===".bold());
+    for error in errors {
+        println!("{} [{}] {}: {}", "!".red(), error.code.code(), error.target, error.message.red());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::ScanError;
+    use crate::testutil::{host, scan_result};
+
+    fn failure(name: &str, error: &str) -> ResolveFailure {
+        ResolveFailure { name: name.to_string(), error: error.to_string(), code: crate::dns::failure_code(error) }
+    }
+
+    #[test]
+    fn only_scanner_side_errors_are_collected() {
+        let mut ports = HashMap::new();
+        for (port, error) in [(22, None), (80, Some(ScanError::TooManyOpenFiles)), (443, Some(ScanError::AddressInUse)), (8080, Some(ScanError::Panicked))] {
+            let mut result = scan_result(false);
+            result.error = error;
+            // 被拒與逾時是探測的判定
+            result.codes = vec![ErrorCode::ConnectionRefused];
+            ports.insert(PortInfo::new(port, "Test", "Test"), result);
+        }
+        let results = BTreeMap::from([(host(1), ports)]);
+        let failures = [
+            failure("gone.example", "無法解析目標 gone.example: failed to lookup address information: Name or service not known"),
+            failure("db01.example", "無法解析目標 db01.example: failed to lookup address information: Temporary failure in name resolution"),
+        ];
+
+        let errors = collect(&results, &failures);
+        let seen: Vec<(ErrorCode, &str)> = errors.iter().map(|e| (e.code, e.target.as_str())).collect();
+        assert_eq!(
+            seen,
+            [
+                (ErrorCode::DnsUnavailable, "db01.example"),
+                (ErrorCode::FdExhausted, "192.0.2.1:80"),
+                (ErrorCode::LocalPortExhausted, "192.0.2.1:443"),
+                (ErrorCode::ProbePanicked, "192.0.2.1:8080"),
+            ]
+        );
+        assert!(errors.iter().all(|e| e.code.is_internal()));
+        assert_eq!(serde_json::to_value(&errors[1]).unwrap()["code"], "E2001");
+    }
+}
@@ -0,0 +1,336 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// 半開放掃描的結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SynState {
+    // 收到 SYN-ACK
+    Open,
+    // 收到 RST
+    Closed,
+    // 逾時沒有回應
+    Filtered,
+}
+
+impl SynState {
+    pub fn describe(self) -> &'static str {
+        match self {
+            SynState::Open => "SYN-ACK (開放)",
+            SynState::Closed => "RST (關閉)",
+            SynState::Filtered => "無回應 (被過濾)",
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use linux::SynScanner;
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::collections::{BTreeMap, HashMap};
+    use std::io::ErrorKind;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::{Arc, Mutex, Weak};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+    use tokio::sync::oneshot;
+    use tokio::time::timeout;
+    use crate::osguess::{self, TcpSample};
+    use crate::privhelper::{Channel, Helper, RawSocket};
+    use super::SynState;
+
+    // TCP 旗標
+    const FIN: u8 = 0x01;
+    const SYN: u8 = 0x02;
+    const RST: u8 = 0x04;
+    const ACK: u8 = 0x10;
+
+    // 等待回應的探測：(目標, 目的端口) -> (送出的序號, 通知)
+    type Pending = Mutex<HashMap<(Ipv4Addr, u16), (u32, oneshot::Sender<SynState>)>>;
+
+    // 各主機 SYN-ACK 的特徵，供作業系統猜測
+    type Samples = Mutex<HashMap<Ipv4Addr, Vec<TcpSample>>>;
+
+    // 以原始 socket 送出 SYN 並比對回應 (只支援 IPv4)
+    #[derive(Debug)]
+    pub struct SynScanner {
+        socket: Arc<RawSocket>,
+        pending: Arc<Pending>,
+        samples: Arc<Samples>,
+        // 保留來源端口，避免被其他程式使用
+        _reserved: Socket,
+        source_port: u16,
+        next_seq: AtomicU32,
+    }
+
+    impl SynScanner {
+        // 需要 root 或 CAP_NET_RAW，或經由 --privileged-helper 的輔助程序
+        pub fn open(helper: Option<&Arc<Helper>>) -> Result<Self, String> {
+            let socket = RawSocket::open(Channel::Tcp, helper).map_err(|e| match (e.kind(), helper) {
+                (_, Some(_)) => e.to_string(),
+                (ErrorKind::PermissionDenied, None) => "--syn 需要 root 權限或 CAP_NET_RAW".to_string(),
+                _ => format!("無法開啟原始 socket: {}", e),
+            })?;
+
+            let reserved = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP)).map_err(|e| e.to_string())?;
+            reserved
+                .bind(&SockAddr::from(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)))
+                .map_err(|e| format!("無法保留來源端口: {}", e))?;
+            let source_port = reserved
+                .local_addr()
+                .ok()
+                .and_then(|addr| addr.as_socket())
+                .map(|addr| addr.port())
+                .ok_or("無法取得來源端口")?;
+
+            let socket = Arc::new(socket);
+            let pending: Arc<Pending> = Arc::default();
+            let samples: Arc<Samples> = Arc::default();
+            let (receiver, waiting, recorded) = (socket.clone(), Arc::downgrade(&pending), samples.clone());
+            std::thread::Builder::new()
+                .name("syn-receiver".to_string())
+                .spawn(move || receive(&receiver, waiting, &recorded, source_port))
+                .map_err(|e| e.to_string())?;
+
+            let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or_default();
+            Ok(SynScanner {
+                socket,
+                pending,
+                samples,
+                _reserved: reserved,
+                source_port,
+                next_seq: AtomicU32::new(seed),
+            })
+        }
+
+        pub fn source_port(&self) -> u16 {
+            self.source_port
+        }
+
+        // 掃描期間收到的 SYN-ACK 特徵，每台主機最多 osguess::SAMPLES_PER_HOST 個
+        pub fn samples(&self) -> BTreeMap<IpAddr, Vec<TcpSample>> {
+            let samples = self.samples.lock().unwrap_or_else(|e| e.into_inner());
+            samples.iter().map(|(host, list)| (IpAddr::V4(*host), list.clone())).collect()
+        }
+
+        // 只支援 IPv4 目標，其他由呼叫端改用完整連線
+        pub fn supports(dest: IpAddr) -> Option<Ipv4Addr> {
+            match dest {
+                IpAddr::V4(addr) => Some(addr),
+                IpAddr::V6(_) => None,
+            }
+        }
+
+        // 送出 SYN 並等待 SYN-ACK 或 RST；收到 SYN-ACK 後送 RST 中止，不完成握手
+        pub async fn probe(&self, dest: Ipv4Addr, port: u16, limit: Duration) -> SynState {
+            let Some(source) = source_for(dest) else {
+                return SynState::Filtered;
+            };
+            let seq = self.next_seq.fetch_add(0x0101_0101, Ordering::Relaxed);
+            let (tx, rx) = oneshot::channel();
+            if let Ok(mut pending) = self.pending.lock() {
+                pending.insert((dest, port), (seq, tx));
+            }
+
+            let syn = build_segment(source, dest, self.source_port, port, seq, 0, SYN);
+            let state = match self.socket.send_to(&syn, dest) {
+                Ok(_) => timeout(limit, rx).await.ok().and_then(Result::ok).unwrap_or(SynState::Filtered),
+                Err(_) => SynState::Filtered,
+            };
+            if let Ok(mut pending) = self.pending.lock() {
+                pending.remove(&(dest, port));
+            }
+
+            if state == SynState::Open {
+                let rst = build_segment(source, dest, self.source_port, port, seq.wrapping_add(1), 0, RST);
+                let _ = self.socket.send_to(&rst, dest);
+            }
+            state
+        }
+    }
+
+    // 依路由表取得送往目標時使用的本機位址 (UDP connect 不會送出封包)
+    fn source_for(dest: Ipv4Addr) -> Option<Ipv4Addr> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+        socket.connect((dest, 9)).ok()?;
+        match socket.local_addr().ok()? {
+            SocketAddr::V4(addr) => Some(*addr.ip()),
+            SocketAddr::V6(_) => None,
+        }
+    }
+
+    // 網際網路檢查碼 (RFC 1071)
+    fn checksum(chunks: &[&[u8]]) -> u16 {
+        let mut sum: u32 = 0;
+        let mut odd: Option<u8> = None;
+        for &byte in chunks.iter().flat_map(|c| c.iter()) {
+            match odd.take() {
+                Some(high) => sum += u32::from(u16::from_be_bytes([high, byte])),
+                None => odd = Some(byte),
+            }
+        }
+        if let Some(high) = odd {
+            sum += u32::from(u16::from_be_bytes([high, 0]));
+        }
+        while sum >> 16 != 0 {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        !(sum as u16)
+    }
+
+    // 建立 TCP 標頭 (含虛擬標頭檢查碼)；SYN 附帶 MSS 選項
+    fn build_segment(src: Ipv4Addr, dst: Ipv4Addr, sport: u16, dport: u16, seq: u32, ack: u32, flags: u8) -> Vec<u8> {
+        let options: &[u8] = if flags & SYN != 0 { &[2, 4, 0x05, 0xb4] } else { &[] };
+        let len = 20 + options.len();
+
+        let mut segment = Vec::with_capacity(len);
+        segment.extend_from_slice(&sport.to_be_bytes());
+        segment.extend_from_slice(&dport.to_be_bytes());
+        segment.extend_from_slice(&seq.to_be_bytes());
+        segment.extend_from_slice(&ack.to_be_bytes());
+        segment.push(((len / 4) as u8) << 4);
+        segment.push(flags);
+        segment.extend_from_slice(&64240u16.to_be_bytes());
+        segment.extend_from_slice(&[0, 0, 0, 0]);
+        segment.extend_from_slice(options);
+
+        let mut pseudo = Vec::with_capacity(12);
+        pseudo.extend_from_slice(&src.octets());
+        pseudo.extend_from_slice(&dst.octets());
+        pseudo.extend_from_slice(&[0, 6]);
+        pseudo.extend_from_slice(&(len as u16).to_be_bytes());
+        let sum = checksum(&[&pseudo, &segment]);
+        segment[16..18].copy_from_slice(&sum.to_be_bytes());
+        segment
+    }
+
+    // 收到的 TCP 回應
+    struct Reply {
+        source: Ipv4Addr,
+        sport: u16,
+        dport: u16,
+        ack: u32,
+        flags: u8,
+        ttl: u8,
+        window: u16,
+        // 選項種類依出現順序
+        options: Vec<u8>,
+    }
+
+    // 依序取出 TCP 選項的種類；長度錯誤時停止
+    fn option_kinds(mut options: &[u8]) -> Vec<u8> {
+        let mut kinds = Vec::new();
+        while let Some(&kind) = options.first() {
+            kinds.push(kind);
+            match kind {
+                osguess::OPT_EOL => break,
+                osguess::OPT_NOP => options = &options[1..],
+                _ => match options.get(1).map(|&len| usize::from(len)) {
+                    Some(len) if len >= 2 && len <= options.len() => options = &options[len..],
+                    _ => break,
+                },
+            }
+        }
+        kinds
+    }
+
+    // 從收到的 IPv4 封包取出回應的欄位
+    fn parse_reply(packet: &[u8]) -> Option<Reply> {
+        if packet.first()? >> 4 != 4 || *packet.get(9)? != 6 {
+            return None;
+        }
+        let header_len = usize::from(packet[0] & 0x0f) * 4;
+        let source = Ipv4Addr::from(<[u8; 4]>::try_from(packet.get(12..16)?).ok()?);
+        let tcp = packet.get(header_len..header_len + 20)?;
+        let data_offset = usize::from(tcp[12] >> 4) * 4;
+        let options = packet.get(header_len + 20..header_len + data_offset.max(20)).unwrap_or_default();
+        Some(Reply {
+            source,
+            sport: u16::from_be_bytes([tcp[0], tcp[1]]),
+            dport: u16::from_be_bytes([tcp[2], tcp[3]]),
+            ack: u32::from_be_bytes([tcp[8], tcp[9], tcp[10], tcp[11]]),
+            flags: tcp[13],
+            ttl: packet[8],
+            window: u16::from_be_bytes([tcp[14], tcp[15]]),
+            options: option_kinds(options),
+        })
+    }
+
+    // 接收執行緒：依目標、端口與確認號比對回應；掃描器釋放後結束
+    fn receive(socket: &RawSocket, pending: Weak<Pending>, samples: &Samples, source_port: u16) {
+        let mut buf = [0u8; 1500];
+
+        loop {
+            let received = socket.recv(&mut buf);
+            let Some(pending) = pending.upgrade() else {
+                return;
+            };
+            let len = match received {
+                Ok((len, _)) => len,
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) => continue,
+                Err(_) => return,
+            };
+            let packet = &buf[..len];
+
+            let Some(reply) = parse_reply(packet) else {
+                continue;
+            };
+            let Reply { source: from, sport, dport, ack, flags, .. } = reply;
+            if dport != source_port || flags & FIN != 0 {
+                continue;
+            }
+            let state = match flags & (SYN | RST | ACK) {
+                f if f == SYN | ACK => SynState::Open,
+                f if f & RST != 0 => SynState::Closed,
+                _ => continue,
+            };
+
+            let Ok(mut pending) = pending.lock() else {
+                return;
+            };
+            let matches = pending.get(&(from, sport)).is_some_and(|(seq, _)| ack == seq.wrapping_add(1));
+            if matches {
+                if let Some((_, tx)) = pending.remove(&(from, sport)) {
+                    let _ = tx.send(state);
+                }
+                if state == SynState::Open {
+                    let mut samples = samples.lock().unwrap_or_else(|e| e.into_inner());
+                    let host = samples.entry(from).or_default();
+                    if host.len() < osguess::SAMPLES_PER_HOST {
+                        host.push(TcpSample { port: sport, ttl: reply.ttl, window: reply.window, options: reply.options });
+                    }
+                }
+            }
+        }
+    }
+}
+
+// 其他平台無法開啟，維持完整連線掃描
+#[cfg(not(target_os = "linux"))]
+#[derive(Debug)]
+pub struct SynScanner;
+
+#[cfg(not(target_os = "linux"))]
+impl SynScanner {
+    pub fn open(_helper: Option<&std::sync::Arc<crate::privhelper::Helper>>) -> Result<Self, String> {
+        Err("--syn 目前只支援 Linux".to_string())
+    }
+
+    pub fn source_port(&self) -> u16 {
+        0
+    }
+
+    pub async fn probe(&self, _dest: std::net::Ipv4Addr, _port: u16, _limit: std::time::Duration) -> SynState {
+        SynState::Filtered
+    }
+
+    pub fn supports(_dest: std::net::IpAddr) -> Option<std::net::Ipv4Addr> {
+        None
+    }
+
+    pub fn samples(&self) -> std::collections::BTreeMap<std::net::IpAddr, Vec<crate::osguess::TcpSample>> {
+        Default::default()
+    }
+}
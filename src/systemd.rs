@@ -0,0 +1,279 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::time::Duration;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::context::ScanContext;
+use crate::{PortInfo, ScanResult};
+
+// 查詢 systemd 的上限；D-Bus 沒有回應時不拖住報告
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// ListenStream 綁定的位址
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bind {
+    // 只有端口或 [::]：systemd 預設 (BindIPv6Only=default) 同時接受 IPv4 與 IPv6
+    Any,
+    // 0.0.0.0
+    AnyV4,
+    Addr(IpAddr),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Listen {
+    bind: Bind,
+    port: u16,
+}
+
+impl Listen {
+    fn covers(self, host: IpAddr, port: u16) -> bool {
+        port == self.port
+            && match self.bind {
+                Bind::Any => true,
+                Bind::AnyV4 => host.is_ipv4(),
+                Bind::Addr(addr) => addr == host,
+            }
+    }
+}
+
+// ListenStream 的值：22、0.0.0.0:22、127.0.0.1:22、[::]:22、[fe80::1%eth0]:22；
+// Unix socket (/run/x.sock、@abstract)、vsock 等不是 TCP 端口，回傳 None
+fn parse_listen(value: &str) -> Option<Listen> {
+    let value = value.trim();
+    if let Ok(port) = value.parse::<u16>() {
+        return (port != 0).then_some(Listen { bind: Bind::Any, port });
+    }
+    let (host, port) = match value.strip_prefix('[') {
+        Some(rest) => {
+            let (host, port) = rest.split_once("]:")?;
+            // 連結本地位址的 %介面 不影響比對
+            (host.split('%').next()?, port)
+        }
+        None => value.rsplit_once(':')?,
+    };
+    let port = port.parse::<u16>().ok().filter(|&port| port != 0)?;
+    let bind = match host {
+        "*" => Bind::Any,
+        host => match host.parse::<IpAddr>().ok()? {
+            addr if addr.is_unspecified() && addr.is_ipv6() => Bind::Any,
+            addr if addr.is_unspecified() => Bind::AnyV4,
+            addr => Bind::Addr(addr),
+        },
+    };
+    // 沒有方括號的 IPv6 位址 (例如 ::1:22) 不合法
+    if !value.starts_with('[') && matches!(bind, Bind::Addr(IpAddr::V6(_))) {
+        return None;
+    }
+    Some(Listen { bind, port })
+}
+
+// 監聽端口的 socket unit 與其啟動的 service
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SocketUnit {
+    pub unit: String,
+    // ActiveState：active、inactive、failed 等
+    pub state: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service_state: Option<String>,
+}
+
+impl SocketUnit {
+    // 啟動失敗的 unit (socket 本身或它啟動的 service)
+    pub fn failed_unit(&self) -> Option<&str> {
+        match (self.state.as_str(), &self.service, self.service_state.as_deref()) {
+            ("failed", _, _) => Some(&self.unit),
+            (_, Some(service), Some("failed")) => Some(service),
+            _ => None,
+        }
+    }
+
+    pub fn note(&self) -> String {
+        match (self.failed_unit(), self.state.as_str()) {
+            (Some(unit), _) => format!("對應 unit 啟動失敗 ({})", unit),
+            (None, "active") => format!("由 {} 監聽 (active)", self.unit),
+            (None, state) => format!("對應 {} 未監聽 ({})", self.unit, state),
+        }
+    }
+}
+
+// 所有 socket unit 的 ListenStream 位址
+#[derive(Debug, Default)]
+pub struct Units {
+    listens: Vec<(Listen, SocketUnit)>,
+}
+
+impl Units {
+    // 每個 unit 與其 ListenStream 的值；無法解析的位址略過
+    fn new(sockets: Vec<(SocketUnit, Vec<String>)>) -> Self {
+        let listens = sockets
+            .into_iter()
+            .flat_map(|(unit, values)| {
+                values.iter().filter_map(|value| parse_listen(value)).map(|listen| (listen, unit.clone())).collect::<Vec<_>>()
+            })
+            .collect();
+        Units { listens }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.listens.is_empty()
+    }
+
+    // 指定位址的 ListenStream 優先於萬用位址
+    fn lookup(&self, host: IpAddr, port: u16) -> Option<&SocketUnit> {
+        self.listens
+            .iter()
+            .filter(|(listen, _)| listen.covers(host, port))
+            .min_by_key(|(listen, _)| !matches!(listen.bind, Bind::Addr(_)))
+            .map(|(_, unit)| unit)
+    }
+
+    // 標示本機目標 (loopback 與本機介面位址) 的結果；回傳標示的端口數
+    pub fn apply(&self, results: &mut BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>, context: &ScanContext) -> usize {
+        let local = |host: IpAddr| host.is_loopback() || host.is_unspecified() || context.interfaces.contains(&host);
+        let mut annotated = 0;
+        for (host, ports) in results.iter_mut().filter(|(host, _)| local(**host)) {
+            for (port, result) in ports.iter_mut() {
+                let Some(unit) = self.lookup(*host, port.port) else {
+                    continue;
+                };
+                let note = unit.note();
+                result.note = Some(match result.note.take() {
+                    Some(existing) => format!("{}；{}", existing, note),
+                    None => note,
+                });
+                result.systemd = Some(unit.clone());
+                annotated += 1;
+            }
+        }
+        annotated
+    }
+}
+
+// 經由 system bus 查詢所有已載入的 socket unit；沒有 systemd 或 D-Bus 時回傳錯誤說明
+pub async fn query() -> Result<Units, String> {
+    match tokio::time::timeout(QUERY_TIMEOUT, platform::socket_units()).await {
+        Ok(Ok(sockets)) => Ok(Units::new(sockets)),
+        Ok(Err(e)) => Err(e),
+        Err(_) => Err(format!("systemd 在 {} 秒內沒有回應", QUERY_TIMEOUT.as_secs())),
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use zbus::zvariant::OwnedObjectPath;
+    use zbus::{Connection, Proxy};
+    use super::SocketUnit;
+
+    const DESTINATION: &str = "org.freedesktop.systemd1";
+
+    // ListUnits 系列的一筆：名稱、說明、載入狀態、ActiveState、子狀態、跟隨的 unit、物件路徑、工作 ID、工作類型、工作路徑
+    type UnitEntry = (String, String, String, String, String, String, OwnedObjectPath, u32, String, OwnedObjectPath);
+
+    pub async fn socket_units() -> Result<Vec<(SocketUnit, Vec<String>)>, String> {
+        let connection = Connection::system().await.map_err(|e| format!("無法連線到 system bus: {}", e))?;
+        query(&connection).await.map_err(|e| format!("查詢 systemd 失敗: {}", e))
+    }
+
+    async fn query(connection: &Connection) -> zbus::Result<Vec<(SocketUnit, Vec<String>)>> {
+        let manager = Proxy::new(connection, DESTINATION, "/org/freedesktop/systemd1", "org.freedesktop.systemd1.Manager").await?;
+        let entries: Vec<UnitEntry> = manager.call("ListUnitsByPatterns", &(Vec::<String>::new(), vec!["*.socket"])).await?;
+        let mut sockets = Vec::new();
+        for (name, _, _, state, _, _, path, ..) in entries {
+            let socket = Proxy::new(connection, DESTINATION, path.clone(), "org.freedesktop.systemd1.Socket").await?;
+            // (類型, 位址)：Stream 為 ListenStream
+            let listen: Vec<(String, String)> = socket.get_property("Listen").await?;
+            let streams: Vec<String> = listen.into_iter().filter(|(kind, _)| kind == "Stream").map(|(_, addr)| addr).collect();
+            if streams.is_empty() {
+                continue;
+            }
+            let unit = Proxy::new(connection, DESTINATION, path, "org.freedesktop.systemd1.Unit").await?;
+            let triggers: Vec<String> = unit.get_property("Triggers").await?;
+            let service = triggers.into_iter().find(|unit| unit.ends_with(".service"));
+            let service_state = match &service {
+                // Accept=yes 的 socket 啟動 foo@.service 範本，沒有單一的狀態
+                Some(service) if !service.contains("@.") => {
+                    let found: Vec<UnitEntry> = manager.call("ListUnitsByNames", &(vec![service.as_str()],)).await?;
+                    found.into_iter().next().map(|entry| entry.3)
+                }
+                _ => None,
+            };
+            sockets.push((SocketUnit { unit: name, state, service, service_state }, streams));
+        }
+        Ok(sockets)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use super::SocketUnit;
+
+    pub async fn socket_units() -> Result<Vec<(SocketUnit, Vec<String>)>, String> {
+        Err("--systemd 只支援 Linux".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use crate::context::ExternalIpSource;
+    use crate::testutil::scan_result;
+
+    fn unit(name: &str, state: &str, service_state: Option<&str>) -> SocketUnit {
+        SocketUnit {
+            unit: name.to_string(),
+            state: state.to_string(),
+            service: Some(name.replace(".socket", ".service")),
+            service_state: service_state.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn listen_stream_forms() {
+        let v4: IpAddr = Ipv4Addr::new(127, 0, 0, 1).into();
+        let v6: IpAddr = Ipv6Addr::LOCALHOST.into();
+        assert_eq!(parse_listen("22"), Some(Listen { bind: Bind::Any, port: 22 }));
+        assert_eq!(parse_listen("0.0.0.0:80"), Some(Listen { bind: Bind::AnyV4, port: 80 }));
+        assert_eq!(parse_listen("[::]:443"), Some(Listen { bind: Bind::Any, port: 443 }));
+        assert_eq!(parse_listen("127.0.0.1:631"), Some(Listen { bind: Bind::Addr(v4), port: 631 }));
+        assert_eq!(parse_listen("[::1]:631"), Some(Listen { bind: Bind::Addr(v6), port: 631 }));
+        assert_eq!(parse_listen("[fe80::1%eth0]:8080").map(|listen| listen.port), Some(8080));
+        for other in ["/run/dbus/system_bus_socket", "@/org/kernel/udev", "vsock:2:1234", "::1:22", "0", "[::1]:99999"] {
+            assert_eq!(parse_listen(other), None, "{}", other);
+        }
+
+        assert!(parse_listen("22").unwrap().covers(v4, 22));
+        assert!(!parse_listen("0.0.0.0:22").unwrap().covers(v6, 22));
+        assert!(!parse_listen("127.0.0.1:22").unwrap().covers(Ipv4Addr::new(10, 0, 0, 1).into(), 22));
+    }
+
+    #[test]
+    fn local_results_are_annotated() {
+        let units = Units::new(vec![
+            (unit("sshd.socket", "active", Some("inactive")), vec!["22".to_string()]),
+            (unit("cups.socket", "active", Some("failed")), vec!["127.0.0.1:631".to_string(), "/run/cups/cups.sock".to_string()]),
+            (unit("web.socket", "active", None), vec!["0.0.0.0:8080".to_string()]),
+            (unit("web-local.socket", "failed", None), vec!["127.0.0.1:8080".to_string()]),
+        ]);
+        let local: IpAddr = Ipv4Addr::LOCALHOST.into();
+        let remote: IpAddr = Ipv4Addr::new(192, 0, 2, 10).into();
+        let mut results = BTreeMap::new();
+        for host in [local, remote] {
+            let ports = [22, 631, 8080, 9000].map(|port| (PortInfo::new(port, "svc", "Other"), scan_result(false)));
+            results.insert(host, HashMap::from(ports));
+        }
+        let context = ScanContext::new(ExternalIpSource::Disabled);
+        assert_eq!(units.apply(&mut results, &context), 3);
+
+        let note = |host: &IpAddr, port: u16| results[host].iter().find(|(info, _)| info.port == port).and_then(|(_, r)| r.note.clone());
+        assert_eq!(note(&local, 22).as_deref(), Some("由 sshd.socket 監聽 (active)"));
+        assert_eq!(note(&local, 631).as_deref(), Some("對應 unit 啟動失敗 (cups.service)"));
+        // 指定位址的 unit 優先
+        assert_eq!(note(&local, 8080).as_deref(), Some("對應 unit 啟動失敗 (web-local.socket)"));
+        assert_eq!(note(&local, 9000), None);
+        // 遠端目標不是這台主機的 systemd
+        assert!(results[&remote].values().all(|result| result.note.is_none() && result.systemd.is_none()));
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+use serde::Deserialize;
+use crate::portspec;
+use crate::PortInfo;
+
+// 設定檔 [ports."端口"] 的覆蓋，例如
+//   [ports.8080]
+//   service = "內部 API"
+//   tags = ["env:prod", "owner:platform-team"]
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PortOverride {
+    pub service: Option<String>,
+    pub category: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+// 端口覆蓋與 [tags] 範圍對應，啟動時驗證
+#[derive(Debug, Default)]
+pub struct TagRules {
+    overrides: BTreeMap<u16, PortOverride>,
+    // (起點, 終點, 標籤)
+    ranges: Vec<(u16, u16, Vec<String>)>,
+}
+
+fn validate_tags(tags: &[String], section: &str) -> Result<(), String> {
+    match tags.iter().find(|tag| tag.trim().is_empty() || tag.contains(',')) {
+        Some(tag) => Err(format!("設定檔 [{}] 無效的標籤: {:?}", section, tag)),
+        None => Ok(()),
+    }
+}
+
+impl TagRules {
+    // ports: 端口 -> 覆蓋；tags: "22" 或 "8000-8100" -> 標籤
+    pub fn build(ports: BTreeMap<String, PortOverride>, tags: &BTreeMap<String, Vec<String>>) -> Result<Self, String> {
+        let mut rules = TagRules::default();
+        for (key, port_override) in ports {
+            validate_tags(&port_override.tags, "ports")?;
+            let port = portspec::parse_port(&key).map_err(|e| format!("設定檔 [ports] {}", e))?;
+            rules.overrides.insert(port, port_override);
+        }
+        for (key, labels) in tags {
+            validate_tags(labels, "tags")?;
+            let range = portspec::parse_range(key).map_err(|e| format!("設定檔 [tags] {}", e))?;
+            rules.ranges.push((*range.start(), *range.end(), labels.clone()));
+        }
+        Ok(rules)
+    }
+
+    // 套用到要掃描的端口；標籤排序去重
+    pub fn apply(&self, ports: &mut [PortInfo]) {
+        for port in ports {
+            if let Some(port_override) = self.overrides.get(&port.port) {
+                if let Some(service) = &port_override.service {
+                    port.service = service.clone();
+                }
+                if let Some(category) = &port_override.category {
+                    port.category = category.clone();
+                }
+                port.tags.extend(port_override.tags.iter().cloned());
+            }
+            for (start, end, labels) in &self.ranges {
+                if (*start..=*end).contains(&port.port) {
+                    port.tags.extend(labels.iter().cloned());
+                }
+            }
+            port.tags.sort();
+            port.tags.dedup();
+        }
+    }
+}
+
+// --tag 篩選：env:prod 比對完整標籤，env 比對所有 env:* 標籤
+fn tag_matches(tag: &str, filter: &str) -> bool {
+    tag == filter || (!filter.contains(':') && tag.split_once(':').is_some_and(|(key, _)| key == filter))
+}
+
+// 端口須符合每一個 --tag
+pub fn matches_all(port: &PortInfo, filters: &[String]) -> bool {
+    filters.iter().all(|filter| port.tags.iter().any(|tag| tag_matches(tag, filter)))
+}
+
+// 分組與終端顯示用的標籤文字
+pub fn describe(tags: &[String]) -> String {
+    tags.iter().map(|tag| format!("#{}", tag)).collect::<Vec<_>>().join(" ")
+}
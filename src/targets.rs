@@ -0,0 +1,1069 @@
+use std::error::Error;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use ipnet::{IpNet, Ipv4Net, Ipv4Subnets, Ipv6Net, Ipv6Subnets};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::errors::{coded, ErrorCode, WithCode};
+use crate::dns::{self, DnsCache};
+use crate::zone::Zones;
+
+// 設定檔 [safety] 區段
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SafetyConfig {
+    // 未加 --allow-large 時最多掃描的主機數
+    #[serde(default = "default_max_hosts")]
+    pub max_hosts: u64,
+    // 稽核紀錄的位置 (預設為設定目錄下的 audit.jsonl)
+    #[serde(default)]
+    pub audit_log: Option<PathBuf>,
+}
+
+impl Default for SafetyConfig {
+    fn default() -> Self {
+        SafetyConfig {
+            max_hosts: default_max_hosts(),
+            audit_log: None,
+        }
+    }
+}
+
+fn default_max_hosts() -> u64 {
+    256
+}
+
+// 單個掃描目標：主機 (可能來自主機名稱) 或整個網段
+#[derive(Debug, Clone)]
+pub enum TargetSpec {
+    Host { name: String, addr: IpAddr },
+    // 網段與落在其中的排除範圍
+    Network(IpNet, Arc<Exclusions>),
+    // 只在 dry-run --no-resolve 時出現，不會被實際掃描
+    Unresolved(String),
+}
+
+impl TargetSpec {
+    // 此目標包含的主機數量
+    pub fn host_count(&self) -> u128 {
+        match self {
+            TargetSpec::Host { .. } | TargetSpec::Unresolved(_) => 1,
+            // size_hint 為精確數量，超大 IPv6 網段會飽和於 usize::MAX
+            TargetSpec::Network(net, excluded) => {
+                let (first, last) = host_range(net);
+                (net.hosts().size_hint().0 as u128).saturating_sub(excluded.overlap(net, first, last))
+            }
+        }
+    }
+
+    // 逐一產生目標位址，大型網段不會一次展開到記憶體
+    pub fn addrs(&self) -> Box<dyn Iterator<Item = IpAddr> + Send> {
+        match self {
+            TargetSpec::Host { addr, .. } => Box::new(std::iter::once(*addr)),
+            TargetSpec::Network(net, excluded) if excluded.is_empty() => Box::new(net.hosts()),
+            TargetSpec::Network(net, excluded) => {
+                let excluded = excluded.clone();
+                Box::new(net.hosts().filter(move |addr| !excluded.contains(*addr)))
+            }
+            TargetSpec::Unresolved(_) => Box::new(std::iter::empty()),
+        }
+    }
+
+    // 位址是否為此目標要掃描的主機
+    pub fn contains(&self, host: IpAddr) -> bool {
+        match self {
+            TargetSpec::Host { addr, .. } => *addr == host,
+            TargetSpec::Network(net, excluded) => net.contains(&host) && !excluded.contains(host),
+            TargetSpec::Unresolved(_) => false,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            TargetSpec::Host { name, addr } if name != &addr.to_string() => format!("{} ({})", display_hostname(name), addr),
+            TargetSpec::Host { addr, .. } => addr.to_string(),
+            TargetSpec::Network(net, _) => net.to_string(),
+            TargetSpec::Unresolved(name) => display_hostname(name),
+        }
+    }
+
+    // 國際化網域名稱混用易混淆字母時的警告
+    pub fn confusable_warning(&self) -> Option<String> {
+        match self {
+            TargetSpec::Host { name, .. } | TargetSpec::Unresolved(name) => mixed_script_warning(name),
+            TargetSpec::Network(..) => None,
+        }
+    }
+}
+
+// 無法解析的主機名稱；其他目標仍會掃描，結束時列入失敗歸因
+#[derive(Debug, Clone)]
+pub struct ResolveFailure {
+    pub name: String,
+    pub error: String,
+    // E1001 名稱無法解析，或 E2007 掃描端的解析器無法使用
+    pub code: ErrorCode,
+}
+
+// 解析以逗號分隔的目標清單 (IP、CIDR 網段、主機名稱、帶 %zone 的連結本地位址或 ndp)
+// resolve 為 false 時主機名稱不做 DNS 查詢；部分主機名稱無法解析時略過並回傳失敗清單，全部失敗時為錯誤
+// 帶 %zone 的位址與 ndp 的鄰居記入 zones
+pub async fn parse_targets(
+    spec: &str,
+    resolve: bool,
+    zones: &mut Zones,
+    dns: &DnsCache,
+) -> Result<(Vec<TargetSpec>, Vec<ResolveFailure>), Box<dyn Error>> {
+    let mut targets = Vec::new();
+    let mut failures = Vec::new();
+
+    for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        if item == "ndp" || item.starts_with("ndp%") {
+            // NDP 快取中的連結本地鄰居，各自帶上所在的介面
+            let neighbors = zones.neighbors(item.strip_prefix("ndp%")).code(ErrorCode::InvalidZone)?;
+            if neighbors.is_empty() {
+                return Err(coded(ErrorCode::NoTargets, format!("NDP 快取中沒有連結本地鄰居: {}", item)));
+            }
+            for (addr, _) in neighbors {
+                targets.push(TargetSpec::Host { name: addr.to_string(), addr: IpAddr::V6(addr) });
+            }
+        } else if let Some(addr) = zones.parse(item) {
+            let addr = IpAddr::V6(addr.code(ErrorCode::InvalidZone)?);
+            targets.push(TargetSpec::Host { name: addr.to_string(), addr });
+        } else if item.contains('/') {
+            let net: IpNet = item.parse().map_err(|_| coded(ErrorCode::InvalidTarget, format!("無效的網段: {}", item)))?;
+            targets.push(TargetSpec::Network(net.trunc(), Arc::default()));
+        } else if let Ok(addr) = item.parse::<IpAddr>() {
+            targets.push(TargetSpec::Host { name: item.to_string(), addr });
+        } else if !resolve {
+            targets.push(TargetSpec::Unresolved(to_ascii_hostname(item).code(ErrorCode::InvalidTarget)?));
+        } else {
+            // 重複的名稱使用這次執行的 DNS 快取
+            let name = to_ascii_hostname(item).code(ErrorCode::InvalidTarget)?;
+            match dns.resolve(&name).await {
+                Ok(addr) => targets.push(TargetSpec::Host { name, addr }),
+                Err(error) => failures.push(ResolveFailure { name, code: dns::failure_code(&error), error }),
+            }
+        }
+    }
+
+    match (targets.is_empty(), failures.first()) {
+        (true, Some(failure)) => Err(coded(failure.code, failure.error.clone())),
+        (true, None) => Err(coded(ErrorCode::NoTargets, "沒有指定任何目標")),
+        (false, _) => Ok((targets, failures)),
+    }
+}
+
+// 以 xn-- 開頭的標籤為 punycode 編碼的國際化標籤
+fn has_ace_label(name: &str) -> bool {
+    name.split('.').any(|label| label.get(..4).is_some_and(|prefix| prefix.eq_ignore_ascii_case("xn--")))
+}
+
+// 主機名稱轉為 DNS 查詢用的 ACE (punycode) 形式；Unicode 與 xn-- 形式都接受
+// 一般 ASCII 名稱維持原樣，只有含非 ASCII 字元或 xn-- 標籤時才做 IDNA 正規化；長度與空標籤的檢查對所有名稱都適用
+pub fn to_ascii_hostname(name: &str) -> Result<String, String> {
+    let ascii = match name.is_ascii() && !has_ace_label(name) {
+        true => name.to_string(),
+        false => {
+            let ascii = idna::domain_to_ascii(name).map_err(|_| format!("無效的國際化網域名稱: {}", name))?;
+            // xn-- 標籤必須能解回合法的 Unicode 標籤
+            if idna::domain_to_unicode(&ascii).1.is_err() {
+                return Err(format!("無效的國際化網域名稱: {}", name));
+            }
+            ascii
+        }
+    };
+    if !idna::uts46::verify_dns_length(&ascii, true) {
+        return Err(format!("網域名稱過長或含空標籤 (每個標籤最多 63 字元、全長最多 253 字元): {}", name));
+    }
+    Ok(ascii)
+}
+
+// 顯示用：國際化網域名稱同時列出 Unicode 與 ACE 形式，例如 bücher.example [xn--bcher-kva.example]
+pub fn display_hostname(name: &str) -> String {
+    match unicode_hostname(name) {
+        Some(unicode) => format!("{} [{}]", unicode, name),
+        None => name.to_string(),
+    }
+}
+
+// ACE 形式解回的 Unicode 名稱；不是國際化網域名稱時為 None
+pub fn unicode_hostname(name: &str) -> Option<String> {
+    if !has_ace_label(name) {
+        return None;
+    }
+    match idna::domain_to_unicode(name) {
+        (unicode, Ok(())) if unicode != name => Some(unicode),
+        _ => None,
+    }
+}
+
+// 容易互相仿冒的字母系統；中日韓文字與拉丁字母混用很常見，不列入
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Armenian,
+}
+
+impl Script {
+    fn of(c: char) -> Option<Script> {
+        match c {
+            'a'..='z' | 'A'..='Z' => Some(Script::Latin),
+            '\u{00c0}'..='\u{024f}' if c != '×' && c != '÷' => Some(Script::Latin),
+            '\u{0370}'..='\u{03ff}' | '\u{1f00}'..='\u{1fff}' => Some(Script::Greek),
+            '\u{0400}'..='\u{052f}' => Some(Script::Cyrillic),
+            '\u{0530}'..='\u{058f}' => Some(Script::Armenian),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Script::Latin => "拉丁",
+            Script::Greek => "希臘",
+            Script::Cyrillic => "西里爾",
+            Script::Armenian => "亞美尼亞",
+        }
+    }
+}
+
+// 同一標籤混用拉丁、希臘、西里爾或亞美尼亞字母時，可能是仿冒網域 (例如以西里爾字母 а 取代 a)
+fn mixed_script_warning(name: &str) -> Option<String> {
+    let unicode = unicode_hostname(name)?;
+    for label in unicode.split('.') {
+        let mut scripts: Vec<Script> = Vec::new();
+        for script in label.chars().filter_map(Script::of) {
+            if !scripts.contains(&script) {
+                scripts.push(script);
+            }
+        }
+        if scripts.len() > 1 {
+            let names: Vec<&str> = scripts.iter().map(|script| script.name()).collect();
+            return Some(format!(
+                "警告: {} 的標籤 '{}' 混用{}字母，可能是仿冒網域",
+                display_hostname(name),
+                label,
+                names.join("、")
+            ));
+        }
+    }
+    None
+}
+
+// 位址轉為可比較的整數；IPv4 與 IPv6 分開處理
+fn to_int(addr: IpAddr) -> u128 {
+    match addr {
+        IpAddr::V4(v4) => u128::from(u32::from(v4)),
+        IpAddr::V6(v6) => u128::from(v6),
+    }
+}
+
+// 網段 hosts() 涵蓋的位址範圍 (IPv4 /30 以上不含網路與廣播位址)
+fn host_range(net: &IpNet) -> (u128, u128) {
+    let (first, last) = (to_int(net.network()), to_int(net.broadcast()));
+    match net {
+        IpNet::V4(v4) if v4.prefix_len() < 31 => (first + 1, last - 1),
+        _ => (first, last),
+    }
+}
+
+// 排除清單：重疊與相鄰的網段合併後，依起點排序的位址範圍，以二分搜尋判斷
+#[derive(Debug, Clone, Default)]
+pub struct Exclusions {
+    v4: Vec<(u128, u128)>,
+    v6: Vec<(u128, u128)>,
+}
+
+impl Exclusions {
+    pub fn new(nets: &[IpNet]) -> Self {
+        let mut exclusions = Exclusions::default();
+        for net in IpNet::aggregate(&nets.to_vec()) {
+            let range = (to_int(net.network()), to_int(net.broadcast()));
+            match net {
+                IpNet::V4(_) => exclusions.v4.push(range),
+                IpNet::V6(_) => exclusions.v6.push(range),
+            }
+        }
+        for ranges in [&mut exclusions.v4, &mut exclusions.v6] {
+            ranges.sort_unstable();
+            // aggregate 之後仍可能有相鄰但無法合成單一網段的範圍
+            ranges.dedup_by(|next, prev| {
+                if next.0 <= prev.1.saturating_add(1) {
+                    prev.1 = prev.1.max(next.1);
+                    true
+                } else {
+                    false
+                }
+            });
+        }
+        exclusions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.v4.is_empty() && self.v6.is_empty()
+    }
+
+    // 合併後的範圍換回網段 (--record-session 記錄目標時使用)
+    pub fn nets(&self) -> Vec<IpNet> {
+        let v4 = self.v4.iter().flat_map(|&(start, end)| {
+            Ipv4Subnets::new(Ipv4Addr::from(start as u32), Ipv4Addr::from(end as u32), 0).map(IpNet::V4)
+        });
+        let v6 = self
+            .v6
+            .iter()
+            .flat_map(|&(start, end)| Ipv6Subnets::new(Ipv6Addr::from(start), Ipv6Addr::from(end), 0).map(IpNet::V6));
+        v4.chain(v6).collect()
+    }
+
+    fn ranges(&self, v6: bool) -> &[(u128, u128)] {
+        if v6 {
+            &self.v6
+        } else {
+            &self.v4
+        }
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        let value = to_int(addr);
+        let ranges = self.ranges(addr.is_ipv6());
+        // 最後一個起點不大於 value 的範圍
+        let index = ranges.partition_point(|(start, _)| *start <= value);
+        index > 0 && ranges[index - 1].1 >= value
+    }
+
+    // 與 [first, last] 重疊的位址數
+    fn overlap(&self, net: &IpNet, first: u128, last: u128) -> u128 {
+        self.ranges(matches!(net, IpNet::V6(_)))
+            .iter()
+            .filter(|(start, end)| *start <= last && *end >= first)
+            .map(|(start, end)| (*end).min(last) - (*start).max(first) + 1)
+            .sum()
+    }
+
+    // 只保留與網段重疊的範圍
+    fn within(&self, net: &IpNet) -> Exclusions {
+        let (first, last) = (to_int(net.network()), to_int(net.broadcast()));
+        let keep = |ranges: &[(u128, u128)]| -> Vec<(u128, u128)> {
+            ranges.iter().copied().filter(|(start, end)| *start <= last && *end >= first).collect()
+        };
+        match net {
+            IpNet::V4(_) => Exclusions { v4: keep(&self.v4), v6: Vec::new() },
+            IpNet::V6(_) => Exclusions { v4: Vec::new(), v6: keep(&self.v6) },
+        }
+    }
+}
+
+// 解析 --exclude 或 --exclude-file 的項目 (IP 或 CIDR)
+fn parse_exclusion(item: &str) -> Result<IpNet, String> {
+    if item.contains('/') {
+        return item.parse::<IpNet>().map(|net| net.trunc()).map_err(|_| format!("無效的排除網段: {}", item));
+    }
+    match item.parse::<IpAddr>() {
+        Ok(IpAddr::V4(addr)) => Ok(IpNet::V4(Ipv4Net::from(addr))),
+        Ok(IpAddr::V6(addr)) => Ok(IpNet::V6(Ipv6Net::from(addr))),
+        Err(_) => Err(format!("排除項目必須是 IP 或網段: {}", item)),
+    }
+}
+
+// --exclude 逗號分隔的清單，加上 --exclude-file 每行一項 (# 之後為註解)
+pub fn load_exclusions(spec: Option<&str>, file: Option<&Path>) -> Result<Vec<IpNet>, String> {
+    let mut nets = Vec::new();
+    for item in spec.unwrap_or_default().split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        nets.push(parse_exclusion(item)?);
+    }
+    if let Some(path) = file {
+        let text = fs::read_to_string(path).map_err(|e| format!("無法讀取排除清單 {}: {}", path.display(), e))?;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            for item in line.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+                nets.push(parse_exclusion(item).map_err(|e| format!("{}:{}: {}", path.display(), number + 1, e))?);
+            }
+        }
+    }
+    Ok(nets)
+}
+
+// 實際影響到目標的排除項目
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Excluded {
+    pub spec: String,
+    // 從目標中移除的主機數
+    pub hosts: u128,
+}
+
+// 套用排除清單：被排除的主機直接移除，網段則在展開時略過
+pub fn apply_exclusions(targets: Vec<TargetSpec>, nets: &[IpNet]) -> (Vec<TargetSpec>, Vec<Excluded>) {
+    if nets.is_empty() {
+        return (targets, Vec::new());
+    }
+
+    // 每個排除項目各自從原本的目標移除了多少主機
+    let excluded = nets
+        .iter()
+        .filter_map(|net| {
+            let single = Exclusions::new(std::slice::from_ref(net));
+            let hosts = targets.iter().map(|target| removed_by(target, &single)).sum();
+            (hosts > 0).then(|| Excluded { spec: net.to_string(), hosts })
+        })
+        .collect();
+
+    let exclusions = Exclusions::new(nets);
+    let kept = targets
+        .into_iter()
+        .filter_map(|target| match target {
+            TargetSpec::Host { addr, .. } if exclusions.contains(addr) => None,
+            TargetSpec::Network(net, _) => {
+                let target = TargetSpec::Network(net, Arc::new(exclusions.within(&net)));
+                (target.host_count() > 0).then_some(target)
+            }
+            target => Some(target),
+        })
+        .collect();
+    (kept, excluded)
+}
+
+fn removed_by(target: &TargetSpec, exclusions: &Exclusions) -> u128 {
+    match target {
+        TargetSpec::Host { addr, .. } => u128::from(exclusions.contains(*addr)),
+        TargetSpec::Network(net, _) => {
+            let (first, last) = host_range(net);
+            exclusions.overlap(net, first, last)
+        }
+        TargetSpec::Unresolved(_) => 0,
+    }
+}
+
+// 私有、回環、鏈路本地與 CGNAT 位址；其餘視為公網
+fn is_private(addr: IpAddr) -> bool {
+    match addr {
+        IpAddr::V4(v4) => {
+            v4.is_private() || v4.is_loopback() || v4.is_link_local() || v4.is_unspecified() || is_cgnat(v4)
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_private(IpAddr::V4(v4));
+            }
+            let segment = v6.segments()[0];
+            v6.is_loopback()
+                || v6.is_unspecified()
+                // fc00::/7 唯一本地位址、fe80::/10 鏈路本地
+                || segment & 0xfe00 == 0xfc00
+                || segment & 0xffc0 == 0xfe80
+        }
+    }
+}
+
+// 外部 IP 必須是公網單播位址；不是時回傳原因
+pub fn check_global_unicast(addr: IpAddr) -> Result<(), &'static str> {
+    let (special, documentation) = match addr {
+        IpAddr::V4(v4) => (v4.is_multicast() || v4.is_broadcast(), v4.is_documentation()),
+        IpAddr::V6(v6) => (v6.is_multicast(), v6.segments()[..2] == [0x2001, 0x0db8]),
+    };
+    match addr {
+        _ if addr.is_unspecified() => Err("未指定位址"),
+        _ if addr.is_loopback() => Err("回環位址"),
+        _ if special => Err("群播或廣播位址"),
+        _ if documentation => Err("文件範例位址"),
+        _ if is_private(addr) => Err("私有、鏈路本地或 CGNAT 位址"),
+        _ => Ok(()),
+    }
+}
+
+fn is_cgnat(addr: Ipv4Addr) -> bool {
+    let [a, b, ..] = addr.octets();
+    a == 100 && (64..128).contains(&b)
+}
+
+// 目標位址的類別，用於掃描前檢查不合理的目標
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressScope {
+    Unspecified,
+    Loopback,
+    Private,
+    Cgnat,
+    LinkLocal,
+    Multicast,
+    Broadcast,
+    Documentation,
+    // 240.0.0.0/4 等保留位址
+    Reserved,
+    Global,
+}
+
+impl AddressScope {
+    pub fn label(self) -> &'static str {
+        match self {
+            AddressScope::Unspecified => "未指定位址",
+            AddressScope::Loopback => "回環位址",
+            AddressScope::Private => "私有位址",
+            AddressScope::Cgnat => "CGNAT 位址",
+            AddressScope::LinkLocal => "鏈路本地位址",
+            AddressScope::Multicast => "群播位址",
+            AddressScope::Broadcast => "廣播位址",
+            AddressScope::Documentation => "文件範例位址",
+            AddressScope::Reserved => "保留位址",
+            AddressScope::Global => "公網位址",
+        }
+    }
+
+    // 不可能有 TCP 服務的類別：拒絕掃描的原因 (--force 仍會掃描)
+    pub fn rejection(self) -> Option<&'static str> {
+        match self {
+            AddressScope::Unspecified => Some("不是可連線的主機；主機名稱解析為此位址時通常是被 DNS 封鎖"),
+            AddressScope::Multicast => Some("不接受 TCP 連線"),
+            AddressScope::Broadcast => Some("不接受 TCP 連線"),
+            AddressScope::Documentation => Some("RFC 5737 / RFC 3849 保留給文件範例，不會有真實的主機"),
+            AddressScope::Reserved => Some("0.0.0.0/8 與 240.0.0.0/4 保留未分配，不會有真實的主機"),
+            _ => None,
+        }
+    }
+}
+
+// 依位址範圍分類；IPv4 對應的 IPv6 位址 (::ffff:a.b.c.d) 依 IPv4 位址分類
+pub fn classify(addr: IpAddr) -> AddressScope {
+    match addr {
+        IpAddr::V4(v4) => {
+            let [a, ..] = v4.octets();
+            match v4 {
+                _ if v4.is_unspecified() => AddressScope::Unspecified,
+                _ if v4.is_broadcast() => AddressScope::Broadcast,
+                _ if v4.is_loopback() => AddressScope::Loopback,
+                _ if v4.is_private() => AddressScope::Private,
+                _ if is_cgnat(v4) => AddressScope::Cgnat,
+                _ if v4.is_link_local() => AddressScope::LinkLocal,
+                _ if v4.is_multicast() => AddressScope::Multicast,
+                _ if v4.is_documentation() => AddressScope::Documentation,
+                // 0.0.0.0/8 的其他位址與 240.0.0.0/4
+                _ if a == 0 || a >= 240 => AddressScope::Reserved,
+                _ => AddressScope::Global,
+            }
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return classify(IpAddr::V4(v4));
+            }
+            let segments = v6.segments();
+            match v6 {
+                _ if v6.is_unspecified() => AddressScope::Unspecified,
+                _ if v6.is_loopback() => AddressScope::Loopback,
+                _ if v6.is_multicast() => AddressScope::Multicast,
+                _ if segments[0] & 0xffc0 == 0xfe80 => AddressScope::LinkLocal,
+                _ if segments[0] & 0xfe00 == 0xfc00 => AddressScope::Private,
+                _ if segments[..2] == [0x2001, 0x0db8] => AddressScope::Documentation,
+                _ => AddressScope::Global,
+            }
+        }
+    }
+}
+
+// 整段都是保留位址、但首尾位址類別不同的範圍 (0.0.0.0 是未指定位址、255.255.255.255 是廣播位址)
+const RESERVED_NETS: [&str; 2] = ["0.0.0.0/8", "240.0.0.0/4"];
+
+// 網段的類別：首尾位址類別相同時為該類別；落在保留範圍內時為保留位址
+// 跨類別的網段只要任一端是應拒絕的類別 (例如 0.0.0.0/0、224.0.0.0/3) 就以該端的類別拒絕，其餘不分類
+fn network_scope(net: &IpNet) -> Option<AddressScope> {
+    let (first, last) = (classify(net.network()), classify(net.broadcast()));
+    if first == last {
+        return Some(first);
+    }
+    let reserved = RESERVED_NETS.iter().any(|range| range.parse::<IpNet>().is_ok_and(|range| range.contains(net)));
+    if reserved {
+        return Some(AddressScope::Reserved);
+    }
+    [first, last].into_iter().find(|scope| scope.rejection().is_some())
+}
+
+fn target_scope(target: &TargetSpec) -> Option<AddressScope> {
+    match target {
+        TargetSpec::Host { addr, .. } => Some(classify(*addr)),
+        TargetSpec::Network(net, _) => network_scope(net),
+        TargetSpec::Unresolved(_) => None,
+    }
+}
+
+// 掃描前的目標檢查：回傳 (拒絕的目標與原因, 附在報告中的解讀提醒)
+pub fn validate_scopes(targets: &[TargetSpec], zones: &Zones) -> (Vec<String>, Vec<String>) {
+    let mut rejected = Vec::new();
+    let mut warnings = Vec::new();
+    for target in targets {
+        let Some(scope) = target_scope(target) else {
+            continue;
+        };
+        if let Some(reason) = scope.rejection() {
+            rejected.push(format!("{}: {} — {}", zones.label(target), scope.label(), reason));
+            continue;
+        }
+        let warning = match (scope, target) {
+            (AddressScope::Cgnat, _) => "CGNAT 位址 — 通常是電信業者的 NAT 設備，結果反映業者的網路而非單一主機",
+            (AddressScope::LinkLocal, TargetSpec::Host { addr: IpAddr::V6(addr), .. }) => match zones.scope_of(*addr) {
+                0 => "IPv6 連結本地位址沒有指定區域 — 請以 %介面 指定 (例如 fe80::1%eth0)，否則連線可能失敗",
+                _ => continue,
+            },
+            (AddressScope::LinkLocal, _) => "鏈路本地位址 — 只在同一網段有效，結果反映本機所在的連結",
+            _ => continue,
+        };
+        warnings.push(format!("{}: {}", zones.label(target), warning));
+    }
+    (rejected, warnings)
+}
+
+// 目標包含掃描端自己的外部 IP 時，連線要經過 NAT 迴流 (NAT loopback)，結果不代表外部看到的狀態
+pub fn external_ip_warnings(targets: &[TargetSpec], external: IpAddr) -> Vec<String> {
+    targets
+        .iter()
+        .filter(|target| target.contains(external))
+        .map(|target| match target {
+            TargetSpec::Host { .. } => format!("{}: 目標是您自己的外部 IP — 結果受 NAT 影響", target.label()),
+            _ => format!("{}: 目標包含您自己的外部 IP {} — 該主機的結果受 NAT 影響", target.label(), external),
+        })
+        .collect()
+}
+
+// 網段的首尾位址都在私有範圍內才算私有 (各私有範圍都是對齊的網段)
+fn target_is_private(target: &TargetSpec) -> bool {
+    match target {
+        TargetSpec::Host { addr, .. } => is_private(*addr),
+        TargetSpec::Network(net, _) => {
+            let network = net.network();
+            let broadcast = net.broadcast();
+            let same_range = match (network, broadcast) {
+                (IpAddr::V4(first), IpAddr::V4(last)) => private_v4_range(first) == private_v4_range(last),
+                (IpAddr::V6(first), IpAddr::V6(last)) => private_v6_range(first) == private_v6_range(last),
+                _ => false,
+            };
+            is_private(network) && is_private(broadcast) && same_range
+        }
+        // 尚未解析，無法判斷
+        TargetSpec::Unresolved(_) => true,
+    }
+}
+
+// 位址所屬私有範圍的前綴長度，用來確認網段沒有跨出範圍
+fn private_v4_range(addr: Ipv4Addr) -> Option<u8> {
+    let [a, b, ..] = addr.octets();
+    match (a, b) {
+        (10, _) | (127, _) => Some(8),
+        (172, 16..=31) => Some(12),
+        (192, 168) | (169, 254) => Some(16),
+        (100, 64..=127) => Some(10),
+        _ => None,
+    }
+}
+
+fn private_v6_range(addr: Ipv6Addr) -> Option<u16> {
+    match addr.segments()[0] {
+        s if s & 0xfe00 == 0xfc00 => Some(0xfc00),
+        s if s & 0xffc0 == 0xfe80 => Some(0xfe80),
+        _ => addr.to_ipv4_mapped().and_then(private_v4_range).map(u16::from),
+    }
+}
+
+// 非私有位址的目標
+pub fn public_labels(targets: &[TargetSpec]) -> Vec<String> {
+    targets.iter().filter(|t| !target_is_private(t)).map(TargetSpec::label).collect()
+}
+
+// 掃描前的安全檢查：主機數上限與公網位址；回傳所有違反的項目
+pub fn guardrail_violations(targets: &[TargetSpec], max_hosts: u64, allow_large: bool, allow_public: bool) -> Vec<String> {
+    let mut violations = Vec::new();
+    let total: u128 = targets.iter().map(TargetSpec::host_count).sum();
+    if !allow_large && total > u128::from(max_hosts) {
+        violations.push(format!("目標共 {} 台主機，超過上限 {} 台 (確認無誤請加上 --allow-large)", total, max_hosts));
+    }
+    if !allow_public {
+        let public = public_labels(targets);
+        if !public.is_empty() {
+            violations.push(format!("目標包含非私有位址: {} (確認已獲授權請加上 --allow-public)", public.join(", ")));
+        }
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idn_round_trips() {
+        for (unicode, ace) in [
+            ("bücher.example", "xn--bcher-kva.example"),
+            ("例子.測試", "xn--fsqu00a.xn--g6w251d"),
+            ("пример.испытание", "xn--e1afmkfd.xn--80akhbyknj4f"),
+            ("münchen.de", "xn--mnchen-3ya.de"),
+            ("ドメイン.テスト", "xn--eckwd4c7c.xn--zckzah"),
+        ] {
+            assert_eq!(to_ascii_hostname(unicode).unwrap(), ace, "{}", unicode);
+            // ACE 形式與大小寫不同的輸入得到相同結果
+            assert_eq!(to_ascii_hostname(ace).unwrap(), ace);
+            assert_eq!(to_ascii_hostname(&ace.to_uppercase()).unwrap(), ace);
+            assert_eq!(unicode_hostname(ace).as_deref(), Some(unicode));
+            assert_eq!(display_hostname(ace), format!("{} [{}]", unicode, ace));
+        }
+    }
+
+    #[test]
+    fn ascii_names_stay_as_given() {
+        for name in ["example.com", "Example.COM", "localhost", "host-1.internal", "example.com."] {
+            assert_eq!(to_ascii_hostname(name).unwrap(), name);
+            assert_eq!(unicode_hostname(name), None);
+            assert_eq!(display_hostname(name), name);
+        }
+    }
+
+    #[test]
+    fn invalid_labels_are_rejected() {
+        let long_label = "a".repeat(64);
+        let long_name = vec!["a".repeat(63); 4].join(".");
+        for name in [
+            // 長度與空標籤的檢查也適用於純 ASCII 名稱
+            long_label.as_str(),
+            &format!("{}.example", long_label),
+            long_name.as_str(),
+            "a..example",
+            ".example",
+            "",
+            // 無法解碼的 punycode
+            "xn--.example",
+            "xn--a.example",
+            &format!("{}ü.example", "a".repeat(60)),
+        ] {
+            assert!(to_ascii_hostname(name).is_err(), "{:?}", name);
+        }
+        assert!(to_ascii_hostname(&"a".repeat(63)).is_ok());
+        assert!(to_ascii_hostname(&vec!["a".repeat(63); 3].join(".")).is_ok());
+    }
+
+    #[test]
+    fn mixed_scripts_are_flagged() {
+        // 以西里爾字母 а 取代拉丁字母 a
+        let spoof = to_ascii_hostname("exаmple.com").unwrap();
+        let warning = mixed_script_warning(&spoof).unwrap();
+        assert!(warning.contains("拉丁、西里爾"), "{}", warning);
+        for name in ["bücher.example", "пример.испытание", "例子.測試", "example.com"] {
+            assert_eq!(mixed_script_warning(&to_ascii_hostname(name).unwrap()), None, "{}", name);
+        }
+    }
+
+    fn scope(addr: &str) -> AddressScope {
+        classify(addr.parse().unwrap())
+    }
+
+    fn net_scope(net: &str) -> Option<AddressScope> {
+        network_scope(&net.parse().unwrap())
+    }
+
+    #[test]
+    fn classifies_ipv4_ranges_at_their_boundaries() {
+        use AddressScope::*;
+        let cases = [
+            ("0.0.0.0", Unspecified),
+            ("0.0.0.1", Reserved),
+            ("0.255.255.255", Reserved),
+            ("1.0.0.0", Global),
+            ("9.255.255.255", Global),
+            ("10.0.0.0", Private),
+            ("10.255.255.255", Private),
+            ("11.0.0.0", Global),
+            ("100.63.255.255", Global),
+            ("100.64.0.0", Cgnat),
+            ("100.127.255.255", Cgnat),
+            ("100.128.0.0", Global),
+            ("126.255.255.255", Global),
+            ("127.0.0.0", Loopback),
+            ("127.255.255.255", Loopback),
+            ("128.0.0.0", Global),
+            ("169.253.255.255", Global),
+            ("169.254.0.0", LinkLocal),
+            ("169.254.255.255", LinkLocal),
+            ("169.255.0.0", Global),
+            ("172.15.255.255", Global),
+            ("172.16.0.0", Private),
+            ("172.31.255.255", Private),
+            ("172.32.0.0", Global),
+            ("192.0.1.255", Global),
+            ("192.0.2.0", Documentation),
+            ("192.0.2.255", Documentation),
+            ("192.0.3.0", Global),
+            ("192.167.255.255", Global),
+            ("192.168.0.0", Private),
+            ("192.168.255.255", Private),
+            ("192.169.0.0", Global),
+            ("198.51.100.7", Documentation),
+            ("198.51.101.0", Global),
+            ("203.0.112.255", Global),
+            ("203.0.113.0", Documentation),
+            ("203.0.113.255", Documentation),
+            ("223.255.255.255", Global),
+            ("224.0.0.0", Multicast),
+            ("239.255.255.255", Multicast),
+            ("240.0.0.0", Reserved),
+            ("255.255.255.254", Reserved),
+            ("255.255.255.255", Broadcast),
+            ("8.8.8.8", Global),
+        ];
+        for (addr, expected) in cases {
+            assert_eq!(scope(addr), expected, "{}", addr);
+        }
+    }
+
+    #[test]
+    fn classifies_ipv6_ranges_at_their_boundaries() {
+        use AddressScope::*;
+        let cases = [
+            ("::", Unspecified),
+            ("::1", Loopback),
+            ("::2", Global),
+            ("fbff:ffff::", Global),
+            ("fc00::", Private),
+            ("fdff:ffff:ffff:ffff:ffff:ffff:ffff:ffff", Private),
+            ("fe00::", Global),
+            ("fe7f:ffff::", Global),
+            ("fe80::", LinkLocal),
+            ("febf:ffff:ffff:ffff:ffff:ffff:ffff:ffff", LinkLocal),
+            ("fec0::", Global),
+            ("ff00::", Multicast),
+            ("ff02::1", Multicast),
+            ("2001:db7:ffff::", Global),
+            ("2001:db8::", Documentation),
+            ("2001:db8:ffff:ffff:ffff:ffff:ffff:ffff", Documentation),
+            ("2001:db9::", Global),
+            ("2606:4700::1111", Global),
+        ];
+        for (addr, expected) in cases {
+            assert_eq!(scope(addr), expected, "{}", addr);
+        }
+    }
+
+    #[test]
+    fn mapped_addresses_follow_ipv4() {
+        for addr in ["0.0.0.0", "10.1.2.3", "100.64.1.1", "127.0.0.1", "192.0.2.1", "224.0.0.1", "240.0.0.1", "255.255.255.255", "8.8.8.8"] {
+            let v4: Ipv4Addr = addr.parse().unwrap();
+            assert_eq!(classify(IpAddr::V6(v4.to_ipv6_mapped())), scope(addr), "{}", addr);
+        }
+    }
+
+    // 每個類別是否拒絕都是明確決定的，新增類別時必須在這裡表態
+    #[test]
+    fn rejections_are_explicit_per_scope() {
+        use AddressScope::*;
+        for (scope, rejected) in [
+            (Unspecified, true),
+            (Loopback, false),
+            (Private, false),
+            (Cgnat, false),
+            (LinkLocal, false),
+            (Multicast, true),
+            (Broadcast, true),
+            (Documentation, true),
+            (Reserved, true),
+            (Global, false),
+        ] {
+            assert_eq!(scope.rejection().is_some(), rejected, "{:?}", scope);
+        }
+    }
+
+    #[test]
+    fn networks_inside_rejected_ranges_are_rejected() {
+        use AddressScope::*;
+        for (net, expected) in [
+            ("0.0.0.0/8", Reserved),
+            ("0.0.0.0/9", Reserved),
+            ("0.128.0.0/9", Reserved),
+            ("240.0.0.0/4", Reserved),
+            ("248.0.0.0/5", Reserved),
+            ("224.0.0.0/4", Multicast),
+            ("192.0.2.0/24", Documentation),
+            ("2001:db8::/32", Documentation),
+            ("ff00::/8", Multicast),
+        ] {
+            assert_eq!(net_scope(net), Some(expected), "{}", net);
+            assert!(expected.rejection().is_some());
+        }
+    }
+
+    #[test]
+    fn networks_with_a_rejected_endpoint_are_rejected() {
+        for net in ["0.0.0.0/0", "0.0.0.0/7", "224.0.0.0/3", "192.0.0.0/2", "::/0", "::/127"] {
+            let scope = net_scope(net);
+            assert!(scope.is_some_and(|scope| scope.rejection().is_some()), "{} {:?}", net, scope);
+        }
+    }
+
+    #[test]
+    fn ordinary_networks_are_classified_or_left_alone() {
+        use AddressScope::*;
+        assert_eq!(net_scope("10.0.0.0/8"), Some(Private));
+        assert_eq!(net_scope("100.64.0.0/10"), Some(Cgnat));
+        assert_eq!(net_scope("169.254.0.0/16"), Some(LinkLocal));
+        assert_eq!(net_scope("8.8.8.0/24"), Some(Global));
+        assert_eq!(net_scope("8.8.8.8/32"), Some(Global));
+        // 公網與回環之間：沒有應拒絕的一端，不分類
+        assert_eq!(net_scope("96.0.0.0/3"), None);
+        assert_eq!(net_scope("192.0.0.0/16"), Some(Global));
+    }
+
+    #[test]
+    fn validate_scopes_rejects_and_warns() {
+        let network = |net: &str| TargetSpec::Network(net.parse().unwrap(), Arc::new(Exclusions::new(&[])));
+        let host = |addr: &str| TargetSpec::Host { name: addr.to_string(), addr: addr.parse().unwrap() };
+        let targets = [network("0.0.0.0/9"), network("240.0.0.0/4"), host("192.0.2.1"), host("100.64.0.1"), host("10.0.0.1"), host("fe80::1")];
+        let (rejected, warnings) = validate_scopes(&targets, &Zones::default());
+        assert_eq!(rejected.len(), 3, "{:?}", rejected);
+        assert!(rejected[0].starts_with("0.0.0.0/9: 保留位址"));
+        assert!(rejected[2].starts_with("192.0.2.1: 文件範例位址"));
+        assert_eq!(warnings.len(), 2, "{:?}", warnings);
+        assert!(warnings[0].starts_with("100.64.0.1: CGNAT"));
+        assert!(warnings[1].contains("沒有指定區域"));
+    }
+
+    fn nets(items: &[&str]) -> Vec<IpNet> {
+        items.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
+    fn host(addr: &str) -> TargetSpec {
+        TargetSpec::Host { name: addr.to_string(), addr: addr.parse().unwrap() }
+    }
+
+    fn network(net: &str) -> TargetSpec {
+        TargetSpec::Network(net.parse().unwrap(), Arc::default())
+    }
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn overlapping_and_adjacent_exclusions_merge() {
+        // 重疊、包含與相鄰 (無法合成單一網段) 的範圍都合併成一段
+        let exclusions = Exclusions::new(&nets(&[
+            "10.0.0.0/25",
+            "10.0.0.64/26",
+            "10.0.0.128/26",
+            "10.0.0.192/27",
+            "10.0.1.5/32",
+            "10.0.0.100/32",
+        ]));
+        assert_eq!(exclusions.v4, vec![(to_int(ip("10.0.0.0")), to_int(ip("10.0.0.223"))), (to_int(ip("10.0.1.5")), to_int(ip("10.0.1.5")))]);
+        assert!(exclusions.v6.is_empty());
+
+        for (addr, expected) in [
+            ("9.255.255.255", false),
+            ("10.0.0.0", true),
+            ("10.0.0.223", true),
+            ("10.0.0.224", false),
+            ("10.0.1.4", false),
+            ("10.0.1.5", true),
+            ("10.0.1.6", false),
+        ] {
+            assert_eq!(exclusions.contains(ip(addr)), expected, "{}", addr);
+        }
+    }
+
+    #[test]
+    fn exclusion_families_stay_separate() {
+        let exclusions = Exclusions::new(&nets(&["2001:db8::/127", "2001:db8::2/127", "192.0.2.0/24"]));
+        assert_eq!(exclusions.v6.len(), 1);
+        assert!(exclusions.contains(ip("2001:db8::3")));
+        assert!(!exclusions.contains(ip("2001:db8::4")));
+        assert!(exclusions.contains(ip("192.0.2.255")));
+        // IPv4 映射位址不會被 IPv4 範圍誤判
+        assert!(!exclusions.contains(ip("::ffff:192.0.2.1")));
+        assert!(Exclusions::new(&[]).is_empty());
+        assert!(!Exclusions::new(&[]).contains(ip("192.0.2.1")));
+    }
+
+    #[test]
+    fn exclusions_load_from_spec_and_file() {
+        let file = crate::testutil::TempPath::with("exclude.txt", "# 閘道\n192.0.2.1\n\n192.0.2.128/25 # 測試機, 2001:db8::1\n10.1.2.3/8, \n");
+        let loaded = load_exclusions(Some(" 198.51.100.7 ,,203.0.113.0/30"), Some(file.path())).unwrap();
+        assert_eq!(
+            loaded.iter().map(IpNet::to_string).collect::<Vec<_>>(),
+            // CIDR 會截到網路位址
+            vec!["198.51.100.7/32", "203.0.113.0/30", "192.0.2.1/32", "192.0.2.128/25", "10.0.0.0/8"]
+        );
+        assert!(load_exclusions(None, None).unwrap().is_empty());
+
+        let bad = crate::testutil::TempPath::with("exclude-bad.txt", "192.0.2.1\n# ok\nexample.com\n");
+        let err = load_exclusions(None, Some(bad.path())).unwrap_err();
+        assert_eq!(err, format!("{}:3: 排除項目必須是 IP 或網段: example.com", bad.path().display()));
+        assert_eq!(load_exclusions(Some("10.0.0.0/33"), None).unwrap_err(), "無效的排除網段: 10.0.0.0/33");
+        assert!(load_exclusions(None, Some(Path::new("/nonexistent/exclude.txt"))).unwrap_err().starts_with("無法讀取排除清單"));
+    }
+
+    #[test]
+    fn exclusions_remove_hosts_and_shrink_networks() {
+        let targets = vec![host("192.0.2.10"), host("198.51.100.1"), network("192.0.2.0/24"), network("203.0.113.0/30")];
+        let excludes = nets(&["192.0.2.0/28", "192.0.2.8/29", "192.0.2.255/32", "203.0.113.0/30"]);
+        let (kept, excluded) = apply_exclusions(targets, &excludes);
+
+        // 主機與 /30 整個被排除；/24 去掉 .1-.15 (網路與廣播位址本來就不掃描)
+        let labels: Vec<String> = kept.iter().map(TargetSpec::label).collect();
+        assert_eq!(labels, vec!["198.51.100.1", "192.0.2.0/24"]);
+        assert_eq!(kept[1].host_count(), 254 - 15);
+        let addrs: Vec<IpAddr> = kept[1].addrs().collect();
+        assert_eq!(addrs.len(), 239);
+        assert_eq!(addrs[0], ip("192.0.2.16"));
+        assert_eq!(addrs.last(), Some(&ip("192.0.2.254")));
+        assert!(!kept[1].contains(ip("192.0.2.3")));
+        assert!(kept[1].contains(ip("192.0.2.16")));
+
+        // 每個項目各自計算，重疊的部分會重複計入
+        let counts: Vec<(String, u128)> = excluded.iter().map(|e| (e.spec.clone(), e.hosts)).collect();
+        assert_eq!(
+            counts,
+            vec![("192.0.2.0/28".to_string(), 15 + 1), ("192.0.2.8/29".to_string(), 8 + 1), ("203.0.113.0/30".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn no_exclusions_leave_targets_untouched() {
+        let (kept, excluded) = apply_exclusions(vec![network("192.0.2.0/30"), host("192.0.2.9")], &[]);
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[0].host_count(), 2);
+        assert!(excluded.is_empty());
+    }
+
+    #[test]
+    fn guardrails_cap_hosts_and_public_targets() {
+        let private = vec![network("10.0.0.0/24"), host("192.168.1.1")];
+        assert!(guardrail_violations(&private, 256, false, false).is_empty());
+
+        let large = vec![network("10.0.0.0/23")];
+        let violations = guardrail_violations(&large, 256, false, false);
+        assert_eq!(violations, vec!["目標共 510 台主機，超過上限 256 台 (確認無誤請加上 --allow-large)"]);
+        assert!(guardrail_violations(&large, 256, true, false).is_empty());
+        assert!(guardrail_violations(&large, 510, false, false).is_empty());
+
+        // 排除後的主機數才算數
+        let (shrunk, _) = apply_exclusions(large, &nets(&["10.0.1.0/24"]));
+        assert!(guardrail_violations(&shrunk, 256, false, false).is_empty());
+
+        let public = vec![host("8.8.8.8"), network("10.0.0.0/30"), network("2001:db8::/126")];
+        let violations = guardrail_violations(&public, 256, false, false);
+        assert_eq!(violations, vec!["目標包含非私有位址: 8.8.8.8, 2001:db8::/126 (確認已獲授權請加上 --allow-public)"]);
+        assert!(guardrail_violations(&public, 256, false, true).is_empty());
+
+        let both = guardrail_violations(&[network("8.8.8.0/23")], 256, false, false);
+        assert_eq!(both.len(), 2);
+    }
+
+    #[test]
+    fn networks_straddling_private_ranges_count_as_public() {
+        for (net, private) in [
+            ("172.16.0.0/12", true),
+            ("172.0.0.0/8", false),
+            ("192.168.0.0/16", true),
+            ("192.160.0.0/12", false),
+            ("fd00::/8", true),
+            ("fc00::/6", false),
+        ] {
+            assert_eq!(target_is_private(&network(net)), private, "{}", net);
+        }
+        assert!(target_is_private(&TargetSpec::Unresolved("example.com".to_string())));
+        assert_eq!(public_labels(&[network("172.0.0.0/8"), network("10.0.0.0/8")]), vec!["172.0.0.0/8"]);
+    }
+}
@@ -0,0 +1,321 @@
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::BuildHasher;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use colored::*;
+use schemars::JsonSchema;
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use crate::{PortInfo, ScanResult};
+use crate::context::ScanContext;
+
+// 掃描結果的開放比例達此值才抽樣 (一般主機很少有一半以上的端口開放)
+const TRIGGER_RATE: f64 = 0.5;
+
+// 掃描的端口少於此數時比例沒有意義，不抽樣
+const MIN_PORTS: usize = 10;
+
+// 抽樣的少見高端口範圍與數量
+const SAMPLE_FIRST: u16 = 40000;
+const SAMPLE_LAST: u16 = 65000;
+const SAMPLE_SIZE: usize = 8;
+
+// 抽樣端口的開放比例達此值才可能判定
+const SAMPLE_OPEN_RATE: f64 = 0.75;
+
+// 信心分數達此值時判定為可疑
+const FLAG_CONFIDENCE: f64 = 0.6;
+
+// 延遲差距在此值或中位數的一半以內視為相同
+const SIMILAR_LATENCY: Duration = Duration::from_millis(5);
+
+// 讀取回應的期限與長度
+const READ_LIMIT: Duration = Duration::from_secs(1);
+const READ_SIZE: usize = 512;
+
+// 送往抽樣端口的內容；攔截設備通常對任何端口回覆同樣的內容
+const PROBE: &[u8] = b"GET / HTTP/1.0\r\n\r\n";
+
+// 單個抽樣端口的探測結果
+#[derive(Debug, Clone, Default)]
+pub struct Sample {
+    pub port: u16,
+    pub open: bool,
+    pub latency: Option<Duration>,
+    // 去除數字後的回應 (日期等欄位每次不同)；沒有回應時為空字串
+    pub response: String,
+}
+
+// 掃描後的 honeypot / tarpit 判斷
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct TarpitAssessment {
+    // 是否判定為可疑；為 true 時整個主機的結果不可信
+    pub suspected: bool,
+    // 0 到 1 的信心分數
+    pub confidence: f64,
+    // 掃描結果中出站可連線的比例
+    pub scan_open_rate: f64,
+    // 抽樣的端口與其中可連線的數量
+    pub sampled: Vec<u16>,
+    pub sample_open: usize,
+    // 可連線的抽樣端口中，回應與最常見者相同的比例
+    pub identical_responses: f64,
+    // 可連線的抽樣端口最大與最小延遲的差距
+    pub latency_spread_ms: f64,
+}
+
+// 掃描結果的開放比例超過門檻時才值得抽樣
+pub fn should_sample(results: &HashMap<PortInfo, ScanResult>) -> Option<f64> {
+    if results.len() < MIN_PORTS {
+        return None;
+    }
+    let open = results.values().filter(|r| r.outbound).count();
+    let rate = open as f64 / results.len() as f64;
+    (rate >= TRIGGER_RATE).then_some(rate)
+}
+
+// 隨機挑選沒有掃描過的少見高端口
+pub fn sample_ports(scanned: &HashSet<u16>, count: usize) -> Vec<u16> {
+    let state = RandomState::new();
+    let span = u64::from(SAMPLE_LAST - SAMPLE_FIRST) + 1;
+    let mut ports = Vec::new();
+    let mut seed = 0u64;
+    while ports.len() < count && seed < span {
+        let port = SAMPLE_FIRST + (state.hash_one(seed) % span) as u16;
+        seed += 1;
+        if !scanned.contains(&port) && !ports.contains(&port) {
+            ports.push(port);
+        }
+    }
+    ports
+}
+
+// 比對數字以外的內容
+fn normalize(response: &[u8]) -> String {
+    String::from_utf8_lossy(response).chars().filter(|c| !c.is_ascii_digit()).collect()
+}
+
+async fn probe(addr: SocketAddr, limit: Duration) -> Sample {
+    let port = addr.port();
+    let started = Instant::now();
+    let Ok(Ok(mut stream)) = timeout(limit, TcpStream::connect(addr)).await else {
+        return Sample { port, ..Default::default() };
+    };
+    let latency = started.elapsed();
+    let mut response = vec![0u8; READ_SIZE];
+    let read = match stream.write_all(PROBE).await {
+        Ok(()) => timeout(READ_LIMIT, stream.read(&mut response)).await.ok().and_then(Result::ok).unwrap_or(0),
+        Err(_) => 0,
+    };
+    Sample {
+        port,
+        open: true,
+        latency: Some(latency),
+        response: normalize(&response[..read]),
+    }
+}
+
+// 依抽樣結果計算信心分數：抽樣端口的開放比例，乘上回應與延遲的一致程度
+pub fn assess(scan_open_rate: f64, samples: &[Sample]) -> TarpitAssessment {
+    let open: Vec<&Sample> = samples.iter().filter(|s| s.open).collect();
+    let open_rate = match samples.len() {
+        0 => 0.0,
+        n => open.len() as f64 / n as f64,
+    };
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for sample in &open {
+        *counts.entry(sample.response.as_str()).or_default() += 1;
+    }
+    let identical = match open.len() {
+        0 => 0.0,
+        n => counts.values().copied().max().unwrap_or(0) as f64 / n as f64,
+    };
+
+    let mut latencies: Vec<Duration> = open.iter().filter_map(|s| s.latency).collect();
+    latencies.sort_unstable();
+    let spread = match (latencies.first(), latencies.last()) {
+        (Some(min), Some(max)) => *max - *min,
+        _ => Duration::ZERO,
+    };
+    let median = latencies.get(latencies.len() / 2).copied().unwrap_or_default();
+    let similar_latency = match latencies.is_empty() {
+        true => 0.0,
+        false if spread <= SIMILAR_LATENCY.max(median / 2) => 1.0,
+        false => 0.0,
+    };
+
+    let confidence = open_rate * (0.4 + 0.4 * identical + 0.2 * similar_latency);
+    TarpitAssessment {
+        suspected: open_rate >= SAMPLE_OPEN_RATE && confidence >= FLAG_CONFIDENCE,
+        confidence: (confidence * 100.0).round() / 100.0,
+        scan_open_rate: (scan_open_rate * 100.0).round() / 100.0,
+        sampled: samples.iter().map(|s| s.port).collect(),
+        sample_open: open.len(),
+        identical_responses: (identical * 100.0).round() / 100.0,
+        latency_spread_ms: (spread.as_secs_f64() * 100_000.0).round() / 100.0,
+    }
+}
+
+// 對開放比例異常高的主機抽樣少見高端口；其他主機不會產生額外的連線
+// 經由代理時直接連線的結果不代表掃描路徑，呼叫端應略過
+pub async fn detect(
+    results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>,
+    context: &ScanContext,
+    limit: Duration,
+) -> BTreeMap<IpAddr, TarpitAssessment> {
+    let mut assessments = BTreeMap::new();
+    for (host, host_results) in results {
+        let Some(scan_open_rate) = should_sample(host_results) else {
+            continue;
+        };
+        let scanned: HashSet<u16> = host_results.keys().map(|p| p.port).collect();
+        let handles: Vec<_> = sample_ports(&scanned, SAMPLE_SIZE)
+            .into_iter()
+            .map(|port| tokio::spawn(probe(context.socket_addr(*host, port), limit)))
+            .collect();
+        let mut samples = Vec::new();
+        for handle in handles {
+            if let Ok(sample) = handle.await {
+                samples.push(sample);
+            }
+        }
+        assessments.insert(*host, assess(scan_open_rate, &samples));
+    }
+    assessments
+}
+
+// 報告開頭的醒目警告，只列出判定為可疑的主機
+pub fn display_warnings(assessments: &BTreeMap<IpAddr, TarpitAssessment>) {
+    for (host, assessment) in assessments.iter().filter(|(_, a)| a.suspected) {
+        println!(
+            "\n{}",
+            format!("⚠ {} 目標可能為 honeypot/攔截設備，結果不可信 (信心 {:.0}%)", host, assessment.confidence * 100.0)
+                .white()
+                .on_red()
+                .bold()
+        );
+        println!(
+            "{}",
+            format!(
+                "掃描的端口有 {:.0}% 開放；隨機抽樣 {} 個少見高端口有 {} 個開放，{:.0}% 回應相同，延遲差距 {:.1}ms",
+                assessment.scan_open_rate * 100.0,
+                assessment.sampled.len(),
+                assessment.sample_open,
+                assessment.identical_responses * 100.0,
+                assessment.latency_spread_ms
+            )
+            .red()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::scan_result;
+
+    // 端口 1..=count，前 open 個可連線
+    fn results(count: u16, open: u16) -> HashMap<PortInfo, ScanResult> {
+        (1..=count).map(|port| (PortInfo::new(port, "Test", "Test"), scan_result(port <= open))).collect()
+    }
+
+    fn sample(port: u16, latency_ms: Option<u64>, response: &str) -> Sample {
+        Sample {
+            port,
+            open: latency_ms.is_some(),
+            latency: latency_ms.map(Duration::from_millis),
+            response: response.to_string(),
+        }
+    }
+
+    // 每個端口都回覆同樣內容、延遲幾乎相同的攔截設備
+    fn intercepted() -> Vec<Sample> {
+        (0..SAMPLE_SIZE as u16).map(|i| sample(40000 + i, Some(20 + u64::from(i % 2)), "HTTP/. OK")).collect()
+    }
+
+    #[test]
+    fn only_mostly_open_hosts_are_sampled() {
+        assert_eq!(should_sample(&results(100, 100)), Some(1.0));
+        assert_eq!(should_sample(&results(20, 10)), Some(0.5));
+        // 一般主機：少數端口開放
+        assert_eq!(should_sample(&results(100, 3)), None);
+        assert_eq!(should_sample(&results(20, 9)), None);
+        // 端口太少時不判斷
+        assert_eq!(should_sample(&results(MIN_PORTS as u16 - 1, 9)), None);
+    }
+
+    #[test]
+    fn samples_avoid_scanned_ports() {
+        let scanned: HashSet<u16> = (SAMPLE_FIRST..SAMPLE_FIRST + 20000).collect();
+        let ports = sample_ports(&scanned, SAMPLE_SIZE);
+        assert_eq!(ports.len(), SAMPLE_SIZE);
+        assert_eq!(ports.iter().collect::<HashSet<_>>().len(), SAMPLE_SIZE);
+        assert!(ports.iter().all(|p| (SAMPLE_FIRST..=SAMPLE_LAST).contains(p) && !scanned.contains(p)), "{:?}", ports);
+
+        // 可用的端口不足時不會重複或挑到掃描過的端口
+        let scanned: HashSet<u16> = (SAMPLE_FIRST..=SAMPLE_LAST).filter(|p| *p != 50000).collect();
+        assert!([vec![], vec![50000]].contains(&sample_ports(&scanned, SAMPLE_SIZE)));
+        let scanned: HashSet<u16> = (SAMPLE_FIRST..=SAMPLE_LAST).collect();
+        assert!(sample_ports(&scanned, SAMPLE_SIZE).is_empty());
+    }
+
+    #[test]
+    fn responses_ignore_digits() {
+        assert_eq!(normalize(b"HTTP/1.1 200 OK\r\nDate: 12:34:56"), "HTTP/.  OK\r\nDate: ::");
+        assert_eq!(normalize(b""), "");
+    }
+
+    #[test]
+    fn an_all_open_interceptor_is_flagged() {
+        let assessment = assess(1.0, &intercepted());
+        assert!(assessment.suspected);
+        assert_eq!(assessment.confidence, 1.0);
+        assert_eq!((assessment.sample_open, assessment.identical_responses), (SAMPLE_SIZE, 1.0));
+        assert_eq!(assessment.latency_spread_ms, 1.0);
+        assert_eq!(assessment.sampled.len(), SAMPLE_SIZE);
+
+        // 沒有回應內容 (只接受連線) 仍算相同
+        let silent: Vec<Sample> = (0..8).map(|i| sample(40000 + i, Some(3), "")).collect();
+        assert!(assess(0.9, &silent).suspected);
+    }
+
+    #[test]
+    fn a_real_host_with_varied_services_is_not_flagged() {
+        // 抽樣端口大多關閉
+        let mut samples: Vec<Sample> = (0..8).map(|i| sample(40000 + i, None, "")).collect();
+        samples[0] = sample(40000, Some(12), "SSH-.-OpenSSH");
+        let assessment = assess(0.6, &samples);
+        assert!(!assessment.suspected);
+        assert_eq!(assessment.sample_open, 1);
+        assert_eq!(assessment.confidence, 0.13);
+
+        // 全部開放但回應與延遲都不同：分數低於門檻
+        let varied: Vec<Sample> = (0..8u16)
+            .map(|i| sample(40000 + i, Some(5 + 40 * u64::from(i)), &format!("service-{}", char::from(b'a' + i as u8))))
+            .collect();
+        let assessment = assess(1.0, &varied);
+        assert!(!assessment.suspected);
+        assert_eq!(assessment.identical_responses, 0.13);
+        assert_eq!(assessment.latency_spread_ms, 280.0);
+        assert!(assessment.confidence < FLAG_CONFIDENCE);
+    }
+
+    #[test]
+    fn partial_matches_stay_below_the_threshold() {
+        // 開放比例不足時即使回應相同也不判定
+        let mut samples = intercepted();
+        for s in samples.iter_mut().skip(5) {
+            *s = sample(s.port, None, "");
+        }
+        let assessment = assess(1.0, &samples);
+        assert_eq!(assessment.sample_open, 5);
+        assert!(!assessment.suspected, "{:?}", assessment);
+
+        assert!(!assess(1.0, &[]).suspected);
+        assert_eq!(assess(1.0, &[]).confidence, 0.0);
+    }
+}
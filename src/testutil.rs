@@ -0,0 +1,111 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use indicatif::ProgressBar;
+use tokio::sync::mpsc;
+use crate::prober::Prober;
+use crate::scanner::{self, ScanPlan, ScanRecord};
+use crate::selftest;
+use crate::targets::TargetSpec;
+use crate::timeouts::Timeouts;
+use crate::{PortInfo, ScanResult};
+
+// 暫存檔名；每次呼叫不同，同時執行的測試不會互相干擾
+fn unique(name: &str) -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!("r1-{}-{}-{}", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed), name))
+}
+
+// 測試用的暫存檔路徑，離開作用域時刪除
+pub struct TempPath(PathBuf);
+
+impl TempPath {
+    pub fn new(name: &str) -> Self {
+        TempPath(unique(name))
+    }
+
+    // 建立並寫入內容
+    pub fn with(name: &str, text: &str) -> Self {
+        let path = TempPath::new(name);
+        fs::write(&path.0, text).unwrap();
+        path
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+// 測試用的暫存目錄 (例如 SQLite 資料庫與它的 -wal、-shm 檔)，離開作用域時整個刪除
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = unique(name);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+// 只有連線結果的掃描結果，其餘欄位為預設值
+pub fn scan_result(outbound: bool) -> ScanResult {
+    serde_json::from_value(serde_json::json!({ "inbound": false, "outbound": outbound })).unwrap()
+}
+
+// 文件用的測試位址 192.0.2.n (RFC 5737)
+pub fn host(n: u8) -> IpAddr {
+    IpAddr::V4(Ipv4Addr::new(192, 0, 2, n))
+}
+
+// 對假網路掃描的計劃：每個端口逾時 1 秒，其餘與自我測試相同
+pub fn scripted_plan(hosts: &[IpAddr], ports: &[u16], concurrency: usize, prober: Arc<dyn Prober>) -> ScanPlan {
+    let ports = ports.iter().map(|&port| PortInfo::new(port, "Test", "Test")).collect();
+    ScanPlan {
+        targets: hosts.iter().map(|&addr| TargetSpec::Host { name: addr.to_string(), addr }).collect(),
+        prober,
+        ..selftest::localhost_plan(ports, concurrency, Timeouts::fixed(Duration::from_secs(1)), Vec::new())
+    }
+}
+
+// 執行掃描並依送出順序收集紀錄
+pub async fn scan(plan: &ScanPlan) -> Vec<ScanRecord> {
+    let (tx, mut rx) = mpsc::channel(16);
+    let collect = tokio::spawn(async move {
+        let mut records = Vec::new();
+        while let Some(record) = rx.recv().await {
+            records.push(record);
+        }
+        records
+    });
+    scanner::run_scan(plan, tx, &ProgressBar::hidden()).await;
+    collect.await.unwrap()
+}
+
+// 與掃描結果相同的形式：主機 -> 端口 -> 結果
+pub fn by_host(records: Vec<ScanRecord>) -> BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>> {
+    let mut results: BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>> = BTreeMap::new();
+    for record in records {
+        results.entry(record.host).or_default().insert(record.port, record.result);
+    }
+    results
+}
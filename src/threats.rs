@@ -0,0 +1,289 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::recommend::{Recommendation, Severity};
+use crate::{PortInfo, ScanResult};
+
+// 設定檔的 [[threats]]：與惡意程式或後門相關的端口，覆蓋相同端口的內建項目
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThreatEntry {
+    pub port: u16,
+    pub name: String,
+    pub description: String,
+    #[serde(default = "default_severity")]
+    pub severity: Severity,
+}
+
+fn default_severity() -> Severity {
+    Severity::High
+}
+
+impl ThreatEntry {
+    fn builtin(port: u16, name: &str, severity: Severity, description: &str) -> Self {
+        ThreatEntry { port, name: name.to_string(), description: description.to_string(), severity }
+    }
+}
+
+// 內建的可疑端口；這些端口也可能被正常服務使用，只作為檢查的提示
+fn builtin_entries() -> Vec<ThreatEntry> {
+    vec![
+        ThreatEntry::builtin(1243, "SubSeven", Severity::High, "早期 SubSeven 木馬的預設端口"),
+        ThreatEntry::builtin(1524, "ingreslock", Severity::Medium, "入侵後常被植入 root shell 的端口"),
+        ThreatEntry::builtin(2745, "Bagle", Severity::High, "Bagle 蠕蟲開啟的後門"),
+        ThreatEntry::builtin(3127, "MyDoom", Severity::High, "MyDoom 蠕蟲開啟的後門"),
+        ThreatEntry::builtin(4444, "Metasploit / Blaster", Severity::Medium, "Metasploit 反向連線的預設端口，也是 Blaster 蠕蟲的遠端 shell"),
+        ThreatEntry::builtin(5554, "Sasser", Severity::High, "Sasser 蠕蟲的 FTP 伺服器"),
+        ThreatEntry::builtin(9996, "Sasser", Severity::High, "Sasser 蠕蟲的遠端 shell"),
+        ThreatEntry::builtin(12345, "NetBus", Severity::High, "NetBus 遠端控制木馬"),
+        ThreatEntry::builtin(12346, "NetBus", Severity::High, "NetBus 遠端控制木馬"),
+        ThreatEntry::builtin(20034, "NetBus Pro", Severity::High, "NetBus 2 Pro 遠端控制木馬"),
+        ThreatEntry::builtin(27374, "SubSeven", Severity::High, "SubSeven 2.x 木馬的預設端口"),
+        ThreatEntry::builtin(31337, "Back Orifice", Severity::High, "Back Orifice 遠端控制木馬，也常被各種後門沿用"),
+        ThreatEntry::builtin(31338, "Back Orifice", Severity::Medium, "Back Orifice 與 DeepBO 的替代端口"),
+        ThreatEntry::builtin(54320, "Back Orifice 2000", Severity::High, "Back Orifice 2000 遠端控制木馬"),
+        ThreatEntry::builtin(54321, "Back Orifice 2000", Severity::High, "Back Orifice 2000 遠端控制木馬"),
+    ]
+}
+
+// 名稱與描述會直接放進單行的表格與建議，不能是空字串或含換行
+pub fn validate(entries: &[ThreatEntry]) -> Result<(), String> {
+    for (i, entry) in entries.iter().enumerate() {
+        if entry.port == 0 {
+            return Err(format!("可疑端口 #{} 的 port 不能是 0", i + 1));
+        }
+        for (field, text) in [("name", &entry.name), ("description", &entry.description)] {
+            if text.trim().is_empty() {
+                return Err(format!("可疑端口 #{} (port {}) 缺少 {}", i + 1, entry.port, field));
+            }
+            if text.chars().any(char::is_control) {
+                return Err(format!("可疑端口 #{} (port {}) 的 {} 不能含換行或控制字元", i + 1, entry.port, field));
+            }
+        }
+    }
+    Ok(())
+}
+
+// 內建項目加上設定檔項目 (後者優先)
+#[derive(Debug, Clone)]
+pub struct ThreatTable(BTreeMap<u16, ThreatEntry>);
+
+impl ThreatTable {
+    pub fn build(configured: &[ThreatEntry]) -> Result<Self, String> {
+        validate(configured)?;
+        let entries = builtin_entries().into_iter().chain(configured.iter().cloned()).map(|entry| (entry.port, entry));
+        Ok(ThreatTable(entries.collect()))
+    }
+
+    pub fn get(&self, port: u16) -> Option<&ThreatEntry> {
+        self.0.get(&port)
+    }
+}
+
+// 未設定 [[threats]] 時只有內建表
+impl Default for ThreatTable {
+    fn default() -> Self {
+        ThreatTable(builtin_entries().into_iter().map(|entry| (entry.port, entry)).collect())
+    }
+}
+
+// 任一方向可用的可疑端口
+pub fn flagged<'a>(table: &'a ThreatTable, port: u16, result: &ScanResult) -> Option<&'a ThreatEntry> {
+    table.get(port).filter(|_| result.inbound || result.outbound)
+}
+
+// 端口行之後的紅色標示
+pub fn mark(entry: &ThreatEntry) -> ColoredString {
+    format!("⚠ 可疑: {}", entry.name).red().bold()
+}
+
+// 報告中的一個可疑端口
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct Suspicious {
+    pub severity: Severity,
+    pub host: IpAddr,
+    pub port: u16,
+    pub name: String,
+    pub description: String,
+    pub inbound: bool,
+    pub outbound: bool,
+}
+
+impl Suspicious {
+    fn direction(&self) -> &'static str {
+        match (self.inbound, self.outbound) {
+            (true, true) => "雙向可用",
+            (true, false) => "可接收連線",
+            _ => "可對外連線",
+        }
+    }
+}
+
+// 依最終結果找出可疑端口，依嚴重程度、主機、端口排序
+pub fn evaluate(table: &ThreatTable, results: &BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>) -> Vec<Suspicious> {
+    let mut found = Vec::new();
+    for (host, ports) in results {
+        for (port, result) in ports {
+            if let Some(entry) = flagged(table, port.port, result) {
+                found.push(Suspicious {
+                    severity: entry.severity,
+                    host: *host,
+                    port: port.port,
+                    name: entry.name.clone(),
+                    description: entry.description.clone(),
+                    inbound: result.inbound,
+                    outbound: result.outbound,
+                });
+            }
+        }
+    }
+    found.sort_by(|a, b| b.severity.cmp(&a.severity).then(a.host.cmp(&b.host)).then(a.port.cmp(&b.port)));
+    found
+}
+
+// 每個可疑端口在建議事項中也列一項
+pub fn recommendations(suspicious: &[Suspicious]) -> Vec<Recommendation> {
+    suspicious
+        .iter()
+        .map(|item| Recommendation {
+            severity: item.severity,
+            host: item.host,
+            port: item.port,
+            service: item.name.clone(),
+            message: format!(
+                "Port {} 是 {} 常用的端口 ({}) — 建議確認主機是否遭入侵，並找出佔用此端口的程式",
+                item.port, item.name, item.description
+            ),
+        })
+        .collect()
+}
+
+// 摘要中的可疑端口區段；多目標時標示主機
+pub fn display(suspicious: &[Suspicious], show_host: bool) {
+    if suspicious.is_empty() {
+        return;
+    }
+    println!("\n{}", "=== 可疑端口 ===".red().bold());
+    for item in suspicious {
+        let host = if show_host { format!("{} ", item.host) } else { String::new() };
+        println!("[{}] {}{}", item.severity.label(), host.dimmed(), line(item).red());
+    }
+}
+
+// 區段中的單行說明 (不含顏色)
+fn line(item: &Suspicious) -> String {
+    format!("Port {:5} {} — {} ({})", item.port, item.name, item.description, item.direction())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{host, scan_result};
+
+    fn result(inbound: bool, outbound: bool) -> ScanResult {
+        ScanResult { inbound, ..scan_result(outbound) }
+    }
+
+    fn configured(text: &str) -> Result<ThreatTable, String> {
+        #[derive(Deserialize)]
+        struct Config {
+            threats: Vec<ThreatEntry>,
+        }
+        let config: Config = toml::from_str(text).map_err(|e| e.to_string())?;
+        ThreatTable::build(&config.threats)
+    }
+
+    #[test]
+    fn builtin_entries_are_well_formed() {
+        let entries = builtin_entries();
+        validate(&entries).unwrap();
+        let table = ThreatTable::default();
+        assert_eq!(table.0.len(), entries.len(), "內建表不應有重複的端口");
+        for entry in &entries {
+            let item = Suspicious {
+                severity: entry.severity,
+                host: host(1),
+                port: entry.port,
+                name: entry.name.clone(),
+                description: entry.description.clone(),
+                inbound: true,
+                outbound: false,
+            };
+            let text = line(&item);
+            assert!(!text.contains('\n') && text.starts_with(&format!("Port {:5} {} — ", entry.port, entry.name)), "{}", text);
+            let advice = &recommendations(std::slice::from_ref(&item))[0];
+            assert!(advice.message.contains(&entry.description) && !advice.message.contains('\n'), "{}", advice.message);
+        }
+    }
+
+    #[test]
+    fn open_ports_are_flagged_in_either_direction() {
+        let table = ThreatTable::default();
+        assert_eq!(flagged(&table, 31337, &result(true, false)).unwrap().name, "Back Orifice");
+        assert_eq!(flagged(&table, 12345, &result(false, true)).unwrap().name, "NetBus");
+        assert!(flagged(&table, 31337, &result(false, false)).is_none());
+        assert!(flagged(&table, 22, &result(true, true)).is_none());
+        assert!(mark(table.get(4444).unwrap()).contains("⚠ 可疑: Metasploit / Blaster"));
+    }
+
+    #[test]
+    fn suspicious_ports_sort_by_severity_then_host() {
+        let table = ThreatTable::default();
+        let port = |n: u16| PortInfo::new(n, "Test", "Test");
+        let mut results = BTreeMap::new();
+        results.insert(host(2), HashMap::from([(port(1524), result(true, true)), (port(31337), result(false, true)), (port(22), result(true, true))]));
+        results.insert(host(1), HashMap::from([(port(12345), result(true, false)), (port(54321), result(false, false))]));
+
+        let found = evaluate(&table, &results);
+        let order: Vec<_> = found.iter().map(|item| (item.host, item.port, item.severity)).collect();
+        assert_eq!(order, vec![(host(1), 12345, Severity::High), (host(2), 31337, Severity::High), (host(2), 1524, Severity::Medium)]);
+        assert_eq!(found.iter().map(line).collect::<Vec<_>>(), vec![
+            "Port 12345 NetBus — NetBus 遠端控制木馬 (可接收連線)",
+            "Port 31337 Back Orifice — Back Orifice 遠端控制木馬，也常被各種後門沿用 (可對外連線)",
+            "Port  1524 ingreslock — 入侵後常被植入 root shell 的端口 (雙向可用)",
+        ]);
+
+        let advice = recommendations(&found);
+        assert_eq!(advice.len(), 3);
+        assert_eq!((advice[2].host, advice[2].port, advice[2].service.as_str()), (host(2), 1524, "ingreslock"));
+        assert!(advice[2].message.starts_with("Port 1524 是 ingreslock 常用的端口 (入侵後常被植入 root shell 的端口)"));
+    }
+
+    #[test]
+    fn config_entries_extend_and_override_the_builtin_table() {
+        let table = configured(
+            r#"
+            [[threats]]
+            port = 6667
+            name = "IRC botnet"
+            description = "常見的 IRC 殭屍網路控制通道"
+
+            [[threats]]
+            port = 4444
+            name = "內部代理"
+            description = "本公司的除錯代理"
+            severity = "low"
+            "#,
+        )
+        .unwrap();
+        let irc = table.get(6667).unwrap();
+        assert_eq!((irc.name.as_str(), irc.severity), ("IRC botnet", Severity::High));
+        let proxy = table.get(4444).unwrap();
+        assert_eq!((proxy.name.as_str(), proxy.severity), ("內部代理", Severity::Low));
+        assert_eq!(table.get(31337).unwrap().name, "Back Orifice");
+    }
+
+    #[test]
+    fn malformed_config_entries_are_rejected() {
+        let entry = |port: &str, name: &str, description: &str| {
+            format!("[[threats]]\nport = {}\nname = {:?}\ndescription = {:?}\n", port, name, description)
+        };
+        assert_eq!(configured(&entry("0", "x", "y")).unwrap_err(), "可疑端口 #1 的 port 不能是 0");
+        assert_eq!(configured(&entry("6667", " ", "y")).unwrap_err(), "可疑端口 #1 (port 6667) 缺少 name");
+        let two = entry("6667", "IRC", "y") + &entry("6668", "IRC", "第一行\n第二行");
+        assert_eq!(configured(&two).unwrap_err(), "可疑端口 #2 (port 6668) 的 description 不能含換行或控制字元");
+        assert!(configured(&(entry("6667", "IRC", "y") + "color = \"red\"\n")).unwrap_err().contains("unknown field"));
+    }
+}
@@ -0,0 +1,398 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use crate::pipeline::Evidence;
+use crate::context::ScanContext;
+use crate::{PortInfo, ScanResult};
+
+// 量測窗口長度；goodput 以窗口為單位計算
+const WINDOW: Duration = Duration::from_millis(250);
+
+// 回送模式中已送出但尚未收回的位元組上限，避免只量到本機送出緩衝區的速度
+const ECHO_IN_FLIGHT: u64 = 256 * 1024;
+
+// 每次寫入與讀取的區塊大小
+const CHUNK: usize = 64 * 1024;
+
+// 連線與等待回應標頭的最短逾時；掃描的連線逾時通常只有一秒
+pub const MIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+// HTTP 回應標頭的長度上限
+const MAX_HEADER: usize = 16 * 1024;
+
+// --throughput-test 的設定；只對列出的端口執行
+#[derive(Debug, Clone)]
+pub struct ThroughputConfig {
+    pub ports: Vec<u16>,
+    // Web 端口下載的物件路徑
+    pub path: String,
+    // 列出的端口為回送 (echo) 端點：送出的資料原樣收回
+    pub echo: bool,
+    pub max_bytes: u64,
+    pub duration: Duration,
+    // 連線與 HTTP 回應標頭的逾時
+    pub timeout: Duration,
+    // 連結本地位址的區域與 --resource-report 的計數
+    pub context: Arc<ScanContext>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ThroughputMode {
+    HttpGet,
+    HttpsGet,
+    Echo,
+}
+
+impl ThroughputMode {
+    fn label(self) -> &'static str {
+        match self {
+            ThroughputMode::HttpGet => "HTTP GET",
+            ThroughputMode::HttpsGet => "HTTPS GET",
+            ThroughputMode::Echo => "回送",
+        }
+    }
+}
+
+// 傳輸結束的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    // 達到 --throughput-duration
+    Duration,
+    // 達到 --throughput-max-bytes
+    Bytes,
+    // 物件已下載完畢或對方關閉連線
+    Complete,
+}
+
+// 一個端口的頻寬測試結果；報告中以 throughput 欄位標示這是額外的資料傳輸
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Throughput {
+    pub mode: ThroughputMode,
+    // 收到的酬載位元組 (回送模式為收回的位元組)
+    pub bytes: u64,
+    pub elapsed_ms: u64,
+    // 去掉第一個窗口 (TCP 慢啟動) 之後的平均 goodput，單位 MB/s (10^6 位元組)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mb_per_sec: Option<f64>,
+    // 單一量測窗口的最高速率
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peak_mb_per_sec: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stopped: Option<StopReason>,
+    // HTTP 回應的 Content-Length
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub object_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+}
+
+impl Throughput {
+    fn failed(mode: ThroughputMode, note: String) -> Self {
+        Throughput {
+            mode,
+            bytes: 0,
+            elapsed_ms: 0,
+            mb_per_sec: None,
+            peak_mb_per_sec: None,
+            stopped: None,
+            object_bytes: None,
+            notes: vec![note],
+        }
+    }
+}
+
+// 以固定長度的窗口累計位元組
+#[derive(Debug)]
+struct Windows {
+    started: Instant,
+    bytes: Vec<u64>,
+}
+
+impl Windows {
+    fn new() -> Self {
+        Windows { started: Instant::now(), bytes: Vec::new() }
+    }
+
+    fn record(&mut self, bytes: usize) {
+        let index = (self.started.elapsed().as_nanos() / WINDOW.as_nanos()) as usize;
+        if self.bytes.len() <= index {
+            self.bytes.resize(index + 1, 0);
+        }
+        self.bytes[index] += bytes as u64;
+    }
+
+    fn total(&self) -> u64 {
+        self.bytes.iter().sum()
+    }
+
+    fn rate(bytes: u64, time: Duration) -> f64 {
+        bytes as f64 / time.as_secs_f64().max(f64::EPSILON) / 1_000_000.0
+    }
+
+    // 有三個以上窗口時略過第一個 (連線剛建立、壅塞窗口還小)；最後一個窗口依實際經過的時間計算
+    fn summary(&self) -> (Option<f64>, Option<f64>) {
+        let elapsed = self.started.elapsed();
+        if self.total() == 0 || self.bytes.is_empty() {
+            return (None, None);
+        }
+        let skip = usize::from(self.bytes.len() >= 3);
+        let counted: u64 = self.bytes[skip..].iter().sum();
+        let time = elapsed.saturating_sub(WINDOW * skip as u32);
+        let full = self.bytes.len().saturating_sub(1);
+        let peak = self.bytes[skip.min(full)..full].iter().map(|b| Self::rate(*b, WINDOW)).reduce(f64::max);
+        (Some(Self::rate(counted, time)), peak)
+    }
+}
+
+fn finish(mode: ThroughputMode, windows: &Windows, stopped: StopReason, object_bytes: Option<u64>, notes: Vec<String>) -> Throughput {
+    let (mb_per_sec, peak_mb_per_sec) = windows.summary();
+    Throughput {
+        mode,
+        bytes: windows.total(),
+        elapsed_ms: windows.started.elapsed().as_millis() as u64,
+        mb_per_sec,
+        peak_mb_per_sec,
+        stopped: Some(stopped),
+        object_bytes,
+        notes,
+    }
+}
+
+// 回應標頭：狀態碼與 Content-Length；回傳標頭之後已讀到的酬載
+async fn read_head<S: AsyncRead + Unpin>(stream: &mut S, context: &ScanContext) -> Result<(u16, Option<u64>, Vec<u8>), String> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let end = loop {
+        let n = stream.read(&mut chunk).await.map_err(|e| e.to_string())?;
+        if n == 0 {
+            return Err("回應標頭不完整".to_string());
+        }
+        context.received(n);
+        buffer.extend_from_slice(&chunk[..n]);
+        if let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if buffer.len() > MAX_HEADER {
+            return Err("回應標頭過長".to_string());
+        }
+    };
+    let head = String::from_utf8_lossy(&buffer[..end]).into_owned();
+    let mut lines = head.lines();
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or("不是 HTTP 回應")?;
+    let length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse().ok());
+    Ok((status, length, buffer[end..].to_vec()))
+}
+
+// 下載物件直到時間或位元組上限；以 Content-Length 確認物件大小
+async fn download<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    mode: ThroughputMode,
+    host: IpAddr,
+    config: &ThroughputConfig,
+) -> Throughput {
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: portscanner-throughput\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+        config.path, host
+    );
+    if let Err(e) = stream.write_all(request.as_bytes()).await {
+        return Throughput::failed(mode, format!("送出請求失敗: {}", e));
+    }
+    config.context.sent(request.len());
+    let (status, object_bytes, body) = match tokio::time::timeout(config.timeout, read_head(&mut stream, &config.context)).await {
+        Ok(Ok(head)) => head,
+        Ok(Err(e)) => return Throughput::failed(mode, e),
+        Err(_) => return Throughput::failed(mode, "等待回應標頭逾時".to_string()),
+    };
+    if !(200..300).contains(&status) {
+        return Throughput::failed(mode, format!("{} 回應 HTTP {}，需要可下載的物件 (--throughput-path)", config.path, status));
+    }
+
+    // 計時從收到標頭開始，不含連線與伺服器產生回應的時間
+    let mut windows = Windows::new();
+    let deadline = tokio::time::Instant::now() + config.duration;
+    windows.record(body.len());
+    let mut buffer = vec![0u8; CHUNK];
+    let stopped = loop {
+        if windows.total() >= config.max_bytes {
+            break StopReason::Bytes;
+        }
+        if object_bytes.is_some_and(|size| windows.total() >= size) {
+            break StopReason::Complete;
+        }
+        let want = buffer.len().min((config.max_bytes - windows.total()) as usize);
+        match tokio::time::timeout_at(deadline, stream.read(&mut buffer[..want])).await {
+            Err(_) => break StopReason::Duration,
+            Ok(Ok(0)) => break StopReason::Complete,
+            Ok(Ok(n)) => {
+                config.context.received(n);
+                windows.record(n);
+            }
+            Ok(Err(e)) => {
+                let notes = vec![format!("傳輸中斷: {}", e)];
+                return finish(mode, &windows, StopReason::Complete, object_bytes, notes);
+            }
+        }
+    };
+    let mut notes = Vec::new();
+    if stopped == StopReason::Complete && windows.started.elapsed() < config.duration / 2 {
+        notes.push("物件太小，量測時間不足；請改用較大的物件".to_string());
+    }
+    finish(mode, &windows, stopped, object_bytes, notes)
+}
+
+// 回送端點：持續送出資料並收回，未收回的資料不超過 ECHO_IN_FLIGHT
+async fn echo(stream: TcpStream, config: &ThroughputConfig) -> Throughput {
+    let (mut reader, mut writer) = stream.into_split();
+    let mut windows = Windows::new();
+    let deadline = tokio::time::Instant::now() + config.duration;
+    let payload = vec![0x5au8; CHUNK];
+    let mut buffer = vec![0u8; CHUNK];
+    let mut written = 0u64;
+    let stopped = loop {
+        if windows.total() >= config.max_bytes {
+            break StopReason::Bytes;
+        }
+        let in_flight = written.saturating_sub(windows.total());
+        let room = ECHO_IN_FLIGHT.saturating_sub(in_flight).min(config.max_bytes.saturating_sub(written)) as usize;
+        let send = async {
+            match room {
+                0 => std::future::pending().await,
+                room => writer.write(&payload[..room.min(CHUNK)]).await,
+            }
+        };
+        tokio::select! {
+            _ = tokio::time::sleep_until(deadline) => break StopReason::Duration,
+            read = reader.read(&mut buffer) => match read {
+                Ok(0) => break StopReason::Complete,
+                Ok(n) => {
+                    config.context.received(n);
+                    windows.record(n);
+                }
+                Err(e) => {
+                    return finish(ThroughputMode::Echo, &windows, StopReason::Complete, None, vec![format!("傳輸中斷: {}", e)]);
+                }
+            },
+            sent = send => match sent {
+                Ok(n) => {
+                    config.context.sent(n);
+                    written += n as u64;
+                }
+                Err(e) => {
+                    return finish(ThroughputMode::Echo, &windows, StopReason::Complete, None, vec![format!("傳輸中斷: {}", e)]);
+                }
+            },
+        }
+    };
+    let mut notes = Vec::new();
+    if windows.total() == 0 {
+        notes.push(format!("端點沒有回送資料 (已送出 {} 位元組)", written));
+    }
+    finish(ThroughputMode::Echo, &windows, stopped, None, notes)
+}
+
+// 一個端口的測試；連線失敗時回傳只有說明的結果
+pub async fn measure(host: IpAddr, port: &PortInfo, result: &ScanResult, config: &ThroughputConfig) -> Throughput {
+    let evidence = Evidence::of(port, result, false);
+    let mode = match (config.echo, evidence.speaks_tls(), evidence.speaks_http()) {
+        (true, _, _) => ThroughputMode::Echo,
+        (false, true, _) => ThroughputMode::HttpsGet,
+        (false, false, true) => ThroughputMode::HttpGet,
+        (false, false, false) => {
+            return Throughput::failed(ThroughputMode::HttpGet, "不是 Web 端口；其他服務需要 --throughput-echo 指定回送端點".to_string())
+        }
+    };
+    let addr = config.context.socket_addr(host, port.port);
+    let _socket = config.context.socket();
+    let stream = match tokio::time::timeout(config.timeout, TcpStream::connect(addr)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => return Throughput::failed(mode, format!("連線失敗: {}", e)),
+        Err(_) => return Throughput::failed(mode, "連線逾時".to_string()),
+    };
+    let _ = stream.set_nodelay(true);
+    match mode {
+        ThroughputMode::Echo => echo(stream, config).await,
+        ThroughputMode::HttpGet => download(stream, mode, host, config).await,
+        // 只量測傳輸，不驗證憑證
+        ThroughputMode::HttpsGet => {
+            let connector = match native_tls::TlsConnector::builder()
+                .danger_accept_invalid_certs(true)
+                .danger_accept_invalid_hostnames(true)
+                .request_alpns(&["http/1.1"])
+                .build()
+            {
+                Ok(connector) => tokio_native_tls::TlsConnector::from(connector),
+                Err(e) => return Throughput::failed(mode, e.to_string()),
+            };
+            match tokio::time::timeout(config.timeout, connector.connect(&host.to_string(), stream)).await {
+                Ok(Ok(stream)) => download(stream, mode, host, config).await,
+                Ok(Err(e)) => Throughput::failed(mode, format!("TLS 握手失敗: {}", e)),
+                Err(_) => Throughput::failed(mode, "TLS 握手逾時".to_string()),
+            }
+        }
+    }
+}
+
+// 對 --throughput-test 列出且出站可連線的端口逐一測試；同時只進行一個，避免彼此搶頻寬
+pub async fn measure_results(results: &mut BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>, config: &ThroughputConfig) {
+    let mut selected: Vec<(IpAddr, PortInfo)> = results
+        .iter()
+        .flat_map(|(host, ports)| {
+            ports
+                .iter()
+                .filter(|(port, result)| result.outbound && config.ports.contains(&port.port))
+                .map(|(port, _)| (*host, port.clone()))
+        })
+        .collect();
+    selected.sort_by_key(|(host, port)| (*host, port.port));
+    for (host, port) in selected {
+        let Some(result) = results.get(&host).and_then(|r| r.get(&port)) else {
+            continue;
+        };
+        let measured = measure(host, &port, result, config).await;
+        if let Some(result) = results.get_mut(&host).and_then(|r| r.get_mut(&port)) {
+            result.throughput = Some(measured);
+        }
+    }
+}
+
+fn megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1_000_000.0)
+}
+
+// 結果中的一行，例如 [頻寬測試 HTTPS GET] 11.8 MB/s (峰值 14.2 MB/s，23.9 MB / 2.0s)
+pub fn describe(throughput: &Throughput) -> String {
+    let tag = format!("[頻寬測試 {}]", throughput.mode.label()).magenta();
+    let Some(rate) = throughput.mb_per_sec else {
+        return format!("{} {}", tag, throughput.notes.join("；").yellow());
+    };
+    let mut details = Vec::new();
+    if let Some(peak) = throughput.peak_mb_per_sec {
+        details.push(format!("峰值 {:.1} MB/s", peak));
+    }
+    details.push(format!("{} / {:.2}s", megabytes(throughput.bytes), throughput.elapsed_ms as f64 / 1000.0));
+    match throughput.stopped {
+        Some(StopReason::Bytes) => details.push("達到位元組上限".to_string()),
+        Some(StopReason::Complete) if throughput.object_bytes.is_some() => details.push("物件下載完畢".to_string()),
+        _ => {}
+    }
+    let mut line = format!("{} {:.1} MB/s ({})", tag, rate, details.join("，"));
+    if !throughput.notes.is_empty() {
+        line.push_str(&format!("  {}", throughput.notes.join("；").yellow()));
+    }
+    line
+}
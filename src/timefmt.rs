@@ -0,0 +1,76 @@
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use chrono::{DateTime, Local, Utc};
+use clap::ValueEnum;
+
+// 終端、CSV 與 HTML 的時間戳格式 (--time-format)；JSON 一律為 RFC3339 UTC
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum TimeFormat {
+    // 2024-06-10T06:13:20Z
+    #[default]
+    Rfc3339,
+    // 本機時區並標示時差，例如 2024-06-10T14:13:20+08:00
+    Local,
+    // Unix 秒
+    Unix,
+}
+
+static FORMAT: OnceLock<TimeFormat> = OnceLock::new();
+
+// 啟動時設定一次，之後的時間戳都依此格式
+pub fn set_format(format: TimeFormat) {
+    let _ = FORMAT.set(format);
+}
+
+// 目前時間 (Unix 秒)
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+fn utc(secs: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(secs, 0).unwrap_or_default()
+}
+
+// JSON 與機器可讀輸出使用的時間戳
+pub fn rfc3339_utc(secs: i64) -> String {
+    utc(secs).format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+// 依 --time-format 顯示時間戳；值不含空白，可放進以空白分隔的欄位
+pub fn timestamp(secs: i64) -> String {
+    match FORMAT.get().copied().unwrap_or_default() {
+        TimeFormat::Rfc3339 => rfc3339_utc(secs),
+        TimeFormat::Local => utc(secs).with_timezone(&Local).format("%Y-%m-%dT%H:%M:%S%:z").to_string(),
+        TimeFormat::Unix => secs.to_string(),
+    }
+}
+
+// 時間長度，例如 350ms、4.2s、1m 23s、2h 5m
+pub fn duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0 => format!("{}ms", duration.as_millis()),
+        1..=59 => format!("{:.1}s", duration.as_secs_f64()),
+        60..=3599 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, (secs % 3600) / 60),
+    }
+}
+
+// 時鐘格式的時間長度，例如 00:42 或 1:05:00
+pub fn clock(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs % 3600 / 60, secs % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{:02}:{:02}", minutes, seconds)
+    }
+}
+
+// 固定以毫秒顯示，用於多在毫秒以下的量測
+pub fn millis(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
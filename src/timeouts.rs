@@ -0,0 +1,97 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use colored::*;
+use crate::units::{deprecation, parse_duration};
+use crate::scanner::OUTBOUND_TIMEOUT;
+use crate::PortInfo;
+
+// 逾時查詢表：端口優先於類別，類別優先於全域預設
+#[derive(Debug, Clone)]
+pub struct Timeouts {
+    pub default: Duration,
+    by_category: HashMap<String, Duration>,
+    by_port: HashMap<u16, Duration>,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            default: OUTBOUND_TIMEOUT,
+            by_category: HashMap::new(),
+            by_port: HashMap::new(),
+        }
+    }
+}
+
+impl Timeouts {
+    // 所有端口使用同一個逾時
+    pub fn fixed(default: Duration) -> Self {
+        Timeouts { default, ..Default::default() }
+    }
+
+    // 依設定檔 [timeouts] 與命令列建立，命令列覆蓋設定檔中相同的鍵
+    pub fn build(
+        config: &BTreeMap<String, String>,
+        cli_default: Option<Duration>,
+        cli_overrides: Option<&str>,
+    ) -> Result<Self, String> {
+        let mut timeouts = Timeouts::default();
+
+        for (key, value) in config {
+            let duration = parse_duration(value).map_err(|e| format!("設定檔 [timeouts] {}: {}", key, e))?;
+            if key == "default" {
+                timeouts.default = duration;
+            } else {
+                timeouts.insert(key, duration);
+            }
+        }
+
+        if let Some(overrides) = cli_overrides {
+            for item in overrides.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+                let (key, value) = item
+                    .split_once('=')
+                    .ok_or_else(|| format!("無效的逾時覆蓋: {} (應為 類別=時間 或 端口=時間)", item))?;
+                let duration = parse_duration(value.trim()).map_err(|e| format!("--timeout-override {}: {}", item, e))?;
+                if let Some(warning) = deprecation("--timeout-override", value, "s") {
+                    eprintln!("{}", warning.yellow());
+                }
+                timeouts.insert(key.trim(), duration);
+            }
+        }
+
+        if let Some(default) = cli_default {
+            timeouts.default = default;
+        }
+        Ok(timeouts)
+    }
+
+    // 數字鍵視為端口，其餘視為類別名稱 (不分大小寫)
+    fn insert(&mut self, key: &str, duration: Duration) {
+        match key.parse::<u16>() {
+            Ok(port) => {
+                self.by_port.insert(port, duration);
+            }
+            Err(_) => {
+                self.by_category.insert(key.to_lowercase(), duration);
+            }
+        }
+    }
+
+    // 取得某個端口實際使用的逾時
+    pub fn for_port(&self, port: &PortInfo) -> Duration {
+        self.by_port
+            .get(&port.port)
+            .or_else(|| self.by_category.get(&port.category.to_lowercase()))
+            .copied()
+            .unwrap_or(self.default)
+    }
+
+    // 依實際逾時將端口分組，供計劃顯示
+    pub fn groups<'a>(&self, ports: impl Iterator<Item = &'a PortInfo>) -> BTreeMap<Duration, Vec<u16>> {
+        let mut groups: BTreeMap<Duration, Vec<u16>> = BTreeMap::new();
+        for port in ports {
+            groups.entry(self.for_port(port)).or_default().push(port.port);
+        }
+        groups
+    }
+}
@@ -0,0 +1,59 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+use crate::socks;
+
+// Tor 的預設 SOCKS 端口：tor 服務 9050，Tor Browser 9150
+pub const TOR_PROXIES: [&str; 2] = ["127.0.0.1:9050", "127.0.0.1:9150"];
+
+// 經過 Tor 的連線延遲高，未指定 --timeout 時使用較長的逾時
+pub const TOR_TIMEOUT: Duration = Duration::from_secs(10);
+
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Tor 預設出口政策 reject 的端口，多數出口節點沿用
+const COMMONLY_BLOCKED: &[(u16, u16)] = &[
+    (25, 25),
+    (119, 119),
+    (135, 139),
+    (445, 445),
+    (563, 563),
+    (1214, 1214),
+    (4661, 4666),
+    (6346, 6429),
+    (6699, 6699),
+    (6881, 6999),
+];
+
+// 端口是否常被出口節點封鎖
+pub fn commonly_blocked(port: u16) -> bool {
+    COMMONLY_BLOCKED.iter().any(|(start, end)| (*start..=*end).contains(&port))
+}
+
+// 找出可用的 Tor SOCKS 代理；明確指定時只試該位址
+pub async fn find_proxy(explicit: Option<SocketAddr>) -> Result<SocketAddr, String> {
+    let candidates: Vec<SocketAddr> = match explicit {
+        Some(addr) => vec![addr],
+        None => TOR_PROXIES.iter().filter_map(|s| s.parse().ok()).collect(),
+    };
+
+    let mut errors = Vec::new();
+    for proxy in candidates {
+        match socks::verify(proxy, VERIFY_TIMEOUT).await {
+            Ok(()) => return Ok(proxy),
+            Err(e) => errors.push(format!("{}: {}", proxy, e)),
+        }
+    }
+    Err(format!("找不到可用的 Tor SOCKS 代理 ({})", errors.join("; ")))
+}
+
+// 透過代理取得出口節點的外部 IP
+pub async fn exit_ip(proxy: SocketAddr) -> Result<String, String> {
+    let client = reqwest::Client::builder()
+        .proxy(reqwest::Proxy::all(format!("socks5h://{}", proxy)).map_err(|e| e.to_string())?)
+        .timeout(TOR_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let response = client.get("https://api.ipify.org").send().await.map_err(|e| e.without_url().to_string())?;
+    response.text().await.map(|ip| ip.trim().to_string()).map_err(|e| e.to_string())
+}
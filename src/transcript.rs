@@ -0,0 +1,178 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use crate::timefmt;
+
+// 逐字紀錄檔 (--transcript)：標準輸出同時寫到檔案，去除 ANSI 色碼並在每行加上時間戳
+// 與分頁程式相同，在檔案描述符層級導向，所有模組的 println! 都會被記錄
+// 每行完成時就寫入檔案，程序中途結束時已寫入的內容仍會保留
+struct Sink {
+    file: File,
+    // 尚未遇到換行的內容
+    partial: Vec<u8>,
+    // 寫入失敗後不再寫入
+    failed: bool,
+}
+
+static SINK: OnceLock<Mutex<Sink>> = OnceLock::new();
+
+// 去除 CSI 色碼與其他控制字元 (保留 tab)
+fn strip_ansi(line: &str) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+            continue;
+        }
+        if c == '\t' || !c.is_control() {
+            text.push(c);
+        }
+    }
+    text
+}
+
+impl Sink {
+    fn write_line(&mut self, line: &[u8]) {
+        if self.failed {
+            return;
+        }
+        let line = strip_ansi(&String::from_utf8_lossy(line));
+        let stamped = format!("[{}] {}\n", timefmt::timestamp(timefmt::now()), line);
+        if let Err(e) = self.file.write_all(stamped.as_bytes()) {
+            self.failed = true;
+            eprintln!("無法寫入逐字紀錄檔: {}，之後的輸出不會記錄", e);
+        }
+    }
+
+    // 依換行切出完整的行
+    fn feed(&mut self, bytes: &[u8]) {
+        self.partial.extend_from_slice(bytes);
+        while let Some(end) = self.partial.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.partial.drain(..=end).collect();
+            self.write_line(&line[..end]);
+        }
+    }
+
+    fn flush_partial(&mut self) {
+        if !self.partial.is_empty() {
+            let line = std::mem::take(&mut self.partial);
+            self.write_line(&line);
+        }
+    }
+}
+
+fn with_sink(f: impl FnOnce(&mut Sink)) {
+    if let Some(sink) = SINK.get() {
+        f(&mut sink.lock().unwrap_or_else(|e| e.into_inner()));
+    }
+}
+
+// 不經過標準輸出的紀錄 (例如只顯示在進度列的掃描進度)；未啟用時不做任何事
+pub fn note(text: &str) {
+    with_sink(|sink| {
+        sink.flush_partial();
+        sink.write_line(text.as_bytes());
+    });
+}
+
+#[cfg(unix)]
+fn open(path: &Path) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("無法建立逐字紀錄檔 {}: {}", path.display(), e))?;
+    let sink = Sink {
+        file,
+        partial: Vec::new(),
+        failed: false,
+    };
+    SINK.set(Mutex::new(sink)).map_err(|_| "逐字紀錄檔已啟用".to_string())
+}
+
+#[cfg(unix)]
+pub use unix::start;
+
+#[cfg(unix)]
+mod unix {
+    use std::fs::File;
+    use std::io::{self, Read, Write};
+    use std::os::fd::FromRawFd;
+    use std::path::Path;
+    use std::sync::Mutex;
+    use std::thread::JoinHandle;
+
+    // 還原標準輸出用的描述符與讀取管線的執行緒
+    struct Tee {
+        saved_stdout: libc::c_int,
+        reader: JoinHandle<()>,
+    }
+
+    static TEE: Mutex<Option<Tee>> = Mutex::new(None);
+
+    // 把標準輸出導向管線，由背景執行緒同時寫到終端與紀錄檔
+    pub fn start(path: &Path) -> Result<(), String> {
+        super::open(path)?;
+        // 導向後 colored 會以為不是終端，依導向前的判斷固定色彩
+        colored::control::set_override(colored::control::SHOULD_COLORIZE.should_colorize());
+        let _ = io::stdout().flush();
+
+        let failed = || format!("無法啟用逐字紀錄檔: {}", io::Error::last_os_error());
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(failed());
+        }
+        let [read_fd, write_fd] = fds;
+        let saved_stdout = unsafe { libc::dup(libc::STDOUT_FILENO) };
+        let passthrough = unsafe { libc::dup(libc::STDOUT_FILENO) };
+        if saved_stdout < 0 || passthrough < 0 || unsafe { libc::dup2(write_fd, libc::STDOUT_FILENO) } < 0 {
+            let error = failed();
+            unsafe {
+                libc::close(read_fd);
+                libc::close(write_fd);
+            }
+            return Err(error);
+        }
+        unsafe { libc::close(write_fd) };
+
+        let reader = std::thread::spawn(move || {
+            let mut pipe = unsafe { File::from_raw_fd(read_fd) };
+            let mut terminal = unsafe { File::from_raw_fd(passthrough) };
+            let mut buffer = [0u8; 8192];
+            loop {
+                let n = match pipe.read(&mut buffer) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => n,
+                };
+                let _ = terminal.write_all(&buffer[..n]);
+                super::with_sink(|sink| sink.feed(&buffer[..n]));
+            }
+            super::with_sink(|sink| sink.flush_partial());
+        });
+        *TEE.lock().unwrap_or_else(|e| e.into_inner()) = Some(Tee { saved_stdout, reader });
+        // std::process::exit 與 main 返回都會執行，確保管線中的輸出寫完
+        unsafe { libc::atexit(finish) };
+        Ok(())
+    }
+
+    // 還原標準輸出，等待管線中剩下的輸出寫到終端與紀錄檔
+    extern "C" fn finish() {
+        let Some(tee) = TEE.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+            return;
+        };
+        let _ = io::stdout().flush();
+        unsafe {
+            libc::dup2(tee.saved_stdout, libc::STDOUT_FILENO);
+            libc::close(tee.saved_stdout);
+        }
+        let _ = tee.reader.join();
+    }
+}
+
+// 其他平台不支援導向標準輸出
+#[cfg(not(unix))]
+pub fn start(_path: &Path) -> Result<(), String> {
+    Err("--transcript 目前只支援 Unix 平台".to_string())
+}
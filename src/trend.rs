@@ -0,0 +1,154 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::net::IpAddr;
+use std::path::Path;
+use rusqlite::Connection;
+use crate::identity::Identities;
+use crate::{PortInfo, ScanResult};
+
+// --trend 可顯示的最多次數
+pub const MAX_RUNS: usize = 64;
+
+// 趨勢圖的字元；終端不支援 UTF-8 時改用 ASCII
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Glyphs {
+    #[default]
+    Unicode,
+    Ascii,
+}
+
+impl Glyphs {
+    // 依 LC_ALL、LC_CTYPE、LANG 中第一個有設定的值判斷
+    pub fn detect() -> Self {
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"].iter().find_map(|name| env::var(name).ok().filter(|v| !v.is_empty()));
+        Glyphs::for_locale(locale.as_deref())
+    }
+
+    // 沒有設定語系時 Windows 終端仍可顯示 Unicode，其他平台視為 C 語系
+    fn for_locale(locale: Option<&str>) -> Self {
+        let utf8 = match locale {
+            Some(locale) => {
+                let locale = locale.to_ascii_lowercase();
+                locale.contains("utf-8") || locale.contains("utf8")
+            }
+            None => cfg!(windows),
+        };
+        match utf8 {
+            true => Glyphs::Unicode,
+            false => Glyphs::Ascii,
+        }
+    }
+
+    fn symbols(self) -> (char, char) {
+        match self {
+            Glyphs::Unicode => ('▇', '_'),
+            Glyphs::Ascii => ('#', '_'),
+        }
+    }
+}
+
+// 由舊到新的開放狀態，例如 ▇▇__▇▇▇
+pub fn render(states: &[bool], glyphs: Glyphs) -> String {
+    let (open, closed) = glyphs.symbols();
+    states.iter().map(|&state| if state { open } else { closed }).collect()
+}
+
+// 識別 -> 端口 -> 最近 runs 次的出站狀態 (由舊到新)
+pub type History = HashMap<String, BTreeMap<u16, Vec<bool>>>;
+
+// 以一次查詢取得所有識別每個端口最近 runs 次的紀錄；未測試出站的紀錄不計
+pub fn load(conn: &Connection, identities: &[String], runs: usize) -> rusqlite::Result<History> {
+    let mut statement = conn.prepare(
+        "SELECT identity, port, outbound FROM (
+             SELECT identity, port, outbound, scanned_at,
+                    ROW_NUMBER() OVER (PARTITION BY identity, port ORDER BY scanned_at DESC) AS recent
+             FROM scan_results
+             WHERE identity IN (SELECT value FROM json_each(?1)) AND outbound IS NOT NULL
+         )
+         WHERE recent <= ?2
+         ORDER BY identity, port, scanned_at",
+    )?;
+    let names = serde_json::Value::from(identities.to_vec()).to_string();
+    let rows = statement.query_map(rusqlite::params![names, runs as i64], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, u16>(1)?, row.get::<_, bool>(2)?))
+    })?;
+    let mut history = History::new();
+    for row in rows {
+        let (identity, port, open) = row?;
+        history.entry(identity).or_default().entry(port).or_default().push(open);
+    }
+    Ok(history)
+}
+
+// 為每個有紀錄的端口填入趨勢；依主機識別查詢，IP 變動後仍能對上紀錄
+pub fn apply(
+    db: &Path,
+    runs: usize,
+    results: &mut BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>,
+    identities: &Identities,
+) -> Result<(), String> {
+    let conn = crate::output::open_database(db).map_err(|e| format!("無法開啟結果資料庫 {}: {}", db.display(), e))?;
+    let names: BTreeMap<IpAddr, String> = results.keys().map(|host| (*host, identities.of(*host).name)).collect();
+    let wanted: Vec<String> = names.values().cloned().collect();
+    let history = load(&conn, &wanted, runs).map_err(|e| format!("無法讀取 {} 的紀錄: {}", db.display(), e))?;
+    for (host, ports) in results.iter_mut() {
+        let Some(recorded) = history.get(&names[host]) else {
+            continue;
+        };
+        for (port, result) in ports.iter_mut() {
+            if let Some(states) = recorded.get(&port.port) {
+                result.trend = states.clone();
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        crate::output::migrate(&conn).unwrap();
+        conn
+    }
+
+    fn insert(conn: &Connection, at: i64, identity: &str, port: u16, outbound: Option<bool>) {
+        conn.execute(
+            "INSERT INTO scan_results (scanned_at, host, port, service, category, inbound, outbound, tags, identity)
+             VALUES (?1, '192.0.2.1', ?2, 'svc', 'Other', NULL, ?3, '[]', ?4)",
+            rusqlite::params![at, port, outbound, identity],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn recent_states_are_loaded_oldest_first_in_one_query() {
+        let conn = database();
+        for (at, open) in [(1, true), (2, false), (3, false), (4, true), (5, true)] {
+            insert(&conn, at, "db01", 5432, Some(open));
+        }
+        insert(&conn, 3, "db01", 22, Some(true));
+        insert(&conn, 4, "db01", 22, None);
+        insert(&conn, 5, "web01", 443, Some(true));
+        insert(&conn, 5, "other", 80, Some(true));
+
+        let history = load(&conn, &["db01".to_string(), "web01".to_string()], 4).unwrap();
+        assert_eq!(history["db01"][&5432], [false, false, true, true]);
+        // 未測試出站的紀錄不出現在趨勢中
+        assert_eq!(history["db01"][&22], [true]);
+        assert_eq!(history["web01"][&443], [true]);
+        assert!(!history.contains_key("other"));
+    }
+
+    #[test]
+    fn sparkline_falls_back_to_ascii() {
+        let states = [true, true, false, false, true];
+        assert_eq!(render(&states, Glyphs::Unicode), "▇▇__▇");
+        assert_eq!(render(&states, Glyphs::Ascii), "##__#");
+        assert_eq!(Glyphs::for_locale(Some("zh_TW.UTF-8")), Glyphs::Unicode);
+        assert_eq!(Glyphs::for_locale(Some("en_US.utf8")), Glyphs::Unicode);
+        assert_eq!(Glyphs::for_locale(Some("C")), Glyphs::Ascii);
+    }
+}
@@ -0,0 +1,310 @@
+use std::ffi::OsStr;
+use std::time::Duration;
+use clap::builder::TypedValueParser;
+use clap::error::ErrorKind;
+use clap::{Arg, Command};
+use colored::*;
+
+// 所有時間長度與大小的選項共用同一套寫法：
+//   時間：500ms、2s、1.5m、1m30s、7d (單位由大到小，各出現一次)
+//   大小：512K、10MB、1.5GiB (1024 進位)
+// 沒有單位的數字仍然接受 (時間視為秒、大小視為位元組)，但會顯示不建議使用的警告
+
+const DURATION_EXAMPLES: &str = "500ms、2s、1m30s";
+const SIZE_EXAMPLES: &str = "512K、10MB、1G";
+
+const NANOS: u128 = 1_000_000_000;
+
+// 時間單位與對應的奈秒數，由小到大
+const DURATION_UNITS: [(&str, u128); 5] = [
+    ("ms", 1_000_000),
+    ("s", NANOS),
+    ("m", 60 * NANOS),
+    ("h", 3600 * NANOS),
+    ("d", 86400 * NANOS),
+];
+
+// 十進位數字乘上單位，小數部分精確計算後捨去；超出範圍時回傳 None
+fn scaled(number: &str, unit: u128) -> Option<u128> {
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    // 超過 18 位的小數對任何單位都已小於 1
+    let fraction = &fraction[..fraction.len().min(18)];
+    let digits: u128 = format!("{}{}", whole, fraction).parse().ok()?;
+    Some(digits.checked_mul(unit)? / 10u128.pow(fraction.len() as u32))
+}
+
+// 切出開頭的數字 (數字與小數點)，其餘為單位與後面的部分
+fn split_number(s: &str) -> Result<(&str, &str), String> {
+    let end = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let (number, rest) = s.split_at(end);
+    let valid = number.bytes().any(|b| b.is_ascii_digit()) && number.matches('.').count() <= 1;
+    if !valid {
+        return Err(match number {
+            "" => format!("'{}' 缺少數字", s),
+            _ => format!("'{}' 不是有效的數字", number),
+        });
+    }
+    Ok((number, rest))
+}
+
+// 沒有單位的數字 (相容舊寫法)
+fn is_bare(s: &str) -> bool {
+    let s = s.trim();
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit() || b == b'.')
+}
+
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err("時間不能是空的".to_string());
+    }
+    if is_bare(s) {
+        let nanos = scaled(split_number(s)?.0, NANOS).ok_or_else(|| format!("時間超出範圍: {}", s))?;
+        return to_duration(nanos, s);
+    }
+
+    let mut rest = s;
+    let mut total: u128 = 0;
+    // 上一個單位的位置；之後的單位必須比它小
+    let mut previous: Option<usize> = None;
+    while !rest.is_empty() {
+        let (number, after) = split_number(rest)?;
+        let after = after.trim_start();
+        let unit_end = after.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(after.len());
+        let (unit, after) = after.split_at(unit_end);
+        let index = match DURATION_UNITS.iter().position(|(name, _)| *name == unit) {
+            Some(index) => index,
+            None if unit.is_empty() => return Err(format!("'{}' 缺少單位 (可用 ms、s、m、h、d)", number)),
+            None => return Err(format!("無效的時間單位 '{}' (可用 ms、s、m、h、d)", unit)),
+        };
+        if previous.is_some_and(|previous| index >= previous) {
+            return Err(format!("時間單位應由大到小且各出現一次: {}", s));
+        }
+        previous = Some(index);
+        total = scaled(number, DURATION_UNITS[index].1)
+            .and_then(|nanos| total.checked_add(nanos))
+            .ok_or_else(|| format!("時間超出範圍: {}", s))?;
+        rest = after.trim_start();
+    }
+    to_duration(total, s)
+}
+
+fn to_duration(nanos: u128, s: &str) -> Result<Duration, String> {
+    let secs = u64::try_from(nanos / NANOS).map_err(|_| format!("時間超出範圍: {}", s))?;
+    Ok(Duration::new(secs, (nanos % NANOS) as u32))
+}
+
+// 位元組數，可加 K、M、G、T (1024 進位；KB、KiB 等寫法相同)
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err("大小不能是空的".to_string());
+    }
+    let (number, unit) = split_number(s)?;
+    let scale: u128 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => return Err(format!("無效的大小單位 '{}' (可用 B、K、M、G、T)", unit.trim())),
+    };
+    match scaled(number, scale).map(u64::try_from) {
+        Some(Ok(0)) => Err("大小必須大於 0".to_string()),
+        Some(Ok(bytes)) => Ok(bytes),
+        _ => Err(format!("大小超出範圍: {}", s)),
+    }
+}
+
+// 錯誤與警告中顯示的選項名稱
+fn flag(arg: Option<&Arg>) -> String {
+    match arg {
+        Some(arg) => match arg.get_long() {
+            Some(long) => format!("--{}", long),
+            None => arg.get_id().to_string(),
+        },
+        None => "值".to_string(),
+    }
+}
+
+fn invalid(cmd: &Command, flag: &str, value: &str, reason: &str, examples: &str) -> clap::Error {
+    clap::Error::raw(ErrorKind::ValueValidation, format!("{} 的值 '{}' 無效: {}；例如 {}\n", flag, value, reason, examples))
+        .with_cmd(cmd)
+}
+
+// 沒有單位的非零數字：回傳警告，說明假設的單位
+pub fn deprecation(flag: &str, value: &str, unit: &str) -> Option<String> {
+    let value = value.trim();
+    let zero = value.bytes().all(|b| b == b'0' || b == b'.');
+    (is_bare(value) && !zero).then(|| {
+        format!("警告: {} {} 沒有單位，視為 {}{}；沒有單位的寫法已不建議使用，請改寫成 {}{}", flag, value, value, unit, value, unit)
+    })
+}
+
+fn utf8<'a>(cmd: &Command, flag: &str, value: &'a OsStr, examples: &str) -> Result<&'a str, clap::Error> {
+    value.to_str().ok_or_else(|| invalid(cmd, flag, &value.to_string_lossy(), "不是有效的 UTF-8", examples))
+}
+
+// 時間長度選項的 clap 解析器；within 另外要求介於 0 (不含) 與上限之間
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DurationParser {
+    max: Option<Duration>,
+}
+
+impl DurationParser {
+    pub fn within(max: Duration) -> Self {
+        DurationParser { max: Some(max) }
+    }
+}
+
+impl TypedValueParser for DurationParser {
+    type Value = Duration;
+
+    fn parse_ref(&self, cmd: &Command, arg: Option<&Arg>, value: &OsStr) -> Result<Duration, clap::Error> {
+        let flag = flag(arg);
+        let text = utf8(cmd, &flag, value, DURATION_EXAMPLES)?;
+        let duration = parse_duration(text).map_err(|reason| invalid(cmd, &flag, text, &reason, DURATION_EXAMPLES))?;
+        if let Some(max) = self.max {
+            if duration.is_zero() || duration > max {
+                let reason = format!("應介於 0 與 {} 之間", crate::timefmt::duration(max));
+                return Err(invalid(cmd, &flag, text, &reason, DURATION_EXAMPLES));
+            }
+        }
+        if let Some(warning) = deprecation(&flag, text, "s") {
+            eprintln!("{}", warning.yellow());
+        }
+        Ok(duration)
+    }
+}
+
+// 大小選項的 clap 解析器
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SizeParser;
+
+impl TypedValueParser for SizeParser {
+    type Value = u64;
+
+    fn parse_ref(&self, cmd: &Command, arg: Option<&Arg>, value: &OsStr) -> Result<u64, clap::Error> {
+        let flag = flag(arg);
+        let text = utf8(cmd, &flag, value, SIZE_EXAMPLES)?;
+        let bytes = parse_size(text).map_err(|reason| invalid(cmd, &flag, text, &reason, SIZE_EXAMPLES))?;
+        if let Some(warning) = deprecation(&flag, text, "B") {
+            eprintln!("{}", warning.yellow());
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_accept_every_unit_and_compound_forms() {
+        let cases = [
+            ("500ms", Duration::from_millis(500)),
+            ("2s", Duration::from_secs(2)),
+            ("1.5m", Duration::from_secs(90)),
+            ("1m30s", Duration::from_secs(90)),
+            ("1m 30s", Duration::from_secs(90)),
+            ("2h", Duration::from_secs(7200)),
+            ("7d", Duration::from_secs(7 * 86400)),
+            ("1d2h3m4s5ms", Duration::new(86400 + 7200 + 180 + 4, 5_000_000)),
+            ("0.1s", Duration::from_millis(100)),
+            ("1.234567891s", Duration::new(1, 234_567_891)),
+            ("0.5ms", Duration::from_micros(500)),
+            (".5s", Duration::from_millis(500)),
+            ("0s", Duration::ZERO),
+            (" 3s ", Duration::from_secs(3)),
+            // 相容舊寫法：沒有單位視為秒
+            ("3", Duration::from_secs(3)),
+            ("2.5", Duration::from_millis(2500)),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_duration(input), Ok(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn invalid_durations_explain_the_problem() {
+        let cases = [
+            ("", "空的"),
+            ("   ", "空的"),
+            ("s", "缺少數字"),
+            ("-1s", "缺少數字"),
+            ("5x", "無效的時間單位 'x'"),
+            ("5 sec", "無效的時間單位 'sec'"),
+            ("5S", "無效的時間單位 'S'"),
+            ("1m30", "'30' 缺少單位"),
+            ("30s1m", "由大到小"),
+            ("1s1s", "由大到小"),
+            ("1.2.3s", "不是有效的數字"),
+            (".s", "不是有效的數字"),
+            ("99999999999999999999d", "超出範圍"),
+            ("18446744073709551616", "超出範圍"),
+        ];
+        for (input, expected) in cases {
+            let error = parse_duration(input).unwrap_err();
+            assert!(error.contains(expected), "{:?}: {}", input, error);
+        }
+        // 上限附近仍可表示
+        assert_eq!(parse_duration("18446744073709551615s"), Ok(Duration::from_secs(u64::MAX)));
+    }
+
+    #[test]
+    fn sizes_accept_binary_units() {
+        let cases = [
+            ("1", 1),
+            ("100B", 100),
+            ("512K", 512 << 10),
+            ("512kb", 512 << 10),
+            ("10MB", 10 << 20),
+            ("10MiB", 10 << 20),
+            ("10 M", 10 << 20),
+            ("1.5G", 3 << 29),
+            ("2T", 2 << 40),
+            ("1.5B", 1),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_size(input), Ok(expected), "{}", input);
+        }
+        let invalid = [("", "空的"), ("0", "大於 0"), ("0.1B", "大於 0"), ("10X", "'X'"), ("MB", "缺少數字"), ("99999999999T", "超出範圍")];
+        for (input, expected) in invalid {
+            let error = parse_size(input).unwrap_err();
+            assert!(error.contains(expected), "{:?}: {}", input, error);
+        }
+    }
+
+    #[test]
+    fn bare_numbers_warn_with_the_assumed_unit() {
+        let warning = deprecation("--timeout", "3", "s").unwrap();
+        assert!(warning.contains("--timeout 3 沒有單位，視為 3s"), "{}", warning);
+        assert!(deprecation("--timeout", "3s", "s").is_none());
+        assert!(deprecation("--timeout", "0", "s").is_none());
+        assert!(deprecation("--throughput-max-bytes", "1048576", "B").unwrap().contains("視為 1048576B"));
+    }
+
+    fn command() -> Command {
+        Command::new("portscanner")
+            .arg(Arg::new("timeout").long("timeout").value_parser(DurationParser::default()))
+            .arg(Arg::new("short").long("short").value_parser(DurationParser::within(Duration::from_secs(10))))
+            .arg(Arg::new("size").long("size").value_parser(SizeParser))
+    }
+
+    #[test]
+    fn errors_name_the_flag_the_input_and_examples() {
+        let error = command().try_get_matches_from(["portscanner", "--timeout", "5x"]).unwrap_err().to_string();
+        assert!(error.contains("--timeout 的值 '5x' 無效"), "{}", error);
+        assert!(error.contains("例如 500ms、2s、1m30s"), "{}", error);
+
+        let error = command().try_get_matches_from(["portscanner", "--short", "1m"]).unwrap_err().to_string();
+        assert!(error.contains("--short 的值 '1m' 無效: 應介於 0 與"), "{}", error);
+
+        let error = command().try_get_matches_from(["portscanner", "--size", "10XB"]).unwrap_err().to_string();
+        assert!(error.contains("--size 的值 '10XB' 無效") && error.contains("例如 512K"), "{}", error);
+
+        let matches = command().try_get_matches_from(["portscanner", "--timeout", "1m30s", "--size", "10MB"]).unwrap();
+        assert_eq!(matches.get_one::<Duration>("timeout"), Some(&Duration::from_secs(90)));
+        assert_eq!(matches.get_one::<u64>("size"), Some(&(10 << 20)));
+    }
+}
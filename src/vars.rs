@@ -0,0 +1,216 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+
+// 政策檔與範本檔中 ${VAR} / ${VAR:-預設值} 的值：--set 優先，其次是環境變數
+#[derive(Debug, Clone, Default)]
+pub struct Variables {
+    values: BTreeMap<String, String>,
+    // 測試不讀取環境變數
+    environment: bool,
+}
+
+impl Variables {
+    pub fn new(set: &[(String, String)]) -> Self {
+        Variables { values: set.iter().cloned().collect(), environment: true }
+    }
+
+    // 空值視為未設定，與 shell 的 ${VAR:-預設值} 相同
+    fn get(&self, name: &str) -> Option<String> {
+        let value = match self.values.get(name) {
+            Some(value) => Some(value.clone()),
+            None if self.environment => env::var(name).ok(),
+            None => None,
+        };
+        value.filter(|value| !value.is_empty())
+    }
+
+    // 替換一段文字，未設定的變數記入 missing；$${VAR} 是跳脫，保留為字面的 ${VAR}
+    // 預設值中可以再引用變數，例如 ${HOST:-${FALLBACK_HOST}}
+    fn expand_into(&self, text: &str, missing: &mut BTreeSet<String>) -> Result<String, String> {
+        let mut out = String::new();
+        let mut rest = text;
+        while let Some(at) = rest.find('$') {
+            out.push_str(&rest[..at]);
+            let after = &rest[at + 1..];
+            if let Some(escaped) = after.strip_prefix("${") {
+                out.push_str("${");
+                rest = escaped;
+            } else if let Some(body) = after.strip_prefix('{') {
+                let end = closing(body).ok_or_else(|| format!("未結束的變數: {}", &rest[at..]))?;
+                out.push_str(&self.placeholder(&body[..end], missing)?);
+                rest = &body[end + 1..];
+            } else {
+                out.push('$');
+                rest = after;
+            }
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    // 大括號內的 VAR 或 VAR:-預設值
+    fn placeholder(&self, inner: &str, missing: &mut BTreeSet<String>) -> Result<String, String> {
+        let (name, default) = match inner.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (inner, None),
+        };
+        let valid = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(format!("無效的變數名稱: ${{{}}}", inner));
+        }
+        match (self.get(name), default) {
+            (Some(value), _) => Ok(value),
+            (None, Some(default)) => self.expand_into(default, missing),
+            (None, None) => {
+                missing.insert(name.to_string());
+                Ok(String::new())
+            }
+        }
+    }
+
+    // 替換 TOML 文件中所有的字串值 (含陣列與表格中的)；註解與鍵名不替換
+    // 在解析成設定欄位之前替換，值中的引號或換行不會改變文件結構
+    pub fn expand_toml(&self, table: &mut toml::Table) -> Result<(), String> {
+        let mut missing = BTreeSet::new();
+        for (_, value) in table.iter_mut() {
+            self.expand_value(value, &mut missing)?;
+        }
+        match missing.is_empty() {
+            true => Ok(()),
+            false => Err(unset(&missing)),
+        }
+    }
+
+    fn expand_value(&self, value: &mut toml::Value, missing: &mut BTreeSet<String>) -> Result<(), String> {
+        match value {
+            toml::Value::String(text) => *text = self.expand_into(text, missing)?,
+            toml::Value::Array(items) => {
+                for item in items {
+                    self.expand_value(item, missing)?;
+                }
+            }
+            toml::Value::Table(table) => {
+                for (_, item) in table.iter_mut() {
+                    self.expand_value(item, missing)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+// 與 ${ 對應的 } 的位置；預設值中可以有巢狀的 ${...}
+fn closing(body: &str) -> Option<usize> {
+    let mut depth = 0;
+    let bytes = body.as_bytes();
+    for (i, &byte) in bytes.iter().enumerate() {
+        match byte {
+            b'{' if i > 0 && bytes[i - 1] == b'$' => depth += 1,
+            b'}' if depth == 0 => return Some(i),
+            b'}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn unset(missing: &BTreeSet<String>) -> String {
+    let names: Vec<&str> = missing.iter().map(String::as_str).collect();
+    format!("未設定的變數: {} (以環境變數或 --set KEY=VALUE 提供，或改用 ${{VAR:-預設值}})", names.join("、"))
+}
+
+// --set KEY=VALUE
+pub fn parse_assignment(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s.split_once('=').ok_or_else(|| format!("格式應為 KEY=VALUE: {}", s))?;
+    if key.is_empty() {
+        return Err(format!("缺少變數名稱: {}", s));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> Variables {
+        Variables {
+            values: pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            environment: false,
+        }
+    }
+
+    impl Variables {
+        fn expand(&self, text: &str) -> Result<String, String> {
+            let mut missing = BTreeSet::new();
+            let expanded = self.expand_into(text, &mut missing)?;
+            match missing.is_empty() {
+                true => Ok(expanded),
+                false => Err(unset(&missing)),
+            }
+        }
+    }
+
+    #[test]
+    fn placeholders_and_defaults_are_expanded() {
+        let vars = vars(&[("DEPLOY_HOST", "db01"), ("EMPTY", "")]);
+        assert_eq!(vars.expand("${DEPLOY_HOST}:5432").unwrap(), "db01:5432");
+        assert_eq!(vars.expand("${APP_PORTS:-80,443}").unwrap(), "80,443");
+        // 空值與未設定相同，使用預設值；預設值可以是空字串
+        assert_eq!(vars.expand("${EMPTY:-x}").unwrap(), "x");
+        assert_eq!(vars.expand("a${UNSET:-}b").unwrap(), "ab");
+        // 單獨的 $ 原樣保留
+        assert_eq!(vars.expand("cost $5 {x}").unwrap(), "cost $5 {x}");
+    }
+
+    #[test]
+    fn defaults_can_nest_other_variables() {
+        let vars = vars(&[("FALLBACK", "web01")]);
+        assert_eq!(vars.expand("${HOST:-${FALLBACK}}").unwrap(), "web01");
+        assert_eq!(vars.expand("${HOST:-${OTHER:-${FALLBACK}.example}}").unwrap(), "web01.example");
+        let err = vars.expand("${HOST:-${MISSING}}").unwrap_err();
+        assert!(err.contains("MISSING") && !err.contains("HOST"), "{}", err);
+    }
+
+    #[test]
+    fn escaped_placeholders_stay_literal() {
+        let vars = vars(&[("HOST", "db01")]);
+        assert_eq!(vars.expand("$${HOST} is ${HOST}").unwrap(), "${HOST} is db01");
+        assert_eq!(vars.expand("$${UNSET}").unwrap(), "${UNSET}");
+    }
+
+    #[test]
+    fn missing_variables_are_all_listed() {
+        let err = vars(&[]).expand("${DEPLOY_HOST}:${APP_PORTS} ${DEPLOY_HOST}").unwrap_err();
+        assert!(err.starts_with("未設定的變數: APP_PORTS、DEPLOY_HOST "), "{}", err);
+        assert!(vars(&[]).expand("${OPEN").unwrap_err().contains("未結束"));
+        assert!(vars(&[]).expand("${1X}").unwrap_err().contains("無效的變數名稱"));
+    }
+
+    #[test]
+    fn only_toml_string_values_are_expanded() {
+        let mut table: toml::Table = toml::from_str(
+            "# ${COMMENT}\nname = \"${NAME}\"\nlimit = 3\n[[expect]]\nports = \"${PORTS}\"\nreasons = ['${NAME}']",
+        )
+        .unwrap();
+        // 值中的引號不會破壞 TOML 結構
+        vars(&[("NAME", "ci \"prod\""), ("PORTS", "80")]).expand_toml(&mut table).unwrap();
+        assert_eq!(table["name"].as_str(), Some("ci \"prod\""));
+        assert_eq!(table["expect"][0]["ports"].as_str(), Some("80"));
+        assert_eq!(table["expect"][0]["reasons"][0].as_str(), Some("ci \"prod\""));
+        assert_eq!(table["limit"].as_integer(), Some(3));
+
+        let mut table: toml::Table = toml::from_str("a = \"${X}\"\n[b]\nc = [\"${Y}\"]").unwrap();
+        let err = vars(&[]).expand_toml(&mut table).unwrap_err();
+        assert!(err.contains("X、Y"), "{}", err);
+    }
+
+    #[test]
+    fn assignments_split_on_the_first_equals() {
+        assert_eq!(parse_assignment("Q=a=b").unwrap(), ("Q".to_string(), "a=b".to_string()));
+        assert_eq!(parse_assignment("EMPTY=").unwrap(), ("EMPTY".to_string(), String::new()));
+        assert!(parse_assignment("NOVALUE").is_err());
+        assert!(parse_assignment("=x").is_err());
+    }
+}
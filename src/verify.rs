@@ -0,0 +1,298 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpSocket;
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
+use crate::closure::{Failure, DOMINANT};
+use crate::errors;
+use crate::grade;
+use crate::limits::ScanError;
+use crate::scanner::ScanPlan;
+use crate::{PortInfo, ScanResult};
+
+// 失敗所花時間達逾時的此比例，視為卡在期限邊緣
+const NEAR_DEADLINE: f64 = 0.9;
+
+// 同類別至少要有這麼多個端口，才以同類別的結果判斷單一失敗
+const MIN_PEERS: usize = 3;
+
+// 設定檔 [verify] 區段
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VerifyConfig {
+    // 重新探測的逾時為原本的幾倍
+    #[serde(default = "default_timeout_factor")]
+    pub timeout_factor: f64,
+
+    // 重新探測時綁定的本機來源位址 (例如另一張網卡的 IP)
+    pub source: Option<IpAddr>,
+}
+
+impl Default for VerifyConfig {
+    fn default() -> Self {
+        VerifyConfig {
+            timeout_factor: default_timeout_factor(),
+            source: None,
+        }
+    }
+}
+
+fn default_timeout_factor() -> f64 {
+    3.0
+}
+
+// 覆核後的結論
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Verification {
+    // 重新探測結果相同
+    Confirmed,
+    // 重新探測結果不同，已改用新的結果
+    Changed,
+}
+
+// 結果可疑的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Suspicion {
+    // 失敗發生在逾時期限附近，較長的逾時可能成功
+    NearDeadline,
+    // 掃描端錯誤 (例如 EADDRINUSE)，不代表端口狀態
+    ScanError,
+    // 同類別其他端口都可連線，只有這個失敗
+    LoneFailure,
+}
+
+// 判斷單一主機中哪些結果可疑
+// 主機大多數失敗都是逾時時 (有狀態防火牆)，逾時是預期行為，不列為可疑
+pub fn suspicious(results: &HashMap<PortInfo, ScanResult>, deadline: impl Fn(&PortInfo) -> Duration) -> Vec<(PortInfo, Suspicion)> {
+    let failures: Vec<Failure> = results.values().filter_map(|r| r.failure).collect();
+    let timeouts = failures.iter().filter(|f| **f == Failure::Timeout).count();
+    let firewalled = !failures.is_empty() && timeouts as f64 / failures.len() as f64 >= DOMINANT;
+
+    // 類別 -> (端口數, 可連線數)
+    let mut categories: HashMap<&str, (usize, usize)> = HashMap::new();
+    for (port, result) in results {
+        let entry = categories.entry(port.category.as_str()).or_default();
+        entry.0 += 1;
+        entry.1 += usize::from(result.outbound);
+    }
+
+    let mut found: Vec<(PortInfo, Suspicion)> = results
+        .iter()
+        .filter_map(|(port, result)| {
+            if result.error.is_some() {
+                return Some((port.clone(), Suspicion::ScanError));
+            }
+            if result.outbound {
+                return None;
+            }
+            let limit_ms = deadline(port).as_secs_f64() * 1000.0;
+            let near_deadline = match result.failure {
+                Some(Failure::Timeout) => !firewalled,
+                Some(Failure::Reset { latency_ms }) => latency_ms >= limit_ms * NEAR_DEADLINE,
+                _ => false,
+            };
+            if near_deadline {
+                return Some((port.clone(), Suspicion::NearDeadline));
+            }
+            let (total, open) = categories[port.category.as_str()];
+            (total >= MIN_PEERS && open == total - 1).then(|| (port.clone(), Suspicion::LoneFailure))
+        })
+        .collect();
+    found.sort_by_key(|(port, _)| port.port);
+    found
+}
+
+// 重新探測的結果 (可連線, 延遲, 失敗方式, 掃描端錯誤)
+pub type Reprobe = (bool, Option<f64>, Option<Failure>, Option<ScanError>);
+
+// 以較長逾時與 (可選的) 其他來源位址重新連線
+pub async fn reprobe(dest: SocketAddr, limit: Duration, source: Option<IpAddr>) -> Reprobe {
+    let socket = match dest {
+        SocketAddr::V4(_) => TcpSocket::new_v4(),
+        SocketAddr::V6(_) => TcpSocket::new_v6(),
+    };
+    let socket = match socket {
+        Ok(socket) => socket,
+        Err(e) => return (false, None, None, ScanError::classify(&e)),
+    };
+    // 來源位址與目標位址族不同時不綁定
+    if let Some(source) = source.filter(|s| s.is_ipv4() == dest.is_ipv4()) {
+        if let Err(e) = socket.bind(SocketAddr::new(source, 0)) {
+            return (false, None, None, ScanError::classify(&e));
+        }
+    }
+    connect(socket, dest, limit).await
+}
+
+// 以設定好 (已綁定) 的 socket 連線並分類結果；覆核與 --test-source-ports 共用
+pub async fn connect(socket: TcpSocket, dest: SocketAddr, limit: Duration) -> Reprobe {
+    let started = Instant::now();
+    match timeout(limit, socket.connect(dest)).await {
+        Ok(Ok(_)) => (true, Some(started.elapsed().as_secs_f64() * 1000.0), None, None),
+        Ok(Err(e)) => match ScanError::classify(&e) {
+            Some(error) => (false, None, None, Some(error)),
+            None => (false, None, Some(Failure::from_error(&e, started.elapsed())), None),
+        },
+        Err(_) => (false, None, Some(Failure::Timeout), None),
+    }
+}
+
+// 覆核的統計
+#[derive(Debug, Default)]
+pub struct VerifySummary {
+    pub reprobed: usize,
+    pub changed: usize,
+    // 依可疑原因 (期限邊緣, 掃描端錯誤, 同類別唯一失敗)
+    pub reasons: [usize; 3],
+}
+
+// 結果前的覆核說明，沒有重新探測時不顯示
+pub fn display_summary(summary: &VerifySummary) {
+    if summary.reprobed == 0 {
+        return;
+    }
+    let [deadline, error, lone] = summary.reasons;
+    println!(
+        "{} 重新探測 {} 個可疑結果 (期限邊緣 {}、掃描端錯誤 {}、同類別唯一失敗 {})，{} 個結果改變",
+        "覆核:".bold(),
+        summary.reprobed,
+        deadline,
+        error,
+        lone,
+        summary.changed
+    );
+}
+
+// 重新探測所有可疑結果；結果改變時以新結果取代並標記為已覆核
+// 經由代理時無法改變來源位址或分辨失敗方式，不覆核
+pub async fn verify(
+    plan: &ScanPlan,
+    config: &VerifyConfig,
+    results: &mut BTreeMap<IpAddr, HashMap<PortInfo, ScanResult>>,
+) -> VerifySummary {
+    let mut summary = VerifySummary::default();
+    if plan.proxy.is_some() {
+        return summary;
+    }
+    let factor = config.timeout_factor.max(1.0);
+    let semaphore = std::sync::Arc::new(Semaphore::new(plan.concurrency.max(1)));
+    for (host, host_results) in results.iter_mut() {
+        let candidates = suspicious(host_results, |port| plan.timeouts.for_port(port));
+        let handles: Vec<_> = candidates
+            .into_iter()
+            .map(|(port, suspicion)| {
+                summary.reasons[suspicion as usize] += 1;
+                let (dest, source) = (plan.context.socket_addr(*host, port.port), config.source);
+                let limit = plan.timeouts.for_port(&port).mul_f64(factor);
+                let (semaphore, prober) = (semaphore.clone(), plan.prober.clone());
+                tokio::spawn(async move {
+                    let _permit = semaphore.acquire_owned().await;
+                    let outcome = prober.reprobe(dest, limit, source).await;
+                    (port, outcome)
+                })
+            })
+            .collect();
+        for handle in handles {
+            let Ok((port, (connected, latency_ms, failure, error))) = handle.await else {
+                continue;
+            };
+            let Some(result) = host_results.get_mut(&port) else {
+                continue;
+            };
+            summary.reprobed += 1;
+            // 重新探測本身發生掃描端錯誤時無法判斷，保留原結果
+            if error.is_some() {
+                continue;
+            }
+            if connected == result.outbound && result.error.is_none() {
+                result.verification = Some(Verification::Confirmed);
+                result.confirmations = result.confirmations.saturating_add(1);
+                continue;
+            }
+            summary.changed += 1;
+            result.outbound = connected;
+            result.latency_ms = latency_ms;
+            result.failure = failure;
+            result.error = None;
+            result.icmp = None;
+            errors::reprobed(&mut result.codes, failure);
+            result.confirmations = 0;
+            result.verification = Some(Verification::Changed);
+            result.grade = grade::grade_result(result, None, &plan.grading);
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::prober::fake::{Script, Scripted, ScriptedProber};
+    use crate::testutil::{by_host, host, scan, scripted_plan};
+
+    fn script(outcome: Scripted, ms: u64) -> Script {
+        Script::new(outcome, Duration::from_millis(ms))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn suspicious_results_are_reprobed() {
+        let target = host(1);
+        let ports = [22, 25, 80, 443, 8080, 8443];
+        let web = |prober: ScriptedProber| {
+            prober
+                .with(target, 80, script(Scripted::Open, 10))
+                .with(target, 443, script(Scripted::Open, 10))
+                .with(target, 8080, script(Scripted::Open, 10))
+        };
+        // 第一次掃描：8443 是 Web 類別中唯一的失敗，22 是掃描端錯誤，25 逾時
+        let first = web(ScriptedProber::new())
+            .with(target, 22, script(Scripted::Error(ScanError::TooManyOpenFiles), 1))
+            .with(target, 25, script(Scripted::Silent, 0))
+            .with(target, 8443, script(Scripted::Refused, 5));
+        let mut plan = scripted_plan(&[target], &ports, 8, Arc::new(first));
+        for port in plan.ports.iter_mut().filter(|p| p.port >= 80) {
+            port.category = "Web".to_string();
+        }
+        let mut results = by_host(scan(&plan).await);
+
+        // 覆核：22 與 25 恢復 (25 需要比原本逾時更長的時間)，8443 仍被拒
+        let second = Arc::new(
+            web(ScriptedProber::new())
+                .with(target, 22, script(Scripted::Open, 10))
+                .with(target, 25, script(Scripted::Open, 2000))
+                .with(target, 8443, script(Scripted::Refused, 5)),
+        );
+        let plan = ScanPlan { prober: second.clone(), ..plan };
+        let summary = verify(&plan, &VerifyConfig::default(), &mut results).await;
+
+        let mut reprobed: Vec<u16> = second.calls().into_iter().map(|(_, port)| port).collect();
+        reprobed.sort_unstable();
+        assert_eq!(reprobed, vec![22, 25, 8443]);
+        assert_eq!((summary.reprobed, summary.changed, summary.reasons), (3, 2, [1, 1, 1]));
+        let result = |port: u16| results[&target].iter().find(|(info, _)| info.port == port).map(|(_, r)| r).unwrap();
+        assert_eq!(result(22).verification, Some(Verification::Changed));
+        assert!(result(22).outbound && result(22).error.is_none());
+        assert_eq!(result(25).verification, Some(Verification::Changed));
+        assert!(result(25).outbound && result(25).failure.is_none());
+        assert_eq!(result(8443).verification, Some(Verification::Confirmed));
+        assert_eq!(result(8443).confirmations, 1);
+        assert_eq!(result(80).verification, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn proxied_scans_are_not_reprobed() {
+        let prober = Arc::new(ScriptedProber::new().fallback(script(Scripted::Silent, 0)));
+        let plan = scripted_plan(&[host(1)], &[22], 1, prober.clone());
+        let mut results = by_host(scan(&plan).await);
+        let plan = ScanPlan { proxy: Some("127.0.0.1:9050".parse().unwrap()), ..plan };
+        let summary = verify(&plan, &VerifyConfig::default(), &mut results).await;
+        assert_eq!(summary.reprobed, 0);
+        assert_eq!(prober.calls().len(), 1);
+    }
+}
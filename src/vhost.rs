@@ -0,0 +1,206 @@
+use std::error::Error;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use reqwest::redirect::Policy;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use crate::PortInfo;
+use crate::context::ScanContext;
+
+// HTTP 請求須等待完整回應標頭，比單純連線需要更多時間
+pub const WEB_TIMEOUT: Duration = Duration::from_secs(3);
+
+// 單個虛擬主機的探測結果
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct VhostResult {
+    pub name: String,
+    pub tls: bool,
+    pub status: Option<u16>,
+    // 只有 TLS 才有意義：憑證是否通過驗證 (包含名稱比對)
+    pub cert_valid: Option<bool>,
+    pub error: Option<String>,
+}
+
+// 判斷是否為 Web 端口
+pub fn is_web_port(port: &PortInfo) -> bool {
+    port.category == "Web" || matches!(port.port, 80 | 443 | 8080 | 8443)
+}
+
+// 依端口與服務名稱判斷是否使用 TLS
+pub fn uses_tls(port: &PortInfo) -> bool {
+    matches!(port.port, 443 | 8443) || port.service.contains("HTTPS") || port.service.contains("SSL")
+}
+
+// 請求失敗的原因；憑證驗證失敗時略過驗證再請求一次
+pub struct RequestError {
+    certificate: bool,
+    message: String,
+}
+
+impl From<reqwest::Error> for RequestError {
+    fn from(error: reqwest::Error) -> Self {
+        RequestError { certificate: is_certificate_error(&error), message: error.without_url().to_string() }
+    }
+}
+
+impl From<String> for RequestError {
+    fn from(message: String) -> Self {
+        RequestError { certificate: false, message }
+    }
+}
+
+// 錯誤鏈中是否包含憑證驗證失敗
+pub fn is_certificate_error(error: &(dyn Error + 'static)) -> bool {
+    let mut source: Option<&dyn Error> = Some(error);
+    while let Some(e) = source {
+        let text = e.to_string().to_lowercase();
+        if text.contains("certificate") || text.contains("verify") {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
+
+// 以指定名稱作為 SNI 與 Host 對目標位址送出請求
+async fn request(addr: SocketAddr, name: &str, tls: bool, insecure: bool) -> Result<u16, reqwest::Error> {
+    let client = reqwest::Client::builder()
+        .resolve(name, addr)
+        .timeout(WEB_TIMEOUT)
+        .redirect(Policy::none())
+        .danger_accept_invalid_certs(insecure)
+        .build()?;
+
+    let scheme = if tls { "https" } else { "http" };
+    let url = format!("{}://{}:{}/", scheme, name, addr.port());
+    let response = client.get(url).send().await?;
+    Ok(response.status().as_u16())
+}
+
+// 經由已連線的串流 (SSH 通道) 送出 HTTP/1.1 請求，只讀取狀態列
+pub async fn request_on<S>(stream: S, name: &str, tls: bool, insecure: bool) -> Result<u16, RequestError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let attempt = async {
+        match tls {
+            true => {
+                let connector = native_tls::TlsConnector::builder()
+                    .danger_accept_invalid_certs(insecure)
+                    .build()
+                    .map_err(|e| e.to_string())?;
+                let stream = tokio_native_tls::TlsConnector::from(connector)
+                    .connect(name, stream)
+                    .await
+                    .map_err(|e| RequestError { certificate: is_certificate_error(&e), message: e.to_string() })?;
+                Ok(status_line(stream, name).await?)
+            }
+            false => Ok(status_line(stream, name).await?),
+        }
+    };
+    tokio::time::timeout(WEB_TIMEOUT, attempt).await.map_err(|_| RequestError::from("逾時".to_string()))?
+}
+
+async fn status_line<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, name: &str) -> Result<u16, String> {
+    let request = format!("GET / HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", name);
+    stream.write_all(request.as_bytes()).await.map_err(|e| e.to_string())?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).await.map_err(|e| e.to_string())?;
+    parse_status(&line).ok_or_else(|| format!("無效的 HTTP 回應: {}", line.trim()))
+}
+
+// "HTTP/1.1 200 OK" -> 200
+pub fn parse_status(line: &str) -> Option<u16> {
+    let mut parts = line.split_whitespace();
+    parts.next().filter(|version| version.starts_with("HTTP/"))?;
+    parts.next()?.parse().ok()
+}
+
+// request 以 insecure 參數決定是否略過憑證驗證
+pub async fn probe_one<F, Fut>(name: &str, tls: bool, request: F) -> VhostResult
+where
+    F: Fn(bool) -> Fut,
+    Fut: Future<Output = Result<u16, RequestError>>,
+{
+    let mut result = VhostResult {
+        name: name.to_string(),
+        tls,
+        status: None,
+        cert_valid: None,
+        error: None,
+    };
+
+    match request(false).await {
+        Ok(status) => {
+            result.status = Some(status);
+            result.cert_valid = tls.then_some(true);
+        }
+        // 憑證不符時略過驗證再請求一次，仍取得狀態碼
+        Err(e) if tls && e.certificate => {
+            result.cert_valid = Some(false);
+            match request(true).await {
+                Ok(status) => result.status = Some(status),
+                Err(e) => result.error = Some(e.message),
+            }
+        }
+        Err(e) => result.error = Some(e.message),
+    }
+
+    result
+}
+
+// 對同一個位址依序探測每個虛擬主機名稱
+pub async fn probe_vhosts(context: &ScanContext, addr: IpAddr, port: &PortInfo, names: &[String]) -> Vec<VhostResult> {
+    let tls = uses_tls(port);
+    let socket = context.socket_addr(addr, port.port);
+
+    let mut results = Vec::with_capacity(names.len());
+    for name in names {
+        results.push(probe_one(name, tls, |insecure| async move { Ok(request(socket, name, tls, insecure).await?) }).await);
+    }
+    results
+}
+
+// 顯示用的一行描述
+pub fn describe(result: &VhostResult) -> String {
+    let status = match (result.status, &result.error) {
+        (Some(421), _) => "421 (伺服器不服務此名稱)".to_string(),
+        (Some(code), _) => code.to_string(),
+        (None, Some(e)) => format!("失敗: {}", e),
+        (None, None) => "無回應".to_string(),
+    };
+    match result.cert_valid {
+        Some(true) => format!("{}  憑證 ✓", status),
+        Some(false) => format!("{}  憑證 ✗ (名稱不符或不受信任)", status),
+        None => status,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[test]
+    fn status_lines() {
+        assert_eq!(parse_status("HTTP/1.1 301 Moved Permanently\r\n"), Some(301));
+        assert_eq!(parse_status("HTTP/2 200"), Some(200));
+        assert_eq!(parse_status("SSH-2.0-OpenSSH_9.6\r\n"), None);
+        assert_eq!(parse_status("HTTP/1.1\r\n"), None);
+    }
+
+    #[tokio::test]
+    async fn plain_requests_over_a_stream_send_the_host_name() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        let serve = tokio::spawn(async move {
+            let mut request = vec![0; 512];
+            let len = server.read(&mut request).await.unwrap();
+            server.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n").await.unwrap();
+            String::from_utf8_lossy(&request[..len]).to_string()
+        });
+        assert_eq!(request_on(client, "intranet.example", false, false).await.ok(), Some(404));
+        assert!(serve.await.unwrap().contains("Host: intranet.example\r\n"));
+    }
+}
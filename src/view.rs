@@ -0,0 +1,277 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use clap::ValueEnum;
+use crate::cloud::CloudContext;
+use crate::layout::Layout;
+use crate::threats::ThreatTable;
+use crate::trend::Glyphs;
+use crate::zone::Zones;
+use crate::{anonymize, PortInfo, ScanResult};
+
+// 結果分組方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum GroupBy {
+    #[default]
+    Category,
+    State,
+    // 依設定檔標籤；多個標籤的端口出現在每個標籤下
+    Tag,
+    None,
+}
+
+// 組內排序方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SortBy {
+    #[default]
+    Port,
+    Latency,
+    State,
+}
+
+// 結果顯示方式
+#[derive(Debug, Clone, Default)]
+pub struct ResultView {
+    pub group_by: GroupBy,
+    pub sort: SortBy,
+    // --expand-groups：服務群組逐一列出成員端口
+    pub expand_groups: bool,
+    // 信心分數低於此值的狀態加上 "?"
+    pub low_confidence: f64,
+    // 結果標題的主機標示
+    pub hosts: Arc<HostLabels>,
+    // 端口行標示的可疑端口
+    pub threats: Arc<ThreatTable>,
+    // 端口行標示的雲端平台註記 (--cloud / --detect-cloud)
+    pub cloud: CloudContext,
+    // 終端寬度 (--width)；決定欄寬、截斷與窄終端的兩行排版
+    pub layout: Layout,
+    // --trend 趨勢圖的字元
+    pub glyphs: Glyphs,
+}
+
+// 結果標題中主機的標示：連結本地位址的 zone 與萬用字元目標展開的名稱
+#[derive(Debug, Clone, Default)]
+pub struct HostLabels {
+    pub zones: Zones,
+    pub names: BTreeMap<IpAddr, Vec<String>>,
+}
+
+impl HostLabels {
+    // 例如 "fe80::1%eth0" 或 "192.0.2.10 ← www.example.com, api.example.com"
+    pub fn title(&self, host: IpAddr) -> String {
+        let shown = self.zones.display(host);
+        match self.names.get(&host) {
+            Some(names) if !names.is_empty() => format!("{} ← {}", shown, names.join(", ")),
+            _ => shown,
+        }
+    }
+
+    // 掃描結束後結果換成假名時，名稱表也一起換
+    pub fn anonymize(&self, anonymizer: &anonymize::Anonymizer) -> Self {
+        HostLabels {
+            zones: Zones::default(),
+            names: self
+                .names
+                .iter()
+                .map(|(addr, list)| (anonymizer.ip(*addr), list.iter().map(|name| anonymizer.hostname(name)).collect()))
+                .collect(),
+        }
+    }
+}
+
+// 狀態順序與圖例一致：雙向、只能接收、只能發送、不可用
+pub fn state_rank(result: &ScanResult) -> usize {
+    match result.directions.normalize(result.inbound, result.outbound) {
+        (true, true) => 0,
+        (true, false) => 1,
+        (false, true) => 2,
+        (false, false) => 3,
+    }
+}
+
+const STATE_TITLES: [&str; 4] = ["✓ 雙向可用", "↓ 只能接收", "↑ 只能發送", "✗ 不可用"];
+
+pub type Entry<'a> = (&'a PortInfo, &'a ScanResult);
+
+// 依排序方式排列；沒有延遲的結果排在最後，同值時依端口
+pub fn sort_entries(entries: &mut [Entry], sort: SortBy) {
+    match sort {
+        SortBy::Port => entries.sort_by_key(|(p, _)| (p.port, p.service.clone())),
+        SortBy::State => entries.sort_by_key(|(p, r)| (state_rank(r), p.port)),
+        SortBy::Latency => entries.sort_by(|(pa, ra), (pb, rb)| match (ra.latency_ms, rb.latency_ms) {
+            (Some(a), Some(b)) => a.total_cmp(&b).then(pa.port.cmp(&pb.port)),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => pa.port.cmp(&pb.port),
+        }),
+    }
+}
+
+// 排序後分組；回傳 (標題, 項目)，不分組時標題為 None
+pub fn arrange<'a>(results: impl Iterator<Item = Entry<'a>>, view: &ResultView) -> Vec<(Option<String>, Vec<Entry<'a>>)> {
+    let mut entries: Vec<Entry> = results.collect();
+    sort_entries(&mut entries, view.sort);
+
+    match view.group_by {
+        GroupBy::None => vec![(None, entries)],
+        GroupBy::Category => {
+            let mut groups: BTreeMap<&str, Vec<Entry>> = BTreeMap::new();
+            for entry in entries {
+                groups.entry(entry.0.category.as_str()).or_default().push(entry);
+            }
+            groups
+                .into_iter()
+                .map(|(category, items)| (Some(category.to_string()), items))
+                .collect()
+        }
+        GroupBy::Tag => {
+            let mut groups: BTreeMap<&str, Vec<Entry>> = BTreeMap::new();
+            let mut untagged = Vec::new();
+            for entry in entries {
+                if entry.0.tags.is_empty() {
+                    untagged.push(entry);
+                }
+                for tag in &entry.0.tags {
+                    groups.entry(tag.as_str()).or_default().push(entry);
+                }
+            }
+            let mut arranged: Vec<(Option<String>, Vec<Entry>)> = groups
+                .into_iter()
+                .map(|(tag, items)| (Some(format!("#{} ({})", tag, items.len())), items))
+                .collect();
+            if !untagged.is_empty() {
+                arranged.push((Some(format!("無標籤 ({})", untagged.len())), untagged));
+            }
+            arranged
+        }
+        GroupBy::State => {
+            let mut groups: [Vec<Entry>; 4] = Default::default();
+            for entry in entries {
+                groups[state_rank(entry.1)].push(entry);
+            }
+            groups
+                .into_iter()
+                .enumerate()
+                .filter(|(_, items)| !items.is_empty())
+                .map(|(rank, items)| (Some(format!("{} ({})", STATE_TITLES[rank], items.len())), items))
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::scan_result;
+
+    // 合成的結果：四種狀態、兩個類別，部分有延遲
+    fn synthetic() -> Vec<(PortInfo, ScanResult)> {
+        let result = |inbound: bool, outbound: bool, latency_ms: Option<f64>| {
+            let mut result = scan_result(outbound);
+            result.inbound = inbound;
+            result.latency_ms = latency_ms;
+            result
+        };
+        let mut tagged = PortInfo::new(5432, "PostgreSQL", "Database");
+        tagged.tags = vec!["env:prod".to_string(), "tier:db".to_string()];
+        vec![
+            (PortInfo::new(443, "HTTPS", "Web"), result(true, true, Some(40.0))),
+            (PortInfo::new(80, "HTTP", "Web"), result(false, true, Some(15.0))),
+            (PortInfo::new(8080, "HTTP-ALT", "Web"), result(false, false, None)),
+            (tagged, result(true, false, None)),
+            (PortInfo::new(3306, "MySQL", "Database"), result(false, true, Some(15.0))),
+            (PortInfo::new(22, "SSH", "Remote"), result(true, true, Some(2.5))),
+        ]
+    }
+
+    fn layout(results: &[(PortInfo, ScanResult)], group_by: GroupBy, sort: SortBy) -> Vec<(Option<String>, Vec<u16>)> {
+        let view = ResultView { group_by, sort, ..Default::default() };
+        arrange(results.iter().map(|(p, r)| (p, r)), &view)
+            .into_iter()
+            .map(|(title, entries)| (title, entries.iter().map(|(p, _)| p.port).collect()))
+            .collect()
+    }
+
+    fn title(text: &str) -> Option<String> {
+        Some(text.to_string())
+    }
+
+    #[test]
+    fn default_groups_by_category_sorted_by_port() {
+        let results = synthetic();
+        assert_eq!(
+            layout(&results, GroupBy::default(), SortBy::default()),
+            vec![
+                (title("Database"), vec![3306, 5432]),
+                (title("Remote"), vec![22]),
+                (title("Web"), vec![80, 443, 8080]),
+            ]
+        );
+    }
+
+    #[test]
+    fn flat_orders() {
+        let results = synthetic();
+        assert_eq!(layout(&results, GroupBy::None, SortBy::Port), vec![(None, vec![22, 80, 443, 3306, 5432, 8080])]);
+        // 同狀態依端口
+        assert_eq!(layout(&results, GroupBy::None, SortBy::State), vec![(None, vec![22, 443, 5432, 80, 3306, 8080])]);
+        // 同延遲依端口，沒有延遲的排在最後
+        assert_eq!(layout(&results, GroupBy::None, SortBy::Latency), vec![(None, vec![22, 80, 3306, 443, 5432, 8080])]);
+    }
+
+    #[test]
+    fn state_groups_use_legend_titles_with_counts() {
+        let results = synthetic();
+        assert_eq!(
+            layout(&results, GroupBy::State, SortBy::Latency),
+            vec![
+                (title("✓ 雙向可用 (2)"), vec![22, 443]),
+                (title("↓ 只能接收 (1)"), vec![5432]),
+                (title("↑ 只能發送 (2)"), vec![80, 3306]),
+                (title("✗ 不可用 (1)"), vec![8080]),
+            ]
+        );
+        // 沒有結果的狀態不顯示
+        let open: Vec<_> = results.into_iter().filter(|(_, r)| r.outbound).collect();
+        let titles: Vec<_> = layout(&open, GroupBy::State, SortBy::Port).into_iter().map(|(t, _)| t.unwrap()).collect();
+        assert_eq!(titles, vec!["✓ 雙向可用 (2)", "↑ 只能發送 (2)"]);
+    }
+
+    #[test]
+    fn category_groups_keep_the_chosen_order() {
+        let results = synthetic();
+        assert_eq!(
+            layout(&results, GroupBy::Category, SortBy::Latency),
+            vec![
+                (title("Database"), vec![3306, 5432]),
+                (title("Remote"), vec![22]),
+                (title("Web"), vec![80, 443, 8080]),
+            ]
+        );
+        assert_eq!(layout(&results, GroupBy::Category, SortBy::State)[2], (title("Web"), vec![443, 80, 8080]));
+    }
+
+    #[test]
+    fn tag_groups_repeat_multi_tagged_ports() {
+        let results = synthetic();
+        assert_eq!(
+            layout(&results, GroupBy::Tag, SortBy::Port),
+            vec![
+                (title("#env:prod (1)"), vec![5432]),
+                (title("#tier:db (1)"), vec![5432]),
+                (title("無標籤 (5)"), vec![22, 80, 443, 3306, 8080]),
+            ]
+        );
+    }
+
+    #[test]
+    fn untested_directions_rank_by_the_tested_one() {
+        // --no-inbound 時入站為 false 只是未測試，可連線的端口排在最前
+        let mut result = scan_result(true);
+        result.directions = crate::direction::Directions::Outbound;
+        assert_eq!(state_rank(&result), 0);
+        result.outbound = false;
+        assert_eq!(state_rank(&result), 3);
+    }
+}
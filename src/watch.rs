@@ -0,0 +1,350 @@
+use std::error::Error;
+use std::time::{Duration, Instant};
+use colored::*;
+use serde::Serialize;
+use crate::alerts::{Alert, AlertEngine, Change, Severity};
+use crate::backoff::{self, Backoff, Reload};
+use crate::checks::health;
+use crate::clock::{self, JumpDetector, TimeJump};
+use crate::config::WatchConfig;
+use crate::context::ScanContext;
+use crate::fingerprints::{self, Tracker};
+use crate::eventlog::{
+    EventLevel, EventLog, EVENT_ALERT, EVENT_EXTERNAL_IP_CHANGED, EVENT_SERVICE_RESTART, EVENT_STATE_CHANGED,
+};
+use crate::adaptive;
+use crate::hooks::{self, HookEvent, HookRunner};
+use crate::notify::{self, Digest, EmailConfig, QuietHours, Route, Router, SystemClock, Verdict};
+use crate::restarts::{Restart, RestartDetector};
+use crate::scanid;
+use crate::scanner::ScanPlan;
+use crate::share::{ShareLine, Tally};
+use crate::timefmt;
+use crate::direction::Directions;
+use crate::view::ResultView;
+
+// webhook 送出的逾時
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 外部IP變更事件
+#[derive(Debug, Serialize)]
+struct ExternalIpChange<'a> {
+    event: &'static str,
+    iteration: u64,
+    old: Option<&'a str>,
+    new: &'a str,
+}
+
+// 事件記錄的端口狀態改變
+#[derive(Debug, Serialize)]
+struct StateChanges<'a> {
+    iteration: u64,
+    changes: &'a [Change],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clock_jump: Option<&'a TimeJump>,
+}
+
+// 監看結束時的摘要：最後一次掃描的計數，以及期間是否有端口狀態或指紋改變
+pub struct Outcome {
+    pub tally: Tally,
+    pub changed: bool,
+}
+
+// 命令列決定的監看選項
+pub struct Options {
+    pub interval: Duration,
+    // 結果標題列出主機 (指定了 --target)
+    pub show_host: bool,
+    // 不回應目標的退避狀態
+    pub backoff: Backoff,
+}
+
+// 定期重新掃描，顯示狀態改變並評估告警規則，直到 Ctrl+C
+pub async fn run(
+    plan: &ScanPlan,
+    watch: &WatchConfig,
+    options: Options,
+    result_view: ResultView,
+    eventlog: Option<&EventLog>,
+    mut fingerprints: Option<&mut Tracker>,
+) -> Result<Outcome, Box<dyn Error>> {
+    let Options { interval, show_host, mut backoff } = options;
+    let mut engine = AlertEngine::new(watch.alerts.clone());
+    let mut restarts = RestartDetector::new(watch.restart_window);
+    let started = Instant::now();
+    let client = reqwest::Client::builder().timeout(WEBHOOK_TIMEOUT).build()?;
+    let webhook = watch.webhook.as_deref();
+    let quiet = watch.quiet_hours.as_deref().map(QuietHours::parse).transpose()?;
+    let mut router = Router::new(SystemClock, watch.dedup_window, quiet);
+    // 上次檢查發現外部IP變更，本次掃描的入站結果要標示出來
+    let mut ip_changed = false;
+    // 第一次掃描由掃描器觸發 --on-open，之後改由狀態改變觸發
+    let hooks = plan.hooks.clone();
+    let mut plan = plan.clone();
+    let mut outcome = Outcome { tally: Tally::default(), changed: false };
+    // 間隔與耗時以單調時間計算；時間跳變或休眠只標示在受影響的那次掃描
+    let mut clock_jumps = JumpDetector::new(SystemClock);
+    let targets = plan.targets.clone();
+    let mut reload = Reload::listen();
+
+    loop {
+        let (due, skipped) = backoff.partition(&targets, Instant::now());
+        plan.targets = due;
+        let scanned_at = Instant::now();
+        let mut results = match plan.targets.is_empty() {
+            true => Default::default(),
+            false => crate::perform_scan(&plan, None, false, false).await,
+        };
+        backoff.record(&plan.targets, &results, Instant::now());
+        if let Err(e) = backoff.persist() {
+            eprintln!("{}", e.red());
+        }
+        plan.hooks = None;
+        plan.dependencies.annotate(&mut results);
+        // 健康狀態需在評估告警規則之前取得
+        health::probe_results(&mut results, &plan).await;
+        // 資料庫暫時無法寫入時只略過這次比對，不中斷監看
+        let fingerprint_report = match fingerprints.as_deref_mut() {
+            Some(tracker) => tracker.observe(&mut results, &plan).await.map_err(|e| eprintln!("{}", e.red())).ok(),
+            None => None,
+        };
+        let clock_jump = clock_jumps.take();
+        let (changes, mut alerts) = engine.observe(&results);
+        let mut share = ShareLine::default();
+        for (port, result) in results.values().flatten() {
+            share.add_result(port.port, result);
+        }
+        share.finish(scanned_at.elapsed());
+        outcome.tally = share.tally();
+        outcome.changed |= !changes.is_empty()
+            || fingerprint_report.as_ref().is_some_and(|report| report.unaccepted().next().is_some());
+        if let Some(report) = &fingerprint_report {
+            alerts.extend(fingerprints::alerts(report, engine.iteration(), &plan.context));
+        }
+        if let Some(hooks) = &hooks {
+            fire_hooks(hooks, &changes);
+        }
+        let detected = restarts.observe(engine.iteration(), started.elapsed(), &results);
+
+        if engine.iteration() == 1 {
+            if let Some(jump) = &clock_jump {
+                println!("{}", format!("⚠ {}", jump.describe()).yellow());
+            }
+            crate::show_external_ip(&plan.context, None).await;
+            for (host, host_results) in &results {
+                crate::display_results(show_host.then_some(*host), host_results, None, &result_view);
+            }
+            crate::print_legend(plan.directions);
+            if let Some(report) = &fingerprint_report {
+                fingerprints::display(report, &plan.context);
+            }
+        } else {
+            display_changes(engine.iteration(), &changes, ip_changed, clock_jump.as_ref(), plan.directions);
+        }
+        backoff::display(&skipped);
+
+        for restart in &detected {
+            report_restart(&client, restart, webhook, &plan.context.scan_id, eventlog).await;
+        }
+
+        if let (Some(log), false) = (eventlog, changes.is_empty()) {
+            let message = format!("第 {} 次掃描有 {} 個端口狀態改變", engine.iteration(), changes.len());
+            let payload = StateChanges { iteration: engine.iteration(), changes: &changes, clock_jump: clock_jump.as_ref() };
+            log.report(EventLevel::Warning, EVENT_STATE_CHANGED, &message, &payload);
+        }
+
+        for alert in &mut alerts {
+            alert.external_ip_changed = ip_changed;
+            alert.clock_jump = clock_jump.is_some();
+        }
+        for alert in alerts {
+            let alert = match router.submit(alert) {
+                Verdict::Send(alert, route) => {
+                    deliver(&client, &alert, route, webhook, &plan.context.scan_id, watch.email.as_ref()).await;
+                    alert
+                }
+                Verdict::Suppressed { rule, count } => {
+                    println!("{}", format!("[{}] 重複告警已抑制 (第 {} 次)", rule, count).dimmed());
+                    continue;
+                }
+                Verdict::Queued(alert) => {
+                    println!("{} [{}] {} {}", "告警".yellow().bold(), alert.rule, alert.message, "(靜音時段，稍後彙整送出)".dimmed());
+                    alert
+                }
+            };
+            if let Some(log) = eventlog {
+                let level = match alert.severity {
+                    Severity::Info => EventLevel::Information,
+                    Severity::Warning => EventLevel::Warning,
+                    Severity::Critical => EventLevel::Error,
+                };
+                log.report(level, EVENT_ALERT, &format!("[{}] {}", alert.rule, alert.message), &alert);
+            }
+        }
+        if let Some(digest) = router.digest() {
+            deliver_digest(&client, &digest, webhook, &plan.context.scan_id).await;
+        }
+
+        ip_changed = false;
+        if watch.ip_check_every > 0 && engine.iteration().is_multiple_of(watch.ip_check_every) {
+            ip_changed = check_external_ip(&plan.context, &client, engine.iteration(), webhook, eventlog).await;
+        }
+
+        println!("\n下次掃描於 {} 後 (按 Ctrl+C 結束)", timefmt::duration(interval));
+        tokio::select! {
+            _ = clock::wait(interval, &mut clock_jumps) => {}
+            _ = reload.recv() => {
+                backoff.reset();
+                if let Err(e) = backoff.persist() {
+                    eprintln!("{}", e.red());
+                }
+                println!("{}", "收到 SIGHUP：已清除所有目標的退避，立即重新掃描".yellow());
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    restarts.display_summary(started.elapsed());
+    if router.pending() > 0 {
+        println!("{}", format!("{} 個靜音時段中的告警尚未送出", router.pending()).yellow());
+    }
+    if let Some(hooks) = &hooks {
+        hooks.wait().await;
+        hooks.display_summary();
+    }
+    if let Some(limit) = &plan.adaptive {
+        adaptive::display_summary(&limit.summary());
+    }
+    Ok(outcome)
+}
+
+// 狀態改變觸發 --on-change；變為可出站連線時同時觸發 --on-open
+fn fire_hooks(hooks: &HookRunner, changes: &[Change]) {
+    for change in changes {
+        let state = hooks::state_name(change.after.0, change.after.1);
+        let old_state = hooks::state_name(change.before.0, change.before.1);
+        hooks.fire(HookEvent::Change, change.host, &change.port, state, Some(old_state));
+        if change.after.1 && !change.before.1 {
+            hooks.fire(HookEvent::Open, change.host, &change.port, state, Some(old_state));
+        }
+    }
+}
+
+// 重新查詢外部IP，變更時更新並通知；查詢失敗時沿用原本的值
+async fn check_external_ip(
+    context: &ScanContext,
+    client: &reqwest::Client,
+    iteration: u64,
+    webhook: Option<&str>,
+    eventlog: Option<&EventLog>,
+) -> bool {
+    let Ok(ip) = context.fetch_external_ip().await else {
+        println!("{}", "無法重新確認外部 IP，沿用先前的值".yellow());
+        return false;
+    };
+
+    let old = context.external_ip();
+    if old.as_deref() == Some(ip.as_str()) {
+        return false;
+    }
+    context.set_external_ip(ip.clone());
+
+    let message = format!("外部 IP 已變更: {} → {}", old.as_deref().unwrap_or("未知"), ip);
+    println!("{} {}", "告警".red().bold(), message);
+
+    let event = ExternalIpChange {
+        event: "external_ip_changed",
+        iteration,
+        old: old.as_deref(),
+        new: &ip,
+    };
+    if let Some(url) = webhook {
+        post(client, url, &context.scan_id, &event).await;
+    }
+    if let Some(log) = eventlog {
+        log.report(EventLevel::Warning, EVENT_EXTERNAL_IP_CHANGED, &message, &event);
+    }
+    true
+}
+
+// 顯示與上一次掃描的差異
+fn display_changes(iteration: u64, changes: &[Change], ip_changed: bool, clock_jump: Option<&TimeJump>, directions: Directions) {
+    println!("\n{}", format!("=== 第 {} 次掃描 ({}) ===", iteration, timefmt::timestamp(timefmt::now())).bold());
+    if ip_changed {
+        println!("{}", "外部IP已變更：入站結果不能直接與先前的掃描比較".yellow());
+    }
+    if let Some(jump) = clock_jump {
+        println!("{}", format!("⚠ {}", jump.describe()).yellow());
+    }
+    if changes.is_empty() {
+        println!("沒有端口狀態改變");
+        return;
+    }
+
+    for change in changes {
+        println!(
+            "{} Port {:5} ({:15}): {} → {}",
+            change.host,
+            change.port.port,
+            change.port.service,
+            crate::portline::status_label(directions, change.before.0, change.before.1),
+            crate::portline::status_label(directions, change.after.0, change.after.1),
+        );
+    }
+}
+
+// 疑似重啟與一般狀態改變分開顯示，並以獨立的事件類型通知
+async fn report_restart(client: &reqwest::Client, restart: &Restart, webhook: Option<&str>, scan_id: &str, eventlog: Option<&EventLog>) {
+    let message = restart.message();
+    println!("{} {}", "重啟".yellow().bold(), message);
+    if let Some(url) = webhook {
+        post(client, url, scan_id, restart).await;
+    }
+    if let Some(log) = eventlog {
+        log.report(EventLevel::Warning, EVENT_SERVICE_RESTART, &message, restart);
+    }
+}
+
+// 顯示告警並依嚴重程度送到 webhook 與電子郵件
+async fn deliver(client: &reqwest::Client, alert: &Alert, route: Route, webhook: Option<&str>, scan_id: &str, email: Option<&EmailConfig>) {
+    let repeated = match alert.suppressed {
+        0 => String::new(),
+        n => format!(" (另有 {} 次重複已抑制)", n),
+    };
+    let label = match alert.severity {
+        Severity::Info => "告警".normal(),
+        Severity::Warning => "告警".red().bold(),
+        Severity::Critical => "嚴重告警".red().bold().reversed(),
+    };
+    println!("{} [{}] {}{}", label, alert.rule, alert.message, repeated);
+    if let (Some(url), true) = (webhook, route.webhook) {
+        post(client, url, scan_id, alert).await;
+    }
+    if let (Some(email), true) = (email, route.email) {
+        let subject = format!("[portscanner] {} {}", alert.severity.label(), alert.rule);
+        let body = format!("{}{}\n\n{}\n", alert.message, repeated, serde_json::to_string_pretty(&scanid::tag(scan_id, alert)).unwrap_or_default());
+        if let Err(e) = notify::email(email, &subject, &body).await {
+            println!("{}", format!("告警郵件寄送失敗: {}", e).yellow());
+        }
+    }
+}
+
+// 靜音時段結束後，暫存的告警以一個事件送到 webhook
+async fn deliver_digest(client: &reqwest::Client, digest: &Digest, webhook: Option<&str>, scan_id: &str) {
+    println!("{} 靜音時段結束，彙整送出 {} 個告警", "告警".red().bold(), digest.alerts.len());
+    for alert in &digest.alerts {
+        println!("  [{}] {}", alert.rule, alert.message);
+    }
+    if let Some(url) = webhook {
+        post(client, url, scan_id, digest).await;
+    }
+}
+
+// POST 加上掃描 ID 的 JSON 到 webhook；送出失敗只顯示警告
+async fn post(client: &reqwest::Client, url: &str, scan_id: &str, payload: &impl Serialize) {
+    match client.post(url).json(&scanid::tag(scan_id, payload)).send().await {
+        Ok(response) if response.status().is_success() => {}
+        Ok(response) => println!("{}", format!("webhook 回應 {}", response.status()).yellow()),
+        Err(e) => println!("{}", format!("webhook 送出失敗: {}", e).yellow()),
+    }
+}
@@ -0,0 +1,314 @@
+use std::net::IpAddr;
+use std::time::Duration;
+use schemars::JsonSchema;
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use crate::targets::TargetSpec;
+
+// 起始查詢伺服器，由此取得負責該位址的 RIR
+const IANA_SERVER: &str = "whois.iana.org";
+
+// 每次查詢的逾時與回應大小上限
+const WHOIS_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_RESPONSE: usize = 64 * 1024;
+
+// 最多跟隨的轉介次數
+const MAX_REFERRALS: usize = 3;
+
+// 從 WHOIS 回應中取出的資訊
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct WhoisInfo {
+    pub netname: Option<String>,
+    pub org: Option<String>,
+    pub abuse: Option<String>,
+    pub server: String,
+}
+
+// 送出一次 WHOIS 查詢 (TCP 43)
+async fn query(server: &str, port: u16, request: &str) -> Result<String, String> {
+    let exchange = async {
+        let mut stream = TcpStream::connect((server, port)).await?;
+        stream.write_all(format!("{}\r\n", request).as_bytes()).await?;
+
+        let mut response = Vec::new();
+        let mut chunk = [0u8; 4096];
+        loop {
+            let n = stream.read(&mut chunk).await?;
+            if n == 0 || response.len() >= MAX_RESPONSE {
+                break;
+            }
+            response.extend_from_slice(&chunk[..n]);
+        }
+        Ok::<_, std::io::Error>(String::from_utf8_lossy(&response).into_owned())
+    };
+
+    match timeout(WHOIS_TIMEOUT, exchange).await {
+        Ok(Ok(text)) => Ok(text),
+        Ok(Err(e)) => Err(format!("{}: {}", server, e)),
+        Err(_) => Err(format!("{}: 逾時", server)),
+    }
+}
+
+// 取出 "key: value" 行，鍵不分大小寫
+fn field<'a>(text: &'a str, keys: &[&str]) -> Option<&'a str> {
+    for key in keys {
+        for line in text.lines() {
+            let Some((k, v)) = line.split_once(':') else {
+                continue;
+            };
+            let v = v.trim();
+            if k.trim().eq_ignore_ascii_case(key) && !v.is_empty() {
+                return Some(v);
+            }
+        }
+    }
+    None
+}
+
+// 解析轉介伺服器 (IANA 的 refer:/whois:，ARIN 的 ReferralServer: whois://host:port)
+pub fn parse_referral(text: &str) -> Option<(String, u16)> {
+    let value = field(text, &["refer", "whois", "ReferralServer"])?;
+    let value = value.strip_prefix("whois://").unwrap_or(value);
+    if value.contains("://") {
+        // rwhois:// 等其他協定不跟隨
+        return None;
+    }
+
+    let value = value.trim_end_matches('/');
+    match value.rsplit_once(':') {
+        Some((host, port)) => Some((host.to_string(), port.parse().ok()?)),
+        None => Some((value.to_string(), 43)),
+    }
+}
+
+// RIPE 系列會以註解行提供 abuse 聯絡方式：% Abuse contact for '...' is 'abuse@example.net'
+fn ripe_abuse_comment(text: &str) -> Option<String> {
+    text.lines()
+        .find(|line| line.starts_with('%') && line.contains("Abuse contact for"))
+        .and_then(|line| line.rsplit(" is ").next())
+        .map(|value| value.trim().trim_matches('\'').to_string())
+        .filter(|value| value.contains('@'))
+}
+
+// 解析 RIR 回應，相容 RIPE/APNIC/AFRINIC (小寫連字號) 與 ARIN (駝峰) 鍵名
+pub fn parse_response(server: &str, text: &str) -> WhoisInfo {
+    WhoisInfo {
+        netname: field(text, &["netname", "NetName", "inetnum-name"]).map(str::to_string),
+        org: field(text, &["org-name", "OrgName", "owner", "Organization", "descr"]).map(str::to_string),
+        abuse: field(text, &["OrgAbuseEmail", "abuse-mailbox", "RAbuseEmail"])
+            .map(str::to_string)
+            .or_else(|| ripe_abuse_comment(text)),
+        server: server.to_string(),
+    }
+}
+
+// 各 RIR 的查詢語法略有不同
+fn request_for(server: &str, addr: IpAddr) -> String {
+    if server.eq_ignore_ascii_case("whois.arin.net") {
+        format!("n + {}", addr)
+    } else {
+        addr.to_string()
+    }
+}
+
+// 非公網位址查詢 WHOIS 沒有意義
+fn is_public(addr: IpAddr) -> bool {
+    match addr {
+        IpAddr::V4(v4) => !(v4.is_private() || v4.is_loopback() || v4.is_link_local() || v4.is_unspecified() || v4.is_broadcast() || v4.is_documentation()),
+        IpAddr::V6(v6) => !(v6.is_loopback() || v6.is_unspecified() || (v6.segments()[0] & 0xfe00) == 0xfc00 || (v6.segments()[0] & 0xffc0) == 0xfe80),
+    }
+}
+
+// 從 IANA 開始跟隨轉介查詢位址的 WHOIS 資訊
+pub async fn lookup(addr: IpAddr) -> Result<WhoisInfo, String> {
+    if !is_public(addr) {
+        return Err("私有或保留位址".to_string());
+    }
+
+    let mut server = (IANA_SERVER.to_string(), 43);
+    let mut info: Option<WhoisInfo> = None;
+
+    for _ in 0..=MAX_REFERRALS {
+        let text = query(&server.0, server.1, &request_for(&server.0, addr)).await?;
+        let parsed = parse_response(&server.0, &text);
+
+        // IANA 回應只用來找出 RIR，不當作結果
+        if server.0 != IANA_SERVER && (parsed.netname.is_some() || parsed.org.is_some()) {
+            info = Some(parsed);
+        }
+
+        match parse_referral(&text) {
+            Some(next) if next.0 != server.0 => server = next,
+            _ => break,
+        }
+    }
+
+    info.ok_or_else(|| "回應中沒有網段資訊".to_string())
+}
+
+// 每個目標查詢一次：主機查自己的位址，網段查網段位址
+pub async fn lookup_targets(targets: &[TargetSpec]) -> Vec<(TargetSpec, Result<WhoisInfo, String>)> {
+    let mut results = Vec::new();
+    for target in targets {
+        let addr = match target {
+            TargetSpec::Host { addr, .. } => *addr,
+            TargetSpec::Network(net, _) => net.network(),
+            TargetSpec::Unresolved(_) => continue,
+        };
+        results.push((target.clone(), lookup(addr).await));
+    }
+    results
+}
+
+// 找出某個主機所屬目標的 WHOIS 結果
+pub fn for_host(whois: &[(TargetSpec, Result<WhoisInfo, String>)], host: IpAddr) -> Option<&Result<WhoisInfo, String>> {
+    whois
+        .iter()
+        .find(|(target, _)| match target {
+            TargetSpec::Host { addr, .. } => *addr == host,
+            TargetSpec::Network(net, _) => net.contains(&host),
+            TargetSpec::Unresolved(_) => false,
+        })
+        .map(|(_, result)| result)
+}
+
+// 顯示用的一行描述
+pub fn describe(result: &Result<WhoisInfo, String>) -> String {
+    match result {
+        Ok(info) => {
+            let mut parts = Vec::new();
+            if let Some(netname) = &info.netname {
+                parts.push(netname.clone());
+            }
+            if let Some(org) = &info.org {
+                parts.push(org.clone());
+            }
+            if let Some(abuse) = &info.abuse {
+                parts.push(format!("abuse: {}", abuse));
+            }
+            format!("{} (來源 {})", parts.join(" / "), info.server)
+        }
+        Err(e) => format!("WHOIS 無法取得 ({})", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncBufReadExt;
+    use tokio::net::TcpListener;
+
+    const IANA: &str = include_str!("fixtures/whois/iana.txt");
+    const RIPE: &str = include_str!("fixtures/whois/ripe.txt");
+    const ARIN: &str = include_str!("fixtures/whois/arin.txt");
+    const ARIN_REFERRAL: &str = include_str!("fixtures/whois/arin-referral.txt");
+    const APNIC: &str = include_str!("fixtures/whois/apnic.txt");
+    const LACNIC: &str = include_str!("fixtures/whois/lacnic.txt");
+    const RWHOIS_REFERRAL: &str = include_str!("fixtures/whois/rwhois-referral.txt");
+
+    fn info(netname: Option<&str>, org: Option<&str>, abuse: Option<&str>, server: &str) -> WhoisInfo {
+        WhoisInfo {
+            netname: netname.map(str::to_string),
+            org: org.map(str::to_string),
+            abuse: abuse.map(str::to_string),
+            server: server.to_string(),
+        }
+    }
+
+    #[test]
+    fn referrals() {
+        assert_eq!(parse_referral(IANA), Some(("whois.ripe.net".to_string(), 43)));
+        assert_eq!(parse_referral(ARIN_REFERRAL), Some(("whois.apnic.net".to_string(), 43)));
+        assert_eq!(parse_referral("ReferralServer: whois://whois.example.net:4343/"), Some(("whois.example.net".to_string(), 4343)));
+        assert_eq!(parse_referral(RWHOIS_REFERRAL), None);
+        assert_eq!(parse_referral(RIPE), None);
+        assert_eq!(parse_referral(ARIN), None);
+        assert_eq!(parse_referral("refer: whois.example.net:x"), None);
+    }
+
+    #[test]
+    fn ripe_style_response() {
+        assert_eq!(
+            parse_response("whois.ripe.net", RIPE),
+            info(
+                Some("RIPE-NCC"),
+                Some("Reseaux IP Europeens Network Coordination Centre (RIPE NCC)"),
+                Some("abuse@ripe.net"),
+                "whois.ripe.net"
+            )
+        );
+        assert_eq!(
+            parse_response("whois.apnic.net", APNIC),
+            info(
+                Some("APNIC-LABS"),
+                Some("APNIC and Cloudflare DNS Resolver project"),
+                Some("helpdesk@apnic.net"),
+                "whois.apnic.net"
+            )
+        );
+    }
+
+    #[test]
+    fn arin_style_response() {
+        assert_eq!(
+            parse_response("whois.arin.net", ARIN),
+            info(Some("GOGL"), Some("Google LLC"), Some("network-abuse@google.com"), "whois.arin.net")
+        );
+        let parsed = parse_response("whois.arin.net", ARIN_REFERRAL);
+        assert_eq!(parsed.netname.as_deref(), Some("APNIC-203"));
+        assert_eq!(parsed.org.as_deref(), Some("Asia Pacific Network Information Centre (APNIC)"));
+    }
+
+    #[test]
+    fn lacnic_owner_and_iana_placeholder() {
+        assert_eq!(
+            parse_response("whois.lacnic.net", LACNIC),
+            info(None, Some("Núcleo de Inf. e Coord. do Ponto BR - NIC.BR"), None, "whois.lacnic.net")
+        );
+        // IANA 的回應沒有網段資訊
+        assert_eq!(parse_response(IANA_SERVER, IANA), info(None, None, None, IANA_SERVER));
+    }
+
+    #[test]
+    fn requests_and_public_addresses() {
+        let addr: IpAddr = "8.8.8.8".parse().unwrap();
+        assert_eq!(request_for("whois.arin.net", addr), "n + 8.8.8.8");
+        assert_eq!(request_for("whois.ripe.net", addr), "8.8.8.8");
+        assert!(is_public(addr));
+        for private in ["10.0.0.1", "192.168.1.1", "127.0.0.1", "169.254.1.1", "192.0.2.1", "::1", "fd00::1", "fe80::1"] {
+            assert!(!is_public(private.parse().unwrap()), "{}", private);
+        }
+        assert!(is_public("2001:4860:4860::8888".parse().unwrap()));
+    }
+
+    #[test]
+    fn descriptions() {
+        let found = Ok(info(Some("GOGL"), Some("Google LLC"), Some("network-abuse@google.com"), "whois.arin.net"));
+        assert_eq!(describe(&found), "GOGL / Google LLC / abuse: network-abuse@google.com (來源 whois.arin.net)");
+        assert_eq!(describe(&Err("whois.iana.org: 逾時".to_string())), "WHOIS 無法取得 (whois.iana.org: 逾時)");
+    }
+
+    #[tokio::test]
+    async fn queries_send_one_line_and_read_to_close() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = tokio::io::BufReader::new(stream);
+            let mut line = String::new();
+            stream.read_line(&mut line).await.unwrap();
+            stream.get_mut().write_all(ARIN.as_bytes()).await.unwrap();
+            line
+        });
+        let text = query("127.0.0.1", port, "n + 8.8.8.8").await.unwrap();
+        assert_eq!(server.await.unwrap(), "n + 8.8.8.8\r\n");
+        assert_eq!(parse_response("whois.arin.net", &text).netname.as_deref(), Some("GOGL"));
+    }
+
+    #[tokio::test]
+    async fn private_addresses_are_not_looked_up() {
+        assert_eq!(lookup("10.1.2.3".parse().unwrap()).await, Err("私有或保留位址".to_string()));
+    }
+}
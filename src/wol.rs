@@ -0,0 +1,401 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use colored::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use crate::anonymize;
+use crate::config;
+use crate::scanner::ScanPlan;
+use crate::context::ScanContext;
+
+// Wake-on-LAN 預設的 UDP 目的端口 (discard)
+pub const DEFAULT_PORT: u16 = 9;
+
+// 每個 MAC 送出的魔術封包數；UDP 可能遺失
+const REPEAT: usize = 3;
+
+// 判斷主機是否醒著時最多嘗試的端口數
+const PROBE_PORTS: usize = 3;
+
+// 沒有 MAC 的主機最多列出的數量
+const UNKNOWN_SHOWN: usize = 5;
+
+// 等待期間每次輪詢的間隔
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// 預設位置：設定目錄下的 macs.json
+pub fn default_path() -> Option<PathBuf> {
+    config::config_dir().map(|dir| dir.join("macs.json"))
+}
+
+// 乙太網路位址
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Mac(pub [u8; 6]);
+
+impl FromStr for Mac {
+    type Err = String;
+
+    // aa:bb:cc:dd:ee:ff 或 aa-bb-cc-dd-ee-ff
+    fn from_str(s: &str) -> Result<Self, String> {
+        let invalid = || format!("無效的 MAC 位址 '{}' (應為 aa:bb:cc:dd:ee:ff)", s);
+        let parts: Vec<&str> = s.trim().split([':', '-']).collect();
+        if parts.len() != 6 {
+            return Err(invalid());
+        }
+        let mut mac = [0u8; 6];
+        for (byte, part) in mac.iter_mut().zip(&parts) {
+            if part.len() != 2 {
+                return Err(invalid());
+            }
+            *byte = u8::from_str_radix(part, 16).map_err(|_| invalid())?;
+        }
+        Ok(Mac(mac))
+    }
+}
+
+impl fmt::Display for Mac {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", a, b, c, d, e, g)
+    }
+}
+
+impl Mac {
+    // 全為 0 的項目是尚未解析的鄰居
+    fn is_unset(&self) -> bool {
+        self.0 == [0; 6]
+    }
+}
+
+// 魔術封包：6 個 0xff 之後重複 16 次 MAC
+pub fn magic_packet(mac: Mac) -> [u8; 102] {
+    let mut packet = [0xffu8; 102];
+    for chunk in packet[6..].chunks_exact_mut(6) {
+        chunk.copy_from_slice(&mac.0);
+    }
+    packet
+}
+
+// --wol-mac HOST=MAC
+pub fn parse_mapping(s: &str) -> Result<(IpAddr, Mac), String> {
+    let (host, mac) = s
+        .split_once('=')
+        .ok_or_else(|| format!("--wol-mac 格式應為 IP=MAC: {}", s))?;
+    let host = host.trim().parse().map_err(|_| format!("--wol-mac 的主機應為 IP: {}", host))?;
+    Ok((host, mac.parse()?))
+}
+
+// 各目標的 MAC：--wol-mac 優先，其次是過去記下的位址
+pub fn resolve(plan: &ScanPlan, mappings: &[(IpAddr, Mac)]) -> Result<BTreeMap<IpAddr, Mac>, String> {
+    let book = match default_path() {
+        Some(path) => MacBook::load(&path)?,
+        None => MacBook::default(),
+    };
+    let mut macs: BTreeMap<IpAddr, Mac> = plan
+        .targets
+        .iter()
+        .flat_map(|target| target.addrs())
+        .filter_map(|host| book.get(host).map(|mac| (host, mac)))
+        .collect();
+    macs.extend(mappings.iter().copied());
+    Ok(macs)
+}
+
+// 過去掃描時從鄰居表學到的 MAC
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Learned {
+    mac: String,
+    // Unix 時間，只供檢視
+    seen: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MacBook {
+    #[serde(default)]
+    hosts: BTreeMap<IpAddr, Learned>,
+}
+
+impl MacBook {
+    // 檔案不存在時為空
+    pub fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(MacBook::default());
+        }
+        let text = fs::read_to_string(path).map_err(|e| format!("無法讀取 MAC 紀錄 {}: {}", path.display(), e))?;
+        serde_json::from_str(&text).map_err(|e| format!("MAC 紀錄 {} 格式錯誤: {}", path.display(), e))
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("無法建立 {}: {}", dir.display(), e))?;
+        }
+        let text = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, text).map_err(|e| format!("無法寫入 MAC 紀錄 {}: {}", path.display(), e))
+    }
+
+    pub fn get(&self, host: IpAddr) -> Option<Mac> {
+        self.hosts.get(&host).and_then(|learned| learned.mac.parse().ok())
+    }
+}
+
+// 掃描後記下鄰居表中掃描過的主機的 MAC，之後 --wol 可以不指定 MAC
+// 沒有設定目錄或讀不到鄰居表時不做任何事
+pub fn learn(hosts: impl IntoIterator<Item = IpAddr>) {
+    let Some(path) = default_path() else {
+        return;
+    };
+    let neighbors = neighbors();
+    if neighbors.is_empty() {
+        return;
+    }
+    let mut book = match MacBook::load(&path) {
+        Ok(book) => book,
+        Err(e) => {
+            eprintln!("{}", format!("{}，不記錄 MAC", e).yellow());
+            return;
+        }
+    };
+    let seen = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    let mut changed = false;
+    for host in hosts {
+        if let Some(mac) = neighbors.get(&host) {
+            let mac = mac.to_string();
+            if book.hosts.get(&host).is_none_or(|learned| learned.mac != mac) {
+                changed = true;
+            }
+            book.hosts.insert(host, Learned { mac, seen });
+        }
+    }
+    if changed {
+        if let Err(e) = book.save(&path) {
+            eprintln!("{}", e.yellow());
+        }
+    }
+}
+
+// 核心的 ARP 表 (IPv4)；只取已解析的項目
+#[cfg(target_os = "linux")]
+fn neighbors() -> BTreeMap<IpAddr, Mac> {
+    // 已解析 (ATF_COM)
+    const COMPLETE: u32 = 0x2;
+    let Ok(text) = fs::read_to_string("/proc/net/arp") else {
+        return BTreeMap::new();
+    };
+    text.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [addr, _, flags, mac, ..] = fields.as_slice() else {
+                return None;
+            };
+            let flags = u32::from_str_radix(flags.trim_start_matches("0x"), 16).ok()?;
+            let mac: Mac = mac.parse().ok().filter(|mac: &Mac| !mac.is_unset())?;
+            (flags & COMPLETE != 0).then_some((addr.parse().ok()?, mac))
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn neighbors() -> BTreeMap<IpAddr, Mac> {
+    BTreeMap::new()
+}
+
+// 目標所在網段的廣播位址；找不到相符的網路介面時為 255.255.255.255
+#[cfg(unix)]
+fn broadcast_for(host: Ipv4Addr) -> Ipv4Addr {
+    use ipnet::Ipv4Net;
+    let mut addrs: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut addrs) } != 0 {
+        return Ipv4Addr::BROADCAST;
+    }
+    let mut found = Ipv4Addr::BROADCAST;
+    let mut cursor = addrs;
+    while !cursor.is_null() {
+        // 安全性：cursor 來自 getifaddrs 的串列，freeifaddrs 之前都有效
+        let entry = unsafe { &*cursor };
+        cursor = entry.ifa_next;
+        if entry.ifa_addr.is_null() || entry.ifa_netmask.is_null() {
+            continue;
+        }
+        if unsafe { (*entry.ifa_addr).sa_family } as i32 != libc::AF_INET {
+            continue;
+        }
+        let (addr, mask) = unsafe {
+            let addr = &*(entry.ifa_addr as *const libc::sockaddr_in);
+            let mask = &*(entry.ifa_netmask as *const libc::sockaddr_in);
+            (
+                Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)),
+                Ipv4Addr::from(u32::from_be(mask.sin_addr.s_addr)),
+            )
+        };
+        if let Ok(net) = Ipv4Net::with_netmask(addr, mask) {
+            if net.prefix_len() < 31 && net.contains(&host) {
+                found = net.broadcast();
+                break;
+            }
+        }
+    }
+    unsafe { libc::freeifaddrs(addrs) };
+    found
+}
+
+#[cfg(not(unix))]
+fn broadcast_for(_host: Ipv4Addr) -> Ipv4Addr {
+    Ipv4Addr::BROADCAST
+}
+
+// 送出魔術封包到目標網段的廣播位址
+fn send(host: Ipv4Addr, mac: Mac, port: u16) -> io::Result<Ipv4Addr> {
+    let broadcast = broadcast_for(host);
+    let socket = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.set_broadcast(true)?;
+    let packet = magic_packet(mac);
+    for _ in 0..REPEAT {
+        socket.send_to(&packet, (broadcast, port))?;
+    }
+    Ok(broadcast)
+}
+
+// 主機是否醒著：任一端口完成連線或回應 RST 都算有回應
+async fn responds(context: &ScanContext, host: IpAddr, ports: &[u16], limit: Duration) -> bool {
+    for port in ports {
+        match timeout(limit, TcpStream::connect(context.socket_addr(host, *port))).await {
+            Ok(Ok(_)) => return true,
+            Ok(Err(e)) if e.kind() == io::ErrorKind::ConnectionRefused => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+// 單一主機的喚醒結果
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct WakeOutcome {
+    pub host: IpAddr,
+    // --anonymize 時不輸出
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+    // 送出魔術封包前已有回應
+    pub already_awake: bool,
+    // 送出魔術封包後開始回應的時間
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub responded_after_ms: Option<u64>,
+}
+
+// --wol 的結果；JSON 報告的 wake 欄位
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+pub struct WakeReport {
+    pub grace_ms: u64,
+    pub hosts: Vec<WakeOutcome>,
+    // 找不到 MAC 而沒有喚醒的主機
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unknown: Vec<IpAddr>,
+}
+
+impl WakeReport {
+    // 換成假名並移除 MAC
+    pub fn anonymize(&mut self, anonymizer: &anonymize::Anonymizer) {
+        for outcome in &mut self.hosts {
+            outcome.host = anonymizer.ip(outcome.host);
+            outcome.mac = None;
+        }
+        self.unknown = self.unknown.iter().map(|host| anonymizer.ip(*host)).collect();
+    }
+}
+
+// 先確認哪些主機醒著，對其餘主機送出魔術封包，在寬限期間內輪詢到全部回應或時間到
+// 寬限期間在標準錯誤顯示倒數；quiet 時不顯示
+pub async fn wake(
+    plan: &ScanPlan,
+    macs: &BTreeMap<IpAddr, Mac>,
+    grace: Duration,
+    port: u16,
+    quiet: bool,
+) -> Result<WakeReport, String> {
+    let probe_ports: Vec<u16> = plan.ports.iter().take(PROBE_PORTS).map(|p| p.port).collect();
+    let limit = plan.timeouts.default;
+    let mut report = WakeReport {
+        grace_ms: grace.as_millis() as u64,
+        ..Default::default()
+    };
+    let hosts: Vec<IpAddr> = plan.targets.iter().flat_map(|target| target.addrs()).collect();
+
+    let mut asleep = Vec::new();
+    for host in hosts {
+        let Some(mac) = macs.get(&host).copied() else {
+            report.unknown.push(host);
+            continue;
+        };
+        let IpAddr::V4(v4) = host else {
+            report.unknown.push(host);
+            continue;
+        };
+        if responds(&plan.context, host, &probe_ports, limit).await {
+            report.hosts.push(WakeOutcome { host, mac: Some(mac.to_string()), already_awake: true, responded_after_ms: None });
+            continue;
+        }
+        let broadcast = send(v4, mac, port).map_err(|e| format!("無法送出 Wake-on-LAN 封包給 {}: {}", mac, e))?;
+        if !quiet {
+            println!("{} {} ({}) → {}:{}", "喚醒:".bold(), plan.context.show_ip(host), mac, broadcast, port);
+        }
+        asleep.push((report.hosts.len(), host));
+        report.hosts.push(WakeOutcome { host, mac: Some(mac.to_string()), already_awake: false, responded_after_ms: None });
+    }
+
+    let started = Instant::now();
+    while !asleep.is_empty() && started.elapsed() < grace {
+        let woke = asleep.len();
+        let mut still = Vec::new();
+        for (index, host) in asleep {
+            match responds(&plan.context, host, &probe_ports, limit).await {
+                true => report.hosts[index].responded_after_ms = Some(started.elapsed().as_millis() as u64),
+                false => still.push((index, host)),
+            }
+        }
+        asleep = still;
+        if !quiet {
+            let left = grace.saturating_sub(started.elapsed()).as_secs();
+            eprint!("\r{}", format!("等待主機喚醒… 剩 {} 秒，{} 台尚未回應   ", left, asleep.len()).dimmed());
+            let _ = io::stderr().flush();
+        }
+        if asleep.len() == woke {
+            tokio::time::sleep(POLL_INTERVAL.min(grace.saturating_sub(started.elapsed()))).await;
+        }
+    }
+    if !quiet && report.hosts.iter().any(|outcome| !outcome.already_awake) {
+        eprintln!();
+    }
+    Ok(report)
+}
+
+// 掃描前顯示喚醒結果
+pub fn display(report: &WakeReport, context: &ScanContext) {
+    for outcome in &report.hosts {
+        let host = context.show_ip(outcome.host);
+        match (outcome.already_awake, outcome.responded_after_ms) {
+            (true, _) => println!("  {} 已醒著", host),
+            (false, Some(ms)) => println!("  {} {}", host, format!("已喚醒，{:.1} 秒後回應", ms as f64 / 1000.0).green()),
+            (false, None) => println!(
+                "  {} {}",
+                host,
+                format!("寬限 {} 秒內沒有回應", report.grace_ms / 1000).yellow()
+            ),
+        }
+    }
+    if !report.unknown.is_empty() {
+        let hosts: Vec<String> = report.unknown.iter().take(UNKNOWN_SHOWN).map(|host| context.show_ip(*host)).collect();
+        let more = match report.unknown.len() > UNKNOWN_SHOWN {
+            true => format!(" 等 {} 台", report.unknown.len()),
+            false => String::new(),
+        };
+        println!("{}", format!("  沒有 MAC 位址，未喚醒: {}{}", hosts.join(", "), more).dimmed());
+    }
+}
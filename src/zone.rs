@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use crate::targets::TargetSpec;
+
+// 連結本地位址的區域 (網路介面)；name 在平台不支援介面名稱時為索引
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Zone {
+    pub index: u32,
+    pub name: String,
+}
+
+// fe80::/10
+pub fn is_link_local(addr: Ipv6Addr) -> bool {
+    addr.segments()[0] & 0xffc0 == 0xfe80
+}
+
+// 目標清單中帶 %zone 的連結本地位址；探測連線時依位址查詢 scope_id
+// 每次掃描各自一份，放在 ScanContext 中
+#[derive(Debug, Clone, Default)]
+pub struct Zones(BTreeMap<Ipv6Addr, Zone>);
+
+impl Zones {
+    // 解析 fe80::1%eth0 或 fe80::1%2 並記下區域；沒有 % 時為 None
+    // 區域必須對應到存在的網路介面，找不到時錯誤訊息列出可用的介面
+    pub fn parse(&mut self, item: &str) -> Option<Result<Ipv6Addr, String>> {
+        let (addr, zone) = item.split_once('%')?;
+        Some(self.parse_parts(item, addr, zone))
+    }
+
+    fn parse_parts(&mut self, item: &str, addr: &str, zone: &str) -> Result<Ipv6Addr, String> {
+        let addr: Ipv6Addr = addr.parse().map_err(|_| format!("無效的 IPv6 位址: {}", item))?;
+        if !is_link_local(addr) {
+            return Err(format!("只有連結本地位址 (fe80::/10) 可以指定區域: {}", item));
+        }
+        let zone = resolve(zone)?;
+        self.register(addr, zone)?;
+        Ok(addr)
+    }
+
+    fn register(&mut self, addr: Ipv6Addr, zone: Zone) -> Result<(), String> {
+        match self.0.get(&addr) {
+            // 同一個位址在不同介面是不同的主機，但結果以位址為鍵，無法同時掃描
+            Some(existing) if existing.index != zone.index => {
+                Err(format!("{} 不能同時指定 %{} 與 %{}，請分開掃描", addr, existing.name, zone.name))
+            }
+            _ => {
+                self.0.insert(addr, zone);
+                Ok(())
+            }
+        }
+    }
+
+    // NDP 快取中的連結本地鄰居；只取可達或近期可達的項目，依介面與位址排序
+    pub fn neighbors(&mut self, only: Option<&str>) -> Result<Vec<(Ipv6Addr, Zone)>, String> {
+        let only = only.map(resolve).transpose()?;
+        let interfaces = platform::interfaces();
+        let mut found: Vec<(Ipv6Addr, Zone)> = platform::ndp_cache()?
+            .into_iter()
+            .filter(|(addr, _)| is_link_local(*addr))
+            .filter_map(|(addr, name)| Some((addr, interfaces.iter().find(|i| i.name == name)?.clone())))
+            .filter(|(_, zone)| only.as_ref().is_none_or(|only| only.index == zone.index))
+            .collect();
+        found.sort_by_key(|(addr, zone)| (zone.index, *addr));
+        found.dedup();
+        for (addr, zone) in &found {
+            self.register(*addr, zone.clone())?;
+        }
+        Ok(found)
+    }
+
+    // 位址記下的區域索引；沒有指定區域時為 0
+    pub fn scope_of(&self, addr: Ipv6Addr) -> u32 {
+        self.0.get(&addr).map_or(0, |zone| zone.index)
+    }
+
+    // 連線目標用的 socket 位址：連結本地位址帶上目標清單指定的 scope_id
+    pub fn socket_addr(&self, host: IpAddr, port: u16) -> SocketAddr {
+        match host {
+            IpAddr::V6(addr) if is_link_local(addr) => SocketAddr::V6(SocketAddrV6::new(addr, port, 0, self.scope_of(addr))),
+            _ => SocketAddr::new(host, port),
+        }
+    }
+
+    // 顯示用：有區域時為 fe80::1%eth0
+    pub fn display(&self, host: IpAddr) -> String {
+        match host {
+            IpAddr::V6(addr) => match self.0.get(&addr) {
+                Some(zone) => format!("{}%{}", addr, zone.name),
+                None => host.to_string(),
+            },
+            IpAddr::V4(_) => host.to_string(),
+        }
+    }
+
+    // 目標的顯示名稱；帶區域的位址顯示區域
+    pub fn label(&self, target: &TargetSpec) -> String {
+        match target {
+            TargetSpec::Host { name, addr } if name == &addr.to_string() => self.display(*addr),
+            _ => target.label(),
+        }
+    }
+}
+
+// 區域可以是介面名稱或索引，兩者都必須是存在的介面
+fn resolve(zone: &str) -> Result<Zone, String> {
+    let interfaces = platform::interfaces();
+    let found = match zone.parse::<u32>() {
+        Ok(index) => interfaces.iter().find(|i| i.index == index),
+        Err(_) => interfaces.iter().find(|i| i.name == zone),
+    };
+    if let Some(found) = found {
+        return Ok(found.clone());
+    }
+    // 平台無法列出介面時，只接受非零的數字索引
+    if interfaces.is_empty() {
+        return match zone.parse::<u32>() {
+            Ok(index) if index > 0 => Ok(Zone { index, name: zone.to_string() }),
+            _ => Err(format!("無法列出網路介面，區域請改用數字索引: %{}", zone)),
+        };
+    }
+    let candidates: Vec<String> = interfaces.iter().map(|i| format!("{} ({})", i.name, i.index)).collect();
+    Err(format!("找不到網路介面 %{}，可用的介面: {}", zone, candidates.join(", ")))
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::ffi::CStr;
+    use std::net::Ipv6Addr;
+    use std::process::Command;
+    use super::Zone;
+
+    pub fn interfaces() -> Vec<Zone> {
+        let list = unsafe { libc::if_nameindex() };
+        if list.is_null() {
+            return Vec::new();
+        }
+        let mut interfaces = Vec::new();
+        let mut cursor = list;
+        loop {
+            // 安全性：if_nameindex 回傳以 if_index 為 0 結尾的陣列，if_freenameindex 之前都有效
+            let entry = unsafe { &*cursor };
+            if entry.if_index == 0 || entry.if_name.is_null() {
+                break;
+            }
+            let name = unsafe { CStr::from_ptr(entry.if_name) }.to_string_lossy().into_owned();
+            interfaces.push(Zone { index: entry.if_index, name });
+            cursor = unsafe { cursor.add(1) };
+        }
+        unsafe { libc::if_freenameindex(list) };
+        interfaces.sort_by_key(|i| i.index);
+        interfaces
+    }
+
+    // Linux: ip -6 neigh show，例如 fe80::1 dev eth0 lladdr 52:54:00:12:34:56 router REACHABLE
+    #[cfg(target_os = "linux")]
+    pub fn ndp_cache() -> Result<Vec<(Ipv6Addr, String)>, String> {
+        let output = Command::new("ip").args(["-6", "neigh", "show"]).output().map_err(|e| format!("無法執行 ip -6 neigh: {}", e))?;
+        let text = String::from_utf8_lossy(&output.stdout);
+        Ok(text
+            .lines()
+            .filter(|line| !line.ends_with("FAILED") && !line.ends_with("INCOMPLETE"))
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                let [addr, "dev", name, ..] = fields.as_slice() else {
+                    return None;
+                };
+                Some((addr.parse().ok()?, name.to_string()))
+            })
+            .collect())
+    }
+
+    // macOS / BSD: ndp -an，第一欄為 fe80::1%en0
+    #[cfg(not(target_os = "linux"))]
+    pub fn ndp_cache() -> Result<Vec<(Ipv6Addr, String)>, String> {
+        let output = Command::new("ndp").arg("-an").output().map_err(|e| format!("無法執行 ndp -an: {}", e))?;
+        let text = String::from_utf8_lossy(&output.stdout);
+        Ok(text
+            .lines()
+            .skip(1)
+            .filter(|line| !line.contains("(incomplete)"))
+            .filter_map(|line| {
+                let (addr, name) = line.split_whitespace().next()?.split_once('%')?;
+                Some((addr.parse().ok()?, name.to_string()))
+            })
+            .collect())
+    }
+}
+
+#[cfg(not(unix))]
+mod platform {
+    use std::net::Ipv6Addr;
+    use super::Zone;
+
+    pub fn interfaces() -> Vec<Zone> {
+        Vec::new()
+    }
+
+    pub fn ndp_cache() -> Result<Vec<(Ipv6Addr, String)>, String> {
+        Err("此平台不支援讀取 NDP 快取".to_string())
+    }
+}
@@ -0,0 +1,67 @@
+// 同一次執行的標頭、報告、串流輸出與進度檔都帶著 --scan-id 指定的掃描 ID
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const SCAN_ID: &str = "ci-job-4711";
+
+// 不使用網路、設定檔與互動功能的本機掃描
+fn scan(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_r1"))
+        .args(["--no-config", "--no-external-ip", "--no-sanity-check", "--no-captive-check", "--no-network-info"])
+        .args(["--no-tarpit-check", "--no-inbound", "--no-progress", "--no-pager", "--target", "127.0.0.1", "--ports", "1"])
+        .args(["--scan-id", SCAN_ID])
+        .args(args)
+        .env("NO_COLOR", "1")
+        .output()
+        .expect("無法執行掃描程式")
+}
+
+fn temp(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("scan-id-{}-{}", std::process::id(), name))
+}
+
+fn read(path: &Path) -> String {
+    std::fs::read_to_string(path).unwrap_or_else(|e| panic!("無法讀取 {}: {}", path.display(), e))
+}
+
+#[test]
+fn the_scan_id_appears_in_every_output() {
+    let (ndjson, csv, progress) = (temp("scan.ndjson"), temp("scan.csv"), temp("scan.progress"));
+    let output = scan(&["--json"]);
+    assert!(output.status.success(), "{:?}", output);
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["metadata"]["scan_id"], SCAN_ID);
+
+    let output = scan(&[
+        "--output",
+        ndjson.to_str().unwrap(),
+        "--output",
+        csv.to_str().unwrap(),
+        "--progress-file",
+        progress.to_str().unwrap(),
+        "--heartbeat",
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    // 結果與心跳事件的每一行
+    let lines: Vec<serde_json::Value> = read(&ndjson).lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert!(lines.len() >= 2, "{:?}", lines);
+    assert!(lines.iter().all(|line| line["scan_id"] == SCAN_ID), "{:?}", lines);
+    assert!(read(&csv).lines().any(|line| line == format!("# scan_id: {}", SCAN_ID)));
+    let document: serde_json::Value = serde_json::from_str(&read(&progress)).unwrap();
+    assert_eq!(document["scan_id"], SCAN_ID);
+    assert!(String::from_utf8_lossy(&output.stderr).lines().last().unwrap().contains(&format!(" scan_id={} ", SCAN_ID)));
+    for path in [ndjson, csv, progress] {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[test]
+fn the_terminal_header_shows_the_scan_id() {
+    let output = scan(&[]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains(&format!("掃描 ID: {}", SCAN_ID)));
+
+    let invalid = Command::new(env!("CARGO_BIN_EXE_r1")).args(["--no-config", "--scan-id", "has space"]).output().unwrap();
+    assert!(!invalid.status.success());
+    assert!(String::from_utf8_lossy(&invalid.stderr).contains("--scan-id"));
+}
@@ -0,0 +1,83 @@
+// 執行掃描程式並解析標準錯誤最後一行的狀態 (portscanner: status=... exit=... scan_id=... v=2)
+use std::collections::BTreeMap;
+use std::net::TcpListener;
+use std::process::{Command, Output};
+
+// 不使用網路、設定檔與互動功能的本機掃描
+fn scan(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_r1"))
+        .args(["--no-config", "--no-external-ip", "--no-sanity-check", "--no-captive-check", "--no-network-info"])
+        .args(["--no-tarpit-check", "--no-inbound", "--no-progress", "--no-pager", "--target", "127.0.0.1"])
+        .args(args)
+        .env("NO_COLOR", "1")
+        .output()
+        .expect("無法執行掃描程式")
+}
+
+// 最後一行的欄位，依出現順序
+fn status_fields(output: &Output) -> Vec<(String, String)> {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let last = stderr.lines().last().unwrap_or_default();
+    let fields = last.strip_prefix("portscanner: ").unwrap_or_else(|| panic!("最後一行不是狀態行: {:?}", stderr));
+    fields
+        .split(' ')
+        .map(|field| {
+            let (key, value) = field.split_once('=').unwrap_or_else(|| panic!("欄位格式錯誤: {}", field));
+            (key.to_string(), value.to_string())
+        })
+        .collect()
+}
+
+#[test]
+fn every_scan_ends_with_one_status_line() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let open = listener.local_addr().unwrap().port();
+    // 綁定後立刻關閉的端口會被拒絕連線
+    let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let ports = format!("{},{}", open, closed);
+
+    let csv = std::env::temp_dir().join(format!("status-line-{}.csv", std::process::id()));
+    let csv = csv.to_str().unwrap();
+    for format in [&["--json"][..], &["--output", csv][..], &[][..]] {
+        let output = scan(&[&["--ports", ports.as_str(), "--scan-id", "ci-42"][..], format].concat());
+        assert!(output.status.success(), "{:?}", output);
+        let fields = status_fields(&output);
+        let keys: Vec<&str> = fields.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, ["status", "open", "closed", "filtered", "duration_ms", "exit", "scan_id", "v"]);
+        let values: BTreeMap<&str, &str> = fields.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect();
+        assert_eq!(values["status"], "ok");
+        assert_eq!(values["open"], "1");
+        assert_eq!(values["closed"], "1");
+        assert_eq!(values["filtered"], "0");
+        assert!(values["duration_ms"].parse::<u64>().is_ok());
+        assert_eq!(values["exit"], "0");
+        assert_eq!(values["scan_id"], "ci-42");
+        assert_eq!(values["v"], "2");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(stderr.matches("portscanner: status=").count(), 1, "{}", stderr);
+    }
+    // --compact 的端口無法連線是判定不成立
+    let output = scan(&["--ports", ports.as_str(), "--compact"]);
+    let fields: BTreeMap<String, String> = status_fields(&output).into_iter().collect();
+    assert_eq!(fields["status"], "violations");
+    assert_eq!(fields["exit"], output.status.code().unwrap().to_string());
+    drop(listener);
+    let _ = std::fs::remove_file(csv);
+}
+
+#[test]
+fn errors_report_the_exit_code() {
+    let output = scan(&["--ports", "not-a-port"]);
+    let code = output.status.code().unwrap();
+    assert_ne!(code, 0);
+    let fields: BTreeMap<String, String> = status_fields(&output).into_iter().collect();
+    assert_eq!(fields["status"], "error");
+    assert_eq!(fields["exit"], code.to_string());
+}
+
+#[test]
+fn the_status_line_can_be_turned_off() {
+    let output = scan(&["--ports", "1", "--json", "--no-status-line"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("portscanner: status="));
+}